@@ -3,6 +3,12 @@
 ///
 pub mod coroutine;
 
+///
+/// Pool of binder channels to one service with multiplexed requests
+///
+pub mod pool;
+
+use async_trait::async_trait;
 use tokio::sync::mpsc::{Sender, Receiver};
 use crate::tokio::tokio_block_on;
 
@@ -40,6 +46,30 @@ pub trait BinderChannel<T>: Send + Sync where T: Send + Sync{
 }
 
 
+///
+/// Binder channel which may be used from async code, e.g. from transport workers.
+/// Unlike BinderChannel it never blocks on runtime, so service running on the same
+/// runtime keeps handling requests while caller awaits response.
+///
+#[async_trait]
+pub trait AsyncBinderChannel<T>: Send + Sync where T: Send + Sync{
+    ///
+    /// Sends message to remote binder
+    ///
+    /// # Panics
+    /// * If sending error occurs
+    ///
+    async fn send_message_async(&mut self, message: T);
+
+    ///
+    /// Receives message from remote binder
+    ///
+    /// # Panics
+    /// * If receiving error occurs
+    ///
+    async fn receive_message_async(&mut self) -> T;
+}
+
 ///
 /// Provides binder channel for specified message type
 /// 
@@ -98,6 +128,27 @@ pub trait Binder<Q: Send + Sync, R: Send + Sync>: Send + Sync{
 }
 
 
+///
+/// Non-blocking variant of Binder for async code
+///
+#[async_trait]
+pub trait AsyncBinder<Q: Send + Sync, R: Send + Sync>: Send + Sync{
+    ///
+    /// Executes RPC call for request Q and awaits result
+    ///
+    /// # Arguments
+    /// * request: Q: request message
+    ///
+    /// returns: R: response message
+    ///
+    async fn handle_request_async(&mut self, request: Q) -> R;
+
+    ///
+    /// Unbinds this binder from service
+    ///
+    async fn unbind_async(&mut self);
+}
+
 ///
 /// A handler that used to receive messages and execute RPC commands
 ///
@@ -140,6 +191,25 @@ impl<Q, R> Binder<Q, R> for dyn BinderChannel<BinderMessage<Q, R>>
     }
 }
 
+#[async_trait]
+impl<Q, R> AsyncBinder<Q, R> for dyn AsyncBinderChannel<BinderMessage<Q, R>>
+    where Q: Sync + Send, R: Sync + Send
+{
+    async fn handle_request_async(&mut self, request: Q) -> R {
+        self.send_message_async(BinderMessage::Query(request)).await;
+        match self.receive_message_async().await {
+            BinderMessage::Unbind => panic!("Service-side unbind is not supported"),
+            BinderMessage::Query(_) => panic!("Received query from service"),
+            BinderMessage::Response(response) => response,
+        }
+    }
+
+    #[inline]
+    async fn unbind_async(&mut self) {
+        self.send_message_async(BinderMessage::Unbind).await;
+    }
+}
+
 ///
 /// Asynchronous binder channel
 ///
@@ -215,6 +285,21 @@ impl<T> BinderChannel<T> for AsyncBinderChannelImpl<T> where T: Send + Sync{
     }
 }
 
+#[async_trait]
+impl<T> AsyncBinderChannel<T> for AsyncBinderChannelImpl<T> where T: Send + Sync{
+    async fn send_message_async(&mut self, message: T) {
+        self.tx.send(message).await.unwrap();
+        if let Some(signal_tx) = &self.signal_tx{
+            signal_tx.send(true).await.unwrap();
+        }
+    }
+
+    #[inline]
+    async fn receive_message_async(&mut self) -> T {
+        self.rx.recv().await.unwrap()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
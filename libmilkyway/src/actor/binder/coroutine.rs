@@ -5,7 +5,8 @@ use std::collections::HashMap;
 
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 
-use crate::actor::binder::{AsyncBinderChannelImpl, BinderChannel, BinderChannelProvider, BinderMessage, BinderServiceHandler};
+use crate::actor::binder::{AsyncBinderChannel, AsyncBinderChannelImpl, BinderChannel, BinderChannelProvider, BinderMessage,
+                           BinderServiceHandler};
 use crate::actor::binder::coroutine::BinderAsyncServiceMessage::{BindRequest, BindResponse, ControlTx, SignalTx};
 use crate::tokio::{tokio_block_on, tokio_spawn};
 use crate::unwrap_variant;
@@ -123,20 +124,32 @@ impl<Q, R> BinderAsyncService<Q, R> where Q: Send + Sync + 'static, R: Send + Sy
     }
 }
 
-impl<Q, R> BinderChannelProvider<BinderMessage<Q, R>> for BinderAsyncService<Q, R> 
-    where Q: Send + Sync + 'static, R: Send + Sync +'static{
-    fn bind(&mut self) -> Box<dyn BinderChannel<BinderMessage<Q, R>>>{
+impl<Q, R> BinderAsyncService<Q, R> where Q: Send + Sync + 'static, R: Send + Sync + 'static{
+    ///
+    /// Creates new service binder without blocking on runtime, so it may be called from
+    /// coroutines running on the same runtime as service
+    ///
+    /// returns: Channel of non-blocking communication with service
+    ///
+    pub async fn bind_async(&mut self) -> Box<dyn AsyncBinderChannel<BinderMessage<Q, R>>>{
+        Box::new(self.create_channel().await)
+    }
+
+    async fn create_channel(&mut self) -> AsyncBinderChannelImpl<BinderMessage<Q, R>>{
         let (service_tx, local_rx) = channel::<BinderMessage<Q,R>>(ASYNC_BINDER_SERVICE_CHANNEL_BUFSIZE);
         let ctl_tx = self.control_tx.clone().unwrap();
-        tokio_block_on(async{
-            ctl_tx.send(BindRequest(service_tx)).await.unwrap();
-            self.signal_tx.clone().send(true).await.unwrap();
-        });
-        let recv_coroutine = self.control_rx.as_mut().unwrap().recv();
-        let local_tx = tokio_block_on(recv_coroutine).unwrap();
+        ctl_tx.send(BindRequest(service_tx)).await.unwrap();
+        self.signal_tx.clone().send(true).await.unwrap();
+        let local_tx = self.control_rx.as_mut().unwrap().recv().await.unwrap();
         let local_tx= unwrap_variant!(local_tx, BindResponse);
-        let result = AsyncBinderChannelImpl::new(Some(self.signal_tx.clone()), local_tx, local_rx);
-        Box::new(result)
+        AsyncBinderChannelImpl::new(Some(self.signal_tx.clone()), local_tx, local_rx)
+    }
+}
+
+impl<Q, R> BinderChannelProvider<BinderMessage<Q, R>> for BinderAsyncService<Q, R> 
+    where Q: Send + Sync + 'static, R: Send + Sync +'static{
+    fn bind(&mut self) -> Box<dyn BinderChannel<BinderMessage<Q, R>>>{
+        Box::new(tokio_block_on(self.create_channel()))
     }
 }
 
@@ -145,7 +158,7 @@ mod tests {
     use std::time::Duration;
     use super::*;
     use tokio::time::sleep;
-    use crate::actor::binder::{Binder, BinderMessage, BinderServiceHandler};
+    use crate::actor::binder::{AsyncBinder, Binder, BinderMessage, BinderServiceHandler};
     use crate::actor::binder::BinderMessage::Unbind;
     use crate::tokio::{init_tokio, tokio_block_on};
 
@@ -226,4 +239,22 @@ mod tests {
 
         assert_eq!(response, expected_response);
     }
+
+    #[test]
+    fn test_handle_request_async() {
+        init_tokio();
+        let handler = Box::new(TestHandler);
+        let mut service = BinderAsyncService::run(handler);
+
+        // Binder is created and used from coroutine on the runtime of service
+        let response = tokio_block_on(async {
+            let mut binder_channel = service.bind_async().await;
+            let first = binder_channel.handle_request_async(1).await;
+            let second = binder_channel.handle_request_async(first).await;
+            binder_channel.unbind_async().await;
+            second
+        });
+
+        assert_eq!(response, 3);
+    }
 }
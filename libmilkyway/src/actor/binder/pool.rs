@@ -0,0 +1,181 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::actor::binder::{BinderChannel, BinderChannelProvider, BinderMessage};
+
+///
+/// Number of channels in binder pool if configuration does not set it
+///
+pub const DEFAULT_BINDER_POOL_SIZE: usize = 4;
+
+///
+/// Requests sent over one channel before their responses are read. Both directions of channel
+/// are buffered by ASYNC_BINDER_SERVICE_CHANNEL_BUFSIZE, so pipeline must be shorter.
+///
+const MAX_PIPELINED_REQUESTS: usize = 64;
+
+///
+/// Binder channel which may be shared by several consumers
+///
+pub type SharedBinderChannel<Q, R> = Arc<Mutex<Box<dyn BinderChannel<BinderMessage<Q, R>>>>>;
+
+///
+/// Several binder channels to one service, so concurrent consumers do not wait for the single
+/// binder held by somebody else. Requests are sent through a channel nobody uses, batches of
+/// requests are spread over all free channels and matched with responses by correlation IDs.
+///
+/// # Template arguments
+/// * Q: request message type
+/// * R: response message type
+///
+pub struct BinderPool<Q: Send + Sync, R: Send + Sync>{
+    channels: Vec<SharedBinderChannel<Q, R>>,
+    next_correlation_id: AtomicU64,
+}
+
+impl<Q, R> BinderPool<Q, R> where Q: Send + Sync, R: Send + Sync {
+    ///
+    /// Binds pool of channels to service
+    ///
+    /// # Arguments
+    /// * provider: &mut dyn BinderChannelProvider: service to bind to
+    /// * size: usize: number of channels, at least one is bound
+    ///
+    pub fn new(provider: &mut dyn BinderChannelProvider<BinderMessage<Q, R>>, size: usize) -> BinderPool<Q, R>{
+        Self::from_channels((0..size.max(1)).map(|_| provider.bind()).collect())
+    }
+
+    ///
+    /// Creates pool of channels already bound to one service
+    ///
+    /// # Panics
+    /// * If no channels are given
+    ///
+    pub fn from_channels(channels: Vec<Box<dyn BinderChannel<BinderMessage<Q, R>>>>) -> BinderPool<Q, R>{
+        assert!(!channels.is_empty(), "Binder pool requires at least one channel");
+        BinderPool{
+            channels: channels.into_iter().map(|channel| Arc::new(Mutex::new(channel))).collect(),
+            next_correlation_id: AtomicU64::new(0),
+        }
+    }
+
+    #[inline]
+    pub fn get_size(&self) -> usize{
+        self.channels.len()
+    }
+
+    ///
+    /// Gets channel held by fewest consumers, e.g. to give every namespace of module its own one
+    ///
+    pub fn get_shared(&self) -> SharedBinderChannel<Q, R>{
+        self.channels.iter().min_by_key(|channel| Arc::strong_count(channel)).unwrap().clone()
+    }
+
+    // Locks channel nobody uses or waits for the one held by fewest consumers
+    fn lock_free(&self) -> MutexGuard<'_, Box<dyn BinderChannel<BinderMessage<Q, R>>>>{
+        for channel in self.channels.iter(){
+            if let Ok(guard) = channel.try_lock(){
+                return guard;
+            }
+        }
+        self.channels.iter().min_by_key(|channel| Arc::strong_count(channel)).unwrap().lock().unwrap()
+    }
+
+    ///
+    /// Executes RPC call through free channel of pool and waits for result
+    ///
+    /// # Arguments
+    /// * request: Q: request message
+    ///
+    /// returns: R: response message
+    ///
+    pub fn handle_request(&self, request: Q) -> R{
+        let mut channel = self.lock_free();
+        channel.send_message(BinderMessage::Query(request));
+        receive_response(channel.as_mut())
+    }
+
+    ///
+    /// Executes several RPC calls multiplexed over all free channels of pool. Requests are
+    /// pipelined, so channel does not wait for response before it sends next request.
+    ///
+    /// # Arguments
+    /// * requests: Vec<Q>: request messages
+    ///
+    /// returns: Vec<R>: responses in order of requests
+    ///
+    pub fn handle_many(&self, requests: Vec<Q>) -> Vec<R>{
+        let mut channels: Vec<_> = self.channels.iter().filter_map(|channel| channel.try_lock().ok()).collect();
+        if channels.is_empty(){
+            channels.push(self.lock_free());
+        }
+        let first_id = self.next_correlation_id.fetch_add(requests.len() as u64, Ordering::Relaxed);
+        let ids: Vec<u64> = (first_id..first_id + requests.len() as u64).collect();
+        let mut responses = HashMap::<u64, R>::with_capacity(requests.len());
+        let mut requests = ids.iter().copied().zip(requests).peekable();
+        while requests.peek().is_some(){
+            // Correlation IDs of requests sent but not answered yet, by channel
+            let mut pending = vec![VecDeque::<u64>::new(); channels.len()];
+            for (index, (id, request)) in requests.by_ref().take(channels.len() * MAX_PIPELINED_REQUESTS).enumerate(){
+                let channel = index % channels.len();
+                channels[channel].send_message(BinderMessage::Query(request));
+                pending[channel].push_back(id);
+            }
+            // Service answers requests of one channel in order they were sent
+            for (channel, ids) in channels.iter_mut().zip(pending){
+                for id in ids{
+                    responses.insert(id, receive_response(channel.as_mut()));
+                }
+            }
+        }
+        ids.iter().map(|id| responses.remove(id).expect("Every request is answered")).collect()
+    }
+}
+
+// Reads response to query sent over channel
+fn receive_response<Q: Send + Sync, R: Send + Sync>(channel: &mut dyn BinderChannel<BinderMessage<Q, R>>) -> R{
+    match channel.receive_message() {
+        BinderMessage::Response(response) => response,
+        BinderMessage::Unbind => panic!("Service-side unbind is not supported"),
+        BinderMessage::Query(_) => panic!("Received query from service"),
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actor::binder::BinderServiceHandler;
+    use crate::actor::binder::coroutine::BinderAsyncService;
+    use crate::tokio::init_tokio;
+
+    struct TestHandler;
+
+    impl BinderServiceHandler<u32, u32> for TestHandler {
+        fn handle_message(&mut self, request: u32) -> u32 {
+            request * 2
+        }
+    }
+
+    #[test]
+    fn test_binder_pool() {
+        init_tokio();
+        let mut service = BinderAsyncService::run(Box::new(TestHandler));
+        let pool = BinderPool::new(&mut service, 3);
+        assert_eq!(pool.get_size(), 3);
+        assert_eq!(pool.handle_request(21), 42);
+
+        // Consumers get distinct channels while there are free ones
+        let first = pool.get_shared();
+        let second = pool.get_shared();
+        let third = pool.get_shared();
+        assert!(!Arc::ptr_eq(&first, &second) && !Arc::ptr_eq(&second, &third) && !Arc::ptr_eq(&first, &third));
+
+        // Busy channel is skipped
+        let busy = first.lock().unwrap();
+        let requests: Vec<u32> = (0..500).collect();
+        assert_eq!(pool.handle_many(requests.clone()), requests.iter().map(|request| request * 2).collect::<Vec<_>>());
+        drop(busy);
+        assert_eq!(pool.handle_many(vec![1, 2, 3]), vec![2, 4, 6]);
+    }
+}
@@ -16,4 +16,24 @@ pub mod arguments;
 ///
 /// Interface IO utils for interacting with user
 /// 
-pub mod io;
\ No newline at end of file
+pub mod io;
+
+///
+/// Commands for inspecting transport tap
+///
+pub mod tap;
+
+///
+/// Metadata of commands for help and completion
+///
+pub mod describe;
+
+///
+/// Output of messages to terminal, plain text or syslog
+///
+pub mod output;
+
+///
+/// Cached dynamic completion of argument values
+///
+pub mod completion;
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+///
+/// Default time in milliseconds completion values are reused for
+///
+pub const DEFAULT_COMPLETION_TTL: u64 = 30000;
+
+///
+/// Default time in milliseconds completion source is waited for
+///
+pub const DEFAULT_COMPLETION_TIMEOUT: u64 = 300;
+
+struct CachedValues{
+    fetched: Instant,
+    values: Vec<String>,
+}
+
+///
+/// Cache of values for dynamic completion, e.g. serials of certificates. Sources are queried
+/// on background thread, so a slow service can not freeze the shell: if source does not answer
+/// within timeout, stale values(or nothing) are returned and fresh ones are cached once they arrive.
+///
+#[derive(Clone)]
+pub struct CompletionCache{
+    entries: Arc<Mutex<HashMap<String, CachedValues>>>,
+    ttl: Duration,
+    timeout: Duration,
+}
+
+impl Default for CompletionCache {
+    fn default() -> Self {
+        CompletionCache::new(DEFAULT_COMPLETION_TTL, DEFAULT_COMPLETION_TIMEOUT)
+    }
+}
+
+impl CompletionCache {
+    ///
+    /// Creates empty cache
+    ///
+    /// # Arguments
+    /// * ttl: u64: milliseconds values are reused for
+    /// * timeout: u64: milliseconds source is waited for
+    ///
+    pub fn new(ttl: u64, timeout: u64) -> CompletionCache{
+        CompletionCache{
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            ttl: Duration::from_millis(ttl),
+            timeout: Duration::from_millis(timeout),
+        }
+    }
+
+    ///
+    /// Gets values starting with prefix, querying source if cached values are missing or expired
+    ///
+    /// # Arguments
+    /// * key: &str: what values are, e.g. `signing-serials`
+    /// * prefix: &str: part of value typed by user
+    /// * source: F: gets all values, called on background thread
+    ///
+    /// returns: Vec<String>: sorted matching values
+    ///
+    pub fn complete<F>(&self, key: &str, prefix: &str, source: F) -> Vec<String>
+        where F: FnOnce() -> Vec<String> + Send + 'static{
+        let cached = self.entries.lock().unwrap().get(key)
+            .map(|cached| (cached.fetched.elapsed() < self.ttl, cached.values.clone()));
+        let values = match cached {
+            Some((true, values)) => values,
+            _ => {
+                let (sender, receiver) = mpsc::channel();
+                let entries = self.entries.clone();
+                let key_owned = key.to_string();
+                std::thread::spawn(move || {
+                    let values = source();
+                    entries.lock().unwrap().insert(key_owned, CachedValues{
+                        fetched: Instant::now(),
+                        values: values.clone(),
+                    });
+                    // Receiver is gone if source was too slow, values are cached anyway
+                    let _ = sender.send(values);
+                });
+                match receiver.recv_timeout(self.timeout) {
+                    Ok(values) => values,
+                    Err(_) => {
+                        log::warn!("Completion source of {} did not answer within {:?}", key, self.timeout);
+                        cached.map(|(_, values)| values).unwrap_or_default()
+                    }
+                }
+            }
+        };
+        filter_values(values, prefix)
+    }
+
+    ///
+    /// Drops cached values, e.g. after a command changed them
+    ///
+    pub fn invalidate(&self, key: &str){
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    ///
+    /// Drops all cached values
+    ///
+    pub fn clear(&self){
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+// Keeps sorted unique values starting with prefix
+fn filter_values(values: Vec<String>, prefix: &str) -> Vec<String>{
+    let mut result: Vec<String> = values.into_iter().filter(|value| value.starts_with(prefix)).collect();
+    result.sort();
+    result.dedup();
+    result
+}
+
+///
+/// Completes path to local file. Directories are suffixed with `/`, so user may continue typing.
+///
+/// # Arguments
+/// * prefix: &str: part of path typed by user
+///
+/// returns: Vec<String>: sorted matching paths
+///
+pub fn complete_path(prefix: &str) -> Vec<String>{
+    let (directory, name) = match prefix.rfind('/') {
+        Some(position) => (&prefix[..=position], &prefix[position + 1..]),
+        None => ("", prefix),
+    };
+    let entries = match std::fs::read_dir(if directory.is_empty() { Path::new(".") } else { Path::new(directory) }) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    let values = entries.filter_map(|entry| entry.ok()).filter_map(|entry| {
+        let file_name = entry.file_name().into_string().ok()?;
+        // Hidden files are listed only if user asked for them
+        if file_name.starts_with('.') && !name.starts_with('.'){
+            return None;
+        }
+        let suffix = if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) { "/" } else { "" };
+        Some(format!("{}{}{}", directory, file_name, suffix))
+    }).collect();
+    filter_values(values, prefix)
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_completion_cache() {
+        let cache = CompletionCache::new(60000, 1000);
+        let queries = Arc::new(AtomicUsize::new(0));
+        let source = |queries: Arc<AtomicUsize>| move || {
+            queries.fetch_add(1, Ordering::SeqCst);
+            vec!["12".to_string(), "100".to_string(), "13".to_string()]
+        };
+        assert_eq!(cache.complete("serials", "1", source(queries.clone())), vec!["100", "12", "13"]);
+        assert_eq!(cache.complete("serials", "12", source(queries.clone())), vec!["12"]);
+        assert_eq!(queries.load(Ordering::SeqCst), 1);
+        cache.invalidate("serials");
+        assert_eq!(cache.complete("serials", "13", source(queries.clone())), vec!["13"]);
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+
+        let slow = CompletionCache::new(0, 10);
+        let started = Instant::now();
+        assert!(slow.complete("peers", "", || {
+            std::thread::sleep(Duration::from_millis(200));
+            vec!["alice".to_string()]
+        }).is_empty());
+        assert!(started.elapsed() < Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_complete_path() {
+        let directory = std::env::temp_dir().join(format!("milkyway-completion-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(directory.join("keys")).unwrap();
+        std::fs::write(directory.join("root.cert"), b"").unwrap();
+        std::fs::write(directory.join(".hidden"), b"").unwrap();
+        let prefix = format!("{}/", directory.to_str().unwrap());
+        assert_eq!(complete_path(&prefix), vec![format!("{}keys/", prefix), format!("{}root.cert", prefix)]);
+        assert_eq!(complete_path(&format!("{}r", prefix)), vec![format!("{}root.cert", prefix)]);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+use libmilkyway_derive::{Deserializable, EnumDeserializable, EnumSerializable, Serializable};
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+
+///
+/// Kind of value argument takes, tells CLI where to complete value from
+///
+#[derive(Clone, Copy, Debug, PartialEq, EnumSerializable, EnumDeserializable)]
+pub enum ArgumentKind{
+    /** Free text, not completed **/
+    Text,
+    /** Serial of certificate, completed by namespace from certificate service **/
+    Serial,
+    /** ID or name of peer, completed by namespace from name service **/
+    Peer,
+    /** Path to local file, completed by CLI itself **/
+    File,
+}
+
+///
+/// Description of a command argument
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable)]
+pub struct ArgumentDescription{
+    pub name: String,
+    pub description: String,
+    /** Whether command fails without this argument **/
+    pub required: bool,
+    /** Whether argument is passed as `name=value` rather than just `name` **/
+    pub takes_value: bool,
+    pub kind: ArgumentKind,
+}
+
+impl ArgumentDescription {
+    ///
+    /// Describes a required argument with value
+    ///
+    pub fn required(name: &str, description: &str) -> ArgumentDescription{
+        ArgumentDescription{
+            name: name.to_string(),
+            description: description.to_string(),
+            required: true,
+            takes_value: true,
+            kind: ArgumentKind::Text,
+        }
+    }
+
+    ///
+    /// Describes an optional argument with value
+    ///
+    pub fn optional(name: &str, description: &str) -> ArgumentDescription{
+        ArgumentDescription{
+            name: name.to_string(),
+            description: description.to_string(),
+            required: false,
+            takes_value: true,
+            kind: ArgumentKind::Text,
+        }
+    }
+
+    ///
+    /// Describes an optional argument without value, e.g. `force`
+    ///
+    pub fn flag(name: &str, description: &str) -> ArgumentDescription{
+        ArgumentDescription{
+            name: name.to_string(),
+            description: description.to_string(),
+            required: false,
+            takes_value: false,
+            kind: ArgumentKind::Text,
+        }
+    }
+
+    ///
+    /// Builder-like function setting kind of value, e.g.
+    /// `ArgumentDescription::required("serial", "Serial number").with_kind(ArgumentKind::Serial)`
+    ///
+    pub fn with_kind(mut self, kind: ArgumentKind) -> ArgumentDescription{
+        self.kind = kind;
+        self
+    }
+
+    ///
+    /// Formats argument for usage line, e.g. `name=<value>` or `[force]`
+    ///
+    pub fn get_usage(&self) -> String{
+        let usage = if self.takes_value {
+            format!("{}=<value>", self.name)
+        } else {
+            self.name.clone()
+        };
+        if self.required { usage } else { format!("[{}]", usage) }
+    }
+}
+
+///
+/// Description of a single command inside a namespace
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable)]
+pub struct CommandDescription{
+    pub name: String,
+    pub description: String,
+    pub arguments: Vec<ArgumentDescription>,
+}
+
+impl CommandDescription {
+    ///
+    /// Creates command description
+    ///
+    /// # Arguments
+    /// * name: &str: name of command, e.g. `generate`
+    /// * description: &str: what command does
+    /// * arguments: Vec<ArgumentDescription>: arguments accepted by command
+    ///
+    pub fn new(name: &str, description: &str, arguments: Vec<ArgumentDescription>) -> CommandDescription{
+        CommandDescription{
+            name: name.to_string(),
+            description: description.to_string(),
+            arguments,
+        }
+    }
+
+    ///
+    /// Formats arguments for usage line
+    ///
+    pub fn get_usage(&self) -> String{
+        let arguments: Vec<String> = self.arguments.iter().map(|argument| argument.get_usage()).collect();
+        arguments.join(" ")
+    }
+}
+
+///
+/// Description of a namespace with its commands
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable)]
+pub struct NamespaceDescription{
+    /** Path to namespace, e.g. ["certman", "signing"] **/
+    pub path: Vec<String>,
+    pub commands: Vec<CommandDescription>,
+}
+
+impl NamespaceDescription {
+    ///
+    /// Gets full path of a command as typed in CLI, e.g. `certman/signing/generate`
+    ///
+    pub fn get_command_path(&self, command: &CommandDescription) -> String{
+        let mut path = self.path.clone();
+        path.push(command.name.clone());
+        path.join("/")
+    }
+}
+
+///
+/// Description of everything module provides to CLI
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable)]
+pub struct ModuleDescription{
+    pub module_id: u64,
+    /** Top-level commands, same as MilkywayModule::get_commands **/
+    pub commands: Vec<String>,
+    pub namespaces: Vec<NamespaceDescription>,
+}
+
+impl ModuleDescription {
+    ///
+    /// Creates module description
+    ///
+    /// # Arguments
+    /// * module_id: u64: ID of module
+    /// * commands: Vec<String>: top-level commands of module
+    /// * namespaces: Vec<NamespaceDescription>: namespaces of module, e.g. from CommandRouter::describe
+    ///
+    pub fn new(module_id: u64, commands: Vec<String>, namespaces: Vec<NamespaceDescription>) -> ModuleDescription{
+        ModuleDescription{
+            module_id,
+            commands,
+            namespaces,
+        }
+    }
+
+    ///
+    /// Gets completion data: one entry per command with its full path and arguments
+    ///
+    /// returns: Vec<(String, Vec<String>)>: command paths with argument prefixes,
+    /// e.g. ("certman/signing/export", ["serial=", "file="])
+    ///
+    pub fn get_completions(&self) -> Vec<(String, Vec<String>)>{
+        let mut result = Vec::new();
+        for namespace in self.namespaces.iter(){
+            for command in namespace.commands.iter(){
+                let arguments = command.arguments.iter().map(|argument| {
+                    if argument.takes_value { format!("{}=", argument.name) } else { argument.name.clone() }
+                }).collect();
+                result.push((namespace.get_command_path(command), arguments));
+            }
+        }
+        result
+    }
+
+    ///
+    /// Finds description of argument of command
+    ///
+    /// # Arguments
+    /// * command: &[String]: full path to command, e.g. ["certman", "signing", "export"]
+    /// * argument: &str: name of argument
+    ///
+    pub fn get_argument(&self, command: &[String], argument: &str) -> Option<&ArgumentDescription>{
+        let (name, path) = command.split_last()?;
+        self.namespaces.iter()
+            .filter(|namespace| namespace.path == path)
+            .flat_map(|namespace| namespace.commands.iter())
+            .find(|description| &description.name == name)?
+            .arguments.iter()
+            .find(|description| description.name == argument)
+    }
+
+    ///
+    /// Finds commands and namespaces claimed by both modules
+    ///
+    /// # Arguments
+    /// * other: &ModuleDescription: description of another module
+    ///
+    /// returns: Vec<String>: colliding paths, empty if modules can be loaded together
+    ///
+    pub fn find_collisions(&self, other: &ModuleDescription) -> Vec<String>{
+        let mut result: Vec<String> = self.commands.iter()
+            .filter(|command| other.commands.contains(command))
+            .cloned()
+            .collect();
+        for namespace in self.namespaces.iter(){
+            if other.namespaces.iter().any(|other_namespace| other_namespace.path == namespace.path){
+                result.push(namespace.path.join("/"));
+            }
+        }
+        result
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn describe_module(module_id: u64, command: &str) -> ModuleDescription{
+        ModuleDescription::new(module_id, vec![command.to_string()], vec![NamespaceDescription{
+            path: vec![command.to_string(), "keys".to_string()],
+            commands: vec![CommandDescription::new("export", "Exports a key", vec![
+                ArgumentDescription::required("serial", "Serial of key").with_kind(ArgumentKind::Serial),
+                ArgumentDescription::flag("force", "Overwrite file"),
+            ])],
+        }])
+    }
+
+    #[test]
+    fn test_usage_and_completions() {
+        let description = describe_module(1, "certman");
+        assert_eq!(description.namespaces[0].commands[0].get_usage(), "serial=<value> [force]");
+        assert_eq!(description.get_completions(),
+                   vec![("certman/keys/export".to_string(), vec!["serial=".to_string(), "force".to_string()])]);
+        let path = vec!["certman".to_string(), "keys".to_string(), "export".to_string()];
+        assert_eq!(description.get_argument(&path, "serial").map(|argument| argument.kind), Some(ArgumentKind::Serial));
+        assert_eq!(description.get_argument(&path, "force").map(|argument| argument.kind), Some(ArgumentKind::Text));
+        assert!(description.get_argument(&path, "file").is_none());
+    }
+
+    #[test]
+    fn test_find_collisions() {
+        let certman = describe_module(1, "certman");
+        let other = describe_module(2, "other");
+        assert!(certman.find_collisions(&other).is_empty());
+        let duplicate = describe_module(3, "certman");
+        assert_eq!(certman.find_collisions(&duplicate), vec!["certman".to_string(), "certman/keys".to_string()]);
+    }
+}
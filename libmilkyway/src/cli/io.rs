@@ -1,5 +1,12 @@
-use std::io::{BufRead, stdin, stdout, Write};
+use std::future::Future;
+use std::io::{BufRead, IsTerminal, stdin, stdout, Write};
+use std::process::{Command, Stdio};
+use std::time::Duration;
 use colored::Colorize;
+use crate::module::session::{CLIProgress, CLIPrompt, CLISessionHost};
+
+const SPINNER_FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+const SPINNER_INTERVAL_MILLISECONDS: u64 = 100;
 
 ///
 /// Asks user for confirmation
@@ -19,4 +26,108 @@ pub fn confirm(prompt: &str) -> bool{
             return c == 'y' || c == 'Y';
         }
     }
-}
\ No newline at end of file
+}
+
+///
+/// Asks user for a value
+///
+/// # Arguments
+/// * prompt: &str: Prompt to show user
+/// * default: Option<&str>: value to use if user entered an empty line
+///
+/// returns: String: value entered by user or default value
+///
+pub fn ask(prompt: &str, default: Option<&str>) -> String{
+    loop {
+        match default {
+            Some(value) if !value.is_empty() => print!("{} [{}]: ", prompt.bold(), value),
+            _ => print!("{}: ", prompt.bold()),
+        }
+        stdout().lock().flush().expect("Can not flush");
+        let result = stdin().lock().lines().next().expect("Can not read line").unwrap();
+        let result = result.trim();
+        if !result.is_empty(){
+            return result.to_string();
+        }
+        if let Some(value) = default{
+            return value.to_string();
+        }
+    }
+}
+
+// Turns echo of terminal on or off, does nothing if input is not a terminal
+fn set_echo(enabled: bool){
+    if !stdin().is_terminal(){
+        return;
+    }
+    let _ = Command::new("stty").arg(if enabled { "echo" } else { "-echo" })
+        .stdin(Stdio::inherit())
+        .status();
+}
+
+///
+/// Shows progress and asks prompts of interactive module commands on terminal
+///
+pub struct TerminalSessionHost;
+
+impl CLISessionHost for TerminalSessionHost{
+    fn on_progress(&mut self, progress: &CLIProgress) {
+        match progress.percent {
+            Some(percent) => println!("[{:>3}%] {}", percent, progress.message),
+            None => println!("{} {}", "...".blue(), progress.message),
+        }
+    }
+
+    fn on_prompt(&mut self, prompt: &CLIPrompt) -> Option<String> {
+        match &prompt.default {
+            Some(value) if !value.is_empty() && !prompt.secret => print!("{} [{}]: ", prompt.message.bold(), value),
+            _ => print!("{}: ", prompt.message.bold()),
+        }
+        stdout().lock().flush().expect("Can not flush");
+        if prompt.secret{
+            set_echo(false);
+        }
+        let line = stdin().lock().lines().next();
+        if prompt.secret{
+            set_echo(true);
+            println!();
+        }
+        match line {
+            Some(Ok(line)) => Some(line.trim().to_string()),
+            _ => None,
+        }
+    }
+}
+
+///
+/// Awaits a future showing a spinner with message. Spinner is shown only if
+/// output is a terminal.
+///
+/// # Arguments
+/// * message: &str: message to show near spinner
+/// * future: F: future to await
+///
+/// returns: F::Output: result of future
+///
+pub async fn spin_while<F: Future>(message: &str, future: F) -> F::Output{
+    if !stdout().is_terminal(){
+        return future.await;
+    }
+    tokio::pin!(future);
+    let mut interval = tokio::time::interval(Duration::from_millis(SPINNER_INTERVAL_MILLISECONDS));
+    let mut frame = 0;
+    loop {
+        tokio::select! {
+            result = &mut future => {
+                print!("\r{}\r", " ".repeat(message.len() + 2));
+                stdout().lock().flush().expect("Can not flush");
+                return result;
+            }
+            _ = interval.tick() => {
+                print!("\r{} {}", SPINNER_FRAMES[frame % SPINNER_FRAMES.len()], message);
+                stdout().lock().flush().expect("Can not flush");
+                frame += 1;
+            }
+        }
+    }
+}
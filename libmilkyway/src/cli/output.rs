@@ -0,0 +1,453 @@
+use std::fmt::{Display, Formatter};
+use std::io::{IsTerminal, stdout};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::str::FromStr;
+use std::sync::RwLock;
+use colored::Colorize;
+use once_cell::sync::Lazy;
+use crate::errors::ErrorCode;
+use crate::serialization::schema::json_string;
+
+///
+/// Environment variable with output mode. It is used instead of a global variable
+/// because every module has its own copy of libmilkyway.
+///
+pub const OUTPUT_MODE_VARIABLE: &str = "MWAY_OUTPUT";
+
+///
+/// Identifier of messages in syslog/journald
+///
+pub const SYSLOG_IDENTIFIER: &str = "mway";
+
+///
+/// Field with code of error(see errors module), terminal and plain output show it next to severity
+///
+pub const CODE_FIELD: &str = "code";
+
+const JOURNALD_SOCKET_PATH: &str = "/run/systemd/journal/socket";
+const SYSLOG_SOCKET_PATH: &str = "/dev/log";
+/* Facility "user" of syslog */
+const SYSLOG_FACILITY: u8 = 1;
+/* Structured data ID in enterprise number space reserved for documentation(RFC 5612) */
+const SYSLOG_STRUCTURED_DATA_ID: &str = "mway@32473";
+
+///
+/// Severity of a message
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputLevel{
+    Info,
+    Warning,
+    Error,
+}
+
+impl OutputLevel {
+    ///
+    /// Gets name of level as shown in plain and JSON output
+    ///
+    pub fn get_name(&self) -> &'static str{
+        match self {
+            OutputLevel::Info => "info",
+            OutputLevel::Warning => "warning",
+            OutputLevel::Error => "error",
+        }
+    }
+
+    ///
+    /// Gets syslog severity of level
+    ///
+    pub fn get_syslog_severity(&self) -> u8{
+        match self {
+            OutputLevel::Info => 6,
+            OutputLevel::Warning => 4,
+            OutputLevel::Error => 3,
+        }
+    }
+}
+
+///
+/// Target of messages printed by CLI commands
+///
+pub trait OutputBackend: Send + Sync{
+    ///
+    /// Writes a message
+    ///
+    /// # Arguments
+    /// * level: OutputLevel: severity of message
+    /// * message: &str: human-readable message
+    /// * fields: &[(&str, String)]: structured fields, e.g. serial of certificate
+    ///
+    fn write(&self, level: OutputLevel, message: &str, fields: &[(&str, String)]);
+}
+
+fn format_fields(fields: &[(&str, String)]) -> String{
+    fields.iter().map(|(key, value)| format!(" {}={}", key, value)).collect()
+}
+
+// Formats severity with code of error if fields have one, e.g. `error[MW-CERT-0001]:`, and the rest of fields
+fn format_severity(level: OutputLevel, fields: &[(&str, String)]) -> (String, String){
+    let code = fields.iter().find(|(key, _)| *key == CODE_FIELD);
+    let rest: String = fields.iter()
+        .filter(|(key, _)| *key != CODE_FIELD)
+        .map(|(key, value)| format!(" {}={}", key, value))
+        .collect();
+    match code {
+        Some((_, code)) => (format!("{}[{}]:", level.get_name(), code), rest),
+        None => (format!("{}:", level.get_name()), rest),
+    }
+}
+
+///
+/// Colored output for interactive terminals
+///
+pub struct TerminalOutput;
+
+impl OutputBackend for TerminalOutput {
+    fn write(&self, level: OutputLevel, message: &str, fields: &[(&str, String)]) {
+        let (severity, rest) = format_severity(level, fields);
+        match level {
+            OutputLevel::Info => println!("{}{}", message, format_fields(fields).dimmed()),
+            OutputLevel::Warning => println!("{} {}{}", severity.yellow().bold().underline(), message,
+                                             rest.dimmed()),
+            OutputLevel::Error => println!("{} {}{}", severity.red().bold().underline(), message,
+                                           rest.dimmed()),
+        }
+    }
+}
+
+///
+/// Plain text output for scripts: no colors, warnings and errors go to stderr
+///
+pub struct PlainOutput;
+
+impl OutputBackend for PlainOutput {
+    fn write(&self, level: OutputLevel, message: &str, fields: &[(&str, String)]) {
+        let (severity, rest) = format_severity(level, fields);
+        match level {
+            OutputLevel::Info => println!("{}{}", message, format_fields(fields)),
+            _ => eprintln!("{} {}{}", severity, message, rest),
+        }
+    }
+}
+
+///
+/// Output for scripts parsing messages: one JSON object per line with level, message and
+/// fields, including code of error. Like plain output, warnings and errors go to stderr.
+///
+pub struct JsonOutput;
+
+impl JsonOutput {
+    ///
+    /// Formats message as JSON object
+    ///
+    pub fn format(level: OutputLevel, message: &str, fields: &[(&str, String)]) -> String{
+        let fields: String = fields.iter()
+            .map(|(key, value)| format!(",{}:{}", json_string(key), json_string(value)))
+            .collect();
+        format!("{{\"level\":{},\"message\":{}{}}}", json_string(level.get_name()), json_string(message), fields)
+    }
+}
+
+impl OutputBackend for JsonOutput {
+    fn write(&self, level: OutputLevel, message: &str, fields: &[(&str, String)]) {
+        match level {
+            OutputLevel::Info => println!("{}", Self::format(level, message, fields)),
+            _ => eprintln!("{}", Self::format(level, message, fields)),
+        }
+    }
+}
+
+///
+/// Output to journald with structured fields or, if journald is not running, to syslog
+/// with RFC 5424 structured data. Falls back to plain output if neither is available,
+/// which is always the case on platforms other than unix.
+///
+pub struct SyslogOutput{
+    #[cfg(unix)]
+    socket: Option<UnixDatagram>,
+    journald: bool,
+}
+
+impl SyslogOutput {
+    ///
+    /// Connects to journald or syslog socket
+    ///
+    pub fn new() -> SyslogOutput{
+        #[cfg(unix)]
+        for (path, journald) in [(JOURNALD_SOCKET_PATH, true), (SYSLOG_SOCKET_PATH, false)]{
+            let socket = UnixDatagram::unbound().and_then(|socket| {
+                socket.connect(path)?;
+                Ok(socket)
+            });
+            if let Ok(socket) = socket{
+                return SyslogOutput{
+                    socket: Some(socket),
+                    journald,
+                };
+            }
+        }
+        SyslogOutput{
+            #[cfg(unix)]
+            socket: None,
+            journald: false,
+        }
+    }
+
+    #[cfg(unix)]
+    fn send(&self, datagram: &[u8]) -> bool{
+        self.socket.as_ref().is_some_and(|socket| socket.send(datagram).is_ok())
+    }
+
+    #[cfg(not(unix))]
+    fn send(&self, _datagram: &[u8]) -> bool{
+        false
+    }
+
+    ///
+    /// Converts field name to journald one: uppercase letters, digits and underscores
+    ///
+    fn to_journald_field(key: &str) -> String{
+        let name: String = key.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+            .collect();
+        format!("MWAY_{}", name)
+    }
+
+    fn append_journald_field(datagram: &mut Vec<u8>, key: &str, value: &str){
+        if value.contains('\n'){
+            // Binary-safe format: name, newline, little-endian length, value
+            datagram.extend_from_slice(key.as_bytes());
+            datagram.push(b'\n');
+            datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+            datagram.extend_from_slice(value.as_bytes());
+        } else {
+            datagram.extend_from_slice(format!("{}={}", key, value).as_bytes());
+        }
+        datagram.push(b'\n');
+    }
+
+    ///
+    /// Builds a datagram of journald native protocol
+    ///
+    pub fn format_journald(level: OutputLevel, message: &str, fields: &[(&str, String)]) -> Vec<u8>{
+        let mut datagram = Vec::new();
+        Self::append_journald_field(&mut datagram, "MESSAGE", message);
+        Self::append_journald_field(&mut datagram, "PRIORITY", &level.get_syslog_severity().to_string());
+        Self::append_journald_field(&mut datagram, "SYSLOG_IDENTIFIER", SYSLOG_IDENTIFIER);
+        for (key, value) in fields{
+            Self::append_journald_field(&mut datagram, &Self::to_journald_field(key), value);
+        }
+        datagram
+    }
+
+    ///
+    /// Builds RFC 5424 syslog message
+    ///
+    pub fn format_syslog(level: OutputLevel, message: &str, fields: &[(&str, String)]) -> Vec<u8>{
+        let priority = SYSLOG_FACILITY * 8 + level.get_syslog_severity();
+        let structured_data = if fields.is_empty(){
+            "-".to_string()
+        } else {
+            let parameters: String = fields.iter().map(|(key, value)| {
+                let value = value.replace('\\', "\\\\").replace('"', "\\\"").replace(']', "\\]");
+                format!(" {}=\"{}\"", key, value)
+            }).collect();
+            format!("[{}{}]", SYSLOG_STRUCTURED_DATA_ID, parameters)
+        };
+        format!("<{}>1 - - {} {} - {} {}", priority, SYSLOG_IDENTIFIER, std::process::id(),
+                structured_data, message).into_bytes()
+    }
+}
+
+impl Default for SyslogOutput {
+    fn default() -> Self {
+        SyslogOutput::new()
+    }
+}
+
+impl OutputBackend for SyslogOutput {
+    fn write(&self, level: OutputLevel, message: &str, fields: &[(&str, String)]) {
+        let datagram = if self.journald{
+            Self::format_journald(level, message, fields)
+        } else {
+            Self::format_syslog(level, message, fields)
+        };
+        if !self.send(&datagram){
+            PlainOutput.write(level, message, fields);
+        }
+    }
+}
+
+///
+/// Selects output backend
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputMode{
+    /** Terminal if stdout is a terminal, plain text otherwise **/
+    Auto,
+    Terminal,
+    Plain,
+    Syslog,
+    Json,
+}
+
+impl OutputMode {
+    ///
+    /// Gets mode from MWAY_OUTPUT environment variable, Auto if it is not set or invalid
+    ///
+    pub fn from_environment() -> OutputMode{
+        std::env::var(OUTPUT_MODE_VARIABLE).ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(OutputMode::Auto)
+    }
+
+    ///
+    /// Resolves Auto mode using TTY detection
+    ///
+    pub fn resolve(&self) -> OutputMode{
+        match self {
+            OutputMode::Auto if stdout().is_terminal() => OutputMode::Terminal,
+            OutputMode::Auto => OutputMode::Plain,
+            mode => *mode,
+        }
+    }
+
+    ///
+    /// Creates backend for mode
+    ///
+    pub fn create_backend(&self) -> Box<dyn OutputBackend>{
+        match self.resolve() {
+            OutputMode::Terminal => Box::new(TerminalOutput),
+            OutputMode::Syslog => Box::new(SyslogOutput::new()),
+            OutputMode::Json => Box::new(JsonOutput),
+            _ => Box::new(PlainOutput),
+        }
+    }
+}
+
+impl Display for OutputMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputMode::Auto => write!(f, "auto"),
+            OutputMode::Terminal => write!(f, "terminal"),
+            OutputMode::Plain => write!(f, "plain"),
+            OutputMode::Syslog => write!(f, "syslog"),
+            OutputMode::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl FromStr for OutputMode {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(OutputMode::Auto),
+            "terminal" => Ok(OutputMode::Terminal),
+            "plain" => Ok(OutputMode::Plain),
+            "syslog" | "journald" => Ok(OutputMode::Syslog),
+            "json" => Ok(OutputMode::Json),
+            _ => Err("Unknown output mode"),
+        }
+    }
+}
+
+static OUTPUT: Lazy<RwLock<Box<dyn OutputBackend>>> =
+    Lazy::new(|| RwLock::new(OutputMode::from_environment().create_backend()));
+
+///
+/// Sets output mode for this process, including modules loaded after the call
+///
+/// # Arguments
+/// * mode: OutputMode: new output mode
+///
+pub fn set_output_mode(mode: OutputMode){
+    std::env::set_var(OUTPUT_MODE_VARIABLE, mode.to_string());
+    if mode.resolve() != OutputMode::Terminal{
+        // Tables and prompts use colors directly
+        colored::control::set_override(false);
+        std::env::set_var("NO_COLOR", "1");
+    }
+    *OUTPUT.write().unwrap() = mode.create_backend();
+}
+
+///
+/// Writes a message with structured fields to current output
+///
+pub fn write(level: OutputLevel, message: &str, fields: &[(&str, String)]){
+    OUTPUT.read().unwrap().write(level, message, fields);
+}
+
+///
+/// Writes an informational message
+///
+pub fn info<T: Display>(message: T){
+    write(OutputLevel::Info, &message.to_string(), &[]);
+}
+
+///
+/// Writes a warning
+///
+pub fn warning<T: Display>(message: T){
+    write(OutputLevel::Warning, &message.to_string(), &[]);
+}
+
+///
+/// Writes an error
+///
+pub fn error<T: Display>(message: T){
+    write(OutputLevel::Error, &message.to_string(), &[]);
+}
+
+///
+/// Writes an error together with code of its cause, which `mway explain <code>` describes
+///
+/// # Arguments
+/// * message: T: message shown to user, usually context followed by the error itself
+/// * error: &E: typed error message is about
+///
+pub fn coded_error<T: Display, E: ErrorCode + ?Sized>(message: T, error: &E){
+    write(OutputLevel::Error, &message.to_string(), &[(CODE_FIELD, error.get_error_code().to_string())]);
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_mode_parsing() {
+        for mode in [OutputMode::Auto, OutputMode::Terminal, OutputMode::Plain, OutputMode::Syslog, OutputMode::Json]{
+            assert_eq!(mode.to_string().parse::<OutputMode>(), Ok(mode));
+        }
+        assert_eq!("journald".parse::<OutputMode>(), Ok(OutputMode::Syslog));
+        assert!("colored".parse::<OutputMode>().is_err());
+        assert_ne!(OutputMode::Auto.resolve(), OutputMode::Auto);
+    }
+
+    #[test]
+    fn test_syslog_formats() {
+        let fields = [("serial", "42".to_string()), ("file", "a \"b\"]".to_string())];
+        let syslog = String::from_utf8(SyslogOutput::format_syslog(OutputLevel::Error, "Bad certificate",
+                                                                   &fields)).unwrap();
+        assert!(syslog.starts_with("<11>1 - - mway "));
+        assert!(syslog.ends_with(" [mway@32473 serial=\"42\" file=\"a \\\"b\\\"\\]\"] Bad certificate"));
+
+        let journald = SyslogOutput::format_journald(OutputLevel::Warning, "two\nlines", &fields[..1]);
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&9u64.to_le_bytes());
+        expected.extend_from_slice(b"two\nlines\nPRIORITY=4\nSYSLOG_IDENTIFIER=mway\nMWAY_SERIAL=42\n");
+        assert_eq!(journald, expected);
+    }
+
+    #[test]
+    fn test_coded_error_formats() {
+        let fields = [(CODE_FIELD, "MW-PEER-0007".to_string()), ("peer", "nobody".to_string())];
+        assert_eq!(format_severity(OutputLevel::Error, &fields),
+                   ("error[MW-PEER-0007]:".to_string(), " peer=nobody".to_string()));
+        assert_eq!(format_severity(OutputLevel::Warning, &fields[1..]), ("warning:".to_string(), " peer=nobody".to_string()));
+        assert_eq!(JsonOutput::format(OutputLevel::Error, "unknown \"peer\"", &fields),
+                   "{\"level\":\"error\",\"message\":\"unknown \\\"peer\\\"\",\"code\":\"MW-PEER-0007\",\"peer\":\"nobody\"}");
+    }
+}
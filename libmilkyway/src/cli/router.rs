@@ -1,4 +1,10 @@
 use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use async_trait::async_trait;
+use crate::cli::describe::{CommandDescription, NamespaceDescription};
+use crate::cli::io::spin_while;
+use crate::cli::output;
+use crate::tokio::tokio_block_on;
 
 ///
 /// CommandNamespace is a trait which implements on namespace of commands
@@ -6,16 +12,178 @@ use std::collections::HashMap;
 ///
 pub trait CommandNamespace: Send + Sync{
     fn on_command(&mut self, command: String, args: Vec<String>);
+
+    ///
+    /// Describes commands of namespace for help and completion.
+    /// Namespaces without description are still routed, but not listed.
+    ///
+    fn describe(&self) -> Vec<CommandDescription>{
+        vec![]
+    }
+
+    ///
+    /// Completes value of argument, e.g. serials for `serial=`. Values which come from services
+    /// should be fetched through CompletionCache, so shell stays responsive.
+    ///
+    /// # Arguments
+    /// * command: &str: name of command
+    /// * argument: &str: name of argument
+    /// * prefix: &str: part of value typed by user
+    ///
+    /// returns: Vec<String>: values starting with prefix
+    ///
+    fn complete(&self, _command: &str, _argument: &str, _prefix: &str) -> Vec<String>{
+        vec![]
+    }
+}
+
+///
+/// A namespace which commands need to await, e.g. for network responses.
+///
+/// # Warning
+/// Commands are executed inside tokio runtime, so they must use asynchronous APIs
+/// and must not block on runtime(e.g. through synchronous binders)
+///
+#[async_trait]
+pub trait AsyncCommandNamespace: Send + Sync{
+    async fn on_command_async(&mut self, command: String, args: Vec<String>);
+
+    ///
+    /// Describes commands of namespace for help and completion
+    ///
+    fn describe(&self) -> Vec<CommandDescription>{
+        vec![]
+    }
+
+    ///
+    /// Completes value of argument, see CommandNamespace::complete
+    ///
+    fn complete(&self, _command: &str, _argument: &str, _prefix: &str) -> Vec<String>{
+        vec![]
+    }
+}
+
+///
+/// Every synchronous namespace may be used where asynchronous one is expected
+///
+#[async_trait]
+impl<T: CommandNamespace + ?Sized> AsyncCommandNamespace for T{
+    async fn on_command_async(&mut self, command: String, args: Vec<String>) {
+        self.on_command(command, args);
+    }
+
+    fn describe(&self) -> Vec<CommandDescription> {
+        CommandNamespace::describe(self)
+    }
+
+    fn complete(&self, command: &str, argument: &str, prefix: &str) -> Vec<String> {
+        CommandNamespace::complete(self, command, argument, prefix)
+    }
+}
+
+///
+/// A namespace registered in router. Synchronous namespaces are kept apart, so
+/// they are never executed inside runtime.
+///
+enum RegisteredNamespace{
+    Sync(Box<dyn CommandNamespace>),
+    Async(Box<dyn AsyncCommandNamespace>),
+}
+
+impl RegisteredNamespace {
+    fn describe(&self) -> Vec<CommandDescription>{
+        match self {
+            RegisteredNamespace::Sync(namespace) => CommandNamespace::describe(namespace.as_ref()),
+            RegisteredNamespace::Async(namespace) => AsyncCommandNamespace::describe(namespace.as_ref()),
+        }
+    }
+
+    fn complete(&self, command: &str, argument: &str, prefix: &str) -> Vec<String>{
+        match self {
+            RegisteredNamespace::Sync(namespace) => CommandNamespace::complete(namespace.as_ref(), command, argument, prefix),
+            RegisteredNamespace::Async(namespace) =>
+                AsyncCommandNamespace::complete(namespace.as_ref(), command, argument, prefix),
+        }
+    }
+}
+
+///
+/// Error of resolving a shortened command which prefix matches several commands
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct AmbiguousCommand{
+    /** Path resolved so far including ambiguous word **/
+    pub path: Vec<String>,
+    /** Full words which ambiguous word may mean **/
+    pub candidates: Vec<String>,
+}
+
+impl Display for AmbiguousCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is ambiguous, it may be one of: {}", self.path.join("/"), self.candidates.join(", "))
+    }
 }
 
+///
+/// Completes a word of command path by prefix
+///
+/// # Arguments
+/// * word: &str: word as typed by user
+/// * candidates: &[String]: full words allowed at this place
+///
+/// returns: Result<Option<String>, Vec<String>>: the word itself on exact match,
+/// the only candidate starting with it, None if nothing matches or sorted matching
+/// candidates if there are several
+///
+pub fn complete_word(word: &str, candidates: &[String]) -> Result<Option<String>, Vec<String>>{
+    if candidates.iter().any(|candidate| candidate == word){
+        return Ok(Some(word.to_string()));
+    }
+    let mut matching: Vec<String> = candidates.iter()
+        .filter(|candidate| !word.is_empty() && candidate.starts_with(word))
+        .cloned()
+        .collect();
+    matching.sort();
+    matching.dedup();
+    match matching.len() {
+        0 => Ok(None),
+        1 => Ok(matching.pop()),
+        _ => Err(matching),
+    }
+}
+
+///
+/// Replaces the longest prefix of path which is an alias with its target
+///
+/// # Arguments
+/// * path: &[String]: command path as typed by user
+/// * aliases: &HashMap<Vec<String>, Vec<String>>: targets by alias paths
+///
+/// returns: Vec<String>: expanded path or same path if no alias matches
+///
+pub fn expand_alias(path: &[String], aliases: &HashMap<Vec<String>, Vec<String>>) -> Vec<String>{
+    for length in (1..=path.len()).rev(){
+        if let Some(target) = aliases.get(&path[0..length]){
+            let mut result = target.clone();
+            result.extend_from_slice(&path[length..]);
+            return result;
+        }
+    }
+    path.to_vec()
+}
 
 ///
 /// CommandRouter allows quickly implementing namespaces by just adding path
 /// and namespace.
 ///
+/// Besides exact paths router accepts aliases of namespaces and commands and
+/// unambiguous prefixes of them, e.g. `certman/sign/gen`. Exact paths always win.
+///
 pub struct CommandRouter{
-    namespaces: HashMap<Vec<String>, Box<dyn CommandNamespace>>,
+    namespaces: HashMap<Vec<String>, RegisteredNamespace>,
     subnamespaces: Vec<Vec<String>>,
+    /** Full words by namespace path with alias appended **/
+    aliases: HashMap<Vec<String>, String>,
 }
 
 impl CommandRouter {
@@ -27,7 +195,21 @@ impl CommandRouter {
         CommandRouter{
             namespaces: HashMap::new(),
             subnamespaces: vec![],
+            aliases: HashMap::new(),
+        }
+    }
+
+    fn register(&mut self, namespace_path: Vec<String>, namespace: RegisteredNamespace){
+        if self.namespaces.contains_key(&namespace_path){
+            panic!("Namespace is already registered");
+        }
+        for i in 1..namespace_path.len(){
+            let subpath = namespace_path[0..i].to_vec();
+            if !self.subnamespaces.contains(&subpath){
+                self.subnamespaces.push(subpath);
+            }
         }
+        self.namespaces.insert(namespace_path, namespace);
     }
     
     ///
@@ -44,20 +226,108 @@ impl CommandRouter {
     #[inline]
     pub fn register_namespace(&mut self, namespace_path: Vec<String>, 
                               namespace: Box<dyn CommandNamespace>){
-        if self.namespaces.contains_key(&namespace_path){
-            panic!("Namespace is already registered");
+        self.register(namespace_path, RegisteredNamespace::Sync(namespace));
+    }
+
+    ///
+    /// Adds new asynchronous namespace to router
+    ///
+    /// # Arguments
+    /// * namespace_path: Vec<String>: path to a namespace
+    /// * namespace: Box<dyn AsyncCommandNamespace>: A boxed trait object with handler of particular namespace
+    ///
+    /// # Panics
+    /// * If the namespace is already registered
+    ///
+    #[inline]
+    pub fn register_async_namespace(&mut self, namespace_path: Vec<String>,
+                                    namespace: Box<dyn AsyncCommandNamespace>){
+        self.register(namespace_path, RegisteredNamespace::Async(namespace));
+    }
+    
+    ///
+    /// Adds short form of a subnamespace or command of namespace
+    ///
+    /// # Arguments
+    /// * namespace_path: Vec<String>: path to namespace, empty for top level
+    /// * alias: &str: short form, e.g. `sg`
+    /// * target: &str: full word it stands for, e.g. `signing`
+    ///
+    pub fn add_alias(&mut self, namespace_path: Vec<String>, alias: &str, target: &str){
+        let mut path = namespace_path;
+        path.push(alias.to_string());
+        self.aliases.insert(path, target.to_string());
+    }
+
+    ///
+    /// Gets words allowed after given path: subnamespaces and described commands
+    ///
+    fn get_children(&self, path: &[String]) -> Vec<String>{
+        let mut children: Vec<String> = self.namespaces.keys()
+            .chain(self.subnamespaces.iter())
+            .filter(|namespace| namespace.len() == path.len() + 1 && namespace.starts_with(path))
+            .map(|namespace| namespace[path.len()].clone())
+            .collect();
+        if let Some(namespace) = self.namespaces.get(path){
+            children.extend(namespace.describe().into_iter().map(|command| command.name));
         }
-        for i in 1..namespace_path.len(){
-            let subpath = namespace_path[0..i].to_vec();
-            if !self.subnamespaces.contains(&subpath){
-                self.subnamespaces.push(subpath);
+        children
+    }
+
+    ///
+    /// Checks that path is a namespace or a command which needs no resolving
+    ///
+    fn is_exact(&self, path: &[String]) -> bool{
+        if path.is_empty() || self.is_namespace(&path.to_vec()){
+            return true;
+        }
+        let (command, namespace) = path.split_last().unwrap();
+        match self.namespaces.get(namespace) {
+            None => false,
+            Some(namespace) => {
+                let commands = namespace.describe();
+                // Commands of namespaces without description can not be resolved anyway
+                commands.is_empty() || commands.iter().any(|description| &description.name == command)
             }
         }
-        self.namespaces.insert(namespace_path, namespace);
     }
-    
+
+    ///
+    /// Resolves aliases and prefixes in path. Words which match nothing are kept as is.
+    ///
+    /// # Arguments
+    /// * path: &[String]: path to namespace or command as typed by user
+    ///
+    /// returns: Result<Vec<String>, AmbiguousCommand>: full path or error if some word
+    /// is a prefix of several words
+    ///
+    pub fn resolve(&self, path: &[String]) -> Result<Vec<String>, AmbiguousCommand>{
+        if self.is_exact(path){
+            return Ok(path.to_vec());
+        }
+        let mut result = Vec::<String>::with_capacity(path.len());
+        for word in path{
+            let children = self.get_children(&result);
+            let mut aliased = result.clone();
+            aliased.push(word.clone());
+            let resolved = if children.contains(word) {
+                word.clone()
+            } else if let Some(target) = self.aliases.get(&aliased) {
+                target.clone()
+            } else {
+                match complete_word(word, &children) {
+                    Ok(resolved) => resolved.unwrap_or(word.clone()),
+                    Err(candidates) => return Err(AmbiguousCommand{ path: aliased, candidates }),
+                }
+            };
+            result.push(resolved);
+        }
+        Ok(result)
+    }
+
     ///
-    /// Handles command
+    /// Handles command. Commands of asynchronous namespaces are awaited on tokio runtime
+    /// of current thread with a spinner shown.
     /// 
     /// # Arguments
     /// * command: Vec<String>: a full path to command(including command itself)
@@ -65,21 +335,92 @@ impl CommandRouter {
     /// 
     /// # Panics
     /// * If command vector is empty
+    /// * If asynchronous command is called from inside runtime, use on_command_async there
     /// 
     /// # Returns
-    /// true if command was found, false otherwise
+    /// true if command was found or ambiguity was reported, false otherwise
     /// 
     pub fn on_command(&mut self, command: Vec<String>, arguments: Vec<String>) -> bool{
         if command.len() == 0{
             panic!("Empty command vector");
         } 
+        let command = match self.resolve(&command) {
+            Ok(command) => command,
+            Err(error) => {
+                output::error(error.to_string());
+                return true;
+            }
+        };
         let command_name = command.last().unwrap();
         let namespace = command[0..command.len()-1].to_vec();
-        if !self.namespaces.contains_key(&namespace){
-            return false;
+        match self.namespaces.get_mut(&namespace) {
+            None => false,
+            Some(RegisteredNamespace::Sync(namespace)) => {
+                namespace.on_command(command_name.clone(), arguments);
+                true
+            }
+            Some(RegisteredNamespace::Async(namespace)) => {
+                let message = command.join("/");
+                tokio_block_on(spin_while(&message,
+                                          namespace.on_command_async(command_name.clone(), arguments)));
+                true
+            }
+        }
+    }
+
+    ///
+    /// Handles command from asynchronous context. Synchronous namespaces may block, so they
+    /// are run on blocking thread of runtime instead of worker thread.
+    ///
+    /// # Arguments
+    /// * command: Vec<String>: a full path to command(including command itself)
+    /// * arguments: Vec<String>: all arguments to command
+    ///
+    /// # Panics
+    /// * If command vector is empty
+    ///
+    /// # Returns
+    /// true if command was found or ambiguity was reported, false otherwise
+    ///
+    pub async fn on_command_async(&mut self, command: Vec<String>, arguments: Vec<String>) -> bool{
+        if command.is_empty(){
+            panic!("Empty command vector");
+        }
+        let command = match self.resolve(&command) {
+            Ok(command) => command,
+            Err(error) => {
+                output::error(error.to_string());
+                return true;
+            }
+        };
+        let command_name = command.last().unwrap().clone();
+        let path = command[0..command.len()-1].to_vec();
+        match self.namespaces.get_mut(&path) {
+            None => false,
+            Some(RegisteredNamespace::Sync(_)) => {
+                // Namespace is moved to blocking thread and put back once command is done
+                let mut namespace = match self.namespaces.remove(&path) {
+                    Some(RegisteredNamespace::Sync(namespace)) => namespace,
+                    _ => unreachable!(),
+                };
+                let handled = tokio::task::spawn_blocking(move || {
+                    namespace.on_command(command_name, arguments);
+                    namespace
+                }).await;
+                match handled {
+                    Ok(namespace) => {
+                        self.namespaces.insert(path, RegisteredNamespace::Sync(namespace));
+                    }
+                    Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+                    Err(error) => log::error!("Command {} is cancelled: {}", command.join("/"), error),
+                }
+                true
+            }
+            Some(RegisteredNamespace::Async(namespace)) => {
+                namespace.on_command_async(command_name, arguments).await;
+                true
+            }
         }
-        self.namespaces.get_mut(&namespace).unwrap().on_command(command_name.clone(), arguments);
-        true
     }
     
     
@@ -95,12 +436,53 @@ impl CommandRouter {
     pub fn is_namespace(&self, path: &Vec<String>) -> bool{
         self.namespaces.contains_key(path) || self.subnamespaces.contains(path)
     }
+
+    ///
+    /// Completes value of argument of command
+    ///
+    /// # Arguments
+    /// * command: &[String]: path to command as typed by user
+    /// * argument: &str: name of argument
+    /// * prefix: &str: part of value typed by user
+    ///
+    /// returns: Vec<String>: values starting with prefix, empty if command is unknown
+    ///
+    pub fn complete(&self, command: &[String], argument: &str, prefix: &str) -> Vec<String>{
+        let command = match self.resolve(command) {
+            Ok(command) if !command.is_empty() => command,
+            _ => return vec![],
+        };
+        let (command_name, namespace) = command.split_last().unwrap();
+        match self.namespaces.get(namespace) {
+            Some(namespace) => namespace.complete(command_name, argument, prefix).into_iter()
+                .filter(|value| value.starts_with(prefix))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    ///
+    /// Describes all registered namespaces
+    ///
+    /// returns: Vec<NamespaceDescription>: descriptions sorted by path
+    ///
+    pub fn describe(&self) -> Vec<NamespaceDescription>{
+        let mut result: Vec<NamespaceDescription> = self.namespaces.iter()
+            .map(|(path, namespace)| NamespaceDescription{
+                path: path.clone(),
+                commands: namespace.describe(),
+            })
+            .collect();
+        result.sort_by(|a, b| a.path.cmp(&b.path));
+        result
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::sync::{Arc, Mutex};
+    use crate::tokio::init_tokio;
 
     // Mock CommandNamespace implementation for testing
     struct MockNamespace {
@@ -180,6 +562,134 @@ mod tests {
         assert!(!result);
     }
 
+    #[test]
+    fn test_describe() {
+        let mut router = CommandRouter::new();
+        router.register_namespace(vec!["certman".to_string(), "signing".to_string()],
+                                  Box::new(MockNamespace::new()));
+        router.register_namespace(vec!["certman".to_string(), "encryption".to_string()],
+                                  Box::new(MockNamespace::new()));
+        let paths: Vec<Vec<String>> = router.describe().into_iter().map(|namespace| namespace.path).collect();
+        assert_eq!(paths, vec![vec!["certman".to_string(), "encryption".to_string()],
+                               vec!["certman".to_string(), "signing".to_string()]]);
+    }
+
+    struct MockAsyncNamespace {
+        received_commands: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl AsyncCommandNamespace for MockAsyncNamespace {
+        async fn on_command_async(&mut self, command: String, _args: Vec<String>) {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            self.received_commands.lock().unwrap().push(command);
+        }
+    }
+
+    #[test]
+    fn test_on_command_async_namespace() {
+        init_tokio();
+        let mut router = CommandRouter::new();
+        let received_commands = Arc::new(Mutex::new(Vec::new()));
+        router.register_async_namespace(vec!["ping".to_string()],
+                                        Box::new(MockAsyncNamespace{ received_commands: received_commands.clone() }));
+        let sync_namespace = MockNamespace::new();
+        let sync_commands = sync_namespace.get_received_commands();
+        router.register_namespace(vec!["certman".to_string()], Box::new(sync_namespace));
+
+        assert!(router.on_command(vec!["ping".to_string(), "host".to_string()], vec![]));
+        assert_eq!(*received_commands.lock().unwrap(), vec!["host".to_string()]);
+        for _ in 0..2{
+            let result = tokio_block_on(router.on_command_async(vec!["certman".to_string(), "show".to_string()],
+                                                                vec![]));
+            assert!(result);
+        }
+        assert_eq!(sync_commands.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_sync_namespace_as_async() {
+        init_tokio();
+        let mut namespace: Box<dyn AsyncCommandNamespace> = Box::new(MockNamespace::new());
+        tokio_block_on(namespace.on_command_async("show".to_string(), vec![]));
+    }
+
+    struct DescribedNamespace;
+
+    impl CommandNamespace for DescribedNamespace {
+        fn on_command(&mut self, _command: String, _args: Vec<String>) {}
+
+        fn describe(&self) -> Vec<CommandDescription> {
+            vec![CommandDescription::new("generate", "", vec![]),
+                 CommandDescription::new("get", "", vec![]),
+                 CommandDescription::new("show", "", vec![])]
+        }
+
+        fn complete(&self, command: &str, argument: &str, _prefix: &str) -> Vec<String> {
+            if command == "get" && argument == "serial" {
+                vec!["12".to_string(), "100".to_string(), "200".to_string()]
+            } else {
+                vec![]
+            }
+        }
+    }
+
+    fn to_path(path: &str) -> Vec<String>{
+        path.split("/").map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut router = CommandRouter::new();
+        router.register_namespace(to_path("certman/signing"), Box::new(DescribedNamespace));
+        router.register_namespace(to_path("certman/encryption"), Box::new(DescribedNamespace));
+        router.register_namespace(to_path("certman/group"), Box::new(DescribedNamespace));
+        router.add_alias(vec![], "cm", "certman");
+        router.add_alias(to_path("certman"), "sg", "signing");
+        router.add_alias(to_path("certman/signing"), "gen", "generate");
+        // Namespace with the same name as an alias wins
+        router.register_namespace(to_path("certman/sg"), Box::new(DescribedNamespace));
+
+        assert_eq!(router.resolve(&to_path("cm/sg/gen")).unwrap(), to_path("certman/sg/generate"));
+        assert_eq!(router.resolve(&to_path("cm/sig/gen")).unwrap(), to_path("certman/signing/generate"));
+        assert_eq!(router.resolve(&to_path("cert/enc/sh")).unwrap(), to_path("certman/encryption/show"));
+        assert_eq!(router.resolve(&to_path("certman/e")).unwrap(), to_path("certman/encryption"));
+        assert_eq!(router.resolve(&to_path("certman/unknown/x")).unwrap(), to_path("certman/unknown/x"));
+        assert_eq!(router.resolve(&to_path("cm/s/show")),
+                   Err(AmbiguousCommand{ path: to_path("certman/s"),
+                                         candidates: vec!["sg".to_string(), "signing".to_string()] }));
+        assert_eq!(router.resolve(&to_path("cm/group/g")).unwrap_err().candidates,
+                   vec!["generate".to_string(), "get".to_string()]);
+
+        let namespace = MockNamespace::new();
+        let received_commands = namespace.get_received_commands();
+        router.register_namespace(to_path("ping"), Box::new(namespace));
+        assert!(router.on_command(to_path("pi/host"), vec![]));
+        assert_eq!(received_commands.lock().unwrap()[0].0, "host");
+        assert!(router.on_command(to_path("cm/s/show"), vec![]));
+    }
+
+    #[test]
+    fn test_complete_argument() {
+        let mut router = CommandRouter::new();
+        router.register_namespace(to_path("certman/signing"), Box::new(DescribedNamespace));
+        assert_eq!(router.complete(&to_path("certman/signing/get"), "serial", "1"), vec!["12", "100"]);
+        assert_eq!(router.complete(&to_path("cert/sig/get"), "serial", "2"), vec!["200"]);
+        assert!(router.complete(&to_path("certman/signing/get"), "file", "").is_empty());
+        assert!(router.complete(&to_path("certman/unknown/get"), "serial", "").is_empty());
+    }
+
+    #[test]
+    fn test_expand_alias() {
+        let aliases = HashMap::from([(to_path("gen"), to_path("certman/signing/generate")),
+                                     (to_path("cm"), to_path("certman"))]);
+        assert_eq!(expand_alias(&to_path("cm/signing"), &aliases), to_path("certman/signing"));
+        assert_eq!(expand_alias(&to_path("gen"), &aliases), to_path("certman/signing/generate"));
+        assert_eq!(expand_alias(&to_path("peers/cm"), &aliases), to_path("peers/cm"));
+        assert_eq!(complete_word("cert", &["certman".to_string(), "modules".to_string()]), Ok(Some("certman".to_string())));
+        assert_eq!(complete_word("x", &["certman".to_string()]), Ok(None));
+    }
+
     #[test]
     #[should_panic(expected = "Empty command vector")]
     fn test_on_command_empty_command() {
@@ -30,11 +30,41 @@ impl Table {
     /// Prints table to the console
     ///
     pub fn display(&self) {
+        self.print_headers();
+        self.print_rows();
+    }
+
+    ///
+    /// Prints table page by page, so rows are shown as soon as they are fetched and
+    /// never kept all at once. Rows added before call are printed as first page.
+    ///
+    /// # Arguments
+    /// * next_page: F: adds rows of next page to table, returns whether more pages follow
+    ///
+    /// returns: usize: number of printed rows
+    ///
+    pub fn display_pages<F>(&mut self, mut next_page: F) -> usize where F: FnMut(&mut Table) -> bool {
+        self.print_headers();
+        let mut count = 0;
+        loop {
+            let has_more = next_page(self);
+            self.print_rows();
+            count += self.rows.len();
+            self.rows.clear();
+            if !has_more {
+                return count;
+            }
+        }
+    }
+
+    fn print_headers(&self) {
         for header in &self.headers {
             print!("{:<15}", header.bold().underline().blue());
         }
         println!();
-        
+    }
+
+    fn print_rows(&self) {
         for row in &self.rows {
             for cell in row {
                 print!("{:<15}", cell.green());
@@ -42,4 +72,4 @@ impl Table {
             println!();
         }
     }
-}
\ No newline at end of file
+}
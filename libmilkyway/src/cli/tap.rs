@@ -0,0 +1,105 @@
+use crate::cli::output;
+use crate::cli::arguments::parse_arguments;
+use crate::cli::describe::{ArgumentDescription, CommandDescription};
+use crate::cli::router::CommandNamespace;
+use crate::cli::table::Table;
+use crate::transport::tap::{SharedTransportTap, TapDirection, TapRecord};
+
+///
+/// Implements `transport tap` namespace with `show` and `clear` commands
+///
+pub struct TransportTapNamespace{
+    tap: Option<SharedTransportTap>,
+}
+
+impl TransportTapNamespace {
+    ///
+    /// Creates namespace over a tap
+    ///
+    /// # Arguments
+    /// * tap: Option<SharedTransportTap>: tap of transport service, None if tapping is disabled
+    ///
+    pub fn new(tap: Option<SharedTransportTap>) -> TransportTapNamespace{
+        TransportTapNamespace{
+            tap,
+        }
+    }
+
+    fn format_record(record: &TapRecord) -> Vec<String>{
+        let direction = match record.direction {
+            TapDirection::Incoming => "IN",
+            TapDirection::Outgoing => "OUT",
+        };
+        let payload = match &record.payload {
+            Some(payload) => format!("{:?}", payload),
+            None => "-".to_string(),
+        };
+        vec![record.timestamp.to_string(), direction.to_string(),
+             format!("{:?}", record.message_type), record.source.to_string(),
+             record.destination.to_string(), record.module_id.to_string(),
+             record.size.to_string(), payload]
+    }
+
+    pub fn show(&mut self, tap: SharedTransportTap, args: Vec<String>){
+        let argmap = parse_arguments(args);
+        let mut records = tap.lock().unwrap().get_records();
+        if argmap.contains_key("last"){
+            let last = argmap.get("last").unwrap();
+            if last.is_none(){
+                output::error("Argument 'last' requires a value");
+                return;
+            }
+            let last = last.clone().unwrap().parse::<usize>();
+            if last.is_err(){
+                output::error("Argument 'last' must be a positive number");
+                return;
+            }
+            let last = last.unwrap();
+            if records.len() > last{
+                records = records.split_off(records.len() - last);
+            }
+        }
+        let mut table = Table::new(vec!["TIMESTAMP", "DIRECTION", "TYPE", "SOURCE", "DESTINATION",
+                                        "MODULE", "SIZE", "PAYLOAD"]);
+        for record in records.iter(){
+            let row = Self::format_record(record);
+            table.add_row(row.iter().map(|cell| cell.as_str()).collect());
+        }
+        table.display();
+    }
+
+    pub fn clear(&mut self, tap: SharedTransportTap){
+        tap.lock().unwrap().clear();
+        println!("Transport tap is cleared");
+    }
+}
+
+impl CommandNamespace for TransportTapNamespace{
+    fn on_command(&mut self, command: String, args: Vec<String>) {
+        if self.tap.is_none(){
+            output::error("Transport tap is not enabled on this host");
+            return;
+        }
+        let tap = self.tap.clone().unwrap();
+        match command.as_str() {
+            "show" => {
+                self.show(tap, args);
+            }
+            "clear" => {
+                self.clear(tap);
+            }
+            &_ => {
+                output::error("No such command");
+            }
+        }
+    }
+
+    fn describe(&self) -> Vec<CommandDescription> {
+        vec![
+            CommandDescription::new("show", "Shows recently passed messages", vec![
+                ArgumentDescription::optional("last", "Show only N newest messages"),
+            ]),
+            CommandDescription::new("clear", "Removes all recorded messages", vec![]),
+        ]
+    }
+}
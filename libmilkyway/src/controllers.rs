@@ -2,4 +2,19 @@
 /// Module containing a controller for authorization and establishing secure communications
 /// mechanisms
 /// 
-pub mod authorization;
\ No newline at end of file
+pub mod authorization;
+
+///
+/// Module containing admin control channel of daemon
+///
+pub mod admin;
+
+///
+/// Module containing HTTP gateway for external integrations
+///
+pub mod gateway;
+
+///
+/// Module containing ordered steps of graceful shutdown
+///
+pub mod shutdown;
@@ -0,0 +1,727 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+#[cfg(unix)]
+use std::fs::{DirBuilder, Permissions};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::str::FromStr;
+#[cfg(unix)]
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+#[cfg(unix)]
+use std::time::Duration;
+use libmilkyway_derive::{Deserializable, EnumDeserializable, EnumSerializable, Serializable};
+#[cfg(unix)]
+use crate::controllers::authorization::factor::get_peer_credentials;
+use crate::controllers::authorization::factor::{OsUserFactor, PeerCredentials};
+use crate::get_timestamp_with_milliseconds;
+#[cfg(unix)]
+use crate::module::isolated::{read_frame, write_frame};
+use crate::module::supervisor::ModuleStatus;
+use crate::pki::certificate::{Certificate, FLAG_NO_READ, FLAG_NO_WRITE, FLAG_REQUIRE_2FA};
+use crate::pki::hash::HashType;
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+use crate::pki::impls::CryptoError;
+use crate::pki::signature::Signature;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::services::certificate::CertificateService;
+use crate::transport::operator::is_operator_certificate;
+use crate::transport::stats::SharedFrameStats;
+
+///
+/// Default path of admin socket of daemon
+///
+pub const DEFAULT_ADMIN_SOCKET_PATH: &str = "/run/mway/admin.sock";
+
+///
+/// Milliseconds admin request is accepted after it was signed
+///
+pub const ADMIN_REQUEST_LIFETIME: u128 = 30_000;
+
+///
+/// Seconds connection to admin socket may stay silent before it is closed
+///
+pub const ADMIN_READ_TIMEOUT_SECONDS: u64 = 30;
+
+///
+/// Commands of admin channel
+///
+#[derive(Clone, Copy, Debug, PartialEq, EnumSerializable, EnumDeserializable)]
+pub enum AdminCommand{
+    /** Uptime, peers and modules of daemon **/
+    Status,
+    /** Reloads configuration **/
+    Reload,
+    /** Stops accepting connections, connections in flight are finished **/
+    Drain,
+    /** Changes maximal level of logs, argument is level name **/
+    SetLogLevel,
+    /** Re-reads certificate store changed on disk(see CertificateService::reload) **/
+    ReloadCertificates,
+    /** Shows frame statistics, argument `on`, `off` or `reset` controls their collection **/
+    FrameStats,
+}
+
+impl AdminCommand {
+    ///
+    /// Finds command by name used in CLI, e.g. `log-level`
+    ///
+    pub fn from_name(name: &str) -> Option<AdminCommand>{
+        match name {
+            "status" => Some(AdminCommand::Status),
+            "reload" => Some(AdminCommand::Reload),
+            "drain" => Some(AdminCommand::Drain),
+            "log-level" => Some(AdminCommand::SetLogLevel),
+            "reload-certificates" => Some(AdminCommand::ReloadCertificates),
+            "frame-stats" => Some(AdminCommand::FrameStats),
+            _ => None,
+        }
+    }
+
+    ///
+    /// Checks whether command changes state of daemon, such commands are denied
+    /// to certificates with FLAG_NO_WRITE
+    ///
+    #[inline]
+    pub fn is_write(&self) -> bool{
+        *self != AdminCommand::Status
+    }
+}
+
+///
+/// Reasons why admin request is refused
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum AdminError{
+    /** Signing certificate is not known to daemon **/
+    UnknownSigner(u128),
+    /** Signer is not operator certificate(user-cert and sign-messages flags) or is not trusted **/
+    NotOperator(u128),
+    /** Request is not signed or signature is not valid **/
+    InvalidSignature,
+    /** Request was signed too long ago **/
+    Expired,
+    /** Request was already handled **/
+    Replayed,
+    /** Flags of signer forbid command **/
+    Forbidden,
+    /** Signer requires second factor and user connected to socket is not allowed to use it **/
+    UserNotAllowed(u128),
+    InvalidArgument(String),
+    /** Daemon failed to execute command **/
+    Failed(String),
+}
+
+impl Display for AdminError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AdminError::UnknownSigner(serial) => write!(f, "signing certificate {} is not known to daemon", serial),
+            AdminError::NotOperator(serial) => write!(f, "certificate {} is not a trusted operator certificate", serial),
+            AdminError::InvalidSignature => write!(f, "signature of request is not valid"),
+            AdminError::Expired => write!(f, "request is expired"),
+            AdminError::Replayed => write!(f, "request was already handled"),
+            AdminError::Forbidden => write!(f, "flags of certificate forbid this command"),
+            AdminError::UserNotAllowed(serial) => write!(f, "user is not allowed to use certificate {}", serial),
+            AdminError::InvalidArgument(error) => write!(f, "invalid argument: {}", error),
+            AdminError::Failed(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+///
+/// Command sent to daemon, signed by operator certificate
+///
+#[derive(Clone, Serializable, Deserializable)]
+pub struct AdminRequest{
+    pub command: AdminCommand,
+    /** Argument of command, e.g. level of SetLogLevel **/
+    pub argument: Option<String>,
+    pub timestamp: u128,
+    /** Random number which makes every request unique **/
+    pub nonce: u128,
+    pub signer_serial: u128,
+    pub signature: Option<Signature>,
+}
+
+impl AdminRequest {
+    pub fn new(command: AdminCommand, argument: Option<String>) -> AdminRequest{
+        AdminRequest{
+            command,
+            argument,
+            timestamp: get_timestamp_with_milliseconds(),
+            nonce: rand::random(),
+            signer_serial: 0,
+            signature: None,
+        }
+    }
+
+    ///
+    /// Checks whether request changes state of daemon(see AdminCommand::is_write), showing
+    /// frame statistics does not
+    ///
+    pub fn is_write(&self) -> bool{
+        match self.command {
+            AdminCommand::FrameStats => !matches!(self.argument.as_deref(), None | Some("show")),
+            command => command.is_write(),
+        }
+    }
+
+    pub fn clone_without_signature(&self) -> AdminRequest{
+        let mut request = self.clone();
+        request.signature = None;
+        request
+    }
+
+    ///
+    /// Signs request with operator certificate
+    ///
+    /// # Arguments
+    /// * certificate: &Falcon1024Certificate: certificate with secret key
+    ///
+    pub fn sign(&mut self, certificate: &Falcon1024Certificate) -> Result<&mut Self, CryptoError>{
+        self.signer_serial = certificate.get_serial();
+        self.signature = Some(certificate.sign_data(&self.clone_without_signature(), HashType::None)?);
+        Ok(self)
+    }
+}
+
+///
+/// State of daemon reported by Status command
+///
+#[derive(Clone, Debug, Default, PartialEq, Serializable, Deserializable)]
+pub struct DaemonStatus{
+    pub uptime_seconds: u64,
+    /** IDs of connected peers **/
+    pub peers: Vec<u128>,
+    pub modules: Vec<ModuleReport>,
+    pub is_draining: bool,
+    pub log_level: String,
+}
+
+///
+/// Health of module of daemon reported by Status command
+///
+#[derive(Clone, Debug, Default, PartialEq, Serializable, Deserializable)]
+pub struct ModuleReport{
+    pub name: String,
+    /** ModuleHealth as it is shown to operator, e.g. running **/
+    pub health: String,
+    pub failures: u32,
+    pub restarts: u32,
+    /** Callback which panicked last and panic message **/
+    pub last_error: Option<String>,
+}
+
+impl From<&ModuleStatus> for ModuleReport {
+    fn from(status: &ModuleStatus) -> Self {
+        ModuleReport{
+            name: status.name.clone(),
+            health: status.health.to_string(),
+            failures: status.failures,
+            restarts: status.restarts,
+            last_error: status.last_error.clone(),
+        }
+    }
+}
+
+///
+/// Answer of daemon
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable)]
+pub struct AdminResponse{
+    /** Description of error, None if command succeeded **/
+    pub error: Option<String>,
+    pub message: String,
+    /** Set for Status command **/
+    pub status: Option<DaemonStatus>,
+}
+
+///
+/// Daemon side of admin commands
+///
+pub trait AdminHandler: Send{
+    ///
+    /// Gets state of daemon, log level is filled in by AdminServer
+    ///
+    fn get_status(&mut self) -> DaemonStatus;
+
+    ///
+    /// Reloads configuration
+    ///
+    /// returns: Result<String, String>: description of what was reloaded or error
+    ///
+    fn reload(&mut self) -> Result<String, String>;
+
+    ///
+    /// Stops accepting new connections and lets connections in flight finish
+    ///
+    /// returns: Result<String, String>: description of drain or error
+    ///
+    fn drain(&mut self) -> Result<String, String>;
+}
+
+///
+/// Serves admin channel of daemon. Requests must be signed by a trusted operator
+/// certificate(user-cert and sign-messages flags); certificates with no-read flag may not
+/// get status and ones with no-write flag may not run other commands. Each request is
+/// accepted only once and only within ADMIN_REQUEST_LIFETIME after it was signed.
+/// Once OS user factor is set, operators whose certificates require second factor must also
+/// connect as one of users allowed to use certificate.
+///
+pub struct AdminServer<S: CertificateService>{
+    certificates: S,
+    handler: Box<dyn AdminHandler>,
+    /** Nonces of handled requests with their timestamps **/
+    nonces: HashMap<u128, u128>,
+    frame_stats: Option<SharedFrameStats>,
+    os_user_factor: Option<OsUserFactor>,
+}
+
+impl<S: CertificateService> AdminServer<S> {
+    ///
+    /// Creates admin server
+    ///
+    /// # Arguments
+    /// * certificates: S: certificates operators are verified against
+    /// * handler: Box<dyn AdminHandler>: daemon executing commands
+    ///
+    pub fn new(certificates: S, handler: Box<dyn AdminHandler>) -> AdminServer<S>{
+        AdminServer{
+            certificates,
+            handler,
+            nonces: HashMap::new(),
+            frame_stats: None,
+            os_user_factor: None,
+        }
+    }
+
+    ///
+    /// Sets frame statistics of daemon's transports, FrameStats command fails without them
+    ///
+    pub fn set_frame_stats(&mut self, stats: SharedFrameStats) -> &mut Self{
+        self.frame_stats = Some(stats);
+        self
+    }
+
+    ///
+    /// Sets users allowed to use operator certificates with FLAG_REQUIRE_2FA, without it the
+    /// flag is not checked
+    ///
+    pub fn set_os_user_factor(&mut self, factor: OsUserFactor) -> &mut Self{
+        self.os_user_factor = Some(factor);
+        self
+    }
+
+    ///
+    /// Checks signature, freshness and signer of request
+    ///
+    /// # Arguments
+    /// * request: &AdminRequest: request to check
+    /// * peer: Option<&PeerCredentials>: credentials of user connected to socket, if known
+    ///
+    /// returns: Result<Falcon1024Certificate, AdminError>: certificate of operator
+    ///
+    pub fn authenticate(&mut self, request: &AdminRequest,
+                        peer: Option<&PeerCredentials>) -> Result<Falcon1024Certificate, AdminError>{
+        let signature = request.signature.as_ref().ok_or(AdminError::InvalidSignature)?;
+        let certificate = self.certificates.get_signing_certificate(request.signer_serial)
+            .ok_or(AdminError::UnknownSigner(request.signer_serial))?;
+        if !is_operator_certificate(&certificate) || !self.certificates.verify_signing_certificate(&certificate){
+            return Err(AdminError::NotOperator(request.signer_serial));
+        }
+        if !certificate.verify_signature(&request.clone_without_signature(), signature){
+            return Err(AdminError::InvalidSignature);
+        }
+        let now = get_timestamp_with_milliseconds();
+        if now.abs_diff(request.timestamp) > ADMIN_REQUEST_LIFETIME{
+            return Err(AdminError::Expired);
+        }
+        self.nonces.retain(|_, timestamp| now.abs_diff(*timestamp) <= ADMIN_REQUEST_LIFETIME);
+        if self.nonces.insert(request.nonce, request.timestamp).is_some(){
+            return Err(AdminError::Replayed);
+        }
+        let forbidden = if request.is_write() { FLAG_NO_WRITE } else { FLAG_NO_READ };
+        if certificate.check_flag(forbidden){
+            return Err(AdminError::Forbidden);
+        }
+        if let Some(factor) = &self.os_user_factor{
+            if certificate.check_flag(FLAG_REQUIRE_2FA) && !factor.is_allowed(certificate.get_serial(), peer){
+                return Err(AdminError::UserNotAllowed(certificate.get_serial()));
+            }
+        }
+        Ok(certificate)
+    }
+
+    fn execute(&mut self, request: &AdminRequest) -> Result<AdminResponse, AdminError>{
+        let mut response = AdminResponse{
+            error: None,
+            message: String::new(),
+            status: None,
+        };
+        match request.command {
+            AdminCommand::Status => {
+                let mut status = self.handler.get_status();
+                status.log_level = log::max_level().to_string();
+                response.status = Some(status);
+            }
+            AdminCommand::Reload => response.message = self.handler.reload().map_err(AdminError::Failed)?,
+            AdminCommand::Drain => response.message = self.handler.drain().map_err(AdminError::Failed)?,
+            AdminCommand::SetLogLevel => {
+                let level = request.argument.as_deref()
+                    .and_then(|level| log::LevelFilter::from_str(level).ok())
+                    .ok_or_else(|| AdminError::InvalidArgument(format!("unknown log level {:?}", request.argument)))?;
+                log::set_max_level(level);
+                response.message = format!("Log level is set to {}", level);
+            }
+            AdminCommand::ReloadCertificates => {
+                let report = self.certificates.reload().map_err(|error| AdminError::Failed(error.to_string()))?;
+                let mut lines = vec![format!("Reloaded certificate store: {} changes, {} conflicts",
+                                             report.changes.len(), report.conflicts.len())];
+                lines.extend(report.changes.iter().map(|change| change.to_string()));
+                lines.extend(report.conflicts.iter().map(|serial| format!("kept uncommitted certificate {}", serial)));
+                response.message = lines.join("\n");
+            }
+            AdminCommand::FrameStats => {
+                let stats = self.frame_stats.as_ref()
+                    .ok_or_else(|| AdminError::Failed("daemon does not collect frame statistics".to_string()))?;
+                response.message = match request.argument.as_deref() {
+                    None | Some("show") => {
+                        let state = if stats.is_enabled() { "enabled" } else { "disabled" };
+                        format!("Frame statistics are {}\n{}", state, stats.get_counters())
+                    }
+                    Some("on") => {
+                        stats.set_enabled(true);
+                        "Frame statistics are enabled".to_string()
+                    }
+                    Some("off") => {
+                        stats.set_enabled(false);
+                        "Frame statistics are disabled".to_string()
+                    }
+                    Some("reset") => {
+                        stats.reset();
+                        "Frame statistics are reset".to_string()
+                    }
+                    Some(argument) => return Err(AdminError::InvalidArgument(
+                        format!("expected on, off, show or reset, got {}", argument))),
+                };
+            }
+        }
+        Ok(response)
+    }
+
+    ///
+    /// Authenticates and executes request
+    ///
+    /// # Arguments
+    /// * request: &AdminRequest: request to handle
+    /// * peer: Option<&PeerCredentials>: credentials of user connected to socket, if known
+    ///
+    pub fn handle(&mut self, request: &AdminRequest, peer: Option<&PeerCredentials>) -> AdminResponse{
+        let result = self.authenticate(request, peer).and_then(|certificate| {
+            log::info!("Admin command {:?} by operator {}", request.command, certificate.get_serial());
+            self.execute(request)
+        });
+        result.unwrap_or_else(|error| {
+            log::warn!("Admin command {:?} signed by {} is refused: {}", request.command, request.signer_serial, error);
+            AdminResponse{
+                error: Some(error.to_string()),
+                message: String::new(),
+                status: None,
+            }
+        })
+    }
+
+    ///
+    /// Handles requests of connection until it is closed
+    ///
+    #[cfg(unix)]
+    pub fn serve_connection(&mut self, stream: &mut UnixStream) -> std::io::Result<()>{
+        let peer = get_peer_credentials(stream)?;
+        serve_requests(stream, |request| self.handle(request, Some(&peer)))
+    }
+}
+
+impl<S: CertificateService + Send + 'static> AdminServer<S> {
+    ///
+    /// Listens on unix socket, accessible only by owner of daemon. Every connection is
+    /// served on its own thread and closed once it is silent for ADMIN_READ_TIMEOUT_SECONDS,
+    /// requests are handled one by one.
+    ///
+    /// # Arguments
+    /// * path: &Path: path of socket, stale socket is replaced
+    ///
+    #[cfg(unix)]
+    pub fn listen(self, path: &Path) -> std::io::Result<JoinHandle<()>>{
+        if path.exists(){
+            std::fs::remove_file(path)?;
+        }
+        let listener = bind_private(path)?;
+        let server = Arc::new(Mutex::new(self));
+        Ok(std::thread::spawn(move || {
+            for stream in listener.incoming(){
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(error) => {
+                        log::warn!("Admin connection failed: {}", error);
+                        continue;
+                    }
+                };
+                let server = server.clone();
+                std::thread::spawn(move || {
+                    let result = stream.set_read_timeout(Some(Duration::from_secs(ADMIN_READ_TIMEOUT_SECONDS)))
+                        .and_then(|_| get_peer_credentials(&stream))
+                        .and_then(|peer| serve_requests(&mut stream, |request| {
+                            server.lock().unwrap().handle(request, Some(&peer))
+                        }));
+                    if let Err(error) = result{
+                        log::warn!("Admin connection failed: {}", error);
+                    }
+                });
+            }
+        }))
+    }
+
+    ///
+    /// Admin socket is a unix socket, on other platforms daemon runs without it
+    ///
+    #[cfg(not(unix))]
+    pub fn listen(self, _path: &Path) -> std::io::Result<JoinHandle<()>>{
+        Err(admin_socket_unsupported())
+    }
+}
+
+// Reads requests of connection and writes responses until connection is closed
+#[cfg(unix)]
+fn serve_requests<F: FnMut(&AdminRequest) -> AdminResponse>(stream: &mut UnixStream, mut handle: F)
+                                                             -> std::io::Result<()>{
+    loop {
+        let data = match read_frame(stream) {
+            Ok(data) => data,
+            Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        let response = match AdminRequest::from_serialized(&data) {
+            Ok((request, _)) => handle(&request),
+            Err(_) => AdminResponse{
+                error: Some("malformed request".to_string()),
+                message: String::new(),
+                status: None,
+            },
+        };
+        write_frame(stream, &response.serialize())?;
+    }
+}
+
+// Binds socket in a directory only owner may enter and moves it into place once its
+// permissions are restricted, so socket is never reachable by others
+#[cfg(unix)]
+fn bind_private(path: &Path) -> std::io::Result<UnixListener>{
+    let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let staging = parent.join(format!(".mway-admin-{}", rand::random::<u64>()));
+    DirBuilder::new().mode(0o700).create(&staging)?;
+    let staged = staging.join("admin.sock");
+    let result = UnixListener::bind(&staged).and_then(|listener| {
+        std::fs::set_permissions(&staged, Permissions::from_mode(0o600))?;
+        std::fs::rename(&staged, path)?;
+        Ok(listener)
+    });
+    if result.is_err(){
+        let _ = std::fs::remove_file(&staged);
+    }
+    let _ = std::fs::remove_dir(&staging);
+    result
+}
+
+///
+/// Sends request to admin socket of daemon and waits for answer
+///
+/// # Arguments
+/// * path: &Path: path of admin socket
+/// * request: &AdminRequest: signed request
+///
+#[cfg(unix)]
+pub fn send_admin_request(path: &Path, request: &AdminRequest) -> std::io::Result<AdminResponse>{
+    let mut stream = UnixStream::connect(path)?;
+    write_frame(&mut stream, &request.serialize())?;
+    let data = read_frame(&mut stream)?;
+    AdminResponse::from_serialized(&data)
+        .map(|(response, _)| response)
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "Malformed admin response"))
+}
+
+///
+/// Admin socket is a unix socket, on other platforms there is no daemon to send request to
+///
+#[cfg(not(unix))]
+pub fn send_admin_request(_path: &Path, _request: &AdminRequest) -> std::io::Result<AdminResponse>{
+    Err(admin_socket_unsupported())
+}
+
+#[cfg(not(unix))]
+fn admin_socket_unsupported() -> std::io::Error{
+    std::io::Error::new(std::io::ErrorKind::Unsupported, "admin socket is supported on unix only")
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::pki::impls::keys::falcon1024::generate_falcon1024_keypair_from_seed;
+    use crate::services::certificate::{CertificateServiceError, ROOT_CERTIFICATE_SERIAL};
+    use crate::pki::certificate::{FLAG_SIGN_MESSAGES, FLAG_USER_CERT};
+    use crate::testing::certificate::{test_certificates, MockCertificateService};
+    use crate::transport::stats::FrameStats;
+
+    struct TestDaemon{
+        drained: Arc<Mutex<bool>>,
+    }
+
+    impl AdminHandler for TestDaemon{
+        fn get_status(&mut self) -> DaemonStatus {
+            DaemonStatus{
+                uptime_seconds: 42,
+                peers: vec![10, 11],
+                modules: vec![ModuleReport{
+                    name: "certman".to_string(),
+                    health: "failed".to_string(),
+                    failures: 1,
+                    restarts: 0,
+                    last_error: Some("on_message: crash".to_string()),
+                }],
+                is_draining: *self.drained.lock().unwrap(),
+                log_level: String::new(),
+            }
+        }
+
+        fn reload(&mut self) -> Result<String, String> {
+            Err("configuration is not valid".to_string())
+        }
+
+        fn drain(&mut self) -> Result<String, String> {
+            *self.drained.lock().unwrap() = true;
+            Ok("Draining 2 connections".to_string())
+        }
+    }
+
+    fn operator_certificate(serial: u128, flags: u128) -> Falcon1024Certificate{
+        let (public_key, secret_key) = generate_falcon1024_keypair_from_seed(b"test-operator");
+        let mut certificate = Falcon1024Certificate{
+            serial_number: serial,
+            parent_serial_number: ROOT_CERTIFICATE_SERIAL,
+            secret_key: Some(secret_key),
+            public_key,
+            signature: None,
+            name: "operator.test".to_string(),
+            flags,
+            metadata: Default::default(),
+        };
+        certificate.signature = Some(test_certificates().root.sign_data(
+            &certificate.clone_without_signature_and_sk(), HashType::None).unwrap());
+        certificate
+    }
+
+    fn signed(command: AdminCommand, argument: Option<&str>, certificate: &Falcon1024Certificate) -> AdminRequest{
+        let mut request = AdminRequest::new(command, argument.map(|argument| argument.to_string()));
+        request.sign(certificate).unwrap();
+        request
+    }
+
+    #[test]
+    fn test_admin_commands() {
+        let mut certificates = MockCertificateService::with_test_certificates();
+        let operator = operator_certificate(20, FLAG_USER_CERT | FLAG_SIGN_MESSAGES);
+        let auditor = operator_certificate(21, FLAG_USER_CERT | FLAG_SIGN_MESSAGES | FLAG_NO_WRITE);
+        certificates.add_signing_certificate(operator.clone());
+        certificates.add_signing_certificate(auditor.clone());
+        let drained = Arc::new(Mutex::new(false));
+        let mut server = AdminServer::new(certificates, Box::new(TestDaemon{ drained: drained.clone() }));
+
+        let request = signed(AdminCommand::Status, None, &operator);
+        let status = server.handle(&request, None).status.unwrap();
+        assert_eq!((status.uptime_seconds, status.peers), (42, vec![10, 11]));
+        assert_eq!(status.modules[0].last_error, Some("on_message: crash".to_string()));
+        assert_eq!(server.handle(&request, None).error, Some(AdminError::Replayed.to_string()));
+        // Signing certificate of node is not an operator one
+        let request = signed(AdminCommand::Status, None, &test_certificates().signing);
+        assert_eq!(server.handle(&request, None).error, Some(AdminError::NotOperator(1).to_string()));
+        let mut request = signed(AdminCommand::Status, None, &operator);
+        request.command = AdminCommand::Drain;
+        assert_eq!(server.handle(&request, None).error, Some(AdminError::InvalidSignature.to_string()));
+        let mut request = AdminRequest::new(AdminCommand::Status, None);
+        request.timestamp -= ADMIN_REQUEST_LIFETIME + 1;
+        request.sign(&operator).unwrap();
+        assert_eq!(server.handle(&request, None).error, Some(AdminError::Expired.to_string()));
+
+        assert!(server.handle(&signed(AdminCommand::Status, None, &auditor), None).error.is_none());
+        assert_eq!(server.handle(&signed(AdminCommand::Drain, None, &auditor), None).error,
+                   Some(AdminError::Forbidden.to_string()));
+        assert!(!*drained.lock().unwrap());
+        assert_eq!(server.handle(&signed(AdminCommand::Drain, None, &operator), None).message, "Draining 2 connections");
+        assert!(*drained.lock().unwrap());
+        assert_eq!(server.handle(&signed(AdminCommand::Reload, None, &operator), None).error,
+                   Some("configuration is not valid".to_string()));
+        assert!(server.handle(&signed(AdminCommand::SetLogLevel, Some("loud"), &operator), None).error.is_some());
+        assert_eq!(server.handle(&signed(AdminCommand::ReloadCertificates, None, &operator), None).error,
+                   Some(CertificateServiceError::ReloadUnsupported.to_string()));
+
+        assert!(server.handle(&signed(AdminCommand::FrameStats, None, &operator), None).error.is_some());
+        let stats = FrameStats::new_shared(false);
+        server.set_frame_stats(stats.clone());
+        assert!(server.handle(&signed(AdminCommand::FrameStats, None, &auditor), None).message.contains("disabled"));
+        assert_eq!(server.handle(&signed(AdminCommand::FrameStats, Some("on"), &auditor), None).error,
+                   Some(AdminError::Forbidden.to_string()));
+        assert!(server.handle(&signed(AdminCommand::FrameStats, Some("on"), &operator), None).error.is_none());
+        assert!(stats.is_enabled());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_admin_socket() {
+        let path = std::env::temp_dir().join(format!("milkyway-admin-{}.sock", rand::random::<u64>()));
+        let mut certificates = MockCertificateService::with_test_certificates();
+        let operator = operator_certificate(20, FLAG_USER_CERT | FLAG_SIGN_MESSAGES);
+        certificates.add_signing_certificate(operator.clone());
+        AdminServer::new(certificates, Box::new(TestDaemon{ drained: Arc::new(Mutex::new(false)) }))
+            .listen(&path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        // Silent connection does not hold others back
+        let _idle = UnixStream::connect(&path).unwrap();
+        let level = log::max_level();
+        let response = send_admin_request(&path, &signed(AdminCommand::SetLogLevel, Some(&level.to_string()), &operator))
+            .unwrap();
+        assert_eq!(response.error, None);
+        let response = send_admin_request(&path, &signed(AdminCommand::Status, None, &operator)).unwrap();
+        assert_eq!(response.status.unwrap().log_level, level.to_string());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_admin_os_user_factor() {
+        let path = std::env::temp_dir().join(format!("milkyway-admin-{}.sock", rand::random::<u64>()));
+        let mut certificates = MockCertificateService::with_test_certificates();
+        let operator = operator_certificate(20, FLAG_USER_CERT | FLAG_SIGN_MESSAGES | FLAG_REQUIRE_2FA);
+        let other = operator_certificate(21, FLAG_USER_CERT | FLAG_SIGN_MESSAGES | FLAG_REQUIRE_2FA);
+        certificates.add_signing_certificate(operator.clone());
+        certificates.add_signing_certificate(other.clone());
+        let mut factor = OsUserFactor::default();
+        factor.allow_user(20, unsafe { libc::geteuid() });
+        let mut server = AdminServer::new(certificates, Box::new(TestDaemon{ drained: Arc::new(Mutex::new(false)) }));
+        server.set_os_user_factor(factor);
+        // User is unknown unless request comes through socket
+        assert_eq!(server.handle(&signed(AdminCommand::Status, None, &operator), None).error,
+                   Some(AdminError::UserNotAllowed(20).to_string()));
+        server.listen(&path).unwrap();
+        let response = send_admin_request(&path, &signed(AdminCommand::Status, None, &operator)).unwrap();
+        assert_eq!(response.error, None);
+        let response = send_admin_request(&path, &signed(AdminCommand::Status, None, &other)).unwrap();
+        assert_eq!(response.error, Some(AdminError::UserNotAllowed(21).to_string()));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
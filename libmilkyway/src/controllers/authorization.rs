@@ -1,16 +1,41 @@
+///
+/// Additional authentication factors(TOTP, OS user, external command) required for
+/// certificates with FLAG_REQUIRE_2FA
+///
+pub mod factor;
+
+///
+/// Requests for signing certificates missing in chains of authorization messages
+///
+pub mod chain;
+
+///
+/// Policy deciding which certificates of chain are sent to each peer
+///
+pub mod inclusion;
+
+use std::collections::HashMap;
 use crate::serialization::error::SerializationError;
-use crate::serialization::deserializable::Deserializable;
+use crate::serialization::deserializable::{Deserializable, ParsingMode};
 use crate::serialization::serializable::Serializable;
 use libmilkyway_derive::{Deserializable, Serializable};
 use crate::actor::binder::Binder;
 use crate::get_timestamp_with_milliseconds;
-use crate::pki::certificate::{Certificate, FLAG_SIGN_CERTS, FLAG_SIGN_MESSAGES};
+use crate::controllers::authorization::chain::{ChainRequest, ChainResponse};
+use crate::controllers::authorization::factor::{AuthChallenge, AuthChallengeResponse, AuthenticationFactor};
+use crate::controllers::authorization::inclusion::{BuiltChain, SharedChainInclusionPolicy};
+use crate::pki::certificate::{Certificate, FLAG_REQUIRE_2FA, FLAG_SIGN_CERTS, FLAG_SIGN_MESSAGES};
 use crate::pki::hash::HashType;
 use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
 use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
 use crate::pki::signature::Signature;
 use crate::serialization::serializable::Serialized;
 use crate::services::certificate::{CertificateService, CertificateServiceBinder, ROOT_CERTIFICATE_SERIAL};
+use crate::services::certificate::chain::{CertificateChain, ChainVerificationError};
+use crate::trace::{Span, SpanContext};
+use crate::transport::access::SharedAccessControl;
+use crate::transport::identity::SharedExpectedIdentities;
+use crate::transport::pinning::{PinCheck, SharedPeerPins};
 
 ///
 /// Controls authorization process.
@@ -23,10 +48,131 @@ use crate::services::certificate::{CertificateService, CertificateServiceBinder,
 /// 5. Now secure communication is established with help of above certificates
 ///
 /// ## Note
-/// Additionally each party can share own certificate chain, so it would be no gaps in verification
+/// Additionally each party can share own certificate chain, so it would be no gaps in verification.
+/// Shared chain is verified without touching certificate store and is saved to store only once
+/// whole message is verified. If chain still has gaps, server sends ChainRequest and continues
+/// once ChainResponse with missing certificates is received.
+///
+/// ## Chain inclusion
+/// Messages generated for a known peer(see generate_peer_authorization_message) include part of
+/// chain selected by chain inclusion policy, by default the whole chain. Chain is built as far
+/// as local store allows, ancestor missing locally is reported as gap instead of failing.
+///
+/// ## Additional factors
+/// If signing certificate carries FLAG_REQUIRE_2FA, server sends AuthChallenge after step 2 and
+/// continues only once challenge is satisfied by one of configured authentication factors.
+///
+/// ## Access lists
+/// If access control is set, verified certificates are checked against its allow and block
+/// lists before client is authorized or challenged.
+///
+/// ## Pinning
+/// If peer pins are set, peers authorized by ID(see authorize_peer) must present the signing
+/// certificate they are pinned to. Pinned peers are trusted even if their chain can not be
+/// verified yet, fingerprints of unpinned peers are recorded for confirmation by operator.
+///
+/// ## Expected identity
+/// If expected identities are set, client checks that signing certificate of peer it connected
+/// to(see check_peer_authorization_message) matches name, serial or fingerprint configured for
+/// that peer, once the certificate is verified.
+///
+/// ## Tracing
+/// Each step(authorize, chain and challenge responses) is recorded as span with certificate
+/// serial, peer ID and outcome. If span of connection is set, steps become its children.
 ///
 pub struct AuthorizationController{
     certificate_service_binder: Box<CertificateServiceBinder>,
+    factors: Vec<Box<dyn AuthenticationFactor>>,
+    pending_challenges: HashMap<u128, PendingChallenge>,
+    challenge_timeout: u128,
+    pending_chain_requests: HashMap<u128, PendingChainRequest>,
+    persist_chain: bool,
+    access_control: Option<SharedAccessControl>,
+    peer_pins: Option<SharedPeerPins>,
+    expected_identities: Option<SharedExpectedIdentities>,
+    chain_inclusion: Option<SharedChainInclusionPolicy>,
+    span_context: Option<SpanContext>,
+}
+
+///
+/// Default time to respond to authentication challenge in milliseconds
+///
+pub const DEFAULT_CHALLENGE_TIMEOUT: u128 = 120_000;
+
+///
+/// Certificates waiting for response to authentication challenge
+///
+struct PendingChallenge{
+    challenge: AuthChallenge,
+    certificates: (Falcon1024Certificate, Kyber1024Certificate),
+    /** Verified chain saved once challenge is answered, None if certificates are trusted by pin **/
+    chain: Option<Box<CertificateChain>>,
+}
+
+///
+/// Authorization message waiting for certificates missing in its chain
+///
+struct PendingChainRequest{
+    request: ChainRequest,
+    message: AuthorizationMessage,
+}
+
+///
+/// Result of authorization which may require an additional factor
+///
+pub enum AuthorizationStatus{
+    Rejected,
+    /** Signing and encryption certificates of client **/
+    Authorized(Box<(Falcon1024Certificate, Kyber1024Certificate)>),
+    /** Challenge must be sent to client and answered before certificates are accepted **/
+    ChallengeRequired(AuthChallenge),
+    /** Request must be sent to client and answered with missing certificates **/
+    ChainRequired(ChainRequest),
+}
+
+impl AuthorizationStatus {
+    ///
+    /// Gets name of outcome as recorded in spans
+    ///
+    pub fn get_name(&self) -> &'static str{
+        match self {
+            AuthorizationStatus::Rejected => "rejected",
+            AuthorizationStatus::Authorized(_) => "authorized",
+            AuthorizationStatus::ChallengeRequired(_) => "challenge_required",
+            AuthorizationStatus::ChainRequired(_) => "chain_required",
+        }
+    }
+}
+
+///
+/// Outcome of verification of authorization message against a temporary chain
+///
+enum MessageVerification{
+    Valid(Box<CertificateChain>),
+    /** Signing certificate with given serial is neither in message nor in store **/
+    MissingCertificate(u128),
+    Invalid,
+}
+
+///
+/// Outcome of checking authorization message against peer pins
+///
+enum PinVerdict{
+    /** Peer is pinned to signing certificate of message and message is signed by it **/
+    Trusted,
+    Rejected,
+    /** Peer must be trusted through chain **/
+    Unpinned,
+}
+
+impl PinVerdict {
+    fn get_name(&self) -> &'static str{
+        match self {
+            PinVerdict::Trusted => "trusted",
+            PinVerdict::Rejected => "rejected",
+            PinVerdict::Unpinned => "unpinned",
+        }
+    }
 }
 
 
@@ -50,6 +196,14 @@ impl AuthorizationMessage {
         m_copy.signature = None;
         m_copy
     }
+
+    ///
+    /// Parses authorization message received during handshake(see Message::parse)
+    ///
+    #[inline]
+    pub fn parse(serialized: &Serialized, mode: ParsingMode) -> Result<AuthorizationMessage, SerializationError>{
+        mode.deserialize(serialized)
+    }
 }
 
 
@@ -63,9 +217,119 @@ impl AuthorizationController {
     pub fn new(binder: Box<CertificateServiceBinder>) -> AuthorizationController{
         AuthorizationController{
             certificate_service_binder: binder,
+            factors: Vec::new(),
+            pending_challenges: HashMap::new(),
+            challenge_timeout: DEFAULT_CHALLENGE_TIMEOUT,
+            pending_chain_requests: HashMap::new(),
+            persist_chain: true,
+            access_control: None,
+            peer_pins: None,
+            expected_identities: None,
+            chain_inclusion: None,
+            span_context: None,
         }
     }
 
+    ///
+    /// Adds authentication factor used for certificates with FLAG_REQUIRE_2FA.
+    /// Challenges are issued for the first added factor.
+    ///
+    pub fn add_factor(&mut self, factor: Box<dyn AuthenticationFactor>) -> &mut AuthorizationController{
+        self.factors.push(factor);
+        self
+    }
+
+    ///
+    /// Sets time to respond to authentication challenge
+    ///
+    /// # Arguments
+    /// * timeout: u128: timeout in milliseconds
+    ///
+    #[inline]
+    pub fn set_challenge_timeout(&mut self, timeout: u128) -> &mut AuthorizationController{
+        self.challenge_timeout = timeout;
+        self
+    }
+
+    ///
+    /// Sets whether certificates of verified chains are saved to certificate store.
+    /// Chains of messages which fail verification are never saved.
+    ///
+    /// # Arguments
+    /// * persist: bool: true to save chains(default), false to use them only for verification
+    ///
+    #[inline]
+    pub fn set_persist_chain(&mut self, persist: bool) -> &mut AuthorizationController{
+        self.persist_chain = persist;
+        self
+    }
+
+    ///
+    /// Sets allow and block lists checked once certificates of client are verified
+    ///
+    /// # Arguments
+    /// * access: SharedAccessControl: lists to enforce
+    ///
+    #[inline]
+    pub fn set_access_control(&mut self, access: SharedAccessControl) -> &mut AuthorizationController{
+        self.access_control = Some(access);
+        self
+    }
+
+    ///
+    /// Sets pins checked when peer is authorized by its ID
+    ///
+    /// # Arguments
+    /// * pins: SharedPeerPins: pins to enforce
+    ///
+    #[inline]
+    pub fn set_peer_pins(&mut self, pins: SharedPeerPins) -> &mut AuthorizationController{
+        self.peer_pins = Some(pins);
+        self
+    }
+
+    ///
+    /// Sets identities which peers must match when their messages are checked by peer ID
+    ///
+    /// # Arguments
+    /// * identities: SharedExpectedIdentities: identities of configured peers
+    ///
+    #[inline]
+    pub fn set_expected_identities(&mut self, identities: SharedExpectedIdentities) -> &mut AuthorizationController{
+        self.expected_identities = Some(identities);
+        self
+    }
+
+    ///
+    /// Sets policy selecting certificates of chain sent to peers, chains it caches are
+    /// shared by all controllers using it
+    ///
+    /// # Arguments
+    /// * policy: SharedChainInclusionPolicy: policy to use
+    ///
+    #[inline]
+    pub fn set_chain_inclusion(&mut self, policy: SharedChainInclusionPolicy) -> &mut AuthorizationController{
+        self.chain_inclusion = Some(policy);
+        self
+    }
+
+    ///
+    /// Sets span of connection which is authorized, spans of authorization steps become its children
+    ///
+    #[inline]
+    pub fn set_span_context(&mut self, context: SpanContext) -> &mut AuthorizationController{
+        self.span_context = Some(context);
+        self
+    }
+
+    ///
+    /// Starts span of authorization step
+    ///
+    #[inline]
+    fn start_span(&self, name: &'static str) -> Span{
+        Span::child_of(self.span_context, name)
+    }
+
     ///
     /// Finalizes authorization procedure and cleans up
     ///
@@ -89,25 +353,105 @@ impl AuthorizationController {
         if certificate.is_none(){
             return Err("Can not find a certificate used for encryption with provided serial");
         }
-        let mut chain = Vec::<Falcon1024Certificate>::new();
         let certificate = certificate.unwrap();
+        let mut chain = Vec::<Falcon1024Certificate>::new();
         if fullchain{
-            let current_serial = certificate.get_serial();
-            if current_serial == ROOT_CERTIFICATE_SERIAL{
-                // Something strange is going on
-                return Err("Serial of encryption certificate can not be serial of root certificate");
+            let built = self.build_chain(&certificate)?;
+            if built.gap.is_some(){
+                return Err("Can not trust chain: parent is missing");
             }
-            let mut parent_serial = certificate.get_parent_serial().expect("Must have a parent serial");
-            while parent_serial != ROOT_CERTIFICATE_SERIAL {
-                let certificate = self.certificate_service_binder.get_signing_certificate(parent_serial);
-                if certificate.is_none(){
-                    return Err("Can not trust chain: parent is missing");
+            chain = built.certificates;
+        }
+        self.sign_authorization_message(certificate, signing_serial, chain)
+    }
+
+    ///
+    /// Generates authorization message for peer with known ID, including part of chain selected
+    /// by chain inclusion policy(whole chain if policy is not set)
+    ///
+    /// # Arguments
+    /// * peer_id: u128: ID of peer message is sent to
+    /// * serial: u128: a certificate which should be used for encryption
+    /// * signing_serial: u128: a certificate which would be used for signing messages
+    ///
+    /// returns: either an authorization message with serial of first ancestor missing in local
+    /// store(peer may still know it), or error with str description
+    ///
+    pub fn generate_peer_authorization_message(&mut self, peer_id: u128, serial: u128, signing_serial: u128)
+        -> Result<(AuthorizationMessage, Option<u128>), &'static str>{
+        let certificate = self.certificate_service_binder.get_encryption_certificate(serial);
+        if certificate.is_none(){
+            return Err("Can not find a certificate used for encryption with provided serial");
+        }
+        let certificate = certificate.unwrap();
+        let built = match self.chain_inclusion.clone() {
+            Some(policy) => {
+                let cached = policy.lock().unwrap().get_chain(serial);
+                let built = match cached {
+                    Some(built) => built,
+                    None => {
+                        let built = self.build_chain(&certificate)?;
+                        policy.lock().unwrap().cache_chain(serial, built.clone());
+                        built
+                    }
+                };
+                let certificates = policy.lock().unwrap().select(peer_id, &built);
+                BuiltChain{
+                    certificates,
+                    gap: built.gap,
                 }
-                let certificate = certificate.unwrap().clone_without_sk();
-                chain.insert(0, certificate.clone());
-                parent_serial = certificate.get_parent_serial().expect("Must have a parent serial");
             }
+            None => self.build_chain(&certificate)?,
+        };
+        if let Some(gap) = built.gap{
+            log::warn!("Certificate {} is missing in local store, chain sent to peer {} is incomplete",
+                gap, peer_id);
+        }
+        let chain = built.certificates;
+        let serials: Vec<u128> = chain.iter().map(|certificate| certificate.get_serial()).collect();
+        let message = self.sign_authorization_message(certificate, signing_serial, chain)?;
+        if let Some(policy) = &self.chain_inclusion{
+            policy.lock().unwrap().record_known(peer_id, &serials);
+        }
+        Ok((message, built.gap))
+    }
+
+    ///
+    /// Collects ancestors of encryption certificate up to root, stopping at the first one
+    /// missing in local store
+    ///
+    fn build_chain(&mut self, certificate: &Kyber1024Certificate) -> Result<BuiltChain, &'static str>{
+        if certificate.get_serial() == ROOT_CERTIFICATE_SERIAL{
+            // Something strange is going on
+            return Err("Serial of encryption certificate can not be serial of root certificate");
+        }
+        let mut chain = BuiltChain{
+            certificates: Vec::new(),
+            gap: None,
+        };
+        let mut parent_serial = certificate.get_parent_serial().expect("Must have a parent serial");
+        while parent_serial != ROOT_CERTIFICATE_SERIAL {
+            if chain.certificates.iter().any(|certificate| certificate.get_serial() == parent_serial){
+                return Err("Can not trust chain: it has a loop");
+            }
+            let certificate = match self.certificate_service_binder.get_signing_certificate(parent_serial) {
+                Some(certificate) => certificate.clone_without_sk(),
+                None => {
+                    chain.gap = Some(parent_serial);
+                    break;
+                }
+            };
+            parent_serial = certificate.get_parent_serial().expect("Must have a parent serial");
+            chain.certificates.insert(0, certificate);
         }
+        Ok(chain)
+    }
+
+    ///
+    /// Signs authorization message with given chain
+    ///
+    fn sign_authorization_message(&mut self, certificate: Kyber1024Certificate, signing_serial: u128,
+                                  chain: Vec<Falcon1024Certificate>) -> Result<AuthorizationMessage, &'static str>{
         let signing_certificate = self.certificate_service_binder.get_signing_certificate(signing_serial);
         if signing_certificate.is_none(){
             return Err("Can not find a certificate used for signing with provided serial");
@@ -115,7 +459,7 @@ impl AuthorizationController {
         let signing_certificate = signing_certificate.unwrap();
         let mut message = AuthorizationMessage{
             encryption_certificate: certificate.clone_without_sk(),
-            signing_certificate: signing_certificate.clone(),
+            signing_certificate: signing_certificate.clone_without_sk(),
             signing_chain: chain,
             timestamp: get_timestamp_with_milliseconds(),
             signature: None,
@@ -132,51 +476,462 @@ impl AuthorizationController {
     }
 
 
+    ///
+    /// Verifies authorization message against certificate store and certificates presented
+    /// by peer without modifying the store
+    ///
+    /// # Arguments
+    /// * message: &AuthorizationMessage: message to verify
+    /// * extra: &[Falcon1024Certificate]: certificates received in ChainResponse
+    ///
+    fn verify_authorization_message(&mut self, message: &AuthorizationMessage,
+                                    extra: &[Falcon1024Certificate]) -> MessageVerification{
+        let signing_certificate = &message.signing_certificate;
+        if signing_certificate.signature.is_none() || message.signature.is_none(){
+            /* Unsigned certificate or message */
+            return MessageVerification::Invalid;
+        }
+        if !signing_certificate.check_flag(FLAG_SIGN_MESSAGES){
+            /* Wrong flags */
+            log::warn!("Signing certificate {} can not sign messages", signing_certificate.get_serial());
+            return MessageVerification::Invalid;
+        }
+        let mut chain = CertificateChain::new();
+        // Certificates from store take precedence over presented ones with same serial
+        chain.add_from_service(&mut *self.certificate_service_binder);
+        for certificate in message.signing_chain.iter().chain(extra.iter()){
+            if !certificate.check_flag(FLAG_SIGN_CERTS){
+                // Wrong flags
+                return MessageVerification::Invalid;
+            }
+            chain.add_signing_certificate(certificate.clone());
+        }
+        match chain.verify_signing_certificate(signing_certificate) {
+            Ok(_) => {}
+            Err(ChainVerificationError::MissingParent{parent, ..}) =>
+                return MessageVerification::MissingCertificate(parent),
+            Err(error) => {
+                log::warn!("Signing certificate is not trusted: {}", error);
+                return MessageVerification::Invalid;
+            }
+        }
+        let message_no_signature = message.clone_without_signature();
+        if !signing_certificate.verify_signature(&message_no_signature, message.signature.as_ref().unwrap()){
+            /* Message signature invalid */
+            return MessageVerification::Invalid;
+        }
+        chain.add_signing_certificate(signing_certificate.clone());
+        match chain.verify_encryption_certificate(&message.encryption_certificate) {
+            Ok(_) => MessageVerification::Valid(Box::new(chain)),
+            Err(ChainVerificationError::MissingParent{parent, ..}) => MessageVerification::MissingCertificate(parent),
+            Err(error) => {
+                log::warn!("Encryption certificate is not trusted: {}", error);
+                MessageVerification::Invalid
+            }
+        }
+    }
+
+    ///
+    /// Saves certificates of verified message to store, ancestors first
+    ///
+    fn persist_certificates(&mut self, chain: &CertificateChain, encryption_certificate: &Kyber1024Certificate){
+        if self.persist_chain{
+            let mut serials = Vec::<u128>::new();
+            let mut parent = encryption_certificate.get_parent_serial();
+            while let Some(serial) = parent{
+                if serial == ROOT_CERTIFICATE_SERIAL || serials.contains(&serial){
+                    break;
+                }
+                serials.insert(0, serial);
+                parent = chain.get_signing_certificate(serial).and_then(|certificate| certificate.get_parent_serial());
+            }
+            for serial in serials{
+                if self.certificate_service_binder.get_signing_certificate(serial).is_some(){
+                    continue;
+                }
+                if let Some(certificate) = chain.get_signing_certificate(serial){
+                    self.certificate_service_binder.add_signing_certificate(certificate.clone());
+                }
+            }
+        }
+        self.certificate_service_binder.add_encryption_certificate(encryption_certificate.clone());
+    }
+
+    ///
+    /// Adds certificates peer was authorized with to store unless they are known, so crypto
+    /// layer of session can find them. Chain is persisted by authorization itself.
+    ///
+    /// # Arguments
+    /// * certificates: &(Falcon1024Certificate, Kyber1024Certificate): signing and encryption
+    ///   certificates of peer
+    ///
+    pub fn add_peer_certificates(&mut self, certificates: &(Falcon1024Certificate, Kyber1024Certificate)){
+        let (signing_certificate, encryption_certificate) = certificates;
+        if self.certificate_service_binder.get_signing_certificate(signing_certificate.get_serial()).is_none(){
+            self.certificate_service_binder.add_signing_certificate(signing_certificate.clone_without_sk());
+        }
+        if self.certificate_service_binder.get_encryption_certificate(encryption_certificate.get_serial()).is_none(){
+            self.certificate_service_binder.add_encryption_certificate(encryption_certificate.clone_without_sk());
+        }
+    }
+
+    ///
+    /// Checks signing certificate of message against pin of peer before chain is verified
+    ///
+    fn check_pin(&mut self, peer_id: u128, message: &AuthorizationMessage) -> PinVerdict{
+        let pins = match &self.peer_pins {
+            Some(pins) => pins,
+            None => return PinVerdict::Unpinned,
+        };
+        let signing_certificate = &message.signing_certificate;
+        let fingerprint = signing_certificate.get_fingerprint();
+        let mut pins = pins.lock().unwrap();
+        match pins.check(peer_id, &fingerprint) {
+            PinCheck::Unpinned => {
+                if pins.observe(peer_id, &fingerprint){
+                    log::info!("Peer {} is seen first time with certificate {}, fingerprint {} awaits confirmation",
+                        peer_id, signing_certificate.get_serial(), fingerprint);
+                    pins.commit();
+                }
+                PinVerdict::Unpinned
+            }
+            PinCheck::Mismatched => {
+                log::warn!("Peer {} presented certificate {} which does not match its pin", peer_id,
+                    signing_certificate.get_serial());
+                PinVerdict::Rejected
+            }
+            PinCheck::Matched => {
+                // Pin authenticates signing certificate, signature of message binds encryption certificate to it
+                let is_signed = signing_certificate.check_flag(FLAG_SIGN_MESSAGES) && message.signature.as_ref()
+                    .is_some_and(|signature| signing_certificate.verify_signature(&message.clone_without_signature(),
+                                                                                 signature));
+                if is_signed { PinVerdict::Trusted } else { PinVerdict::Rejected }
+            }
+        }
+    }
+
+    ///
+    /// Gets chain of message trusted by pin if chain can already be verified, so it is saved
+    ///
+    fn get_pinned_chain(&mut self, message: &AuthorizationMessage) -> Option<Box<CertificateChain>>{
+        match self.verify_authorization_message(message, &[]) {
+            MessageVerification::Valid(chain) => Some(chain),
+            _ => None,
+        }
+    }
+
+    ///
+    /// Checks an authorization message of peer with known ID, taking peer pins into account
+    ///
+    /// # Arguments
+    /// * peer_id: u128: ID of peer which sent message
+    /// * message: a message to verify
+    ///
+    /// returns: None if verification failed, chain has gaps or peer does not match its expected
+    /// identity, pair of signing and encryption certificates otherwise
+    ///
+    pub fn check_peer_authorization_message(&mut self, peer_id: u128, message: AuthorizationMessage)
+        -> Option<(Falcon1024Certificate, Kyber1024Certificate)>{
+        let certificates = self.check_peer_certificates(peer_id, message)?;
+        let is_expected = self.expected_identities.as_ref()
+            .is_none_or(|identities| identities.lock().unwrap().verify(peer_id, &certificates.0));
+        if is_expected { Some(certificates) } else { None }
+    }
+
+    ///
+    /// Verifies certificates of peer message against pins or chain
+    ///
+    fn check_peer_certificates(&mut self, peer_id: u128, message: AuthorizationMessage)
+        -> Option<(Falcon1024Certificate, Kyber1024Certificate)>{
+        match self.check_pin(peer_id, &message) {
+            PinVerdict::Unpinned => self.check_authorization_message(message),
+            PinVerdict::Rejected => None,
+            PinVerdict::Trusted => {
+                if !self.is_access_allowed(&message.signing_certificate, &message.encryption_certificate){
+                    return None;
+                }
+                if let Some(chain) = self.get_pinned_chain(&message){
+                    self.persist_certificates(&chain, &message.encryption_certificate);
+                }
+                Some((message.signing_certificate, message.encryption_certificate))
+            }
+        }
+    }
+
     ///
     /// Checks an authorization message
     ///
     /// # Arguments
     /// * message: a message to verify
     ///
-    /// returns: None if verification failed, pair of signing and encryption certificates otherwise
+    /// returns: None if verification failed or chain has gaps, pair of signing and encryption
+    /// certificates otherwise
     ///
     pub fn check_authorization_message(&mut self,
                                        message: AuthorizationMessage) -> Option<(Falcon1024Certificate, Kyber1024Certificate)>{
-        let signing_certificate  = message.signing_certificate.clone();
-        if signing_certificate.signature.is_none(){
-            /* Unsigned certificate */
-            return None;
+        match self.verify_authorization_message(&message, &[]) {
+            MessageVerification::Valid(chain) => {
+                if !self.is_access_allowed(&message.signing_certificate, &message.encryption_certificate){
+                    return None;
+                }
+                self.persist_certificates(&chain, &message.encryption_certificate);
+                Some((message.signing_certificate, message.encryption_certificate))
+            }
+            _ => None,
         }
-        if !signing_certificate.check_flag(FLAG_SIGN_MESSAGES){
-            /* Wrong flags */
-            println!("Signing certificate can not sign messages");
-            return None;
+    }
+
+    ///
+    /// Checks verified certificates against access lists if they are set
+    ///
+    fn is_access_allowed(&self, signing_certificate: &Falcon1024Certificate,
+                         encryption_certificate: &Kyber1024Certificate) -> bool{
+        match &self.access_control {
+            Some(access) => access.lock().unwrap().check_certificates(None, signing_certificate,
+                                                                      encryption_certificate),
+            None => true,
+        }
+    }
+
+    ///
+    /// Issues a challenge if signing certificate requires additional factor. Chain is saved only
+    /// once certificates are authorized, so rejected or unanswered clients leave nothing in store.
+    ///
+    fn authorize_verified(&mut self, signing_certificate: Falcon1024Certificate,
+                          encryption_certificate: Kyber1024Certificate,
+                          chain: Option<Box<CertificateChain>>) -> AuthorizationStatus{
+        if !self.is_access_allowed(&signing_certificate, &encryption_certificate){
+            return AuthorizationStatus::Rejected;
         }
-        for cert in &message.signing_chain{
-            if !self.certificate_service_binder.add_signing_certificate(cert.clone()){
-                // Invalid certificate
-                return None;
+        if !signing_certificate.check_flag(FLAG_REQUIRE_2FA){
+            if let Some(chain) = &chain{
+                self.persist_certificates(chain, &encryption_certificate);
             }
-            if !cert.check_flag(FLAG_SIGN_CERTS){
-                // Wrong flags
-                return None;
+            return AuthorizationStatus::Authorized(Box::new((signing_certificate, encryption_certificate)));
+        }
+        let factor = match self.factors.first() {
+            Some(factor) => factor.get_kind(),
+            None => {
+                log::error!("Certificate {} requires additional factor, but none is configured",
+                    signing_certificate.get_serial());
+                return AuthorizationStatus::Rejected;
             }
+        };
+        let now = get_timestamp_with_milliseconds();
+        self.pending_challenges.retain(|_, pending| now - pending.challenge.timestamp < self.challenge_timeout);
+        let challenge = AuthChallenge{
+            challenge_id: rand::random(),
+            factor,
+            certificate_serial: signing_certificate.get_serial(),
+            timestamp: now,
+        };
+        self.pending_challenges.insert(challenge.challenge_id, PendingChallenge{
+            challenge: challenge.clone(),
+            certificates: (signing_certificate, encryption_certificate),
+            chain,
+        });
+        AuthorizationStatus::ChallengeRequired(challenge)
+    }
+
+    ///
+    /// Checks an authorization message and issues a challenge if certificate requires additional factor
+    ///
+    /// # Arguments
+    /// * message: a message to verify
+    ///
+    /// returns: AuthorizationStatus: whether client is authorized, rejected or must answer challenge
+    /// or send certificates missing in chain
+    ///
+    pub fn authorize(&mut self, message: AuthorizationMessage) -> AuthorizationStatus{
+        let mut span = self.start_span("authorize")
+            .with_field("serial", message.signing_certificate.get_serial());
+        let status = self.authorize_message(message);
+        span.record("status", status.get_name());
+        status
+    }
+
+    ///
+    /// Verifies message and requests missing part of its chain if needed
+    ///
+    fn authorize_message(&mut self, message: AuthorizationMessage) -> AuthorizationStatus{
+        match self.verify_authorization_message(&message, &[]) {
+            MessageVerification::Valid(chain) => {
+                self.authorize_verified(message.signing_certificate, message.encryption_certificate, Some(chain))
+            }
+            MessageVerification::MissingCertificate(serial) => {
+                let now = get_timestamp_with_milliseconds();
+                self.pending_chain_requests.retain(|_, pending| now - pending.request.timestamp < self.challenge_timeout);
+                let request = ChainRequest{
+                    request_id: rand::random(),
+                    serials: vec![serial],
+                    timestamp: now,
+                };
+                self.pending_chain_requests.insert(request.request_id, PendingChainRequest{
+                    request: request.clone(),
+                    message,
+                });
+                AuthorizationStatus::ChainRequired(request)
+            }
+            MessageVerification::Invalid => AuthorizationStatus::Rejected,
         }
-        if !self.certificate_service_binder.verify_signing_certificate(&signing_certificate){
-            /* Certificate is invalid event though chain was updated */
-            return None;
+    }
+
+    ///
+    /// Authorizes peer with known ID, taking peer pins into account
+    ///
+    /// # Arguments
+    /// * peer_id: u128: ID of peer which sent message
+    /// * message: a message to verify
+    ///
+    /// returns: AuthorizationStatus: same as authorize, but pinned peers never need their chain
+    ///
+    pub fn authorize_peer(&mut self, peer_id: u128, message: AuthorizationMessage) -> AuthorizationStatus{
+        let mut span = self.start_span("authorize")
+            .with_field("peer_id", peer_id)
+            .with_field("serial", message.signing_certificate.get_serial());
+        let verdict = self.check_pin(peer_id, &message);
+        span.record("pin", verdict.get_name());
+        let mut presented: Vec<u128> = message.signing_chain.iter().map(|certificate| certificate.get_serial()).collect();
+        presented.push(message.signing_certificate.get_serial());
+        let status = match verdict {
+            PinVerdict::Unpinned => self.authorize_message(message),
+            PinVerdict::Rejected => AuthorizationStatus::Rejected,
+            PinVerdict::Trusted => {
+                let chain = self.get_pinned_chain(&message);
+                self.authorize_verified(message.signing_certificate, message.encryption_certificate, chain)
+            }
+        };
+        if !matches!(status, AuthorizationStatus::Rejected){
+            if let Some(policy) = &self.chain_inclusion{
+                policy.lock().unwrap().record_known(peer_id, &presented);
+            }
         }
-        let message_no_signature = message.clone_without_signature();
-        if !signing_certificate.verify_signature(&message_no_signature, &message.signature.unwrap()){
-            /* Message signature invalid */
+        span.record("status", status.get_name());
+        status
+    }
+
+    ///
+    /// Continues authorization suspended by ChainRequest
+    ///
+    /// # Arguments
+    /// * response: &ChainResponse: certificates sent by client
+    ///
+    /// returns: AuthorizationStatus: whether client is authorized, rejected or must answer challenge.
+    /// Chain is requested only once, so gaps left after response reject authorization.
+    ///
+    pub fn check_chain_response(&mut self, response: &ChainResponse) -> AuthorizationStatus{
+        let mut span = self.start_span("chain_response")
+            .with_field("request_id", response.request_id)
+            .with_field("certificates", response.certificates.len());
+        let status = self.continue_with_chain(response);
+        span.record("status", status.get_name());
+        status
+    }
+
+    fn continue_with_chain(&mut self, response: &ChainResponse) -> AuthorizationStatus{
+        let pending = match self.pending_chain_requests.remove(&response.request_id) {
+            Some(pending) => pending,
+            None => return AuthorizationStatus::Rejected,
+        };
+        if get_timestamp_with_milliseconds() - pending.request.timestamp >= self.challenge_timeout{
+            return AuthorizationStatus::Rejected;
+        }
+        let message = pending.message;
+        match self.verify_authorization_message(&message, &response.certificates) {
+            MessageVerification::Valid(chain) => {
+                self.authorize_verified(message.signing_certificate, message.encryption_certificate, Some(chain))
+            }
+            MessageVerification::MissingCertificate(serial) => {
+                log::warn!("Certificate {} is still missing in chain sent by client", serial);
+                AuthorizationStatus::Rejected
+            }
+            MessageVerification::Invalid => AuthorizationStatus::Rejected,
+        }
+    }
+
+    ///
+    /// Collects certificates requested by server with their ancestors up to root
+    ///
+    /// # Arguments
+    /// * request: &ChainRequest: request of server
+    ///
+    /// returns: ChainResponse: response with certificates found in store, without secret keys
+    ///
+    pub fn generate_chain_response(&mut self, request: &ChainRequest) -> ChainResponse{
+        let mut certificates = Vec::<Falcon1024Certificate>::new();
+        for serial in &request.serials{
+            let mut current = *serial;
+            while current != ROOT_CERTIFICATE_SERIAL
+                && !certificates.iter().any(|certificate| certificate.get_serial() == current){
+                let certificate = match self.certificate_service_binder.get_signing_certificate(current) {
+                    Some(certificate) => certificate.clone_without_sk(),
+                    None => break,
+                };
+                current = match certificate.get_parent_serial() {
+                    Some(parent) => parent,
+                    None => break,
+                };
+                certificates.push(certificate);
+            }
+        }
+        ChainResponse{
+            request_id: request.request_id,
+            certificates,
+        }
+    }
+
+    ///
+    /// Collects certificates requested by peer with known ID, so chain inclusion policy sends
+    /// them to peer in later authorization messages
+    ///
+    /// # Arguments
+    /// * peer_id: u128: ID of peer which sent request
+    /// * request: &ChainRequest: request of peer
+    ///
+    /// returns: ChainResponse: same as generate_chain_response
+    ///
+    pub fn generate_peer_chain_response(&mut self, peer_id: u128, request: &ChainRequest) -> ChainResponse{
+        if let Some(policy) = &self.chain_inclusion{
+            policy.lock().unwrap().record_requested(peer_id, &request.serials);
+        }
+        self.generate_chain_response(request)
+    }
+
+    ///
+    /// Checks response to challenge issued by authorize
+    ///
+    /// # Arguments
+    /// * response: &AuthChallengeResponse: response of client
+    ///
+    /// returns: None if challenge is unknown, expired or not satisfied, pair of signing and
+    /// encryption certificates otherwise
+    ///
+    pub fn check_challenge_response(&mut self,
+                                    response: &AuthChallengeResponse) -> Option<(Falcon1024Certificate, Kyber1024Certificate)>{
+        let mut span = self.start_span("challenge_response")
+            .with_field("challenge_id", response.challenge_id);
+        let certificates = self.verify_challenge_response(response);
+        span.record("status", if certificates.is_some() { "authorized" } else { "rejected" });
+        certificates
+    }
+
+    fn verify_challenge_response(&mut self, response: &AuthChallengeResponse)
+        -> Option<(Falcon1024Certificate, Kyber1024Certificate)>{
+        // Each challenge may be answered only once
+        let pending = self.pending_challenges.remove(&response.challenge_id)?;
+        if get_timestamp_with_milliseconds() - pending.challenge.timestamp >= self.challenge_timeout{
             return None;
         }
-        if !self.certificate_service_binder.verify_encryption_certificate(&message.encryption_certificate){
-            /* The encryption certificate is invalid */
+        let factor = self.factors.iter_mut().find(|factor| factor.get_kind() == pending.challenge.factor)?;
+        // Responses come over TCP sessions, so factors of local peers(OsUser) are never satisfied
+        if !factor.verify(&pending.challenge, response, None){
+            log::warn!("Additional factor of certificate {} is not satisfied", pending.challenge.certificate_serial);
             return None;
         }
-        self.certificate_service_binder.add_encryption_certificate(message.encryption_certificate.clone());
-        return Some((message.signing_certificate, message.encryption_certificate));
+        if let Some(chain) = &pending.chain{
+            self.persist_certificates(chain, &pending.certificates.1);
+        }
+        Some(pending.certificates)
     }
 }
 
@@ -190,20 +945,30 @@ mod tests {
     use crate::pki::impls::certificates::falcon1024::{Falcon1024Certificate, Falcon1024RootCertificate};
     use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
     use crate::actor::binder::coroutine::BinderAsyncService;
-    use crate::pki::impls::keys::falcon1024::generate_falcon1024_keypair;
-    use crate::pki::impls::keys::kyber1024::generate_kyber1024_keypair;
+    use crate::pki::impls::keys::falcon1024::generate_falcon1024_keypair_from_seed;
+    use crate::pki::impls::keys::kyber1024::generate_kyber1024_keypair_from_seed;
     use crate::services::impls::certificate::AsyncCertificateServiceImpl;
     use crate::tokio::init_tokio;
+    use crate::controllers::authorization::factor::{AuthFactorKind, ExternalCommandFactor};
+    use crate::transport::identity::{ExpectedIdentities, ExpectedIdentity, IdentityMode};
+    use crate::transport::pinning::PeerPins;
+    use crate::transport::access::{AccessControl, AccessRule, PeerSelector};
+    use std::sync::{Arc, Mutex};
+    use crate::controllers::authorization::inclusion::{ChainInclusion, ChainInclusionPolicy};
     
     fn create_sample_certificates() -> (Kyber1024Certificate, Falcon1024RootCertificate, Falcon1024Certificate) {
+        create_sample_certificates_with_flags(FLAG_SIGN_MESSAGES | FLAG_SIGN_CERTS)
+    }
+
+    fn create_sample_certificates_with_flags(flags: u128) -> (Kyber1024Certificate, Falcon1024RootCertificate, Falcon1024Certificate) {
         // Create some sample certificates for testing
-        let (root_public_key, root_secret_key) = generate_falcon1024_keypair();
+        let (root_public_key, root_secret_key) = generate_falcon1024_keypair_from_seed(b"root");
         let root_certificate = Falcon1024RootCertificate {
             secret_key: Some(root_secret_key),
             public_key: root_public_key.clone(),
             name: "test".to_string(),
         };
-        let (encipherment_public_key, encipherment_secret_key) = generate_kyber1024_keypair();
+        let (encipherment_public_key, encipherment_secret_key) = generate_kyber1024_keypair_from_seed(b"encryption");
         let mut encryption_cert = Kyber1024Certificate {
             serial_number: 2,
             parent_serial_number: 1,
@@ -212,8 +977,9 @@ mod tests {
             signature: None,
             name: "test".to_string(),
             flags: 0,
+            metadata: Default::default(),
         };
-        let (signing_public_key, signing_secret_key) = generate_falcon1024_keypair();
+        let (signing_public_key, signing_secret_key) = generate_falcon1024_keypair_from_seed(b"signing");
         let mut signing_certificate = Falcon1024Certificate {
             serial_number: 1,
             parent_serial_number: 0,
@@ -221,7 +987,8 @@ mod tests {
             public_key: signing_public_key.clone(),
             signature: None,
             name: "test".to_string(),
-            flags: FLAG_SIGN_MESSAGES | FLAG_SIGN_CERTS,
+            flags,
+            metadata: Default::default(),
         };
         assert!(signing_certificate.check_flag(FLAG_SIGN_MESSAGES));
         signing_certificate.signature = Some(root_certificate.sign_data(&signing_certificate.clone_without_signature_and_sk(),
@@ -250,6 +1017,9 @@ mod tests {
         assert_eq!(auth_message.encryption_certificate.get_serial(), 2);
         assert_eq!(auth_message.signing_certificate.get_serial(), 1);
         assert!(auth_message.signature.is_some());
+        // Peer must not receive secret keys of certificates it is authorized with
+        assert!(auth_message.signing_certificate.secret_key.is_none());
+        assert!(auth_message.encryption_certificate.secret_key.is_none());
     }
 
     #[test]
@@ -286,4 +1056,269 @@ mod tests {
         assert_eq!(signing_cert_out.get_serial(), signing_cert.get_serial());
         assert_eq!(encryption_cert_out.get_serial(), encryption_cert.get_serial());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_add_peer_certificates() {
+        init_tokio();
+        let mut service = BinderAsyncService::run(Box::new(AsyncCertificateServiceImpl::new("/tmp/test_peer_certs.dat")));
+        let mut binder = service.bind();
+        let (encryption_cert, root_certificate, signing_cert) = create_sample_certificates();
+        binder.set_root_certificate(root_certificate.clone());
+
+        let mut controller = AuthorizationController::new(binder);
+        controller.add_peer_certificates(&(signing_cert.clone_without_sk(), encryption_cert.clone_without_sk()));
+        // Adding known certificates again is harmless
+        controller.add_peer_certificates(&(signing_cert.clone_without_sk(), encryption_cert.clone_without_sk()));
+
+        let mut binder = service.bind();
+        assert!(binder.get_signing_certificate(signing_cert.get_serial()).is_some());
+        assert!(binder.get_encryption_certificate(encryption_cert.get_serial()).is_some());
+    }
+
+    #[test]
+    fn test_authorize_with_additional_factor() {
+        init_tokio();
+        let mut service = BinderAsyncService::run(Box::new(AsyncCertificateServiceImpl::new("/tmp/test_2fa.dat")));
+        let mut binder = service.bind();
+        let (encryption_cert, root_certificate, signing_cert) =
+            create_sample_certificates_with_flags(FLAG_SIGN_MESSAGES | FLAG_SIGN_CERTS | FLAG_REQUIRE_2FA);
+        binder.set_root_certificate(root_certificate.clone());
+        assert!(binder.add_signing_certificate(signing_cert.clone()));
+        assert!(binder.add_encryption_certificate(encryption_cert.clone()));
+
+        let mut controller = AuthorizationController::new(binder);
+        let message = controller.generate_authorization_message(2, 1, false).unwrap();
+        // Without factors certificate can not be authorized at all
+        assert!(matches!(controller.authorize(message.clone()), AuthorizationStatus::Rejected));
+
+        let factor = ExternalCommandFactor::new("sh", vec!["-c".to_string(), "[ \"$(cat)\" = operator ]".to_string()]);
+        controller.add_factor(Box::new(factor));
+        let challenge = match controller.authorize(message.clone()) {
+            AuthorizationStatus::ChallengeRequired(challenge) => challenge,
+            _ => panic!("Challenge must be required"),
+        };
+        assert_eq!(challenge.factor, AuthFactorKind::ExternalCommand);
+        assert!(controller.check_challenge_response(&AuthChallengeResponse::new(&challenge, "root")).is_none());
+        // Failed challenge can not be retried
+        assert!(controller.check_challenge_response(&AuthChallengeResponse::new(&challenge, "operator")).is_none());
+
+        let challenge = match controller.authorize(message) {
+            AuthorizationStatus::ChallengeRequired(challenge) => challenge,
+            _ => panic!("Challenge must be required"),
+        };
+        let (signing_cert_out, _) = controller.check_challenge_response(
+            &AuthChallengeResponse::new(&challenge, "operator")).unwrap();
+        assert_eq!(signing_cert_out.get_serial(), 1);
+    }
+
+    ///
+    /// Creates stores of server and client: server knows only root, client has
+    /// root -> intermediate(10) -> signing(11) -> encryption(12)
+    ///
+    fn create_chain_stores() -> (Box<CertificateServiceBinder>, Box<CertificateServiceBinder>){
+        let (_, root_certificate, _) = create_sample_certificates();
+        let (public_key, secret_key) = generate_falcon1024_keypair_from_seed(b"intermediate");
+        let mut intermediate = Falcon1024Certificate{
+            serial_number: 10,
+            parent_serial_number: ROOT_CERTIFICATE_SERIAL,
+            secret_key: Some(secret_key),
+            public_key,
+            signature: None,
+            name: "intermediate".to_string(),
+            flags: FLAG_SIGN_CERTS,
+            metadata: Default::default(),
+        };
+        intermediate.signature = Some(root_certificate.sign_data(&intermediate.clone_without_signature_and_sk(),
+                                                                 HashType::None).unwrap());
+        let (public_key, secret_key) = generate_falcon1024_keypair_from_seed(b"signing");
+        let mut signing = Falcon1024Certificate{
+            serial_number: 11,
+            parent_serial_number: 10,
+            secret_key: Some(secret_key),
+            public_key,
+            signature: None,
+            name: "signing".to_string(),
+            flags: FLAG_SIGN_MESSAGES | FLAG_SIGN_CERTS,
+            metadata: Default::default(),
+        };
+        signing.signature = Some(intermediate.sign_data(&signing.clone_without_signature_and_sk(),
+                                                        HashType::None).unwrap());
+        let (public_key, secret_key) = generate_kyber1024_keypair_from_seed(b"encryption");
+        let mut encryption = Kyber1024Certificate{
+            serial_number: 12,
+            parent_serial_number: 11,
+            secret_key: Some(secret_key),
+            public_key,
+            signature: None,
+            name: "encryption".to_string(),
+            flags: 0,
+            metadata: Default::default(),
+        };
+        encryption.signature = Some(signing.sign_data(&encryption.clone_without_signature_and_sk(),
+                                                      HashType::None).unwrap());
+
+        let mut server = BinderAsyncService::run(Box::new(AsyncCertificateServiceImpl::new("/tmp/test_chain_server.dat")));
+        let mut server_binder = server.bind();
+        server_binder.set_root_certificate(root_certificate.clone_without_sk());
+        let mut client = BinderAsyncService::run(Box::new(AsyncCertificateServiceImpl::new("/tmp/test_chain_client.dat")));
+        let mut client_binder = client.bind();
+        client_binder.set_root_certificate(root_certificate);
+        assert!(client_binder.add_signing_certificate(intermediate));
+        assert!(client_binder.add_signing_certificate(signing));
+        assert!(client_binder.add_encryption_certificate(encryption));
+        (server_binder, client_binder)
+    }
+
+    #[test]
+    fn test_chain_persisted_only_after_verification() {
+        init_tokio();
+        let (server_binder, client_binder) = create_chain_stores();
+        let mut client = AuthorizationController::new(client_binder);
+        let mut server = AuthorizationController::new(server_binder);
+        let message = client.generate_authorization_message(12, 11, true).unwrap();
+        assert_eq!(message.signing_chain.len(), 2);
+
+        let mut tampered = message.clone();
+        tampered.timestamp += 1;
+        assert!(server.check_authorization_message(tampered).is_none());
+        assert!(server.certificate_service_binder.get_signing_certificate(10).is_none());
+
+        server.set_persist_chain(false);
+        assert!(server.check_authorization_message(message.clone()).is_some());
+        assert!(server.certificate_service_binder.get_signing_certificate(10).is_none());
+
+        server.set_persist_chain(true);
+        assert!(server.check_authorization_message(message).is_some());
+        assert!(server.certificate_service_binder.get_signing_certificate(10).is_some());
+        assert!(server.certificate_service_binder.get_signing_certificate(11).is_some());
+        assert!(server.certificate_service_binder.get_encryption_certificate(12).is_some());
+    }
+
+    #[test]
+    fn test_chain_persisted_only_when_authorized() {
+        init_tokio();
+        let (server_binder, client_binder) = create_chain_stores();
+        let mut client = AuthorizationController::new(client_binder);
+        let mut server = AuthorizationController::new(server_binder);
+        let message = client.generate_authorization_message(12, 11, true).unwrap();
+        let file = std::env::temp_dir().join(format!("milkyway-authorization-access-{}.dat", rand::random::<u64>()));
+        let access = AccessControl::open_shared(file.to_str().unwrap());
+        access.lock().unwrap().add(AccessRule::Block, PeerSelector::Serial(11));
+        server.set_access_control(access.clone());
+        // Valid chain of blocked client is not saved
+        assert!(matches!(server.authorize(message.clone()), AuthorizationStatus::Rejected));
+        assert!(server.certificate_service_binder.get_signing_certificate(10).is_none());
+        assert!(server.certificate_service_binder.get_encryption_certificate(12).is_none());
+
+        access.lock().unwrap().remove(&PeerSelector::Serial(11));
+        assert!(matches!(server.authorize(message), AuthorizationStatus::Authorized(_)));
+        assert!(server.certificate_service_binder.get_signing_certificate(10).is_some());
+        assert!(server.certificate_service_binder.get_encryption_certificate(12).is_some());
+        let _ = std::fs::remove_file(file);
+    }
+
+    #[test]
+    fn test_expected_identity_of_peer() {
+        init_tokio();
+        let (verifier_binder, peer_binder) = create_chain_stores();
+        let message = AuthorizationController::new(peer_binder).generate_authorization_message(12, 11, true).unwrap();
+        let identities = Arc::new(Mutex::new(ExpectedIdentities::new()));
+        identities.lock().unwrap().set_identity(5, ExpectedIdentity::new().set_name("signing").set_serial(11).clone());
+        let mut verifier = AuthorizationController::new(verifier_binder);
+        verifier.set_expected_identities(identities.clone());
+        assert!(verifier.check_peer_authorization_message(5, message.clone()).is_some());
+        // Address of peer 6 is answered by a host with valid certificate, but not the expected one
+        identities.lock().unwrap().set_identity(6, ExpectedIdentity::new().set_serial(42).clone());
+        assert!(verifier.check_peer_authorization_message(6, message.clone()).is_none());
+        identities.lock().unwrap().set_mode(IdentityMode::Lenient);
+        assert!(verifier.check_peer_authorization_message(6, message).is_some());
+    }
+
+    #[test]
+    fn test_missing_chain_requested() {
+        init_tokio();
+        let (server_binder, client_binder) = create_chain_stores();
+        let mut client = AuthorizationController::new(client_binder);
+        let mut server = AuthorizationController::new(server_binder);
+        let message = client.generate_authorization_message(12, 11, false).unwrap();
+        let request = match server.authorize(message) {
+            AuthorizationStatus::ChainRequired(request) => request,
+            _ => panic!("Chain must be requested"),
+        };
+        assert_eq!(request.serials, vec![10]);
+        let response = client.generate_chain_response(&request);
+        assert_eq!(response.certificates.len(), 1);
+        assert!(response.certificates[0].secret_key.is_none());
+        match server.check_chain_response(&response) {
+            AuthorizationStatus::Authorized(certificates) => assert_eq!(certificates.0.get_serial(), 11),
+            _ => panic!("Client must be authorized"),
+        }
+        // Each request may be answered only once
+        assert!(matches!(server.check_chain_response(&response), AuthorizationStatus::Rejected));
+    }
+
+    #[test]
+    fn test_pinned_peer_trusted_without_root() {
+        init_tokio();
+        let (encryption_cert, root_certificate, signing_cert) = create_sample_certificates();
+        let mut client_service = BinderAsyncService::run(Box::new(AsyncCertificateServiceImpl::new("/tmp/test_pin_client.dat")));
+        let mut client_binder = client_service.bind();
+        client_binder.set_root_certificate(root_certificate);
+        assert!(client_binder.add_signing_certificate(signing_cert.clone()));
+        assert!(client_binder.add_encryption_certificate(encryption_cert));
+        let message = AuthorizationController::new(client_binder).generate_authorization_message(2, 1, false).unwrap();
+
+        // Server does not know root yet
+        let mut server_service = BinderAsyncService::run(Box::new(AsyncCertificateServiceImpl::new("/tmp/test_pin_server.dat")));
+        let file = std::env::temp_dir().join(format!("milkyway-pins-{}.dat", rand::random::<u64>()));
+        let pins = PeerPins::open_shared(file.to_str().unwrap());
+        let mut server = AuthorizationController::new(server_service.bind());
+        server.set_peer_pins(pins.clone());
+        assert!(matches!(server.authorize_peer(5, message.clone()), AuthorizationStatus::Rejected));
+        assert_eq!(pins.lock().unwrap().get_pending()[0].fingerprint, signing_cert.get_fingerprint());
+        // Operator confirms fingerprint seen on first connection
+        pins.lock().unwrap().confirm(5);
+        assert!(matches!(server.authorize_peer(5, message.clone()), AuthorizationStatus::Authorized(_)));
+        assert!(server.check_peer_authorization_message(5, message.clone()).is_some());
+        // Another peer can not use the same pin, pinned peer can not present another certificate
+        assert!(matches!(server.authorize_peer(6, message.clone()), AuthorizationStatus::Rejected));
+        pins.lock().unwrap().pin(5, "00").unwrap();
+        assert!(matches!(server.authorize_peer(5, message.clone()), AuthorizationStatus::Rejected));
+        assert!(server.check_peer_authorization_message(5, message).is_none());
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_chain_inclusion_policy() {
+        init_tokio();
+        let (_, client_binder) = create_chain_stores();
+        let mut client = AuthorizationController::new(client_binder);
+        let policy = ChainInclusionPolicy::new_shared(ChainInclusion::Missing);
+        client.set_chain_inclusion(policy.clone());
+        // Peer seen first time gets whole chain, then only certificates it is missing
+        let (message, gap) = client.generate_peer_authorization_message(5, 12, 11).unwrap();
+        assert_eq!(message.signing_chain.len(), 2);
+        assert!(gap.is_none());
+        let (message, _) = client.generate_peer_authorization_message(5, 12, 11).unwrap();
+        assert!(message.signing_chain.is_empty());
+        assert_eq!(policy.lock().unwrap().get_stats().cache_hits, 1);
+        // Peer which requests intermediate again does not keep chains
+        client.generate_peer_chain_response(5, &ChainRequest{
+            request_id: 1,
+            serials: vec![10],
+            timestamp: 0,
+        });
+        let (message, _) = client.generate_peer_authorization_message(5, 12, 11).unwrap();
+        assert_eq!(message.signing_chain.iter().map(|certificate| certificate.get_serial()).collect::<Vec<u128>>(),
+                   vec![10]);
+
+        // Parent missing locally is flagged instead of failing
+        assert!(client.certificate_service_binder.remove_signing_certificate(10));
+        policy.lock().unwrap().invalidate_chains();
+        assert!(client.generate_authorization_message(12, 11, true).is_err());
+        let (message, gap) = client.generate_peer_authorization_message(6, 12, 11).unwrap();
+        assert_eq!(gap, Some(10));
+        assert_eq!(message.signing_chain.len(), 1);
+        assert_eq!(policy.lock().unwrap().get_stats().gaps, 1);
+    }
+}
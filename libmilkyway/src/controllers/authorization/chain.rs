@@ -0,0 +1,61 @@
+use libmilkyway_derive::{Describe, Deserializable, Serializable};
+use crate::message::common::{AsMessage, Message};
+use crate::message::types::MessageType;
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+
+///
+/// Request for signing certificates which are missing to verify an authorization message
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq, Describe)]
+pub struct ChainRequest{
+    pub request_id: u128,
+    /** Serials of missing certificates, their ancestors are expected as well **/
+    pub serials: Vec<u128>,
+    /** When request was issued, in milliseconds **/
+    pub timestamp: u128,
+}
+
+///
+/// Certificates sent in response to ChainRequest
+///
+#[derive(Serializable, Deserializable, Clone, PartialEq, Describe)]
+pub struct ChainResponse{
+    pub request_id: u128,
+    pub certificates: Vec<Falcon1024Certificate>,
+}
+
+impl AsMessage for ChainRequest{
+    fn as_message(&self) -> Message {
+        Message{
+            id: 0,
+            timestamp: 0,
+            message_type: MessageType::ChainRequest,
+            data: Some(self.serialize()),
+            signature: None,
+            source: 0,
+            destination: 0,
+            module_id: 0,
+            certificate_id: 0,
+        }
+    }
+}
+
+impl AsMessage for ChainResponse{
+    fn as_message(&self) -> Message {
+        Message{
+            id: 0,
+            timestamp: 0,
+            message_type: MessageType::ChainResponse,
+            data: Some(self.serialize()),
+            signature: None,
+            source: 0,
+            destination: 0,
+            module_id: 0,
+            certificate_id: 0,
+        }
+    }
+}
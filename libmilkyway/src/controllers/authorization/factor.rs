@@ -0,0 +1,566 @@
+use std::collections::HashMap;
+#[cfg(unix)]
+use std::io::Write;
+#[cfg(unix)]
+use std::process::{Command, Stdio};
+#[cfg(unix)]
+use std::sync::mpsc;
+use std::time::Duration;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use libmilkyway_derive::{Describe, Deserializable, EnumDeserializable, EnumSerializable, Serializable};
+use crate::message::common::{AsMessage, Message};
+use crate::message::types::MessageType;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+
+///
+/// Default time step of TOTP codes in seconds
+///
+pub const DEFAULT_TOTP_STEP: u64 = 30;
+
+///
+/// Default amount of digits in TOTP codes
+///
+pub const DEFAULT_TOTP_DIGITS: u32 = 6;
+
+///
+/// Maximum amount of digits in TOTP codes, codes are taken from 31-bit number
+///
+pub const MAX_TOTP_DIGITS: u32 = 9;
+
+///
+/// Default time given to authentication command before it is killed, in seconds
+///
+pub const DEFAULT_COMMAND_TIMEOUT_SECONDS: u64 = 10;
+
+///
+/// Kinds of additional authentication factors
+///
+#[derive(EnumSerializable, EnumDeserializable, Clone, Copy, Debug, PartialEq, Describe)]
+pub enum AuthFactorKind{
+    /** Time-based one-time password(RFC 6238) **/
+    Totp,
+    /** Response is checked by an external command configured on daemon **/
+    ExternalCommand,
+    /** Operating system user of peer connected over unix socket **/
+    OsUser,
+}
+
+///
+/// Operating system user and group of process on the other end of unix socket, taken from
+/// socket itself, so peer can not forge them
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PeerCredentials{
+    pub uid: u32,
+    pub gid: u32,
+}
+
+///
+/// Gets credentials of peer connected to unix socket
+///
+/// # Arguments
+/// * socket: &S: connected unix socket, e.g. UnixStream
+///
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn get_peer_credentials<S: std::os::unix::io::AsRawFd>(socket: &S) -> std::io::Result<PeerCredentials>{
+    let mut credentials = libc::ucred{ pid: 0, uid: 0, gid: 0 };
+    let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(socket.as_raw_fd(), libc::SOL_SOCKET, libc::SO_PEERCRED,
+                         &mut credentials as *mut libc::ucred as *mut libc::c_void, &mut length)
+    };
+    if result != 0{
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(PeerCredentials{
+        uid: credentials.uid,
+        gid: credentials.gid,
+    })
+}
+
+///
+/// Gets credentials of peer connected to unix socket
+///
+/// # Arguments
+/// * socket: &S: connected unix socket, e.g. UnixStream
+///
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+pub fn get_peer_credentials<S: std::os::unix::io::AsRawFd>(socket: &S) -> std::io::Result<PeerCredentials>{
+    let (mut uid, mut gid) = (0, 0);
+    if unsafe { libc::getpeereid(socket.as_raw_fd(), &mut uid, &mut gid) } != 0{
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(PeerCredentials{
+        uid,
+        gid,
+    })
+}
+
+///
+/// Challenge sent to holder of certificate with FLAG_REQUIRE_2FA
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq, Describe)]
+pub struct AuthChallenge{
+    pub challenge_id: u128,
+    pub factor: AuthFactorKind,
+    /** Serial of signing certificate being authorized **/
+    pub certificate_serial: u128,
+    /** When challenge was issued, in milliseconds **/
+    pub timestamp: u128,
+}
+
+///
+/// Response to authentication challenge
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq, Describe)]
+pub struct AuthChallengeResponse{
+    pub challenge_id: u128,
+    /** Factor specific data, e.g. TOTP code as text **/
+    pub response: Serialized,
+}
+
+impl AuthChallengeResponse {
+    ///
+    /// Creates response to challenge
+    ///
+    /// # Arguments
+    /// * challenge: &AuthChallenge: challenge to respond to
+    /// * response: &str: e.g. TOTP code
+    ///
+    pub fn new(challenge: &AuthChallenge, response: &str) -> AuthChallengeResponse{
+        AuthChallengeResponse{
+            challenge_id: challenge.challenge_id,
+            response: response.as_bytes().to_vec(),
+        }
+    }
+}
+
+impl AsMessage for AuthChallenge{
+    fn as_message(&self) -> Message {
+        Message{
+            id: 0,
+            timestamp: 0,
+            message_type: MessageType::AuthChallenge,
+            data: Some(self.serialize()),
+            signature: None,
+            source: 0,
+            destination: 0,
+            module_id: 0,
+            certificate_id: 0,
+        }
+    }
+}
+
+impl AsMessage for AuthChallengeResponse{
+    fn as_message(&self) -> Message {
+        Message{
+            id: 0,
+            timestamp: 0,
+            message_type: MessageType::AuthChallengeResponse,
+            data: Some(self.serialize()),
+            signature: None,
+            source: 0,
+            destination: 0,
+            module_id: 0,
+            certificate_id: 0,
+        }
+    }
+}
+
+///
+/// An additional authentication factor checked after certificate was verified
+///
+pub trait AuthenticationFactor: Send + Sync{
+    ///
+    /// Gets kind of factor which is sent in challenges
+    ///
+    fn get_kind(&self) -> AuthFactorKind;
+
+    ///
+    /// Verifies response to challenge
+    ///
+    /// # Arguments
+    /// * challenge: &AuthChallenge: challenge issued by controller
+    /// * response: &AuthChallengeResponse: response of certificate holder
+    /// * peer: Option<&PeerCredentials>: credentials of peer, None unless it is connected over unix socket
+    ///
+    /// returns: bool: true if factor is satisfied
+    ///
+    fn verify(&mut self, challenge: &AuthChallenge, response: &AuthChallengeResponse,
+              peer: Option<&PeerCredentials>) -> bool;
+}
+
+///
+/// Decodes RFC 4648 base32 as used by authenticator applications, padding and case are ignored
+///
+pub fn decode_base32(value: &str) -> Option<Vec<u8>>{
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut result = Vec::new();
+    let mut buffer: u64 = 0;
+    let mut bits = 0;
+    for symbol in value.trim_end_matches('=').bytes(){
+        let index = ALPHABET.iter().position(|known| *known == symbol.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | index as u64;
+        bits += 5;
+        if bits >= 8{
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+        }
+    }
+    Some(result)
+}
+
+///
+/// Generates TOTP code(RFC 6238 with HMAC-SHA1)
+///
+/// # Arguments
+/// * secret: &[u8]: shared secret
+/// * timestamp: u64: time in seconds since epoch
+/// * step: u64: time step in seconds
+/// * digits: u32: amount of digits in code
+///
+pub fn generate_totp(secret: &[u8], timestamp: u64, step: u64, digits: u32) -> u32{
+    let counter = timestamp / step;
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let code = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+    code % 10u32.pow(digits)
+}
+
+///
+/// TOTP factor with secrets shared with holders of certificates
+///
+pub struct TotpFactor{
+    secrets: HashMap<u128, Vec<u8>>,
+    step: u64,
+    digits: u32,
+    /** How many steps before and after current one are accepted **/
+    skew: u64,
+    /** Last accepted counter for each certificate, codes can not be reused **/
+    last_counters: HashMap<u128, u64>,
+}
+
+impl Default for TotpFactor {
+    fn default() -> Self {
+        TotpFactor::new(DEFAULT_TOTP_STEP, DEFAULT_TOTP_DIGITS, 1).unwrap()
+    }
+}
+
+impl TotpFactor {
+    ///
+    /// Creates TOTP factor
+    ///
+    /// # Arguments
+    /// * step: u64: time step in seconds, can not be zero
+    /// * digits: u32: amount of digits in code, from 1 to MAX_TOTP_DIGITS
+    /// * skew: u64: how many steps before and after current one are accepted
+    ///
+    pub fn new(step: u64, digits: u32, skew: u64) -> Result<TotpFactor, &'static str>{
+        if step == 0{
+            return Err("TOTP step can not be zero");
+        }
+        if digits == 0 || digits > MAX_TOTP_DIGITS{
+            return Err("TOTP codes must have from 1 to 9 digits");
+        }
+        Ok(TotpFactor{
+            secrets: HashMap::new(),
+            step,
+            digits,
+            skew,
+            last_counters: HashMap::new(),
+        })
+    }
+
+    ///
+    /// Sets shared secret of certificate holder
+    ///
+    pub fn add_secret(&mut self, certificate_serial: u128, secret: Vec<u8>) -> &mut TotpFactor{
+        self.secrets.insert(certificate_serial, secret);
+        self
+    }
+
+    ///
+    /// Verifies code at given time
+    ///
+    /// # Arguments
+    /// * certificate_serial: u128: serial of certificate holder
+    /// * code: &str: code entered by certificate holder
+    /// * timestamp: u64: current time in seconds
+    ///
+    pub fn verify_code(&mut self, certificate_serial: u128, code: &str, timestamp: u64) -> bool{
+        let (secret, code) = match (self.secrets.get(&certificate_serial), code.trim().parse::<u32>()) {
+            (Some(secret), Ok(code)) => (secret, code),
+            _ => return false,
+        };
+        let current = timestamp / self.step;
+        let first = current.saturating_sub(self.skew);
+        let last_used = self.last_counters.get(&certificate_serial).copied();
+        for counter in first..=current + self.skew{
+            if last_used.is_some_and(|used| counter <= used){
+                continue;
+            }
+            if generate_totp(secret, counter * self.step, self.step, self.digits) == code{
+                self.last_counters.insert(certificate_serial, counter);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+impl AuthenticationFactor for TotpFactor{
+    #[inline]
+    fn get_kind(&self) -> AuthFactorKind {
+        AuthFactorKind::Totp
+    }
+
+    fn verify(&mut self, challenge: &AuthChallenge, response: &AuthChallengeResponse,
+              _peer: Option<&PeerCredentials>) -> bool {
+        let code = String::from_utf8_lossy(&response.response).to_string();
+        let now = (crate::get_timestamp_with_milliseconds() / 1000) as u64;
+        self.verify_code(challenge.certificate_serial, &code, now)
+    }
+}
+
+///
+/// Factor checked by an external command. Response is written to standard input of command,
+/// serial of certificate is passed in MWAY_CERTIFICATE_SERIAL environment variable.
+/// Factor is satisfied if command exits successfully before timeout, otherwise command is killed.
+/// Commands are run on unix only, elsewhere factor is never satisfied.
+///
+pub struct ExternalCommandFactor{
+    command: String,
+    arguments: Vec<String>,
+    timeout: Duration,
+}
+
+impl ExternalCommandFactor {
+    pub fn new(command: &str, arguments: Vec<String>) -> ExternalCommandFactor{
+        ExternalCommandFactor{
+            command: command.to_string(),
+            arguments,
+            timeout: Duration::from_secs(DEFAULT_COMMAND_TIMEOUT_SECONDS),
+        }
+    }
+
+    ///
+    /// Sets time given to command to check response
+    ///
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut ExternalCommandFactor{
+        self.timeout = timeout;
+        self
+    }
+
+    // Runs command with response on its standard input, command is killed once timeout elapses
+    #[cfg(unix)]
+    fn run(&self, challenge: &AuthChallenge, response: &AuthChallengeResponse) -> bool{
+        let child = Command::new(&self.command)
+            .args(&self.arguments)
+            .env("MWAY_CERTIFICATE_SERIAL", challenge.certificate_serial.to_string())
+            .env("MWAY_CHALLENGE_ID", challenge.challenge_id.to_string())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(error) => {
+                log::error!("Can not run authentication command {}: {}", self.command, error);
+                return false;
+            }
+        };
+        if let Some(mut stdin) = child.stdin.take(){
+            if stdin.write_all(&response.response).is_err(){
+                let _ = child.kill();
+                let _ = child.wait();
+                return false;
+            }
+        }
+        // Exit is awaited on a thread of its own, while child stays here so it can be killed
+        let (exited, exit) = mpsc::channel();
+        let process_id = child.id();
+        std::thread::spawn(move || {
+            wait_for_exit(process_id);
+            let _ = exited.send(());
+        });
+        if exit.recv_timeout(self.timeout).is_ok(){
+            return child.wait().is_ok_and(|status| status.success());
+        }
+        log::error!("Authentication command {} timed out", self.command);
+        let _ = child.kill();
+        let _ = child.wait();
+        false
+    }
+
+    // Command could not be killed once it times out without waiting for it like on unix
+    #[cfg(not(unix))]
+    fn run(&self, _challenge: &AuthChallenge, _response: &AuthChallengeResponse) -> bool{
+        log::error!("Can not run authentication command {}: commands are supported on unix only", self.command);
+        false
+    }
+}
+
+impl AuthenticationFactor for ExternalCommandFactor{
+    #[inline]
+    fn get_kind(&self) -> AuthFactorKind {
+        AuthFactorKind::ExternalCommand
+    }
+
+    fn verify(&mut self, challenge: &AuthChallenge, response: &AuthChallengeResponse,
+              _peer: Option<&PeerCredentials>) -> bool {
+        self.run(challenge, response)
+    }
+}
+
+///
+/// Factor asserting that certificate is used by one of allowed operating system users.
+/// Users are taken from credentials of unix socket(see get_peer_credentials), so factor is
+/// satisfied only on local connections, e.g. admin socket of daemon, and never over TCP.
+///
+#[derive(Clone, Default)]
+pub struct OsUserFactor{
+    /** User IDs allowed to use certificate by its serial **/
+    allowed_users: HashMap<u128, Vec<u32>>,
+}
+
+impl OsUserFactor {
+    ///
+    /// Allows user to use certificate
+    ///
+    /// # Arguments
+    /// * certificate_serial: u128: serial of signing certificate
+    /// * uid: u32: ID of operating system user
+    ///
+    pub fn allow_user(&mut self, certificate_serial: u128, uid: u32) -> &mut OsUserFactor{
+        self.allowed_users.entry(certificate_serial).or_default().push(uid);
+        self
+    }
+
+    ///
+    /// Checks whether peer may use certificate
+    ///
+    /// # Arguments
+    /// * certificate_serial: u128: serial of signing certificate
+    /// * peer: Option<&PeerCredentials>: credentials of peer, None if peer is not local
+    ///
+    pub fn is_allowed(&self, certificate_serial: u128, peer: Option<&PeerCredentials>) -> bool{
+        let peer = match peer {
+            Some(peer) => peer,
+            None => {
+                log::warn!("Certificate {} requires OS user, which is only known on unix socket", certificate_serial);
+                return false;
+            }
+        };
+        self.allowed_users.get(&certificate_serial).is_some_and(|users| users.contains(&peer.uid))
+    }
+}
+
+impl AuthenticationFactor for OsUserFactor{
+    #[inline]
+    fn get_kind(&self) -> AuthFactorKind {
+        AuthFactorKind::OsUser
+    }
+
+    fn verify(&mut self, challenge: &AuthChallenge, _response: &AuthChallengeResponse,
+              peer: Option<&PeerCredentials>) -> bool {
+        self.is_allowed(challenge.certificate_serial, peer)
+    }
+}
+
+///
+/// Blocks until process exits without reaping it, so its ID is not reused while Child
+/// of process may still kill it
+///
+#[cfg(unix)]
+fn wait_for_exit(process_id: u32){
+    loop {
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let result = unsafe {
+            libc::waitid(libc::P_PID, process_id as libc::id_t, &mut info, libc::WEXITED | libc::WNOWAIT)
+        };
+        if result == 0 || std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted{
+            return;
+        }
+    }
+}
+
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    fn create_challenge(factor: AuthFactorKind) -> AuthChallenge{
+        AuthChallenge{
+            challenge_id: 1,
+            factor,
+            certificate_serial: 7,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn test_totp_vectors() {
+        // RFC 6238 appendix B, SHA1
+        assert_eq!(generate_totp(RFC_SECRET, 59, 30, 8), 94287082);
+        assert_eq!(generate_totp(RFC_SECRET, 1111111109, 30, 8), 7081804);
+        assert_eq!(generate_totp(RFC_SECRET, 2000000000, 30, 8), 69279037);
+        assert_eq!(decode_base32("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap(), RFC_SECRET.to_vec());
+        assert_eq!(decode_base32("not base32!"), None);
+    }
+
+    #[test]
+    fn test_totp_factor() {
+        assert!(TotpFactor::new(0, 6, 1).is_err());
+        assert!(TotpFactor::new(30, 10, 1).is_err());
+        let mut factor = TotpFactor::new(30, 8, 1).unwrap();
+        factor.add_secret(7, RFC_SECRET.to_vec());
+        assert!(!factor.verify_code(7, "00000000", 59));
+        assert!(!factor.verify_code(8, "94287082", 59));
+        // Previous step is accepted because of skew
+        assert!(factor.verify_code(7, "94287082", 75));
+        // Code can not be reused
+        assert!(!factor.verify_code(7, "94287082", 75));
+    }
+
+    #[test]
+    fn test_external_command_factor() {
+        let challenge = create_challenge(AuthFactorKind::ExternalCommand);
+        let mut command = ExternalCommandFactor::new("sh", vec!["-c".to_string(),
+                                                               "[ \"$(cat)\" = \"secret-$MWAY_CERTIFICATE_SERIAL\" ]".to_string()]);
+        assert!(command.verify(&challenge, &AuthChallengeResponse::new(&challenge, "secret-7"), None));
+        assert!(!command.verify(&challenge, &AuthChallengeResponse::new(&challenge, "secret-8"), None));
+
+        let mut command = ExternalCommandFactor::new("sh", vec!["-c".to_string(), "sleep 5".to_string()]);
+        command.set_timeout(Duration::from_millis(100));
+        let started = Instant::now();
+        assert!(!command.verify(&challenge, &AuthChallengeResponse::new(&challenge, "secret-7"), None));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_os_user_factor() {
+        let (local, _remote) = std::os::unix::net::UnixStream::pair().unwrap();
+        let peer = get_peer_credentials(&local).unwrap();
+        assert_eq!(peer.uid, unsafe { libc::geteuid() });
+
+        let challenge = create_challenge(AuthFactorKind::OsUser);
+        let response = AuthChallengeResponse::new(&challenge, "");
+        let mut factor = OsUserFactor::default();
+        assert!(!factor.verify(&challenge, &response, Some(&peer)));
+        factor.allow_user(7, peer.uid);
+        assert!(factor.verify(&challenge, &response, Some(&peer)));
+        // Peers connected over TCP have no credentials
+        assert!(!factor.verify(&challenge, &response, None));
+        assert!(!factor.is_allowed(8, Some(&peer)));
+    }
+}
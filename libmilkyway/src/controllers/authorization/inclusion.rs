@@ -0,0 +1,204 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use crate::pki::certificate::Certificate;
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+
+///
+/// Which part of signing chain is included in outgoing authorization messages
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChainInclusion{
+    /** Chain is never sent, peer asks for missing certificates with ChainRequest **/
+    None,
+    /** Whole chain up to root is sent **/
+    Full,
+    /** Only certificates peer is likely missing are sent, whole chain to peers seen first time **/
+    Missing,
+}
+
+///
+/// Ancestors of a certificate as found in local store
+///
+#[derive(Clone)]
+pub struct BuiltChain{
+    /** Ancestors without secret keys, the one closest to root first **/
+    pub certificates: Vec<Falcon1024Certificate>,
+    /** Serial of first ancestor missing in local store if chain does not reach root **/
+    pub gap: Option<u128>,
+}
+
+///
+/// Counters of chain inclusion
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChainInclusionStats{
+    /** Chains built from certificate store **/
+    pub built: u64,
+    /** Chains taken from cache **/
+    pub cache_hits: u64,
+    /** Certificates included in messages **/
+    pub included: u64,
+    /** Certificates left out as already known to peer **/
+    pub omitted: u64,
+    /** Chains sent with a gap **/
+    pub gaps: u64,
+}
+
+///
+/// What is known about certificates of a peer
+///
+#[derive(Default)]
+struct PeerChainKnowledge{
+    /** Certificates peer presented or was sent **/
+    known: HashSet<u128>,
+    /** Certificates peer requested after it was assumed to have them, they are always sent **/
+    forgotten: HashSet<u128>,
+}
+
+///
+/// Decides which certificates of chain are included in authorization messages sent to peers.
+///
+/// Certificates peer presented in its own authorization messages or was already sent are assumed
+/// to be known to it and are left out. If peer still requests them with ChainRequest, it does not
+/// keep received chains, so they are always sent to it from now on. Built chains are cached by
+/// serial of encryption certificate, cache must be invalidated once local certificates change.
+///
+pub struct ChainInclusionPolicy{
+    inclusion: ChainInclusion,
+    peers: HashMap<u128, PeerChainKnowledge>,
+    chains: HashMap<u128, BuiltChain>,
+    stats: ChainInclusionStats,
+}
+
+///
+/// A chain inclusion policy shared between connections
+///
+pub type SharedChainInclusionPolicy = Arc<Mutex<ChainInclusionPolicy>>;
+
+impl ChainInclusionPolicy {
+    ///
+    /// Creates policy
+    ///
+    /// # Arguments
+    /// * inclusion: ChainInclusion: which part of chain to include
+    ///
+    pub fn new(inclusion: ChainInclusion) -> ChainInclusionPolicy{
+        ChainInclusionPolicy{
+            inclusion,
+            peers: HashMap::new(),
+            chains: HashMap::new(),
+            stats: ChainInclusionStats::default(),
+        }
+    }
+
+    ///
+    /// Creates a shared policy
+    ///
+    #[inline]
+    pub fn new_shared(inclusion: ChainInclusion) -> SharedChainInclusionPolicy{
+        Arc::new(Mutex::new(Self::new(inclusion)))
+    }
+
+    #[inline]
+    pub fn get_inclusion(&self) -> ChainInclusion{
+        self.inclusion
+    }
+
+    #[inline]
+    pub fn set_inclusion(&mut self, inclusion: ChainInclusion){
+        self.inclusion = inclusion;
+    }
+
+    #[inline]
+    pub fn get_stats(&self) -> ChainInclusionStats{
+        self.stats.clone()
+    }
+
+    ///
+    /// Gets cached chain of encryption certificate
+    ///
+    pub fn get_chain(&mut self, serial: u128) -> Option<BuiltChain>{
+        let chain = self.chains.get(&serial).cloned();
+        if chain.is_some(){
+            self.stats.cache_hits += 1;
+        }
+        chain
+    }
+
+    ///
+    /// Caches chain of encryption certificate
+    ///
+    pub fn cache_chain(&mut self, serial: u128, chain: BuiltChain){
+        self.stats.built += 1;
+        self.chains.insert(serial, chain);
+    }
+
+    ///
+    /// Drops cached chains, must be called once local certificates are added, rotated or removed
+    ///
+    #[inline]
+    pub fn invalidate_chains(&mut self){
+        self.chains.clear();
+    }
+
+    ///
+    /// Selects certificates of chain to send to peer
+    ///
+    /// # Arguments
+    /// * peer_id: u128: ID of peer message is sent to
+    /// * chain: &BuiltChain: whole chain
+    ///
+    /// returns: Vec<Falcon1024Certificate>: certificates to include, the one closest to root first
+    ///
+    pub fn select(&mut self, peer_id: u128, chain: &BuiltChain) -> Vec<Falcon1024Certificate>{
+        let selected: Vec<Falcon1024Certificate> = match self.inclusion {
+            ChainInclusion::None => Vec::new(),
+            ChainInclusion::Full => chain.certificates.clone(),
+            ChainInclusion::Missing => match self.peers.get(&peer_id) {
+                Some(peer) => chain.certificates.iter()
+                    .filter(|certificate| {
+                        let serial = certificate.get_serial();
+                        !peer.known.contains(&serial) || peer.forgotten.contains(&serial)
+                    })
+                    .cloned()
+                    .collect(),
+                None => chain.certificates.clone(),
+            },
+        };
+        self.stats.included += selected.len() as u64;
+        self.stats.omitted += (chain.certificates.len() - selected.len()) as u64;
+        if chain.gap.is_some(){
+            self.stats.gaps += 1;
+        }
+        selected
+    }
+
+    ///
+    /// Records certificates peer presented or was sent, so they are not sent to it again
+    ///
+    pub fn record_known(&mut self, peer_id: u128, serials: &[u128]){
+        let peer = self.peers.entry(peer_id).or_default();
+        peer.known.extend(serials.iter().copied());
+    }
+
+    ///
+    /// Records certificates peer requested with ChainRequest. Certificates it was assumed to
+    /// have are always sent to it from now on.
+    ///
+    pub fn record_requested(&mut self, peer_id: u128, serials: &[u128]){
+        let peer = self.peers.entry(peer_id).or_default();
+        for serial in serials{
+            if peer.known.remove(serial){
+                peer.forgotten.insert(*serial);
+            }
+        }
+    }
+
+    ///
+    /// Forgets everything known about peer, e.g. once it is removed from network
+    ///
+    #[inline]
+    pub fn forget_peer(&mut self, peer_id: u128){
+        self.peers.remove(&peer_id);
+    }
+}
@@ -0,0 +1,679 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use sha2::{Digest, Sha256};
+use crate::controllers::admin::DaemonStatus;
+use crate::message::common::Message;
+use crate::message::types::MessageType;
+use crate::pki::certificate::{Certificate, FLAG_NO_READ, FLAG_NO_WRITE};
+use crate::pki::certificate::flags::format_flags;
+use crate::pki::key::CryptoKey;
+use crate::serialization::schema::json_string;
+use crate::services::certificate::CertificateService;
+use crate::services::name::NameService;
+use crate::transport::operator::is_operator_certificate;
+
+///
+/// Default address of gateway, loopback only as gateway speaks plain HTTP
+///
+pub const DEFAULT_GATEWAY_ADDRESS: &str = "127.0.0.1:8088";
+
+///
+/// Largest body of request gateway accepts, in bytes
+///
+pub const GATEWAY_MAX_BODY_SIZE: usize = 1 << 20;
+
+///
+/// Largest request line together with headers, in bytes
+///
+pub const GATEWAY_MAX_HEADER_SIZE: usize = 16 << 10;
+
+///
+/// Most connections gateway serves at once, others are answered with 503
+///
+pub const GATEWAY_MAX_CONNECTIONS: usize = 64;
+
+// Slow clients must not hold their connection threads forever
+const GATEWAY_IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+///
+/// Token external systems authenticate with(`Authorization: Bearer <token>`)
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct GatewayToken{
+    /** Name of integration, used in logs **/
+    pub name: String,
+    pub token: String,
+    /** Token may only query state **/
+    pub read_only: bool,
+    /** Operator certificate token acts for. Token is refused once certificate is not a trusted
+     operator certificate anymore, and no-read/no-write flags of certificate apply. **/
+    pub certificate: Option<u128>,
+}
+
+///
+/// Reasons why gateway refuses request
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum GatewayError{
+    /** Token is missing or unknown **/
+    Unauthorized,
+    /** Token or its certificate may not perform request **/
+    Forbidden(String),
+    NotFound,
+    BadRequest(String),
+    /** Request is larger than GATEWAY_MAX_HEADER_SIZE or GATEWAY_MAX_BODY_SIZE **/
+    TooLarge,
+    /** Daemon failed to execute request **/
+    Failed(String),
+    /** GATEWAY_MAX_CONNECTIONS connections are already served **/
+    Busy,
+}
+
+impl GatewayError {
+    ///
+    /// Gets HTTP status code of error
+    ///
+    pub fn get_status_code(&self) -> u16{
+        match self {
+            GatewayError::Unauthorized => 401,
+            GatewayError::Forbidden(_) => 403,
+            GatewayError::NotFound => 404,
+            GatewayError::BadRequest(_) => 400,
+            GatewayError::TooLarge => 413,
+            GatewayError::Failed(_) => 502,
+            GatewayError::Busy => 503,
+        }
+    }
+}
+
+impl Display for GatewayError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GatewayError::Unauthorized => write!(f, "valid bearer token is required"),
+            GatewayError::Forbidden(reason) => write!(f, "forbidden: {}", reason),
+            GatewayError::NotFound => write!(f, "not found"),
+            GatewayError::BadRequest(reason) => write!(f, "bad request: {}", reason),
+            GatewayError::TooLarge => write!(f, "request is too large"),
+            GatewayError::Failed(error) => write!(f, "{}", error),
+            GatewayError::Busy => write!(f, "gateway is busy"),
+        }
+    }
+}
+
+///
+/// Parsed HTTP request
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GatewayRequest{
+    pub method: String,
+    /** Path without query **/
+    pub path: String,
+    pub query: HashMap<String, String>,
+    /** Headers with lowercase names **/
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl GatewayRequest {
+    ///
+    /// Creates a request without headers and body
+    ///
+    /// # Arguments
+    /// * method: &str: HTTP method, e.g. GET
+    /// * target: &str: path with optional query, e.g. `/v1/messages?destination=1`
+    ///
+    pub fn new(method: &str, target: &str) -> GatewayRequest{
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        GatewayRequest{
+            method: method.to_string(),
+            path: path.to_string(),
+            query: query.split('&')
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| {
+                    let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                    (key.to_string(), value.to_string())
+                })
+                .collect(),
+            headers: HashMap::new(),
+            body: Vec::new(),
+        }
+    }
+
+    ///
+    /// Sets header, name is case-insensitive
+    ///
+    pub fn set_header(&mut self, name: &str, value: &str) -> &mut Self{
+        self.headers.insert(name.to_ascii_lowercase(), value.trim().to_string());
+        self
+    }
+
+    fn get_bearer_token(&self) -> Option<&str>{
+        self.headers.get("authorization")?.strip_prefix("Bearer ").map(str::trim)
+    }
+}
+
+///
+/// Reads HTTP/1.1 request. Chunked bodies are not supported.
+///
+/// # Arguments
+/// * reader: &mut R: connection of client
+///
+pub fn read_gateway_request<R: BufRead>(reader: &mut R) -> Result<GatewayRequest, GatewayError>{
+    let mut header_size = 0;
+    let mut read_line = |reader: &mut R| -> Result<String, GatewayError>{
+        let mut line = String::new();
+        let limit = (GATEWAY_MAX_HEADER_SIZE - header_size) as u64 + 1;
+        reader.by_ref().take(limit).read_line(&mut line)
+            .map_err(|error| GatewayError::BadRequest(error.to_string()))?;
+        header_size += line.len();
+        if header_size > GATEWAY_MAX_HEADER_SIZE{
+            return Err(GatewayError::TooLarge);
+        }
+        if !line.ends_with('\n'){
+            return Err(GatewayError::BadRequest("request is truncated".to_string()));
+        }
+        Ok(line.trim_end().to_string())
+    };
+    let request_line = read_line(reader)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/1.") => (method, target),
+        _ => return Err(GatewayError::BadRequest("malformed request line".to_string())),
+    };
+    let mut request = GatewayRequest::new(method, target);
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty(){
+            break;
+        }
+        let (name, value) = line.split_once(':')
+            .ok_or_else(|| GatewayError::BadRequest("malformed header".to_string()))?;
+        request.set_header(name, value);
+    }
+    if request.headers.contains_key("transfer-encoding"){
+        return Err(GatewayError::BadRequest("chunked bodies are not supported".to_string()));
+    }
+    let length = match request.headers.get("content-length") {
+        Some(length) => length.parse::<usize>()
+            .map_err(|_| GatewayError::BadRequest("invalid content-length".to_string()))?,
+        None => 0,
+    };
+    if length > GATEWAY_MAX_BODY_SIZE{
+        return Err(GatewayError::TooLarge);
+    }
+    request.body = vec![0u8; length];
+    reader.read_exact(&mut request.body).map_err(|error| GatewayError::BadRequest(error.to_string()))?;
+    Ok(request)
+}
+
+///
+/// Answer of gateway, body is JSON
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct GatewayResponse{
+    pub status: u16,
+    pub body: String,
+}
+
+impl GatewayResponse {
+    fn error(error: &GatewayError) -> GatewayResponse{
+        GatewayResponse{
+            status: error.get_status_code(),
+            body: format!("{{\"error\":{}}}", json_string(&error.to_string())),
+        }
+    }
+
+    ///
+    /// Writes response as HTTP/1.1, connection is closed afterwards
+    ///
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()>{
+        let reason = match self.status {
+            200 => "OK",
+            202 => "Accepted",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            413 => "Payload Too Large",
+            503 => "Service Unavailable",
+            _ => "Bad Gateway",
+        };
+        write!(writer, "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                        Connection: close\r\n\r\n{}", self.status, reason, self.body.len(), self.body)?;
+        writer.flush()
+    }
+}
+
+///
+/// Daemon side of gateway requests
+///
+pub trait GatewayHandler: Send{
+    ///
+    /// Gets state of daemon: uptime, connected peers and whether it is draining
+    ///
+    fn get_status(&mut self) -> DaemonStatus;
+
+    ///
+    /// Sends message into network
+    ///
+    /// returns: Result<(), String>: error if message can not be sent
+    ///
+    fn send_message(&mut self, message: Message) -> Result<(), String>;
+}
+
+///
+/// Finds message type external systems may send by its name. Types driving protocol
+/// itself(authorization, certificates, key exchange) are never accepted from gateway.
+///
+pub fn get_gateway_message_type(name: &str) -> Option<MessageType>{
+    match name {
+        "Ping" => Some(MessageType::Ping),
+        "Exec" => Some(MessageType::Exec),
+        "StateApply" => Some(MessageType::StateApply),
+        "StateRevert" => Some(MessageType::StateRevert),
+        "Report" => Some(MessageType::Report),
+        "LogMessage" => Some(MessageType::LogMessage),
+        _ => None,
+    }
+}
+
+fn certificate_json<PK: CryptoKey, SK: CryptoKey, C: Certificate<PK, SK>>(certificate: &C, kind: &str,
+                                                                         trusted: bool) -> String{
+    format!("{{\"serial\":\"{}\",\"parent\":{},\"type\":\"{}\",\"name\":{},\"flags\":{},\
+             \"fingerprint\":{},\"trusted\":{},\"metadata\":{}}}",
+            certificate.get_serial(),
+            certificate.get_parent_serial().map_or("null".to_string(), |serial| format!("\"{}\"", serial)),
+            kind, json_string(&certificate.get_name()), json_string(&format_flags(certificate.get_flags())),
+            json_string(&certificate.get_fingerprint()), trusted, certificate.get_metadata().to_json())
+}
+
+fn digest(token: &str) -> [u8; 32]{
+    Sha256::digest(token.as_bytes()).into()
+}
+
+///
+/// HTTP gateway letting external systems send messages into network and query peers
+/// and certificates. Every endpoint except health requires a bearer token from
+/// configuration. Routes:
+/// * `GET /v1/health`: uptime, 503 while daemon is draining
+/// * `GET /v1/peers`: connected peers with their names
+/// * `GET /v1/certificates` and `GET /v1/certificates/<serial>`: metadata of certificates,
+///   never their keys
+/// * `POST /v1/messages?destination=<peer>&module=<module>&type=<type>`: sends body as data
+///   of message, see get_gateway_message_type
+///
+/// 64-bit and larger IDs are written as JSON strings.
+///
+pub struct GatewayServer<S: CertificateService>{
+    certificates: S,
+    handler: Box<dyn GatewayHandler>,
+    /** Tokens by SHA-256 digest, so lookup does not leak tokens through timing **/
+    tokens: HashMap<[u8; 32], GatewayToken>,
+    names: Option<Box<dyn NameService>>,
+}
+
+impl<S: CertificateService> GatewayServer<S> {
+    ///
+    /// Creates gateway without tokens
+    ///
+    /// # Arguments
+    /// * certificates: S: certificates which are queried and operators are verified against
+    /// * handler: Box<dyn GatewayHandler>: daemon executing requests
+    ///
+    pub fn new(certificates: S, handler: Box<dyn GatewayHandler>) -> GatewayServer<S>{
+        GatewayServer{
+            certificates,
+            handler,
+            tokens: HashMap::new(),
+            names: None,
+        }
+    }
+
+    ///
+    /// Allows token to access gateway
+    ///
+    pub fn add_token(&mut self, token: GatewayToken) -> &mut Self{
+        self.tokens.insert(digest(&token.token), token);
+        self
+    }
+
+    ///
+    /// Sets name service peers are named with
+    ///
+    pub fn set_name_service(&mut self, names: Box<dyn NameService>) -> &mut Self{
+        self.names = Some(names);
+        self
+    }
+
+    ///
+    /// Checks token of request and rights of its certificate
+    ///
+    /// # Arguments
+    /// * request: &GatewayRequest: request with `Authorization` header
+    /// * write: bool: whether request changes state of network
+    ///
+    /// returns: Result<GatewayToken, GatewayError>: token request is made with
+    ///
+    pub fn authenticate(&mut self, request: &GatewayRequest, write: bool) -> Result<GatewayToken, GatewayError>{
+        let token = request.get_bearer_token()
+            .and_then(|token| self.tokens.get(&digest(token)))
+            .cloned()
+            .ok_or(GatewayError::Unauthorized)?;
+        if write && token.read_only{
+            return Err(GatewayError::Forbidden(format!("token {} is read-only", token.name)));
+        }
+        if let Some(serial) = token.certificate{
+            let certificate = self.certificates.get_signing_certificate(serial)
+                .filter(is_operator_certificate)
+                .filter(|certificate| self.certificates.verify_signing_certificate(certificate))
+                .ok_or_else(|| GatewayError::Forbidden(format!("certificate {} is not a trusted operator certificate",
+                                                               serial)))?;
+            let forbidden = if write { FLAG_NO_WRITE } else { FLAG_NO_READ };
+            if certificate.check_flag(forbidden){
+                return Err(GatewayError::Forbidden("flags of certificate forbid this request".to_string()));
+            }
+        }
+        Ok(token)
+    }
+
+    fn get_peers(&mut self) -> String{
+        let peers: Vec<String> = self.handler.get_status().peers.iter()
+            .map(|peer| {
+                let name = self.names.as_ref().map_or(peer.to_string(), |names| names.get_name_by_id(*peer));
+                format!("{{\"id\":\"{}\",\"name\":{}}}", peer, json_string(&name))
+            })
+            .collect();
+        format!("{{\"peers\":[{}]}}", peers.join(","))
+    }
+
+    fn get_certificates(&mut self) -> String{
+        let mut certificates = Vec::new();
+        for certificate in self.certificates.get_signing_certificates(){
+            let trusted = self.certificates.verify_signing_certificate(&certificate);
+            certificates.push(certificate_json(&certificate, "signing", trusted));
+        }
+        for certificate in self.certificates.get_encryption_certificates(){
+            let trusted = self.certificates.verify_encryption_certificate(&certificate);
+            certificates.push(certificate_json(&certificate, "encryption", trusted));
+        }
+        format!("{{\"certificates\":[{}]}}", certificates.join(","))
+    }
+
+    fn get_certificate(&mut self, serial: &str) -> Result<String, GatewayError>{
+        let serial = serial.parse::<u128>().map_err(|_| GatewayError::NotFound)?;
+        if let Some(certificate) = self.certificates.get_signing_certificate(serial){
+            let trusted = self.certificates.verify_signing_certificate(&certificate);
+            return Ok(certificate_json(&certificate, "signing", trusted));
+        }
+        let certificate = self.certificates.get_encryption_certificate(serial).ok_or(GatewayError::NotFound)?;
+        let trusted = self.certificates.verify_encryption_certificate(&certificate);
+        Ok(certificate_json(&certificate, "encryption", trusted))
+    }
+
+    fn send_message(&mut self, request: &GatewayRequest, token: &GatewayToken) -> Result<String, GatewayError>{
+        let parameter = |name: &str| request.query.get(name)
+            .ok_or_else(|| GatewayError::BadRequest(format!("parameter '{}' is required", name)));
+        let destination = parameter("destination")?.parse::<u128>()
+            .map_err(|_| GatewayError::BadRequest("destination must be a peer ID".to_string()))?;
+        let module_id = parameter("module")?.parse::<u64>()
+            .map_err(|_| GatewayError::BadRequest("module must be a module ID".to_string()))?;
+        let message_type = parameter("type")?;
+        let message_type = get_gateway_message_type(message_type)
+            .ok_or_else(|| GatewayError::BadRequest(format!("message type '{}' can not be sent", message_type)))?;
+        let mut message = Message::new();
+        message.set_type(message_type)
+            .set_destination(destination)
+            .set_module_id(module_id)
+            .set_data(if request.body.is_empty() { None } else { Some(request.body.clone()) })
+            .set_current_timestamp()
+            .ensure_id();
+        let id = message.id;
+        self.handler.send_message(message).map_err(GatewayError::Failed)?;
+        log::info!("Gateway token {} sent message id={} to {}", token.name, id, destination);
+        Ok(format!("{{\"id\":\"{}\"}}", id))
+    }
+
+    fn route(&mut self, request: &GatewayRequest) -> Result<GatewayResponse, GatewayError>{
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        let ok = |body: String| GatewayResponse{ status: 200, body };
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["v1", "health"]) => {
+                let status = self.handler.get_status();
+                Ok(GatewayResponse{
+                    status: if status.is_draining { 503 } else { 200 },
+                    body: format!("{{\"status\":\"{}\",\"uptime_seconds\":{}}}",
+                                  if status.is_draining { "draining" } else { "ok" }, status.uptime_seconds),
+                })
+            }
+            ("GET", ["v1", "peers"]) => {
+                self.authenticate(request, false)?;
+                Ok(ok(self.get_peers()))
+            }
+            ("GET", ["v1", "certificates"]) => {
+                self.authenticate(request, false)?;
+                Ok(ok(self.get_certificates()))
+            }
+            ("GET", ["v1", "certificates", serial]) => {
+                self.authenticate(request, false)?;
+                Ok(ok(self.get_certificate(serial)?))
+            }
+            ("POST", ["v1", "messages"]) => {
+                let token = self.authenticate(request, true)?;
+                Ok(GatewayResponse{ status: 202, body: self.send_message(request, &token)? })
+            }
+            _ => Err(GatewayError::NotFound),
+        }
+    }
+
+    ///
+    /// Authenticates and executes request
+    ///
+    pub fn handle(&mut self, request: &GatewayRequest) -> GatewayResponse{
+        self.route(request).unwrap_or_else(|error| {
+            if matches!(error, GatewayError::Unauthorized | GatewayError::Forbidden(_)){
+                log::warn!("Gateway request {} {} is refused: {}", request.method, request.path, error);
+            }
+            GatewayResponse::error(&error)
+        })
+    }
+
+    ///
+    /// Reads request of connection, answers it and closes connection
+    ///
+    pub fn serve_connection(&mut self, stream: &mut TcpStream) -> std::io::Result<()>{
+        serve_request(stream, |request| self.handle(request))
+    }
+}
+
+impl<S: CertificateService + Send + 'static> GatewayServer<S> {
+    ///
+    /// Listens on TCP address. Every connection is served on its own thread, up to
+    /// GATEWAY_MAX_CONNECTIONS at once, and requests are handled one by one.
+    /// Gateway speaks plain HTTP, so it should listen on loopback or a trusted network.
+    ///
+    /// # Arguments
+    /// * address: &str: address to listen on, e.g. DEFAULT_GATEWAY_ADDRESS
+    ///
+    pub fn listen(self, address: &str) -> std::io::Result<JoinHandle<()>>{
+        let listener = TcpListener::bind(address)?;
+        let server = Arc::new(Mutex::new(self));
+        let connections = Arc::new(AtomicUsize::new(0));
+        Ok(std::thread::spawn(move || {
+            for stream in listener.incoming(){
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(error) => {
+                        log::warn!("Gateway connection failed: {}", error);
+                        continue;
+                    }
+                };
+                if connections.fetch_add(1, Ordering::SeqCst) >= GATEWAY_MAX_CONNECTIONS{
+                    connections.fetch_sub(1, Ordering::SeqCst);
+                    let result = stream.set_write_timeout(Some(GATEWAY_IO_TIMEOUT))
+                        .and_then(|_| GatewayResponse::error(&GatewayError::Busy).write_to(&mut stream));
+                    if let Err(error) = result{
+                        log::warn!("Gateway connection failed: {}", error);
+                    }
+                    continue;
+                }
+                let server = server.clone();
+                let connections = connections.clone();
+                std::thread::spawn(move || {
+                    // Request is read without holding server, so slow clients do not hold others back
+                    let result = serve_request(&mut stream, |request| server.lock().unwrap().handle(request));
+                    connections.fetch_sub(1, Ordering::SeqCst);
+                    if let Err(error) = result{
+                        log::warn!("Gateway connection failed: {}", error);
+                    }
+                });
+            }
+        }))
+    }
+}
+
+// Reads request of connection and writes its answer
+fn serve_request<F: FnOnce(&GatewayRequest) -> GatewayResponse>(stream: &mut TcpStream, handle: F)
+                                                                -> std::io::Result<()>{
+    stream.set_read_timeout(Some(GATEWAY_IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(GATEWAY_IO_TIMEOUT))?;
+    let response = match read_gateway_request(&mut BufReader::new(&*stream)) {
+        Ok(request) => handle(&request),
+        Err(error) => GatewayResponse::error(&error),
+    };
+    response.write_to(stream)
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::{Arc, Mutex};
+    use crate::testing::certificate::{MockCertificateService, TEST_SIGNING_CERTIFICATE_SERIAL};
+    use crate::testing::module::StaticNameService;
+
+    struct TestDaemon{
+        sent: Arc<Mutex<Vec<Message>>>,
+    }
+
+    impl GatewayHandler for TestDaemon{
+        fn get_status(&mut self) -> DaemonStatus {
+            DaemonStatus{
+                uptime_seconds: 42,
+                peers: vec![10, 11],
+                ..Default::default()
+            }
+        }
+
+        fn send_message(&mut self, message: Message) -> Result<(), String> {
+            self.sent.lock().unwrap().push(message);
+            Ok(())
+        }
+    }
+
+    fn request(method: &str, target: &str, token: Option<&str>) -> GatewayRequest{
+        let mut request = GatewayRequest::new(method, target);
+        if let Some(token) = token{
+            request.set_header("Authorization", &format!("Bearer {}", token));
+        }
+        request
+    }
+
+    #[test]
+    fn test_gateway_requests() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let mut server = GatewayServer::new(MockCertificateService::with_test_certificates(),
+                                            Box::new(TestDaemon{ sent: sent.clone() }));
+        let mut names = StaticNameService::new("corp.example");
+        names.add_name(10, "gateway");
+        server.set_name_service(Box::new(names))
+            .add_token(GatewayToken{ name: "billing".to_string(), token: "secret".to_string(),
+                                     read_only: false, certificate: None })
+            .add_token(GatewayToken{ name: "dashboard".to_string(), token: "viewer".to_string(),
+                                     read_only: true, certificate: None })
+            // Signing certificate of node is not an operator one
+            .add_token(GatewayToken{ name: "legacy".to_string(), token: "node".to_string(),
+                                     read_only: false, certificate: Some(TEST_SIGNING_CERTIFICATE_SERIAL) });
+
+        let response = server.handle(&request("GET", "/v1/health", None));
+        assert_eq!(response, GatewayResponse{ status: 200, body: "{\"status\":\"ok\",\"uptime_seconds\":42}".to_string() });
+        assert_eq!(server.handle(&request("GET", "/v1/peers", None)).status, 401);
+        assert_eq!(server.handle(&request("GET", "/v1/peers", Some("guess"))).status, 401);
+        assert_eq!(server.handle(&request("GET", "/v1/peers", Some("viewer"))).body,
+                   "{\"peers\":[{\"id\":\"10\",\"name\":\"gateway\"},{\"id\":\"11\",\"name\":\"11\"}]}");
+        assert_eq!(server.handle(&request("GET", "/v1/peers", Some("node"))).status, 403);
+
+        let certificate = server.handle(&request("GET", &format!("/v1/certificates/{}", TEST_SIGNING_CERTIFICATE_SERIAL),
+                                                 Some("viewer")));
+        assert_eq!(certificate.status, 200);
+        assert!(certificate.body.contains("\"type\":\"signing\""));
+        assert!(certificate.body.contains("\"trusted\":true"));
+        assert_eq!(server.handle(&request("GET", "/v1/certificates/999", Some("viewer"))).status, 404);
+        assert!(server.handle(&request("GET", "/v1/certificates", Some("viewer"))).body.contains("\"type\":\"encryption\""));
+
+        let target = "/v1/messages?destination=10&module=3&type=Exec";
+        assert_eq!(server.handle(&request("POST", target, Some("viewer"))).status, 403);
+        let mut send = request("POST", target, Some("secret"));
+        send.body = b"uptime".to_vec();
+        let response = server.handle(&send);
+        assert_eq!(response.status, 202);
+        let message = sent.lock().unwrap().pop().unwrap();
+        assert_eq!((message.destination, message.module_id, message.message_type), (10, 3, MessageType::Exec));
+        assert_eq!(message.data, Some(b"uptime".to_vec()));
+        assert_eq!(response.body, format!("{{\"id\":\"{}\"}}", message.id));
+        let forged = request("POST", "/v1/messages?destination=10&module=3&type=KeyEx", Some("secret"));
+        assert_eq!(server.handle(&forged).status, 400);
+        assert_eq!(server.handle(&request("DELETE", "/v1/peers", Some("secret"))).status, 404);
+    }
+
+    #[test]
+    fn test_gateway_connections() {
+        let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        GatewayServer::new(MockCertificateService::with_test_certificates(),
+                           Box::new(TestDaemon{ sent: Arc::new(Mutex::new(Vec::new())) }))
+            .listen(&address.to_string()).unwrap();
+        let get_health = || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(b"GET /v1/health HTTP/1.1\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        // Silent connection does not hold others back
+        let mut idle = vec![TcpStream::connect(address).unwrap()];
+        assert!(get_health().starts_with("HTTP/1.1 200 OK"));
+        idle.extend((1..GATEWAY_MAX_CONNECTIONS).map(|_| TcpStream::connect(address).unwrap()));
+        // Busy gateway answers without reading request
+        let mut response = String::new();
+        TcpStream::connect(address).unwrap().read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable"));
+    }
+
+    #[test]
+    fn test_read_gateway_request() {
+        let data = b"POST /v1/messages?destination=10&module=3 HTTP/1.1\r\nHost: mway\r\n\
+                     authorization: Bearer secret\r\nContent-Length: 4\r\n\r\nping";
+        let request = read_gateway_request(&mut Cursor::new(data.to_vec())).unwrap();
+        assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/v1/messages"));
+        assert_eq!(request.query.get("module"), Some(&"3".to_string()));
+        assert_eq!(request.get_bearer_token(), Some("secret"));
+        assert_eq!(request.body, b"ping".to_vec());
+
+        let oversized = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", GATEWAY_MAX_BODY_SIZE + 1);
+        assert_eq!(read_gateway_request(&mut Cursor::new(oversized.into_bytes())), Err(GatewayError::TooLarge));
+        let long_header = format!("GET / HTTP/1.1\r\nX-Padding: {}\r\n\r\n", "a".repeat(GATEWAY_MAX_HEADER_SIZE));
+        assert_eq!(read_gateway_request(&mut Cursor::new(long_header.into_bytes())), Err(GatewayError::TooLarge));
+        assert!(read_gateway_request(&mut Cursor::new(b"GET /\r\n\r\n".to_vec())).is_err());
+
+        let mut written = Vec::new();
+        GatewayResponse::error(&GatewayError::NotFound).write_to(&mut written).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), "HTTP/1.1 404 Not Found\r\nContent-Type: application/json\r\n\
+                   Content-Length: 21\r\nConnection: close\r\n\r\n{\"error\":\"not found\"}");
+    }
+}
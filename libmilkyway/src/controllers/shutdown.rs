@@ -0,0 +1,238 @@
+use std::fmt::{Display, Formatter};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use crate::module::supervisor::panic_message;
+use crate::services::certificate::{CertificateService, CertificateServiceBinder};
+
+///
+/// Stage of graceful shutdown. Stages run in order of declaration, hooks of one stage run
+/// in order they were registered.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ShutdownStage{
+    /** Stop taking new work, e.g. unload modules and close listeners **/
+    Stop,
+    /** Write pending state to disk **/
+    Flush,
+    /** Release what is left, e.g. sockets and temporary files **/
+    Release,
+}
+
+///
+/// Step of graceful shutdown
+///
+pub trait ShutdownHook: Send{
+    ///
+    /// Gets name of step reported in logs and in ShutdownReport
+    ///
+    fn get_name(&self) -> String;
+
+    ///
+    /// Runs step, it should block until step is complete
+    ///
+    /// returns: Result<(), String>: error describing why step failed
+    ///
+    fn on_shutdown(&mut self) -> Result<(), String>;
+}
+
+///
+/// Step of shutdown which failed or panicked
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShutdownFailure{
+    pub hook: String,
+    pub error: String,
+}
+
+impl Display for ShutdownFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.hook, self.error)
+    }
+}
+
+///
+/// Result of shutdown
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ShutdownReport{
+    /** Names of steps which completed, in order they ran **/
+    pub completed: Vec<String>,
+    pub failures: Vec<ShutdownFailure>,
+}
+
+impl ShutdownReport {
+    ///
+    /// Checks whether every step completed
+    ///
+    #[inline]
+    pub fn is_clean(&self) -> bool{
+        self.failures.is_empty()
+    }
+}
+
+///
+/// Runs steps of graceful shutdown in a deterministic order. Every step runs even if
+/// previous ones failed, so one broken store does not leave others unflushed. Shutdown
+/// runs once, later calls report nothing.
+///
+#[derive(Default)]
+pub struct ShutdownController{
+    hooks: Vec<(ShutdownStage, Box<dyn ShutdownHook>)>,
+    done: bool,
+}
+
+impl ShutdownController {
+    pub fn new() -> ShutdownController{
+        ShutdownController::default()
+    }
+
+    ///
+    /// Registers step of shutdown
+    ///
+    /// # Arguments
+    /// * stage: ShutdownStage: stage step runs in
+    /// * hook: Box<dyn ShutdownHook>: step to run
+    ///
+    pub fn register(&mut self, stage: ShutdownStage, hook: Box<dyn ShutdownHook>) -> &mut Self{
+        let position = self.hooks.iter()
+            .position(|(registered, _)| *registered > stage)
+            .unwrap_or(self.hooks.len());
+        self.hooks.insert(position, (stage, hook));
+        self
+    }
+
+    #[inline]
+    pub fn is_done(&self) -> bool{
+        self.done
+    }
+
+    ///
+    /// Runs all registered steps, panics of steps are reported as failures
+    ///
+    pub fn shutdown(&mut self) -> ShutdownReport{
+        let mut report = ShutdownReport::default();
+        if self.done{
+            return report;
+        }
+        self.done = true;
+        for (stage, hook) in self.hooks.iter_mut(){
+            let name = hook.get_name();
+            log::info!("Shutdown: running {:?} step {}", stage, name);
+            match catch_unwind(AssertUnwindSafe(|| hook.on_shutdown())) {
+                Ok(Ok(())) => report.completed.push(name),
+                Ok(Err(error)) => report.failures.push(ShutdownFailure{ hook: name, error }),
+                Err(payload) => report.failures.push(ShutdownFailure{
+                    hook: name,
+                    error: format!("panicked: {}", panic_message(payload.as_ref())),
+                }),
+            }
+        }
+        for failure in report.failures.iter(){
+            log::error!("Shutdown step failed: {}", failure);
+        }
+        report
+    }
+}
+
+///
+/// Flushes commit queue of certificate service: the flush request is queued after every
+/// change requested before it, so once it is answered all of them are durable
+///
+pub struct CertificateFlushHook{
+    certificates: Box<CertificateServiceBinder>,
+}
+
+impl CertificateFlushHook {
+    pub fn new(certificates: Box<CertificateServiceBinder>) -> CertificateFlushHook{
+        CertificateFlushHook{
+            certificates,
+        }
+    }
+}
+
+impl ShutdownHook for CertificateFlushHook{
+    fn get_name(&self) -> String {
+        "certificates".to_string()
+    }
+
+    fn on_shutdown(&mut self) -> Result<(), String> {
+        self.certificates.flush().map_err(|error| error.to_string())
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::actor::binder::BinderChannelProvider;
+    use crate::actor::binder::coroutine::BinderAsyncService;
+    use crate::services::impls::certificate::AsyncCertificateServiceImpl;
+    use crate::testing::certificate::test_certificates;
+    use crate::tokio::init_tokio;
+
+    struct RecordingHook{
+        name: &'static str,
+        result: Result<(), String>,
+        log: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl ShutdownHook for RecordingHook{
+        fn get_name(&self) -> String {
+            self.name.to_string()
+        }
+
+        fn on_shutdown(&mut self) -> Result<(), String> {
+            self.log.lock().unwrap().push(self.name);
+            if self.name == "panicking"{
+                panic!("broken hook");
+            }
+            self.result.clone()
+        }
+    }
+
+    #[test]
+    fn test_shutdown_order() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let hook = |name: &'static str, result: Result<(), String>| Box::new(RecordingHook{ name, result, log: log.clone() });
+        let mut controller = ShutdownController::new();
+        controller.register(ShutdownStage::Release, hook("sockets", Ok(())))
+            .register(ShutdownStage::Flush, hook("failing", Err("disk full".to_string())))
+            .register(ShutdownStage::Stop, hook("modules", Ok(())))
+            .register(ShutdownStage::Flush, hook("panicking", Ok(())))
+            .register(ShutdownStage::Flush, hook("outbox", Ok(())));
+        let report = controller.shutdown();
+        assert_eq!(*log.lock().unwrap(), vec!["modules", "failing", "panicking", "outbox", "sockets"]);
+        assert_eq!(report.completed, vec!["modules", "outbox", "sockets"]);
+        assert_eq!(report.failures[0], ShutdownFailure{ hook: "failing".to_string(), error: "disk full".to_string() });
+        assert_eq!(report.failures[1].error, "panicked: broken hook");
+        assert!(!report.is_clean());
+        assert!(controller.is_done());
+        assert_eq!(controller.shutdown(), ShutdownReport::default());
+    }
+
+    #[test]
+    fn test_certificate_flush_hook() {
+        init_tokio();
+        let file = std::env::temp_dir().join(format!("milkyway-flush-{}.dat", rand::random::<u64>()));
+        let mut service = BinderAsyncService::run(Box::new(AsyncCertificateServiceImpl::new(file.to_str().unwrap())));
+        let mut binder = service.bind();
+        assert!(!binder.is_dirty());
+        binder.set_root_certificate(test_certificates().root);
+        assert!(binder.add_signing_certificate(test_certificates().signing));
+        assert!(binder.is_dirty());
+
+        let mut controller = ShutdownController::new();
+        controller.register(ShutdownStage::Flush, Box::new(CertificateFlushHook::new(service.bind())));
+        assert!(controller.shutdown().is_clean());
+        assert!(!binder.is_dirty());
+        let mut stored = AsyncCertificateServiceImpl::load_from_file(file.to_str().unwrap());
+        assert!(stored.get_signing_certificate(test_certificates().signing.serial_number).is_some());
+
+        // Store which can not be written is reported and changes are kept pending
+        let mut broken = AsyncCertificateServiceImpl::new("/nonexistent/milkyway/certs.dat");
+        broken.set_usage_thresholds(Default::default());
+        assert_eq!(broken.flush(), Err(crate::services::certificate::CertificateServiceError::CommitFailed));
+        assert!(broken.is_dirty());
+        std::fs::remove_file(file).unwrap();
+    }
+}
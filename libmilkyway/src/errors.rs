@@ -0,0 +1,245 @@
+///
+/// Typed error with a stable code, codes look like `MW-CERT-0042`: prefix, area and number.
+/// Codes never change their meaning and are never reused, so scripts and support may rely
+/// on them while messages are reworded.
+///
+pub trait ErrorCode{
+    ///
+    /// Gets code of error, it must be described in CATALOG
+    ///
+    fn get_error_code(&self) -> &'static str;
+}
+
+///
+/// Description of error code shown by `mway explain <code>`
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ErrorExplanation{
+    pub code: &'static str,
+    /** One line describing what went wrong **/
+    pub summary: &'static str,
+    /** Why error happens and how to fix it **/
+    pub explanation: &'static str,
+}
+
+macro_rules! explanation {
+    ($code:literal, $summary:literal, $explanation:literal) => {
+        ErrorExplanation{ code: $code, summary: $summary, explanation: $explanation }
+    };
+}
+
+///
+/// Every known error code in order of codes
+///
+pub static CATALOG: &[ErrorExplanation] = &[
+    // Certificate service
+    explanation!("MW-CERT-0001", "Certificate store is read-only",
+        "Certificate service was opened with `read_only: true`, so certificates, policy and usage thresholds \
+         can not be added, changed or removed. Remove the option from configuration once maintenance is over."),
+    explanation!("MW-CERT-0002", "Certificate service can not reload its store",
+        "Service was created without a file to keep certificates in, e.g. an in-memory service of tests, so \
+         there is nothing to reload."),
+    explanation!("MW-CERT-0003", "Certificate store can not be read",
+        "Store file is missing, damaged or of unknown version, the service keeps certificates it had. Check it \
+         with `mway certman store inspect` and repair it with `mway certman store repair`."),
+    explanation!("MW-CERT-0004", "Changes can not be written to certificate store",
+        "Store or key store file can not be written or synced to disk, e.g. the disk is full or permissions are \
+         wrong. Changes are kept pending, fix the cause and run `mway certman commit wait` again."),
+    // Verification of certificate chains
+    explanation!("MW-CERT-0101", "Certificate has no parent",
+        "Only root certificate may have no parent, other certificates must be issued by a signing certificate."),
+    explanation!("MW-CERT-0102", "Certificate is not signed",
+        "Certificate was never signed by its parent, so it can not be trusted. Issue it again."),
+    explanation!("MW-CERT-0103", "Parent of certificate is not found",
+        "Neither the chain nor the store contains parent of certificate. Import the parent first, e.g. with \
+         `certman signing import`."),
+    explanation!("MW-CERT-0104", "No root certificate is known",
+        "Certificate is issued by root, but store has no root certificate. Import root with `certman root import`."),
+    explanation!("MW-CERT-0105", "Parent can not sign certificates",
+        "Parent of certificate has no `sign-certs` flag, so certificates it issued are not trusted."),
+    explanation!("MW-CERT-0106", "Certificate has unknown flags",
+        "Certificate has flag bits which are neither known nor registered as user-defined. It was issued by a \
+         newer version or flags are not registered on this host."),
+    explanation!("MW-CERT-0107", "Signature of certificate is not made by its parent",
+        "Certificate was changed after it was signed or it claims a wrong parent. Do not trust it."),
+    explanation!("MW-CERT-0108", "Certificate is its own ancestor",
+        "Parents of certificate form a loop, so chain never reaches root. Such chain is never valid."),
+    // Signed exports
+    explanation!("MW-CERT-0201", "Export is signed by another certificate",
+        "Exported file is signed by a different certificate than the one expected by `signer=`. Check who \
+         exported the file."),
+    explanation!("MW-CERT-0202", "Content of export does not match its hash",
+        "File was changed or truncated after export. Export it again."),
+    explanation!("MW-CERT-0203", "Signature of export is invalid",
+        "Envelope of export is not signed by its signer, file may be forged. Do not import it."),
+    // Flags
+    explanation!("MW-CERT-0301", "Unknown flag",
+        "Name is neither a known flag nor a user-defined one, names of user-defined flags start with `user-`."),
+    explanation!("MW-CERT-0302", "Unknown flag bits",
+        "Numeric flags contain bits which are not assigned to any flag."),
+    explanation!("MW-CERT-0303", "User-defined flags are not allowed",
+        "User-defined flags may only be given where they are explicitly allowed, e.g. in policy rules."),
+    // Profiles
+    explanation!("MW-CERT-0401", "Unknown profile",
+        "No built-in or configured profile has such name. `certman signing profiles` lists profiles."),
+    explanation!("MW-CERT-0402", "Profiles can not create root certificates",
+        "Root certificates are only created with `certman root generate`, remove `root` from flags of profile."),
+    explanation!("MW-CERT-0403", "Name template of profile is invalid",
+        "Name template in `certificate_profiles` must contain `{name}`, which is replaced by given name."),
+    explanation!("MW-CERT-0404", "Name does not follow naming convention",
+        "Names given to profiles may consist only of lowercase letters, digits, `-` and `.`."),
+    // Certificate policy
+    explanation!("MW-POL-0001", "Certificate policy is not signed by root certificate",
+        "Policy in store has a broken signature, so none of its rules is trusted and everything is denied. Set \
+         policy again on a host with root secret key."),
+    explanation!("MW-POL-0002", "Root certificate with secret key is required to sign policy",
+        "Policy is signed by root certificate, so it can only be changed where root secret key is available."),
+    explanation!("MW-POL-0003", "Policy is outdated",
+        "Version of given policy is not newer than the one in store, older policies can not be replayed."),
+    explanation!("MW-POL-0004", "Policy denies operation",
+        "Issuer has policy rules and none of them allows the name or flags of certificate. Show rules with \
+         `certman policy show` and check an operation with `certman policy test`."),
+    explanation!("MW-POL-0005", "Policy can not be changed in read-only store",
+        "Certificate service was opened with `read_only: true`."),
+    explanation!("MW-POL-0006", "Certificate service does not keep policy",
+        "Service does not support certificate policy, e.g. it is a remote service of an older daemon."),
+    // Exported secret keys
+    explanation!("MW-KEY-0001", "Certificate has no secret key",
+        "Only certificates whose secret key is in key store may be exported or used for signing."),
+    explanation!("MW-KEY-0002", "File is not an exported secret key",
+        "File is truncated or was written by something else than `export-key`."),
+    explanation!("MW-KEY-0003", "Version of exported key is not supported",
+        "File was written by a newer version, update this host to import it."),
+    explanation!("MW-KEY-0004", "Wrong passphrase or damaged file",
+        "Key can not be decrypted. Check `passphrase=` or `MWAY_KEY_PASSPHRASE` and that file was copied intact."),
+    explanation!("MW-KEY-0005", "Key is exported for another certificate",
+        "Serial written in file differs from serial of certificate key is imported to."),
+    explanation!("MW-KEY-0006", "Key does not match certificate",
+        "Secret key does not belong to public key of certificate, it was never issued together with it."),
+    explanation!("MW-KEY-0007", "File of exported key can not be read or written",
+        "Check path and permissions of file."),
+    // IDs of peers
+    explanation!("MW-PEER-0001", "Value is not an ID of peer",
+        "IDs of peers are positive integers. Values which are not numbers are looked up as names of peers."),
+    explanation!("MW-PEER-0002", "ID of peer is negative",
+        "IDs of peers are positive integers."),
+    explanation!("MW-PEER-0003", "ID of peer is too large",
+        "IDs of peers must fit into 128 bits."),
+    explanation!("MW-PEER-0004", "ID is reserved",
+        "ID 0 marks unassigned peers and IDs with the highest bit set are addresses of groups, neither can be \
+         given to a peer."),
+    explanation!("MW-PEER-0005", "Range of IDs is empty",
+        "First ID of range is above its last one."),
+    explanation!("MW-PEER-0006", "All IDs of range are given out",
+        "Allocator has no IDs left, configure a larger range."),
+    explanation!("MW-PEER-0007", "Unknown name of peer",
+        "Name service does not know peer with such name. Check `names` section of configuration or use ID of peer."),
+    // Transport
+    explanation!("MW-TRAN-0001", "Tapping raw frames is not enabled",
+        "Set `raw_frame_tap: true` in daemon configuration to allow modules to tap raw frames."),
+    explanation!("MW-TRAN-0002", "Certificate can not tap raw frames",
+        "Operator certificate must have `transport-tap` flag to tap raw frames."),
+    explanation!("MW-TRAN-0003", "Certificate is not trusted",
+        "Chain of operator certificate can not be verified by this host."),
+    explanation!("MW-TRAN-0004", "Filter matches no direction of frames",
+        "Filter of raw frames must include sent frames, received frames or both."),
+];
+
+///
+/// Finds explanation of error code, letter case of code is ignored
+///
+/// # Arguments
+/// * code: &str: code of error, e.g. MW-CERT-0042
+///
+/// returns: Option<&'static ErrorExplanation>: explanation or None if code is unknown
+///
+pub fn explain(code: &str) -> Option<&'static ErrorExplanation>{
+    CATALOG.iter().find(|explanation| explanation.code.eq_ignore_ascii_case(code.trim()))
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use crate::peer::PeerIdError;
+    use crate::pki::certificate::flags::FlagError;
+    use crate::pki::certificate::profile::ProfileError;
+    use crate::pki::export::ExportError;
+    use crate::pki::keyexport::KeyExportError;
+    use crate::services::certificate::CertificateServiceError;
+    use crate::services::certificate::chain::ChainVerificationError;
+    use crate::services::certificate::policy::{PolicyError, PolicyOperation};
+    use crate::transport::rawtap::RawTapError;
+
+    #[test]
+    fn test_catalog_codes() {
+        let mut codes = HashSet::new();
+        for explanation in CATALOG{
+            let parts: Vec<&str> = explanation.code.split('-').collect();
+            assert_eq!(parts.len(), 3, "{}", explanation.code);
+            assert_eq!(parts[0], "MW");
+            assert!(parts[1].bytes().all(|byte| byte.is_ascii_uppercase()), "{}", explanation.code);
+            assert!(parts[2].len() == 4 && parts[2].bytes().all(|byte| byte.is_ascii_digit()), "{}", explanation.code);
+            assert!(codes.insert(explanation.code), "{} is listed twice", explanation.code);
+        }
+        assert_eq!(explain(" mw-cert-0004 ").map(|explanation| explanation.summary),
+                   Some("Changes can not be written to certificate store"));
+        assert!(explain("MW-CERT-9999").is_none());
+    }
+
+    #[test]
+    fn test_errors_are_explained() {
+        let errors: Vec<Box<dyn ErrorCode>> = vec![
+            Box::new(CertificateServiceError::ReadOnly),
+            Box::new(CertificateServiceError::ReloadUnsupported),
+            Box::new(CertificateServiceError::ReloadFailed),
+            Box::new(CertificateServiceError::CommitFailed),
+            Box::new(ChainVerificationError::NoParent{ serial: 1 }),
+            Box::new(ChainVerificationError::Unsigned{ serial: 1 }),
+            Box::new(ChainVerificationError::MissingParent{ serial: 1, parent: 2 }),
+            Box::new(ChainVerificationError::MissingRoot{ serial: 1 }),
+            Box::new(ChainVerificationError::ParentCanNotSign{ serial: 1, parent: 2 }),
+            Box::new(ChainVerificationError::UnknownFlags{ serial: 1, flags: 2 }),
+            Box::new(ChainVerificationError::BadSignature{ serial: 1, parent: 2 }),
+            Box::new(ChainVerificationError::Loop{ serial: 1 }),
+            Box::new(ExportError::SignerMismatch{ expected: 1, actual: 2 }),
+            Box::new(ExportError::HashMismatch),
+            Box::new(ExportError::InvalidSignature),
+            Box::new(FlagError::UnknownFlag("x".to_string())),
+            Box::new(FlagError::UnknownBits(1)),
+            Box::new(FlagError::UserDefinedNotAllowed(1)),
+            Box::new(ProfileError::UnknownProfile("x".to_string())),
+            Box::new(ProfileError::RootFlag),
+            Box::new(ProfileError::InvalidTemplate("x".to_string())),
+            Box::new(ProfileError::InvalidName("x".to_string())),
+            Box::new(PolicyError::InvalidSignature),
+            Box::new(PolicyError::NoSigningKey),
+            Box::new(PolicyError::Outdated{ current: 2, given: 1 }),
+            Box::new(PolicyError::Denied{ operation: PolicyOperation::Sign, issuer: 1, name: "x".to_string() }),
+            Box::new(PolicyError::ReadOnly),
+            Box::new(PolicyError::Unsupported),
+            Box::new(KeyExportError::NoSecretKey(1)),
+            Box::new(KeyExportError::Malformed),
+            Box::new(KeyExportError::UnsupportedVersion(9)),
+            Box::new(KeyExportError::DecryptionFailed),
+            Box::new(KeyExportError::SerialMismatch{ expected: 1, actual: 2 }),
+            Box::new(KeyExportError::KeyMismatch(1)),
+            Box::new(KeyExportError::Io("x".to_string())),
+            Box::new(PeerIdError::Invalid("x".to_string())),
+            Box::new(PeerIdError::Negative("-1".to_string())),
+            Box::new(PeerIdError::Overflow("x".to_string())),
+            Box::new(PeerIdError::Reserved(0)),
+            Box::new(PeerIdError::EmptyRange{ first: 2, last: 1 }),
+            Box::new(PeerIdError::Exhausted(1)),
+            Box::new(PeerIdError::UnknownName("x".to_string())),
+            Box::new(RawTapError::Disabled),
+            Box::new(RawTapError::NotPermitted(1)),
+            Box::new(RawTapError::Untrusted(1)),
+            Box::new(RawTapError::EmptyFilter),
+        ];
+        let codes: Vec<&str> = errors.iter().map(|error| error.get_error_code()).collect();
+        assert_eq!(codes, CATALOG.iter().map(|explanation| explanation.code).collect::<Vec<&str>>());
+        assert_eq!(ProfileError::InvalidFlags(FlagError::UnknownBits(1)).get_error_code(), "MW-CERT-0302");
+    }
+}
@@ -38,6 +38,11 @@ pub mod module;
 /// 
 pub mod services;
 
+///
+/// IDs of peers and their allocation
+///
+pub mod peer;
+
 /// 
 /// CLI utilites
 /// 
@@ -53,6 +58,32 @@ pub mod actor;
 /// Common controllers
 /// 
 pub mod controllers;
+
+///
+/// Resolution of configuration, storage and modules paths
+///
+pub mod paths;
+
+///
+/// Spans correlating logs of connection, handshake and messages, with exporters
+///
+pub mod trace;
+
+///
+/// Encrypted values of configuration files
+///
+pub mod secrets;
+
+///
+/// Stable codes of typed errors and catalog explaining them
+///
+pub mod errors;
+
+///
+/// Test doubles for writing module tests without a running daemon
+///
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 mod utils;
 
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -2,4 +2,12 @@ pub mod types;
 pub mod state;
 pub mod common;
 pub mod exec;
-pub mod ping;
\ No newline at end of file
+pub mod ping;
+pub mod certsync;
+pub mod certpush;
+pub mod group;
+pub mod inventory;pub mod builder;
+pub mod protocol;
+pub mod stream;
+pub mod id;
+pub mod header;
@@ -0,0 +1,197 @@
+use std::fmt::{Display, Formatter};
+use crate::get_timestamp_with_milliseconds;
+use crate::message::common::{AsMessage, Message};
+use crate::message::id::generate_message_id;
+use crate::message::types::MessageType;
+use crate::pki::hash::HashType;
+use crate::pki::impls::CryptoError;
+use crate::pki::key::CryptoKey;
+use crate::serialization::serializable::Serialized;
+
+///
+/// Errors of building a message
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum MessageBuildError{
+    MissingType,
+    MissingDestination,
+    MissingModule,
+    SigningFailed(CryptoError),
+}
+
+impl Display for MessageBuildError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MessageBuildError::MissingType => write!(f, "Type of message is not set"),
+            MessageBuildError::MissingDestination => write!(f, "Destination of message is not set"),
+            MessageBuildError::MissingModule => write!(f, "Module of message is not set"),
+            MessageBuildError::SigningFailed(error) => write!(f, "Can not sign message: {:?}", error),
+        }
+    }
+}
+
+///
+/// Builds messages ready to be sent. Type, destination and module must be set explicitly,
+/// ID(see generate_message_id) and timestamp are filled automatically unless set.
+///
+#[derive(Clone, Default)]
+pub struct MessageBuilder{
+    id: Option<u128>,
+    timestamp: Option<u128>,
+    message_type: Option<MessageType>,
+    certificate_id: u128,
+    data: Option<Serialized>,
+    source: u128,
+    destination: Option<u128>,
+    module_id: Option<u64>,
+}
+
+impl MessageBuilder {
+    pub fn new() -> MessageBuilder{
+        MessageBuilder::default()
+    }
+
+    ///
+    /// Creates a builder with type and data of payload
+    ///
+    /// # Arguments
+    /// * payload: &T: structure convertible to message, e.g. GroupRecord
+    ///
+    pub fn from_payload<T: AsMessage + ?Sized>(payload: &T) -> MessageBuilder{
+        let message = payload.as_message();
+        let mut builder = MessageBuilder::new();
+        builder.set_type(message.message_type).set_data(message.data);
+        builder
+    }
+
+    ///
+    /// Sets ID of message, generated ID is used if not set
+    ///
+    #[inline]
+    pub fn set_id(&mut self, id: u128) -> &mut Self{
+        self.id = Some(id);
+        self
+    }
+
+    ///
+    /// Sets timestamp of message, current time is used if not set
+    ///
+    #[inline]
+    pub fn set_timestamp(&mut self, timestamp: u128) -> &mut Self{
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    #[inline]
+    pub fn set_type(&mut self, message_type: MessageType) -> &mut Self{
+        self.message_type = Some(message_type);
+        self
+    }
+
+    #[inline]
+    pub fn set_data(&mut self, data: Option<Serialized>) -> &mut Self{
+        self.data = data;
+        self
+    }
+
+    #[inline]
+    pub fn set_source(&mut self, source: u128) -> &mut Self{
+        self.source = source;
+        self
+    }
+
+    #[inline]
+    pub fn set_destination(&mut self, destination: u128) -> &mut Self{
+        self.destination = Some(destination);
+        self
+    }
+
+    #[inline]
+    pub fn set_module_id(&mut self, module_id: u64) -> &mut Self{
+        self.module_id = Some(module_id);
+        self
+    }
+
+    ///
+    /// Sets serial of certificate which signs message
+    ///
+    #[inline]
+    pub fn set_certificate_id(&mut self, certificate_id: u128) -> &mut Self{
+        self.certificate_id = certificate_id;
+        self
+    }
+
+    ///
+    /// Builds unsigned message
+    ///
+    /// returns: Result<Message, MessageBuildError>: message or error naming a missing field
+    ///
+    pub fn build(&self) -> Result<Message, MessageBuildError>{
+        Ok(Message{
+            id: self.id.unwrap_or_else(generate_message_id),
+            timestamp: self.timestamp.unwrap_or_else(get_timestamp_with_milliseconds),
+            message_type: self.message_type.clone().ok_or(MessageBuildError::MissingType)?,
+            certificate_id: self.certificate_id,
+            data: self.data.clone(),
+            signature: None,
+            source: self.source,
+            destination: self.destination.ok_or(MessageBuildError::MissingDestination)?,
+            module_id: self.module_id.ok_or(MessageBuildError::MissingModule)?,
+        })
+    }
+
+    ///
+    /// Builds message signed with key
+    ///
+    /// # Arguments
+    /// * key: &K: key capable of signing data
+    /// * hash_type: HashType: type of hash to use for signature
+    ///
+    pub fn build_signed<K: CryptoKey>(&self, key: &K, hash_type: HashType) -> Result<Message, MessageBuildError>{
+        let mut message = self.build()?;
+        let signature = key.sign(&message.as_signable(), hash_type)
+            .map_err(MessageBuildError::SigningFailed)?;
+        message.signature = Some(signature);
+        Ok(message)
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::group::{GroupOperation, GroupRecord};
+    use crate::pki::certificate::Certificate;
+    use crate::testing::certificate::test_certificates;
+
+    #[test]
+    fn test_required_fields() {
+        let mut builder = MessageBuilder::new();
+        assert_eq!(builder.build().err(), Some(MessageBuildError::MissingType));
+        builder.set_type(MessageType::Ping);
+        assert_eq!(builder.build().err(), Some(MessageBuildError::MissingDestination));
+        builder.set_destination(2);
+        assert_eq!(builder.build().err(), Some(MessageBuildError::MissingModule));
+        builder.set_module_id(0).set_source(1);
+        let first = builder.build().unwrap();
+        let second = builder.build().unwrap();
+        assert_eq!((first.source, first.destination), (1, 2));
+        assert!(first.timestamp > 0);
+        assert_ne!(first.id, second.id);
+    }
+
+    #[test]
+    fn test_build_signed() {
+        let certificates = test_certificates();
+        let record = GroupRecord::new(1, "group", GroupOperation::Create, &certificates.signing).unwrap();
+        let mut message = MessageBuilder::from_payload(&record)
+            .set_destination(2)
+            .set_module_id(3)
+            .set_certificate_id(certificates.signing.get_serial())
+            .build_signed(certificates.signing.secret_key.as_ref().unwrap(), HashType::None)
+            .unwrap();
+        assert_eq!(message.message_type, MessageType::GroupRecord);
+        assert_eq!(message.data, record.as_message().data);
+        assert!(message.verify_signature(&certificates.signing.public_key));
+    }
+}
@@ -0,0 +1,139 @@
+use crate::serialization::error::SerializationError;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::serializable::Serializable;
+use libmilkyway_derive::{Describe, Deserializable, Serializable};
+use crate::message::certsync::{CertificateSyncEntry, CertificateSyncMessage};
+use crate::message::common::{AsMessage, Message};
+use crate::message::types::MessageType;
+use crate::pki::certificate::Certificate;
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use crate::serialization::serializable::Serialized;
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+use crate::services::certificate::{CertificateService, ROOT_CERTIFICATE_SERIAL};
+
+///
+/// Certificate sent to a peer for installation, e.g. client certificate issued on CA node.
+/// Receiver installs it only after verification against its root certificate.
+///
+#[derive(Serializable, Deserializable, Clone, Describe)]
+pub struct CertificatePushMessage{
+    /** Pushed certificate, either signing or encryption one **/
+    pub entry: CertificateSyncEntry,
+    /** Signing certificates between root and pushed certificate, closest to root first **/
+    pub chain: Vec<Falcon1024Certificate>,
+}
+
+impl CertificatePushMessage {
+    ///
+    /// Creates a push of signing certificate without chain, secret key is never sent
+    ///
+    pub fn new_signing(certificate: &Falcon1024Certificate) -> CertificatePushMessage{
+        CertificatePushMessage{
+            entry: CertificateSyncEntry::SigningCertificate(certificate.clone_without_sk()),
+            chain: Vec::new(),
+        }
+    }
+
+    ///
+    /// Creates a push of encryption certificate without chain, secret key is never sent
+    ///
+    pub fn new_encryption(certificate: &Kyber1024Certificate) -> CertificatePushMessage{
+        CertificatePushMessage{
+            entry: CertificateSyncEntry::EncryptionCertificate(certificate.clone_without_sk()),
+            chain: Vec::new(),
+        }
+    }
+
+    ///
+    /// Gets serial of pushed certificate
+    ///
+    pub fn get_serial(&self) -> u128{
+        match &self.entry {
+            CertificateSyncEntry::SigningCertificate(certificate) => certificate.get_serial(),
+            CertificateSyncEntry::EncryptionCertificate(certificate) => certificate.get_serial(),
+            CertificateSyncEntry::Revocation(revocation) => revocation.serial,
+        }
+    }
+
+    ///
+    /// Gets name of pushed certificate
+    ///
+    pub fn get_name(&self) -> String{
+        match &self.entry {
+            CertificateSyncEntry::SigningCertificate(certificate) => certificate.get_name(),
+            CertificateSyncEntry::EncryptionCertificate(certificate) => certificate.get_name(),
+            CertificateSyncEntry::Revocation(_) => String::new(),
+        }
+    }
+
+    ///
+    /// Gets serial of certificate which signed pushed one
+    ///
+    pub fn get_parent_serial(&self) -> Option<u128>{
+        match &self.entry {
+            CertificateSyncEntry::SigningCertificate(certificate) => certificate.get_parent_serial(),
+            CertificateSyncEntry::EncryptionCertificate(certificate) => certificate.get_parent_serial(),
+            CertificateSyncEntry::Revocation(revocation) => Some(revocation.issuer_serial),
+        }
+    }
+
+    ///
+    /// Attaches signing certificates from pushed certificate up to root
+    ///
+    /// # Arguments
+    /// * service: &mut S: service to take chain from
+    ///
+    /// returns: bool: true if whole chain was found, false otherwise
+    ///
+    pub fn add_chain<S: CertificateService + ?Sized>(&mut self, service: &mut S) -> bool{
+        let mut chain = Vec::new();
+        let mut parent = self.get_parent_serial();
+        while let Some(serial) = parent {
+            if serial == ROOT_CERTIFICATE_SERIAL{
+                break;
+            }
+            let certificate = match service.get_signing_certificate(serial) {
+                Some(certificate) => certificate,
+                None => return false,
+            };
+            if chain.iter().any(|known: &Falcon1024Certificate| known.get_serial() == serial){
+                // Loop in chain
+                return false;
+            }
+            parent = certificate.get_parent_serial();
+            chain.push(certificate.clone_without_sk());
+        }
+        chain.reverse();
+        self.chain = chain;
+        true
+    }
+
+    ///
+    /// Converts push to sync message installing chain first and pushed certificate last
+    ///
+    pub fn to_sync_message(&self) -> CertificateSyncMessage{
+        let mut message = CertificateSyncMessage::new();
+        for certificate in self.chain.iter(){
+            message.add_signing_certificate(certificate);
+        }
+        message.entries.push(self.entry.clone());
+        message
+    }
+}
+
+impl AsMessage for CertificatePushMessage{
+    fn as_message(&self) -> Message {
+        Message{
+            id: 0,
+            timestamp: 0,
+            message_type: MessageType::CertificatePush,
+            data: Some(self.serialize()),
+            signature: None,
+            source: 0,
+            destination: 0,
+            module_id: 0,
+            certificate_id: 0,
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use crate::serialization::error::SerializationError;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::serializable::Serializable;
+use libmilkyway_derive::{Describe, Deserializable, Serializable};
+use crate::get_timestamp_with_milliseconds;
+use crate::message::common::{AsMessage, Message};
+use crate::message::types::MessageType;
+use crate::pki::certificate::Certificate;
+use crate::pki::hash::HashType;
+use crate::pki::impls::CryptoError;
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use crate::pki::key::CryptoKey;
+use crate::pki::signature::Signature;
+use crate::serialization::serializable::Serialized;
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+use crate::services::certificate::CertificateService;
+
+///
+/// Revocation of a certificate. Must be signed by issuer of revoked certificate.
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq, Describe)]
+pub struct CertificateRevocation{
+    /** Serial of revoked certificate **/
+    pub serial: u128,
+    /** Serial of certificate which signed the revoked one, 0 for root **/
+    pub issuer_serial: u128,
+    pub timestamp: u128,
+    pub signature: Option<Signature>,
+}
+
+impl CertificateRevocation {
+    ///
+    /// Creates a revocation signed by issuer
+    ///
+    /// # Arguments
+    /// * serial: u128: serial of certificate to revoke
+    /// * issuer: &C: issuer of revoked certificate, must have secret key
+    ///
+    /// returns: Result<CertificateRevocation, CryptoError>: signed revocation or error if
+    /// issuer can not sign
+    ///
+    pub fn new<PK: CryptoKey, SK: CryptoKey, C: Certificate<PK, SK>>(serial: u128, issuer: &C)
+        -> Result<CertificateRevocation, CryptoError>{
+        let mut revocation = CertificateRevocation{
+            serial,
+            issuer_serial: issuer.get_serial(),
+            timestamp: get_timestamp_with_milliseconds(),
+            signature: None,
+        };
+        revocation.signature = Some(issuer.sign_data(&revocation.as_signable(), HashType::None)?);
+        Ok(revocation)
+    }
+
+    ///
+    /// Clones and strips signature, allowing to sign/verify revocation
+    ///
+    pub fn as_signable(&self) -> CertificateRevocation{
+        let mut copy = self.clone();
+        copy.signature = None;
+        copy
+    }
+}
+
+///
+/// A single change of PKI state propagated to peers
+///
+#[derive(Clone, PartialEq, Describe)]
+#[allow(clippy::large_enum_variant)]
+pub enum CertificateSyncEntry{
+    ///
+    /// New or rotated signing certificate, replaces certificate with same serial
+    ///
+    SigningCertificate(Falcon1024Certificate),
+    ///
+    /// New or rotated encryption certificate, replaces certificate with same serial
+    ///
+    EncryptionCertificate(Kyber1024Certificate),
+    ///
+    /// Revoked certificate of any kind
+    ///
+    Revocation(CertificateRevocation),
+}
+
+impl Serializable for CertificateSyncEntry {
+    fn serialize(&self) -> Serialized {
+        let mut result = Serialized::new();
+        match self {
+            CertificateSyncEntry::SigningCertificate(certificate) => {
+                result.extend(0u8.serialize());
+                result.extend(certificate.serialize());
+            }
+            CertificateSyncEntry::EncryptionCertificate(certificate) => {
+                result.extend(1u8.serialize());
+                result.extend(certificate.serialize());
+            }
+            CertificateSyncEntry::Revocation(revocation) => {
+                result.extend(2u8.serialize());
+                result.extend(revocation.serialize());
+            }
+        }
+        result
+    }
+}
+
+impl Deserializable for CertificateSyncEntry {
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        if serialized.is_empty(){
+            return Err(SerializationError::LengthError);
+        }
+        let data = serialized[1..].to_vec();
+        match serialized[0] {
+            0 => {
+                let (certificate, offset) = Falcon1024Certificate::from_serialized(&data)?;
+                Ok((CertificateSyncEntry::SigningCertificate(certificate), offset + 1))
+            }
+            1 => {
+                let (certificate, offset) = Kyber1024Certificate::from_serialized(&data)?;
+                Ok((CertificateSyncEntry::EncryptionCertificate(certificate), offset + 1))
+            }
+            2 => {
+                let (revocation, offset) = CertificateRevocation::from_serialized(&data)?;
+                Ok((CertificateSyncEntry::Revocation(revocation), offset + 1))
+            }
+            _ => Err(SerializationError::InvalidDataError("Unknown type of certificate sync entry"))
+        }
+    }
+}
+
+///
+/// Message carrying certificate changes. Each entry is signed by its issuer, so
+/// message may be relayed by any peer.
+///
+#[derive(Serializable, Deserializable, Clone, Default, Describe)]
+pub struct CertificateSyncMessage{
+    pub entries: Vec<CertificateSyncEntry>,
+}
+
+impl CertificateSyncMessage {
+    ///
+    /// Creates an empty message
+    ///
+    pub fn new() -> CertificateSyncMessage{
+        CertificateSyncMessage{
+            entries: Vec::new(),
+        }
+    }
+
+    ///
+    /// Creates message with all certificates and revocations known to service, used for periodic sync
+    ///
+    /// # Arguments
+    /// * service: &mut S: service to take certificates from
+    ///
+    pub fn snapshot<S: CertificateService + ?Sized>(service: &mut S) -> CertificateSyncMessage{
+        let mut message = Self::new();
+        for certificate in service.get_signing_certificates(){
+            message.add_signing_certificate(&certificate);
+        }
+        for certificate in service.get_encryption_certificates(){
+            message.add_encryption_certificate(&certificate);
+        }
+        for revocation in service.get_revocations(){
+            message.add_revocation(revocation);
+        }
+        message
+    }
+
+    ///
+    /// Adds signing certificate to message, secret key is never sent
+    ///
+    pub fn add_signing_certificate(&mut self, certificate: &Falcon1024Certificate) -> &mut CertificateSyncMessage{
+        self.entries.push(CertificateSyncEntry::SigningCertificate(certificate.clone_without_sk()));
+        self
+    }
+
+    ///
+    /// Adds encryption certificate to message, secret key is never sent
+    ///
+    pub fn add_encryption_certificate(&mut self, certificate: &Kyber1024Certificate) -> &mut CertificateSyncMessage{
+        self.entries.push(CertificateSyncEntry::EncryptionCertificate(certificate.clone_without_sk()));
+        self
+    }
+
+    ///
+    /// Adds revocation to message
+    ///
+    pub fn add_revocation(&mut self, revocation: CertificateRevocation) -> &mut CertificateSyncMessage{
+        self.entries.push(CertificateSyncEntry::Revocation(revocation));
+        self
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool{
+        self.entries.is_empty()
+    }
+}
+
+impl AsMessage for CertificateSyncMessage{
+    fn as_message(&self) -> Message {
+        Message{
+            id: 0,
+            timestamp: 0,
+            message_type: MessageType::CertificateSync,
+            data: Some(self.serialize()),
+            signature: None,
+            source: 0,
+            destination: 0,
+            module_id: 0,
+            certificate_id: 0,
+        }
+    }
+}
@@ -1,18 +1,28 @@
 use crate::serialization::error::SerializationError;
-use crate::serialization::deserializable::Deserializable;
+use crate::serialization::deserializable::{Deserializable, ParsingMode};
 use crate::serialization::serializable::Serializable;
-use libmilkyway_derive::{Deserializable, Serializable};
+use libmilkyway_derive::{Describe, Deserializable, Serializable};
 use crate::get_timestamp_with_milliseconds;
+use crate::message::id::generate_message_id;
 use crate::message::types::MessageType;
 use crate::pki::hash::HashType;
+use crate::pki::certificate::Certificate;
+use crate::pki::impls::CryptoError;
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
 use crate::pki::key::CryptoKey;
-use crate::pki::signature::Signature;
+use crate::pki::signature::{Signature, MESSAGE_SIGNATURE_LABEL};
 use crate::serialization::serializable::Serialized;
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+
+///
+/// Module ID of messages handled by MilkyWay itself rather than by modules
+///
+pub const CORE_MODULE_ID: u64 = 0;
 
 ///
 /// A common message structure which may contain other messages
 ///
-#[derive(Clone, Serializable, Deserializable, PartialEq)]
+#[derive(Clone, Serializable, Deserializable, PartialEq, Describe)]
 pub struct Message{
     pub id: u128,
     pub timestamp: u128,
@@ -39,6 +49,17 @@ impl<'a> Message {
             module_id: 0,
         }
     }
+    ///
+    /// Assigns generated ID(see generate_message_id) if ID is not set. Signed message is
+    /// left as is, as its ID is covered by signature.
+    ///
+    pub fn ensure_id(&'a mut self) -> &'a mut Message{
+        if self.id == 0 && self.signature.is_none(){
+            self.id = generate_message_id();
+        }
+        self
+    }
+
     ///
     /// Builder-like function for setting id of message.
     ///
@@ -93,6 +114,31 @@ impl<'a> Message {
         self
     }
 
+    ///
+    /// Builder-like signing with signing certificate. Serial of certificate is set as
+    /// certificate_id and embedded in signature together with time of signing.
+    ///
+    /// # Arguments
+    /// * certificate: &Falcon1024Certificate: signing certificate with secret key
+    ///
+    /// returns: Result<&Message, CryptoError>: message or error if certificate can not sign
+    ///
+    pub fn sign_by(&'a mut self, certificate: &Falcon1024Certificate) -> Result<&'a Message, CryptoError>{
+        self.certificate_id = certificate.get_serial();
+        let signature = certificate.sign_data_with_context(&self.as_signable(), HashType::None,
+                                                           MESSAGE_SIGNATURE_LABEL)?;
+        self.signature = Some(signature);
+        Ok(self)
+    }
+
+    ///
+    /// Gets serial of certificate which signed message: serial embedded in signature, which is
+    /// covered by it, or certificate_id for signatures without context
+    ///
+    pub fn get_signer_serial(&self) -> u128{
+        self.signature.as_ref().and_then(|signature| signature.get_signer_serial()).unwrap_or(self.certificate_id)
+    }
+
     ///
     /// Function
     pub fn verify_signature<T: CryptoKey>(&'a mut self, key: &T) -> bool{
@@ -124,6 +170,18 @@ impl<'a> Message {
         self
     }
 
+    ///
+    /// Builder-like function to set a module which message belongs to
+    ///
+    /// # Arguments
+    /// * module_id: u64: ID of module
+    ///
+    #[inline]
+    pub fn set_module_id(&'a mut self, module_id: u64) -> &'a mut Message{
+        self.module_id = module_id;
+        self
+    }
+
     ///
     /// Clones message without signature, which allows to verify whole-message signature
     ///
@@ -161,6 +219,21 @@ impl<'a> Message {
         self.data = data;
         self
     }
+
+    ///
+    /// Parses message received as a frame
+    ///
+    /// # Arguments
+    /// * serialized: &Serialized: frame
+    /// * mode: ParsingMode: whether bytes after message are an error
+    ///
+    /// returns: Result<Message, SerializationError>: message or error, TrailingDataError if
+    /// frame is longer than message in strict mode
+    ///
+    #[inline]
+    pub fn parse(serialized: &Serialized, mode: ParsingMode) -> Result<Message, SerializationError>{
+        mode.deserialize(serialized)
+    }
 }
 
 pub trait AsMessage{
@@ -206,6 +279,7 @@ mod tests {
                 algorithm: HashType::SHA512,
                 crypto_algorithm: CryptoType::Aes256GCM,
                 serialized_signature: data.serialize(),
+                context: None,
             })
         }
 
@@ -1,15 +1,16 @@
 use crate::serialization::error::SerializationError;
 use crate::serialization::deserializable::Deserializable;
 use crate::serialization::serializable::Serializable;
-use libmilkyway_derive::{Deserializable, Serializable};
+use libmilkyway_derive::{Describe, Deserializable, Serializable};
 use crate::message::common::{AsMessage, Message};
 use crate::message::types::MessageType;
 use crate::serialization::serializable::Serialized;
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
 
 ///
 /// Command for executing something
 /// 
-#[derive(Serializable, Deserializable)]
+#[derive(Serializable, Deserializable, Describe)]
 pub struct ExecData{
     ///
     /// ID of module to which command is sent
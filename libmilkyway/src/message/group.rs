@@ -0,0 +1,188 @@
+use crate::serialization::error::SerializationError;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::serializable::Serializable;
+use libmilkyway_derive::{Describe, Deserializable, Serializable};
+use crate::get_timestamp_with_milliseconds;
+use crate::message::common::{AsMessage, Message};
+use crate::message::types::MessageType;
+use crate::pki::certificate::{Certificate, FLAG_SIGN_CERTS};
+use crate::pki::hash::HashType;
+use crate::pki::impls::CryptoError;
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+use crate::pki::signature::Signature;
+use crate::serialization::serializable::Serialized;
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+use crate::services::certificate::CertificateService;
+
+///
+/// Change of a peer group
+///
+#[derive(Clone, Debug, PartialEq, Describe)]
+pub enum GroupOperation{
+    /** Creates group with given ID and name **/
+    Create,
+    /** Deletes group with all its members **/
+    Delete,
+    /** Adds peer with given ID to group **/
+    AddMember(u128),
+    /** Removes peer with given ID from group **/
+    RemoveMember(u128),
+}
+
+impl Serializable for GroupOperation {
+    fn serialize(&self) -> Serialized {
+        let mut result = Serialized::new();
+        match self {
+            GroupOperation::Create => result.extend(0u8.serialize()),
+            GroupOperation::Delete => result.extend(1u8.serialize()),
+            GroupOperation::AddMember(member) => {
+                result.extend(2u8.serialize());
+                result.extend(member.serialize());
+            }
+            GroupOperation::RemoveMember(member) => {
+                result.extend(3u8.serialize());
+                result.extend(member.serialize());
+            }
+        }
+        result
+    }
+}
+
+impl Deserializable for GroupOperation {
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        if serialized.is_empty(){
+            return Err(SerializationError::LengthError);
+        }
+        let data = serialized[1..].to_vec();
+        match serialized[0] {
+            0 => Ok((GroupOperation::Create, 1)),
+            1 => Ok((GroupOperation::Delete, 1)),
+            2 => {
+                let (member, offset) = u128::from_serialized(&data)?;
+                Ok((GroupOperation::AddMember(member), offset + 1))
+            }
+            3 => {
+                let (member, offset) = u128::from_serialized(&data)?;
+                Ok((GroupOperation::RemoveMember(member), offset + 1))
+            }
+            _ => Err(SerializationError::InvalidDataError("Unknown group operation"))
+        }
+    }
+}
+
+///
+/// A signed change of peer group. Records are signed by a certificate which can sign
+/// other certificates, so they may be relayed by any peer.
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq, Describe)]
+pub struct GroupRecord{
+    pub group_id: u128,
+    pub name: String,
+    pub operation: GroupOperation,
+    /** Records of a group are applied in order of timestamps **/
+    pub timestamp: u128,
+    pub signer_serial: u128,
+    pub signature: Option<Signature>,
+}
+
+impl GroupRecord {
+    ///
+    /// Creates a record signed by given certificate
+    ///
+    /// # Arguments
+    /// * group_id: u128: ID of group
+    /// * name: &str: name of group
+    /// * operation: GroupOperation: change of group
+    /// * signer: &Falcon1024Certificate: certificate to sign with, must have secret key
+    ///
+    /// returns: Result<GroupRecord, CryptoError>: signed record or error if certificate
+    /// can not sign it
+    ///
+    pub fn new(group_id: u128, name: &str, operation: GroupOperation,
+               signer: &Falcon1024Certificate) -> Result<GroupRecord, CryptoError>{
+        if !signer.check_flag(FLAG_SIGN_CERTS){
+            return Err(CryptoError::ArgumentError("Certificate can not sign group records"));
+        }
+        let mut record = GroupRecord{
+            group_id,
+            name: name.to_string(),
+            operation,
+            timestamp: get_timestamp_with_milliseconds(),
+            signer_serial: signer.get_serial(),
+            signature: None,
+        };
+        record.signature = Some(signer.sign_data(&record.as_signable(), HashType::None)?);
+        Ok(record)
+    }
+
+    ///
+    /// Clones and strips signature, allowing to sign/verify record
+    ///
+    pub fn as_signable(&self) -> GroupRecord{
+        let mut copy = self.clone();
+        copy.signature = None;
+        copy
+    }
+
+    ///
+    /// Verifies that record is signed by a trusted certificate which can sign certificates
+    ///
+    /// # Arguments
+    /// * service: &mut S: service with certificate of signer
+    ///
+    pub fn verify<S: CertificateService + ?Sized>(&self, service: &mut S) -> bool{
+        let signature = match &self.signature {
+            Some(signature) => signature,
+            None => return false,
+        };
+        let signer = match service.get_signing_certificate(self.signer_serial) {
+            Some(signer) => signer,
+            None => return false,
+        };
+        if !signer.check_flag(FLAG_SIGN_CERTS) || !service.verify_signing_certificate(&signer){
+            return false;
+        }
+        signer.verify_signature(&self.as_signable(), signature)
+    }
+}
+
+impl AsMessage for GroupRecord{
+    fn as_message(&self) -> Message {
+        Message{
+            id: 0,
+            timestamp: 0,
+            message_type: MessageType::GroupRecord,
+            data: Some(self.serialize()),
+            signature: None,
+            source: 0,
+            destination: 0,
+            module_id: 0,
+            certificate_id: 0,
+        }
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pki::certificate::FLAG_SIGN_MESSAGES;
+    use crate::testing::certificate::{test_certificates, MockCertificateService, TEST_SIGNING_CERTIFICATE_SERIAL};
+
+    #[test]
+    fn test_group_record_signature() {
+        let mut signing = test_certificates().signing;
+        let mut service = MockCertificateService::with_test_certificates();
+        let record = GroupRecord::new(7, "branch-office-A", GroupOperation::AddMember(42), &signing).unwrap();
+        assert_eq!(record.signer_serial, TEST_SIGNING_CERTIFICATE_SERIAL);
+        assert_eq!(GroupRecord::from_serialized(&record.serialize()).unwrap().0, record);
+        assert!(record.verify(&mut service));
+
+        let mut tampered = record.clone();
+        tampered.operation = GroupOperation::AddMember(43);
+        assert!(!tampered.verify(&mut service));
+
+        signing.flags = FLAG_SIGN_MESSAGES;
+        assert!(GroupRecord::new(7, "branch-office-A", GroupOperation::Create, &signing).is_err());
+    }
+}
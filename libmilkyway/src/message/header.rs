@@ -0,0 +1,175 @@
+use crate::message::common::Message;
+use crate::message::types::MessageType;
+use crate::pki::signature::Signature;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::Serialized;
+
+///
+/// Metadata of serialized message read without parsing its data and signature, so routers
+/// and filters do not copy payloads they only pass further
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct MessageHeader{
+    pub id: u128,
+    pub timestamp: u128,
+    pub message_type: MessageType,
+    pub certificate_id: u128,
+    /** Length of data in bytes, None if message has no data **/
+    pub data_size: Option<usize>,
+    pub is_signed: bool,
+    pub source: u128,
+    pub destination: u128,
+    pub module_id: u64,
+}
+
+// Reads fields of serialized message one by one, skipped fields are never copied
+struct HeaderReader<'a>{
+    serialized: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> HeaderReader<'a> {
+    // Takes next size bytes
+    fn take(&mut self, size: usize) -> Result<&'a [u8], SerializationError>{
+        let end = self.offset.checked_add(size).ok_or(SerializationError::LengthError)?;
+        let bytes = self.serialized.get(self.offset..end).ok_or(SerializationError::LengthError)?;
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn read_u128(&mut self) -> Result<u128, SerializationError>{
+        Ok(u128::from_le_bytes(self.take(size_of::<u128>())?.try_into().unwrap()))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, SerializationError>{
+        Ok(u64::from_le_bytes(self.take(size_of::<u64>())?.try_into().unwrap()))
+    }
+
+    // Reads tag of Option, true if value follows
+    fn read_flag(&mut self) -> Result<bool, SerializationError>{
+        Ok(self.take(1)?[0] != 0)
+    }
+}
+
+impl MessageHeader {
+    ///
+    /// Reads header of serialized message. Data and signature are skipped by their lengths,
+    /// so time does not depend on size of payload.
+    ///
+    /// # Arguments
+    /// * serialized: &[u8]: serialized message
+    ///
+    /// returns: Result<MessageHeader, SerializationError>: header or error if message is truncated
+    ///
+    pub fn peek(serialized: &[u8]) -> Result<MessageHeader, SerializationError>{
+        let mut reader = HeaderReader{ serialized, offset: 0 };
+        let id = reader.read_u128()?;
+        let timestamp = reader.read_u128()?;
+        let (message_type, _) = MessageType::from_serialized(&reader.take(1)?.to_vec())?;
+        let certificate_id = reader.read_u128()?;
+        let data_size = match reader.read_flag()? {
+            true => {
+                let size = usize::from_le_bytes(reader.take(size_of::<usize>())?.try_into().unwrap());
+                reader.take(size)?;
+                Some(size)
+            }
+            false => None,
+        };
+        let is_signed = reader.read_flag()?;
+        if is_signed{
+            let size = Signature::get_serialized_length(&serialized[reader.offset..])?;
+            reader.take(size)?;
+        }
+        Ok(MessageHeader{
+            id,
+            timestamp,
+            message_type,
+            certificate_id,
+            data_size,
+            is_signed,
+            source: reader.read_u128()?,
+            destination: reader.read_u128()?,
+            module_id: reader.read_u64()?,
+        })
+    }
+}
+
+///
+/// Serialized message with its header already read. Payload is parsed only when consumer
+/// asks for the whole message, until then message may be forwarded as is.
+///
+#[derive(Clone, Debug)]
+pub struct LazyMessage{
+    header: MessageHeader,
+    serialized: Serialized,
+}
+
+impl LazyMessage {
+    ///
+    /// Reads header of serialized message
+    ///
+    /// returns: Result<LazyMessage, SerializationError>: message or error if header is malformed
+    ///
+    pub fn from_serialized(serialized: Serialized) -> Result<LazyMessage, SerializationError>{
+        Ok(LazyMessage{
+            header: MessageHeader::peek(&serialized)?,
+            serialized,
+        })
+    }
+
+    #[inline]
+    pub fn get_header(&self) -> &MessageHeader{
+        &self.header
+    }
+
+    ///
+    /// Gets message exactly as it was received, e.g. to forward it
+    ///
+    #[inline]
+    pub fn as_serialized(&self) -> &Serialized{
+        &self.serialized
+    }
+
+    ///
+    /// Parses the whole message
+    ///
+    pub fn to_message(&self) -> Result<Message, SerializationError>{
+        Message::from_serialized(&self.serialized).map(|(message, _)| message)
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::serializable::Serializable;
+    use crate::testing::certificate::test_certificates;
+
+    #[test]
+    fn test_peek_header() {
+        let mut message = Message::new();
+        message.set_id(7);
+        message.set_type(MessageType::Exec);
+        message.data = Some(vec![5; 1 << 20]);
+        message.source = 2;
+        message.destination = 3;
+        message.module_id = 9;
+        let header = MessageHeader::peek(&message.serialize()).unwrap();
+        assert_eq!((header.id, header.source, header.destination, header.module_id), (7, 2, 3, 9));
+        assert_eq!(header.message_type, MessageType::Exec);
+        assert_eq!(header.data_size, Some(1 << 20));
+        assert!(!header.is_signed);
+
+        let certificate = test_certificates().signing;
+        message.data = Some(vec![1, 2, 3]);
+        message.sign_by(&certificate).unwrap();
+        let serialized = message.serialize();
+        let lazy = LazyMessage::from_serialized(serialized.clone()).unwrap();
+        assert!(lazy.get_header().is_signed);
+        assert_eq!(lazy.get_header().destination, 3);
+        assert_eq!(lazy.get_header().certificate_id, message.certificate_id);
+        assert!(lazy.to_message().unwrap() == message);
+        assert_eq!(MessageHeader::peek(&serialized[..serialized.len() - 1]), Err(SerializationError::LengthError));
+    }
+}
@@ -0,0 +1,153 @@
+use std::sync::Mutex;
+use once_cell::sync::Lazy;
+
+///
+/// Bits of ID taken by counter, the rest of lower half is a random session
+///
+const COUNTER_BITS: u32 = 40;
+
+const COUNTER_MASK: u64 = (1 << COUNTER_BITS) - 1;
+
+const SESSION_MASK: u64 = (1 << (64 - COUNTER_BITS)) - 1;
+
+///
+/// Generates message IDs which do not depend on clocks.
+///
+/// Once ID of node is known, IDs consist of node prefix(upper 64 bits), random session
+/// chosen on start(24 bits) and monotonic counter(40 bits), so IDs of different nodes
+/// never collide and IDs of one node do not repeat after restart. Before that IDs are
+/// random 128-bit numbers. Zero is never generated, as it marks ID which is not set.
+///
+pub struct MessageIdGenerator{
+    node: Option<u64>,
+    session: u64,
+    counter: u64,
+}
+
+impl MessageIdGenerator {
+    ///
+    /// Creates generator of random IDs
+    ///
+    pub fn random() -> MessageIdGenerator{
+        MessageIdGenerator{
+            node: None,
+            session: 0,
+            counter: 0,
+        }
+    }
+
+    ///
+    /// Creates generator of IDs prefixed with peer ID
+    ///
+    /// # Arguments
+    /// * peer_id: u128: ID of this node, folded to 64 bits
+    ///
+    pub fn for_peer(peer_id: u128) -> MessageIdGenerator{
+        MessageIdGenerator{
+            node: Some((peer_id as u64) ^ ((peer_id >> 64) as u64)),
+            session: rand::random::<u64>() & SESSION_MASK,
+            counter: 0,
+        }
+    }
+
+    ///
+    /// Gets prefix of generated IDs, None if IDs are random
+    ///
+    #[inline]
+    pub fn get_node(&self) -> Option<u64>{
+        self.node
+    }
+
+    pub fn next_id(&mut self) -> u128{
+        let node = match self.node {
+            Some(node) => node,
+            None => return random_id(),
+        };
+        if self.counter == COUNTER_MASK{
+            // Counter is exhausted, a new session keeps IDs unique
+            let previous = self.session;
+            while self.session == previous{
+                self.session = rand::random::<u64>() & SESSION_MASK;
+            }
+            self.counter = 0;
+        }
+        self.counter += 1;
+        ((node as u128) << 64) | ((self.session << COUNTER_BITS) | self.counter) as u128
+    }
+}
+
+fn random_id() -> u128{
+    loop {
+        let id: u128 = rand::random();
+        if id != 0{
+            return id;
+        }
+    }
+}
+
+static GENERATOR: Lazy<Mutex<MessageIdGenerator>> = Lazy::new(|| Mutex::new(MessageIdGenerator::random()));
+
+///
+/// Makes IDs of this process prefixed with ID of node, called once peer ID is assigned
+///
+/// # Arguments
+/// * peer_id: u128: ID assigned to this node
+///
+pub fn set_node_id(peer_id: u128){
+    let mut generator = GENERATOR.lock().unwrap();
+    if generator.get_node() != MessageIdGenerator::for_peer(peer_id).get_node(){
+        *generator = MessageIdGenerator::for_peer(peer_id);
+    }
+}
+
+///
+/// Generates unique non-zero message ID
+///
+pub fn generate_message_id() -> u128{
+    GENERATOR.lock().unwrap().next_id()
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use crate::message::common::Message;
+    use crate::pki::hash::HashType;
+    use crate::testing::certificate::test_certificates;
+
+    #[test]
+    fn test_sequential_ids() {
+        let mut first = MessageIdGenerator::for_peer(100);
+        let mut second = MessageIdGenerator::for_peer(101);
+        let mut ids = HashSet::new();
+        for _ in 0..1000{
+            assert!(ids.insert(first.next_id()));
+            assert!(ids.insert(second.next_id()));
+        }
+        let id = first.next_id();
+        assert_eq!(id >> 64, 100);
+        assert_eq!(id as u64 & COUNTER_MASK, 1001);
+        // Exhausted counter switches session instead of repeating IDs
+        let session = first.session;
+        first.counter = COUNTER_MASK;
+        let id = first.next_id();
+        assert_ne!(first.session, session);
+        assert_eq!(id as u64 & COUNTER_MASK, 1);
+        assert!(ids.insert(id));
+    }
+
+    #[test]
+    fn test_random_ids() {
+        let mut generator = MessageIdGenerator::random();
+        assert_eq!(generator.get_node(), None);
+        assert_ne!(generator.next_id(), generator.next_id());
+        assert_ne!(generate_message_id(), 0);
+        let mut message = Message::new();
+        assert_ne!(message.ensure_id().id, 0);
+        let id = message.id;
+        assert_eq!(message.ensure_id().id, id);
+        message.set_id(0).sign(&test_certificates().signing.secret_key.unwrap(), HashType::None);
+        assert_eq!(message.ensure_id().id, 0);
+    }
+}
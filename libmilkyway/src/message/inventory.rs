@@ -0,0 +1,167 @@
+use crate::serialization::error::SerializationError;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::serializable::Serializable;
+use libmilkyway_derive::{Describe, Deserializable, Serializable};
+use crate::get_timestamp_with_milliseconds;
+use crate::message::common::{AsMessage, Message};
+use crate::message::types::MessageType;
+use crate::pki::certificate::Certificate;
+use crate::serialization::serializable::Serialized;
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+use crate::services::certificate::CertificateService;
+use crate::transport::version::PROTOCOL_VERSION;
+
+///
+/// Facts about node reported to peers asking for them
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq, Describe)]
+pub struct NodeInventory{
+    pub hostname: String,
+    /** Operating system and architecture, e.g. `linux/x86_64` **/
+    pub os: String,
+    /** Version of libmilkyway node is built with **/
+    pub version: String,
+    pub protocol_version: u32,
+    /** Names of modules loaded on node **/
+    pub modules: Vec<String>,
+    /** Addresses node accepts connections on **/
+    pub endpoints: Vec<String>,
+    /** Serials of signing certificates node holds secret keys of **/
+    pub signing_certificates: Vec<u128>,
+    /** Serials of encryption certificates node holds secret keys of **/
+    pub encryption_certificates: Vec<u128>,
+    /** Milliseconds since epoch when facts were collected **/
+    pub collected_at: u128,
+}
+
+// Reads name of host from kernel or from environment, `unknown` if neither has it
+fn read_hostname() -> String{
+    ["/proc/sys/kernel/hostname", "/etc/hostname"].iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .chain(std::env::var("HOSTNAME").ok())
+        .map(|hostname| hostname.trim().to_string())
+        .find(|hostname| !hostname.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+impl NodeInventory {
+    ///
+    /// Collects facts about this node
+    ///
+    /// # Arguments
+    /// * certificates: &mut S: certificates of node, only ones with secret keys are reported
+    /// * modules: Vec<String>: names of loaded modules
+    /// * endpoints: Vec<String>: addresses node listens on
+    ///
+    pub fn collect<S: CertificateService + ?Sized>(certificates: &mut S, modules: Vec<String>,
+                                                   endpoints: Vec<String>) -> NodeInventory{
+        let mut signing_certificates: Vec<u128> = certificates.get_signing_certificates().iter()
+            .filter(|certificate| certificate.get_secret_key().is_some())
+            .map(|certificate| certificate.get_serial())
+            .collect();
+        let mut encryption_certificates: Vec<u128> = certificates.get_encryption_certificates().iter()
+            .filter(|certificate| certificate.get_secret_key().is_some())
+            .map(|certificate| certificate.get_serial())
+            .collect();
+        signing_certificates.sort();
+        encryption_certificates.sort();
+        NodeInventory{
+            hostname: read_hostname(),
+            os: format!("{}/{}", std::env::consts::OS, std::env::consts::ARCH),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            protocol_version: PROTOCOL_VERSION,
+            modules,
+            endpoints,
+            signing_certificates,
+            encryption_certificates,
+            collected_at: get_timestamp_with_milliseconds(),
+        }
+    }
+
+    ///
+    /// Gets milliseconds passed since facts were collected
+    ///
+    /// # Arguments
+    /// * now: u128: current time in milliseconds since epoch
+    ///
+    #[inline]
+    pub fn get_age(&self, now: u128) -> u128{
+        now.saturating_sub(self.collected_at)
+    }
+}
+
+///
+/// Request for inventory of a node, MUST be signed by a trusted certificate allowed to
+/// sign messages
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq, Describe)]
+pub struct InventoryRequest{
+    pub request_id: u128,
+    /** Age in milliseconds of facts node may answer with instead of collecting them again **/
+    pub max_age: u64,
+}
+
+///
+/// Inventory of node answering InventoryRequest, signed by signing certificate of node
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq, Describe)]
+pub struct InventoryResponse{
+    pub request_id: u128,
+    pub inventory: NodeInventory,
+}
+
+impl AsMessage for InventoryRequest{
+    fn as_message(&self) -> Message {
+        Message{
+            id: 0,
+            timestamp: 0,
+            message_type: MessageType::InventoryRequest,
+            data: Some(self.serialize()),
+            signature: None,
+            source: 0,
+            destination: 0,
+            module_id: 0,
+            certificate_id: 0,
+        }
+    }
+}
+
+impl AsMessage for InventoryResponse{
+    fn as_message(&self) -> Message {
+        Message{
+            id: 0,
+            timestamp: 0,
+            message_type: MessageType::InventoryResponse,
+            data: Some(self.serialize()),
+            signature: None,
+            source: 0,
+            destination: 0,
+            module_id: 0,
+            certificate_id: 0,
+        }
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::certificate::{test_certificates, MockCertificateService};
+
+    #[test]
+    fn test_collect_inventory() {
+        let mut certificates = MockCertificateService::with_test_certificates();
+        let mut public = test_certificates().signing.clone_without_sk();
+        public.serial_number = 100;
+        certificates.add_signing_certificate(public);
+        let inventory = NodeInventory::collect(&mut certificates, vec!["certman".to_string()],
+                                               vec!["0.0.0.0:4444".to_string()]);
+        assert!(!inventory.hostname.is_empty());
+        assert_eq!(inventory.os, format!("{}/{}", std::env::consts::OS, std::env::consts::ARCH));
+        assert_eq!(inventory.signing_certificates, vec![test_certificates().signing.serial_number]);
+        assert_eq!(inventory.encryption_certificates, vec![test_certificates().encryption.serial_number]);
+        assert_eq!(inventory.get_age(inventory.collected_at + 1500), 1500);
+        let (deserialized, _) = NodeInventory::from_serialized(&inventory.serialize()).unwrap();
+        assert_eq!(deserialized, inventory);
+    }
+}
@@ -1,6 +1,8 @@
 use crate::message::common::{AsMessage, Message};
 use crate::message::types::MessageType;
 use crate::serialization::serializable::Serializable;
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+use libmilkyway_derive::Describe;
 
 
 ///
@@ -37,6 +39,7 @@ impl AsMessage for PingMessage{
 ///
 /// A response to ping. Has an ID from message headers on which ping exactly we reply
 /// 
+#[derive(Describe)]
 pub struct PongMessage{
     pub ping_message_id: u128,
 }
@@ -65,7 +68,7 @@ impl AsMessage for PongMessage{
         Message{
             id: 0,
             timestamp: 0,
-            message_type: MessageType::Ping,
+            message_type: MessageType::Pong,
             data: Some(self.ping_message_id.serialize()),
             signature: None,
             source: 0,
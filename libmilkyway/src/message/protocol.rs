@@ -0,0 +1,159 @@
+use crate::controllers::authorization::chain::{ChainRequest, ChainResponse};
+use crate::controllers::authorization::factor::{AuthChallenge, AuthChallengeResponse};
+use crate::message::certpush::CertificatePushMessage;
+use crate::message::certsync::CertificateSyncMessage;
+use crate::message::common::Message;
+use crate::message::exec::ExecData;
+use crate::message::group::GroupRecord;
+use crate::message::inventory::{InventoryRequest, InventoryResponse};
+use crate::message::ping::PongMessage;
+use crate::message::stream::{StreamChunk, StreamCredit};
+use crate::message::types::MessageType;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::schema::{json_string, Describe, SchemaRegistry, TypeSchema};
+use crate::serialization::{MIN_COMPATIBLE_WIRE_FORMAT_VERSION, WIRE_FORMAT_VERSION};
+use crate::services::certificate::remote::{RemoteCertificateRequest, RemoteCertificateResponse};
+use crate::services::name::exchange::NameExchangeMessage;
+use crate::transport::sequence::{SequenceAck, SequencedEnvelope};
+use crate::transport::shaping::{BandwidthControlRequest, BandwidthControlResponse};
+
+///
+/// Message type together with layout of its `data` field
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct MessageDescriptor{
+    pub message_type: MessageType,
+    pub tag: u8,
+    pub name: String,
+    /** `None` if message carries everything in its headers **/
+    pub payload: Option<TypeSchema>,
+}
+
+///
+/// Machine-readable description of protocol: message envelope, all message types
+/// and definitions of every type they refer to
+///
+pub struct ProtocolDescriptor{
+    pub messages: Vec<MessageDescriptor>,
+    pub registry: SchemaRegistry,
+}
+
+///
+/// Adds payload type of message to registry
+///
+/// returns: Option<TypeSchema>: layout of payload or None if message has no payload
+///
+fn describe_payload(message_type: &MessageType, registry: &mut SchemaRegistry) -> Option<TypeSchema>{
+    fn payload<T: Describe>(registry: &mut SchemaRegistry) -> Option<TypeSchema>{
+        registry.add::<T>();
+        Some(T::describe())
+    }
+    // Match is exhaustive, so new message type can not be added without describing it
+    match message_type {
+        MessageType::Ping => None,
+        MessageType::Pong => payload::<PongMessage>(registry),
+        MessageType::Exec => payload::<ExecData>(registry),
+        MessageType::StateApply | MessageType::StateRevert | MessageType::Report |
+        MessageType::KeyEx | MessageType::LogMessage | MessageType::Ack |
+        MessageType::SetPeerID => None,
+        MessageType::CertificateSync => payload::<CertificateSyncMessage>(registry),
+        MessageType::CertificatePush => payload::<CertificatePushMessage>(registry),
+        MessageType::AuthChallenge => payload::<AuthChallenge>(registry),
+        MessageType::AuthChallengeResponse => payload::<AuthChallengeResponse>(registry),
+        MessageType::GroupRecord => payload::<GroupRecord>(registry),
+        MessageType::ChainRequest => payload::<ChainRequest>(registry),
+        MessageType::ChainResponse => payload::<ChainResponse>(registry),
+        MessageType::CertificateServiceRequest => payload::<RemoteCertificateRequest>(registry),
+        MessageType::CertificateServiceResponse => payload::<RemoteCertificateResponse>(registry),
+        MessageType::BandwidthControlRequest => payload::<BandwidthControlRequest>(registry),
+        MessageType::BandwidthControlResponse => payload::<BandwidthControlResponse>(registry),
+        MessageType::StreamChunk => payload::<StreamChunk>(registry),
+        MessageType::StreamCredit => payload::<StreamCredit>(registry),
+        MessageType::NameExchange => payload::<NameExchangeMessage>(registry),
+        MessageType::Sequenced => payload::<SequencedEnvelope>(registry),
+        MessageType::SequenceAck => payload::<SequenceAck>(registry),
+        MessageType::InventoryRequest => payload::<InventoryRequest>(registry),
+        MessageType::InventoryResponse => payload::<InventoryResponse>(registry),
+        MessageType::Unknown(_) => None,
+    }
+}
+
+///
+/// Collects description of all message types known to this build
+///
+pub fn describe_protocol() -> ProtocolDescriptor{
+    let mut registry = SchemaRegistry::new();
+    registry.add::<Message>();
+    let mut messages = Vec::new();
+    // Message types are tagged in order of declaration, so first unknown tag ends the list
+    for tag in 0..=u8::MAX{
+        let message_type = match MessageType::from_serialized(&vec![tag]) {
+            Ok((MessageType::Unknown(_), _)) | Err(_) => break,
+            Ok((message_type, _)) => message_type,
+        };
+        let payload = describe_payload(&message_type, &mut registry);
+        messages.push(MessageDescriptor{
+            name: format!("{:?}", message_type),
+            message_type,
+            tag,
+            payload,
+        });
+    }
+    ProtocolDescriptor{
+        messages,
+        registry,
+    }
+}
+
+impl ProtocolDescriptor {
+    ///
+    /// Exports protocol as JSON suitable for generating bindings in other languages
+    ///
+    pub fn to_json(&self) -> String{
+        let messages: Vec<String> = self.messages.iter().map(|message| {
+            let payload = match &message.payload {
+                Some(payload) => payload.to_json(),
+                None => "null".to_string(),
+            };
+            format!("{{\"type\":{},\"tag\":{},\"payload\":{}}}", json_string(&message.name), message.tag, payload)
+        }).collect();
+        format!("{{\"wire_format_version\":{},\"min_compatible_wire_format_version\":{},\"message\":{},\
+                 \"messages\":[{}],\"types\":{}}}",
+                WIRE_FORMAT_VERSION, MIN_COMPATIBLE_WIRE_FORMAT_VERSION, Message::describe().to_json(),
+                messages.join(","), self.registry.to_json())
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::schema::DefinitionSchema;
+
+    #[test]
+    fn test_describe_protocol() {
+        let protocol = describe_protocol();
+        assert_eq!(protocol.messages.last().unwrap().message_type, MessageType::InventoryResponse);
+        assert_eq!(protocol.messages.last().unwrap().tag as usize + 1, protocol.messages.len());
+        assert_eq!(protocol.messages[2].name, "Exec");
+        assert_eq!(protocol.messages[2].payload, Some(TypeSchema::Named("ExecData".to_string())));
+        assert_eq!(protocol.messages[0].payload, None);
+        // Every referenced type must be defined
+        for (name, definition) in protocol.registry.get_definitions(){
+            let fields: Vec<&TypeSchema> = match definition {
+                DefinitionSchema::Struct(fields) => fields.iter().map(|field| &field.schema).collect(),
+                DefinitionSchema::Enum(variants) => variants.iter()
+                    .flat_map(|variant| variant.fields.iter().map(|field| &field.schema)).collect(),
+            };
+            for schema in fields{
+                if let TypeSchema::Named(reference) = schema{
+                    assert!(protocol.registry.get_definition(reference).is_some(),
+                            "{} refers to undefined {}", name, reference);
+                }
+            }
+        }
+        let json = protocol.to_json();
+        assert!(json.starts_with("{\"wire_format_version\":"));
+        assert!(json.contains("\"message\":{\"ref\":\"Message\"}"));
+    }
+}
@@ -0,0 +1,67 @@
+use crate::serialization::error::SerializationError;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::serializable::Serializable;
+use libmilkyway_derive::{Describe, Deserializable, Serializable};
+use crate::message::common::{AsMessage, Message};
+use crate::message::types::MessageType;
+use crate::serialization::serializable::Serialized;
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+
+///
+/// Part of a payload streamed to a module. Chunks of one stream share stream ID and
+/// are numbered from zero.
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq, Describe)]
+pub struct StreamChunk{
+    pub stream_id: u128,
+    pub sequence: u64,
+    pub data: Vec<u8>,
+    /** Set on the last chunk of stream, it may carry data as well **/
+    pub is_last: bool,
+    /** Sender gave up on stream, everything received so far is incomplete **/
+    pub is_aborted: bool,
+}
+
+///
+/// Flow control of a stream sent by receiver: sender may send chunks with sequence
+/// below `allowed`
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq, Describe)]
+pub struct StreamCredit{
+    pub stream_id: u128,
+    pub allowed: u64,
+    /** Receiver is no longer interested in stream **/
+    pub is_cancelled: bool,
+}
+
+impl AsMessage for StreamChunk{
+    fn as_message(&self) -> Message {
+        Message{
+            id: 0,
+            timestamp: 0,
+            message_type: MessageType::StreamChunk,
+            data: Some(self.serialize()),
+            signature: None,
+            source: 0,
+            destination: 0,
+            module_id: 0,
+            certificate_id: 0,
+        }
+    }
+}
+
+impl AsMessage for StreamCredit{
+    fn as_message(&self) -> Message {
+        Message{
+            id: 0,
+            timestamp: 0,
+            message_type: MessageType::StreamCredit,
+            data: Some(self.serialize()),
+            signature: None,
+            source: 0,
+            destination: 0,
+            module_id: 0,
+            certificate_id: 0,
+        }
+    }
+}
@@ -1,14 +1,19 @@
 use crate::serialization::error::SerializationError;
 use crate::serialization::serializable::Serialized;
-use libmilkyway_derive::{EnumDeserializable, EnumSerializable};
+use libmilkyway_derive::{Describe, EnumDeserializable, EnumSerializable};
 use crate::serialization::serializable::Serializable;
 use crate::serialization::deserializable::Deserializable;
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
 
 ///
 /// Message type.
 /// Defines a type of messages being sent.
+///
+/// Types added by newer builds are read as Unknown, so older nodes still accept
+/// and forward such messages.
 /// 
-#[derive(EnumSerializable, EnumDeserializable, Clone, Debug, PartialEq)]
+#[derive(EnumSerializable, EnumDeserializable, Clone, Debug, PartialEq, Describe)]
+#[enum_serializable(other = Unknown)]
 pub enum MessageType{
     ///
     /// Ping request from other host
@@ -49,5 +54,125 @@ pub enum MessageType{
     ///
     /// Set peer ID in the network
     /// 
-    SetPeerID
-}
\ No newline at end of file
+    SetPeerID,
+    ///
+    /// Added, rotated or revoked certificates signed by their issuers
+    ///
+    CertificateSync,
+    ///
+    /// Certificate sent to a peer for installation
+    ///
+    CertificatePush,
+    ///
+    /// Request to pass an additional authentication factor
+    ///
+    AuthChallenge,
+    ///
+    /// Response to authentication challenge, e.g. TOTP code
+    ///
+    AuthChallengeResponse,
+    ///
+    /// Signed change of a peer group
+    ///
+    GroupRecord,
+    ///
+    /// Request for certificates missing in chain of authorization message
+    ///
+    ChainRequest,
+    ///
+    /// Certificates requested by ChainRequest
+    ///
+    ChainResponse,
+    ///
+    /// Call of certificate service of a broker
+    ///
+    CertificateServiceRequest,
+    ///
+    /// Result of call of certificate service of a broker
+    ///
+    CertificateServiceResponse,
+    ///
+    /// Request to change bandwidth caps of a host or to get its throttling stats
+    ///
+    BandwidthControlRequest,
+    ///
+    /// Result of bandwidth control request
+    ///
+    BandwidthControlResponse,
+    ///
+    /// Chunk of a payload streamed to a module
+    ///
+    StreamChunk,
+    ///
+    /// Flow control of a stream: how many chunks receiver is ready to take
+    ///
+    StreamCredit,
+    ///
+    /// Name records signed by their owners, gossiped between peers
+    ///
+    NameExchange,
+    ///
+    /// Message numbered per peer to be delivered exactly once, wraps type and data of original
+    ///
+    Sequenced,
+    ///
+    /// Receiver recorded sequence of Sequenced message
+    ///
+    SequenceAck,
+    ///
+    /// Request for facts about a host: OS, versions, modules, endpoints and certificates
+    ///
+    InventoryRequest,
+    ///
+    /// Facts about a host requested by InventoryRequest
+    ///
+    InventoryResponse,
+    ///
+    /// Type unknown to this build, holds its tag as received. New types MUST be
+    /// declared before it.
+    ///
+    Unknown(Serialized),
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libmilkyway_derive::{Deserializable, Serializable};
+    use crate::message::common::Message;
+
+    #[derive(Serializable, Deserializable, Debug, PartialEq)]
+    struct TypedRecord{
+        message_type: MessageType,
+        sequence: u64,
+        payload: Vec<u8>,
+    }
+
+    #[test]
+    fn test_unknown_type_round_trip() {
+        let (message_type, size) = MessageType::from_serialized(&vec![200, 7]).unwrap();
+        assert_eq!(message_type, MessageType::Unknown(vec![200]));
+        assert_eq!(size, 1);
+        assert_eq!(message_type.serialize(), vec![200]);
+        assert_eq!(MessageType::from_serialized(&vec![2]).unwrap(), (MessageType::Exec, 1));
+
+        // Message of newer type is read with the rest of its fields intact
+        let mut message = Message::new();
+        message.set_type(MessageType::Unknown(vec![200]));
+        message.set_data(Some(vec![1, 2, 3]));
+        message.source = 5;
+        let (deserialized, _) = Message::from_serialized(&message.serialize()).unwrap();
+        assert_eq!(deserialized.message_type, MessageType::Unknown(vec![200]));
+        assert_eq!(deserialized.source, 5);
+        assert_eq!(deserialized.data, Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_unknown_type_in_container() {
+        for message_type in [MessageType::Exec, MessageType::Unknown(vec![200])]{
+            let record = TypedRecord{ message_type, sequence: 42, payload: vec![1, 2, 3] };
+            let serialized = record.serialize();
+            assert_eq!(TypedRecord::from_serialized(&serialized).unwrap(), (record, serialized.len()));
+        }
+    }
+}
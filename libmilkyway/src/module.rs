@@ -1,22 +1,53 @@
 pub mod loader;
+pub mod isolation;
+pub mod isolated;
+pub mod supervisor;
+pub mod state;
+pub mod session;
+pub mod registry;
 
+use std::sync::Arc;
+use libmilkyway_derive::{EnumDeserializable, EnumSerializable};
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+
+use crate::cli::describe::ModuleDescription;
 use crate::message::common::Message;
-use crate::services::certificate::CertificateServiceBinder;
+use crate::module::session::{CLIFailure, CLIInput, CLIProgress, CLIPrompt};
+use crate::module::state::ModuleState;
+use crate::pki::certificate::profile::CertificateProfile;
+use crate::services::certificate::{CertificateServiceBinder, CertificateServicePool};
+use crate::services::group::SharedGroupService;
 use crate::services::name::NameService;
 use crate::services::transport::TransportService;
+use crate::transport::access::SharedAccessControl;
+use crate::transport::operator::OperatorIdentity;
+use crate::transport::pinning::SharedPeerPins;
 
 ///
 /// A enum for storing data about CLI commands result
+///
+/// Commands taking several steps return InProgress or NeedsInput, then host keeps calling
+/// on_cli_continue of the same module until it returns Done, NamespaceChange or Failed
+/// (see session::drive_cli_session).
 /// 
+#[derive(Clone, Debug, PartialEq)]
 pub enum CLIStatus{
     Done,
     NamespaceChange(Vec<String>),
+    /** Command continues, host shows progress and calls on_cli_continue with CLIInput::Next **/
+    InProgress(CLIProgress),
+    /** Command waits for user, host asks prompt and passes answer to on_cli_continue **/
+    NeedsInput(CLIPrompt),
+    /** Command failed, host shows error **/
+    Failed(CLIFailure),
 }
 
 ///
 /// Types of hosts which may load modules
 /// 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Debug, EnumSerializable, EnumDeserializable)]
 pub enum HostType{
     ///
     /// A CLI host
@@ -61,6 +92,16 @@ pub trait ModuleDataBus: Send + Sync{
     ///
     fn get_certificate_service(&self) -> Box<CertificateServiceBinder>;
 
+    ///
+    /// Gets pool of binders to certificate service sized by configuration of host, so
+    /// consumers of module, e.g. its namespaces and message handlers, do not wait for each other
+    ///
+    /// returns: CertificateServicePool: pool or pool of one binder if host does not configure it
+    ///
+    fn get_certificate_pool(&self) -> CertificateServicePool{
+        CertificateServicePool::from_channels(vec![self.get_certificate_service()])
+    }
+
     ///
     /// Gets a host type on which module is loaded
     ///
@@ -72,6 +113,90 @@ pub trait ModuleDataBus: Send + Sync{
     /// returns: Option<u128>: ID of host or None
     /// 
     fn get_host_id(&self) -> Option<u128>;
+
+    ///
+    /// Gets a group service of current host
+    ///
+    /// returns: Option<SharedGroupService>: a service or None if host does not keep groups
+    ///
+    #[inline]
+    fn get_group_service(&self) -> Option<SharedGroupService>{
+        None
+    }
+
+    ///
+    /// Gets allow and block lists of peers of current host
+    ///
+    /// returns: Option<SharedAccessControl>: access control or None if host does not keep lists
+    ///
+    #[inline]
+    fn get_access_control(&self) -> Option<SharedAccessControl>{
+        None
+    }
+
+    ///
+    /// Gets fingerprints peers of current host are pinned to
+    ///
+    /// returns: Option<SharedPeerPins>: pins or None if host does not keep them
+    ///
+    #[inline]
+    fn get_peer_pins(&self) -> Option<SharedPeerPins>{
+        None
+    }
+
+    ///
+    /// Gets certificate profiles configured on current host, in addition to built-in ones
+    ///
+    /// returns: Vec<CertificateProfile>: profiles or empty list if host does not configure them
+    ///
+    #[inline]
+    fn get_certificate_profiles(&self) -> Vec<CertificateProfile>{
+        Vec::new()
+    }
+
+    ///
+    /// Gets persistent key-value state of module, namespaced by module ID and limited by quota
+    ///
+    /// # Arguments
+    /// * module_id: u64: ID of module requesting state
+    ///
+    /// returns: Option<ModuleState>: state or None if host does not keep state of modules
+    ///
+    #[inline]
+    fn get_module_state(&self, _module_id: u64) -> Option<ModuleState>{
+        None
+    }
+
+    ///
+    /// Gets operator on whose behalf host issues commands. Supervised modules get transport
+    /// service which signs every outgoing message as operator(see transport::operator).
+    ///
+    /// returns: Option<Arc<OperatorIdentity>>: operator or None if host has no operator
+    ///
+    #[inline]
+    fn get_operator(&self) -> Option<Arc<OperatorIdentity>>{
+        None
+    }
+
+    ///
+    /// Gets names of modules loaded on current host, reported in its inventory
+    ///
+    /// returns: Vec<String>: names or empty list if host does not report them
+    ///
+    #[inline]
+    fn get_loaded_modules(&self) -> Vec<String>{
+        Vec::new()
+    }
+
+    ///
+    /// Gets addresses current host accepts connections on, reported in its inventory
+    ///
+    /// returns: Vec<String>: addresses or empty list if host does not listen
+    ///
+    #[inline]
+    fn get_listener_endpoints(&self) -> Vec<String>{
+        Vec::new()
+    }
 }
 
 ///
@@ -88,6 +213,16 @@ pub trait MilkywayModule: Send + Sync{
     /// 
     fn get_commands(&self) -> Vec<String>;
 
+    ///
+    /// Describes CLI commands, namespaces and their arguments. Used by CLI for help,
+    /// completion and detecting collisions between modules.
+    ///
+    /// By default only top-level commands are described.
+    ///
+    fn describe(&self) -> ModuleDescription{
+        ModuleDescription::new(self.get_id(), self.get_commands(), vec![])
+    }
+
     ///
     /// Called when module is loaded
     ///
@@ -114,6 +249,34 @@ pub trait MilkywayModule: Send + Sync{
     ///```
     fn on_cli_command(&mut self, command: Vec<String>, arguments: Vec<String>) -> CLIStatus;
 
+    ///
+    /// Continues command which returned InProgress or NeedsInput. Module keeps state of
+    /// such command itself, host never runs two commands of module at once.
+    ///
+    /// # Arguments
+    /// * input: CLIInput: answer to prompt, Next after progress or Cancelled
+    ///
+    /// returns: CLIStatus: next step of command
+    ///
+    fn on_cli_continue(&mut self, _input: CLIInput) -> CLIStatus{
+        CLIStatus::Done
+    }
+
+    ///
+    /// Completes value of argument of CLI command, e.g. serials of certificates. Modules using
+    /// CommandRouter usually forward this to CommandRouter::complete.
+    ///
+    /// # Arguments
+    /// * command: Vec<String>: path to command as typed by user
+    /// * argument: String: name of argument
+    /// * prefix: String: part of value typed by user
+    ///
+    /// returns: Vec<String>: values starting with prefix
+    ///
+    fn on_cli_complete(&mut self, _command: Vec<String>, _argument: String, _prefix: String) -> Vec<String>{
+        vec![]
+    }
+
     ///
     /// Handles message on milkyway server
     ///
@@ -0,0 +1,881 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use libmilkyway_derive::{Deserializable, Serializable};
+use crate::actor::binder::BinderChannelProvider;
+use crate::cli::describe::ModuleDescription;
+use crate::message::certsync::CertificateSyncMessage;
+use crate::message::common::Message;
+use crate::module::{CLIStatus, HostType, MilkywayModule, ModuleDataBus};
+use crate::module::session::{CLIFailure, CLIInput};
+use crate::pki::certificate::Certificate;
+use crate::pki::impls::certificates::falcon1024::Falcon1024RootCertificate;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::services::certificate::{CertificateAsyncService, CertificateService, CertificateServiceBinder};
+use crate::services::certificate::sync::apply_certificate_sync;
+use crate::services::impls::certificate::AsyncCertificateServiceImpl;
+use crate::services::name::NameService;
+use crate::services::transport::{MessageFilter, TransportService};
+use crate::transport::{TransportListener, TransportSender};
+
+///
+/// Maximal size of a frame exchanged with module runner
+///
+pub const MAX_ISOLATED_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+///
+/// How long to wait for module runner to connect
+///
+pub const RUNNER_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+///
+/// Writes a length-prefixed frame
+///
+/// # Arguments
+/// * writer: &mut W: stream to write to
+/// * data: &Serialized: frame payload
+///
+pub fn write_frame<W: Write>(writer: &mut W, data: &Serialized) -> std::io::Result<()>{
+    writer.write_all(&(data.len() as u64).to_le_bytes())?;
+    writer.write_all(data)?;
+    writer.flush()
+}
+
+///
+/// Reads a length-prefixed frame
+///
+/// # Arguments
+/// * reader: &mut R: stream to read from
+///
+/// returns: std::io::Result<Serialized>: frame payload or error if stream is closed or frame is too big
+///
+pub fn read_frame<R: Read>(reader: &mut R) -> std::io::Result<Serialized>{
+    let mut length = [0u8; 8];
+    reader.read_exact(&mut length)?;
+    let length = u64::from_le_bytes(length) as usize;
+    if length > MAX_ISOLATED_FRAME_SIZE{
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Frame is too big"));
+    }
+    let mut data = vec![0u8; length];
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+///
+/// State passed to isolated module when it is loaded
+///
+#[derive(Serializable, Deserializable, Clone)]
+pub struct IsolatedLoadRequest{
+    pub host_type: HostType,
+    pub host_id: Option<u128>,
+    pub domain: String,
+    /** Root certificate without secret key **/
+    pub root_certificate: Option<Falcon1024RootCertificate>,
+    /** Known certificates without secret keys **/
+    pub certificates: CertificateSyncMessage,
+}
+
+///
+/// Requests sent by host to module runner
+///
+pub enum IsolatedHostRequest{
+    /** Asks for module description, answered with Description **/
+    Describe,
+    /** Calls on_load, answered with Done **/
+    Load(Box<IsolatedLoadRequest>),
+    /** Calls on_cli_command, answered with CliStatus **/
+    CliCommand(Vec<String>, Vec<String>),
+    /** Calls on_cli_continue, answered with CliStatus **/
+    CliContinue(CLIInput),
+    /** Calls receive callback corresponding to host type, answered with Done **/
+    Receive(HostType, Message),
+    /** Delivers message to subscription of module, not answered **/
+    Deliver(u128, Message),
+    /** Stops module runner, not answered **/
+    Shutdown,
+}
+
+///
+/// Responses and calls sent by module runner to host
+///
+pub enum IsolatedRunnerEvent{
+    Description(ModuleDescription),
+    Done,
+    /** Result of CLI command or of its next step **/
+    CliStatus(CLIStatus),
+    /** Module sends a message **/
+    Send(Message),
+    /** Module subscribes to messages, ID is local to runner **/
+    Subscribe(u128, MessageFilter),
+    /** Module unsubscribes from messages **/
+    Unsubscribe(u128),
+    /** Module sends several messages at once **/
+    SendBatch(Vec<Message>),
+}
+
+impl Serializable for IsolatedHostRequest {
+    fn serialize(&self) -> Serialized {
+        let mut result = Serialized::new();
+        match self {
+            IsolatedHostRequest::Describe => {
+                result.extend(0u8.serialize());
+            }
+            IsolatedHostRequest::Load(request) => {
+                result.extend(1u8.serialize());
+                result.extend(request.serialize());
+            }
+            IsolatedHostRequest::CliCommand(command, arguments) => {
+                result.extend(2u8.serialize());
+                result.extend(command.serialize());
+                result.extend(arguments.serialize());
+            }
+            IsolatedHostRequest::Receive(host_type, message) => {
+                result.extend(3u8.serialize());
+                result.extend(host_type.serialize());
+                result.extend(message.serialize());
+            }
+            IsolatedHostRequest::Deliver(subscription, message) => {
+                result.extend(4u8.serialize());
+                result.extend(subscription.serialize());
+                result.extend(message.serialize());
+            }
+            IsolatedHostRequest::Shutdown => {
+                result.extend(5u8.serialize());
+            }
+            IsolatedHostRequest::CliContinue(input) => {
+                result.extend(6u8.serialize());
+                result.extend(input.serialize());
+            }
+        }
+        result
+    }
+}
+
+impl Deserializable for IsolatedHostRequest {
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        if serialized.is_empty(){
+            return Err(SerializationError::LengthError);
+        }
+        let data = serialized[1..].to_vec();
+        match serialized[0] {
+            0 => Ok((IsolatedHostRequest::Describe, 1)),
+            1 => {
+                let (request, offset) = IsolatedLoadRequest::from_serialized(&data)?;
+                Ok((IsolatedHostRequest::Load(Box::new(request)), offset + 1))
+            }
+            2 => {
+                let ((command, arguments), offset) = <(Vec<String>, Vec<String>)>::from_serialized(&data)?;
+                Ok((IsolatedHostRequest::CliCommand(command, arguments), offset + 1))
+            }
+            3 => {
+                let ((host_type, message), offset) = <(HostType, Message)>::from_serialized(&data)?;
+                Ok((IsolatedHostRequest::Receive(host_type, message), offset + 1))
+            }
+            4 => {
+                let ((subscription, message), offset) = <(u128, Message)>::from_serialized(&data)?;
+                Ok((IsolatedHostRequest::Deliver(subscription, message), offset + 1))
+            }
+            5 => Ok((IsolatedHostRequest::Shutdown, 1)),
+            6 => {
+                let (input, offset) = CLIInput::from_serialized(&data)?;
+                Ok((IsolatedHostRequest::CliContinue(input), offset + 1))
+            }
+            _ => Err(SerializationError::InvalidDataError("Unknown isolated host request"))
+        }
+    }
+}
+
+impl Serializable for IsolatedRunnerEvent {
+    fn serialize(&self) -> Serialized {
+        let mut result = Serialized::new();
+        match self {
+            IsolatedRunnerEvent::Description(description) => {
+                result.extend(0u8.serialize());
+                result.extend(description.serialize());
+            }
+            IsolatedRunnerEvent::Done => {
+                result.extend(1u8.serialize());
+            }
+            IsolatedRunnerEvent::CliStatus(status) => {
+                result.extend(2u8.serialize());
+                result.extend(status.serialize());
+            }
+            IsolatedRunnerEvent::Send(message) => {
+                result.extend(3u8.serialize());
+                result.extend(message.serialize());
+            }
+            IsolatedRunnerEvent::Subscribe(subscription, filter) => {
+                result.extend(4u8.serialize());
+                result.extend(subscription.serialize());
+                result.extend(filter.serialize());
+            }
+            IsolatedRunnerEvent::Unsubscribe(subscription) => {
+                result.extend(5u8.serialize());
+                result.extend(subscription.serialize());
+            }
+            IsolatedRunnerEvent::SendBatch(messages) => {
+                result.extend(6u8.serialize());
+                result.extend(messages.serialize());
+            }
+        }
+        result
+    }
+}
+
+impl Deserializable for IsolatedRunnerEvent {
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        if serialized.is_empty(){
+            return Err(SerializationError::LengthError);
+        }
+        let data = serialized[1..].to_vec();
+        match serialized[0] {
+            0 => {
+                let (description, offset) = ModuleDescription::from_serialized(&data)?;
+                Ok((IsolatedRunnerEvent::Description(description), offset + 1))
+            }
+            1 => Ok((IsolatedRunnerEvent::Done, 1)),
+            2 => {
+                let (status, offset) = CLIStatus::from_serialized(&data)?;
+                Ok((IsolatedRunnerEvent::CliStatus(status), offset + 1))
+            }
+            3 => {
+                let (message, offset) = Message::from_serialized(&data)?;
+                Ok((IsolatedRunnerEvent::Send(message), offset + 1))
+            }
+            4 => {
+                let ((subscription, filter), offset) = <(u128, MessageFilter)>::from_serialized(&data)?;
+                Ok((IsolatedRunnerEvent::Subscribe(subscription, filter), offset + 1))
+            }
+            5 => {
+                let (subscription, offset) = u128::from_serialized(&data)?;
+                Ok((IsolatedRunnerEvent::Unsubscribe(subscription), offset + 1))
+            }
+            6 => {
+                let (messages, offset) = Vec::<Message>::from_serialized(&data)?;
+                Ok((IsolatedRunnerEvent::SendBatch(messages), offset + 1))
+            }
+            _ => Err(SerializationError::InvalidDataError("Unknown isolated runner event"))
+        }
+    }
+}
+
+/* Host side */
+
+///
+/// Listener forwarding messages of host transport to subscription of isolated module
+///
+struct ForwardingListener{
+    subscription: u128,
+    writer: Arc<Mutex<UnixStream>>,
+}
+
+impl TransportListener for ForwardingListener{
+    fn on_message(&mut self, message: Message) {
+        let request = IsolatedHostRequest::Deliver(self.subscription, message);
+        if let Err(error) = write_frame(&mut *self.writer.lock().unwrap(), &request.serialize()){
+            log::error!("Can not deliver message to isolated module: {}", error);
+        }
+    }
+}
+
+///
+/// Host state used by thread reading events of module runner
+///
+struct IsolatedHostState{
+    transport: Option<Box<dyn TransportService>>,
+    /** Host subscription IDs by runner subscription IDs **/
+    subscriptions: HashMap<u128, u128>,
+}
+
+fn handle_runner_events(mut reader: UnixStream, writer: Arc<Mutex<UnixStream>>,
+                        state: Arc<Mutex<IsolatedHostState>>, responses: Sender<IsolatedRunnerEvent>){
+    loop {
+        let frame = match read_frame(&mut reader) {
+            Ok(frame) => frame,
+            Err(_) => {
+                log::info!("Isolated module disconnected");
+                return;
+            }
+        };
+        let event = match IsolatedRunnerEvent::from_serialized(&frame) {
+            Ok((event, _)) => event,
+            Err(_) => {
+                log::error!("Malformed event from isolated module, disconnecting");
+                return;
+            }
+        };
+        let mut state = state.lock().unwrap();
+        match event {
+            IsolatedRunnerEvent::Send(message) => match state.transport.as_mut() {
+                Some(transport) => transport.send_message(message),
+                None => log::warn!("Isolated module sends message before being loaded"),
+            },
+            IsolatedRunnerEvent::SendBatch(messages) => match state.transport.as_mut() {
+                Some(transport) => transport.send_batch(messages),
+                None => log::warn!("Isolated module sends messages before being loaded"),
+            },
+            IsolatedRunnerEvent::Subscribe(subscription, filter) => {
+                let listener = Box::new(ForwardingListener{ subscription, writer: writer.clone() });
+                let host_subscription = match state.transport.as_mut() {
+                    Some(transport) => transport.subscribe_to_messages(&filter, listener),
+                    None => {
+                        log::warn!("Isolated module subscribes before being loaded");
+                        continue;
+                    }
+                };
+                state.subscriptions.insert(subscription, host_subscription);
+            }
+            IsolatedRunnerEvent::Unsubscribe(subscription) => {
+                if let Some(host_subscription) = state.subscriptions.remove(&subscription){
+                    if let Some(transport) = state.transport.as_mut(){
+                        transport.unsubscribe(host_subscription);
+                    }
+                }
+            }
+            response => {
+                drop(state);
+                if responses.send(response).is_err(){
+                    return;
+                }
+            }
+        }
+    }
+}
+
+///
+/// A module running in a separate module runner process. Secret keys and memory of host
+/// are not available to module: it receives only public certificates and talks to host
+/// through a unix socket.
+///
+pub struct IsolatedModule{
+    description: ModuleDescription,
+    writer: Arc<Mutex<UnixStream>>,
+    responses: Mutex<Receiver<IsolatedRunnerEvent>>,
+    state: Arc<Mutex<IsolatedHostState>>,
+    reader: Option<JoinHandle<()>>,
+    child: Option<Child>,
+    socket_path: Option<PathBuf>,
+}
+
+impl IsolatedModule {
+    ///
+    /// Starts module runner process for module and connects to it
+    ///
+    /// # Arguments
+    /// * runner: &Path: path to module runner binary
+    /// * module: &Path: path to module library
+    /// * socket_path: &Path: path of unix socket to create for communication
+    ///
+    /// returns: std::io::Result<IsolatedModule>: connected module or error if runner can not be started
+    ///
+    pub fn spawn(runner: &Path, module: &Path, socket_path: &Path) -> std::io::Result<IsolatedModule>{
+        if socket_path.exists(){
+            std::fs::remove_file(socket_path)?;
+        }
+        let listener = UnixListener::bind(socket_path)?;
+        listener.set_nonblocking(true)?;
+        let mut child = Command::new(runner)
+            .arg("--socket").arg(socket_path)
+            .arg("--module").arg(module)
+            .spawn()?;
+        let started = Instant::now();
+        let stream = loop {
+            match listener.accept() {
+                Ok((stream, _)) => break stream,
+                Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => {
+                    if child.try_wait()?.is_some() || started.elapsed() > RUNNER_CONNECT_TIMEOUT{
+                        let _ = child.kill();
+                        let _ = std::fs::remove_file(socket_path);
+                        return Err(std::io::Error::new(std::io::ErrorKind::TimedOut,
+                                                       "Module runner did not connect"));
+                    }
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                Err(error) => return Err(error),
+            }
+        };
+        stream.set_nonblocking(false)?;
+        let mut module = Self::connect(stream)?;
+        module.child = Some(child);
+        module.socket_path = Some(socket_path.to_path_buf());
+        Ok(module)
+    }
+
+    ///
+    /// Connects to module runner through already established stream and gets module description
+    ///
+    /// # Arguments
+    /// * stream: UnixStream: stream connected to module runner
+    ///
+    pub fn connect(stream: UnixStream) -> std::io::Result<IsolatedModule>{
+        let writer = Arc::new(Mutex::new(stream.try_clone()?));
+        let state = Arc::new(Mutex::new(IsolatedHostState{
+            transport: None,
+            subscriptions: HashMap::new(),
+        }));
+        let (responses_tx, responses_rx) = channel();
+        let reader = {
+            let writer = writer.clone();
+            let state = state.clone();
+            std::thread::spawn(move || handle_runner_events(stream, writer, state, responses_tx))
+        };
+        let mut module = IsolatedModule{
+            description: ModuleDescription::new(0, vec![], vec![]),
+            writer,
+            responses: Mutex::new(responses_rx),
+            state,
+            reader: Some(reader),
+            child: None,
+            socket_path: None,
+        };
+        match module.request(IsolatedHostRequest::Describe) {
+            Some(IsolatedRunnerEvent::Description(description)) => module.description = description,
+            _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                                                "Module runner did not describe module")),
+        }
+        Ok(module)
+    }
+
+    ///
+    /// Sends request to runner and waits for response
+    ///
+    fn request(&self, request: IsolatedHostRequest) -> Option<IsolatedRunnerEvent>{
+        let responses = self.responses.lock().unwrap();
+        if let Err(error) = write_frame(&mut *self.writer.lock().unwrap(), &request.serialize()){
+            log::error!("Can not send request to isolated module {}: {}", self.description.module_id, error);
+            return None;
+        }
+        match responses.recv() {
+            Ok(response) => Some(response),
+            Err(_) => {
+                log::error!("Isolated module {} has terminated", self.description.module_id);
+                None
+            }
+        }
+    }
+}
+
+impl MilkywayModule for IsolatedModule{
+    #[inline]
+    fn get_id(&self) -> u64 {
+        self.description.module_id
+    }
+
+    #[inline]
+    fn get_commands(&self) -> Vec<String> {
+        self.description.commands.clone()
+    }
+
+    #[inline]
+    fn describe(&self) -> ModuleDescription {
+        self.description.clone()
+    }
+
+    fn on_load(&mut self, data_bus: Box<dyn ModuleDataBus>) {
+        let mut certificates = data_bus.get_certificate_service();
+        let request = IsolatedLoadRequest{
+            host_type: data_bus.get_host_type(),
+            host_id: data_bus.get_host_id(),
+            domain: data_bus.get_name_service().get_domain(),
+            root_certificate: certificates.get_root_certificate().map(|root| root.clone_without_sk()),
+            certificates: CertificateSyncMessage::snapshot(&mut *certificates),
+        };
+        self.state.lock().unwrap().transport = Some(data_bus.get_transport_service());
+        if !matches!(self.request(IsolatedHostRequest::Load(Box::new(request))), Some(IsolatedRunnerEvent::Done)){
+            log::error!("Isolated module {} failed to load", self.description.module_id);
+        }
+    }
+
+    fn on_cli_command(&mut self, command: Vec<String>, arguments: Vec<String>) -> CLIStatus {
+        match self.request(IsolatedHostRequest::CliCommand(command, arguments)) {
+            Some(IsolatedRunnerEvent::CliStatus(status)) => status,
+            _ => CLIStatus::Done,
+        }
+    }
+
+    fn on_cli_continue(&mut self, input: CLIInput) -> CLIStatus {
+        match self.request(IsolatedHostRequest::CliContinue(input)) {
+            Some(IsolatedRunnerEvent::CliStatus(status)) => status,
+            _ => CLIStatus::Failed(CLIFailure::new("isolated module has terminated")),
+        }
+    }
+
+    fn on_server_receive(&self, packet: &Message) {
+        self.request(IsolatedHostRequest::Receive(HostType::Broker, packet.clone()));
+    }
+
+    fn on_client_receive(&self, packet: &Message) {
+        self.request(IsolatedHostRequest::Receive(HostType::Peer, packet.clone()));
+    }
+
+    fn on_cli_receive(&self, packet: &Message) {
+        self.request(IsolatedHostRequest::Receive(HostType::CLI, packet.clone()));
+    }
+}
+
+impl Drop for IsolatedModule {
+    fn drop(&mut self) {
+        let _ = write_frame(&mut *self.writer.lock().unwrap(), &IsolatedHostRequest::Shutdown.serialize());
+        if let Some(mut child) = self.child.take(){
+            let started = Instant::now();
+            while let Ok(None) = child.try_wait(){
+                if started.elapsed() > RUNNER_CONNECT_TIMEOUT{
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+        let _ = self.writer.lock().unwrap().shutdown(std::net::Shutdown::Both);
+        if let Some(reader) = self.reader.take(){
+            let _ = reader.join();
+        }
+        // Listeners forwarding to runner which is gone must not stay subscribed on host
+        let mut state = self.state.lock().unwrap();
+        let subscriptions: Vec<u128> = state.subscriptions.drain().map(|(_, subscription)| subscription).collect();
+        if let Some(transport) = state.transport.as_mut(){
+            for subscription in subscriptions{
+                transport.unsubscribe(subscription);
+            }
+        }
+        drop(state);
+        if let Some(socket_path) = self.socket_path.take(){
+            let _ = std::fs::remove_file(socket_path);
+        }
+    }
+}
+
+/* Runner side */
+
+type RunnerListeners = Arc<Mutex<HashMap<u128, Box<dyn TransportListener>>>>;
+
+fn send_event(writer: &Arc<Mutex<UnixStream>>, event: IsolatedRunnerEvent){
+    if let Err(error) = write_frame(&mut *writer.lock().unwrap(), &event.serialize()){
+        log::error!("Can not send event to host: {}", error);
+    }
+}
+
+///
+/// Sender forwarding messages of isolated module to host
+///
+struct IsolatedSender{
+    writer: Arc<Mutex<UnixStream>>,
+}
+
+impl TransportSender for IsolatedSender{
+    #[inline]
+    fn send_message(&mut self, mut message: Message) {
+        message.ensure_id();
+        send_event(&self.writer, IsolatedRunnerEvent::Send(message));
+    }
+
+    fn send_batch(&mut self, mut messages: Vec<Message>) {
+        for message in messages.iter_mut(){
+            message.ensure_id();
+        }
+        send_event(&self.writer, IsolatedRunnerEvent::SendBatch(messages));
+    }
+}
+
+///
+/// Transport service of isolated module, subscriptions are registered on host
+///
+#[derive(Clone)]
+struct IsolatedTransportService{
+    writer: Arc<Mutex<UnixStream>>,
+    listeners: RunnerListeners,
+    last_subscription_id: Arc<Mutex<u128>>,
+}
+
+impl TransportService for IsolatedTransportService{
+    fn subscribe_to_messages(&mut self, filter: &MessageFilter, listener: Box<dyn TransportListener>) -> u128 {
+        let mut last_id = self.last_subscription_id.lock().unwrap();
+        *last_id += 1;
+        self.listeners.lock().unwrap().insert(*last_id, listener);
+        send_event(&self.writer, IsolatedRunnerEvent::Subscribe(*last_id, filter.clone()));
+        *last_id
+    }
+
+    fn unsubscribe(&mut self, filter_id: u128) {
+        self.listeners.lock().unwrap().remove(&filter_id);
+        send_event(&self.writer, IsolatedRunnerEvent::Unsubscribe(filter_id));
+    }
+
+    fn get_sender(&mut self) -> Box<dyn TransportSender> {
+        Box::new(IsolatedSender{
+            writer: self.writer.clone(),
+        })
+    }
+}
+
+///
+/// Name service of isolated module, knows only domain and numeric IDs
+///
+#[derive(Clone)]
+struct IsolatedNameService{
+    domain: String,
+}
+
+impl NameService for IsolatedNameService{
+    #[inline]
+    fn get_name_by_id(&self, id: u128) -> String {
+        id.to_string()
+    }
+
+    #[inline]
+    fn get_id_by_name(&self, name: &str) -> Option<u128> {
+        name.parse().ok()
+    }
+
+    #[inline]
+    fn get_domain(&self) -> String {
+        self.domain.clone()
+    }
+}
+
+struct IsolatedDataBus{
+    host_type: HostType,
+    host_id: Option<u128>,
+    transport: IsolatedTransportService,
+    names: IsolatedNameService,
+    certificate_service: Arc<Mutex<CertificateAsyncService>>,
+}
+
+impl ModuleDataBus for IsolatedDataBus{
+    fn get_transport_service(&self) -> Box<dyn TransportService> {
+        Box::new(self.transport.clone())
+    }
+
+    fn get_name_service(&self) -> Box<dyn NameService> {
+        Box::new(self.names.clone())
+    }
+
+    fn get_certificate_service(&self) -> Box<CertificateServiceBinder> {
+        self.certificate_service.lock().unwrap().bind()
+    }
+
+    #[inline]
+    fn get_host_type(&self) -> HostType {
+        self.host_type
+    }
+
+    #[inline]
+    fn get_host_id(&self) -> Option<u128> {
+        self.host_id
+    }
+}
+
+fn create_isolated_data_bus(request: IsolatedLoadRequest, transport: IsolatedTransportService) -> IsolatedDataBus{
+    // Module gets its own copy of public certificates, changes are never written back to host
+    static LAST_STORAGE_ID: AtomicUsize = AtomicUsize::new(0);
+    let storage = std::env::temp_dir().join(format!("milkyway-isolated-{}-{}.dat", std::process::id(),
+                                                    LAST_STORAGE_ID.fetch_add(1, Ordering::Relaxed)));
+    let mut service = AsyncCertificateServiceImpl::new(&storage.to_string_lossy());
+    if let Some(root) = request.root_certificate{
+        service.set_root_certificate(root);
+    }
+    apply_certificate_sync(&mut service, &request.certificates);
+    IsolatedDataBus{
+        host_type: request.host_type,
+        host_id: request.host_id,
+        transport,
+        names: IsolatedNameService{ domain: request.domain },
+        certificate_service: Arc::new(Mutex::new(CertificateAsyncService::run(Box::new(service)))),
+    }
+}
+
+fn deliver_to_listener(listeners: &RunnerListeners, subscription: u128, message: Message){
+    // Listener is taken out while it runs, so it may subscribe or unsubscribe itself
+    let listener = listeners.lock().unwrap().remove(&subscription);
+    if let Some(mut listener) = listener{
+        listener.on_message(message);
+        listeners.lock().unwrap().entry(subscription).or_insert(listener);
+    }
+}
+
+///
+/// Serves requests of host for a module until host asks to shut down or disconnects.
+/// Used by module runner binary.
+///
+/// # Arguments
+/// * module: &mut dyn MilkywayModule: module to run
+/// * stream: UnixStream: stream connected to host
+///
+/// # Warning
+/// init_tokio() must be called in current thread before
+///
+pub fn run_isolated_module(module: &mut dyn MilkywayModule, stream: UnixStream) -> std::io::Result<()>{
+    let mut reader = stream.try_clone()?;
+    let writer = Arc::new(Mutex::new(stream));
+    let transport = IsolatedTransportService{
+        writer: writer.clone(),
+        listeners: Arc::new(Mutex::new(HashMap::new())),
+        last_subscription_id: Arc::new(Mutex::new(0)),
+    };
+    loop {
+        let frame = match read_frame(&mut reader) {
+            Ok(frame) => frame,
+            Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        let request = match IsolatedHostRequest::from_serialized(&frame) {
+            Ok((request, _)) => request,
+            Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Malformed request")),
+        };
+        match request {
+            IsolatedHostRequest::Describe => {
+                send_event(&writer, IsolatedRunnerEvent::Description(module.describe()));
+            }
+            IsolatedHostRequest::Load(request) => {
+                module.on_load(Box::new(create_isolated_data_bus(*request, transport.clone())));
+                send_event(&writer, IsolatedRunnerEvent::Done);
+            }
+            IsolatedHostRequest::CliCommand(command, arguments) => {
+                let status = module.on_cli_command(command, arguments);
+                send_event(&writer, IsolatedRunnerEvent::CliStatus(status));
+            }
+            IsolatedHostRequest::CliContinue(input) => {
+                send_event(&writer, IsolatedRunnerEvent::CliStatus(module.on_cli_continue(input)));
+            }
+            IsolatedHostRequest::Receive(host_type, message) => {
+                match host_type {
+                    HostType::CLI => module.on_cli_receive(&message),
+                    HostType::Broker => module.on_server_receive(&message),
+                    HostType::Peer => module.on_client_receive(&message),
+                }
+                send_event(&writer, IsolatedRunnerEvent::Done);
+            }
+            IsolatedHostRequest::Deliver(subscription, message) => {
+                deliver_to_listener(&transport.listeners, subscription, message);
+            }
+            IsolatedHostRequest::Shutdown => return Ok(()),
+        }
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::describe::CommandDescription;
+    use crate::cli::describe::NamespaceDescription;
+    use crate::testing::certificate::{MockCertificateService, TEST_SIGNING_CERTIFICATE_SERIAL};
+    use crate::testing::module::TestDataBus;
+    use crate::tokio::init_tokio;
+
+    const ISOLATED_MODULE_ID: u64 = 77;
+
+    struct Echo{
+        sender: Box<dyn TransportSender>,
+    }
+
+    impl TransportListener for Echo{
+        fn on_message(&mut self, message: Message) {
+            let mut reply = message.clone();
+            reply.destination = message.source;
+            reply.source = message.destination;
+            self.sender.send_message(reply);
+        }
+    }
+
+    struct IsolatedEchoModule{
+        has_secret_key: Option<bool>,
+    }
+
+    impl MilkywayModule for IsolatedEchoModule{
+        fn get_id(&self) -> u64 {
+            ISOLATED_MODULE_ID
+        }
+
+        fn get_commands(&self) -> Vec<String> {
+            vec!["echo".to_string()]
+        }
+
+        fn describe(&self) -> ModuleDescription {
+            ModuleDescription::new(self.get_id(), self.get_commands(), vec![NamespaceDescription{
+                path: vec!["echo".to_string()],
+                commands: vec![CommandDescription::new("keys", "Reports secret keys", vec![])],
+            }])
+        }
+
+        fn on_load(&mut self, data_bus: Box<dyn ModuleDataBus>) {
+            let signing = data_bus.get_certificate_service().get_signing_certificate(TEST_SIGNING_CERTIFICATE_SERIAL);
+            self.has_secret_key = signing.map(|certificate| certificate.get_secret_key().is_some());
+            let mut transport = data_bus.get_transport_service();
+            let echo = Echo{ sender: transport.get_sender() };
+            transport.subscribe_to_messages(MessageFilter::new().filter_module(ISOLATED_MODULE_ID), Box::new(echo));
+        }
+
+        fn on_cli_command(&mut self, command: Vec<String>, _arguments: Vec<String>) -> CLIStatus {
+            // Reports whether certificate was visible and whether it carried secret key
+            match self.has_secret_key {
+                Some(false) => CLIStatus::NamespaceChange(command),
+                _ => CLIStatus::Done,
+            }
+        }
+
+        fn on_server_receive(&self, _packet: &Message) {}
+
+        fn on_client_receive(&self, _packet: &Message) {}
+
+        fn on_cli_receive(&self, _packet: &Message) {}
+    }
+
+    fn start_isolated_module() -> (IsolatedModule, JoinHandle<()>){
+        let (host_stream, runner_stream) = UnixStream::pair().unwrap();
+        let runner = std::thread::spawn(move || {
+            init_tokio();
+            let mut module = IsolatedEchoModule{ has_secret_key: None };
+            run_isolated_module(&mut module, runner_stream).unwrap();
+        });
+        (IsolatedModule::connect(host_stream).unwrap(), runner)
+    }
+
+    #[test]
+    fn test_isolated_module_describes_itself() {
+        let (module, runner) = start_isolated_module();
+        assert_eq!(module.get_id(), ISOLATED_MODULE_ID);
+        assert_eq!(module.get_commands(), vec!["echo".to_string()]);
+        assert_eq!(module.describe().namespaces[0].commands[0].name, "keys");
+        drop(module);
+        runner.join().unwrap();
+    }
+
+    #[test]
+    fn test_isolated_module_gets_public_certificates_and_transport() {
+        init_tokio();
+        let (mut module, runner) = start_isolated_module();
+        let data_bus = TestDataBus::new(HostType::Peer, 1, MockCertificateService::with_test_certificates());
+        let loopback = data_bus.get_loopback();
+        module.on_load(Box::new(data_bus));
+        // Signing certificate is visible, but without secret key
+        let status = module.on_cli_command(vec!["echo".to_string(), "keys".to_string()], vec![]);
+        assert!(matches!(status, CLIStatus::NamespaceChange(_)));
+
+        let mut peer = loopback.connect(2);
+        let mut message = Message::new();
+        message.set_destination(1);
+        message.source = 2;
+        message.module_id = ISOLATED_MODULE_ID;
+        peer.send_message(message);
+        let started = Instant::now();
+        while loopback.sent_messages().is_empty() && started.elapsed() < Duration::from_secs(5){
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let sent = loopback.sent_messages();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].destination, 2);
+        drop(module);
+        runner.join().unwrap();
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use libmilkyway_derive::{Deserializable, Serializable};
+use crate::pki::certificate::{Certificate, FLAG_SIGN_CODE};
+use crate::pki::hash::HashType;
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+use crate::pki::signature::Signature;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::services::certificate::CertificateService;
+
+///
+/// Extension of detached module signature file, e.g. `libcertman.so.sig`
+///
+pub const MODULE_SIGNATURE_EXTENSION: &str = "sig";
+
+///
+/// Where module runs
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModuleIsolation{
+    /** Module is loaded into host process and has access to all its memory **/
+    InProcess,
+    /** Module runs in a separate module runner process **/
+    Isolated,
+}
+
+///
+/// Detached signature of a module library
+///
+#[derive(Clone, Serializable, Deserializable)]
+pub struct ModuleSignature{
+    /** Serial of signing certificate which signed module **/
+    pub signer_serial: u128,
+    pub signature: Signature,
+}
+
+impl ModuleSignature {
+    ///
+    /// Signs contents of module library
+    ///
+    /// # Arguments
+    /// * contents: &Serialized: contents of module library
+    /// * certificate: &Falcon1024Certificate: signing certificate with secret key
+    ///
+    /// returns: Option<ModuleSignature>: signature or None if certificate has no secret key
+    ///
+    pub fn sign(contents: &Serialized, certificate: &Falcon1024Certificate) -> Option<ModuleSignature>{
+        let signature = certificate.sign_data(contents, HashType::None).ok()?;
+        Some(ModuleSignature{
+            signer_serial: certificate.get_serial(),
+            signature,
+        })
+    }
+
+    ///
+    /// Gets path of detached signature of module
+    ///
+    pub fn get_path(module_path: &Path) -> PathBuf{
+        let mut path = module_path.as_os_str().to_owned();
+        path.push(".");
+        path.push(MODULE_SIGNATURE_EXTENSION);
+        PathBuf::from(path)
+    }
+}
+
+///
+/// Finds who signed contents of module library. Signer must be a trusted certificate allowed
+/// to sign code. Contents are passed by caller, so the very bytes which were verified can be
+/// loaded, not a file which might have been replaced since.
+///
+/// # Arguments
+/// * service: &mut S: service with known certificates
+/// * module_path: &Path: path to module library, signature is read from `<module_path>.sig`
+/// * contents: &Serialized: contents of module library
+///
+/// returns: Option<u128>: serial of signer or None if module is not signed or signature is invalid
+///
+pub fn find_module_signer<S: CertificateService + ?Sized>(service: &mut S, module_path: &Path,
+                                                          contents: &Serialized) -> Option<u128>{
+    let signature = std::fs::read(ModuleSignature::get_path(module_path)).ok()?;
+    let (signature, _) = ModuleSignature::from_serialized(&signature).ok()?;
+    let certificate = service.get_signing_certificate(signature.signer_serial)?;
+    if !certificate.check_flag(FLAG_SIGN_CODE) || !service.verify_signing_certificate(&certificate){
+        return None;
+    }
+    if !certificate.verify_signature(contents, &signature.signature){
+        return None;
+    }
+    Some(signature.signer_serial)
+}
+
+///
+/// Chooses whether module runs in-process or isolated
+///
+#[derive(Clone, Debug)]
+pub struct IsolationPolicy{
+    /** Used for modules which are neither overridden nor signed by trusted signer **/
+    pub default: ModuleIsolation,
+    /** Isolation by module file name, e.g. `libcertman.so` **/
+    pub overrides: HashMap<String, ModuleIsolation>,
+    /** Serials of certificates whose modules may run in-process **/
+    pub trusted_signers: Vec<u128>,
+}
+
+impl Default for IsolationPolicy {
+    fn default() -> Self {
+        IsolationPolicy::new(ModuleIsolation::InProcess)
+    }
+}
+
+impl IsolationPolicy {
+    ///
+    /// Creates policy applying same isolation to all modules
+    ///
+    pub fn new(default: ModuleIsolation) -> IsolationPolicy{
+        IsolationPolicy{
+            default,
+            overrides: HashMap::new(),
+            trusted_signers: Vec::new(),
+        }
+    }
+
+    ///
+    /// Sets isolation of a module regardless of its signature
+    ///
+    pub fn set_override(&mut self, module_name: &str, isolation: ModuleIsolation) -> &mut IsolationPolicy{
+        self.overrides.insert(module_name.to_string(), isolation);
+        self
+    }
+
+    ///
+    /// Allows modules signed by certificate to run in-process
+    ///
+    pub fn trust_signer(&mut self, serial: u128) -> &mut IsolationPolicy{
+        if !self.trusted_signers.contains(&serial){
+            self.trusted_signers.push(serial);
+        }
+        self
+    }
+
+    ///
+    /// Chooses isolation of module
+    ///
+    /// # Arguments
+    /// * module_name: &str: file name of module library
+    /// * signer: Option<u128>: verified signer of module, see find_module_signer
+    ///
+    pub fn choose(&self, module_name: &str, signer: Option<u128>) -> ModuleIsolation{
+        if let Some(isolation) = self.overrides.get(module_name){
+            return *isolation;
+        }
+        if signer.is_some_and(|serial| self.trusted_signers.contains(&serial)){
+            return ModuleIsolation::InProcess;
+        }
+        self.default
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pki::certificate::FLAG_SIGN_MESSAGES;
+    use crate::testing::certificate::{test_certificates, MockCertificateService, TEST_SIGNING_CERTIFICATE_SERIAL};
+
+    #[test]
+    fn test_policy_choice() {
+        let mut policy = IsolationPolicy::new(ModuleIsolation::Isolated);
+        policy.set_override("libtrusted.so", ModuleIsolation::InProcess)
+            .set_override("libsandboxed.so", ModuleIsolation::Isolated)
+            .trust_signer(TEST_SIGNING_CERTIFICATE_SERIAL);
+        assert_eq!(policy.choose("libtrusted.so", None), ModuleIsolation::InProcess);
+        assert_eq!(policy.choose("libsandboxed.so", Some(TEST_SIGNING_CERTIFICATE_SERIAL)), ModuleIsolation::Isolated);
+        assert_eq!(policy.choose("libother.so", Some(TEST_SIGNING_CERTIFICATE_SERIAL)), ModuleIsolation::InProcess);
+        assert_eq!(policy.choose("libother.so", Some(100)), ModuleIsolation::Isolated);
+        assert_eq!(policy.choose("libother.so", None), ModuleIsolation::Isolated);
+    }
+
+    #[test]
+    fn test_module_signer() {
+        let module_path = std::env::temp_dir().join(format!("milkyway-module-{}.so", rand::random::<u64>()));
+        let module_path = module_path.as_path();
+        let contents = vec![1u8, 2, 3, 4];
+        let mut signing = test_certificates().signing;
+        let signature = ModuleSignature::sign(&contents, &signing).unwrap();
+        std::fs::write(ModuleSignature::get_path(module_path), signature.serialize()).unwrap();
+        let mut service = MockCertificateService::with_test_certificates();
+        // Certificate allowed to sign messages only may not sign code
+        assert_eq!(find_module_signer(&mut service, module_path, &contents), None);
+        signing.flags = FLAG_SIGN_MESSAGES | FLAG_SIGN_CODE;
+        service.add_signing_certificate(signing);
+        assert_eq!(find_module_signer(&mut service, module_path, &contents), Some(TEST_SIGNING_CERTIFICATE_SERIAL));
+
+        service.set_verification_result(false);
+        assert_eq!(find_module_signer(&mut service, module_path, &contents), None);
+
+        service.set_verification_result(true);
+        assert_eq!(find_module_signer(&mut service, module_path, &vec![1u8, 2, 3, 5]), None);
+        std::fs::remove_file(ModuleSignature::get_path(module_path)).unwrap();
+        assert_eq!(find_module_signer(&mut service, module_path, &contents), None);
+    }
+}
@@ -2,7 +2,15 @@
 /* WARNING: Unsafe code ahead */
 #[allow(unsafe_code)]
 use libloading::{Library, Symbol};
+use std::fs::DirBuilder;
+#[cfg(unix)]
+use std::os::unix::fs::DirBuilderExt;
+use std::path::Path;
 use crate::module::MilkywayModule;
+use crate::module::isolated::IsolatedModule;
+use crate::module::isolation::{find_module_signer, IsolationPolicy, ModuleIsolation};
+use crate::module::supervisor::ModuleConstructor;
+use crate::services::certificate::CertificateService;
 
 pub struct DynamicModule {
     pub instance: Box<dyn MilkywayModule>,
@@ -26,4 +34,94 @@ impl DynamicModule {
             _library: library,
         })
     }
+
+    ///
+    /// Splits module into its instance and constructor creating new instances
+    /// from the same library. Library is unloaded once constructor is dropped.
+    ///
+    pub fn into_parts(self) -> (Box<dyn MilkywayModule>, ModuleConstructor) {
+        type Constructor = unsafe fn() -> *mut dyn MilkywayModule;
+        let library = self._library;
+        let constructor: ModuleConstructor = Box::new(move || {
+            unsafe {
+                let create: Symbol<Constructor> = library.get(b"create").ok()?;
+                Some(Box::from_raw(create()))
+            }
+        });
+        (self.instance, constructor)
+    }
+}
+
+///
+/// A module loaded either in-process or in a module runner
+///
+pub enum LoadedModule {
+    InProcess(DynamicModule),
+    Isolated(IsolatedModule),
+}
+
+impl LoadedModule {
+    ///
+    /// Gets module regardless of where it runs
+    ///
+    pub fn get_module(&mut self) -> &mut dyn MilkywayModule {
+        match self {
+            LoadedModule::InProcess(module) => module.instance.as_mut(),
+            LoadedModule::Isolated(module) => module,
+        }
+    }
+
+    ///
+    /// Gets where module runs
+    ///
+    pub fn get_isolation(&self) -> ModuleIsolation {
+        match self {
+            LoadedModule::InProcess(_) => ModuleIsolation::InProcess,
+            LoadedModule::Isolated(_) => ModuleIsolation::Isolated,
+        }
+    }
+}
+
+///
+/// Loads module in-process or starts it in module runner according to isolation policy.
+/// In-process modules are loaded from a private copy of the verified contents, so library
+/// replaced after its signature was checked is never loaded.
+///
+/// # Arguments
+/// * path: &Path: path to module library
+/// * policy: &IsolationPolicy: policy choosing isolation of module
+/// * certificates: &mut S: service used to verify module signature
+/// * runner: &Path: path to module runner binary
+/// * socket_dir: &Path: directory for unix sockets of module runners
+///
+/// # Safety
+/// In-process modules are loaded with DynamicModule::load, so library must export a valid
+/// `create` constructor built against the same libmilkyway
+///
+pub unsafe fn load_module<S: CertificateService + ?Sized>(path: &Path, policy: &IsolationPolicy,
+                                                          certificates: &mut S, runner: &Path,
+                                                          socket_dir: &Path) -> Result<LoadedModule, Box<dyn std::error::Error>> {
+    let module_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let contents = std::fs::read(path)?;
+    let signer = find_module_signer(certificates, path, &contents);
+    match policy.choose(&module_name, signer) {
+        ModuleIsolation::InProcess => {
+            let directory = std::env::temp_dir().join(format!("milkyway-module-{}", rand::random::<u64>()));
+            let mut builder = DirBuilder::new();
+            #[cfg(unix)]
+            builder.mode(0o700);
+            builder.create(&directory)?;
+            let copy = directory.join(&module_name);
+            let result = std::fs::write(&copy, &contents).map_err(|error| error.into())
+                .and_then(|_| DynamicModule::load(&copy.to_string_lossy()));
+            // Loaded library stays mapped once its file is removed
+            let _ = std::fs::remove_file(&copy);
+            let _ = std::fs::remove_dir(&directory);
+            Ok(LoadedModule::InProcess(result?))
+        }
+        ModuleIsolation::Isolated => {
+            let socket = socket_dir.join(format!("{}.sock", module_name));
+            Ok(LoadedModule::Isolated(IsolatedModule::spawn(runner, path, &socket)?))
+        }
+    }
 }
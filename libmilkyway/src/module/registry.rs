@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use crate::module::loader::DynamicModule;
+use crate::module::supervisor::SupervisedModule;
+use crate::module::MilkywayModule;
+
+///
+/// Module compiled into host instead of being loaded from library. Module crates export it
+/// for hosts enabling them with cargo feature, e.g. `certman::BUILTIN_MODULE`.
+///
+#[derive(Clone, Copy)]
+pub struct BuiltinModule{
+    /** Name of module shown to user **/
+    pub name: &'static str,
+    /** Creates instance of module, called again when module is restarted **/
+    pub constructor: fn() -> Box<dyn MilkywayModule>,
+}
+
+///
+/// Where registered module comes from
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModuleOrigin{
+    Builtin,
+    Dynamic,
+}
+
+impl Display for ModuleOrigin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModuleOrigin::Builtin => write!(f, "built-in"),
+            ModuleOrigin::Dynamic => write!(f, "dynamic"),
+        }
+    }
+}
+
+///
+/// Errors of registering module
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum ModuleRegistryError{
+    /** Another module with the same ID is registered already **/
+    Duplicate{
+        name: String,
+        id: u64,
+        registered: String,
+        origin: ModuleOrigin,
+    },
+}
+
+impl Display for ModuleRegistryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModuleRegistryError::Duplicate{ name, id, registered, origin } =>
+                write!(f, "module {} has ID {} of {} module {}", name, id, origin, registered),
+        }
+    }
+}
+
+///
+/// Modules of host: built-in ones are registered first, so a library of the same module in
+/// modules directory is refused instead of shadowing them. Both kinds are supervised and go
+/// through the same MilkywayModule lifecycle.
+///
+#[derive(Default)]
+pub struct ModuleRegistry{
+    modules: Vec<(ModuleOrigin, SupervisedModule)>,
+    /** Names and origins of registered modules by ID **/
+    ids: HashMap<u64, (String, ModuleOrigin)>,
+}
+
+impl ModuleRegistry {
+    pub fn new() -> ModuleRegistry{
+        ModuleRegistry::default()
+    }
+
+    // Reserves ID of module for it or reports module holding it
+    fn reserve(&mut self, name: &str, id: u64, origin: ModuleOrigin) -> Result<(), ModuleRegistryError>{
+        if let Some((registered, registered_origin)) = self.ids.get(&id){
+            return Err(ModuleRegistryError::Duplicate{
+                name: name.to_string(),
+                id,
+                registered: registered.clone(),
+                origin: *registered_origin,
+            });
+        }
+        self.ids.insert(id, (name.to_string(), origin));
+        Ok(())
+    }
+
+    ///
+    /// Registers module compiled into host
+    ///
+    /// # Arguments
+    /// * module: BuiltinModule: module exported by its crate
+    ///
+    pub fn register_builtin(&mut self, module: BuiltinModule) -> Result<&mut Self, ModuleRegistryError>{
+        let instance = (module.constructor)();
+        self.reserve(module.name, instance.get_id(), ModuleOrigin::Builtin)?;
+        let constructor = module.constructor;
+        let supervised = SupervisedModule::new(module.name, instance, Box::new(move || Some(constructor())));
+        self.modules.push((ModuleOrigin::Builtin, supervised));
+        Ok(self)
+    }
+
+    ///
+    /// Registers module loaded from library, it is unloaded if module is refused
+    ///
+    /// # Arguments
+    /// * name: &str: name of module shown to user, usually file name of library
+    /// * module: DynamicModule: loaded module
+    ///
+    pub fn register_dynamic(&mut self, name: &str, module: DynamicModule) -> Result<&mut Self, ModuleRegistryError>{
+        self.reserve(name, module.instance.get_id(), ModuleOrigin::Dynamic)?;
+        self.modules.push((ModuleOrigin::Dynamic, SupervisedModule::from_dynamic(name, module)));
+        Ok(self)
+    }
+
+    ///
+    /// Gets origin of module with given ID, None if it is not registered
+    ///
+    pub fn get_origin(&self, module_id: u64) -> Option<ModuleOrigin>{
+        self.ids.get(&module_id).map(|(_, origin)| *origin)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize{
+        self.modules.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool{
+        self.modules.is_empty()
+    }
+
+    ///
+    /// Takes registered modules, not loaded yet, in order of registration
+    ///
+    pub fn into_modules(self) -> Vec<SupervisedModule>{
+        self.modules.into_iter().map(|(_, module)| module).collect()
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::common::Message;
+    use crate::module::{CLIStatus, ModuleDataBus};
+
+    struct TestModule;
+
+    impl MilkywayModule for TestModule {
+        fn get_id(&self) -> u64 {
+            42
+        }
+
+        fn get_commands(&self) -> Vec<String> {
+            vec!["test".to_string()]
+        }
+
+        fn on_load(&mut self, _data_bus: Box<dyn ModuleDataBus>) {}
+
+        fn on_cli_command(&mut self, _command: Vec<String>, _arguments: Vec<String>) -> CLIStatus {
+            CLIStatus::Done
+        }
+
+        fn on_server_receive(&self, _packet: &Message) {}
+
+        fn on_client_receive(&self, _packet: &Message) {}
+
+        fn on_cli_receive(&self, _packet: &Message) {}
+    }
+
+    #[test]
+    fn test_module_registry() {
+        const BUILTIN: BuiltinModule = BuiltinModule{
+            name: "test",
+            constructor: || Box::new(TestModule),
+        };
+        let mut registry = ModuleRegistry::new();
+        assert!(registry.register_builtin(BUILTIN).is_ok());
+        assert_eq!(registry.get_origin(42), Some(ModuleOrigin::Builtin));
+        let error = registry.register_builtin(BuiltinModule{ name: "copy", ..BUILTIN }).err().unwrap();
+        assert_eq!(error.to_string(), "module copy has ID 42 of built-in module test");
+        assert_eq!(registry.len(), 1);
+        let modules = registry.into_modules();
+        assert_eq!(modules[0].get_status().name, "test");
+    }
+}
@@ -0,0 +1,301 @@
+use libmilkyway_derive::{Deserializable, Serializable};
+use crate::module::CLIStatus;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+
+///
+/// Progress of command which continues after returning from module
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable)]
+pub struct CLIProgress{
+    pub message: String,
+    /** Percent of work done, None if it is not known **/
+    pub percent: Option<u8>,
+}
+
+impl CLIProgress {
+    pub fn new(message: &str) -> CLIProgress{
+        CLIProgress{
+            message: message.to_string(),
+            percent: None,
+        }
+    }
+
+    ///
+    /// Sets percent of work done, values above 100 are lowered to 100
+    ///
+    pub fn set_percent(&mut self, percent: u8) -> &mut Self{
+        self.percent = Some(percent.min(100));
+        self
+    }
+}
+
+///
+/// Question host asks user on behalf of module
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable)]
+pub struct CLIPrompt{
+    pub message: String,
+    /** Answer is not echoed, e.g. for passphrases **/
+    pub secret: bool,
+    /** Answer used if user enters nothing **/
+    pub default: Option<String>,
+}
+
+impl CLIPrompt {
+    pub fn new(message: &str) -> CLIPrompt{
+        CLIPrompt{
+            message: message.to_string(),
+            secret: false,
+            default: None,
+        }
+    }
+
+    pub fn set_secret(&mut self) -> &mut Self{
+        self.secret = true;
+        self
+    }
+
+    pub fn set_default(&mut self, default: &str) -> &mut Self{
+        self.default = Some(default.to_string());
+        self
+    }
+}
+
+///
+/// Error of command shown to user by host
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable)]
+pub struct CLIFailure{
+    pub message: String,
+    /** Additional lines explaining error, e.g. values which were rejected **/
+    pub details: Vec<String>,
+}
+
+impl CLIFailure {
+    pub fn new(message: &str) -> CLIFailure{
+        CLIFailure{
+            message: message.to_string(),
+            details: vec![],
+        }
+    }
+
+    pub fn add_detail(&mut self, detail: &str) -> &mut Self{
+        self.details.push(detail.to_string());
+        self
+    }
+}
+
+///
+/// What host passes to module continuing a command(see MilkywayModule::on_cli_continue)
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum CLIInput{
+    /** Progress was shown, command should make next step **/
+    Next,
+    /** Answer of user to prompt, default is already applied **/
+    Answer(String),
+    /** User cancelled prompt, command should clean up and finish **/
+    Cancelled,
+}
+
+impl Serializable for CLIInput {
+    fn serialize(&self) -> Serialized {
+        let mut result = Serialized::new();
+        match self {
+            CLIInput::Next => result.extend(0u8.serialize()),
+            CLIInput::Answer(answer) => {
+                result.extend(1u8.serialize());
+                result.extend(answer.serialize());
+            }
+            CLIInput::Cancelled => result.extend(2u8.serialize()),
+        }
+        result
+    }
+}
+
+impl Deserializable for CLIInput {
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        if serialized.is_empty(){
+            return Err(SerializationError::LengthError);
+        }
+        match serialized[0] {
+            0 => Ok((CLIInput::Next, 1)),
+            1 => {
+                let (answer, offset) = String::from_serialized(&serialized[1..].to_vec())?;
+                Ok((CLIInput::Answer(answer), offset + 1))
+            }
+            2 => Ok((CLIInput::Cancelled, 1)),
+            _ => Err(SerializationError::InvalidDataError("Unknown CLI input")),
+        }
+    }
+}
+
+impl Serializable for CLIStatus {
+    fn serialize(&self) -> Serialized {
+        let mut result = Serialized::new();
+        match self {
+            CLIStatus::Done => result.extend(0u8.serialize()),
+            CLIStatus::NamespaceChange(namespace) => {
+                result.extend(1u8.serialize());
+                result.extend(namespace.serialize());
+            }
+            CLIStatus::InProgress(progress) => {
+                result.extend(2u8.serialize());
+                result.extend(progress.serialize());
+            }
+            CLIStatus::NeedsInput(prompt) => {
+                result.extend(3u8.serialize());
+                result.extend(prompt.serialize());
+            }
+            CLIStatus::Failed(failure) => {
+                result.extend(4u8.serialize());
+                result.extend(failure.serialize());
+            }
+        }
+        result
+    }
+}
+
+impl Deserializable for CLIStatus {
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        if serialized.is_empty(){
+            return Err(SerializationError::LengthError);
+        }
+        let data = serialized[1..].to_vec();
+        match serialized[0] {
+            0 => Ok((CLIStatus::Done, 1)),
+            1 => {
+                let (namespace, offset) = Vec::<String>::from_serialized(&data)?;
+                Ok((CLIStatus::NamespaceChange(namespace), offset + 1))
+            }
+            2 => {
+                let (progress, offset) = CLIProgress::from_serialized(&data)?;
+                Ok((CLIStatus::InProgress(progress), offset + 1))
+            }
+            3 => {
+                let (prompt, offset) = CLIPrompt::from_serialized(&data)?;
+                Ok((CLIStatus::NeedsInput(prompt), offset + 1))
+            }
+            4 => {
+                let (failure, offset) = CLIFailure::from_serialized(&data)?;
+                Ok((CLIStatus::Failed(failure), offset + 1))
+            }
+            _ => Err(SerializationError::InvalidDataError("Unknown CLI status")),
+        }
+    }
+}
+
+///
+/// Terminal of host driving interactive command
+///
+pub trait CLISessionHost{
+    ///
+    /// Shows progress of command
+    ///
+    fn on_progress(&mut self, progress: &CLIProgress);
+
+    ///
+    /// Asks user a question
+    ///
+    /// returns: Option<String>: answer or None if user cancelled, e.g. closed input
+    ///
+    fn on_prompt(&mut self, prompt: &CLIPrompt) -> Option<String>;
+}
+
+///
+/// Drives command of module until it finishes: progress is shown, prompts are asked and
+/// answers are passed back to module.
+///
+/// # Arguments
+/// * status: CLIStatus: status returned by on_cli_command
+/// * host: &mut dyn CLISessionHost: terminal of host
+/// * next: F: calls on_cli_continue of module, returns None if module failed(e.g. panicked)
+///
+/// returns: Option<CLIStatus>: Done, NamespaceChange or Failed, or None if module failed.
+/// Commands which keep going after prompt was cancelled are reported as Failed.
+///
+pub fn drive_cli_session<F>(mut status: CLIStatus, host: &mut dyn CLISessionHost,
+                            mut next: F) -> Option<CLIStatus> where F: FnMut(CLIInput) -> Option<CLIStatus>{
+    loop {
+        let input = match &status {
+            CLIStatus::InProgress(progress) => {
+                host.on_progress(progress);
+                CLIInput::Next
+            }
+            CLIStatus::NeedsInput(prompt) => match host.on_prompt(prompt) {
+                Some(answer) if answer.is_empty() && prompt.default.is_some() => {
+                    CLIInput::Answer(prompt.default.clone().unwrap())
+                }
+                Some(answer) => CLIInput::Answer(answer),
+                None => CLIInput::Cancelled,
+            },
+            _ => return Some(status),
+        };
+        let cancelled = input == CLIInput::Cancelled;
+        status = next(input)?;
+        if cancelled && matches!(status, CLIStatus::InProgress(_) | CLIStatus::NeedsInput(_)){
+            return Some(CLIStatus::Failed(CLIFailure::new("command is cancelled")));
+        }
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct ScriptedHost{
+        answers: Vec<Option<String>>,
+        shown: Vec<String>,
+    }
+
+    impl CLISessionHost for ScriptedHost{
+        fn on_progress(&mut self, progress: &CLIProgress) {
+            self.shown.push(progress.message.clone());
+        }
+
+        fn on_prompt(&mut self, prompt: &CLIPrompt) -> Option<String> {
+            self.shown.push(prompt.message.clone());
+            self.answers.remove(0)
+        }
+    }
+
+    // Asks for name and flags, then generates in two steps
+    fn guided_step(input: CLIInput, answers: &mut Vec<String>) -> CLIStatus{
+        if let CLIInput::Answer(answer) = input{
+            answers.push(answer);
+        }
+        match answers.len() {
+            0 => CLIStatus::NeedsInput(CLIPrompt::new("name")),
+            1 => CLIStatus::NeedsInput(CLIPrompt::new("flags").set_default("sign-messages").clone()),
+            2 => {
+                answers.push("generated".to_string());
+                CLIStatus::InProgress(CLIProgress::new("generating").set_percent(50).clone())
+            }
+            _ => CLIStatus::Done,
+        }
+    }
+
+    #[test]
+    fn test_drive_cli_session() {
+        let mut answers = vec![];
+        let mut host = ScriptedHost{ answers: vec![Some("web-1".to_string()), Some("".to_string())], shown: vec![] };
+        let status = guided_step(CLIInput::Next, &mut answers);
+        let result = drive_cli_session(status, &mut host, |input| Some(guided_step(input, &mut answers)));
+        assert_eq!(result, Some(CLIStatus::Done));
+        assert_eq!(answers, vec!["web-1", "sign-messages", "generated"]);
+        assert_eq!(host.shown, vec!["name", "flags", "generating"]);
+
+        let mut host = ScriptedHost{ answers: vec![None], shown: vec![] };
+        let result = drive_cli_session(CLIStatus::NeedsInput(CLIPrompt::new("name")), &mut host,
+                                       |_| Some(CLIStatus::NeedsInput(CLIPrompt::new("name"))));
+        assert!(matches!(result, Some(CLIStatus::Failed(_))));
+
+        let failure = CLIStatus::Failed(CLIFailure::new("bad serial").add_detail("serial=0").clone());
+        assert_eq!(CLIStatus::from_serialized(&failure.serialize()).unwrap().0, failure);
+        let answer = CLIInput::Answer("yes".to_string());
+        assert_eq!(CLIInput::from_serialized(&answer.serialize()).unwrap().0, answer);
+    }
+}
@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use libmilkyway_derive::{Deserializable, Serializable};
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::migration::{dump_versioned, load_versioned, VersionedStorage};
+use crate::serialization::serializable::{Serializable, Serialized};
+
+///
+/// Default count of bytes(keys and values together) each module may store
+///
+pub const DEFAULT_MODULE_STATE_QUOTA: u64 = 1024 * 1024;
+
+///
+/// Errors of changing state of module
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum StateError{
+    EmptyKey,
+    /** Value would make module use more bytes than its quota allows **/
+    QuotaExceeded{ module_id: u64, required: u64, quota: u64 },
+}
+
+impl Display for StateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateError::EmptyKey => write!(f, "Key of state must not be empty"),
+            StateError::QuotaExceeded{ module_id, required, quota } =>
+                write!(f, "Module {} needs {} bytes of state, but its quota is {} bytes", module_id, required, quota),
+        }
+    }
+}
+
+///
+/// Key-value state of all modules of a host, namespaced by module ID
+///
+#[derive(Serializable, Deserializable)]
+pub struct ModuleStateStore{
+    storage_file_name: String,
+    /** Values by module ID and key **/
+    values: HashMap<u64, HashMap<String, Vec<u8>>>,
+    default_quota: u64,
+    /** Quotas of modules differing from default one **/
+    quotas: HashMap<u64, u64>,
+}
+
+///
+/// State store shared between modules and CLI
+///
+pub type SharedModuleStateStore = Arc<Mutex<ModuleStateStore>>;
+
+impl ModuleStateStore {
+    ///
+    /// Creates empty store keeping data in provided file
+    ///
+    pub fn new(filename: &str) -> ModuleStateStore{
+        ModuleStateStore{
+            storage_file_name: filename.to_string(),
+            values: HashMap::new(),
+            default_quota: DEFAULT_MODULE_STATE_QUOTA,
+            quotas: HashMap::new(),
+        }
+    }
+
+    #[inline]
+    pub fn load_from_file(file: &str) -> ModuleStateStore{
+        let mut store = load_versioned::<ModuleStateStore>(Path::new(file)).expect("Failed to load module state");
+        store.storage_file_name = file.to_string();
+        store
+    }
+
+    ///
+    /// Loads store from file or creates empty one if file does not exist
+    ///
+    pub fn open_shared(file: &str) -> SharedModuleStateStore{
+        let store = if Path::new(file).exists(){
+            ModuleStateStore::load_from_file(file)
+        } else {
+            ModuleStateStore::new(file)
+        };
+        Arc::new(Mutex::new(store))
+    }
+
+    ///
+    /// Sets quotas of modules, replacing previous ones
+    ///
+    /// # Arguments
+    /// * default_quota: u64: bytes each module may store
+    /// * quotas: HashMap<u64, u64>: quotas of particular modules by module ID
+    ///
+    pub fn set_quotas(&mut self, default_quota: u64, quotas: HashMap<u64, u64>) -> &mut Self{
+        self.default_quota = default_quota;
+        self.quotas = quotas;
+        self
+    }
+
+    #[inline]
+    pub fn get_quota(&self, module_id: u64) -> u64{
+        *self.quotas.get(&module_id).unwrap_or(&self.default_quota)
+    }
+
+    ///
+    /// Gets bytes used by keys and values of module
+    ///
+    pub fn get_usage(&self, module_id: u64) -> u64{
+        self.values.get(&module_id).map_or(0, |values| {
+            values.iter().map(|(key, value)| (key.len() + value.len()) as u64).sum()
+        })
+    }
+
+    ///
+    /// Gets IDs of modules which store anything
+    ///
+    pub fn get_modules(&self) -> Vec<u64>{
+        let mut modules: Vec<u64> = self.values.keys().cloned().collect();
+        modules.sort();
+        modules
+    }
+
+    ///
+    /// Gets sorted keys of module
+    ///
+    pub fn get_keys(&self, module_id: u64) -> Vec<String>{
+        let mut keys: Vec<String> = self.values.get(&module_id)
+            .map_or(Vec::new(), |values| values.keys().cloned().collect());
+        keys.sort();
+        keys
+    }
+
+    #[inline]
+    pub fn get(&self, module_id: u64, key: &str) -> Option<&Vec<u8>>{
+        self.values.get(&module_id)?.get(key)
+    }
+
+    ///
+    /// Sets value of module, checking quota of module
+    ///
+    pub fn set(&mut self, module_id: u64, key: &str, value: Vec<u8>) -> Result<(), StateError>{
+        if key.is_empty(){
+            return Err(StateError::EmptyKey);
+        }
+        let previous = self.get(module_id, key).map_or(0, |previous| (key.len() + previous.len()) as u64);
+        let required = self.get_usage(module_id) - previous + (key.len() + value.len()) as u64;
+        let quota = self.get_quota(module_id);
+        if required > quota{
+            return Err(StateError::QuotaExceeded{ module_id, required, quota });
+        }
+        self.values.entry(module_id).or_default().insert(key.to_string(), value);
+        Ok(())
+    }
+
+    ///
+    /// Removes value of module
+    ///
+    /// returns: bool: whether value existed
+    ///
+    pub fn remove(&mut self, module_id: u64, key: &str) -> bool{
+        let values = match self.values.get_mut(&module_id) {
+            Some(values) => values,
+            None => return false,
+        };
+        let is_removed = values.remove(key).is_some();
+        if values.is_empty(){
+            self.values.remove(&module_id);
+        }
+        is_removed
+    }
+
+    ///
+    /// Removes all values of module
+    ///
+    /// returns: usize: count of removed values
+    ///
+    pub fn clear(&mut self, module_id: u64) -> usize{
+        self.values.remove(&module_id).map_or(0, |values| values.len())
+    }
+
+    ///
+    /// Re-reads values of all modules from storage, so changes saved by other processes sharing
+    /// it(e.g. CLI and daemon) are seen. Quotas are kept, store which was never saved is kept as is.
+    ///
+    pub fn reload(&mut self){
+        if !Path::new(&self.storage_file_name).exists(){
+            return;
+        }
+        match load_versioned::<ModuleStateStore>(Path::new(&self.storage_file_name)) {
+            Ok(stored) => self.values = stored.values,
+            Err(error) => log::error!("Failed to reload module state from {}: {}", self.storage_file_name, error),
+        }
+    }
+
+    ///
+    /// Saves state to storage
+    ///
+    #[inline]
+    pub fn commit(&mut self){
+        if dump_versioned(self, &self.storage_file_name).is_err(){
+            log::error!("Failed to save module state to {}", self.storage_file_name);
+        }
+    }
+}
+
+impl VersionedStorage for ModuleStateStore {
+    const STORE_NAME: &'static str = "module state";
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+///
+/// State of one module given to it by ModuleDataBus. Values are re-read from storage on every
+/// access and changes are saved immediately, so processes sharing storage see changes of each
+/// other.
+///
+#[derive(Clone)]
+pub struct ModuleState{
+    module_id: u64,
+    store: SharedModuleStateStore,
+}
+
+impl ModuleState {
+    pub fn new(module_id: u64, store: SharedModuleStateStore) -> ModuleState{
+        ModuleState{
+            module_id,
+            store,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<Vec<u8>>{
+        let mut store = self.store.lock().unwrap();
+        store.reload();
+        store.get(self.module_id, key).cloned()
+    }
+
+    ///
+    /// Gets value and deserializes it
+    ///
+    /// returns: Option<T>: value or None if it is missing or can not be deserialized
+    ///
+    pub fn get_value<T: Deserializable>(&self, key: &str) -> Option<T>{
+        T::from_serialized(&self.get(key)?).ok().map(|(value, _)| value)
+    }
+
+    pub fn set(&self, key: &str, value: Vec<u8>) -> Result<(), StateError>{
+        let mut store = self.store.lock().unwrap();
+        store.reload();
+        store.set(self.module_id, key, value)?;
+        store.commit();
+        Ok(())
+    }
+
+    #[inline]
+    pub fn set_value<T: Serializable>(&self, key: &str, value: &T) -> Result<(), StateError>{
+        self.set(key, value.serialize())
+    }
+
+    ///
+    /// Changes value in place while store is locked, so concurrent updates of this process are
+    /// not lost. Value is removed if update leaves None.
+    ///
+    /// # Arguments
+    /// * key: &str: key of value
+    /// * update: F: changes value, None if value is missing or can not be deserialized
+    ///
+    /// returns: Result<R, StateError>: what update returned or error if changed value exceeds quota
+    ///
+    pub fn update<T: Serializable + Deserializable, R, F: FnOnce(&mut Option<T>) -> R>(&self, key: &str,
+                                                                                          update: F) -> Result<R, StateError>{
+        let mut store = self.store.lock().unwrap();
+        store.reload();
+        let mut value = store.get(self.module_id, key)
+            .and_then(|value| T::from_serialized(value).ok())
+            .map(|(value, _)| value);
+        let result = update(&mut value);
+        match value {
+            Some(value) => store.set(self.module_id, key, value.serialize())?,
+            None => {
+                store.remove(self.module_id, key);
+            }
+        }
+        store.commit();
+        Ok(result)
+    }
+
+    pub fn remove(&self, key: &str) -> bool{
+        let mut store = self.store.lock().unwrap();
+        store.reload();
+        let is_removed = store.remove(self.module_id, key);
+        if is_removed{
+            store.commit();
+        }
+        is_removed
+    }
+
+    pub fn get_keys(&self) -> Vec<String>{
+        let mut store = self.store.lock().unwrap();
+        store.reload();
+        store.get_keys(self.module_id)
+    }
+
+    ///
+    /// Gets bytes used by module and its quota
+    ///
+    pub fn get_usage(&self) -> (u64, u64){
+        let store = self.store.lock().unwrap();
+        (store.get_usage(self.module_id), store.get_quota(self.module_id))
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_state() {
+        let file = std::env::temp_dir().join(format!("milkyway-state-{}.dat", rand::random::<u64>()));
+        let file = file.to_str().unwrap();
+        let shared = ModuleStateStore::open_shared(file);
+        shared.lock().unwrap().set_quotas(32, HashMap::from([(2, 4)]));
+        let first = ModuleState::new(1, shared.clone());
+        let second = ModuleState::new(2, shared.clone());
+        first.set_value("filter", &42u128).unwrap();
+        assert_eq!(first.get_value::<u128>("filter"), Some(42));
+        assert_eq!(second.get("filter"), None);
+        // Key and value are counted, replaced value is not
+        assert_eq!(first.set("stats", vec![0; 6]), Err(StateError::QuotaExceeded{ module_id: 1, required: 33, quota: 32 }));
+        first.set_value("filter", &7u64).unwrap();
+        assert_eq!(first.get_usage(), (14, 32));
+        assert_eq!(second.set("k", vec![1, 2, 3, 4]), Err(StateError::QuotaExceeded{ module_id: 2, required: 5, quota: 4 }));
+        assert_eq!(second.set("", vec![]), Err(StateError::EmptyKey));
+        second.set("k", vec![1]).unwrap();
+
+        let mut loaded = ModuleStateStore::load_from_file(file);
+        assert_eq!(loaded.get_modules(), vec![1, 2]);
+        assert_eq!(loaded.get_keys(1), vec!["filter".to_string()]);
+        assert_eq!(loaded.get_quota(2), 4);
+        assert!(second.remove("k"));
+        assert!(!second.remove("k"));
+        assert_eq!(loaded.clear(1), 1);
+        assert_eq!(loaded.get_usage(1), 0);
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_module_state_shared_between_processes() {
+        let file = std::env::temp_dir().join(format!("milkyway-state-{}.dat", rand::random::<u64>()));
+        let file = file.to_str().unwrap();
+        // Each process opens storage on its own
+        let daemon = ModuleState::new(1, ModuleStateStore::open_shared(file));
+        let cli = ModuleState::new(1, ModuleStateStore::open_shared(file));
+        daemon.set_value("policy", &"confirm".to_string()).unwrap();
+        assert_eq!(cli.get_value::<String>("policy"), Some("confirm".to_string()));
+        assert_eq!(cli.update("queue", |queue: &mut Option<Vec<u64>>| queue.get_or_insert_with(Vec::new).push(5)), Ok(()));
+        let taken = daemon.update("queue", |queue: &mut Option<Vec<u64>>| {
+            let taken = queue.as_mut().and_then(|queue| queue.pop());
+            *queue = None;
+            taken
+        });
+        assert_eq!(taken, Ok(Some(5)));
+        assert_eq!(cli.get("queue"), None);
+        assert_eq!(daemon.get_value::<String>("policy"), Some("confirm".to_string()));
+        std::fs::remove_file(file).unwrap();
+    }
+}
@@ -0,0 +1,775 @@
+use std::any::Any;
+use std::fmt::{Display, Formatter};
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use crate::module::loader::DynamicModule;
+use crate::module::state::ModuleState;
+use crate::module::{HostType, MilkywayModule, ModuleDataBus};
+use crate::pki::certificate::profile::CertificateProfile;
+use crate::services::certificate::{CertificateServiceBinder, CertificateServicePool};
+use crate::services::group::SharedGroupService;
+use crate::services::name::NameService;
+use crate::message::common::Message;
+use crate::services::transport::{ModuleTransportService, OperatorTransportService, TransportService};
+use crate::transport::TransportListener;
+use crate::transport::handler::TransportHandlerServiceBinder;
+use crate::transport::access::SharedAccessControl;
+use crate::transport::crypto::{CryptoAlert, CryptoAlertListener};
+use crate::transport::events::{ConnectionEvent, ConnectionEventListener};
+use crate::transport::operator::OperatorIdentity;
+use crate::transport::pinning::SharedPeerPins;
+
+///
+/// Creates new instance of module, used for restarting it after panic
+///
+pub type ModuleConstructor = Box<dyn FnMut() -> Option<Box<dyn MilkywayModule>> + Send>;
+
+///
+/// Creates data bus passed to module on every (re)load
+///
+pub type DataBusProvider = Arc<dyn Fn() -> Box<dyn ModuleDataBus> + Send + Sync>;
+
+///
+/// Health of supervised module
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModuleHealth{
+    /** Module handles callbacks **/
+    Running,
+    /** Module panicked and waits for restart **/
+    Failed,
+    /** Module panicked and will not be restarted **/
+    Stopped,
+    /** Module was unloaded by host **/
+    Unloaded,
+}
+
+impl Display for ModuleHealth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModuleHealth::Running => write!(f, "running"),
+            ModuleHealth::Failed => write!(f, "failed"),
+            ModuleHealth::Stopped => write!(f, "stopped"),
+            ModuleHealth::Unloaded => write!(f, "unloaded"),
+        }
+    }
+}
+
+///
+/// How panicked modules are restarted. Delay before restart starts with initial backoff
+/// and doubles after every consecutive failure up to max backoff.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct RestartPolicy{
+    /** Restarts allowed before module is stopped for good, 0 disables restarts **/
+    pub max_restarts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy{
+            max_restarts: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RestartPolicy {
+    ///
+    /// Creates policy which never restarts modules
+    ///
+    pub fn never() -> RestartPolicy{
+        RestartPolicy{
+            max_restarts: 0,
+            ..RestartPolicy::default()
+        }
+    }
+
+    ///
+    /// Gets delay before restart following given number of consecutive failures
+    ///
+    pub fn get_backoff(&self, failures: u32) -> Duration{
+        let factor = 1u32.checked_shl(failures.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+///
+/// Health of module together with its failure history
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModuleStatus{
+    pub name: String,
+    pub module_id: Option<u64>,
+    pub health: ModuleHealth,
+    /** Panics since module was loaded **/
+    pub failures: u32,
+    pub restarts: u32,
+    /** Callback which panicked last and panic message **/
+    pub last_error: Option<String>,
+    /** When failed module is restarted **/
+    pub restart_at: Option<Instant>,
+}
+
+///
+/// Gets message of panic payload
+///
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String{
+    if let Some(message) = payload.downcast_ref::<&str>(){
+        return message.to_string();
+    }
+    if let Some(message) = payload.downcast_ref::<String>(){
+        return message.clone();
+    }
+    "unknown panic".to_string()
+}
+
+///
+/// Panic of module callback called by services of host(message listeners, connection events,
+/// crypto alerts) rather than through SupervisedModule::invoke. Module is failed once its
+/// supervisor checks it next time, meanwhile its listeners are not called anymore.
+///
+#[derive(Clone, Default)]
+pub struct ModuleFault{
+    /** Callback which panicked and panic message, only the first panic is kept **/
+    error: Arc<Mutex<Option<(String, String)>>>,
+}
+
+impl ModuleFault {
+    ///
+    /// Records panic of callback unless another one is recorded already
+    ///
+    pub fn record(&self, callback: &str, message: String){
+        let mut error = self.error.lock().unwrap();
+        if error.is_none(){
+            *error = Some((callback.to_string(), message));
+        }
+    }
+
+    #[inline]
+    pub fn is_set(&self) -> bool{
+        self.error.lock().unwrap().is_some()
+    }
+
+    fn get(&self) -> Option<(String, String)>{
+        self.error.lock().unwrap().clone()
+    }
+
+    fn clear(&self){
+        *self.error.lock().unwrap() = None;
+    }
+
+    ///
+    /// Calls listener of module unless module has failed, panic is recorded
+    ///
+    /// returns: Result<Option<R>, Box<dyn Any + Send>>: result of call, None if module has
+    /// failed before, or payload of panic
+    ///
+    fn guard<R>(&self, callback: &str, call: impl FnOnce() -> R) -> Result<Option<R>, Box<dyn Any + Send>>{
+        if self.is_set(){
+            return Ok(None);
+        }
+        catch_unwind(AssertUnwindSafe(call)).map(Some).inspect_err(|payload| {
+            self.record(callback, panic_message(payload.as_ref()));
+        })
+    }
+}
+
+///
+/// Message listener of module which reports its panics to supervisor. If host counts failed
+/// deliveries(see TransportService::get_dead_letter_queue), panic is passed on, so messages
+/// module failed on are dead-lettered and may be replayed once it is restarted.
+///
+pub(crate) struct GuardedListener{
+    inner: Box<dyn TransportListener>,
+    fault: ModuleFault,
+    rethrow: bool,
+}
+
+impl GuardedListener {
+    pub(crate) fn new(inner: Box<dyn TransportListener>, fault: ModuleFault, rethrow: bool) -> GuardedListener{
+        GuardedListener{
+            inner,
+            fault,
+            rethrow,
+        }
+    }
+
+    fn deliver<R>(&mut self, callback: &str, call: impl FnOnce(&mut dyn TransportListener) -> R) -> Option<R>{
+        let inner = &mut self.inner;
+        match self.fault.guard(callback, || call(inner.as_mut())) {
+            Ok(Some(result)) => Some(result),
+            Ok(None) if self.rethrow => resume_unwind(Box::new("module has failed")),
+            Err(payload) if self.rethrow => resume_unwind(payload),
+            _ => None,
+        }
+    }
+}
+
+impl TransportListener for GuardedListener{
+    fn on_message(&mut self, message: Message) {
+        self.deliver("on_message", |listener| listener.on_message(message));
+    }
+
+    fn on_exclusive_message(&mut self, message: Message) -> bool {
+        self.deliver("on_exclusive_message", |listener| listener.on_exclusive_message(message)).unwrap_or(false)
+    }
+
+    fn on_binded_to_handler(&mut self, binder: Box<TransportHandlerServiceBinder>) {
+        self.deliver("on_binded_to_handler", |listener| listener.on_binded_to_handler(binder));
+    }
+}
+
+///
+/// Listener of connection events of module which reports its panics to supervisor
+///
+pub(crate) struct GuardedConnectionListener{
+    inner: Box<dyn ConnectionEventListener>,
+    fault: ModuleFault,
+}
+
+impl GuardedConnectionListener {
+    pub(crate) fn new(inner: Box<dyn ConnectionEventListener>, fault: ModuleFault) -> GuardedConnectionListener{
+        GuardedConnectionListener{
+            inner,
+            fault,
+        }
+    }
+}
+
+impl ConnectionEventListener for GuardedConnectionListener{
+    fn on_connection_event(&mut self, event: &ConnectionEvent) {
+        let inner = &mut self.inner;
+        let _ = self.fault.guard("on_connection_event", || inner.on_connection_event(event));
+    }
+}
+
+///
+/// Listener of crypto alerts of module which reports its panics to supervisor
+///
+pub(crate) struct GuardedAlertListener{
+    inner: Box<dyn CryptoAlertListener>,
+    fault: ModuleFault,
+}
+
+impl GuardedAlertListener {
+    pub(crate) fn new(inner: Box<dyn CryptoAlertListener>, fault: ModuleFault) -> GuardedAlertListener{
+        GuardedAlertListener{
+            inner,
+            fault,
+        }
+    }
+}
+
+impl CryptoAlertListener for GuardedAlertListener{
+    fn on_alert(&mut self, alert: &CryptoAlert) {
+        let inner = &mut self.inner;
+        let _ = self.fault.guard("on_alert", || inner.on_alert(alert));
+    }
+}
+
+///
+/// Data bus which transport service makes subscriptions owned by module, so they are
+/// removed once module is unloaded or its instance panics
+///
+struct ModuleScopedDataBus{
+    inner: Box<dyn ModuleDataBus>,
+    module_id: u64,
+    fault: ModuleFault,
+    /** Set once module asks for transport, hosts without transport never have to clean it up **/
+    uses_transport: Arc<AtomicBool>,
+}
+
+impl ModuleDataBus for ModuleScopedDataBus{
+    fn get_transport_service(&self) -> Box<dyn TransportService> {
+        self.uses_transport.store(true, Ordering::Relaxed);
+        let mut transport = Box::new(ModuleTransportService::new(self.inner.get_transport_service(), self.module_id));
+        transport.set_fault(self.fault.clone());
+        match self.inner.get_operator() {
+            Some(operator) => Box::new(OperatorTransportService::new(transport, operator)),
+            None => transport,
+        }
+    }
+
+    fn get_name_service(&self) -> Box<dyn NameService> {
+        self.inner.get_name_service()
+    }
+
+    fn get_certificate_service(&self) -> Box<CertificateServiceBinder> {
+        self.inner.get_certificate_service()
+    }
+
+    fn get_certificate_pool(&self) -> CertificateServicePool {
+        self.inner.get_certificate_pool()
+    }
+
+    fn get_host_type(&self) -> HostType {
+        self.inner.get_host_type()
+    }
+
+    fn get_host_id(&self) -> Option<u128> {
+        self.inner.get_host_id()
+    }
+
+    fn get_group_service(&self) -> Option<SharedGroupService> {
+        self.inner.get_group_service()
+    }
+
+    fn get_access_control(&self) -> Option<SharedAccessControl> {
+        self.inner.get_access_control()
+    }
+
+    fn get_peer_pins(&self) -> Option<SharedPeerPins> {
+        self.inner.get_peer_pins()
+    }
+
+    fn get_certificate_profiles(&self) -> Vec<CertificateProfile> {
+        self.inner.get_certificate_profiles()
+    }
+
+    fn get_module_state(&self, module_id: u64) -> Option<ModuleState> {
+        self.inner.get_module_state(module_id)
+    }
+
+    fn get_operator(&self) -> Option<Arc<OperatorIdentity>> {
+        self.inner.get_operator()
+    }
+
+    fn get_loaded_modules(&self) -> Vec<String> {
+        self.inner.get_loaded_modules()
+    }
+
+    fn get_listener_endpoints(&self) -> Vec<String> {
+        self.inner.get_listener_endpoints()
+    }
+}
+
+///
+/// A module which callbacks are isolated from host: panic inside of module marks it failed
+/// instead of unwinding into host. Failed module is skipped until it is restarted according
+/// to restart policy, restart happens on first callback after backoff elapsed.
+///
+/// Instance which panicked is leaked rather than dropped, as its state may be inconsistent.
+/// Subscriptions module made through its data bus are removed when it panics or is unloaded,
+/// so listeners of leaked instances are not called anymore.
+///
+pub struct SupervisedModule{
+    /* Instance is declared before constructor, so it is dropped before library it came from */
+    instance: Option<Box<dyn MilkywayModule>>,
+    constructor: ModuleConstructor,
+    data_bus: Option<DataBusProvider>,
+    uses_transport: Arc<AtomicBool>,
+    fault: ModuleFault,
+    policy: RestartPolicy,
+    status: ModuleStatus,
+}
+
+impl SupervisedModule {
+    ///
+    /// Creates supervised module
+    ///
+    /// # Arguments
+    /// * name: &str: name of module shown to user
+    /// * instance: Box<dyn MilkywayModule>: instance of module, not loaded yet
+    /// * constructor: ModuleConstructor: creates instances on restart
+    ///
+    pub fn new(name: &str, instance: Box<dyn MilkywayModule>, constructor: ModuleConstructor) -> SupervisedModule{
+        SupervisedModule{
+            instance: Some(instance),
+            constructor,
+            data_bus: None,
+            uses_transport: Arc::new(AtomicBool::new(false)),
+            fault: ModuleFault::default(),
+            policy: RestartPolicy::default(),
+            status: ModuleStatus{
+                name: name.to_string(),
+                module_id: None,
+                health: ModuleHealth::Running,
+                failures: 0,
+                restarts: 0,
+                last_error: None,
+                restart_at: None,
+            },
+        }
+    }
+
+    ///
+    /// Creates supervised module restarted with new instances from the library of dynamic module
+    ///
+    pub fn from_dynamic(name: &str, module: DynamicModule) -> SupervisedModule{
+        let (instance, constructor) = module.into_parts();
+        SupervisedModule::new(name, instance, constructor)
+    }
+
+    pub fn set_restart_policy(&mut self, policy: RestartPolicy) -> &mut Self{
+        self.policy = policy;
+        self
+    }
+
+    #[inline]
+    pub fn get_status(&self) -> &ModuleStatus{
+        &self.status
+    }
+
+    ///
+    /// Checks whether module handles callbacks now
+    ///
+    #[inline]
+    pub fn is_running(&self) -> bool{
+        self.status.health == ModuleHealth::Running
+    }
+
+    ///
+    /// Loads module with data bus which is also kept for restarts
+    ///
+    /// returns: bool: whether module loaded without panic
+    ///
+    pub fn load(&mut self, data_bus: DataBusProvider) -> bool{
+        self.data_bus = Some(data_bus.clone());
+        let (uses_transport, fault) = (self.uses_transport.clone(), self.fault.clone());
+        self.invoke("on_load", |module| Self::load_instance(module, &data_bus, uses_transport, fault)).is_some()
+    }
+
+    fn load_instance(module: &mut dyn MilkywayModule, data_bus: &DataBusProvider, uses_transport: Arc<AtomicBool>,
+                     fault: ModuleFault){
+        let module_id = module.get_id();
+        module.on_load(Box::new(ModuleScopedDataBus{
+            inner: data_bus(),
+            module_id,
+            fault,
+            uses_transport,
+        }));
+    }
+
+    ///
+    /// Unloads module: instance is dropped and its subscriptions are removed
+    ///
+    /// returns: usize: count of removed subscriptions
+    ///
+    pub fn unload(&mut self) -> usize{
+        if let Some(instance) = self.instance.take(){
+            // Panic in destructor of module must not unwind into host either
+            if catch_unwind(AssertUnwindSafe(|| drop(instance))).is_err(){
+                log::error!("Module {} panicked while being unloaded", self.status.name);
+            }
+        }
+        self.status.health = ModuleHealth::Unloaded;
+        self.status.restart_at = None;
+        self.remove_subscriptions()
+    }
+
+    ///
+    /// Removes subscriptions of module from transport service of data bus
+    ///
+    fn remove_subscriptions(&mut self) -> usize{
+        let (data_bus, module_id) = match (&self.data_bus, self.status.module_id) {
+            (Some(data_bus), Some(module_id)) if self.uses_transport.load(Ordering::Relaxed) => (data_bus, module_id),
+            _ => return 0,
+        };
+        let removed = data_bus().get_transport_service().unsubscribe_all(module_id);
+        if removed != 0{
+            log::info!("Removed {} subscriptions of module {}", removed, self.status.name);
+        }
+        removed
+    }
+
+    ///
+    /// Calls module with panic isolation
+    ///
+    /// # Arguments
+    /// * callback: &str: name of callback shown in status if it panics
+    /// * call: FnOnce(&mut dyn MilkywayModule) -> R: invocation of module
+    ///
+    /// returns: Option<R>: result of call or None if module panicked or is not running
+    ///
+    pub fn invoke<R>(&mut self, callback: &str, call: impl FnOnce(&mut dyn MilkywayModule) -> R) -> Option<R>{
+        self.supervise();
+        let instance = match self.instance.as_mut() {
+            Some(instance) if self.status.health == ModuleHealth::Running => instance,
+            _ => return None,
+        };
+        if self.status.module_id.is_none(){
+            self.status.module_id = catch_unwind(AssertUnwindSafe(|| instance.get_id())).ok();
+        }
+        match catch_unwind(AssertUnwindSafe(|| call(instance.as_mut()))) {
+            Ok(result) => Some(result),
+            Err(payload) => {
+                self.fail(callback, panic_message(payload.as_ref()));
+                None
+            }
+        }
+    }
+
+    ///
+    /// Fails module which listener panicked and restarts failed module once its backoff
+    /// elapsed. Hosts which do not call modules on their own, e.g. daemon, call it periodically.
+    ///
+    /// returns: bool: whether module is running
+    ///
+    pub fn supervise(&mut self) -> bool{
+        if let Some((callback, message)) = self.fault.get(){
+            if self.status.health == ModuleHealth::Running{
+                self.fail(&callback, message);
+            }
+        }
+        if self.status.health == ModuleHealth::Failed
+            && self.status.restart_at.is_some_and(|restart_at| restart_at <= Instant::now()){
+            self.restart();
+        }
+        self.is_running()
+    }
+
+    fn fail(&mut self, callback: &str, message: String){
+        log::error!("Module {} panicked in {}: {}", self.status.name, callback, message);
+        std::mem::forget(self.instance.take());
+        self.remove_subscriptions();
+        self.status.failures += 1;
+        self.status.last_error = Some(format!("{}: {}", callback, message));
+        if self.status.restarts >= self.policy.max_restarts{
+            self.status.health = ModuleHealth::Stopped;
+            self.status.restart_at = None;
+            return;
+        }
+        self.status.health = ModuleHealth::Failed;
+        self.status.restart_at = Some(Instant::now() + self.policy.get_backoff(self.status.failures));
+    }
+
+    fn restart(&mut self){
+        self.status.restarts += 1;
+        self.status.restart_at = None;
+        let instance = match catch_unwind(AssertUnwindSafe(|| (self.constructor)())) {
+            Ok(Some(instance)) => instance,
+            Ok(None) => {
+                self.fail("restart", "module can not be created".to_string());
+                return;
+            }
+            Err(payload) => {
+                self.fail("restart", panic_message(payload.as_ref()));
+                return;
+            }
+        };
+        log::info!("Restarting module {}", self.status.name);
+        // Listeners of failed instance are removed, so new ones may be called again
+        self.fault.clear();
+        self.instance = Some(instance);
+        self.status.health = ModuleHealth::Running;
+        if let Some(data_bus) = self.data_bus.clone(){
+            let (uses_transport, fault) = (self.uses_transport.clone(), self.fault.clone());
+            self.invoke("on_load", |module| Self::load_instance(module, &data_bus, uses_transport, fault));
+        }
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::atomic::AtomicU32;
+    use crate::message::common::Message;
+    use crate::module::{CLIStatus, HostType};
+    use crate::testing::certificate::MockCertificateService;
+    use crate::services::transport::MessageFilter;
+    use crate::testing::module::TestDataBus;
+    use crate::testing::transport::LoopbackTransportService;
+    use crate::transport::deadletter::{DeadLetterQueue, SharedDeadLetterQueue};
+    use crate::transport::TransportListener;
+    use crate::tokio::init_tokio;
+
+    struct IgnoringListener;
+
+    impl TransportListener for IgnoringListener{
+        fn on_message(&mut self, _message: Message) {}
+    }
+
+    struct FragileModule{
+        loads: Arc<AtomicU32>,
+    }
+
+    impl MilkywayModule for FragileModule{
+        fn get_id(&self) -> u64 {
+            7
+        }
+
+        fn get_commands(&self) -> Vec<String> {
+            vec!["fragile".to_string()]
+        }
+
+        fn on_load(&mut self, data_bus: Box<dyn ModuleDataBus>) {
+            self.loads.fetch_add(1, Ordering::SeqCst);
+            data_bus.get_transport_service().subscribe_to_messages(&MessageFilter::new(), Box::new(IgnoringListener));
+        }
+
+        fn on_cli_command(&mut self, command: Vec<String>, _arguments: Vec<String>) -> CLIStatus {
+            if command.last().is_some_and(|command| command == "crash"){
+                panic!("crash requested");
+            }
+            CLIStatus::Done
+        }
+
+        fn on_server_receive(&self, _packet: &Message) {}
+
+        fn on_client_receive(&self, _packet: &Message) {}
+
+        fn on_cli_receive(&self, _packet: &Message) {}
+    }
+
+    struct PanickingListener;
+
+    impl TransportListener for PanickingListener{
+        fn on_message(&mut self, _message: Message) {
+            panic!("can not handle message");
+        }
+    }
+
+    struct PoisonedModule;
+
+    impl MilkywayModule for PoisonedModule{
+        fn get_id(&self) -> u64 {
+            8
+        }
+
+        fn get_commands(&self) -> Vec<String> {
+            vec!["poisoned".to_string()]
+        }
+
+        fn on_load(&mut self, data_bus: Box<dyn ModuleDataBus>) {
+            data_bus.get_transport_service().subscribe_to_messages(&MessageFilter::new(), Box::new(PanickingListener));
+        }
+
+        fn on_cli_command(&mut self, _command: Vec<String>, _arguments: Vec<String>) -> CLIStatus {
+            CLIStatus::Done
+        }
+
+        fn on_server_receive(&self, _packet: &Message) {}
+
+        fn on_client_receive(&self, _packet: &Message) {}
+
+        fn on_cli_receive(&self, _packet: &Message) {}
+    }
+
+    fn command(module: &mut SupervisedModule, command: &str) -> bool{
+        module.invoke("on_cli_command", |module| {
+            module.on_cli_command(vec!["fragile".to_string(), command.to_string()], vec![]);
+        }).is_some()
+    }
+
+    #[test]
+    fn test_supervised_module_restarts() {
+        init_tokio();
+        let loads = Arc::new(AtomicU32::new(0));
+        let constructor_loads = loads.clone();
+        let mut module = SupervisedModule::new("fragile", Box::new(FragileModule{ loads: loads.clone() }),
+                                               Box::new(move || Some(Box::new(FragileModule{
+                                                   loads: constructor_loads.clone(),
+                                               }) as Box<dyn MilkywayModule>)));
+        module.set_restart_policy(RestartPolicy{
+            max_restarts: 1,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        });
+        let data_bus = TestDataBus::new(HostType::CLI, 1, MockCertificateService::with_test_certificates());
+        assert!(module.load(Arc::new(move || Box::new(data_bus.clone()) as Box<dyn ModuleDataBus>)));
+        assert!(command(&mut module, "ok"));
+        assert!(!command(&mut module, "crash"));
+        assert_eq!(module.get_status().health, ModuleHealth::Failed);
+        assert_eq!(module.get_status().last_error, Some("on_cli_command: crash requested".to_string()));
+        // Backoff is zero, so next callback restarts module and loads it again
+        assert!(command(&mut module, "ok"));
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+        assert_eq!(module.get_status().restarts, 1);
+        assert!(!command(&mut module, "crash"));
+        assert_eq!(module.get_status().health, ModuleHealth::Stopped);
+        assert!(!command(&mut module, "ok"));
+        assert_eq!(module.get_status().failures, 2);
+        assert_eq!(module.get_status().module_id, Some(7));
+    }
+
+    #[test]
+    fn test_subscriptions_removed() {
+        init_tokio();
+        let loads = Arc::new(AtomicU32::new(0));
+        let constructor_loads = loads.clone();
+        let mut module = SupervisedModule::new("fragile", Box::new(FragileModule{ loads: loads.clone() }),
+                                               Box::new(move || Some(Box::new(FragileModule{
+                                                   loads: constructor_loads.clone(),
+                                               }) as Box<dyn MilkywayModule>)));
+        module.set_restart_policy(RestartPolicy{
+            max_restarts: 1,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        });
+        let data_bus = TestDataBus::new(HostType::CLI, 1, MockCertificateService::with_test_certificates());
+        let transport = data_bus.get_transport_service();
+        assert!(module.load(Arc::new(move || Box::new(data_bus.clone()) as Box<dyn ModuleDataBus>)));
+        assert_eq!(transport.get_module_subscription_counts(), HashMap::from([(7, 1)]));
+        // Subscription of panicked instance is removed, restarted one subscribes again
+        assert!(!command(&mut module, "crash"));
+        assert!(transport.get_module_subscription_counts().is_empty());
+        assert!(command(&mut module, "ok"));
+        assert_eq!(transport.get_module_subscription_counts(), HashMap::from([(7, 1)]));
+        assert_eq!(module.unload(), 1);
+        assert_eq!(module.get_status().health, ModuleHealth::Unloaded);
+        assert!(transport.get_module_subscription_counts().is_empty());
+        assert!(!command(&mut module, "ok"));
+    }
+
+    fn check_listener_panic(queue: Option<SharedDeadLetterQueue>) {
+        let mut module = SupervisedModule::new("poisoned", Box::new(PoisonedModule),
+                                               Box::new(|| Some(Box::new(PoisonedModule) as Box<dyn MilkywayModule>)));
+        module.set_restart_policy(RestartPolicy{
+            max_restarts: 1,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        });
+        let (mut sender, mut receiver) = LoopbackTransportService::pair(1, 2);
+        if let Some(queue) = queue{
+            receiver.set_dead_letter_queue(queue);
+        }
+        let data_bus = TestDataBus::new(HostType::Broker, 2, MockCertificateService::with_test_certificates())
+            .with_transport(receiver.clone());
+        assert!(module.load(Arc::new(move || Box::new(data_bus.clone()) as Box<dyn ModuleDataBus>)));
+        assert!(module.supervise());
+        // Panic of listener does not unwind into transport, supervisor fails module instead and
+        // restarts it at once as backoff is zero
+        let mut message = Message::new();
+        message.set_destination(2);
+        message.source = 1;
+        sender.send_message(message.clone());
+        assert!(module.supervise());
+        assert_eq!(module.get_status().failures, 1);
+        assert_eq!(module.get_status().restarts, 1);
+        assert_eq!(module.get_status().last_error, Some("on_message: can not handle message".to_string()));
+        assert_eq!(receiver.get_module_subscription_counts(), HashMap::from([(8, 1)]));
+        // Restarts are exhausted, so module is stopped with its subscriptions removed
+        sender.send_message(message);
+        assert!(!module.supervise());
+        assert_eq!(module.get_status().health, ModuleHealth::Stopped);
+        assert!(receiver.get_module_subscription_counts().is_empty());
+    }
+
+    #[test]
+    fn test_listener_panic_fails_module() {
+        init_tokio();
+        check_listener_panic(None);
+        let file = std::env::temp_dir().join(format!("milkyway-supervisor-dlq-{}.dat", rand::random::<u64>()));
+        let queue = Arc::new(Mutex::new(DeadLetterQueue::new(file.to_str().unwrap(), 2)));
+        check_listener_panic(Some(queue.clone()));
+        // Hosts with dead letter queue keep messages which failed module
+        assert_eq!(queue.lock().unwrap().get_depth(), 2);
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_restart_backoff() {
+        let policy = RestartPolicy::default();
+        assert_eq!(policy.get_backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.get_backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.get_backoff(40), Duration::from_secs(60));
+    }
+}
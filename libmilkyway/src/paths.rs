@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+///
+/// Environment variable overriding path to configuration file
+///
+pub const CONFIGURATION_VARIABLE: &str = "MWAY_CONFIG";
+
+///
+/// Environment variable overriding path to storage directory
+///
+pub const STORAGE_VARIABLE: &str = "MWAY_STORAGE_PATH";
+
+///
+/// Environment variable overriding path to modules directory
+///
+pub const MODULES_VARIABLE: &str = "MWAY_MODULES_PATH";
+
+///
+/// Name of directory of MilkyWay inside of base directories, e.g. ~/.config/mway
+///
+pub const APPLICATION_DIRECTORY: &str = "mway";
+
+///
+/// Name of configuration file inside of configuration directory
+///
+pub const CONFIGURATION_FILE_NAME: &str = "mwayrc.yml";
+
+///
+/// Environment variable overriding path to configuration file of daemon
+///
+pub const SERVER_CONFIGURATION_VARIABLE: &str = "MWAY_SERVER_CONFIG";
+
+///
+/// Name of configuration file of daemon inside of configuration directory
+///
+pub const SERVER_CONFIGURATION_FILE_NAME: &str = "mway-server.yml";
+
+///
+/// Where a path was taken from. Sources are listed from highest precedence to lowest.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathSource{
+    /** Command line option, e.g. --config **/
+    Argument(&'static str),
+    /** Environment variable, e.g. MWAY_CONFIG **/
+    Environment(&'static str),
+    /** Value from configuration file **/
+    Configuration(&'static str),
+    /** Per-user default derived from base directory, e.g. XDG_DATA_HOME **/
+    Default(&'static str),
+}
+
+impl Display for PathSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathSource::Argument(name) => write!(f, "argument {}", name),
+            PathSource::Environment(name) => write!(f, "environment variable {}", name),
+            PathSource::Configuration(name) => write!(f, "configuration key {}", name),
+            PathSource::Default(base) => write!(f, "default({})", base),
+        }
+    }
+}
+
+///
+/// A path together with the place it was taken from
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedPath{
+    pub path: PathBuf,
+    pub source: PathSource,
+}
+
+///
+/// Kinds of base directories, mapped to XDG base directories on Linux and to their
+/// equivalents on other platforms
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BaseDirectory{
+    Configuration,
+    Data,
+}
+
+///
+/// Resolves paths of configuration, storage and modules. Precedence is:
+/// 1. command line argument(configuration only)
+/// 2. environment variable(MWAY_CONFIG, MWAY_STORAGE_PATH, MWAY_MODULES_PATH)
+/// 3. value from configuration file(storage and modules only)
+/// 4. per-user default: XDG base directories on Linux, ~/Library/Application Support on macOS,
+///    %APPDATA%/%LOCALAPPDATA% on Windows
+///
+pub struct PathResolver{
+    variables: HashMap<String, String>,
+}
+
+impl PathResolver {
+    ///
+    /// Creates a resolver reading variables of current process
+    ///
+    pub fn from_environment() -> PathResolver{
+        PathResolver::with_variables(std::env::vars().collect())
+    }
+
+    ///
+    /// Creates a resolver with provided environment variables
+    ///
+    pub fn with_variables(variables: HashMap<String, String>) -> PathResolver{
+        PathResolver{
+            variables,
+        }
+    }
+
+    ///
+    /// Gets non-empty environment variable
+    ///
+    fn get_variable(&self, name: &str) -> Option<&str>{
+        self.variables.get(name).map(|value| value.as_str()).filter(|value| !value.is_empty())
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    fn get_base_directory(&self, kind: BaseDirectory) -> (PathBuf, &'static str){
+        let (variable, fallback) = match kind {
+            BaseDirectory::Configuration => ("XDG_CONFIG_HOME", ".config"),
+            BaseDirectory::Data => ("XDG_DATA_HOME", ".local/share"),
+        };
+        // XDG specification requires relative paths to be ignored
+        if let Some(path) = self.get_variable(variable).map(Path::new).filter(|path| path.is_absolute()){
+            return (path.to_path_buf(), variable);
+        }
+        (Path::new(self.get_variable("HOME").unwrap_or("/")).join(fallback), "HOME")
+    }
+
+    #[cfg(target_os = "macos")]
+    fn get_base_directory(&self, _kind: BaseDirectory) -> (PathBuf, &'static str){
+        (Path::new(self.get_variable("HOME").unwrap_or("/")).join("Library/Application Support"), "HOME")
+    }
+
+    #[cfg(target_os = "windows")]
+    fn get_base_directory(&self, kind: BaseDirectory) -> (PathBuf, &'static str){
+        let variable = match kind {
+            BaseDirectory::Configuration => "APPDATA",
+            BaseDirectory::Data => "LOCALAPPDATA",
+        };
+        (PathBuf::from(self.get_variable(variable).unwrap_or(".")), variable)
+    }
+
+    fn get_default(&self, kind: BaseDirectory, tail: &[&str]) -> ResolvedPath{
+        let (mut path, base) = self.get_base_directory(kind);
+        path.push(APPLICATION_DIRECTORY);
+        for part in tail{
+            path.push(part);
+        }
+        ResolvedPath{
+            path,
+            source: PathSource::Default(base),
+        }
+    }
+
+    fn resolve(&self, variable: &'static str, configured: Option<(&Path, &'static str)>,
+               default: ResolvedPath) -> ResolvedPath{
+        if let Some(path) = self.get_variable(variable){
+            return ResolvedPath{
+                path: PathBuf::from(path),
+                source: PathSource::Environment(variable),
+            };
+        }
+        if let Some((path, key)) = configured{
+            return ResolvedPath{
+                path: path.to_path_buf(),
+                source: PathSource::Configuration(key),
+            };
+        }
+        default
+    }
+
+    ///
+    /// Resolves path to configuration file
+    ///
+    /// # Arguments
+    /// * argument: Option<&Path>: value of --config option if provided
+    ///
+    pub fn resolve_configuration(&self, argument: Option<&Path>) -> ResolvedPath{
+        if let Some(path) = argument{
+            return ResolvedPath{
+                path: path.to_path_buf(),
+                source: PathSource::Argument("--config"),
+            };
+        }
+        self.resolve(CONFIGURATION_VARIABLE, None,
+                     self.get_default(BaseDirectory::Configuration, &[CONFIGURATION_FILE_NAME]))
+    }
+
+    ///
+    /// Resolves path to configuration file of daemon
+    ///
+    /// # Arguments
+    /// * argument: Option<&Path>: value of --config option of daemon if provided
+    ///
+    pub fn resolve_server_configuration(&self, argument: Option<&Path>) -> ResolvedPath{
+        if let Some(path) = argument{
+            return ResolvedPath{
+                path: path.to_path_buf(),
+                source: PathSource::Argument("--config"),
+            };
+        }
+        self.resolve(SERVER_CONFIGURATION_VARIABLE, None,
+                     self.get_default(BaseDirectory::Configuration, &[SERVER_CONFIGURATION_FILE_NAME]))
+    }
+
+    ///
+    /// Resolves path to storage directory
+    ///
+    /// # Arguments
+    /// * configured: Option<&Path>: `storage_path` from configuration file
+    ///
+    pub fn resolve_storage(&self, configured: Option<&Path>) -> ResolvedPath{
+        self.resolve(STORAGE_VARIABLE, configured.map(|path| (path, "storage_path")),
+                     self.get_default(BaseDirectory::Data, &[]))
+    }
+
+    ///
+    /// Resolves path to modules directory
+    ///
+    /// # Arguments
+    /// * configured: Option<&Path>: `modules_path` from configuration file
+    ///
+    pub fn resolve_modules(&self, configured: Option<&Path>) -> ResolvedPath{
+        self.resolve(MODULES_VARIABLE, configured.map(|path| (path, "modules_path")),
+                     self.get_default(BaseDirectory::Data, &["modules"]))
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolver(variables: &[(&str, &str)]) -> PathResolver{
+        PathResolver::with_variables(variables.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect())
+    }
+
+    #[test]
+    fn test_precedence() {
+        let resolver = resolver(&[(CONFIGURATION_VARIABLE, "/etc/env.yml"), (STORAGE_VARIABLE, "/srv/env"),
+                                  ("HOME", "/home/user")]);
+        let configuration = resolver.resolve_configuration(Some(Path::new("/etc/argument.yml")));
+        assert_eq!(configuration.path, PathBuf::from("/etc/argument.yml"));
+        assert_eq!(configuration.source, PathSource::Argument("--config"));
+        assert_eq!(resolver.resolve_configuration(None).source, PathSource::Environment(CONFIGURATION_VARIABLE));
+        let storage = resolver.resolve_storage(Some(Path::new("/srv/configured")));
+        assert_eq!(storage.path, PathBuf::from("/srv/env"));
+        let modules = resolver.resolve_modules(Some(Path::new("/srv/modules")));
+        assert_eq!(modules.path, PathBuf::from("/srv/modules"));
+        assert_eq!(modules.source, PathSource::Configuration("modules_path"));
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    #[test]
+    fn test_xdg_defaults() {
+        let resolver = resolver(&[("HOME", "/home/user"), ("XDG_DATA_HOME", "/data"), ("XDG_CONFIG_HOME", "relative")]);
+        let configuration = resolver.resolve_configuration(None);
+        assert_eq!(configuration.path, PathBuf::from("/home/user/.config/mway/mwayrc.yml"));
+        assert_eq!(configuration.source, PathSource::Default("HOME"));
+        assert_eq!(resolver.resolve_server_configuration(None).path,
+                   PathBuf::from("/home/user/.config/mway/mway-server.yml"));
+        let modules = resolver.resolve_modules(None);
+        assert_eq!(modules.path, PathBuf::from("/data/mway/modules"));
+        assert_eq!(modules.source, PathSource::Default("XDG_DATA_HOME"));
+    }
+}
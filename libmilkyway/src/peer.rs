@@ -0,0 +1,270 @@
+use std::fmt::{Display, Formatter};
+use std::num::IntErrorKind;
+use std::str::FromStr;
+use crate::services::group::GROUP_ADDRESS_FLAG;
+use crate::services::name::NameService;
+use crate::errors::ErrorCode;
+
+///
+/// ID marking that source or destination of message is not set, it is never given to a peer
+///
+pub const UNASSIGNED_PEER_ID: u128 = 0;
+
+///
+/// Errors of parsing, checking or allocating ID of peer
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum PeerIdError{
+    /** Value is not a number **/
+    Invalid(String),
+    /** Value is a negative number **/
+    Negative(String),
+    /** Value does not fit into 128 bits **/
+    Overflow(String),
+    /** ID is reserved: either unassigned ID or address of a group **/
+    Reserved(u128),
+    /** First ID of allocator is above its last one **/
+    EmptyRange{
+        first: u128,
+        last: u128,
+    },
+    /** Allocator gave out all IDs up to its last one **/
+    Exhausted(u128),
+    /** Name service does not know peer with such name **/
+    UnknownName(String),
+}
+
+impl Display for PeerIdError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PeerIdError::Invalid(value) => write!(f, "'{}' is not an ID of peer", value),
+            PeerIdError::Negative(value) => write!(f, "ID of peer can not be negative, got {}", value),
+            PeerIdError::Overflow(value) => write!(f, "ID of peer {} is too large", value),
+            PeerIdError::Reserved(UNASSIGNED_PEER_ID) => write!(f, "ID {} is reserved for unassigned peers",
+                                                                UNASSIGNED_PEER_ID),
+            PeerIdError::Reserved(id) => write!(f, "ID {} is reserved for addresses of groups, IDs of peers are \
+                                                    up to {}", id, PeerId::MAX),
+            PeerIdError::EmptyRange{ first, last } => write!(f, "range of IDs from {} to {} is empty", first, last),
+            PeerIdError::Exhausted(last) => write!(f, "all IDs up to {} are given out", last),
+            PeerIdError::UnknownName(name) => write!(f, "unknown peer {}", name),
+        }
+    }
+}
+
+impl ErrorCode for PeerIdError {
+    fn get_error_code(&self) -> &'static str {
+        match self {
+            PeerIdError::Invalid(_) => "MW-PEER-0001",
+            PeerIdError::Negative(_) => "MW-PEER-0002",
+            PeerIdError::Overflow(_) => "MW-PEER-0003",
+            PeerIdError::Reserved(_) => "MW-PEER-0004",
+            PeerIdError::EmptyRange{ .. } => "MW-PEER-0005",
+            PeerIdError::Exhausted(_) => "MW-PEER-0006",
+            PeerIdError::UnknownName(_) => "MW-PEER-0007",
+        }
+    }
+}
+
+///
+/// ID of a single peer. Unlike raw u128 it is never an unassigned ID or an address of group,
+/// group addresses are recognised by their own bit(see services::group) instead of sharing
+/// the range of peers.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PeerId(u128);
+
+impl PeerId {
+    ///
+    /// Largest ID of peer, larger values are addresses of groups
+    ///
+    pub const MAX: u128 = GROUP_ADDRESS_FLAG - 1;
+
+    ///
+    /// Checks ID of peer
+    ///
+    /// # Arguments
+    /// * id: u128: raw ID
+    ///
+    /// returns: Result<PeerId, PeerIdError>: ID or PeerIdError::Reserved
+    ///
+    pub fn new(id: u128) -> Result<PeerId, PeerIdError>{
+        match id {
+            UNASSIGNED_PEER_ID => Err(PeerIdError::Reserved(id)),
+            id if id > PeerId::MAX => Err(PeerIdError::Reserved(id)),
+            id => Ok(PeerId(id)),
+        }
+    }
+
+    #[inline]
+    pub fn get(&self) -> u128{
+        self.0
+    }
+}
+
+impl Display for PeerId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<PeerId> for u128 {
+    #[inline]
+    fn from(id: PeerId) -> Self {
+        id.0
+    }
+}
+
+impl TryFrom<i64> for PeerId {
+    type Error = PeerIdError;
+
+    ///
+    /// Checks ID written as signed integer, e.g. in YAML configuration
+    ///
+    fn try_from(id: i64) -> Result<Self, Self::Error> {
+        let id = u128::try_from(id).map_err(|_| PeerIdError::Negative(id.to_string()))?;
+        PeerId::new(id)
+    }
+}
+
+impl FromStr for PeerId {
+    type Err = PeerIdError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let id = value.parse::<u128>().map_err(|error| match error.kind() {
+            IntErrorKind::PosOverflow => PeerIdError::Overflow(value.to_string()),
+            _ if value.starts_with('-') && value[1..].bytes().all(|byte| byte.is_ascii_digit()) =>
+                PeerIdError::Negative(value.to_string()),
+            _ => PeerIdError::Invalid(value.to_string()),
+        })?;
+        PeerId::new(id)
+    }
+}
+
+///
+/// Resolves peer given either by ID or by name. Values which look like numbers are always
+/// treated as IDs, so a mistyped ID is reported instead of being looked up as a name.
+///
+/// # Arguments
+/// * names: &N: name service to look names up in
+/// * peer: &str: ID or name of peer
+///
+/// returns: Result<PeerId, PeerIdError>: ID of peer or reason why it is not resolved
+///
+pub fn resolve_peer<N: NameService + ?Sized>(names: &N, peer: &str) -> Result<PeerId, PeerIdError>{
+    match peer.parse::<PeerId>() {
+        Err(PeerIdError::Invalid(_)) => {
+            let id = names.get_id_by_name(peer).ok_or_else(|| PeerIdError::UnknownName(peer.to_string()))?;
+            PeerId::new(id)
+        }
+        result => result,
+    }
+}
+
+///
+/// Gives out IDs of peers one by one from a range, never wrapping around or
+/// reaching reserved IDs
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeerIdAllocator{
+    /** Next ID to give out, None once last one is given out **/
+    next: Option<u128>,
+    last: u128,
+}
+
+impl PeerIdAllocator {
+    ///
+    /// Creates allocator of IDs from first to last inclusively
+    ///
+    /// # Arguments
+    /// * first: u128: first ID to give out
+    /// * last: u128: last ID to give out
+    ///
+    /// returns: Result<PeerIdAllocator, PeerIdError>: allocator or error if range is empty or
+    /// includes reserved IDs
+    ///
+    pub fn new(first: u128, last: u128) -> Result<PeerIdAllocator, PeerIdError>{
+        PeerId::new(first)?;
+        PeerId::new(last)?;
+        if first > last{
+            return Err(PeerIdError::EmptyRange{ first, last });
+        }
+        Ok(PeerIdAllocator{
+            next: Some(first),
+            last,
+        })
+    }
+
+    ///
+    /// Gives out next ID
+    ///
+    /// returns: Result<PeerId, PeerIdError>: ID or PeerIdError::Exhausted
+    ///
+    pub fn allocate(&mut self) -> Result<PeerId, PeerIdError>{
+        let id = self.next.ok_or(PeerIdError::Exhausted(self.last))?;
+        self.next = id.checked_add(1).filter(|next| *next <= self.last);
+        PeerId::new(id)
+    }
+
+    ///
+    /// Gets how many IDs are left
+    ///
+    pub fn get_remaining(&self) -> u128{
+        self.next.map_or(0, |next| self.last - next + 1)
+    }
+}
+
+impl Default for PeerIdAllocator {
+    ///
+    /// Allocator of all IDs of peers
+    ///
+    fn default() -> Self {
+        PeerIdAllocator{
+            next: Some(UNASSIGNED_PEER_ID + 1),
+            last: PeerId::MAX,
+        }
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::group::get_group_address;
+    use crate::testing::module::StaticNameService;
+
+    #[test]
+    fn test_parse_peer_id() {
+        assert_eq!("42".parse::<PeerId>().map(u128::from), Ok(42));
+        assert_eq!(" 7 ".parse::<PeerId>(), PeerId::new(7));
+        assert_eq!("0".parse::<PeerId>(), Err(PeerIdError::Reserved(0)));
+        assert_eq!("-3".parse::<PeerId>(), Err(PeerIdError::Negative("-3".to_string())));
+        assert_eq!("abc".parse::<PeerId>(), Err(PeerIdError::Invalid("abc".to_string())));
+        let huge = format!("{}0", u128::MAX);
+        assert_eq!(huge.parse::<PeerId>(), Err(PeerIdError::Overflow(huge)));
+        let group = get_group_address(5);
+        assert_eq!(group.to_string().parse::<PeerId>(), Err(PeerIdError::Reserved(group)));
+        assert_eq!(PeerId::try_from(-1i64), Err(PeerIdError::Negative("-1".to_string())));
+        assert_eq!(PeerId::try_from(9i64).unwrap().get(), 9);
+
+        let mut names = StaticNameService::new("test");
+        names.add_name(12, "gateway");
+        assert_eq!(resolve_peer(&names, "gateway").map(u128::from), Ok(12));
+        assert_eq!(resolve_peer(&names, "12").map(u128::from), Ok(12));
+        assert_eq!(resolve_peer(&names, "nobody"), Err(PeerIdError::UnknownName("nobody".to_string())));
+        assert_eq!(resolve_peer(&names, "-12"), Err(PeerIdError::Negative("-12".to_string())));
+    }
+
+    #[test]
+    fn test_peer_id_allocator() {
+        assert_eq!(PeerIdAllocator::new(5, 4), Err(PeerIdError::EmptyRange{ first: 5, last: 4 }));
+        assert!(PeerIdAllocator::new(0, 4).is_err());
+        let mut allocator = PeerIdAllocator::new(PeerId::MAX - 1, PeerId::MAX).unwrap();
+        assert_eq!(allocator.get_remaining(), 2);
+        assert_eq!(allocator.allocate().map(u128::from), Ok(PeerId::MAX - 1));
+        assert_eq!(allocator.allocate().map(u128::from), Ok(PeerId::MAX));
+        assert_eq!(allocator.allocate(), Err(PeerIdError::Exhausted(PeerId::MAX)));
+        assert_eq!(allocator.get_remaining(), 0);
+        assert_eq!(PeerIdAllocator::default().allocate().map(u128::from), Ok(1));
+    }
+}
@@ -2,4 +2,9 @@ pub mod certificate;
 pub mod key;
 pub mod hash;
 pub mod signature;
+pub mod export;
+pub mod kdf;
+pub mod keyexport;
+pub mod shamir;
+pub mod ceremony;
 pub mod impls;
@@ -0,0 +1,353 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use libmilkyway_derive::{Deserializable, Serializable};
+use crate::pki::certificate::Certificate;
+use crate::pki::impls::certificates::falcon1024::Falcon1024RootCertificate;
+use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use crate::pki::impls::keys::falcon1024::Falcon1024SecretKey;
+use crate::pki::keyexport::is_key_pair;
+use crate::pki::shamir::{combine_shares, split_secret, SecretShare, ShamirError};
+use crate::secrets::wipe;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+
+///
+/// Marker at the beginning of files with share of root secret key
+///
+pub const KEY_SHARE_MAGIC: [u8; 8] = *b"MWAYSHRE";
+
+///
+/// Marker at the beginning of files with share decrypted by its custodian
+///
+pub const DECRYPTED_KEY_SHARE_MAGIC: [u8; 8] = *b"MWAYSHRD";
+
+///
+/// Version of format of key share written after magic. MUST be bumped whenever
+/// EncryptedKeyShare or DecryptedKeyShare changes.
+///
+pub const KEY_SHARE_VERSION: u16 = 1;
+
+///
+/// Errors of splitting root secret key between custodians and reconstructing it
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum CeremonyError{
+    /** Root certificate has no secret key to split **/
+    NoSecretKey,
+    /** More custodians than shares of split may have(255) **/
+    TooManyCustodians(usize),
+    Shamir(ShamirError),
+    /** Share is encrypted to another custodian **/
+    CustodianMismatch{ expected: u128, actual: u128 },
+    /** Certificate of custodian carries secret key, only public certificates are accepted for split **/
+    CustodianSecretKey(u128),
+    /** Custodian certificate has no secret key or share was tampered **/
+    DecryptionFailed(u128),
+    /** Share belongs to another root certificate **/
+    RootMismatch,
+    /** Reconstructed key does not match public key of root certificate **/
+    KeyMismatch,
+    /** File is not a key share or is truncated **/
+    Malformed,
+    /** File was written by newer version **/
+    UnsupportedVersion(u16),
+    Io(String),
+}
+
+impl Display for CeremonyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CeremonyError::NoSecretKey => write!(f, "root certificate has no secret key"),
+            CeremonyError::TooManyCustodians(count) =>
+                write!(f, "{} custodians are given, at most 255 are supported", count),
+            CeremonyError::Shamir(error) => write!(f, "{}", error),
+            CeremonyError::CustodianMismatch{ expected, actual } =>
+                write!(f, "share is encrypted to custodian {}, not {}", actual, expected),
+            CeremonyError::CustodianSecretKey(serial) =>
+                write!(f, "certificate of custodian {} carries secret key, only public certificates are accepted", serial),
+            CeremonyError::DecryptionFailed(serial) =>
+                write!(f, "share can not be decrypted with encryption certificate {}", serial),
+            CeremonyError::RootMismatch => write!(f, "share belongs to another root certificate"),
+            CeremonyError::KeyMismatch => write!(f, "shares do not restore key of root certificate"),
+            CeremonyError::Malformed => write!(f, "file is not a key share"),
+            CeremonyError::UnsupportedVersion(version) =>
+                write!(f, "version {} of key share is not supported, latest is {}", version, KEY_SHARE_VERSION),
+            CeremonyError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+// Writes magic, KEY_SHARE_VERSION and content to file readable only by its owner
+fn dump_share(magic: &[u8; 8], content: Serialized, file_name: &str) -> Result<(), CeremonyError>{
+    let mut data = magic.to_vec();
+    data.extend(KEY_SHARE_VERSION.serialize());
+    data.extend(content);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let result = options.open(file_name).and_then(|mut file| std::io::Write::write_all(&mut file, &data));
+    wipe(&mut data);
+    result.map_err(|error| CeremonyError::Io(error.to_string()))
+}
+
+// Parses contents of share file, rejecting unknown formats and trailing data
+fn parse_share<T: Deserializable>(magic: &[u8; 8], data: &[u8]) -> Result<T, CeremonyError>{
+    let data = data.strip_prefix(magic).ok_or(CeremonyError::Malformed)?.to_vec();
+    let (version, offset) = u16::from_serialized(&data).map_err(|_| CeremonyError::Malformed)?;
+    if version != KEY_SHARE_VERSION{
+        return Err(CeremonyError::UnsupportedVersion(version));
+    }
+    let (share, size) = T::from_serialized(&data[offset..].to_vec())
+        .map_err(|_| CeremonyError::Malformed)?;
+    if offset + size != data.len(){
+        return Err(CeremonyError::Malformed);
+    }
+    Ok(share)
+}
+
+///
+/// Share decrypted by its custodian with identity of root it belongs to. It is also what is
+/// encrypted in EncryptedKeyShare, so header of encrypted file can not be swapped without notice.
+///
+/// Custodian decrypts share on own machine and hands it over to ceremony, so neither
+/// secret key of custodian nor other shares are ever present on one host.
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable)]
+pub struct DecryptedKeyShare{
+    /** Fingerprint of root certificate key belongs to **/
+    pub fingerprint: String,
+    pub share: SecretShare,
+}
+
+impl DecryptedKeyShare {
+    ///
+    /// Saves share to file prefixed with DECRYPTED_KEY_SHARE_MAGIC and KEY_SHARE_VERSION.
+    /// File is readable only by its owner and MUST be deleted after ceremony.
+    ///
+    pub fn dump_to_file(&self, file_name: &str) -> Result<(), CeremonyError>{
+        dump_share(&DECRYPTED_KEY_SHARE_MAGIC, self.serialize(), file_name)
+    }
+
+    ///
+    /// Reads share from file, rejecting unknown formats and trailing data
+    ///
+    pub fn read_from_file(path: &Path) -> Result<DecryptedKeyShare, CeremonyError>{
+        let mut data = std::fs::read(path).map_err(|error| CeremonyError::Io(error.to_string()))?;
+        let result = parse_share(&DECRYPTED_KEY_SHARE_MAGIC, &data);
+        wipe(&mut data);
+        result
+    }
+
+    ///
+    /// Overwrites share with zeros, see secrets::wipe
+    ///
+    pub fn wipe(&mut self){
+        self.share.wipe();
+    }
+}
+
+///
+/// Share of root secret key encrypted to encryption certificate of its custodian. No single
+/// custodian can restore the key, `threshold` of them have to bring their shares together.
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable)]
+pub struct EncryptedKeyShare{
+    /** Fingerprint of root certificate key belongs to **/
+    pub fingerprint: String,
+    /** Serial of encryption certificate share is encrypted to **/
+    pub custodian_serial: u128,
+    pub index: u8,
+    pub threshold: u8,
+    pub ciphertext: Vec<u8>,
+}
+
+impl EncryptedKeyShare {
+    ///
+    /// Decrypts share with encryption certificate of custodian. Runs on machine of custodian,
+    /// result is handed over to ceremony(see reconstruct_root_key).
+    ///
+    /// # Arguments
+    /// * custodian: &Kyber1024Certificate: certificate share is encrypted to, must have secret key
+    ///
+    pub fn decrypt(&self, custodian: &Kyber1024Certificate) -> Result<DecryptedKeyShare, CeremonyError>{
+        if self.custodian_serial != custodian.get_serial(){
+            return Err(CeremonyError::CustodianMismatch{ expected: custodian.get_serial(),
+                                                         actual: self.custodian_serial });
+        }
+        let mut plaintext: DecryptedKeyShare = custodian.decrypt(&self.ciphertext)
+            .map_err(|_| CeremonyError::DecryptionFailed(self.custodian_serial))?;
+        if plaintext.fingerprint != self.fingerprint || plaintext.share.index != self.index
+            || plaintext.share.threshold != self.threshold{
+            plaintext.wipe();
+            return Err(CeremonyError::Malformed);
+        }
+        Ok(plaintext)
+    }
+
+    ///
+    /// Saves share to file prefixed with KEY_SHARE_MAGIC and KEY_SHARE_VERSION
+    ///
+    pub fn dump_to_file(&self, file_name: &str) -> Result<(), CeremonyError>{
+        dump_share(&KEY_SHARE_MAGIC, self.serialize(), file_name)
+    }
+
+    ///
+    /// Reads share from file, rejecting unknown formats and trailing data
+    ///
+    pub fn read_from_file(path: &Path) -> Result<EncryptedKeyShare, CeremonyError>{
+        let data = std::fs::read(path).map_err(|error| CeremonyError::Io(error.to_string()))?;
+        Self::from_bytes(&data)
+    }
+
+    ///
+    /// Parses contents of key share file
+    ///
+    pub fn from_bytes(data: &[u8]) -> Result<EncryptedKeyShare, CeremonyError>{
+        parse_share(&KEY_SHARE_MAGIC, data)
+    }
+}
+
+///
+/// Splits secret key of root certificate into one share per custodian
+///
+/// # Arguments
+/// * root: &Falcon1024RootCertificate: root certificate with secret key
+/// * threshold: u8: count of shares required to restore key
+/// * custodians: &[Kyber1024Certificate]: public encryption certificates shares are encrypted to,
+///   certificates with secret keys are rejected
+///
+/// returns: Result<Vec<EncryptedKeyShare>, CeremonyError>: shares in order of custodians
+///
+pub fn split_root_key(root: &Falcon1024RootCertificate, threshold: u8,
+                      custodians: &[Kyber1024Certificate]) -> Result<Vec<EncryptedKeyShare>, CeremonyError>{
+    let secret_key = root.get_secret_key().ok_or(CeremonyError::NoSecretKey)?;
+    if let Some(custodian) = custodians.iter().find(|custodian| custodian.get_secret_key().is_some()){
+        return Err(CeremonyError::CustodianSecretKey(custodian.get_serial()));
+    }
+    let count = u8::try_from(custodians.len()).map_err(|_| CeremonyError::TooManyCustodians(custodians.len()))?;
+    let mut secret = secret_key.serialize();
+    let shares = split_secret(&secret, threshold, count);
+    wipe(&mut secret);
+    let shares = shares.map_err(CeremonyError::Shamir)?;
+    let fingerprint = root.get_fingerprint();
+    Ok(shares.into_iter().zip(custodians.iter()).map(|(share, custodian)| {
+        let mut plaintext = DecryptedKeyShare{
+            fingerprint: fingerprint.clone(),
+            share,
+        };
+        let share = EncryptedKeyShare{
+            fingerprint: fingerprint.clone(),
+            custodian_serial: custodian.get_serial(),
+            index: plaintext.share.index,
+            threshold,
+            ciphertext: custodian.encrypt(&plaintext).expect("Kyber1024 encryption does not fail"),
+        };
+        plaintext.wipe();
+        share
+    }).collect())
+}
+
+///
+/// Restores secret key of root certificate from shares. Result is meant for signing in memory
+/// and MUST NOT be stored: once it is used, its key must be wiped(see Falcon1024SecretKey::wipe),
+/// so key exists only as shares again.
+///
+/// # Arguments
+/// * root: &Falcon1024RootCertificate: root certificate without secret key
+/// * shares: &[DecryptedKeyShare]: shares decrypted by custodians, at least threshold of them
+///
+/// returns: Result<Falcon1024RootCertificate, CeremonyError>: root certificate with secret key
+///
+pub fn reconstruct_root_key(root: &Falcon1024RootCertificate,
+                            shares: &[DecryptedKeyShare]) -> Result<Falcon1024RootCertificate, CeremonyError>{
+    let fingerprint = root.get_fingerprint();
+    if shares.iter().any(|share| share.fingerprint != fingerprint){
+        return Err(CeremonyError::RootMismatch);
+    }
+    let shares: Vec<SecretShare> = shares.iter().map(|share| share.share.clone()).collect();
+    let secret = combine_shares(&shares);
+    shares.into_iter().for_each(|mut share| share.wipe());
+    let mut secret = secret.map_err(CeremonyError::Shamir)?;
+    let parsed = Falcon1024SecretKey::from_serialized(&secret);
+    let length = secret.len();
+    wipe(&mut secret);
+    let (mut secret_key, size) = parsed.map_err(|_| CeremonyError::KeyMismatch)?;
+    if size != length || !is_key_pair(&root.public_key, &secret_key){
+        secret_key.wipe();
+        return Err(CeremonyError::KeyMismatch);
+    }
+    let mut restored = root.clone();
+    restored.secret_key = Some(secret_key);
+    Ok(restored)
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pki::hash::HashType;
+    use crate::testing::certificate::{generate_test_certificates, test_certificates};
+
+    #[test]
+    fn test_root_key_ceremony() {
+        let certificates = test_certificates();
+        let custodians: Vec<Kyber1024Certificate> = ["alice", "bob", "carol"].iter().enumerate().map(|(serial, seed)| {
+            let mut custodian = generate_test_certificates(seed).encryption;
+            custodian.serial_number = 100 + serial as u128;
+            custodian
+        }).collect();
+        let public_custodians: Vec<Kyber1024Certificate> = custodians.iter().map(|custodian| {
+            let mut custodian = custodian.clone();
+            custodian.secret_key = None;
+            custodian
+        }).collect();
+        assert_eq!(split_root_key(&certificates.root, 2, &custodians), Err(CeremonyError::CustodianSecretKey(100)));
+        let shares = split_root_key(&certificates.root, 2, &public_custodians).unwrap();
+        let public_only = certificates.root.clone_without_sk();
+        assert_eq!(split_root_key(&public_only, 2, &public_custodians), Err(CeremonyError::NoSecretKey));
+
+        let file = std::env::temp_dir().join(format!("milkyway-share-{}.share", rand::random::<u64>()));
+        shares[2].dump_to_file(file.to_str().unwrap()).unwrap();
+        let loaded = EncryptedKeyShare::read_from_file(&file).unwrap();
+        std::fs::remove_file(file).unwrap();
+        assert_eq!(loaded, shares[2]);
+
+        // Each custodian decrypts own share and hands it over
+        let third = loaded.decrypt(&custodians[2]).unwrap();
+        let file = std::env::temp_dir().join(format!("milkyway-share-{}.share", rand::random::<u64>()));
+        third.dump_to_file(file.to_str().unwrap()).unwrap();
+        let third = DecryptedKeyShare::read_from_file(&file).unwrap();
+        assert_eq!(EncryptedKeyShare::read_from_file(&file), Err(CeremonyError::Malformed));
+        std::fs::remove_file(file).unwrap();
+        let first = shares[0].decrypt(&custodians[0]).unwrap();
+        let mut restored = reconstruct_root_key(&public_only, &[third.clone(), first.clone()]).unwrap();
+        assert!(restored.get_secret_key().unwrap() == certificates.root.get_secret_key().unwrap());
+        let signature = restored.sign_data(&"signing certificate".to_string(), HashType::None).unwrap();
+        assert!(public_only.verify_signature(&"signing certificate".to_string(), &signature));
+        restored.secret_key.as_mut().unwrap().wipe();
+        assert!(restored.get_secret_key().unwrap() != certificates.root.get_secret_key().unwrap());
+        let mut wiped = first.clone();
+        wiped.wipe();
+        assert!(wiped.share.data.iter().all(|byte| *byte == 0));
+
+        assert_eq!(reconstruct_root_key(&public_only, std::slice::from_ref(&third)).err(),
+                   Some(CeremonyError::Shamir(ShamirError::NotEnoughShares{ threshold: 2, provided: 1 })));
+        let other_root = generate_test_certificates("other").root.clone_without_sk();
+        assert_eq!(reconstruct_root_key(&other_root, &[third.clone(), first.clone()]).err(),
+                   Some(CeremonyError::RootMismatch));
+        // Shares claiming to belong to other root do not restore its key
+        let foreign: Vec<DecryptedKeyShare> = [third, first].into_iter().map(|mut share| {
+            share.fingerprint = other_root.get_fingerprint();
+            share
+        }).collect();
+        assert_eq!(reconstruct_root_key(&other_root, &foreign).err(), Some(CeremonyError::KeyMismatch));
+        assert_eq!(shares[0].decrypt(&custodians[1]),
+                   Err(CeremonyError::CustodianMismatch{ expected: 101, actual: 100 }));
+        assert_eq!(shares[0].decrypt(&public_custodians[0]), Err(CeremonyError::DecryptionFailed(100)));
+        let mut tampered = shares[1].clone();
+        tampered.ciphertext[40] ^= 1;
+        assert_eq!(tampered.decrypt(&custodians[1]), Err(CeremonyError::DecryptionFailed(101)));
+    }
+}
@@ -1,12 +1,28 @@
 use libmilkyway_derive::{EnumDeserializable, EnumSerializable};
 use crate::pki::hash::{CryptoHashable, HashType};
 use crate::pki::impls::CryptoError;
+use crate::pki::certificate::metadata::CertificateMetadata;
 use crate::pki::key::CryptoKey;
-use crate::pki::signature::Signature;
+use crate::pki::signature::{Signature, SignatureContext};
 use crate::serialization::deserializable::Deserializable;
 use crate::serialization::error::SerializationError;
 use crate::serialization::serializable::{Serializable, Serialized};
 
+///
+/// Registry of certificate flags: names, letters, user-defined range, parsing and formatting
+///
+pub mod flags;
+
+///
+/// Named profiles predefining flags and naming convention of generated certificates
+///
+pub mod profile;
+
+///
+/// Optional description, owner and tags of certificates and queries over them
+///
+pub mod metadata;
+
 ///
 /// Ceritificate types
 ///
@@ -104,7 +120,27 @@ pub trait  Certificate<PK: CryptoKey, SK: CryptoKey>: Serializable + Deserializa
     }
 
     ///
-    /// Verifies signature of data
+    /// Signs piece of data together with context: serial of certificate, current time and label
+    /// telling what signature is made for, so verifiers know which certificate to use
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: Data to sign
+    /// * `hash_type`: Hash type to use during signature
+    /// * `label`: Purpose of signature, e.g. FILE_SIGNATURE_LABEL
+    ///
+    /// returns: Result<Signature, CryptoError>
+    ///
+    fn sign_data_with_context<T: Serializable + CryptoHashable>(&self, data: &T, hash_type: HashType,
+                                                                label: &str) -> Result<Signature, CryptoError>{
+        let context = SignatureContext::new(self.get_serial(), label);
+        let mut signature = self.sign_data(&context.bind(data), hash_type)?;
+        signature.context = Some(context);
+        Ok(signature)
+    }
+
+    ///
+    /// Verifies signature of data. Signature with context is valid only if it was made by this certificate.
     ///
     /// # Arguments
     ///
@@ -120,7 +156,11 @@ pub trait  Certificate<PK: CryptoKey, SK: CryptoKey>: Serializable + Deserializa
             panic!("Trying to use encipherment certificate for signature verification");
         }
         let key = self.get_public_key();
-        key.verify_signature(data, signature)
+        match &signature.context {
+            Some(context) => context.signer_serial == self.get_serial()
+                && key.verify_signature(&context.bind(data), signature),
+            None => key.verify_signature(data, signature),
+        }
     }
 
     ///
@@ -220,6 +260,23 @@ pub trait  Certificate<PK: CryptoKey, SK: CryptoKey>: Serializable + Deserializa
         let current_flags = self.get_flags();
         self.set_flags(current_flags & (!mask));
     }
+
+    ///
+    /// Gets metadata of certificate, empty for certificates which can not have it(e.g. root)
+    ///
+    #[inline]
+    fn get_metadata(&self) -> CertificateMetadata{
+        CertificateMetadata::default()
+    }
+
+    ///
+    /// Gets fingerprint of certificate: hex-encoded SHA512 hash of its public key.
+    /// Unlike serial, fingerprint can not be reused by another key.
+    ///
+    fn get_fingerprint(&self) -> String{
+        let hash = self.get_public_key().crypto_hash(HashType::SHA512);
+        hash.hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
 }
 
 ///
@@ -262,3 +319,39 @@ pub const FLAG_NO_WRITE: u128 = 1<<6;
 /// 
 pub const FLAG_NO_READ: u128 = 1<<7;
 
+///
+/// Flag that host with this certificate may record messages passing through its transport
+///
+pub const FLAG_TRANSPORT_TAP: u128 = 1<<8;
+
+///
+/// Flag that holder of this certificate must pass an additional authentication factor(e.g. TOTP)
+///
+pub const FLAG_REQUIRE_2FA: u128 = 1<<9;
+
+///
+/// Flag that holder of this certificate may use certificate service of a broker remotely
+///
+pub const FLAG_REMOTE_CERTIFICATES: u128 = 1<<10;
+
+///
+/// Flag that holder of this certificate may change bandwidth caps of a host at runtime
+///
+pub const FLAG_TRANSPORT_SHAPING: u128 = 1<<11;
+
+///
+/// Marks on wire that metadata follows flags of certificate. It is set and cleared by
+/// serialization, so certificates never have it in their flags.
+///
+pub const FLAG_HAS_METADATA: u128 = 1<<12;
+
+///
+/// Marks on wire that validity(issue and expiry time) follows metadata of certificate.
+/// It is set and cleared by serialization like FLAG_HAS_METADATA.
+///
+pub const FLAG_HAS_VALIDITY: u128 = 1<<13;
+
+///
+/// Flag that modules signed by this certificate may be trusted by hosts loading them
+///
+pub const FLAG_SIGN_CODE: u128 = 1<<14;
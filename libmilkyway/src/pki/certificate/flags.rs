@@ -0,0 +1,234 @@
+use std::fmt::{Display, Formatter};
+use crate::errors::ErrorCode;
+use crate::pki::certificate::{FLAG_CLIENT_CERT, FLAG_NO_READ, FLAG_NO_WRITE, FLAG_REMOTE_CERTIFICATES,
+                              FLAG_REQUIRE_2FA, FLAG_ROOT_CERT, FLAG_SERVER_CERT, FLAG_SIGN_CERTS, FLAG_SIGN_CODE,
+                              FLAG_SIGN_MESSAGES, FLAG_TRANSPORT_SHAPING, FLAG_TRANSPORT_TAP, FLAG_USER_CERT};
+
+///
+/// First bit of range reserved for user-defined flags, bits below it belong to MilkyWay
+///
+pub const USER_DEFINED_FLAGS_FIRST_BIT: u32 = 64;
+
+///
+/// Mask of bits reserved for user-defined flags
+///
+pub const USER_DEFINED_FLAGS_MASK: u128 = !0u128 << USER_DEFINED_FLAGS_FIRST_BIT;
+
+///
+/// Prefix of names of user-defined flags, e.g. `user-3` for bit 67
+///
+pub const USER_DEFINED_FLAG_PREFIX: &str = "user-";
+
+///
+/// Description of a certificate flag
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlagDescription{
+    pub mask: u128,
+    /** Name used in CLI arguments, e.g. `sign-messages` **/
+    pub name: &'static str,
+    /** Letter used in tables **/
+    pub letter: char,
+    pub description: &'static str,
+    /** Whether letter is shown when flag is NOT set, e.g. W for writable certificates **/
+    pub letter_when_unset: bool,
+}
+
+///
+/// All flags known to MilkyWay in order of their letters in tables
+///
+pub const CERTIFICATE_FLAGS: &[FlagDescription] = &[
+    FlagDescription{ mask: FLAG_SIGN_CERTS, name: "sign-certs", letter: 'G',
+        description: "Can sign other certificates", letter_when_unset: false },
+    FlagDescription{ mask: FLAG_SIGN_MESSAGES, name: "sign-messages", letter: 'M',
+        description: "Can sign data and messages", letter_when_unset: false },
+    FlagDescription{ mask: FLAG_NO_WRITE, name: "no-write", letter: 'W',
+        description: "Commands signed by certificate can not write anything", letter_when_unset: true },
+    FlagDescription{ mask: FLAG_NO_READ, name: "no-read", letter: 'R',
+        description: "Commands signed by certificate can not read state", letter_when_unset: true },
+    FlagDescription{ mask: FLAG_CLIENT_CERT, name: "client-cert", letter: 'C',
+        description: "Used by client machine", letter_when_unset: false },
+    FlagDescription{ mask: FLAG_USER_CERT, name: "user-cert", letter: 'U',
+        description: "Used by user", letter_when_unset: false },
+    FlagDescription{ mask: FLAG_SERVER_CERT, name: "server-cert", letter: 'S',
+        description: "Used by server/broker", letter_when_unset: false },
+    FlagDescription{ mask: FLAG_ROOT_CERT, name: "root", letter: 'O',
+        description: "Root certificate", letter_when_unset: false },
+    FlagDescription{ mask: FLAG_TRANSPORT_TAP, name: "transport-tap", letter: 'T',
+        description: "Host may record messages passing through its transport", letter_when_unset: false },
+    FlagDescription{ mask: FLAG_REQUIRE_2FA, name: "require-2fa", letter: '2',
+        description: "Holder must pass an additional authentication factor", letter_when_unset: false },
+    FlagDescription{ mask: FLAG_REMOTE_CERTIFICATES, name: "remote-certificates", letter: 'P',
+        description: "Holder may use certificate service of a broker remotely", letter_when_unset: false },
+    FlagDescription{ mask: FLAG_TRANSPORT_SHAPING, name: "transport-shaping", letter: 'B',
+        description: "Holder may change bandwidth caps of a host at runtime", letter_when_unset: false },
+    FlagDescription{ mask: FLAG_SIGN_CODE, name: "sign-code", letter: 'X',
+        description: "Can sign modules loaded by hosts", letter_when_unset: false },
+];
+
+///
+/// Errors of parsing and validating flags
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum FlagError{
+    /** Name is neither known flag nor user-defined one **/
+    UnknownFlag(String),
+    /** Bits which are not assigned to any known flag and are not user-defined **/
+    UnknownBits(u128),
+    /** User-defined flags are used where they are not allowed **/
+    UserDefinedNotAllowed(u128),
+}
+
+impl Display for FlagError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlagError::UnknownFlag(name) => write!(f, "Unknown flag '{}'", name),
+            FlagError::UnknownBits(bits) => write!(f, "Unknown flag bits {:#x}", bits),
+            FlagError::UserDefinedNotAllowed(bits) => write!(f, "User-defined flags {:#x} are not allowed", bits),
+        }
+    }
+}
+
+impl ErrorCode for FlagError {
+    fn get_error_code(&self) -> &'static str {
+        match self {
+            FlagError::UnknownFlag(_) => "MW-CERT-0301",
+            FlagError::UnknownBits(_) => "MW-CERT-0302",
+            FlagError::UserDefinedNotAllowed(_) => "MW-CERT-0303",
+        }
+    }
+}
+
+///
+/// Gets mask of all flags known to MilkyWay
+///
+pub fn get_known_flags_mask() -> u128{
+    CERTIFICATE_FLAGS.iter().fold(0, |mask, flag| mask | flag.mask)
+}
+
+///
+/// Finds description of flag by name
+///
+pub fn find_flag(name: &str) -> Option<&'static FlagDescription>{
+    CERTIFICATE_FLAGS.iter().find(|flag| flag.name == name)
+}
+
+///
+/// Parses name of a single flag, known or user-defined
+///
+fn parse_flag(name: &str) -> Result<u128, FlagError>{
+    if let Some(flag) = find_flag(name){
+        return Ok(flag.mask);
+    }
+    name.strip_prefix(USER_DEFINED_FLAG_PREFIX)
+        .and_then(|index| index.parse::<u32>().ok())
+        .filter(|index| *index < 128 - USER_DEFINED_FLAGS_FIRST_BIT)
+        .map(|index| 1u128 << (USER_DEFINED_FLAGS_FIRST_BIT + index))
+        .ok_or_else(|| FlagError::UnknownFlag(name.to_string()))
+}
+
+///
+/// Parses comma-separated flag names, e.g. `sign-messages,client-cert,user-2`
+///
+/// # Arguments
+/// * value: &str: flag names, empty string means no flags
+/// * allow_user_defined: bool: whether user-defined flags are accepted
+///
+/// returns: Result<u128, FlagError>: flags mask or error
+///
+pub fn parse_flags(value: &str, allow_user_defined: bool) -> Result<u128, FlagError>{
+    let mut result = 0;
+    for name in value.split(',').map(|name| name.trim()).filter(|name| !name.is_empty()){
+        result |= parse_flag(name)?;
+    }
+    validate_flags(result, allow_user_defined)?;
+    Ok(result)
+}
+
+///
+/// Checks that all set bits belong to known or(if allowed) user-defined flags
+///
+/// # Arguments
+/// * flags: u128: flags to validate
+/// * allow_user_defined: bool: whether user-defined flags are accepted
+///
+pub fn validate_flags(flags: u128, allow_user_defined: bool) -> Result<(), FlagError>{
+    let user_defined = flags & USER_DEFINED_FLAGS_MASK;
+    let unknown = flags & !USER_DEFINED_FLAGS_MASK & !get_known_flags_mask();
+    if unknown != 0{
+        return Err(FlagError::UnknownBits(unknown));
+    }
+    if user_defined != 0 && !allow_user_defined{
+        return Err(FlagError::UserDefinedNotAllowed(user_defined));
+    }
+    Ok(())
+}
+
+///
+/// Formats flags as comma-separated names, the reverse of parse_flags.
+/// Bits not assigned to any flag are shown as hex number.
+///
+pub fn format_flags(flags: u128) -> String{
+    let mut names: Vec<String> = CERTIFICATE_FLAGS.iter()
+        .filter(|flag| flags & flag.mask != 0)
+        .map(|flag| flag.name.to_string())
+        .collect();
+    for bit in USER_DEFINED_FLAGS_FIRST_BIT..128{
+        if flags & (1u128 << bit) != 0{
+            names.push(format!("{}{}", USER_DEFINED_FLAG_PREFIX, bit - USER_DEFINED_FLAGS_FIRST_BIT));
+        }
+    }
+    let unknown = flags & !USER_DEFINED_FLAGS_MASK & !get_known_flags_mask();
+    if unknown != 0{
+        names.push(format!("{:#x}", unknown));
+    }
+    names.join(",")
+}
+
+///
+/// Formats flags as letters for tables, e.g. `GMWR`. User-defined flags are shown as `+`.
+///
+pub fn format_flags_short(flags: u128) -> String{
+    let mut result: String = CERTIFICATE_FLAGS.iter()
+        .filter(|flag| (flags & flag.mask != 0) != flag.letter_when_unset)
+        .map(|flag| flag.letter)
+        .collect();
+    if flags & USER_DEFINED_FLAGS_MASK != 0{
+        result.push('+');
+    }
+    result
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format_flags() {
+        let flags = parse_flags("sign-messages, client-cert,user-2", true).unwrap();
+        assert_eq!(flags, FLAG_SIGN_MESSAGES | FLAG_CLIENT_CERT | (1u128 << 66));
+        assert_eq!(format_flags(flags), "sign-messages,client-cert,user-2");
+        assert_eq!(parse_flags(&format_flags(flags), true), Ok(flags));
+        assert_eq!(parse_flags("", false), Ok(0));
+        assert_eq!(parse_flags("sign-everything", true), Err(FlagError::UnknownFlag("sign-everything".to_string())));
+        assert_eq!(parse_flags("user-64", true), Err(FlagError::UnknownFlag("user-64".to_string())));
+        assert_eq!(parse_flags("user-0", false), Err(FlagError::UserDefinedNotAllowed(1u128 << 64)));
+    }
+
+    #[test]
+    fn test_validate_and_short_format() {
+        assert!(validate_flags(FLAG_SIGN_CERTS | FLAG_REQUIRE_2FA, false).is_ok());
+        assert_eq!(validate_flags(1u128 << 40, true), Err(FlagError::UnknownBits(1u128 << 40)));
+        assert_eq!(format_flags_short(FLAG_SIGN_CERTS | FLAG_SIGN_MESSAGES), "GMWR");
+        assert_eq!(format_flags_short(FLAG_NO_WRITE | FLAG_NO_READ | FLAG_TRANSPORT_TAP | (1u128 << 70)), "T+");
+        // Every flag has unique bit, name and letter
+        for (index, flag) in CERTIFICATE_FLAGS.iter().enumerate(){
+            assert_eq!(flag.mask.count_ones(), 1);
+            assert_eq!(flag.mask & USER_DEFINED_FLAGS_MASK, 0);
+            for other in CERTIFICATE_FLAGS[index + 1..].iter(){
+                assert!(flag.mask != other.mask && flag.name != other.name && flag.letter != other.letter);
+            }
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use libmilkyway_derive::{Describe, Deserializable, Serializable};
+use crate::pki::certificate::{FLAG_HAS_METADATA, FLAG_HAS_VALIDITY};
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::schema::{json_string, Describe, SchemaRegistry, TypeSchema};
+use crate::serialization::serializable::{Serializable, Serialized};
+
+///
+/// Optional information about certificate, covered by its signature like the rest of it
+///
+#[derive(Clone, Debug, Default, PartialEq, Serializable, Deserializable, Describe)]
+pub struct CertificateMetadata{
+    /** Free-text description **/
+    pub description: String,
+    /** Person or team responsible for certificate **/
+    pub owner: String,
+    /** Key-value tags sorted by key, so signed bytes do not depend on order of setting them **/
+    pub tags: Vec<(String, String)>,
+    /** When certificate was issued and when it expires, None for certificates issued without it **/
+    pub validity: Option<CertificateValidity>,
+}
+
+///
+/// Lifetime of certificate, seconds since UNIX epoch
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serializable, Deserializable, Describe)]
+pub struct CertificateValidity{
+    /** Orders rotations of certificate: only a later issue replaces current one **/
+    pub issued_at: u64,
+    /** Time certificate is not valid since, None if it does not expire **/
+    pub expires_at: Option<u64>,
+}
+
+impl CertificateValidity {
+    ///
+    /// Creates validity of certificate issued now
+    ///
+    /// # Arguments
+    /// * lifetime: Option<u64>: seconds certificate is valid for, None if it does not expire
+    ///
+    pub fn issued_now(lifetime: Option<u64>) -> CertificateValidity{
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+            .expect("Time went backwards").as_secs();
+        CertificateValidity{
+            issued_at: now,
+            expires_at: lifetime.map(|lifetime| now.saturating_add(lifetime)),
+        }
+    }
+}
+
+impl CertificateMetadata {
+    #[inline]
+    pub fn new() -> CertificateMetadata{
+        CertificateMetadata::default()
+    }
+
+    pub fn set_description(&mut self, description: &str) -> &mut Self{
+        self.description = description.to_string();
+        self
+    }
+
+    pub fn set_owner(&mut self, owner: &str) -> &mut Self{
+        self.owner = owner.to_string();
+        self
+    }
+
+    ///
+    /// Sets tag replacing its previous value
+    ///
+    pub fn set_tag(&mut self, key: &str, value: &str) -> &mut Self{
+        match self.tags.binary_search_by(|(tag, _)| tag.as_str().cmp(key)) {
+            Ok(index) => self.tags[index].1 = value.to_string(),
+            Err(index) => self.tags.insert(index, (key.to_string(), value.to_string())),
+        }
+        self
+    }
+
+    pub fn get_tag(&self, key: &str) -> Option<&str>{
+        self.tags.iter().find(|(tag, _)| tag == key).map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_validity(&mut self, validity: CertificateValidity) -> &mut Self{
+        self.validity = Some(validity);
+        self
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool{
+        !self.has_text() && self.validity.is_none()
+    }
+
+    // Whether description, owner or tags are set, they are written under FLAG_HAS_METADATA
+    #[inline]
+    fn has_text(&self) -> bool{
+        !self.description.is_empty() || !self.owner.is_empty() || !self.tags.is_empty()
+    }
+
+    ///
+    /// Formats tags as `key:value` pairs separated by commas, as they are passed to CLI
+    ///
+    pub fn format_tags(&self) -> String{
+        self.tags.iter().map(|(key, value)| format!("{}:{}", key, value)).collect::<Vec<String>>().join(",")
+    }
+
+    ///
+    /// Parses tags formatted by format_tags
+    ///
+    /// returns: Result<&mut Self, String>: metadata with tags or malformed pair
+    ///
+    pub fn parse_tags(&mut self, tags: &str) -> Result<&mut Self, String>{
+        for pair in tags.split(',').filter(|pair| !pair.is_empty()){
+            match pair.split_once(':') {
+                Some((key, value)) if !key.is_empty() => self.set_tag(key, value),
+                _ => return Err(pair.to_string()),
+            };
+        }
+        Ok(self)
+    }
+
+    pub fn to_json(&self) -> String{
+        let tags: Vec<String> = self.tags.iter()
+            .map(|(key, value)| format!("{}:{}", json_string(key), json_string(value)))
+            .collect();
+        format!("{{\"description\":{},\"owner\":{},\"tags\":{{{}}}}}", json_string(&self.description),
+                json_string(&self.owner), tags.join(","))
+    }
+}
+
+///
+/// Serializes flags of certificate followed by its metadata. Description, owner and tags are
+/// written only if one of them is set and FLAG_HAS_METADATA tells they are there, validity is
+/// written after them only if it is set and FLAG_HAS_VALIDITY tells it is there. So certificates
+/// without metadata keep the layout(and signatures) they had before metadata was introduced.
+///
+pub(crate) fn serialize_flags_and_metadata(flags: u128, metadata: &CertificateMetadata) -> Serialized{
+    let mut flags = flags & !(FLAG_HAS_METADATA | FLAG_HAS_VALIDITY);
+    if metadata.has_text(){
+        flags |= FLAG_HAS_METADATA;
+    }
+    if metadata.validity.is_some(){
+        flags |= FLAG_HAS_VALIDITY;
+    }
+    let mut result = flags.serialize();
+    if metadata.has_text(){
+        result.extend(metadata.description.serialize());
+        result.extend(metadata.owner.serialize());
+        result.extend(metadata.tags.serialize());
+    }
+    if let Some(validity) = &metadata.validity{
+        result.extend(validity.serialize());
+    }
+    result
+}
+
+///
+/// Deserializes what serialize_flags_and_metadata wrote, FLAG_HAS_METADATA and FLAG_HAS_VALIDITY
+/// are not returned
+///
+pub(crate) fn deserialize_flags_and_metadata(serialized: &Serialized)
+    -> Result<(u128, CertificateMetadata, usize), SerializationError>{
+    let (flags, mut offset) = u128::from_serialized(serialized)?;
+    let mut metadata = CertificateMetadata::default();
+    if flags & FLAG_HAS_METADATA != 0{
+        let (description, size) = String::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (owner, size) = String::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (tags, size) = Vec::<(String, String)>::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        metadata.description = description;
+        metadata.owner = owner;
+        metadata.tags = tags;
+    }
+    if flags & FLAG_HAS_VALIDITY != 0{
+        let (validity, size) = CertificateValidity::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        metadata.validity = Some(validity);
+    }
+    Ok((flags & !(FLAG_HAS_METADATA | FLAG_HAS_VALIDITY), metadata, offset))
+}
+
+///
+/// Query of certificates by metadata, empty fields match everything
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CertificateQuery{
+    /** Substring of name **/
+    pub name: Option<String>,
+    pub owner: Option<String>,
+    /** Tags which must be set, value None matches any value **/
+    pub tags: Vec<(String, Option<String>)>,
+    /** Substring of name, owner or description, case-insensitive **/
+    pub text: Option<String>,
+}
+
+impl CertificateQuery {
+    #[inline]
+    pub fn new() -> CertificateQuery{
+        CertificateQuery::default()
+    }
+
+    pub fn set_name(&mut self, name: &str) -> &mut Self{
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn set_owner(&mut self, owner: &str) -> &mut Self{
+        self.owner = Some(owner.to_string());
+        self
+    }
+
+    pub fn add_tag(&mut self, key: &str, value: Option<&str>) -> &mut Self{
+        self.tags.push((key.to_string(), value.map(|value| value.to_string())));
+        self
+    }
+
+    pub fn set_text(&mut self, text: &str) -> &mut Self{
+        self.text = Some(text.to_lowercase());
+        self
+    }
+
+    ///
+    /// Checks whether certificate with given name and metadata matches query
+    ///
+    pub fn matches(&self, name: &str, metadata: &CertificateMetadata) -> bool{
+        if self.name.as_ref().is_some_and(|pattern| !name.contains(pattern.as_str())){
+            return false;
+        }
+        if self.owner.as_ref().is_some_and(|owner| *owner != metadata.owner){
+            return false;
+        }
+        let has_tags = self.tags.iter().all(|(key, value)| match (metadata.get_tag(key), value) {
+            (Some(actual), Some(expected)) => actual == expected,
+            (Some(_), None) => true,
+            (None, _) => false,
+        });
+        if !has_tags{
+            return false;
+        }
+        match &self.text {
+            Some(text) => [name, &metadata.owner, &metadata.description].iter()
+                .any(|field| field.to_lowercase().contains(text.as_str())),
+            None => true,
+        }
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pki::certificate::Certificate;
+    use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+    use crate::testing::certificate::test_certificates;
+
+    #[test]
+    fn test_metadata_serialization() {
+        let certificates = test_certificates();
+        let mut signing = certificates.signing.clone();
+        // Certificates without metadata keep their layout and signatures
+        let legacy = signing.serialize();
+        assert!(certificates.root.verify_signature(&signing.clone_without_signature_and_sk(),
+                                                   signing.signature.as_ref().unwrap()));
+
+        signing.metadata.set_owner("alice").set_tag("env", "prod").set_tag("app", "web");
+        assert_eq!(signing.metadata.format_tags(), "app:web,env:prod");
+        let serialized = signing.serialize();
+        assert!(serialized.len() > legacy.len());
+        let (deserialized, size) = Falcon1024Certificate::from_serialized(&serialized).unwrap();
+        assert_eq!(size, serialized.len());
+        assert_eq!(deserialized.metadata, signing.metadata);
+        assert_eq!(deserialized.flags, certificates.signing.flags);
+        // Metadata is covered by signature
+        assert!(!certificates.root.verify_signature(&signing.clone_without_signature_and_sk(),
+                                                    signing.signature.as_ref().unwrap()));
+
+        let mut validity_only = certificates.signing.clone();
+        validity_only.metadata.set_validity(CertificateValidity{ issued_at: 10, expires_at: Some(20) });
+        signing.metadata.set_validity(CertificateValidity{ issued_at: 10, expires_at: None });
+        for certificate in [validity_only, signing]{
+            let serialized = certificate.serialize();
+            let (deserialized, size) = Falcon1024Certificate::from_serialized(&serialized).unwrap();
+            assert_eq!(size, serialized.len());
+            assert_eq!(deserialized.metadata, certificate.metadata);
+            assert_eq!(deserialized.flags, certificates.signing.flags);
+        }
+    }
+
+    #[test]
+    fn test_query() {
+        let mut metadata = CertificateMetadata::new();
+        metadata.set_owner("alice").set_description("Web frontend").parse_tags("env:prod,app:web").unwrap();
+        assert_eq!(metadata.get_tag("env"), Some("prod"));
+        assert!(CertificateMetadata::new().parse_tags("env").is_err());
+        assert!(CertificateQuery::new().matches("web-1", &metadata));
+        assert!(CertificateQuery::new().set_owner("alice").add_tag("env", Some("prod")).matches("web-1", &metadata));
+        assert!(CertificateQuery::new().add_tag("app", None).set_name("web").matches("web-1", &metadata));
+        assert!(CertificateQuery::new().set_text("FRONTEND").matches("web-1", &metadata));
+        assert!(!CertificateQuery::new().set_owner("bob").matches("web-1", &metadata));
+        assert!(!CertificateQuery::new().add_tag("env", Some("dev")).matches("web-1", &metadata));
+        assert!(!CertificateQuery::new().add_tag("team", None).matches("web-1", &metadata));
+        assert_eq!(metadata.to_json(),
+                   "{\"description\":\"Web frontend\",\"owner\":\"alice\",\"tags\":{\"app\":\"web\",\"env\":\"prod\"}}");
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt::{Display, Formatter};
+use crate::pki::certificate::{FLAG_CLIENT_CERT, FLAG_REQUIRE_2FA, FLAG_ROOT_CERT, FLAG_SERVER_CERT,
+                              FLAG_SIGN_CERTS, FLAG_SIGN_MESSAGES, FLAG_USER_CERT};
+use crate::pki::certificate::flags::{validate_flags, FlagError};
+use crate::errors::ErrorCode;
+
+///
+/// Placeholder in name template replaced with name given by user
+///
+pub const NAME_PLACEHOLDER: &str = "{name}";
+
+///
+/// Errors of validating and applying profiles
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProfileError{
+    /** No built-in or configured profile with such name **/
+    UnknownProfile(String),
+    /** Flags of profile are not valid **/
+    InvalidFlags(FlagError),
+    /** Root certificates are only created with `root generate` **/
+    RootFlag,
+    /** Name template does not contain {name} **/
+    InvalidTemplate(String),
+    /** Name given by user does not follow naming convention **/
+    InvalidName(String),
+}
+
+impl Display for ProfileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProfileError::UnknownProfile(name) => write!(f, "Unknown profile '{}'", name),
+            ProfileError::InvalidFlags(error) => write!(f, "{}", error),
+            ProfileError::RootFlag => write!(f, "Profiles can not create root certificates"),
+            ProfileError::InvalidTemplate(template) =>
+                write!(f, "Name template '{}' must contain {}", template, NAME_PLACEHOLDER),
+            ProfileError::InvalidName(name) =>
+                write!(f, "Name '{}' must consist of lowercase letters, digits, '-' and '.'", name),
+        }
+    }
+}
+
+impl ErrorCode for ProfileError {
+    fn get_error_code(&self) -> &'static str {
+        match self {
+            ProfileError::UnknownProfile(_) => "MW-CERT-0401",
+            ProfileError::InvalidFlags(error) => error.get_error_code(),
+            ProfileError::RootFlag => "MW-CERT-0402",
+            ProfileError::InvalidTemplate(_) => "MW-CERT-0403",
+            ProfileError::InvalidName(_) => "MW-CERT-0404",
+        }
+    }
+}
+
+///
+/// Named set of flags and naming convention for generating certificates of one kind,
+/// e.g. certificates of servers. Certificates have no validity period, so profiles do not
+/// define one either.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct CertificateProfile{
+    pub name: String,
+    pub description: String,
+    pub flags: u128,
+    /** Name of certificate, e.g. `server.{name}` **/
+    pub name_template: String,
+}
+
+impl CertificateProfile {
+    pub fn new(name: &str, description: &str, flags: u128, name_template: &str) -> CertificateProfile{
+        CertificateProfile{
+            name: name.to_string(),
+            description: description.to_string(),
+            flags,
+            name_template: name_template.to_string(),
+        }
+    }
+
+    ///
+    /// Checks that profile can be used to generate certificates
+    ///
+    pub fn validate(&self) -> Result<(), ProfileError>{
+        validate_flags(self.flags, true).map_err(ProfileError::InvalidFlags)?;
+        if self.flags & FLAG_ROOT_CERT != 0{
+            return Err(ProfileError::RootFlag);
+        }
+        if !self.name_template.contains(NAME_PLACEHOLDER){
+            return Err(ProfileError::InvalidTemplate(self.name_template.clone()));
+        }
+        Ok(())
+    }
+
+    ///
+    /// Makes name of certificate following naming convention of profile
+    ///
+    /// # Arguments
+    /// * name: &str: name given by user, e.g. host name
+    ///
+    /// returns: Result<String, ProfileError>: full name of certificate
+    ///
+    pub fn make_name(&self, name: &str) -> Result<String, ProfileError>{
+        let is_valid = !name.is_empty() && name.chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.');
+        if !is_valid{
+            return Err(ProfileError::InvalidName(name.to_string()));
+        }
+        Ok(self.name_template.replace(NAME_PLACEHOLDER, name))
+    }
+}
+
+///
+/// Gets profiles which are always available
+///
+pub fn get_builtin_profiles() -> Vec<CertificateProfile>{
+    vec![
+        CertificateProfile::new("server", "Server or broker accepting connections",
+                                FLAG_SERVER_CERT | FLAG_SIGN_MESSAGES, "server.{name}"),
+        CertificateProfile::new("client", "Client machine connecting to brokers",
+                                FLAG_CLIENT_CERT | FLAG_SIGN_MESSAGES, "client.{name}"),
+        CertificateProfile::new("operator", "Human operator managing the network",
+                                FLAG_USER_CERT | FLAG_SIGN_MESSAGES | FLAG_REQUIRE_2FA, "operator.{name}"),
+        CertificateProfile::new("ca-intermediate", "Intermediate authority signing other certificates",
+                                FLAG_SIGN_CERTS, "ca.{name}"),
+    ]
+}
+
+///
+/// Gets built-in profiles together with configured ones. Configured profile replaces
+/// built-in profile with the same name.
+///
+pub fn get_profiles(configured: &[CertificateProfile]) -> Vec<CertificateProfile>{
+    let mut profiles: Vec<CertificateProfile> = get_builtin_profiles().into_iter()
+        .filter(|profile| configured.iter().all(|configured| configured.name != profile.name))
+        .collect();
+    profiles.extend(configured.iter().cloned());
+    profiles
+}
+
+///
+/// Finds profile by name
+///
+/// # Arguments
+/// * name: &str: name of profile
+/// * configured: &[CertificateProfile]: profiles from configuration, they take precedence
+///
+pub fn find_profile(name: &str, configured: &[CertificateProfile]) -> Result<CertificateProfile, ProfileError>{
+    get_profiles(configured).into_iter()
+        .find(|profile| profile.name == name)
+        .ok_or_else(|| ProfileError::UnknownProfile(name.to_string()))
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles() {
+        for profile in get_builtin_profiles(){
+            assert_eq!(profile.validate(), Ok(()));
+        }
+        let server = find_profile("server", &[]).unwrap();
+        assert_eq!(server.make_name("web-1.eu"), Ok("server.web-1.eu".to_string()));
+        assert_eq!(server.make_name("Web 1"), Err(ProfileError::InvalidName("Web 1".to_string())));
+        assert_eq!(find_profile("gateway", &[]), Err(ProfileError::UnknownProfile("gateway".to_string())));
+        // Configured profile replaces built-in one
+        let configured = vec![CertificateProfile::new("server", "Edge server", FLAG_SERVER_CERT, "edge-{name}")];
+        assert_eq!(find_profile("server", &configured).unwrap().flags, FLAG_SERVER_CERT);
+        assert_eq!(get_profiles(&configured).len(), 4);
+        assert_eq!(CertificateProfile::new("bad", "", FLAG_ROOT_CERT, "{name}").validate(),
+                   Err(ProfileError::RootFlag));
+        assert_eq!(CertificateProfile::new("bad", "", 0, "fixed").validate(),
+                   Err(ProfileError::InvalidTemplate("fixed".to_string())));
+        assert_eq!(CertificateProfile::new("bad", "", 1 << 20, "{name}").validate(),
+                   Err(ProfileError::InvalidFlags(FlagError::UnknownBits(1 << 20))));
+    }
+}
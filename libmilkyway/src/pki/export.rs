@@ -0,0 +1,217 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use libmilkyway_derive::{Deserializable, Serializable};
+use crate::get_timestamp_with_milliseconds;
+use crate::pki::certificate::Certificate;
+use crate::pki::hash::{CryptoHashable, Hash, HashType};
+use crate::pki::impls::CryptoError;
+use crate::pki::key::CryptoKey;
+use crate::pki::signature::Signature;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::errors::ErrorCode;
+
+///
+/// Marker at the beginning of files with signed export, files without it are bare exports
+///
+pub const SIGNED_EXPORT_MAGIC: [u8; 8] = *b"MWAYEXP1";
+
+///
+/// Who exported file, when and what exactly was exported
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable)]
+pub struct ExportProvenance{
+    pub signer_serial: u128,
+    /** Time of export in milliseconds since UNIX epoch **/
+    pub timestamp: u128,
+    /** SHA512 of serialized content **/
+    pub content_hash: Hash,
+}
+
+///
+/// Errors of verifying signed export
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExportError{
+    /** Export is signed by another certificate than the one provided **/
+    SignerMismatch{ expected: u128, actual: u128 },
+    /** Content was changed after export **/
+    HashMismatch,
+    InvalidSignature,
+}
+
+impl Display for ExportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportError::SignerMismatch{ expected, actual } =>
+                write!(f, "Export is signed by certificate {}, not {}", actual, expected),
+            ExportError::HashMismatch => write!(f, "Content of export does not match its hash"),
+            ExportError::InvalidSignature => write!(f, "Signature of export is invalid"),
+        }
+    }
+}
+
+impl ErrorCode for ExportError {
+    fn get_error_code(&self) -> &'static str {
+        match self {
+            ExportError::SignerMismatch{ .. } => "MW-CERT-0201",
+            ExportError::HashMismatch => "MW-CERT-0202",
+            ExportError::InvalidSignature => "MW-CERT-0203",
+        }
+    }
+}
+
+///
+/// Exported certificates, bundles or revocations wrapped with signed provenance
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable)]
+pub struct SignedExport{
+    pub provenance: ExportProvenance,
+    /** Signature of provenance, content is covered by its hash **/
+    pub signature: Signature,
+    pub content: Serialized,
+}
+
+impl SignedExport {
+    ///
+    /// Wraps content into signed export
+    ///
+    /// # Arguments
+    /// * content: &T: exported object
+    /// * signer: &C: certificate of exporter with secret key
+    ///
+    /// returns: Result<SignedExport, CryptoError>: export or error if signer can not sign
+    ///
+    pub fn sign<T: Serializable, PK: CryptoKey, SK: CryptoKey, C: Certificate<PK, SK>>(content: &T, signer: &C)
+        -> Result<SignedExport, CryptoError>{
+        let content = content.serialize();
+        let provenance = ExportProvenance{
+            signer_serial: signer.get_serial(),
+            timestamp: get_timestamp_with_milliseconds(),
+            content_hash: content.crypto_hash(HashType::SHA512),
+        };
+        Ok(SignedExport{
+            signature: signer.sign_data(&provenance, HashType::None)?,
+            provenance,
+            content,
+        })
+    }
+
+    ///
+    /// Verifies that content is intact and signed by given certificate
+    ///
+    /// # Arguments
+    /// * signer: &C: certificate with serial from provenance
+    ///
+    pub fn verify<PK: CryptoKey, SK: CryptoKey, C: Certificate<PK, SK>>(&self, signer: &C) -> Result<(), ExportError>{
+        if signer.get_serial() != self.provenance.signer_serial{
+            return Err(ExportError::SignerMismatch{ expected: signer.get_serial(),
+                                                    actual: self.provenance.signer_serial });
+        }
+        if self.content.crypto_hash(self.provenance.content_hash.algorithm.clone()) != self.provenance.content_hash{
+            return Err(ExportError::HashMismatch);
+        }
+        if !signer.verify_signature(&self.provenance, &self.signature){
+            return Err(ExportError::InvalidSignature);
+        }
+        Ok(())
+    }
+
+    ///
+    /// Gets milliseconds passed since export
+    ///
+    #[inline]
+    pub fn get_age(&self) -> u128{
+        get_timestamp_with_milliseconds().saturating_sub(self.provenance.timestamp)
+    }
+
+    ///
+    /// Saves export to file prefixed with SIGNED_EXPORT_MAGIC
+    ///
+    pub fn dump_to_file(&self, file_name: &str) -> std::io::Result<()>{
+        let mut data = SIGNED_EXPORT_MAGIC.to_vec();
+        data.extend(self.serialize());
+        std::fs::write(file_name, data)
+    }
+}
+
+///
+/// Contents of an exported file
+///
+pub enum ExportFile{
+    Signed(SignedExport),
+    /** File without envelope, e.g. exported by older versions **/
+    Unsigned(Serialized),
+}
+
+impl ExportFile {
+    ///
+    /// Reads export from file, detecting whether it is signed
+    ///
+    pub fn read(path: &Path) -> Result<ExportFile, SerializationError>{
+        let data = std::fs::read(path).map_err(|_| SerializationError::InvalidDataError("Can not read file"))?;
+        if data.starts_with(&SIGNED_EXPORT_MAGIC){
+            let (export, _) = SignedExport::from_serialized(&data[SIGNED_EXPORT_MAGIC.len()..].to_vec())?;
+            return Ok(ExportFile::Signed(export));
+        }
+        Ok(ExportFile::Unsigned(data))
+    }
+
+    ///
+    /// Gets signed export, None if file has no envelope
+    ///
+    #[inline]
+    pub fn get_signed(&self) -> Option<&SignedExport>{
+        match self {
+            ExportFile::Signed(export) => Some(export),
+            ExportFile::Unsigned(_) => None,
+        }
+    }
+
+    ///
+    /// Deserializes exported object
+    ///
+    pub fn get_content<T: Deserializable>(&self) -> Result<T, SerializationError>{
+        let content = match self {
+            ExportFile::Signed(export) => &export.content,
+            ExportFile::Unsigned(content) => content,
+        };
+        T::from_serialized(content).map(|(content, _)| content)
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::certificate::test_certificates;
+
+    #[test]
+    fn test_signed_export() {
+        let certificates = test_certificates();
+        let file = std::env::temp_dir().join(format!("milkyway-export-{}.cert", rand::random::<u64>()));
+        let export = SignedExport::sign(&certificates.encryption.clone_without_sk(), &certificates.signing).unwrap();
+        export.dump_to_file(file.to_str().unwrap()).unwrap();
+        let loaded = ExportFile::read(&file).unwrap();
+        let signed = loaded.get_signed().unwrap();
+        assert!(signed.get_age() < 60_000);
+        assert_eq!(signed.verify(&certificates.signing), Ok(()));
+        assert_eq!(loaded.get_content::<crate::pki::impls::certificates::kyber1024::Kyber1024Certificate>()
+                       .unwrap().get_serial(), certificates.encryption.get_serial());
+        assert!(matches!(signed.verify(&certificates.root), Err(ExportError::SignerMismatch{ .. })));
+
+        let mut tampered = signed.clone();
+        tampered.content[0] ^= 1;
+        assert_eq!(tampered.verify(&certificates.signing), Err(ExportError::HashMismatch));
+        let mut tampered = signed.clone();
+        tampered.provenance.timestamp += 1;
+        assert_eq!(tampered.verify(&certificates.signing), Err(ExportError::InvalidSignature));
+
+        // Bare exports are still readable
+        certificates.signing.dump(file.to_str().unwrap()).unwrap();
+        let loaded = ExportFile::read(&file).unwrap();
+        assert!(loaded.get_signed().is_none());
+        std::fs::remove_file(file).unwrap();
+    }
+}
@@ -1,11 +1,15 @@
+use std::io::Read;
+use sha2::{Digest, Sha512};
 use crate::serialization::deserializable::Deserializable;
 use crate::serialization::error::SerializationError;
 use crate::serialization::serializable::{Serializable, Serialized};
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+use libmilkyway_derive::Describe;
 
 ///
 /// Type of hashing algorithm to use
 ///
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Describe)]
 pub enum HashType {
     ///
     /// Used for algorithms which are strictly require own hashing
@@ -82,17 +86,132 @@ impl Deserializable for Hash {
 }
 
 ///
-/// Hashable type abstraction
+/// Size of chunks read by Hasher::update_from_reader
+///
+pub const HASH_CHUNK_SIZE: usize = 65536;
+
+enum HasherState{
+    None,
+    SHA512(Box<Sha512>),
+}
+
+///
+/// Incremental hasher: data is fed by parts, so it is never required to hold all of it in memory
+///
+/// # Note
+/// HashType::None hashes to the same placeholder value regardless of data, as algorithms
+/// using it hash data themselves
+///
+pub struct Hasher{
+    state: HasherState,
+}
+
+impl Hasher {
+    ///
+    /// Creates a hasher
+    ///
+    /// # Arguments
+    /// * hash_type: HashType: hashing algorithm to use
+    ///
+    pub fn new(hash_type: HashType) -> Hasher{
+        let state = match hash_type {
+            HashType::None => HasherState::None,
+            HashType::SHA512 => HasherState::SHA512(Box::new(Sha512::new())),
+        };
+        Hasher{
+            state,
+        }
+    }
+
+    ///
+    /// Feeds next part of data to hasher
+    ///
+    pub fn update(&mut self, data: &[u8]) -> &mut Self{
+        if let HasherState::SHA512(hasher) = &mut self.state{
+            hasher.update(data);
+        }
+        self
+    }
+
+    ///
+    /// Feeds everything reader provides to hasher, reading it by HASH_CHUNK_SIZE chunks
+    ///
+    /// returns: std::io::Result<u64>: number of bytes hashed
+    ///
+    pub fn update_from_reader<R: Read + ?Sized>(&mut self, reader: &mut R) -> std::io::Result<u64>{
+        let mut buffer = vec![0u8; HASH_CHUNK_SIZE];
+        let mut total = 0u64;
+        loop {
+            let bytes_read = match reader.read(&mut buffer) {
+                Ok(0) => return Ok(total),
+                Ok(bytes_read) => bytes_read,
+                Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            self.update(&buffer[..bytes_read]);
+            total += bytes_read as u64;
+        }
+    }
+
+    ///
+    /// Gets hash of all data fed to hasher
+    ///
+    pub fn finalize(self) -> Hash{
+        match self.state {
+            HasherState::None => Hash{
+                algorithm: HashType::None,
+                hash: vec![0],
+            },
+            HasherState::SHA512(hasher) => Hash{
+                algorithm: HashType::SHA512,
+                hash: hasher.finalize().to_vec(),
+            },
+        }
+    }
+
+    ///
+    /// Hashes everything reader provides
+    ///
+    /// # Arguments
+    /// * hash_type: HashType: hashing algorithm to use
+    /// * reader: &mut R: source of data, e.g. a file
+    ///
+    pub fn hash_reader<R: Read + ?Sized>(hash_type: HashType, reader: &mut R) -> std::io::Result<Hash>{
+        let mut hasher = Hasher::new(hash_type);
+        hasher.update_from_reader(reader)?;
+        Ok(hasher.finalize())
+    }
+}
+
+///
+/// Hashable type abstraction. Implemented for all Serializable types, types which are not
+/// Serializable may use #[derive(CryptoHashable)]
 ///
 pub trait CryptoHashable {
-    fn crypto_hash(&self, hash_type: HashType) -> Hash;
+    ///
+    /// Feeds data of value to hasher
+    ///
+    fn update_hasher(&self, hasher: &mut Hasher);
+
+    fn crypto_hash(&self, hash_type: HashType) -> Hash{
+        let mut hasher = Hasher::new(hash_type);
+        self.update_hasher(&mut hasher);
+        hasher.finalize()
+    }
 }
 
 /* Tests begin here */
 #[cfg(test)]
 mod tests {
+    use libmilkyway_derive::CryptoHashable;
     use super::*;
 
+    #[derive(CryptoHashable)]
+    struct Chunked{
+        header: u32,
+        chunks: Vec<Vec<u8>>,
+    }
+
     #[test]
     fn test_serialize_deserialize_hashtype_sha512() {
         let hash_type = HashType::SHA512;
@@ -139,4 +258,28 @@ mod tests {
         let result = Hash::from_serialized(&serialized);
         assert!(matches!(result, Err(SerializationError::LengthError)));
     }
+
+    #[test]
+    fn test_incremental_hash() {
+        let data: Vec<u8> = (0..HASH_CHUNK_SIZE * 2 + 17).map(|i| i as u8).collect();
+        let mut hasher = Hasher::new(HashType::SHA512);
+        hasher.update(&data[..5]).update(&data[5..]);
+        let incremental = hasher.finalize();
+        let streamed = Hasher::hash_reader(HashType::SHA512, &mut data.as_slice()).unwrap();
+        assert_eq!(incremental, streamed);
+        assert_eq!(incremental.hash.len(), 64);
+        assert_eq!(data.crypto_hash(HashType::SHA512), Hasher::hash_reader(HashType::SHA512,
+                                                                          &mut data.serialize().as_slice()).unwrap());
+    }
+
+    #[test]
+    fn test_derived_hash() {
+        let value = Chunked{
+            header: 7,
+            chunks: vec![vec![1, 2], vec![3]],
+        };
+        assert_eq!(value.crypto_hash(HashType::SHA512),
+                   (value.header, value.chunks.clone()).crypto_hash(HashType::SHA512));
+        assert_ne!(value.crypto_hash(HashType::SHA512), (8u32, value.chunks.clone()).crypto_hash(HashType::SHA512));
+    }
 }
\ No newline at end of file
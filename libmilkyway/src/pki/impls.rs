@@ -1,7 +1,8 @@
-use libmilkyway_derive::{EnumDeserializable, EnumSerializable};
+use libmilkyway_derive::{Describe, EnumDeserializable, EnumSerializable};
 use crate::serialization::serializable::{Serialized, Serializable};
 use crate::serialization::deserializable::Deserializable;
 use crate::serialization::error::SerializationError;
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
 
 pub mod keys;
 pub mod certificates;
@@ -10,7 +11,7 @@ pub mod hashable;
 ///
 /// Crypto alogrithm type
 ///
-#[derive(PartialEq, Debug, Clone, EnumSerializable, EnumDeserializable)]
+#[derive(PartialEq, Debug, Clone, EnumSerializable, EnumDeserializable, Describe)]
 pub enum CryptoType {
     Falcon1024,
     Kyber1024Aes256GCM,
@@ -37,4 +38,10 @@ pub enum CryptoError {
     /// Argument error(e.g. wrong certificate type)
     ///
     ArgumentError(&'static str),
+
+    ///
+    /// Data was replayed or reordered beyond allowed window, the session
+    /// must not be trusted anymore
+    ///
+    SequenceViolation,
 }
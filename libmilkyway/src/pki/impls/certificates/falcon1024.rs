@@ -1,5 +1,6 @@
-use libmilkyway_derive::{Deserializable, Serializable};
+use libmilkyway_derive::{Describe, Deserializable, Serializable};
 use crate::pki::certificate::{Certificate, CertificateType, FLAG_NO_READ, FLAG_NO_WRITE, FLAG_ROOT_CERT, FLAG_SIGN_CERTS};
+use crate::pki::certificate::metadata::{deserialize_flags_and_metadata, serialize_flags_and_metadata, CertificateMetadata};
 use crate::pki::certificate::CertificateType::{RootCertificate,
                                                SigningCertificate};
 use crate::pki::impls::keys::falcon1024::{Falcon1024PublicKey, Falcon1024SecretKey, generate_falcon1024_keypair};
@@ -7,11 +8,12 @@ use crate::pki::signature::Signature;
 use crate::serialization::deserializable::Deserializable;
 use crate::serialization::error::SerializationError;
 use crate::serialization::serializable::{Serializable, Serialized};
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
 
 ///
 /// A general-usage certificate with Falcon1024 keys encapsulated
 ///
-#[derive(Clone, Serializable, Deserializable, PartialEq)]
+#[derive(Clone, PartialEq, Describe)]
 pub struct Falcon1024Certificate {
     pub serial_number: u128,
     pub parent_serial_number: u128,
@@ -20,6 +22,48 @@ pub struct Falcon1024Certificate {
     pub signature: Option<Signature>,
     pub name: String,
     pub flags: u128,
+    /** Written after flags only when it is not empty, see serialize_flags_and_metadata **/
+    pub metadata: CertificateMetadata,
+}
+
+impl Serializable for Falcon1024Certificate {
+    fn serialize(&self) -> Serialized {
+        let mut result = self.serial_number.serialize();
+        result.extend(self.parent_serial_number.serialize());
+        result.extend(self.secret_key.serialize());
+        result.extend(self.public_key.serialize());
+        result.extend(self.signature.serialize());
+        result.extend(self.name.serialize());
+        result.extend(serialize_flags_and_metadata(self.flags, &self.metadata));
+        result
+    }
+}
+
+impl Deserializable for Falcon1024Certificate {
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        let (serial_number, mut offset) = u128::from_serialized(serialized)?;
+        let (parent_serial_number, size) = u128::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (secret_key, size) = Option::<Falcon1024SecretKey>::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (public_key, size) = Falcon1024PublicKey::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (signature, size) = Option::<Signature>::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (name, size) = String::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (flags, metadata, size) = deserialize_flags_and_metadata(&serialized[offset..].to_vec())?;
+        Ok((Falcon1024Certificate{
+            serial_number,
+            parent_serial_number,
+            secret_key,
+            public_key,
+            signature,
+            name,
+            flags,
+            metadata,
+        }, offset + size))
+    }
 }
 
 impl Certificate<Falcon1024PublicKey, Falcon1024SecretKey> for Falcon1024Certificate {
@@ -89,12 +133,17 @@ impl Certificate<Falcon1024PublicKey, Falcon1024SecretKey> for Falcon1024Certifi
     fn set_flags(&mut self, flags: u128) {
         self.flags = flags;
     }
+
+    #[inline]
+    fn get_metadata(&self) -> CertificateMetadata {
+        self.metadata.clone()
+    }
 }
 
 ///
 /// A root certificate which may be used only for signing other certificates
 ///
-#[derive(Clone, Serializable, Deserializable, PartialEq)]
+#[derive(Clone, Serializable, Deserializable, PartialEq, Describe)]
 pub struct Falcon1024RootCertificate {
     pub secret_key: Option<Falcon1024SecretKey>,
     pub public_key: Falcon1024PublicKey,
@@ -177,7 +226,7 @@ pub fn generate_falcon1024_root_certificate(name: String) -> Falcon1024RootCerti
 mod tests {
     use super::*;
     use crate::pki::certificate::Certificate;
-    use crate::pki::impls::keys::falcon1024::generate_falcon1024_keypair;
+    use crate::pki::impls::keys::falcon1024::generate_falcon1024_keypair_from_seed;
     use crate::serialization::error::SerializationError;
     use crate::serialization::serializable::{Serializable, Serialized};
     use crate::pki::hash::HashType;
@@ -190,8 +239,8 @@ mod tests {
 
     #[test]
     fn test_full_pki_use_case() {
-        let (root_public_key, root_secret_key) = generate_falcon1024_keypair();
-        let (signing_public_key, signing_secret_key) = generate_falcon1024_keypair();
+        let (root_public_key, root_secret_key) = generate_falcon1024_keypair_from_seed(b"root");
+        let (signing_public_key, signing_secret_key) = generate_falcon1024_keypair_from_seed(b"signing");
 
         let root_certificate = Falcon1024RootCertificate {
             secret_key: Some(root_secret_key),
@@ -207,6 +256,7 @@ mod tests {
             signature: None,
             name: "test".to_string(),
             flags: 0,
+            metadata: Default::default(),
         };
 
         let signature = root_certificate.sign_data(&signing_certificate.clone_without_signature_and_sk(),
@@ -239,7 +289,7 @@ mod tests {
 
     #[test]
     fn test_certificate_serialization_deserialization() {
-        let (public_key, secret_key) = generate_falcon1024_keypair();
+        let (public_key, secret_key) = generate_falcon1024_keypair_from_seed(b"signing");
         let certificate = Falcon1024Certificate {
             serial_number: 1,
             parent_serial_number: 0,
@@ -248,6 +298,7 @@ mod tests {
             signature: None,
             name: "test".to_string(),
             flags: 0,
+            metadata: Default::default(),
         };
 
         let serialized = certificate.serialize();
@@ -257,7 +308,7 @@ mod tests {
 
     #[test]
     fn test_clone_without_private() {
-        let (public_key, secret_key) = generate_falcon1024_keypair();
+        let (public_key, secret_key) = generate_falcon1024_keypair_from_seed(b"signing");
         let certificate = Falcon1024Certificate {
             serial_number: 1,
             parent_serial_number: 0,
@@ -266,6 +317,7 @@ mod tests {
             signature: None,
             name: "test".to_string(),
             flags: 0,
+            metadata: Default::default(),
         };
 
         let cloned_certificate = certificate.clone_without_signature_and_sk();
@@ -275,7 +327,7 @@ mod tests {
 
     #[test]
     fn test_certificate_signing_and_verification() {
-        let (public_key, secret_key) = generate_falcon1024_keypair();
+        let (public_key, secret_key) = generate_falcon1024_keypair_from_seed(b"signing");
         let certificate = Falcon1024Certificate {
             serial_number: 1,
             parent_serial_number: 0,
@@ -284,6 +336,7 @@ mod tests {
             signature: None,
             name: "test".to_string(),
             flags: 0,
+            metadata: Default::default(),
         };
 
         let test_data = TestData {
@@ -4,12 +4,14 @@ use crate::pki::impls::SerializationError;
 use crate::serialization::serializable::Serialized;
 use crate::serialization::serializable::Serializable;
 use crate::serialization::deserializable::Deserializable;
-use libmilkyway_derive::{Deserializable, Serializable};
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+use libmilkyway_derive::Describe;
 use crate::pki::certificate::{Certificate, CertificateType, FLAG_SIGN_CERTS, FLAG_SIGN_MESSAGES};
+use crate::pki::certificate::metadata::{deserialize_flags_and_metadata, serialize_flags_and_metadata, CertificateMetadata};
 use crate::pki::signature::Signature;
 
 
-#[derive(Clone, Serializable, Deserializable, PartialEq)]
+#[derive(Clone, PartialEq, Describe)]
 pub struct Kyber1024Certificate{
     pub serial_number: u128,
     pub parent_serial_number: u128,
@@ -18,6 +20,48 @@ pub struct Kyber1024Certificate{
     pub signature: Option<Signature>,
     pub name: String,
     pub flags: u128,
+    /** Written after flags only when it is not empty, see serialize_flags_and_metadata **/
+    pub metadata: CertificateMetadata,
+}
+
+impl Serializable for Kyber1024Certificate {
+    fn serialize(&self) -> Serialized {
+        let mut result = self.serial_number.serialize();
+        result.extend(self.parent_serial_number.serialize());
+        result.extend(self.secret_key.serialize());
+        result.extend(self.public_key.serialize());
+        result.extend(self.signature.serialize());
+        result.extend(self.name.serialize());
+        result.extend(serialize_flags_and_metadata(self.flags, &self.metadata));
+        result
+    }
+}
+
+impl Deserializable for Kyber1024Certificate {
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        let (serial_number, mut offset) = u128::from_serialized(serialized)?;
+        let (parent_serial_number, size) = u128::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (secret_key, size) = Option::<SecretKey>::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (public_key, size) = PublicKey::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (signature, size) = Option::<Signature>::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (name, size) = String::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (flags, metadata, size) = deserialize_flags_and_metadata(&serialized[offset..].to_vec())?;
+        Ok((Kyber1024Certificate{
+            serial_number,
+            parent_serial_number,
+            secret_key,
+            public_key,
+            signature,
+            name,
+            flags,
+            metadata,
+        }, offset + size))
+    }
 }
 
 
@@ -87,19 +131,26 @@ impl Certificate<kyber1024::PublicKey, kyber1024::SecretKey> for Kyber1024Certif
         }
         self.flags = flags;
     }
+
+    #[inline]
+    fn get_metadata(&self) -> CertificateMetadata {
+        self.metadata.clone()
+    }
 }
 
 /* Tests begin here */
 #[cfg(test)]
 mod tests {
     use super::*;
-    use pqcrypto::kem::kyber1024::{self, PublicKey, SecretKey};
+    use pqcrypto::kem::kyber1024::{PublicKey, SecretKey};
     use crate::pki::certificate::Certificate;
     use crate::serialization::serializable::{Serializable, Serialized};
     use crate::serialization::deserializable::Deserializable;
     use crate::pki::hash::HashType;
     use crate::pki::impls::certificates::falcon1024::Falcon1024RootCertificate;
-    use crate::pki::impls::keys::falcon1024::generate_falcon1024_keypair;
+    use crate::pki::impls::keys::falcon1024::generate_falcon1024_keypair_from_seed;
+    use libmilkyway_derive::{Deserializable, Serializable};
+    use crate::pki::impls::keys::kyber1024::generate_kyber1024_keypair_from_seed;
 
     #[derive(Clone, Serializable, Deserializable, Debug, PartialEq)]
     struct TestData {
@@ -107,12 +158,12 @@ mod tests {
     }
 
     fn generate_kyber1024_keypair() -> (PublicKey, SecretKey) {
-        kyber1024::keypair()
+        generate_kyber1024_keypair_from_seed(b"encryption")
     }
 
     #[test]
     fn test_full_pki_use_case() {
-        let (root_public_key, root_secret_key) = generate_falcon1024_keypair();
+        let (root_public_key, root_secret_key) = generate_falcon1024_keypair_from_seed(b"root");
         let (encipherment_public_key, encipherment_secret_key) = generate_kyber1024_keypair();
 
         let root_certificate = Falcon1024RootCertificate {
@@ -129,6 +180,7 @@ mod tests {
             signature: None,
             name: "test".to_string(),
             flags: 0,
+            metadata: Default::default(),
         };
 
         // Sign the encipherment certificate with the root certificate
@@ -174,6 +226,7 @@ mod tests {
             signature: None,
             name: "test".to_string(),
             flags: 0,
+            metadata: Default::default(),
         };
 
         let serialized = certificate.serialize();
@@ -192,6 +245,7 @@ mod tests {
             signature: None,
             name: "test".to_string(),
             flags: 0,
+            metadata: Default::default(),
         };
 
         let cloned_certificate = certificate.clone_without_signature_and_sk();
@@ -210,6 +264,7 @@ mod tests {
             signature: None,
             name: "test".to_string(),
             flags: 0,
+            metadata: Default::default(),
         };
 
         let test_data = TestData {
@@ -1,3 +1,25 @@
+use hkdf::Hkdf;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::Sha256;
+
 pub mod aes256;
 pub mod falcon1024;
 pub mod kyber1024;
+
+///
+/// Expands seed of *_keypair_from_seed functions into randomness consumed by key generation:
+/// HKDF-SHA256 of seed keys ChaCha20 stream the output is taken from. Label separates
+/// algorithms, so the same seed gives unrelated keys for each of them.
+///
+/// # Arguments
+/// * seed: &[u8]: seed of keypair
+/// * label: &[u8]: name of algorithm randomness is generated for
+/// * output: &mut [u8]: buffer to fill with randomness
+///
+pub(crate) fn expand_keygen_seed(seed: &[u8], label: &[u8], output: &mut [u8]){
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(None, seed).expand(label, &mut key)
+        .expect("32 bytes are within HKDF-SHA256 output limit");
+    ChaCha20Rng::from_seed(key).fill_bytes(output);
+}
@@ -21,6 +21,9 @@ impl Deserializable for aes_gcm::Key<Aes256Gcm>{
             return Err(result.err().unwrap());
         }
         let (key, offset) = result.unwrap();
+        if key.len() != 32{
+            return Err(SerializationError::InvalidDataError("Invalid AES256 key length"));
+        }
         Ok((*Self::from_slice(&key), offset))
     }
 }
@@ -48,14 +51,14 @@ impl CryptoKey for aes_gcm::Key<Aes256Gcm>{
 
     fn decrypt_raw(&self, data: &Serialized) -> Result<Serialized, CryptoError> {
         let cipher = Aes256Gcm::new(self);
-        let nonce_data_result = Vec::<u8>::from_serialized(data);
-        if nonce_data_result.is_err(){
-
+        let (nonce_data, offset) = Vec::<u8>::from_serialized(data)
+            .map_err(|_| CryptoError::FormatError)?;
+        if nonce_data.len() != 12{
+            return Err(CryptoError::FormatError);
         }
-        let (nonce_data, offset) = nonce_data_result.unwrap();
         let nonce = Nonce::from_slice(&nonce_data);
         let (ciphertext, _) = Vec::<u8>::from_serialized(&data[offset..].to_vec())
-            .unwrap();
+            .map_err(|_| CryptoError::FormatError)?;
         let decryption_result = cipher.decrypt(nonce, ciphertext.as_ref());
         if decryption_result.is_err(){
             return Err(CryptoError::DataTampered);
@@ -137,4 +140,18 @@ mod tests {
 
         assert!(result.is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_decrypt_malformed_aes256gcm() {
+        let key = Aes256Gcm::generate_key(OsRng);
+        // Nonce of wrong length
+        let mut data = vec![0u8; 5].serialize();
+        data.extend(vec![0u8; 32].serialize());
+        assert_eq!(key.decrypt_raw(&data), Err(CryptoError::FormatError));
+        // Missing ciphertext
+        assert_eq!(key.decrypt_raw(&vec![0u8; 12].serialize()), Err(CryptoError::FormatError));
+        assert_eq!(key.decrypt_raw(&vec![]), Err(CryptoError::FormatError));
+        // Key of wrong length
+        assert!(Key::<Aes256Gcm>::from_serialized(&vec![0u8; 16].serialize()).is_err());
+    }
+}
@@ -1,11 +1,14 @@
+use std::ffi::c_uint;
 use pqcrypto::traits::sign::{PublicKey, SecretKey, SignedMessage};
 use pqcrypto_falcon::falcon1024;
 use crate::pki::hash::{CryptoHashable, HashType};
 use crate::pki::impls::{CryptoError, CryptoType};
+use crate::pki::impls::keys::expand_keygen_seed;
 use crate::pki::key::{CryptoKey, KeyType};
 use crate::pki::signature::Signature;
 use crate::serialization::deserializable::Deserializable;
 use crate::serialization::error::SerializationError;
+use crate::serialization::schema::{Describe, TypeSchema};
 use crate::serialization::serializable::{Serializable, Serialized};
 
 #[derive(PartialEq, Clone)]
@@ -32,6 +35,106 @@ pub fn generate_falcon1024_keypair() -> (Falcon1024PublicKey, Falcon1024SecretKe
     (pk, sk)
 }
 
+/* log2 of Falcon1024 degree */
+const FALCON1024_LOGN: c_uint = 10;
+/* Size of seed of SHAKE256 context Falcon keygen extracts randomness from */
+const FALCON1024_KEYGEN_SEED_SIZE: usize = 48;
+/* Size of temporary buffer of Falcon1024 keygen(FALCON_KEYGEN_TEMP_10) */
+const FALCON1024_KEYGEN_TEMP_SIZE: usize = 28672;
+
+/* Incremental SHAKE256 context of PQClean(shake256incctx) */
+#[repr(C)]
+struct Shake256Context{
+    state: [u64; 26],
+}
+
+// Internals of PQClean Falcon1024 linked by pqcrypto_falcon: its keypair() seeds keygen with
+// system randomness only, so deterministic keygen does the same steps with a given seed
+#[allow(non_snake_case)]
+extern "C" {
+    fn shake256_inc_init(state: *mut Shake256Context);
+    fn shake256_inc_absorb(state: *mut Shake256Context, input: *const u8, length: usize);
+    fn shake256_inc_finalize(state: *mut Shake256Context);
+    fn shake256_inc_ctx_release(state: *mut Shake256Context);
+    fn PQCLEAN_FALCON1024_CLEAN_keygen(rng: *mut Shake256Context, f: *mut i8, g: *mut i8, F: *mut i8,
+                                       G: *mut i8, h: *mut u16, logn: c_uint, tmp: *mut u8);
+    fn PQCLEAN_FALCON1024_CLEAN_trim_i8_encode(out: *mut u8, max_out_len: usize, x: *const i8,
+                                               logn: c_uint, bits: c_uint) -> usize;
+    fn PQCLEAN_FALCON1024_CLEAN_modq_encode(out: *mut u8, max_out_len: usize, x: *const u16,
+                                            logn: c_uint) -> usize;
+    static PQCLEAN_FALCON1024_CLEAN_max_fg_bits: [u8; 11];
+    static PQCLEAN_FALCON1024_CLEAN_max_FG_bits: [u8; 11];
+}
+
+///
+/// Generates Falcon1024 keypair deterministically: same seed always gives same keypair.
+/// Randomness of key generation is derived from seed(see expand_keygen_seed), keys are
+/// encoded exactly as by generate_falcon1024_keypair.
+///
+/// # Warning
+/// For tests and reproducible provisioning ONLY. Anyone knowing the seed can recompute
+/// the secret key, so seed must be kept as secret as the key itself.
+///
+/// # Arguments
+/// * seed: &[u8]: seed of keypair
+///
+#[allow(non_snake_case)]
+pub fn generate_falcon1024_keypair_from_seed(seed: &[u8]) -> (Falcon1024PublicKey, Falcon1024SecretKey) {
+    let mut keygen_seed = [0u8; FALCON1024_KEYGEN_SEED_SIZE];
+    expand_keygen_seed(seed, b"falcon1024 keygen", &mut keygen_seed);
+    let (mut f, mut g, mut F) = ([0i8; 1024], [0i8; 1024], [0i8; 1024]);
+    let mut h = [0u16; 1024];
+    // Keygen requires 64-bit alignment of its buffer
+    let mut tmp = vec![0u64; FALCON1024_KEYGEN_TEMP_SIZE / 8];
+    let mut secret_key = vec![0u8; falcon1024::secret_key_bytes()];
+    let mut public_key = vec![0u8; falcon1024::public_key_bytes()];
+    let (mut sk_size, pk_size);
+    unsafe {
+        let mut rng = Shake256Context{ state: [0; 26] };
+        shake256_inc_init(&mut rng);
+        shake256_inc_absorb(&mut rng, keygen_seed.as_ptr(), keygen_seed.len());
+        shake256_inc_finalize(&mut rng);
+        PQCLEAN_FALCON1024_CLEAN_keygen(&mut rng, f.as_mut_ptr(), g.as_mut_ptr(), F.as_mut_ptr(),
+                                        std::ptr::null_mut(), h.as_mut_ptr(), FALCON1024_LOGN,
+                                        tmp.as_mut_ptr() as *mut u8);
+        shake256_inc_ctx_release(&mut rng);
+        // Secret key: header, f, g and F, public key: header and h
+        secret_key[0] = 0x50 + FALCON1024_LOGN as u8;
+        sk_size = 1;
+        let fg_bits = PQCLEAN_FALCON1024_CLEAN_max_fg_bits[FALCON1024_LOGN as usize] as c_uint;
+        let big_fg_bits = PQCLEAN_FALCON1024_CLEAN_max_FG_bits[FALCON1024_LOGN as usize] as c_uint;
+        for (element, bits) in [(&f, fg_bits), (&g, fg_bits), (&F, big_fg_bits)]{
+            sk_size += PQCLEAN_FALCON1024_CLEAN_trim_i8_encode(secret_key[sk_size..].as_mut_ptr(),
+                                                               secret_key.len() - sk_size, element.as_ptr(),
+                                                               FALCON1024_LOGN, bits);
+        }
+        public_key[0] = FALCON1024_LOGN as u8;
+        pk_size = 1 + PQCLEAN_FALCON1024_CLEAN_modq_encode(public_key[1..].as_mut_ptr(), public_key.len() - 1,
+                                                           h.as_ptr(), FALCON1024_LOGN);
+    }
+    assert!(sk_size == secret_key.len() && pk_size == public_key.len(), "Falcon1024 keygen failed to encode keys");
+    let pk = Falcon1024PublicKey {
+        internal: falcon1024::PublicKey::from_bytes(&public_key).unwrap(),
+    };
+    let sk = Falcon1024SecretKey {
+        internal: falcon1024::SecretKey::from_bytes(&secret_key).unwrap(),
+    };
+    (pk, sk)
+}
+
+impl Falcon1024SecretKey {
+    ///
+    /// Overwrites key with zeros, so it does not stay in memory after use
+    ///
+    pub fn wipe(&mut self){
+        let zeroed = falcon1024::SecretKey::from_bytes(&vec![0u8; falcon1024::secret_key_bytes()])
+            .expect("Zeroed key has size of Falcon1024 secret key");
+        // Volatile write can not be optimized out as a dead store
+        #[allow(unsafe_code)]
+        unsafe { std::ptr::write_volatile(&mut self.internal, zeroed) };
+    }
+}
+
 impl Serializable for Falcon1024SecretKey {
     #[inline]
     fn serialize(&self) -> Serialized {
@@ -58,6 +161,16 @@ impl Deserializable for Falcon1024SecretKey {
     }
 }
 
+impl Describe for Falcon1024SecretKey {
+    ///
+    /// Keys are written as list of raw bytes
+    ///
+    #[inline]
+    fn describe() -> TypeSchema {
+        TypeSchema::List(Box::new(TypeSchema::Primitive("u8")))
+    }
+}
+
 
 impl Serializable for falcon1024::SignedMessage {
     #[inline]
@@ -111,6 +224,7 @@ impl CryptoKey for Falcon1024SecretKey {
             algorithm: HashType::None,
             crypto_algorithm: CryptoType::Falcon1024,
             serialized_signature: signed_message.serialize(),
+            context: None,
         })
     }
 
@@ -147,6 +261,16 @@ impl Deserializable for Falcon1024PublicKey {
     }
 }
 
+impl Describe for Falcon1024PublicKey {
+    ///
+    /// Keys are written as list of raw bytes
+    ///
+    #[inline]
+    fn describe() -> TypeSchema {
+        TypeSchema::List(Box::new(TypeSchema::Primitive("u8")))
+    }
+}
+
 impl CryptoKey for Falcon1024PublicKey {
     fn get_key_type(&self) -> KeyType {
         KeyType::Public
@@ -196,7 +320,7 @@ mod tests {
 
     #[test]
     fn test_serialize_deserialize_falcon1024_public_key() {
-        let (pk, _sk) = generate_falcon1024_keypair();
+        let (pk, _sk) = generate_falcon1024_keypair_from_seed(b"falcon1024-keys-test");
         let serialized = pk.serialize();
         let (deserialized, size) =
             Falcon1024PublicKey::from_serialized(&serialized).unwrap();
@@ -206,7 +330,7 @@ mod tests {
 
     #[test]
     fn test_serialize_deserialize_falcon1024_secret_key() {
-        let (_pk, sk) = generate_falcon1024_keypair();
+        let (_pk, sk) = generate_falcon1024_keypair_from_seed(b"falcon1024-keys-test");
         let serialized = sk.serialize();
         let (deserialized, size) = Falcon1024SecretKey::from_serialized(&serialized).unwrap();
         assert!(sk == deserialized);
@@ -215,7 +339,7 @@ mod tests {
 
     #[test]
     fn test_sign_verify_signature_falcon1024() {
-        let (pk, sk) = generate_falcon1024_keypair();
+        let (pk, sk) = generate_falcon1024_keypair_from_seed(b"falcon1024-keys-test");
         let data: Vec<u8> = vec![1, 2, 3, 4, 5];
         
         let signature = sk.sign(&data, HashType::None).unwrap();
@@ -225,7 +349,7 @@ mod tests {
 
     #[test]
     fn test_verify_signature_falcon1024_invalid_data() {
-        let (pk, sk) = generate_falcon1024_keypair();
+        let (pk, sk) = generate_falcon1024_keypair_from_seed(b"falcon1024-keys-test");
         let data: Vec<u8> = vec![1, 2, 3, 4, 5];
         let invalid_data: Vec<u8> = vec![6, 7, 8, 9, 10];
         let signature = sk.sign(&data, HashType::None).unwrap();
@@ -236,7 +360,7 @@ mod tests {
     #[test]
     fn test_serialize_deserialize_signed_message() {
         let data = vec![1, 2, 3, 4, 5];
-        let (_pk, sk) = generate_falcon1024_keypair();
+        let (_pk, sk) = generate_falcon1024_keypair_from_seed(b"falcon1024-keys-test");
         let signed_message = falcon1024::sign(&data, &sk.internal);
 
         let serialized = signed_message.serialize();
@@ -247,7 +371,7 @@ mod tests {
 
     #[test]
     fn test_invalid_signature_verification() {
-        let (pk, sk) = generate_falcon1024_keypair();
+        let (pk, sk) = generate_falcon1024_keypair_from_seed(b"falcon1024-keys-test");
         let data = vec![1, 2, 3, 4, 5];
 
         let signature = sk.sign(&data, HashType::None);
@@ -258,5 +382,19 @@ mod tests {
         let is_valid = pk.verify_signature(&data, &tampered_signature);
         assert!(!is_valid);
     }
-}
 
+    #[test]
+    fn test_falcon1024_keypair_from_seed() {
+        let (first_pk, first_sk) = generate_falcon1024_keypair_from_seed(b"seed");
+        let (second_pk, second_sk) = generate_falcon1024_keypair_from_seed(b"seed");
+        let (other_pk, _other_sk) = generate_falcon1024_keypair_from_seed(b"other seed");
+        assert!(first_pk == second_pk);
+        assert!(first_sk == second_sk);
+        assert!(first_pk != other_pk);
+        // Derived keys are valid keys of the pair
+        let data: Vec<u8> = vec![1, 2, 3, 4, 5];
+        let signature = first_sk.sign(&data, HashType::None).unwrap();
+        assert!(second_pk.verify_signature(&data, &signature));
+        assert!(!other_pk.verify_signature(&data, &signature));
+    }
+}
@@ -4,10 +4,12 @@ use pqcrypto::kem::kyber1024;
 use pqcrypto::traits::kem::{Ciphertext, PublicKey, SecretKey, SharedSecret};
 use crate::pki::hash::{CryptoHashable, HashType};
 use crate::pki::impls::{CryptoError, CryptoType};
+use crate::pki::impls::keys::expand_keygen_seed;
 use crate::pki::key::{CryptoKey, KeyType};
 use crate::pki::signature::Signature;
 use crate::serialization::deserializable::Deserializable;
 use crate::serialization::error::SerializationError;
+use crate::serialization::schema::{Describe, TypeSchema};
 use crate::serialization::serializable::{Serializable, Serialized};
 
 impl Serializable for kyber1024::PublicKey{
@@ -32,6 +34,16 @@ impl Deserializable for kyber1024::PublicKey{
     }
 }
 
+impl Describe for kyber1024::PublicKey {
+    ///
+    /// Keys are written as list of raw bytes
+    ///
+    #[inline]
+    fn describe() -> TypeSchema {
+        TypeSchema::List(Box::new(TypeSchema::Primitive("u8")))
+    }
+}
+
 impl Serializable for kyber1024::SecretKey{
     #[inline]
     fn serialize(&self) -> Serialized {
@@ -53,6 +65,16 @@ impl Deserializable for kyber1024::SecretKey{
     }
 }
 
+impl Describe for kyber1024::SecretKey {
+    ///
+    /// Keys are written as list of raw bytes
+    ///
+    #[inline]
+    fn describe() -> TypeSchema {
+        TypeSchema::List(Box::new(TypeSchema::Primitive("u8")))
+    }
+}
+
 impl CryptoKey for kyber1024::PublicKey {
     #[inline]
     fn get_key_type(&self) -> KeyType {
@@ -65,11 +87,10 @@ impl CryptoKey for kyber1024::PublicKey {
     }
 
     fn encrypt_raw(&self, data: &Serialized) -> Result<Serialized, CryptoError> {
-        let (shared_secret, cipher_text) = kyber1024::encapsulate(self);
-        let key = aes_gcm::Key::<Aes256Gcm>::from_slice(&shared_secret.as_bytes()[..32]);
+        // Every call encapsulates a new key, sessions avoid that with encapsulate_kyber1024_key
+        let (cipher_text, key) = encapsulate_kyber1024_key(self);
         let mut result = Serialized::new();
-        //FIXME: Do we really need cipher text every time in encryption?
-        result.extend(cipher_text.as_bytes().to_vec().serialize());
+        result.extend(cipher_text.serialize());
         let encryption_result = key.encrypt_raw(data);
         if encryption_result.is_err(){
             // It is already an error
@@ -108,22 +129,16 @@ impl CryptoKey for kyber1024::SecretKey {
     }
 
     fn decrypt_raw(&self, data: &Serialized) -> Result<Serialized, CryptoError> {
-        let deserialized_data = Vec::<u8>::from_serialized(data);
-        if deserialized_data.is_err(){
+        // Data usually comes from network, so every stage must fail gracefully
+        let (cipher_text, offset) = Vec::<u8>::from_serialized(data)
+            .map_err(|_| CryptoError::FormatError)?;
+        let key = decapsulate_kyber1024_key(self, &cipher_text)?;
+        let (encrypted_data, encrypted_data_offset) = Vec::<u8>::from_serialized(&data[offset..].to_vec())
+            .map_err(|_| CryptoError::FormatError)?;
+        if offset + encrypted_data_offset != data.len(){
+            // Trailing garbage after payload
             return Err(CryptoError::FormatError);
         }
-        let (cipher_text_bytes, offset) = deserialized_data.unwrap();
-        let cipher_text_result =
-            kyber1024::Ciphertext::from_bytes(&cipher_text_bytes);
-        let cipher_text = cipher_text_result.unwrap();
-        let shared_secret = kyber1024::decapsulate(&cipher_text, self);
-        let key = GenericArray::from_slice(&shared_secret.as_bytes());
-        let encrypted_data_result = Vec::<u8>::from_serialized(
-            &data[offset..].to_vec());
-        if encrypted_data_result.is_err(){
-            return Err(CryptoError::FormatError)
-        }
-        let (encrypted_data, _encrypted_data_offset) = encrypted_data_result.unwrap();
         key.decrypt_raw(&encrypted_data)
     }
 
@@ -136,11 +151,72 @@ impl CryptoKey for kyber1024::SecretKey {
     }
 }
 
+///
+/// Encapsulates a new AES-256-GCM key for holder of secret key
+///
+/// # Arguments
+/// * public_key: &kyber1024::PublicKey: public key of recipient
+///
+/// returns: (Serialized, aes_gcm::Key<Aes256Gcm>): Kyber ciphertext to send and key itself
+///
+pub fn encapsulate_kyber1024_key(public_key: &kyber1024::PublicKey) -> (Serialized, aes_gcm::Key<Aes256Gcm>) {
+    let (shared_secret, cipher_text) = kyber1024::encapsulate(public_key);
+    let key = *aes_gcm::Key::<Aes256Gcm>::from_slice(&shared_secret.as_bytes()[..32]);
+    (cipher_text.as_bytes().to_vec(), key)
+}
+
+///
+/// Recovers AES-256-GCM key encapsulated by encapsulate_kyber1024_key
+///
+/// # Arguments
+/// * secret_key: &kyber1024::SecretKey: secret key of recipient
+/// * cipher_text: &[u8]: Kyber ciphertext
+///
+pub fn decapsulate_kyber1024_key(secret_key: &kyber1024::SecretKey,
+                                 cipher_text: &[u8]) -> Result<aes_gcm::Key<Aes256Gcm>, CryptoError> {
+    let cipher_text = kyber1024::Ciphertext::from_bytes(cipher_text)
+        .map_err(|_| CryptoError::FormatError)?;
+    let shared_secret = kyber1024::decapsulate(&cipher_text, secret_key);
+    Ok(*GenericArray::from_slice(&shared_secret.as_bytes()[..32]))
+}
+
 #[inline]
 pub fn generate_kyber1024_keypair() -> (kyber1024::PublicKey, kyber1024::SecretKey) {
     kyber1024::keypair()
 }
 
+/* Size of randomness Kyber1024 keygen consumes */
+const KYBER1024_KEYGEN_COINS_SIZE: usize = 64;
+
+// Derandomized keygen of PQClean Kyber1024 linked by pqcrypto, which does not expose it
+extern "C" {
+    fn PQCLEAN_KYBER1024_CLEAN_crypto_kem_keypair_derand(pk: *mut u8, sk: *mut u8, coins: *const u8) -> i32;
+}
+
+///
+/// Generates Kyber1024 keypair deterministically: same seed always gives same keypair.
+/// Randomness of key generation is derived from seed(see expand_keygen_seed).
+///
+/// # Warning
+/// For tests and reproducible provisioning ONLY. Anyone knowing the seed can recompute
+/// the secret key, so seed must be kept as secret as the key itself.
+///
+/// # Arguments
+/// * seed: &[u8]: seed of keypair
+///
+pub fn generate_kyber1024_keypair_from_seed(seed: &[u8]) -> (kyber1024::PublicKey, kyber1024::SecretKey) {
+    let mut coins = [0u8; KYBER1024_KEYGEN_COINS_SIZE];
+    expand_keygen_seed(seed, b"kyber1024 keygen", &mut coins);
+    let mut public_key = vec![0u8; kyber1024::public_key_bytes()];
+    let mut secret_key = vec![0u8; kyber1024::secret_key_bytes()];
+    let result = unsafe {
+        PQCLEAN_KYBER1024_CLEAN_crypto_kem_keypair_derand(public_key.as_mut_ptr(), secret_key.as_mut_ptr(),
+                                                          coins.as_ptr())
+    };
+    assert_eq!(result, 0, "Kyber1024 keygen failed");
+    (kyber1024::PublicKey::from_bytes(&public_key).unwrap(), kyber1024::SecretKey::from_bytes(&secret_key).unwrap())
+}
+
 /* Tests begin here */
 #[cfg(test)]
 mod tests {
@@ -154,7 +230,7 @@ mod tests {
     }
     #[test]
     fn test_serialize_deserialize_kyber1024_public_key() {
-        let (public_key, _secret_key) = kyber1024::keypair();
+        let (public_key, _secret_key) = generate_kyber1024_keypair_from_seed(b"kyber1024-keys-test");
         let serialized = public_key.serialize();
         let (deserialized, size) = kyber1024::PublicKey::from_serialized(&serialized).unwrap();
         assert!(public_key == deserialized);
@@ -163,7 +239,7 @@ mod tests {
 
     #[test]
     fn test_serialize_deserialize_kyber1024_secret_key() {
-        let (_public_key, secret_key) = kyber1024::keypair();
+        let (_public_key, secret_key) = generate_kyber1024_keypair_from_seed(b"kyber1024-keys-test");
         let serialized = secret_key.serialize();
         let (deserialized, size) = kyber1024::SecretKey::from_serialized(&serialized).unwrap();
         assert!(secret_key == deserialized);
@@ -172,7 +248,7 @@ mod tests {
 
     #[test]
     fn test_encrypt_decrypt_kyber1024_aes256gcm() {
-        let (public_key, secret_key) = kyber1024::keypair();
+        let (public_key, secret_key) = generate_kyber1024_keypair_from_seed(b"kyber1024-keys-test");
         let data = b"secret datafj".to_vec().serialize();
 
         let encrypted_data = public_key.encrypt_raw(&data).unwrap();
@@ -183,7 +259,7 @@ mod tests {
 
     #[test]
     fn test_encrypt_decrypt_highlevel_kyber1024_aes256gcm(){
-        let (public_key, secret_key) = kyber1024::keypair();
+        let (public_key, secret_key) = generate_kyber1024_keypair_from_seed(b"kyber1024-keys-test");
         let data = TestStruct {
             data: "A quick brown fox jumps over a lazy dog".as_bytes().to_vec(),
         };
@@ -195,7 +271,7 @@ mod tests {
 
     #[test]
     fn test_invalid_encryption_with_private_key() {
-        let (_public_key, secret_key) = kyber1024::keypair();
+        let (_public_key, secret_key) = generate_kyber1024_keypair_from_seed(b"kyber1024-keys-test");
         let data = b"secret datax".to_vec();
 
         let result = std::panic::catch_unwind(|| {
@@ -207,7 +283,7 @@ mod tests {
 
     #[test]
     fn test_invalid_decryption_with_public_key() {
-        let (public_key, _secret_key) = kyber1024::keypair();
+        let (public_key, _secret_key) = generate_kyber1024_keypair_from_seed(b"kyber1024-keys-test");
         let data = b"secret data".to_vec();
 
         let result = std::panic::catch_unwind(|| {
@@ -219,10 +295,62 @@ mod tests {
 
     #[test]
     fn test_kyber1024_encryption_decryption_with_signature() {
-        let (public_key, secret_key) = kyber1024::keypair();
+        let (public_key, secret_key) = generate_kyber1024_keypair_from_seed(b"kyber1024-keys-test");
         let data = b"secret data".to_vec().serialize();
         let encrypted_data = public_key.encrypt_raw(&data).unwrap();
         let decrypted_data = secret_key.decrypt_raw(&encrypted_data).unwrap();
         assert_eq!(data, decrypted_data);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_kyber1024_keypair_from_seed() {
+        let (first_pk, first_sk) = generate_kyber1024_keypair_from_seed(b"seed");
+        let (second_pk, second_sk) = generate_kyber1024_keypair_from_seed(b"seed");
+        let (other_pk, _other_sk) = generate_kyber1024_keypair_from_seed(b"other seed");
+        assert!(first_pk == second_pk);
+        assert!(first_sk == second_sk);
+        assert!(first_pk != other_pk);
+        // Derived keys are valid keys of the pair
+        let data = b"secret data".to_vec().serialize();
+        let encrypted_data = first_pk.encrypt_raw(&data).unwrap();
+        assert_eq!(second_sk.decrypt_raw(&encrypted_data).unwrap(), data);
+    }
+
+    #[test]
+    fn test_kyber1024_decrypt_malformed_data() {
+        let (public_key, secret_key) = generate_kyber1024_keypair_from_seed(b"kyber1024-keys-test");
+        let encrypted_data = public_key.encrypt_raw(&b"secret data".to_vec().serialize()).unwrap();
+        // Every truncation of valid ciphertext
+        for length in 0..encrypted_data.len(){
+            assert!(secret_key.decrypt_raw(&encrypted_data[..length].to_vec()).is_err());
+        }
+        // Trailing garbage
+        let mut extended = encrypted_data.clone();
+        extended.push(0);
+        assert_eq!(secret_key.decrypt_raw(&extended), Err(CryptoError::FormatError));
+        // Kyber ciphertext of wrong length
+        let mut wrong_length = vec![0u8; 100].serialize();
+        wrong_length.extend(vec![0u8; 40].serialize());
+        assert_eq!(secret_key.decrypt_raw(&wrong_length), Err(CryptoError::FormatError));
+    }
+
+    #[test]
+    fn test_kyber1024_decrypt_fuzz() {
+        use rand::{Rng, SeedableRng};
+        use rand::rngs::StdRng;
+        let (public_key, secret_key) = generate_kyber1024_keypair_from_seed(b"kyber1024-keys-test");
+        let encrypted_data = public_key.encrypt_raw(&b"secret data".to_vec().serialize()).unwrap();
+        let mut rng = StdRng::seed_from_u64(0x4d57);
+        for _ in 0..256{
+            // Random bit flips in valid ciphertext must be detected
+            let mut corrupted = encrypted_data.clone();
+            let index = rng.gen_range(0..corrupted.len());
+            corrupted[index] ^= 1 << rng.gen_range(0..8);
+            assert!(secret_key.decrypt_raw(&corrupted).is_err());
+            // Random data must not panic
+            let length = rng.gen_range(0..2048);
+            let random: Vec<u8> = (0..length).map(|_| rng.gen()).collect();
+            assert!(secret_key.decrypt_raw(&random).is_err());
+        }
+    }
+}
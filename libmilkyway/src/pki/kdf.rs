@@ -0,0 +1,206 @@
+use std::fmt::{Display, Formatter};
+use aes_gcm::Aes256Gcm;
+use hmac::{Hmac, Mac};
+use hmac::digest::KeyInit;
+use sha2::{Sha256, Sha512};
+
+///
+/// Prefix of every label, so keys derived here never collide with keys derived by other protocols
+///
+const LABEL_PREFIX: &[u8] = b"milkyway v1 ";
+
+///
+/// Hash functions HKDF may be based on
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KdfHash{
+    Sha256,
+    Sha512,
+}
+
+impl KdfHash {
+    ///
+    /// Gets size of hash output in bytes
+    ///
+    #[inline]
+    pub fn get_size(&self) -> usize{
+        match self {
+            KdfHash::Sha256 => 32,
+            KdfHash::Sha512 => 64,
+        }
+    }
+
+    fn mac(&self, key: &[u8], parts: &[&[u8]]) -> Vec<u8>{
+        match self {
+            KdfHash::Sha256 => compute_mac::<Hmac<Sha256>>(key, parts),
+            KdfHash::Sha512 => compute_mac::<Hmac<Sha512>>(key, parts),
+        }
+    }
+}
+
+fn compute_mac<M: Mac + KeyInit>(key: &[u8], parts: &[&[u8]]) -> Vec<u8>{
+    let mut mac = <M as KeyInit>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    for part in parts{
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().to_vec()
+}
+
+///
+/// Errors of key derivation
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum KdfError{
+    /** HKDF can not produce more than 255 blocks of hash output **/
+    LengthTooLarge(usize),
+}
+
+impl Display for KdfError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KdfError::LengthTooLarge(length) => write!(f, "can not derive {} bytes of key material", length),
+        }
+    }
+}
+
+///
+/// HKDF-Extract(RFC 5869): concentrates entropy of input key material into pseudorandom key
+///
+/// # Arguments
+/// * hash: KdfHash: hash function
+/// * salt: &[u8]: optional salt, empty salt is replaced by zeroes of hash size
+/// * input: &[u8]: input key material, e.g. KEM shared secret
+///
+pub fn hkdf_extract(hash: KdfHash, salt: &[u8], input: &[u8]) -> Vec<u8>{
+    if salt.is_empty(){
+        return hash.mac(&vec![0u8; hash.get_size()], &[input]);
+    }
+    hash.mac(salt, &[input])
+}
+
+///
+/// HKDF-Expand(RFC 5869): expands pseudorandom key into output key material bound to info
+///
+/// # Arguments
+/// * hash: KdfHash: hash function
+/// * prk: &[u8]: pseudorandom key returned by hkdf_extract
+/// * info: &[u8]: context and application specific information
+/// * length: usize: size of output, at most 255 hash sizes
+///
+pub fn hkdf_expand(hash: KdfHash, prk: &[u8], info: &[u8], length: usize) -> Result<Vec<u8>, KdfError>{
+    if length > 255 * hash.get_size(){
+        return Err(KdfError::LengthTooLarge(length));
+    }
+    let mut output = Vec::with_capacity(length);
+    let mut block = Vec::new();
+    let mut counter = 1u8;
+    while output.len() < length{
+        block = hash.mac(prk, &[&block, info, &[counter]]);
+        output.extend_from_slice(&block[..block.len().min(length - output.len())]);
+        counter = counter.wrapping_add(1);
+    }
+    Ok(output)
+}
+
+///
+/// HKDF-Extract followed by HKDF-Expand
+///
+pub fn hkdf(hash: KdfHash, salt: &[u8], input: &[u8], info: &[u8], length: usize) -> Result<Vec<u8>, KdfError>{
+    hkdf_expand(hash, &hkdf_extract(hash, salt, input), info, length)
+}
+
+///
+/// Derives AES-256 key from passphrase with PBKDF2-HMAC-SHA256(RFC 8018). Key is as long as hash,
+/// so only the first block is computed.
+///
+/// # Arguments
+/// * passphrase: &str: passphrase entered by user
+/// * salt: &[u8]: random salt kept with encrypted data
+/// * iterations: u32: count of iterations, see secrets::DEFAULT_KDF_ITERATIONS
+///
+pub fn derive_passphrase_key(passphrase: &str, salt: &[u8], iterations: u32) -> aes_gcm::Key<Aes256Gcm>{
+    let mac = <Hmac<Sha256> as KeyInit>::new_from_slice(passphrase.as_bytes()).expect("HMAC accepts keys of any size");
+    let mut block = mac.clone().chain_update(salt).chain_update(1u32.to_be_bytes()).finalize().into_bytes();
+    let mut key = block;
+    for _ in 1..iterations{
+        block = mac.clone().chain_update(block).finalize().into_bytes();
+        key.iter_mut().zip(block.iter()).for_each(|(key, byte)| *key ^= byte);
+    }
+    *aes_gcm::Key::<Aes256Gcm>::from_slice(&key)
+}
+
+///
+/// Derives key for a labeled purpose with HKDF-SHA256. Label and context are length-prefixed
+/// in info, so different purposes and contexts never give the same key.
+///
+/// # Arguments
+/// * label: &str: purpose of key, e.g. "session"
+/// * secret: &[u8]: input key material
+/// * salt: &[u8]: optional salt
+/// * context: &[u8]: data key is bound to, e.g. serials and epoch
+///
+pub fn derive_labeled_key(label: &str, secret: &[u8], salt: &[u8], context: &[u8]) -> aes_gcm::Key<Aes256Gcm>{
+    let mut info = Vec::with_capacity(LABEL_PREFIX.len() + label.len() + context.len() + 8);
+    info.extend_from_slice(&((LABEL_PREFIX.len() + label.len()) as u32).to_be_bytes());
+    info.extend_from_slice(LABEL_PREFIX);
+    info.extend_from_slice(label.as_bytes());
+    info.extend_from_slice(&(context.len() as u32).to_be_bytes());
+    info.extend_from_slice(context);
+    let key = hkdf(KdfHash::Sha256, salt, secret, &info, 32).expect("32 bytes are within HKDF limits");
+    *aes_gcm::Key::<Aes256Gcm>::from_slice(&key)
+}
+
+///
+/// Derives key of a session from KEM shared secret
+///
+/// # Arguments
+/// * shared_secret: &[u8]: secret agreed by both sides
+/// * encryption_serial: u128: serial of encryption certificate secret was encapsulated to
+/// * epoch: u32: number of session key within connection
+///
+pub fn derive_session_key(shared_secret: &[u8], encryption_serial: u128, epoch: u32) -> aes_gcm::Key<Aes256Gcm>{
+    let mut context = encryption_serial.to_be_bytes().to_vec();
+    context.extend_from_slice(&epoch.to_be_bytes());
+    derive_labeled_key("session", shared_secret, &[], &context)
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(data: &str) -> Vec<u8>{
+        (0..data.len()).step_by(2).map(|index| u8::from_str_radix(&data[index..index + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_hkdf_rfc5869() {
+        // Test case 1 of RFC 5869
+        let input = from_hex("0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b");
+        let salt = from_hex("000102030405060708090a0b0c");
+        let info = from_hex("f0f1f2f3f4f5f6f7f8f9");
+        let prk = hkdf_extract(KdfHash::Sha256, &salt, &input);
+        assert_eq!(prk, from_hex("077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5"));
+        assert_eq!(hkdf_expand(KdfHash::Sha256, &prk, &info, 42).unwrap(),
+                   from_hex("3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"));
+        // Test case 3: empty salt and info
+        assert_eq!(hkdf(KdfHash::Sha256, &[], &input, &[], 42).unwrap(),
+                   from_hex("8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d9d201395faa4b61a96c8"));
+        assert_eq!(hkdf(KdfHash::Sha512, &salt, &input, &info, 200).unwrap().len(), 200);
+        assert_eq!(hkdf_expand(KdfHash::Sha256, &prk, &info, 255 * 32 + 1), Err(KdfError::LengthTooLarge(8161)));
+    }
+
+    #[test]
+    fn test_domain_separation() {
+        let secret = [7u8; 32];
+        let session = derive_session_key(&secret, 5, 0);
+        assert_eq!(session, derive_session_key(&secret, 5, 0));
+        assert_ne!(session, derive_session_key(&secret, 5, 1));
+        assert_ne!(session, derive_session_key(&secret, 6, 0));
+        let mut context = 5u128.to_be_bytes().to_vec();
+        context.extend_from_slice(&0u32.to_be_bytes());
+        assert_eq!(session, derive_labeled_key("session", &secret, &[], &context));
+        assert_ne!(session, derive_labeled_key("group", &secret, &[], &context));
+        assert_ne!(session, derive_labeled_key("session", &secret, b"salt", &context));
+    }
+}
@@ -117,6 +117,7 @@ pub trait CryptoKey: Serializable + Deserializable{
             algorithm: hash_type,
             crypto_algorithm: self.get_crypto_type(),
             serialized_signature: encrypted.unwrap(),
+            context: None,
         })
     }
 
@@ -0,0 +1,266 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use libmilkyway_derive::{Deserializable, Serializable};
+use crate::pki::certificate::Certificate;
+use crate::pki::hash::HashType;
+use crate::pki::impls::keys::falcon1024::{Falcon1024PublicKey, Falcon1024SecretKey};
+use crate::pki::kdf::derive_passphrase_key;
+use crate::pki::key::CryptoKey;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::errors::ErrorCode;
+
+///
+/// Marker at the beginning of files with exported secret key
+///
+pub const KEY_EXPORT_MAGIC: [u8; 8] = *b"MWAYSKEY";
+
+///
+/// Version of format of exported secret key written after magic. MUST be bumped whenever
+/// EncryptedSecretKey or its plaintext changes.
+///
+pub const KEY_EXPORT_VERSION: u16 = 1;
+
+///
+/// Environment variable with passphrase of exported keys, used by certman if passphrase
+/// is not given as argument
+///
+pub const KEY_PASSPHRASE_VARIABLE: &str = "MWAY_KEY_PASSPHRASE";
+
+const SALT_LENGTH: usize = 16;
+
+///
+/// Errors of exporting and importing secret keys
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeyExportError{
+    /** Certificate has no secret key to export **/
+    NoSecretKey(u128),
+    /** File is not an exported secret key or is truncated **/
+    Malformed,
+    /** File was written by newer version **/
+    UnsupportedVersion(u16),
+    /** Wrong passphrase or file was tampered **/
+    DecryptionFailed,
+    /** Key is exported for another certificate **/
+    SerialMismatch{ expected: u128, actual: u128 },
+    /** Key does not match public key of certificate **/
+    KeyMismatch(u128),
+    Io(String),
+}
+
+impl Display for KeyExportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyExportError::NoSecretKey(serial) => write!(f, "certificate {} has no secret key", serial),
+            KeyExportError::Malformed => write!(f, "file is not an exported secret key"),
+            KeyExportError::UnsupportedVersion(version) =>
+                write!(f, "version {} of exported key is not supported, latest is {}", version, KEY_EXPORT_VERSION),
+            KeyExportError::DecryptionFailed => write!(f, "wrong passphrase or file is damaged"),
+            KeyExportError::SerialMismatch{ expected, actual } =>
+                write!(f, "key is exported for certificate {}, not {}", actual, expected),
+            KeyExportError::KeyMismatch(serial) =>
+                write!(f, "key does not match public key of certificate {}", serial),
+            KeyExportError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl ErrorCode for KeyExportError {
+    fn get_error_code(&self) -> &'static str {
+        match self {
+            KeyExportError::NoSecretKey(_) => "MW-KEY-0001",
+            KeyExportError::Malformed => "MW-KEY-0002",
+            KeyExportError::UnsupportedVersion(_) => "MW-KEY-0003",
+            KeyExportError::DecryptionFailed => "MW-KEY-0004",
+            KeyExportError::SerialMismatch{ .. } => "MW-KEY-0005",
+            KeyExportError::KeyMismatch(_) => "MW-KEY-0006",
+            KeyExportError::Io(_) => "MW-KEY-0007",
+        }
+    }
+}
+
+///
+/// What is encrypted: key with identity of certificate it belongs to, so header of file can not
+/// be swapped without notice
+///
+#[derive(Clone, Serializable, Deserializable)]
+struct SecretKeyPlaintext{
+    serial: u128,
+    fingerprint: String,
+    secret_key: Falcon1024SecretKey,
+}
+
+///
+/// Falcon1024 secret key encrypted with AES-256-GCM under key derived from passphrase
+/// with PBKDF2-HMAC-SHA256. Allows to move a key alone between hosts, e.g. when duties of CA
+/// are split, while certificate itself is distributed as usual.
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable)]
+pub struct EncryptedSecretKey{
+    /** Serial of certificate key belongs to **/
+    pub serial: u128,
+    /** Fingerprint of certificate key belongs to **/
+    pub fingerprint: String,
+    pub iterations: u32,
+    pub salt: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+}
+
+impl EncryptedSecretKey {
+    ///
+    /// Encrypts secret key of certificate
+    ///
+    /// # Arguments
+    /// * certificate: &C: signing or root certificate with secret key
+    /// * passphrase: &str: passphrase key is encrypted with
+    /// * iterations: u32: PBKDF2 iterations, see secrets::DEFAULT_KDF_ITERATIONS
+    ///
+    pub fn encrypt<C: Certificate<Falcon1024PublicKey, Falcon1024SecretKey>>(certificate: &C, passphrase: &str,
+                                                                            iterations: u32) -> Result<EncryptedSecretKey, KeyExportError>{
+        let secret_key = certificate.get_secret_key().ok_or(KeyExportError::NoSecretKey(certificate.get_serial()))?;
+        let plaintext = SecretKeyPlaintext{
+            serial: certificate.get_serial(),
+            fingerprint: certificate.get_fingerprint(),
+            secret_key,
+        };
+        let salt: [u8; SALT_LENGTH] = rand::random();
+        let ciphertext = derive_passphrase_key(passphrase, &salt, iterations).encrypt_raw(&plaintext.serialize())
+            .expect("AES-256-GCM encryption does not fail");
+        Ok(EncryptedSecretKey{
+            serial: plaintext.serial,
+            fingerprint: plaintext.fingerprint,
+            iterations,
+            salt: salt.to_vec(),
+            ciphertext,
+        })
+    }
+
+    ///
+    /// Decrypts secret key and checks that it belongs to certificate
+    ///
+    /// # Arguments
+    /// * certificate: &C: certificate key is imported for
+    /// * passphrase: &str: passphrase key was encrypted with
+    ///
+    /// returns: Result<Falcon1024SecretKey, KeyExportError>: key which signatures are verified
+    /// by public key of certificate
+    ///
+    pub fn decrypt<C: Certificate<Falcon1024PublicKey, Falcon1024SecretKey>>(&self, certificate: &C, passphrase: &str)
+        -> Result<Falcon1024SecretKey, KeyExportError>{
+        let serial = certificate.get_serial();
+        if self.serial != serial{
+            return Err(KeyExportError::SerialMismatch{ expected: serial, actual: self.serial });
+        }
+        if self.fingerprint != certificate.get_fingerprint(){
+            return Err(KeyExportError::KeyMismatch(serial));
+        }
+        if self.iterations == 0 || self.salt.len() != SALT_LENGTH{
+            return Err(KeyExportError::Malformed);
+        }
+        let decrypted = derive_passphrase_key(passphrase, &self.salt, self.iterations).decrypt_raw(&self.ciphertext)
+            .map_err(|_| KeyExportError::DecryptionFailed)?;
+        let (plaintext, size) = SecretKeyPlaintext::from_serialized(&decrypted)
+            .map_err(|_| KeyExportError::Malformed)?;
+        if size != decrypted.len() || plaintext.serial != self.serial || plaintext.fingerprint != self.fingerprint{
+            return Err(KeyExportError::Malformed);
+        }
+        if !is_key_pair(&certificate.get_public_key(), &plaintext.secret_key){
+            return Err(KeyExportError::KeyMismatch(serial));
+        }
+        Ok(plaintext.secret_key)
+    }
+
+    ///
+    /// Saves key to file prefixed with KEY_EXPORT_MAGIC and KEY_EXPORT_VERSION
+    ///
+    pub fn dump_to_file(&self, file_name: &str) -> Result<(), KeyExportError>{
+        let mut data = KEY_EXPORT_MAGIC.to_vec();
+        data.extend(KEY_EXPORT_VERSION.serialize());
+        data.extend(self.serialize());
+        std::fs::write(file_name, data).map_err(|error| KeyExportError::Io(error.to_string()))
+    }
+
+    ///
+    /// Reads key from file, rejecting unknown formats and trailing data
+    ///
+    pub fn read_from_file(path: &Path) -> Result<EncryptedSecretKey, KeyExportError>{
+        let data = std::fs::read(path).map_err(|error| KeyExportError::Io(error.to_string()))?;
+        Self::from_bytes(&data)
+    }
+
+    ///
+    /// Parses contents of exported key file
+    ///
+    pub fn from_bytes(data: &[u8]) -> Result<EncryptedSecretKey, KeyExportError>{
+        let data = data.strip_prefix(&KEY_EXPORT_MAGIC).ok_or(KeyExportError::Malformed)?.to_vec();
+        let (version, offset) = u16::from_serialized(&data).map_err(|_| KeyExportError::Malformed)?;
+        if version != KEY_EXPORT_VERSION{
+            return Err(KeyExportError::UnsupportedVersion(version));
+        }
+        let (key, size) = EncryptedSecretKey::from_serialized(&data[offset..].to_vec())
+            .map_err(|_| KeyExportError::Malformed)?;
+        if offset + size != data.len(){
+            return Err(KeyExportError::Malformed);
+        }
+        Ok(key)
+    }
+}
+
+///
+/// Checks that secret key makes signatures verified by public key
+///
+pub fn is_key_pair(public_key: &Falcon1024PublicKey, secret_key: &Falcon1024SecretKey) -> bool{
+    let challenge: [u8; 32] = rand::random();
+    match secret_key.sign(&challenge.to_vec(), HashType::None) {
+        Ok(signature) => public_key.verify_signature(&challenge.to_vec(), &signature),
+        Err(_) => false,
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::certificate::test_certificates;
+
+    #[test]
+    fn test_secret_key_export() {
+        let certificates = test_certificates();
+        let exported = EncryptedSecretKey::encrypt(&certificates.signing, "correct horse", 10).unwrap();
+        assert_eq!(EncryptedSecretKey::encrypt(&certificates.signing.clone_without_sk(), "correct horse", 10),
+                   Err(KeyExportError::NoSecretKey(certificates.signing.get_serial())));
+        let file = std::env::temp_dir().join(format!("milkyway-key-{}.sk", rand::random::<u64>()));
+        exported.dump_to_file(file.to_str().unwrap()).unwrap();
+        let loaded = EncryptedSecretKey::read_from_file(&file).unwrap();
+        std::fs::remove_file(file).unwrap();
+        assert_eq!(loaded, exported);
+
+        let public_only = certificates.signing.clone_without_sk();
+        let secret_key = loaded.decrypt(&public_only, "correct horse").unwrap();
+        assert!(secret_key == certificates.signing.get_secret_key().unwrap());
+        assert!(!is_key_pair(&certificates.root.public_key, &secret_key));
+        assert_eq!(loaded.decrypt(&public_only, "wrong").err(), Some(KeyExportError::DecryptionFailed));
+        assert!(matches!(loaded.decrypt(&certificates.root, "correct horse"),
+                         Err(KeyExportError::SerialMismatch{ .. })));
+        // Key of another certificate with the same serial is rejected
+        let mut impostor = certificates.signing.clone_without_sk();
+        impostor.public_key = certificates.root.public_key.clone();
+        assert_eq!(loaded.decrypt(&impostor, "correct horse").err(),
+                   Some(KeyExportError::KeyMismatch(impostor.get_serial())));
+
+        let mut data = KEY_EXPORT_MAGIC.to_vec();
+        data.extend(2u16.serialize());
+        data.extend(exported.serialize());
+        assert_eq!(EncryptedSecretKey::from_bytes(&data), Err(KeyExportError::UnsupportedVersion(2)));
+        let mut data = KEY_EXPORT_MAGIC.to_vec();
+        data.extend(KEY_EXPORT_VERSION.serialize());
+        data.extend(exported.serialize());
+        data.push(0);
+        assert_eq!(EncryptedSecretKey::from_bytes(&data), Err(KeyExportError::Malformed));
+        let mut tampered = exported;
+        tampered.ciphertext[0] ^= 1;
+        assert_eq!(tampered.decrypt(&public_only, "correct horse").err(), Some(KeyExportError::DecryptionFailed));
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt::{Display, Formatter};
+use libmilkyway_derive::{Deserializable, Serializable};
+use crate::secrets::wipe;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+
+///
+/// Errors of splitting and combining secrets
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShamirError{
+    /** Threshold must be at least 1 and not greater than count of shares **/
+    InvalidThreshold{ threshold: u8, shares: u8 },
+    /** Less shares than threshold are given **/
+    NotEnoughShares{ threshold: u8, provided: usize },
+    /** Two shares have the same index **/
+    DuplicateShare(u8),
+    /** Shares belong to different splits **/
+    InconsistentShares,
+}
+
+impl Display for ShamirError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShamirError::InvalidThreshold{ threshold, shares } =>
+                write!(f, "threshold {} is not between 1 and count of shares {}", threshold, shares),
+            ShamirError::NotEnoughShares{ threshold, provided } =>
+                write!(f, "{} shares are required, {} provided", threshold, provided),
+            ShamirError::DuplicateShare(index) => write!(f, "share {} is given twice", index),
+            ShamirError::InconsistentShares => write!(f, "shares belong to different secrets"),
+        }
+    }
+}
+
+///
+/// One share of secret: value of polynomial of each secret byte at point `index`
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable)]
+pub struct SecretShare{
+    /** Point shares are evaluated at, never 0 **/
+    pub index: u8,
+    /** Count of shares required to combine secret **/
+    pub threshold: u8,
+    pub data: Vec<u8>,
+}
+
+impl SecretShare {
+    ///
+    /// Overwrites data of share with zeros, see secrets::wipe
+    ///
+    pub fn wipe(&mut self){
+        wipe(&mut self.data);
+    }
+}
+
+// Multiplication in GF(2^8) with reduction polynomial of AES(x^8 + x^4 + x^3 + x + 1)
+fn gf_multiply(mut a: u8, mut b: u8) -> u8{
+    let mut product = 0u8;
+    while b != 0{
+        if b & 1 != 0{
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry{
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+// Inverse in GF(2^8), a^254 as multiplicative group has order 255
+fn gf_inverse(a: u8) -> u8{
+    let mut result = 1u8;
+    let mut base = a;
+    let mut exponent = 254u8;
+    while exponent != 0{
+        if exponent & 1 != 0{
+            result = gf_multiply(result, base);
+        }
+        base = gf_multiply(base, base);
+        exponent >>= 1;
+    }
+    result
+}
+
+///
+/// Splits secret into shares, any `threshold` of them restore it while less reveal nothing
+///
+/// # Arguments
+/// * secret: &[u8]: secret to split
+/// * threshold: u8: count of shares required to combine secret
+/// * shares: u8: count of shares to create, at most 255
+///
+/// returns: Result<Vec<SecretShare>, ShamirError>: shares with indexes from 1 to `shares`
+///
+pub fn split_secret(secret: &[u8], threshold: u8, shares: u8) -> Result<Vec<SecretShare>, ShamirError>{
+    if threshold == 0 || threshold > shares{
+        return Err(ShamirError::InvalidThreshold{ threshold, shares });
+    }
+    let mut result: Vec<SecretShare> = (1..=shares).map(|index| SecretShare{
+        index,
+        threshold,
+        data: Vec::with_capacity(secret.len()),
+    }).collect();
+    for byte in secret{
+        // Coefficients of polynomial, constant one is secret byte
+        let mut coefficients = vec![*byte];
+        coefficients.extend((1..threshold).map(|_| rand::random::<u8>()));
+        for share in result.iter_mut(){
+            // Horner's scheme
+            let value = coefficients.iter().rev()
+                .fold(0u8, |value, coefficient| gf_multiply(value, share.index) ^ coefficient);
+            share.data.push(value);
+        }
+    }
+    Ok(result)
+}
+
+///
+/// Restores secret from shares created by split_secret
+///
+/// # Arguments
+/// * shares: &[SecretShare]: at least `threshold` shares of the same split, extra ones are
+///   ignored
+///
+pub fn combine_shares(shares: &[SecretShare]) -> Result<Vec<u8>, ShamirError>{
+    let threshold = match shares.first() {
+        Some(share) => share.threshold,
+        None => return Err(ShamirError::NotEnoughShares{ threshold: 1, provided: 0 }),
+    };
+    if shares.len() < threshold as usize{
+        return Err(ShamirError::NotEnoughShares{ threshold, provided: shares.len() });
+    }
+    for (position, share) in shares.iter().enumerate(){
+        if share.index == 0 || share.threshold != threshold || share.data.len() != shares[0].data.len(){
+            return Err(ShamirError::InconsistentShares);
+        }
+        if shares[..position].iter().any(|other| other.index == share.index){
+            return Err(ShamirError::DuplicateShare(share.index));
+        }
+    }
+    let shares = &shares[..threshold as usize];
+    // Lagrange basis polynomials at point 0, subtraction is XOR in GF(2^8)
+    let basis: Vec<u8> = shares.iter().map(|share| {
+        shares.iter().filter(|other| other.index != share.index)
+            .fold(1u8, |value, other| gf_multiply(value, gf_multiply(other.index,
+                                                                      gf_inverse(other.index ^ share.index))))
+    }).collect();
+    Ok((0..shares[0].data.len()).map(|position| {
+        shares.iter().zip(basis.iter())
+            .fold(0u8, |value, (share, basis)| value ^ gf_multiply(share.data[position], *basis))
+    }).collect())
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shamir() {
+        for a in 1..=255u8{
+            assert_eq!(gf_multiply(a, gf_inverse(a)), 1);
+        }
+        let secret = b"root secret key".to_vec();
+        let shares = split_secret(&secret, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+        assert_eq!(combine_shares(&shares[..3]).unwrap(), secret);
+        assert_eq!(combine_shares(&[shares[4].clone(), shares[1].clone(), shares[3].clone()]).unwrap(), secret);
+        assert_eq!(combine_shares(&shares).unwrap(), secret);
+
+        assert_eq!(combine_shares(&shares[..2]), Err(ShamirError::NotEnoughShares{ threshold: 3, provided: 2 }));
+        assert_eq!(combine_shares(&[shares[0].clone(), shares[1].clone(), shares[0].clone()]),
+                   Err(ShamirError::DuplicateShare(1)));
+        let other = split_secret(&secret, 2, 3).unwrap();
+        assert_eq!(combine_shares(&[shares[0].clone(), shares[1].clone(), other[2].clone()]),
+                   Err(ShamirError::InconsistentShares));
+        assert_eq!(split_secret(&secret, 4, 3), Err(ShamirError::InvalidThreshold{ threshold: 4, shares: 3 }));
+        assert_eq!(split_secret(&secret, 0, 3), Err(ShamirError::InvalidThreshold{ threshold: 0, shares: 3 }));
+        assert_eq!(combine_shares(&split_secret(&secret, 1, 1).unwrap()).unwrap(), secret);
+    }
+}
@@ -1,18 +1,532 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
 use crate::serialization::error::SerializationError;
 use crate::serialization::deserializable::Deserializable;
 use crate::serialization::serializable::Serializable;
-use libmilkyway_derive::{Deserializable, Serializable};
-use crate::pki::hash::HashType;
-use crate::pki::impls::CryptoType;
+use libmilkyway_derive::{Describe, Deserializable, Serializable};
+use crate::get_timestamp_with_milliseconds;
+use crate::pki::certificate::Certificate;
+use crate::pki::hash::{Hash, HashType, Hasher};
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+use crate::pki::impls::{CryptoError, CryptoType};
 use crate::serialization::serializable::Serialized;
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
 
 
+///
+/// Label of context of file signatures
+///
+pub const FILE_SIGNATURE_LABEL: &str = "file";
+
+///
+/// Label of context of message signatures
+///
+pub const MESSAGE_SIGNATURE_LABEL: &str = "message";
+
+/** Set in serialized hash type of signatures followed by context **/
+const SIGNATURE_CONTEXT_FLAG: u8 = 0x80;
+
+///
+/// Who made signature, when and for what. Context is signed together with data, so it can not
+/// be changed or moved to signature made for another purpose.
+///
+#[derive(Clone, Serializable, Deserializable, PartialEq, Debug, Describe)]
+pub struct SignatureContext{
+    /** Serial of certificate which made signature **/
+    pub signer_serial: u128,
+    /** When signature was made, milliseconds since UNIX epoch **/
+    pub timestamp: u128,
+    /** Purpose of signature, e.g. FILE_SIGNATURE_LABEL **/
+    pub label: String,
+}
+
+impl SignatureContext {
+    ///
+    /// Creates context of signature made now
+    ///
+    pub fn new(signer_serial: u128, label: &str) -> SignatureContext{
+        SignatureContext{
+            signer_serial,
+            timestamp: get_timestamp_with_milliseconds(),
+            label: label.to_string(),
+        }
+    }
+
+    ///
+    /// Gets canonical bytes actually signed for data: serialized data followed by context
+    ///
+    pub fn bind<T: Serializable>(&self, data: &T) -> Serialized{
+        let mut result = data.serialize();
+        result.extend(self.serialize());
+        result
+    }
+}
+
 ///
 /// Signature with metadata
 ///
-#[derive(Clone, Serializable, Deserializable, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Describe)]
 pub struct Signature {
     pub algorithm: HashType,
     pub crypto_algorithm: CryptoType,
     pub serialized_signature: Serialized,
-}
\ No newline at end of file
+    /** Written after signature only when set and flagged in byte of hash type, None for signatures made by keys **/
+    pub context: Option<SignatureContext>,
+}
+
+impl Signature {
+    ///
+    /// Gets serial of certificate which made signature if it is embedded
+    ///
+    #[inline]
+    pub fn get_signer_serial(&self) -> Option<u128>{
+        self.context.as_ref().map(|context| context.signer_serial)
+    }
+
+    ///
+    /// Gets time signature was made at if it is embedded
+    ///
+    #[inline]
+    pub fn get_timestamp(&self) -> Option<u128>{
+        self.context.as_ref().map(|context| context.timestamp)
+    }
+
+    ///
+    /// Checks whether signature has context with given label. Signatures without context are
+    /// not bound to any purpose and never match, see is_made_for_or_legacy.
+    ///
+    #[inline]
+    pub fn is_made_for(&self, label: &str) -> bool{
+        self.context.as_ref().is_some_and(|context| context.label == label)
+    }
+
+    ///
+    /// Checks whether signature has context with given label or has no context at all, as
+    /// signatures made by keys directly. Such signatures may be moved between purposes, so only
+    /// callers which explicitly accept legacy signatures may use it.
+    ///
+    #[inline]
+    pub fn is_made_for_or_legacy(&self, label: &str) -> bool{
+        self.context.as_ref().is_none_or(|context| context.label == label)
+    }
+
+    ///
+    /// Gets length of serialized signature without parsing it, so signed data embedded into
+    /// signature is not copied, e.g. when only header of message is read
+    ///
+    /// # Arguments
+    /// * serialized: &[u8]: data starting with serialized signature
+    ///
+    /// returns: Result<usize, SerializationError>: length or LengthError if signature is truncated
+    ///
+    pub fn get_serialized_length(serialized: &[u8]) -> Result<usize, SerializationError>{
+        if serialized.is_empty(){
+            return Err(SerializationError::LengthError);
+        }
+        let has_context = serialized[0] & SIGNATURE_CONTEXT_FLAG != 0;
+        // Hash type and crypto type take one byte each
+        let mut offset = skip_sized(serialized, 2)?;
+        if has_context{
+            // Serial and timestamp of context are followed by its label
+            offset = skip_sized(serialized, offset + 2 * size_of::<u128>())?;
+        }
+        Ok(offset)
+    }
+}
+
+// Skips vector or string at offset: its length followed by its bytes, returns offset after it
+fn skip_sized(serialized: &[u8], offset: usize) -> Result<usize, SerializationError>{
+    let start = offset + size_of::<usize>();
+    let length = match serialized.get(offset..start) {
+        Some(length) => usize::from_le_bytes(length.try_into().unwrap()),
+        None => return Err(SerializationError::LengthError),
+    };
+    match start.checked_add(length) {
+        Some(end) if end <= serialized.len() => Ok(end),
+        _ => Err(SerializationError::LengthError),
+    }
+}
+
+// Signatures without context keep their original encoding, so signatures stored in
+// certificates, exports and files before context was added are still readable
+impl Serializable for Signature {
+    fn serialize(&self) -> Serialized {
+        let mut result = self.algorithm.serialize();
+        if self.context.is_some(){
+            result[0] |= SIGNATURE_CONTEXT_FLAG;
+        }
+        result.extend(self.crypto_algorithm.serialize());
+        result.extend(self.serialized_signature.serialize());
+        if let Some(context) = &self.context{
+            result.extend(context.serialize());
+        }
+        result
+    }
+}
+
+impl Deserializable for Signature {
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        if serialized.is_empty(){
+            return Err(SerializationError::LengthError);
+        }
+        let has_context = serialized[0] & SIGNATURE_CONTEXT_FLAG != 0;
+        let (algorithm, _) = HashType::from_serialized(&vec![serialized[0] & !SIGNATURE_CONTEXT_FLAG])?;
+        let mut offset = 1;
+        let (crypto_algorithm, size) = CryptoType::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (serialized_signature, size) = Serialized::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let context = if has_context {
+            let (context, size) = SignatureContext::from_serialized(&serialized[offset..].to_vec())?;
+            offset += size;
+            Some(context)
+        } else {
+            None
+        };
+        Ok((Signature{
+            algorithm,
+            crypto_algorithm,
+            serialized_signature,
+            context,
+        }, offset))
+    }
+}
+
+
+///
+/// Detached signature of a file. Falcon1024 signatures embed signed data, so hash of file
+/// is signed instead of its contents, and file is hashed in a streaming fashion.
+///
+#[derive(Clone, Serializable, Deserializable, PartialEq, Debug)]
+pub struct FileSignature {
+    /** Serial of signing certificate which signed file **/
+    pub signer_serial: u128,
+    /** Algorithm used to hash contents of file **/
+    pub hash_type: HashType,
+    pub signature: Signature,
+}
+
+impl FileSignature {
+    ///
+    /// Signs hash of file
+    ///
+    /// # Arguments
+    /// * hash: &Hash: hash of contents of file, e.g. from Hasher::hash_reader
+    /// * certificate: &Falcon1024Certificate: signing certificate with secret key
+    ///
+    /// returns: Result<FileSignature, CryptoError>: signature or ArgumentError if hash is a
+    /// placeholder of HashType::None or certificate has no secret key
+    ///
+    pub fn sign(hash: &Hash, certificate: &Falcon1024Certificate) -> Result<FileSignature, CryptoError>{
+        if hash.algorithm == HashType::None{
+            return Err(CryptoError::ArgumentError("Files must be hashed with a real hash algorithm"));
+        }
+        Ok(FileSignature{
+            signer_serial: certificate.get_serial(),
+            hash_type: hash.algorithm.clone(),
+            signature: certificate.sign_data_with_context(hash, HashType::None, FILE_SIGNATURE_LABEL)?,
+        })
+    }
+
+    ///
+    /// Gets serial of signer, embedded one is preferred since it is covered by signature
+    ///
+    #[inline]
+    pub fn get_signer_serial(&self) -> u128{
+        self.signature.get_signer_serial().unwrap_or(self.signer_serial)
+    }
+
+    ///
+    /// Verifies signature against hash of file
+    ///
+    /// # Arguments
+    /// * hash: &Hash: hash of contents of file computed with hash_type of signature
+    /// * certificate: &Falcon1024Certificate: certificate of signer
+    ///
+    pub fn verify(&self, hash: &Hash, certificate: &Falcon1024Certificate) -> bool{
+        hash.algorithm == self.hash_type && certificate.get_serial() == self.signer_serial
+            && self.signature.is_made_for(FILE_SIGNATURE_LABEL) && certificate.verify_signature(hash, &self.signature)
+    }
+}
+
+///
+/// Marker at the beginning of files with ChunkedFileSignature, FileSignature has no marker
+///
+pub const CHUNKED_SIGNATURE_MAGIC: [u8; 8] = *b"MWAYCSIG";
+
+///
+/// Default size of chunks hashed independently, large enough to keep signature files small
+///
+pub const DEFAULT_SIGNATURE_CHUNK_SIZE: u64 = 16 * 1024 * 1024;
+
+///
+/// Checks whether contents of signature file is a ChunkedFileSignature
+///
+#[inline]
+pub fn is_chunked_signature(data: &[u8]) -> bool{
+    data.starts_with(&CHUNKED_SIGNATURE_MAGIC)
+}
+
+///
+/// Hashes chunks of file on several threads. Every worker reads chunks with its own handle,
+/// so chunks are hashed in parallel while hashes are kept in order of file.
+///
+/// # Arguments
+/// * path: &Path: file to hash
+/// * hash_type: HashType: hashing algorithm
+/// * chunk_size: u64: size of every chunk but the last one
+/// * jobs: usize: count of worker threads, at least one is used
+///
+/// returns: std::io::Result<(Vec<Hash>, u64)>: hashes of chunks and size of file
+///
+pub fn hash_file_chunks(path: &Path, hash_type: HashType, chunk_size: u64,
+                        jobs: usize) -> std::io::Result<(Vec<Hash>, u64)>{
+    if chunk_size == 0{
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "chunk size must be positive"));
+    }
+    let length = std::fs::metadata(path)?.len();
+    let count = length.div_ceil(chunk_size);
+    let next_chunk = AtomicU64::new(0);
+    let hashes: Mutex<Vec<Option<Hash>>> = Mutex::new(vec![None; count as usize]);
+    let hash_chunks = || -> std::io::Result<()>{
+        let mut file = File::open(path)?;
+        loop {
+            let index = next_chunk.fetch_add(1, Ordering::Relaxed);
+            if index >= count{
+                return Ok(());
+            }
+            let offset = index * chunk_size;
+            file.seek(SeekFrom::Start(offset))?;
+            let mut hasher = Hasher::new(hash_type.clone());
+            let hashed = hasher.update_from_reader(&mut (&mut file).take(chunk_size))?;
+            if hashed != chunk_size.min(length - offset){
+                return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "file changed while hashing"));
+            }
+            hashes.lock().unwrap()[index as usize] = Some(hasher.finalize());
+        }
+    };
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.clamp(1, count.max(1) as usize))
+            .map(|_| scope.spawn(hash_chunks))
+            .collect();
+        workers.into_iter()
+            .map(|worker| worker.join().expect("Hashing worker panicked"))
+            .collect::<std::io::Result<Vec<()>>>()
+    })?;
+    let hashes = hashes.into_inner().unwrap().into_iter()
+        .map(|hash| hash.expect("Every chunk is hashed"))
+        .collect();
+    Ok((hashes, length))
+}
+
+///
+/// Detached signature of a file hashed by chunks(see hash_file_chunks). Hashes of chunks are
+/// kept in order of file and one signature covers all of them, so large files are hashed on
+/// several threads and damaged chunks can be pointed out on verification.
+///
+#[derive(Clone, Serializable, Deserializable, PartialEq, Debug)]
+pub struct ChunkedFileSignature{
+    /** Serial of signing certificate which signed file **/
+    pub signer_serial: u128,
+    pub hash_type: HashType,
+    pub chunk_size: u64,
+    /** Size of file in bytes **/
+    pub length: u64,
+    /** Hashes of chunks in order of file **/
+    pub chunk_hashes: Vec<Hash>,
+    pub signature: Signature,
+}
+
+impl ChunkedFileSignature {
+    ///
+    /// Gets hash signed by signature: it covers chunk size, size of file and hashes of all chunks
+    ///
+    fn get_manifest_hash(hash_type: HashType, chunk_size: u64, length: u64, chunk_hashes: &[Hash]) -> Hash{
+        let mut hasher = Hasher::new(hash_type);
+        hasher.update(&CHUNKED_SIGNATURE_MAGIC)
+            .update(&chunk_size.to_be_bytes())
+            .update(&length.to_be_bytes());
+        for hash in chunk_hashes{
+            hasher.update(&hash.hash);
+        }
+        hasher.finalize()
+    }
+
+    ///
+    /// Signs hashes of chunks of file
+    ///
+    /// # Arguments
+    /// * chunk_hashes: Vec<Hash>: hashes returned by hash_file_chunks
+    /// * chunk_size: u64: size of chunks file was hashed by
+    /// * length: u64: size of file
+    /// * certificate: &Falcon1024Certificate: signing certificate with secret key
+    ///
+    /// returns: Result<ChunkedFileSignature, CryptoError>: signature or ArgumentError if chunks
+    /// are hashed with HashType::None or do not match size of file
+    ///
+    pub fn sign(chunk_hashes: Vec<Hash>, chunk_size: u64, length: u64,
+                certificate: &Falcon1024Certificate) -> Result<ChunkedFileSignature, CryptoError>{
+        if chunk_size == 0 || length.div_ceil(chunk_size) != chunk_hashes.len() as u64{
+            return Err(CryptoError::ArgumentError("Count of chunk hashes does not match size of file"));
+        }
+        let hash_type = chunk_hashes.first().map_or(HashType::SHA512, |hash| hash.algorithm.clone());
+        if hash_type == HashType::None || chunk_hashes.iter().any(|hash| hash.algorithm != hash_type){
+            return Err(CryptoError::ArgumentError("Files must be hashed with a real hash algorithm"));
+        }
+        let manifest = Self::get_manifest_hash(hash_type.clone(), chunk_size, length, &chunk_hashes);
+        Ok(ChunkedFileSignature{
+            signer_serial: certificate.get_serial(),
+            hash_type,
+            chunk_size,
+            length,
+            chunk_hashes,
+            signature: certificate.sign_data_with_context(&manifest, HashType::None, FILE_SIGNATURE_LABEL)?,
+        })
+    }
+
+    ///
+    /// Gets serial of signer, see FileSignature::get_signer_serial
+    ///
+    #[inline]
+    pub fn get_signer_serial(&self) -> u128{
+        self.signature.get_signer_serial().unwrap_or(self.signer_serial)
+    }
+
+    ///
+    /// Verifies that hashes of chunks kept in signature are signed by certificate
+    ///
+    pub fn verify_manifest(&self, certificate: &Falcon1024Certificate) -> bool{
+        let manifest = Self::get_manifest_hash(self.hash_type.clone(), self.chunk_size, self.length,
+                                               &self.chunk_hashes);
+        certificate.get_serial() == self.signer_serial && self.signature.is_made_for(FILE_SIGNATURE_LABEL)
+            && certificate.verify_signature(&manifest, &self.signature)
+    }
+
+    ///
+    /// Finds chunks of file which differ from signed ones
+    ///
+    /// # Arguments
+    /// * chunk_hashes: &[Hash]: hashes of file computed with chunk_size and hash_type of signature
+    /// * length: u64: size of file
+    ///
+    /// returns: Vec<usize>: indexes of damaged chunks, chunks missing on either side included
+    ///
+    pub fn find_mismatches(&self, chunk_hashes: &[Hash], length: u64) -> Vec<usize>{
+        let count = self.chunk_hashes.len().max(chunk_hashes.len());
+        let mut mismatches: Vec<usize> = (0..count)
+            .filter(|index| self.chunk_hashes.get(*index) != chunk_hashes.get(*index))
+            .collect();
+        // Truncation within the last chunk changes its hash, so only size is left to check
+        if mismatches.is_empty() && length != self.length{
+            mismatches.push(count.saturating_sub(1));
+        }
+        mismatches
+    }
+
+    ///
+    /// Verifies signature against hashes of file
+    ///
+    pub fn verify(&self, chunk_hashes: &[Hash], length: u64, certificate: &Falcon1024Certificate) -> bool{
+        self.find_mismatches(chunk_hashes, length).is_empty() && self.verify_manifest(certificate)
+    }
+
+    ///
+    /// Serializes signature prefixed with CHUNKED_SIGNATURE_MAGIC
+    ///
+    pub fn to_bytes(&self) -> Serialized{
+        let mut data = CHUNKED_SIGNATURE_MAGIC.to_vec();
+        data.extend(self.serialize());
+        data
+    }
+
+    ///
+    /// Parses contents of signature file written by to_bytes
+    ///
+    pub fn from_bytes(data: &[u8]) -> Result<ChunkedFileSignature, SerializationError>{
+        let data = data.strip_prefix(&CHUNKED_SIGNATURE_MAGIC)
+            .ok_or(SerializationError::InvalidDataError("Not a chunked file signature"))?.to_vec();
+        let (signature, size) = ChunkedFileSignature::from_serialized(&data)?;
+        if size != data.len(){
+            return Err(SerializationError::InvalidDataError("Trailing data after chunked file signature"));
+        }
+        Ok(signature)
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::certificate::test_certificates;
+
+    #[test]
+    fn test_file_signature() {
+        let certificate = test_certificates().signing;
+        let contents = vec![42u8; 200000];
+        let hash = Hasher::hash_reader(HashType::SHA512, &mut contents.as_slice()).unwrap();
+        let signature = FileSignature::sign(&hash, &certificate).unwrap();
+        let (signature, _) = FileSignature::from_serialized(&signature.serialize()).unwrap();
+        assert!(signature.verify(&hash, &certificate));
+        assert_eq!(signature.get_signer_serial(), certificate.get_serial());
+        assert_eq!(signature.signature.context.as_ref().unwrap().label, FILE_SIGNATURE_LABEL);
+        // Signature made for another purpose or moved to another certificate is rejected
+        let mut relabeled = signature.clone();
+        relabeled.signature.context.as_mut().unwrap().label = MESSAGE_SIGNATURE_LABEL.to_string();
+        assert!(!relabeled.verify(&hash, &certificate));
+        let mut reserialed = signature.signature.clone();
+        reserialed.context.as_mut().unwrap().signer_serial += 1;
+        assert!(!certificate.verify_signature(&hash, &reserialed));
+        // Signatures without context keep their encoding
+        let legacy = certificate.sign_data(&hash, HashType::None).unwrap();
+        let serialized = legacy.serialize();
+        assert_eq!(serialized[0], 0);
+        assert_eq!(Signature::from_serialized(&serialized).unwrap(), (legacy, serialized.len()));
+
+        let mut tampered = contents.clone();
+        tampered[100000] = 0;
+        let tampered = Hasher::hash_reader(HashType::SHA512, &mut tampered.as_slice()).unwrap();
+        assert!(!signature.verify(&tampered, &certificate));
+        assert!(FileSignature::sign(&Hasher::new(HashType::None).finalize(), &certificate).is_err());
+    }
+
+    #[test]
+    fn test_chunked_file_signature() {
+        let certificate = test_certificates().signing;
+        let path = std::env::temp_dir().join(format!("milkyway-chunked-{}", rand::random::<u64>()));
+        let contents: Vec<u8> = (0..100000u32).map(|index| (index % 251) as u8).collect();
+        std::fs::write(&path, &contents).unwrap();
+        let (hashes, length) = hash_file_chunks(&path, HashType::SHA512, 4096, 4).unwrap();
+        assert_eq!(length, 100000);
+        assert_eq!(hashes.len(), 25);
+        // Order of chunks does not depend on count of workers
+        assert_eq!(hash_file_chunks(&path, HashType::SHA512, 4096, 1).unwrap().0, hashes);
+        assert_eq!(hashes[3], Hasher::hash_reader(HashType::SHA512, &mut &contents[3 * 4096..4 * 4096]).unwrap());
+
+        let signature = ChunkedFileSignature::sign(hashes.clone(), 4096, length, &certificate).unwrap();
+        let data = signature.to_bytes();
+        assert!(is_chunked_signature(&data));
+        let signature = ChunkedFileSignature::from_bytes(&data).unwrap();
+        assert!(signature.verify(&hashes, length, &certificate));
+
+        let mut tampered = contents.clone();
+        tampered[50000] ^= 1;
+        std::fs::write(&path, &tampered).unwrap();
+        let (tampered_hashes, _) = hash_file_chunks(&path, HashType::SHA512, 4096, 3).unwrap();
+        assert_eq!(signature.find_mismatches(&tampered_hashes, length), vec![12]);
+        assert!(!signature.verify(&tampered_hashes, length, &certificate));
+        std::fs::write(&path, &contents[..99999]).unwrap();
+        let (truncated, truncated_length) = hash_file_chunks(&path, HashType::SHA512, 4096, 2).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(signature.find_mismatches(&truncated, truncated_length), vec![24]);
+
+        let mut forged = signature.clone();
+        forged.chunk_hashes[0] = tampered_hashes[12].clone();
+        assert!(!forged.verify_manifest(&certificate));
+        assert!(ChunkedFileSignature::sign(hashes[1..].to_vec(), 4096, length, &certificate).is_err());
+        assert!(ChunkedFileSignature::from_bytes(&FileSignature::sign(&hashes[0], &certificate).unwrap().serialize())
+            .is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use crate::pki::certificate::Certificate;
+use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use crate::pki::kdf::derive_passphrase_key;
+use crate::pki::key::CryptoKey;
+use crate::serialization::serializable::Serialized;
+use crate::services::certificate::CertificateService;
+
+///
+/// Prefix of encrypted configuration values
+///
+pub const SECRET_PREFIX: &str = "enc:";
+
+///
+/// Environment variable with passphrase of values encrypted with a derived key
+///
+pub const PASSPHRASE_VARIABLE: &str = "MWAY_CONFIG_PASSPHRASE";
+
+///
+/// PBKDF2-HMAC-SHA256 iterations used for new values
+///
+pub const DEFAULT_KDF_ITERATIONS: u32 = 600_000;
+
+const SALT_LENGTH: usize = 16;
+
+///
+/// Reasons why encrypted value can not be resolved
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum SecretError{
+    /** Value starts with prefix, but is not a valid encrypted value **/
+    Malformed,
+    /** Encryption certificate with secret key is not in store **/
+    UnknownCertificate(u128),
+    /** Value is encrypted with passphrase, but passphrase is not provided **/
+    NoPassphrase,
+    /** Wrong key or value was tampered **/
+    DecryptionFailed,
+}
+
+impl Display for SecretError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecretError::Malformed => write!(f, "malformed encrypted value"),
+            SecretError::UnknownCertificate(serial) =>
+                write!(f, "encryption certificate {} with secret key is not found", serial),
+            SecretError::NoPassphrase => write!(f, "passphrase is not set in {}", PASSPHRASE_VARIABLE),
+            SecretError::DecryptionFailed => write!(f, "value can not be decrypted"),
+        }
+    }
+}
+
+fn to_hex(data: &[u8]) -> String{
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(data: &str) -> Option<Vec<u8>>{
+    if !data.len().is_multiple_of(2) || !data.is_ascii(){
+        return None;
+    }
+    (0..data.len()).step_by(2).map(|index| u8::from_str_radix(&data[index..index + 2], 16).ok()).collect()
+}
+
+///
+/// Checks whether configuration value is encrypted
+///
+#[inline]
+pub fn is_encrypted(value: &str) -> bool{
+    value.starts_with(SECRET_PREFIX)
+}
+
+///
+/// Encrypts value to encryption certificate, only holder of its secret key can resolve it
+///
+/// returns: String: value in format `enc:cert:<serial>:<hex>`
+///
+pub fn encrypt_with_certificate(value: &str, certificate: &Kyber1024Certificate) -> String{
+    let encrypted = certificate.encrypt(&value.to_string()).expect("Kyber1024 encryption does not fail");
+    format!("{}cert:{}:{}", SECRET_PREFIX, certificate.get_serial(), to_hex(&encrypted))
+}
+
+///
+/// Encrypts value with key derived from passphrase
+///
+/// returns: String: value in format `enc:kdf:<iterations>:<salt>:<hex>`
+///
+pub fn encrypt_with_passphrase(value: &str, passphrase: &str, iterations: u32) -> String{
+    let salt: [u8; SALT_LENGTH] = rand::random();
+    let encrypted = derive_passphrase_key(passphrase, &salt, iterations).encrypt_raw(&value.as_bytes().to_vec())
+        .expect("AES-256-GCM encryption does not fail");
+    format!("{}kdf:{}:{}:{}", SECRET_PREFIX, iterations, to_hex(&salt), to_hex(&encrypted))
+}
+
+///
+/// Overwrites secret with zeros, so it does not stay in memory after use
+///
+pub fn wipe(data: &mut [u8]){
+    for byte in data.iter_mut(){
+        // Volatile write can not be optimized out as a dead store
+        #[allow(unsafe_code)]
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+}
+
+///
+/// Decrypts encrypted configuration values with keys of local node.
+///
+/// Values may be encrypted either to encryption certificate of node(see encrypt_with_certificate)
+/// or with passphrase given in MWAY_CONFIG_PASSPHRASE(see encrypt_with_passphrase).
+///
+#[derive(Default)]
+pub struct SecretResolver{
+    passphrase: Option<String>,
+    certificates: HashMap<u128, Kyber1024Certificate>,
+}
+
+impl SecretResolver {
+    pub fn new() -> SecretResolver{
+        SecretResolver::default()
+    }
+
+    ///
+    /// Creates resolver with passphrase from environment if it is set
+    ///
+    pub fn from_environment() -> SecretResolver{
+        SecretResolver{
+            passphrase: std::env::var(PASSPHRASE_VARIABLE).ok().filter(|passphrase| !passphrase.is_empty()),
+            certificates: HashMap::new(),
+        }
+    }
+
+    pub fn set_passphrase(&mut self, passphrase: &str) -> &mut Self{
+        self.passphrase = Some(passphrase.to_string());
+        self
+    }
+
+    ///
+    /// Adds encryption certificate, it is used only if it has secret key
+    ///
+    pub fn add_certificate(&mut self, certificate: Kyber1024Certificate) -> &mut Self{
+        if certificate.get_secret_key().is_some(){
+            self.certificates.insert(certificate.get_serial(), certificate);
+        }
+        self
+    }
+
+    ///
+    /// Adds all encryption certificates with secret keys from store
+    ///
+    pub fn add_certificates_from<S: CertificateService + ?Sized>(&mut self, service: &mut S) -> &mut Self{
+        for certificate in service.get_encryption_certificates(){
+            self.add_certificate(certificate);
+        }
+        self
+    }
+
+    ///
+    /// Resolves configuration value
+    ///
+    /// returns: Result<String, SecretError>: decrypted value or value itself if it is not encrypted
+    ///
+    pub fn resolve(&self, value: &str) -> Result<String, SecretError>{
+        let encrypted = match value.strip_prefix(SECRET_PREFIX) {
+            Some(encrypted) => encrypted,
+            None => return Ok(value.to_string()),
+        };
+        let parts: Vec<&str> = encrypted.split(':').collect();
+        match parts.as_slice() {
+            ["cert", serial, data] => {
+                let serial = serial.parse::<u128>().map_err(|_| SecretError::Malformed)?;
+                let data: Serialized = from_hex(data).ok_or(SecretError::Malformed)?;
+                let certificate = self.certificates.get(&serial).ok_or(SecretError::UnknownCertificate(serial))?;
+                certificate.decrypt::<String>(&data).map_err(|_| SecretError::DecryptionFailed)
+            }
+            ["kdf", iterations, salt, data] => {
+                let iterations = iterations.parse::<u32>().ok().filter(|iterations| *iterations > 0)
+                    .ok_or(SecretError::Malformed)?;
+                let salt = from_hex(salt).ok_or(SecretError::Malformed)?;
+                let data: Serialized = from_hex(data).ok_or(SecretError::Malformed)?;
+                let passphrase = self.passphrase.as_ref().ok_or(SecretError::NoPassphrase)?;
+                let decrypted = derive_passphrase_key(passphrase, &salt, iterations).decrypt_raw(&data)
+                    .map_err(|_| SecretError::DecryptionFailed)?;
+                String::from_utf8(decrypted).map_err(|_| SecretError::DecryptionFailed)
+            }
+            _ => Err(SecretError::Malformed),
+        }
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::certificate::{MockCertificateService, TEST_ENCRYPTION_CERTIFICATE_SERIAL};
+
+    #[test]
+    fn test_resolve_secrets() {
+        let mut service = MockCertificateService::with_test_certificates();
+        let certificate = service.get_encryption_certificate(TEST_ENCRYPTION_CERTIFICATE_SERIAL).unwrap();
+        let by_certificate = encrypt_with_certificate("psk-1", &certificate.clone_without_sk());
+        let by_passphrase = encrypt_with_passphrase("token-2", "correct horse", 10);
+        assert!(is_encrypted(&by_certificate) && is_encrypted(&by_passphrase));
+        let mut resolver = SecretResolver::new();
+        assert_eq!(resolver.resolve("plain"), Ok("plain".to_string()));
+        assert_eq!(resolver.resolve(&by_certificate),
+                   Err(SecretError::UnknownCertificate(TEST_ENCRYPTION_CERTIFICATE_SERIAL)));
+        assert_eq!(resolver.resolve(&by_passphrase), Err(SecretError::NoPassphrase));
+        resolver.add_certificates_from(&mut service).set_passphrase("wrong");
+        assert_eq!(resolver.resolve(&by_certificate), Ok("psk-1".to_string()));
+        assert_eq!(resolver.resolve(&by_passphrase), Err(SecretError::DecryptionFailed));
+        resolver.set_passphrase("correct horse");
+        assert_eq!(resolver.resolve(&by_passphrase), Ok("token-2".to_string()));
+        assert_eq!(resolver.resolve("enc:kdf:10:zz:00"), Err(SecretError::Malformed));
+        assert_eq!(resolver.resolve("enc:other"), Err(SecretError::Malformed));
+    }
+
+    #[test]
+    fn test_derive_key() {
+        // PBKDF2-HMAC-SHA256 test vector(RFC 7914, section 11)
+        let key = derive_passphrase_key("passwd", b"salt", 1);
+        assert_eq!(to_hex(&key), "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc");
+    }
+}
@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 use std::hash::Hash;
+use std::rc::Rc;
+use std::sync::Arc;
 use crate::serialization::deserializable::Deserializable;
 use crate::serialization::error::SerializationError;
 use crate::serialization::error::SerializationError::{InvalidDataError, LengthError};
@@ -8,6 +10,20 @@ use crate::serialization::serializable::{Serializable, Serialized};
 pub mod serializable;
 pub mod deserializable;
 pub mod error;
+pub mod decimal;
+pub mod migration;
+pub mod schema;
+
+///
+/// Version of wire format of messages and certificates. MUST be bumped whenever
+/// serialization of any of them changes, see testing::wire
+///
+pub const WIRE_FORMAT_VERSION: u32 = 2;
+
+///
+/// Oldest wire format version which current version is able to read
+///
+pub const MIN_COMPATIBLE_WIRE_FORMAT_VERSION: u32 = 1;
 
 
 macro_rules! int_type_serializable_deserializable {
@@ -35,6 +51,46 @@ macro_rules! int_type_serializable_deserializable {
 
 int_type_serializable_deserializable!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, usize);
 
+///
+/// IEEE-754 floats are serialized as little-endian bits.
+///
+/// # NaN canonicalization
+/// All NaNs are serialized as canonical quiet NaN, so same logical value always gives
+/// same bytes(important for signatures). NaN payloads are therefore not preserved and
+/// NaNs with any other bit pattern are rejected on deserialization.
+/// Zero sign is preserved: 0.0 and -0.0 have different representations. For values
+/// which must compare equal when bytes are equal use FixedDecimal.
+///
+macro_rules! float_type_serializable_deserializable {
+    ($($t:ty),*) => {
+        $(
+            impl Serializable for $t {
+                fn serialize(&self) -> Serialized {
+                    let value = if self.is_nan() { <$t>::NAN } else { *self };
+                    value.to_bits().to_le_bytes().to_vec()
+                }
+            }
+
+            impl Deserializable for $t {
+                fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+                    let size = std::mem::size_of::<$t>();
+                    if serialized.len() < size {
+                        return Err(SerializationError::LengthError);
+                    }
+                    let bytes: [u8; std::mem::size_of::<$t>()] = serialized[..size].try_into().map_err(|_| SerializationError::InvalidDataError("Read out of bytes"))?;
+                    let value = <$t>::from_le_bytes(bytes);
+                    if value.is_nan() && value.to_bits() != <$t>::NAN.to_bits() {
+                        return Err(SerializationError::InvalidDataError("Non-canonical NaN"));
+                    }
+                    Ok((value, size))
+                }
+            }
+        )*
+    }
+}
+
+float_type_serializable_deserializable!(f32, f64);
+
 impl<T> Serializable for Vec<T> where T: Serializable{
     fn serialize(&self) -> Serialized {
         let mut result = Serialized::new();
@@ -67,15 +123,81 @@ impl<T> Deserializable for Vec<T> where T: Deserializable{
     }
 }
 
-impl<T> Serializable for Option<T> where T: Serializable + Clone {
+///
+/// Arrays have fixed size, so unlike vectors they are serialized without length
+///
+impl<T, const N: usize> Serializable for [T; N] where T: Serializable{
     fn serialize(&self) -> Serialized {
-        if self.is_none(){
-            Serialized::from(&[0])
-        } else {
-            let mut result = Serialized::from(&[1]);
-            let serialized_internal = self.clone().unwrap().serialize();
-            result.extend(serialized_internal);
-            result
+        let mut result = Serialized::new();
+        for element in self.iter(){
+            result.extend(element.serialize());
+        }
+        result
+    }
+}
+
+impl<T, const N: usize> Deserializable for [T; N] where T: Deserializable{
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        let mut elements = Vec::<T>::with_capacity(N);
+        let mut offset = 0;
+        for _ in 0..N{
+            let (element, element_offset) = T::from_serialized(&serialized[offset..].to_vec())?;
+            elements.push(element);
+            offset += element_offset;
+        }
+        let result: [T; N] = elements.try_into()
+            .map_err(|_| SerializationError::InvalidDataError("Wrong number of array elements"))?;
+        Ok((result, offset))
+    }
+}
+
+macro_rules! tuple_serializable_deserializable {
+    ($(($($name:ident: $index:tt),+)),*) => {
+        $(
+            impl<$($name: Serializable),+> Serializable for ($($name,)+) {
+                fn serialize(&self) -> Serialized {
+                    let mut result = Serialized::new();
+                    $(result.extend(self.$index.serialize());)+
+                    result
+                }
+            }
+
+            impl<$($name: Deserializable),+> Deserializable for ($($name,)+) {
+                fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+                    let mut offset = 0;
+                    // Tuple elements are evaluated left to right, so offset advances in order
+                    let result = ($({
+                        let (element, element_offset) = $name::from_serialized(&serialized[offset..].to_vec())?;
+                        offset += element_offset;
+                        element
+                    },)+);
+                    Ok((result, offset))
+                }
+            }
+        )*
+    }
+}
+
+tuple_serializable_deserializable!(
+    (A: 0),
+    (A: 0, B: 1),
+    (A: 0, B: 1, C: 2),
+    (A: 0, B: 1, C: 2, D: 3),
+    (A: 0, B: 1, C: 2, D: 3, E: 4),
+    (A: 0, B: 1, C: 2, D: 3, E: 4, F: 5),
+    (A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6),
+    (A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7)
+);
+
+impl<T> Serializable for Option<T> where T: Serializable {
+    fn serialize(&self) -> Serialized {
+        match self {
+            None => Serialized::from(&[0]),
+            Some(value) => {
+                let mut result = Serialized::from(&[1]);
+                result.extend(value.serialize());
+                result
+            }
         }
     }
 }
@@ -99,6 +221,34 @@ impl<T> Deserializable for Option<T> where T: Deserializable{
     }
 }
 
+///
+/// Smart pointers are transparent: only value they point to is serialized, so
+/// `Box<T>`, `Rc<T>` and `Arc<T>` have same layout as `T`. Deserialization allocates
+/// new pointer, sharing between fields is not preserved.
+///
+macro_rules! pointer_serializable_deserializable {
+    ($($pointer:ident),*) => {
+        $(
+            impl<T: Serializable> Serializable for $pointer<T> {
+                #[inline]
+                fn serialize(&self) -> Serialized {
+                    (**self).serialize()
+                }
+            }
+
+            impl<T: Deserializable> Deserializable for $pointer<T> {
+                #[inline]
+                fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+                    let (value, offset) = T::from_serialized(serialized)?;
+                    Ok(($pointer::new(value), offset))
+                }
+            }
+        )*
+    }
+}
+
+pointer_serializable_deserializable!(Box, Rc, Arc);
+
 impl Serializable for bool {
     fn serialize(&self) -> Serialized {
         if *self{
@@ -188,6 +338,7 @@ impl Deserializable for String{
 
 
 /* Tests begin here */
+#[cfg(test)]
 mod tests {
     use libmilkyway_derive::{Deserializable, Serializable};
     use super::*;
@@ -220,6 +371,71 @@ mod tests {
         test_usize: usize
     );
 
+    #[test]
+    fn test_serialize_deserialize_floats() {
+        for value in [0.0f64, -0.0, 1.5, -273.15, f64::MAX, f64::MIN_POSITIVE, f64::INFINITY]{
+            let serialized = value.serialize();
+            let (deserialized, size) = f64::from_serialized(&serialized).unwrap();
+            assert_eq!(value.to_bits(), deserialized.to_bits());
+            assert_eq!(size, 8);
+        }
+        let value = 3.25f32;
+        assert_eq!(value.serialize(), vec![0, 0, 80, 64]);
+        assert_eq!(f32::from_serialized(&value.serialize()).unwrap(), (value, 4));
+    }
+
+    #[test]
+    fn test_float_nan_canonicalization() {
+        let other_nan = f64::from_bits(0x7ff8_0000_0000_0001);
+        assert!(other_nan.is_nan());
+        assert_eq!(other_nan.serialize(), f64::NAN.serialize());
+        assert_eq!((-f32::NAN).serialize(), f32::NAN.serialize());
+        assert!(f64::from_serialized(&other_nan.serialize()).unwrap().0.is_nan());
+        assert_eq!(f64::from_serialized(&other_nan.to_bits().to_le_bytes().to_vec()),
+                   Err(SerializationError::InvalidDataError("Non-canonical NaN")));
+        assert!(f32::from_serialized(&(-f32::NAN).to_bits().to_le_bytes().to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_serialize_deserialize_tuples() {
+        let value = (1u8, "two".to_string(), vec![3u32], Some(4i64));
+        let serialized = value.serialize();
+        let (deserialized, size) = <(u8, String, Vec<u32>, Option<i64>)>::from_serialized(&serialized).unwrap();
+        assert_eq!(value, deserialized);
+        assert_eq!(size, serialized.len());
+        let value = (1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8, 8u8);
+        assert_eq!(value.serialize(), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(<(u64, u64)>::from_serialized(&vec![0u8; 12]).is_err());
+    }
+
+    #[test]
+    fn test_serialize_deserialize_arrays() {
+        let value: [u16; 3] = [1, 2, 3];
+        let serialized = value.serialize();
+        assert_eq!(serialized.len(), 6);
+        assert_eq!(<[u16; 3]>::from_serialized(&serialized).unwrap(), (value, 6));
+        let value: [String; 2] = ["a".to_string(), "bc".to_string()];
+        let (deserialized, _) = <[String; 2]>::from_serialized(&value.serialize()).unwrap();
+        assert_eq!(value, deserialized);
+        assert!(<[u16; 4]>::from_serialized(&serialized).is_err());
+    }
+
+    #[derive(Debug, PartialEq, Serializable, Deserializable)]
+    struct Wrapper(u32, String);
+
+    #[derive(Debug, PartialEq, Serializable, Deserializable)]
+    struct Marker;
+
+    #[test]
+    fn test_derive_tuple_struct() {
+        let value = Wrapper(7, "seven".to_string());
+        let serialized = value.serialize();
+        assert_eq!(serialized, (7u32, "seven".to_string()).serialize());
+        assert_eq!(Wrapper::from_serialized(&serialized).unwrap(), (value, serialized.len()));
+        assert!(Marker.serialize().is_empty());
+        assert_eq!(Marker::from_serialized(&vec![]).unwrap(), (Marker, 0));
+    }
+
     #[test]
     fn test_length_error() {
         let serialized = vec![0u8; 1];
@@ -227,6 +443,17 @@ mod tests {
         assert!(matches!(result, Err(SerializationError::LengthError)));
     }
 
+    #[test]
+    fn test_deserialize_exact() {
+        use crate::serialization::deserializable::{deserialize_exact, ParsingMode};
+        let mut serialized = (7u32, "seven".to_string()).serialize();
+        assert_eq!(deserialize_exact::<(u32, String)>(&serialized).unwrap(), (7, "seven".to_string()));
+        serialized.extend([0, 0]);
+        assert_eq!(deserialize_exact::<(u32, String)>(&serialized), Err(SerializationError::TrailingDataError(2)));
+        assert_eq!(ParsingMode::Lenient.deserialize::<(u32, String)>(&serialized).unwrap().0, 7);
+        assert!(ParsingMode::Strict.deserialize::<(u32, String)>(&serialized).is_err());
+    }
+
     #[test]
     fn test_serialize_deserialize_vec_usize() {
         let vec: Vec<usize> = vec![1, 2, 3, 4, 5];
@@ -320,6 +547,46 @@ mod tests {
         assert!(matches!(result, Err(SerializationError::LengthError)));
     }
     
+    #[test]
+    fn test_serialize_deserialize_nested_option() {
+        for value in [None, Some(None), Some(Some(7u32))]{
+            let serialized = value.serialize();
+            let (deserialized, size) = Option::<Option<u32>>::from_serialized(&serialized).unwrap();
+            assert_eq!(deserialized, value);
+            assert_eq!(size, serialized.len());
+        }
+        assert_eq!(Some(None::<u32>).serialize(), vec![1, 0]);
+    }
+
+    #[derive(Serializable, Deserializable, Debug, PartialEq)]
+    struct PointerStruct{
+        boxed: Box<u32>,
+        shared: Arc<Vec<u8>>,
+        local: Rc<String>,
+        nested: Option<Box<PointerStruct>>,
+    }
+
+    #[test]
+    fn test_serialize_deserialize_pointers() {
+        let value = PointerStruct{
+            boxed: Box::new(1),
+            shared: Arc::new(vec![2, 3]),
+            local: Rc::new("four".to_string()),
+            nested: Some(Box::new(PointerStruct{
+                boxed: Box::new(5),
+                shared: Arc::new(vec![]),
+                local: Rc::new(String::new()),
+                nested: None,
+            })),
+        };
+        assert_eq!(Box::new(1u32).serialize(), 1u32.serialize());
+        assert_eq!(Arc::new(vec![2u8, 3]).serialize(), vec![2u8, 3].serialize());
+        let serialized = value.serialize();
+        let (deserialized, size) = PointerStruct::from_serialized(&serialized).unwrap();
+        assert_eq!(deserialized, value);
+        assert_eq!(size, serialized.len());
+    }
+
     #[test]
     fn test_serialize_true() {
         let value = true;
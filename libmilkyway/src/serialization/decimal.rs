@@ -0,0 +1,177 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+
+///
+/// Fixed-point decimal number with `SCALE` digits after decimal point.
+///
+/// Unlike floats, every value has exactly one representation(no NaNs or negative zero),
+/// so equal values always serialize to equal bytes and may be safely signed.
+/// Value is stored as an integer `raw = value * 10^SCALE`.
+///
+/// # Example
+/// `FixedDecimal::<3>::from_raw(12345)` is `12.345`
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct FixedDecimal<const SCALE: u32>(i64);
+
+impl<const SCALE: u32> FixedDecimal<SCALE> {
+    ///
+    /// Multiplier between value and raw representation
+    ///
+    pub const FACTOR: i64 = 10i64.pow(SCALE);
+
+    ///
+    /// Creates decimal from raw representation
+    ///
+    /// # Arguments
+    /// * raw: i64: value multiplied by 10^SCALE
+    ///
+    #[inline]
+    pub const fn from_raw(raw: i64) -> Self{
+        FixedDecimal(raw)
+    }
+
+    ///
+    /// Gets raw representation, i.e. value multiplied by 10^SCALE
+    ///
+    #[inline]
+    pub const fn get_raw(&self) -> i64{
+        self.0
+    }
+
+    ///
+    /// Converts float to decimal rounding it to SCALE digits
+    ///
+    /// returns: Option<FixedDecimal>: decimal or None if value is not finite or out of range
+    ///
+    pub fn from_f64(value: f64) -> Option<Self>{
+        let raw = (value * Self::FACTOR as f64).round();
+        if !raw.is_finite() || raw < i64::MIN as f64 || raw >= i64::MAX as f64{
+            return None;
+        }
+        Some(FixedDecimal(raw as i64))
+    }
+
+    ///
+    /// Converts decimal to float, precision may be lost
+    ///
+    #[inline]
+    pub fn to_f64(&self) -> f64{
+        self.0 as f64 / Self::FACTOR as f64
+    }
+
+    ///
+    /// Adds decimals
+    ///
+    /// returns: Option<FixedDecimal>: sum or None on overflow
+    ///
+    #[inline]
+    pub fn checked_add(&self, other: Self) -> Option<Self>{
+        self.0.checked_add(other.0).map(FixedDecimal)
+    }
+
+    ///
+    /// Subtracts decimals
+    ///
+    /// returns: Option<FixedDecimal>: difference or None on overflow
+    ///
+    #[inline]
+    pub fn checked_sub(&self, other: Self) -> Option<Self>{
+        self.0.checked_sub(other.0).map(FixedDecimal)
+    }
+}
+
+impl<const SCALE: u32> Display for FixedDecimal<SCALE> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let absolute = self.0.unsigned_abs();
+        let factor = Self::FACTOR as u64;
+        if SCALE == 0{
+            return write!(f, "{}{}", sign, absolute);
+        }
+        write!(f, "{}{}.{:0width$}", sign, absolute / factor, absolute % factor, width = SCALE as usize)
+    }
+}
+
+impl<const SCALE: u32> FromStr for FixedDecimal<SCALE> {
+    type Err = SerializationError;
+
+    ///
+    /// Parses decimal exactly, without going through floats. More than SCALE
+    /// fraction digits is an error.
+    ///
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match value.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, value),
+        };
+        let (integer, fraction) = match digits.split_once('.') {
+            Some((integer, fraction)) => (integer, fraction),
+            None => (digits, ""),
+        };
+        if integer.is_empty() || fraction.len() > SCALE as usize
+            || !integer.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()){
+            return Err(SerializationError::InvalidDataError("Not a decimal number"));
+        }
+        let padded = format!("{}{:0<width$}", integer, fraction, width = SCALE as usize);
+        let raw = i64::from_str(&padded)
+            .map_err(|_| SerializationError::InvalidDataError("Decimal number is out of range"))?;
+        Ok(FixedDecimal(if negative { -raw } else { raw }))
+    }
+}
+
+impl<const SCALE: u32> Serializable for FixedDecimal<SCALE> {
+    #[inline]
+    fn serialize(&self) -> Serialized {
+        self.0.serialize()
+    }
+}
+
+impl<const SCALE: u32> Deserializable for FixedDecimal<SCALE> {
+    #[inline]
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        let (raw, offset) = i64::from_serialized(serialized)?;
+        Ok((FixedDecimal(raw), offset))
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Money = FixedDecimal<2>;
+
+    #[test]
+    fn test_decimal_parse_and_display() {
+        assert_eq!(Money::from_str("12.3").unwrap(), Money::from_raw(1230));
+        assert_eq!(Money::from_str("-0.05").unwrap().to_string(), "-0.05");
+        assert_eq!(Money::from_str("7").unwrap().to_string(), "7.00");
+        assert!(Money::from_str("1.234").is_err());
+        assert!(Money::from_str("abc").is_err());
+        assert!(Money::from_str(".5").is_err());
+        assert_eq!(FixedDecimal::<0>::from_raw(-42).to_string(), "-42");
+    }
+
+    #[test]
+    fn test_decimal_is_bit_stable() {
+        let parsed = Money::from_str("0.10").unwrap();
+        let converted = Money::from_f64(0.1).unwrap();
+        let sum = Money::from_f64(0.07).unwrap().checked_add(Money::from_f64(0.03).unwrap()).unwrap();
+        assert_eq!(parsed.serialize(), converted.serialize());
+        assert_eq!(parsed.serialize(), sum.serialize());
+        assert_eq!(Money::from_serialized(&parsed.serialize()).unwrap(), (parsed, 8));
+    }
+
+    #[test]
+    fn test_decimal_from_f64_range() {
+        assert!(Money::from_f64(f64::NAN).is_none());
+        assert!(Money::from_f64(f64::INFINITY).is_none());
+        assert!(Money::from_f64(1e30).is_none());
+        assert_eq!(Money::from_f64(-1.005).unwrap().to_f64(), -1.0);
+        assert!(Money::from_raw(i64::MAX).checked_add(Money::from_raw(1)).is_none());
+    }
+}
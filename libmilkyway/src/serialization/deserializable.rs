@@ -48,4 +48,45 @@ pub trait Deserializable: Sized {
             Err(result.err().unwrap())
         }
     }
-}
\ No newline at end of file
+}
+
+///
+/// Deserializes data which must contain exactly one value
+///
+/// # Arguments
+/// * serialized: &Serialized: serialized value
+///
+/// returns: Result<T, SerializationError>: value or TrailingDataError with number of bytes
+/// left after it
+///
+pub fn deserialize_exact<T: Deserializable>(serialized: &Serialized) -> Result<T, SerializationError>{
+    let (value, size) = T::from_serialized(serialized)?;
+    if size < serialized.len(){
+        return Err(SerializationError::TrailingDataError(serialized.len() - size));
+    }
+    Ok(value)
+}
+
+///
+/// How parsers treat bytes left after value
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ParsingMode{
+    /** Bytes after value are ignored, e.g. fields appended by newer peers **/
+    #[default]
+    Lenient,
+    /** Bytes after value are an error(see deserialize_exact) **/
+    Strict,
+}
+
+impl ParsingMode {
+    ///
+    /// Deserializes value in this mode
+    ///
+    pub fn deserialize<T: Deserializable>(&self, serialized: &Serialized) -> Result<T, SerializationError>{
+        match self {
+            ParsingMode::Lenient => T::from_serialized(serialized).map(|(value, _)| value),
+            ParsingMode::Strict => deserialize_exact(serialized),
+        }
+    }
+}
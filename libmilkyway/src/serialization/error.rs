@@ -17,5 +17,10 @@ pub enum SerializationError {
     ///
     /// Cryptographic error during serialization of ciphertexts,etc.
     ///
-    CryptographicError(CryptoError)
+    CryptographicError(CryptoError),
+
+    ///
+    /// Data was parsed but number of bytes remained after it, e.g. because of framing bug
+    ///
+    TrailingDataError(usize),
 }
\ No newline at end of file
@@ -0,0 +1,409 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+
+///
+/// Magic bytes which start every versioned storage file
+///
+pub const STORAGE_MAGIC: [u8; 4] = *b"MWST";
+
+///
+/// Schema version of storage files written before versioning, they have no header
+///
+pub const LEGACY_SCHEMA_VERSION: u32 = 0;
+
+///
+/// Errors of loading and migrating storage files
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum MigrationError{
+    /** File can not be read or written **/
+    Io(PathBuf, String),
+    /** File was written by newer version of MilkyWay **/
+    NewerVersion{ store: &'static str, found: u32, supported: u32 },
+    /** File has older schema and must be migrated before loading **/
+    Outdated{ store: &'static str, found: u32, supported: u32 },
+    /** No step migrates from this version **/
+    MissingStep{ store: &'static str, version: u32 },
+    /** Migration step could not transform data **/
+    StepFailed{ store: &'static str, version: u32, error: SerializationError },
+    /** Migrated data can not be read as current schema **/
+    ValidationFailed{ store: &'static str },
+}
+
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            MigrationError::NewerVersion { store, found, supported } =>
+                write!(f, "{} storage has schema v{}, but only v{} is supported", store, found, supported),
+            MigrationError::Outdated { store, found, supported } =>
+                write!(f, "{} storage has schema v{} and must be migrated to v{}", store, found, supported),
+            MigrationError::MissingStep { store, version } =>
+                write!(f, "{} storage has no migration from schema v{}", store, version),
+            MigrationError::StepFailed { store, version, error } =>
+                write!(f, "{} storage migration from schema v{} failed: {:?}", store, version, error),
+            MigrationError::ValidationFailed { store } =>
+                write!(f, "{} storage can not be read after migration", store),
+        }
+    }
+}
+
+///
+/// A step transforming serialized store from one schema version to next one
+///
+#[derive(Clone, Copy)]
+pub struct MigrationStep{
+    /** Version step migrates from, result has version from_version + 1 **/
+    pub from_version: u32,
+    /** Human-readable description shown in migration preview **/
+    pub description: &'static str,
+    pub apply: fn(Serialized) -> Result<Serialized, SerializationError>,
+}
+
+impl MigrationStep {
+    pub fn new(from_version: u32, description: &'static str,
+               apply: fn(Serialized) -> Result<Serialized, SerializationError>) -> MigrationStep{
+        MigrationStep{
+            from_version,
+            description,
+            apply,
+        }
+    }
+}
+
+///
+/// A store persisted to a file with schema version header.
+///
+/// Whenever serialized layout of store changes, SCHEMA_VERSION must be bumped and
+/// a step migrating from previous version must be appended to get_migrations.
+///
+pub trait VersionedStorage: Serializable + Deserializable{
+    ///
+    /// Name of store shown in migration reports
+    ///
+    const STORE_NAME: &'static str;
+
+    ///
+    /// Current schema version of store
+    ///
+    const SCHEMA_VERSION: u32;
+
+    ///
+    /// Gets ordered migration steps. Default contains only step adding header to legacy files.
+    ///
+    fn get_migrations() -> Vec<MigrationStep>{
+        vec![MigrationStep::new(LEGACY_SCHEMA_VERSION, "Add schema version header", Ok)]
+    }
+}
+
+///
+/// Prepends schema version header to serialized store
+///
+pub fn encode_versioned(version: u32, payload: &Serialized) -> Serialized{
+    let mut result = Serialized::with_capacity(STORAGE_MAGIC.len() + 4 + payload.len());
+    result.extend(STORAGE_MAGIC);
+    result.extend(version.serialize());
+    result.extend(payload);
+    result
+}
+
+///
+/// Splits serialized file into schema version and payload. Files without header are legacy.
+///
+pub fn decode_versioned(data: Serialized) -> Result<(u32, Serialized), SerializationError>{
+    if !data.starts_with(&STORAGE_MAGIC){
+        return Ok((LEGACY_SCHEMA_VERSION, data));
+    }
+    let (version, size) = u32::from_serialized(&data[STORAGE_MAGIC.len()..].to_vec())?;
+    Ok((version, data[STORAGE_MAGIC.len() + size..].to_vec()))
+}
+
+fn read_file(path: &Path) -> Result<Serialized, MigrationError>{
+    fs::read(path).map_err(|error| MigrationError::Io(path.to_path_buf(), error.to_string()))
+}
+
+///
+/// Loads store from file written by dump_versioned
+///
+/// # Arguments
+/// * path: &Path: path to storage file
+///
+/// returns: Result<T, MigrationError>: store or error, outdated files are not migrated implicitly
+///
+pub fn load_versioned<T: VersionedStorage>(path: &Path) -> Result<T, MigrationError>{
+    let (version, payload) = decode_versioned(read_file(path)?)
+        .map_err(|error| MigrationError::StepFailed{ store: T::STORE_NAME, version: 0, error })?;
+    if version > T::SCHEMA_VERSION{
+        return Err(MigrationError::NewerVersion{ store: T::STORE_NAME, found: version, supported: T::SCHEMA_VERSION });
+    }
+    if version < T::SCHEMA_VERSION{
+        return Err(MigrationError::Outdated{ store: T::STORE_NAME, found: version, supported: T::SCHEMA_VERSION });
+    }
+    match T::from_serialized(&payload) {
+        Ok((store, _)) => Ok(store),
+        Err(_) => Err(MigrationError::ValidationFailed{ store: T::STORE_NAME }),
+    }
+}
+
+///
+/// Dumps store to file with header of current schema version
+///
+pub fn dump_versioned<T: VersionedStorage>(store: &T, file_name: &str) -> std::io::Result<usize>{
+    let data = encode_versioned(T::SCHEMA_VERSION, &store.serialize());
+    fs::write(file_name, &data)?;
+    Ok(data.len())
+}
+
+///
+/// Copies storage file to `<file>.v<version>.bak` before it is replaced
+///
+/// # Arguments
+/// * path: &Path: storage file
+/// * version: u32: schema version of file
+///
+/// returns: Result<PathBuf, MigrationError>: path to backup
+///
+pub fn backup_file(path: &Path, version: u32) -> Result<PathBuf, MigrationError>{
+    let mut backup = path.to_path_buf().into_os_string();
+    backup.push(format!(".v{}.bak", version));
+    let backup = PathBuf::from(backup);
+    fs::copy(path, &backup).map_err(|error| MigrationError::Io(backup.clone(), error.to_string()))?;
+    Ok(backup)
+}
+
+///
+/// Atomically replaces storage file: data is written to `<file>.tmp` which is renamed then
+///
+pub fn replace_file(path: &Path, data: &Serialized) -> Result<(), MigrationError>{
+    let mut temporary = path.to_path_buf().into_os_string();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    fs::write(&temporary, data).map_err(|error| MigrationError::Io(temporary.clone(), error.to_string()))?;
+    fs::rename(&temporary, path).map_err(|error| MigrationError::Io(path.to_path_buf(), error.to_string()))
+}
+
+///
+/// Result of migration of one store
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct MigrationReport{
+    pub store: &'static str,
+    pub path: PathBuf,
+    pub from_version: u32,
+    pub to_version: u32,
+    /** Descriptions of applied steps, empty if store is up to date **/
+    pub steps: Vec<&'static str>,
+    /** Copy of file before migration, None on dry run or if nothing changed **/
+    pub backup: Option<PathBuf>,
+}
+
+impl MigrationReport {
+    #[inline]
+    pub fn is_up_to_date(&self) -> bool{
+        self.steps.is_empty()
+    }
+}
+
+struct RegisteredStore{
+    name: &'static str,
+    path: PathBuf,
+    version: u32,
+    steps: Vec<MigrationStep>,
+    validate: fn(&Serialized) -> bool,
+}
+
+impl RegisteredStore {
+    ///
+    /// Migrates contents of file in memory
+    ///
+    fn migrate(&self, data: Serialized) -> Result<(MigrationReport, Serialized), MigrationError>{
+        let (from_version, mut payload) = decode_versioned(data)
+            .map_err(|error| MigrationError::StepFailed{ store: self.name, version: 0, error })?;
+        if from_version > self.version{
+            return Err(MigrationError::NewerVersion{ store: self.name, found: from_version, supported: self.version });
+        }
+        let mut applied = Vec::new();
+        for version in from_version..self.version{
+            let step = self.steps.iter().find(|step| step.from_version == version)
+                .ok_or(MigrationError::MissingStep{ store: self.name, version })?;
+            payload = (step.apply)(payload)
+                .map_err(|error| MigrationError::StepFailed{ store: self.name, version, error })?;
+            applied.push(step.description);
+        }
+        if !(self.validate)(&payload){
+            return Err(MigrationError::ValidationFailed{ store: self.name });
+        }
+        let report = MigrationReport{
+            store: self.name,
+            path: self.path.clone(),
+            from_version,
+            to_version: self.version,
+            steps: applied,
+            backup: None,
+        };
+        Ok((report, encode_versioned(self.version, &payload)))
+    }
+}
+
+///
+/// Applies migrations to storage files of registered stores.
+///
+/// Migration is transactional: all stores are migrated in memory first and nothing is
+/// written if any of them fails. Original files are kept as `<file>.v<version>.bak`, and
+/// if writing of some store fails, already written ones are restored from backups.
+///
+pub struct Migrator{
+    stores: Vec<RegisteredStore>,
+}
+
+impl Migrator {
+    pub fn new() -> Migrator{
+        Migrator{
+            stores: Vec::new(),
+        }
+    }
+
+    ///
+    /// Registers storage file of a store, missing files are skipped during migration
+    ///
+    /// # Arguments
+    /// * path: &Path: path to storage file
+    ///
+    pub fn register<T: VersionedStorage>(&mut self, path: &Path) -> &mut Migrator{
+        let mut steps = T::get_migrations();
+        steps.sort_by_key(|step| step.from_version);
+        self.stores.push(RegisteredStore{
+            name: T::STORE_NAME,
+            path: path.to_path_buf(),
+            version: T::SCHEMA_VERSION,
+            steps,
+            validate: |payload| T::from_serialized(payload).is_ok_and(|(_, size)| size == payload.len()),
+        });
+        self
+    }
+
+    ///
+    /// Migrates all registered stores to their current schema versions
+    ///
+    /// # Arguments
+    /// * dry_run: bool: only compute what would be changed without touching files
+    ///
+    /// returns: Result<Vec<MigrationReport>, MigrationError>: reports of existing stores
+    ///
+    pub fn run(&self, dry_run: bool) -> Result<Vec<MigrationReport>, MigrationError>{
+        let mut migrated = Vec::new();
+        for store in self.stores.iter().filter(|store| store.path.exists()){
+            migrated.push(store.migrate(read_file(&store.path)?)?);
+        }
+        if dry_run{
+            return Ok(migrated.into_iter().map(|(report, _)| report).collect());
+        }
+        let mut reports = Vec::new();
+        for (mut report, data) in migrated{
+            if !report.is_up_to_date(){
+                match Self::replace(&report, &data) {
+                    Ok(backup) => report.backup = Some(backup),
+                    Err(error) => {
+                        Self::rollback(&reports);
+                        return Err(error);
+                    }
+                }
+            }
+            reports.push(report);
+        }
+        Ok(reports)
+    }
+
+    ///
+    /// Backs store file up and atomically replaces it with migrated data
+    ///
+    fn replace(report: &MigrationReport, data: &Serialized) -> Result<PathBuf, MigrationError>{
+        let backup = backup_file(&report.path, report.from_version)?;
+        replace_file(&report.path, data)?;
+        Ok(backup)
+    }
+
+    ///
+    /// Restores already migrated files from backups
+    ///
+    fn rollback(reports: &[MigrationReport]){
+        for report in reports{
+            if let Some(backup) = &report.backup{
+                if fs::copy(backup, &report.path).is_err(){
+                    log::error!("Failed to restore {} from {}", report.path.display(), backup.display());
+                }
+            }
+        }
+    }
+}
+
+impl Default for Migrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libmilkyway_derive::{Deserializable, Serializable};
+
+    #[derive(Serializable, Deserializable, Debug, PartialEq)]
+    struct Store{
+        name: String,
+        limit: u64,
+    }
+
+    impl VersionedStorage for Store{
+        const STORE_NAME: &'static str = "test";
+        const SCHEMA_VERSION: u32 = 2;
+
+        fn get_migrations() -> Vec<MigrationStep> {
+            vec![
+                MigrationStep::new(LEGACY_SCHEMA_VERSION, "Add schema version header", Ok),
+                MigrationStep::new(1, "Add limit", |mut payload| {
+                    payload.extend(10u64.serialize());
+                    Ok(payload)
+                }),
+            ]
+        }
+    }
+
+    #[test]
+    fn test_migration() {
+        let path = Path::new("/tmp/test_migration.dat");
+        let _ = fs::remove_file("/tmp/test_migration.dat.v0.bak");
+        fs::write(path, "store".to_string().serialize()).unwrap();
+        assert!(matches!(load_versioned::<Store>(path), Err(MigrationError::Outdated{ found: 0, .. })));
+        let mut migrator = Migrator::new();
+        migrator.register::<Store>(path).register::<Store>(Path::new("/tmp/test_migration_missing.dat"));
+
+        // Dry run reports steps, but does not touch file
+        let reports = migrator.run(true).unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].steps, vec!["Add schema version header", "Add limit"]);
+        assert!(load_versioned::<Store>(path).is_err());
+
+        let reports = migrator.run(false).unwrap();
+        assert_eq!(reports[0].backup, Some(PathBuf::from("/tmp/test_migration.dat.v0.bak")));
+        assert_eq!(load_versioned::<Store>(path).unwrap(), Store{ name: "store".to_string(), limit: 10 });
+        assert!(migrator.run(false).unwrap()[0].is_up_to_date());
+    }
+
+    #[test]
+    fn test_failed_migration_keeps_files() {
+        let path = Path::new("/tmp/test_migration_newer.dat");
+        let valid = Path::new("/tmp/test_migration_valid.dat");
+        fs::write(path, encode_versioned(3, &vec![])).unwrap();
+        fs::write(valid, "store".to_string().serialize()).unwrap();
+        let mut migrator = Migrator::new();
+        migrator.register::<Store>(valid).register::<Store>(path);
+        assert_eq!(migrator.run(false).err(), Some(MigrationError::NewerVersion{ store: "test", found: 3, supported: 2 }));
+        assert_eq!(fs::read(valid).unwrap(), "store".to_string().serialize());
+    }
+}
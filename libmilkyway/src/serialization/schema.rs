@@ -0,0 +1,358 @@
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::sync::Arc;
+
+///
+/// Wire layout of a type.
+///
+/// Integers and floats are little-endian of their natural size(usize is written as u64),
+/// bool takes one byte, lists and strings are prefixed with u64 count of elements,
+/// options with one byte flag, maps are list of keys followed by list of values and
+/// enums with one byte tag followed by fields of variant.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypeSchema{
+    /** Integer, float, bool or UTF-8 string, e.g. "u128" or "string" **/
+    Primitive(&'static str),
+    Option(Box<TypeSchema>),
+    List(Box<TypeSchema>),
+    /** Fixed-size array, written without length **/
+    Array(Box<TypeSchema>, usize),
+    Map(Box<TypeSchema>, Box<TypeSchema>),
+    Tuple(Vec<TypeSchema>),
+    /** Struct or enum defined in schema registry **/
+    Named(String),
+}
+
+///
+/// Field of a struct or enum variant. Tuple fields are named by their index.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldSchema{
+    pub name: String,
+    pub schema: TypeSchema,
+}
+
+///
+/// Variant of enum together with its tag byte
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct VariantSchema{
+    pub tag: u8,
+    pub name: String,
+    pub fields: Vec<FieldSchema>,
+}
+
+///
+/// Tag, name and fields of enum variant passed to SchemaRegistry::define_enum
+///
+pub type VariantDefinition<'a> = (u8, &'a str, Vec<(&'a str, TypeSchema)>);
+
+///
+/// Definition of named type, fields are written in order they are listed
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum DefinitionSchema{
+    Struct(Vec<FieldSchema>),
+    Enum(Vec<VariantSchema>),
+}
+
+///
+/// A type which wire layout can be exported. Derive it with #[derive(Describe)].
+///
+pub trait Describe{
+    ///
+    /// Describes layout of type, named types are referenced by name
+    ///
+    fn describe() -> TypeSchema;
+
+    ///
+    /// Adds definitions of this type and types it consists of to registry
+    ///
+    fn register(_registry: &mut SchemaRegistry){}
+}
+
+///
+/// Definitions of named types collected from Describe implementations
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SchemaRegistry{
+    definitions: BTreeMap<String, DefinitionSchema>,
+}
+
+impl SchemaRegistry {
+    pub fn new() -> SchemaRegistry{
+        SchemaRegistry{
+            definitions: BTreeMap::new(),
+        }
+    }
+
+    ///
+    /// Adds type with all types it consists of
+    ///
+    pub fn add<T: Describe>(&mut self) -> &mut SchemaRegistry{
+        T::register(self);
+        self
+    }
+
+    fn define(&mut self, name: &str, definition: DefinitionSchema) -> bool{
+        if self.definitions.contains_key(name){
+            return false;
+        }
+        self.definitions.insert(name.to_string(), definition);
+        true
+    }
+
+    fn to_fields(fields: Vec<(&str, TypeSchema)>) -> Vec<FieldSchema>{
+        fields.into_iter().map(|(name, schema)| FieldSchema{ name: name.to_string(), schema }).collect()
+    }
+
+    ///
+    /// Defines a struct
+    ///
+    /// returns: bool: false if type was already defined
+    ///
+    pub fn define_struct(&mut self, name: &str, fields: Vec<(&str, TypeSchema)>) -> bool{
+        self.define(name, DefinitionSchema::Struct(Self::to_fields(fields)))
+    }
+
+    ///
+    /// Defines an enum from (tag, name, fields) of each variant
+    ///
+    /// returns: bool: false if type was already defined
+    ///
+    pub fn define_enum(&mut self, name: &str, variants: Vec<VariantDefinition>) -> bool{
+        let variants = variants.into_iter().map(|(tag, name, fields)| VariantSchema{
+            tag,
+            name: name.to_string(),
+            fields: Self::to_fields(fields),
+        }).collect();
+        self.define(name, DefinitionSchema::Enum(variants))
+    }
+
+    #[inline]
+    pub fn get_definition(&self, name: &str) -> Option<&DefinitionSchema>{
+        self.definitions.get(name)
+    }
+
+    #[inline]
+    pub fn get_definitions(&self) -> &BTreeMap<String, DefinitionSchema>{
+        &self.definitions
+    }
+
+    ///
+    /// Exports definitions as JSON object keyed by type name
+    ///
+    pub fn to_json(&self) -> String{
+        let definitions: Vec<String> = self.definitions.iter()
+            .map(|(name, definition)| format!("{}:{}", json_string(name), definition.to_json()))
+            .collect();
+        format!("{{{}}}", definitions.join(","))
+    }
+}
+
+///
+/// Quotes and escapes string for JSON
+///
+pub fn json_string(value: &str) -> String{
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for character in value.chars(){
+        match character {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            character if (character as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", character as u32)),
+            character => result.push(character),
+        }
+    }
+    result.push('"');
+    result
+}
+
+impl TypeSchema {
+    ///
+    /// Exports schema as JSON: primitives are strings, other kinds are objects with single key
+    ///
+    pub fn to_json(&self) -> String{
+        match self {
+            TypeSchema::Primitive(name) => json_string(name),
+            TypeSchema::Option(inner) => format!("{{\"option\":{}}}", inner.to_json()),
+            TypeSchema::List(inner) => format!("{{\"list\":{}}}", inner.to_json()),
+            TypeSchema::Array(inner, length) => format!("{{\"array\":{},\"length\":{}}}", inner.to_json(), length),
+            TypeSchema::Map(key, value) => format!("{{\"map\":[{},{}]}}", key.to_json(), value.to_json()),
+            TypeSchema::Tuple(elements) => {
+                let elements: Vec<String> = elements.iter().map(|element| element.to_json()).collect();
+                format!("{{\"tuple\":[{}]}}", elements.join(","))
+            }
+            TypeSchema::Named(name) => format!("{{\"ref\":{}}}", json_string(name)),
+        }
+    }
+}
+
+fn fields_to_json(fields: &[FieldSchema]) -> String{
+    let fields: Vec<String> = fields.iter()
+        .map(|field| format!("{{\"name\":{},\"type\":{}}}", json_string(&field.name), field.schema.to_json()))
+        .collect();
+    format!("[{}]", fields.join(","))
+}
+
+impl DefinitionSchema {
+    pub fn to_json(&self) -> String{
+        match self {
+            DefinitionSchema::Struct(fields) => format!("{{\"kind\":\"struct\",\"fields\":{}}}", fields_to_json(fields)),
+            DefinitionSchema::Enum(variants) => {
+                let variants: Vec<String> = variants.iter()
+                    .map(|variant| format!("{{\"tag\":{},\"name\":{},\"fields\":{}}}", variant.tag,
+                                           json_string(&variant.name), fields_to_json(&variant.fields)))
+                    .collect();
+                format!("{{\"kind\":\"enum\",\"variants\":[{}]}}", variants.join(","))
+            }
+        }
+    }
+}
+
+macro_rules! primitive_describe {
+    ($($t:ty => $name:expr),*) => {
+        $(
+            impl Describe for $t {
+                #[inline]
+                fn describe() -> TypeSchema {
+                    TypeSchema::Primitive($name)
+                }
+            }
+        )*
+    }
+}
+
+primitive_describe!(u8 => "u8", u16 => "u16", u32 => "u32", u64 => "u64", u128 => "u128",
+                    i8 => "i8", i16 => "i16", i32 => "i32", i64 => "i64", i128 => "i128",
+                    usize => "u64", f32 => "f32", f64 => "f64", bool => "bool", String => "string");
+
+impl<T: Describe> Describe for Option<T> {
+    fn describe() -> TypeSchema {
+        TypeSchema::Option(Box::new(T::describe()))
+    }
+
+    fn register(registry: &mut SchemaRegistry) {
+        T::register(registry);
+    }
+}
+
+macro_rules! pointer_describe {
+    ($($pointer:ident),*) => {
+        $(
+            impl<T: Describe> Describe for $pointer<T> {
+                #[inline]
+                fn describe() -> TypeSchema {
+                    T::describe()
+                }
+
+                fn register(registry: &mut SchemaRegistry) {
+                    T::register(registry);
+                }
+            }
+        )*
+    }
+}
+
+pointer_describe!(Box, Rc, Arc);
+
+impl<T: Describe> Describe for Vec<T> {
+    fn describe() -> TypeSchema {
+        TypeSchema::List(Box::new(T::describe()))
+    }
+
+    fn register(registry: &mut SchemaRegistry) {
+        T::register(registry);
+    }
+}
+
+impl<T: Describe, const N: usize> Describe for [T; N] {
+    fn describe() -> TypeSchema {
+        TypeSchema::Array(Box::new(T::describe()), N)
+    }
+
+    fn register(registry: &mut SchemaRegistry) {
+        T::register(registry);
+    }
+}
+
+impl<K: Describe, V: Describe> Describe for HashMap<K, V> {
+    fn describe() -> TypeSchema {
+        TypeSchema::Map(Box::new(K::describe()), Box::new(V::describe()))
+    }
+
+    fn register(registry: &mut SchemaRegistry) {
+        K::register(registry);
+        V::register(registry);
+    }
+}
+
+macro_rules! tuple_describe {
+    ($(($($name:ident),+)),*) => {
+        $(
+            impl<$($name: Describe),+> Describe for ($($name,)+) {
+                fn describe() -> TypeSchema {
+                    TypeSchema::Tuple(vec![$($name::describe()),+])
+                }
+
+                fn register(registry: &mut SchemaRegistry) {
+                    $($name::register(registry);)+
+                }
+            }
+        )*
+    }
+}
+
+tuple_describe!((A, B), (A, B, C), (A, B, C, D));
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libmilkyway_derive::Describe;
+
+    #[derive(Describe)]
+    #[allow(dead_code)]
+    struct Node{
+        name: String,
+        children: Vec<Node>,
+        kind: Kind,
+    }
+
+    #[derive(Describe)]
+    #[allow(dead_code)]
+    enum Kind{
+        Leaf,
+        Weighted(u32, Option<bool>),
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = SchemaRegistry::new();
+        registry.add::<Node>().add::<Vec<Node>>();
+        assert_eq!(registry.get_definitions().len(), 2);
+        assert_eq!(registry.get_definition("Kind"), Some(&DefinitionSchema::Enum(vec![
+            VariantSchema{ tag: 0, name: "Leaf".to_string(), fields: vec![] },
+            VariantSchema{ tag: 1, name: "Weighted".to_string(), fields: vec![
+                FieldSchema{ name: "0".to_string(), schema: TypeSchema::Primitive("u32") },
+                FieldSchema{ name: "1".to_string(), schema: TypeSchema::Option(Box::new(TypeSchema::Primitive("bool"))) },
+            ]},
+        ])));
+        assert_eq!(registry.to_json(),
+                   "{\"Kind\":{\"kind\":\"enum\",\"variants\":[{\"tag\":0,\"name\":\"Leaf\",\"fields\":[]},\
+                   {\"tag\":1,\"name\":\"Weighted\",\"fields\":[{\"name\":\"0\",\"type\":\"u32\"},\
+                   {\"name\":\"1\",\"type\":{\"option\":\"bool\"}}]}]},\
+                   \"Node\":{\"kind\":\"struct\",\"fields\":[{\"name\":\"name\",\"type\":\"string\"},\
+                   {\"name\":\"children\",\"type\":{\"list\":{\"ref\":\"Node\"}}},\
+                   {\"name\":\"kind\",\"type\":{\"ref\":\"Kind\"}}]}}");
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("a\"b\\c\n\u{1}"), "\"a\\\"b\\\\c\\n\\u0001\"");
+    }
+}
@@ -15,6 +15,16 @@ pub mod certificate;
 /// 
 pub mod transport;
 
+///
+/// Group service maintains groups of peers which messages may be addressed to
+///
+pub mod group;
+
+///
+/// Inventory service answers peers asking for facts about node and caches their answers
+///
+pub mod inventory;
+
 
 ///
 /// An impelementations of services which may be commonly used
@@ -1,14 +1,138 @@
-use crate::actor::binder::{Binder, BinderChannel, BinderChannelProvider, BinderMessage, BinderServiceHandler};
+use std::fmt::{Display, Formatter};
+use async_trait::async_trait;
+use crate::actor::binder::{AsyncBinder, AsyncBinderChannel, Binder, BinderChannel, BinderChannelProvider, BinderMessage,
+                           BinderServiceHandler};
 use crate::actor::binder::coroutine::BinderAsyncService;
+use crate::actor::binder::pool::BinderPool;
+use crate::message::certsync::CertificateRevocation;
+use crate::pki::certificate::metadata::CertificateQuery;
 use crate::pki::impls::certificates::falcon1024::{Falcon1024Certificate, Falcon1024RootCertificate};
 use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
 use crate::services::certificate::CertificateServiceBinderRequest::SetSigningCertificate;
-use crate::services::certificate::CertificateServiceBinderResponse::{Falcon1024Cert, Falcon1024Certs, Kyber1024Cert, Kyber1024Certs, RootCert, Status};
+use crate::services::certificate::CertificateServiceBinderResponse::{Falcon1024Cert, Falcon1024Certs, KeyUsages, Kyber1024Cert, Kyber1024Certs, Page, Policy, PolicyDecision, Rejected, Reloaded, Revocations, RootCert, Status, Statuses, Thresholds};
+use crate::services::certificate::usage::{KeyUsage, UsageThresholds};
+use crate::services::certificate::listing::{get_page, CertificatePage};
+use crate::services::certificate::reload::CertificateReloadReport;
+use crate::services::certificate::policy::{CertificatePolicy, PolicyError, PolicySubject};
 use crate::unwrap_variant;
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+use libmilkyway_derive::Describe;
+use crate::errors::ErrorCode;
 
+///
+/// Propagation of added, rotated and revoked certificates between peers
+///
+pub mod sync;
+
+///
+/// Installation of certificates pushed by peers
+///
+pub mod push;
+
+///
+/// Verification of certificate chains outside of certificate store
+///
+pub mod chain;
+
+///
+/// Certificate service of a broker used over transport by hosts without local store
+///
+pub mod remote;
+
+///
+/// Counters of key usage and thresholds after which keys should be rotated
+///
+pub mod usage;
+
+///
+/// Certificate service which rejects changes of PKI state while in read-only mode
+///
+pub mod readonly;
+
+///
+/// Import of exported certificates from a directory, once or as files appear
+///
+pub mod directory;
+
+///
+/// Random serials of new certificates
+///
+pub mod serial;
+
+///
+/// Offline inspection and repair of damaged certificate store files
+///
+pub mod inspect;
+
+///
+/// Paginated listing of certificates without their keys
+///
+pub mod listing;
+
+///
+/// Reload of certificate store changed on disk by another process
+///
+pub mod reload;
+
+///
+/// Removal of expired certificates and of certificates whose chain is broken
+///
+pub mod gc;
+
+///
+/// Secret keys of certificates stored apart from public certificate store
+///
+pub mod keystore;
+
+///
+/// Signed rules restricting which certificates signing certificates may issue
+///
+pub mod policy;
+
+///
+/// Certificate service usable from coroutines and threads without runtime
+///
+pub mod detached;
 
 pub const ROOT_CERTIFICATE_SERIAL: u128 = 0;
 
+///
+/// Reasons why certificate service rejects a request
+///
+#[derive(Clone, Copy, Debug, PartialEq, Describe)]
+pub enum CertificateServiceError{
+    /** Service is in read-only mode, certificates can not be added, set or removed **/
+    ReadOnly,
+    /** Service does not keep its store on disk, so there is nothing to reload **/
+    ReloadUnsupported,
+    /** Store can not be read, service keeps its state **/
+    ReloadFailed,
+    /** Changes can not be written to store, they are kept pending **/
+    CommitFailed,
+}
+
+impl Display for CertificateServiceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CertificateServiceError::ReadOnly => write!(f, "certificate store is read-only"),
+            CertificateServiceError::ReloadUnsupported => write!(f, "certificate service can not reload its store"),
+            CertificateServiceError::ReloadFailed => write!(f, "certificate store can not be read"),
+            CertificateServiceError::CommitFailed => write!(f, "changes can not be written to certificate store"),
+        }
+    }
+}
+
+impl ErrorCode for CertificateServiceError {
+    fn get_error_code(&self) -> &'static str {
+        match self {
+            CertificateServiceError::ReadOnly => "MW-CERT-0001",
+            CertificateServiceError::ReloadUnsupported => "MW-CERT-0002",
+            CertificateServiceError::ReloadFailed => "MW-CERT-0003",
+            CertificateServiceError::CommitFailed => "MW-CERT-0004",
+        }
+    }
+}
+
 ///
 /// Certificate service is responsible for handling, storing and obtaining certificates
 ///
@@ -128,13 +252,222 @@ pub trait CertificateService: Send + Sync{
     ///
     fn remove_encryption_certificate(&mut self, serial: u128) -> bool;
 
+
+    ///
+    /// Verifies many certificates at once, e.g. on bundle import or chain sync.
+    /// Services may verify shared parts of chains only once.
+    ///
+    /// # Arguments
+    /// * certs: &[VerifiableCertificate]: certificates to verify
+    ///
+    /// returns: Vec<bool>: whether each certificate is valid, in order of certs
+    ///
+    fn verify_many(&mut self, certs: &[VerifiableCertificate]) -> Vec<bool>{
+        certs.iter().map(|cert| match cert {
+            VerifiableCertificate::Signing(cert) => self.verify_signing_certificate(cert),
+            VerifiableCertificate::Encryption(cert) => self.verify_encryption_certificate(cert),
+        }).collect()
+    }
     
+    ///
+    /// Adds usage to counters of certificate. Counters are persisted on commit.
+    ///
+    /// # Arguments
+    /// * usage: KeyUsage: usage since previous record, with serial of certificate
+    ///
+    /// returns: bool: whether a rotation threshold was reached by this usage
+    ///
+    fn record_key_usage(&mut self, _usage: KeyUsage) -> bool{
+        false
+    }
+
+    ///
+    /// Gets usage counters of all certificates which were used
+    ///
+    fn get_key_usage(&mut self) -> Vec<KeyUsage>{
+        vec![]
+    }
+
+    ///
+    /// Sets thresholds after which rotation warnings are emitted
+    ///
+    fn set_usage_thresholds(&mut self, _thresholds: UsageThresholds){}
+
+    fn get_usage_thresholds(&mut self) -> UsageThresholds{
+        UsageThresholds::default()
+    }
+
+    ///
+    /// Finds signing certificates matching query by name and metadata
+    ///
+    /// returns: Vec<Falcon1024Certificate>: matching certificates sorted by serial
+    ///
+    fn query_signing_certificates(&mut self, query: &CertificateQuery) -> Vec<Falcon1024Certificate>{
+        let mut certificates: Vec<Falcon1024Certificate> = self.get_signing_certificates().into_iter()
+            .filter(|certificate| query.matches(&certificate.name, &certificate.metadata))
+            .collect();
+        certificates.sort_by_key(|certificate| certificate.serial_number);
+        certificates
+    }
+
+    ///
+    /// Finds encryption certificates matching query, see query_signing_certificates
+    ///
+    fn query_encryption_certificates(&mut self, query: &CertificateQuery) -> Vec<Kyber1024Certificate>{
+        let mut certificates: Vec<Kyber1024Certificate> = self.get_encryption_certificates().into_iter()
+            .filter(|certificate| query.matches(&certificate.name, &certificate.metadata))
+            .collect();
+        certificates.sort_by_key(|certificate| certificate.serial_number);
+        certificates
+    }
+
+    ///
+    /// Lists signing certificates page by page without their keys, so callers which only show
+    /// certificates do not fetch secret keys. Full certificates are fetched only for export.
+    ///
+    /// # Arguments
+    /// * cursor: Option<u128>: next_cursor of previous page or None for first page
+    /// * limit: u32: maximal number of certificates in page, clamped to MAX_CERTIFICATE_PAGE_SIZE
+    ///
+    /// returns: CertificatePage: summaries of certificates sorted by serial
+    ///
+    fn list_signing_certificates(&mut self, cursor: Option<u128>, limit: u32) -> CertificatePage{
+        get_page(&self.get_signing_certificates(), cursor, limit)
+    }
+
+    ///
+    /// Lists encryption certificates, see list_signing_certificates
+    ///
+    fn list_encryption_certificates(&mut self, cursor: Option<u128>, limit: u32) -> CertificatePage{
+        get_page(&self.get_encryption_certificates(), cursor, limit)
+    }
+
+    ///
+    /// Switches read-only mode: certificates can not be added, set or removed, while
+    /// verification and lookups keep working. Services without the mode ignore it.
+    ///
+    fn set_read_only(&mut self, _read_only: bool){}
+
+    fn is_read_only(&mut self) -> bool{
+        false
+    }
+
+    ///
+    /// Checks whether PKI state may be changed, so callers may report why a change is refused
+    ///
+    fn check_writable(&mut self) -> Result<(), CertificateServiceError>{
+        if self.is_read_only(){
+            return Err(CertificateServiceError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    ///
+    /// Re-reads store, e.g. after it was restored or changed by another process, and applies
+    /// differences to service. Certificates changed since last commit are kept and reported as
+    /// conflicts, they overwrite ones of store on next commit. Requests are handled one by one,
+    /// so reload never interleaves with a change.
+    ///
+    /// returns: Result<CertificateReloadReport, CertificateServiceError>: applied changes or
+    /// error if service has no store or store can not be read
+    ///
+    fn reload(&mut self) -> Result<CertificateReloadReport, CertificateServiceError>{
+        Err(CertificateServiceError::ReloadUnsupported)
+    }
+
+    ///
+    /// Gets policy restricting which certificates signing certificates may issue. Services
+    /// without policy have an empty one, which allows everything.
+    ///
+    fn get_policy(&mut self) -> CertificatePolicy{
+        CertificatePolicy::default()
+    }
+
+    ///
+    /// Replaces policy, it is checked at every add and verification since then
+    ///
+    /// # Arguments
+    /// * policy: CertificatePolicy: policy signed by root certificate of service, its version
+    ///   must be greater than one of current policy
+    ///
+    fn set_policy(&mut self, _policy: CertificatePolicy) -> Result<(), PolicyError>{
+        Err(PolicyError::Unsupported)
+    }
+
+    ///
+    /// Evaluates policy for operation, which may be hypothetical, e.g. before certificate is signed
+    ///
+    fn evaluate_policy(&mut self, _subject: &PolicySubject) -> Result<(), PolicyError>{
+        Ok(())
+    }
+
+    ///
+    /// Records revocation of certificate by its serial and removes revoked certificate.
+    /// Certificate with revoked serial is never added or verified again. Revocation is persisted
+    /// on commit. Services without revocation list refuse it.
+    ///
+    /// # Arguments
+    /// * revocation: CertificateRevocation: revocation already verified against issuer of
+    ///   revoked certificate(see sync::apply_certificate_sync)
+    ///
+    /// returns: bool: whether revocation was recorded
+    ///
+    fn add_revocation(&mut self, _revocation: CertificateRevocation) -> bool{
+        false
+    }
+
+    ///
+    /// Checks whether certificate with serial was revoked
+    ///
+    fn is_revoked(&mut self, _serial: u128) -> bool{
+        false
+    }
+
+    ///
+    /// Gets recorded revocations sorted by serial of revoked certificate
+    ///
+    fn get_revocations(&mut self) -> Vec<CertificateRevocation>{
+        vec![]
+    }
+
     ///
     /// Commits changes, i.e. writes new certificates to storage/sends to peers/etc.
     /// 
     fn commit(&mut self);
+
+    ///
+    /// Checks whether service has changes which are not committed yet
+    ///
+    fn is_dirty(&mut self) -> bool{
+        false
+    }
+
+    ///
+    /// Commits changes and waits until they are durable. Requests are handled one by one, so
+    /// every change requested before flush is committed by it, which makes flush a barrier
+    /// e.g. for shutdown.
+    ///
+    /// returns: Result<(), CertificateServiceError>: CommitFailed if changes are still pending
+    ///
+    fn flush(&mut self) -> Result<(), CertificateServiceError>{
+        self.commit();
+        if self.is_dirty(){
+            return Err(CertificateServiceError::CommitFailed);
+        }
+        Ok(())
+    }
 }
 
+///
+/// Certificate of any kind passed to bulk verification
+///
+#[derive(Clone, Describe)]
+pub enum VerifiableCertificate{
+    Signing(Box<Falcon1024Certificate>),
+    Encryption(Box<Kyber1024Certificate>),
+}
+
+#[derive(Clone, Describe)]
 pub enum CertificateServiceBinderRequest{
     AddEncryptionCertificate(Kyber1024Certificate),
     AddSigningCertificate(Falcon1024Certificate),
@@ -149,9 +482,44 @@ pub enum CertificateServiceBinderRequest{
     RemoveSigningCertificate(u128),
     RemoveEncryptionCertificate(u128),
     Commit,
+    VerifyMany(Vec<VerifiableCertificate>),
+    RecordKeyUsage(KeyUsage),
+    GetKeyUsage,
+    SetUsageThresholds(UsageThresholds),
+    GetUsageThresholds,
+    SetReadOnly(bool),
+    IsReadOnly,
+    /** Cursor and limit of page **/
+    ListSigningCertificates(Option<u128>, u32),
+    ListEncryptionCertificates(Option<u128>, u32),
+    Reload,
+    GetPolicy,
+    SetPolicy(CertificatePolicy),
+    EvaluatePolicy(PolicySubject),
+    IsDirty,
+    Flush,
+    AddRevocation(CertificateRevocation),
+    IsRevoked(u128),
+    GetRevocations,
 }
 
+impl CertificateServiceBinderRequest {
+    ///
+    /// Checks whether request changes PKI state, such requests are rejected in read-only mode
+    ///
+    pub fn is_mutating(&self) -> bool{
+        matches!(self, CertificateServiceBinderRequest::AddEncryptionCertificate(_)
+            | CertificateServiceBinderRequest::AddSigningCertificate(_)
+            | CertificateServiceBinderRequest::SetSigningCertificate(_)
+            | CertificateServiceBinderRequest::RemoveSigningCertificate(_)
+            | CertificateServiceBinderRequest::RemoveEncryptionCertificate(_)
+            | CertificateServiceBinderRequest::SetPolicy(_)
+            | CertificateServiceBinderRequest::AddRevocation(_))
+    }
+}
 
+
+#[derive(Clone, Describe)]
 pub enum CertificateServiceBinderResponse{
     Falcon1024Cert(Option<Falcon1024Certificate>),
     Kyber1024Cert(Option<Kyber1024Certificate>),
@@ -159,6 +527,64 @@ pub enum CertificateServiceBinderResponse{
     Falcon1024Certs(Vec<Falcon1024Certificate>),
     Kyber1024Certs(Vec<Kyber1024Certificate>),
     Status(bool),
+    Statuses(Vec<bool>),
+    KeyUsages(Vec<KeyUsage>),
+    Thresholds(UsageThresholds),
+    Rejected(CertificateServiceError),
+    Page(CertificatePage),
+    Reloaded(CertificateReloadReport),
+    Policy(CertificatePolicy),
+    /** None if policy allows operation or was set **/
+    PolicyDecision(Option<PolicyError>),
+    Revocations(Vec<CertificateRevocation>),
+}
+
+///
+/// Gets status of response to request changing PKI state, rejection is logged
+///
+fn get_write_status(response: CertificateServiceBinderResponse) -> bool{
+    match response {
+        Status(status) => status,
+        Rejected(error) => {
+            log::warn!("Change of certificates is rejected: {}", error);
+            false
+        }
+        _ => panic!("Expected variant Status"),
+    }
+}
+
+///
+/// Gets result of reload request
+///
+fn get_reload_result(response: CertificateServiceBinderResponse) -> Result<CertificateReloadReport, CertificateServiceError>{
+    match response {
+        Reloaded(report) => Ok(report),
+        Rejected(error) => Err(error),
+        _ => panic!("Expected variant Reloaded"),
+    }
+}
+
+///
+/// Gets result of flush request
+///
+fn get_flush_result(response: CertificateServiceBinderResponse) -> Result<(), CertificateServiceError>{
+    match response {
+        Status(_) => Ok(()),
+        Rejected(error) => Err(error),
+        _ => panic!("Expected variant Status"),
+    }
+}
+
+///
+/// Gets result of request setting or evaluating policy
+///
+fn get_policy_result(response: CertificateServiceBinderResponse) -> Result<(), PolicyError>{
+    match response {
+        PolicyDecision(None) => Ok(()),
+        PolicyDecision(Some(error)) => Err(error),
+        Rejected(_) => Err(PolicyError::ReadOnly),
+        _ => panic!("Expected variant PolicyDecision"),
+    }
 }
 
 /// 
@@ -173,29 +599,31 @@ pub type CertificateServiceBinderProvider = dyn BinderChannelProvider<BinderMess
 pub type CertificateServiceBinder = dyn BinderChannel<BinderMessage<CertificateServiceBinderRequest,
     CertificateServiceBinderResponse>>;
 
+///
+/// A pool of binders to certificate service, see ModuleDataBus::get_certificate_pool
+///
+pub type CertificateServicePool = BinderPool<CertificateServiceBinderRequest, CertificateServiceBinderResponse>;
+
 impl CertificateService for dyn BinderChannel<BinderMessage<CertificateServiceBinderRequest,
     CertificateServiceBinderResponse>>{
 
     #[inline]
     fn set_root_certificate(&mut self, root_cert: Falcon1024RootCertificate) {
-        let result = unwrap_variant!(self.handle_request(SetSigningCertificate(root_cert)),
-            Status);
-        if !result{
-            panic!("Can not set root certificate!");
+        match self.handle_request(SetSigningCertificate(root_cert)) {
+            Status(true) => {}
+            Rejected(error) => log::error!("Can not set root certificate: {}", error),
+            _ => panic!("Can not set root certificate!"),
         }
     }
 
     #[inline]
     fn add_signing_certificate(&mut self, cert: Falcon1024Certificate) -> bool {
-        let result = unwrap_variant!(self.handle_request(CertificateServiceBinderRequest::AddSigningCertificate(cert)), Status);
-        result
+        get_write_status(self.handle_request(CertificateServiceBinderRequest::AddSigningCertificate(cert)))
     }
 
     #[inline]
     fn add_encryption_certificate(&mut self, cert: Kyber1024Certificate) -> bool {
-        let result = unwrap_variant!(self.handle_request(CertificateServiceBinderRequest::AddEncryptionCertificate(cert)),
-            Status);
-        result
+        get_write_status(self.handle_request(CertificateServiceBinderRequest::AddEncryptionCertificate(cert)))
     }
 
     #[inline]
@@ -234,11 +662,75 @@ impl CertificateService for dyn BinderChannel<BinderMessage<CertificateServiceBi
     }
 
     fn remove_signing_certificate(&mut self, serial: u128) -> bool {
-        unwrap_variant!(self.handle_request(CertificateServiceBinderRequest::RemoveSigningCertificate(serial)), Status)
+        get_write_status(self.handle_request(CertificateServiceBinderRequest::RemoveSigningCertificate(serial)))
     }
 
     fn remove_encryption_certificate(&mut self, serial: u128) -> bool {
-        unwrap_variant!(self.handle_request(CertificateServiceBinderRequest::RemoveEncryptionCertificate(serial)), Status)
+        get_write_status(self.handle_request(CertificateServiceBinderRequest::RemoveEncryptionCertificate(serial)))
+    }
+
+    fn verify_many(&mut self, certs: &[VerifiableCertificate]) -> Vec<bool> {
+        unwrap_variant!(self.handle_request(CertificateServiceBinderRequest::VerifyMany(certs.to_vec())), Statuses)
+    }
+
+    fn record_key_usage(&mut self, usage: KeyUsage) -> bool {
+        unwrap_variant!(self.handle_request(CertificateServiceBinderRequest::RecordKeyUsage(usage)), Status)
+    }
+
+    fn get_key_usage(&mut self) -> Vec<KeyUsage> {
+        unwrap_variant!(self.handle_request(CertificateServiceBinderRequest::GetKeyUsage), KeyUsages)
+    }
+
+    fn set_usage_thresholds(&mut self, thresholds: UsageThresholds) {
+        unwrap_variant!(self.handle_request(CertificateServiceBinderRequest::SetUsageThresholds(thresholds)), Status);
+    }
+
+    fn get_usage_thresholds(&mut self) -> UsageThresholds {
+        unwrap_variant!(self.handle_request(CertificateServiceBinderRequest::GetUsageThresholds), Thresholds)
+    }
+
+    fn set_read_only(&mut self, read_only: bool) {
+        unwrap_variant!(self.handle_request(CertificateServiceBinderRequest::SetReadOnly(read_only)), Status);
+    }
+
+    fn is_read_only(&mut self) -> bool {
+        unwrap_variant!(self.handle_request(CertificateServiceBinderRequest::IsReadOnly), Status)
+    }
+
+    fn list_signing_certificates(&mut self, cursor: Option<u128>, limit: u32) -> CertificatePage {
+        unwrap_variant!(self.handle_request(CertificateServiceBinderRequest::ListSigningCertificates(cursor, limit)), Page)
+    }
+
+    fn list_encryption_certificates(&mut self, cursor: Option<u128>, limit: u32) -> CertificatePage {
+        unwrap_variant!(self.handle_request(CertificateServiceBinderRequest::ListEncryptionCertificates(cursor, limit)), Page)
+    }
+
+    fn reload(&mut self) -> Result<CertificateReloadReport, CertificateServiceError> {
+        get_reload_result(self.handle_request(CertificateServiceBinderRequest::Reload))
+    }
+
+    fn get_policy(&mut self) -> CertificatePolicy {
+        unwrap_variant!(self.handle_request(CertificateServiceBinderRequest::GetPolicy), Policy)
+    }
+
+    fn set_policy(&mut self, policy: CertificatePolicy) -> Result<(), PolicyError> {
+        get_policy_result(self.handle_request(CertificateServiceBinderRequest::SetPolicy(policy)))
+    }
+
+    fn evaluate_policy(&mut self, subject: &PolicySubject) -> Result<(), PolicyError> {
+        get_policy_result(self.handle_request(CertificateServiceBinderRequest::EvaluatePolicy(subject.clone())))
+    }
+
+    fn add_revocation(&mut self, revocation: CertificateRevocation) -> bool {
+        get_write_status(self.handle_request(CertificateServiceBinderRequest::AddRevocation(revocation)))
+    }
+
+    fn is_revoked(&mut self, serial: u128) -> bool {
+        unwrap_variant!(self.handle_request(CertificateServiceBinderRequest::IsRevoked(serial)), Status)
+    }
+
+    fn get_revocations(&mut self) -> Vec<CertificateRevocation> {
+        unwrap_variant!(self.handle_request(CertificateServiceBinderRequest::GetRevocations), Revocations)
     }
 
     #[inline]
@@ -248,6 +740,183 @@ impl CertificateService for dyn BinderChannel<BinderMessage<CertificateServiceBi
             panic!("Remote commit failed");
         }
     }
+
+    fn is_dirty(&mut self) -> bool {
+        unwrap_variant!(self.handle_request(CertificateServiceBinderRequest::IsDirty), Status)
+    }
+
+    fn flush(&mut self) -> Result<(), CertificateServiceError> {
+        get_flush_result(self.handle_request(CertificateServiceBinderRequest::Flush))
+    }
+}
+
+///
+/// Non-blocking facade of certificate service for async code paths(e.g. transport workers
+/// of daemon), where blocking calls of CertificateServiceBinder would stall the runtime.
+/// Methods have the same meaning as ones of CertificateService.
+///
+#[async_trait]
+pub trait AsyncCertificateService: Send + Sync{
+    async fn set_root_certificate(&mut self, root_cert: Falcon1024RootCertificate);
+    async fn add_signing_certificate(&mut self, cert: Falcon1024Certificate) -> bool;
+    async fn add_encryption_certificate(&mut self, cert: Kyber1024Certificate) -> bool;
+    async fn verify_signing_certificate(&mut self, cert: &Falcon1024Certificate) -> bool;
+    async fn verify_encryption_certificate(&mut self, cert: &Kyber1024Certificate) -> bool;
+    async fn get_signing_certificate(&mut self, serial: u128) -> Option<Falcon1024Certificate>;
+    async fn get_encryption_certificate(&mut self, serial: u128) -> Option<Kyber1024Certificate>;
+    async fn get_root_certificate(&mut self) -> Option<Falcon1024RootCertificate>;
+    async fn get_signing_certificates(&mut self) -> Vec<Falcon1024Certificate>;
+    async fn get_encryption_certificates(&mut self) -> Vec<Kyber1024Certificate>;
+    async fn remove_signing_certificate(&mut self, serial: u128) -> bool;
+    async fn remove_encryption_certificate(&mut self, serial: u128) -> bool;
+    async fn verify_many(&mut self, certs: &[VerifiableCertificate]) -> Vec<bool>;
+    async fn record_key_usage(&mut self, usage: KeyUsage) -> bool;
+    async fn get_key_usage(&mut self) -> Vec<KeyUsage>;
+    async fn set_usage_thresholds(&mut self, thresholds: UsageThresholds);
+    async fn get_usage_thresholds(&mut self) -> UsageThresholds;
+    async fn set_read_only(&mut self, read_only: bool);
+    async fn is_read_only(&mut self) -> bool;
+    async fn list_signing_certificates(&mut self, cursor: Option<u128>, limit: u32) -> CertificatePage;
+    async fn list_encryption_certificates(&mut self, cursor: Option<u128>, limit: u32) -> CertificatePage;
+    async fn reload(&mut self) -> Result<CertificateReloadReport, CertificateServiceError>;
+    async fn get_policy(&mut self) -> CertificatePolicy;
+    async fn set_policy(&mut self, policy: CertificatePolicy) -> Result<(), PolicyError>;
+    async fn evaluate_policy(&mut self, subject: &PolicySubject) -> Result<(), PolicyError>;
+    async fn commit(&mut self);
+    async fn is_dirty(&mut self) -> bool;
+    async fn flush(&mut self) -> Result<(), CertificateServiceError>;
+}
+
+///
+/// A non-blocking binder type for certificate service, created by CertificateAsyncService::bind_async
+///
+pub type AsyncCertificateServiceBinder = dyn AsyncBinderChannel<BinderMessage<CertificateServiceBinderRequest,
+    CertificateServiceBinderResponse>>;
+
+#[async_trait]
+impl AsyncCertificateService for dyn AsyncBinderChannel<BinderMessage<CertificateServiceBinderRequest,
+    CertificateServiceBinderResponse>>{
+    async fn set_root_certificate(&mut self, root_cert: Falcon1024RootCertificate) {
+        match self.handle_request_async(SetSigningCertificate(root_cert)).await {
+            Status(true) => {}
+            Rejected(error) => log::error!("Can not set root certificate: {}", error),
+            _ => panic!("Can not set root certificate!"),
+        }
+    }
+
+    async fn add_signing_certificate(&mut self, cert: Falcon1024Certificate) -> bool {
+        get_write_status(self.handle_request_async(CertificateServiceBinderRequest::AddSigningCertificate(cert)).await)
+    }
+
+    async fn add_encryption_certificate(&mut self, cert: Kyber1024Certificate) -> bool {
+        get_write_status(self.handle_request_async(CertificateServiceBinderRequest::AddEncryptionCertificate(cert)).await)
+    }
+
+    async fn verify_signing_certificate(&mut self, cert: &Falcon1024Certificate) -> bool {
+        unwrap_variant!(self.handle_request_async(CertificateServiceBinderRequest::VerifySigningCertificate(cert.clone())).await, Status)
+    }
+
+    async fn verify_encryption_certificate(&mut self, cert: &Kyber1024Certificate) -> bool {
+        unwrap_variant!(self.handle_request_async(CertificateServiceBinderRequest::VerifyEncryptionCertificate(cert.clone())).await, Status)
+    }
+
+    async fn get_signing_certificate(&mut self, serial: u128) -> Option<Falcon1024Certificate> {
+        unwrap_variant!(self.handle_request_async(CertificateServiceBinderRequest::GetSigningCertificate(serial)).await, Falcon1024Cert)
+    }
+
+    async fn get_encryption_certificate(&mut self, serial: u128) -> Option<Kyber1024Certificate> {
+        unwrap_variant!(self.handle_request_async(CertificateServiceBinderRequest::GetEncryptionCertificate(serial)).await, Kyber1024Cert)
+    }
+
+    async fn get_root_certificate(&mut self) -> Option<Falcon1024RootCertificate> {
+        unwrap_variant!(self.handle_request_async(CertificateServiceBinderRequest::GetRootCertificate).await, RootCert)
+    }
+
+    async fn get_signing_certificates(&mut self) -> Vec<Falcon1024Certificate> {
+        unwrap_variant!(self.handle_request_async(CertificateServiceBinderRequest::GetSigningCertificates).await, Falcon1024Certs)
+    }
+
+    async fn get_encryption_certificates(&mut self) -> Vec<Kyber1024Certificate> {
+        unwrap_variant!(self.handle_request_async(CertificateServiceBinderRequest::GetEncryptionCertificates).await, Kyber1024Certs)
+    }
+
+    async fn remove_signing_certificate(&mut self, serial: u128) -> bool {
+        get_write_status(self.handle_request_async(CertificateServiceBinderRequest::RemoveSigningCertificate(serial)).await)
+    }
+
+    async fn remove_encryption_certificate(&mut self, serial: u128) -> bool {
+        get_write_status(self.handle_request_async(CertificateServiceBinderRequest::RemoveEncryptionCertificate(serial)).await)
+    }
+
+    async fn verify_many(&mut self, certs: &[VerifiableCertificate]) -> Vec<bool> {
+        unwrap_variant!(self.handle_request_async(CertificateServiceBinderRequest::VerifyMany(certs.to_vec())).await, Statuses)
+    }
+
+    async fn record_key_usage(&mut self, usage: KeyUsage) -> bool {
+        unwrap_variant!(self.handle_request_async(CertificateServiceBinderRequest::RecordKeyUsage(usage)).await, Status)
+    }
+
+    async fn get_key_usage(&mut self) -> Vec<KeyUsage> {
+        unwrap_variant!(self.handle_request_async(CertificateServiceBinderRequest::GetKeyUsage).await, KeyUsages)
+    }
+
+    async fn set_usage_thresholds(&mut self, thresholds: UsageThresholds) {
+        unwrap_variant!(self.handle_request_async(CertificateServiceBinderRequest::SetUsageThresholds(thresholds)).await, Status);
+    }
+
+    async fn get_usage_thresholds(&mut self) -> UsageThresholds {
+        unwrap_variant!(self.handle_request_async(CertificateServiceBinderRequest::GetUsageThresholds).await, Thresholds)
+    }
+
+    async fn set_read_only(&mut self, read_only: bool) {
+        unwrap_variant!(self.handle_request_async(CertificateServiceBinderRequest::SetReadOnly(read_only)).await, Status);
+    }
+
+    async fn is_read_only(&mut self) -> bool {
+        unwrap_variant!(self.handle_request_async(CertificateServiceBinderRequest::IsReadOnly).await, Status)
+    }
+
+    async fn list_signing_certificates(&mut self, cursor: Option<u128>, limit: u32) -> CertificatePage {
+        let request = CertificateServiceBinderRequest::ListSigningCertificates(cursor, limit);
+        unwrap_variant!(self.handle_request_async(request).await, Page)
+    }
+
+    async fn list_encryption_certificates(&mut self, cursor: Option<u128>, limit: u32) -> CertificatePage {
+        let request = CertificateServiceBinderRequest::ListEncryptionCertificates(cursor, limit);
+        unwrap_variant!(self.handle_request_async(request).await, Page)
+    }
+
+    async fn reload(&mut self) -> Result<CertificateReloadReport, CertificateServiceError> {
+        get_reload_result(self.handle_request_async(CertificateServiceBinderRequest::Reload).await)
+    }
+
+    async fn get_policy(&mut self) -> CertificatePolicy {
+        unwrap_variant!(self.handle_request_async(CertificateServiceBinderRequest::GetPolicy).await, Policy)
+    }
+
+    async fn set_policy(&mut self, policy: CertificatePolicy) -> Result<(), PolicyError> {
+        get_policy_result(self.handle_request_async(CertificateServiceBinderRequest::SetPolicy(policy)).await)
+    }
+
+    async fn evaluate_policy(&mut self, subject: &PolicySubject) -> Result<(), PolicyError> {
+        let request = CertificateServiceBinderRequest::EvaluatePolicy(subject.clone());
+        get_policy_result(self.handle_request_async(request).await)
+    }
+
+    async fn commit(&mut self) {
+        let result = unwrap_variant!(self.handle_request_async(CertificateServiceBinderRequest::Commit).await, Status);
+        if !result{
+            panic!("Remote commit failed");
+        }
+    }
+
+    async fn is_dirty(&mut self) -> bool {
+        unwrap_variant!(self.handle_request_async(CertificateServiceBinderRequest::IsDirty).await, Status)
+    }
+
+    async fn flush(&mut self) -> Result<(), CertificateServiceError> {
+        get_flush_result(self.handle_request_async(CertificateServiceBinderRequest::Flush).await)
+    }
 }
 
 ///
@@ -266,6 +935,11 @@ impl BinderServiceHandler<CertificateServiceBinderRequest,
     CertificateServiceBinderResponse> for dyn CertificateService {
     fn handle_message(&mut self, 
                       request: CertificateServiceBinderRequest) -> CertificateServiceBinderResponse {
+        if request.is_mutating(){
+            if let Err(error) = self.check_writable(){
+                return Rejected(error);
+            }
+        }
         match request {
             CertificateServiceBinderRequest::AddEncryptionCertificate(certificate) => {
                 Status(self.add_encryption_certificate(certificate))
@@ -309,6 +983,64 @@ impl BinderServiceHandler<CertificateServiceBinderRequest,
             CertificateServiceBinderRequest::RemoveEncryptionCertificate(serial) => {
                 Status(self.remove_encryption_certificate(serial))
             }
+            CertificateServiceBinderRequest::VerifyMany(certificates) => {
+                Statuses(self.verify_many(&certificates))
+            }
+            CertificateServiceBinderRequest::RecordKeyUsage(usage) => {
+                Status(self.record_key_usage(usage))
+            }
+            CertificateServiceBinderRequest::GetKeyUsage => {
+                KeyUsages(self.get_key_usage())
+            }
+            CertificateServiceBinderRequest::SetUsageThresholds(thresholds) => {
+                self.set_usage_thresholds(thresholds);
+                Status(true)
+            }
+            CertificateServiceBinderRequest::GetUsageThresholds => {
+                Thresholds(self.get_usage_thresholds())
+            }
+            CertificateServiceBinderRequest::SetReadOnly(read_only) => {
+                self.set_read_only(read_only);
+                Status(true)
+            }
+            CertificateServiceBinderRequest::IsReadOnly => {
+                Status(self.is_read_only())
+            }
+            CertificateServiceBinderRequest::ListSigningCertificates(cursor, limit) => {
+                Page(self.list_signing_certificates(cursor, limit))
+            }
+            CertificateServiceBinderRequest::ListEncryptionCertificates(cursor, limit) => {
+                Page(self.list_encryption_certificates(cursor, limit))
+            }
+            CertificateServiceBinderRequest::Reload => match self.reload() {
+                Ok(report) => Reloaded(report),
+                Err(error) => Rejected(error),
+            },
+            CertificateServiceBinderRequest::GetPolicy => {
+                Policy(self.get_policy())
+            }
+            CertificateServiceBinderRequest::SetPolicy(policy) => {
+                PolicyDecision(self.set_policy(policy).err())
+            }
+            CertificateServiceBinderRequest::EvaluatePolicy(subject) => {
+                PolicyDecision(self.evaluate_policy(&subject).err())
+            }
+            CertificateServiceBinderRequest::IsDirty => {
+                Status(self.is_dirty())
+            }
+            CertificateServiceBinderRequest::Flush => match self.flush() {
+                Ok(()) => Status(true),
+                Err(error) => Rejected(error),
+            },
+            CertificateServiceBinderRequest::AddRevocation(revocation) => {
+                Status(self.add_revocation(revocation))
+            }
+            CertificateServiceBinderRequest::IsRevoked(serial) => {
+                Status(self.is_revoked(serial))
+            }
+            CertificateServiceBinderRequest::GetRevocations => {
+                Revocations(self.get_revocations())
+            }
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use crate::pki::certificate::{Certificate, FLAG_SIGN_CERTS};
+use crate::pki::certificate::flags::{validate_flags, FlagError};
+use crate::pki::impls::certificates::falcon1024::{Falcon1024Certificate, Falcon1024RootCertificate};
+use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use crate::pki::key::CryptoKey;
+use crate::services::certificate::{CertificateService, ROOT_CERTIFICATE_SERIAL};
+use crate::errors::ErrorCode;
+
+///
+/// Exact point where verification of a certificate chain failed
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChainVerificationError{
+    /** Certificate has no parent serial, i.e. it was never issued **/
+    NoParent{serial: u128},
+    /** Certificate has no signature **/
+    Unsigned{serial: u128},
+    /** Parent of certificate is neither in chain nor in store **/
+    MissingParent{serial: u128, parent: u128},
+    /** Chain ends at root, but no root certificate is known **/
+    MissingRoot{serial: u128},
+    /** Parent of certificate is not allowed to sign certificates **/
+    ParentCanNotSign{serial: u128, parent: u128},
+    /** Certificate has flags unknown to registry **/
+    UnknownFlags{serial: u128, flags: u128},
+    /** Signature of certificate is not made by its parent **/
+    BadSignature{serial: u128, parent: u128},
+    /** Certificate is its own ancestor **/
+    Loop{serial: u128},
+}
+
+impl ChainVerificationError {
+    ///
+    /// Gets stable code of error for machine-readable output
+    ///
+    pub fn get_code(&self) -> &'static str{
+        match self {
+            ChainVerificationError::NoParent{..} => "no-parent",
+            ChainVerificationError::Unsigned{..} => "unsigned",
+            ChainVerificationError::MissingParent{..} => "missing-parent",
+            ChainVerificationError::MissingRoot{..} => "missing-root",
+            ChainVerificationError::ParentCanNotSign{..} => "parent-can-not-sign",
+            ChainVerificationError::UnknownFlags{..} => "unknown-flags",
+            ChainVerificationError::BadSignature{..} => "bad-signature",
+            ChainVerificationError::Loop{..} => "loop",
+        }
+    }
+
+    ///
+    /// Gets serial of certificate at which verification failed
+    ///
+    pub fn get_serial(&self) -> u128{
+        match self {
+            ChainVerificationError::NoParent{serial} |
+            ChainVerificationError::Unsigned{serial} |
+            ChainVerificationError::MissingParent{serial, ..} |
+            ChainVerificationError::MissingRoot{serial} |
+            ChainVerificationError::ParentCanNotSign{serial, ..} |
+            ChainVerificationError::UnknownFlags{serial, ..} |
+            ChainVerificationError::BadSignature{serial, ..} |
+            ChainVerificationError::Loop{serial} => *serial,
+        }
+    }
+}
+
+impl Display for ChainVerificationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChainVerificationError::NoParent{serial} =>
+                write!(f, "Certificate {} has no parent", serial),
+            ChainVerificationError::Unsigned{serial} =>
+                write!(f, "Certificate {} is not signed", serial),
+            ChainVerificationError::MissingParent{serial, parent} =>
+                write!(f, "Parent {} of certificate {} is not found", parent, serial),
+            ChainVerificationError::MissingRoot{serial} =>
+                write!(f, "Certificate {} is signed by root, but no root certificate is known", serial),
+            ChainVerificationError::ParentCanNotSign{serial, parent} =>
+                write!(f, "Parent {} of certificate {} can not sign certificates", parent, serial),
+            ChainVerificationError::UnknownFlags{serial, flags} =>
+                write!(f, "Certificate {} has unknown flags {:#x}", serial, flags),
+            ChainVerificationError::BadSignature{serial, parent} =>
+                write!(f, "Signature of certificate {} is not made by {}", serial, parent),
+            ChainVerificationError::Loop{serial} =>
+                write!(f, "Certificate {} is its own ancestor", serial),
+        }
+    }
+}
+
+impl ErrorCode for ChainVerificationError {
+    fn get_error_code(&self) -> &'static str {
+        match self {
+            ChainVerificationError::NoParent{..} => "MW-CERT-0101",
+            ChainVerificationError::Unsigned{..} => "MW-CERT-0102",
+            ChainVerificationError::MissingParent{..} => "MW-CERT-0103",
+            ChainVerificationError::MissingRoot{..} => "MW-CERT-0104",
+            ChainVerificationError::ParentCanNotSign{..} => "MW-CERT-0105",
+            ChainVerificationError::UnknownFlags{..} => "MW-CERT-0106",
+            ChainVerificationError::BadSignature{..} => "MW-CERT-0107",
+            ChainVerificationError::Loop{..} => "MW-CERT-0108",
+        }
+    }
+}
+
+///
+/// A set of certificates to verify chains against without touching certificate store,
+/// e.g. for checking certificates received out of band before importing them.
+///
+/// # Note
+/// Certificates have no validity period, so chains are never rejected as expired
+///
+#[derive(Clone, Default)]
+pub struct CertificateChain{
+    root: Option<Falcon1024RootCertificate>,
+    signing_certificates: HashMap<u128, Falcon1024Certificate>,
+}
+
+impl CertificateChain {
+    ///
+    /// Creates an empty chain without root certificate
+    ///
+    pub fn new() -> CertificateChain{
+        CertificateChain::default()
+    }
+
+    ///
+    /// Sets root certificate which terminates chains
+    ///
+    pub fn set_root_certificate(&mut self, root: Falcon1024RootCertificate) -> &mut Self{
+        self.root = Some(root.clone_without_sk());
+        self
+    }
+
+    ///
+    /// Adds signing certificate which may be a parent of verified one.
+    /// Certificates added earlier take precedence over later ones with same serial.
+    ///
+    pub fn add_signing_certificate(&mut self, certificate: Falcon1024Certificate) -> &mut Self{
+        self.signing_certificates.entry(certificate.get_serial())
+            .or_insert_with(|| certificate.clone_without_sk());
+        self
+    }
+
+    ///
+    /// Fills root and signing certificates which are not in chain yet from certificate store
+    ///
+    pub fn add_from_service<S: CertificateService + ?Sized>(&mut self, service: &mut S) -> &mut Self{
+        if self.root.is_none(){
+            if let Some(root) = service.get_root_certificate(){
+                self.set_root_certificate(root);
+            }
+        }
+        for certificate in service.get_signing_certificates(){
+            self.add_signing_certificate(certificate);
+        }
+        self
+    }
+
+    ///
+    /// Gets signing certificate known to chain
+    ///
+    #[inline]
+    pub fn get_signing_certificate(&self, serial: u128) -> Option<&Falcon1024Certificate>{
+        self.signing_certificates.get(&serial)
+    }
+
+    ///
+    /// Verifies that certificate is signed by given parent
+    ///
+    fn verify_link<PK: CryptoKey, SK: CryptoKey, C: Certificate<PK, SK>>(&self, certificate: &C)
+        -> Result<u128, ChainVerificationError>{
+        let serial = certificate.get_serial();
+        if let Err(FlagError::UnknownBits(flags)) = validate_flags(certificate.get_flags(), true){
+            return Err(ChainVerificationError::UnknownFlags{serial, flags});
+        }
+        let parent = certificate.get_parent_serial()
+            .ok_or(ChainVerificationError::NoParent{serial})?;
+        let signature = certificate.get_signature()
+            .ok_or(ChainVerificationError::Unsigned{serial})?;
+        let signable = certificate.clone_without_signature_and_sk();
+        let is_valid = if parent == ROOT_CERTIFICATE_SERIAL{
+            let root = self.root.as_ref().ok_or(ChainVerificationError::MissingRoot{serial})?;
+            root.verify_signature(&signable, &signature)
+        } else {
+            let parent_certificate = self.signing_certificates.get(&parent)
+                .ok_or(ChainVerificationError::MissingParent{serial, parent})?;
+            if !parent_certificate.check_flag(FLAG_SIGN_CERTS){
+                return Err(ChainVerificationError::ParentCanNotSign{serial, parent});
+            }
+            parent_certificate.verify_signature(&signable, &signature)
+        };
+        if !is_valid{
+            return Err(ChainVerificationError::BadSignature{serial, parent});
+        }
+        Ok(parent)
+    }
+
+    ///
+    /// Verifies chain from parent of certificate up to root
+    ///
+    fn verify_ancestors(&self, mut parent: u128, path: &mut Vec<u128>) -> Result<(), ChainVerificationError>{
+        while parent != ROOT_CERTIFICATE_SERIAL{
+            if path.contains(&parent){
+                return Err(ChainVerificationError::Loop{serial: parent});
+            }
+            path.push(parent);
+            // Presence of parent is checked by verify_link of its child
+            let certificate = &self.signing_certificates[&parent];
+            parent = self.verify_link(certificate)?;
+        }
+        path.push(ROOT_CERTIFICATE_SERIAL);
+        Ok(())
+    }
+
+    ///
+    /// Verifies signing certificate up to root
+    ///
+    /// returns: Result<Vec<u128>, ChainVerificationError>: serials of chain starting with
+    /// certificate itself and ending with root or the point where verification failed
+    ///
+    pub fn verify_signing_certificate(&self, certificate: &Falcon1024Certificate)
+        -> Result<Vec<u128>, ChainVerificationError>{
+        let mut path = vec![certificate.get_serial()];
+        let parent = self.verify_link(certificate)?;
+        self.verify_ancestors(parent, &mut path)?;
+        Ok(path)
+    }
+
+    ///
+    /// Verifies encryption certificate up to root
+    ///
+    /// returns: Result<Vec<u128>, ChainVerificationError>: serials of chain starting with
+    /// certificate itself and ending with root or the point where verification failed
+    ///
+    pub fn verify_encryption_certificate(&self, certificate: &Kyber1024Certificate)
+        -> Result<Vec<u128>, ChainVerificationError>{
+        let mut path = vec![certificate.get_serial()];
+        let parent = self.verify_link(certificate)?;
+        self.verify_ancestors(parent, &mut path)?;
+        Ok(path)
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use crate::pki::certificate::FLAG_SIGN_MESSAGES;
+    use crate::pki::hash::HashType;
+    use crate::pki::impls::certificates::falcon1024::generate_falcon1024_root_certificate;
+    use crate::pki::impls::keys::falcon1024::generate_falcon1024_keypair;
+    use super::*;
+
+    fn issue(parent: &Falcon1024Certificate, serial: u128, flags: u128) -> Falcon1024Certificate{
+        let (public_key, secret_key) = generate_falcon1024_keypair();
+        let mut certificate = Falcon1024Certificate{
+            serial_number: serial,
+            parent_serial_number: parent.get_serial(),
+            secret_key: Some(secret_key),
+            public_key,
+            signature: None,
+            name: format!("test{}", serial),
+            flags,
+            metadata: Default::default(),
+        };
+        certificate.signature = Some(parent.sign_data(&certificate.clone_without_signature_and_sk(),
+                                                      HashType::None).unwrap());
+        certificate
+    }
+
+    #[test]
+    fn test_verify_chain() {
+        let root = generate_falcon1024_root_certificate("root".to_string());
+        let (public_key, secret_key) = generate_falcon1024_keypair();
+        let mut intermediate = Falcon1024Certificate{
+            serial_number: 1,
+            parent_serial_number: ROOT_CERTIFICATE_SERIAL,
+            secret_key: Some(secret_key),
+            public_key,
+            signature: None,
+            name: "intermediate".to_string(),
+            flags: FLAG_SIGN_CERTS,
+            metadata: Default::default(),
+        };
+        intermediate.signature = Some(root.sign_data(&intermediate.clone_without_signature_and_sk(),
+                                                     HashType::None).unwrap());
+        let leaf = issue(&intermediate, 2, FLAG_SIGN_MESSAGES);
+        let mut chain = CertificateChain::new();
+        assert_eq!(chain.verify_signing_certificate(&leaf),
+                   Err(ChainVerificationError::MissingParent{serial: 2, parent: 1}));
+        chain.add_signing_certificate(intermediate.clone());
+        assert_eq!(chain.verify_signing_certificate(&leaf),
+                   Err(ChainVerificationError::MissingRoot{serial: 1}));
+        chain.set_root_certificate(root);
+        assert_eq!(chain.verify_signing_certificate(&leaf), Ok(vec![2, 1, 0]));
+
+        // Leaf can not sign certificates
+        let orphan = issue(&leaf, 3, 0);
+        chain.add_signing_certificate(leaf.clone());
+        assert_eq!(chain.verify_signing_certificate(&orphan),
+                   Err(ChainVerificationError::ParentCanNotSign{serial: 3, parent: 2}));
+
+        let mut tampered = leaf.clone();
+        tampered.flags |= FLAG_SIGN_CERTS;
+        let error = chain.verify_signing_certificate(&tampered).unwrap_err();
+        assert_eq!(error, ChainVerificationError::BadSignature{serial: 2, parent: 1});
+        assert_eq!(error.get_code(), "bad-signature");
+    }
+}
@@ -0,0 +1,218 @@
+use std::sync::mpsc;
+use crate::message::certsync::CertificateRevocation;
+use crate::pki::impls::certificates::falcon1024::{Falcon1024Certificate, Falcon1024RootCertificate};
+use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use crate::services::certificate::{CertificateService, CertificateServiceBinder, CertificateServiceError,
+                                   VerifiableCertificate};
+use crate::services::certificate::listing::CertificatePage;
+use crate::services::certificate::policy::{CertificatePolicy, PolicyError, PolicySubject};
+use crate::services::certificate::reload::CertificateReloadReport;
+use crate::services::certificate::usage::{KeyUsage, UsageThresholds};
+use crate::tokio::init_tokio;
+
+type DetachedCall = Box<dyn FnOnce(&mut CertificateServiceBinder) + Send>;
+
+///
+/// Certificate service whose requests are made by a binder on its own thread. Binders block
+/// on runtime of calling thread, so they can not be used from coroutines or from threads
+/// without runtime, e.g. threads of admin server and of watchers; this service may be.
+///
+/// Calls wait for the binder thread, so coroutines calling it stall their runtime until
+/// request is handled. If binder thread is gone, calls return what a service without
+/// certificates would.
+///
+#[derive(Clone)]
+pub struct DetachedCertificateService{
+    calls: mpsc::Sender<DetachedCall>,
+}
+
+impl DetachedCertificateService {
+    ///
+    /// Starts thread of binder, it is stopped once all clones are dropped
+    ///
+    /// # Arguments
+    /// * create: F: binds to certificate service on thread of binder
+    ///
+    pub fn spawn<F>(create: F) -> DetachedCertificateService
+        where F: FnOnce() -> Box<CertificateServiceBinder> + Send + 'static{
+        let (calls, receiver) = mpsc::channel::<DetachedCall>();
+        std::thread::spawn(move || {
+            init_tokio();
+            let mut binder = create();
+            for call in receiver{
+                call(binder.as_mut());
+            }
+        });
+        DetachedCertificateService{
+            calls,
+        }
+    }
+
+    // Runs call on binder thread and waits for its result
+    fn call<R, F>(&self, call: F) -> Option<R>
+        where R: Send + 'static,
+              F: FnOnce(&mut CertificateServiceBinder) -> R + Send + 'static{
+        let (reply, result) = mpsc::channel();
+        let sent = self.calls.send(Box::new(move |binder| {
+            let _ = reply.send(call(binder));
+        }));
+        if sent.is_err(){
+            log::error!("Certificate service is not available");
+            return None;
+        }
+        result.recv().ok()
+    }
+}
+
+impl CertificateService for DetachedCertificateService{
+    fn set_root_certificate(&mut self, root_cert: Falcon1024RootCertificate) {
+        self.call(move |binder| binder.set_root_certificate(root_cert));
+    }
+
+    fn add_signing_certificate(&mut self, cert: Falcon1024Certificate) -> bool {
+        self.call(move |binder| binder.add_signing_certificate(cert)).unwrap_or(false)
+    }
+
+    fn add_encryption_certificate(&mut self, cert: Kyber1024Certificate) -> bool {
+        self.call(move |binder| binder.add_encryption_certificate(cert)).unwrap_or(false)
+    }
+
+    fn verify_signing_certificate(&mut self, cert: &Falcon1024Certificate) -> bool {
+        let cert = cert.clone();
+        self.call(move |binder| binder.verify_signing_certificate(&cert)).unwrap_or(false)
+    }
+
+    fn verify_encryption_certificate(&mut self, cert: &Kyber1024Certificate) -> bool {
+        let cert = cert.clone();
+        self.call(move |binder| binder.verify_encryption_certificate(&cert)).unwrap_or(false)
+    }
+
+    fn get_signing_certificate(&mut self, serial: u128) -> Option<Falcon1024Certificate> {
+        self.call(move |binder| binder.get_signing_certificate(serial)).flatten()
+    }
+
+    fn get_encryption_certificate(&mut self, serial: u128) -> Option<Kyber1024Certificate> {
+        self.call(move |binder| binder.get_encryption_certificate(serial)).flatten()
+    }
+
+    fn get_root_certificate(&mut self) -> Option<Falcon1024RootCertificate> {
+        self.call(|binder| binder.get_root_certificate()).flatten()
+    }
+
+    fn get_signing_certificates(&mut self) -> Vec<Falcon1024Certificate> {
+        self.call(|binder| binder.get_signing_certificates()).unwrap_or_default()
+    }
+
+    fn get_encryption_certificates(&mut self) -> Vec<Kyber1024Certificate> {
+        self.call(|binder| binder.get_encryption_certificates()).unwrap_or_default()
+    }
+
+    fn remove_signing_certificate(&mut self, serial: u128) -> bool {
+        self.call(move |binder| binder.remove_signing_certificate(serial)).unwrap_or(false)
+    }
+
+    fn remove_encryption_certificate(&mut self, serial: u128) -> bool {
+        self.call(move |binder| binder.remove_encryption_certificate(serial)).unwrap_or(false)
+    }
+
+    fn verify_many(&mut self, certs: &[VerifiableCertificate]) -> Vec<bool> {
+        let certs = certs.to_vec();
+        let count = certs.len();
+        self.call(move |binder| binder.verify_many(&certs)).unwrap_or_else(|| vec![false; count])
+    }
+
+    fn record_key_usage(&mut self, usage: KeyUsage) -> bool {
+        self.call(move |binder| binder.record_key_usage(usage)).unwrap_or(false)
+    }
+
+    fn get_key_usage(&mut self) -> Vec<KeyUsage> {
+        self.call(|binder| binder.get_key_usage()).unwrap_or_default()
+    }
+
+    fn set_usage_thresholds(&mut self, thresholds: UsageThresholds) {
+        self.call(move |binder| binder.set_usage_thresholds(thresholds));
+    }
+
+    fn get_usage_thresholds(&mut self) -> UsageThresholds {
+        self.call(|binder| binder.get_usage_thresholds()).unwrap_or_default()
+    }
+
+    fn list_signing_certificates(&mut self, cursor: Option<u128>, limit: u32) -> CertificatePage {
+        self.call(move |binder| binder.list_signing_certificates(cursor, limit)).unwrap_or_default()
+    }
+
+    fn list_encryption_certificates(&mut self, cursor: Option<u128>, limit: u32) -> CertificatePage {
+        self.call(move |binder| binder.list_encryption_certificates(cursor, limit)).unwrap_or_default()
+    }
+
+    fn set_read_only(&mut self, read_only: bool) {
+        self.call(move |binder| binder.set_read_only(read_only));
+    }
+
+    fn is_read_only(&mut self) -> bool {
+        self.call(|binder| binder.is_read_only()).unwrap_or(true)
+    }
+
+    fn reload(&mut self) -> Result<CertificateReloadReport, CertificateServiceError> {
+        self.call(|binder| binder.reload()).unwrap_or(Err(CertificateServiceError::ReloadFailed))
+    }
+
+    fn get_policy(&mut self) -> CertificatePolicy {
+        self.call(|binder| binder.get_policy()).unwrap_or_default()
+    }
+
+    fn set_policy(&mut self, policy: CertificatePolicy) -> Result<(), PolicyError> {
+        self.call(move |binder| binder.set_policy(policy)).unwrap_or(Err(PolicyError::Unsupported))
+    }
+
+    fn evaluate_policy(&mut self, subject: &PolicySubject) -> Result<(), PolicyError> {
+        let subject = subject.clone();
+        self.call(move |binder| binder.evaluate_policy(&subject)).unwrap_or(Err(PolicyError::Unsupported))
+    }
+
+    fn add_revocation(&mut self, revocation: CertificateRevocation) -> bool {
+        self.call(move |binder| binder.add_revocation(revocation)).unwrap_or(false)
+    }
+
+    fn is_revoked(&mut self, serial: u128) -> bool {
+        self.call(move |binder| binder.is_revoked(serial)).unwrap_or(false)
+    }
+
+    fn get_revocations(&mut self) -> Vec<CertificateRevocation> {
+        self.call(|binder| binder.get_revocations()).unwrap_or_default()
+    }
+
+    fn commit(&mut self) {
+        self.call(|binder| binder.commit());
+    }
+
+    fn is_dirty(&mut self) -> bool {
+        self.call(|binder| binder.is_dirty()).unwrap_or(false)
+    }
+
+    fn flush(&mut self) -> Result<(), CertificateServiceError> {
+        self.call(|binder| binder.flush()).unwrap_or(Err(CertificateServiceError::CommitFailed))
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actor::binder::BinderChannelProvider;
+    use crate::services::certificate::CertificateAsyncService;
+    use crate::testing::certificate::{MockCertificateService, TEST_SIGNING_CERTIFICATE_SERIAL};
+
+    #[tokio::test]
+    async fn test_called_from_coroutine() {
+        let mock = MockCertificateService::with_test_certificates();
+        let committed = mock.clone();
+        let mut service = DetachedCertificateService::spawn(move || {
+            CertificateAsyncService::run(Box::new(mock)).bind()
+        });
+        assert!(service.get_signing_certificate(TEST_SIGNING_CERTIFICATE_SERIAL).is_some());
+        assert!(service.clone().get_root_certificate().is_some());
+        service.commit();
+        assert_eq!(committed.get_commit_count(), 1);
+    }
+}
@@ -0,0 +1,403 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use notify::event::{AccessKind, AccessMode, CreateKind, ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use crate::get_timestamp_with_milliseconds;
+use crate::pki::certificate::Certificate;
+use crate::pki::export::ExportFile;
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use crate::services::certificate::{CertificateService, VerifiableCertificate, ROOT_CERTIFICATE_SERIAL};
+
+///
+/// Default count of entries kept in audit of directory watcher
+///
+pub const DEFAULT_DIRECTORY_AUDIT_CAPACITY: usize = 256;
+
+///
+/// Why certificate file was not imported
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum DirectoryImportError{
+    /** File can not be read **/
+    Io(String),
+    /** File is neither signing nor encryption certificate **/
+    Malformed,
+    /** Envelope of export is signed by unknown certificate or does not verify **/
+    UntrustedExport(String),
+    /** Certificate failed verification by certificate service **/
+    Rejected(u128),
+    /** Certificate store is read-only **/
+    ReadOnly,
+}
+
+impl Display for DirectoryImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DirectoryImportError::Io(error) => write!(f, "{}", error),
+            DirectoryImportError::Malformed => write!(f, "file is not a signing or encryption certificate"),
+            DirectoryImportError::UntrustedExport(error) => write!(f, "export is not trusted: {}", error),
+            DirectoryImportError::Rejected(serial) => write!(f, "certificate {} failed verification", serial),
+            DirectoryImportError::ReadOnly => write!(f, "certificate store is read-only"),
+        }
+    }
+}
+
+///
+/// What happened to certificate file which was read successfully
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DirectoryImportOutcome{
+    ImportedSigning(u128),
+    ImportedEncryption(u128),
+    /** Certificate with such serial is already in store, file is skipped **/
+    Known(u128),
+}
+
+impl DirectoryImportOutcome {
+    pub fn get_serial(&self) -> u128{
+        match self {
+            DirectoryImportOutcome::ImportedSigning(serial) |
+            DirectoryImportOutcome::ImportedEncryption(serial) |
+            DirectoryImportOutcome::Known(serial) => *serial,
+        }
+    }
+
+    #[inline]
+    pub fn is_imported(&self) -> bool{
+        !matches!(self, DirectoryImportOutcome::Known(_))
+    }
+}
+
+///
+/// Results of importing every file of directory
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DirectoryImportReport{
+    pub imported: Vec<(PathBuf, DirectoryImportOutcome)>,
+    pub skipped: Vec<(PathBuf, u128)>,
+    pub failed: Vec<(PathBuf, DirectoryImportError)>,
+}
+
+///
+/// Checks envelope of export against certificates in store, files without envelope are accepted
+///
+fn check_export<S: CertificateService + ?Sized>(service: &mut S, file: &ExportFile) -> Result<(), DirectoryImportError>{
+    let export = match file.get_signed() {
+        Some(export) => export,
+        None => return Ok(()),
+    };
+    let signer_serial = export.provenance.signer_serial;
+    let result = if signer_serial == ROOT_CERTIFICATE_SERIAL {
+        service.get_root_certificate().map(|root| export.verify(&root))
+    } else {
+        service.get_signing_certificate(signer_serial).map(|certificate| export.verify(&certificate))
+    };
+    match result {
+        Some(Ok(())) => Ok(()),
+        Some(Err(error)) => Err(DirectoryImportError::UntrustedExport(error.to_string())),
+        None => Err(DirectoryImportError::UntrustedExport(format!("signed by unknown certificate {}", signer_serial))),
+    }
+}
+
+///
+/// Reads certificate of any kind from exported file. Public keys of both kinds have
+/// different sizes, so certificate of one kind is never read as the other one.
+///
+fn read_certificate(file: &ExportFile) -> Option<VerifiableCertificate>{
+    if let Ok(certificate) = file.get_content::<Falcon1024Certificate>(){
+        return Some(VerifiableCertificate::Signing(Box::new(certificate)));
+    }
+    file.get_content::<Kyber1024Certificate>().ok().map(|certificate| VerifiableCertificate::Encryption(Box::new(certificate)))
+}
+
+///
+/// Imports signing or encryption certificate from file exported by certman. Certificates with
+/// serials already in store are skipped, others are verified by service before being added.
+/// Changes are not committed.
+///
+/// # Arguments
+/// * service: &mut S: service to import certificate to
+/// * path: &Path: exported certificate, signed or not
+///
+pub fn import_certificate_file<S: CertificateService + ?Sized>(service: &mut S,
+                                                              path: &Path) -> Result<DirectoryImportOutcome, DirectoryImportError>{
+    if service.check_writable().is_err(){
+        return Err(DirectoryImportError::ReadOnly);
+    }
+    if !path.is_file(){
+        return Err(DirectoryImportError::Io(format!("{} is not a file", path.display())));
+    }
+    let file = ExportFile::read(path).map_err(|_| DirectoryImportError::Io(format!("can not read {}", path.display())))?;
+    let certificate = read_certificate(&file).ok_or(DirectoryImportError::Malformed)?;
+    check_export(service, &file)?;
+    match certificate {
+        VerifiableCertificate::Signing(certificate) => {
+            let serial = certificate.get_serial();
+            if service.get_signing_certificate(serial).is_some(){
+                return Ok(DirectoryImportOutcome::Known(serial));
+            }
+            if !service.add_signing_certificate(*certificate){
+                return Err(DirectoryImportError::Rejected(serial));
+            }
+            Ok(DirectoryImportOutcome::ImportedSigning(serial))
+        }
+        VerifiableCertificate::Encryption(certificate) => {
+            let serial = certificate.get_serial();
+            if service.get_encryption_certificate(serial).is_some(){
+                return Ok(DirectoryImportOutcome::Known(serial));
+            }
+            if !service.add_encryption_certificate(*certificate){
+                return Err(DirectoryImportError::Rejected(serial));
+            }
+            Ok(DirectoryImportOutcome::ImportedEncryption(serial))
+        }
+    }
+}
+
+///
+/// Checks whether file should be looked at: hidden files are usually temporary files
+/// of editors and configuration management
+///
+fn is_candidate(path: &Path) -> bool{
+    path.file_name().and_then(|name| name.to_str()).is_some_and(|name| !name.starts_with('.'))
+}
+
+///
+/// Imports all certificate files of directory in order of names, see import_certificate_file.
+/// Changes are committed if anything was imported.
+///
+/// # Arguments
+/// * service: &mut S: service to import certificates to
+/// * directory: &Path: directory with exported certificates, subdirectories are ignored
+///
+pub fn import_certificate_directory<S: CertificateService + ?Sized>(service: &mut S,
+                                                                   directory: &Path) -> std::io::Result<DirectoryImportReport>{
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && is_candidate(path))
+        .collect();
+    paths.sort();
+    let mut report = DirectoryImportReport::default();
+    for path in paths{
+        match import_certificate_file(service, &path) {
+            Ok(DirectoryImportOutcome::Known(serial)) => report.skipped.push((path, serial)),
+            Ok(outcome) => report.imported.push((path, outcome)),
+            Err(error) => report.failed.push((path, error)),
+        }
+    }
+    if !report.imported.is_empty(){
+        service.commit();
+    }
+    Ok(report)
+}
+
+///
+/// Checks whether event means that file is written completely: it was closed after writing
+/// or moved into place
+///
+pub(crate) fn is_complete_file_event(kind: &EventKind) -> bool{
+    matches!(kind, EventKind::Create(CreateKind::File) | EventKind::Create(CreateKind::Any)
+        | EventKind::Access(AccessKind::Close(AccessMode::Write))
+        | EventKind::Modify(ModifyKind::Name(RenameMode::To)) | EventKind::Modify(ModifyKind::Name(RenameMode::Any)))
+}
+
+///
+/// Record about file seen by directory watcher
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct DirectoryImportAuditEntry{
+    /** When file was processed **/
+    pub timestamp: u128,
+    pub path: PathBuf,
+    pub result: Result<DirectoryImportOutcome, DirectoryImportError>,
+}
+
+struct DirectoryAudit{
+    entries: Vec<DirectoryImportAuditEntry>,
+    capacity: usize,
+}
+
+impl DirectoryAudit {
+    fn record(&mut self, path: PathBuf, result: Result<DirectoryImportOutcome, DirectoryImportError>){
+        match &result {
+            Ok(DirectoryImportOutcome::Known(serial)) =>
+                log::debug!("Certificate {} from {} is already known", serial, path.display()),
+            Ok(outcome) => log::info!("Imported certificate {} from {}", outcome.get_serial(), path.display()),
+            Err(error) => log::warn!("Can not import certificate from {}: {}", path.display(), error),
+        }
+        if self.entries.len() >= self.capacity{
+            self.entries.remove(0);
+        }
+        self.entries.push(DirectoryImportAuditEntry{
+            timestamp: get_timestamp_with_milliseconds(),
+            path,
+            result,
+        });
+    }
+}
+
+///
+/// Imports certificates dropped into directory, e.g. by configuration management. Files already
+/// in directory are imported once watcher starts, later files are imported once they are closed
+/// after writing or moved into directory. Every file is verified as by import_certificate_file,
+/// logged and kept in audit. Directory is watched until watcher is dropped.
+///
+pub struct CertificateDirectoryWatcher{
+    /** Stops watching once dropped **/
+    _watcher: RecommendedWatcher,
+    directory: PathBuf,
+    audit: Arc<Mutex<DirectoryAudit>>,
+}
+
+impl CertificateDirectoryWatcher {
+    ///
+    /// Imports certificates already in directory and starts watching it
+    ///
+    /// # Arguments
+    /// * directory: &Path: directory to watch
+    /// * service: Arc<Mutex<Box<S>>>: service to import certificates to
+    ///
+    pub fn start<S: CertificateService + ?Sized + Send + 'static>(directory: &Path, service: Arc<Mutex<Box<S>>>)
+        -> notify::Result<CertificateDirectoryWatcher>{
+        let audit = Arc::new(Mutex::new(DirectoryAudit{
+            entries: Vec::new(),
+            capacity: DEFAULT_DIRECTORY_AUDIT_CAPACITY,
+        }));
+        let report = import_certificate_directory(service.lock().unwrap().as_mut(), directory)?;
+        {
+            let mut audit = audit.lock().unwrap();
+            for (path, outcome) in report.imported{
+                audit.record(path, Ok(outcome));
+            }
+            for (path, error) in report.failed{
+                audit.record(path, Err(error));
+            }
+        }
+        let audit_clone = audit.clone();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let event = match event {
+                Ok(event) => event,
+                Err(error) => {
+                    log::error!("Error while watching certificate directory: {}", error);
+                    return;
+                }
+            };
+            if !is_complete_file_event(&event.kind){
+                return;
+            }
+            for path in event.paths.iter().filter(|path| is_candidate(path)){
+                // Empty files are just created, they are imported once closed after writing
+                if std::fs::metadata(path).is_ok_and(|metadata| metadata.len() == 0){
+                    continue;
+                }
+                let mut service = service.lock().unwrap();
+                let result = import_certificate_file(service.as_mut(), path);
+                if result.as_ref().is_ok_and(|outcome| outcome.is_imported()){
+                    service.commit();
+                }
+                audit_clone.lock().unwrap().record(path.clone(), result);
+            }
+        })?;
+        watcher.watch(directory, RecursiveMode::NonRecursive)?;
+        log::info!("Watching {} for certificates", directory.display());
+        Ok(CertificateDirectoryWatcher{
+            _watcher: watcher,
+            directory: directory.to_path_buf(),
+            audit,
+        })
+    }
+
+    #[inline]
+    pub fn get_directory(&self) -> &Path{
+        &self.directory
+    }
+
+    ///
+    /// Gets files processed by watcher, oldest first
+    ///
+    pub fn get_audit(&self) -> Vec<DirectoryImportAuditEntry>{
+        self.audit.lock().unwrap().entries.clone()
+    }
+
+    ///
+    /// Sets how many audit entries are kept, oldest ones are removed first
+    ///
+    pub fn set_audit_capacity(&mut self, capacity: usize) -> &mut Self{
+        let mut audit = self.audit.lock().unwrap();
+        audit.capacity = capacity.max(1);
+        while audit.entries.len() > audit.capacity{
+            audit.entries.remove(0);
+        }
+        drop(audit);
+        self
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+    use crate::pki::export::SignedExport;
+    use crate::serialization::serializable::Serializable;
+    use crate::services::certificate::readonly::ReadOnlyCertificateService;
+    use crate::testing::certificate::{test_certificates, MockCertificateService, TEST_ENCRYPTION_CERTIFICATE_SERIAL,
+                                      TEST_SIGNING_CERTIFICATE_SERIAL};
+
+    fn create_directory(name: &str) -> PathBuf{
+        let directory = std::env::temp_dir().join(format!("milkyway-{}-{}", name, rand::random::<u64>()));
+        std::fs::create_dir(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn test_import_certificate_directory() {
+        let certificates = test_certificates();
+        let directory = create_directory("import-dir");
+        certificates.signing.clone_without_sk().dump(directory.join("signing.cert").to_str().unwrap()).unwrap();
+        SignedExport::sign(&certificates.encryption.clone_without_sk(), &certificates.root).unwrap()
+            .dump_to_file(directory.join("encryption.cert").to_str().unwrap()).unwrap();
+        std::fs::write(directory.join("garbage"), [1, 2, 3]).unwrap();
+        std::fs::write(directory.join(".hidden"), [1, 2, 3]).unwrap();
+
+        let mut service = MockCertificateService::with_test_certificates();
+        assert!(service.remove_encryption_certificate(TEST_ENCRYPTION_CERTIFICATE_SERIAL));
+        let report = import_certificate_directory(&mut service, &directory).unwrap();
+        assert_eq!(report.imported, vec![(directory.join("encryption.cert"),
+                                          DirectoryImportOutcome::ImportedEncryption(TEST_ENCRYPTION_CERTIFICATE_SERIAL))]);
+        assert_eq!(report.skipped, vec![(directory.join("signing.cert"), TEST_SIGNING_CERTIFICATE_SERIAL)]);
+        assert_eq!(report.failed, vec![(directory.join("garbage"), DirectoryImportError::Malformed)]);
+        assert_eq!(service.get_commit_count(), 1);
+
+        let mut service = ReadOnlyCertificateService::new(service, true);
+        assert_eq!(import_certificate_file(&mut service, &directory.join("signing.cert")),
+                   Err(DirectoryImportError::ReadOnly));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_certificate_directory_watcher() {
+        let certificates = test_certificates();
+        let directory = create_directory("watch-dir");
+        let mut service = MockCertificateService::with_test_certificates();
+        assert!(service.remove_signing_certificate(TEST_SIGNING_CERTIFICATE_SERIAL));
+        let service: Arc<Mutex<Box<MockCertificateService>>> = Arc::new(Mutex::new(Box::new(service)));
+        let watcher = CertificateDirectoryWatcher::start(&directory, service.clone()).unwrap();
+        assert!(watcher.get_audit().is_empty());
+
+        let staged = directory.join(".signing.tmp");
+        std::fs::write(&staged, certificates.signing.clone_without_sk().serialize()).unwrap();
+        std::fs::rename(&staged, directory.join("signing.cert")).unwrap();
+        let started = Instant::now();
+        while service.lock().unwrap().get_signing_certificate(TEST_SIGNING_CERTIFICATE_SERIAL).is_none(){
+            assert!(started.elapsed() < Duration::from_secs(5), "Certificate was not imported");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let audit = watcher.get_audit();
+        assert_eq!(audit[0].result, Ok(DirectoryImportOutcome::ImportedSigning(TEST_SIGNING_CERTIFICATE_SERIAL)));
+        drop(watcher);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
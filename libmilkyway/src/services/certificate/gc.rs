@@ -0,0 +1,360 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::get_timestamp_with_milliseconds;
+use crate::pki::certificate::Certificate;
+use crate::pki::key::CryptoKey;
+use crate::services::certificate::{CertificateService, ROOT_CERTIFICATE_SERIAL};
+use crate::services::certificate::reload::CertificateKind;
+
+///
+/// Default interval of garbage collection in daemon, seconds
+///
+pub const DEFAULT_GC_INTERVAL: u64 = 86400;
+
+///
+/// Which certificates are garbage and how often daemon looks for them
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CertificateGcPolicy{
+    /** Seconds expired and revoked certificates are kept after they expire or are revoked **/
+    pub retention: u64,
+    /** Seconds between collections of run_certificate_gc **/
+    pub interval: u64,
+}
+
+impl Default for CertificateGcPolicy {
+    fn default() -> Self {
+        CertificateGcPolicy{
+            retention: 0,
+            interval: DEFAULT_GC_INTERVAL,
+        }
+    }
+}
+
+///
+/// Why certificate is garbage
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GarbageReason{
+    /** Certificate expired at given time(see validity of metadata) and its retention passed **/
+    Expired(u64),
+    /** Certificate with given serial, this one or one above it, was revoked at given time and
+     * retention passed **/
+    Revoked(u128, u64),
+    /** Chain of certificate does not verify or certificate with given serial which signed it
+     * is garbage itself **/
+    Orphaned(u128),
+}
+
+impl Display for GarbageReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GarbageReason::Expired(expires) => write!(f, "expired at {}", expires),
+            GarbageReason::Revoked(serial, revoked) => write!(f, "{} was revoked at {}", serial, revoked),
+            GarbageReason::Orphaned(parent) => write!(f, "chain through parent {} is broken", parent),
+        }
+    }
+}
+
+///
+/// Certificate which may be removed from store
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct GarbageCertificate{
+    pub kind: CertificateKind,
+    pub serial: u128,
+    pub name: String,
+    pub reason: GarbageReason,
+}
+
+impl Display for GarbageCertificate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} certificate {}({}): {}", self.kind, self.serial, self.name, self.reason)
+    }
+}
+
+///
+/// Record about certificate pruned by collect_garbage
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct CertificateGcAuditEntry{
+    /** When certificate was pruned **/
+    pub timestamp: u128,
+    pub certificate: GarbageCertificate,
+    /** False if service refused to remove certificate, e.g. it is read-only **/
+    pub removed: bool,
+}
+
+// Certificate of store as garbage collection sees it
+struct StoredCertificate{
+    kind: CertificateKind,
+    serial: u128,
+    parent: Option<u128>,
+    name: String,
+    expires: Option<u64>,
+    /** Certificate belongs to this host **/
+    has_secret_key: bool,
+    /** Service verified chain of certificate **/
+    verified: bool,
+}
+
+impl StoredCertificate {
+    fn from_certificate<PK: CryptoKey, SK: CryptoKey, C: Certificate<PK, SK>>(kind: CertificateKind, certificate: &C,
+                                                                           verified: bool) -> StoredCertificate{
+        StoredCertificate{
+            kind,
+            serial: certificate.get_serial(),
+            parent: certificate.get_parent_serial(),
+            name: certificate.get_name(),
+            expires: certificate.get_metadata().validity.and_then(|validity| validity.expires_at),
+            has_secret_key: certificate.get_secret_key().is_some(),
+            verified,
+        }
+    }
+
+    fn to_garbage(&self, reason: GarbageReason) -> GarbageCertificate{
+        GarbageCertificate{
+            kind: self.kind,
+            serial: self.serial,
+            name: self.name.clone(),
+            reason,
+        }
+    }
+}
+
+///
+/// Finds certificates which may be removed: expired and revoked ones past retention and ones
+/// whose chain does not verify. Certificates holding secret keys and certificates above them are
+/// never garbage. Nothing is removed, so it serves as dry run of collect_garbage.
+///
+/// # Arguments
+/// * service: &mut S: service to look in
+/// * policy: &CertificateGcPolicy: retention of expired and revoked certificates
+/// * now: u64: current time, seconds since UNIX epoch
+///
+/// returns: Vec<GarbageCertificate>: expired and revoked certificates first, then orphaned ones
+/// with parents before their children
+///
+pub fn find_garbage<S: CertificateService + ?Sized>(service: &mut S, policy: &CertificateGcPolicy,
+                                                    now: u64) -> Vec<GarbageCertificate>{
+    let mut certificates = Vec::new();
+    for certificate in service.get_signing_certificates(){
+        let verified = service.verify_signing_certificate(&certificate);
+        certificates.push(StoredCertificate::from_certificate(CertificateKind::Signing, &certificate, verified));
+    }
+    for certificate in service.get_encryption_certificates(){
+        let verified = service.verify_encryption_certificate(&certificate);
+        certificates.push(StoredCertificate::from_certificate(CertificateKind::Encryption, &certificate, verified));
+    }
+    certificates.sort_by_key(|certificate| certificate.serial);
+    let revocations: HashMap<u128, u64> = service.get_revocations().iter()
+        .map(|revocation| (revocation.serial, (revocation.timestamp / 1000) as u64))
+        .collect();
+    let parents: HashMap<u128, u128> = certificates.iter()
+        .filter_map(|certificate| certificate.parent.map(|parent| (certificate.serial, parent)))
+        .collect();
+    // Walks chain up to root, calling visit for every serial until it returns a value
+    let find_in_chain = |serial: u128, visit: &mut dyn FnMut(u128) -> Option<(u128, u64)>| {
+        let mut current = Some(serial);
+        let mut seen = HashSet::new();
+        while let Some(serial) = current.filter(|serial| *serial != ROOT_CERTIFICATE_SERIAL && seen.insert(*serial)){
+            if let Some(found) = visit(serial){
+                return Some(found);
+            }
+            current = parents.get(&serial).copied();
+        }
+        None
+    };
+    // Certificates of this host and the chain they rely on are left to operator
+    let mut protected = HashSet::new();
+    for certificate in certificates.iter().filter(|certificate| certificate.has_secret_key){
+        find_in_chain(certificate.serial, &mut |serial| {
+            protected.insert(serial);
+            None
+        });
+    }
+    let mut garbage = Vec::new();
+    let mut collected = HashSet::new();
+    // Certificates under revoked ones are kept until retention passes, even though chain is broken
+    let mut retained = HashSet::new();
+    for certificate in certificates.iter().filter(|certificate| !protected.contains(&certificate.serial)){
+        if let Some(expires) = certificate.expires.filter(|expires| expires.saturating_add(policy.retention) <= now){
+            garbage.push(certificate.to_garbage(GarbageReason::Expired(expires)));
+            collected.insert(certificate.serial);
+            continue;
+        }
+        let revocation = find_in_chain(certificate.serial, &mut |serial| {
+            revocations.get(&serial).map(|revoked| (serial, *revoked))
+        });
+        match revocation {
+            Some((serial, revoked)) if revoked.saturating_add(policy.retention) <= now => {
+                garbage.push(certificate.to_garbage(GarbageReason::Revoked(serial, revoked)));
+                collected.insert(certificate.serial);
+            }
+            Some(_) => {
+                retained.insert(certificate.serial);
+            }
+            None => {}
+        }
+    }
+    // Collecting certificate orphans its children, so store is walked until nothing changes
+    loop {
+        let orphans: Vec<GarbageCertificate> = certificates.iter()
+            .filter(|certificate| !collected.contains(&certificate.serial) && !retained.contains(&certificate.serial)
+                && !protected.contains(&certificate.serial))
+            .filter_map(|certificate| {
+                let parent = certificate.parent.unwrap_or(ROOT_CERTIFICATE_SERIAL);
+                match !certificate.verified || collected.contains(&parent) {
+                    true => Some(certificate.to_garbage(GarbageReason::Orphaned(parent))),
+                    false => None,
+                }
+            })
+            .collect();
+        if orphans.is_empty(){
+            return garbage;
+        }
+        collected.extend(orphans.iter().map(|orphan| orphan.serial));
+        garbage.extend(orphans);
+    }
+}
+
+///
+/// Removes certificates found by find_garbage and commits service if any was removed.
+/// Every pruned certificate is logged and returned as audit entry.
+///
+/// # Arguments
+/// * service: &mut S: service to prune
+/// * policy: &CertificateGcPolicy: retention of expired and revoked certificates
+/// * now: u64: current time, seconds since UNIX epoch
+///
+pub fn collect_garbage<S: CertificateService + ?Sized>(service: &mut S, policy: &CertificateGcPolicy,
+                                                       now: u64) -> Vec<CertificateGcAuditEntry>{
+    let garbage = find_garbage(service, policy, now);
+    let mut audit = Vec::with_capacity(garbage.len());
+    // Children are removed before their parents
+    for certificate in garbage.into_iter().rev(){
+        let removed = match certificate.kind {
+            CertificateKind::Encryption => service.remove_encryption_certificate(certificate.serial),
+            _ => service.remove_signing_certificate(certificate.serial),
+        };
+        match removed {
+            true => log::info!("Garbage collection removed {}", certificate),
+            false => log::warn!("Garbage collection can not remove {}", certificate),
+        }
+        audit.push(CertificateGcAuditEntry{
+            timestamp: get_timestamp_with_milliseconds(),
+            certificate,
+            removed,
+        });
+    }
+    if audit.iter().any(|entry| entry.removed){
+        service.commit();
+    }
+    audit
+}
+
+///
+/// Collects garbage every interval of policy. Runs forever, so it should be spawned as a
+/// separate task.
+///
+pub async fn run_certificate_gc<S: CertificateService + ?Sized>(service: Arc<Mutex<Box<S>>>,
+                                                                policy: CertificateGcPolicy){
+    loop {
+        tokio::time::sleep(Duration::from_secs(policy.interval.max(1))).await;
+        let now = (get_timestamp_with_milliseconds() / 1000) as u64;
+        let audit = collect_garbage(service.lock().unwrap().as_mut(), &policy, now);
+        if !audit.is_empty(){
+            log::info!("Garbage collection pruned {} certificates", audit.iter().filter(|entry| entry.removed).count());
+        }
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::certsync::CertificateRevocation;
+    use crate::pki::certificate::metadata::CertificateValidity;
+    use crate::pki::hash::HashType;
+    use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+    use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+    use crate::services::impls::certificate::AsyncCertificateServiceImpl;
+    use crate::testing::certificate::test_certificates;
+
+    #[test]
+    fn test_collect_garbage() {
+        let certificates = test_certificates();
+        let now = (get_timestamp_with_milliseconds() / 1000) as u64;
+        let sign = |certificate: &mut Falcon1024Certificate, issuer: Option<&Falcon1024Certificate>| {
+            let data = certificate.clone_without_signature_and_sk();
+            certificate.signature = Some(match issuer {
+                Some(issuer) => issuer.sign_data(&data, HashType::None).unwrap(),
+                None => certificates.root.sign_data(&data, HashType::None).unwrap(),
+            });
+        };
+        // Signing certificates keep secret key, so they can sign children
+        let signing = |serial: u128, issuer: Option<&Falcon1024Certificate>, expires: Option<u64>| {
+            let mut certificate = certificates.signing.clone();
+            certificate.serial_number = serial;
+            certificate.parent_serial_number = issuer.map(|issuer| issuer.serial_number).unwrap_or(ROOT_CERTIFICATE_SERIAL);
+            certificate.metadata.set_validity(CertificateValidity{ issued_at: 0, expires_at: expires });
+            sign(&mut certificate, issuer);
+            certificate
+        };
+        let encryption = |serial: u128, issuer: &Falcon1024Certificate| {
+            let mut certificate: Kyber1024Certificate = certificates.encryption.clone_without_sk();
+            certificate.serial_number = serial;
+            certificate.parent_serial_number = issuer.serial_number;
+            certificate.signature = Some(issuer.sign_data(&certificate.clone_without_signature_and_sk(),
+                                                          HashType::None).unwrap());
+            certificate
+        };
+        let file = std::env::temp_dir().join(format!("milkyway-gc-{}.dat", rand::random::<u64>()));
+        let file = file.to_str().unwrap().to_string();
+        let mut service = AsyncCertificateServiceImpl::new(&file);
+        service.set_root_certificate(certificates.root.clone_without_sk());
+        let expired = signing(1, None, Some(now - 100));
+        assert!(service.add_signing_certificate(expired.clone_without_sk()));
+        assert!(service.add_signing_certificate(signing(2, Some(&expired), None).clone_without_sk()));
+        assert!(service.add_signing_certificate(signing(3, None, None).clone_without_sk()));
+        let revoked = signing(4, None, None);
+        assert!(service.add_signing_certificate(revoked.clone_without_sk()));
+        assert!(service.add_encryption_certificate(encryption(6, &revoked)));
+        assert!(service.add_revocation(CertificateRevocation::new(4, &certificates.root).unwrap()));
+        // Certificate of this host is kept even when expired, so is certificate signed by it
+        let own = signing(7, None, Some(now - 100));
+        assert!(service.add_signing_certificate(own.clone()));
+        assert!(service.add_encryption_certificate(encryption(8, &own)));
+        let removed = signing(10, None, None);
+        assert!(service.add_signing_certificate(removed.clone_without_sk()));
+        assert!(service.add_encryption_certificate(encryption(9, &removed)));
+        assert!(service.remove_signing_certificate(10));
+
+        let policy = CertificateGcPolicy{ retention: 50, interval: DEFAULT_GC_INTERVAL };
+        let garbage = find_garbage(&mut service, &policy, now);
+        assert_eq!(garbage.iter().map(|garbage| (garbage.serial, garbage.reason)).collect::<Vec<_>>(),
+                   vec![(1, GarbageReason::Expired(now - 100)), (2, GarbageReason::Orphaned(1)),
+                        (9, GarbageReason::Orphaned(10))]);
+        // Revocation is past retention later
+        let garbage = find_garbage(&mut service, &policy, now + 100);
+        let revoked_at = match garbage.iter().find(|garbage| garbage.serial == 6).unwrap().reason {
+            GarbageReason::Revoked(4, revoked_at) => revoked_at,
+            reason => panic!("Unexpected reason {}", reason),
+        };
+        assert!(revoked_at >= now && revoked_at <= now + 50);
+        assert_eq!(service.get_signing_certificates().len(), 4);
+
+        let audit = collect_garbage(&mut service, &policy, now + 100);
+        assert_eq!(audit.iter().map(|entry| entry.certificate.serial).collect::<Vec<_>>(), vec![9, 2, 6, 1]);
+        assert!(audit.iter().all(|entry| entry.removed));
+        let mut remaining: Vec<u128> = service.get_signing_certificates().iter()
+            .map(|certificate| certificate.serial_number)
+            .chain(service.get_encryption_certificates().iter().map(|certificate| certificate.serial_number))
+            .collect();
+        remaining.sort();
+        assert_eq!(remaining, vec![3, 7, 8]);
+        let _ = std::fs::remove_file(file);
+    }
+}
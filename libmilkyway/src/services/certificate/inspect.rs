@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use sha2::{Digest, Sha256};
+use crate::message::certsync::CertificateRevocation;
+use crate::pki::certificate::Certificate;
+use crate::pki::impls::certificates::falcon1024::{Falcon1024Certificate, Falcon1024RootCertificate};
+use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::migration::{backup_file, decode_versioned, encode_versioned, load_versioned, replace_file,
+                                      MigrationError, VersionedStorage};
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::services::certificate::chain::{CertificateChain, ChainVerificationError};
+use crate::services::certificate::policy::CertificatePolicy;
+use crate::services::certificate::usage::{KeyUsage, UsageThresholds};
+use crate::services::impls::certificate::AsyncCertificateServiceImpl;
+
+///
+/// Schema version since which store has key usage counters
+///
+const USAGE_SCHEMA_VERSION: u32 = 2;
+
+///
+/// Schema version since which store has certificate policy
+///
+const POLICY_SCHEMA_VERSION: u32 = 3;
+
+///
+/// Schema version since which store has certificate revocations
+///
+const REVOCATION_SCHEMA_VERSION: u32 = 4;
+
+///
+/// Kind of certificate found in store
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StoreEntryKind{
+    Root,
+    Signing,
+    Encryption,
+}
+
+impl StoreEntryKind {
+    pub fn get_name(&self) -> &'static str{
+        match self {
+            StoreEntryKind::Root => "root",
+            StoreEntryKind::Signing => "signing",
+            StoreEntryKind::Encryption => "encryption",
+        }
+    }
+}
+
+///
+/// Certificate which could be read from store
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoreEntry{
+    pub kind: StoreEntryKind,
+    pub serial: u128,
+    pub name: String,
+    /** Size of serialized certificate in bytes **/
+    pub size: usize,
+    pub has_secret_key: bool,
+    /** Why chain of certificate does not verify against readable certificates, None if it does **/
+    pub chain_error: Option<ChainVerificationError>,
+}
+
+///
+/// Report of parsing certificate store file without loading certificate service
+///
+pub struct StoreInspection{
+    pub file_size: usize,
+    /** Hex-encoded SHA-256 of whole file **/
+    pub checksum: String,
+    pub schema_version: u32,
+    pub entries: Vec<StoreEntry>,
+    pub usage_records: usize,
+    pub policy_rules: usize,
+    pub revocations: usize,
+    /** Problems met while parsing, empty if every section is readable **/
+    pub errors: Vec<String>,
+    /** Bytes after the last section **/
+    pub trailing_bytes: usize,
+    root: Option<Falcon1024RootCertificate>,
+    signing: HashMap<u128, Falcon1024Certificate>,
+    encryption: HashMap<u128, Kyber1024Certificate>,
+    usage: HashMap<u128, KeyUsage>,
+    thresholds: UsageThresholds,
+    policy: CertificatePolicy,
+    revoked: HashMap<u128, CertificateRevocation>,
+}
+
+///
+/// Report of converting certificate store to current schema version
+///
+pub struct StoreUpgrade{
+    pub from_version: u32,
+    pub to_version: u32,
+    /** Certificates written to upgraded store **/
+    pub entries: Vec<StoreEntry>,
+    pub usage_records: usize,
+    /** Copy of store before it was replaced, None if store was written elsewhere or not at all **/
+    pub backup: Option<PathBuf>,
+}
+
+impl StoreUpgrade {
+    #[inline]
+    pub fn is_up_to_date(&self) -> bool{
+        self.from_version == self.to_version
+    }
+}
+
+///
+/// Reads values one after another, remembering how far data is readable
+///
+struct SectionReader{
+    data: Vec<u8>,
+    offset: usize,
+}
+
+impl SectionReader {
+    fn read<T: Deserializable>(&mut self) -> Option<(T, usize)>{
+        let (value, size) = T::from_serialized(&self.data[self.offset..].to_vec()).ok()?;
+        self.offset += size;
+        Some((value, size))
+    }
+
+    ///
+    /// Reads map serialized as vector of keys followed by vector of values. Values before
+    /// a damaged one are kept.
+    ///
+    /// returns: (Vec<(T, usize)>, bool): values with their sizes and whether section is
+    /// readable to its end
+    ///
+    fn read_map_values<T: Deserializable>(&mut self, section: &str, errors: &mut Vec<String>) -> (Vec<(T, usize)>, bool){
+        let keys = match self.read::<Vec<u128>>() {
+            Some((keys, _)) => keys,
+            None => {
+                errors.push(format!("serials of {} are damaged", section));
+                return (Vec::new(), false);
+            }
+        };
+        let count = match self.read::<usize>() {
+            Some((count, _)) => count,
+            None => {
+                errors.push(format!("count of {} is damaged", section));
+                return (Vec::new(), false);
+            }
+        };
+        if count != keys.len(){
+            errors.push(format!("{} has {} serials, but {} entries", section, keys.len(), count));
+        }
+        let mut values = Vec::new();
+        for index in 0..count{
+            match self.read::<T>() {
+                Some(value) => values.push(value),
+                None => {
+                    errors.push(format!("{} {} of {} and later ones are damaged", section, index + 1, count));
+                    return (values, false);
+                }
+            }
+        }
+        (values, true)
+    }
+}
+
+///
+/// Parses certificate store file section by section, keeping everything readable before
+/// the first damaged value of each section
+///
+/// # Arguments
+/// * path: &Path: path to store, e.g. certs.dat
+///
+/// returns: Result<StoreInspection, MigrationError>: report or error if file can not be read or
+/// is written by newer version
+///
+pub fn inspect_certificate_store(path: &Path) -> Result<StoreInspection, MigrationError>{
+    let data = std::fs::read(path).map_err(|error| MigrationError::Io(path.to_path_buf(), error.to_string()))?;
+    let store = AsyncCertificateServiceImpl::STORE_NAME;
+    let supported = AsyncCertificateServiceImpl::SCHEMA_VERSION;
+    let mut inspection = StoreInspection{
+        file_size: data.len(),
+        checksum: Sha256::digest(&data).iter().map(|byte| format!("{:02x}", byte)).collect(),
+        schema_version: 0,
+        entries: Vec::new(),
+        usage_records: 0,
+        policy_rules: 0,
+        revocations: 0,
+        errors: Vec::new(),
+        trailing_bytes: 0,
+        root: None,
+        signing: HashMap::new(),
+        encryption: HashMap::new(),
+        usage: HashMap::new(),
+        thresholds: UsageThresholds::default(),
+        policy: CertificatePolicy::default(),
+        revoked: HashMap::new(),
+    };
+    let (version, payload) = match decode_versioned(data) {
+        Ok(decoded) => decoded,
+        Err(_) => {
+            inspection.errors.push("header is damaged".to_string());
+            return Ok(inspection);
+        }
+    };
+    if version > supported{
+        return Err(MigrationError::NewerVersion{ store, found: version, supported });
+    }
+    inspection.schema_version = version;
+    let mut reader = SectionReader{ data: payload, offset: 0 };
+    inspection.parse(&mut reader);
+    inspection.trailing_bytes = reader.data.len() - reader.offset;
+    inspection.verify_chains();
+    Ok(inspection)
+}
+
+impl StoreInspection {
+    fn parse(&mut self, reader: &mut SectionReader){
+        if reader.read::<String>().is_none(){
+            self.errors.push("file name is damaged, no certificates can be read".to_string());
+            return;
+        }
+        match reader.read::<Option<Falcon1024RootCertificate>>() {
+            Some((root, size)) => {
+                if let Some(root) = &root{
+                    self.entries.push(StoreEntry{
+                        kind: StoreEntryKind::Root,
+                        serial: root.get_serial(),
+                        name: root.get_name(),
+                        size,
+                        has_secret_key: root.get_secret_key().is_some(),
+                        chain_error: None,
+                    });
+                }
+                self.root = root;
+            }
+            None => {
+                self.errors.push("root certificate is damaged, no certificates after it can be read".to_string());
+                return;
+            }
+        }
+        let (signing, complete) = reader.read_map_values::<Falcon1024Certificate>("signing certificates",
+                                                                                 &mut self.errors);
+        for (certificate, size) in signing{
+            self.entries.push(StoreEntry{
+                kind: StoreEntryKind::Signing,
+                serial: certificate.get_serial(),
+                name: certificate.get_name(),
+                size,
+                has_secret_key: certificate.get_secret_key().is_some(),
+                chain_error: None,
+            });
+            self.signing.insert(certificate.get_serial(), certificate);
+        }
+        if !complete{
+            return;
+        }
+        let (encryption, complete) = reader.read_map_values::<Kyber1024Certificate>("encryption certificates",
+                                                                                   &mut self.errors);
+        for (certificate, size) in encryption{
+            self.entries.push(StoreEntry{
+                kind: StoreEntryKind::Encryption,
+                serial: certificate.get_serial(),
+                name: certificate.get_name(),
+                size,
+                has_secret_key: certificate.get_secret_key().is_some(),
+                chain_error: None,
+            });
+            self.encryption.insert(certificate.get_serial(), certificate);
+        }
+        if !complete || self.schema_version < USAGE_SCHEMA_VERSION{
+            return;
+        }
+        match reader.read::<HashMap<u128, KeyUsage>>() {
+            Some((usage, _)) => {
+                self.usage_records = usage.len();
+                self.usage = usage;
+            }
+            None => {
+                self.errors.push("key usage counters are damaged".to_string());
+                return;
+            }
+        }
+        match reader.read::<UsageThresholds>() {
+            Some((thresholds, _)) => self.thresholds = thresholds,
+            None => {
+                self.errors.push("key usage thresholds are damaged".to_string());
+                return;
+            }
+        }
+        if self.schema_version < POLICY_SCHEMA_VERSION{
+            return;
+        }
+        match reader.read::<CertificatePolicy>() {
+            Some((policy, _)) => {
+                self.policy_rules = policy.rules.len();
+                self.policy = policy;
+            }
+            None => {
+                self.errors.push("certificate policy is damaged".to_string());
+                return;
+            }
+        }
+        if self.schema_version < REVOCATION_SCHEMA_VERSION{
+            return;
+        }
+        match reader.read::<HashMap<u128, CertificateRevocation>>() {
+            Some((revoked, _)) => {
+                self.revocations = revoked.len();
+                self.revoked = revoked;
+            }
+            None => self.errors.push("certificate revocations are damaged".to_string()),
+        }
+    }
+
+    fn verify_chains(&mut self){
+        let mut chain = CertificateChain::new();
+        if let Some(root) = &self.root{
+            chain.set_root_certificate(root.clone());
+        }
+        for certificate in self.signing.values(){
+            chain.add_signing_certificate(certificate.clone());
+        }
+        for entry in self.entries.iter_mut(){
+            entry.chain_error = match entry.kind {
+                StoreEntryKind::Root => None,
+                StoreEntryKind::Signing => chain.verify_signing_certificate(&self.signing[&entry.serial]).err(),
+                StoreEntryKind::Encryption => chain.verify_encryption_certificate(&self.encryption[&entry.serial]).err(),
+            };
+        }
+    }
+
+    ///
+    /// Checks that every section is readable and nothing follows them
+    ///
+    #[inline]
+    pub fn is_intact(&self) -> bool{
+        self.errors.is_empty() && self.trailing_bytes == 0
+    }
+
+    ///
+    /// Gets count of readable certificates of kind
+    ///
+    pub fn get_count(&self, kind: StoreEntryKind) -> usize{
+        self.entries.iter().filter(|entry| entry.kind == kind).count()
+    }
+
+    ///
+    /// Writes readable certificates and usage counters to a new store of current schema version
+    ///
+    /// # Arguments
+    /// * file_name: &str: file to write, it should not be the damaged store itself
+    ///
+    /// returns: std::io::Result<usize>: size of written file
+    ///
+    pub fn write_repaired(&self, file_name: &str) -> std::io::Result<usize>{
+        let data = self.encode(file_name);
+        std::fs::write(file_name, &data)?;
+        Ok(data.len())
+    }
+
+    // Readable contents in layout of AsyncCertificateServiceImpl of current schema version
+    fn encode(&self, file_name: &str) -> Serialized{
+        let mut payload = file_name.to_string().serialize();
+        payload.extend(self.root.serialize());
+        payload.extend(self.signing.serialize());
+        payload.extend(self.encryption.serialize());
+        payload.extend(self.usage.serialize());
+        payload.extend(self.thresholds.serialize());
+        payload.extend(self.policy.serialize());
+        payload.extend(self.revoked.serialize());
+        encode_versioned(AsyncCertificateServiceImpl::SCHEMA_VERSION, &payload)
+    }
+}
+
+///
+/// Converts certificate store of older schema, including legacy files without version header,
+/// to current schema version. Unlike implicit migration it reports every converted certificate
+/// and refuses damaged stores, which must be salvaged with write_repaired instead.
+///
+/// # Arguments
+/// * path: &Path: store to convert
+/// * output: Option<&Path>: file to write converted store to, store is replaced in place with
+///   backup `<file>.v<version>.bak` if None
+/// * dry_run: bool: only report what would be converted
+///
+/// returns: Result<StoreUpgrade, MigrationError>: report or error, original store is kept on error
+///
+pub fn upgrade_certificate_store(path: &Path, output: Option<&Path>,
+                                 dry_run: bool) -> Result<StoreUpgrade, MigrationError>{
+    let store = AsyncCertificateServiceImpl::STORE_NAME;
+    let inspection = inspect_certificate_store(path)?;
+    if !inspection.is_intact(){
+        return Err(MigrationError::ValidationFailed{ store });
+    }
+    let mut upgrade = StoreUpgrade{
+        from_version: inspection.schema_version,
+        to_version: AsyncCertificateServiceImpl::SCHEMA_VERSION,
+        entries: inspection.entries.clone(),
+        usage_records: inspection.usage_records,
+        backup: None,
+    };
+    let target = output.unwrap_or(path);
+    if dry_run || (target == path && upgrade.is_up_to_date()){
+        return Ok(upgrade);
+    }
+    let data = inspection.encode(&target.to_string_lossy());
+    if target == path{
+        upgrade.backup = Some(backup_file(path, upgrade.from_version)?);
+    }
+    replace_file(target, &data)?;
+    if load_versioned::<AsyncCertificateServiceImpl>(target).is_err(){
+        if let Some(backup) = &upgrade.backup{
+            std::fs::copy(backup, path).map_err(|error| MigrationError::Io(path.to_path_buf(), error.to_string()))?;
+        }
+        return Err(MigrationError::ValidationFailed{ store });
+    }
+    Ok(upgrade)
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::certificate::CertificateService;
+    use crate::testing::certificate::test_certificates;
+
+    #[test]
+    fn test_inspect_certificate_store() {
+        let certificates = test_certificates();
+        let directory = std::env::temp_dir();
+        let file = directory.join(format!("milkyway-inspect-{}.dat", rand::random::<u64>()));
+        let mut service = AsyncCertificateServiceImpl::new(file.to_str().unwrap());
+        service.set_root_certificate(certificates.root.clone_without_sk());
+        assert!(service.add_signing_certificate(certificates.signing.clone()));
+        assert!(service.add_encryption_certificate(certificates.encryption.clone()));
+        service.commit();
+
+        let inspection = inspect_certificate_store(&file).unwrap();
+        assert!(inspection.is_intact());
+        assert_eq!(inspection.schema_version, AsyncCertificateServiceImpl::SCHEMA_VERSION);
+        assert_eq!(inspection.checksum.len(), 64);
+        assert_eq!(inspection.entries.len(), 3);
+        assert_eq!(inspection.get_count(StoreEntryKind::Signing), 1);
+        assert!(inspection.entries.iter().all(|entry| entry.chain_error.is_none()));
+        assert!(!inspection.entries[0].has_secret_key);
+        assert!(inspection.entries[1].has_secret_key);
+
+        // Store cut in the middle of encryption certificate keeps signing one
+        let data = std::fs::read(&file).unwrap();
+        std::fs::write(&file, &data[..data.len() - 200]).unwrap();
+        let inspection = inspect_certificate_store(&file).unwrap();
+        assert!(!inspection.is_intact());
+        assert_eq!(inspection.errors, vec!["encryption certificates 1 of 1 and later ones are damaged".to_string()]);
+        assert_eq!(inspection.get_count(StoreEntryKind::Encryption), 0);
+        assert_eq!(inspection.get_count(StoreEntryKind::Signing), 1);
+
+        let repaired = directory.join(format!("milkyway-repaired-{}.dat", rand::random::<u64>()));
+        inspection.write_repaired(repaired.to_str().unwrap()).unwrap();
+        let mut service = AsyncCertificateServiceImpl::load_from_file(repaired.to_str().unwrap());
+        assert!(service.get_signing_certificate(certificates.signing.get_serial()).is_some());
+        assert!(service.get_encryption_certificate(certificates.encryption.get_serial()).is_none());
+        assert!(inspect_certificate_store(&repaired).unwrap().is_intact());
+
+        std::fs::write(&file, [data.clone(), vec![0]].concat()).unwrap();
+        assert_eq!(inspect_certificate_store(&file).unwrap().trailing_bytes, 1);
+        std::fs::remove_file(file).unwrap();
+        std::fs::remove_file(repaired).unwrap();
+    }
+
+    #[test]
+    fn test_upgrade_legacy_store() {
+        let certificates = test_certificates();
+        let file = std::env::temp_dir().join(format!("milkyway-legacy-{}.dat", rand::random::<u64>()));
+        let mut service = AsyncCertificateServiceImpl::new(file.to_str().unwrap());
+        service.set_root_certificate(certificates.root.clone_without_sk());
+        assert!(service.add_signing_certificate(certificates.signing.clone()));
+        assert!(service.add_encryption_certificate(certificates.encryption.clone()));
+        service.commit();
+        // Legacy store is raw payload without header and usage counters
+        let (_, payload) = decode_versioned(std::fs::read(&file).unwrap()).unwrap();
+        let usage_size = service.get_key_usage().into_iter().map(|usage| (usage.serial, usage))
+            .collect::<HashMap<u128, KeyUsage>>().serialize().len() + UsageThresholds::default().serialize().len()
+            + CertificatePolicy::default().serialize().len()
+            + HashMap::<u128, CertificateRevocation>::new().serialize().len();
+        let legacy = payload[..payload.len() - usage_size].to_vec();
+        std::fs::write(&file, &legacy).unwrap();
+        assert!(matches!(load_versioned::<AsyncCertificateServiceImpl>(&file), Err(MigrationError::Outdated{ found: 0, .. })));
+
+        let preview = upgrade_certificate_store(&file, None, true).unwrap();
+        assert_eq!((preview.from_version, preview.entries.len(), preview.backup), (0, 3, None));
+        assert_eq!(std::fs::read(&file).unwrap(), legacy);
+
+        let upgrade = upgrade_certificate_store(&file, None, false).unwrap();
+        let backup = upgrade.backup.clone().unwrap();
+        assert_eq!(std::fs::read(&backup).unwrap(), legacy);
+        let mut service = load_versioned::<AsyncCertificateServiceImpl>(&file).unwrap();
+        assert_eq!(service.get_signing_certificates().len(), 1);
+        assert!(service.get_encryption_certificate(certificates.encryption.get_serial()).is_some());
+        assert!(upgrade_certificate_store(&file, None, false).unwrap().is_up_to_date());
+
+        std::fs::write(&file, &legacy[..legacy.len() - 10]).unwrap();
+        assert!(matches!(upgrade_certificate_store(&file, None, false), Err(MigrationError::ValidationFailed{ .. })));
+        std::fs::remove_file(file).unwrap();
+        std::fs::remove_file(backup).unwrap();
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use libmilkyway_derive::{Deserializable, Serializable};
+use aes_gcm::Aes256Gcm;
+use pqcrypto::kem::kyber1024::SecretKey as Kyber1024SecretKey;
+use crate::pki::impls::certificates::falcon1024::{Falcon1024Certificate, Falcon1024RootCertificate};
+use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use crate::pki::impls::keys::falcon1024::Falcon1024SecretKey;
+use crate::pki::kdf::derive_passphrase_key;
+use crate::pki::key::CryptoKey;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+
+///
+/// Marker at the beginning of secret key store
+///
+pub const KEY_STORE_MAGIC: [u8; 8] = *b"MWAYKSTR";
+
+///
+/// Version of format of secret key store written after magic. MUST be bumped whenever
+/// KeyStoreFile or SecretKeyStore changes.
+///
+pub const KEY_STORE_VERSION: u16 = 1;
+
+///
+/// Environment variable with passphrase of secret key store. Store is kept unencrypted
+/// if it is not set, so only permissions of file protect it; every write of unencrypted
+/// store is logged as warning.
+///
+pub const KEY_STORE_PASSPHRASE_VARIABLE: &str = "MWAY_KEYSTORE_PASSPHRASE";
+
+const SALT_LENGTH: usize = 16;
+
+///
+/// Errors of reading and writing secret key store
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeyStoreError{
+    /** File is not a secret key store or is truncated **/
+    Malformed,
+    /** File was written by newer version **/
+    UnsupportedVersion(u16),
+    /** Store is encrypted, but no passphrase is given **/
+    PassphraseRequired,
+    /** Wrong passphrase or file was tampered **/
+    DecryptionFailed,
+    /** Key store already exists and would be overwritten **/
+    AlreadyExists(PathBuf),
+    Io(String),
+}
+
+impl Display for KeyStoreError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyStoreError::Malformed => write!(f, "file is not a secret key store"),
+            KeyStoreError::UnsupportedVersion(version) =>
+                write!(f, "version {} of key store is not supported, latest is {}", version, KEY_STORE_VERSION),
+            KeyStoreError::PassphraseRequired =>
+                write!(f, "key store is encrypted, set {} to open it", KEY_STORE_PASSPHRASE_VARIABLE),
+            KeyStoreError::DecryptionFailed => write!(f, "wrong passphrase or key store is damaged"),
+            KeyStoreError::AlreadyExists(path) => write!(f, "key store {} already exists", path.display()),
+            KeyStoreError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+///
+/// What is written after header: keys are encrypted with AES-256-GCM under key derived from
+/// passphrase with PBKDF2-HMAC-SHA256, zero iterations mean keys are not encrypted
+///
+#[derive(Clone, Serializable, Deserializable)]
+struct KeyStoreFile{
+    iterations: u32,
+    salt: Vec<u8>,
+    payload: Vec<u8>,
+}
+
+///
+/// Passphrase of key store with key derived from it. PBKDF2 is slow by design, so key is
+/// derived once per salt and reused for every later write instead of on each commit.
+///
+#[derive(Clone)]
+pub struct KeyStoreKey{
+    passphrase: String,
+    iterations: u32,
+    /** Salt of last read or written store and key derived for it **/
+    derived: Option<(Vec<u8>, aes_gcm::Key<Aes256Gcm>)>,
+}
+
+impl KeyStoreKey {
+    ///
+    /// Creates key, derivation is deferred until store is read or written
+    ///
+    /// # Arguments
+    /// * passphrase: &str: passphrase store is encrypted with
+    /// * iterations: u32: PBKDF2 iterations of new stores, see secrets::DEFAULT_KDF_ITERATIONS
+    ///
+    pub fn new(passphrase: &str, iterations: u32) -> KeyStoreKey{
+        KeyStoreKey{
+            passphrase: passphrase.to_string(),
+            iterations: iterations.max(1),
+            derived: None,
+        }
+    }
+
+    // Gets key for salt and iterations of existing store, deriving it only if they changed
+    fn get_for(&mut self, salt: &[u8], iterations: u32) -> aes_gcm::Key<Aes256Gcm>{
+        match &self.derived {
+            Some((derived_salt, key)) if derived_salt == salt && self.iterations == iterations => *key,
+            _ => {
+                let key = derive_passphrase_key(&self.passphrase, salt, iterations);
+                self.iterations = iterations;
+                self.derived = Some((salt.to_vec(), key));
+                key
+            }
+        }
+    }
+
+    // Gets salt, iterations and key to write store with, new salt is generated on first write
+    fn get_for_writing(&mut self) -> (Vec<u8>, u32, aes_gcm::Key<Aes256Gcm>){
+        let salt = match &self.derived {
+            Some((salt, _)) => salt.clone(),
+            None => rand::random::<[u8; SALT_LENGTH]>().to_vec(),
+        };
+        let key = self.get_for(&salt, self.iterations);
+        (salt, self.iterations, key)
+    }
+}
+
+///
+/// Secret keys of certificates kept apart from public certificate store, so the latter may be
+/// backed up and shared freely. Keys are matched with certificates by serial.
+///
+#[derive(Clone, Default, PartialEq, Serializable, Deserializable)]
+pub struct SecretKeyStore{
+    pub root: Option<Falcon1024SecretKey>,
+    pub signing: HashMap<u128, Falcon1024SecretKey>,
+    pub encryption: HashMap<u128, Kyber1024SecretKey>,
+}
+
+impl SecretKeyStore {
+    ///
+    /// Moves secret keys out of certificates, which keep their public parts only
+    ///
+    pub fn take_from(root: &mut Option<Falcon1024RootCertificate>, signing: &mut HashMap<u128, Falcon1024Certificate>,
+                     encryption: &mut HashMap<u128, Kyber1024Certificate>) -> SecretKeyStore{
+        SecretKeyStore{
+            root: root.as_mut().and_then(|root| root.secret_key.take()),
+            signing: signing.iter_mut()
+                .filter_map(|(serial, certificate)| certificate.secret_key.take().map(|key| (*serial, key)))
+                .collect(),
+            encryption: encryption.iter_mut()
+                .filter_map(|(serial, certificate)| certificate.secret_key.take().map(|key| (*serial, key)))
+                .collect(),
+        }
+    }
+
+    ///
+    /// Puts secret keys back to certificates with the same serials
+    ///
+    /// returns: usize: number of keys whose certificates are missing, such keys are dropped
+    ///
+    pub fn join_into(self, root: &mut Option<Falcon1024RootCertificate>, signing: &mut HashMap<u128, Falcon1024Certificate>,
+                     encryption: &mut HashMap<u128, Kyber1024Certificate>) -> usize{
+        let mut unmatched = 0;
+        match (self.root, root.as_mut()) {
+            (Some(key), Some(root)) => root.secret_key = Some(key),
+            (Some(_), None) => unmatched += 1,
+            _ => {}
+        }
+        for (serial, key) in self.signing{
+            match signing.get_mut(&serial) {
+                Some(certificate) => certificate.secret_key = Some(key),
+                None => unmatched += 1,
+            }
+        }
+        for (serial, key) in self.encryption{
+            match encryption.get_mut(&serial) {
+                Some(certificate) => certificate.secret_key = Some(key),
+                None => unmatched += 1,
+            }
+        }
+        unmatched
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize{
+        self.root.iter().count() + self.signing.len() + self.encryption.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool{
+        self.len() == 0
+    }
+
+    ///
+    /// Encodes store prefixed with KEY_STORE_MAGIC and KEY_STORE_VERSION
+    ///
+    /// # Arguments
+    /// * key: Option<&mut KeyStoreKey>: key keys are encrypted with, None to keep them unencrypted
+    ///
+    pub fn to_bytes(&self, key: Option<&mut KeyStoreKey>) -> Serialized{
+        let file = match key {
+            Some(key) => {
+                let (salt, iterations, key) = key.get_for_writing();
+                let payload = key.encrypt_raw(&self.serialize()).expect("AES-256-GCM encryption does not fail");
+                KeyStoreFile{ iterations, salt, payload }
+            }
+            None => KeyStoreFile{ iterations: 0, salt: Vec::new(), payload: self.serialize() },
+        };
+        let mut data = KEY_STORE_MAGIC.to_vec();
+        data.extend(KEY_STORE_VERSION.serialize());
+        data.extend(file.serialize());
+        data
+    }
+
+    ///
+    /// Parses contents of key store file, rejecting unknown formats and trailing data
+    ///
+    /// # Arguments
+    /// * data: &[u8]: contents of file
+    /// * key: Option<&mut KeyStoreKey>: key store was encrypted with, ignored if it is not encrypted.
+    ///   Key derived for salt of store is kept in it, so writing store back does not derive it again
+    ///
+    pub fn from_bytes(data: &[u8], key: Option<&mut KeyStoreKey>) -> Result<SecretKeyStore, KeyStoreError>{
+        let data = data.strip_prefix(&KEY_STORE_MAGIC).ok_or(KeyStoreError::Malformed)?.to_vec();
+        let (version, offset) = u16::from_serialized(&data).map_err(|_| KeyStoreError::Malformed)?;
+        if version != KEY_STORE_VERSION{
+            return Err(KeyStoreError::UnsupportedVersion(version));
+        }
+        let (file, size) = KeyStoreFile::from_serialized(&data[offset..].to_vec())
+            .map_err(|_| KeyStoreError::Malformed)?;
+        if offset + size != data.len(){
+            return Err(KeyStoreError::Malformed);
+        }
+        let payload = match file.iterations {
+            0 => file.payload,
+            _ if file.salt.len() != SALT_LENGTH => return Err(KeyStoreError::Malformed),
+            iterations => {
+                let key = key.ok_or(KeyStoreError::PassphraseRequired)?;
+                key.get_for(&file.salt, iterations).decrypt_raw(&file.payload)
+                    .map_err(|_| KeyStoreError::DecryptionFailed)?
+            }
+        };
+        match SecretKeyStore::from_serialized(&payload) {
+            Ok((store, size)) if size == payload.len() => Ok(store),
+            _ => Err(KeyStoreError::Malformed),
+        }
+    }
+
+    ///
+    /// Atomically writes store to file readable by owner only: data is written to `<file>.tmp`
+    /// created with mode 0600 on unix, which is renamed then. Writing unencrypted store is logged
+    /// as warning.
+    ///
+    pub fn dump_to_file(&self, path: &Path, key: Option<&mut KeyStoreKey>) -> Result<(), KeyStoreError>{
+        if key.is_none(){
+            log::warn!("Secret keys are written to {} unencrypted, set {} to encrypt them", path.display(),
+                       KEY_STORE_PASSPHRASE_VARIABLE);
+        }
+        let mut temporary = path.to_path_buf().into_os_string();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+        let _ = std::fs::remove_file(&temporary);
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&temporary).map_err(|error| KeyStoreError::Io(error.to_string()))?;
+        file.write_all(&self.to_bytes(key)).and_then(|_| file.sync_all())
+            .map_err(|error| KeyStoreError::Io(error.to_string()))?;
+        std::fs::rename(&temporary, path).map_err(|error| KeyStoreError::Io(error.to_string()))
+    }
+
+    pub fn read_from_file(path: &Path, key: Option<&mut KeyStoreKey>) -> Result<SecretKeyStore, KeyStoreError>{
+        let data = std::fs::read(path).map_err(|error| KeyStoreError::Io(error.to_string()))?;
+        Self::from_bytes(&data, key)
+    }
+}
+
+///
+/// Gets passphrase of key store from KEY_STORE_PASSPHRASE_VARIABLE
+///
+pub fn get_key_store_passphrase() -> Option<String>{
+    std::env::var(KEY_STORE_PASSPHRASE_VARIABLE).ok().filter(|passphrase| !passphrase.is_empty())
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use crate::testing::certificate::test_certificates;
+
+    #[test]
+    fn test_key_store() {
+        let certificates = test_certificates();
+        let mut root = Some(certificates.root.clone());
+        let mut signing = HashMap::from([(certificates.signing.serial_number, certificates.signing.clone())]);
+        let mut encryption = HashMap::from([(certificates.encryption.serial_number, certificates.encryption.clone())]);
+        let keys = SecretKeyStore::take_from(&mut root, &mut signing, &mut encryption);
+        assert_eq!(keys.len(), 3);
+        assert!(root.as_ref().unwrap().secret_key.is_none());
+        assert!(signing.values().all(|certificate| certificate.secret_key.is_none()));
+        assert!(encryption.values().all(|certificate| certificate.secret_key.is_none()));
+
+        let file = std::env::temp_dir().join(format!("milkyway-keys-{}.dat", rand::random::<u64>()));
+        let mut key = KeyStoreKey::new("correct horse", 10);
+        keys.dump_to_file(&file, Some(&mut key)).unwrap();
+        assert_eq!(std::fs::metadata(&file).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(SecretKeyStore::read_from_file(&file, None).err(), Some(KeyStoreError::PassphraseRequired));
+        assert_eq!(SecretKeyStore::read_from_file(&file, Some(&mut KeyStoreKey::new("wrong", 10))).err(),
+                   Some(KeyStoreError::DecryptionFailed));
+        let mut reader = KeyStoreKey::new("correct horse", 1);
+        let loaded = SecretKeyStore::read_from_file(&file, Some(&mut reader)).unwrap();
+        std::fs::remove_file(file).unwrap();
+        assert!(loaded == keys);
+        assert!(SecretKeyStore::from_bytes(&keys.to_bytes(None), None).unwrap() == keys);
+
+        // Key derived once is reused: salt and iterations of store read are kept for writing
+        let derived = key.derived.clone().unwrap();
+        assert_eq!(reader.iterations, 10);
+        assert_eq!(reader.derived.as_ref().unwrap().0, derived.0);
+        let rewritten = keys.to_bytes(Some(&mut key));
+        assert_eq!(key.derived.as_ref().unwrap().0, derived.0);
+        assert!(SecretKeyStore::from_bytes(&rewritten, Some(&mut reader)).unwrap() == keys);
+
+        signing.clear();
+        assert_eq!(loaded.join_into(&mut root, &mut signing, &mut encryption), 1);
+        assert!(root.unwrap() == certificates.root);
+        assert!(encryption.values().next().unwrap() == &certificates.encryption);
+    }
+}
@@ -0,0 +1,114 @@
+use libmilkyway_derive::{Describe, Deserializable, Serializable};
+use crate::pki::certificate::Certificate;
+use crate::pki::certificate::metadata::CertificateMetadata;
+use crate::pki::key::CryptoKey;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+use crate::serialization::serializable::{Serializable, Serialized};
+
+///
+/// Default number of certificates in a page of listing
+///
+pub const DEFAULT_CERTIFICATE_PAGE_SIZE: u32 = 50;
+
+///
+/// Maximal number of certificates in a page of listing, larger limits are clamped to it, so
+/// a single request can not make service serialize the whole store
+///
+pub const MAX_CERTIFICATE_PAGE_SIZE: u32 = 500;
+
+///
+/// Public information about certificate without its keys, used to list certificates
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable, Describe)]
+pub struct CertificateSummary{
+    pub serial: u128,
+    pub name: String,
+    pub flags: u128,
+    pub parent_serial: Option<u128>,
+    pub metadata: CertificateMetadata,
+    /** Whether service holds secret key of certificate, the key itself is never listed **/
+    pub has_secret_key: bool,
+}
+
+impl CertificateSummary {
+    ///
+    /// Creates summary of certificate
+    ///
+    pub fn of<PK: CryptoKey, SK: CryptoKey, C: Certificate<PK, SK>>(certificate: &C) -> CertificateSummary{
+        CertificateSummary{
+            serial: certificate.get_serial(),
+            name: certificate.get_name(),
+            flags: certificate.get_flags(),
+            parent_serial: certificate.get_parent_serial(),
+            metadata: certificate.get_metadata(),
+            has_secret_key: certificate.get_secret_key().is_some(),
+        }
+    }
+}
+
+///
+/// A page of certificate listing
+///
+#[derive(Clone, Debug, Default, PartialEq, Serializable, Deserializable, Describe)]
+pub struct CertificatePage{
+    /** Summaries sorted by serial **/
+    pub certificates: Vec<CertificateSummary>,
+    /** Cursor of next page or None if this page is the last one **/
+    pub next_cursor: Option<u128>,
+}
+
+///
+/// Makes a page of certificates with serials greater than cursor
+///
+/// # Arguments
+/// * certificates: I: certificates in any order
+/// * cursor: Option<u128>: next_cursor of previous page or None for first page
+/// * limit: u32: maximal number of certificates in page, clamped to MAX_CERTIFICATE_PAGE_SIZE
+///
+/// returns: CertificatePage: the page
+///
+pub fn get_page<'a, PK, SK, C, I>(certificates: I, cursor: Option<u128>, limit: u32) -> CertificatePage
+    where PK: CryptoKey, SK: CryptoKey, C: Certificate<PK, SK> + 'a, I: IntoIterator<Item = &'a C>{
+    let limit = limit.clamp(1, MAX_CERTIFICATE_PAGE_SIZE) as usize;
+    let mut selected: Vec<&C> = certificates.into_iter()
+        .filter(|certificate| cursor.is_none_or(|cursor| certificate.get_serial() > cursor))
+        .collect();
+    selected.sort_by_key(|certificate| certificate.get_serial());
+    let next_cursor = match selected.len() > limit {
+        true => Some(selected[limit - 1].get_serial()),
+        false => None,
+    };
+    CertificatePage{
+        certificates: selected.into_iter().take(limit).map(CertificateSummary::of).collect(),
+        next_cursor,
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::certificate::test_certificates;
+
+    #[test]
+    fn test_get_page() {
+        let base = test_certificates().signing;
+        let certificates: Vec<_> = [7u128, 3, 12, 5, 9].iter().map(|serial| {
+            let mut certificate = base.clone();
+            certificate.serial_number = *serial;
+            certificate
+        }).collect();
+        let first = get_page(&certificates, None, 2);
+        assert_eq!(first.certificates.iter().map(|summary| summary.serial).collect::<Vec<_>>(), vec![3, 5]);
+        assert!(first.certificates[0].has_secret_key);
+        assert_eq!(first.next_cursor, Some(5));
+        let second = get_page(&certificates, first.next_cursor, 2);
+        assert_eq!(second.certificates.iter().map(|summary| summary.serial).collect::<Vec<_>>(), vec![7, 9]);
+        let last = get_page(&certificates, second.next_cursor, 2);
+        assert_eq!(last.certificates.len(), 1);
+        assert_eq!(last.next_cursor, None);
+        assert_eq!(get_page(&certificates, None, 0).certificates.len(), 1);
+    }
+}
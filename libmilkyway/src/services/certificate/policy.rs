@@ -0,0 +1,363 @@
+use std::fmt::{Display, Formatter};
+use libmilkyway_derive::{Describe, Deserializable, EnumDeserializable, EnumSerializable, Serializable};
+use crate::pki::certificate::{Certificate, FLAG_HAS_METADATA, FLAG_HAS_VALIDITY};
+use crate::pki::certificate::flags::format_flags;
+use crate::pki::hash::HashType;
+use crate::pki::impls::certificates::falcon1024::Falcon1024RootCertificate;
+use crate::pki::key::CryptoKey;
+use crate::pki::signature::Signature;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::services::certificate::ROOT_CERTIFICATE_SERIAL;
+use crate::errors::ErrorCode;
+
+///
+/// Label of context of policy signatures
+///
+pub const POLICY_SIGNATURE_LABEL: &str = "certificate-policy";
+
+///
+/// Operation on certificate policy is evaluated for
+///
+#[derive(Clone, Copy, Debug, PartialEq, EnumSerializable, EnumDeserializable, Describe)]
+pub enum PolicyOperation{
+    /** Certificate is added to store **/
+    Add,
+    /** Certificate is issued by signing certificate of this node **/
+    Sign,
+    /** Chain of certificate is verified **/
+    Verify,
+}
+
+impl PolicyOperation {
+    pub fn from_name(name: &str) -> Option<PolicyOperation>{
+        match name {
+            "add" => Some(PolicyOperation::Add),
+            "sign" => Some(PolicyOperation::Sign),
+            "verify" => Some(PolicyOperation::Verify),
+            _ => None,
+        }
+    }
+}
+
+impl Display for PolicyOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyOperation::Add => write!(f, "add"),
+            PolicyOperation::Sign => write!(f, "sign"),
+            PolicyOperation::Verify => write!(f, "verify"),
+        }
+    }
+}
+
+///
+/// Errors of certificate policy
+///
+#[derive(Clone, Debug, PartialEq, Describe)]
+pub enum PolicyError{
+    /** Policy is not signed by root certificate of store, so none of its rules is trusted **/
+    InvalidSignature,
+    /** Policy is signed by root certificate, which must have secret key **/
+    NoSigningKey,
+    /** Policy is not newer than one in store, so older policy can not be replayed **/
+    Outdated{ current: u64, given: u64 },
+    /** No rule of issuer allows certificate **/
+    Denied{ operation: PolicyOperation, issuer: u128, name: String },
+    /** Service is in read-only mode, so policy can not be changed **/
+    ReadOnly,
+    /** Service does not keep policy **/
+    Unsupported,
+}
+
+impl Display for PolicyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyError::InvalidSignature => write!(f, "certificate policy is not signed by root certificate"),
+            PolicyError::NoSigningKey => write!(f, "root certificate with secret key is required to sign policy"),
+            PolicyError::Outdated{ current, given } =>
+                write!(f, "policy version {} is not newer than version {} in store", given, current),
+            PolicyError::Denied{ operation, issuer, name } =>
+                write!(f, "policy of issuer {} does not allow to {} certificate {}", issuer, operation, name),
+            PolicyError::ReadOnly => write!(f, "certificate store is read-only"),
+            PolicyError::Unsupported => write!(f, "certificate service does not keep policy"),
+        }
+    }
+}
+
+impl ErrorCode for PolicyError {
+    fn get_error_code(&self) -> &'static str {
+        match self {
+            PolicyError::InvalidSignature => "MW-POL-0001",
+            PolicyError::NoSigningKey => "MW-POL-0002",
+            PolicyError::Outdated{ .. } => "MW-POL-0003",
+            PolicyError::Denied{ .. } => "MW-POL-0004",
+            PolicyError::ReadOnly => "MW-POL-0005",
+            PolicyError::Unsupported => "MW-POL-0006",
+        }
+    }
+}
+
+impl Serializable for PolicyError {
+    fn serialize(&self) -> Serialized {
+        let mut result = Serialized::new();
+        match self {
+            PolicyError::InvalidSignature => result.extend(0u8.serialize()),
+            PolicyError::NoSigningKey => result.extend(1u8.serialize()),
+            PolicyError::Outdated{ current, given } => {
+                result.extend(2u8.serialize());
+                result.extend(current.serialize());
+                result.extend(given.serialize());
+            }
+            PolicyError::Denied{ operation, issuer, name } => {
+                result.extend(3u8.serialize());
+                result.extend(operation.serialize());
+                result.extend(issuer.serialize());
+                result.extend(name.serialize());
+            }
+            PolicyError::ReadOnly => result.extend(4u8.serialize()),
+            PolicyError::Unsupported => result.extend(5u8.serialize()),
+        }
+        result
+    }
+}
+
+impl Deserializable for PolicyError {
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        if serialized.is_empty(){
+            return Err(SerializationError::LengthError);
+        }
+        let data = serialized[1..].to_vec();
+        let (error, offset) = match serialized[0] {
+            0 => (PolicyError::InvalidSignature, 0),
+            1 => (PolicyError::NoSigningKey, 0),
+            2 => {
+                let (current, mut offset) = u64::from_serialized(&data)?;
+                let (given, size) = u64::from_serialized(&data[offset..].to_vec())?;
+                offset += size;
+                (PolicyError::Outdated{ current, given }, offset)
+            }
+            3 => {
+                let (operation, mut offset) = PolicyOperation::from_serialized(&data)?;
+                let (issuer, size) = u128::from_serialized(&data[offset..].to_vec())?;
+                offset += size;
+                let (name, size) = String::from_serialized(&data[offset..].to_vec())?;
+                offset += size;
+                (PolicyError::Denied{ operation, issuer, name }, offset)
+            }
+            4 => (PolicyError::ReadOnly, 0),
+            5 => (PolicyError::Unsupported, 0),
+            _ => return Err(SerializationError::InvalidDataError("Unknown policy error")),
+        };
+        Ok((error, offset + 1))
+    }
+}
+
+///
+/// Restriction of certificates issued by one signing certificate. Certificates of issuer
+/// having any rule must match at least one of them, issuers without rules are restricted
+/// by flags only.
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable, Describe)]
+pub struct PolicyRule{
+    /** Serial of issuing certificate, ROOT_CERTIFICATE_SERIAL for root **/
+    pub issuer: u128,
+    /** Names of issued certificates, `*` matches any characters and `?` a single one **/
+    pub name_pattern: String,
+    /** Flags issued certificates may have **/
+    pub allowed_flags: u128,
+}
+
+impl PolicyRule {
+    pub fn new(issuer: u128, name_pattern: &str, allowed_flags: u128) -> PolicyRule{
+        PolicyRule{
+            issuer,
+            name_pattern: name_pattern.to_string(),
+            allowed_flags,
+        }
+    }
+
+    pub fn matches(&self, subject: &PolicySubject) -> bool{
+        let flags = subject.flags & !(FLAG_HAS_METADATA | FLAG_HAS_VALIDITY);
+        self.issuer == subject.issuer && flags & !self.allowed_flags == 0
+            && matches_pattern(&self.name_pattern, &subject.name)
+    }
+}
+
+impl Display for PolicyRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let issuer = match self.issuer {
+            ROOT_CERTIFICATE_SERIAL => "root".to_string(),
+            issuer => issuer.to_string(),
+        };
+        write!(f, "{} may sign {} with flags [{}]", issuer, self.name_pattern, format_flags(self.allowed_flags))
+    }
+}
+
+///
+/// Certificate, existing or hypothetical, policy is evaluated for
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable, Describe)]
+pub struct PolicySubject{
+    pub operation: PolicyOperation,
+    /** Serial of certificate which signs or signed subject **/
+    pub issuer: u128,
+    pub name: String,
+    pub flags: u128,
+}
+
+impl PolicySubject {
+    ///
+    /// Describes certificate for policy evaluation
+    ///
+    /// returns: Option<PolicySubject>: subject or None if certificate has no issuer, e.g. it is root
+    ///
+    pub fn of<PK: CryptoKey, SK: CryptoKey, C: Certificate<PK, SK>>(operation: PolicyOperation,
+                                                                   certificate: &C) -> Option<PolicySubject>{
+        Some(PolicySubject{
+            operation,
+            issuer: certificate.get_parent_serial()?,
+            name: certificate.get_name(),
+            flags: certificate.get_flags(),
+        })
+    }
+}
+
+///
+/// Declarative rules of who may sign what, kept in certificate store and signed by its root
+/// certificate, so rules changed in store by anybody else are not trusted.
+///
+#[derive(Clone, Debug, Default, PartialEq, Serializable, Deserializable, Describe)]
+pub struct CertificatePolicy{
+    pub rules: Vec<PolicyRule>,
+    /** Incremented with every change **/
+    pub version: u64,
+    pub signature: Option<Signature>,
+}
+
+impl CertificatePolicy {
+    pub fn clone_without_signature(&self) -> CertificatePolicy{
+        let mut policy = self.clone();
+        policy.signature = None;
+        policy
+    }
+
+    ///
+    /// Signs policy with root certificate
+    ///
+    pub fn sign(&mut self, root: &Falcon1024RootCertificate) -> Result<&mut Self, PolicyError>{
+        let signature = root.sign_data_with_context(&self.clone_without_signature(), HashType::None,
+                                                    POLICY_SIGNATURE_LABEL)
+            .map_err(|_| PolicyError::NoSigningKey)?;
+        self.signature = Some(signature);
+        Ok(self)
+    }
+
+    ///
+    /// Checks that policy is signed by root certificate. Default policy restricts nothing,
+    /// so it is trusted unsigned.
+    ///
+    pub fn is_signed_by(&self, root: Option<&Falcon1024RootCertificate>) -> bool{
+        let (signature, root) = match (&self.signature, root) {
+            (None, _) => return self == &CertificatePolicy::default(),
+            (Some(signature), Some(root)) => (signature, root),
+            (Some(_), None) => return false,
+        };
+        let is_policy_signature = signature.context.as_ref()
+            .is_some_and(|context| context.label == POLICY_SIGNATURE_LABEL);
+        is_policy_signature && root.verify_signature(&self.clone_without_signature(), signature)
+    }
+
+    ///
+    /// Evaluates rules of issuer of subject. Signature of policy is not checked.
+    ///
+    pub fn evaluate(&self, subject: &PolicySubject) -> Result<(), PolicyError>{
+        let mut rules = self.rules.iter().filter(|rule| rule.issuer == subject.issuer).peekable();
+        if rules.peek().is_none() || rules.any(|rule| rule.matches(subject)){
+            return Ok(());
+        }
+        Err(PolicyError::Denied{
+            operation: subject.operation,
+            issuer: subject.issuer,
+            name: subject.name.clone(),
+        })
+    }
+}
+
+///
+/// Matches name with pattern, `*` matches any characters and `?` a single one
+///
+pub fn matches_pattern(pattern: &str, name: &str) -> bool{
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut pattern_index, mut name_index) = (0, 0);
+    // Position of last `*` and of name it matched up to, to backtrack to
+    let mut star: Option<(usize, usize)> = None;
+    while name_index < name.len(){
+        match pattern.get(pattern_index) {
+            Some('*') => {
+                star = Some((pattern_index, name_index));
+                pattern_index += 1;
+            }
+            Some(&character) if character == '?' || character == name[name_index] => {
+                pattern_index += 1;
+                name_index += 1;
+            }
+            _ => match star {
+                Some((star_index, matched)) => {
+                    pattern_index = star_index + 1;
+                    name_index = matched + 1;
+                    star = Some((star_index, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[pattern_index..].iter().all(|character| *character == '*')
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pki::certificate::{FLAG_CLIENT_CERT, FLAG_SERVER_CERT, FLAG_SIGN_MESSAGES};
+    use crate::testing::certificate::test_certificates;
+
+    #[test]
+    fn test_certificate_policy() {
+        assert!(matches_pattern("branch-*", "branch-12"));
+        assert!(matches_pattern("*-?", "a-b-c"));
+        assert!(!matches_pattern("branch-*", "hq-1"));
+        assert!(!matches_pattern("a?", "a"));
+
+        let subject = |issuer: u128, name: &str, flags: u128| PolicySubject{
+            operation: PolicyOperation::Sign,
+            issuer,
+            name: name.to_string(),
+            flags,
+        };
+        let mut policy = CertificatePolicy::default();
+        policy.rules.push(PolicyRule::new(5, "branch-*", FLAG_CLIENT_CERT | FLAG_SIGN_MESSAGES));
+        policy.version = 1;
+        assert_eq!(policy.evaluate(&subject(5, "branch-1", FLAG_CLIENT_CERT | FLAG_HAS_METADATA)), Ok(()));
+        assert!(matches!(policy.evaluate(&subject(5, "hq", FLAG_CLIENT_CERT)), Err(PolicyError::Denied{ issuer: 5, .. })));
+        assert!(policy.evaluate(&subject(5, "branch-1", FLAG_SERVER_CERT)).is_err());
+        // Issuers without rules are not restricted
+        assert_eq!(policy.evaluate(&subject(6, "hq", FLAG_SERVER_CERT)), Ok(()));
+
+        let certificates = test_certificates();
+        assert!(!policy.is_signed_by(Some(&certificates.root)));
+        assert_eq!(policy.sign(&certificates.root.clone_without_sk()).err(), Some(PolicyError::NoSigningKey));
+        policy.sign(&certificates.root).unwrap();
+        assert!(policy.is_signed_by(Some(&certificates.root)));
+        let (loaded, _) = CertificatePolicy::from_serialized(&policy.serialize()).unwrap();
+        let denied = PolicyError::Denied{ operation: PolicyOperation::Verify, issuer: 5, name: "hq".to_string() };
+        assert_eq!(PolicyError::from_serialized(&denied.serialize()).unwrap().0, denied);
+        assert!(loaded.is_signed_by(Some(&certificates.root)));
+        let mut tampered = loaded;
+        tampered.rules[0].name_pattern = "*".to_string();
+        assert!(!tampered.is_signed_by(Some(&certificates.root)));
+        assert!(CertificatePolicy::default().is_signed_by(None));
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use libmilkyway_derive::{Deserializable, Serializable};
+use crate::message::certpush::CertificatePushMessage;
+use crate::message::certsync::CertificateSyncEntry;
+use crate::pki::certificate::Certificate;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::services::certificate::CertificateService;
+use crate::services::certificate::sync::{apply_certificate_sync, CertificateSyncReport};
+
+///
+/// What receiver does with certificates pushed by peers
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CertificatePushPolicy{
+    /** All pushes are refused **/
+    Reject,
+    /** Pushes wait until operator accepts or rejects them **/
+    Confirm,
+    /** Pushes are installed right away if they pass verification **/
+    AcceptVerified,
+}
+
+impl Display for CertificatePushPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CertificatePushPolicy::Reject => write!(f, "reject"),
+            CertificatePushPolicy::Confirm => write!(f, "confirm"),
+            CertificatePushPolicy::AcceptVerified => write!(f, "accept-verified"),
+        }
+    }
+}
+
+impl FromStr for CertificatePushPolicy {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "reject" => Ok(CertificatePushPolicy::Reject),
+            "confirm" => Ok(CertificatePushPolicy::Confirm),
+            "accept-verified" => Ok(CertificatePushPolicy::AcceptVerified),
+            _ => Err("Unknown push policy"),
+        }
+    }
+}
+
+///
+/// How many pushes may wait for confirmation, further ones are rejected until operator
+/// handles queued ones
+///
+pub const MAX_PENDING_CERTIFICATE_PUSHES: usize = 64;
+
+///
+/// Certificate push waiting for operator confirmation
+///
+#[derive(Clone, Serializable, Deserializable)]
+pub struct PendingCertificatePush{
+    /** ID of peer which pushed certificate **/
+    pub source: u128,
+    pub push: CertificatePushMessage,
+}
+
+///
+/// Installs pushed certificate together with its chain. Each certificate is verified
+/// by service before being added, certificates with local secret keys are never replaced.
+/// Changes are committed if anything was installed.
+///
+/// # Arguments
+/// * service: &mut S: service to install certificate to
+/// * push: &CertificatePushMessage: received push
+///
+/// returns: Result<CertificateSyncReport, &'static str>: report on installed certificates or
+/// error if pushed certificate was not installed
+///
+pub fn install_certificate_push<S: CertificateService + ?Sized>(service: &mut S,
+                                push: &CertificatePushMessage) -> Result<CertificateSyncReport, &'static str>{
+    if let CertificateSyncEntry::Revocation(_) = push.entry{
+        return Err("Revocations can not be pushed");
+    }
+    let report = apply_certificate_sync(service, &push.to_sync_message());
+    let serial = push.get_serial();
+    let installed = match &push.entry {
+        CertificateSyncEntry::SigningCertificate(certificate) => service.get_signing_certificate(serial)
+            .is_some_and(|stored| stored.clone_without_sk() == *certificate),
+        CertificateSyncEntry::EncryptionCertificate(certificate) => service.get_encryption_certificate(serial)
+            .is_some_and(|stored| stored.clone_without_sk() == *certificate),
+        CertificateSyncEntry::Revocation(_) => false,
+    };
+    if !installed{
+        return Err("Certificate failed verification");
+    }
+    Ok(report)
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pki::certificate::FLAG_SIGN_CERTS;
+    use crate::pki::hash::HashType;
+    use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+    use crate::pki::impls::keys::falcon1024::generate_falcon1024_keypair;
+    use crate::serialization::deserializable::Deserializable;
+    use crate::serialization::serializable::Serializable;
+    use crate::services::impls::certificate::AsyncCertificateServiceImpl;
+    use crate::testing::certificate::{test_certificates, TEST_ENCRYPTION_CERTIFICATE_SERIAL,
+                                      TEST_SIGNING_CERTIFICATE_SERIAL};
+
+    fn create_service(path: &str) -> AsyncCertificateServiceImpl{
+        let certificates = test_certificates();
+        let mut service = AsyncCertificateServiceImpl::new(path);
+        service.set_root_certificate(certificates.root.clone_without_sk());
+        service
+    }
+
+    #[test]
+    fn test_push_installs_certificate_with_chain() {
+        let certificates = test_certificates();
+        let mut sender = create_service("/tmp/test_certpush_sender.dat");
+        assert!(sender.add_signing_certificate(certificates.signing.clone()));
+        let mut push = CertificatePushMessage::new_encryption(&certificates.encryption);
+        assert!(push.add_chain(&mut sender));
+        assert_eq!(push.chain.len(), 1);
+        let push = CertificatePushMessage::from_serialized(&push.serialize()).unwrap().0;
+
+        let mut receiver = create_service("/tmp/test_certpush_receiver.dat");
+        let report = install_certificate_push(&mut receiver, &push).unwrap();
+        assert_eq!(report.applied, 2);
+        let stored = receiver.get_encryption_certificate(TEST_ENCRYPTION_CERTIFICATE_SERIAL).unwrap();
+        assert!(stored.get_secret_key().is_none());
+        assert!(receiver.get_signing_certificate(TEST_SIGNING_CERTIFICATE_SERIAL).is_some());
+    }
+
+    #[test]
+    fn test_push_without_chain_is_rejected() {
+        let certificates = test_certificates();
+        let push = CertificatePushMessage::new_encryption(&certificates.encryption);
+        let mut receiver = create_service("/tmp/test_certpush_nochain.dat");
+        assert!(install_certificate_push(&mut receiver, &push).is_err());
+        assert!(receiver.get_encryption_certificate(TEST_ENCRYPTION_CERTIFICATE_SERIAL).is_none());
+        assert_eq!("accept-verified".parse::<CertificatePushPolicy>(), Ok(CertificatePushPolicy::AcceptVerified));
+        assert!("other".parse::<CertificatePushPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_push_with_forged_signature_is_rejected() {
+        let certificates = test_certificates();
+        let (public_key, secret_key) = generate_falcon1024_keypair();
+        let mut forged = Falcon1024Certificate{
+            serial_number: 10,
+            parent_serial_number: 0,
+            secret_key: Some(secret_key),
+            public_key,
+            signature: None,
+            name: "forged".to_string(),
+            flags: FLAG_SIGN_CERTS,
+            metadata: Default::default(),
+        };
+        // Signed by signing certificate while claiming root as parent
+        forged.signature = Some(certificates.signing.sign_data(&forged.clone_without_signature_and_sk(),
+                                                               HashType::None).unwrap());
+        let push = CertificatePushMessage::new_signing(&forged);
+        let mut receiver = create_service("/tmp/test_certpush_forged.dat");
+        assert!(install_certificate_push(&mut receiver, &push).is_err());
+        assert!(receiver.get_signing_certificate(10).is_none());
+    }
+}
@@ -0,0 +1,272 @@
+use crate::actor::binder::BinderServiceHandler;
+use crate::pki::certificate::metadata::CertificateQuery;
+use crate::pki::impls::certificates::falcon1024::{Falcon1024Certificate, Falcon1024RootCertificate};
+use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use crate::services::certificate::{CertificateService, CertificateServiceBinderRequest,
+                                   CertificateServiceBinderResponse, CertificateServiceError,
+                                   VerifiableCertificate};
+use crate::services::certificate::usage::{KeyUsage, UsageThresholds};
+use crate::services::certificate::reload::CertificateReloadReport;
+use crate::services::certificate::policy::{CertificatePolicy, PolicyError, PolicySubject};
+use crate::message::certsync::CertificateRevocation;
+
+///
+/// Certificate service which may be switched to read-only mode, e.g. on replicas or during
+/// maintenance windows. While read-only, root certificate can not be set and certificates
+/// can not be added or removed. Verification, lookups and usage counters keep working.
+///
+pub struct ReadOnlyCertificateService<S: CertificateService>{
+    inner: S,
+    read_only: bool,
+}
+
+impl<S: CertificateService> ReadOnlyCertificateService<S> {
+    ///
+    /// Wraps certificate service
+    ///
+    /// # Arguments
+    /// * inner: S: service requests are passed to
+    /// * read_only: bool: whether service starts in read-only mode
+    ///
+    pub fn new(inner: S, read_only: bool) -> ReadOnlyCertificateService<S>{
+        ReadOnlyCertificateService{
+            inner,
+            read_only,
+        }
+    }
+
+    ///
+    /// Unwraps certificate service
+    ///
+    #[inline]
+    pub fn into_inner(self) -> S{
+        self.inner
+    }
+
+    fn reject(&self, action: &str) -> bool{
+        log::warn!("Can not {}: {}", action, CertificateServiceError::ReadOnly);
+        false
+    }
+}
+
+impl<S: CertificateService> CertificateService for ReadOnlyCertificateService<S>{
+    fn set_root_certificate(&mut self, root_cert: Falcon1024RootCertificate) {
+        if self.read_only{
+            self.reject("set root certificate");
+            return;
+        }
+        self.inner.set_root_certificate(root_cert)
+    }
+
+    fn add_signing_certificate(&mut self, cert: Falcon1024Certificate) -> bool {
+        if self.read_only{
+            return self.reject("add signing certificate");
+        }
+        self.inner.add_signing_certificate(cert)
+    }
+
+    fn add_encryption_certificate(&mut self, cert: Kyber1024Certificate) -> bool {
+        if self.read_only{
+            return self.reject("add encryption certificate");
+        }
+        self.inner.add_encryption_certificate(cert)
+    }
+
+    #[inline]
+    fn verify_signing_certificate(&mut self, cert: &Falcon1024Certificate) -> bool {
+        self.inner.verify_signing_certificate(cert)
+    }
+
+    #[inline]
+    fn verify_encryption_certificate(&mut self, cert: &Kyber1024Certificate) -> bool {
+        self.inner.verify_encryption_certificate(cert)
+    }
+
+    #[inline]
+    fn get_signing_certificate(&mut self, serial: u128) -> Option<Falcon1024Certificate> {
+        self.inner.get_signing_certificate(serial)
+    }
+
+    #[inline]
+    fn get_encryption_certificate(&mut self, serial: u128) -> Option<Kyber1024Certificate> {
+        self.inner.get_encryption_certificate(serial)
+    }
+
+    #[inline]
+    fn get_root_certificate(&mut self) -> Option<Falcon1024RootCertificate> {
+        self.inner.get_root_certificate()
+    }
+
+    #[inline]
+    fn get_signing_certificates(&mut self) -> Vec<Falcon1024Certificate> {
+        self.inner.get_signing_certificates()
+    }
+
+    #[inline]
+    fn get_encryption_certificates(&mut self) -> Vec<Kyber1024Certificate> {
+        self.inner.get_encryption_certificates()
+    }
+
+    fn remove_signing_certificate(&mut self, serial: u128) -> bool {
+        if self.read_only{
+            return self.reject("remove signing certificate");
+        }
+        self.inner.remove_signing_certificate(serial)
+    }
+
+    fn remove_encryption_certificate(&mut self, serial: u128) -> bool {
+        if self.read_only{
+            return self.reject("remove encryption certificate");
+        }
+        self.inner.remove_encryption_certificate(serial)
+    }
+
+    #[inline]
+    fn verify_many(&mut self, certs: &[VerifiableCertificate]) -> Vec<bool> {
+        self.inner.verify_many(certs)
+    }
+
+    #[inline]
+    fn record_key_usage(&mut self, usage: KeyUsage) -> bool {
+        self.inner.record_key_usage(usage)
+    }
+
+    #[inline]
+    fn get_key_usage(&mut self) -> Vec<KeyUsage> {
+        self.inner.get_key_usage()
+    }
+
+    #[inline]
+    fn set_usage_thresholds(&mut self, thresholds: UsageThresholds) {
+        self.inner.set_usage_thresholds(thresholds)
+    }
+
+    #[inline]
+    fn get_usage_thresholds(&mut self) -> UsageThresholds {
+        self.inner.get_usage_thresholds()
+    }
+
+    #[inline]
+    fn query_signing_certificates(&mut self, query: &CertificateQuery) -> Vec<Falcon1024Certificate> {
+        self.inner.query_signing_certificates(query)
+    }
+
+    #[inline]
+    fn query_encryption_certificates(&mut self, query: &CertificateQuery) -> Vec<Kyber1024Certificate> {
+        self.inner.query_encryption_certificates(query)
+    }
+
+    fn set_read_only(&mut self, read_only: bool) {
+        if self.read_only != read_only{
+            log::info!("Certificate store is now {}", if read_only { "read-only" } else { "writable" });
+        }
+        self.read_only = read_only;
+    }
+
+    #[inline]
+    fn is_read_only(&mut self) -> bool {
+        self.read_only || self.inner.is_read_only()
+    }
+
+    // Replicas follow store written by primary, so reload is allowed while read-only
+    #[inline]
+    fn reload(&mut self) -> Result<CertificateReloadReport, CertificateServiceError> {
+        self.inner.reload()
+    }
+
+    #[inline]
+    fn get_policy(&mut self) -> CertificatePolicy {
+        self.inner.get_policy()
+    }
+
+    fn set_policy(&mut self, policy: CertificatePolicy) -> Result<(), PolicyError> {
+        if self.read_only{
+            self.reject("set certificate policy");
+            return Err(PolicyError::ReadOnly);
+        }
+        self.inner.set_policy(policy)
+    }
+
+    #[inline]
+    fn evaluate_policy(&mut self, subject: &PolicySubject) -> Result<(), PolicyError> {
+        self.inner.evaluate_policy(subject)
+    }
+
+    fn add_revocation(&mut self, revocation: CertificateRevocation) -> bool {
+        if self.read_only{
+            return self.reject("revoke certificate");
+        }
+        self.inner.add_revocation(revocation)
+    }
+
+    #[inline]
+    fn is_revoked(&mut self, serial: u128) -> bool {
+        self.inner.is_revoked(serial)
+    }
+
+    #[inline]
+    fn get_revocations(&mut self) -> Vec<CertificateRevocation> {
+        self.inner.get_revocations()
+    }
+
+    #[inline]
+    fn commit(&mut self) {
+        self.inner.commit()
+    }
+
+    #[inline]
+    fn is_dirty(&mut self) -> bool {
+        self.inner.is_dirty()
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), CertificateServiceError> {
+        self.inner.flush()
+    }
+}
+
+impl<S: CertificateService + 'static> BinderServiceHandler<CertificateServiceBinderRequest,
+    CertificateServiceBinderResponse> for ReadOnlyCertificateService<S>{
+    fn handle_message(&mut self, request: CertificateServiceBinderRequest) -> CertificateServiceBinderResponse {
+        let ptr: &mut dyn CertificateService = self;
+        ptr.handle_message(request)
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actor::binder::{Binder, BinderChannelProvider};
+    use crate::actor::binder::coroutine::BinderAsyncService;
+    use crate::services::certificate::CertificateServiceBinderResponse::{Rejected, Status};
+    use crate::testing::certificate::{test_certificates, MockCertificateService, TEST_SIGNING_CERTIFICATE_SERIAL};
+    use crate::tokio::init_tokio;
+
+    #[test]
+    fn test_read_only_service() {
+        let certificates = test_certificates();
+        let mut service = ReadOnlyCertificateService::new(MockCertificateService::with_test_certificates(), true);
+        assert!(service.is_read_only());
+        assert_eq!(service.check_writable(), Err(CertificateServiceError::ReadOnly));
+        assert!(!service.remove_signing_certificate(TEST_SIGNING_CERTIFICATE_SERIAL));
+        assert!(service.get_signing_certificate(TEST_SIGNING_CERTIFICATE_SERIAL).is_some());
+        assert!(service.verify_signing_certificate(&certificates.signing));
+        let request = CertificateServiceBinderRequest::AddSigningCertificate(certificates.signing.clone());
+        assert!(request.is_mutating());
+        assert!(matches!(service.handle_message(request), Rejected(CertificateServiceError::ReadOnly)));
+        assert!(matches!(service.handle_message(CertificateServiceBinderRequest::GetSigningCertificates),
+                         CertificateServiceBinderResponse::Falcon1024Certs(_)));
+
+        init_tokio();
+        let mut async_service = BinderAsyncService::run(Box::new(service));
+        let mut binder = async_service.bind();
+        assert!(binder.is_read_only());
+        assert!(!binder.remove_signing_certificate(TEST_SIGNING_CERTIFICATE_SERIAL));
+        binder.set_read_only(false);
+        assert!(!binder.is_read_only());
+        assert!(binder.remove_signing_certificate(TEST_SIGNING_CERTIFICATE_SERIAL));
+        assert!(binder.get_signing_certificate(TEST_SIGNING_CERTIFICATE_SERIAL).is_none());
+        assert!(matches!(binder.handle_request(CertificateServiceBinderRequest::IsReadOnly), Status(false)));
+    }
+}
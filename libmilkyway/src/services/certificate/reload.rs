@@ -0,0 +1,320 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use libmilkyway_derive::{Describe, Deserializable, EnumDeserializable, EnumSerializable, Serializable};
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::services::certificate::{CertificateService, ROOT_CERTIFICATE_SERIAL};
+use crate::services::certificate::directory::is_complete_file_event;
+
+///
+/// Kind of certificate changed in store
+///
+#[derive(Clone, Copy, Debug, PartialEq, EnumSerializable, EnumDeserializable, Describe)]
+pub enum CertificateKind{
+    Root,
+    Signing,
+    Encryption,
+}
+
+impl Display for CertificateKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CertificateKind::Root => write!(f, "root"),
+            CertificateKind::Signing => write!(f, "signing"),
+            CertificateKind::Encryption => write!(f, "encryption"),
+        }
+    }
+}
+
+///
+/// Change of certificate found in store by reload, with serial of certificate
+///
+#[derive(Clone, Copy, Debug, PartialEq, Describe)]
+pub enum CertificateChange{
+    Added(CertificateKind, u128),
+    /** Certificate with the same serial differs, e.g. it was rotated or lost its secret key **/
+    Replaced(CertificateKind, u128),
+    Removed(CertificateKind, u128),
+}
+
+impl CertificateChange {
+    pub fn get_serial(&self) -> u128{
+        match self {
+            CertificateChange::Added(_, serial) |
+            CertificateChange::Replaced(_, serial) |
+            CertificateChange::Removed(_, serial) => *serial,
+        }
+    }
+}
+
+impl Display for CertificateChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CertificateChange::Added(kind, serial) => write!(f, "added {} certificate {}", kind, serial),
+            CertificateChange::Replaced(kind, serial) => write!(f, "replaced {} certificate {}", kind, serial),
+            CertificateChange::Removed(kind, serial) => write!(f, "removed {} certificate {}", kind, serial),
+        }
+    }
+}
+
+impl Serializable for CertificateChange {
+    fn serialize(&self) -> Serialized {
+        let (code, kind, serial) = match self {
+            CertificateChange::Added(kind, serial) => (0u8, kind, serial),
+            CertificateChange::Replaced(kind, serial) => (1u8, kind, serial),
+            CertificateChange::Removed(kind, serial) => (2u8, kind, serial),
+        };
+        let mut result = code.serialize();
+        result.extend(kind.serialize());
+        result.extend(serial.serialize());
+        result
+    }
+}
+
+impl Deserializable for CertificateChange {
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        if serialized.is_empty(){
+            return Err(SerializationError::LengthError);
+        }
+        let (kind, kind_offset) = CertificateKind::from_serialized(&serialized[1..].to_vec())?;
+        let (serial, serial_offset) = u128::from_serialized(&serialized[1 + kind_offset..].to_vec())?;
+        let change = match serialized[0] {
+            0 => CertificateChange::Added(kind, serial),
+            1 => CertificateChange::Replaced(kind, serial),
+            2 => CertificateChange::Removed(kind, serial),
+            _ => return Err(SerializationError::InvalidDataError("Unknown certificate change")),
+        };
+        Ok((change, 1 + kind_offset + serial_offset))
+    }
+}
+
+///
+/// Result of reloading certificate store
+///
+#[derive(Clone, Debug, Default, PartialEq, Serializable, Deserializable, Describe)]
+pub struct CertificateReloadReport{
+    /** Changes applied to service, root first, then certificates by serial **/
+    pub changes: Vec<CertificateChange>,
+    /** Serials changed in service since last commit which differ in store, changes of service are kept **/
+    pub conflicts: Vec<u128>,
+}
+
+impl CertificateReloadReport {
+    #[inline]
+    pub fn is_empty(&self) -> bool{
+        self.changes.is_empty() && self.conflicts.is_empty()
+    }
+}
+
+///
+/// Listener of changes applied by reload
+///
+pub type CertificateChangeListener = Box<dyn Fn(&CertificateChange) + Send + Sync>;
+
+///
+/// Tracks certificates changed since last commit, so reload does not overwrite them, and
+/// notifies listeners about changes found by reload. Nothing of it is persisted.
+///
+#[derive(Default)]
+pub struct CertificateChangeTracker{
+    pending: HashSet<u128>,
+    /** Whether state other than certificates changed, e.g. key usage or policy **/
+    dirty: bool,
+    listeners: Vec<CertificateChangeListener>,
+}
+
+impl CertificateChangeTracker {
+    ///
+    /// Marks certificate as changed but not committed, ROOT_CERTIFICATE_SERIAL marks root
+    ///
+    #[inline]
+    pub fn mark(&mut self, serial: u128){
+        self.pending.insert(serial);
+    }
+
+    #[inline]
+    pub fn is_pending(&self, serial: u128) -> bool{
+        self.pending.contains(&serial)
+    }
+
+    ///
+    /// Marks change of state other than certificates, it is not merged by reload
+    ///
+    #[inline]
+    pub fn mark_dirty(&mut self){
+        self.dirty = true;
+    }
+
+    ///
+    /// Checks whether anything changed since last commit
+    ///
+    #[inline]
+    pub fn is_dirty(&self) -> bool{
+        self.dirty || !self.pending.is_empty()
+    }
+
+    ///
+    /// Forgets changes once they are written to store
+    ///
+    #[inline]
+    pub fn clear(&mut self){
+        self.pending.clear();
+        self.dirty = false;
+    }
+
+    pub fn subscribe(&mut self, listener: CertificateChangeListener){
+        self.listeners.push(listener);
+    }
+
+    ///
+    /// Logs result of reload and passes its changes to listeners
+    ///
+    pub fn notify(&self, report: &CertificateReloadReport){
+        for change in report.changes.iter(){
+            log::info!("Certificate store reload: {}", change);
+            for listener in self.listeners.iter(){
+                listener(change);
+            }
+        }
+        if !report.conflicts.is_empty(){
+            log::warn!("Certificates {:?} were changed both in memory and in store, uncommitted changes are kept",
+                       report.conflicts);
+        }
+    }
+
+    ///
+    /// Applies root certificate read from store, see merge_certificates
+    ///
+    pub(crate) fn merge_root<C: PartialEq>(&self, current: &mut Option<C>, stored: Option<C>,
+                                          report: &mut CertificateReloadReport){
+        if *current == stored{
+            return;
+        }
+        if self.is_pending(ROOT_CERTIFICATE_SERIAL){
+            report.conflicts.push(ROOT_CERTIFICATE_SERIAL);
+            return;
+        }
+        let change = match (current.is_some(), stored.is_some()) {
+            (false, _) => CertificateChange::Added(CertificateKind::Root, ROOT_CERTIFICATE_SERIAL),
+            (true, true) => CertificateChange::Replaced(CertificateKind::Root, ROOT_CERTIFICATE_SERIAL),
+            (true, false) => CertificateChange::Removed(CertificateKind::Root, ROOT_CERTIFICATE_SERIAL),
+        };
+        *current = stored;
+        report.changes.push(change);
+    }
+
+    ///
+    /// Applies certificates of one kind read from store to ones of service. Certificates
+    /// changed since last commit are kept and reported as conflicts if store has other version.
+    ///
+    pub(crate) fn merge_certificates<C: PartialEq>(&self, kind: CertificateKind, current: &mut HashMap<u128, C>,
+                                                  mut stored: HashMap<u128, C>, report: &mut CertificateReloadReport){
+        let mut serials: Vec<u128> = current.keys().chain(stored.keys()).copied().collect();
+        serials.sort();
+        serials.dedup();
+        for serial in serials{
+            let stored_certificate = stored.remove(&serial);
+            if current.get(&serial) == stored_certificate.as_ref(){
+                continue;
+            }
+            if self.is_pending(serial){
+                report.conflicts.push(serial);
+                continue;
+            }
+            let change = match stored_certificate {
+                Some(certificate) => match current.insert(serial, certificate) {
+                    Some(_) => CertificateChange::Replaced(kind, serial),
+                    None => CertificateChange::Added(kind, serial),
+                },
+                None => {
+                    current.remove(&serial);
+                    CertificateChange::Removed(kind, serial)
+                }
+            };
+            report.changes.push(change);
+        }
+    }
+}
+
+///
+/// Reloads certificate service once its store is written by another process, e.g. by restore
+/// or CLI sharing the store with daemon. Store is replaced by some tools, so its directory is
+/// watched. Store is watched until watcher is dropped.
+///
+pub struct CertificateStoreWatcher{
+    /** Stops watching once dropped **/
+    _watcher: RecommendedWatcher,
+    path: PathBuf,
+}
+
+impl CertificateStoreWatcher {
+    ///
+    /// Starts watching store
+    ///
+    /// # Arguments
+    /// * path: &Path: file of certificate store
+    /// * service: Arc<Mutex<Box<S>>>: service to reload
+    ///
+    pub fn start<S: CertificateService + ?Sized + Send + 'static>(path: &Path, service: Arc<Mutex<Box<S>>>)
+        -> notify::Result<CertificateStoreWatcher>{
+        let directory = match path.parent() {
+            Some(directory) if !directory.as_os_str().is_empty() => directory.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let file_name = path.file_name().map(|name| name.to_os_string());
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let event = match event {
+                Ok(event) => event,
+                Err(error) => {
+                    log::error!("Error while watching certificate store: {}", error);
+                    return;
+                }
+            };
+            if !is_complete_file_event(&event.kind)
+                || !event.paths.iter().any(|changed| changed.file_name() == file_name.as_deref()){
+                return;
+            }
+            if let Err(error) = service.lock().unwrap().reload(){
+                log::error!("Can not reload certificate store: {}", error);
+            }
+        })?;
+        watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+        log::info!("Watching {} for changes", path.display());
+        Ok(CertificateStoreWatcher{
+            _watcher: watcher,
+            path: path.to_path_buf(),
+        })
+    }
+
+    #[inline]
+    pub fn get_path(&self) -> &Path{
+        &self.path
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_certificates() {
+        let mut tracker = CertificateChangeTracker::default();
+        let mut current = HashMap::from([(1u128, "a"), (2, "b"), (3, "c")]);
+        let stored = HashMap::from([(1u128, "a"), (3, "d"), (4, "e"), (5, "f")]);
+        tracker.mark(5);
+        let mut report = CertificateReloadReport::default();
+        tracker.merge_certificates(CertificateKind::Signing, &mut current, stored, &mut report);
+        assert_eq!(report.changes, vec![CertificateChange::Removed(CertificateKind::Signing, 2),
+                                        CertificateChange::Replaced(CertificateKind::Signing, 3),
+                                        CertificateChange::Added(CertificateKind::Signing, 4)]);
+        assert_eq!(report.conflicts, vec![5]);
+        assert_eq!(current, HashMap::from([(1u128, "a"), (3, "d"), (4, "e")]));
+        assert_eq!(CertificateReloadReport::from_serialized(&report.serialize()).unwrap().0, report);
+    }
+}
@@ -0,0 +1,1104 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use libmilkyway_derive::{Describe, Deserializable, Serializable};
+use crate::actor::binder::Binder;
+use crate::message::builder::MessageBuilder;
+use crate::message::certsync::CertificateRevocation;
+use crate::message::common::{AsMessage, Message, CORE_MODULE_ID};
+use crate::message::types::MessageType;
+use crate::pki::certificate::{Certificate, FLAG_NO_READ, FLAG_NO_WRITE, FLAG_REMOTE_CERTIFICATES,
+                              FLAG_SIGN_MESSAGES};
+use crate::pki::hash::HashType;
+use crate::pki::impls::certificates::falcon1024::{Falcon1024Certificate, Falcon1024RootCertificate};
+use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+use crate::services::certificate::{CertificateService, CertificateServiceBinder,
+                                   CertificateServiceBinderRequest, CertificateServiceBinderResponse,
+                                   CertificateServiceError, VerifiableCertificate};
+use crate::services::certificate::chain::CertificateChain;
+use crate::services::certificate::listing::CertificatePage;
+use crate::services::certificate::policy::{CertificatePolicy, PolicyError, PolicySubject};
+use crate::services::certificate::reload::CertificateReloadReport;
+use crate::services::certificate::usage::{KeyUsage, UsageThresholds};
+use crate::services::transport::{MessageFilter, TransportService};
+use crate::transport::{TransportListener, TransportSender};
+
+///
+/// Default time to wait for response of broker
+///
+pub const DEFAULT_REMOTE_CERTIFICATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl Serializable for VerifiableCertificate {
+    fn serialize(&self) -> Serialized {
+        let mut result = Serialized::new();
+        match self {
+            VerifiableCertificate::Signing(certificate) => {
+                result.extend(0u8.serialize());
+                result.extend(certificate.serialize());
+            }
+            VerifiableCertificate::Encryption(certificate) => {
+                result.extend(1u8.serialize());
+                result.extend(certificate.serialize());
+            }
+        }
+        result
+    }
+}
+
+impl Deserializable for VerifiableCertificate {
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        if serialized.is_empty(){
+            return Err(SerializationError::LengthError);
+        }
+        let data = serialized[1..].to_vec();
+        let (certificate, offset) = match serialized[0] {
+            0 => {
+                let (certificate, offset) = Falcon1024Certificate::from_serialized(&data)?;
+                (VerifiableCertificate::Signing(Box::new(certificate)), offset)
+            }
+            1 => {
+                let (certificate, offset) = Kyber1024Certificate::from_serialized(&data)?;
+                (VerifiableCertificate::Encryption(Box::new(certificate)), offset)
+            }
+            _ => return Err(SerializationError::InvalidDataError("Unknown kind of certificate")),
+        };
+        Ok((certificate, offset + 1))
+    }
+}
+
+impl Serializable for CertificateServiceBinderRequest {
+    fn serialize(&self) -> Serialized {
+        let mut result = Serialized::new();
+        match self {
+            CertificateServiceBinderRequest::AddEncryptionCertificate(certificate) => {
+                result.extend(0u8.serialize());
+                result.extend(certificate.serialize());
+            }
+            CertificateServiceBinderRequest::AddSigningCertificate(certificate) => {
+                result.extend(1u8.serialize());
+                result.extend(certificate.serialize());
+            }
+            CertificateServiceBinderRequest::SetSigningCertificate(certificate) => {
+                result.extend(2u8.serialize());
+                result.extend(certificate.serialize());
+            }
+            CertificateServiceBinderRequest::VerifySigningCertificate(certificate) => {
+                result.extend(3u8.serialize());
+                result.extend(certificate.serialize());
+            }
+            CertificateServiceBinderRequest::VerifyEncryptionCertificate(certificate) => {
+                result.extend(4u8.serialize());
+                result.extend(certificate.serialize());
+            }
+            CertificateServiceBinderRequest::GetSigningCertificate(serial) => {
+                result.extend(5u8.serialize());
+                result.extend(serial.serialize());
+            }
+            CertificateServiceBinderRequest::GetEncryptionCertificate(serial) => {
+                result.extend(6u8.serialize());
+                result.extend(serial.serialize());
+            }
+            CertificateServiceBinderRequest::GetRootCertificate => result.extend(7u8.serialize()),
+            CertificateServiceBinderRequest::GetEncryptionCertificates => result.extend(8u8.serialize()),
+            CertificateServiceBinderRequest::GetSigningCertificates => result.extend(9u8.serialize()),
+            CertificateServiceBinderRequest::RemoveSigningCertificate(serial) => {
+                result.extend(10u8.serialize());
+                result.extend(serial.serialize());
+            }
+            CertificateServiceBinderRequest::RemoveEncryptionCertificate(serial) => {
+                result.extend(11u8.serialize());
+                result.extend(serial.serialize());
+            }
+            CertificateServiceBinderRequest::Commit => result.extend(12u8.serialize()),
+            CertificateServiceBinderRequest::VerifyMany(certificates) => {
+                result.extend(13u8.serialize());
+                result.extend(certificates.serialize());
+            }
+            CertificateServiceBinderRequest::RecordKeyUsage(usage) => {
+                result.extend(14u8.serialize());
+                result.extend(usage.serialize());
+            }
+            CertificateServiceBinderRequest::GetKeyUsage => result.extend(15u8.serialize()),
+            CertificateServiceBinderRequest::SetUsageThresholds(thresholds) => {
+                result.extend(16u8.serialize());
+                result.extend(thresholds.serialize());
+            }
+            CertificateServiceBinderRequest::GetUsageThresholds => result.extend(17u8.serialize()),
+            CertificateServiceBinderRequest::SetReadOnly(read_only) => {
+                result.extend(18u8.serialize());
+                result.extend(read_only.serialize());
+            }
+            CertificateServiceBinderRequest::IsReadOnly => result.extend(19u8.serialize()),
+            CertificateServiceBinderRequest::ListSigningCertificates(cursor, limit) => {
+                result.extend(20u8.serialize());
+                result.extend(cursor.serialize());
+                result.extend(limit.serialize());
+            }
+            CertificateServiceBinderRequest::ListEncryptionCertificates(cursor, limit) => {
+                result.extend(21u8.serialize());
+                result.extend(cursor.serialize());
+                result.extend(limit.serialize());
+            }
+            CertificateServiceBinderRequest::Reload => result.extend(22u8.serialize()),
+            CertificateServiceBinderRequest::GetPolicy => result.extend(23u8.serialize()),
+            CertificateServiceBinderRequest::SetPolicy(policy) => {
+                result.extend(24u8.serialize());
+                result.extend(policy.serialize());
+            }
+            CertificateServiceBinderRequest::EvaluatePolicy(subject) => {
+                result.extend(25u8.serialize());
+                result.extend(subject.serialize());
+            }
+            CertificateServiceBinderRequest::IsDirty => result.extend(26u8.serialize()),
+            CertificateServiceBinderRequest::Flush => result.extend(27u8.serialize()),
+            CertificateServiceBinderRequest::AddRevocation(revocation) => {
+                result.extend(28u8.serialize());
+                result.extend(revocation.serialize());
+            }
+            CertificateServiceBinderRequest::IsRevoked(serial) => {
+                result.extend(29u8.serialize());
+                result.extend(serial.serialize());
+            }
+            CertificateServiceBinderRequest::GetRevocations => result.extend(30u8.serialize()),
+        }
+        result
+    }
+}
+
+impl Deserializable for CertificateServiceBinderRequest {
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        if serialized.is_empty(){
+            return Err(SerializationError::LengthError);
+        }
+        let data = serialized[1..].to_vec();
+        let (request, offset) = match serialized[0] {
+            0 => {
+                let (certificate, offset) = Kyber1024Certificate::from_serialized(&data)?;
+                (CertificateServiceBinderRequest::AddEncryptionCertificate(certificate), offset)
+            }
+            1 => {
+                let (certificate, offset) = Falcon1024Certificate::from_serialized(&data)?;
+                (CertificateServiceBinderRequest::AddSigningCertificate(certificate), offset)
+            }
+            2 => {
+                let (certificate, offset) = Falcon1024RootCertificate::from_serialized(&data)?;
+                (CertificateServiceBinderRequest::SetSigningCertificate(certificate), offset)
+            }
+            3 => {
+                let (certificate, offset) = Falcon1024Certificate::from_serialized(&data)?;
+                (CertificateServiceBinderRequest::VerifySigningCertificate(certificate), offset)
+            }
+            4 => {
+                let (certificate, offset) = Kyber1024Certificate::from_serialized(&data)?;
+                (CertificateServiceBinderRequest::VerifyEncryptionCertificate(certificate), offset)
+            }
+            5 => {
+                let (serial, offset) = u128::from_serialized(&data)?;
+                (CertificateServiceBinderRequest::GetSigningCertificate(serial), offset)
+            }
+            6 => {
+                let (serial, offset) = u128::from_serialized(&data)?;
+                (CertificateServiceBinderRequest::GetEncryptionCertificate(serial), offset)
+            }
+            7 => (CertificateServiceBinderRequest::GetRootCertificate, 0),
+            8 => (CertificateServiceBinderRequest::GetEncryptionCertificates, 0),
+            9 => (CertificateServiceBinderRequest::GetSigningCertificates, 0),
+            10 => {
+                let (serial, offset) = u128::from_serialized(&data)?;
+                (CertificateServiceBinderRequest::RemoveSigningCertificate(serial), offset)
+            }
+            11 => {
+                let (serial, offset) = u128::from_serialized(&data)?;
+                (CertificateServiceBinderRequest::RemoveEncryptionCertificate(serial), offset)
+            }
+            12 => (CertificateServiceBinderRequest::Commit, 0),
+            13 => {
+                let (certificates, offset) = Vec::<VerifiableCertificate>::from_serialized(&data)?;
+                (CertificateServiceBinderRequest::VerifyMany(certificates), offset)
+            }
+            14 => {
+                let (usage, offset) = KeyUsage::from_serialized(&data)?;
+                (CertificateServiceBinderRequest::RecordKeyUsage(usage), offset)
+            }
+            15 => (CertificateServiceBinderRequest::GetKeyUsage, 0),
+            16 => {
+                let (thresholds, offset) = UsageThresholds::from_serialized(&data)?;
+                (CertificateServiceBinderRequest::SetUsageThresholds(thresholds), offset)
+            }
+            17 => (CertificateServiceBinderRequest::GetUsageThresholds, 0),
+            18 => {
+                let (read_only, offset) = bool::from_serialized(&data)?;
+                (CertificateServiceBinderRequest::SetReadOnly(read_only), offset)
+            }
+            19 => (CertificateServiceBinderRequest::IsReadOnly, 0),
+            20 | 21 => {
+                let (cursor, cursor_offset) = Option::<u128>::from_serialized(&data)?;
+                let (limit, limit_offset) = u32::from_serialized(&data[cursor_offset..].to_vec())?;
+                let request = match serialized[0] {
+                    20 => CertificateServiceBinderRequest::ListSigningCertificates(cursor, limit),
+                    _ => CertificateServiceBinderRequest::ListEncryptionCertificates(cursor, limit),
+                };
+                (request, cursor_offset + limit_offset)
+            }
+            22 => (CertificateServiceBinderRequest::Reload, 0),
+            23 => (CertificateServiceBinderRequest::GetPolicy, 0),
+            24 => {
+                let (policy, offset) = CertificatePolicy::from_serialized(&data)?;
+                (CertificateServiceBinderRequest::SetPolicy(policy), offset)
+            }
+            25 => {
+                let (subject, offset) = PolicySubject::from_serialized(&data)?;
+                (CertificateServiceBinderRequest::EvaluatePolicy(subject), offset)
+            }
+            26 => (CertificateServiceBinderRequest::IsDirty, 0),
+            27 => (CertificateServiceBinderRequest::Flush, 0),
+            28 => {
+                let (revocation, offset) = CertificateRevocation::from_serialized(&data)?;
+                (CertificateServiceBinderRequest::AddRevocation(revocation), offset)
+            }
+            29 => {
+                let (serial, offset) = u128::from_serialized(&data)?;
+                (CertificateServiceBinderRequest::IsRevoked(serial), offset)
+            }
+            30 => (CertificateServiceBinderRequest::GetRevocations, 0),
+            _ => return Err(SerializationError::InvalidDataError("Unknown certificate service request")),
+        };
+        Ok((request, offset + 1))
+    }
+}
+
+impl Serializable for CertificateServiceBinderResponse {
+    fn serialize(&self) -> Serialized {
+        let mut result = Serialized::new();
+        match self {
+            CertificateServiceBinderResponse::Falcon1024Cert(certificate) => {
+                result.extend(0u8.serialize());
+                result.extend(certificate.serialize());
+            }
+            CertificateServiceBinderResponse::Kyber1024Cert(certificate) => {
+                result.extend(1u8.serialize());
+                result.extend(certificate.serialize());
+            }
+            CertificateServiceBinderResponse::RootCert(certificate) => {
+                result.extend(2u8.serialize());
+                result.extend(certificate.serialize());
+            }
+            CertificateServiceBinderResponse::Falcon1024Certs(certificates) => {
+                result.extend(3u8.serialize());
+                result.extend(certificates.serialize());
+            }
+            CertificateServiceBinderResponse::Kyber1024Certs(certificates) => {
+                result.extend(4u8.serialize());
+                result.extend(certificates.serialize());
+            }
+            CertificateServiceBinderResponse::Status(status) => {
+                result.extend(5u8.serialize());
+                result.extend(status.serialize());
+            }
+            CertificateServiceBinderResponse::Statuses(statuses) => {
+                result.extend(6u8.serialize());
+                result.extend(statuses.serialize());
+            }
+            CertificateServiceBinderResponse::KeyUsages(usages) => {
+                result.extend(7u8.serialize());
+                result.extend(usages.serialize());
+            }
+            CertificateServiceBinderResponse::Thresholds(thresholds) => {
+                result.extend(8u8.serialize());
+                result.extend(thresholds.serialize());
+            }
+            CertificateServiceBinderResponse::Rejected(error) => {
+                result.extend(9u8.serialize());
+                let code: u8 = match error {
+                    CertificateServiceError::ReadOnly => 0,
+                    CertificateServiceError::ReloadUnsupported => 1,
+                    CertificateServiceError::ReloadFailed => 2,
+                    CertificateServiceError::CommitFailed => 3,
+                };
+                result.extend(code.serialize());
+            }
+            CertificateServiceBinderResponse::Page(page) => {
+                result.extend(10u8.serialize());
+                result.extend(page.serialize());
+            }
+            CertificateServiceBinderResponse::Reloaded(report) => {
+                result.extend(11u8.serialize());
+                result.extend(report.serialize());
+            }
+            CertificateServiceBinderResponse::Policy(policy) => {
+                result.extend(12u8.serialize());
+                result.extend(policy.serialize());
+            }
+            CertificateServiceBinderResponse::PolicyDecision(error) => {
+                result.extend(13u8.serialize());
+                result.extend(error.serialize());
+            }
+            CertificateServiceBinderResponse::Revocations(revocations) => {
+                result.extend(14u8.serialize());
+                result.extend(revocations.serialize());
+            }
+        }
+        result
+    }
+}
+
+impl Deserializable for CertificateServiceBinderResponse {
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        if serialized.is_empty(){
+            return Err(SerializationError::LengthError);
+        }
+        let data = serialized[1..].to_vec();
+        let (response, offset) = match serialized[0] {
+            0 => {
+                let (certificate, offset) = Option::<Falcon1024Certificate>::from_serialized(&data)?;
+                (CertificateServiceBinderResponse::Falcon1024Cert(certificate), offset)
+            }
+            1 => {
+                let (certificate, offset) = Option::<Kyber1024Certificate>::from_serialized(&data)?;
+                (CertificateServiceBinderResponse::Kyber1024Cert(certificate), offset)
+            }
+            2 => {
+                let (certificate, offset) = Option::<Falcon1024RootCertificate>::from_serialized(&data)?;
+                (CertificateServiceBinderResponse::RootCert(certificate), offset)
+            }
+            3 => {
+                let (certificates, offset) = Vec::<Falcon1024Certificate>::from_serialized(&data)?;
+                (CertificateServiceBinderResponse::Falcon1024Certs(certificates), offset)
+            }
+            4 => {
+                let (certificates, offset) = Vec::<Kyber1024Certificate>::from_serialized(&data)?;
+                (CertificateServiceBinderResponse::Kyber1024Certs(certificates), offset)
+            }
+            5 => {
+                let (status, offset) = bool::from_serialized(&data)?;
+                (CertificateServiceBinderResponse::Status(status), offset)
+            }
+            6 => {
+                let (statuses, offset) = Vec::<bool>::from_serialized(&data)?;
+                (CertificateServiceBinderResponse::Statuses(statuses), offset)
+            }
+            7 => {
+                let (usages, offset) = Vec::<KeyUsage>::from_serialized(&data)?;
+                (CertificateServiceBinderResponse::KeyUsages(usages), offset)
+            }
+            8 => {
+                let (thresholds, offset) = UsageThresholds::from_serialized(&data)?;
+                (CertificateServiceBinderResponse::Thresholds(thresholds), offset)
+            }
+            9 => {
+                let (code, offset) = u8::from_serialized(&data)?;
+                let error = match code {
+                    0 => CertificateServiceError::ReadOnly,
+                    1 => CertificateServiceError::ReloadUnsupported,
+                    2 => CertificateServiceError::ReloadFailed,
+                    3 => CertificateServiceError::CommitFailed,
+                    _ => return Err(SerializationError::InvalidDataError("Unknown certificate service error")),
+                };
+                (CertificateServiceBinderResponse::Rejected(error), offset)
+            }
+            10 => {
+                let (page, offset) = CertificatePage::from_serialized(&data)?;
+                (CertificateServiceBinderResponse::Page(page), offset)
+            }
+            11 => {
+                let (report, offset) = CertificateReloadReport::from_serialized(&data)?;
+                (CertificateServiceBinderResponse::Reloaded(report), offset)
+            }
+            12 => {
+                let (policy, offset) = CertificatePolicy::from_serialized(&data)?;
+                (CertificateServiceBinderResponse::Policy(policy), offset)
+            }
+            13 => {
+                let (error, offset) = Option::<PolicyError>::from_serialized(&data)?;
+                (CertificateServiceBinderResponse::PolicyDecision(error), offset)
+            }
+            14 => {
+                let (revocations, offset) = Vec::<CertificateRevocation>::from_serialized(&data)?;
+                (CertificateServiceBinderResponse::Revocations(revocations), offset)
+            }
+            _ => return Err(SerializationError::InvalidDataError("Unknown certificate service response")),
+        };
+        Ok((response, offset + 1))
+    }
+}
+
+///
+/// Call of certificate service sent to broker
+///
+#[derive(Serializable, Deserializable, Describe)]
+pub struct RemoteCertificateRequest{
+    pub request_id: u128,
+    pub request: CertificateServiceBinderRequest,
+}
+
+///
+/// Result of call of certificate service
+///
+#[derive(Serializable, Deserializable, Describe)]
+pub struct RemoteCertificateResponse{
+    pub request_id: u128,
+    /** None if request is not allowed by policy of broker **/
+    pub response: Option<CertificateServiceBinderResponse>,
+}
+
+impl AsMessage for RemoteCertificateRequest{
+    fn as_message(&self) -> Message {
+        Message{
+            id: 0,
+            timestamp: 0,
+            message_type: MessageType::CertificateServiceRequest,
+            data: Some(self.serialize()),
+            signature: None,
+            source: 0,
+            destination: 0,
+            module_id: 0,
+            certificate_id: 0,
+        }
+    }
+}
+
+impl AsMessage for RemoteCertificateResponse{
+    fn as_message(&self) -> Message {
+        Message{
+            id: 0,
+            timestamp: 0,
+            message_type: MessageType::CertificateServiceResponse,
+            data: Some(self.serialize()),
+            signature: None,
+            source: 0,
+            destination: 0,
+            module_id: 0,
+            certificate_id: 0,
+        }
+    }
+}
+
+///
+/// Decides which calls peers may make to certificate service of broker.
+/// Requests must be signed by a certificate with FLAG_REMOTE_CERTIFICATES, reads are
+/// denied by FLAG_NO_READ and writes by FLAG_NO_WRITE. Root certificate can never be set and
+/// read-only mode can never be switched remotely.
+///
+#[derive(Clone, Debug, Default)]
+pub struct RemoteCertificatePolicy{
+    /** Whether peers may add or remove certificates **/
+    pub allow_write: bool,
+}
+
+impl RemoteCertificatePolicy {
+    ///
+    /// Checks whether holder of certificate may make a request
+    ///
+    /// # Arguments
+    /// * certificate: &Falcon1024Certificate: certificate which signed request
+    /// * request: &CertificateServiceBinderRequest: request to check
+    ///
+    pub fn is_allowed(&self, certificate: &Falcon1024Certificate, request: &CertificateServiceBinderRequest) -> bool{
+        if !certificate.check_flag(FLAG_REMOTE_CERTIFICATES){
+            return false;
+        }
+        match request {
+            CertificateServiceBinderRequest::SetSigningCertificate(_) |
+            CertificateServiceBinderRequest::SetReadOnly(_) => false,
+            CertificateServiceBinderRequest::AddEncryptionCertificate(_) |
+            CertificateServiceBinderRequest::AddSigningCertificate(_) |
+            CertificateServiceBinderRequest::RemoveSigningCertificate(_) |
+            CertificateServiceBinderRequest::RemoveEncryptionCertificate(_) |
+            CertificateServiceBinderRequest::RecordKeyUsage(_) |
+            CertificateServiceBinderRequest::SetUsageThresholds(_) |
+            CertificateServiceBinderRequest::SetPolicy(_) |
+            CertificateServiceBinderRequest::Reload |
+            CertificateServiceBinderRequest::Commit |
+            CertificateServiceBinderRequest::Flush => self.allow_write && !certificate.check_flag(FLAG_NO_WRITE),
+            _ => !certificate.check_flag(FLAG_NO_READ),
+        }
+    }
+}
+
+///
+/// Exposes certificate service of broker to peers: answers CertificateServiceRequest
+/// messages according to policy
+///
+pub struct RemoteCertificateServer{
+    service: Box<CertificateServiceBinder>,
+    sender: Box<dyn TransportSender>,
+    policy: RemoteCertificatePolicy,
+    host_id: u128,
+}
+
+impl RemoteCertificateServer {
+    ///
+    /// Creates a server
+    ///
+    /// # Arguments
+    /// * service: Box<CertificateServiceBinder>: certificate service to expose
+    /// * sender: Box<dyn TransportSender>: sender of responses
+    /// * policy: RemoteCertificatePolicy: allowed calls
+    /// * host_id: u128: ID of broker
+    ///
+    pub fn new(service: Box<CertificateServiceBinder>, sender: Box<dyn TransportSender>,
+               policy: RemoteCertificatePolicy, host_id: u128) -> RemoteCertificateServer{
+        RemoteCertificateServer{
+            service,
+            sender,
+            policy,
+            host_id,
+        }
+    }
+
+    ///
+    /// Gets certificate which signed message, if it is trusted and signature is valid
+    ///
+    fn authenticate(&mut self, message: &Message) -> Option<Falcon1024Certificate>{
+        let signature = message.signature.as_ref()?;
+        let certificate = self.service.get_signing_certificate(message.get_signer_serial())?;
+        if !certificate.check_flag(FLAG_SIGN_MESSAGES) || !self.service.verify_signing_certificate(&certificate){
+            return None;
+        }
+        if !certificate.verify_signature(&message.as_signable(), signature){
+            return None;
+        }
+        Some(certificate)
+    }
+}
+
+impl TransportListener for RemoteCertificateServer{
+    fn on_message(&mut self, message: Message) {
+        if message.message_type != MessageType::CertificateServiceRequest{
+            return;
+        }
+        let request = match message.data.as_ref().map(RemoteCertificateRequest::from_serialized) {
+            Some(Ok((request, _))) => request,
+            _ => {
+                log::warn!("Malformed certificate service request from {}", message.source);
+                return;
+            }
+        };
+        let allowed = match self.authenticate(&message) {
+            Some(certificate) => self.policy.is_allowed(&certificate, &request.request),
+            None => false,
+        };
+        let response = if allowed{
+            Some(self.service.handle_request(request.request))
+        } else {
+            log::warn!("Denied certificate service request from {} signed by {}",
+                message.source, message.certificate_id);
+            None
+        };
+        let reply = MessageBuilder::from_payload(&RemoteCertificateResponse{
+            request_id: request.request_id,
+            response,
+        }).set_source(self.host_id)
+            .set_destination(message.source)
+            .set_module_id(message.module_id)
+            .build()
+            .expect("All required fields are set");
+        self.sender.send_message(reply);
+    }
+}
+
+type PendingResponses = Arc<(Mutex<HashMap<u128, Option<CertificateServiceBinderResponse>>>, Condvar)>;
+
+///
+/// Collects responses of broker for RemoteCertificateService
+///
+struct RemoteCertificateResponseListener{
+    responses: PendingResponses,
+}
+
+impl TransportListener for RemoteCertificateResponseListener{
+    fn on_message(&mut self, message: Message) {
+        if message.message_type != MessageType::CertificateServiceResponse{
+            return;
+        }
+        if let Some(Ok((response, _))) = message.data.as_ref().map(RemoteCertificateResponse::from_serialized){
+            let (responses, condvar) = &*self.responses;
+            responses.lock().unwrap().insert(response.request_id, response.response);
+            condvar.notify_all();
+        }
+    }
+}
+
+///
+/// Certificate service without local store: calls are proxied to certificate service
+/// of a trusted broker. Root certificate and verified certificates are cached, so chains
+/// verified once are verified locally afterwards.
+///
+/// # Note
+/// Cached certificates are not revoked when they are removed on broker, use clear_cache
+/// after receiving revocations.
+///
+pub struct RemoteCertificateService{
+    sender: Box<dyn TransportSender>,
+    responses: PendingResponses,
+    signing_certificate: Falcon1024Certificate,
+    host_id: u128,
+    broker_id: u128,
+    timeout: Duration,
+    root_certificate: Option<Falcon1024RootCertificate>,
+    signing_certificates: HashMap<u128, Falcon1024Certificate>,
+    encryption_certificates: HashMap<u128, Kyber1024Certificate>,
+}
+
+impl RemoteCertificateService {
+    ///
+    /// Creates a service proxying calls to broker
+    ///
+    /// # Arguments
+    /// * transport: &mut dyn TransportService: transport connected to broker
+    /// * host_id: u128: ID of this host
+    /// * broker_id: u128: ID of broker
+    /// * signing_certificate: Falcon1024Certificate: certificate with secret key to sign requests,
+    ///   must have FLAG_REMOTE_CERTIFICATES
+    ///
+    pub fn new(transport: &mut dyn TransportService, host_id: u128, broker_id: u128,
+               signing_certificate: Falcon1024Certificate) -> RemoteCertificateService{
+        let responses: PendingResponses = Arc::new((Mutex::new(HashMap::new()), Condvar::new()));
+        let mut filter = MessageFilter::new();
+        filter.filter_from(broker_id);
+        transport.subscribe_to_messages(&filter, Box::new(RemoteCertificateResponseListener{
+            responses: responses.clone(),
+        }));
+        RemoteCertificateService{
+            sender: transport.get_sender(),
+            responses,
+            signing_certificate,
+            host_id,
+            broker_id,
+            timeout: DEFAULT_REMOTE_CERTIFICATE_TIMEOUT,
+            root_certificate: None,
+            signing_certificates: HashMap::new(),
+            encryption_certificates: HashMap::new(),
+        }
+    }
+
+    ///
+    /// Sets time to wait for response of broker
+    ///
+    #[inline]
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self{
+        self.timeout = timeout;
+        self
+    }
+
+    ///
+    /// Removes all cached certificates
+    ///
+    pub fn clear_cache(&mut self){
+        self.root_certificate = None;
+        self.signing_certificates.clear();
+        self.encryption_certificates.clear();
+    }
+
+    ///
+    /// Sends request to broker and waits for response
+    ///
+    /// returns: Option<CertificateServiceBinderResponse>: response or None if request is denied
+    /// or broker did not respond in time
+    ///
+    fn request(&mut self, request: CertificateServiceBinderRequest) -> Option<CertificateServiceBinderResponse>{
+        let request_id: u128 = rand::random();
+        let secret_key = match &self.signing_certificate.secret_key {
+            Some(secret_key) => secret_key,
+            None => {
+                log::error!("Certificate {} used for remote certificate service has no secret key",
+                    self.signing_certificate.get_serial());
+                return None;
+            }
+        };
+        let message = MessageBuilder::from_payload(&RemoteCertificateRequest{
+            request_id,
+            request,
+        }).set_source(self.host_id)
+            .set_destination(self.broker_id)
+            .set_module_id(CORE_MODULE_ID)
+            .set_certificate_id(self.signing_certificate.get_serial())
+            .build_signed(secret_key, HashType::None);
+        let message = match message {
+            Ok(message) => message,
+            Err(error) => {
+                log::error!("Can not build certificate service request: {}", error);
+                return None;
+            }
+        };
+        self.sender.send_message(message);
+        let (responses, condvar) = &*self.responses;
+        let deadline = Instant::now() + self.timeout;
+        let mut responses = responses.lock().unwrap();
+        loop {
+            if let Some(response) = responses.remove(&request_id){
+                if response.is_none(){
+                    log::warn!("Broker {} denied certificate service request", self.broker_id);
+                }
+                return response;
+            }
+            let now = Instant::now();
+            if now >= deadline{
+                log::warn!("Broker {} did not respond to certificate service request", self.broker_id);
+                return None;
+            }
+            responses = condvar.wait_timeout(responses, deadline - now).unwrap().0;
+        }
+    }
+
+    ///
+    /// Builds a chain of cached certificates
+    ///
+    fn get_cached_chain(&mut self) -> CertificateChain{
+        let mut chain = CertificateChain::new();
+        if let Some(root) = self.get_root_certificate(){
+            chain.set_root_certificate(root);
+        }
+        for certificate in self.signing_certificates.values(){
+            chain.add_signing_certificate(certificate.clone());
+        }
+        chain
+    }
+}
+
+impl CertificateService for RemoteCertificateService {
+    fn set_root_certificate(&mut self, _root_cert: Falcon1024RootCertificate) {
+        log::error!("Root certificate can not be set through remote certificate service");
+    }
+
+    fn add_signing_certificate(&mut self, cert: Falcon1024Certificate) -> bool {
+        matches!(self.request(CertificateServiceBinderRequest::AddSigningCertificate(cert)),
+            Some(CertificateServiceBinderResponse::Status(true)))
+    }
+
+    fn add_encryption_certificate(&mut self, cert: Kyber1024Certificate) -> bool {
+        matches!(self.request(CertificateServiceBinderRequest::AddEncryptionCertificate(cert)),
+            Some(CertificateServiceBinderResponse::Status(true)))
+    }
+
+    fn verify_signing_certificate(&mut self, cert: &Falcon1024Certificate) -> bool {
+        if self.signing_certificates.get(&cert.get_serial()) == Some(cert){
+            return true;
+        }
+        if self.get_cached_chain().verify_signing_certificate(cert).is_ok(){
+            self.signing_certificates.insert(cert.get_serial(), cert.clone_without_sk());
+            return true;
+        }
+        let is_valid = matches!(self.request(CertificateServiceBinderRequest::VerifySigningCertificate(cert.clone_without_sk())),
+            Some(CertificateServiceBinderResponse::Status(true)));
+        if is_valid{
+            self.signing_certificates.insert(cert.get_serial(), cert.clone_without_sk());
+        }
+        is_valid
+    }
+
+    fn verify_encryption_certificate(&mut self, cert: &Kyber1024Certificate) -> bool {
+        if self.encryption_certificates.get(&cert.get_serial()) == Some(cert){
+            return true;
+        }
+        let is_valid = self.get_cached_chain().verify_encryption_certificate(cert).is_ok()
+            || matches!(self.request(CertificateServiceBinderRequest::VerifyEncryptionCertificate(cert.clone_without_sk())),
+                Some(CertificateServiceBinderResponse::Status(true)));
+        if is_valid{
+            self.encryption_certificates.insert(cert.get_serial(), cert.clone_without_sk());
+        }
+        is_valid
+    }
+
+    fn verify_many(&mut self, certs: &[VerifiableCertificate]) -> Vec<bool> {
+        let chain = self.get_cached_chain();
+        let mut result = Vec::with_capacity(certs.len());
+        let mut unknown = Vec::<usize>::new();
+        for (index, cert) in certs.iter().enumerate(){
+            let is_valid = match cert {
+                VerifiableCertificate::Signing(cert) => self.signing_certificates.get(&cert.get_serial()) == Some(&**cert)
+                    || chain.verify_signing_certificate(cert).is_ok(),
+                VerifiableCertificate::Encryption(cert) => self.encryption_certificates.get(&cert.get_serial()) == Some(&**cert)
+                    || chain.verify_encryption_certificate(cert).is_ok(),
+            };
+            if !is_valid{
+                unknown.push(index);
+            }
+            result.push(is_valid);
+        }
+        // Certificates which can not be verified locally are sent to broker in one request
+        if !unknown.is_empty(){
+            let request = unknown.iter().map(|index| match &certs[*index] {
+                VerifiableCertificate::Signing(cert) => VerifiableCertificate::Signing(Box::new(cert.clone_without_sk())),
+                VerifiableCertificate::Encryption(cert) => VerifiableCertificate::Encryption(Box::new(cert.clone_without_sk())),
+            }).collect();
+            if let Some(CertificateServiceBinderResponse::Statuses(statuses)) =
+                self.request(CertificateServiceBinderRequest::VerifyMany(request)){
+                for (index, is_valid) in unknown.into_iter().zip(statuses){
+                    result[index] = is_valid;
+                }
+            }
+        }
+        for (cert, is_valid) in certs.iter().zip(result.iter()){
+            if !*is_valid{
+                continue;
+            }
+            match cert {
+                VerifiableCertificate::Signing(cert) => {
+                    self.signing_certificates.insert(cert.get_serial(), cert.clone_without_sk());
+                }
+                VerifiableCertificate::Encryption(cert) => {
+                    self.encryption_certificates.insert(cert.get_serial(), cert.clone_without_sk());
+                }
+            }
+        }
+        result
+    }
+
+    fn get_signing_certificate(&mut self, serial: u128) -> Option<Falcon1024Certificate> {
+        if let Some(certificate) = self.signing_certificates.get(&serial){
+            return Some(certificate.clone());
+        }
+        // Certificates in store of broker are verified on insertion
+        match self.request(CertificateServiceBinderRequest::GetSigningCertificate(serial)) {
+            Some(CertificateServiceBinderResponse::Falcon1024Cert(Some(certificate))) => {
+                self.signing_certificates.insert(serial, certificate.clone());
+                Some(certificate)
+            }
+            _ => None,
+        }
+    }
+
+    fn get_encryption_certificate(&mut self, serial: u128) -> Option<Kyber1024Certificate> {
+        if let Some(certificate) = self.encryption_certificates.get(&serial){
+            return Some(certificate.clone());
+        }
+        match self.request(CertificateServiceBinderRequest::GetEncryptionCertificate(serial)) {
+            Some(CertificateServiceBinderResponse::Kyber1024Cert(Some(certificate))) => {
+                self.encryption_certificates.insert(serial, certificate.clone());
+                Some(certificate)
+            }
+            _ => None,
+        }
+    }
+
+    fn get_root_certificate(&mut self) -> Option<Falcon1024RootCertificate> {
+        if self.root_certificate.is_none(){
+            if let Some(CertificateServiceBinderResponse::RootCert(root)) =
+                self.request(CertificateServiceBinderRequest::GetRootCertificate){
+                self.root_certificate = root;
+            }
+        }
+        self.root_certificate.clone()
+    }
+
+    fn get_signing_certificates(&mut self) -> Vec<Falcon1024Certificate> {
+        match self.request(CertificateServiceBinderRequest::GetSigningCertificates) {
+            Some(CertificateServiceBinderResponse::Falcon1024Certs(certificates)) => certificates,
+            _ => Vec::new(),
+        }
+    }
+
+    fn get_encryption_certificates(&mut self) -> Vec<Kyber1024Certificate> {
+        match self.request(CertificateServiceBinderRequest::GetEncryptionCertificates) {
+            Some(CertificateServiceBinderResponse::Kyber1024Certs(certificates)) => certificates,
+            _ => Vec::new(),
+        }
+    }
+
+    fn list_signing_certificates(&mut self, cursor: Option<u128>, limit: u32) -> CertificatePage {
+        match self.request(CertificateServiceBinderRequest::ListSigningCertificates(cursor, limit)) {
+            Some(CertificateServiceBinderResponse::Page(page)) => page,
+            _ => CertificatePage::default(),
+        }
+    }
+
+    fn list_encryption_certificates(&mut self, cursor: Option<u128>, limit: u32) -> CertificatePage {
+        match self.request(CertificateServiceBinderRequest::ListEncryptionCertificates(cursor, limit)) {
+            Some(CertificateServiceBinderResponse::Page(page)) => page,
+            _ => CertificatePage::default(),
+        }
+    }
+
+    fn remove_signing_certificate(&mut self, serial: u128) -> bool {
+        self.signing_certificates.remove(&serial);
+        matches!(self.request(CertificateServiceBinderRequest::RemoveSigningCertificate(serial)),
+            Some(CertificateServiceBinderResponse::Status(true)))
+    }
+
+    fn remove_encryption_certificate(&mut self, serial: u128) -> bool {
+        self.encryption_certificates.remove(&serial);
+        matches!(self.request(CertificateServiceBinderRequest::RemoveEncryptionCertificate(serial)),
+            Some(CertificateServiceBinderResponse::Status(true)))
+    }
+
+    fn record_key_usage(&mut self, usage: KeyUsage) -> bool {
+        matches!(self.request(CertificateServiceBinderRequest::RecordKeyUsage(usage)),
+            Some(CertificateServiceBinderResponse::Status(true)))
+    }
+
+    fn get_key_usage(&mut self) -> Vec<KeyUsage> {
+        match self.request(CertificateServiceBinderRequest::GetKeyUsage) {
+            Some(CertificateServiceBinderResponse::KeyUsages(usages)) => usages,
+            _ => Vec::new(),
+        }
+    }
+
+    fn set_usage_thresholds(&mut self, thresholds: UsageThresholds) {
+        self.request(CertificateServiceBinderRequest::SetUsageThresholds(thresholds));
+    }
+
+    fn get_usage_thresholds(&mut self) -> UsageThresholds {
+        match self.request(CertificateServiceBinderRequest::GetUsageThresholds) {
+            Some(CertificateServiceBinderResponse::Thresholds(thresholds)) => thresholds,
+            _ => UsageThresholds::default(),
+        }
+    }
+
+    fn is_read_only(&mut self) -> bool {
+        matches!(self.request(CertificateServiceBinderRequest::IsReadOnly),
+            Some(CertificateServiceBinderResponse::Status(true)))
+    }
+
+    fn reload(&mut self) -> Result<CertificateReloadReport, CertificateServiceError> {
+        match self.request(CertificateServiceBinderRequest::Reload) {
+            Some(CertificateServiceBinderResponse::Reloaded(report)) => {
+                // Cached certificates may be replaced or removed in store of broker
+                self.root_certificate = None;
+                self.signing_certificates.clear();
+                self.encryption_certificates.clear();
+                Ok(report)
+            }
+            Some(CertificateServiceBinderResponse::Rejected(error)) => Err(error),
+            _ => Err(CertificateServiceError::ReloadFailed),
+        }
+    }
+
+    fn get_policy(&mut self) -> CertificatePolicy {
+        match self.request(CertificateServiceBinderRequest::GetPolicy) {
+            Some(CertificateServiceBinderResponse::Policy(policy)) => policy,
+            _ => CertificatePolicy::default(),
+        }
+    }
+
+    // Policy of broker is not cached, so it is evaluated by broker only
+    fn evaluate_policy(&mut self, subject: &PolicySubject) -> Result<(), PolicyError> {
+        match self.request(CertificateServiceBinderRequest::EvaluatePolicy(subject.clone())) {
+            Some(CertificateServiceBinderResponse::PolicyDecision(None)) => Ok(()),
+            Some(CertificateServiceBinderResponse::PolicyDecision(Some(error))) => Err(error),
+            _ => Err(PolicyError::Unsupported),
+        }
+    }
+
+    fn add_revocation(&mut self, revocation: CertificateRevocation) -> bool {
+        self.signing_certificates.remove(&revocation.serial);
+        self.encryption_certificates.remove(&revocation.serial);
+        matches!(self.request(CertificateServiceBinderRequest::AddRevocation(revocation)),
+            Some(CertificateServiceBinderResponse::Status(true)))
+    }
+
+    // Revocations are not cached, so certificate revoked on broker is never trusted from cache
+    fn is_revoked(&mut self, serial: u128) -> bool {
+        matches!(self.request(CertificateServiceBinderRequest::IsRevoked(serial)),
+            Some(CertificateServiceBinderResponse::Status(true)))
+    }
+
+    fn get_revocations(&mut self) -> Vec<CertificateRevocation> {
+        match self.request(CertificateServiceBinderRequest::GetRevocations) {
+            Some(CertificateServiceBinderResponse::Revocations(revocations)) => revocations,
+            _ => Vec::new(),
+        }
+    }
+
+    fn commit(&mut self) {
+        if self.request(CertificateServiceBinderRequest::Commit).is_none(){
+            log::warn!("Changes of certificates are not committed by broker {}", self.broker_id);
+        }
+    }
+
+    fn is_dirty(&mut self) -> bool {
+        matches!(self.request(CertificateServiceBinderRequest::IsDirty),
+            Some(CertificateServiceBinderResponse::Status(true)))
+    }
+
+    fn flush(&mut self) -> Result<(), CertificateServiceError> {
+        match self.request(CertificateServiceBinderRequest::Flush) {
+            Some(CertificateServiceBinderResponse::Status(_)) => Ok(()),
+            Some(CertificateServiceBinderResponse::Rejected(error)) => Err(error),
+            _ => Err(CertificateServiceError::CommitFailed),
+        }
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actor::binder::BinderChannelProvider;
+    use crate::actor::binder::coroutine::BinderAsyncService;
+    use crate::pki::certificate::FLAG_SIGN_CERTS;
+    use crate::services::impls::certificate::AsyncCertificateServiceImpl;
+    use crate::testing::certificate::{test_certificates, TEST_ENCRYPTION_CERTIFICATE_SERIAL,
+                                      TEST_SIGNING_CERTIFICATE_SERIAL};
+    use crate::testing::transport::LoopbackTransportService;
+    use crate::tokio::init_tokio;
+
+    fn create_broker(transport: &mut LoopbackTransportService, client_flags: u128,
+                     policy: RemoteCertificatePolicy) -> Falcon1024Certificate{
+        let certificates = test_certificates();
+        let mut client_certificate = certificates.signing.clone();
+        client_certificate.flags = client_flags;
+        // Flags are part of signed data, so certificate is signed again by root
+        client_certificate.signature = Some(certificates.root.sign_data(&client_certificate.clone_without_signature_and_sk(),
+                                                                        HashType::None).unwrap());
+        let mut service = BinderAsyncService::run(Box::new(AsyncCertificateServiceImpl::new("/tmp/test_remote.dat")));
+        let mut binder = service.bind();
+        binder.set_root_certificate(certificates.root.clone_without_sk());
+        assert!(binder.add_signing_certificate(client_certificate.clone_without_sk()));
+        assert!(binder.add_encryption_certificate(certificates.encryption.clone_without_sk()));
+        let server = RemoteCertificateServer::new(binder, transport.get_sender(), policy,
+                                                  transport.get_host_id());
+        transport.subscribe_to_messages(&MessageFilter::new(), Box::new(server));
+        client_certificate
+    }
+
+    #[test]
+    fn test_request_serialization() {
+        let request = CertificateServiceBinderRequest::RemoveEncryptionCertificate(42);
+        let (deserialized, size) = CertificateServiceBinderRequest::from_serialized(&request.serialize()).unwrap();
+        assert!(matches!(deserialized, CertificateServiceBinderRequest::RemoveEncryptionCertificate(42)));
+        assert_eq!(size, request.serialize().len());
+        let response = CertificateServiceBinderResponse::Falcon1024Certs(vec![test_certificates().signing]);
+        let (deserialized, _) = CertificateServiceBinderResponse::from_serialized(&response.serialize()).unwrap();
+        assert!(matches!(deserialized, CertificateServiceBinderResponse::Falcon1024Certs(certificates)
+            if certificates.len() == 1));
+    }
+
+    #[test]
+    fn test_remote_certificate_service() {
+        init_tokio();
+        let (mut broker, mut client) = LoopbackTransportService::pair(1, 2);
+        let flags = FLAG_SIGN_MESSAGES | FLAG_SIGN_CERTS | FLAG_REMOTE_CERTIFICATES;
+        let certificate = create_broker(&mut broker, flags, RemoteCertificatePolicy::default());
+        let mut service = RemoteCertificateService::new(&mut client, 2, 1, certificate);
+        assert!(service.get_root_certificate().is_some());
+        let signing = service.get_signing_certificate(TEST_SIGNING_CERTIFICATE_SERIAL).unwrap();
+        let encryption = service.get_encryption_certificate(TEST_ENCRYPTION_CERTIFICATE_SERIAL).unwrap();
+        // Writes are not allowed by default policy
+        assert!(!service.add_signing_certificate(signing.clone()));
+
+        // Certificates are verified against cached chain without asking broker
+        service.clear_cache();
+        assert!(service.verify_signing_certificate(&signing));
+        let sent = client.sent_messages().len();
+        assert!(service.verify_signing_certificate(&signing));
+        assert!(service.verify_encryption_certificate(&encryption));
+        assert_eq!(client.sent_messages().len(), sent);
+    }
+
+    #[test]
+    fn test_remote_request_requires_flag() {
+        init_tokio();
+        let (mut broker, mut client) = LoopbackTransportService::pair(1, 2);
+        let certificate = create_broker(&mut broker, FLAG_SIGN_MESSAGES | FLAG_SIGN_CERTS,
+                                        RemoteCertificatePolicy{ allow_write: true });
+        let mut service = RemoteCertificateService::new(&mut client, 2, 1, certificate);
+        service.set_timeout(Duration::from_millis(100));
+        assert!(service.get_root_certificate().is_none());
+        assert!(service.get_signing_certificates().is_empty());
+    }
+}
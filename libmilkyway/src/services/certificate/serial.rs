@@ -0,0 +1,74 @@
+use rand::rngs::OsRng;
+use rand::RngCore;
+use crate::services::certificate::{CertificateService, ROOT_CERTIFICATE_SERIAL};
+
+///
+/// How many random serials are tried before giving up. Collisions of random u128 are
+/// practically impossible, so running out of attempts means random source is broken.
+///
+const SERIAL_GENERATION_ATTEMPTS: usize = 16;
+
+///
+/// Checks whether serial is taken by root, signing or encryption certificate
+///
+/// # Arguments
+/// * service: &mut S: service with known certificates
+/// * serial: u128: serial to check
+///
+pub fn is_serial_taken<S: CertificateService + ?Sized>(service: &mut S, serial: u128) -> bool{
+    serial == ROOT_CERTIFICATE_SERIAL
+        || service.get_signing_certificate(serial).is_some()
+        || service.get_encryption_certificate(serial).is_some()
+}
+
+///
+/// Generates serial of new certificate from operating system random source. Serial is
+/// never zero(reserved for root certificate) and is not used by any known certificate.
+///
+/// # Arguments
+/// * service: &mut S: service with known certificates
+///
+/// returns: Option<u128>: serial or None if no free serial was found
+///
+pub fn generate_serial<S: CertificateService + ?Sized>(service: &mut S) -> Option<u128>{
+    generate_serial_with(service, || {
+        let mut bytes = [0u8; 16];
+        OsRng.fill_bytes(&mut bytes);
+        u128::from_be_bytes(bytes)
+    })
+}
+
+fn generate_serial_with<S: CertificateService + ?Sized, R: FnMut() -> u128>(service: &mut S,
+                                                                            mut random: R) -> Option<u128>{
+    for _ in 0..SERIAL_GENERATION_ATTEMPTS{
+        let serial = random();
+        if !is_serial_taken(service, serial){
+            return Some(serial);
+        }
+        log::warn!("Generated serial {} is already taken, trying another one", serial);
+    }
+    None
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::certificate::{MockCertificateService, TEST_ENCRYPTION_CERTIFICATE_SERIAL,
+                                      TEST_SIGNING_CERTIFICATE_SERIAL};
+
+    #[test]
+    fn test_generate_serial() {
+        let mut service = MockCertificateService::with_test_certificates();
+        assert!(is_serial_taken(&mut service, ROOT_CERTIFICATE_SERIAL));
+        assert!(is_serial_taken(&mut service, TEST_SIGNING_CERTIFICATE_SERIAL));
+        assert!(is_serial_taken(&mut service, TEST_ENCRYPTION_CERTIFICATE_SERIAL));
+        let serial = generate_serial(&mut service).unwrap();
+        assert!(!is_serial_taken(&mut service, serial));
+        assert_ne!(generate_serial(&mut service), Some(serial));
+
+        let mut candidates = vec![ROOT_CERTIFICATE_SERIAL, TEST_SIGNING_CERTIFICATE_SERIAL, 77].into_iter();
+        assert_eq!(generate_serial_with(&mut service, || candidates.next().unwrap()), Some(77));
+        assert_eq!(generate_serial_with(&mut service, || TEST_ENCRYPTION_CERTIFICATE_SERIAL), None);
+    }
+}
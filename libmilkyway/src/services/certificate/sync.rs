@@ -0,0 +1,383 @@
+use crate::message::builder::MessageBuilder;
+use crate::message::certsync::{CertificateRevocation, CertificateSyncEntry, CertificateSyncMessage};
+use crate::pki::certificate::{Certificate, FLAG_SIGN_CERTS};
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use crate::pki::key::CryptoKey;
+use crate::services::certificate::{CertificateService, ROOT_CERTIFICATE_SERIAL};
+use crate::services::transport::TransportService;
+
+///
+/// Seconds between snapshots hosts send to their peers by default(see send_certificate_sync)
+///
+pub const DEFAULT_CERTIFICATE_SYNC_INTERVAL_SECONDS: u64 = 300;
+
+///
+/// Result of applying certificate sync message
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CertificateSyncReport{
+    /** Entries which changed local state **/
+    pub applied: usize,
+    /** Entries which are already known **/
+    pub unchanged: usize,
+    /** Entries which failed verification **/
+    pub rejected: usize,
+}
+
+enum SyncOutcome{
+    Applied,
+    Unchanged,
+    Rejected,
+}
+
+// Issue time of certificate, certificates without validity are older than any issued one
+fn get_issued_at<PK: CryptoKey, SK: CryptoKey, C: Certificate<PK, SK>>(certificate: &C) -> u64{
+    certificate.get_metadata().validity.map(|validity| validity.issued_at).unwrap_or(0)
+}
+
+///
+/// Adds or rotates a certificate of one kind. Certificates with local secret keys are
+/// never replaced by remote ones, rotation replaces certificate only if it is issued later,
+/// so replayed older certificates do not roll rotation back. Revoked serials are never added.
+///
+macro_rules! apply_certificate {
+    ($service:expr, $certificate:expr, $get:ident, $verify:ident, $add:ident, $remove:ident) => {{
+        let certificate = $certificate.clone_without_sk();
+        let serial = certificate.get_serial();
+        if $service.is_revoked(serial){
+            log::warn!("Refusing revoked certificate {}", serial);
+            return SyncOutcome::Rejected;
+        }
+        match $service.$get(serial) {
+            None => {
+                if $service.$add(certificate) { SyncOutcome::Applied } else { SyncOutcome::Rejected }
+            }
+            Some(existing) => {
+                if existing.clone_without_sk() == certificate{
+                    SyncOutcome::Unchanged
+                } else if existing.get_secret_key().is_some(){
+                    log::warn!("Refusing to replace local certificate {} by remote one", serial);
+                    SyncOutcome::Rejected
+                } else if get_issued_at(&certificate) <= get_issued_at(&existing){
+                    log::warn!("Refusing certificate {} which is not issued later than current one", serial);
+                    SyncOutcome::Rejected
+                } else if !$service.$verify(&certificate){
+                    SyncOutcome::Rejected
+                } else {
+                    $service.$remove(serial);
+                    if $service.$add(certificate){
+                        SyncOutcome::Applied
+                    } else {
+                        // Restore previous certificate
+                        $service.$add(existing);
+                        SyncOutcome::Rejected
+                    }
+                }
+            }
+        }
+    }};
+}
+
+fn apply_signing_certificate<S: CertificateService + ?Sized>(service: &mut S,
+                             certificate: &Falcon1024Certificate) -> SyncOutcome{
+    apply_certificate!(service, certificate, get_signing_certificate, verify_signing_certificate,
+        add_signing_certificate, remove_signing_certificate)
+}
+
+fn apply_encryption_certificate<S: CertificateService + ?Sized>(service: &mut S,
+                                certificate: &Kyber1024Certificate) -> SyncOutcome{
+    apply_certificate!(service, certificate, get_encryption_certificate, verify_encryption_certificate,
+        add_encryption_certificate, remove_encryption_certificate)
+}
+
+///
+/// Verifies that revocation is signed by issuer of revoked certificate
+///
+fn verify_revocation<S: CertificateService + ?Sized>(service: &mut S, revocation: &CertificateRevocation,
+                     parent_serial: Option<u128>) -> bool{
+    if parent_serial != Some(revocation.issuer_serial){
+        // Only issuer may revoke certificate
+        return false;
+    }
+    let signature = match &revocation.signature {
+        Some(signature) => signature,
+        None => return false,
+    };
+    if revocation.issuer_serial == ROOT_CERTIFICATE_SERIAL{
+        return match service.get_root_certificate() {
+            Some(root) => root.verify_signature(&revocation.as_signable(), signature),
+            None => false,
+        };
+    }
+    let issuer = match service.get_signing_certificate(revocation.issuer_serial) {
+        Some(issuer) => issuer,
+        None => return false,
+    };
+    issuer.check_flag(FLAG_SIGN_CERTS) && service.verify_signing_certificate(&issuer)
+        && issuer.verify_signature(&revocation.as_signable(), signature)
+}
+
+// Records revocation, so revoked certificate is not accepted again from later syncs
+fn apply_revocation<S: CertificateService + ?Sized>(service: &mut S, revocation: &CertificateRevocation) -> SyncOutcome{
+    if service.is_revoked(revocation.serial){
+        return SyncOutcome::Unchanged;
+    }
+    let parent_serial = match service.get_signing_certificate(revocation.serial) {
+        Some(certificate) => certificate.get_parent_serial(),
+        None => match service.get_encryption_certificate(revocation.serial) {
+            Some(certificate) => certificate.get_parent_serial(),
+            // Issuer of unknown certificate can not be checked
+            None => return SyncOutcome::Unchanged,
+        },
+    };
+    if !verify_revocation(service, revocation, parent_serial) || !service.add_revocation(revocation.clone()){
+        return SyncOutcome::Rejected;
+    }
+    SyncOutcome::Applied
+}
+
+///
+/// Applies certificate changes received from peer. Every entry is verified against
+/// known chains before being applied, entries are applied in order they were sent.
+/// Changes are committed if at least one entry was applied.
+///
+/// # Arguments
+/// * service: &mut S: service to apply changes to
+/// * message: &CertificateSyncMessage: received changes
+///
+/// returns: CertificateSyncReport: counters of applied, unchanged and rejected entries
+///
+pub fn apply_certificate_sync<S: CertificateService + ?Sized>(service: &mut S,
+                              message: &CertificateSyncMessage) -> CertificateSyncReport{
+    let mut report = CertificateSyncReport::default();
+    for entry in message.entries.iter(){
+        let outcome = match entry {
+            CertificateSyncEntry::SigningCertificate(certificate) => {
+                apply_signing_certificate(service, certificate)
+            }
+            CertificateSyncEntry::EncryptionCertificate(certificate) => {
+                apply_encryption_certificate(service, certificate)
+            }
+            CertificateSyncEntry::Revocation(revocation) => {
+                apply_revocation(service, revocation)
+            }
+        };
+        match outcome {
+            SyncOutcome::Applied => report.applied += 1,
+            SyncOutcome::Unchanged => report.unchanged += 1,
+            SyncOutcome::Rejected => report.rejected += 1,
+        }
+    }
+    if report.rejected > 0{
+        log::warn!("Rejected {} certificate sync entries", report.rejected);
+    }
+    if report.applied > 0{
+        service.commit();
+    }
+    report
+}
+
+///
+/// Sends snapshot of all certificates and revocations known to service to peers, so peers which
+/// missed a change converge. Hosts do it every DEFAULT_CERTIFICATE_SYNC_INTERVAL_SECONDS, peers
+/// apply received snapshot with apply_certificate_sync.
+///
+/// # Arguments
+/// * service: &mut S: service to take certificates from
+/// * transport: &mut dyn TransportService: transport to send snapshots with
+/// * source: u128: ID of current host
+/// * module_id: u64: ID of module receiving snapshots on peers
+/// * peers: &[u128]: IDs of peers, current host is skipped
+///
+/// returns: usize: count of peers snapshot was sent to, 0 if service has nothing to share
+///
+pub fn send_certificate_sync<S: CertificateService + ?Sized>(service: &mut S, transport: &mut dyn TransportService,
+                             source: u128, module_id: u64, peers: &[u128]) -> usize{
+    let snapshot = CertificateSyncMessage::snapshot(service);
+    if snapshot.is_empty(){
+        return 0;
+    }
+    let mut sent = 0;
+    for peer in peers.iter().filter(|peer| **peer != source){
+        let message = MessageBuilder::from_payload(&snapshot)
+            .set_source(source)
+            .set_destination(*peer)
+            .set_module_id(module_id)
+            .build()
+            .expect("All required fields are set");
+        transport.send_message(message);
+        sent += 1;
+    }
+    sent
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pki::certificate::metadata::CertificateValidity;
+    use crate::pki::hash::HashType;
+    use crate::message::types::MessageType;
+    use crate::pki::impls::keys::kyber1024::generate_kyber1024_keypair_from_seed;
+    use crate::serialization::deserializable::Deserializable;
+    use crate::serialization::serializable::Serializable;
+    use crate::services::impls::certificate::AsyncCertificateServiceImpl;
+    use crate::testing::transport::LoopbackTransportService;
+    use crate::testing::certificate::{test_certificates, TEST_ENCRYPTION_CERTIFICATE_SERIAL,
+                                      TEST_SIGNING_CERTIFICATE_SERIAL};
+
+    // Each test stores its certificates in own file, so tests do not overwrite each other
+    fn create_receiver() -> (AsyncCertificateServiceImpl, String){
+        let certificates = test_certificates();
+        let file = std::env::temp_dir().join(format!("milkyway-certsync-{}.dat", rand::random::<u64>()));
+        let file = file.to_str().unwrap().to_string();
+        let mut service = AsyncCertificateServiceImpl::new(&file);
+        service.set_root_certificate(certificates.root.clone_without_sk());
+        assert!(service.add_signing_certificate(certificates.signing.clone_without_sk()));
+        (service, file)
+    }
+
+    #[test]
+    fn test_sync_adds_and_revokes_certificates() {
+        let certificates = test_certificates();
+        let (mut service, file) = create_receiver();
+        let mut message = CertificateSyncMessage::new();
+        message.add_signing_certificate(&certificates.signing)
+            .add_encryption_certificate(&certificates.encryption);
+        // Message must survive the wire
+        let message = CertificateSyncMessage::from_serialized(&message.serialize()).unwrap().0;
+        let report = apply_certificate_sync(&mut service, &message);
+        assert_eq!(report, CertificateSyncReport{ applied: 1, unchanged: 1, rejected: 0 });
+        let stored = service.get_encryption_certificate(TEST_ENCRYPTION_CERTIFICATE_SERIAL).unwrap();
+        assert!(stored.get_secret_key().is_none());
+
+        let revocation = CertificateRevocation::new(TEST_ENCRYPTION_CERTIFICATE_SERIAL,
+                                                    &certificates.signing).unwrap();
+        let mut message = CertificateSyncMessage::new();
+        message.add_revocation(revocation);
+        let report = apply_certificate_sync(&mut service, &message);
+        assert_eq!(report.applied, 1);
+        assert!(service.get_encryption_certificate(TEST_ENCRYPTION_CERTIFICATE_SERIAL).is_none());
+        assert_eq!(apply_certificate_sync(&mut service, &message).unchanged, 1);
+
+        // Revoked certificate is not brought back by later syncs, also after restart
+        let mut message = CertificateSyncMessage::new();
+        message.add_encryption_certificate(&certificates.encryption);
+        assert_eq!(apply_certificate_sync(&mut service, &message).rejected, 1);
+        assert!(!service.add_encryption_certificate(certificates.encryption.clone_without_sk()));
+        assert!(!service.verify_encryption_certificate(&certificates.encryption));
+        let mut loaded = AsyncCertificateServiceImpl::load_from_file(&file);
+        assert!(loaded.is_revoked(TEST_ENCRYPTION_CERTIFICATE_SERIAL));
+        assert_eq!(apply_certificate_sync(&mut loaded, &message).rejected, 1);
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_sync_rejects_revocation_not_by_issuer() {
+        let certificates = test_certificates();
+        let (mut service, file) = create_receiver();
+        service.add_encryption_certificate(certificates.encryption.clone_without_sk());
+        // Root did not issue encryption certificate
+        let mut revocation = CertificateRevocation::new(TEST_ENCRYPTION_CERTIFICATE_SERIAL,
+                                                        &certificates.root).unwrap();
+        let mut message = CertificateSyncMessage::new();
+        message.add_revocation(revocation.clone());
+        assert_eq!(apply_certificate_sync(&mut service, &message).rejected, 1);
+        // Forged issuer serial does not match signature
+        revocation.issuer_serial = TEST_SIGNING_CERTIFICATE_SERIAL;
+        let mut message = CertificateSyncMessage::new();
+        message.add_revocation(revocation);
+        assert_eq!(apply_certificate_sync(&mut service, &message).rejected, 1);
+        assert!(service.get_encryption_certificate(TEST_ENCRYPTION_CERTIFICATE_SERIAL).is_some());
+        let _ = std::fs::remove_file(file);
+    }
+
+    #[test]
+    fn test_sync_rotates_certificate() {
+        let certificates = test_certificates();
+        let (mut service, file) = create_receiver();
+        service.add_encryption_certificate(certificates.encryption.clone_without_sk());
+        let (public_key, secret_key) = generate_kyber1024_keypair_from_seed(b"test-encryption-rotated");
+        let mut rotated = certificates.encryption.clone();
+        rotated.public_key = public_key;
+        rotated.secret_key = Some(secret_key);
+        rotated.metadata.set_validity(CertificateValidity{ issued_at: 2, expires_at: None });
+        rotated.signature = Some(certificates.signing.sign_data(&rotated.clone_without_signature_and_sk(),
+                                                                HashType::None).unwrap());
+        let mut message = CertificateSyncMessage::new();
+        message.add_encryption_certificate(&rotated);
+        assert_eq!(apply_certificate_sync(&mut service, &message).applied, 1);
+        assert!(service.get_encryption_certificate(TEST_ENCRYPTION_CERTIFICATE_SERIAL).unwrap()
+            == rotated.clone_without_sk());
+
+        // Tampered rotation keeps current certificate
+        let mut tampered = certificates.encryption.clone_without_sk();
+        tampered.name = "tampered".to_string();
+        let mut message = CertificateSyncMessage::new();
+        message.add_encryption_certificate(&tampered);
+        assert_eq!(apply_certificate_sync(&mut service, &message).rejected, 1);
+        assert!(service.get_encryption_certificate(TEST_ENCRYPTION_CERTIFICATE_SERIAL).unwrap()
+            == rotated.clone_without_sk());
+
+        // Replayed older certificate does not roll rotation back, even if it is validly signed
+        let mut message = CertificateSyncMessage::new();
+        message.add_encryption_certificate(&certificates.encryption);
+        assert_eq!(apply_certificate_sync(&mut service, &message).rejected, 1);
+        let mut older = rotated.clone();
+        older.metadata.set_validity(CertificateValidity{ issued_at: 1, expires_at: None });
+        older.signature = Some(certificates.signing.sign_data(&older.clone_without_signature_and_sk(),
+                                                              HashType::None).unwrap());
+        let mut message = CertificateSyncMessage::new();
+        message.add_encryption_certificate(&older);
+        assert_eq!(apply_certificate_sync(&mut service, &message).rejected, 1);
+        assert!(service.get_encryption_certificate(TEST_ENCRYPTION_CERTIFICATE_SERIAL).unwrap()
+            == rotated.clone_without_sk());
+        let _ = std::fs::remove_file(file);
+    }
+
+    #[test]
+    fn test_sync_does_not_replace_local_certificates() {
+        let certificates = test_certificates();
+        let (mut service, file) = create_receiver();
+        service.remove_signing_certificate(TEST_SIGNING_CERTIFICATE_SERIAL);
+        service.add_signing_certificate(certificates.signing.clone());
+        let mut other = certificates.signing.clone_without_sk();
+        other.name = "other".to_string();
+        let mut message = CertificateSyncMessage::new();
+        message.add_signing_certificate(&other);
+        assert_eq!(apply_certificate_sync(&mut service, &message).rejected, 1);
+        assert!(service.get_signing_certificate(TEST_SIGNING_CERTIFICATE_SERIAL).unwrap()
+            .get_secret_key().is_some());
+        let _ = std::fs::remove_file(file);
+    }
+
+    #[test]
+    fn test_send_certificate_sync() {
+        let certificates = test_certificates();
+        let (mut service, file) = create_receiver();
+        service.add_encryption_certificate(certificates.encryption.clone());
+        let revocation = CertificateRevocation::new(TEST_ENCRYPTION_CERTIFICATE_SERIAL,
+                                                    &certificates.signing).unwrap();
+        let (mut transport, _peer) = LoopbackTransportService::pair(1, 2);
+        assert_eq!(send_certificate_sync(&mut service, &mut transport, 1, 7, &[1, 2]), 1);
+        assert!(service.add_revocation(revocation));
+        assert_eq!(send_certificate_sync(&mut service, &mut transport, 1, 7, &[2]), 1);
+        let sent = transport.sent_messages();
+        assert_eq!(sent.len(), 2);
+        assert!(sent.iter().all(|message| message.message_type == MessageType::CertificateSync
+            && message.destination == 2 && message.module_id == 7));
+        let snapshot = CertificateSyncMessage::from_serialized(sent[1].data.as_ref().unwrap()).unwrap().0;
+        assert_eq!(snapshot.entries.len(), 2);
+        assert!(matches!(&snapshot.entries[1], CertificateSyncEntry::Revocation(revocation)
+            if revocation.serial == TEST_ENCRYPTION_CERTIFICATE_SERIAL));
+
+        // Receiver of snapshot records revocation
+        let (mut receiver, receiver_file) = create_receiver();
+        receiver.add_encryption_certificate(certificates.encryption.clone_without_sk());
+        assert_eq!(apply_certificate_sync(&mut receiver, &snapshot).applied, 1);
+        assert!(receiver.is_revoked(TEST_ENCRYPTION_CERTIFICATE_SERIAL));
+        let _ = std::fs::remove_file(file);
+        let _ = std::fs::remove_file(receiver_file);
+    }
+}
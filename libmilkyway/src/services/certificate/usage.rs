@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use libmilkyway_derive::{Describe, Deserializable, Serializable};
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+
+///
+/// How much key of a certificate was used. Also used as a delta passed to
+/// CertificateService::record_key_usage.
+///
+#[derive(Clone, Debug, Default, PartialEq, Serializable, Deserializable, Describe)]
+pub struct KeyUsage{
+    pub serial: u128,
+    /** Signatures made with signing key **/
+    pub signatures: u64,
+    /** Bytes encrypted to encryption key **/
+    pub encrypted_bytes: u64,
+    /** Sessions established with certificate **/
+    pub sessions: u64,
+}
+
+impl KeyUsage {
+    #[inline]
+    pub fn new(serial: u128) -> KeyUsage{
+        KeyUsage{
+            serial,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_signatures(&mut self, signatures: u64) -> &mut Self{
+        self.signatures = signatures;
+        self
+    }
+
+    pub fn set_encrypted_bytes(&mut self, encrypted_bytes: u64) -> &mut Self{
+        self.encrypted_bytes = encrypted_bytes;
+        self
+    }
+
+    pub fn set_sessions(&mut self, sessions: u64) -> &mut Self{
+        self.sessions = sessions;
+        self
+    }
+
+    ///
+    /// Adds counters of other usage, saturating on overflow
+    ///
+    pub fn add(&mut self, other: &KeyUsage){
+        self.signatures = self.signatures.saturating_add(other.signatures);
+        self.encrypted_bytes = self.encrypted_bytes.saturating_add(other.encrypted_bytes);
+        self.sessions = self.sessions.saturating_add(other.sessions);
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool{
+        self.signatures == 0 && self.encrypted_bytes == 0 && self.sessions == 0
+    }
+}
+
+///
+/// Usage after which certificate should be rotated, zero disables a threshold
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable, Describe)]
+pub struct UsageThresholds{
+    pub signatures: u64,
+    pub encrypted_bytes: u64,
+    pub sessions: u64,
+}
+
+impl Default for UsageThresholds {
+    fn default() -> Self {
+        UsageThresholds{
+            signatures: 1_000_000,
+            encrypted_bytes: 1 << 40,
+            sessions: 100_000,
+        }
+    }
+}
+
+impl UsageThresholds {
+    ///
+    /// Gets thresholds reached by usage
+    ///
+    /// returns: Vec<String>: descriptions of reached thresholds, e.g. `1000 signatures(threshold 1000)`
+    ///
+    pub fn get_exceeded(&self, usage: &KeyUsage) -> Vec<String>{
+        [("signatures", usage.signatures, self.signatures),
+            ("encrypted bytes", usage.encrypted_bytes, self.encrypted_bytes),
+            ("sessions", usage.sessions, self.sessions)]
+            .into_iter()
+            .filter(|(_, value, threshold)| *threshold != 0 && value >= threshold)
+            .map(|(name, value, threshold)| format!("{} {}(threshold {})", value, name, threshold))
+            .collect()
+    }
+}
+
+///
+/// Accumulates usage in memory where asking certificate service on every use would be
+/// too expensive, e.g. for every frame of transport. Accumulated usage is taken and
+/// recorded to service periodically.
+///
+#[derive(Default)]
+pub struct KeyUsageRecorder{
+    pending: Mutex<HashMap<u128, KeyUsage>>,
+}
+
+impl KeyUsageRecorder {
+    #[inline]
+    pub fn new() -> KeyUsageRecorder{
+        KeyUsageRecorder::default()
+    }
+
+    pub fn record(&self, usage: &KeyUsage){
+        self.pending.lock().unwrap().entry(usage.serial)
+            .or_insert_with(|| KeyUsage::new(usage.serial))
+            .add(usage);
+    }
+
+    ///
+    /// Takes usage accumulated since previous call
+    ///
+    pub fn take(&self) -> Vec<KeyUsage>{
+        self.pending.lock().unwrap().drain().map(|(_, usage)| usage).collect()
+    }
+}
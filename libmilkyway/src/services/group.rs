@@ -0,0 +1,164 @@
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use libmilkyway_derive::{Deserializable, Serializable};
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::message::common::Message;
+use crate::message::group::GroupRecord;
+use crate::services::certificate::CertificateService;
+
+///
+/// Highest bit of destination marks that message is addressed to a group of peers
+/// rather than to a single peer
+///
+pub const GROUP_ADDRESS_FLAG: u128 = 1 << 127;
+
+///
+/// Gets destination address of a group
+///
+/// # Arguments
+/// * group_id: u128: ID of group, highest bit is ignored
+///
+#[inline]
+pub fn get_group_address(group_id: u128) -> u128{
+    group_id | GROUP_ADDRESS_FLAG
+}
+
+///
+/// Checks whether destination is an address of a group
+///
+#[inline]
+pub fn is_group_address(destination: u128) -> bool{
+    destination & GROUP_ADDRESS_FLAG != 0
+}
+
+///
+/// Gets ID of group from its destination address
+///
+#[inline]
+pub fn get_group_id(destination: u128) -> u128{
+    destination & !GROUP_ADDRESS_FLAG
+}
+
+///
+/// A named set of peers which may be used as a destination of messages
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq)]
+pub struct Group{
+    pub id: u128,
+    pub name: String,
+    pub members: Vec<u128>,
+}
+
+///
+/// Reason why group record was not applied
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum GroupError{
+    /** Record is not signed by a trusted certificate which can sign certificates **/
+    InvalidSignature,
+    UnknownGroup,
+    GroupExists,
+    NameTaken,
+    AlreadyMember,
+    NotMember,
+    /** Record is not newer than last applied record of group **/
+    Outdated,
+}
+
+impl Display for GroupError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GroupError::InvalidSignature => write!(f, "Group record is not signed by a trusted certificate"),
+            GroupError::UnknownGroup => write!(f, "No such group"),
+            GroupError::GroupExists => write!(f, "Group with such ID already exists"),
+            GroupError::NameTaken => write!(f, "Group with such name already exists"),
+            GroupError::AlreadyMember => write!(f, "Peer is already a member of group"),
+            GroupError::NotMember => write!(f, "Peer is not a member of group"),
+            GroupError::Outdated => write!(f, "Group record is older than current state of group"),
+        }
+    }
+}
+
+///
+/// Group service maintains membership of peer groups
+///
+pub trait GroupService: Send + Sync{
+    ///
+    /// Applies a record which signature is already verified(see apply_group_record)
+    ///
+    /// # Arguments
+    /// * record: &GroupRecord: change of group
+    ///
+    /// returns: Result<(), GroupError>: error if record conflicts with current state
+    ///
+    fn apply_verified_record(&mut self, record: &GroupRecord) -> Result<(), GroupError>;
+
+    ///
+    /// Gets group by its ID
+    ///
+    fn get_group(&self, id: u128) -> Option<Group>;
+
+    ///
+    /// Gets group by its name
+    ///
+    fn get_group_by_name(&self, name: &str) -> Option<Group>;
+
+    ///
+    /// Gets all known groups
+    ///
+    fn get_groups(&self) -> Vec<Group>;
+
+    ///
+    /// Saves groups to storage
+    ///
+    fn commit(&mut self);
+
+    ///
+    /// Expands message addressed to a group into a copy per member
+    ///
+    /// # Arguments
+    /// * message: &Message: message to expand
+    ///
+    /// returns: Vec<Message>: copies of message addressed to members, the message itself if
+    /// it is not addressed to a group, or nothing if group is unknown
+    ///
+    fn expand_destination(&self, message: &Message) -> Vec<Message>{
+        if !is_group_address(message.destination){
+            return vec![message.clone()];
+        }
+        let group = match self.get_group(get_group_id(message.destination)) {
+            Some(group) => group,
+            None => {
+                log::warn!("Message to unknown group {} dropped", get_group_id(message.destination));
+                return vec![];
+            }
+        };
+        group.members.iter().filter(|member| **member != message.source).map(|member| {
+            let mut copy = message.clone();
+            copy.destination = *member;
+            copy
+        }).collect()
+    }
+}
+
+pub type SharedGroupService = Arc<Mutex<dyn GroupService>>;
+
+///
+/// Verifies group record and applies it
+///
+/// # Arguments
+/// * groups: &mut G: service to apply record to
+/// * certificates: &mut S: service with certificate of signer
+/// * record: &GroupRecord: record to apply
+///
+pub fn apply_group_record<G: GroupService + ?Sized, S: CertificateService + ?Sized>(groups: &mut G,
+                                                                                  certificates: &mut S,
+                                                                                  record: &GroupRecord)
+    -> Result<(), GroupError>{
+    if !record.verify(certificates){
+        return Err(GroupError::InvalidSignature);
+    }
+    groups.apply_verified_record(record)
+}
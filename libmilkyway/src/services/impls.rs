@@ -1,4 +1,13 @@
 ///
 /// A common implementations of a certificate service
 /// 
-pub mod certificate;
\ No newline at end of file
+pub mod certificate;
+
+///
+/// A file-backed implementation of a group service
+///
+pub mod group;
+///
+/// A transport service of host which is not connected to any network
+///
+pub mod transport;
@@ -3,21 +3,95 @@ use crate::serialization::error::SerializationError;
 use crate::serialization::serializable::Serialized;
 use crate::serialization::serializable::Serializable;
 use std::collections::HashMap;
-use std::path::Path;
+use std::collections::hash_map::Entry;
+use std::fs::File;
+use std::path::{Path, PathBuf};
 use crate::actor::binder::BinderServiceHandler;
 use crate::pki::certificate::{Certificate, FLAG_SIGN_CERTS};
 use crate::pki::impls::certificates::falcon1024::{Falcon1024Certificate, Falcon1024RootCertificate};
 use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
-use crate::services::certificate::{CertificateService, CertificateServiceBinderRequest, CertificateServiceBinderResponse};
-use libmilkyway_derive::{Deserializable, Serializable};
+use crate::secrets::DEFAULT_KDF_ITERATIONS;
+use crate::message::certsync::CertificateRevocation;
+use crate::serialization::migration::{backup_file, dump_versioned, encode_versioned, load_versioned, replace_file,
+                                      MigrationStep, VersionedStorage, LEGACY_SCHEMA_VERSION};
+use crate::services::certificate::{CertificateService, CertificateServiceBinderRequest, CertificateServiceBinderResponse,
+                                   CertificateServiceError, VerifiableCertificate, ROOT_CERTIFICATE_SERIAL};
+use crate::services::certificate::usage::{KeyUsage, UsageThresholds};
+use crate::services::certificate::listing::{get_page, CertificatePage};
+use crate::services::certificate::reload::{CertificateChange, CertificateChangeListener, CertificateChangeTracker,
+                                           CertificateKind, CertificateReloadReport};
+use crate::services::certificate::keystore::{KeyStoreError, KeyStoreKey, SecretKeyStore};
+use crate::services::certificate::policy::{CertificatePolicy, PolicyError, PolicyOperation, PolicySubject};
+use crate::pki::key::CryptoKey;
 
 
-#[derive(Serializable, Deserializable)]
 pub struct AsyncCertificateServiceImpl {
     storage_file_name: String,
     root_certificate: Option<Falcon1024RootCertificate>,
     signing_certificates: HashMap<u128, Falcon1024Certificate>,
     encryption_certificates: HashMap<u128, Kyber1024Certificate>,
+    /** Usage counters by certificate serial **/
+    key_usage: HashMap<u128, KeyUsage>,
+    usage_thresholds: UsageThresholds,
+    /** Certificates changed since last commit and listeners of reloads, not persisted **/
+    changes: CertificateChangeTracker,
+    /** Separate store of secret keys and key derived from its passphrase, None if keys are kept with certificates, not persisted **/
+    key_store: Option<(PathBuf, Option<KeyStoreKey>)>,
+    policy: CertificatePolicy,
+    /** Whether policy is signed by root certificate, not persisted **/
+    policy_trusted: bool,
+    /** Revocations by serial of revoked certificate **/
+    revocations: HashMap<u128, CertificateRevocation>,
+}
+
+// Serialized by hand, so tracker of changes is not written to store
+impl Serializable for AsyncCertificateServiceImpl {
+    fn serialize(&self) -> Serialized {
+        let mut result = self.storage_file_name.serialize();
+        result.extend(self.root_certificate.serialize());
+        result.extend(self.signing_certificates.serialize());
+        result.extend(self.encryption_certificates.serialize());
+        result.extend(self.key_usage.serialize());
+        result.extend(self.usage_thresholds.serialize());
+        result.extend(self.policy.serialize());
+        result.extend(self.revocations.serialize());
+        result
+    }
+}
+
+impl Deserializable for AsyncCertificateServiceImpl {
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        let (storage_file_name, mut offset) = String::from_serialized(serialized)?;
+        let (root_certificate, size) = Option::<Falcon1024RootCertificate>::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (signing_certificates, size) = HashMap::<u128, Falcon1024Certificate>::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (encryption_certificates, size) = HashMap::<u128, Kyber1024Certificate>::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (key_usage, size) = HashMap::<u128, KeyUsage>::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (usage_thresholds, size) = UsageThresholds::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (policy, size) = CertificatePolicy::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let (revocations, size) = HashMap::<u128, CertificateRevocation>::from_serialized(&serialized[offset..].to_vec())?;
+        offset += size;
+        let mut service = AsyncCertificateServiceImpl{
+            storage_file_name,
+            root_certificate,
+            signing_certificates,
+            encryption_certificates,
+            key_usage,
+            usage_thresholds,
+            changes: CertificateChangeTracker::default(),
+            key_store: None,
+            policy,
+            policy_trusted: false,
+            revocations,
+        };
+        service.trust_policy();
+        Ok((service, offset))
+    }
 }
 
 impl AsyncCertificateServiceImpl {
@@ -30,22 +104,237 @@ impl AsyncCertificateServiceImpl {
             root_certificate: None,
             signing_certificates: HashMap::new(),
             encryption_certificates: HashMap::new(),
+            key_usage: HashMap::new(),
+            usage_thresholds: UsageThresholds::default(),
+            changes: CertificateChangeTracker::default(),
+            key_store: None,
+            policy: CertificatePolicy::default(),
+            policy_trusted: true,
+            revocations: HashMap::new(),
         }
     }
 
     #[inline]
     pub fn load_from_file(file: &str) -> AsyncCertificateServiceImpl {
-        let mut service = AsyncCertificateServiceImpl::from_file(Path::new(file)).expect("Failed to load certificate storage");
+        let mut service = load_versioned::<AsyncCertificateServiceImpl>(Path::new(file))
+            .expect("Failed to load certificate storage");
         service.storage_file_name = file.to_string();
         service
     }
+
+    ///
+    /// Keeps secret keys in separate store, so certificate store holds only public certificates
+    /// and may be shared. Keys of existing store are joined with certificates by serial, once
+    /// service is committed keys are moved from certificate store.
+    ///
+    /// # Arguments
+    /// * file: &Path: secret key store, it is created on commit if it does not exist
+    /// * passphrase: Option<&str>: passphrase key store is encrypted with, None to keep it unencrypted
+    ///
+    /// returns: Result<(), KeyStoreError>: error if existing key store can not be read
+    ///
+    pub fn set_key_store(&mut self, file: &Path, passphrase: Option<&str>) -> Result<(), KeyStoreError>{
+        self.open_key_store(file, passphrase.map(|passphrase| KeyStoreKey::new(passphrase, DEFAULT_KDF_ITERATIONS)))
+    }
+
+    // Joins keys of key store with certificates, key derived while reading is kept for commits
+    fn open_key_store(&mut self, file: &Path, mut key: Option<KeyStoreKey>) -> Result<(), KeyStoreError>{
+        if file.exists(){
+            let keys = SecretKeyStore::read_from_file(file, key.as_mut())?;
+            let unmatched = keys.join_into(&mut self.root_certificate, &mut self.signing_certificates,
+                                           &mut self.encryption_certificates);
+            if unmatched > 0{
+                log::warn!("{} secret keys of {} have no certificates and are dropped", unmatched, file.display());
+            }
+        }
+        self.key_store = Some((file.to_path_buf(), key));
+        Ok(())
+    }
+
+    ///
+    /// Moves secret keys of combined certificate store into new key store. Certificate store is
+    /// backed up to `<file>.v<version>.bak` before it is rewritten without secret keys.
+    ///
+    /// # Arguments
+    /// * file: &Path: certificate store with secret keys
+    /// * key_store: &Path: key store to create, it must not exist
+    /// * passphrase: Option<&str>: passphrase key store is encrypted with
+    ///
+    /// returns: Result<(usize, PathBuf), KeyStoreError>: number of moved keys and backup of certificate store
+    ///
+    pub fn split_key_store(file: &Path, key_store: &Path, passphrase: Option<&str>) -> Result<(usize, PathBuf), KeyStoreError>{
+        if key_store.exists(){
+            return Err(KeyStoreError::AlreadyExists(key_store.to_path_buf()));
+        }
+        let mut service = load_versioned::<AsyncCertificateServiceImpl>(file)
+            .map_err(|error| KeyStoreError::Io(error.to_string()))?;
+        service.storage_file_name = file.to_string_lossy().to_string();
+        let backup = backup_file(file, Self::SCHEMA_VERSION).map_err(|error| KeyStoreError::Io(error.to_string()))?;
+        service.set_key_store(key_store, passphrase)?;
+        let moved = service.dump_split()?;
+        Ok((moved, backup))
+    }
+
+    // Writes secret keys to key store first, so keys are never lost if writing certificates fails
+    fn dump_split(&mut self) -> Result<usize, KeyStoreError>{
+        let keys = SecretKeyStore::take_from(&mut self.root_certificate, &mut self.signing_certificates,
+                                             &mut self.encryption_certificates);
+        let public = encode_versioned(Self::SCHEMA_VERSION, &self.serialize());
+        let (file, key) = self.key_store.as_mut().expect("Key store is set");
+        let result = keys.dump_to_file(file, key.as_mut())
+            .and_then(|_| replace_file(Path::new(&self.storage_file_name), &public)
+                .map_err(|error| KeyStoreError::Io(error.to_string())))
+            .map(|_| keys.len());
+        keys.join_into(&mut self.root_certificate, &mut self.signing_certificates, &mut self.encryption_certificates);
+        result
+    }
+
+    // Checks that policy is signed by current root certificate, otherwise it denies everything
+    fn trust_policy(&mut self){
+        self.policy_trusted = self.policy.is_signed_by(self.root_certificate.as_ref());
+        if !self.policy_trusted{
+            log::warn!("Certificate policy version {} is not signed by root certificate, nothing is allowed",
+                self.policy.version);
+        }
+    }
+
+    fn evaluate_subject(&self, subject: &PolicySubject) -> Result<(), PolicyError>{
+        if !self.policy_trusted{
+            return Err(PolicyError::InvalidSignature);
+        }
+        self.policy.evaluate(subject)
+    }
+
+    // Checks whether policy allows operation on certificate, denial is logged
+    fn check_policy<PK: CryptoKey, SK: CryptoKey, C: Certificate<PK, SK>>(&self, operation: PolicyOperation,
+                                                                        cert: &C) -> bool{
+        let subject = match PolicySubject::of(operation, cert) {
+            Some(subject) => subject,
+            None => return true,
+        };
+        match self.evaluate_subject(&subject) {
+            Ok(()) => true,
+            Err(error) => {
+                log::warn!("Certificate {} is denied: {}", cert.get_serial(), error);
+                false
+            }
+        }
+    }
+
+    ///
+    /// Subscribes to changes applied by reload, e.g. to drop cached sessions of removed certificates
+    ///
+    pub fn subscribe_changes(&mut self, listener: CertificateChangeListener){
+        self.changes.subscribe(listener);
+    }
+
+    ///
+    /// Verifies chain of signing certificate. Validity of stored certificates met on the way is
+    /// remembered in `verified`, so chains shared by several certificates are walked once.
+    ///
+    fn verify_signing_chain(&self, cert: &Falcon1024Certificate, verified: &mut HashMap<u128, bool>) -> bool{
+        // Stored certificates on the way to root, each of them is valid only if chain above it is
+        let mut path = Vec::<u128>::new();
+        let mut current_cert = cert.clone();
+        let is_valid = loop {
+            let (parent_serial, signature) = match (current_cert.get_parent_serial(), current_cert.get_signature()) {
+                (Some(parent_serial), Some(signature)) => (parent_serial, signature),
+                _ => break false,
+            };
+            if self.revocations.contains_key(&current_cert.get_serial())
+                || !self.check_policy(PolicyOperation::Verify, &current_cert){
+                break false;
+            }
+            if parent_serial == ROOT_CERTIFICATE_SERIAL{
+                break match &self.root_certificate {
+                    Some(root) => root.verify_signature(&current_cert.clone_without_signature_and_sk(), &signature),
+                    None => false,
+                };
+            }
+            let parent_cert = match self.signing_certificates.get(&parent_serial) {
+                Some(parent_cert) if !path.contains(&parent_serial) => parent_cert,
+                _ => break false,
+            };
+            if !parent_cert.check_flag(FLAG_SIGN_CERTS)
+                || !parent_cert.verify_signature(&current_cert.clone_without_signature(), &signature){
+                break false;
+            }
+            if let Some(parent_valid) = verified.get(&parent_serial){
+                break *parent_valid;
+            }
+            path.push(parent_serial);
+            current_cert = parent_cert.clone();
+        };
+        for serial in path{
+            verified.insert(serial, is_valid);
+        }
+        is_valid
+    }
+
+    ///
+    /// Verifies encryption certificate, see verify_signing_chain
+    ///
+    fn verify_encryption_chain(&self, cert: &Kyber1024Certificate, verified: &mut HashMap<u128, bool>) -> bool{
+        let (parent_serial, signature) = match (cert.get_parent_serial(), cert.get_signature()) {
+            (Some(parent_serial), Some(signature)) => (parent_serial, signature),
+            _ => return false,
+        };
+        if self.revocations.contains_key(&cert.get_serial()) || !self.check_policy(PolicyOperation::Verify, cert){
+            return false;
+        }
+        if parent_serial == ROOT_CERTIFICATE_SERIAL{
+            return match &self.root_certificate {
+                Some(root) => root.verify_signature(&cert.clone_without_signature_and_sk(), &signature),
+                None => false,
+            };
+        }
+        let parent = match self.signing_certificates.get(&parent_serial) {
+            Some(parent) => parent,
+            None => return false,
+        };
+        let parent_valid = match verified.get(&parent_serial) {
+            Some(parent_valid) => *parent_valid,
+            None => {
+                let parent_valid = self.verify_signing_chain(parent, verified);
+                verified.insert(parent_serial, parent_valid);
+                parent_valid
+            }
+        };
+        parent_valid && parent.check_flag(FLAG_SIGN_CERTS)
+            && parent.verify_signature(&cert.clone_without_signature_and_sk(), &signature)
+    }
 }
 
+impl VersionedStorage for AsyncCertificateServiceImpl {
+    const STORE_NAME: &'static str = "certificates";
+    const SCHEMA_VERSION: u32 = 4;
+
+    fn get_migrations() -> Vec<MigrationStep> {
+        vec![
+            MigrationStep::new(LEGACY_SCHEMA_VERSION, "Add schema version header", Ok),
+            MigrationStep::new(1, "Add key usage counters", |mut payload| {
+                payload.extend(HashMap::<u128, KeyUsage>::new().serialize());
+                payload.extend(UsageThresholds::default().serialize());
+                Ok(payload)
+            }),
+            MigrationStep::new(2, "Add certificate policy", |mut payload| {
+                payload.extend(CertificatePolicy::default().serialize());
+                Ok(payload)
+            }),
+            MigrationStep::new(3, "Add certificate revocations", |mut payload| {
+                payload.extend(HashMap::<u128, CertificateRevocation>::new().serialize());
+                Ok(payload)
+            }),
+        ]
+    }
+}
 
 impl CertificateService for AsyncCertificateServiceImpl {
     #[inline]
     fn set_root_certificate(&mut self, root_cert: Falcon1024RootCertificate) {
         self.root_certificate = Some(root_cert);
+        self.changes.mark(ROOT_CERTIFICATE_SERIAL);
+        self.trust_policy();
     }
 
     fn add_signing_certificate(&mut self, cert: Falcon1024Certificate) -> bool {
@@ -54,6 +343,9 @@ impl CertificateService for AsyncCertificateServiceImpl {
             println!("Unsigned cert");
             return false;
         }
+        if !self.check_policy(PolicyOperation::Add, &cert){
+            return false;
+        }
         if !self.verify_signing_certificate(&cert){
             // Trying to add wrong-signed certificate
             println!("Bad signature");
@@ -74,6 +366,7 @@ impl CertificateService for AsyncCertificateServiceImpl {
             return false;
         }
         self.signing_certificates.insert(serial, cert.clone());
+        self.changes.mark(serial);
         true
     }
 
@@ -90,6 +383,9 @@ impl CertificateService for AsyncCertificateServiceImpl {
             println!("Orphaned\n");
             return false;
         }
+        if !self.check_policy(PolicyOperation::Add, &cert){
+            return false;
+        }
         //let parent_serial = parent_serial.unwrap();
         if !self.verify_encryption_certificate(&cert){
             // Tampered certificate?
@@ -103,12 +399,20 @@ impl CertificateService for AsyncCertificateServiceImpl {
             return false;
         }
         self.encryption_certificates.insert(serial, cert.clone());
+        self.changes.mark(serial);
         true
     }
 
     fn verify_signing_certificate(&mut self, cert: &Falcon1024Certificate) -> bool {
         let mut current_cert = cert.clone();
         loop{
+            if self.revocations.contains_key(&current_cert.get_serial()){
+                log::debug!("Certificate {} is revoked", current_cert.get_serial());
+                return false;
+            }
+            if !self.check_policy(PolicyOperation::Verify, &current_cert){
+                return false;
+            }
             let parent_serial = current_cert.get_parent_serial();
             if parent_serial.is_none(){
                 // No parent certificate
@@ -177,6 +481,13 @@ impl CertificateService for AsyncCertificateServiceImpl {
             return false;
         }
         let signature = signature.unwrap();
+        if self.revocations.contains_key(&cert.get_serial()){
+            log::debug!("Certificate {} is revoked", cert.get_serial());
+            return false;
+        }
+        if !self.check_policy(PolicyOperation::Verify, cert){
+            return false;
+        }
         let parent = self.get_signing_certificate(parent_id.unwrap());
         if parent_id.unwrap() == 0{
             let parent = self.get_root_certificate();
@@ -205,6 +516,14 @@ impl CertificateService for AsyncCertificateServiceImpl {
         return parent.verify_signature(&cert.clone_without_signature_and_sk(), &signature);
     }
 
+    fn verify_many(&mut self, certs: &[VerifiableCertificate]) -> Vec<bool> {
+        let mut verified = HashMap::<u128, bool>::new();
+        certs.iter().map(|cert| match cert {
+            VerifiableCertificate::Signing(cert) => self.verify_signing_chain(cert, &mut verified),
+            VerifiableCertificate::Encryption(cert) => self.verify_encryption_chain(cert, &mut verified),
+        }).collect()
+    }
+
     fn get_signing_certificate(&mut self, serial: u128) -> Option<Falcon1024Certificate> {
         let result = self.signing_certificates.get(&serial);
         if result.is_none(){
@@ -244,11 +563,22 @@ impl CertificateService for AsyncCertificateServiceImpl {
         result
     }
 
+    fn list_signing_certificates(&mut self, cursor: Option<u128>, limit: u32) -> CertificatePage {
+        // Certificates are not cloned, so listing does not copy secret keys
+        get_page(self.signing_certificates.values(), cursor, limit)
+    }
+
+    fn list_encryption_certificates(&mut self, cursor: Option<u128>, limit: u32) -> CertificatePage {
+        get_page(self.encryption_certificates.values(), cursor, limit)
+    }
+
     fn remove_signing_certificate(&mut self, serial: u128) -> bool {
         if !self.signing_certificates.contains_key(&serial){
             return false;
         }
         self.signing_certificates.remove(&serial);
+        self.key_usage.remove(&serial);
+        self.changes.mark(serial);
         true
     }
 
@@ -257,12 +587,169 @@ impl CertificateService for AsyncCertificateServiceImpl {
             return false;
         }
         self.encryption_certificates.remove(&serial);
+        self.key_usage.remove(&serial);
+        self.changes.mark(serial);
+        true
+    }
+
+    fn record_key_usage(&mut self, usage: KeyUsage) -> bool {
+        self.changes.mark_dirty();
+        let total = self.key_usage.entry(usage.serial).or_insert_with(|| KeyUsage::new(usage.serial));
+        let previous = self.usage_thresholds.get_exceeded(total).len();
+        total.add(&usage);
+        let exceeded = self.usage_thresholds.get_exceeded(total);
+        if exceeded.len() == previous{
+            return false;
+        }
+        log::warn!("Certificate {} should be rotated, it reached {}", usage.serial, exceeded.join(", "));
+        true
+    }
+
+    fn get_key_usage(&mut self) -> Vec<KeyUsage> {
+        let mut result: Vec<KeyUsage> = self.key_usage.values().cloned().collect();
+        result.sort_by_key(|usage| usage.serial);
+        result
+    }
+
+    #[inline]
+    fn set_usage_thresholds(&mut self, thresholds: UsageThresholds) {
+        self.usage_thresholds = thresholds;
+        self.changes.mark_dirty();
+    }
+
+    #[inline]
+    fn get_usage_thresholds(&mut self) -> UsageThresholds {
+        self.usage_thresholds.clone()
+    }
+
+    fn reload(&mut self) -> Result<CertificateReloadReport, CertificateServiceError> {
+        let mut stored = match load_versioned::<AsyncCertificateServiceImpl>(Path::new(&self.storage_file_name)) {
+            Ok(stored) => stored,
+            Err(error) => {
+                log::error!("Can not reload certificates: {}", error);
+                return Err(CertificateServiceError::ReloadFailed);
+            }
+        };
+        if let Some((file, key)) = self.key_store.take(){
+            let result = stored.open_key_store(&file, key.clone());
+            // Key derived for salt of reloaded store is kept, so next commit does not derive it again
+            self.key_store = Some(stored.key_store.take().unwrap_or((file.clone(), key)));
+            if let Err(error) = result{
+                log::error!("Can not reload secret keys from {}: {}", file.display(), error);
+                return Err(CertificateServiceError::ReloadFailed);
+            }
+        }
+        // Usage counters and thresholds of running service are newer than stored ones
+        let mut report = CertificateReloadReport::default();
+        self.changes.merge_root(&mut self.root_certificate, stored.root_certificate, &mut report);
+        self.changes.merge_certificates(CertificateKind::Signing, &mut self.signing_certificates,
+                                        stored.signing_certificates, &mut report);
+        self.changes.merge_certificates(CertificateKind::Encryption, &mut self.encryption_certificates,
+                                        stored.encryption_certificates, &mut report);
+        for change in report.changes.iter(){
+            if let CertificateChange::Removed(_, serial) = change{
+                self.key_usage.remove(serial);
+            }
+        }
+        // Policy set by other process replaces ours only if it is newer
+        if stored.policy.version > self.policy.version{
+            self.policy = stored.policy;
+        }
+        // Revocations are never undone, so both sets are kept
+        self.revocations.extend(stored.revocations);
+        self.trust_policy();
+        self.changes.notify(&report);
+        Ok(report)
+    }
+
+    #[inline]
+    fn get_policy(&mut self) -> CertificatePolicy {
+        self.policy.clone()
+    }
+
+    fn set_policy(&mut self, policy: CertificatePolicy) -> Result<(), PolicyError> {
+        if policy.version <= self.policy.version{
+            return Err(PolicyError::Outdated{ current: self.policy.version, given: policy.version });
+        }
+        if !policy.is_signed_by(self.root_certificate.as_ref()){
+            return Err(PolicyError::InvalidSignature);
+        }
+        log::info!("Certificate policy version {} with {} rules is set", policy.version, policy.rules.len());
+        self.policy = policy;
+        self.policy_trusted = true;
+        self.changes.mark_dirty();
+        Ok(())
+    }
+
+    #[inline]
+    fn evaluate_policy(&mut self, subject: &PolicySubject) -> Result<(), PolicyError> {
+        self.evaluate_subject(subject)
+    }
+
+    fn add_revocation(&mut self, revocation: CertificateRevocation) -> bool {
+        let serial = revocation.serial;
+        if serial == ROOT_CERTIFICATE_SERIAL{
+            return false;
+        }
+        self.remove_signing_certificate(serial);
+        self.remove_encryption_certificate(serial);
+        if let Entry::Vacant(entry) = self.revocations.entry(serial){
+            log::info!("Certificate {} is revoked", serial);
+            entry.insert(revocation);
+            self.changes.mark_dirty();
+        }
         true
     }
 
+    #[inline]
+    fn is_revoked(&mut self, serial: u128) -> bool {
+        self.revocations.contains_key(&serial)
+    }
+
+    fn get_revocations(&mut self) -> Vec<CertificateRevocation> {
+        let mut result: Vec<CertificateRevocation> = self.revocations.values().cloned().collect();
+        result.sort_by_key(|revocation| revocation.serial);
+        result
+    }
+
     #[inline]
     fn commit(&mut self) {
-        self.dump(&self.storage_file_name);
+        if self.key_store.is_some(){
+            if let Err(error) = self.dump_split(){
+                log::error!("Failed to save certificates to {}: {}", self.storage_file_name, error);
+                return;
+            }
+        } else if dump_versioned(self, &self.storage_file_name).is_err(){
+            log::error!("Failed to save certificates to {}", self.storage_file_name);
+            return;
+        }
+        self.changes.clear();
+    }
+
+    #[inline]
+    fn is_dirty(&mut self) -> bool {
+        self.changes.is_dirty()
+    }
+
+    fn flush(&mut self) -> Result<(), CertificateServiceError> {
+        // Store is not rewritten without changes, it may be shared with other processes
+        if !self.is_dirty(){
+            return Ok(());
+        }
+        self.commit();
+        if self.is_dirty(){
+            return Err(CertificateServiceError::CommitFailed);
+        }
+        // Written files are synced, so they survive crash of host right after flush
+        let mut files = vec![PathBuf::from(&self.storage_file_name)];
+        files.extend(self.key_store.as_ref().map(|(file, _)| file.clone()));
+        for file in files{
+            if let Err(error) = File::open(&file).and_then(|opened| opened.sync_all()){
+                log::error!("Failed to sync certificates to {}: {}", file.display(), error);
+                return Err(CertificateServiceError::CommitFailed);
+            }
+        }
+        Ok(())
     }
 }
 
@@ -277,13 +764,15 @@ impl BinderServiceHandler<CertificateServiceBinderRequest, CertificateServiceBin
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::pki::impls::keys::falcon1024::{generate_falcon1024_keypair};
-    use crate::pki::impls::keys::kyber1024::{generate_kyber1024_keypair};
+    use crate::pki::impls::keys::falcon1024::{generate_falcon1024_keypair_from_seed};
+    use crate::pki::impls::keys::kyber1024::{generate_kyber1024_keypair_from_seed};
     use std::collections::HashMap;
     use crate::pki::hash::HashType;
+    use crate::pki::certificate::metadata::CertificateQuery;
+    use crate::services::certificate::policy::PolicyRule;
 
     fn create_test_root_certificate() -> Falcon1024RootCertificate {
-        let (public_key, secret_key) = generate_falcon1024_keypair();
+        let (public_key, secret_key) = generate_falcon1024_keypair_from_seed(b"root");
         Falcon1024RootCertificate {
             secret_key: Some(secret_key),
             public_key,
@@ -292,7 +781,7 @@ mod tests {
     }
 
     fn create_test_signing_certificate(parent_serial: u128, root_cert: &Falcon1024RootCertificate) -> Falcon1024Certificate {
-        let (public_key, secret_key) = generate_falcon1024_keypair();
+        let (public_key, secret_key) = generate_falcon1024_keypair_from_seed(b"signing");
         let mut cert = Falcon1024Certificate {
             serial_number: parent_serial + 1,
             parent_serial_number: parent_serial,
@@ -301,6 +790,7 @@ mod tests {
             signature: None,
             name: "".to_string(),
             flags: FLAG_SIGN_CERTS,
+            metadata: Default::default(),
         };
         let signature = root_cert.sign_data(&cert.clone_without_signature_and_sk(), HashType::None).unwrap();
         cert.signature = Some(signature);
@@ -308,7 +798,7 @@ mod tests {
     }
 
     fn create_test_encryption_certificate(parent_serial: u128, signing_cert: &Falcon1024Certificate) -> Kyber1024Certificate {
-        let (public_key, secret_key) = generate_kyber1024_keypair();
+        let (public_key, secret_key) = generate_kyber1024_keypair_from_seed(b"encryption");
         let mut cert = Kyber1024Certificate {
             serial_number: parent_serial + 1,
             parent_serial_number: parent_serial,
@@ -317,6 +807,7 @@ mod tests {
             signature: None,
             name: "Test".to_string(),
             flags: 0,
+            metadata: Default::default(),
         };
         let signature = signing_cert.sign_data(&cert.clone_without_signature_and_sk(), HashType::None).unwrap();
         cert.signature = Some(signature);
@@ -331,6 +822,13 @@ mod tests {
             root_certificate: None,
             signing_certificates: HashMap::new(),
             encryption_certificates: HashMap::new(),
+            key_usage: HashMap::new(),
+            usage_thresholds: UsageThresholds::default(),
+            changes: CertificateChangeTracker::default(),
+            key_store: None,
+            policy: CertificatePolicy::default(),
+            policy_trusted: true,
+            revocations: HashMap::new(),
         };
         service.set_root_certificate(root_cert.clone());
         assert!(service.get_root_certificate() == Some(root_cert));
@@ -344,6 +842,13 @@ mod tests {
             root_certificate: Some(root_cert.clone()),
             signing_certificates: HashMap::new(),
             encryption_certificates: HashMap::new(),
+            key_usage: HashMap::new(),
+            usage_thresholds: UsageThresholds::default(),
+            changes: CertificateChangeTracker::default(),
+            key_store: None,
+            policy: CertificatePolicy::default(),
+            policy_trusted: true,
+            revocations: HashMap::new(),
         };
         let signing_cert = create_test_signing_certificate(0, &root_cert);
         assert!(service.add_signing_certificate(signing_cert.clone()));
@@ -358,6 +863,13 @@ mod tests {
             root_certificate: Some(root_cert.clone()),
             signing_certificates: HashMap::new(),
             encryption_certificates: HashMap::new(),
+            key_usage: HashMap::new(),
+            usage_thresholds: UsageThresholds::default(),
+            changes: CertificateChangeTracker::default(),
+            key_store: None,
+            policy: CertificatePolicy::default(),
+            policy_trusted: true,
+            revocations: HashMap::new(),
         };
         let mut signing_cert = create_test_signing_certificate(0, &root_cert);
         signing_cert.signature = None; // Invalidate the signature
@@ -372,6 +884,13 @@ mod tests {
             root_certificate: Some(root_cert.clone()),
             signing_certificates: HashMap::new(),
             encryption_certificates: HashMap::new(),
+            key_usage: HashMap::new(),
+            usage_thresholds: UsageThresholds::default(),
+            changes: CertificateChangeTracker::default(),
+            key_store: None,
+            policy: CertificatePolicy::default(),
+            policy_trusted: true,
+            revocations: HashMap::new(),
         };
         let signing_cert = create_test_signing_certificate(0, &root_cert);
         assert!(service.verify_signing_certificate(&signing_cert));
@@ -390,6 +909,13 @@ mod tests {
             root_certificate: Some(root_cert.clone()),
             signing_certificates: HashMap::new(),
             encryption_certificates: HashMap::new(),
+            key_usage: HashMap::new(),
+            usage_thresholds: UsageThresholds::default(),
+            changes: CertificateChangeTracker::default(),
+            key_store: None,
+            policy: CertificatePolicy::default(),
+            policy_trusted: true,
+            revocations: HashMap::new(),
         };
         let signing_cert = create_test_signing_certificate(0, &root_cert);
         assert!(service.add_signing_certificate(signing_cert.clone()));
@@ -407,6 +933,13 @@ mod tests {
             root_certificate: Some(root_cert.clone()),
             signing_certificates: HashMap::new(),
             encryption_certificates: HashMap::new(),
+            key_usage: HashMap::new(),
+            usage_thresholds: UsageThresholds::default(),
+            changes: CertificateChangeTracker::default(),
+            key_store: None,
+            policy: CertificatePolicy::default(),
+            policy_trusted: true,
+            revocations: HashMap::new(),
         };
         let signing_cert = create_test_signing_certificate(0, &root_cert);
         assert!(service.add_signing_certificate(signing_cert.clone()));
@@ -421,6 +954,13 @@ mod tests {
             root_certificate: Some(root_cert.clone()),
             signing_certificates: HashMap::new(),
             encryption_certificates: HashMap::new(),
+            key_usage: HashMap::new(),
+            usage_thresholds: UsageThresholds::default(),
+            changes: CertificateChangeTracker::default(),
+            key_store: None,
+            policy: CertificatePolicy::default(),
+            policy_trusted: true,
+            revocations: HashMap::new(),
         };
         let mut signing_cert = create_test_signing_certificate(0, &root_cert);
         signing_cert.signature = None; // Invalidate the signature
@@ -435,6 +975,13 @@ mod tests {
             root_certificate: Some(root_cert.clone()),
             signing_certificates: HashMap::new(),
             encryption_certificates: HashMap::new(),
+            key_usage: HashMap::new(),
+            usage_thresholds: UsageThresholds::default(),
+            changes: CertificateChangeTracker::default(),
+            key_store: None,
+            policy: CertificatePolicy::default(),
+            policy_trusted: true,
+            revocations: HashMap::new(),
         };
         let signing_cert = create_test_signing_certificate(0, &root_cert);
         assert!(service.add_signing_certificate(signing_cert.clone()));
@@ -452,6 +999,13 @@ mod tests {
             root_certificate: Some(root_cert.clone()),
             signing_certificates: HashMap::new(),
             encryption_certificates: HashMap::new(),
+            key_usage: HashMap::new(),
+            usage_thresholds: UsageThresholds::default(),
+            changes: CertificateChangeTracker::default(),
+            key_store: None,
+            policy: CertificatePolicy::default(),
+            policy_trusted: true,
+            revocations: HashMap::new(),
         };
         let signing_cert = create_test_signing_certificate(0, &root_cert);
         assert!(service.add_signing_certificate(signing_cert.clone()));
@@ -460,4 +1014,222 @@ mod tests {
         encryption_cert.signature = None; // Invalidate the signature
         assert!(!service.verify_encryption_certificate(&encryption_cert));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_verify_many() {
+        let root_cert = create_test_root_certificate();
+        let mut service = AsyncCertificateServiceImpl {
+            storage_file_name: "test_storage.bin".to_string(),
+            root_certificate: Some(root_cert.clone()),
+            signing_certificates: HashMap::new(),
+            encryption_certificates: HashMap::new(),
+            key_usage: HashMap::new(),
+            usage_thresholds: UsageThresholds::default(),
+            changes: CertificateChangeTracker::default(),
+            key_store: None,
+            policy: CertificatePolicy::default(),
+            policy_trusted: true,
+            revocations: HashMap::new(),
+        };
+        let signing_cert = create_test_signing_certificate(0, &root_cert);
+        assert!(service.add_signing_certificate(signing_cert.clone()));
+        let (public_key, _) = generate_falcon1024_keypair_from_seed(b"child");
+        let mut child_cert = Falcon1024Certificate {
+            serial_number: 3,
+            parent_serial_number: signing_cert.get_serial(),
+            secret_key: None,
+            public_key,
+            signature: None,
+            name: "".to_string(),
+            flags: 0,
+            metadata: Default::default(),
+        };
+        child_cert.signature = Some(signing_cert.sign_data(&child_cert.clone_without_signature(), HashType::None).unwrap());
+        let encryption_cert = create_test_encryption_certificate(signing_cert.get_serial(), &signing_cert);
+        let mut unsigned_cert = child_cert.clone();
+        unsigned_cert.signature = None;
+        let orphaned_cert = create_test_encryption_certificate(99, &signing_cert);
+        let certs = vec![
+            VerifiableCertificate::Signing(Box::new(signing_cert)),
+            VerifiableCertificate::Signing(Box::new(child_cert)),
+            VerifiableCertificate::Encryption(Box::new(encryption_cert)),
+            VerifiableCertificate::Signing(Box::new(unsigned_cert)),
+            VerifiableCertificate::Encryption(Box::new(orphaned_cert)),
+        ];
+        let expected = vec![true, true, true, false, false];
+        assert_eq!(service.verify_many(&certs), expected);
+        // Bulk verification gives same results as verification one by one
+        let one_by_one: Vec<bool> = certs.iter().map(|cert| match cert {
+            VerifiableCertificate::Signing(cert) => service.verify_signing_certificate(cert),
+            VerifiableCertificate::Encryption(cert) => service.verify_encryption_certificate(cert),
+        }).collect();
+        assert_eq!(one_by_one, expected);
+    }
+
+    #[test]
+    fn test_query_certificates() {
+        let root_cert = create_test_root_certificate();
+        let mut service = AsyncCertificateServiceImpl::new("/tmp/test_query_certificates.dat");
+        service.set_root_certificate(root_cert.clone());
+        let mut signing_cert = create_test_signing_certificate(0, &root_cert);
+        signing_cert.metadata.set_owner("alice").set_tag("env", "prod");
+        // Metadata is signed together with certificate
+        assert!(!service.verify_signing_certificate(&signing_cert));
+        signing_cert.signature = Some(root_cert.sign_data(&signing_cert.clone_without_signature_and_sk(),
+                                                          HashType::None).unwrap());
+        assert!(service.add_signing_certificate(signing_cert.clone()));
+        let encryption_cert = create_test_encryption_certificate(signing_cert.get_serial(), &signing_cert);
+        assert!(service.add_encryption_certificate(encryption_cert));
+
+        let mut query = CertificateQuery::new();
+        query.set_owner("alice").add_tag("env", Some("prod"));
+        let found = service.query_signing_certificates(&query);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].get_metadata(), signing_cert.metadata);
+        assert!(service.query_encryption_certificates(&query).is_empty());
+        assert_eq!(service.query_encryption_certificates(CertificateQuery::new().set_name("Test")).len(), 1);
+    }
+
+    #[test]
+    fn test_key_usage() {
+        let file = std::env::temp_dir().join(format!("milkyway-usage-{}.dat", rand::random::<u64>()));
+        let file = file.to_str().unwrap();
+        let mut service = AsyncCertificateServiceImpl::new(file);
+        service.set_usage_thresholds(UsageThresholds{ signatures: 10, encrypted_bytes: 0, sessions: 2 });
+        assert!(!service.record_key_usage(KeyUsage::new(5).set_signatures(9).clone()));
+        // Warning is emitted once, when threshold is reached
+        assert!(service.record_key_usage(KeyUsage::new(5).set_signatures(1).set_encrypted_bytes(1 << 50).clone()));
+        assert!(!service.record_key_usage(KeyUsage::new(5).set_signatures(1).clone()));
+        assert!(service.record_key_usage(KeyUsage::new(5).set_sessions(2).clone()));
+        service.commit();
+
+        let mut loaded = AsyncCertificateServiceImpl::load_from_file(file);
+        assert_eq!(loaded.get_key_usage(), vec![KeyUsage{ serial: 5, signatures: 11, encrypted_bytes: 1 << 50,
+                                                          sessions: 2 }]);
+        assert_eq!(loaded.get_usage_thresholds().signatures, 10);
+
+        // Stores of previous schema get empty counters
+        let mut payload = AsyncCertificateServiceImpl::new(file).serialize();
+        payload.truncate(payload.len() - HashMap::<u128, KeyUsage>::new().serialize().len()
+            - UsageThresholds::default().serialize().len() - CertificatePolicy::default().serialize().len()
+            - HashMap::<u128, CertificateRevocation>::new().serialize().len());
+        std::fs::write(file, crate::serialization::migration::encode_versioned(1, &payload)).unwrap();
+        crate::serialization::migration::Migrator::new()
+            .register::<AsyncCertificateServiceImpl>(Path::new(file))
+            .run(false).unwrap();
+        let mut migrated = AsyncCertificateServiceImpl::load_from_file(file);
+        assert!(migrated.get_key_usage().is_empty());
+        std::fs::remove_file(file).unwrap();
+        let _ = std::fs::remove_file(format!("{}.v1.bak", file));
+    }
+
+    #[test]
+    fn test_reload() {
+        let file = std::env::temp_dir().join(format!("milkyway-reload-{}.dat", rand::random::<u64>()));
+        let file = file.to_str().unwrap();
+        let root_cert = create_test_root_certificate();
+        let signing_cert = create_test_signing_certificate(ROOT_CERTIFICATE_SERIAL, &root_cert);
+        let encryption_cert = create_test_encryption_certificate(signing_cert.get_serial(), &signing_cert);
+        let mut service = AsyncCertificateServiceImpl::new(file);
+        service.set_root_certificate(root_cert);
+        assert!(service.add_signing_certificate(signing_cert.clone()));
+        service.commit();
+        let applied = std::sync::Arc::new(std::sync::Mutex::new(Vec::<CertificateChange>::new()));
+        let listener_applied = applied.clone();
+        service.subscribe_changes(Box::new(move |change| listener_applied.lock().unwrap().push(*change)));
+
+        // Store is changed by other process
+        let mut other = AsyncCertificateServiceImpl::load_from_file(file);
+        assert!(other.add_encryption_certificate(encryption_cert.clone()));
+        other.commit();
+        let report = service.reload().unwrap();
+        assert_eq!(report.changes, vec![CertificateChange::Added(CertificateKind::Encryption, encryption_cert.get_serial())]);
+        assert!(report.conflicts.is_empty());
+        assert!(service.get_encryption_certificate(encryption_cert.get_serial()).is_some());
+        assert!(service.reload().unwrap().is_empty());
+
+        // Uncommitted removal is kept, other changes are applied
+        assert!(service.remove_encryption_certificate(encryption_cert.get_serial()));
+        assert!(other.remove_signing_certificate(signing_cert.get_serial()));
+        other.commit();
+        let report = service.reload().unwrap();
+        assert_eq!(report.changes, vec![CertificateChange::Removed(CertificateKind::Signing, signing_cert.get_serial())]);
+        assert_eq!(report.conflicts, vec![encryption_cert.get_serial()]);
+        assert!(service.get_encryption_certificate(encryption_cert.get_serial()).is_none());
+        assert_eq!(applied.lock().unwrap().len(), 2);
+
+        std::fs::remove_file(file).unwrap();
+        assert_eq!(service.reload().unwrap_err(), CertificateServiceError::ReloadFailed);
+    }
+
+    #[test]
+    fn test_split_key_store() {
+        let file = std::env::temp_dir().join(format!("milkyway-split-{}.dat", rand::random::<u64>()));
+        let key_store = file.with_extension("keys");
+        let root_cert = create_test_root_certificate();
+        let signing_cert = create_test_signing_certificate(ROOT_CERTIFICATE_SERIAL, &root_cert);
+        let mut service = AsyncCertificateServiceImpl::new(file.to_str().unwrap());
+        service.set_root_certificate(root_cert.clone());
+        assert!(service.add_signing_certificate(signing_cert.clone()));
+        service.commit();
+
+        let (moved, backup) = AsyncCertificateServiceImpl::split_key_store(&file, &key_store, None).unwrap();
+        assert_eq!(moved, 2);
+        assert_eq!(AsyncCertificateServiceImpl::split_key_store(&file, &key_store, None),
+                   Err(KeyStoreError::AlreadyExists(key_store.clone())));
+        let mut public = AsyncCertificateServiceImpl::load_from_file(file.to_str().unwrap());
+        assert!(public.get_root_certificate().unwrap().secret_key.is_none());
+        assert!(public.get_signing_certificate(signing_cert.get_serial()).unwrap().secret_key.is_none());
+
+        public.set_key_store(&key_store, None).unwrap();
+        assert!(public.get_root_certificate().unwrap() == root_cert);
+        assert!(public.get_signing_certificate(signing_cert.get_serial()).unwrap() == signing_cert);
+        // Keys stay in key store once service is committed
+        public.commit();
+        let mut loaded = AsyncCertificateServiceImpl::load_from_file(file.to_str().unwrap());
+        assert!(loaded.get_root_certificate().unwrap().secret_key.is_none());
+        for path in [file, key_store, backup]{
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_certificate_policy() {
+        let file = std::env::temp_dir().join(format!("milkyway-policy-{}.dat", rand::random::<u64>()));
+        let file = file.to_str().unwrap();
+        let root_cert = create_test_root_certificate();
+        let signing_cert = create_test_signing_certificate(ROOT_CERTIFICATE_SERIAL, &root_cert);
+        let encryption_cert = create_test_encryption_certificate(signing_cert.get_serial(), &signing_cert);
+        let mut service = AsyncCertificateServiceImpl::new(file);
+        service.set_root_certificate(root_cert.clone());
+        assert!(service.add_signing_certificate(signing_cert.clone()));
+
+        let mut policy = CertificatePolicy::default();
+        policy.rules.push(PolicyRule::new(signing_cert.get_serial(), "branch-*", 0));
+        policy.version = 1;
+        assert_eq!(service.set_policy(policy.clone()), Err(PolicyError::InvalidSignature));
+        policy.sign(&root_cert).unwrap();
+        assert_eq!(service.set_policy(policy.clone()), Ok(()));
+        assert_eq!(service.set_policy(policy.clone()), Err(PolicyError::Outdated{ current: 1, given: 1 }));
+
+        // Issuer may sign only certificates named branch-*
+        assert!(!service.add_encryption_certificate(encryption_cert.clone()));
+        assert_eq!(service.verify_many(&[VerifiableCertificate::Encryption(Box::new(encryption_cert.clone())),
+                                         VerifiableCertificate::Signing(Box::new(signing_cert.clone()))]), vec![false, true]);
+        let mut subject = PolicySubject::of(PolicyOperation::Sign, &encryption_cert).unwrap();
+        assert!(matches!(service.evaluate_policy(&subject), Err(PolicyError::Denied{ .. })));
+        subject.name = "branch-7".to_string();
+        assert_eq!(service.evaluate_policy(&subject), Ok(()));
+        service.commit();
+
+        let mut loaded = AsyncCertificateServiceImpl::load_from_file(file);
+        assert!(loaded.get_policy() == policy);
+        assert_eq!(loaded.evaluate_policy(&subject), Ok(()));
+        // Policy of another root is not trusted
+        let (public_key, secret_key) = generate_falcon1024_keypair_from_seed(b"other root");
+        loaded.set_root_certificate(Falcon1024RootCertificate{ secret_key: Some(secret_key), public_key,
+                                                              name: "".to_string() });
+        assert_eq!(loaded.evaluate_policy(&subject), Err(PolicyError::InvalidSignature));
+        std::fs::remove_file(file).unwrap();
+    }
+}
@@ -0,0 +1,151 @@
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::Serialized;
+use crate::serialization::serializable::Serializable;
+use std::collections::HashMap;
+use std::path::Path;
+use libmilkyway_derive::{Deserializable, Serializable};
+use crate::message::group::{GroupOperation, GroupRecord};
+use crate::serialization::migration::{dump_versioned, load_versioned, VersionedStorage};
+use crate::services::group::{Group, GroupError, GroupService};
+
+///
+/// Group service storing groups in a file
+///
+#[derive(Serializable, Deserializable)]
+pub struct GroupServiceImpl{
+    storage_file_name: String,
+    groups: HashMap<u128, Group>,
+    /** Timestamps of last applied records, kept after deletion to reject replays **/
+    timestamps: HashMap<u128, u128>,
+}
+
+impl GroupServiceImpl {
+    ///
+    /// Creates a new GroupServiceImpl storing data in provided file
+    ///
+    pub fn new(filename: &str) -> GroupServiceImpl{
+        GroupServiceImpl{
+            storage_file_name: filename.to_string(),
+            groups: HashMap::new(),
+            timestamps: HashMap::new(),
+        }
+    }
+
+    #[inline]
+    pub fn load_from_file(file: &str) -> GroupServiceImpl{
+        let mut service = load_versioned::<GroupServiceImpl>(Path::new(file)).expect("Failed to load group storage");
+        service.storage_file_name = file.to_string();
+        service
+    }
+}
+
+impl VersionedStorage for GroupServiceImpl {
+    const STORE_NAME: &'static str = "groups";
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+impl GroupService for GroupServiceImpl {
+    fn apply_verified_record(&mut self, record: &GroupRecord) -> Result<(), GroupError> {
+        if self.timestamps.get(&record.group_id).is_some_and(|last| *last >= record.timestamp){
+            return Err(GroupError::Outdated);
+        }
+        match &record.operation {
+            GroupOperation::Create => {
+                if self.groups.contains_key(&record.group_id){
+                    return Err(GroupError::GroupExists);
+                }
+                if self.get_group_by_name(&record.name).is_some(){
+                    return Err(GroupError::NameTaken);
+                }
+                self.groups.insert(record.group_id, Group{
+                    id: record.group_id,
+                    name: record.name.clone(),
+                    members: Vec::new(),
+                });
+            }
+            GroupOperation::Delete => {
+                if self.groups.remove(&record.group_id).is_none(){
+                    return Err(GroupError::UnknownGroup);
+                }
+            }
+            GroupOperation::AddMember(member) => {
+                let group = self.groups.get_mut(&record.group_id).ok_or(GroupError::UnknownGroup)?;
+                if group.members.contains(member){
+                    return Err(GroupError::AlreadyMember);
+                }
+                group.members.push(*member);
+            }
+            GroupOperation::RemoveMember(member) => {
+                let group = self.groups.get_mut(&record.group_id).ok_or(GroupError::UnknownGroup)?;
+                let index = group.members.iter().position(|id| id == member)
+                    .ok_or(GroupError::NotMember)?;
+                group.members.remove(index);
+            }
+        }
+        self.timestamps.insert(record.group_id, record.timestamp);
+        Ok(())
+    }
+
+    fn get_group(&self, id: u128) -> Option<Group> {
+        self.groups.get(&id).cloned()
+    }
+
+    fn get_group_by_name(&self, name: &str) -> Option<Group> {
+        self.groups.values().find(|group| group.name == name).cloned()
+    }
+
+    fn get_groups(&self) -> Vec<Group> {
+        let mut result: Vec<Group> = self.groups.values().cloned().collect();
+        result.sort_by_key(|group| group.id);
+        result
+    }
+
+    #[inline]
+    fn commit(&mut self) {
+        if dump_versioned(self, &self.storage_file_name).is_err(){
+            log::error!("Failed to save groups to {}", self.storage_file_name);
+        }
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::common::Message;
+    use crate::services::group::{apply_group_record, get_group_address};
+    use crate::testing::certificate::{test_certificates, MockCertificateService};
+
+    fn record(operation: GroupOperation) -> GroupRecord{
+        GroupRecord::new(5, "branch-office-A", operation, &test_certificates().signing).unwrap()
+    }
+
+    #[test]
+    fn test_group_membership() {
+        let mut certificates = MockCertificateService::with_test_certificates();
+        let mut service = GroupServiceImpl::new("/tmp/groups.dat");
+        assert_eq!(service.apply_verified_record(&record(GroupOperation::AddMember(1))),
+                   Err(GroupError::UnknownGroup));
+        apply_group_record(&mut service, &mut certificates, &record(GroupOperation::Create)).unwrap();
+        let old = record(GroupOperation::AddMember(10));
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        apply_group_record(&mut service, &mut certificates, &record(GroupOperation::AddMember(11))).unwrap();
+        assert_eq!(service.apply_verified_record(&old), Err(GroupError::Outdated));
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        apply_group_record(&mut service, &mut certificates, &record(GroupOperation::AddMember(12))).unwrap();
+        assert_eq!(service.get_group_by_name("branch-office-A").unwrap().members, vec![11, 12]);
+
+        let mut unsigned = record(GroupOperation::RemoveMember(11));
+        unsigned.signature = None;
+        assert_eq!(apply_group_record(&mut service, &mut certificates, &unsigned),
+                   Err(GroupError::InvalidSignature));
+
+        let mut message = Message::new();
+        message.source = 11;
+        message.destination = get_group_address(5);
+        let expanded = service.expand_destination(&message);
+        assert_eq!(expanded.len(), 1);
+        assert_eq!(expanded[0].destination, 12);
+    }
+}
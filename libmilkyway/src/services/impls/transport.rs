@@ -0,0 +1,764 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use crate::message::common::Message;
+use crate::services::transport::{MessageFilter, TransportService};
+use crate::transport::{SendError, TransportListener, TransportSender};
+use crate::transport::access::SharedAccessControl;
+use crate::transport::deadletter::SharedDeadLetterQueue;
+use crate::transport::events::SharedConnectionEvents;
+use crate::transport::ratelimit::{RateLimitVerdict, SharedRateLimiter};
+use crate::transport::signature::{SharedSignaturePolicy, SignatureEnforcement};
+use crate::services::certificate::CertificateService;
+use crate::services::group::SharedGroupService;
+use crate::transport::subscriptions::{SubscriptionStats, Subscriptions};
+use crate::transport::tap::{SharedTransportTap, TapDirection};
+use crate::transport::rawtap::SharedRawFrameTap;
+use crate::transport::crypto::SharedCryptoAlerts;
+
+///
+/// ID of host which is not a member of any network, e.g. CLI started without daemon
+///
+pub const LOCAL_HOST_ID: u128 = 0;
+
+///
+/// State shared by local transport service and its senders
+///
+struct LocalHub{
+    host_id: u128,
+    subscriptions: Mutex<Subscriptions>,
+    /** Messages waiting for delivery **/
+    queue: Mutex<VecDeque<Message>>,
+    /** Received messages over quota, delivered once queue is empty **/
+    deprioritized: Mutex<VecDeque<Message>>,
+    /** Held while messages are delivered, so listeners may send messages themselves **/
+    delivery_lock: Mutex<()>,
+    last_subscription_id: Mutex<u128>,
+    /** Count of messages addressed to other hosts which could not be sent **/
+    undeliverable: Mutex<u64>,
+    /** Sends messages addressed to other hosts, e.g. over connections of daemon **/
+    remote_sender: Mutex<Option<Box<dyn TransportSender>>>,
+    rate_limiter: Mutex<Option<SharedRateLimiter>>,
+    access_control: Mutex<Option<SharedAccessControl>>,
+    signature_policy: Mutex<Option<SignatureEnforcement>>,
+    tap: Mutex<Option<SharedTransportTap>>,
+    /** Expands messages sent to groups to their members **/
+    group_service: Mutex<Option<SharedGroupService>>,
+    dead_letters: Mutex<Option<SharedDeadLetterQueue>>,
+    /** Published by connections of host, see set_connection_events **/
+    connection_events: Mutex<Option<SharedConnectionEvents>>,
+    /** Connections of host publish their frames to it **/
+    raw_frame_tap: Mutex<Option<SharedRawFrameTap>>,
+    /** Crypto transformers of host publish their alerts to it **/
+    crypto_alerts: Mutex<Option<SharedCryptoAlerts>>,
+}
+
+impl LocalHub {
+    fn record(&self, direction: TapDirection, message: &Message){
+        if let Some(tap) = self.tap.lock().unwrap().as_ref(){
+            tap.lock().unwrap().record(direction, message);
+        }
+    }
+
+    fn expand(&self, message: Message) -> Vec<Message>{
+        match self.group_service.lock().unwrap().as_ref() {
+            Some(service) => service.lock().unwrap().expand_destination(&message),
+            None => vec![message],
+        }
+    }
+
+    // Passes message addressed to another host to remote sender, if there is one
+    fn send_remote(&self, message: Message) -> Result<(), SendError>{
+        let (destination, id) = (message.destination, message.id);
+        let result = match self.remote_sender.lock().unwrap().as_mut() {
+            Some(sender) => sender.try_send_message(message),
+            None => Err(SendError::Unreachable(destination)),
+        };
+        if result.is_err(){
+            log::warn!("Local transport: host {} is not reachable, message id={} dropped", destination, id);
+            *self.undeliverable.lock().unwrap() += 1;
+        }
+        result
+    }
+
+    fn send(&self, message: Message) -> Result<(), SendError>{
+        self.record(TapDirection::Outgoing, &message);
+        let mut result = Ok(());
+        for message in self.expand(message){
+            if message.destination != self.host_id{
+                result = result.and(self.send_remote(message));
+                continue;
+            }
+            self.queue.lock().unwrap().push_back(message);
+        }
+        self.deliver_pending();
+        result
+    }
+
+    fn send_batch(&self, messages: Vec<Message>){
+        for message in messages.iter(){
+            self.record(TapDirection::Outgoing, message);
+        }
+        let (deliverable, remote): (Vec<Message>, Vec<Message>) = messages.into_iter()
+            .flat_map(|message| self.expand(message))
+            .partition(|message| message.destination == self.host_id);
+        for message in remote{
+            let _ = self.send_remote(message);
+        }
+        self.queue.lock().unwrap().extend(deliverable);
+        self.deliver_pending();
+    }
+
+    fn receive(&self, message: Message) -> RateLimitVerdict{
+        self.record(TapDirection::Incoming, &message);
+        let limiter = self.rate_limiter.lock().unwrap().clone();
+        let verdict = match limiter {
+            Some(limiter) => limiter.lock().unwrap().check(&message),
+            None => RateLimitVerdict::Allow,
+        };
+        if matches!(verdict, RateLimitVerdict::Drop | RateLimitVerdict::Disconnect){
+            log::warn!("Local transport: message id={} from {} dropped by rate limiter", message.id, message.source);
+            return verdict;
+        }
+        let access = self.access_control.lock().unwrap().clone();
+        if access.is_some_and(|access| !access.lock().unwrap().check_message(&message)){
+            return verdict;
+        }
+        if let Some(enforcement) = self.signature_policy.lock().unwrap().as_mut(){
+            if !enforcement.check(&message){
+                return verdict;
+            }
+        }
+        match verdict {
+            RateLimitVerdict::Deprioritize => self.deprioritized.lock().unwrap().push_back(message),
+            _ => self.queue.lock().unwrap().push_back(message),
+        }
+        self.deliver_pending();
+        verdict
+    }
+
+    ///
+    /// Delivers all queued messages. If delivery is already in progress(e.g. a listener
+    /// replies from on_message) returns immediately and the active delivery loop picks
+    /// the message up.
+    ///
+    fn deliver_pending(&self){
+        loop {
+            let guard = self.delivery_lock.try_lock();
+            if guard.is_err(){
+                return;
+            }
+            loop {
+                let message = self.queue.lock().unwrap().pop_front()
+                    .or_else(|| self.deprioritized.lock().unwrap().pop_front());
+                match message {
+                    Some(message) => {
+                        self.subscriptions.lock().unwrap().dispatch(&message);
+                    }
+                    None => break,
+                }
+            }
+            drop(guard);
+            // Somebody could have queued a message while we were releasing the lock
+            if self.queue.lock().unwrap().is_empty() && self.deprioritized.lock().unwrap().is_empty(){
+                return;
+            }
+        }
+    }
+}
+
+///
+/// Sender delivering messages to listeners of local transport service
+///
+pub struct LocalSender{
+    hub: Arc<LocalHub>,
+}
+
+impl TransportSender for LocalSender{
+    #[inline]
+    fn send_message(&mut self, message: Message) {
+        let _ = self.try_send_message(message);
+    }
+
+    fn try_send_message(&mut self, mut message: Message) -> Result<(), SendError> {
+        message.ensure_id();
+        self.hub.send(message)
+    }
+
+    fn send_batch(&mut self, mut messages: Vec<Message>) {
+        for message in messages.iter_mut(){
+            message.ensure_id();
+        }
+        self.hub.send_batch(messages);
+    }
+}
+
+///
+/// Transport service of host. Messages addressed to the host itself are delivered synchronously
+/// to its listeners, messages addressed to other hosts are passed to remote sender(see
+/// set_remote_sender). Without remote sender they are dropped and try_send_message reports
+/// them as unreachable, which allows modules to load and be exercised offline, e.g. from CLI
+/// without daemon.
+///
+/// Messages received from other hosts(see receive_message) pass policies of service before
+/// they are delivered.
+///
+/// # Warning
+/// Listeners MUST NOT subscribe or unsubscribe from inside of on_message
+///
+#[derive(Clone)]
+pub struct LocalTransportService{
+    hub: Arc<LocalHub>,
+}
+
+impl LocalTransportService {
+    ///
+    /// Creates a new local transport service
+    ///
+    /// # Arguments
+    /// * host_id: u128: ID of local host, see LOCAL_HOST_ID
+    ///
+    pub fn new(host_id: u128) -> LocalTransportService{
+        LocalTransportService{
+            hub: Arc::new(LocalHub{
+                host_id,
+                subscriptions: Mutex::new(Subscriptions::new()),
+                queue: Mutex::new(VecDeque::new()),
+                deprioritized: Mutex::new(VecDeque::new()),
+                delivery_lock: Mutex::new(()),
+                last_subscription_id: Mutex::new(0),
+                undeliverable: Mutex::new(0),
+                remote_sender: Mutex::new(None),
+                rate_limiter: Mutex::new(None),
+                access_control: Mutex::new(None),
+                signature_policy: Mutex::new(None),
+                tap: Mutex::new(None),
+                group_service: Mutex::new(None),
+                dead_letters: Mutex::new(None),
+                connection_events: Mutex::new(None),
+                raw_frame_tap: Mutex::new(None),
+                crypto_alerts: Mutex::new(None),
+            }),
+        }
+    }
+
+    ///
+    /// Gets ID of local host
+    ///
+    #[inline]
+    pub fn get_host_id(&self) -> u128{
+        self.hub.host_id
+    }
+
+    ///
+    /// Gets count of dropped messages which were addressed to other hosts
+    ///
+    #[inline]
+    pub fn get_undeliverable_count(&self) -> u64{
+        *self.hub.undeliverable.lock().unwrap()
+    }
+
+    ///
+    /// Sets sender of messages addressed to other hosts, e.g. router of connections
+    ///
+    /// # Arguments
+    /// * sender: Box<dyn TransportSender>: sender reporting hosts it can not reach
+    ///
+    pub fn set_remote_sender(&mut self, sender: Box<dyn TransportSender>){
+        *self.hub.remote_sender.lock().unwrap() = Some(sender);
+    }
+
+    ///
+    /// Delivers message received from another host, e.g. by connection of daemon. Unlike
+    /// messages sent by host itself, received messages are checked by policies of service.
+    ///
+    /// # Arguments
+    /// * message: Message: received message
+    ///
+    /// returns: RateLimitVerdict: verdict of rate limiter, on Disconnect connection with
+    /// source of message must be closed. Messages rejected by other policies are audited by them.
+    ///
+    pub fn receive_message(&self, message: Message) -> RateLimitVerdict{
+        self.hub.receive(message)
+    }
+
+    ///
+    /// Sets a rate limiter enforcing quotas on received messages
+    ///
+    /// # Arguments
+    /// * limiter: SharedRateLimiter: limiter to check messages with
+    ///
+    pub fn set_rate_limiter(&mut self, limiter: SharedRateLimiter){
+        *self.hub.rate_limiter.lock().unwrap() = Some(limiter);
+    }
+
+    ///
+    /// Sets a queue which messages listeners repeatedly panic on are moved to
+    ///
+    /// # Arguments
+    /// * queue: SharedDeadLetterQueue: queue of poison messages
+    ///
+    pub fn set_dead_letter_queue(&mut self, queue: SharedDeadLetterQueue){
+        self.hub.subscriptions.lock().unwrap().set_dead_letter_queue(queue.clone());
+        *self.hub.dead_letters.lock().unwrap() = Some(queue);
+    }
+
+    ///
+    /// Sets events of connections to peers, connections of host publish events to them
+    ///
+    /// # Arguments
+    /// * events: SharedConnectionEvents: events modules subscribe to
+    ///
+    pub fn set_connection_events(&mut self, events: SharedConnectionEvents){
+        *self.hub.connection_events.lock().unwrap() = Some(events);
+    }
+
+    ///
+    /// Sets alerts of cryptographic failures, crypto transformers of host publish alerts to them
+    ///
+    /// # Arguments
+    /// * alerts: SharedCryptoAlerts: alerts modules subscribe to
+    ///
+    pub fn set_crypto_alerts(&mut self, alerts: SharedCryptoAlerts){
+        *self.hub.crypto_alerts.lock().unwrap() = Some(alerts);
+    }
+
+    ///
+    /// Sets a group service expanding messages which host sends to groups
+    ///
+    /// # Arguments
+    /// * service: SharedGroupService: service with groups known to host
+    ///
+    pub fn set_group_service(&mut self, service: SharedGroupService){
+        *self.hub.group_service.lock().unwrap() = Some(service);
+    }
+
+    ///
+    /// Sets allow and block lists enforced on sources of received messages
+    ///
+    /// # Arguments
+    /// * access: SharedAccessControl: lists to enforce
+    ///
+    pub fn set_access_control(&mut self, access: SharedAccessControl){
+        *self.hub.access_control.lock().unwrap() = Some(access);
+    }
+
+    ///
+    /// Sets a policy requiring signatures on received messages
+    ///
+    /// # Arguments
+    /// * policy: SharedSignaturePolicy: policy to enforce
+    /// * certificates: Box<dyn CertificateService>: service to verify signatures with
+    ///
+    pub fn set_signature_policy(&mut self, policy: SharedSignaturePolicy, certificates: Box<dyn CertificateService>){
+        *self.hub.signature_policy.lock().unwrap() = Some(SignatureEnforcement::new(policy, certificates));
+    }
+
+    ///
+    /// Sets a tap recording messages sent and received by service
+    ///
+    /// # Arguments
+    /// * tap: Option<SharedTransportTap>: tap to record messages to, None disables tapping
+    ///
+    pub fn set_tap(&mut self, tap: Option<SharedTransportTap>){
+        *self.hub.tap.lock().unwrap() = tap;
+    }
+
+    ///
+    /// Sets a tap which connections of host publish raw frames to, so operators can
+    /// subscribe to them through service
+    ///
+    /// # Arguments
+    /// * tap: SharedRawFrameTap: tap given to every connection
+    ///
+    pub fn set_raw_frame_tap(&mut self, tap: SharedRawFrameTap){
+        *self.hub.raw_frame_tap.lock().unwrap() = Some(tap);
+    }
+
+    fn next_subscription_id(&self) -> u128{
+        let mut last_id = self.hub.last_subscription_id.lock().unwrap();
+        *last_id += 1;
+        *last_id
+    }
+}
+
+impl TransportService for LocalTransportService{
+    fn subscribe_to_messages(&mut self, filter: &MessageFilter,
+                             listener: Box<dyn TransportListener>) -> u128 {
+        let id = self.next_subscription_id();
+        self.hub.subscriptions.lock().unwrap().add(id, filter.clone(), listener);
+        id
+    }
+
+    fn unsubscribe(&mut self, filter_id: u128) {
+        self.hub.subscriptions.lock().unwrap().remove(filter_id);
+    }
+
+    fn subscribe_owned(&mut self, module_id: u64, filter: &MessageFilter,
+                       listener: Box<dyn TransportListener>) -> u128 {
+        let id = self.next_subscription_id();
+        self.hub.subscriptions.lock().unwrap().add_owned(id, Some(module_id), filter.clone(), listener);
+        id
+    }
+
+    fn unsubscribe_all(&mut self, module_id: u64) -> usize {
+        self.hub.subscriptions.lock().unwrap().remove_owned(module_id)
+    }
+
+    fn get_module_subscription_counts(&self) -> HashMap<u64, usize> {
+        self.hub.subscriptions.lock().unwrap().get_owner_counts()
+    }
+
+    fn get_subscription_stats(&self, filter_id: u128) -> Option<SubscriptionStats> {
+        self.hub.subscriptions.lock().unwrap().get_stats(filter_id)
+    }
+
+    fn get_sender(&mut self) -> Box<dyn TransportSender> {
+        Box::new(LocalSender{
+            hub: self.hub.clone(),
+        })
+    }
+
+    fn get_rate_limiter(&self) -> Option<SharedRateLimiter> {
+        self.hub.rate_limiter.lock().unwrap().clone()
+    }
+
+    fn get_dead_letter_queue(&self) -> Option<SharedDeadLetterQueue> {
+        self.hub.dead_letters.lock().unwrap().clone()
+    }
+
+    fn get_raw_frame_tap(&self) -> Option<SharedRawFrameTap> {
+        self.hub.raw_frame_tap.lock().unwrap().clone()
+    }
+
+    fn get_connection_events(&self) -> Option<SharedConnectionEvents> {
+        self.hub.connection_events.lock().unwrap().clone()
+    }
+
+    fn get_crypto_alerts(&self) -> Option<SharedCryptoAlerts> {
+        self.hub.crypto_alerts.lock().unwrap().clone()
+    }
+
+    fn get_group_service(&self) -> Option<SharedGroupService> {
+        self.hub.group_service.lock().unwrap().clone()
+    }
+
+    fn get_access_control(&self) -> Option<SharedAccessControl> {
+        self.hub.access_control.lock().unwrap().clone()
+    }
+
+    fn get_signature_policy(&self) -> Option<SharedSignaturePolicy> {
+        self.hub.signature_policy.lock().unwrap().as_ref().map(|enforcement| enforcement.policy.clone())
+    }
+
+    fn get_tap(&self) -> Option<SharedTransportTap> {
+        self.hub.tap.lock().unwrap().clone()
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::types::MessageType;
+    use crate::pki::certificate::{FLAG_SIGN_MESSAGES, FLAG_TRANSPORT_TAP, FLAG_USER_CERT};
+    use crate::message::group::{GroupOperation, GroupRecord};
+    use crate::services::group::{get_group_address, GroupService};
+    use crate::services::impls::group::GroupServiceImpl;
+    use crate::transport::access::{AccessControl, AccessRule, PeerSelector};
+    use crate::transport::deadletter::DeadLetterQueue;
+    use crate::transport::events::{ConnectionEvent, ConnectionEvents};
+    use crate::testing::certificate::{test_certificates, MockCertificateService, TEST_SIGNING_CERTIFICATE_SERIAL};
+    use crate::transport::ratelimit::{QuotaAction, QuotaLimits, RateLimitPolicy, RateLimiter};
+    use crate::transport::signature::{SignaturePolicy, SignatureRejection, DEFAULT_SIGNATURE_AUDIT_CAPACITY};
+    use crate::transport::tap::TransportTap;
+    use crate::transport::rawtap::{RawFrameFilter, RawFrameTap};
+    use crate::transport::stats::FrameDirection;
+    use crate::transport::operator::OperatorIdentity;
+    use crate::transport::crypto::{CryptoAlert, CryptoAlertKind, CryptoAlerts, CryptoFailureStats};
+    use crate::services::transport::ModuleTransportService;
+
+    struct EchoListener{
+        received: Arc<Mutex<Vec<Message>>>,
+        sender: Box<dyn TransportSender>,
+    }
+
+    impl TransportListener for EchoListener{
+        fn on_message(&mut self, message: Message) {
+            self.received.lock().unwrap().push(message.clone());
+            if message.message_type == MessageType::Ping{
+                let mut reply = message;
+                reply.set_type(MessageType::Pong);
+                self.sender.send_message(reply);
+            }
+        }
+    }
+
+    #[test]
+    fn test_local_transport() {
+        let mut service = LocalTransportService::new(LOCAL_HOST_ID);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let listener = EchoListener{
+            received: received.clone(),
+            sender: service.get_sender(),
+        };
+        let filter_id = service.subscribe_owned(2, MessageFilter::new().filter_module(2), Box::new(listener));
+
+        let mut message = Message::new();
+        message.set_type(MessageType::Ping);
+        message.module_id = 2;
+        message.destination = LOCAL_HOST_ID;
+        service.send_message(message.clone());
+        let types: Vec<MessageType> = received.lock().unwrap().iter().map(|message| message.message_type.clone()).collect();
+        assert_eq!(types, vec![MessageType::Ping, MessageType::Pong]);
+        assert_eq!(service.get_subscription_stats(filter_id).unwrap().delivered, 2);
+
+        message.destination = 5;
+        assert_eq!(service.try_send_message(message.clone()), Err(SendError::Unreachable(5)));
+        assert_eq!(received.lock().unwrap().len(), 2);
+        assert_eq!(service.get_undeliverable_count(), 1);
+        let mut other = message.clone();
+        other.destination = LOCAL_HOST_ID;
+        service.send_batch(vec![other.clone(), message, other]);
+        assert_eq!(received.lock().unwrap().len(), 6);
+        assert_eq!(service.get_undeliverable_count(), 2);
+        assert_eq!(service.get_module_subscription_counts().get(&2), Some(&1));
+        assert_eq!(service.clone().unsubscribe_all(2), 1);
+    }
+
+    struct RemoteSender{
+        sent: Arc<Mutex<Vec<u128>>>,
+    }
+
+    impl TransportSender for RemoteSender{
+        fn send_message(&mut self, message: Message) {
+            let _ = self.try_send_message(message);
+        }
+
+        fn try_send_message(&mut self, message: Message) -> Result<(), SendError> {
+            if message.destination == 9{
+                return Err(SendError::Unreachable(9));
+            }
+            self.sent.lock().unwrap().push(message.destination);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_remote_messages_passed_to_remote_sender() {
+        let (mut service, received) = create_receiving_service();
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        service.set_remote_sender(Box::new(RemoteSender{ sent: sent.clone() }));
+        assert_eq!(service.try_send_message(message_from(1, 5)), Ok(()));
+        assert_eq!(service.try_send_message(message_from(1, 9)), Err(SendError::Unreachable(9)));
+        service.send_batch(vec![message_from(1, 6), message_from(1, 1), message_from(1, 9)]);
+        assert_eq!(*sent.lock().unwrap(), vec![5, 6]);
+        assert_eq!(received.lock().unwrap().len(), 1);
+        assert_eq!(service.get_undeliverable_count(), 2);
+    }
+
+    // Creates service with listener collecting received messages
+    fn create_receiving_service() -> (LocalTransportService, Arc<Mutex<Vec<Message>>>){
+        let mut service = LocalTransportService::new(1);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let listener = EchoListener{
+            received: received.clone(),
+            sender: service.get_sender(),
+        };
+        service.subscribe_to_messages(&MessageFilter::new(), Box::new(listener));
+        (service, received)
+    }
+
+    fn message_from(source: u128, destination: u128) -> Message{
+        let mut message = Message::new();
+        message.set_type(MessageType::Pong);
+        message.source = source;
+        message.destination = destination;
+        message
+    }
+
+    #[test]
+    fn test_received_messages_rate_limited() {
+        let (mut service, received) = create_receiving_service();
+        let mut policy = RateLimitPolicy::new(QuotaAction::Disconnect);
+        policy.peer_limits = Some(QuotaLimits{
+            messages_per_second: 1,
+            message_burst: 2,
+            ..Default::default()
+        });
+        service.set_rate_limiter(RateLimiter::new_shared(policy));
+        let verdicts: Vec<RateLimitVerdict> = (0..3).map(|_| service.receive_message(message_from(5, 1))).collect();
+        assert_eq!(verdicts, vec![RateLimitVerdict::Allow, RateLimitVerdict::Allow, RateLimitVerdict::Disconnect]);
+        // Messages of host itself are not limited
+        for _ in 0..3{
+            service.send_message(message_from(1, 1));
+        }
+        assert_eq!(received.lock().unwrap().len(), 5);
+        assert_eq!(service.get_rate_limiter().unwrap().lock().unwrap().get_metrics().disconnected, 1);
+    }
+
+    #[test]
+    fn test_received_messages_signature_checked() {
+        let (mut service, received) = create_receiving_service();
+        let policy = SignaturePolicy::new_shared(DEFAULT_SIGNATURE_AUDIT_CAPACITY);
+        policy.lock().unwrap().require_type(MessageType::Pong).bind_peer_certificate(5, TEST_SIGNING_CERTIFICATE_SERIAL);
+        service.set_signature_policy(policy, Box::new(MockCertificateService::with_test_certificates()));
+        let mut signed = message_from(5, 1);
+        signed.sign_by(&test_certificates().signing).unwrap();
+        service.receive_message(signed.clone());
+        service.receive_message(message_from(5, 1));
+        // Signer is not bound to peer 6
+        signed.source = 6;
+        signed.sign_by(&test_certificates().signing).unwrap();
+        service.receive_message(signed);
+        // Messages of host itself are not checked
+        service.send_message(message_from(1, 1));
+        assert_eq!(received.lock().unwrap().len(), 2);
+        let audit = service.get_signature_policy().unwrap().lock().unwrap().get_audit_entries();
+        assert_eq!(audit.iter().map(|entry| entry.reason).collect::<Vec<_>>(),
+                   vec![SignatureRejection::Unsigned, SignatureRejection::NotPeerCertificate]);
+    }
+
+    #[test]
+    fn test_messages_tapped() {
+        let (mut service, _) = create_receiving_service();
+        assert!(service.get_tap().is_none());
+        service.set_tap(TransportTap::new_shared(8, FLAG_TRANSPORT_TAP));
+        service.receive_message(message_from(5, 1));
+        service.send_message(message_from(1, 5));
+        let records = service.get_tap().unwrap().lock().unwrap().get_records();
+        let directions: Vec<(TapDirection, u128)> = records.iter()
+            .map(|record| (record.direction, record.source))
+            .collect();
+        assert_eq!(directions, vec![(TapDirection::Incoming, 5), (TapDirection::Outgoing, 1)]);
+    }
+
+    #[test]
+    fn test_received_messages_access_checked() {
+        let (mut service, received) = create_receiving_service();
+        let file = std::env::temp_dir().join(format!("milkyway-access-{}.dat", rand::random::<u64>()));
+        let mut access = AccessControl::new(file.to_str().unwrap());
+        access.add(AccessRule::Block, PeerSelector::PeerId(5));
+        service.set_access_control(Arc::new(Mutex::new(access)));
+        service.receive_message(message_from(5, 1));
+        service.receive_message(message_from(6, 1));
+        assert_eq!(received.lock().unwrap().iter().map(|message| message.source).collect::<Vec<u128>>(), vec![6]);
+        assert_eq!(service.get_access_control().unwrap().lock().unwrap().get_audit().len(), 1);
+    }
+
+    #[test]
+    fn test_group_destination_expanded() {
+        let (mut service, received) = create_receiving_service();
+        let signing = test_certificates().signing;
+        let file = std::env::temp_dir().join(format!("milkyway-groups-{}.dat", rand::random::<u64>()));
+        let mut groups = GroupServiceImpl::new(file.to_str().unwrap());
+        let operations = [GroupOperation::Create, GroupOperation::AddMember(1), GroupOperation::AddMember(3),
+                          GroupOperation::AddMember(4)];
+        for (index, operation) in operations.into_iter().enumerate(){
+            let mut record = GroupRecord::new(9, "branch-office-A", operation, &signing).unwrap();
+            record.timestamp = index as u128 + 1;
+            groups.apply_verified_record(&record).unwrap();
+        }
+        service.set_group_service(Arc::new(Mutex::new(groups)));
+        // Every member except of source gets a copy
+        let result = service.try_send_message(message_from(2, get_group_address(9)));
+        assert_eq!(result, Err(SendError::Unreachable(3)));
+        assert_eq!(received.lock().unwrap().iter().map(|message| message.destination).collect::<Vec<u128>>(), vec![1]);
+        assert_eq!(service.get_undeliverable_count(), 2);
+    }
+
+    struct PoisonedListener{
+        poisoned: Arc<Mutex<bool>>,
+        received: Arc<Mutex<Vec<Message>>>,
+    }
+
+    impl TransportListener for PoisonedListener{
+        fn on_message(&mut self, message: Message) {
+            if *self.poisoned.lock().unwrap(){
+                panic!("can not handle message");
+            }
+            self.received.lock().unwrap().push(message);
+        }
+    }
+
+    #[test]
+    fn test_dead_letter_replay() {
+        let mut service = LocalTransportService::new(1);
+        let file = std::env::temp_dir().join(format!("milkyway-local-dlq-{}.dat", rand::random::<u64>()));
+        let queue = Arc::new(Mutex::new(DeadLetterQueue::new(file.to_str().unwrap(), 2)));
+        service.set_dead_letter_queue(queue.clone());
+        let poisoned = Arc::new(Mutex::new(true));
+        let received = Arc::new(Mutex::new(Vec::new()));
+        service.subscribe_to_messages(&MessageFilter::new(), Box::new(PoisonedListener{
+            poisoned: poisoned.clone(),
+            received: received.clone(),
+        }));
+        service.receive_message(message_from(5, 1));
+        assert!(received.lock().unwrap().is_empty());
+        assert_eq!(service.get_dead_letter_queue().unwrap().lock().unwrap().get_depth(), 1);
+
+        *poisoned.lock().unwrap() = false;
+        queue.lock().unwrap().request_replay(None);
+        assert_eq!(service.replay_dead_letters(), 1);
+        assert_eq!(received.lock().unwrap().len(), 1);
+        assert_eq!(queue.lock().unwrap().get_depth(), 0);
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_connection_events_subscribed() {
+        let mut service = LocalTransportService::new(1);
+        assert!(service.subscribe_connection_events(Box::new(|_: &ConnectionEvent| {})).is_none());
+        let events = ConnectionEvents::new_shared();
+        service.set_connection_events(events.clone());
+        let connected = Arc::new(Mutex::new(Vec::new()));
+        let collected = connected.clone();
+        service.subscribe_connection_events(Box::new(move |event: &ConnectionEvent| {
+            collected.lock().unwrap().push(event.get_connection_id());
+        })).unwrap();
+        events.lock().unwrap().on_connected(7, "127.0.0.1:1234");
+        assert_eq!(*connected.lock().unwrap(), vec![7]);
+    }
+
+    #[test]
+    fn test_crypto_alerts_subscribed_by_module() {
+        let mut service = LocalTransportService::new(1);
+        assert!(service.subscribe_crypto_alerts(Box::new(|_: &CryptoAlert| {})).is_none());
+        let alerts = CryptoAlerts::new_shared();
+        service.set_crypto_alerts(alerts.clone());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let collected = received.clone();
+        let mut module_service = ModuleTransportService::new(Box::new(service.clone()), 3);
+        module_service.subscribe_crypto_alerts(Box::new(move |alert: &CryptoAlert| {
+            collected.lock().unwrap().push(alert.kind);
+        })).unwrap();
+        let alert = CryptoAlert{
+            kind: CryptoAlertKind::SignatureFailure,
+            remote_serial: 5,
+            remote_name: "peer".to_string(),
+            stats: CryptoFailureStats{ signature_failures: 1, decryption_failures: 0 },
+        };
+        alerts.lock().unwrap().publish(&alert);
+        assert_eq!(*received.lock().unwrap(), vec![CryptoAlertKind::SignatureFailure]);
+        assert_eq!(alerts.lock().unwrap().get_audit()[0].alert, alert);
+
+        assert_eq!(module_service.unsubscribe_all(3), 1);
+        alerts.lock().unwrap().publish(&alert);
+        assert_eq!(received.lock().unwrap().len(), 1);
+        assert_eq!(alerts.lock().unwrap().get_audit().len(), 2);
+    }
+
+    #[test]
+    fn test_raw_frames_subscribed() {
+        let mut service = LocalTransportService::new(1);
+        assert!(service.get_raw_frame_tap().is_none());
+        let mut certificates = MockCertificateService::with_test_certificates();
+        let mut certificate = test_certificates().signing;
+        certificate.flags = FLAG_USER_CERT | FLAG_SIGN_MESSAGES | FLAG_TRANSPORT_TAP;
+        let operator = OperatorIdentity::new(certificate).unwrap();
+        service.set_raw_frame_tap(RawFrameTap::new_shared(true));
+        let subscription = service.get_raw_frame_tap().unwrap()
+            .subscribe(&mut certificates, &operator, RawFrameFilter::all(), 4).unwrap();
+        service.get_raw_frame_tap().unwrap().publish(FrameDirection::Receive, 1, 5, &vec![1, 2, 3]);
+        assert_eq!(subscription.take_pending()[0].peer_id, 5);
+    }
+}
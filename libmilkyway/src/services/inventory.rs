@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use crate::get_timestamp_with_milliseconds;
+use crate::message::builder::MessageBuilder;
+use crate::message::common::Message;
+use crate::message::inventory::{InventoryRequest, InventoryResponse, NodeInventory};
+use crate::message::types::MessageType;
+use crate::pki::certificate::{Certificate, FLAG_SIGN_MESSAGES, FLAG_USER_CERT};
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+use crate::serialization::deserializable::Deserializable;
+use crate::services::certificate::{CertificateService, CertificateServiceBinder, ROOT_CERTIFICATE_SERIAL};
+use crate::transport::{TransportListener, TransportSender};
+use crate::transport::signature::SharedSignaturePolicy;
+
+///
+/// ID inventory module is registered with, requests and answers are sent to it
+///
+pub const INVENTORY_MODULE_ID: u64 = 3;
+
+///
+/// Inventory of peer as it was received
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachedInventory{
+    pub inventory: NodeInventory,
+    /** Serial of certificate which signed response **/
+    pub signer: u128,
+    /** Milliseconds since epoch when response was received **/
+    pub received_at: u128,
+}
+
+///
+/// Inventories received from peers, shared between receiver and consumers waiting for them
+///
+pub type SharedInventoryCache = Arc<InventoryCache>;
+
+///
+/// Last inventory received from each peer. Freshness is judged by time facts were collected
+/// on peer(NodeInventory::collected_at), as peer may answer with facts it cached itself.
+///
+#[derive(Debug, Default)]
+pub struct InventoryCache{
+    entries: Mutex<HashMap<u128, CachedInventory>>,
+    updated: Condvar,
+}
+
+impl InventoryCache {
+    #[inline]
+    pub fn new_shared() -> SharedInventoryCache{
+        Arc::new(InventoryCache::default())
+    }
+
+    ///
+    /// Stores inventory of peer replacing older one and wakes up waiters
+    ///
+    pub fn insert(&self, peer_id: u128, inventory: CachedInventory){
+        self.entries.lock().unwrap().insert(peer_id, inventory);
+        self.updated.notify_all();
+    }
+
+    pub fn get(&self, peer_id: u128) -> Option<CachedInventory>{
+        self.entries.lock().unwrap().get(&peer_id).cloned()
+    }
+
+    ///
+    /// Gets inventory of peer collected not longer than max_age milliseconds ago
+    ///
+    /// # Arguments
+    /// * peer_id: u128: ID of peer
+    /// * max_age: u64: oldest acceptable age in milliseconds
+    /// * now: u128: current time in milliseconds since epoch
+    ///
+    pub fn get_fresh(&self, peer_id: u128, max_age: u64, now: u128) -> Option<CachedInventory>{
+        self.get(peer_id).filter(|cached| cached.inventory.get_age(now) <= max_age as u128)
+    }
+
+    ///
+    /// Gets inventories of all peers ordered by peer ID
+    ///
+    pub fn get_all(&self) -> Vec<(u128, CachedInventory)>{
+        let mut entries: Vec<(u128, CachedInventory)> = self.entries.lock().unwrap().iter()
+            .map(|(peer_id, cached)| (*peer_id, cached.clone()))
+            .collect();
+        entries.sort_by_key(|(peer_id, _)| *peer_id);
+        entries
+    }
+
+    ///
+    /// Waits until inventory of peer is received after given time
+    ///
+    /// # Arguments
+    /// * peer_id: u128: ID of peer
+    /// * received_after: u128: milliseconds since epoch, older responses are ignored
+    /// * timeout: Duration: how long to wait
+    ///
+    /// returns: Option<CachedInventory>: inventory or None if peer did not answer in time
+    ///
+    pub fn wait_for(&self, peer_id: u128, received_after: u128, timeout: Duration) -> Option<CachedInventory>{
+        let deadline = Instant::now() + timeout;
+        let mut entries = self.entries.lock().unwrap();
+        loop {
+            if let Some(cached) = entries.get(&peer_id).filter(|cached| cached.received_at >= received_after){
+                return Some(cached.clone());
+            }
+            let left = deadline.checked_duration_since(Instant::now())?;
+            entries = self.updated.wait_timeout(entries, left).unwrap().0;
+        }
+    }
+}
+
+// Gets serial of trusted certificate allowed to sign messages which signed message
+fn find_signer<S: CertificateService + ?Sized>(certificates: &mut S, message: &Message) -> Option<u128>{
+    let signature = message.signature.as_ref()?;
+    let certificate = certificates.get_signing_certificate(message.get_signer_serial())?;
+    if !certificate.check_flag(FLAG_SIGN_MESSAGES) || !certificates.verify_signing_certificate(&certificate){
+        return None;
+    }
+    if !certificate.verify_signature(&message.as_signable(), signature){
+        return None;
+    }
+    Some(certificate.get_serial())
+}
+
+///
+/// Finds certificate node signs its inventory with: signing certificate with secret key
+/// allowed to sign messages, which is neither root nor an operator(user) one. The lowest
+/// serial wins if node has several.
+///
+pub fn find_node_signing_certificate<S: CertificateService + ?Sized>(certificates: &mut S) -> Option<Falcon1024Certificate>{
+    certificates.get_signing_certificates().into_iter()
+        .filter(|certificate| certificate.get_secret_key().is_some() && certificate.get_serial() != ROOT_CERTIFICATE_SERIAL)
+        .filter(|certificate| certificate.check_flag(FLAG_SIGN_MESSAGES) && !certificate.check_flag(FLAG_USER_CERT))
+        .min_by_key(|certificate| certificate.get_serial())
+}
+
+///
+/// Collects facts about node for its inventory
+///
+pub type InventoryCollector = Box<dyn FnMut() -> NodeInventory + Send + Sync>;
+
+///
+/// Answers InventoryRequest messages signed by trusted certificates with inventory signed by
+/// node. Collected facts are cached and reused while they are younger than age requester
+/// accepts.
+///
+pub struct InventoryResponder{
+    collector: InventoryCollector,
+    certificates: Box<CertificateServiceBinder>,
+    sender: Box<dyn TransportSender>,
+    host_id: u128,
+    cached: Option<NodeInventory>,
+}
+
+impl InventoryResponder {
+    ///
+    /// Creates a responder
+    ///
+    /// # Arguments
+    /// * collector: InventoryCollector: collects facts about node
+    /// * certificates: Box<CertificateServiceBinder>: service to authenticate requests and sign responses with
+    /// * sender: Box<dyn TransportSender>: sender of responses
+    /// * host_id: u128: ID of host
+    ///
+    pub fn new(collector: InventoryCollector, certificates: Box<CertificateServiceBinder>,
+               sender: Box<dyn TransportSender>, host_id: u128) -> InventoryResponder{
+        InventoryResponder{
+            collector,
+            certificates,
+            sender,
+            host_id,
+            cached: None,
+        }
+    }
+
+    // Gets cached inventory if it is young enough, otherwise collects it again
+    fn get_inventory(&mut self, max_age: u64) -> NodeInventory{
+        let now = get_timestamp_with_milliseconds();
+        match &self.cached {
+            Some(inventory) if inventory.get_age(now) <= max_age as u128 => inventory.clone(),
+            _ => {
+                let inventory = (self.collector)();
+                self.cached = Some(inventory.clone());
+                inventory
+            }
+        }
+    }
+}
+
+impl TransportListener for InventoryResponder{
+    fn on_message(&mut self, message: Message) {
+        if message.message_type != MessageType::InventoryRequest{
+            return;
+        }
+        let request = match message.data.as_ref().map(InventoryRequest::from_serialized) {
+            Some(Ok((request, _))) => request,
+            _ => {
+                log::warn!("Malformed inventory request from {}", message.source);
+                return;
+            }
+        };
+        if find_signer(self.certificates.as_mut(), &message).is_none(){
+            log::warn!("Denied inventory request from {} signed by {}", message.source, message.get_signer_serial());
+            return;
+        }
+        let certificate = match find_node_signing_certificate(self.certificates.as_mut()) {
+            Some(certificate) => certificate,
+            None => {
+                log::error!("Can not answer inventory request from {}: no certificate to sign it", message.source);
+                return;
+            }
+        };
+        let response = InventoryResponse{
+            request_id: request.request_id,
+            inventory: self.get_inventory(request.max_age),
+        };
+        let mut reply = MessageBuilder::from_payload(&response)
+            .set_source(self.host_id)
+            .set_destination(message.source)
+            .set_module_id(INVENTORY_MODULE_ID)
+            .build()
+            .expect("All required fields are set");
+        if let Err(error) = reply.sign_by(&certificate){
+            log::error!("Can not sign inventory for {}: {:?}", message.source, error);
+            return;
+        }
+        self.sender.send_message(reply);
+    }
+}
+
+///
+/// Stores inventories answering requests of this node. Responses which are not signed by
+/// trusted certificates bound to their source in signature policy are dropped, so node can not
+/// publish inventory of another one.
+///
+pub struct InventoryReceiver{
+    certificates: Box<CertificateServiceBinder>,
+    cache: SharedInventoryCache,
+    signature_policy: Option<SharedSignaturePolicy>,
+}
+
+impl InventoryReceiver {
+    ///
+    /// Creates a receiver
+    ///
+    /// # Arguments
+    /// * certificates: Box<CertificateServiceBinder>: service to verify responses with
+    /// * cache: SharedInventoryCache: cache to store responses in
+    /// * signature_policy: Option<SharedSignaturePolicy>: policy binding signing certificates to
+    ///   peers(see TransportService::get_signature_policy), all responses are
+    ///   dropped without it
+    ///
+    pub fn new(certificates: Box<CertificateServiceBinder>, cache: SharedInventoryCache,
+               signature_policy: Option<SharedSignaturePolicy>) -> InventoryReceiver{
+        InventoryReceiver{
+            certificates,
+            cache,
+            signature_policy,
+        }
+    }
+}
+
+impl TransportListener for InventoryReceiver{
+    fn on_message(&mut self, message: Message) {
+        if message.message_type != MessageType::InventoryResponse{
+            return;
+        }
+        let response = match message.data.as_ref().map(InventoryResponse::from_serialized) {
+            Some(Ok((response, _))) => response,
+            _ => {
+                log::warn!("Malformed inventory from {}", message.source);
+                return;
+            }
+        };
+        let signer = match find_signer(self.certificates.as_mut(), &message) {
+            Some(signer) => signer,
+            None => {
+                log::warn!("Dropped inventory from {} which is not signed by trusted certificate", message.source);
+                return;
+            }
+        };
+        let is_peer_certificate = self.signature_policy.as_ref()
+            .is_some_and(|policy| policy.lock().unwrap().is_peer_certificate(message.source, signer));
+        if !is_peer_certificate{
+            log::warn!("Dropped inventory from {} signed by {} which is not bound to it", message.source, signer);
+            return;
+        }
+        self.cache.insert(message.source, CachedInventory{
+            inventory: response.inventory,
+            signer,
+            received_at: get_timestamp_with_milliseconds(),
+        });
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::transport::{MessageFilter, TransportService};
+    use crate::testing::certificate::{test_certificates, MockCertificateService};
+    use crate::module::{HostType, ModuleDataBus};
+    use crate::testing::module::TestDataBus;
+    use crate::testing::transport::LoopbackTransportService;
+    use crate::tokio::init_tokio;
+    use crate::transport::signature::{SignaturePolicy, DEFAULT_SIGNATURE_AUDIT_CAPACITY};
+    use crate::testing::certificate::TEST_SIGNING_CERTIFICATE_SERIAL;
+
+    #[test]
+    fn test_inventory_exchange() {
+        init_tokio();
+        let (node, mut operator) = LoopbackTransportService::pair(1, 2);
+        let certificates = MockCertificateService::with_test_certificates();
+        let data_bus = TestDataBus::new(HostType::Peer, 1, certificates.clone()).with_transport(node);
+        let collected = Arc::new(Mutex::new(0));
+        let counter = collected.clone();
+        let mut collector_certificates = certificates.clone();
+        let collector: InventoryCollector = Box::new(move || {
+            *counter.lock().unwrap() += 1;
+            NodeInventory::collect(&mut collector_certificates, vec!["inventory".to_string()], vec![])
+        });
+        let mut transport = data_bus.get_transport_service();
+        let responder = InventoryResponder::new(collector, data_bus.get_certificate_service(), transport.get_sender(), 1);
+        transport.subscribe_to_messages(&MessageFilter::new(), Box::new(responder));
+        let cache = InventoryCache::new_shared();
+        let policy = SignaturePolicy::new_shared(DEFAULT_SIGNATURE_AUDIT_CAPACITY);
+        policy.lock().unwrap().bind_peer_certificate(1, TEST_SIGNING_CERTIFICATE_SERIAL);
+        operator.subscribe_to_messages(&MessageFilter::new(),
+                                       Box::new(InventoryReceiver::new(data_bus.get_certificate_service(), cache.clone(),
+                                                                       Some(policy))));
+        let request = |max_age: u64| MessageBuilder::from_payload(&InventoryRequest{ request_id: 7, max_age })
+            .set_source(2)
+            .set_destination(1)
+            .set_module_id(INVENTORY_MODULE_ID)
+            .build()
+            .unwrap();
+
+        // Unsigned request is not answered
+        let started = get_timestamp_with_milliseconds();
+        operator.send_message(request(0));
+        assert!(cache.wait_for(1, started, Duration::ZERO).is_none());
+
+        let mut signed = request(60_000);
+        signed.sign_by(&test_certificates().signing).unwrap();
+        operator.send_message(signed.clone());
+        let cached = cache.wait_for(1, started, Duration::from_secs(1)).unwrap();
+        assert_eq!(cached.signer, test_certificates().signing.serial_number);
+        assert_eq!(cached.inventory.modules, vec!["inventory".to_string()]);
+        assert!(cache.get_fresh(1, 60_000, get_timestamp_with_milliseconds()).is_some());
+        assert!(cache.get_fresh(1, 0, cached.inventory.collected_at + 1).is_none());
+
+        // Facts are collected once while they are young enough
+        operator.send_message(signed);
+        assert_eq!(*collected.lock().unwrap(), 1);
+        assert_eq!(cache.get_all().len(), 1);
+    }
+
+    #[test]
+    fn test_inventory_of_other_peer_dropped() {
+        init_tokio();
+        let mut certificates = MockCertificateService::with_test_certificates();
+        let cache = InventoryCache::new_shared();
+        let policy = SignaturePolicy::new_shared(DEFAULT_SIGNATURE_AUDIT_CAPACITY);
+        policy.lock().unwrap().bind_peer_certificate(1, TEST_SIGNING_CERTIFICATE_SERIAL);
+        let data_bus = TestDataBus::new(HostType::Peer, 2, certificates.clone());
+        let mut receiver = InventoryReceiver::new(data_bus.get_certificate_service(), cache.clone(), Some(policy));
+        let mut response = |source: u128| {
+            let inventory = NodeInventory::collect(&mut certificates, vec![], vec![]);
+            let mut message = MessageBuilder::from_payload(&InventoryResponse{ request_id: 1, inventory })
+                .set_source(source)
+                .set_destination(2)
+                .set_module_id(INVENTORY_MODULE_ID)
+                .build()
+                .unwrap();
+            message.sign_by(&test_certificates().signing).unwrap();
+            message
+        };
+        // Certificate of peer 1 can not vouch for peer 5
+        receiver.on_message(response(5));
+        assert!(cache.get(5).is_none());
+        receiver.on_message(response(1));
+        assert!(cache.get(1).is_some());
+
+        let mut unbound = InventoryReceiver::new(data_bus.get_certificate_service(), cache.clone(), None);
+        unbound.on_message(response(6));
+        assert!(cache.get(6).is_none());
+    }
+}
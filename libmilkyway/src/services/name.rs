@@ -1,8 +1,23 @@
+///
+/// Chain of name backends with cached records
+///
+pub mod resolver;
+
+///
+/// Name backend looking peers up in DNS
+///
+pub mod dns;
+
+///
+/// Name records signed by their owners and exchanged between peers
+///
+pub mod exchange;
+
 ///
 /// Name service is responsible for handling known machine names
 /// and certificates
 /// 
-pub trait NameService{
+pub trait NameService: Send + Sync{
     ///
     /// Gets name of client by ID
     /// 
@@ -12,10 +27,29 @@ pub trait NameService{
     /// returns: String: name of client
     /// 
     fn get_name_by_id(&self, id: u128) -> String;
+
+    ///
+    /// Gets ID of client by name or alias
+    ///
+    /// # Arguments
+    /// * name: &str: name to lookup
+    ///
+    /// returns: Option<u128>: ID of client or None if name is unknown, always None by default
+    ///
+    fn get_id_by_name(&self, _name: &str) -> Option<u128>{
+        None
+    }
     
     ///
     /// Gets domain of whole network
     /// 
     /// returns: String: domain name
     fn get_domain(&self) -> String;
-}
\ No newline at end of file
+
+    ///
+    /// Gets names known without asking network, e.g. for completion in CLI
+    ///
+    fn get_known_names(&self) -> Vec<String>{
+        vec![]
+    }
+}
@@ -0,0 +1,447 @@
+use std::fmt::{Display, Formatter};
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+use rand::random;
+use crate::services::name::resolver::{NameBackend, NameRecord};
+
+///
+/// Type of DNS records with text, e.g. `id=...` of peer
+///
+pub const DNS_TYPE_TXT: u16 = 16;
+
+///
+/// Type of DNS records with host and port of service
+///
+pub const DNS_TYPE_SRV: u16 = 33;
+
+const DNS_CLASS_IN: u16 = 1;
+const DNS_HEADER_SIZE: usize = 12;
+// Pointers of compressed names are followed at most this many times, so loops are detected
+const DNS_MAX_POINTERS: usize = 32;
+const DNS_MAX_PACKET_SIZE: usize = 4096;
+const DNS_RCODE_NXDOMAIN: u8 = 3;
+
+///
+/// Milliseconds to wait for response of DNS server
+///
+pub const DEFAULT_DNS_TIMEOUT: u64 = 2000;
+
+///
+/// Records of DNS response which are used by name backend
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum DnsRecord{
+    /** Strings of record joined together **/
+    Txt{ ttl: u32, text: String },
+    Srv{ ttl: u32, priority: u16, weight: u16, port: u16, target: String },
+}
+
+impl DnsRecord {
+    ///
+    /// Gets seconds record may be cached for
+    ///
+    pub fn get_ttl(&self) -> u32{
+        match self {
+            DnsRecord::Txt{ ttl, .. } | DnsRecord::Srv{ ttl, .. } => *ttl,
+        }
+    }
+}
+
+///
+/// Reasons why DNS lookup fails
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum DnsError{
+    /** Server can not be reached or did not answer in time **/
+    Io(String),
+    /** Response can not be parsed or does not match query **/
+    Malformed,
+    /** Server returned error code other than NXDOMAIN **/
+    ServerFailure(u8),
+}
+
+impl Display for DnsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DnsError::Io(error) => write!(f, "DNS server is not reachable: {}", error),
+            DnsError::Malformed => write!(f, "malformed DNS response"),
+            DnsError::ServerFailure(code) => write!(f, "DNS server returned error code {}", code),
+        }
+    }
+}
+
+///
+/// Encodes query of single record type
+///
+/// # Arguments
+/// * id: u16: ID of query which response must carry
+/// * name: &str: fully qualified name, trailing dot is optional
+/// * record_type: u16: e.g. DNS_TYPE_TXT
+///
+/// returns: Option<Vec<u8>>: packet or None if name has labels longer than 63 bytes
+///
+pub fn encode_dns_query(id: u16, name: &str, record_type: u16) -> Option<Vec<u8>>{
+    let mut packet = Vec::with_capacity(DNS_HEADER_SIZE + name.len() + 6);
+    packet.extend(id.to_be_bytes());
+    // Standard query, recursion desired
+    packet.extend(0x0100u16.to_be_bytes());
+    packet.extend(1u16.to_be_bytes());
+    packet.extend([0u8; 6]);
+    for label in name.trim_end_matches('.').split('.').filter(|label| !label.is_empty()){
+        if label.len() > 63{
+            return None;
+        }
+        packet.push(label.len() as u8);
+        packet.extend(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend(record_type.to_be_bytes());
+    packet.extend(DNS_CLASS_IN.to_be_bytes());
+    Some(packet)
+}
+
+fn read_u16(packet: &[u8], offset: usize) -> Result<u16, DnsError>{
+    let bytes = packet.get(offset..offset + 2).ok_or(DnsError::Malformed)?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(packet: &[u8], offset: usize) -> Result<u32, DnsError>{
+    let bytes = packet.get(offset..offset + 4).ok_or(DnsError::Malformed)?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+///
+/// Reads possibly compressed name
+///
+/// returns: Result<(String, usize), DnsError>: name and offset right after it in packet
+///
+fn read_dns_name(packet: &[u8], offset: usize) -> Result<(String, usize), DnsError>{
+    let mut labels = Vec::new();
+    let mut position = offset;
+    let mut end = None;
+    let mut pointers = 0;
+    loop {
+        let length = *packet.get(position).ok_or(DnsError::Malformed)? as usize;
+        if length & 0xC0 == 0xC0{
+            pointers += 1;
+            if pointers > DNS_MAX_POINTERS{
+                return Err(DnsError::Malformed);
+            }
+            let target = (read_u16(packet, position)? & 0x3FFF) as usize;
+            end.get_or_insert(position + 2);
+            position = target;
+            continue;
+        }
+        if length == 0{
+            let end = end.unwrap_or(position + 1);
+            return Ok((labels.join("."), end));
+        }
+        let label = packet.get(position + 1..position + 1 + length).ok_or(DnsError::Malformed)?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        position += 1 + length;
+    }
+}
+
+///
+/// Parses TXT and SRV answers of response, other answers are skipped
+///
+/// # Arguments
+/// * id: u16: ID of query
+/// * packet: &[u8]: response of server
+///
+/// returns: Result<Vec<DnsRecord>, DnsError>: answers, empty if name does not exist
+///
+pub fn parse_dns_response(id: u16, packet: &[u8]) -> Result<Vec<DnsRecord>, DnsError>{
+    if read_u16(packet, 0)? != id{
+        return Err(DnsError::Malformed);
+    }
+    let flags = read_u16(packet, 2)?;
+    if flags & 0x8000 == 0{
+        return Err(DnsError::Malformed);
+    }
+    let code = (flags & 0x000F) as u8;
+    if code == DNS_RCODE_NXDOMAIN{
+        return Ok(Vec::new());
+    }
+    if code != 0{
+        return Err(DnsError::ServerFailure(code));
+    }
+    let questions = read_u16(packet, 4)?;
+    let answers = read_u16(packet, 6)?;
+    let mut offset = DNS_HEADER_SIZE;
+    for _ in 0..questions{
+        offset = read_dns_name(packet, offset)?.1 + 4;
+    }
+    let mut records = Vec::new();
+    for _ in 0..answers{
+        offset = read_dns_name(packet, offset)?.1;
+        let record_type = read_u16(packet, offset)?;
+        let ttl = read_u32(packet, offset + 4)?;
+        let length = read_u16(packet, offset + 8)? as usize;
+        let start = offset + 10;
+        let data = packet.get(start..start + length).ok_or(DnsError::Malformed)?;
+        match record_type {
+            DNS_TYPE_TXT => {
+                let mut text = Vec::new();
+                let mut position = 0;
+                while position < data.len(){
+                    let size = data[position] as usize;
+                    text.extend(data.get(position + 1..position + 1 + size).ok_or(DnsError::Malformed)?);
+                    position += 1 + size;
+                }
+                records.push(DnsRecord::Txt{ ttl, text: String::from_utf8_lossy(&text).into_owned() });
+            }
+            DNS_TYPE_SRV => {
+                if length < 7{
+                    return Err(DnsError::Malformed);
+                }
+                // Target may point to names anywhere in packet
+                let (target, _) = read_dns_name(packet, start + 6)?;
+                records.push(DnsRecord::Srv{
+                    ttl,
+                    priority: read_u16(packet, start)?,
+                    weight: read_u16(packet, start + 2)?,
+                    port: read_u16(packet, start + 4)?,
+                    target,
+                });
+            }
+            _ => {}
+        }
+        offset = start + length;
+    }
+    Ok(records)
+}
+
+///
+/// Performs DNS queries, allows to replace network with fixed answers
+///
+pub trait DnsLookup: Send{
+    ///
+    /// Queries records of name
+    ///
+    /// # Arguments
+    /// * name: &str: fully qualified name
+    /// * record_type: u16: DNS_TYPE_TXT or DNS_TYPE_SRV
+    ///
+    fn lookup(&mut self, name: &str, record_type: u16) -> Result<Vec<DnsRecord>, DnsError>;
+}
+
+///
+/// DNS lookup over UDP with a single server
+///
+pub struct UdpDnsLookup{
+    server: SocketAddr,
+    timeout: Duration,
+}
+
+impl UdpDnsLookup {
+    ///
+    /// Creates lookup asking server
+    ///
+    /// # Arguments
+    /// * server: SocketAddr: address of recursive DNS server
+    ///
+    pub fn new(server: SocketAddr) -> UdpDnsLookup{
+        UdpDnsLookup{
+            server,
+            timeout: Duration::from_millis(DEFAULT_DNS_TIMEOUT),
+        }
+    }
+
+    ///
+    /// Sets time to wait for response
+    ///
+    /// # Arguments
+    /// * timeout: u64: milliseconds
+    ///
+    pub fn set_timeout(&mut self, timeout: u64) -> &mut Self{
+        self.timeout = Duration::from_millis(timeout.max(1));
+        self
+    }
+}
+
+impl DnsLookup for UdpDnsLookup{
+    fn lookup(&mut self, name: &str, record_type: u16) -> Result<Vec<DnsRecord>, DnsError> {
+        let id: u16 = random();
+        let query = encode_dns_query(id, name, record_type).ok_or(DnsError::Malformed)?;
+        let bind_address: SocketAddr = if self.server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }.parse().unwrap();
+        let io_error = |error: std::io::Error| DnsError::Io(error.to_string());
+        let socket = UdpSocket::bind(bind_address).map_err(io_error)?;
+        socket.set_read_timeout(Some(self.timeout)).map_err(io_error)?;
+        socket.connect(self.server).map_err(io_error)?;
+        socket.send(&query).map_err(io_error)?;
+        let mut buffer = vec![0u8; DNS_MAX_PACKET_SIZE];
+        loop {
+            let size = socket.recv(&mut buffer).map_err(io_error)?;
+            match parse_dns_response(id, &buffer[..size]) {
+                // Late answer to an earlier query, keep waiting
+                Err(DnsError::Malformed) if read_u16(&buffer[..size], 0) != Ok(id) => continue,
+                result => return result,
+            }
+        }
+    }
+}
+
+///
+/// Backend looking peers up in DNS of network domain:
+/// * TXT `_mway.<name>.<domain>` with `id=<peer ID>` and optional `endpoint=<host:port>` entries
+/// * SRV `_mway._tcp.<name>.<domain>` with endpoints of peer
+///
+/// Records are cached for the smallest TTL of answers.
+///
+pub struct DnsNameBackend{
+    lookup: Box<dyn DnsLookup>,
+    domain: String,
+}
+
+impl DnsNameBackend {
+    ///
+    /// Creates a backend
+    ///
+    /// # Arguments
+    /// * lookup: Box<dyn DnsLookup>: lookup to query, usually UdpDnsLookup
+    /// * domain: &str: domain of network
+    ///
+    pub fn new(lookup: Box<dyn DnsLookup>, domain: &str) -> DnsNameBackend{
+        DnsNameBackend{
+            lookup,
+            domain: domain.trim_matches('.').to_string(),
+        }
+    }
+
+    fn query(&mut self, name: &str, record_type: u16) -> Vec<DnsRecord>{
+        match self.lookup.lookup(name, record_type) {
+            Ok(records) => records,
+            Err(error) => {
+                log::warn!("DNS lookup of {} failed: {}", name, error);
+                Vec::new()
+            }
+        }
+    }
+}
+
+impl NameBackend for DnsNameBackend{
+    #[inline]
+    fn get_backend_name(&self) -> &str {
+        "dns"
+    }
+
+    fn resolve(&mut self, name: &str) -> Option<NameRecord> {
+        let texts = self.query(&format!("_mway.{}.{}", name, self.domain), DNS_TYPE_TXT);
+        let mut peer_id = None;
+        let mut endpoints = Vec::new();
+        let mut ttl = u32::MAX;
+        for record in texts.iter(){
+            if let DnsRecord::Txt{ text, .. } = record{
+                ttl = ttl.min(record.get_ttl());
+                for entry in text.split_whitespace(){
+                    match entry.split_once('=') {
+                        Some(("id", id)) => peer_id = id.parse::<u128>().ok().or(peer_id),
+                        Some(("endpoint", endpoint)) => endpoints.push(endpoint.to_string()),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        let peer_id = peer_id?;
+        let mut services: Vec<(u16, u16, String)> = Vec::new();
+        for record in self.query(&format!("_mway._tcp.{}.{}", name, self.domain), DNS_TYPE_SRV){
+            if let DnsRecord::Srv{ ttl: record_ttl, priority, weight, port, target } = record{
+                ttl = ttl.min(record_ttl);
+                services.push((priority, weight, format!("{}:{}", target, port)));
+            }
+        }
+        // Lower priority first, heavier weight first among equal priorities
+        services.sort_by(|left, right| left.0.cmp(&right.0).then(right.1.cmp(&left.1)));
+        let mut service_endpoints: Vec<String> = services.into_iter().map(|(_, _, endpoint)| endpoint).collect();
+        service_endpoints.extend(endpoints);
+        Some(NameRecord{
+            name: name.to_string(),
+            peer_id,
+            endpoints: service_endpoints,
+            ttl: ttl as u64,
+        })
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn answer(packet: &mut Vec<u8>, record_type: u16, ttl: u32, data: &[u8]){
+        // Owner name points to question
+        packet.extend([0xC0, DNS_HEADER_SIZE as u8]);
+        packet.extend(record_type.to_be_bytes());
+        packet.extend(DNS_CLASS_IN.to_be_bytes());
+        packet.extend(ttl.to_be_bytes());
+        packet.extend((data.len() as u16).to_be_bytes());
+        packet.extend(data);
+    }
+
+    #[test]
+    fn test_parse_dns_response() {
+        let mut packet = encode_dns_query(77, "_mway._tcp.gateway.corp.example", DNS_TYPE_SRV).unwrap();
+        packet[2] |= 0x80;
+        packet[7] = 3;
+        let mut txt = vec![5];
+        txt.extend(b"id=42");
+        answer(&mut packet, DNS_TYPE_TXT, 60, &txt);
+        // Target is `gw` followed by pointer to `corp.example` of question
+        let srv = vec![0, 10, 0, 5, 0x0F, 0xC8, 2, b'g', b'w', 0xC0, (DNS_HEADER_SIZE + 19) as u8];
+        answer(&mut packet, DNS_TYPE_SRV, 30, &srv);
+        answer(&mut packet, 1, 30, &[10, 0, 0, 1]);
+        assert_eq!(parse_dns_response(77, &packet), Ok(vec![
+            DnsRecord::Txt{ ttl: 60, text: "id=42".to_string() },
+            DnsRecord::Srv{ ttl: 30, priority: 10, weight: 5, port: 4040, target: "gw.corp.example".to_string() },
+        ]));
+        assert_eq!(parse_dns_response(78, &packet), Err(DnsError::Malformed));
+        assert_eq!(parse_dns_response(77, &packet[..packet.len() - 3]), Err(DnsError::Malformed));
+
+        // Name pointing to itself
+        let mut looped = packet[..DNS_HEADER_SIZE].to_vec();
+        looped.extend([0xC0, DNS_HEADER_SIZE as u8, 0, 16, 0, 1]);
+        assert_eq!(parse_dns_response(77, &looped), Err(DnsError::Malformed));
+        packet[3] |= DNS_RCODE_NXDOMAIN;
+        assert_eq!(parse_dns_response(77, &packet), Ok(vec![]));
+        packet[3] = (packet[3] & 0xF0) | 2;
+        assert_eq!(parse_dns_response(77, &packet), Err(DnsError::ServerFailure(2)));
+    }
+
+    struct FixedLookup{
+        answers: HashMap<(String, u16), Vec<DnsRecord>>,
+    }
+
+    impl DnsLookup for FixedLookup{
+        fn lookup(&mut self, name: &str, record_type: u16) -> Result<Vec<DnsRecord>, DnsError> {
+            Ok(self.answers.get(&(name.to_string(), record_type)).cloned().unwrap_or_default())
+        }
+    }
+
+    #[test]
+    fn test_dns_name_backend() {
+        let mut answers = HashMap::new();
+        answers.insert(("_mway.gateway.corp.example".to_string(), DNS_TYPE_TXT), vec![
+            DnsRecord::Txt{ ttl: 600, text: "id=42 endpoint=10.0.0.1:4040".to_string() },
+        ]);
+        answers.insert(("_mway._tcp.gateway.corp.example".to_string(), DNS_TYPE_SRV), vec![
+            DnsRecord::Srv{ ttl: 120, priority: 20, weight: 0, port: 4040, target: "backup.corp.example".to_string() },
+            DnsRecord::Srv{ ttl: 300, priority: 10, weight: 0, port: 4040, target: "gw.corp.example".to_string() },
+        ]);
+        answers.insert(("_mway.printer.corp.example".to_string(), DNS_TYPE_TXT), vec![
+            DnsRecord::Txt{ ttl: 600, text: "endpoint=10.0.0.9:4040".to_string() },
+        ]);
+        let mut backend = DnsNameBackend::new(Box::new(FixedLookup{ answers }), "corp.example.");
+        assert_eq!(backend.resolve("gateway"), Some(NameRecord{
+            name: "gateway".to_string(),
+            peer_id: 42,
+            endpoints: vec!["gw.corp.example:4040".to_string(), "backup.corp.example:4040".to_string(),
+                            "10.0.0.1:4040".to_string()],
+            ttl: 120,
+        }));
+        // Records without ID do not name a peer
+        assert_eq!(backend.resolve("printer"), None);
+        assert_eq!(backend.resolve("unknown"), None);
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use libmilkyway_derive::{Describe, Deserializable, Serializable};
+use crate::get_timestamp_with_milliseconds;
+use crate::message::common::{AsMessage, Message};
+use crate::message::types::MessageType;
+use crate::pki::certificate::{Certificate, FLAG_SIGN_MESSAGES};
+use crate::pki::hash::HashType;
+use crate::pki::impls::CryptoError;
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+use crate::pki::signature::Signature;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+use crate::services::certificate::CertificateService;
+use crate::services::name::resolver::{NameBackend, NameRecord};
+
+///
+/// Name record signed by its owner, so peers can pass it further without being trusted
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq, Describe)]
+pub struct SignedNameRecord{
+    pub record: NameRecord,
+    /** Newer records of the same name replace older ones **/
+    pub timestamp: u128,
+    pub signer_serial: u128,
+    pub signature: Option<Signature>,
+}
+
+impl SignedNameRecord {
+    ///
+    /// Creates a record signed by given certificate
+    ///
+    /// # Arguments
+    /// * record: NameRecord: name and endpoints of peer
+    /// * signer: &Falcon1024Certificate: certificate to sign with, must have secret key
+    ///
+    /// returns: Result<SignedNameRecord, CryptoError>: signed record or error if certificate
+    /// can not sign it
+    ///
+    pub fn new(record: NameRecord, signer: &Falcon1024Certificate) -> Result<SignedNameRecord, CryptoError>{
+        if !signer.check_flag(FLAG_SIGN_MESSAGES){
+            return Err(CryptoError::ArgumentError("Certificate can not sign name records"));
+        }
+        let mut signed = SignedNameRecord{
+            record,
+            timestamp: get_timestamp_with_milliseconds(),
+            signer_serial: signer.get_serial(),
+            signature: None,
+        };
+        signed.signature = Some(signer.sign_data(&signed.as_signable(), HashType::None)?);
+        Ok(signed)
+    }
+
+    ///
+    /// Clones and strips signature, allowing to sign/verify record
+    ///
+    pub fn as_signable(&self) -> SignedNameRecord{
+        let mut copy = self.clone();
+        copy.signature = None;
+        copy
+    }
+
+    ///
+    /// Verifies that record is signed by a trusted certificate which can sign messages
+    ///
+    /// # Arguments
+    /// * service: &mut S: service with certificate of signer
+    ///
+    pub fn verify<S: CertificateService + ?Sized>(&self, service: &mut S) -> Result<(), NameExchangeError>{
+        let signer = service.get_signing_certificate(self.signer_serial)
+            .ok_or(NameExchangeError::UnknownSigner(self.signer_serial))?;
+        if !signer.check_flag(FLAG_SIGN_MESSAGES) || !service.verify_signing_certificate(&signer){
+            return Err(NameExchangeError::UntrustedSigner(self.signer_serial));
+        }
+        match &self.signature {
+            Some(signature) if signer.verify_signature(&self.as_signable(), signature) => Ok(()),
+            _ => Err(NameExchangeError::InvalidSignature),
+        }
+    }
+}
+
+///
+/// Reasons why record received from a peer is not accepted
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum NameExchangeError{
+    /** Certificate of signer is not known **/
+    UnknownSigner(u128),
+    /** Certificate of signer is not trusted or can not sign messages **/
+    UntrustedSigner(u128),
+    InvalidSignature,
+    /** Name is owned by another certificate **/
+    OwnerMismatch{ name: String, owner: u128 },
+}
+
+impl Display for NameExchangeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NameExchangeError::UnknownSigner(serial) => write!(f, "signer certificate {} is not known", serial),
+            NameExchangeError::UntrustedSigner(serial) =>
+                write!(f, "signer certificate {} is not trusted to sign messages", serial),
+            NameExchangeError::InvalidSignature => write!(f, "signature of name record is invalid"),
+            NameExchangeError::OwnerMismatch{ name, owner } =>
+                write!(f, "name '{}' is owned by certificate {}", name, owner),
+        }
+    }
+}
+
+///
+/// Name records gossiped between peers
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq, Describe)]
+pub struct NameExchangeMessage{
+    pub records: Vec<SignedNameRecord>,
+}
+
+impl AsMessage for NameExchangeMessage{
+    fn as_message(&self) -> Message {
+        Message{
+            id: 0,
+            timestamp: 0,
+            message_type: MessageType::NameExchange,
+            data: Some(self.serialize()),
+            signature: None,
+            source: 0,
+            destination: 0,
+            module_id: 0,
+            certificate_id: 0,
+        }
+    }
+}
+
+///
+/// Backend with records received from peers. Certificate which first signed a name owns
+/// it: later records of that name are accepted only from the same certificate and only if
+/// they are newer.
+///
+#[derive(Clone, Default)]
+pub struct PeerExchangeNameBackend{
+    records: Arc<Mutex<HashMap<String, SignedNameRecord>>>,
+}
+
+impl PeerExchangeNameBackend {
+    ///
+    /// Creates a backend without records, clones share records
+    ///
+    pub fn new() -> PeerExchangeNameBackend{
+        PeerExchangeNameBackend{
+            records: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    ///
+    /// Verifies and stores record, e.g. own one or received from peer
+    ///
+    /// # Arguments
+    /// * service: &mut S: service with certificate of signer
+    /// * record: SignedNameRecord: record to store
+    ///
+    /// returns: Result<bool, NameExchangeError>: true if record is new and should be passed
+    /// further, false if the same or newer record is already known
+    ///
+    pub fn accept<S: CertificateService + ?Sized>(&self, service: &mut S,
+                                                   record: SignedNameRecord) -> Result<bool, NameExchangeError>{
+        record.verify(service)?;
+        let mut records = self.records.lock().unwrap();
+        if let Some(known) = records.get(&record.record.name){
+            if known.signer_serial != record.signer_serial{
+                return Err(NameExchangeError::OwnerMismatch{
+                    name: record.record.name.clone(),
+                    owner: known.signer_serial,
+                });
+            }
+            if known.timestamp >= record.timestamp{
+                return Ok(false);
+            }
+        }
+        records.insert(record.record.name.clone(), record);
+        Ok(true)
+    }
+
+    ///
+    /// Accepts records of message from peer, rejected records are logged
+    ///
+    /// # Arguments
+    /// * service: &mut S: service with certificates of signers
+    /// * message: NameExchangeMessage: message of peer
+    ///
+    /// returns: Vec<SignedNameRecord>: records which are new and should be passed further
+    ///
+    pub fn accept_message<S: CertificateService + ?Sized>(&self, service: &mut S,
+                                                           message: NameExchangeMessage) -> Vec<SignedNameRecord>{
+        let mut accepted = Vec::new();
+        for record in message.records{
+            let name = record.record.name.clone();
+            match self.accept(service, record.clone()) {
+                Ok(true) => accepted.push(record),
+                Ok(false) => {}
+                Err(error) => log::warn!("Name record of '{}' rejected: {}", name, error),
+            }
+        }
+        accepted
+    }
+
+    ///
+    /// Gets all known records, e.g. to send them to a new peer
+    ///
+    pub fn get_message(&self) -> NameExchangeMessage{
+        NameExchangeMessage{
+            records: self.records.lock().unwrap().values().cloned().collect(),
+        }
+    }
+
+    ///
+    /// Forgets record of name, e.g. if certificate of its owner was revoked
+    ///
+    /// returns: bool: whether record was known
+    ///
+    pub fn remove(&self, name: &str) -> bool{
+        self.records.lock().unwrap().remove(name).is_some()
+    }
+}
+
+impl NameBackend for PeerExchangeNameBackend{
+    #[inline]
+    fn get_backend_name(&self) -> &str {
+        "exchange"
+    }
+
+    fn resolve(&mut self, name: &str) -> Option<NameRecord> {
+        self.records.lock().unwrap().get(name).map(|signed| signed.record.clone())
+    }
+
+    fn resolve_id(&mut self, id: u128) -> Option<NameRecord> {
+        self.records.lock().unwrap().values()
+            .find(|signed| signed.record.peer_id == id)
+            .map(|signed| signed.record.clone())
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pki::certificate::FLAG_SIGN_CERTS;
+    use crate::services::name::resolver::DEFAULT_NAME_TTL;
+    use crate::testing::certificate::{test_certificates, MockCertificateService};
+
+    fn record(name: &str, endpoint: &str) -> NameRecord{
+        NameRecord{
+            name: name.to_string(),
+            peer_id: 7,
+            endpoints: vec![endpoint.to_string()],
+            ttl: DEFAULT_NAME_TTL,
+        }
+    }
+
+    #[test]
+    fn test_name_exchange() {
+        let mut signing = test_certificates().signing;
+        let mut service = MockCertificateService::with_test_certificates();
+        let backend = PeerExchangeNameBackend::new();
+        let first = SignedNameRecord::new(record("gateway", "10.0.0.1:4040"), &signing).unwrap();
+        assert_eq!(backend.accept(&mut service, first.clone()), Ok(true));
+        assert_eq!(backend.accept(&mut service, first.clone()), Ok(false));
+
+        let mut moved = SignedNameRecord::new(record("gateway", "10.0.0.2:4040"), &signing).unwrap();
+        moved.timestamp = first.timestamp + 1;
+        moved.signature = Some(signing.sign_data(&moved.as_signable(), HashType::None).unwrap());
+        let message = NameExchangeMessage{ records: vec![moved.clone(), first.clone()] };
+        let (message, _) = NameExchangeMessage::from_serialized(&message.serialize()).unwrap();
+        assert_eq!(backend.accept_message(&mut service, message), vec![moved.clone()]);
+        assert_eq!(backend.clone().resolve("gateway"), Some(moved.record.clone()));
+        assert_eq!(backend.clone().resolve_id(7), Some(moved.record.clone()));
+
+        let mut tampered = moved.clone();
+        tampered.record.endpoints = vec!["10.6.6.6:4040".to_string()];
+        tampered.timestamp += 1;
+        assert_eq!(backend.accept(&mut service, tampered), Err(NameExchangeError::InvalidSignature));
+        let mut stolen = moved.clone();
+        stolen.signer_serial = 1000;
+        assert_eq!(backend.accept(&mut service, stolen), Err(NameExchangeError::UnknownSigner(1000)));
+
+        signing.flags = FLAG_SIGN_CERTS;
+        assert!(SignedNameRecord::new(record("gateway", "10.0.0.3:4040"), &signing).is_err());
+        assert_eq!(backend.get_message().records, vec![moved]);
+        assert!(backend.remove("gateway"));
+        assert_eq!(backend.clone().resolve("gateway"), None);
+    }
+}
@@ -0,0 +1,423 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use libmilkyway_derive::{Describe, Deserializable, Serializable};
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+use crate::services::name::NameService;
+
+///
+/// Seconds records of backends without own TTL are cached for
+///
+pub const DEFAULT_NAME_TTL: u64 = 300;
+
+///
+/// Longest time in seconds any record is cached, whatever backend says
+///
+pub const DEFAULT_MAX_NAME_TTL: u64 = 3600;
+
+///
+/// Name of a peer together with endpoints it may be reached at
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq, Describe)]
+pub struct NameRecord{
+    pub name: String,
+    pub peer_id: u128,
+    /** Addresses in `host:port` form, most preferred first **/
+    pub endpoints: Vec<String>,
+    /** Seconds record may be cached for, 0 means it must not be cached **/
+    pub ttl: u64,
+}
+
+///
+/// Source of name records, e.g. configuration, DNS or records exchanged with peers
+///
+pub trait NameBackend: Send{
+    ///
+    /// Gets name of backend used to enable or disable it
+    ///
+    fn get_backend_name(&self) -> &str;
+
+    ///
+    /// Looks name up
+    ///
+    /// # Arguments
+    /// * name: &str: name of peer without domain
+    ///
+    /// returns: Option<NameRecord>: record or None if backend does not know name
+    ///
+    fn resolve(&mut self, name: &str) -> Option<NameRecord>;
+
+    ///
+    /// Looks record of peer up by its ID. Backends which can not search by ID(e.g. DNS)
+    /// keep the default.
+    ///
+    /// returns: Option<NameRecord>: record or None if backend does not know peer
+    ///
+    fn resolve_id(&mut self, _id: u128) -> Option<NameRecord>{
+        None
+    }
+}
+
+///
+/// Backend with fixed records, e.g. from configuration
+///
+#[derive(Clone, Default)]
+pub struct StaticNameBackend{
+    records: HashMap<String, NameRecord>,
+}
+
+impl StaticNameBackend {
+    ///
+    /// Creates a backend without records
+    ///
+    pub fn new() -> StaticNameBackend{
+        StaticNameBackend{
+            records: HashMap::new(),
+        }
+    }
+
+    ///
+    /// Adds a record, replacing one with the same name. Static records are not cached as
+    /// looking them up is cheap.
+    ///
+    /// # Arguments
+    /// * name: &str: name of peer
+    /// * peer_id: u128: ID of peer
+    /// * endpoints: Vec<String>: addresses of peer
+    ///
+    pub fn add_record(&mut self, name: &str, peer_id: u128, endpoints: Vec<String>) -> &mut Self{
+        self.records.insert(name.to_string(), NameRecord{
+            name: name.to_string(),
+            peer_id,
+            endpoints,
+            ttl: 0,
+        });
+        self
+    }
+}
+
+impl NameBackend for StaticNameBackend{
+    #[inline]
+    fn get_backend_name(&self) -> &str {
+        "static"
+    }
+
+    fn resolve(&mut self, name: &str) -> Option<NameRecord> {
+        self.records.get(name).cloned()
+    }
+
+    fn resolve_id(&mut self, id: u128) -> Option<NameRecord> {
+        self.records.values().find(|record| record.peer_id == id).cloned()
+    }
+}
+
+struct BackendEntry{
+    backend: Box<dyn NameBackend>,
+    enabled: bool,
+}
+
+struct CachedRecord{
+    record: NameRecord,
+    expires: Instant,
+}
+
+///
+/// Chain of name backends asked in order they were added, first one knowing name wins.
+/// Found records are cached for their TTL.
+///
+pub struct NameResolver{
+    domain: String,
+    backends: Vec<BackendEntry>,
+    cache: HashMap<String, CachedRecord>,
+    /** Seconds, TTLs of records are lowered to it **/
+    max_ttl: u64,
+}
+
+pub type SharedNameResolver = Arc<Mutex<NameResolver>>;
+
+impl NameResolver {
+    ///
+    /// Creates a resolver without backends
+    ///
+    /// # Arguments
+    /// * domain: &str: domain of network
+    ///
+    pub fn new(domain: &str) -> NameResolver{
+        NameResolver{
+            domain: domain.to_string(),
+            backends: Vec::new(),
+            cache: HashMap::new(),
+            max_ttl: DEFAULT_MAX_NAME_TTL,
+        }
+    }
+
+    ///
+    /// Creates a resolver shared between services
+    ///
+    pub fn new_shared(domain: &str) -> SharedNameResolver{
+        Arc::new(Mutex::new(NameResolver::new(domain)))
+    }
+
+    ///
+    /// Adds backend to the end of chain
+    ///
+    /// # Arguments
+    /// * backend: Box<dyn NameBackend>: backend to ask
+    /// * enabled: bool: whether backend is asked until enabled with set_enabled
+    ///
+    pub fn add_backend(&mut self, backend: Box<dyn NameBackend>, enabled: bool) -> &mut Self{
+        self.backends.push(BackendEntry{ backend, enabled });
+        self
+    }
+
+    ///
+    /// Enables or disables backend. Cache is cleared, so records of disabled backend are
+    /// not served anymore.
+    ///
+    /// # Arguments
+    /// * name: &str: name of backend
+    /// * enabled: bool: whether backend is asked
+    ///
+    /// returns: bool: false if there is no backend with such name
+    ///
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool{
+        let mut found = false;
+        for entry in self.backends.iter_mut().filter(|entry| entry.backend.get_backend_name() == name){
+            entry.enabled = enabled;
+            found = true;
+        }
+        if found{
+            self.cache.clear();
+        }
+        found
+    }
+
+    ///
+    /// Sets longest time records are cached for
+    ///
+    /// # Arguments
+    /// * max_ttl: u64: seconds, 0 disables cache
+    ///
+    pub fn set_max_ttl(&mut self, max_ttl: u64) -> &mut Self{
+        self.max_ttl = max_ttl;
+        self
+    }
+
+    ///
+    /// Gets backends in order they are asked
+    ///
+    /// returns: Vec<(String, bool)>: names of backends and whether they are enabled
+    ///
+    pub fn get_backends(&self) -> Vec<(String, bool)>{
+        self.backends.iter()
+            .map(|entry| (entry.backend.get_backend_name().to_string(), entry.enabled))
+            .collect()
+    }
+
+    ///
+    /// Gets domain of network
+    ///
+    #[inline]
+    pub fn get_domain(&self) -> &str{
+        &self.domain
+    }
+
+    ///
+    /// Gets names of records which are cached and not expired
+    ///
+    pub fn get_cached_names(&self) -> Vec<String>{
+        let now = Instant::now();
+        self.cache.iter().filter(|(_, cached)| cached.expires > now).map(|(name, _)| name.clone()).collect()
+    }
+
+    fn store(&mut self, record: &NameRecord){
+        let ttl = record.ttl.min(self.max_ttl);
+        if ttl == 0{
+            return;
+        }
+        self.cache.insert(record.name.clone(), CachedRecord{
+            record: record.clone(),
+            expires: Instant::now() + Duration::from_secs(ttl),
+        });
+    }
+
+    ///
+    /// Resolves name using cache or enabled backends
+    ///
+    /// # Arguments
+    /// * name: &str: name of peer
+    ///
+    /// returns: Option<NameRecord>: record or None if no enabled backend knows name
+    ///
+    pub fn resolve(&mut self, name: &str) -> Option<NameRecord>{
+        if let Some(cached) = self.cache.get(name){
+            if cached.expires > Instant::now(){
+                return Some(cached.record.clone());
+            }
+            self.cache.remove(name);
+        }
+        let record = self.backends.iter_mut()
+            .filter(|entry| entry.enabled)
+            .find_map(|entry| entry.backend.resolve(name))?;
+        self.store(&record);
+        Some(record)
+    }
+
+    ///
+    /// Resolves record of peer by its ID using cache or enabled backends
+    ///
+    /// # Arguments
+    /// * id: u128: ID of peer
+    ///
+    /// returns: Option<NameRecord>: record or None if no enabled backend knows peer
+    ///
+    pub fn resolve_id(&mut self, id: u128) -> Option<NameRecord>{
+        let now = Instant::now();
+        self.cache.retain(|_, cached| cached.expires > now);
+        if let Some(cached) = self.cache.values().find(|cached| cached.record.peer_id == id){
+            return Some(cached.record.clone());
+        }
+        let record = self.backends.iter_mut()
+            .filter(|entry| entry.enabled)
+            .find_map(|entry| entry.backend.resolve_id(id))?;
+        self.store(&record);
+        Some(record)
+    }
+
+    ///
+    /// Drops cached record, so next lookup asks backends again
+    ///
+    pub fn invalidate(&mut self, name: &str){
+        self.cache.remove(name);
+    }
+
+    ///
+    /// Drops all cached records
+    ///
+    pub fn clear_cache(&mut self){
+        self.cache.clear();
+    }
+}
+
+///
+/// Name service answering from a name resolver. Unknown peers are named by their IDs
+/// and numeric names are read as IDs, like in services without resolver.
+///
+#[derive(Clone)]
+pub struct ResolverNameService{
+    resolver: SharedNameResolver,
+}
+
+impl ResolverNameService {
+    ///
+    /// Creates a name service
+    ///
+    /// # Arguments
+    /// * resolver: SharedNameResolver: resolver to ask
+    ///
+    pub fn new(resolver: SharedNameResolver) -> ResolverNameService{
+        ResolverNameService{
+            resolver,
+        }
+    }
+
+    ///
+    /// Gets resolver of service, e.g. to toggle backends
+    ///
+    #[inline]
+    pub fn get_resolver(&self) -> SharedNameResolver{
+        self.resolver.clone()
+    }
+}
+
+impl NameService for ResolverNameService{
+    fn get_name_by_id(&self, id: u128) -> String {
+        match self.resolver.lock().unwrap().resolve_id(id) {
+            Some(record) => record.name,
+            None => id.to_string(),
+        }
+    }
+
+    fn get_id_by_name(&self, name: &str) -> Option<u128> {
+        match self.resolver.lock().unwrap().resolve(name) {
+            Some(record) => Some(record.peer_id),
+            None => name.parse().ok(),
+        }
+    }
+
+    fn get_domain(&self) -> String {
+        self.resolver.lock().unwrap().get_domain().to_string()
+    }
+
+    fn get_known_names(&self) -> Vec<String> {
+        self.resolver.lock().unwrap().get_cached_names()
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct CountingBackend{
+        name: &'static str,
+        record: NameRecord,
+        lookups: Arc<Mutex<usize>>,
+    }
+
+    impl NameBackend for CountingBackend{
+        fn get_backend_name(&self) -> &str {
+            self.name
+        }
+
+        fn resolve(&mut self, name: &str) -> Option<NameRecord> {
+            *self.lookups.lock().unwrap() += 1;
+            if name == self.record.name { Some(self.record.clone()) } else { None }
+        }
+    }
+
+    #[test]
+    fn test_name_resolver() {
+        let lookups = Arc::new(Mutex::new(0));
+        let record = NameRecord{
+            name: "gateway".to_string(),
+            peer_id: 7,
+            endpoints: vec!["10.0.0.7:4040".to_string()],
+            ttl: DEFAULT_NAME_TTL,
+        };
+        let mut backend = StaticNameBackend::new();
+        backend.add_record("database", 9, vec![]);
+        let resolver = NameResolver::new_shared("corp.example");
+        resolver.lock().unwrap()
+            .add_backend(Box::new(backend), true)
+            .add_backend(Box::new(CountingBackend{ name: "dns", record: record.clone(), lookups: lookups.clone() }), true);
+        let names = ResolverNameService::new(resolver.clone());
+
+        assert_eq!(names.get_id_by_name("gateway"), Some(7));
+        assert_eq!(names.get_id_by_name("gateway"), Some(7));
+        assert_eq!(*lookups.lock().unwrap(), 1);
+        assert_eq!(names.get_name_by_id(7), "gateway");
+        // Static records are not cached, but are answered before other backends
+        assert_eq!(names.get_id_by_name("database"), Some(9));
+        assert_eq!(names.get_name_by_id(9), "database");
+        assert_eq!(*lookups.lock().unwrap(), 1);
+
+        assert_eq!(names.get_id_by_name("42"), Some(42));
+        assert_eq!(names.get_name_by_id(42), "42");
+        assert_eq!(*lookups.lock().unwrap(), 2);
+
+        let mut resolver = resolver.lock().unwrap();
+        assert!(resolver.set_enabled("dns", false));
+        assert!(!resolver.set_enabled("ldap", false));
+        assert_eq!(resolver.get_backends(), vec![("static".to_string(), true), ("dns".to_string(), false)]);
+        assert_eq!(resolver.resolve("gateway"), None);
+        resolver.set_enabled("dns", true);
+        resolver.set_max_ttl(0);
+        assert_eq!(resolver.resolve("gateway"), Some(record.clone()));
+        assert_eq!(resolver.resolve("gateway"), Some(record));
+        assert_eq!(*lookups.lock().unwrap(), 4);
+    }
+}
@@ -1,14 +1,43 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use libmilkyway_derive::{Deserializable, Serializable};
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
 use crate::message::common::Message;
-use crate::transport::{TransportListener, TransportSender};
+use crate::message::header::MessageHeader;
+use crate::module::supervisor::{GuardedAlertListener, GuardedConnectionListener, GuardedListener, ModuleFault};
+use crate::transport::{SendError, TransportListener, TransportSender};
+use crate::transport::tap::SharedTransportTap;
+use crate::transport::rawtap::{RawFrameFilter, RawFrameSubscription, RawTapError, SharedRawFrameTap,
+                              DEFAULT_RAW_FRAME_CAPACITY};
+use crate::transport::ratelimit::SharedRateLimiter;
+use crate::transport::signature::SharedSignaturePolicy;
+use crate::transport::access::SharedAccessControl;
+use crate::transport::outbox::SharedOutbox;
+use crate::transport::deadletter::SharedDeadLetterQueue;
+use crate::transport::crypto::{CryptoAlertListener, SharedCryptoAlerts};
+use crate::transport::events::{ConnectionEventListener, SharedConnectionEvents};
+use crate::transport::operator::{OperatorIdentity, OperatorSigningSender};
+use crate::transport::subscriptions::SubscriptionStats;
+use crate::services::group::SharedGroupService;
+use crate::services::certificate::CertificateServiceBinder;
 
 ///
 /// A struct for filtering messages.
 /// The operator between fields is AND
 ///
-#[derive(Clone)]
+/// Matching subscriptions receive message in order of their priority. An exclusive
+/// subscription may consume message, then subscriptions of lower priority do not get it.
+///
+#[derive(Clone, Serializable, Deserializable)]
 pub struct MessageFilter{
     pub from_id: Option<u128>,
     pub module_id: Option<u64>,
+    /** Subscriptions with higher priority receive messages first, default is 0 **/
+    pub priority: i32,
+    /** Whether listener decides if message is consumed(see TransportListener::on_exclusive_message) **/
+    pub exclusive: bool,
 }
 
 impl MessageFilter {
@@ -19,6 +48,8 @@ impl MessageFilter {
         MessageFilter{
             from_id: None,
             module_id: None,
+            priority: 0,
+            exclusive: false,
         }
     }
 
@@ -47,6 +78,60 @@ impl MessageFilter {
         self.module_id = Some(id);
         self
     }
+
+    ///
+    /// Sets priority of subscription
+    ///
+    /// # Arguments
+    /// * priority: i32: subscriptions with higher priority receive messages first
+    ///
+    /// returns: reference to self
+    ///
+    pub fn set_priority(&mut self, priority: i32) -> &mut Self {
+        self.priority = priority;
+        self
+    }
+
+    ///
+    /// Makes subscription exclusive consumer: listener may consume message, so
+    /// subscriptions of lower priority do not receive it
+    ///
+    /// returns: reference to self
+    ///
+    pub fn set_exclusive(&mut self) -> &mut Self {
+        self.exclusive = true;
+        self
+    }
+
+    ///
+    /// Checks whether message passes the filter
+    ///
+    /// # Arguments
+    /// * message: &Message: message to check
+    ///
+    /// returns: bool: true if message matches all set fields
+    ///
+    pub fn matches(&self, message: &Message) -> bool {
+        if self.from_id.is_some() && self.from_id.unwrap() != message.source{
+            return false;
+        }
+        if self.module_id.is_some() && self.module_id.unwrap() != message.module_id{
+            return false;
+        }
+        true
+    }
+
+    ///
+    /// Checks whether message passes the filter by its header only(see MessageHeader::peek)
+    ///
+    /// # Arguments
+    /// * header: &MessageHeader: header of serialized message
+    ///
+    /// returns: bool: true if header matches all set fields
+    ///
+    pub fn matches_header(&self, header: &MessageHeader) -> bool {
+        self.from_id.is_none_or(|id| id == header.source) && self.module_id.is_none_or(|id| id == header.module_id)
+    }
 }
 
 ///
@@ -75,6 +160,59 @@ pub trait TransportService: Send + Sync{
     ///
     fn unsubscribe(&mut self, filter_id: u128);
 
+    ///
+    /// Subscribes to messages on behalf of module, so subscription is removed by
+    /// unsubscribe_all once module is unloaded. Services which do not track ownership
+    /// subscribe as usual.
+    ///
+    /// # Arguments
+    /// * module_id: u64: ID of module making subscription
+    /// * filter: MessageFilter: a filter for messages
+    /// * listener: A listener used for getting
+    ///
+    /// returns: u128: an ID of filter
+    ///
+    #[inline]
+    fn subscribe_owned(&mut self, _module_id: u64, filter: &MessageFilter,
+                       listener: Box<dyn TransportListener>) -> u128{
+        self.subscribe_to_messages(filter, listener)
+    }
+
+    ///
+    /// Removes all subscriptions made by module with subscribe_owned
+    ///
+    /// # Arguments
+    /// * module_id: u64: ID of module
+    ///
+    /// returns: usize: count of removed subscriptions
+    ///
+    #[inline]
+    fn unsubscribe_all(&mut self, _module_id: u64) -> usize{
+        0
+    }
+
+    ///
+    /// Gets count of active subscriptions of every module which has them
+    ///
+    #[inline]
+    fn get_module_subscription_counts(&self) -> HashMap<u64, usize>{
+        HashMap::new()
+    }
+
+    ///
+    /// Gets delivery statistics of subscription
+    ///
+    /// # Arguments
+    /// * filter_id: u128: ID of filter returned by subscribe_to_messages
+    ///
+    /// returns: Option<SubscriptionStats>: statistics or None if subscription does not exist
+    /// or service does not collect them
+    ///
+    #[inline]
+    fn get_subscription_stats(&self, _filter_id: u128) -> Option<SubscriptionStats>{
+        None
+    }
+
     ///
     /// Gets a global transport sender allowing to send messages
     /// anywhere
@@ -94,4 +232,518 @@ pub trait TransportService: Send + Sync{
         let mut sender = self.get_sender();
         sender.send_message(message);
     }
-}
\ No newline at end of file
+
+    ///
+    /// Sends a message using built-in sender, reporting messages which can not be delivered
+    /// (see TransportSender::try_send_message)
+    ///
+    /// # Arguments
+    /// * message: Message: message to be sent
+    ///
+    /// returns: Result<(), SendError>: Ok if message was sent, error if it was dropped
+    ///
+    #[inline]
+    fn try_send_message(&mut self, message: Message) -> Result<(), SendError>{
+        let mut sender = self.get_sender();
+        sender.try_send_message(message)
+    }
+
+    ///
+    /// Sends several messages using one sender, so they are enqueued and flushed
+    /// in one operation(see TransportSender::send_batch)
+    ///
+    /// # Arguments
+    /// * messages: Vec<Message>: messages to be sent in order
+    ///
+    #[inline]
+    fn send_batch(&mut self, messages: Vec<Message>){
+        if messages.is_empty(){
+            return;
+        }
+        let mut sender = self.get_sender();
+        sender.send_batch(messages);
+    }
+
+    ///
+    /// Sends a message which survives crash of transport worker: it is kept in outbox
+    /// until written to connection and replayed after reconnection. Falls back to
+    /// plain sending if service has no outbox.
+    ///
+    /// # Arguments
+    /// * message: Message: message to be sent
+    ///
+    fn send_durable_message(&mut self, mut message: Message){
+        // Outbox acknowledges messages by ID, so it must be unique
+        message.ensure_id();
+        if let Some(outbox) = self.get_outbox(){
+            outbox.lock().unwrap().push(message.clone());
+        }
+        self.send_message(message);
+    }
+
+    ///
+    /// Gets an outbox keeping durable messages until they are transmitted
+    ///
+    /// returns: Option<SharedOutbox>: an outbox or None if messages are not persisted
+    ///
+    #[inline]
+    fn get_outbox(&self) -> Option<SharedOutbox>{
+        None
+    }
+
+    ///
+    /// Gets a queue of messages which repeatedly failed processing
+    ///
+    /// returns: Option<SharedDeadLetterQueue>: a queue or None if poison messages are not kept
+    ///
+    #[inline]
+    fn get_dead_letter_queue(&self) -> Option<SharedDeadLetterQueue>{
+        None
+    }
+
+    ///
+    /// Sends again dead letters marked for replay(see DeadLetterQueue::request_replay)
+    ///
+    /// returns: usize: count of replayed messages
+    ///
+    fn replay_dead_letters(&mut self) -> usize{
+        let messages = match self.get_dead_letter_queue() {
+            Some(queue) => queue.lock().unwrap().take_replays(),
+            None => return 0,
+        };
+        let count = messages.len();
+        for message in messages{
+            self.send_message(message);
+        }
+        count
+    }
+
+    ///
+    /// Gets a tap recording messages passing through the service
+    ///
+    /// returns: Option<SharedTransportTap>: a tap or None if tapping is not enabled
+    ///
+    #[inline]
+    fn get_tap(&self) -> Option<SharedTransportTap>{
+        None
+    }
+
+    ///
+    /// Gets a tap copying frames as they are on wire, before detransformation
+    ///
+    /// returns: Option<SharedRawFrameTap>: a tap or None if service does not handle frames
+    ///
+    #[inline]
+    fn get_raw_frame_tap(&self) -> Option<SharedRawFrameTap>{
+        None
+    }
+
+    ///
+    /// Subscribes to copies of raw frames on behalf of operator, see RawFrameTap::subscribe.
+    /// Frames are delivered besides the pipeline, which is not affected by subscriber.
+    ///
+    /// # Arguments
+    /// * certificates: &mut CertificateServiceBinder: service to verify operator certificate with
+    /// * operator: &OperatorIdentity: operator with transport-tap flag
+    /// * filter: RawFrameFilter: directions of frames to deliver
+    ///
+    /// returns: Result<RawFrameSubscription, RawTapError>: subscription or reason it is refused
+    ///
+    fn subscribe_raw(&mut self, certificates: &mut CertificateServiceBinder, operator: &OperatorIdentity,
+                     filter: RawFrameFilter) -> Result<RawFrameSubscription, RawTapError>{
+        let tap = self.get_raw_frame_tap().ok_or(RawTapError::Disabled)?;
+        tap.subscribe(certificates, operator, filter, DEFAULT_RAW_FRAME_CAPACITY)
+    }
+
+    ///
+    /// Gets a rate limiter enforcing quotas on received messages
+    ///
+    /// returns: Option<SharedRateLimiter>: a limiter or None if quotas are not enforced
+    ///
+    #[inline]
+    fn get_rate_limiter(&self) -> Option<SharedRateLimiter>{
+        None
+    }
+
+    ///
+    /// Gets a policy declaring which received messages must be signed
+    ///
+    /// returns: Option<SharedSignaturePolicy>: a policy or None if signatures are not enforced
+    ///
+    #[inline]
+    fn get_signature_policy(&self) -> Option<SharedSignaturePolicy>{
+        None
+    }
+
+    ///
+    /// Gets allow and block lists of peers enforced on received messages
+    ///
+    /// returns: Option<SharedAccessControl>: access control or None if lists are not enforced
+    ///
+    #[inline]
+    fn get_access_control(&self) -> Option<SharedAccessControl>{
+        None
+    }
+
+    ///
+    /// Gets a group service used to deliver messages addressed to groups
+    ///
+    /// returns: Option<SharedGroupService>: a service or None if groups are not supported
+    ///
+    #[inline]
+    fn get_group_service(&self) -> Option<SharedGroupService>{
+        None
+    }
+
+    ///
+    /// Gets stream of events of connections with peers
+    ///
+    /// returns: Option<SharedConnectionEvents>: events or None if service does not report them
+    ///
+    #[inline]
+    fn get_connection_events(&self) -> Option<SharedConnectionEvents>{
+        None
+    }
+
+    ///
+    /// Subscribes to events of connections with peers: connected, authorized and disconnected
+    ///
+    /// # Arguments
+    /// * listener: Box<dyn ConnectionEventListener>: listener of events
+    ///
+    /// returns: Option<u128>: ID of subscription or None if service does not report events
+    ///
+    fn subscribe_connection_events(&mut self, listener: Box<dyn ConnectionEventListener>) -> Option<u128>{
+        let events = self.get_connection_events()?;
+        let id = events.lock().unwrap().subscribe(listener);
+        Some(id)
+    }
+
+    ///
+    /// Unsubscribes from events of connections
+    ///
+    /// # Arguments
+    /// * subscription_id: u128: ID returned by subscribe_connection_events
+    ///
+    fn unsubscribe_connection_events(&mut self, subscription_id: u128){
+        if let Some(events) = self.get_connection_events(){
+            events.lock().unwrap().unsubscribe(subscription_id);
+        }
+    }
+
+    ///
+    /// Gets alerts raised on cryptographic failures of connections with peers
+    ///
+    /// returns: Option<SharedCryptoAlerts>: alerts or None if service does not report them
+    ///
+    #[inline]
+    fn get_crypto_alerts(&self) -> Option<SharedCryptoAlerts>{
+        None
+    }
+
+    ///
+    /// Subscribes to alerts raised on cryptographic failures, e.g. tampered frames
+    ///
+    /// # Arguments
+    /// * listener: Box<dyn CryptoAlertListener>: listener of alerts
+    ///
+    /// returns: Option<u128>: ID of subscription or None if service does not report alerts
+    ///
+    fn subscribe_crypto_alerts(&mut self, listener: Box<dyn CryptoAlertListener>) -> Option<u128>{
+        let alerts = self.get_crypto_alerts()?;
+        let id = alerts.lock().unwrap().subscribe(listener);
+        Some(id)
+    }
+
+    ///
+    /// Unsubscribes from crypto alerts
+    ///
+    /// # Arguments
+    /// * subscription_id: u128: ID returned by subscribe_crypto_alerts
+    ///
+    fn unsubscribe_crypto_alerts(&mut self, subscription_id: u128){
+        if let Some(alerts) = self.get_crypto_alerts(){
+            alerts.lock().unwrap().unsubscribe(subscription_id);
+        }
+    }
+}
+///
+/// Transport service handed to one module: every subscription it makes is owned by
+/// the module, so all of them can be removed when module is unloaded or restarted
+///
+pub struct ModuleTransportService{
+    inner: Box<dyn TransportService>,
+    module_id: u64,
+    fault: Option<ModuleFault>,
+}
+
+impl ModuleTransportService {
+    pub fn new(inner: Box<dyn TransportService>, module_id: u64) -> ModuleTransportService{
+        ModuleTransportService{
+            inner,
+            module_id,
+            fault: None,
+        }
+    }
+
+    ///
+    /// Sets fault listeners of module report their panics to, so supervisor fails module
+    /// instead of panic unwinding into host
+    ///
+    pub fn set_fault(&mut self, fault: ModuleFault) -> &mut Self{
+        self.fault = Some(fault);
+        self
+    }
+
+    fn guard_listener(&self, listener: Box<dyn TransportListener>) -> Box<dyn TransportListener>{
+        match &self.fault {
+            Some(fault) => {
+                let rethrow = self.inner.get_dead_letter_queue().is_some();
+                Box::new(GuardedListener::new(listener, fault.clone(), rethrow))
+            }
+            None => listener,
+        }
+    }
+}
+
+impl TransportService for ModuleTransportService{
+    fn subscribe_to_messages(&mut self, filter: &MessageFilter, listener: Box<dyn TransportListener>) -> u128 {
+        let listener = self.guard_listener(listener);
+        self.inner.subscribe_owned(self.module_id, filter, listener)
+    }
+
+    #[inline]
+    fn unsubscribe(&mut self, filter_id: u128) {
+        self.inner.unsubscribe(filter_id);
+    }
+
+    fn subscribe_owned(&mut self, module_id: u64, filter: &MessageFilter,
+                       listener: Box<dyn TransportListener>) -> u128 {
+        let listener = self.guard_listener(listener);
+        self.inner.subscribe_owned(module_id, filter, listener)
+    }
+
+    fn unsubscribe_all(&mut self, module_id: u64) -> usize {
+        let mut count = self.inner.unsubscribe_all(module_id);
+        if let Some(events) = self.inner.get_connection_events(){
+            count += events.lock().unwrap().unsubscribe_all(module_id);
+        }
+        if let Some(alerts) = self.inner.get_crypto_alerts(){
+            count += alerts.lock().unwrap().unsubscribe_all(module_id);
+        }
+        count
+    }
+
+    #[inline]
+    fn get_module_subscription_counts(&self) -> HashMap<u64, usize> {
+        self.inner.get_module_subscription_counts()
+    }
+
+    #[inline]
+    fn get_subscription_stats(&self, filter_id: u128) -> Option<SubscriptionStats> {
+        self.inner.get_subscription_stats(filter_id)
+    }
+
+    #[inline]
+    fn get_sender(&mut self) -> Box<dyn TransportSender> {
+        self.inner.get_sender()
+    }
+
+    #[inline]
+    fn send_durable_message(&mut self, message: Message) {
+        self.inner.send_durable_message(message);
+    }
+
+    #[inline]
+    fn get_outbox(&self) -> Option<SharedOutbox> {
+        self.inner.get_outbox()
+    }
+
+    #[inline]
+    fn get_dead_letter_queue(&self) -> Option<SharedDeadLetterQueue> {
+        self.inner.get_dead_letter_queue()
+    }
+
+    #[inline]
+    fn replay_dead_letters(&mut self) -> usize {
+        self.inner.replay_dead_letters()
+    }
+
+    #[inline]
+    fn get_tap(&self) -> Option<SharedTransportTap> {
+        self.inner.get_tap()
+    }
+
+    #[inline]
+    fn get_raw_frame_tap(&self) -> Option<SharedRawFrameTap> {
+        self.inner.get_raw_frame_tap()
+    }
+
+    #[inline]
+    fn get_rate_limiter(&self) -> Option<SharedRateLimiter> {
+        self.inner.get_rate_limiter()
+    }
+
+    #[inline]
+    fn get_signature_policy(&self) -> Option<SharedSignaturePolicy> {
+        self.inner.get_signature_policy()
+    }
+
+    #[inline]
+    fn get_access_control(&self) -> Option<SharedAccessControl> {
+        self.inner.get_access_control()
+    }
+
+    #[inline]
+    fn get_group_service(&self) -> Option<SharedGroupService> {
+        self.inner.get_group_service()
+    }
+
+    #[inline]
+    fn get_connection_events(&self) -> Option<SharedConnectionEvents> {
+        self.inner.get_connection_events()
+    }
+
+    fn subscribe_connection_events(&mut self, listener: Box<dyn ConnectionEventListener>) -> Option<u128> {
+        let events = self.inner.get_connection_events()?;
+        let listener: Box<dyn ConnectionEventListener> = match &self.fault {
+            Some(fault) => Box::new(GuardedConnectionListener::new(listener, fault.clone())),
+            None => listener,
+        };
+        let id = events.lock().unwrap().subscribe_owned(self.module_id, listener);
+        Some(id)
+    }
+
+    #[inline]
+    fn get_crypto_alerts(&self) -> Option<SharedCryptoAlerts> {
+        self.inner.get_crypto_alerts()
+    }
+
+    fn subscribe_crypto_alerts(&mut self, listener: Box<dyn CryptoAlertListener>) -> Option<u128> {
+        let alerts = self.inner.get_crypto_alerts()?;
+        let listener: Box<dyn CryptoAlertListener> = match &self.fault {
+            Some(fault) => Box::new(GuardedAlertListener::new(listener, fault.clone())),
+            None => listener,
+        };
+        let id = alerts.lock().unwrap().subscribe_owned(self.module_id, listener);
+        Some(id)
+    }
+}
+
+///
+/// Transport service which signs every outgoing message with operator certificate, so
+/// commands issued from CLI are attributed to operator by peers receiving them
+///
+pub struct OperatorTransportService{
+    inner: Box<dyn TransportService>,
+    identity: Arc<OperatorIdentity>,
+}
+
+impl OperatorTransportService {
+    pub fn new(inner: Box<dyn TransportService>, identity: Arc<OperatorIdentity>) -> OperatorTransportService{
+        OperatorTransportService{
+            inner,
+            identity,
+        }
+    }
+}
+
+impl TransportService for OperatorTransportService{
+    #[inline]
+    fn subscribe_to_messages(&mut self, filter: &MessageFilter, listener: Box<dyn TransportListener>) -> u128 {
+        self.inner.subscribe_to_messages(filter, listener)
+    }
+
+    #[inline]
+    fn unsubscribe(&mut self, filter_id: u128) {
+        self.inner.unsubscribe(filter_id);
+    }
+
+    #[inline]
+    fn subscribe_owned(&mut self, module_id: u64, filter: &MessageFilter,
+                       listener: Box<dyn TransportListener>) -> u128 {
+        self.inner.subscribe_owned(module_id, filter, listener)
+    }
+
+    #[inline]
+    fn unsubscribe_all(&mut self, module_id: u64) -> usize {
+        self.inner.unsubscribe_all(module_id)
+    }
+
+    #[inline]
+    fn get_module_subscription_counts(&self) -> HashMap<u64, usize> {
+        self.inner.get_module_subscription_counts()
+    }
+
+    #[inline]
+    fn get_subscription_stats(&self, filter_id: u128) -> Option<SubscriptionStats> {
+        self.inner.get_subscription_stats(filter_id)
+    }
+
+    #[inline]
+    fn get_sender(&mut self) -> Box<dyn TransportSender> {
+        Box::new(OperatorSigningSender::new(self.inner.get_sender(), self.identity.clone()))
+    }
+
+    fn send_durable_message(&mut self, mut message: Message) {
+        // Message is signed before it is persisted, so replayed copy carries signature too
+        self.identity.sign(&mut message);
+        self.inner.send_durable_message(message);
+    }
+
+    #[inline]
+    fn get_outbox(&self) -> Option<SharedOutbox> {
+        self.inner.get_outbox()
+    }
+
+    #[inline]
+    fn get_dead_letter_queue(&self) -> Option<SharedDeadLetterQueue> {
+        self.inner.get_dead_letter_queue()
+    }
+
+    #[inline]
+    fn replay_dead_letters(&mut self) -> usize {
+        // Replayed messages are sent as they were received, not signed by operator
+        self.inner.replay_dead_letters()
+    }
+
+    #[inline]
+    fn get_tap(&self) -> Option<SharedTransportTap> {
+        self.inner.get_tap()
+    }
+
+    #[inline]
+    fn get_raw_frame_tap(&self) -> Option<SharedRawFrameTap> {
+        self.inner.get_raw_frame_tap()
+    }
+
+    #[inline]
+    fn get_rate_limiter(&self) -> Option<SharedRateLimiter> {
+        self.inner.get_rate_limiter()
+    }
+
+    #[inline]
+    fn get_signature_policy(&self) -> Option<SharedSignaturePolicy> {
+        self.inner.get_signature_policy()
+    }
+
+    #[inline]
+    fn get_access_control(&self) -> Option<SharedAccessControl> {
+        self.inner.get_access_control()
+    }
+
+    #[inline]
+    fn get_group_service(&self) -> Option<SharedGroupService> {
+        self.inner.get_group_service()
+    }
+
+    #[inline]
+    fn get_connection_events(&self) -> Option<SharedConnectionEvents> {
+        self.inner.get_connection_events()
+    }
+
+    #[inline]
+    fn get_crypto_alerts(&self) -> Option<SharedCryptoAlerts> {
+        self.inner.get_crypto_alerts()
+    }
+}
@@ -0,0 +1,21 @@
+/* Test doubles allowing modules to be tested without a running daemon */
+
+///
+/// In-memory loopback implementations of a stream transport and a transport service
+///
+pub mod transport;
+
+///
+/// Mock certificate service with pre-generated certificates
+///
+pub mod certificate;
+
+///
+/// Helpers for loading modules and driving their callbacks
+///
+pub mod module;
+
+///
+/// Golden vectors guarding wire format against accidental changes
+///
+pub mod wire;
@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use once_cell::sync::Lazy;
+use crate::actor::binder::BinderServiceHandler;
+use crate::pki::certificate::{Certificate, FLAG_SIGN_CERTS, FLAG_SIGN_MESSAGES};
+use crate::pki::hash::HashType;
+use crate::pki::impls::certificates::falcon1024::{Falcon1024Certificate, Falcon1024RootCertificate};
+use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use crate::pki::impls::keys::falcon1024::generate_falcon1024_keypair_from_seed;
+use crate::pki::impls::keys::kyber1024::generate_kyber1024_keypair_from_seed;
+use crate::services::certificate::{CertificateService, CertificateServiceBinderRequest, CertificateServiceBinderResponse, ROOT_CERTIFICATE_SERIAL};
+
+/// Serial number of signing certificate from test_certificates()
+pub const TEST_SIGNING_CERTIFICATE_SERIAL: u128 = 1;
+/// Serial number of encryption certificate from test_certificates()
+pub const TEST_ENCRYPTION_CERTIFICATE_SERIAL: u128 = 2;
+
+///
+/// A valid chain of certificates: root -> signing -> encryption
+///
+#[derive(Clone)]
+pub struct TestCertificates{
+    pub root: Falcon1024RootCertificate,
+    pub signing: Falcon1024Certificate,
+    pub encryption: Kyber1024Certificate,
+}
+
+///
+/// Generates a chain of certificates with serials of test_certificates() from given seed.
+/// Chain generated from other seed than `test` is not trusted by services holding test certificates.
+///
+/// # Arguments
+/// * seed: &str: prefix of seeds and names of certificates
+///
+pub fn generate_test_certificates(seed: &str) -> TestCertificates{
+    let (public_key, secret_key) = generate_falcon1024_keypair_from_seed(format!("{}-root", seed).as_bytes());
+    let root = Falcon1024RootCertificate{
+        secret_key: Some(secret_key),
+        public_key,
+        name: format!("{}-root", seed),
+    };
+    let (public_key, secret_key) = generate_falcon1024_keypair_from_seed(format!("{}-signing", seed).as_bytes());
+    let mut signing = Falcon1024Certificate{
+        serial_number: TEST_SIGNING_CERTIFICATE_SERIAL,
+        parent_serial_number: ROOT_CERTIFICATE_SERIAL,
+        secret_key: Some(secret_key),
+        public_key,
+        signature: None,
+        name: format!("{}-signing", seed),
+        flags: FLAG_SIGN_CERTS | FLAG_SIGN_MESSAGES,
+        metadata: Default::default(),
+    };
+    signing.signature = Some(root.sign_data(&signing.clone_without_signature_and_sk(),
+                                            HashType::None).unwrap());
+    let (public_key, secret_key) = generate_kyber1024_keypair_from_seed(format!("{}-encryption", seed).as_bytes());
+    let mut encryption = Kyber1024Certificate{
+        serial_number: TEST_ENCRYPTION_CERTIFICATE_SERIAL,
+        parent_serial_number: TEST_SIGNING_CERTIFICATE_SERIAL,
+        secret_key: Some(secret_key),
+        public_key,
+        signature: None,
+        name: format!("{}-encryption", seed),
+        flags: 0,
+        metadata: Default::default(),
+    };
+    encryption.signature = Some(signing.sign_data(&encryption.clone_without_signature_and_sk(),
+                                                  HashType::None).unwrap());
+    TestCertificates{
+        root,
+        signing,
+        encryption,
+    }
+}
+
+static TEST_CERTIFICATES: Lazy<TestCertificates> = Lazy::new(|| generate_test_certificates("test"));
+
+///
+/// Gets a chain of test certificates. Keys are derived from fixed seeds, so certificates
+/// are the same in every run, and they are generated only once per process.
+///
+#[inline]
+pub fn test_certificates() -> TestCertificates{
+    TEST_CERTIFICATES.clone()
+}
+
+struct MockCertificateState{
+    root: Option<Falcon1024RootCertificate>,
+    signing: HashMap<u128, Falcon1024Certificate>,
+    encryption: HashMap<u128, Kyber1024Certificate>,
+    verification_result: bool,
+    commits: usize,
+}
+
+///
+/// An in-memory CertificateService which does no real verification.
+/// Result of verification is configured by set_verification_result.
+/// Clones share same state, so a test can inspect service after handing it to a module.
+///
+#[derive(Clone)]
+pub struct MockCertificateService{
+    state: Arc<Mutex<MockCertificateState>>,
+}
+
+impl MockCertificateService {
+    ///
+    /// Creates an empty service which accepts all certificates
+    ///
+    pub fn new() -> MockCertificateService{
+        MockCertificateService{
+            state: Arc::new(Mutex::new(MockCertificateState{
+                root: None,
+                signing: HashMap::new(),
+                encryption: HashMap::new(),
+                verification_result: true,
+                commits: 0,
+            }))
+        }
+    }
+
+    ///
+    /// Creates a service containing certificates from test_certificates()
+    ///
+    pub fn with_test_certificates() -> MockCertificateService{
+        let certificates = test_certificates();
+        let mut service = Self::new();
+        service.set_root_certificate(certificates.root);
+        service.add_signing_certificate(certificates.signing);
+        service.add_encryption_certificate(certificates.encryption);
+        service
+    }
+
+    ///
+    /// Sets result returned by verification and add functions
+    ///
+    /// # Arguments
+    /// * result: bool: whether certificates should be treated as valid
+    ///
+    pub fn set_verification_result(&mut self, result: bool){
+        self.state.lock().unwrap().verification_result = result;
+    }
+
+    ///
+    /// Gets how many times commit was called
+    ///
+    pub fn get_commit_count(&self) -> usize{
+        self.state.lock().unwrap().commits
+    }
+}
+
+impl Default for MockCertificateService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CertificateService for MockCertificateService{
+    fn set_root_certificate(&mut self, root_cert: Falcon1024RootCertificate) {
+        self.state.lock().unwrap().root = Some(root_cert);
+    }
+
+    fn add_signing_certificate(&mut self, cert: Falcon1024Certificate) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if !state.verification_result{
+            return false;
+        }
+        state.signing.insert(cert.serial_number, cert);
+        true
+    }
+
+    fn add_encryption_certificate(&mut self, cert: Kyber1024Certificate) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if !state.verification_result{
+            return false;
+        }
+        state.encryption.insert(cert.serial_number, cert);
+        true
+    }
+
+    #[inline]
+    fn verify_signing_certificate(&mut self, _cert: &Falcon1024Certificate) -> bool {
+        self.state.lock().unwrap().verification_result
+    }
+
+    #[inline]
+    fn verify_encryption_certificate(&mut self, _cert: &Kyber1024Certificate) -> bool {
+        self.state.lock().unwrap().verification_result
+    }
+
+    fn get_signing_certificate(&mut self, serial: u128) -> Option<Falcon1024Certificate> {
+        self.state.lock().unwrap().signing.get(&serial).cloned()
+    }
+
+    fn get_encryption_certificate(&mut self, serial: u128) -> Option<Kyber1024Certificate> {
+        self.state.lock().unwrap().encryption.get(&serial).cloned()
+    }
+
+    fn get_root_certificate(&mut self) -> Option<Falcon1024RootCertificate> {
+        self.state.lock().unwrap().root.clone()
+    }
+
+    fn get_signing_certificates(&mut self) -> Vec<Falcon1024Certificate> {
+        self.state.lock().unwrap().signing.values().cloned().collect()
+    }
+
+    fn get_encryption_certificates(&mut self) -> Vec<Kyber1024Certificate> {
+        self.state.lock().unwrap().encryption.values().cloned().collect()
+    }
+
+    fn remove_signing_certificate(&mut self, serial: u128) -> bool {
+        self.state.lock().unwrap().signing.remove(&serial).is_some()
+    }
+
+    fn remove_encryption_certificate(&mut self, serial: u128) -> bool {
+        self.state.lock().unwrap().encryption.remove(&serial).is_some()
+    }
+
+    fn commit(&mut self) {
+        self.state.lock().unwrap().commits += 1;
+    }
+}
+
+impl BinderServiceHandler<CertificateServiceBinderRequest, CertificateServiceBinderResponse> for MockCertificateService {
+    fn handle_message(&mut self, request: CertificateServiceBinderRequest) -> CertificateServiceBinderResponse {
+        let ptr: &mut dyn CertificateService = self;
+        ptr.handle_message(request)
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actor::binder::BinderChannelProvider;
+    use crate::services::certificate::{AsyncCertificateService, CertificateAsyncService};
+    use crate::tokio::{init_tokio, tokio_block_on};
+
+    #[test]
+    fn test_certificates_are_chained() {
+        let certificates = test_certificates();
+        assert!(certificates.root.verify_signature(&certificates.signing.clone_without_signature_and_sk(),
+                                                   certificates.signing.signature.as_ref().unwrap()));
+        assert!(certificates.signing.verify_signature(&certificates.encryption.clone_without_signature_and_sk(),
+                                                      certificates.encryption.signature.as_ref().unwrap()));
+    }
+
+    #[test]
+    fn test_mock_service_through_binder() {
+        init_tokio();
+        let service = MockCertificateService::with_test_certificates();
+        let mut async_service = CertificateAsyncService::run(Box::new(service.clone()));
+        let mut binder = async_service.bind();
+        assert!(binder.get_root_certificate().is_some());
+        assert!(binder.get_signing_certificate(TEST_SIGNING_CERTIFICATE_SERIAL).is_some());
+        assert_eq!(binder.get_encryption_certificates().len(), 1);
+        let page = binder.list_signing_certificates(None, 10);
+        assert_eq!(page.certificates[0].serial, TEST_SIGNING_CERTIFICATE_SERIAL);
+        assert_eq!(page.next_cursor, None);
+        binder.commit();
+        assert_eq!(service.get_commit_count(), 1);
+    }
+
+    #[test]
+    fn test_mock_service_through_async_binder() {
+        init_tokio();
+        let service = MockCertificateService::with_test_certificates();
+        let mut async_service = CertificateAsyncService::run(Box::new(service.clone()));
+        tokio_block_on(async {
+            let mut binder = async_service.bind_async().await;
+            assert!(binder.get_root_certificate().await.is_some());
+            assert!(binder.get_signing_certificate(TEST_SIGNING_CERTIFICATE_SERIAL).await.is_some());
+            assert_eq!(binder.get_encryption_certificates().await.len(), 1);
+            binder.commit().await;
+        });
+        assert_eq!(service.get_commit_count(), 1);
+    }
+
+    #[test]
+    fn test_mock_service_rejects_when_configured() {
+        let mut service = MockCertificateService::new();
+        service.set_verification_result(false);
+        let certificates = test_certificates();
+        assert!(!service.verify_signing_certificate(&certificates.signing));
+        assert!(!service.add_signing_certificate(certificates.signing));
+        assert!(service.get_signing_certificates().is_empty());
+    }
+}
@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use crate::actor::binder::BinderChannelProvider;
+use crate::message::common::Message;
+use crate::module::{CLIStatus, HostType, MilkywayModule, ModuleDataBus};
+use crate::module::session::CLIInput;
+use crate::services::certificate::{CertificateAsyncService, CertificateServiceBinder};
+use crate::services::name::NameService;
+use crate::services::transport::TransportService;
+use crate::testing::certificate::MockCertificateService;
+use crate::testing::transport::LoopbackTransportService;
+
+///
+/// A NameService with fixed set of names
+///
+#[derive(Clone)]
+pub struct StaticNameService{
+    domain: String,
+    names: HashMap<u128, String>,
+}
+
+impl StaticNameService {
+    ///
+    /// Creates a name service without any names
+    ///
+    /// # Arguments
+    /// * domain: &str: domain of network
+    ///
+    pub fn new(domain: &str) -> StaticNameService{
+        StaticNameService{
+            domain: domain.to_string(),
+            names: HashMap::new(),
+        }
+    }
+
+    ///
+    /// Adds a name of host
+    ///
+    /// # Arguments
+    /// * id: u128: ID of host
+    /// * name: &str: name of host
+    ///
+    pub fn add_name(&mut self, id: u128, name: &str) -> &mut Self{
+        self.names.insert(id, name.to_string());
+        self
+    }
+}
+
+impl NameService for StaticNameService{
+    fn get_name_by_id(&self, id: u128) -> String {
+        let name = self.names.get(&id);
+        if name.is_none(){
+            return id.to_string();
+        }
+        name.unwrap().clone()
+    }
+
+    fn get_id_by_name(&self, name: &str) -> Option<u128> {
+        self.names.iter().find(|(_, known)| known.as_str() == name).map(|(id, _)| *id)
+    }
+
+    #[inline]
+    fn get_domain(&self) -> String {
+        self.domain.clone()
+    }
+
+    fn get_known_names(&self) -> Vec<String> {
+        self.names.values().cloned().collect()
+    }
+}
+
+///
+/// A data bus backed by loopback transport and mock certificate service
+///
+/// # Warning
+/// Certificate service is run on tokio runtime of the thread which created the bus,
+/// so init_tokio() must be called before
+///
+#[derive(Clone)]
+pub struct TestDataBus{
+    host_type: HostType,
+    transport: LoopbackTransportService,
+    names: StaticNameService,
+    certificate_service: Arc<Mutex<CertificateAsyncService>>,
+}
+
+impl TestDataBus {
+    ///
+    /// Creates a data bus with a fresh loopback network and given certificate service
+    ///
+    /// # Arguments
+    /// * host_type: HostType: type of host to emulate
+    /// * host_id: u128: ID of emulated host
+    /// * certificates: MockCertificateService: certificate service to serve to module
+    ///
+    pub fn new(host_type: HostType, host_id: u128,
+               certificates: MockCertificateService) -> TestDataBus{
+        TestDataBus{
+            host_type,
+            transport: LoopbackTransportService::new(host_id),
+            names: StaticNameService::new("test"),
+            certificate_service: Arc::new(Mutex::new(CertificateAsyncService::run(Box::new(certificates)))),
+        }
+    }
+
+    ///
+    /// Builder-like function replacing transport, e.g. with an endpoint of existing loopback network
+    ///
+    /// # Arguments
+    /// * transport: LoopbackTransportService: transport to use
+    ///
+    pub fn with_transport(mut self, transport: LoopbackTransportService) -> TestDataBus{
+        self.transport = transport;
+        self
+    }
+
+    ///
+    /// Builder-like function replacing name service
+    ///
+    /// # Arguments
+    /// * names: StaticNameService: name service to use
+    ///
+    pub fn with_names(mut self, names: StaticNameService) -> TestDataBus{
+        self.names = names;
+        self
+    }
+
+    ///
+    /// Gets transport used by data bus
+    ///
+    #[inline]
+    pub fn get_loopback(&self) -> LoopbackTransportService{
+        self.transport.clone()
+    }
+}
+
+impl ModuleDataBus for TestDataBus{
+    fn get_transport_service(&self) -> Box<dyn TransportService> {
+        Box::new(self.transport.clone())
+    }
+
+    fn get_name_service(&self) -> Box<dyn NameService> {
+        Box::new(self.names.clone())
+    }
+
+    fn get_certificate_service(&self) -> Box<CertificateServiceBinder> {
+        self.certificate_service.lock().unwrap().bind()
+    }
+
+    #[inline]
+    fn get_host_type(&self) -> HostType {
+        self.host_type
+    }
+
+    #[inline]
+    fn get_host_id(&self) -> Option<u128> {
+        Some(self.transport.get_host_id())
+    }
+}
+
+///
+/// Drives callbacks of a module in the same way as a host does
+///
+pub struct ModuleTestHost{
+    module: Box<dyn MilkywayModule>,
+    host_type: HostType,
+}
+
+impl ModuleTestHost {
+    ///
+    /// Loads module with given data bus
+    ///
+    /// # Arguments
+    /// * module: Box<dyn MilkywayModule>: module to test
+    /// * data_bus: TestDataBus: data bus to pass to on_load
+    ///
+    pub fn load(mut module: Box<dyn MilkywayModule>, data_bus: TestDataBus) -> ModuleTestHost{
+        let host_type = data_bus.get_host_type();
+        module.on_load(Box::new(data_bus));
+        ModuleTestHost{
+            module,
+            host_type,
+        }
+    }
+
+    ///
+    /// Runs CLI command
+    ///
+    /// # Arguments
+    /// * command: &str: command path, e.g. "certman/encryption/generate"
+    /// * arguments: &[&str]: arguments of command, e.g. ["name=test"]
+    ///
+    pub fn cli_command(&mut self, command: &str, arguments: &[&str]) -> CLIStatus{
+        let command = command.split('/').map(|s| s.to_string()).collect();
+        let arguments = arguments.iter().map(|s| s.to_string()).collect();
+        self.module.on_cli_command(command, arguments)
+    }
+
+    ///
+    /// Continues CLI command which returned InProgress or NeedsInput
+    ///
+    /// # Arguments
+    /// * input: CLIInput: answer to prompt, Next after progress or Cancelled
+    ///
+    #[inline]
+    pub fn cli_continue(&mut self, input: CLIInput) -> CLIStatus{
+        self.module.on_cli_continue(input)
+    }
+
+    ///
+    /// Passes message to module callback corresponding to host type
+    ///
+    /// # Arguments
+    /// * message: &Message: message to deliver
+    ///
+    pub fn deliver(&self, message: &Message){
+        match self.host_type {
+            HostType::CLI => self.module.on_cli_receive(message),
+            HostType::Broker => self.module.on_server_receive(message),
+            HostType::Peer => self.module.on_client_receive(message),
+        }
+    }
+
+    ///
+    /// Gets tested module
+    ///
+    #[inline]
+    pub fn get_module(&mut self) -> &mut Box<dyn MilkywayModule>{
+        &mut self.module
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::certificate::CertificateService;
+    use crate::services::transport::MessageFilter;
+    use crate::testing::certificate::TEST_SIGNING_CERTIFICATE_SERIAL;
+    use crate::tokio::init_tokio;
+    use crate::transport::TransportListener;
+
+    const ECHO_MODULE_ID: u64 = 42;
+
+    struct EchoModule{
+        data_bus: Option<Box<dyn ModuleDataBus>>,
+        has_signing_certificate: bool,
+    }
+
+    impl MilkywayModule for EchoModule{
+        fn get_id(&self) -> u64 {
+            ECHO_MODULE_ID
+        }
+
+        fn get_commands(&self) -> Vec<String> {
+            vec!["echo".to_string()]
+        }
+
+        fn on_load(&mut self, data_bus: Box<dyn ModuleDataBus>) {
+            let mut certificates = data_bus.get_certificate_service();
+            self.has_signing_certificate = certificates
+                .get_signing_certificate(TEST_SIGNING_CERTIFICATE_SERIAL).is_some();
+            self.data_bus = Some(data_bus);
+        }
+
+        fn on_cli_command(&mut self, command: Vec<String>, _arguments: Vec<String>) -> CLIStatus {
+            CLIStatus::NamespaceChange(command)
+        }
+
+        fn on_server_receive(&self, packet: &Message) {
+            let mut reply = packet.clone();
+            reply.destination = packet.source;
+            reply.source = self.data_bus.as_ref().unwrap().get_host_id().unwrap();
+            self.data_bus.as_ref().unwrap().get_transport_service().send_message(reply);
+        }
+
+        fn on_client_receive(&self, _packet: &Message) {}
+
+        fn on_cli_receive(&self, _packet: &Message) {}
+    }
+
+    struct CollectingListener{
+        received: Arc<Mutex<Vec<Message>>>,
+    }
+
+    impl TransportListener for CollectingListener{
+        fn on_message(&mut self, message: Message) {
+            self.received.lock().unwrap().push(message);
+        }
+    }
+
+    #[test]
+    fn test_module_host_drives_callbacks() {
+        init_tokio();
+        let (mut client, server) = LoopbackTransportService::pair(1, 2);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        client.subscribe_to_messages(&MessageFilter::new(),
+                                     Box::new(CollectingListener{ received: received.clone() }));
+        let data_bus = TestDataBus::new(HostType::Broker, 2,
+                                        MockCertificateService::with_test_certificates())
+            .with_transport(server);
+        let module = Box::new(EchoModule{ data_bus: None, has_signing_certificate: false });
+        let mut host = ModuleTestHost::load(module, data_bus);
+        let status = host.cli_command("echo/test", &[]);
+        match status {
+            CLIStatus::NamespaceChange(path) => assert_eq!(path, vec!["echo", "test"]),
+            status => panic!("Expected namespace change, got {:?}", status),
+        }
+        let mut message = Message::new();
+        message.source = 1;
+        message.destination = 2;
+        message.module_id = ECHO_MODULE_ID;
+        host.deliver(&message);
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].source, 2);
+    }
+
+    #[test]
+    fn test_static_name_service() {
+        let mut names = StaticNameService::new("example");
+        names.add_name(1, "alpha");
+        assert_eq!(names.get_name_by_id(1), "alpha");
+        assert_eq!(names.get_name_by_id(2), "2");
+        assert_eq!(names.get_domain(), "example");
+    }
+}
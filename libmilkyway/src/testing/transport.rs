@@ -0,0 +1,658 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use crate::message::common::Message;
+use crate::services::transport::{MessageFilter, TransportService};
+use crate::transport::{TransportListener, TransportSender};
+use crate::services::certificate::CertificateService;
+use crate::services::group::SharedGroupService;
+use crate::transport::ratelimit::{RateLimitVerdict, SharedRateLimiter};
+use crate::transport::signature::{SharedSignaturePolicy, SignatureEnforcement};
+use crate::transport::access::SharedAccessControl;
+use crate::transport::deadletter::SharedDeadLetterQueue;
+use crate::transport::events::SharedConnectionEvents;
+use crate::transport::subscriptions::{SubscriptionStats, Subscriptions};
+use crate::transport::tap::{SharedTransportTap, TapDirection};
+use crate::transport::async_stream::TokioStreamTransport;
+use tokio::io::{duplex, DuplexStream};
+
+///
+/// State shared by all endpoints of one loopback network
+///
+struct LoopbackHub{
+    /** Subscriptions of each endpoint, by host ID **/
+    endpoints: Mutex<HashMap<u128, Subscriptions>>,
+    /** Messages waiting for delivery **/
+    queue: Mutex<VecDeque<Message>>,
+    /** Messages over quota, delivered once queue is empty **/
+    deprioritized: Mutex<VecDeque<Message>>,
+    /** All messages ever sent with ID of endpoint which sent them **/
+    sent: Mutex<Vec<(u128, Message)>>,
+    /** Held while messages are delivered, so listeners may send messages themselves **/
+    delivery_lock: Mutex<()>,
+    last_subscription_id: Mutex<u128>,
+    /** Taps of endpoints, by host ID **/
+    taps: Mutex<HashMap<u128, SharedTransportTap>>,
+    /** Rate limiters of endpoints, by host ID **/
+    rate_limiters: Mutex<HashMap<u128, SharedRateLimiter>>,
+    /** Signature policies of endpoints with services to verify signatures, by host ID **/
+    signature_policies: Mutex<HashMap<u128, SignatureEnforcement>>,
+    /** Group services expanding messages sent to groups, by host ID **/
+    group_services: Mutex<HashMap<u128, SharedGroupService>>,
+    /** Allow and block lists of endpoints, by host ID **/
+    access_controls: Mutex<HashMap<u128, SharedAccessControl>>,
+    /** Queues of messages listeners of endpoints repeatedly panicked on, by host ID **/
+    dead_letter_queues: Mutex<HashMap<u128, SharedDeadLetterQueue>>,
+    /** Connection events of endpoints, by host ID **/
+    connection_events: Mutex<HashMap<u128, SharedConnectionEvents>>,
+}
+
+impl LoopbackHub {
+    fn new() -> LoopbackHub{
+        LoopbackHub{
+            endpoints: Mutex::new(HashMap::new()),
+            queue: Mutex::new(VecDeque::new()),
+            deprioritized: Mutex::new(VecDeque::new()),
+            sent: Mutex::new(Vec::new()),
+            delivery_lock: Mutex::new(()),
+            last_subscription_id: Mutex::new(0),
+            taps: Mutex::new(HashMap::new()),
+            rate_limiters: Mutex::new(HashMap::new()),
+            signature_policies: Mutex::new(HashMap::new()),
+            group_services: Mutex::new(HashMap::new()),
+            access_controls: Mutex::new(HashMap::new()),
+            dead_letter_queues: Mutex::new(HashMap::new()),
+            connection_events: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn tap(&self, host_id: u128, direction: TapDirection, message: &Message){
+        let taps = self.taps.lock().unwrap();
+        if let Some(tap) = taps.get(&host_id){
+            tap.lock().unwrap().record(direction, message);
+        }
+    }
+
+    fn send(&self, from: u128, message: Message){
+        self.tap(from, TapDirection::Outgoing, &message);
+        self.sent.lock().unwrap().push((from, message.clone()));
+        let group_service = self.group_services.lock().unwrap().get(&from).cloned();
+        let messages = match group_service {
+            Some(service) => service.lock().unwrap().expand_destination(&message),
+            None => vec![message],
+        };
+        self.queue.lock().unwrap().extend(messages);
+        self.deliver_pending();
+    }
+
+    ///
+    /// Delivers all queued messages. If delivery is already in progress(e.g. a listener
+    /// sends a message from on_message) returns immediately and the active delivery
+    /// loop picks the message up.
+    ///
+    fn deliver_pending(&self){
+        loop {
+            let guard = self.delivery_lock.try_lock();
+            if guard.is_err(){
+                return;
+            }
+            loop {
+                let message = self.queue.lock().unwrap().pop_front();
+                if let Some(message) = message{
+                    if self.is_allowed(&message){
+                        self.deliver(message);
+                    }
+                    continue;
+                }
+                let message = self.deprioritized.lock().unwrap().pop_front();
+                match message {
+                    Some(message) => self.deliver(message),
+                    None => break,
+                }
+            }
+            drop(guard);
+            // Somebody could have queued a message while we were releasing the lock
+            if self.queue.lock().unwrap().is_empty() && self.deprioritized.lock().unwrap().is_empty(){
+                return;
+            }
+        }
+    }
+
+    ///
+    /// Checks message against rate limiter of destination endpoint. Deprioritized
+    /// messages are moved to the end of delivery.
+    ///
+    fn is_allowed(&self, message: &Message) -> bool{
+        let limiter = self.rate_limiters.lock().unwrap().get(&message.destination).cloned();
+        let verdict = match limiter {
+            Some(limiter) => limiter.lock().unwrap().check(message),
+            None => return true,
+        };
+        match verdict {
+            RateLimitVerdict::Allow => true,
+            RateLimitVerdict::Deprioritize => {
+                self.deprioritized.lock().unwrap().push_back(message.clone());
+                false
+            }
+            RateLimitVerdict::Drop | RateLimitVerdict::Disconnect => {
+                log::warn!("Loopback: message from {} to {} dropped by rate limiter",
+                    message.source, message.destination);
+                false
+            }
+        }
+    }
+
+    fn deliver(&self, message: Message){
+        self.tap(message.destination, TapDirection::Incoming, &message);
+        let access = self.access_controls.lock().unwrap().get(&message.destination).cloned();
+        if access.is_some_and(|access| !access.lock().unwrap().check_message(&message)){
+            return;
+        }
+        if let Some(enforcement) = self.signature_policies.lock().unwrap().get_mut(&message.destination){
+            if !enforcement.check(&message){
+                return;
+            }
+        }
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let endpoint = endpoints.get_mut(&message.destination);
+        if endpoint.is_none(){
+            log::warn!("Loopback: no endpoint with id={}, message dropped", message.destination);
+            return;
+        }
+        endpoint.unwrap().dispatch(&message);
+    }
+}
+
+///
+/// Sender delivering messages through a loopback network
+///
+pub struct LoopbackSender{
+    host_id: u128,
+    hub: Arc<LoopbackHub>,
+}
+
+impl TransportSender for LoopbackSender{
+    #[inline]
+    fn send_message(&mut self, mut message: Message) {
+        message.ensure_id();
+        self.hub.send(self.host_id, message);
+    }
+}
+
+///
+/// An in-memory TransportService. Messages are delivered synchronously to listeners
+/// of endpoint whose host ID equals to message destination.
+///
+/// # Warning
+/// Listeners MUST NOT subscribe or unsubscribe from inside of on_message
+///
+#[derive(Clone)]
+pub struct LoopbackTransportService{
+    host_id: u128,
+    hub: Arc<LoopbackHub>,
+}
+
+impl LoopbackTransportService {
+    ///
+    /// Creates a new loopback network with one endpoint
+    ///
+    /// # Arguments
+    /// * host_id: u128: ID of endpoint
+    ///
+    pub fn new(host_id: u128) -> LoopbackTransportService{
+        let hub = Arc::new(LoopbackHub::new());
+        hub.endpoints.lock().unwrap().insert(host_id, Subscriptions::new());
+        LoopbackTransportService{
+            host_id,
+            hub,
+        }
+    }
+
+    ///
+    /// Creates a pair of endpoints connected to each other
+    ///
+    /// # Arguments
+    /// * first_id: u128: ID of first endpoint
+    /// * second_id: u128: ID of second endpoint
+    ///
+    pub fn pair(first_id: u128, second_id: u128) -> (LoopbackTransportService, LoopbackTransportService){
+        let first = LoopbackTransportService::new(first_id);
+        let second = first.connect(second_id);
+        (first, second)
+    }
+
+    ///
+    /// Adds a new endpoint to the same loopback network
+    ///
+    /// # Arguments
+    /// * host_id: u128: ID of new endpoint
+    ///
+    /// # Panics
+    /// * If endpoint with such ID already exists
+    ///
+    pub fn connect(&self, host_id: u128) -> LoopbackTransportService{
+        let mut endpoints = self.hub.endpoints.lock().unwrap();
+        if endpoints.contains_key(&host_id){
+            panic!("Endpoint with such ID already exists");
+        }
+        endpoints.insert(host_id, Subscriptions::new());
+        LoopbackTransportService{
+            host_id,
+            hub: self.hub.clone(),
+        }
+    }
+
+    ///
+    /// Gets ID of this endpoint
+    ///
+    #[inline]
+    pub fn get_host_id(&self) -> u128{
+        self.host_id
+    }
+
+    ///
+    /// Sets a tap recording messages sent and received by this endpoint
+    ///
+    /// # Arguments
+    /// * tap: SharedTransportTap: tap to record messages to
+    ///
+    pub fn set_tap(&mut self, tap: SharedTransportTap){
+        self.hub.taps.lock().unwrap().insert(self.host_id, tap);
+    }
+
+    ///
+    /// Sets a rate limiter enforcing quotas on messages received by this endpoint
+    ///
+    /// # Arguments
+    /// * limiter: SharedRateLimiter: limiter to check messages with
+    ///
+    pub fn set_rate_limiter(&mut self, limiter: SharedRateLimiter){
+        self.hub.rate_limiters.lock().unwrap().insert(self.host_id, limiter);
+    }
+
+    ///
+    /// Sets a policy requiring signatures on messages received by this endpoint
+    ///
+    /// # Arguments
+    /// * policy: SharedSignaturePolicy: policy to enforce
+    /// * certificates: Box<dyn CertificateService>: service to verify signatures with
+    ///
+    pub fn set_signature_policy(&mut self, policy: SharedSignaturePolicy,
+                                certificates: Box<dyn CertificateService>){
+        self.hub.signature_policies.lock().unwrap().insert(self.host_id, SignatureEnforcement::new(policy, certificates));
+    }
+
+    ///
+    /// Sets a group service expanding messages which this endpoint sends to groups
+    ///
+    /// # Arguments
+    /// * service: SharedGroupService: service with groups known to this endpoint
+    ///
+    pub fn set_group_service(&mut self, service: SharedGroupService){
+        self.hub.group_services.lock().unwrap().insert(self.host_id, service);
+    }
+
+    ///
+    /// Sets allow and block lists of peers which messages are received by this endpoint
+    ///
+    /// # Arguments
+    /// * access: SharedAccessControl: lists to enforce
+    ///
+    pub fn set_access_control(&mut self, access: SharedAccessControl){
+        self.hub.access_controls.lock().unwrap().insert(self.host_id, access);
+    }
+
+    ///
+    /// Sets a queue which messages listeners of this endpoint repeatedly panic on are moved to
+    ///
+    /// # Arguments
+    /// * queue: SharedDeadLetterQueue: queue of poison messages
+    ///
+    pub fn set_dead_letter_queue(&mut self, queue: SharedDeadLetterQueue){
+        let mut endpoints = self.hub.endpoints.lock().unwrap();
+        endpoints.get_mut(&self.host_id).unwrap().set_dead_letter_queue(queue.clone());
+        self.hub.dead_letter_queues.lock().unwrap().insert(self.host_id, queue);
+    }
+
+    ///
+    /// Sets connection events reported by this endpoint, tests publish events themselves
+    ///
+    pub fn set_connection_events(&mut self, events: SharedConnectionEvents){
+        self.hub.connection_events.lock().unwrap().insert(self.host_id, events);
+    }
+
+    ///
+    /// Gets all messages sent from this endpoint
+    ///
+    pub fn sent_messages(&self) -> Vec<Message>{
+        self.hub.sent.lock().unwrap().iter()
+            .filter(|(from, _)| *from == self.host_id)
+            .map(|(_, message)| message.clone())
+            .collect()
+    }
+}
+
+impl TransportService for LoopbackTransportService{
+    fn subscribe_to_messages(&mut self, filter: &MessageFilter,
+                             listener: Box<dyn TransportListener>) -> u128 {
+        let mut last_id = self.hub.last_subscription_id.lock().unwrap();
+        *last_id += 1;
+        let mut endpoints = self.hub.endpoints.lock().unwrap();
+        endpoints.get_mut(&self.host_id).unwrap().add(*last_id, filter.clone(), listener);
+        *last_id
+    }
+
+    fn unsubscribe(&mut self, filter_id: u128) {
+        let mut endpoints = self.hub.endpoints.lock().unwrap();
+        endpoints.get_mut(&self.host_id).unwrap().remove(filter_id);
+    }
+
+    fn subscribe_owned(&mut self, module_id: u64, filter: &MessageFilter,
+                       listener: Box<dyn TransportListener>) -> u128 {
+        let mut last_id = self.hub.last_subscription_id.lock().unwrap();
+        *last_id += 1;
+        let mut endpoints = self.hub.endpoints.lock().unwrap();
+        endpoints.get_mut(&self.host_id).unwrap().add_owned(*last_id, Some(module_id), filter.clone(), listener);
+        *last_id
+    }
+
+    fn unsubscribe_all(&mut self, module_id: u64) -> usize {
+        self.hub.endpoints.lock().unwrap().get_mut(&self.host_id).unwrap().remove_owned(module_id)
+    }
+
+    fn get_module_subscription_counts(&self) -> HashMap<u64, usize> {
+        self.hub.endpoints.lock().unwrap().get(&self.host_id).map(|endpoint| endpoint.get_owner_counts())
+            .unwrap_or_default()
+    }
+
+    fn get_subscription_stats(&self, filter_id: u128) -> Option<SubscriptionStats> {
+        self.hub.endpoints.lock().unwrap().get(&self.host_id).and_then(|endpoint| endpoint.get_stats(filter_id))
+    }
+
+    fn get_sender(&mut self) -> Box<dyn TransportSender> {
+        Box::new(LoopbackSender{
+            host_id: self.host_id,
+            hub: self.hub.clone(),
+        })
+    }
+
+    fn get_dead_letter_queue(&self) -> Option<SharedDeadLetterQueue> {
+        self.hub.dead_letter_queues.lock().unwrap().get(&self.host_id).cloned()
+    }
+
+    fn get_tap(&self) -> Option<SharedTransportTap> {
+        self.hub.taps.lock().unwrap().get(&self.host_id).cloned()
+    }
+
+    fn get_rate_limiter(&self) -> Option<SharedRateLimiter> {
+        self.hub.rate_limiters.lock().unwrap().get(&self.host_id).cloned()
+    }
+
+    fn get_signature_policy(&self) -> Option<SharedSignaturePolicy> {
+        self.hub.signature_policies.lock().unwrap().get(&self.host_id).map(|enforcement| enforcement.policy.clone())
+    }
+
+    fn get_access_control(&self) -> Option<SharedAccessControl> {
+        self.hub.access_controls.lock().unwrap().get(&self.host_id).cloned()
+    }
+
+    fn get_group_service(&self) -> Option<SharedGroupService> {
+        self.hub.group_services.lock().unwrap().get(&self.host_id).cloned()
+    }
+
+    fn get_connection_events(&self) -> Option<SharedConnectionEvents> {
+        self.hub.connection_events.lock().unwrap().get(&self.host_id).cloned()
+    }
+}
+
+///
+/// Size of in-memory buffer of each direction of loopback stream
+///
+pub const LOOPBACK_STREAM_BUFFER_SIZE: usize = 1 << 20;
+
+///
+/// Stream transport whose other side is in memory
+///
+pub type LoopbackStreamTransport = TokioStreamTransport<DuplexStream>;
+
+///
+/// Creates two stream transports connected to each other in memory. Frames pass the same
+/// framing, handshake and transformers as over TCP, so modules and hosts may exercise them
+/// without sockets. Dropping either side closes connection for the other one.
+///
+pub fn loopback_stream_pair() -> (LoopbackStreamTransport, LoopbackStreamTransport){
+    let (first, second) = duplex(LOOPBACK_STREAM_BUFFER_SIZE);
+    (TokioStreamTransport::from_stream(first), TokioStreamTransport::from_stream(second))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::types::MessageType;
+    use crate::pki::certificate::FLAG_TRANSPORT_TAP;
+    use crate::testing::certificate::{test_certificates, MockCertificateService, TEST_SIGNING_CERTIFICATE_SERIAL};
+    use crate::transport::ratelimit::{QuotaAction, QuotaLimits, RateLimitPolicy, RateLimiter};
+    use crate::transport::signature::{SignaturePolicy, SignatureRejection, DEFAULT_SIGNATURE_AUDIT_CAPACITY};
+    use crate::transport::tap::TransportTap;
+    use crate::transport::deadletter::DeadLetterQueue;
+    use crate::message::group::{GroupOperation, GroupRecord};
+    use crate::services::group::{get_group_address, GroupService};
+    use crate::services::impls::group::GroupServiceImpl;
+    use crate::transport::keepalive::KeepAlivePolicy;
+
+    struct CollectingListener{
+        received: Arc<Mutex<Vec<Message>>>,
+    }
+
+    impl TransportListener for CollectingListener{
+        fn on_message(&mut self, message: Message) {
+            self.received.lock().unwrap().push(message);
+        }
+    }
+
+    struct EchoListener{
+        host_id: u128,
+        sender: Box<dyn TransportSender>,
+    }
+
+    impl TransportListener for EchoListener{
+        fn on_message(&mut self, message: Message) {
+            let mut reply = message.clone();
+            reply.set_type(MessageType::Pong);
+            reply.destination = message.source;
+            reply.source = self.host_id;
+            self.sender.send_message(reply);
+        }
+    }
+
+    fn message_to(source: u128, destination: u128, module_id: u64) -> Message{
+        let mut message = Message::new();
+        message.set_destination(destination);
+        message.source = source;
+        message.module_id = module_id;
+        message
+    }
+
+    #[tokio::test]
+    async fn test_loopback_stream_pair() {
+        let (mut client, mut server) = loopback_stream_pair();
+        let policy = KeepAlivePolicy::default();
+        assert!(client.send_message(&message_to(1, 2, 7)).await);
+        let received = server.receive_message(&policy).await.unwrap();
+        assert_eq!((received.source, received.destination, received.module_id), (1, 2, 7));
+        assert!(server.send_message(&message_to(2, 1, 7)).await);
+        assert_eq!(client.receive_message(&policy).await.unwrap().source, 2);
+        drop(server);
+        assert!(client.receive_message(&policy).await.is_none());
+    }
+
+    #[test]
+    fn test_pair_delivers_by_destination() {
+        let (mut first, mut second) = LoopbackTransportService::pair(1, 2);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        second.subscribe_to_messages(&MessageFilter::new(),
+                                     Box::new(CollectingListener{ received: received.clone() }));
+        first.send_message(message_to(1, 2, 0));
+        first.send_message(message_to(1, 3, 0));
+        assert_eq!(received.lock().unwrap().len(), 1);
+        assert_eq!(first.sent_messages().len(), 2);
+        assert!(second.sent_messages().is_empty());
+    }
+
+    #[test]
+    fn test_filter_and_unsubscribe() {
+        let (mut first, mut second) = LoopbackTransportService::pair(1, 2);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let mut filter = MessageFilter::new();
+        filter.filter_module(7);
+        let id = second.subscribe_to_messages(&filter,
+                                              Box::new(CollectingListener{ received: received.clone() }));
+        first.send_message(message_to(1, 2, 6));
+        first.send_message(message_to(1, 2, 7));
+        assert_eq!(received.lock().unwrap().len(), 1);
+        second.unsubscribe(id);
+        first.send_message(message_to(1, 2, 7));
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_listener_may_reply() {
+        let (mut first, mut second) = LoopbackTransportService::pair(1, 2);
+        let echo = EchoListener{ host_id: 2, sender: second.get_sender() };
+        second.subscribe_to_messages(&MessageFilter::new(), Box::new(echo));
+        let received = Arc::new(Mutex::new(Vec::new()));
+        first.subscribe_to_messages(&MessageFilter::new(),
+                                    Box::new(CollectingListener{ received: received.clone() }));
+        first.send_message(message_to(1, 2, 0));
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].message_type, MessageType::Pong);
+        assert_eq!(received[0].source, 2);
+    }
+
+    #[test]
+    fn test_tap_records_both_directions() {
+        let (mut first, mut second) = LoopbackTransportService::pair(1, 2);
+        assert!(first.get_tap().is_none());
+        let tap = TransportTap::new_shared(8, FLAG_TRANSPORT_TAP).unwrap();
+        first.set_tap(tap.clone());
+        first.send_message(message_to(1, 2, 0));
+        second.send_message(message_to(2, 1, 0));
+        let records = first.get_tap().unwrap().lock().unwrap().get_records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].direction, TapDirection::Outgoing);
+        assert_eq!(records[1].direction, TapDirection::Incoming);
+        assert_eq!(records[1].source, 2);
+    }
+
+    #[test]
+    fn test_rate_limiter_enforced_on_receive() {
+        let (mut first, mut second) = LoopbackTransportService::pair(1, 2);
+        let mut policy = RateLimitPolicy::new(QuotaAction::Drop);
+        policy.peer_limits = Some(QuotaLimits{
+            messages_per_second: 1,
+            message_burst: 2,
+            ..Default::default()
+        });
+        second.set_rate_limiter(RateLimiter::new_shared(policy));
+        let received = Arc::new(Mutex::new(Vec::new()));
+        second.subscribe_to_messages(&MessageFilter::new(),
+                                     Box::new(CollectingListener{ received: received.clone() }));
+        for _ in 0..5{
+            first.send_message(message_to(1, 2, 0));
+        }
+        assert_eq!(received.lock().unwrap().len(), 2);
+        let metrics = second.get_rate_limiter().unwrap().lock().unwrap().get_metrics();
+        assert_eq!(metrics.dropped, 3);
+        assert!(first.get_rate_limiter().is_none());
+    }
+
+    #[test]
+    fn test_signature_policy_enforced_on_receive() {
+        let (mut first, mut second) = LoopbackTransportService::pair(1, 2);
+        let policy = SignaturePolicy::new_shared(DEFAULT_SIGNATURE_AUDIT_CAPACITY);
+        policy.lock().unwrap().require_module(7).bind_peer_certificate(1, TEST_SIGNING_CERTIFICATE_SERIAL);
+        second.set_signature_policy(policy, Box::new(MockCertificateService::with_test_certificates()));
+        let received = Arc::new(Mutex::new(Vec::new()));
+        second.subscribe_to_messages(&MessageFilter::new(),
+                                     Box::new(CollectingListener{ received: received.clone() }));
+        first.send_message(message_to(1, 2, 7));
+        first.send_message(message_to(1, 2, 0));
+        let mut signed = message_to(1, 2, 7);
+        signed.sign_by(&test_certificates().signing).unwrap();
+        first.send_message(signed);
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].module_id, 0);
+        assert!(received[1].signature.is_some());
+        let audit = second.get_signature_policy().unwrap().lock().unwrap().get_audit_entries();
+        assert_eq!(audit.len(), 1);
+        assert_eq!(audit[0].reason, SignatureRejection::Unsigned);
+    }
+
+    #[test]
+    fn test_group_destination_expanded() {
+        let (mut first, mut second) = LoopbackTransportService::pair(1, 2);
+        let mut third = first.connect(3);
+        let signing = test_certificates().signing;
+        let mut groups = GroupServiceImpl::new("/tmp/loopback-groups.dat");
+        let operations = [GroupOperation::Create, GroupOperation::AddMember(2), GroupOperation::AddMember(3)];
+        for (index, operation) in operations.into_iter().enumerate(){
+            let mut record = GroupRecord::new(9, "branch-office-A", operation, &signing).unwrap();
+            // Signature is not checked here, records only have to be ordered
+            record.timestamp = index as u128 + 1;
+            groups.apply_verified_record(&record).unwrap();
+        }
+        let received = Arc::new(Mutex::new(Vec::new()));
+        for endpoint in [&mut second, &mut third]{
+            endpoint.subscribe_to_messages(&MessageFilter::new(),
+                                           Box::new(CollectingListener{ received: received.clone() }));
+        }
+        // Without group service the message has nowhere to go
+        first.send_message(message_to(1, get_group_address(9), 0));
+        assert!(received.lock().unwrap().is_empty());
+        first.set_group_service(Arc::new(Mutex::new(groups)));
+        first.send_message(message_to(1, get_group_address(9), 0));
+        let destinations: Vec<u128> = received.lock().unwrap().iter().map(|message| message.destination).collect();
+        assert_eq!(destinations, vec![2, 3]);
+        assert_eq!(first.sent_messages()[1].destination, get_group_address(9));
+    }
+
+    struct PoisonedListener{
+        poisoned: Arc<Mutex<bool>>,
+        received: Arc<Mutex<Vec<Message>>>,
+    }
+
+    impl TransportListener for PoisonedListener{
+        fn on_message(&mut self, message: Message) {
+            if *self.poisoned.lock().unwrap(){
+                panic!("can not handle message");
+            }
+            self.received.lock().unwrap().push(message);
+        }
+    }
+
+    #[test]
+    fn test_dead_letter_replay() {
+        let (mut first, mut second) = LoopbackTransportService::pair(1, 2);
+        let file = std::env::temp_dir().join(format!("milkyway-loopback-dlq-{}.dat", rand::random::<u64>()));
+        let queue = Arc::new(Mutex::new(DeadLetterQueue::new(file.to_str().unwrap(), 2)));
+        second.set_dead_letter_queue(queue.clone());
+        let poisoned = Arc::new(Mutex::new(true));
+        let received = Arc::new(Mutex::new(Vec::new()));
+        second.subscribe_to_messages(&MessageFilter::new(), Box::new(PoisonedListener{
+            poisoned: poisoned.clone(),
+            received: received.clone(),
+        }));
+        first.send_message(message_to(1, 2, 0));
+        assert!(received.lock().unwrap().is_empty());
+        assert_eq!(second.get_dead_letter_queue().unwrap().lock().unwrap().get_depth(), 1);
+
+        *poisoned.lock().unwrap() = false;
+        assert_eq!(second.replay_dead_letters(), 0);
+        queue.lock().unwrap().request_replay(None);
+        assert_eq!(second.replay_dead_letters(), 1);
+        assert_eq!(received.lock().unwrap().len(), 1);
+        assert_eq!(queue.lock().unwrap().get_depth(), 0);
+        std::fs::remove_file(file).unwrap();
+    }
+}
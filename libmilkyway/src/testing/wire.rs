@@ -0,0 +1,311 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use pqcrypto::kem::kyber1024;
+use pqcrypto::traits::kem::{PublicKey as KemPublicKey, SecretKey as KemSecretKey};
+use pqcrypto::traits::sign::{PublicKey as SignPublicKey, SecretKey as SignSecretKey};
+use pqcrypto_falcon::falcon1024;
+use crate::controllers::authorization::AuthorizationMessage;
+use crate::message::common::Message;
+use crate::message::types::MessageType;
+use crate::pki::certificate::{FLAG_CLIENT_CERT, FLAG_SIGN_CERTS, FLAG_SIGN_MESSAGES};
+use crate::pki::hash::HashType;
+use crate::pki::impls::certificates::falcon1024::{Falcon1024Certificate, Falcon1024RootCertificate};
+use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use crate::pki::impls::CryptoType;
+use crate::pki::impls::keys::falcon1024::{Falcon1024PublicKey, Falcon1024SecretKey};
+use crate::pki::signature::Signature;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::serialization::{MIN_COMPATIBLE_WIRE_FORMAT_VERSION, WIRE_FORMAT_VERSION};
+
+///
+/// Environment variable which makes check_fixtures_or_update write fixtures of current
+/// wire format version instead of checking them
+///
+pub const UPDATE_FIXTURES_VARIABLE: &str = "MILKYWAY_UPDATE_FIXTURES";
+
+///
+/// A value with fixed bytes on wire
+///
+pub struct GoldenVector{
+    /** Name of fixture file without extension **/
+    pub name: &'static str,
+    /** Serialized value for current wire format version **/
+    pub expected: fn() -> Serialized,
+    /** Deserializes and serializes again **/
+    pub round_trip: fn(&Serialized) -> Result<Serialized, SerializationError>,
+}
+
+///
+/// Problems found by check_fixtures
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum CompatibilityError{
+    /** Fixture of current version is not checked in **/
+    MissingFixture(PathBuf),
+    /** Serialization differs from fixture of current version without a version bump **/
+    FormatDrift(PathBuf),
+    /** Fixture of a compatible version can not be read anymore **/
+    Unreadable(PathBuf, SerializationError),
+    /** Fixture of a compatible version is read, but written differently **/
+    NotRoundTripping(PathBuf),
+}
+
+impl Display for CompatibilityError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompatibilityError::MissingFixture(path) => write!(f, "{}: fixture is missing", path.display()),
+            CompatibilityError::FormatDrift(path) => write!(f, "{}: wire format changed, bump WIRE_FORMAT_VERSION \
+                and write new fixtures", path.display()),
+            CompatibilityError::Unreadable(path, error) => write!(f, "{}: can not be deserialized: {:?}",
+                                                                  path.display(), error),
+            CompatibilityError::NotRoundTripping(path) => write!(f, "{}: is not serialized back to same bytes",
+                                                                 path.display()),
+        }
+    }
+}
+
+fn round_trip<T: Serializable + Deserializable>(serialized: &Serialized) -> Result<Serialized, SerializationError>{
+    let (value, size) = T::from_serialized(serialized)?;
+    if size != serialized.len(){
+        return Err(SerializationError::LengthError);
+    }
+    Ok(value.serialize())
+}
+
+///
+/// Deterministic bytes, so fixtures never depend on random keys
+///
+fn pattern(size: usize, seed: u8) -> Vec<u8>{
+    (0..size).map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed)).collect()
+}
+
+fn golden_signature(seed: u8) -> Signature{
+    Signature{
+        algorithm: HashType::None,
+        crypto_algorithm: CryptoType::Falcon1024,
+        serialized_signature: pattern(64, seed),
+        context: None,
+    }
+}
+
+fn golden_falcon1024_public_key(seed: u8) -> Falcon1024PublicKey{
+    Falcon1024PublicKey{
+        internal: falcon1024::PublicKey::from_bytes(&pattern(falcon1024::public_key_bytes(), seed)).unwrap(),
+    }
+}
+
+fn golden_falcon1024_certificate() -> Falcon1024Certificate{
+    Falcon1024Certificate{
+        serial_number: 1,
+        parent_serial_number: 0,
+        secret_key: Some(Falcon1024SecretKey{
+            internal: falcon1024::SecretKey::from_bytes(&pattern(falcon1024::secret_key_bytes(), 2)).unwrap(),
+        }),
+        public_key: golden_falcon1024_public_key(1),
+        signature: Some(golden_signature(3)),
+        name: "golden-signing".to_string(),
+        flags: FLAG_SIGN_MESSAGES | FLAG_SIGN_CERTS,
+        metadata: Default::default(),
+    }
+}
+
+fn golden_falcon1024_certificate_with_metadata() -> Falcon1024Certificate{
+    let mut certificate = golden_falcon1024_certificate();
+    certificate.metadata.set_description("Golden certificate").set_owner("golden-owner").set_tag("env", "test");
+    certificate
+}
+
+fn golden_falcon1024_root_certificate() -> Falcon1024RootCertificate{
+    Falcon1024RootCertificate{
+        secret_key: None,
+        public_key: golden_falcon1024_public_key(4),
+        name: "golden-root".to_string(),
+    }
+}
+
+fn golden_kyber1024_certificate() -> Kyber1024Certificate{
+    Kyber1024Certificate{
+        serial_number: 2,
+        parent_serial_number: 1,
+        secret_key: Some(kyber1024::SecretKey::from_bytes(&pattern(kyber1024::secret_key_bytes(), 5)).unwrap()),
+        public_key: kyber1024::PublicKey::from_bytes(&pattern(kyber1024::public_key_bytes(), 6)).unwrap(),
+        signature: Some(golden_signature(7)),
+        name: "golden-encryption".to_string(),
+        flags: FLAG_CLIENT_CERT,
+        metadata: Default::default(),
+    }
+}
+
+fn golden_message() -> Message{
+    Message{
+        id: 0x0102030405060708090a0b0c0d0e0f10,
+        timestamp: 1_700_000_000_000,
+        message_type: MessageType::LogMessage,
+        certificate_id: 1,
+        data: Some(pattern(16, 8)),
+        signature: Some(golden_signature(9)),
+        source: 2,
+        destination: 3,
+        module_id: 4,
+    }
+}
+
+fn golden_authorization_message() -> AuthorizationMessage{
+    let mut encryption_certificate = golden_kyber1024_certificate();
+    encryption_certificate.secret_key = None;
+    let mut signing_certificate = golden_falcon1024_certificate();
+    signing_certificate.secret_key = None;
+    AuthorizationMessage{
+        encryption_certificate,
+        signing_certificate: signing_certificate.clone(),
+        signing_chain: vec![signing_certificate],
+        timestamp: 1_700_000_000_001,
+        signature: Some(golden_signature(10)),
+    }
+}
+
+///
+/// Gets all golden vectors checked against fixtures
+///
+pub fn golden_vectors() -> Vec<GoldenVector>{
+    vec![
+        GoldenVector{
+            name: "message",
+            expected: || golden_message().serialize(),
+            round_trip: round_trip::<Message>,
+        },
+        GoldenVector{
+            name: "authorization_message",
+            expected: || golden_authorization_message().serialize(),
+            round_trip: round_trip::<AuthorizationMessage>,
+        },
+        GoldenVector{
+            name: "falcon1024_certificate",
+            expected: || golden_falcon1024_certificate().serialize(),
+            round_trip: round_trip::<Falcon1024Certificate>,
+        },
+        GoldenVector{
+            name: "falcon1024_certificate_with_metadata",
+            expected: || golden_falcon1024_certificate_with_metadata().serialize(),
+            round_trip: round_trip::<Falcon1024Certificate>,
+        },
+        GoldenVector{
+            name: "falcon1024_root_certificate",
+            expected: || golden_falcon1024_root_certificate().serialize(),
+            round_trip: round_trip::<Falcon1024RootCertificate>,
+        },
+        GoldenVector{
+            name: "kyber1024_certificate",
+            expected: || golden_kyber1024_certificate().serialize(),
+            round_trip: round_trip::<Kyber1024Certificate>,
+        },
+    ]
+}
+
+///
+/// Gets directory with fixtures of a wire format version
+///
+pub fn get_version_directory(fixtures: &Path, version: u32) -> PathBuf{
+    fixtures.join(format!("v{}", version))
+}
+
+///
+/// Writes fixtures of current wire format version
+///
+/// # Arguments
+/// * fixtures: &Path: directory with fixtures of all versions
+///
+pub fn write_fixtures(fixtures: &Path) -> std::io::Result<()>{
+    let directory = get_version_directory(fixtures, WIRE_FORMAT_VERSION);
+    std::fs::create_dir_all(&directory)?;
+    for vector in golden_vectors(){
+        std::fs::write(directory.join(format!("{}.bin", vector.name)), (vector.expected)())?;
+    }
+    Ok(())
+}
+
+///
+/// Checks that current serialization matches fixtures of current wire format version and
+/// that fixtures of all versions from MIN_COMPATIBLE_WIRE_FORMAT_VERSION are still read and
+/// written back byte-to-byte
+///
+/// # Arguments
+/// * fixtures: &Path: directory with fixtures of all versions
+///
+/// returns: Vec<CompatibilityError>: all problems found, empty if format is compatible
+///
+pub fn check_fixtures(fixtures: &Path) -> Vec<CompatibilityError>{
+    let mut errors = Vec::new();
+    for vector in golden_vectors(){
+        let path = get_version_directory(fixtures, WIRE_FORMAT_VERSION).join(format!("{}.bin", vector.name));
+        match std::fs::read(&path) {
+            Ok(fixture) if fixture != (vector.expected)() => errors.push(CompatibilityError::FormatDrift(path)),
+            Ok(_) => {}
+            Err(_) => errors.push(CompatibilityError::MissingFixture(path)),
+        }
+        for version in MIN_COMPATIBLE_WIRE_FORMAT_VERSION..=WIRE_FORMAT_VERSION{
+            let path = get_version_directory(fixtures, version).join(format!("{}.bin", vector.name));
+            // Types added in later versions have no fixtures in earlier ones
+            let fixture = match std::fs::read(&path) {
+                Ok(fixture) => fixture,
+                Err(_) => continue,
+            };
+            match (vector.round_trip)(&fixture) {
+                Ok(serialized) if serialized != fixture => errors.push(CompatibilityError::NotRoundTripping(path)),
+                Ok(_) => {}
+                Err(error) => errors.push(CompatibilityError::Unreadable(path, error)),
+            }
+        }
+    }
+    errors
+}
+
+///
+/// Checks fixtures, or writes them if UPDATE_FIXTURES_VARIABLE is set
+///
+/// # Panics
+/// * If wire format is not compatible with fixtures
+///
+pub fn check_fixtures_or_update(fixtures: &Path){
+    if std::env::var_os(UPDATE_FIXTURES_VARIABLE).is_some(){
+        write_fixtures(fixtures).expect("Can not write fixtures");
+        return;
+    }
+    let errors = check_fixtures(fixtures);
+    if !errors.is_empty(){
+        let errors: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+        panic!("Wire format is not compatible with fixtures:\n{}", errors.join("\n"));
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixtures_directory() -> PathBuf{
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join("wire")
+    }
+
+    #[test]
+    fn test_wire_format_matches_fixtures() {
+        check_fixtures_or_update(&fixtures_directory());
+    }
+
+    #[test]
+    fn test_drift_detected() {
+        let directory = std::env::temp_dir().join(format!("milkyway-wire-{}", rand::random::<u64>()));
+        write_fixtures(&directory).unwrap();
+        assert!(check_fixtures(&directory).is_empty());
+        let path = get_version_directory(&directory, WIRE_FORMAT_VERSION).join("message.bin");
+        let mut fixture = std::fs::read(&path).unwrap();
+        fixture.push(0);
+        std::fs::write(&path, fixture).unwrap();
+        let errors = check_fixtures(&directory);
+        assert!(errors.contains(&CompatibilityError::FormatDrift(path.clone())));
+        assert!(errors.contains(&CompatibilityError::Unreadable(path, SerializationError::LengthError)));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
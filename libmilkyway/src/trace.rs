@@ -0,0 +1,446 @@
+use std::fmt::{Display, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use once_cell::sync::Lazy;
+use crate::serialization::schema::json_string;
+
+///
+/// Count of spans OTLP exporter sends in one request
+///
+pub const OTLP_BATCH_SIZE: usize = 64;
+
+const OTLP_TIMEOUT: Duration = Duration::from_secs(5);
+
+///
+/// Identifies span so spans created in other tasks may refer to it as parent
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpanContext{
+    /** Shared by all spans of one trace, e.g. one connection with its handshake and messages **/
+    pub trace_id: u128,
+    pub span_id: u64,
+}
+
+///
+/// Finished span as passed to exporter
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpanRecord{
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub parent_id: Option<u64>,
+    pub name: String,
+    /** Correlation fields, e.g. connection_id, peer_id or message_id **/
+    pub fields: Vec<(String, String)>,
+    pub start_unix_nanos: u128,
+    pub duration_nanos: u128,
+    pub error: Option<String>,
+}
+
+///
+/// Receives finished spans
+///
+pub trait SpanExporter: Send{
+    fn export(&mut self, span: SpanRecord);
+
+    ///
+    /// Sends buffered spans
+    ///
+    fn flush(&mut self){}
+}
+
+static EXPORTER: Lazy<Mutex<Option<Box<dyn SpanExporter>>>> = Lazy::new(|| Mutex::new(None));
+
+///
+/// Sets exporter receiving all spans finished in this process. Spans are only recorded
+/// for log correlation while no exporter is set.
+///
+pub fn set_exporter(exporter: Box<dyn SpanExporter>){
+    *EXPORTER.lock().unwrap() = Some(exporter);
+}
+
+///
+/// Flushes and removes current exporter
+///
+pub fn remove_exporter() -> Option<Box<dyn SpanExporter>>{
+    let mut exporter = EXPORTER.lock().unwrap().take();
+    if let Some(exporter) = exporter.as_mut(){
+        exporter.flush();
+    }
+    exporter
+}
+
+///
+/// Flushes current exporter, e.g. before shutdown
+///
+pub fn flush_exporter(){
+    if let Some(exporter) = EXPORTER.lock().unwrap().as_mut(){
+        exporter.flush();
+    }
+}
+
+fn get_unix_nanos() -> u128{
+    SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_nanos()
+}
+
+///
+/// A timed operation with correlation fields. Span is finished and exported when dropped.
+///
+/// Spans are passed between tasks by their SpanContext, e.g. connection span is the parent
+/// of handshake and message spans of that connection. Span displays as its IDs and fields,
+/// so log lines may be correlated with exported spans:
+/// ```ignore
+/// log::warn!("{}: peer is not pinned", span);
+/// ```
+///
+pub struct Span{
+    context: SpanContext,
+    parent_id: Option<u64>,
+    name: &'static str,
+    fields: Vec<(&'static str, String)>,
+    start_unix_nanos: u128,
+    started: Instant,
+    error: Option<String>,
+}
+
+impl Span {
+    ///
+    /// Starts span of a new trace
+    ///
+    pub fn root(name: &'static str) -> Span{
+        Span::start(rand::random(), None, name)
+    }
+
+    ///
+    /// Starts span which is child of given one or root span if there is no parent
+    ///
+    pub fn child_of(parent: Option<SpanContext>, name: &'static str) -> Span{
+        match parent {
+            Some(parent) => Span::start(parent.trace_id, Some(parent.span_id), name),
+            None => Span::root(name),
+        }
+    }
+
+    ///
+    /// Starts child span of this one
+    ///
+    #[inline]
+    pub fn child(&self, name: &'static str) -> Span{
+        Span::child_of(Some(self.context), name)
+    }
+
+    fn start(trace_id: u128, parent_id: Option<u64>, name: &'static str) -> Span{
+        Span{
+            context: SpanContext{
+                trace_id,
+                span_id: rand::random(),
+            },
+            parent_id,
+            name,
+            fields: Vec::new(),
+            start_unix_nanos: get_unix_nanos(),
+            started: Instant::now(),
+            error: None,
+        }
+    }
+
+    ///
+    /// Builder-like function adding a field
+    ///
+    pub fn with_field<T: Display>(mut self, key: &'static str, value: T) -> Span{
+        self.record(key, value);
+        self
+    }
+
+    ///
+    /// Sets field, replacing previous value
+    ///
+    pub fn record<T: Display>(&mut self, key: &'static str, value: T) -> &mut Self{
+        let value = value.to_string();
+        match self.fields.iter_mut().find(|(field, _)| *field == key) {
+            Some(field) => field.1 = value,
+            None => self.fields.push((key, value)),
+        }
+        self
+    }
+
+    ///
+    /// Marks span as failed
+    ///
+    pub fn record_error<T: Display>(&mut self, error: T) -> &mut Self{
+        self.error = Some(error.to_string());
+        self
+    }
+
+    #[inline]
+    pub fn get_context(&self) -> SpanContext{
+        self.context
+    }
+
+    pub fn get_field(&self, key: &str) -> Option<&str>{
+        self.fields.iter().find(|(field, _)| *field == key).map(|(_, value)| value.as_str())
+    }
+
+    fn to_record(&self) -> SpanRecord{
+        SpanRecord{
+            trace_id: self.context.trace_id,
+            span_id: self.context.span_id,
+            parent_id: self.parent_id,
+            name: self.name.to_string(),
+            fields: self.fields.iter().map(|(key, value)| (key.to_string(), value.clone())).collect(),
+            start_unix_nanos: self.start_unix_nanos,
+            duration_nanos: self.started.elapsed().as_nanos(),
+            error: self.error.clone(),
+        }
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{} trace={:032x} span={:016x}", self.name, self.context.trace_id, self.context.span_id)?;
+        for (key, value) in self.fields.iter(){
+            write!(f, " {}={}", key, value)?;
+        }
+        write!(f, "]")
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Ok(mut exporter) = EXPORTER.lock(){
+            if let Some(exporter) = exporter.as_mut(){
+                exporter.export(self.to_record());
+            }
+        }
+    }
+}
+
+impl SpanRecord {
+    ///
+    /// Exports span as a single JSON object
+    ///
+    pub fn to_json(&self) -> String{
+        let fields: Vec<String> = self.fields.iter()
+            .map(|(key, value)| format!("{}:{}", json_string(key), json_string(value)))
+            .collect();
+        format!("{{\"trace_id\":\"{:032x}\",\"span_id\":\"{:016x}\",\"parent_id\":{},\"name\":{},\
+                 \"start_unix_nanos\":{},\"duration_nanos\":{},\"fields\":{{{}}},\"error\":{}}}",
+                self.trace_id, self.span_id,
+                self.parent_id.map_or("null".to_string(), |parent| format!("\"{:016x}\"", parent)),
+                json_string(&self.name), self.start_unix_nanos, self.duration_nanos, fields.join(","),
+                self.error.as_ref().map_or("null".to_string(), |error| json_string(error)))
+    }
+
+    ///
+    /// Exports span as OTLP span object
+    ///
+    fn to_otlp_json(&self) -> String{
+        let attributes: Vec<String> = self.fields.iter()
+            .map(|(key, value)| format!("{{\"key\":{},\"value\":{{\"stringValue\":{}}}}}", json_string(key),
+                                        json_string(value)))
+            .collect();
+        let parent = self.parent_id.map_or(String::new(), |parent| format!("\"parentSpanId\":\"{:016x}\",", parent));
+        // Status codes are 1 for OK and 2 for ERROR
+        let status = match &self.error {
+            Some(error) => format!("{{\"code\":2,\"message\":{}}}", json_string(error)),
+            None => "{\"code\":1}".to_string(),
+        };
+        format!("{{\"traceId\":\"{:032x}\",\"spanId\":\"{:016x}\",{}\"name\":{},\"kind\":1,\
+                 \"startTimeUnixNano\":\"{}\",\"endTimeUnixNano\":\"{}\",\"attributes\":[{}],\"status\":{}}}",
+                self.trace_id, self.span_id, parent, json_string(&self.name), self.start_unix_nanos,
+                self.start_unix_nanos + self.duration_nanos, attributes.join(","), status)
+    }
+}
+
+///
+/// Writes spans to file, one JSON object per line
+///
+pub struct FileSpanExporter{
+    file: File,
+}
+
+impl FileSpanExporter {
+    ///
+    /// Opens file for appending spans
+    ///
+    pub fn open(path: &Path) -> std::io::Result<FileSpanExporter>{
+        Ok(FileSpanExporter{
+            file: OpenOptions::new().create(true).append(true).open(path)?,
+        })
+    }
+}
+
+impl SpanExporter for FileSpanExporter {
+    fn export(&mut self, span: SpanRecord) {
+        if writeln!(self.file, "{}", span.to_json()).is_err(){
+            log::error!("Can not write span to file");
+        }
+    }
+
+    fn flush(&mut self) {
+        let _ = self.file.flush();
+    }
+}
+
+///
+/// Sends spans in batches to OTLP/HTTP collector(JSON encoding)
+///
+pub struct OtlpSpanExporter{
+    address: String,
+    host: String,
+    path: String,
+    service_name: String,
+    batch: Vec<SpanRecord>,
+}
+
+impl OtlpSpanExporter {
+    ///
+    /// Creates exporter
+    ///
+    /// # Arguments
+    /// * endpoint: &str: URL of collector, e.g. "http://127.0.0.1:4318/v1/traces". Only plain HTTP
+    ///   is supported, so collector should run locally.
+    /// * service_name: &str: name of service spans are attributed to
+    ///
+    pub fn new(endpoint: &str, service_name: &str) -> Result<OtlpSpanExporter, &'static str>{
+        let endpoint = endpoint.strip_prefix("http://").ok_or("Only http:// OTLP endpoints are supported")?;
+        let (host, path) = match endpoint.find('/') {
+            Some(index) => (&endpoint[..index], &endpoint[index..]),
+            None => (endpoint, "/v1/traces"),
+        };
+        if host.is_empty(){
+            return Err("OTLP endpoint has no host");
+        }
+        let address = if host.contains(':') { host.to_string() } else { format!("{}:80", host) };
+        Ok(OtlpSpanExporter{
+            address,
+            host: host.to_string(),
+            path: path.to_string(),
+            service_name: service_name.to_string(),
+            batch: Vec::new(),
+        })
+    }
+
+    ///
+    /// Builds OTLP export request from batch
+    ///
+    fn build_request(&self) -> String{
+        let spans: Vec<String> = self.batch.iter().map(|span| span.to_otlp_json()).collect();
+        format!("{{\"resourceSpans\":[{{\"resource\":{{\"attributes\":[{{\"key\":\"service.name\",\
+                 \"value\":{{\"stringValue\":{}}}}}]}},\"scopeSpans\":[{{\"scope\":{{\"name\":\"libmilkyway\"}},\
+                 \"spans\":[{}]}}]}}]}}", json_string(&self.service_name), spans.join(","))
+    }
+
+    fn post(&self, body: &str) -> std::io::Result<()>{
+        let address = self.address.to_socket_addrs()?.next()
+            .ok_or_else(|| std::io::Error::other("OTLP collector address is not resolved"))?;
+        let mut stream = TcpStream::connect_timeout(&address, OTLP_TIMEOUT)?;
+        stream.set_read_timeout(Some(OTLP_TIMEOUT))?;
+        stream.set_write_timeout(Some(OTLP_TIMEOUT))?;
+        write!(stream, "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+                        Content-Length: {}\r\nConnection: close\r\n\r\n{}", self.path, self.host, body.len(), body)?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        if !response.starts_with("HTTP/1.1 2") && !response.starts_with("HTTP/1.0 2"){
+            return Err(std::io::Error::other(response.lines().next().unwrap_or("").to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl SpanExporter for OtlpSpanExporter {
+    fn export(&mut self, span: SpanRecord) {
+        self.batch.push(span);
+        if self.batch.len() >= OTLP_BATCH_SIZE{
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.batch.is_empty(){
+            return;
+        }
+        let body = self.build_request();
+        if let Err(error) = self.post(&body){
+            log::error!("Can not send {} spans to OTLP collector: {}", self.batch.len(), error);
+        }
+        self.batch.clear();
+    }
+}
+
+impl Drop for OtlpSpanExporter {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+///
+/// Keeps spans in memory, e.g. for inspecting them in tests
+///
+#[derive(Clone, Default)]
+pub struct MemorySpanExporter{
+    spans: Arc<Mutex<Vec<SpanRecord>>>,
+}
+
+impl MemorySpanExporter {
+    pub fn new() -> MemorySpanExporter{
+        MemorySpanExporter::default()
+    }
+
+    ///
+    /// Gets exported spans of trace
+    ///
+    pub fn get_trace(&self, trace_id: u128) -> Vec<SpanRecord>{
+        self.spans.lock().unwrap().iter().filter(|span| span.trace_id == trace_id).cloned().collect()
+    }
+}
+
+impl SpanExporter for MemorySpanExporter {
+    fn export(&mut self, span: SpanRecord) {
+        self.spans.lock().unwrap().push(span);
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spans_exported() {
+        let exporter = MemorySpanExporter::new();
+        set_exporter(Box::new(exporter.clone()));
+        let mut connection = Span::root("connection").with_field("connection_id", 3);
+        let trace_id = connection.get_context().trace_id;
+        {
+            let mut handshake = Span::child_of(Some(connection.get_context()), "handshake");
+            handshake.record("peer_id", 5).record("peer_id", 6).record_error("rejected");
+            assert_eq!(handshake.get_field("peer_id"), Some("6"));
+            assert!(handshake.to_string().ends_with(" peer_id=6]"));
+        }
+        connection.record("peer_id", 6);
+        drop(connection);
+        let spans = exporter.get_trace(trace_id);
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].name, "handshake");
+        assert_eq!(spans[0].parent_id, Some(spans[1].span_id));
+        assert_eq!(spans[0].error, Some("rejected".to_string()));
+        assert_eq!(spans[1].fields, vec![("connection_id".to_string(), "3".to_string()),
+                                         ("peer_id".to_string(), "6".to_string())]);
+        assert!(spans[1].to_json().contains("\"fields\":{\"connection_id\":\"3\",\"peer_id\":\"6\"},\"error\":null"));
+        assert!(spans[0].to_otlp_json().contains("\"status\":{\"code\":2,\"message\":\"rejected\"}"));
+    }
+
+    #[test]
+    fn test_otlp_endpoint() {
+        let exporter = OtlpSpanExporter::new("http://collector:4318/v1/traces", "mway").unwrap();
+        assert_eq!(exporter.address, "collector:4318");
+        assert_eq!(exporter.path, "/v1/traces");
+        assert_eq!(OtlpSpanExporter::new("http://collector", "mway").unwrap().address, "collector:80");
+        assert!(OtlpSpanExporter::new("https://collector", "mway").is_err());
+    }
+}
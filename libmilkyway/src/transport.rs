@@ -2,8 +2,37 @@ pub mod crypto;
 pub mod async_stream;
 pub mod worker;
 pub mod handler;
+pub mod tap;
+pub mod ratelimit;
+pub mod signature;
+pub mod access;
+pub mod pinning;
+pub mod outbox;
+pub mod supervisor;
+pub mod shaping;
+pub mod stack;
+pub mod subscriptions;
+pub mod stream;
+pub mod compression;
+pub mod keepalive;
+pub mod faults;
+pub mod operator;
+pub mod deadletter;
+pub mod events;
+pub mod version;
+pub mod sequence;
+pub mod connector;
+pub mod checksum;
+pub mod identity;
+pub mod batch;
+pub mod handshake;
+pub mod stats;
+pub mod rawtap;
+pub mod session;
+pub mod router;
 mod impls;
 
+use std::fmt::{Display, Formatter};
 use crate::message::common::Message;
 use crate::serialization::deserializable::Deserializable;
 use crate::serialization::error::SerializationError;
@@ -13,6 +42,23 @@ use crate::transport::handler::TransportHandlerServiceBinder;
 /** This is a constant address for a main server/broker **/
 pub const TRANSPORT_TARGET_SERVER: u128 = 1;
 
+///
+/// Reasons message could not be sent
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SendError{
+    /** Sender has no way to reach host with given ID, e.g. host is not connected **/
+    Unreachable(u128),
+}
+
+impl Display for SendError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendError::Unreachable(host_id) => write!(f, "host {} is not reachable", host_id),
+        }
+    }
+}
+
 ///
 /// The extensions allow to transform/detransform data.
 /// Each Transport SHOULD NOT have more than one transformer.
@@ -39,6 +85,18 @@ pub trait TransportTransformer: Send + Sync{
     /// # Returns
     /// Transformed data
     fn transform(&self, data: &Serialized) -> Serialized;
+
+    ///
+    /// Checks whether transformer refuses to process any further data
+    /// (e.g. after a replay attack was detected). The connection using such
+    /// transformer MUST be closed.
+    ///
+    /// # Returns
+    /// true if session is terminated, false otherwise
+    #[inline]
+    fn is_terminated(&self) -> bool{
+        false
+    }
 }
 
 ///
@@ -52,6 +110,20 @@ pub trait TransportListener: Send + Sync{
     /// * message: Message: a message received
     /// 
     fn on_message(&mut self, message: Message);
+
+    ///
+    /// Handles message of exclusive subscription(see MessageFilter::set_exclusive)
+    ///
+    /// # Arguments
+    /// * message: Message: a message received
+    ///
+    /// returns: bool: whether message is consumed, consumed message is not delivered to
+    /// subscriptions of lower priority. By default every message is consumed.
+    ///
+    fn on_exclusive_message(&mut self, message: Message) -> bool{
+        self.on_message(message);
+        true
+    }
     
     ///
     /// Called whenever the listener is binded to handler
@@ -76,4 +148,33 @@ pub trait TransportSender: Send + Sync{
     /// * message: a message to send
     ///
     fn send_message(&mut self, message: Message);
+
+    ///
+    /// Sends a message and reports whether it could be handed over for delivery. MUST NOT
+    /// block thread/coroutine. Senders which know their destinations override it, by default
+    /// message is sent with send_message and considered sent.
+    ///
+    /// # Arguments
+    /// * message: a message to send
+    ///
+    /// returns: Result<(), SendError>: Ok if message was sent, error if it was dropped
+    ///
+    fn try_send_message(&mut self, message: Message) -> Result<(), SendError>{
+        self.send_message(message);
+        Ok(())
+    }
+
+    ///
+    /// Sends several messages at once. MUST NOT block thread/coroutine.
+    /// Senders override it to enqueue and flush messages in one operation, by default
+    /// messages are sent one by one.
+    ///
+    /// # Arguments
+    /// * messages: Vec<Message>: messages to send in order
+    ///
+    fn send_batch(&mut self, messages: Vec<Message>){
+        for message in messages{
+            self.send_message(message);
+        }
+    }
 }
\ No newline at end of file
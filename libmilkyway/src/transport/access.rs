@@ -0,0 +1,508 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use libmilkyway_derive::{Deserializable, EnumDeserializable, EnumSerializable, Serializable};
+use crate::get_timestamp_with_milliseconds;
+use crate::message::common::Message;
+use crate::pki::certificate::Certificate;
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::migration::{dump_versioned, load_versioned, VersionedStorage};
+use crate::serialization::serializable::{Serializable, Serialized};
+
+///
+/// Default amount of audit entries kept by access control
+///
+pub const DEFAULT_ACCESS_AUDIT_CAPACITY: usize = 256;
+
+///
+/// Identifies peer in allow and block lists
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum PeerSelector{
+    /** Fingerprint of signing or encryption certificate(see Certificate::get_fingerprint) **/
+    Fingerprint(String),
+    /** Serial of signing or encryption certificate **/
+    Serial(u128),
+    /** ID of peer on network **/
+    PeerId(u128),
+}
+
+impl Display for PeerSelector {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PeerSelector::Fingerprint(fingerprint) => write!(f, "fingerprint:{}", fingerprint),
+            PeerSelector::Serial(serial) => write!(f, "serial:{}", serial),
+            PeerSelector::PeerId(peer_id) => write!(f, "peer:{}", peer_id),
+        }
+    }
+}
+
+impl FromStr for PeerSelector {
+    type Err = &'static str;
+
+    ///
+    /// Parses selector in form of `<kind>:<value>`, where kind is fingerprint, serial or peer
+    ///
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, value) = s.split_once(':').ok_or("Selector must look like <kind>:<value>")?;
+        match kind {
+            "fingerprint" => {
+                if value.is_empty() || !value.chars().all(|c| c.is_ascii_hexdigit()){
+                    return Err("Fingerprint must be a hex string");
+                }
+                Ok(PeerSelector::Fingerprint(value.to_ascii_lowercase()))
+            }
+            "serial" => value.parse().map(PeerSelector::Serial).map_err(|_| "Serial must be a positive integer"),
+            "peer" => value.parse().map(PeerSelector::PeerId).map_err(|_| "Peer ID must be a positive integer"),
+            _ => Err("Kind of selector must be fingerprint, serial or peer"),
+        }
+    }
+}
+
+impl Serializable for PeerSelector {
+    fn serialize(&self) -> Serialized {
+        let mut result = Serialized::new();
+        match self {
+            PeerSelector::Fingerprint(fingerprint) => {
+                result.extend(0u8.serialize());
+                result.extend(fingerprint.serialize());
+            }
+            PeerSelector::Serial(serial) => {
+                result.extend(1u8.serialize());
+                result.extend(serial.serialize());
+            }
+            PeerSelector::PeerId(peer_id) => {
+                result.extend(2u8.serialize());
+                result.extend(peer_id.serialize());
+            }
+        }
+        result
+    }
+}
+
+impl Deserializable for PeerSelector {
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        if serialized.is_empty(){
+            return Err(SerializationError::LengthError);
+        }
+        let data = serialized[1..].to_vec();
+        let (selector, offset) = match serialized[0] {
+            0 => {
+                let (fingerprint, offset) = String::from_serialized(&data)?;
+                (PeerSelector::Fingerprint(fingerprint), offset)
+            }
+            1 => {
+                let (serial, offset) = u128::from_serialized(&data)?;
+                (PeerSelector::Serial(serial), offset)
+            }
+            2 => {
+                let (peer_id, offset) = u128::from_serialized(&data)?;
+                (PeerSelector::PeerId(peer_id), offset)
+            }
+            _ => return Err(SerializationError::InvalidDataError("Unknown kind of peer selector")),
+        };
+        Ok((selector, offset + 1))
+    }
+}
+
+///
+/// List a selector is added to
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccessRule{
+    Allow,
+    Block,
+}
+
+///
+/// Moment at which access is checked
+///
+#[derive(Clone, Copy, Debug, PartialEq, EnumSerializable, EnumDeserializable)]
+pub enum AccessStage{
+    /** Peer connects, only its ID is known **/
+    Connection,
+    /** Certificates of peer are verified during handshake **/
+    Authorization,
+    /** Message is received from already connected peer **/
+    Message,
+}
+
+impl Display for AccessStage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccessStage::Connection => write!(f, "connection"),
+            AccessStage::Authorization => write!(f, "authorization"),
+            AccessStage::Message => write!(f, "message"),
+        }
+    }
+}
+
+///
+/// Reason why peer was denied
+///
+#[derive(Clone, Copy, Debug, PartialEq, EnumSerializable, EnumDeserializable)]
+pub enum AccessDenial{
+    /** Peer matches block list **/
+    Blocked,
+    /** Allow list is not empty and peer does not match it **/
+    NotAllowed,
+}
+
+impl Display for AccessDenial {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccessDenial::Blocked => write!(f, "blocked"),
+            AccessDenial::NotAllowed => write!(f, "not allowed"),
+        }
+    }
+}
+
+///
+/// What is known about peer at the moment of check
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PeerIdentity{
+    pub peer_id: Option<u128>,
+    pub serials: Vec<u128>,
+    pub fingerprints: Vec<String>,
+}
+
+impl PeerIdentity {
+    ///
+    /// Identity of peer known only by its ID
+    ///
+    pub fn from_peer(peer_id: u128) -> PeerIdentity{
+        PeerIdentity{
+            peer_id: Some(peer_id),
+            ..Default::default()
+        }
+    }
+
+    ///
+    /// Identity of peer authorized with certificates
+    ///
+    /// # Arguments
+    /// * peer_id: Option<u128>: ID of peer if known
+    /// * signing_certificate: &Falcon1024Certificate: verified signing certificate of peer
+    /// * encryption_certificate: &Kyber1024Certificate: verified encryption certificate of peer
+    ///
+    pub fn from_certificates(peer_id: Option<u128>, signing_certificate: &Falcon1024Certificate,
+                             encryption_certificate: &Kyber1024Certificate) -> PeerIdentity{
+        PeerIdentity{
+            peer_id,
+            serials: vec![signing_certificate.get_serial(), encryption_certificate.get_serial()],
+            fingerprints: vec![signing_certificate.get_fingerprint(), encryption_certificate.get_fingerprint()],
+        }
+    }
+
+    ///
+    /// Checks whether identity has data selector can be compared to
+    ///
+    fn can_match(&self, selector: &PeerSelector) -> bool{
+        match selector {
+            PeerSelector::Fingerprint(_) => !self.fingerprints.is_empty(),
+            PeerSelector::Serial(_) => !self.serials.is_empty(),
+            PeerSelector::PeerId(_) => self.peer_id.is_some(),
+        }
+    }
+
+    fn matches(&self, selector: &PeerSelector) -> bool{
+        match selector {
+            PeerSelector::Fingerprint(fingerprint) => self.fingerprints.contains(fingerprint),
+            PeerSelector::Serial(serial) => self.serials.contains(serial),
+            PeerSelector::PeerId(peer_id) => self.peer_id == Some(*peer_id),
+        }
+    }
+}
+
+///
+/// Record about denied peer
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable)]
+pub struct AccessAuditEntry{
+    /** When peer was denied **/
+    pub timestamp: u128,
+    pub stage: AccessStage,
+    pub peer_id: Option<u128>,
+    pub serials: Vec<u128>,
+    pub reason: AccessDenial,
+    /** Entry of block list peer matched **/
+    pub selector: Option<PeerSelector>,
+}
+
+///
+/// Allow and block lists of peers stored in a file.
+///
+/// Block list takes precedence: peer matching any of its entries is denied. If allow list is
+/// not empty, peer must match one of its entries. As not everything is known about peer at
+/// every stage(e.g. certificates are unknown before handshake), allow list denies peer only
+/// at stages where all its entries can be evaluated. Denials are logged and kept in audit.
+///
+#[derive(Serializable, Deserializable)]
+pub struct AccessControl{
+    storage_file_name: String,
+    allowed: Vec<PeerSelector>,
+    blocked: Vec<PeerSelector>,
+    audit_capacity: usize,
+    audit: Vec<AccessAuditEntry>,
+}
+
+///
+/// Access control shared between transport, authorization and CLI
+///
+pub type SharedAccessControl = Arc<Mutex<AccessControl>>;
+
+impl AccessControl {
+    ///
+    /// Creates access control with empty lists storing data in provided file
+    ///
+    pub fn new(filename: &str) -> AccessControl{
+        AccessControl{
+            storage_file_name: filename.to_string(),
+            allowed: Vec::new(),
+            blocked: Vec::new(),
+            audit_capacity: DEFAULT_ACCESS_AUDIT_CAPACITY,
+            audit: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn load_from_file(file: &str) -> AccessControl{
+        let mut access = load_versioned::<AccessControl>(Path::new(file)).expect("Failed to load access lists");
+        access.storage_file_name = file.to_string();
+        access
+    }
+
+    ///
+    /// Loads access control from file or creates empty one if file does not exist
+    ///
+    pub fn open_shared(file: &str) -> SharedAccessControl{
+        let access = if Path::new(file).exists(){
+            AccessControl::load_from_file(file)
+        } else {
+            AccessControl::new(file)
+        };
+        Arc::new(Mutex::new(access))
+    }
+
+    ///
+    /// Sets how many audit entries to keep, older entries are discarded
+    ///
+    /// # Panics
+    /// * If capacity is zero
+    ///
+    pub fn set_audit_capacity(&mut self, capacity: usize) -> &mut AccessControl{
+        if capacity == 0{
+            panic!("Capacity of access audit must be positive");
+        }
+        self.audit_capacity = capacity;
+        self.trim_audit();
+        self
+    }
+
+    ///
+    /// Adds selector to a list. Selector is removed from the other list, so it is never both
+    /// allowed and blocked.
+    ///
+    /// returns: bool: false if selector already is in list
+    ///
+    pub fn add(&mut self, rule: AccessRule, selector: PeerSelector) -> bool{
+        let (list, other) = match rule {
+            AccessRule::Allow => (&mut self.allowed, &mut self.blocked),
+            AccessRule::Block => (&mut self.blocked, &mut self.allowed),
+        };
+        other.retain(|entry| *entry != selector);
+        if list.contains(&selector){
+            return false;
+        }
+        list.push(selector);
+        true
+    }
+
+    ///
+    /// Removes selector from both lists
+    ///
+    /// returns: bool: whether selector was in any list
+    ///
+    pub fn remove(&mut self, selector: &PeerSelector) -> bool{
+        let count = self.allowed.len() + self.blocked.len();
+        self.allowed.retain(|entry| entry != selector);
+        self.blocked.retain(|entry| entry != selector);
+        count != self.allowed.len() + self.blocked.len()
+    }
+
+    #[inline]
+    pub fn get_allowed(&self) -> &[PeerSelector]{
+        &self.allowed
+    }
+
+    #[inline]
+    pub fn get_blocked(&self) -> &[PeerSelector]{
+        &self.blocked
+    }
+
+    ///
+    /// Gets audit entries, oldest first
+    ///
+    #[inline]
+    pub fn get_audit(&self) -> &[AccessAuditEntry]{
+        &self.audit
+    }
+
+    #[inline]
+    pub fn clear_audit(&mut self){
+        self.audit.clear();
+    }
+
+    fn trim_audit(&mut self){
+        if self.audit.len() > self.audit_capacity{
+            let excess = self.audit.len() - self.audit_capacity;
+            self.audit.drain(..excess);
+        }
+    }
+
+    fn find_denial(&self, identity: &PeerIdentity) -> Option<(AccessDenial, Option<PeerSelector>)>{
+        if let Some(selector) = self.blocked.iter().find(|selector| identity.matches(selector)){
+            return Some((AccessDenial::Blocked, Some(selector.clone())));
+        }
+        if self.allowed.is_empty() || !self.allowed.iter().all(|selector| identity.can_match(selector)){
+            return None;
+        }
+        if self.allowed.iter().any(|selector| identity.matches(selector)){
+            return None;
+        }
+        Some((AccessDenial::NotAllowed, None))
+    }
+
+    ///
+    /// Checks peer against lists, denial is logged and recorded to audit
+    ///
+    /// # Arguments
+    /// * stage: AccessStage: moment of check
+    /// * identity: &PeerIdentity: what is known about peer
+    ///
+    /// returns: bool: true if peer is allowed
+    ///
+    pub fn check(&mut self, stage: AccessStage, identity: &PeerIdentity) -> bool{
+        let (reason, selector) = match self.find_denial(identity) {
+            Some(denial) => denial,
+            None => return true,
+        };
+        log::warn!("Peer {:?} with certificates {:?} is {} at {}", identity.peer_id, identity.serials,
+            reason, stage);
+        self.audit.push(AccessAuditEntry{
+            timestamp: get_timestamp_with_milliseconds(),
+            stage,
+            peer_id: identity.peer_id,
+            serials: identity.serials.clone(),
+            reason,
+            selector,
+        });
+        self.trim_audit();
+        false
+    }
+
+    ///
+    /// Checks peer before handshake
+    ///
+    #[inline]
+    pub fn check_connection(&mut self, peer_id: u128) -> bool{
+        self.check(AccessStage::Connection, &PeerIdentity::from_peer(peer_id))
+    }
+
+    ///
+    /// Checks peer once its certificates are verified
+    ///
+    #[inline]
+    pub fn check_certificates(&mut self, peer_id: Option<u128>, signing_certificate: &Falcon1024Certificate,
+                              encryption_certificate: &Kyber1024Certificate) -> bool{
+        self.check(AccessStage::Authorization,
+                   &PeerIdentity::from_certificates(peer_id, signing_certificate, encryption_certificate))
+    }
+
+    ///
+    /// Checks source of received message, so peers blocked after handshake are cut off
+    /// immediately. Serial is taken into account only for signed messages.
+    ///
+    pub fn check_message(&mut self, message: &Message) -> bool{
+        let mut identity = PeerIdentity::from_peer(message.source);
+        if message.signature.is_some(){
+            identity.serials.push(message.get_signer_serial());
+        }
+        self.check(AccessStage::Message, &identity)
+    }
+
+    ///
+    /// Saves lists and audit to storage
+    ///
+    #[inline]
+    pub fn commit(&mut self){
+        if dump_versioned(self, &self.storage_file_name).is_err(){
+            log::error!("Failed to save access lists to {}", self.storage_file_name);
+        }
+    }
+}
+
+impl VersionedStorage for AccessControl {
+    const STORE_NAME: &'static str = "access";
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::certificate::test_certificates;
+
+    #[test]
+    fn test_block_and_allow_lists() {
+        let certificates = test_certificates();
+        let mut access = AccessControl::new("/tmp/access.dat");
+        assert!(access.check_connection(5));
+        access.add(AccessRule::Block, PeerSelector::PeerId(5));
+        access.add(AccessRule::Allow, PeerSelector::Fingerprint(certificates.signing.get_fingerprint()));
+        assert!(!access.check_connection(5));
+        // Fingerprint can not be evaluated before handshake
+        assert!(access.check_connection(6));
+        assert!(access.check_certificates(Some(6), &certificates.signing, &certificates.encryption));
+        access.add(AccessRule::Block, PeerSelector::Serial(certificates.signing.get_serial()));
+        assert!(!access.check_certificates(Some(6), &certificates.signing, &certificates.encryption));
+        let mut message = Message::new();
+        message.source = 6;
+        assert!(access.check_message(&message));
+        access.remove(&PeerSelector::Serial(certificates.signing.get_serial()));
+        access.add(AccessRule::Allow, PeerSelector::PeerId(7));
+        access.remove(&PeerSelector::Fingerprint(certificates.signing.get_fingerprint()));
+        assert!(!access.check_message(&message));
+        let audit = access.get_audit();
+        assert_eq!(audit.len(), 3);
+        assert_eq!(audit[0].selector, Some(PeerSelector::PeerId(5)));
+        assert_eq!((audit[1].stage, audit[1].reason), (AccessStage::Authorization, AccessDenial::Blocked));
+        assert_eq!((audit[2].stage, audit[2].reason), (AccessStage::Message, AccessDenial::NotAllowed));
+    }
+
+    #[test]
+    fn test_persistence() {
+        let file = std::env::temp_dir().join(format!("milkyway-access-{}.dat", rand::random::<u64>()));
+        let file = file.to_str().unwrap();
+        let shared = AccessControl::open_shared(file);
+        {
+            let mut access = shared.lock().unwrap();
+            assert!(access.add(AccessRule::Block, "serial:42".parse().unwrap()));
+            assert!(!access.add(AccessRule::Block, PeerSelector::Serial(42)));
+            access.add(AccessRule::Allow, "fingerprint:AB01".parse().unwrap());
+            access.check_connection(1);
+            access.commit();
+        }
+        let loaded = AccessControl::load_from_file(file);
+        assert_eq!(loaded.get_blocked(), &[PeerSelector::Serial(42)]);
+        assert_eq!(loaded.get_allowed(), &[PeerSelector::Fingerprint("ab01".to_string())]);
+        assert!(loaded.get_audit().is_empty());
+        assert_eq!("peer".parse::<PeerSelector>().err(), Some("Selector must look like <kind>:<value>"));
+        std::fs::remove_file(file).unwrap();
+    }
+}
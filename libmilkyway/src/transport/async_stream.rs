@@ -1,16 +1,101 @@
+use std::collections::VecDeque;
 use std::mem::size_of;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use crate::serialization::deserializable::Deserializable;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, Empty, Join, ReadHalf, Sink, WriteHalf};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use crate::message::common::Message;
+use crate::serialization::deserializable::{Deserializable, ParsingMode};
 use crate::serialization::serializable::{Serializable, Serialized};
 use crate::tokio::tokio_timeout;
+use crate::trace::{Span, SpanContext};
+use crate::transport::batch::{decode_batch_frame, encode_batch_frame, supports_batch_frames, MAX_BATCH_FRAME_MESSAGES};
+use crate::transport::keepalive::{KeepAlivePolicy, SharedConnectionReaper, KEEPALIVE_PROBE, KEEPALIVE_REPLY};
+use crate::transport::outbox::SharedOutbox;
+use crate::transport::deadletter::SharedDeadLetterQueue;
+use crate::transport::events::{DisconnectReason, SharedConnectionEvents};
+use crate::transport::handshake::{HandshakeError, HandshakeStage, HandshakeTimeouts, SharedHandshakeMetrics};
+use crate::transport::shaping::ConnectionShaper;
+use crate::transport::stack::{TransformerNegotiationError, TransformerStack, TransformerStackDescriptor};
+use crate::transport::stats::{FrameDirection, SharedFrameStats};
+use crate::transport::rawtap::SharedRawFrameTap;
+use crate::transport::version::{VersionHello, VersionNegotiationError, VersionPolicy};
 use crate::transport::TransportTransformer;
 
+///
+/// Reasons receiving of frame produced no data
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReceiveError{
+    /** Nothing arrived within timeout, connection is intact **/
+    Idle,
+    /** Connection is closed, failed or frame was cut in the middle **/
+    Disconnected,
+    /** Frame was received, but transformers rejected it **/
+    Rejected,
+    /** Peer announced frame longer than maximum frame size, connection must be closed **/
+    FrameTooLarge(usize),
+}
+
+///
+/// Default maximal size of received frame, bytes
+///
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+///
+/// Receiving half of split transport(see TokioStreamTransport::into_split)
+///
+pub type TransportReadHalf<T> = TokioStreamTransport<Join<ReadHalf<T>, Sink>>;
+
+///
+/// Sending half of split transport(see TokioStreamTransport::into_split)
+///
+pub type TransportWriteHalf<T> = TokioStreamTransport<Join<Empty, WriteHalf<T>>>;
+
+/* Connection IDs are unique within process */
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
 ///
 /// A transport over a tokio stream.
 ///
+/// Each transport records a `connection` span with connection ID, which is the parent of spans
+/// of transformer negotiation and sent messages and is exported once transport is dropped.
+///
 pub struct TokioStreamTransport<T: AsyncReadExt + AsyncWriteExt + Sync + Send + Unpin>{
-    stream: T,
-    transformers: Vec<Box<dyn TransportTransformer>>,
+    /** Taken only when transport is split **/
+    stream: Option<T>,
+    /** Shared by halves of split transport **/
+    transformers: Vec<Arc<dyn TransportTransformer>>,
+    /** Names of transformers frame statistics are counted under **/
+    transformer_names: Vec<String>,
+    shaper: Option<ConnectionShaper>,
+    outbox: Option<SharedOutbox>,
+    reaper: Option<SharedConnectionReaper>,
+    dead_letters: Option<SharedDeadLetterQueue>,
+    events: Option<SharedConnectionEvents>,
+    /** Reported in Disconnected event once transport is dropped **/
+    disconnect_reason: DisconnectReason,
+    connection_id: u64,
+    /** ID of peer on the other side, 0 until it is known **/
+    peer_id: u128,
+    /** Protocol version agreed by negotiate_version **/
+    protocol_version: Option<u32>,
+    /** Messages of received batch frame not yet returned by receive_message **/
+    received: VecDeque<Message>,
+    /** Stages of handshake are not limited unless timeouts are set **/
+    handshake_timeouts: Option<HandshakeTimeouts>,
+    handshake_metrics: Option<SharedHandshakeMetrics>,
+    frame_stats: Option<SharedFrameStats>,
+    raw_frame_tap: Option<SharedRawFrameTap>,
+    /** Whether received frames longer than their messages are rejected **/
+    parsing_mode: ParsingMode,
+    /** Frames announced longer than this are rejected before memory is allocated for them **/
+    max_frame_size: usize,
+    /** Receiving half passes keep-alive frames to sending half instead of writing them **/
+    control: Option<UnboundedSender<Serialized>>,
+    control_frames: Option<UnboundedReceiver<Serialized>>,
+    span: Span,
 }
 
 impl<T: AsyncReadExt + AsyncWriteExt + Sync + Send + Unpin> TokioStreamTransport<T> {
@@ -18,24 +103,214 @@ impl<T: AsyncReadExt + AsyncWriteExt + Sync + Send + Unpin> TokioStreamTransport
     /// Creates a transport from tokio stream
     ///
     pub fn from_stream(stream: T) -> TokioStreamTransport<T>{
+        let connection_id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
         TokioStreamTransport {
-            stream,
+            stream: Some(stream),
             transformers: vec![],
+            transformer_names: vec![],
+            shaper: None,
+            outbox: None,
+            reaper: None,
+            dead_letters: None,
+            events: None,
+            disconnect_reason: DisconnectReason::Closed,
+            connection_id,
+            peer_id: 0,
+            protocol_version: None,
+            received: VecDeque::new(),
+            handshake_timeouts: None,
+            handshake_metrics: None,
+            frame_stats: None,
+            raw_frame_tap: None,
+            parsing_mode: ParsingMode::default(),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            control: None,
+            control_frames: None,
+            span: Span::root("connection").with_field("connection_id", connection_id),
         }
     }
 
+    #[inline]
+    fn get_stream(&mut self) -> &mut T{
+        self.stream.as_mut().expect("Stream of split transport is taken by its halves")
+    }
+
+    #[inline]
+    pub fn get_connection_id(&self) -> u64{
+        self.connection_id
+    }
+
+    ///
+    /// Records ID of peer on the other side once it is known, e.g. after authorization
+    ///
+    pub fn set_peer_id(&mut self, peer_id: u128){
+        self.peer_id = peer_id;
+        self.span.record("peer_id", peer_id);
+    }
+
+    ///
+    /// Records authorized peer and reports it to connection events
+    ///
+    /// # Arguments
+    /// * peer_id: u128: ID of peer
+    /// * certificates: Vec<u128>: serials of certificates peer presented, leaf last
+    ///
+    pub fn set_authorized(&mut self, peer_id: u128, certificates: Vec<u128>){
+        self.set_peer_id(peer_id);
+        if let Some(events) = &self.events{
+            events.lock().unwrap().on_authorized(self.connection_id, peer_id, certificates);
+        }
+    }
+
+    ///
+    /// Sets stream of connection events and reports connection as established. Disconnection
+    /// is reported once transport is dropped.
+    ///
+    /// # Arguments
+    /// * events: SharedConnectionEvents: events of host
+    /// * endpoint: &str: address of the other side
+    ///
+    pub fn set_connection_events(&mut self, events: SharedConnectionEvents, endpoint: &str){
+        events.lock().unwrap().on_connected(self.connection_id, endpoint);
+        self.events = Some(events);
+    }
+
+    ///
+    /// Sets reason reported once connection is closed, e.g. when handler closes it after
+    /// failed authorization. Timeouts and terminated sessions are recorded by transport itself.
+    ///
+    pub fn set_disconnect_reason(&mut self, reason: DisconnectReason){
+        self.disconnect_reason = reason;
+    }
+
+    ///
+    /// Gets span of connection, e.g. to make handshake spans its children
+    ///
+    #[inline]
+    pub fn get_span_context(&self) -> SpanContext{
+        self.span.get_context()
+    }
+
+    ///
+    /// Sets shaper pacing sent frames within bandwidth caps of connection
+    ///
+    /// # Arguments
+    /// * shaper: ConnectionShaper: shaper of this connection
+    ///
+    pub fn set_shaper(&mut self, shaper: ConnectionShaper){
+        self.shaper = Some(shaper);
+    }
+
+    ///
+    /// Sets outbox which durable messages are acknowledged in once written to stream
+    ///
+    pub fn set_outbox(&mut self, outbox: SharedOutbox){
+        self.outbox = Some(outbox);
+    }
+
+    ///
+    /// Registers connection in reaper, which is told about received frames and about closing
+    /// of connection once transport is dropped
+    ///
+    pub fn set_reaper(&mut self, reaper: SharedConnectionReaper){
+        reaper.lock().unwrap().register(self.connection_id as u128);
+        self.reaper = Some(reaper);
+    }
+
+    ///
+    /// Sets queue which received frames that are not messages are moved to
+    ///
+    pub fn set_dead_letter_queue(&mut self, queue: SharedDeadLetterQueue){
+        self.dead_letters = Some(queue);
+    }
+
+    ///
+    /// Sets timeouts of handshake stages, peer which stalls longer in any stage fails handshake
+    ///
+    pub fn set_handshake_timeouts(&mut self, timeouts: HandshakeTimeouts){
+        self.handshake_timeouts = Some(timeouts);
+    }
+
+    ///
+    /// Sets metrics which expired handshake stages are counted in
+    ///
+    pub fn set_handshake_metrics(&mut self, metrics: SharedHandshakeMetrics){
+        self.handshake_metrics = Some(metrics);
+    }
+
+    ///
+    /// Sets statistics which frames and invocations of transformers are counted in while
+    /// collection is enabled
+    ///
+    pub fn set_frame_stats(&mut self, stats: SharedFrameStats){
+        self.frame_stats = Some(stats);
+    }
+
+    ///
+    /// Sets tap which copies of frames are passed to as they are on wire
+    ///
+    pub fn set_raw_frame_tap(&mut self, tap: SharedRawFrameTap){
+        self.raw_frame_tap = Some(tap);
+    }
+
+    // Passes copy of frame to raw frame tap if anybody is subscribed to it
+    fn tap_raw(&self, direction: FrameDirection, data: &Serialized){
+        if let Some(tap) = &self.raw_frame_tap{
+            tap.publish(direction, self.connection_id, self.peer_id, data);
+        }
+    }
+
+    // Start time of measured frame or transformer, None unless frame statistics are collected
+    #[inline]
+    fn start_measure(&self) -> Option<Instant>{
+        self.frame_stats.as_ref().and_then(|stats| stats.start())
+    }
+
+    // Counts frame which passed send or receive path if it was measured
+    fn record_frame(&self, direction: FrameDirection, bytes_in: usize, bytes_out: Option<usize>, started: Option<Instant>){
+        if let (Some(stats), Some(started)) = (&self.frame_stats, started){
+            stats.record_frame(direction, bytes_in, bytes_out, started);
+        }
+    }
+
+    ///
+    /// Sets how received messages are parsed. In strict mode frames with bytes after message
+    /// are moved to dead-letter queue, so framing bugs are not masked.
+    ///
+    pub fn set_parsing_mode(&mut self, mode: ParsingMode){
+        self.parsing_mode = mode;
+    }
+
+    ///
+    /// Sets maximal size of received frame, see DEFAULT_MAX_FRAME_SIZE. Peer announcing
+    /// longer frame is disconnected.
+    ///
+    pub fn set_max_frame_size(&mut self, size: usize){
+        self.max_frame_size = size;
+    }
+
     pub fn apply_transform(&self, mut data: Serialized) -> Serialized{
-        for transformer in &self.transformers{
+        for (transformer, name) in self.transformers.iter().zip(self.transformer_names.iter()){
+            let started = self.start_measure();
+            let bytes_in = data.len();
             data = transformer.transform(&data);
+            if let (Some(stats), Some(started)) = (&self.frame_stats, started){
+                stats.record_transformer(name, FrameDirection::Send, bytes_in, Some(data.len()), started);
+            }
         }
         data
     }
 
     pub fn apply_detransform(&self, mut data: Serialized) -> Option<Serialized>{
-        for transformer in self.transformers.iter().rev(){
+        for (transformer, name) in self.transformers.iter().zip(self.transformer_names.iter()).rev(){
+            let started = self.start_measure();
             let data_result = transformer.detransform(&data);
+            if let (Some(stats), Some(started)) = (&self.frame_stats, started){
+                stats.record_transformer(name, FrameDirection::Receive, data.len(),
+                                         data_result.as_ref().ok().map(|data| data.len()), started);
+            }
             if data_result.is_err(){
-                log::error!("Can not detransform data: {:?}", 
+                log::error!("{}: Can not detransform data: {:?}", self.span,
                     data_result.err().unwrap());
                 return None;
             }
@@ -46,63 +321,557 @@ impl<T: AsyncReadExt + AsyncWriteExt + Sync + Send + Unpin> TokioStreamTransport
     
     #[inline]
     pub async fn send_raw(&mut self, data: Serialized) -> Result<usize, tokio::io::Error> {
+        if self.is_terminated(){
+            return Err(tokio::io::Error::new(tokio::io::ErrorKind::ConnectionAborted, "session is terminated"));
+        }
+        if let Some(control) = &self.control{
+            let size = data.len();
+            return control.send(data).map(|_| size)
+                .map_err(|_| tokio::io::Error::new(tokio::io::ErrorKind::BrokenPipe, "sending half is closed"));
+        }
+        let started = self.start_measure();
+        let bytes_in = data.len();
         let data = self.apply_transform(data);
+        let result = self.write_frame(&data).await;
+        if result.is_ok(){
+            self.tap_raw(FrameDirection::Send, &data);
+        }
+        self.record_frame(FrameDirection::Send, bytes_in, result.as_ref().ok().copied(), started);
+        result
+    }
+
+    // Paces and writes transformed frame prefixed with its size
+    async fn write_frame(&mut self, data: &Serialized) -> Result<usize, tokio::io::Error> {
         let size = data.len();
-        let status = self.stream.write(&size.serialize()).await;
-        if status.is_err(){
-            return status;
+        if let Some(shaper) = &self.shaper{
+            shaper.pace((size_of::<usize>() + size) as u64).await;
         }
-        self.stream.write(&data).await
+        // Frame is written whole, stream may accept only part of buffer in one write
+        self.get_stream().write_all(&size.serialize()).await?;
+        self.get_stream().write_all(data).await?;
+        Ok(size)
     }
 
-    pub async fn receive_raw(&mut self, timeout: Option<u64>) -> Option<Serialized> {
-        let mut data_size_buf: Serialized = Serialized::with_capacity(size_of::<usize>());
-        for _ in 0..size_of::<usize>(){
-            data_size_buf.push(0);
+    ///
+    /// Sends message and acknowledges it in outbox if it was written
+    ///
+    /// returns: bool: whether message was written to stream
+    ///
+    pub async fn send_message(&mut self, message: &Message) -> bool {
+        let mut span = self.span.child("send_message")
+            .with_field("message_id", message.id)
+            .with_field("destination", message.destination);
+        if let Err(error) = self.send_raw(message.serialize()).await{
+            span.record_error(error);
+            return false;
         }
-        let result = tokio_timeout(timeout, self.stream.read(&mut data_size_buf)).await;
-        //println!("data_size_buf={:?}", data_size_buf);
-        if result.is_none(){
-            return None;
+        if let Some(outbox) = &self.outbox{
+            outbox.lock().unwrap().acknowledge(message.id);
         }
-        let result = result.unwrap();
-        if result.is_err(){
-            return None;
+        true
+    }
+
+    ///
+    /// Sends several messages and acknowledges them in outbox once written. If peer supports
+    /// batch frames(see transport::batch) messages are coalesced into as few frames as
+    /// possible, otherwise they are sent one per frame.
+    ///
+    /// # Arguments
+    /// * messages: &[Message]: messages to send in order
+    ///
+    /// returns: usize: count of messages written to stream, sending stops on first failed write
+    ///
+    pub async fn send_messages(&mut self, messages: &[Message]) -> usize {
+        let mut sent = 0;
+        if messages.len() < 2 || !supports_batch_frames(self.protocol_version){
+            for message in messages{
+                if !self.send_message(message).await{
+                    break;
+                }
+                sent += 1;
+            }
+            return sent;
         }
-        let data_size = usize::from_serialized(&data_size_buf);
-        if data_size.is_err(){
-            return None;
+        for batch in messages.chunks(MAX_BATCH_FRAME_MESSAGES){
+            let mut span = self.span.child("send_batch").with_field("messages", batch.len());
+            if let Err(error) = self.send_raw(encode_batch_frame(batch)).await{
+                span.record_error(error);
+                break;
+            }
+            if let Some(outbox) = &self.outbox{
+                let mut outbox = outbox.lock().unwrap();
+                for message in batch{
+                    outbox.acknowledge(message.id);
+                }
+            }
+            sent += batch.len();
         }
-        let (data_size_unwrapped, _) = data_size.unwrap();
-        let mut data_buf = Serialized::with_capacity(data_size_unwrapped);
-        for _ in 0..data_size_unwrapped{
-            data_buf.push(0);
+        sent
+    }
+
+    ///
+    /// Sends again messages left in outbox, e.g. by worker which died before transmitting them.
+    /// Should be called right after connection is (re)established and transformers are negotiated.
+    ///
+    /// # Arguments
+    /// * destination: Option<u128>: peer on the other side of connection, None to replay everything
+    ///
+    /// returns: usize: count of replayed messages, replay stops on first failed write
+    ///
+    pub async fn replay_outbox(&mut self, destination: Option<u128>) -> usize {
+        let outbox = match &self.outbox {
+            Some(outbox) => outbox.clone(),
+            None => return 0,
+        };
+        let pending = outbox.lock().unwrap().get_pending(destination);
+        let mut span = self.span.child("replay_outbox").with_field("pending", pending.len());
+        let mut replayed = 0;
+        for message in pending.iter(){
+            if !self.send_message(message).await{
+                log::warn!("{}: Replay interrupted, {} messages are left in outbox", span, pending.len() - replayed);
+                span.record_error("interrupted");
+                break;
+            }
+            outbox.lock().unwrap().record_replayed();
+            replayed += 1;
         }
-        let result = tokio_timeout(timeout,
-                                   self.stream.read(&mut data_buf)).await;
-        //println!("data_buf={:?}", data_buf);
-        if result.is_none(){
-            return None;
+        span.record("replayed", replayed);
+        replayed
+    }
+
+    ///
+    /// Receives next frame of data
+    ///
+    /// # Arguments
+    /// * timeout: Option<u64>: time to wait for frame to start in milliseconds, None to wait forever
+    ///
+    /// returns: Option<Serialized>: data or None if nothing arrived, connection is closed or
+    /// frame was rejected(see receive_frame to tell these apart)
+    ///
+    #[inline]
+    pub async fn receive_raw(&mut self, timeout: Option<u64>) -> Option<Serialized> {
+        self.receive_frame(timeout).await.ok()
+    }
+
+    ///
+    /// Receives next frame of data. Timeout limits waiting for the first byte of frame only,
+    /// so no data is lost if it expires. Once frame has started it must be completed within
+    /// the same timeout, otherwise stream is out of sync and connection is reported as lost.
+    ///
+    /// # Arguments
+    /// * timeout: Option<u64>: time to wait for frame to start in milliseconds, None to wait forever
+    ///
+    /// returns: Result<Serialized, ReceiveError>: data or reason no data was received
+    ///
+    pub async fn receive_frame(&mut self, timeout: Option<u64>) -> Result<Serialized, ReceiveError> {
+        if self.is_terminated(){
+            return Err(ReceiveError::Disconnected);
         }
-        let result = result.unwrap();
-        if result.is_err(){
-            return None;
+        let mut data_size_buf: Serialized = vec![0; size_of::<usize>()];
+        // Reading of one byte is cancellation safe: either it was consumed or not
+        match tokio_timeout(timeout, self.get_stream().read(&mut data_size_buf[..1])).await {
+            None => return Err(ReceiveError::Idle),
+            Some(Ok(0)) | Some(Err(_)) => return Err(ReceiveError::Disconnected),
+            Some(Ok(_)) => {}
         }
-        if result.unwrap() < data_size_unwrapped{
-            return None;
+        // Frame may arrive in several segments, so it is read until complete
+        self.read_remaining(timeout, &mut data_size_buf[1..]).await?;
+        let (data_size, _) = usize::from_serialized(&data_size_buf).map_err(|_| ReceiveError::Disconnected)?;
+        if data_size > self.max_frame_size{
+            log::error!("{}: Peer announced frame of {} bytes, at most {} are allowed", self.span, data_size,
+                self.max_frame_size);
+            self.span.record_error("frame too large");
+            return Err(ReceiveError::FrameTooLarge(data_size));
         }
+        let started = self.start_measure();
+        let mut data_buf: Serialized = vec![0; data_size];
+        self.read_remaining(timeout, &mut data_buf).await?;
+        self.tap_raw(FrameDirection::Receive, &data_buf);
         let detransform_result = self.apply_detransform(data_buf);
+        self.record_frame(FrameDirection::Receive, data_size,
+                          detransform_result.as_ref().map(|data| data.len()), started);
         if detransform_result.is_none(){
-            return None;
+            if self.is_terminated(){
+                self.close_terminated().await;
+                return Err(ReceiveError::Disconnected);
+            }
+            return Err(ReceiveError::Rejected);
+        }
+        Ok(detransform_result.unwrap())
+    }
+
+    // Shuts stream of session terminated by transformer down, so the other side sees it closed
+    async fn close_terminated(&mut self){
+        log::error!("{}: Session is terminated by transformer, closing connection", self.span);
+        self.span.record_error("session terminated by transformer");
+        self.disconnect_reason = DisconnectReason::Terminated;
+        if let Err(error) = self.get_stream().shutdown().await{
+            log::warn!("{}: Can not shut terminated connection down: {}", self.span, error);
+        }
+    }
+
+    // Reads rest of started frame, stream can not be used anymore if it fails or times out
+    async fn read_remaining(&mut self, timeout: Option<u64>, buffer: &mut [u8]) -> Result<(), ReceiveError> {
+        match tokio_timeout(timeout, self.get_stream().read_exact(buffer)).await {
+            Some(Ok(_)) => Ok(()),
+            Some(Err(_)) => Err(ReceiveError::Disconnected),
+            None => {
+                log::warn!("{}: Frame was not completed in time, stream is out of sync", self.span);
+                Err(ReceiveError::Disconnected)
+            }
+        }
+    }
+
+    ///
+    /// Receives next frame of data probing the other side when connection is idle.
+    ///
+    /// If nothing arrives within idle timeout, a probe is sent, and if nothing arrives within
+    /// probe timeout after it, connection is considered dead. Probes of the other side are
+    /// answered and neither probes nor replies are returned.
+    ///
+    /// # Arguments
+    /// * policy: &KeepAlivePolicy: timeouts of idle connection
+    ///
+    /// returns: Option<Serialized>: data or None if connection is dead, closed or terminated
+    ///
+    pub async fn receive_alive(&mut self, policy: &KeepAlivePolicy) -> Option<Serialized> {
+        let mut probing = false;
+        loop {
+            let timeout = if probing { policy.probe_timeout } else { policy.idle_timeout };
+            let data = match self.receive_frame(Some(timeout)).await {
+                Ok(data) => data,
+                Err(ReceiveError::Idle) if probing => {
+                    log::warn!("{}: Connection did not answer keep-alive probe, closing it", self.span);
+                    self.span.record_error("keep-alive timeout");
+                    self.disconnect_reason = DisconnectReason::KeepAliveTimeout;
+                    return None;
+                }
+                Err(ReceiveError::Idle) => {
+                    if self.send_raw(KEEPALIVE_PROBE.to_vec()).await.is_err(){
+                        return None;
+                    }
+                    probing = true;
+                    continue;
+                }
+                Err(ReceiveError::Rejected) => continue,
+                Err(ReceiveError::Disconnected) => return None,
+                Err(ReceiveError::FrameTooLarge(size)) => {
+                    self.disconnect_reason = DisconnectReason::Error(format!("frame of {} bytes is too large", size));
+                    return None;
+                }
+            };
+            if let Some(reaper) = &self.reaper{
+                reaper.lock().unwrap().on_activity(self.connection_id as u128);
+            }
+            probing = false;
+            if data.as_slice() == KEEPALIVE_PROBE{
+                if self.send_raw(KEEPALIVE_REPLY.to_vec()).await.is_err(){
+                    return None;
+                }
+            } else if data.as_slice() != KEEPALIVE_REPLY{
+                return Some(data);
+            }
+        }
+    }
+
+    ///
+    /// Receives next message keeping connection alive(see receive_alive). Messages of batch
+    /// frames are returned one by one. Frames which are not messages are skipped, they are
+    /// moved to dead-letter queue if it is set.
+    ///
+    /// # Arguments
+    /// * policy: &KeepAlivePolicy: timeouts of idle connection
+    ///
+    /// returns: Option<Message>: message or None if connection is dead, closed or terminated
+    ///
+    pub async fn receive_message(&mut self, policy: &KeepAlivePolicy) -> Option<Message> {
+        loop {
+            if let Some(message) = self.received.pop_front(){
+                return Some(message);
+            }
+            let data = self.receive_alive(policy).await?;
+            let result = match decode_batch_frame(&data, self.parsing_mode) {
+                Some(batch) => batch.map(|messages| self.received.extend(messages)),
+                None => match Message::parse(&data, self.parsing_mode) {
+                    Ok(message) => return Some(message),
+                    Err(error) => Err(error),
+                },
+            };
+            if let Err(error) = result{
+                log::error!("{}: Received frame is not a message: {:?}", self.span, error);
+                if let Some(queue) = &self.dead_letters{
+                    queue.lock().unwrap().record_undeserializable(self.peer_id, &data, &error);
+                }
+            }
         }
-        Some(detransform_result.unwrap())
     }
 
+    ///
+    /// Sends frame of handshake stage, e.g. response to authorization message, within
+    /// timeout of stage(see set_handshake_timeouts). Connection must be closed on error.
+    ///
+    /// # Arguments
+    /// * stage: HandshakeStage: stage frame belongs to
+    /// * data: Serialized: frame to send
+    ///
+    /// returns: Result<(), HandshakeError>: error if stage timed out or connection failed
+    ///
+    pub async fn send_handshake_frame(&mut self, stage: HandshakeStage, data: Serialized) -> Result<(), HandshakeError> {
+        let timeout = self.handshake_timeouts.as_ref().map(|timeouts| timeouts.get(stage));
+        match tokio_timeout(timeout, self.send_raw(data)).await {
+            Some(Ok(_)) => Ok(()),
+            Some(Err(_)) => Err(HandshakeError::ConnectionError),
+            None => Err(self.on_handshake_timeout(stage)),
+        }
+    }
+
+    ///
+    /// Receives frame of handshake stage, e.g. authorization message of peer, within
+    /// timeout of stage(see set_handshake_timeouts). Connection must be closed on error.
+    ///
+    /// # Arguments
+    /// * stage: HandshakeStage: stage frame belongs to
+    ///
+    /// returns: Result<Serialized, HandshakeError>: frame or error if stage timed out or
+    /// connection failed
+    ///
+    #[inline]
+    pub async fn receive_handshake_frame(&mut self, stage: HandshakeStage) -> Result<Serialized, HandshakeError> {
+        self.receive_stage_frame(stage, None).await
+    }
+
+    // Explicit timeout takes precedence over timeout of stage
+    async fn receive_stage_frame(&mut self, stage: HandshakeStage,
+                                 timeout: Option<u64>) -> Result<Serialized, HandshakeError> {
+        let timeout = timeout.or(self.handshake_timeouts.as_ref().map(|timeouts| timeouts.get(stage)));
+        match tokio_timeout(timeout, self.receive_raw(None)).await {
+            Some(Some(data)) => Ok(data),
+            Some(None) => Err(HandshakeError::ConnectionError),
+            None => Err(self.on_handshake_timeout(stage)),
+        }
+    }
+
+    fn on_handshake_timeout(&mut self, stage: HandshakeStage) -> HandshakeError{
+        log::warn!("{}: Peer did not complete handshake stage {} in time", self.span, stage);
+        self.span.record_error(format!("handshake stage {} timed out", stage));
+        if let Some(metrics) = &self.handshake_metrics{
+            metrics.lock().unwrap().record_timeout(stage);
+        }
+        self.disconnect_reason = DisconnectReason::HandshakeTimeout(stage);
+        HandshakeError::TimedOut(stage)
+    }
+
+    ///
+    /// Checks whether any of transformers terminated the session(e.g. due to replay attack).
+    /// Terminated session neither sends nor receives frames, its stream is shut down once
+    /// termination is noticed on receive.
+    ///
+    /// returns: bool: true if session is terminated
+    ///
+    pub fn is_terminated(&self) -> bool{
+        self.transformers.iter().any(|transformer| transformer.is_terminated())
+    }
+
+    ///
+    /// Adds transformer on top of added ones, frame statistics name it `layer<N>` by position
+    ///
     #[inline]
     pub fn add_transformer<'a>(&'a mut self, transformer: Box<dyn TransportTransformer>) -> &'a Self {
-        self.transformers.push(transformer);
+        self.transformer_names.push(format!("layer{}", self.transformers.len()));
+        self.transformers.push(Arc::from(transformer));
         self
     }
+
+    ///
+    /// Gets protocol version agreed with remote side, None until negotiate_version succeeds
+    ///
+    #[inline]
+    pub fn get_protocol_version(&self) -> Option<u32>{
+        self.protocol_version
+    }
+
+    ///
+    /// Exchanges protocol versions with remote side. Both sides must call it as the very first
+    /// step, before transformers are negotiated. If versions are not compatible connection
+    /// must be closed, its disconnect reason is set to IncompatibleVersion.
+    ///
+    /// # Arguments
+    /// * policy: &VersionPolicy: versions local side speaks and accepts
+    /// * timeout: Option<u64>: timeout of receiving remote announcement in milliseconds, None
+    ///   to use timeout of version stage(see set_handshake_timeouts)
+    ///
+    /// returns: Result<u32, VersionNegotiationError>: newest version both sides speak or error
+    ///
+    pub async fn negotiate_version(&mut self, policy: &VersionPolicy,
+                                   timeout: Option<u64>) -> Result<u32, VersionNegotiationError> {
+        if !self.transformers.is_empty(){
+            log::error!("{}: Protocol version must be negotiated before any transformer is added", self.span);
+            return Err(VersionNegotiationError::AfterTransformers);
+        }
+        let mut span = self.span.child("negotiate_version")
+            .with_field("version", policy.version)
+            .with_field("minimum", policy.minimum);
+        let result = self.exchange_versions(policy, timeout).await;
+        match &result {
+            Ok(version) => {
+                self.protocol_version = Some(*version);
+                self.span.record("protocol_version", version);
+                if let Some(events) = &self.events{
+                    events.lock().unwrap().on_version_negotiated(self.connection_id, *version);
+                }
+            }
+            Err(error) => {
+                log::error!("{}: Can not agree on protocol version with remote side: {}", span, error);
+                span.record_error(error);
+                if !matches!(self.disconnect_reason, DisconnectReason::HandshakeTimeout(_)){
+                    self.disconnect_reason = DisconnectReason::IncompatibleVersion;
+                }
+            }
+        }
+        result
+    }
+
+    async fn exchange_versions(&mut self, policy: &VersionPolicy,
+                               timeout: Option<u64>) -> Result<u32, VersionNegotiationError> {
+        self.send_handshake_frame(HandshakeStage::Version, policy.get_hello().to_frame()).await
+            .map_err(|_| VersionNegotiationError::ConnectionError)?;
+        let frame = self.receive_stage_frame(HandshakeStage::Version, timeout).await
+            .map_err(|_| VersionNegotiationError::ConnectionError)?;
+        let remote = VersionHello::from_frame(&frame).ok_or(VersionNegotiationError::Unversioned)?;
+        policy.negotiate(&remote)
+    }
+
+    ///
+    /// Exchanges transformer stack descriptors with remote side and installs agreed transformers.
+    /// Both sides must call it right after connection is established. If stacks are not
+    /// compatible no transformers are installed and connection must be closed.
+    ///
+    /// # Arguments
+    /// * stack: &TransformerStack: local transformer stack
+    /// * timeout: Option<u64>: timeout of receiving remote descriptor in milliseconds, None
+    ///   to use timeout of capabilities stage(see set_handshake_timeouts)
+    ///
+    /// returns: Result<TransformerStackDescriptor, TransformerNegotiationError>: stack of remote side or error,
+    /// AlreadyNegotiated if transformers are already installed
+    ///
+    pub async fn negotiate_transformers(&mut self, stack: &TransformerStack,
+                                        timeout: Option<u64>) -> Result<TransformerStackDescriptor, TransformerNegotiationError> {
+        if !self.transformers.is_empty(){
+            log::error!("{}: Transformers are already installed, they can not be negotiated again", self.span);
+            return Err(TransformerNegotiationError::AlreadyNegotiated);
+        }
+        let mut span = self.span.child("negotiate_transformers")
+            .with_field("layers", stack.get_descriptor().get_names().join(","));
+        let result = self.exchange_transformers(stack, timeout).await;
+        if let Err(error) = &result{
+            log::error!("{}: Can not agree on transformers with remote side: {:?}", span, error);
+            span.record_error(format!("{:?}", error));
+        }
+        result
+    }
+
+    async fn exchange_transformers(&mut self, stack: &TransformerStack,
+                                   timeout: Option<u64>) -> Result<TransformerStackDescriptor, TransformerNegotiationError> {
+        let local = stack.get_descriptor();
+        self.send_handshake_frame(HandshakeStage::Capabilities, local.serialize()).await
+            .map_err(|_| TransformerNegotiationError::ConnectionError)?;
+        let remote = self.receive_stage_frame(HandshakeStage::Capabilities, timeout).await.ok()
+            .and_then(|data| TransformerStackDescriptor::from_serialized(&data).ok())
+            .map(|(remote, _)| remote)
+            .ok_or(TransformerNegotiationError::ConnectionError)?;
+        self.transformers = stack.build(&local, &remote)?.into_iter().map(Arc::from).collect();
+        self.transformer_names = remote.get_names();
+        Ok(remote)
+    }
+}
+
+impl<T: AsyncReadExt + AsyncWriteExt + Sync + Send + Unpin> TokioStreamTransport<T> {
+    ///
+    /// Splits transport once handshake is done, so messages may be received and sent by
+    /// different coroutines. Halves share transformers, connection ID and peer ID.
+    ///
+    /// Receiving half keeps connection events, reaper and dead-letter queue, so closing of
+    /// connection is reported once it is dropped. It does not write to stream: keep-alive
+    /// probes and replies are passed to sending half, whose owner must send them(see
+    /// receive_control_frame), so frames are transformed in the order they are written.
+    ///
+    /// returns: (TransportReadHalf<T>, TransportWriteHalf<T>): receiving and sending half
+    ///
+    pub fn into_split(mut self) -> (TransportReadHalf<T>, TransportWriteHalf<T>){
+        let (control, control_frames) = unbounded_channel();
+        let (reader, writer) = tokio::io::split(self.stream.take().expect("Transport is already split"));
+        let mut write_half = self.create_half(tokio::io::join(tokio::io::empty(), writer), self.span.child("send_half"));
+        write_half.shaper = self.shaper.take();
+        write_half.outbox = self.outbox.take();
+        write_half.handshake_timeouts = self.handshake_timeouts.take();
+        write_half.handshake_metrics = self.handshake_metrics.take();
+        write_half.control_frames = Some(control_frames);
+        // Connection span is kept by receiving half, transport itself records moment of split
+        let split_span = self.span.child("split");
+        let span = std::mem::replace(&mut self.span, split_span);
+        let mut read_half = self.create_half(tokio::io::join(reader, tokio::io::sink()), span);
+        read_half.reaper = self.reaper.take();
+        read_half.dead_letters = self.dead_letters.take();
+        read_half.events = self.events.take();
+        read_half.disconnect_reason = self.disconnect_reason.clone();
+        read_half.received = std::mem::take(&mut self.received);
+        read_half.control = Some(control);
+        (read_half, write_half)
+    }
+
+    // Creates half of split transport over part of stream, with transformers of this transport
+    fn create_half<S: AsyncReadExt + AsyncWriteExt + Sync + Send + Unpin>(&self, stream: S, span: Span) -> TokioStreamTransport<S>{
+        TokioStreamTransport {
+            stream: Some(stream),
+            transformers: self.transformers.clone(),
+            transformer_names: self.transformer_names.clone(),
+            shaper: None,
+            outbox: None,
+            reaper: None,
+            dead_letters: None,
+            events: None,
+            disconnect_reason: DisconnectReason::Closed,
+            connection_id: self.connection_id,
+            peer_id: self.peer_id,
+            protocol_version: self.protocol_version,
+            received: VecDeque::new(),
+            handshake_timeouts: None,
+            handshake_metrics: None,
+            frame_stats: self.frame_stats.clone(),
+            raw_frame_tap: self.raw_frame_tap.clone(),
+            parsing_mode: self.parsing_mode,
+            max_frame_size: self.max_frame_size,
+            control: None,
+            control_frames: None,
+            span,
+        }
+    }
+
+    ///
+    /// Waits for keep-alive frame receiving half passed to this sending half, frames must be
+    /// sent with send_raw. Cancellation safe, so it may be awaited together with other sources
+    /// of frames.
+    ///
+    /// returns: Option<Serialized>: frame or None if receiving half is dropped or this
+    /// transport is not a sending half
+    ///
+    pub async fn receive_control_frame(&mut self) -> Option<Serialized>{
+        match self.control_frames.as_mut() {
+            Some(control_frames) => control_frames.recv().await,
+            None => None,
+        }
+    }
+}
+
+impl<T: AsyncReadExt + AsyncWriteExt + Sync + Send + Unpin> Drop for TokioStreamTransport<T> {
+    fn drop(&mut self) {
+        if let Some(events) = &self.events{
+            events.lock().unwrap().on_disconnected(self.connection_id, self.disconnect_reason.clone());
+        }
+        if let Some(reaper) = &self.reaper{
+            reaper.lock().unwrap().close(self.connection_id as u128);
+        }
+    }
 }
 
 /* Tests begin here */
@@ -113,7 +882,10 @@ mod tests {
     use tokio::time::{timeout, Duration};
     use crate::serialization::serializable::{Serializable, Serialized};
     use crate::serialization::deserializable::Deserializable;
-
+    use crate::serialization::error::SerializationError;
+    use std::sync::{Arc, Mutex};
+    use crate::transport::keepalive::ConnectionReaper;
+    use crate::transport::outbox::Outbox;
 
     #[tokio::test]
     async fn test_send_raw() {
@@ -164,6 +936,85 @@ mod tests {
         assert!(result.unwrap().is_none());
     }
 
+    #[tokio::test]
+    async fn test_receive_frame_outcomes() {
+        let (client, server) = duplex(64);
+        let mut client_transport = TokioStreamTransport::from_stream(client);
+        let mut server_transport = TokioStreamTransport::from_stream(server);
+        assert_eq!(server_transport.receive_frame(Some(20)).await, Err(ReceiveError::Idle));
+
+        // Frame arriving in segments slower than timeout is not lost once it started
+        let mut frame = 3usize.serialize();
+        frame.extend([7, 8, 9]);
+        let (received, _) = tokio::join!(server_transport.receive_frame(Some(50)), async {
+            client_transport.get_stream().write_all(&frame[..4]).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            client_transport.get_stream().write_all(&frame[4..]).await.unwrap();
+        });
+        assert_eq!(received, Ok(vec![7, 8, 9]));
+
+        // Frame cut in the middle means stream is out of sync
+        client_transport.get_stream().write_all(&frame[..4]).await.unwrap();
+        assert_eq!(server_transport.receive_frame(Some(20)).await, Err(ReceiveError::Disconnected));
+
+        drop(client_transport);
+        let (client, server) = duplex(64);
+        let mut server_transport = TokioStreamTransport::from_stream(server);
+        drop(client);
+        assert_eq!(server_transport.receive_frame(Some(1000)).await, Err(ReceiveError::Disconnected));
+    }
+
+    struct TerminatingTransformer{
+        terminated: std::sync::atomic::AtomicBool,
+    }
+
+    impl TransportTransformer for TerminatingTransformer{
+        fn detransform(&self, data: &Serialized) -> Result<Serialized, SerializationError> {
+            if data.first() == Some(&0xFF){
+                self.terminated.store(true, Ordering::SeqCst);
+                return Err(SerializationError::InvalidDataError("replayed"));
+            }
+            Ok(data.clone())
+        }
+
+        fn transform(&self, data: &Serialized) -> Serialized {
+            data.clone()
+        }
+
+        fn is_terminated(&self) -> bool {
+            self.terminated.load(Ordering::SeqCst)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_terminated_session_is_closed() {
+        let (client, server) = duplex(64);
+        let mut client_transport = TokioStreamTransport::from_stream(client);
+        let mut server_transport = TokioStreamTransport::from_stream(server);
+        server_transport.add_transformer(Box::new(TerminatingTransformer{ terminated: Default::default() }));
+        client_transport.send_raw(vec![0xFF]).await.unwrap();
+        client_transport.send_raw(vec![1]).await.unwrap();
+        assert_eq!(server_transport.receive_frame(None).await, Err(ReceiveError::Disconnected));
+        assert_eq!(server_transport.disconnect_reason, DisconnectReason::Terminated);
+        // Nothing is sent or received after termination and the other side sees connection closed
+        assert!(server_transport.send_raw(vec![2]).await.is_err());
+        assert_eq!(server_transport.receive_frame(None).await, Err(ReceiveError::Disconnected));
+        assert_eq!(client_transport.receive_frame(Some(1000)).await, Err(ReceiveError::Disconnected));
+    }
+
+    #[tokio::test]
+    async fn test_max_frame_size() {
+        let (client, server) = duplex(64);
+        let mut client_transport = TokioStreamTransport::from_stream(client);
+        let mut server_transport = TokioStreamTransport::from_stream(server);
+        server_transport.set_max_frame_size(4);
+        client_transport.send_raw(vec![1, 2, 3, 4]).await.unwrap();
+        assert_eq!(server_transport.receive_frame(None).await, Ok(vec![1, 2, 3, 4]));
+        // Only size is sent, frame is rejected before its data is awaited
+        client_transport.get_stream().write_all(&usize::MAX.serialize()).await.unwrap();
+        assert_eq!(server_transport.receive_frame(None).await, Err(ReceiveError::FrameTooLarge(usize::MAX)));
+    }
+
     #[tokio::test]
     async fn test_send_and_receive() {
         let (client, server) = duplex(64);
@@ -176,4 +1027,116 @@ mod tests {
         let received_data = server_transport.receive_raw(None).await.unwrap();
         assert_eq!(received_data, data);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_send_and_receive_large_frame() {
+        let (client, server) = duplex(64);
+        let mut client_transport = TokioStreamTransport::from_stream(client);
+        let mut server_transport = TokioStreamTransport::from_stream(server);
+
+        // Frame does not fit into buffer of stream, so it is written and read in many parts
+        let data: Serialized = (0..256 * 1024).map(|index| index as u8).collect();
+        let (sent, received) = tokio::join!(client_transport.send_raw(data.clone()),
+                                            server_transport.receive_raw(None));
+        assert_eq!(sent.unwrap(), data.len());
+        assert_eq!(received.unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_receive_alive() {
+        let (client, server) = duplex(64);
+        let mut client_transport = TokioStreamTransport::from_stream(client);
+        let mut server_transport = TokioStreamTransport::from_stream(server);
+        let policy = KeepAlivePolicy{ idle_timeout: 50, probe_timeout: 50, reap_interval: 10 };
+        let reaper = Arc::new(Mutex::new(ConnectionReaper::new(policy.clone())));
+        server_transport.set_reaper(reaper.clone());
+
+        // Idle client is probed and answers while waiting for data itself
+        let (received, _) = tokio::join!(server_transport.receive_alive(&policy), async {
+            let probe = client_transport.receive_raw(None).await.unwrap();
+            assert_eq!(probe, KEEPALIVE_PROBE.to_vec());
+            client_transport.send_raw(KEEPALIVE_REPLY.to_vec()).await.unwrap();
+            client_transport.send_raw(vec![1, 2, 3]).await.unwrap();
+        });
+        assert_eq!(received, Some(vec![1, 2, 3]));
+
+        // Client which does not answer probes is dead
+        assert_eq!(server_transport.receive_alive(&policy).await, None);
+        assert_eq!(server_transport.disconnect_reason, DisconnectReason::KeepAliveTimeout);
+        assert!(reaper.lock().unwrap().is_tracked(server_transport.get_connection_id() as u128));
+        drop(server_transport);
+        assert!(reaper.lock().unwrap().is_empty());
+
+        // Closed connection is not mistaken for idle one
+        let (client, server) = duplex(64);
+        let mut server_transport = TokioStreamTransport::from_stream(server);
+        drop(client);
+        assert_eq!(server_transport.receive_alive(&policy).await, None);
+        assert_eq!(server_transport.disconnect_reason, DisconnectReason::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_send_messages() {
+        let messages: Vec<Message> = (1..=3).map(|id| {
+            let mut message = Message::new();
+            message.set_id(id);
+            message
+        }).collect();
+        let policy = KeepAlivePolicy{ idle_timeout: 1000, probe_timeout: 1000, reap_interval: 10 };
+        for (version, frames) in [(1, 3), (2, 1)]{
+            let (client, server) = duplex(1 << 16);
+            let mut client_transport = TokioStreamTransport::from_stream(client);
+            let mut server_transport = TokioStreamTransport::from_stream(server);
+            let version_policy = VersionPolicy{ version, minimum: 1 };
+            let _ = tokio::join!(client_transport.negotiate_version(&version_policy, Some(1000)),
+                                 server_transport.negotiate_version(&version_policy, Some(1000)));
+            let outbox = Arc::new(Mutex::new(Outbox::new("/tmp/test_send_messages.outbox", 10)));
+            messages.iter().for_each(|message| outbox.lock().unwrap().push(message.clone()));
+            client_transport.set_outbox(outbox.clone());
+            assert_eq!(client_transport.send_messages(&messages).await, 3);
+            assert!(outbox.lock().unwrap().get_pending(None).is_empty());
+            drop(client_transport);
+            let mut received = vec![];
+            while let Some(message) = server_transport.receive_message(&policy).await{
+                received.push(message.id);
+            }
+            assert_eq!(received, vec![1, 2, 3]);
+
+            let (client, server) = duplex(1 << 16);
+            let mut client_transport = TokioStreamTransport::from_stream(client);
+            let mut server_transport = TokioStreamTransport::from_stream(server);
+            client_transport.protocol_version = Some(version);
+            client_transport.send_messages(&messages).await;
+            drop(client_transport);
+            let mut count = 0;
+            while server_transport.receive_raw(Some(1000)).await.is_some(){
+                count += 1;
+            }
+            assert_eq!(count, frames);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_split_answers_probes_through_sending_half() {
+        let (client, server) = duplex(1 << 16);
+        let mut client_transport = TokioStreamTransport::from_stream(client);
+        let server_transport = TokioStreamTransport::from_stream(server);
+        let connection_id = server_transport.get_connection_id();
+        let (mut read_half, mut write_half) = server_transport.into_split();
+        assert_eq!((read_half.get_connection_id(), write_half.get_connection_id()), (connection_id, connection_id));
+        let policy = KeepAlivePolicy{ idle_timeout: 1000, probe_timeout: 1000, reap_interval: 10 };
+
+        // Probe is answered by sending half, data is returned by receiving half
+        client_transport.send_raw(KEEPALIVE_PROBE.to_vec()).await.unwrap();
+        client_transport.send_raw(vec![1, 2, 3]).await.unwrap();
+        assert_eq!(read_half.receive_alive(&policy).await, Some(vec![1, 2, 3]));
+        let reply = write_half.receive_control_frame().await.unwrap();
+        write_half.send_raw(reply).await.unwrap();
+        write_half.send_raw(vec![4]).await.unwrap();
+        assert_eq!(client_transport.receive_raw(Some(1000)).await, Some(KEEPALIVE_REPLY.to_vec()));
+        assert_eq!(client_transport.receive_raw(Some(1000)).await, Some(vec![4]));
+
+        drop(read_half);
+        assert_eq!(write_half.receive_control_frame().await, None);
+    }
+}
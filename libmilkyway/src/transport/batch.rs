@@ -0,0 +1,89 @@
+use crate::message::common::Message;
+use crate::serialization::deserializable::ParsingMode;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+
+///
+/// Oldest protocol version which understands batch frames. Peers announcing older version
+/// receive messages of batch one per frame.
+///
+pub const BATCH_FRAME_PROTOCOL_VERSION: u32 = 2;
+
+///
+/// Marker at the beginning of frame carrying several messages
+///
+pub const BATCH_FRAME_MAGIC: [u8; 8] = *b"MWAYBTCH";
+
+///
+/// Maximal count of messages coalesced into one frame, longer batches are split into
+/// several frames
+///
+pub const MAX_BATCH_FRAME_MESSAGES: usize = 256;
+
+///
+/// Checks whether batch frames may be sent to peer
+///
+/// # Arguments
+/// * protocol_version: Option<u32>: version agreed with peer, None if it was not negotiated
+///
+#[inline]
+pub fn supports_batch_frames(protocol_version: Option<u32>) -> bool{
+    protocol_version.is_some_and(|version| version >= BATCH_FRAME_PROTOCOL_VERSION)
+}
+
+///
+/// Makes frame: BATCH_FRAME_MAGIC followed by messages serialized as a vector
+///
+pub fn encode_batch_frame(messages: &[Message]) -> Serialized{
+    let mut frame = BATCH_FRAME_MAGIC.to_vec();
+    frame.extend(messages.len().serialize());
+    for message in messages{
+        frame.extend(message.serialize());
+    }
+    frame
+}
+
+///
+/// Parses frame which may carry a batch
+///
+/// # Arguments
+/// * frame: &[u8]: received frame
+/// * mode: ParsingMode: whether bytes after last message are an error
+///
+/// returns: Option<Result<Vec<Message>, SerializationError>>: None if frame is not a batch,
+/// otherwise messages of batch or error if batch is malformed
+///
+pub fn decode_batch_frame(frame: &[u8], mode: ParsingMode) -> Option<Result<Vec<Message>, SerializationError>>{
+    let data = frame.strip_prefix(&BATCH_FRAME_MAGIC)?.to_vec();
+    Some(mode.deserialize(&data))
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_frame() {
+        let messages: Vec<Message> = (1..=3).map(|id| {
+            let mut message = Message::new();
+            message.set_id(id);
+            message.destination = 2;
+            message
+        }).collect();
+        let frame = encode_batch_frame(&messages);
+        let decoded = decode_batch_frame(&frame, ParsingMode::Strict).unwrap().unwrap();
+        assert_eq!(decoded.iter().map(|message| message.id).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(decoded[2].destination, 2);
+        assert!(decode_batch_frame(&messages[0].serialize(), ParsingMode::Lenient).is_none());
+        assert!(decode_batch_frame(&frame[..frame.len() - 1], ParsingMode::Lenient).unwrap().is_err());
+        let mut padded = frame.clone();
+        padded.push(0);
+        assert_eq!(decode_batch_frame(&padded, ParsingMode::Lenient).unwrap().unwrap().len(), 3);
+        assert!(matches!(decode_batch_frame(&padded, ParsingMode::Strict),
+                         Some(Err(SerializationError::TrailingDataError(1)))));
+        assert!(!supports_batch_frames(None));
+        assert!(!supports_batch_frames(Some(1)));
+        assert!(supports_batch_frames(Some(BATCH_FRAME_PROTOCOL_VERSION)));
+    }
+}
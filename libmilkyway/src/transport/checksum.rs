@@ -0,0 +1,245 @@
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::transport::stack::{TransformerDescriptor, TransformerFactory, TransformerNegotiationError};
+use crate::transport::TransportTransformer;
+
+///
+/// Name of ChecksumTransformer in stack descriptors
+///
+pub const CHECKSUM_TRANSFORMER_NAME: &str = "checksum";
+
+///
+/// Version of ChecksumTransformer wire format
+///
+pub const CHECKSUM_TRANSFORMER_VERSION: u32 = 1;
+
+/** Algorithm and checksum preceding data **/
+const CHECKSUM_HEADER_SIZE: usize = 5;
+
+const XXHASH_PRIME_1: u32 = 0x9E3779B1;
+const XXHASH_PRIME_2: u32 = 0x85EBCA77;
+const XXHASH_PRIME_3: u32 = 0xC2B2AE3D;
+const XXHASH_PRIME_4: u32 = 0x27D4EB2F;
+const XXHASH_PRIME_5: u32 = 0x165667B1;
+
+///
+/// Checksum algorithms which may be negotiated
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChecksumAlgorithm{
+    /** CRC-32 of IEEE 802.3 **/
+    Crc32,
+    /** 32-bit xxHash with zero seed, faster on large frames **/
+    XxHash32,
+}
+
+impl ChecksumAlgorithm {
+    ///
+    /// Gets flag of frames checksummed with algorithm
+    ///
+    pub fn get_id(&self) -> u8{
+        match self {
+            ChecksumAlgorithm::Crc32 => 1,
+            ChecksumAlgorithm::XxHash32 => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<ChecksumAlgorithm>{
+        match id {
+            1 => Some(ChecksumAlgorithm::Crc32),
+            2 => Some(ChecksumAlgorithm::XxHash32),
+            _ => None,
+        }
+    }
+
+    ///
+    /// Calculates checksum of data
+    ///
+    pub fn calculate(&self, data: &[u8]) -> u32{
+        match self {
+            ChecksumAlgorithm::Crc32 => crc32(data),
+            ChecksumAlgorithm::XxHash32 => xxhash32(data),
+        }
+    }
+}
+
+///
+/// Whether transformer stack checksums frames
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ChecksumMode{
+    /** Frames are checksummed only if no transformer of stack authenticates them **/
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+fn crc32(data: &[u8]) -> u32{
+    let mut crc = !0u32;
+    for byte in data{
+        crc ^= *byte as u32;
+        for _ in 0..8{
+            crc = (crc >> 1) ^ (0xEDB88320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn xxhash32(data: &[u8]) -> u32{
+    let read = |chunk: &[u8]| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    let round = |accumulator: u32, lane: u32| accumulator.wrapping_add(lane.wrapping_mul(XXHASH_PRIME_2))
+        .rotate_left(13).wrapping_mul(XXHASH_PRIME_1);
+    let stripes = data.chunks_exact(16);
+    let tail = stripes.remainder();
+    let mut hash = if data.len() >= 16 {
+        let mut lanes = [XXHASH_PRIME_1.wrapping_add(XXHASH_PRIME_2), XXHASH_PRIME_2, 0, XXHASH_PRIME_1.wrapping_neg()];
+        for stripe in stripes{
+            for (index, lane) in lanes.iter_mut().enumerate(){
+                *lane = round(*lane, read(&stripe[index * 4..]));
+            }
+        }
+        lanes[0].rotate_left(1).wrapping_add(lanes[1].rotate_left(7))
+            .wrapping_add(lanes[2].rotate_left(12)).wrapping_add(lanes[3].rotate_left(18))
+    } else {
+        XXHASH_PRIME_5
+    };
+    hash = hash.wrapping_add(data.len() as u32);
+    let words = tail.chunks_exact(4);
+    let bytes = words.remainder();
+    for word in words{
+        hash = hash.wrapping_add(read(word).wrapping_mul(XXHASH_PRIME_3)).rotate_left(17).wrapping_mul(XXHASH_PRIME_4);
+    }
+    for byte in bytes{
+        hash = hash.wrapping_add((*byte as u32).wrapping_mul(XXHASH_PRIME_5)).rotate_left(11).wrapping_mul(XXHASH_PRIME_1);
+    }
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(XXHASH_PRIME_2);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(XXHASH_PRIME_3);
+    hash ^ (hash >> 16)
+}
+
+///
+/// Prepends checksum to every frame and rejects received frames which do not match it, so
+/// corruption is detected on connections without authenticated encryption
+///
+pub struct ChecksumTransformer{
+    algorithm: ChecksumAlgorithm,
+}
+
+impl ChecksumTransformer {
+    pub fn new(algorithm: ChecksumAlgorithm) -> ChecksumTransformer{
+        ChecksumTransformer{
+            algorithm,
+        }
+    }
+}
+
+impl TransportTransformer for ChecksumTransformer{
+    fn detransform(&self, data: &Serialized) -> Result<Serialized, SerializationError> {
+        if data.len() < CHECKSUM_HEADER_SIZE{
+            return Err(SerializationError::LengthError);
+        }
+        // Algorithm of frame is used, so sides may prefer different ones
+        let algorithm = ChecksumAlgorithm::from_id(data[0])
+            .ok_or(SerializationError::InvalidDataError("Unknown checksum algorithm"))?;
+        let checksum = u32::from_le_bytes([data[1], data[2], data[3], data[4]]);
+        let payload = &data[CHECKSUM_HEADER_SIZE..];
+        if algorithm.calculate(payload) != checksum{
+            log::warn!("Dropped frame of {} bytes with wrong {:?} checksum", data.len(), algorithm);
+            return Err(SerializationError::InvalidDataError("Checksum mismatch"));
+        }
+        Ok(payload.to_vec())
+    }
+
+    fn transform(&self, data: &Serialized) -> Serialized {
+        let mut result = Serialized::with_capacity(CHECKSUM_HEADER_SIZE + data.len());
+        result.push(self.algorithm.get_id());
+        result.extend(self.algorithm.calculate(data).to_le_bytes());
+        result.extend(data);
+        result
+    }
+}
+
+///
+/// Creates ChecksumTransformer with first local algorithm remote side supports. Descriptor
+/// advertises supported algorithms in order of preference.
+///
+pub struct ChecksumTransformerFactory{
+    algorithms: Vec<ChecksumAlgorithm>,
+}
+
+impl Default for ChecksumTransformerFactory {
+    fn default() -> Self {
+        ChecksumTransformerFactory::new(vec![ChecksumAlgorithm::XxHash32, ChecksumAlgorithm::Crc32])
+    }
+}
+
+impl ChecksumTransformerFactory {
+    ///
+    /// Creates a factory
+    ///
+    /// # Arguments
+    /// * algorithms: Vec<ChecksumAlgorithm>: supported algorithms in order of preference
+    ///
+    pub fn new(algorithms: Vec<ChecksumAlgorithm>) -> ChecksumTransformerFactory{
+        ChecksumTransformerFactory{
+            algorithms,
+        }
+    }
+}
+
+impl TransformerFactory for ChecksumTransformerFactory{
+    fn get_descriptor(&self) -> TransformerDescriptor {
+        let algorithms: Vec<u8> = self.algorithms.iter().map(|algorithm| algorithm.get_id()).collect();
+        TransformerDescriptor{
+            name: CHECKSUM_TRANSFORMER_NAME.to_string(),
+            version: CHECKSUM_TRANSFORMER_VERSION,
+            parameters: algorithms.serialize(),
+        }
+    }
+
+    fn create(&self, _local: &TransformerDescriptor,
+              remote: &TransformerDescriptor) -> Result<Box<dyn TransportTransformer>, TransformerNegotiationError> {
+        let invalid = |reason: &str| TransformerNegotiationError::InvalidParameters(reason.to_string());
+        let (remote_algorithms, _) = Vec::<u8>::from_serialized(&remote.parameters)
+            .map_err(|_| invalid("Malformed parameters of checksum transformer"))?;
+        let algorithm = self.algorithms.iter()
+            .find(|algorithm| remote_algorithms.contains(&algorithm.get_id()))
+            .ok_or_else(|| invalid("No common checksum algorithm"))?;
+        Ok(Box::new(ChecksumTransformer::new(*algorithm)))
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(xxhash32(b""), 0x02CC5D05);
+        assert_eq!(xxhash32(b"a"), 0x550D7456);
+        assert_eq!(xxhash32(b"abc"), 0x32D153FF);
+        assert_eq!(xxhash32(b"Nobody inspects the spammish repetition"), 0xE2293B2F);
+
+        let client = ChecksumTransformerFactory::default();
+        let server = ChecksumTransformerFactory::new(vec![ChecksumAlgorithm::Crc32]);
+        let client_transformer = client.create(&client.get_descriptor(), &server.get_descriptor()).unwrap();
+        let server_transformer = server.create(&server.get_descriptor(), &client.get_descriptor()).unwrap();
+        let data: Serialized = b"milkyway".repeat(10);
+        let frame = client_transformer.transform(&data);
+        assert_eq!(frame[0], ChecksumAlgorithm::Crc32.get_id());
+        assert_eq!(server_transformer.detransform(&frame).unwrap(), data);
+        let mut corrupted = frame.clone();
+        corrupted[20] ^= 0x10;
+        assert_eq!(server_transformer.detransform(&corrupted),
+                   Err(SerializationError::InvalidDataError("Checksum mismatch")));
+        assert!(server_transformer.detransform(&frame[..4].to_vec()).is_err());
+        let none = ChecksumTransformerFactory::new(vec![]);
+        assert!(server.create(&server.get_descriptor(), &none.get_descriptor()).is_err());
+    }
+}
@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::transport::stack::{TransformerDescriptor, TransformerFactory, TransformerNegotiationError};
+use crate::transport::TransportTransformer;
+
+///
+/// Name of CompressionTransformer in stack descriptors
+///
+pub const COMPRESSION_TRANSFORMER_NAME: &str = "compression";
+
+///
+/// Version of CompressionTransformer wire format
+///
+pub const COMPRESSION_TRANSFORMER_VERSION: u32 = 1;
+
+///
+/// Frames smaller than this are sent uncompressed by default
+///
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 512;
+
+///
+/// Maximal size of decompressed frame, larger ones are rejected
+///
+pub const MAX_DECOMPRESSED_SIZE: usize = 64 * 1024 * 1024;
+
+/** Flag of frame sent as is **/
+const FLAG_UNCOMPRESSED: u8 = 0;
+
+/** Flag and original size preceding compressed data **/
+const COMPRESSED_HEADER_SIZE: usize = 5;
+
+///
+/// Compression algorithms which may be negotiated
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompressionAlgorithm{
+    /** Fast, moderate ratio **/
+    Lz4,
+    /** Slower, better ratio **/
+    Deflate,
+}
+
+impl CompressionAlgorithm {
+    ///
+    /// Gets flag of frames compressed with algorithm
+    ///
+    pub fn get_id(&self) -> u8{
+        match self {
+            CompressionAlgorithm::Lz4 => 1,
+            CompressionAlgorithm::Deflate => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<CompressionAlgorithm>{
+        match id {
+            1 => Some(CompressionAlgorithm::Lz4),
+            2 => Some(CompressionAlgorithm::Deflate),
+            _ => None,
+        }
+    }
+
+    ///
+    /// Finds algorithm by name used in configuration
+    ///
+    pub fn from_name(name: &str) -> Option<CompressionAlgorithm>{
+        match name {
+            "lz4" => Some(CompressionAlgorithm::Lz4),
+            "deflate" => Some(CompressionAlgorithm::Deflate),
+            _ => None,
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Vec<u8>{
+        match self {
+            CompressionAlgorithm::Lz4 => lz4_flex::block::compress(data),
+            CompressionAlgorithm::Deflate => miniz_oxide::deflate::compress_to_vec(data, 6),
+        }
+    }
+
+    ///
+    /// Decompresses data
+    ///
+    /// # Arguments
+    /// * data: &[u8]: compressed data
+    /// * size: usize: size of original data
+    ///
+    pub fn decompress(&self, data: &[u8], size: usize) -> Option<Vec<u8>>{
+        let decompressed = match self {
+            CompressionAlgorithm::Lz4 => lz4_flex::block::decompress(data, size).ok()?,
+            CompressionAlgorithm::Deflate => miniz_oxide::inflate::decompress_to_vec_with_limit(data, size).ok()?,
+        };
+        if decompressed.len() != size{
+            return None;
+        }
+        Some(decompressed)
+    }
+}
+
+///
+/// Which algorithms are offered and when frames are compressed
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompressionPolicy{
+    /** Algorithms in order of preference, empty list disables compression **/
+    pub algorithms: Vec<CompressionAlgorithm>,
+    /** Minimal size of frame to be compressed **/
+    pub threshold: usize,
+}
+
+impl Default for CompressionPolicy {
+    fn default() -> Self {
+        CompressionPolicy{
+            algorithms: vec![CompressionAlgorithm::Lz4, CompressionAlgorithm::Deflate],
+            threshold: DEFAULT_COMPRESSION_THRESHOLD,
+        }
+    }
+}
+
+///
+/// Compression counters of one peer, both directions together
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompressionStats{
+    pub frames: u64,
+    pub compressed_frames: u64,
+    /** Size of frames before compression **/
+    pub original_bytes: u64,
+    /** Size of frames on wire, including flag **/
+    pub transmitted_bytes: u64,
+}
+
+impl CompressionStats {
+    ///
+    /// Gets bytes saved by compression, negative if flags cost more than compression saved
+    ///
+    #[inline]
+    pub fn get_saved_bytes(&self) -> i64{
+        self.original_bytes as i64 - self.transmitted_bytes as i64
+    }
+
+    fn record(&mut self, original: usize, transmitted: usize, is_compressed: bool){
+        self.frames += 1;
+        self.compressed_frames += is_compressed as u64;
+        self.original_bytes += original as u64;
+        self.transmitted_bytes += transmitted as u64;
+    }
+}
+
+///
+/// Compression stats by serial of signing certificate of peer
+///
+pub type SharedCompressionStats = Arc<Mutex<HashMap<u128, CompressionStats>>>;
+
+///
+/// Compresses frames larger than threshold with negotiated algorithm. Every frame starts
+/// with a flag: zero for frames sent as is, ID of algorithm followed by original size
+/// otherwise. Frame stays uncompressed if compression does not make it smaller.
+///
+pub struct CompressionTransformer{
+    algorithm: Option<CompressionAlgorithm>,
+    threshold: usize,
+    peer_serial: u128,
+    stats: SharedCompressionStats,
+}
+
+impl CompressionTransformer {
+    ///
+    /// Creates transformer
+    ///
+    /// # Arguments
+    /// * algorithm: Option<CompressionAlgorithm>: algorithm of outgoing frames, None to send them as is
+    /// * threshold: usize: minimal size of frame to be compressed
+    /// * peer_serial: u128: serial of signing certificate of peer stats are recorded for
+    /// * stats: SharedCompressionStats: stats of all peers
+    ///
+    pub fn new(algorithm: Option<CompressionAlgorithm>, threshold: usize, peer_serial: u128,
+               stats: SharedCompressionStats) -> CompressionTransformer{
+        CompressionTransformer{
+            algorithm,
+            threshold,
+            peer_serial,
+            stats,
+        }
+    }
+
+    fn record(&self, original: usize, transmitted: usize, is_compressed: bool){
+        self.stats.lock().unwrap().entry(self.peer_serial).or_default().record(original, transmitted, is_compressed);
+    }
+}
+
+impl TransportTransformer for CompressionTransformer{
+    fn detransform(&self, data: &Serialized) -> Result<Serialized, SerializationError> {
+        let flag = *data.first().ok_or(SerializationError::InvalidDataError("Empty compressed frame"))?;
+        if flag == FLAG_UNCOMPRESSED{
+            self.record(data.len() - 1, data.len(), false);
+            return Ok(data[1..].to_vec());
+        }
+        let algorithm = CompressionAlgorithm::from_id(flag)
+            .ok_or(SerializationError::InvalidDataError("Unknown compression algorithm"))?;
+        if data.len() < COMPRESSED_HEADER_SIZE{
+            return Err(SerializationError::LengthError);
+        }
+        let size = u32::from_le_bytes(data[1..COMPRESSED_HEADER_SIZE].try_into().unwrap()) as usize;
+        if size > MAX_DECOMPRESSED_SIZE{
+            return Err(SerializationError::InvalidDataError("Decompressed frame is too large"));
+        }
+        let decompressed = algorithm.decompress(&data[COMPRESSED_HEADER_SIZE..], size)
+            .ok_or(SerializationError::InvalidDataError("Malformed compressed frame"))?;
+        self.record(decompressed.len(), data.len(), true);
+        Ok(decompressed)
+    }
+
+    fn transform(&self, data: &Serialized) -> Serialized {
+        let algorithm = self.algorithm.filter(|_| data.len() >= self.threshold && data.len() <= MAX_DECOMPRESSED_SIZE);
+        if let Some(algorithm) = algorithm{
+            let compressed = algorithm.compress(data);
+            if compressed.len() + COMPRESSED_HEADER_SIZE < data.len(){
+                let mut frame = Vec::with_capacity(compressed.len() + COMPRESSED_HEADER_SIZE);
+                frame.push(algorithm.get_id());
+                frame.extend((data.len() as u32).to_le_bytes());
+                frame.extend(compressed);
+                self.record(data.len(), frame.len(), true);
+                return frame;
+            }
+        }
+        let mut frame = Vec::with_capacity(data.len() + 1);
+        frame.push(FLAG_UNCOMPRESSED);
+        frame.extend_from_slice(data);
+        self.record(data.len(), frame.len(), false);
+        frame
+    }
+}
+
+///
+/// Creates CompressionTransformer. Outgoing frames use the first algorithm of local policy
+/// which remote side supports; frames are sent as is if there is no such algorithm. Must
+/// be added to stack before CryptoTransformer, as encrypted data does not compress.
+///
+pub struct CompressionTransformerFactory{
+    local_serial: u128,
+    policy: CompressionPolicy,
+    stats: SharedCompressionStats,
+}
+
+impl CompressionTransformerFactory {
+    ///
+    /// Creates a factory
+    ///
+    /// # Arguments
+    /// * local_serial: u128: serial of local signing certificate, identifies this side in stats of peer
+    /// * policy: CompressionPolicy: offered algorithms and threshold
+    ///
+    pub fn new(local_serial: u128, policy: CompressionPolicy) -> CompressionTransformerFactory{
+        CompressionTransformerFactory{
+            local_serial,
+            policy,
+            stats: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    ///
+    /// Gets stats of all connections created by factory
+    ///
+    #[inline]
+    pub fn get_stats(&self) -> SharedCompressionStats{
+        self.stats.clone()
+    }
+}
+
+impl TransformerFactory for CompressionTransformerFactory{
+    fn get_descriptor(&self) -> TransformerDescriptor {
+        let algorithms: Vec<u8> = self.policy.algorithms.iter().map(|algorithm| algorithm.get_id()).collect();
+        TransformerDescriptor{
+            name: COMPRESSION_TRANSFORMER_NAME.to_string(),
+            version: COMPRESSION_TRANSFORMER_VERSION,
+            parameters: (self.local_serial, algorithms).serialize(),
+        }
+    }
+
+    fn create(&self, _local: &TransformerDescriptor,
+              remote: &TransformerDescriptor) -> Result<Box<dyn TransportTransformer>, TransformerNegotiationError> {
+        let ((peer_serial, remote_algorithms), _) = <(u128, Vec<u8>)>::from_serialized(&remote.parameters)
+            .map_err(|_| TransformerNegotiationError::InvalidParameters(
+                "Malformed parameters of compression transformer".to_string()))?;
+        let algorithm = self.policy.algorithms.iter()
+            .find(|algorithm| remote_algorithms.contains(&algorithm.get_id()))
+            .cloned();
+        Ok(Box::new(CompressionTransformer::new(algorithm, self.policy.threshold, peer_serial, self.stats.clone())))
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::stack::TransformerStack;
+
+    fn create_stack(serial: u128, algorithms: Vec<CompressionAlgorithm>) -> (TransformerStack, SharedCompressionStats){
+        let factory = CompressionTransformerFactory::new(serial, CompressionPolicy{
+            algorithms,
+            threshold: 64,
+        });
+        let stats = factory.get_stats();
+        let mut stack = TransformerStack::new();
+        stack.add_factory(Box::new(factory));
+        (stack, stats)
+    }
+
+    #[test]
+    fn test_compression_negotiation_and_threshold() {
+        let (client, client_stats) = create_stack(1, vec![CompressionAlgorithm::Lz4, CompressionAlgorithm::Deflate]);
+        let (server, _) = create_stack(2, vec![CompressionAlgorithm::Deflate]);
+        let client_transformer = client.build(&client.get_descriptor(), &server.get_descriptor()).unwrap().remove(0);
+        let server_transformer = server.build(&server.get_descriptor(), &client.get_descriptor()).unwrap().remove(0);
+        let small: Serialized = vec![7; 10];
+        let frame = client_transformer.transform(&small);
+        assert_eq!(frame[0], FLAG_UNCOMPRESSED);
+        assert_eq!(server_transformer.detransform(&frame).unwrap(), small);
+        // Deflate is the only algorithm both sides support
+        let large: Serialized = b"milkyway ".repeat(100);
+        let frame = client_transformer.transform(&large);
+        assert_eq!(frame[0], CompressionAlgorithm::Deflate.get_id());
+        assert!(frame.len() < large.len() / 4);
+        assert_eq!(server_transformer.detransform(&frame).unwrap(), large);
+        // Random data does not compress and is sent as is
+        let random: Serialized = (0..200).map(|_| rand::random::<u8>()).collect();
+        assert_eq!(client_transformer.transform(&random)[0], FLAG_UNCOMPRESSED);
+        let stats = client_stats.lock().unwrap().get(&2).cloned().unwrap();
+        assert_eq!((stats.frames, stats.compressed_frames), (3, 1));
+        assert!(stats.get_saved_bytes() > 800);
+    }
+
+    #[test]
+    fn test_malformed_compressed_frames() {
+        let transformer = CompressionTransformer::new(Some(CompressionAlgorithm::Lz4), 0, 1,
+                                                      Arc::new(Mutex::new(HashMap::new())));
+        let frame = transformer.transform(&vec![1; 1000]);
+        assert_eq!(frame[0], CompressionAlgorithm::Lz4.get_id());
+        assert_eq!(transformer.detransform(&frame).unwrap(), vec![1; 1000]);
+        let mut wrong_size = frame.clone();
+        wrong_size[1] ^= 1;
+        assert!(transformer.detransform(&wrong_size).is_err());
+        assert!(transformer.detransform(&vec![9, 0, 0, 0, 0]).is_err());
+        assert!(transformer.detransform(&vec![]).is_err());
+        let mut bomb = vec![CompressionAlgorithm::Deflate.get_id()];
+        bomb.extend((MAX_DECOMPRESSED_SIZE as u32 + 1).to_le_bytes());
+        assert!(transformer.detransform(&bomb).is_err());
+    }
+}
@@ -1,3 +1,7 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use aes_gcm::Aes256Gcm;
 use crate::transport::Deserializable;
 use crate::transport::Serializable;
 use libmilkyway_derive::{Deserializable, Serializable};
@@ -6,19 +10,375 @@ use crate::pki::hash::HashType;
 use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
 use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
 use crate::pki::impls::CryptoError;
+use crate::pki::impls::keys::kyber1024::{decapsulate_kyber1024_key, encapsulate_kyber1024_key};
+use crate::pki::kdf::derive_session_key;
+use crate::pki::key::CryptoKey;
 use crate::pki::signature::Signature;
 use crate::serialization::error::SerializationError;
 use crate::serialization::serializable::Serialized;
+use crate::services::certificate::usage::{KeyUsage, KeyUsageRecorder};
 use crate::transport::TransportTransformer;
+use crate::get_timestamp_with_milliseconds;
+
+///
+/// How many frames behind the newest received one may arrive out of order
+///
+pub const SEQUENCE_REORDER_WINDOW: u64 = 64;
+
+///
+/// How many decryption or signature failures are tolerated on a connection before
+/// transformer terminates it
+///
+pub const DEFAULT_CRYPTO_FAILURE_THRESHOLD: u64 = 8;
+
+///
+/// How many frames are encrypted with one session key in compact mode before a new one
+/// is encapsulated
+///
+pub const DEFAULT_SESSION_KEY_FRAMES: u64 = 1 << 16;
+
+///
+/// Default amount of alerts kept in audit of CryptoAlerts
+///
+pub const DEFAULT_CRYPTO_AUDIT_CAPACITY: usize = 256;
+
+///
+/// Kind of cryptographic alert raised by transformer
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CryptoAlertKind{
+    /** Frame signature does not match remote signing certificate **/
+    SignatureFailure,
+    /** Frame can not be decoded or decrypted **/
+    DecryptionFailure,
+    /** Frame is replayed or reordered beyond window **/
+    SequenceViolation,
+    /** Too many failures, connection is terminated **/
+    ThresholdExceeded,
+}
+
+///
+/// Counters of cryptographic failures on a connection
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CryptoFailureStats{
+    pub signature_failures: u64,
+    pub decryption_failures: u64,
+}
+
+impl CryptoFailureStats {
+    ///
+    /// Total amount of failures
+    ///
+    #[inline]
+    pub fn total(&self) -> u64{
+        self.signature_failures + self.decryption_failures
+    }
+}
+
+///
+/// An alert raised on cryptographic failure, may indicate active tampering
+/// or misconfigured certificate chains
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct CryptoAlert{
+    pub kind: CryptoAlertKind,
+    /** Serial of remote signing certificate of connection **/
+    pub remote_serial: u128,
+    /** Name of remote signing certificate of connection **/
+    pub remote_name: String,
+    /** Failure counters at the moment of alert **/
+    pub stats: CryptoFailureStats,
+}
+
+///
+/// A subscriber of cryptographic alerts
+///
+pub trait CryptoAlertListener: Send{
+    ///
+    /// Called when alert is raised
+    ///
+    /// # Arguments
+    /// * alert: &CryptoAlert: raised alert
+    ///
+    fn on_alert(&mut self, alert: &CryptoAlert);
+}
+
+impl<F: FnMut(&CryptoAlert) + Send> CryptoAlertListener for F{
+    fn on_alert(&mut self, alert: &CryptoAlert) {
+        self(alert)
+    }
+}
+
+///
+/// Record about raised alert
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct CryptoAuditEntry{
+    /** When alert was raised **/
+    pub timestamp: u128,
+    pub alert: CryptoAlert,
+}
+
+struct AlertSubscription{
+    id: u128,
+    /** Module which made subscription, if any **/
+    owner: Option<u64>,
+    listener: Box<dyn CryptoAlertListener>,
+}
+
+///
+/// Alerts of all connections of host. Transformers created by CryptoTransformerFactory publish
+/// alerts here, they are kept in audit and passed to subscribers, e.g. modules.
+///
+pub struct CryptoAlerts{
+    subscriptions: Vec<AlertSubscription>,
+    last_subscription_id: u128,
+    audit_capacity: usize,
+    audit: Vec<CryptoAuditEntry>,
+}
+
+///
+/// Crypto alerts shared between transformers and transport service
+///
+pub type SharedCryptoAlerts = Arc<Mutex<CryptoAlerts>>;
+
+impl Default for CryptoAlerts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CryptoAlerts {
+    pub fn new() -> CryptoAlerts{
+        CryptoAlerts{
+            subscriptions: Vec::new(),
+            last_subscription_id: 0,
+            audit_capacity: DEFAULT_CRYPTO_AUDIT_CAPACITY,
+            audit: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn new_shared() -> SharedCryptoAlerts{
+        Arc::new(Mutex::new(Self::new()))
+    }
+
+    ///
+    /// Sets how many audit entries to keep, older entries are discarded
+    ///
+    /// # Panics
+    /// * If capacity is zero
+    ///
+    pub fn set_audit_capacity(&mut self, capacity: usize) -> &mut CryptoAlerts{
+        if capacity == 0{
+            panic!("Capacity of crypto audit must be positive");
+        }
+        self.audit_capacity = capacity;
+        self.trim_audit();
+        self
+    }
+
+    ///
+    /// Subscribes listener to alerts of all connections
+    ///
+    /// returns: u128: ID of subscription
+    ///
+    #[inline]
+    pub fn subscribe(&mut self, listener: Box<dyn CryptoAlertListener>) -> u128{
+        self.add_subscription(None, listener)
+    }
+
+    ///
+    /// Subscribes listener on behalf of module, so subscription is removed by unsubscribe_all
+    ///
+    #[inline]
+    pub fn subscribe_owned(&mut self, module_id: u64, listener: Box<dyn CryptoAlertListener>) -> u128{
+        self.add_subscription(Some(module_id), listener)
+    }
+
+    fn add_subscription(&mut self, owner: Option<u64>, listener: Box<dyn CryptoAlertListener>) -> u128{
+        self.last_subscription_id += 1;
+        self.subscriptions.push(AlertSubscription{
+            id: self.last_subscription_id,
+            owner,
+            listener,
+        });
+        self.last_subscription_id
+    }
+
+    ///
+    /// Removes subscription
+    ///
+    /// returns: bool: whether subscription existed
+    ///
+    pub fn unsubscribe(&mut self, subscription_id: u128) -> bool{
+        let count = self.subscriptions.len();
+        self.subscriptions.retain(|subscription| subscription.id != subscription_id);
+        self.subscriptions.len() != count
+    }
+
+    ///
+    /// Removes all subscriptions of module
+    ///
+    /// returns: usize: count of removed subscriptions
+    ///
+    pub fn unsubscribe_all(&mut self, module_id: u64) -> usize{
+        let count = self.subscriptions.len();
+        self.subscriptions.retain(|subscription| subscription.owner != Some(module_id));
+        count - self.subscriptions.len()
+    }
+
+    ///
+    /// Records alert in audit and passes it to subscribers
+    ///
+    pub fn publish(&mut self, alert: &CryptoAlert){
+        self.audit.push(CryptoAuditEntry{
+            timestamp: get_timestamp_with_milliseconds(),
+            alert: alert.clone(),
+        });
+        self.trim_audit();
+        for subscription in self.subscriptions.iter_mut(){
+            subscription.listener.on_alert(alert);
+        }
+    }
+
+    ///
+    /// Gets audit entries, oldest first
+    ///
+    #[inline]
+    pub fn get_audit(&self) -> &[CryptoAuditEntry]{
+        &self.audit
+    }
+
+    #[inline]
+    pub fn clear_audit(&mut self){
+        self.audit.clear();
+    }
+
+    fn trim_audit(&mut self){
+        if self.audit.len() > self.audit_capacity{
+            let excess = self.audit.len() - self.audit_capacity;
+            self.audit.drain(..excess);
+        }
+    }
+}
+
+///
+/// Sliding window of received sequence numbers.
+/// Allows frames to arrive slightly reordered, but rejects duplicates and
+/// frames which are too old. Sequence of each session starts at 0.
+///
+struct SequenceWindow{
+    /** Highest sequence number received so far, 0 until anything is received **/
+    highest: u64,
+    /** Bit N is set if frame `highest - N` was received **/
+    received_mask: u64,
+}
+
+impl SequenceWindow {
+    fn new() -> SequenceWindow{
+        SequenceWindow{
+            highest: 0,
+            received_mask: 0,
+        }
+    }
+
+    ///
+    /// Registers received sequence number
+    ///
+    /// # Arguments
+    /// * sequence: u64: sequence number of received frame
+    ///
+    /// returns: bool: true if frame is acceptable, false if it is replayed or too old
+    ///
+    fn accept(&mut self, sequence: u64) -> bool{
+        let highest = self.highest;
+        if sequence > highest{
+            let shift = sequence - highest;
+            self.received_mask = if shift >= SEQUENCE_REORDER_WINDOW {
+                0
+            } else {
+                self.received_mask << shift
+            };
+            self.received_mask |= 1;
+            self.highest = sequence;
+            return true;
+        }
+        let offset = highest - sequence;
+        if offset >= SEQUENCE_REORDER_WINDOW{
+            // Frame is too old to tell whether it was already received
+            return false;
+        }
+        let bit = 1u64 << offset;
+        if self.received_mask & bit != 0{
+            // Replayed frame
+            return false;
+        }
+        self.received_mask |= bit;
+        true
+    }
+}
 
 ///
 /// Transforms and detransforms encrypted and signed data
 ///
+/// Each frame carries a per-direction sequence number starting at 0 covered by signature,
+/// so recorded frames can not be replayed or reordered beyond `SEQUENCE_REORDER_WINDOW`.
+/// On such violation the transformer terminates the session and refuses any further data.
+/// Signature also covers nonce receiving side chose for session(see TransformerStack), so
+/// frames recorded in earlier sessions do not verify.
+///
+/// Signature and decryption failures are counted and reported to alert listeners. Once
+/// their total reaches failure threshold the session is terminated as well.
+///
+/// By default every frame carries its own Kyber ciphertext(~1.5KB). In compact mode the
+/// ciphertext of a session key is sent only with first frame of a key epoch, other frames
+/// carry AES-GCM output and nonce only. Keys of epochs are derived from encapsulated secret
+/// with HKDF bound to encryption certificate and epoch. Compact mode relies on ordered
+/// transport: a frame arriving before the frame which introduced its key can not be decrypted.
+///
 pub struct CryptoTransformer{
     local_signing_cert: Falcon1024Certificate,
     local_encryption_cert: Kyber1024Certificate,
     remote_signing_cert: Falcon1024Certificate,
     remote_encryption_cert: Kyber1024Certificate,
+    /** Nonce of session chosen by local side, received frames are signed for it **/
+    local_nonce: u128,
+    /** Nonce of session chosen by remote side, sent frames are signed for it **/
+    remote_nonce: u128,
+    send_sequence: AtomicU64,
+    receive_window: Mutex<SequenceWindow>,
+    terminated: AtomicBool,
+    signature_failures: AtomicU64,
+    decryption_failures: AtomicU64,
+    failure_threshold: u64,
+    alert_listeners: Mutex<Vec<Box<dyn CryptoAlertListener>>>,
+    compact: bool,
+    session_key_frames: u64,
+    send_session: Mutex<Option<SendSession>>,
+    receive_keys: Mutex<HashMap<u32, aes_gcm::Key<Aes256Gcm>>>,
+    usage_recorder: Option<Arc<KeyUsageRecorder>>,
+}
+
+///
+/// Session key used for sending in compact mode
+///
+struct SendSession{
+    epoch: u32,
+    key: aes_gcm::Key<Aes256Gcm>,
+    frames: u64,
+}
+
+///
+/// Encrypted data of a frame in compact mode
+///
+#[derive(Serializable, Deserializable, Debug)]
+struct SessionFrame{
+    epoch: u32,
+    /** Kyber ciphertext of session key, present only in first frame of epoch **/
+    cipher_text: Option<Serialized>,
+    data: Serialized,
 }
 
 ///
@@ -27,50 +387,276 @@ pub struct CryptoTransformer{
 #[derive(Serializable, Deserializable, Debug)]
 pub struct CryptoMessage{
     signature: Signature,
+    sequence: u64,
     data: Serialized,
 }
 
+impl CryptoMessage {
+    ///
+    /// Builds data covered by signature: nonce of receiving side, sequence number and encrypted data
+    ///
+    fn signable(nonce: u128, sequence: u64, data: &Serialized) -> Serialized{
+        let mut result = nonce.serialize();
+        result.extend(sequence.serialize());
+        result.extend(data);
+        result
+    }
+}
+
 impl CryptoTransformer {
+    ///
+    /// Creates transformer of one session
+    ///
+    /// # Arguments
+    /// * local_signing_cert: Falcon1024Certificate: local signing certificate with secret key
+    /// * local_encryption_cert: Kyber1024Certificate: local encryption certificate with secret key
+    /// * remote_signing_cert: Falcon1024Certificate: signing certificate of remote side
+    /// * remote_encryption_cert: Kyber1024Certificate: encryption certificate of remote side
+    /// * nonces: (u128, u128): random nonces local and remote side chose for this session
+    ///
     #[inline]
     pub fn new(local_signing_cert: Falcon1024Certificate,
                local_encryption_cert: Kyber1024Certificate,
                remote_signing_cert: Falcon1024Certificate,
-               remote_encryption_cert: Kyber1024Certificate) -> CryptoTransformer{
+               remote_encryption_cert: Kyber1024Certificate,
+               nonces: (u128, u128)) -> CryptoTransformer{
         CryptoTransformer{
             local_signing_cert,
             local_encryption_cert,
             remote_signing_cert,
             remote_encryption_cert,
+            local_nonce: nonces.0,
+            remote_nonce: nonces.1,
+            send_sequence: AtomicU64::new(0),
+            receive_window: Mutex::new(SequenceWindow::new()),
+            terminated: AtomicBool::new(false),
+            signature_failures: AtomicU64::new(0),
+            decryption_failures: AtomicU64::new(0),
+            failure_threshold: DEFAULT_CRYPTO_FAILURE_THRESHOLD,
+            alert_listeners: Mutex::new(Vec::new()),
+            compact: false,
+            session_key_frames: DEFAULT_SESSION_KEY_FRAMES,
+            send_session: Mutex::new(None),
+            receive_keys: Mutex::new(HashMap::new()),
+            usage_recorder: None,
+        }
+    }
+
+    ///
+    /// Enables compact mode, both sides of connection must use the same mode
+    ///
+    /// # Arguments
+    /// * compact: bool: whether to reuse session keys instead of encapsulating key for every frame
+    ///
+    #[inline]
+    pub fn set_compact(&mut self, compact: bool){
+        self.compact = compact;
+    }
+
+    ///
+    /// Sets recorder counting signatures of local signing key and bytes encrypted to
+    /// remote encryption key
+    ///
+    pub fn set_usage_recorder(&mut self, recorder: Arc<KeyUsageRecorder>){
+        self.usage_recorder = Some(recorder);
+    }
+
+    ///
+    /// Sets how many frames are encrypted with one session key in compact mode
+    ///
+    /// # Panics
+    /// * If amount is zero
+    ///
+    pub fn set_session_key_frames(&mut self, frames: u64){
+        if frames == 0{
+            panic!("Session key must encrypt at least one frame");
+        }
+        self.session_key_frames = frames;
+    }
+
+    ///
+    /// Encrypts data with session key, encapsulating a new one when current is used up
+    ///
+    fn encrypt_compact(&self, data: &Serialized) -> Serialized{
+        let mut session = self.send_session.lock().unwrap();
+        let mut cipher_text = None;
+        if session.as_ref().is_none_or(|session| session.frames >= self.session_key_frames){
+            let (encapsulated, shared_key) = encapsulate_kyber1024_key(&self.remote_encryption_cert.public_key);
+            let epoch = session.as_ref().map_or(0, |session| session.epoch.wrapping_add(1));
+            cipher_text = Some(encapsulated);
+            *session = Some(SendSession{
+                epoch,
+                key: derive_session_key(&shared_key, self.remote_encryption_cert.serial_number, epoch),
+                frames: 0,
+            });
+        }
+        let session = session.as_mut().unwrap();
+        session.frames += 1;
+        let frame = SessionFrame{
+            epoch: session.epoch,
+            cipher_text,
+            data: session.key.encrypt_raw(data).expect("Can not encrypt local packet"),
+        };
+        frame.serialize()
+    }
+
+    ///
+    /// Decrypts data encrypted by encrypt_compact of remote side
+    ///
+    fn decrypt_compact(&self, data: &Serialized) -> Result<Serialized, CryptoError>{
+        let (frame, _) = SessionFrame::from_serialized(data).map_err(|_| CryptoError::FormatError)?;
+        let mut keys = self.receive_keys.lock().unwrap();
+        if let Some(cipher_text) = &frame.cipher_text{
+            let secret_key = self.local_encryption_cert.secret_key.as_ref()
+                .ok_or(CryptoError::ArgumentError("Local encryption certificate has no secret key"))?;
+            let shared_key = decapsulate_kyber1024_key(secret_key, cipher_text)?;
+            let key = derive_session_key(&shared_key, self.local_encryption_cert.serial_number, frame.epoch);
+            // Previous key is kept for frames reordered around key change
+            let previous = frame.epoch.wrapping_sub(1);
+            keys.retain(|epoch, _| *epoch == previous);
+            keys.insert(frame.epoch, key);
+        }
+        let key = keys.get(&frame.epoch).ok_or(CryptoError::FormatError)?;
+        key.decrypt_raw(&frame.data)
+    }
+
+    ///
+    /// Sets how many failures are tolerated before session is terminated
+    ///
+    /// # Arguments
+    /// * threshold: u64: amount of failures, 0 disables termination on failures
+    ///
+    #[inline]
+    pub fn set_failure_threshold(&mut self, threshold: u64){
+        self.failure_threshold = threshold;
+    }
+
+    ///
+    /// Subscribes to alerts of this transformer
+    ///
+    /// # Arguments
+    /// * listener: Box<dyn CryptoAlertListener>: alert subscriber
+    ///
+    pub fn add_alert_listener(&self, listener: Box<dyn CryptoAlertListener>){
+        self.alert_listeners.lock().unwrap().push(listener);
+    }
+
+    ///
+    /// Publishes alerts of this transformer to alerts of host
+    ///
+    pub fn publish_alerts_to(&self, alerts: SharedCryptoAlerts){
+        self.add_alert_listener(Box::new(move |alert: &CryptoAlert| alerts.lock().unwrap().publish(alert)));
+    }
+
+    ///
+    /// Gets failure counters of this connection
+    ///
+    pub fn get_failure_stats(&self) -> CryptoFailureStats{
+        CryptoFailureStats{
+            signature_failures: self.signature_failures.load(Ordering::SeqCst),
+            decryption_failures: self.decryption_failures.load(Ordering::SeqCst),
+        }
+    }
+
+    fn raise_alert(&self, kind: CryptoAlertKind){
+        let alert = CryptoAlert{
+            kind,
+            remote_serial: self.remote_signing_cert.get_serial(),
+            remote_name: self.remote_signing_cert.get_name(),
+            stats: self.get_failure_stats(),
+        };
+        log::warn!("Crypto alert {:?} on connection with {}(serial {}): {} signature and {} decryption failures",
+            alert.kind, alert.remote_name, alert.remote_serial,
+            alert.stats.signature_failures, alert.stats.decryption_failures);
+        for listener in self.alert_listeners.lock().unwrap().iter_mut(){
+            listener.on_alert(&alert);
+        }
+    }
+
+    ///
+    /// Counts a failure, raises alert and terminates session if threshold is reached
+    ///
+    fn register_failure(&self, kind: CryptoAlertKind){
+        match kind {
+            CryptoAlertKind::SignatureFailure => self.signature_failures.fetch_add(1, Ordering::SeqCst),
+            CryptoAlertKind::DecryptionFailure => self.decryption_failures.fetch_add(1, Ordering::SeqCst),
+            _ => 0,
+        };
+        self.raise_alert(kind);
+        if self.failure_threshold != 0 && self.get_failure_stats().total() >= self.failure_threshold
+            && !self.terminated.swap(true, Ordering::SeqCst){
+            log::error!("Too many cryptographic failures, terminating session");
+            self.raise_alert(CryptoAlertKind::ThresholdExceeded);
         }
     }
 }
 
 impl TransportTransformer for CryptoTransformer{
     fn detransform(&self, data: &Serialized) -> Result<Serialized, SerializationError> {
+        if self.is_terminated(){
+            return Err(SerializationError::CryptographicError(CryptoError::SequenceViolation));
+        }
         let message_result = CryptoMessage::from_serialized(data);
         if message_result.is_err(){
+            self.register_failure(CryptoAlertKind::DecryptionFailure);
             return Err(message_result.err().unwrap());
         }
         let (message, _) = message_result.unwrap();
-        if !self.remote_signing_cert.verify_signature(&message.data, &message.signature){
+        let signable = CryptoMessage::signable(self.local_nonce, message.sequence, &message.data);
+        if !self.remote_signing_cert.verify_signature(&signable, &message.signature){
+            self.register_failure(CryptoAlertKind::SignatureFailure);
             return Err(SerializationError::CryptographicError(CryptoError::DataTampered));
         }
-        let decrypted_data_result =
-            self.local_encryption_cert.decrypt::<Vec<u8>>(&message.data);
-        decrypted_data_result
+        // Sequence is checked only after signature, so forged frames can not move the window
+        if !self.receive_window.lock().unwrap().accept(message.sequence){
+            log::error!("Frame with sequence {} is replayed or reordered, terminating session",
+                message.sequence);
+            self.terminated.store(true, Ordering::SeqCst);
+            self.raise_alert(CryptoAlertKind::SequenceViolation);
+            return Err(SerializationError::CryptographicError(CryptoError::SequenceViolation));
+        }
+        let decrypted_data_result = if self.compact{
+            self.decrypt_compact(&message.data).map_err(SerializationError::CryptographicError)
+        } else {
+            self.local_encryption_cert.decrypt::<Vec<u8>>(&message.data)
+        };
+        if decrypted_data_result.is_err(){
+            self.register_failure(CryptoAlertKind::DecryptionFailure);
+        }
+        // Malformed plaintext is reported same as malformed ciphertext
+        decrypted_data_result.map_err(|error| match error {
+            SerializationError::CryptographicError(_) => error,
+            _ => SerializationError::CryptographicError(CryptoError::FormatError),
+        })
     }
 
     fn transform(&self, data: &Serialized) -> Serialized {
-        let encrypted_data = self.remote_encryption_cert.encrypt(data)
-            .expect("Can not encrypt local packet");
+        let encrypted_data = if self.compact{
+            self.encrypt_compact(data)
+        } else {
+            self.remote_encryption_cert.encrypt(data).expect("Can not encrypt local packet")
+        };
+        let sequence = self.send_sequence.fetch_add(1, Ordering::SeqCst);
         let signature = self.local_signing_cert
-            .sign_data(&encrypted_data, HashType::None).expect("Can not sign local packet");
+            .sign_data(&CryptoMessage::signable(self.remote_nonce, sequence, &encrypted_data), HashType::None)
+            .expect("Can not sign local packet");
+        if let Some(recorder) = &self.usage_recorder{
+            recorder.record(KeyUsage::new(self.local_signing_cert.get_serial()).set_signatures(1));
+            recorder.record(KeyUsage::new(self.remote_encryption_cert.get_serial())
+                .set_encrypted_bytes(data.len() as u64));
+        }
         let message = CryptoMessage{
             signature,
+            sequence,
             data: encrypted_data,
         };
         message.serialize()
     }
+
+    #[inline]
+    fn is_terminated(&self) -> bool {
+        self.terminated.load(Ordering::SeqCst)
+    }
 }
 
 
@@ -84,17 +670,20 @@ mod tests {
     use crate::serialization::deserializable::Deserializable;
     use crate::serialization::error::SerializationError;
     use crate::pki::impls::CryptoError;
-    use crate::pki::impls::keys::falcon1024::generate_falcon1024_keypair;
-    use crate::pki::impls::keys::kyber1024::generate_kyber1024_keypair;
+    use crate::pki::impls::keys::falcon1024::generate_falcon1024_keypair_from_seed;
+    use crate::pki::impls::keys::kyber1024::generate_kyber1024_keypair_from_seed;
     use crate::transport::TransportTransformer;
+    use std::sync::Arc;
+
+    const TEST_NONCES: (u128, u128) = (1, 2);
 
     #[derive(Serializable, Deserializable, PartialEq, Debug)]
     struct TestData {
         message: String,
     }
 
-    fn generate_falcon1024_certificate() -> Falcon1024Certificate {
-        let (public_key, secret_key) = generate_falcon1024_keypair();
+    fn generate_falcon1024_certificate(seed: &[u8]) -> Falcon1024Certificate {
+        let (public_key, secret_key) = generate_falcon1024_keypair_from_seed(seed);
         Falcon1024Certificate {
             serial_number: 1,
             parent_serial_number: 0,
@@ -103,11 +692,12 @@ mod tests {
             signature: None,
             name: "test".to_string(),
             flags: 0,
+            metadata: Default::default(),
         }
     }
 
-    fn generate_kyber1024_certificate() -> Kyber1024Certificate {
-        let (public_key, secret_key) = generate_kyber1024_keypair();
+    fn generate_kyber1024_certificate(seed: &[u8]) -> Kyber1024Certificate {
+        let (public_key, secret_key) = generate_kyber1024_keypair_from_seed(seed);
         Kyber1024Certificate {
             serial_number: 1,
             parent_serial_number: 0,
@@ -116,16 +706,17 @@ mod tests {
             signature: None,
             name: "test".to_string(),
             flags: 0,
+            metadata: Default::default(),
         }
     }
 
     #[test]
     fn test_crypto_transformer_transform_and_detransform() {
         // Generate certificates
-        let local_signing_cert = generate_falcon1024_certificate();
-        let local_encryption_cert = generate_kyber1024_certificate();
-        let remote_signing_cert = generate_falcon1024_certificate();
-        let remote_encryption_cert = generate_kyber1024_certificate();
+        let local_signing_cert = generate_falcon1024_certificate(b"local");
+        let local_encryption_cert = generate_kyber1024_certificate(b"local");
+        let remote_signing_cert = generate_falcon1024_certificate(b"remote");
+        let remote_encryption_cert = generate_kyber1024_certificate(b"remote");
 
         // Initialize the CryptoTransformer
         let transformer = CryptoTransformer::new(
@@ -133,6 +724,7 @@ mod tests {
             local_encryption_cert.clone(),
             remote_signing_cert.clone_without_signature_and_sk(),
             remote_encryption_cert.clone_without_signature_and_sk(),
+            TEST_NONCES,
         );
 
         let detransformer = CryptoTransformer::new(
@@ -140,6 +732,7 @@ mod tests {
             remote_encryption_cert.clone(),
             local_signing_cert.clone_without_signature_and_sk(),
             local_encryption_cert.clone_without_signature_and_sk(),
+            (TEST_NONCES.1, TEST_NONCES.0),
         );
 
         // Create test data
@@ -160,10 +753,10 @@ mod tests {
     #[test]
     fn test_crypto_transformer_detransform_with_tampering() {
         // Generate certificates
-        let local_signing_cert = generate_falcon1024_certificate();
-        let local_encryption_cert = generate_kyber1024_certificate();
-        let remote_signing_cert = generate_falcon1024_certificate();
-        let remote_encryption_cert = generate_kyber1024_certificate();
+        let local_signing_cert = generate_falcon1024_certificate(b"local");
+        let local_encryption_cert = generate_kyber1024_certificate(b"local");
+        let remote_signing_cert = generate_falcon1024_certificate(b"remote");
+        let remote_encryption_cert = generate_kyber1024_certificate(b"remote");
 
         // Initialize the CryptoTransformer
         let transformer = CryptoTransformer::new(
@@ -171,6 +764,7 @@ mod tests {
             local_encryption_cert.clone(),
             remote_signing_cert.clone_without_signature_and_sk(),
             remote_encryption_cert.clone_without_signature_and_sk(),
+            TEST_NONCES,
         );
 
         let detransformer = CryptoTransformer::new(
@@ -178,6 +772,7 @@ mod tests {
             remote_encryption_cert.clone(),
             local_signing_cert.clone_without_signature_and_sk(),
             local_encryption_cert.clone_without_signature_and_sk(),
+            (TEST_NONCES.1, TEST_NONCES.0),
         );
 
         // Create test data
@@ -201,10 +796,10 @@ mod tests {
     #[test]
     fn test_crypto_transformer_detransform_with_invalid_data() {
         // Generate certificates
-        let local_signing_cert = generate_falcon1024_certificate();
-        let local_encryption_cert = generate_kyber1024_certificate();
-        let remote_signing_cert = generate_falcon1024_certificate();
-        let remote_encryption_cert = generate_kyber1024_certificate();
+        let local_signing_cert = generate_falcon1024_certificate(b"local");
+        let local_encryption_cert = generate_kyber1024_certificate(b"local");
+        let remote_signing_cert = generate_falcon1024_certificate(b"remote");
+        let remote_encryption_cert = generate_kyber1024_certificate(b"remote");
 
         // Initialize the CryptoTransformer
         let detransformer = CryptoTransformer::new(
@@ -212,6 +807,7 @@ mod tests {
             remote_encryption_cert.clone(),
             local_signing_cert.clone_without_signature_and_sk(),
             local_encryption_cert.clone_without_signature_and_sk(),
+            (TEST_NONCES.1, TEST_NONCES.0),
         );
 
         // Create invalid data
@@ -221,4 +817,168 @@ mod tests {
         let detransform_result = detransformer.detransform(&invalid_data);
         assert!(detransform_result.is_err());
     }
-}
\ No newline at end of file
+
+    fn create_transformer_pair() -> (CryptoTransformer, CryptoTransformer) {
+        let local_signing_cert = generate_falcon1024_certificate(b"local");
+        let local_encryption_cert = generate_kyber1024_certificate(b"local");
+        let remote_signing_cert = generate_falcon1024_certificate(b"remote");
+        let remote_encryption_cert = generate_kyber1024_certificate(b"remote");
+        let transformer = CryptoTransformer::new(
+            local_signing_cert.clone(),
+            local_encryption_cert.clone(),
+            remote_signing_cert.clone_without_signature_and_sk(),
+            remote_encryption_cert.clone_without_signature_and_sk(),
+            TEST_NONCES,
+        );
+        let detransformer = CryptoTransformer::new(
+            remote_signing_cert,
+            remote_encryption_cert,
+            local_signing_cert.clone_without_signature_and_sk(),
+            local_encryption_cert.clone_without_signature_and_sk(),
+            (TEST_NONCES.1, TEST_NONCES.0),
+        );
+        (transformer, detransformer)
+    }
+
+    #[test]
+    fn test_crypto_transformer_replay_terminates_session() {
+        let (transformer, detransformer) = create_transformer_pair();
+        let first = transformer.transform(&vec![1u8, 2, 3].serialize());
+        let second = transformer.transform(&vec![4u8, 5, 6].serialize());
+
+        assert!(detransformer.detransform(&first).is_ok());
+        let replay_result = detransformer.detransform(&first);
+        assert_eq!(replay_result.err().unwrap(),
+                   SerializationError::CryptographicError(CryptoError::SequenceViolation));
+        assert!(detransformer.is_terminated());
+        // Even valid frames are rejected after termination
+        assert!(detransformer.detransform(&second).is_err());
+    }
+
+    #[test]
+    fn test_crypto_transformer_rejects_other_session() {
+        let (transformer, _) = create_transformer_pair();
+        let (_, mut detransformer) = create_transformer_pair();
+        let frame = transformer.transform(&vec![1u8].serialize());
+        assert!(detransformer.detransform(&frame).is_ok());
+        // Same certificates, but receiving side chose another nonce
+        detransformer.local_nonce = 3;
+        detransformer.receive_window = Mutex::new(SequenceWindow::new());
+        assert_eq!(detransformer.detransform(&frame).err().unwrap(),
+                   SerializationError::CryptographicError(CryptoError::DataTampered));
+    }
+
+    #[test]
+    fn test_crypto_transformer_reordering_within_window() {
+        let (transformer, detransformer) = create_transformer_pair();
+        let first = transformer.transform(&vec![1u8].serialize());
+        let second = transformer.transform(&vec![2u8].serialize());
+        let third = transformer.transform(&vec![3u8].serialize());
+
+        assert!(detransformer.detransform(&third).is_ok());
+        assert!(detransformer.detransform(&first).is_ok());
+        assert!(detransformer.detransform(&second).is_ok());
+        assert!(!detransformer.is_terminated());
+    }
+
+    #[test]
+    fn test_sequence_window_rejects_old_frames() {
+        let mut window = SequenceWindow::new();
+        assert!(window.accept(0));
+        assert!(!window.accept(0));
+        assert!(window.accept(SEQUENCE_REORDER_WINDOW + 10));
+        assert!(!window.accept(5));
+        assert!(window.accept(SEQUENCE_REORDER_WINDOW + 9));
+        assert!(!window.accept(SEQUENCE_REORDER_WINDOW + 9));
+        // Window starts at 0, so first frame of session is tracked even if it arrives late
+        let mut window = SequenceWindow::new();
+        assert!(window.accept(1));
+        assert!(window.accept(0));
+        assert!(!window.accept(0));
+    }
+
+    struct RecordingListener{
+        alerts: Arc<Mutex<Vec<CryptoAlert>>>,
+    }
+
+    impl CryptoAlertListener for RecordingListener{
+        fn on_alert(&mut self, alert: &CryptoAlert) {
+            self.alerts.lock().unwrap().push(alert.clone());
+        }
+    }
+
+    #[test]
+    fn test_crypto_transformer_failures_raise_alerts_and_terminate() {
+        let (transformer, mut detransformer) = create_transformer_pair();
+        detransformer.set_failure_threshold(2);
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        detransformer.add_alert_listener(Box::new(RecordingListener{ alerts: alerts.clone() }));
+
+        let mut tampered = transformer.transform(&vec![1u8].serialize());
+        tampered[10] ^= 0xFF;
+        assert!(detransformer.detransform(&tampered).is_err());
+        assert!(!detransformer.is_terminated());
+        assert!(detransformer.detransform(&vec![1, 2, 3]).is_err());
+        assert!(detransformer.is_terminated());
+
+        let stats = detransformer.get_failure_stats();
+        assert_eq!(stats.signature_failures, 1);
+        assert_eq!(stats.decryption_failures, 1);
+        let kinds: Vec<CryptoAlertKind> = alerts.lock().unwrap().iter().map(|alert| alert.kind).collect();
+        assert_eq!(kinds, vec![CryptoAlertKind::SignatureFailure, CryptoAlertKind::DecryptionFailure,
+                               CryptoAlertKind::ThresholdExceeded]);
+        assert_eq!(alerts.lock().unwrap()[0].remote_serial, 1);
+    }
+
+    #[test]
+    fn test_crypto_transformer_malformed_ciphertext_in_signed_frame() {
+        let local_signing_cert = generate_falcon1024_certificate(b"local");
+        let (_, detransformer) = create_transformer_pair();
+        // Peer with valid signing certificate sends garbage instead of ciphertext
+        for (sequence, data) in [vec![], vec![0u8; 7], vec![0xFFu8; 32].serialize()].into_iter().enumerate(){
+            let sequence = sequence as u64;
+            let message = CryptoMessage{
+                signature: local_signing_cert.sign_data(&CryptoMessage::signable(TEST_NONCES.1, sequence, &data),
+                                                        HashType::None).unwrap(),
+                sequence,
+                data,
+            };
+            let result = detransformer.detransform(&message.serialize());
+            assert!(matches!(result, Err(SerializationError::CryptographicError(_))));
+        }
+    }
+
+    #[test]
+    fn test_crypto_transformer_compact_mode() {
+        let (mut transformer, mut detransformer) = create_transformer_pair();
+        let full = transformer.transform(&vec![1u8].serialize());
+        transformer.set_compact(true);
+        transformer.set_session_key_frames(2);
+        detransformer.set_compact(true);
+        assert!(detransformer.detransform(&full).is_err());
+
+        let frames: Vec<Serialized> = (0..3u8).map(|index| transformer.transform(&vec![index].serialize())).collect();
+        // Only first frame of each key epoch carries Kyber ciphertext
+        assert!(frames[1].len() + 1000 < frames[0].len());
+        assert!(frames[2].len() > frames[1].len() + 1000);
+        for (index, frame) in frames.iter().enumerate(){
+            let data = detransformer.detransform(frame).unwrap();
+            assert_eq!(Vec::<u8>::from_serialized(&data).unwrap().0, vec![index as u8]);
+        }
+    }
+
+    #[test]
+    fn test_crypto_transformer_records_usage() {
+        let (mut transformer, _) = create_transformer_pair();
+        let recorder = Arc::new(KeyUsageRecorder::new());
+        transformer.set_usage_recorder(recorder.clone());
+        transformer.transform(&vec![1u8; 10]);
+        transformer.transform(&vec![2u8; 20]);
+        // Test certificates share serial, so usage of both keys is merged
+        assert_eq!(transformer.local_signing_cert.get_serial(), transformer.remote_encryption_cert.get_serial());
+        let expected = KeyUsage::new(transformer.local_signing_cert.get_serial())
+            .set_signatures(2).set_encrypted_bytes(30).clone();
+        assert_eq!(recorder.take(), vec![expected]);
+        assert!(recorder.take().is_empty());
+    }
+}
@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use libmilkyway_derive::{Deserializable, EnumDeserializable, EnumSerializable, Serializable};
+use crate::get_timestamp_with_milliseconds;
+use crate::message::common::Message;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::migration::{dump_versioned, load_versioned, VersionedStorage};
+use crate::serialization::serializable::{Serializable, Serialized};
+
+///
+/// Default count of failed deliveries after which message is moved to dead-letter queue
+///
+pub const DEFAULT_MAX_DELIVERY_FAILURES: u32 = 3;
+
+///
+/// Default count of dead letters kept before the oldest ones are dropped
+///
+pub const DEFAULT_DEAD_LETTER_CAPACITY: u64 = 1024;
+
+///
+/// Why message could not be processed
+///
+#[derive(Clone, Copy, Debug, PartialEq, EnumSerializable, EnumDeserializable)]
+pub enum DeadLetterKind{
+    /** Received frame is not a message **/
+    Undeserializable,
+    /** Listener panicked while handling message **/
+    ListenerPanicked,
+}
+
+impl DeadLetterKind {
+    pub fn get_name(&self) -> &'static str{
+        match self {
+            DeadLetterKind::Undeserializable => "undeserializable",
+            DeadLetterKind::ListenerPanicked => "listener-panicked",
+        }
+    }
+}
+
+///
+/// A message which could not be processed
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable)]
+pub struct DeadLetter{
+    /** ID of letter within queue, assigned in order letters arrive **/
+    pub id: u64,
+    /** ID of message, 0 if frame could not be deserialized **/
+    pub message_id: u128,
+    pub source: u128,
+    pub module_id: u64,
+    pub kind: DeadLetterKind,
+    /** Error of last failed delivery **/
+    pub error: String,
+    pub failures: u32,
+    /** When message was dead-lettered **/
+    pub timestamp: u128,
+    /** Serialized message or raw frame if it is not a message **/
+    pub data: Serialized,
+    /** Whether letter should be delivered again once queue is loaded by transport **/
+    pub replay_requested: bool,
+}
+
+impl DeadLetter {
+    ///
+    /// Gets message of letter
+    ///
+    /// returns: Option<Message>: message or None if letter holds a frame which is not a message
+    ///
+    pub fn get_message(&self) -> Option<Message>{
+        match self.kind {
+            DeadLetterKind::Undeserializable => None,
+            DeadLetterKind::ListenerPanicked => Message::from_serialized(&self.data).ok().map(|(message, _)| message),
+        }
+    }
+}
+
+///
+/// Counters of dead-letter queue, kept across restarts
+///
+#[derive(Clone, Debug, Default, PartialEq, Serializable, Deserializable)]
+pub struct DeadLetterStats{
+    /** Messages moved to queue **/
+    pub dead_lettered: u64,
+    /** Letters delivered again **/
+    pub replayed: u64,
+    /** Letters removed by operator **/
+    pub purged: u64,
+    /** Letters dropped because queue was full **/
+    pub dropped: u64,
+}
+
+///
+/// Messages which repeatedly failed processing. Instead of being retried forever or lost,
+/// message is moved here after max_failures failed deliveries and stays until it is
+/// replayed or purged.
+///
+/// Frames which are not messages are dead-lettered on first failure, as deserializing
+/// them again gives the same result.
+///
+#[derive(Serializable, Deserializable)]
+pub struct DeadLetterQueue{
+    storage_file_name: String,
+    max_failures: u32,
+    capacity: u64,
+    last_id: u64,
+    /** Failed deliveries of messages which are not dead-lettered yet, by message ID **/
+    failures: HashMap<u128, u32>,
+    letters: Vec<DeadLetter>,
+    stats: DeadLetterStats,
+}
+
+///
+/// Dead-letter queue shared between transport and its consumers
+///
+pub type SharedDeadLetterQueue = Arc<Mutex<DeadLetterQueue>>;
+
+impl DeadLetterQueue {
+    ///
+    /// Creates empty queue storing letters in provided file
+    ///
+    /// # Arguments
+    /// * filename: &str: file to store letters in
+    /// * max_failures: u32: failed deliveries after which message is dead-lettered
+    ///
+    pub fn new(filename: &str, max_failures: u32) -> DeadLetterQueue{
+        DeadLetterQueue{
+            storage_file_name: filename.to_string(),
+            max_failures: max_failures.max(1),
+            capacity: DEFAULT_DEAD_LETTER_CAPACITY,
+            last_id: 0,
+            failures: HashMap::new(),
+            letters: Vec::new(),
+            stats: DeadLetterStats::default(),
+        }
+    }
+
+    pub fn load_from_file(file: &str) -> DeadLetterQueue{
+        let mut queue = load_versioned::<DeadLetterQueue>(Path::new(file)).expect("Failed to load dead-letter queue");
+        queue.storage_file_name = file.to_string();
+        queue
+    }
+
+    ///
+    /// Loads queue from file or creates empty one if file does not exist
+    ///
+    pub fn open_shared(file: &str) -> SharedDeadLetterQueue{
+        let queue = if Path::new(file).exists(){
+            DeadLetterQueue::load_from_file(file)
+        } else {
+            DeadLetterQueue::new(file, DEFAULT_MAX_DELIVERY_FAILURES)
+        };
+        Arc::new(Mutex::new(queue))
+    }
+
+    pub fn set_max_failures(&mut self, max_failures: u32) -> &mut Self{
+        self.max_failures = max_failures.max(1);
+        self
+    }
+
+    pub fn set_capacity(&mut self, capacity: u64) -> &mut Self{
+        self.capacity = capacity;
+        self
+    }
+
+    #[inline]
+    pub fn get_max_failures(&self) -> u32{
+        self.max_failures
+    }
+
+    fn push(&mut self, mut letter: DeadLetter){
+        while self.letters.len() as u64 >= self.capacity.max(1){
+            let dropped = self.letters.remove(0);
+            self.stats.dropped += 1;
+            log::warn!("Dead-letter queue is full, letter {} from {} is dropped", dropped.id, dropped.source);
+        }
+        self.last_id += 1;
+        letter.id = self.last_id;
+        log::warn!("Message {} from {} is dead-lettered after {} failures: {}", letter.message_id, letter.source,
+            letter.failures, letter.error);
+        self.letters.push(letter);
+        self.stats.dead_lettered += 1;
+        self.commit();
+    }
+
+    ///
+    /// Records failed delivery of message. Once message failed max_failures times it is
+    /// moved to queue.
+    ///
+    /// # Arguments
+    /// * message: &Message: message which failed
+    /// * error: &str: why delivery failed, e.g. panic message
+    ///
+    /// returns: bool: whether message is dead-lettered and must not be retried
+    ///
+    pub fn record_failure(&mut self, message: &Message, error: &str) -> bool{
+        let failures = self.failures.entry(message.id).or_insert(0);
+        *failures += 1;
+        if *failures < self.max_failures{
+            return false;
+        }
+        let failures = self.failures.remove(&message.id).unwrap();
+        self.push(DeadLetter{
+            id: 0,
+            message_id: message.id,
+            source: message.source,
+            module_id: message.module_id,
+            kind: DeadLetterKind::ListenerPanicked,
+            error: error.to_string(),
+            failures,
+            timestamp: get_timestamp_with_milliseconds(),
+            data: message.serialize(),
+            replay_requested: false,
+        });
+        true
+    }
+
+    ///
+    /// Records successful delivery, so earlier failures of message are forgotten
+    ///
+    #[inline]
+    pub fn record_success(&mut self, message_id: u128){
+        self.failures.remove(&message_id);
+    }
+
+    ///
+    /// Moves frame which is not a message to queue
+    ///
+    /// # Arguments
+    /// * source: u128: peer frame was received from, 0 if unknown
+    /// * data: &Serialized: received frame
+    /// * error: &SerializationError: why frame is not a message
+    ///
+    pub fn record_undeserializable(&mut self, source: u128, data: &Serialized, error: &SerializationError){
+        self.push(DeadLetter{
+            id: 0,
+            message_id: 0,
+            source,
+            module_id: 0,
+            kind: DeadLetterKind::Undeserializable,
+            error: format!("{:?}", error),
+            failures: 1,
+            timestamp: get_timestamp_with_milliseconds(),
+            data: data.clone(),
+            replay_requested: false,
+        });
+    }
+
+    ///
+    /// Gets letters from oldest to newest
+    ///
+    #[inline]
+    pub fn get_letters(&self) -> &Vec<DeadLetter>{
+        &self.letters
+    }
+
+    ///
+    /// Gets count of letters in queue
+    ///
+    #[inline]
+    pub fn get_depth(&self) -> usize{
+        self.letters.len()
+    }
+
+    #[inline]
+    pub fn get_stats(&self) -> &DeadLetterStats{
+        &self.stats
+    }
+
+    ///
+    /// Marks letters to be delivered again by transport, see take_replays.
+    /// Frames which are not messages can not be replayed.
+    ///
+    /// # Arguments
+    /// * id: Option<u64>: ID of letter, all letters if None
+    ///
+    /// returns: usize: count of marked letters
+    ///
+    pub fn request_replay(&mut self, id: Option<u64>) -> usize{
+        let mut count = 0;
+        for letter in self.letters.iter_mut(){
+            if id.is_some_and(|id| letter.id != id) || letter.kind == DeadLetterKind::Undeserializable{
+                continue;
+            }
+            letter.replay_requested = true;
+            count += 1;
+        }
+        if count > 0{
+            self.commit();
+        }
+        count
+    }
+
+    ///
+    /// Removes letters marked for replay
+    ///
+    /// returns: Vec<Message>: messages to deliver again, in order they were dead-lettered
+    ///
+    pub fn take_replays(&mut self) -> Vec<Message>{
+        let (replays, letters): (Vec<DeadLetter>, Vec<DeadLetter>) = std::mem::take(&mut self.letters)
+            .into_iter()
+            .partition(|letter| letter.replay_requested);
+        self.letters = letters;
+        let messages: Vec<Message> = replays.iter().filter_map(|letter| letter.get_message()).collect();
+        if !replays.is_empty(){
+            self.stats.replayed += messages.len() as u64;
+            self.commit();
+        }
+        messages
+    }
+
+    ///
+    /// Removes letters
+    ///
+    /// # Arguments
+    /// * id: Option<u64>: ID of letter, all letters if None
+    ///
+    /// returns: usize: count of removed letters
+    ///
+    pub fn purge(&mut self, id: Option<u64>) -> usize{
+        let count = self.letters.len();
+        self.letters.retain(|letter| id.is_some_and(|id| letter.id != id));
+        let purged = count - self.letters.len();
+        if purged > 0{
+            self.stats.purged += purged as u64;
+            self.commit();
+        }
+        purged
+    }
+
+    ///
+    /// Saves queue to storage
+    ///
+    #[inline]
+    pub fn commit(&mut self){
+        if dump_versioned(self, &self.storage_file_name).is_err(){
+            log::error!("Failed to save dead-letter queue to {}", self.storage_file_name);
+        }
+    }
+}
+
+impl VersionedStorage for DeadLetterQueue {
+    const STORE_NAME: &'static str = "dead-letter queue";
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_queue(max_failures: u32) -> (DeadLetterQueue, String){
+        let file = std::env::temp_dir().join(format!("milkyway-deadletter-{}.dat", rand::random::<u64>()));
+        let file = file.to_str().unwrap().to_string();
+        (DeadLetterQueue::new(&file, max_failures), file)
+    }
+
+    fn message(id: u128) -> Message{
+        let mut message = Message::new();
+        message.id = id;
+        message.source = 7;
+        message
+    }
+
+    #[test]
+    fn test_failures_dead_letter_message() {
+        let (mut queue, file) = temporary_queue(2);
+        assert!(!queue.record_failure(&message(1), "first"));
+        queue.record_success(1);
+        assert!(!queue.record_failure(&message(1), "second"));
+        assert!(queue.record_failure(&message(1), "third"));
+        queue.record_undeserializable(8, &vec![1, 2, 3], &SerializationError::LengthError);
+        assert_eq!(queue.get_depth(), 2);
+        assert_eq!(queue.get_letters()[0].error, "third");
+        assert_eq!(queue.get_letters()[0].get_message().unwrap().id, 1);
+        assert_eq!(queue.get_letters()[1].kind, DeadLetterKind::Undeserializable);
+
+        // Queue survives restart
+        let mut queue = DeadLetterQueue::load_from_file(&file);
+        assert_eq!(queue.get_depth(), 2);
+        assert_eq!(queue.request_replay(None), 1);
+        let replays = queue.take_replays();
+        assert_eq!(replays.len(), 1);
+        assert_eq!(replays[0].id, 1);
+        assert_eq!(queue.purge(Some(100)), 0);
+        assert_eq!(queue.purge(None), 1);
+        assert_eq!(queue.get_stats(), &DeadLetterStats{ dead_lettered: 2, replayed: 1, purged: 1, dropped: 0 });
+        std::fs::remove_file(file).unwrap();
+    }
+}
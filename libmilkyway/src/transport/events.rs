@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use crate::transport::handshake::HandshakeStage;
+
+///
+/// Why connection with peer was closed
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum DisconnectReason{
+    /** Connection was closed by either side **/
+    Closed,
+    /** Peer did not answer keep-alive probe or was reaped as silent **/
+    KeepAliveTimeout,
+    /** Transformer terminated session, e.g. after replay was detected **/
+    Terminated,
+    /** Peer was not authorized **/
+    AuthorizationFailed,
+    /** Sides could not agree on protocol version **/
+    IncompatibleVersion,
+    /** Peer did not complete stage of handshake in time **/
+    HandshakeTimeout(HandshakeStage),
+    /** Local host shuts down **/
+    Shutdown,
+    /** Other failure with description **/
+    Error(String),
+}
+
+impl Display for DisconnectReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisconnectReason::Closed => write!(f, "connection closed"),
+            DisconnectReason::KeepAliveTimeout => write!(f, "keep-alive timeout"),
+            DisconnectReason::Terminated => write!(f, "session terminated by transformer"),
+            DisconnectReason::AuthorizationFailed => write!(f, "authorization failed"),
+            DisconnectReason::IncompatibleVersion => write!(f, "incompatible protocol version"),
+            DisconnectReason::HandshakeTimeout(stage) => write!(f, "handshake stage {} timed out", stage),
+            DisconnectReason::Shutdown => write!(f, "host shuts down"),
+            DisconnectReason::Error(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+///
+/// Change of state of connection with peer
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConnectionEvent{
+    /** Connection is established, peer is not known yet **/
+    Connected{
+        connection_id: u64,
+        endpoint: String,
+    },
+    /** Peer is authorized, certificates are serials of chain it presented, leaf last **/
+    Authorized{
+        connection_id: u64,
+        peer_id: u128,
+        certificates: Vec<u128>,
+        endpoint: String,
+        /** Negotiated protocol version, None if version handshake was not made **/
+        protocol_version: Option<u32>,
+    },
+    /** Connection is closed, peer_id is None if peer was never authorized **/
+    Disconnected{
+        connection_id: u64,
+        peer_id: Option<u128>,
+        endpoint: String,
+        reason: DisconnectReason,
+    },
+}
+
+impl ConnectionEvent {
+    pub fn get_connection_id(&self) -> u64{
+        match self {
+            ConnectionEvent::Connected{ connection_id, .. } |
+            ConnectionEvent::Authorized{ connection_id, .. } |
+            ConnectionEvent::Disconnected{ connection_id, .. } => *connection_id,
+        }
+    }
+
+    pub fn get_peer_id(&self) -> Option<u128>{
+        match self {
+            ConnectionEvent::Connected{ .. } => None,
+            ConnectionEvent::Authorized{ peer_id, .. } => Some(*peer_id),
+            ConnectionEvent::Disconnected{ peer_id, .. } => *peer_id,
+        }
+    }
+
+    pub fn get_endpoint(&self) -> &str{
+        match self {
+            ConnectionEvent::Connected{ endpoint, .. } |
+            ConnectionEvent::Authorized{ endpoint, .. } |
+            ConnectionEvent::Disconnected{ endpoint, .. } => endpoint,
+        }
+    }
+}
+
+///
+/// Receives connection events. Listeners are called while events are published, so they
+/// MUST NOT block and MUST NOT subscribe or publish events themselves.
+///
+pub trait ConnectionEventListener: Send + Sync{
+    fn on_connection_event(&mut self, event: &ConnectionEvent);
+}
+
+impl<F: FnMut(&ConnectionEvent) + Send + Sync> ConnectionEventListener for F{
+    fn on_connection_event(&mut self, event: &ConnectionEvent) {
+        self(event)
+    }
+}
+
+///
+/// Connection as seen by events published so far
+///
+struct ConnectionState{
+    endpoint: String,
+    peer_id: Option<u128>,
+    protocol_version: Option<u32>,
+}
+
+struct EventSubscription{
+    id: u128,
+    /** Module which made subscription, if any **/
+    owner: Option<u64>,
+    listener: Box<dyn ConnectionEventListener>,
+}
+
+///
+/// Stream of connection events fed by listeners accepting connections, transports and
+/// connection reaper, and consumed by modules, e.g. to resend queued state once peer is back.
+///
+/// Connections are tracked from Connected until Disconnected, so every connection is
+/// reported as disconnected exactly once even if several layers notice it.
+///
+pub struct ConnectionEvents{
+    subscriptions: Vec<EventSubscription>,
+    connections: HashMap<u64, ConnectionState>,
+    last_subscription_id: u128,
+}
+
+///
+/// Connection events shared between transport layers and service
+///
+pub type SharedConnectionEvents = Arc<Mutex<ConnectionEvents>>;
+
+impl Default for ConnectionEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConnectionEvents {
+    pub fn new() -> ConnectionEvents{
+        ConnectionEvents{
+            subscriptions: Vec::new(),
+            connections: HashMap::new(),
+            last_subscription_id: 0,
+        }
+    }
+
+    #[inline]
+    pub fn new_shared() -> SharedConnectionEvents{
+        Arc::new(Mutex::new(Self::new()))
+    }
+
+    ///
+    /// Subscribes listener to all events
+    ///
+    /// returns: u128: ID of subscription
+    ///
+    #[inline]
+    pub fn subscribe(&mut self, listener: Box<dyn ConnectionEventListener>) -> u128{
+        self.add_subscription(None, listener)
+    }
+
+    ///
+    /// Subscribes listener on behalf of module, so subscription is removed by unsubscribe_all
+    ///
+    #[inline]
+    pub fn subscribe_owned(&mut self, module_id: u64, listener: Box<dyn ConnectionEventListener>) -> u128{
+        self.add_subscription(Some(module_id), listener)
+    }
+
+    fn add_subscription(&mut self, owner: Option<u64>, listener: Box<dyn ConnectionEventListener>) -> u128{
+        self.last_subscription_id += 1;
+        self.subscriptions.push(EventSubscription{
+            id: self.last_subscription_id,
+            owner,
+            listener,
+        });
+        self.last_subscription_id
+    }
+
+    ///
+    /// Removes subscription
+    ///
+    /// returns: bool: whether subscription existed
+    ///
+    pub fn unsubscribe(&mut self, subscription_id: u128) -> bool{
+        let count = self.subscriptions.len();
+        self.subscriptions.retain(|subscription| subscription.id != subscription_id);
+        self.subscriptions.len() != count
+    }
+
+    ///
+    /// Removes all subscriptions of module
+    ///
+    /// returns: usize: count of removed subscriptions
+    ///
+    pub fn unsubscribe_all(&mut self, module_id: u64) -> usize{
+        let count = self.subscriptions.len();
+        self.subscriptions.retain(|subscription| subscription.owner != Some(module_id));
+        count - self.subscriptions.len()
+    }
+
+    #[inline]
+    pub fn get_subscription_count(&self) -> usize{
+        self.subscriptions.len()
+    }
+
+    ///
+    /// Gets IDs of authorized peers with open connections, sorted
+    ///
+    pub fn get_connected_peers(&self) -> Vec<u128>{
+        let mut peers: Vec<u128> = self.connections.values().filter_map(|connection| connection.peer_id).collect();
+        peers.sort();
+        peers.dedup();
+        peers
+    }
+
+    ///
+    /// Gets protocol version negotiated on open connection
+    ///
+    pub fn get_protocol_version(&self, connection_id: u64) -> Option<u32>{
+        self.connections.get(&connection_id).and_then(|connection| connection.protocol_version)
+    }
+
+    ///
+    /// Counts open connections by negotiated protocol version, e.g. to see whether
+    /// peers of old versions are still around before raising minimum version
+    ///
+    pub fn get_protocol_version_counts(&self) -> HashMap<u32, usize>{
+        let mut counts = HashMap::new();
+        for version in self.connections.values().filter_map(|connection| connection.protocol_version){
+            *counts.entry(version).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    fn publish(&mut self, event: ConnectionEvent){
+        log::debug!("Connection event: {:?}", event);
+        for subscription in self.subscriptions.iter_mut(){
+            subscription.listener.on_connection_event(&event);
+        }
+    }
+
+    ///
+    /// Reports established connection
+    ///
+    /// # Arguments
+    /// * connection_id: u64: ID of connection, see TokioStreamTransport::get_connection_id
+    /// * endpoint: &str: address of the other side
+    ///
+    pub fn on_connected(&mut self, connection_id: u64, endpoint: &str){
+        self.connections.insert(connection_id, ConnectionState{
+            endpoint: endpoint.to_string(),
+            peer_id: None,
+            protocol_version: None,
+        });
+        self.publish(ConnectionEvent::Connected{
+            connection_id,
+            endpoint: endpoint.to_string(),
+        });
+    }
+
+    ///
+    /// Records protocol version negotiated on connection, unknown connections are ignored.
+    /// Version is reported with Authorized event.
+    ///
+    pub fn on_version_negotiated(&mut self, connection_id: u64, protocol_version: u32){
+        if let Some(connection) = self.connections.get_mut(&connection_id){
+            connection.protocol_version = Some(protocol_version);
+        }
+    }
+
+    ///
+    /// Reports authorized peer, unknown connections are ignored
+    ///
+    /// # Arguments
+    /// * connection_id: u64: ID of connection
+    /// * peer_id: u128: ID of peer
+    /// * certificates: Vec<u128>: serials of certificates peer presented, leaf last
+    ///
+    pub fn on_authorized(&mut self, connection_id: u64, peer_id: u128, certificates: Vec<u128>){
+        let (endpoint, protocol_version) = match self.connections.get_mut(&connection_id) {
+            Some(connection) => {
+                connection.peer_id = Some(peer_id);
+                (connection.endpoint.clone(), connection.protocol_version)
+            }
+            None => return,
+        };
+        self.publish(ConnectionEvent::Authorized{
+            connection_id,
+            peer_id,
+            certificates,
+            endpoint,
+            protocol_version,
+        });
+    }
+
+    ///
+    /// Reports closed connection, connections already reported as closed are ignored
+    ///
+    /// returns: bool: whether event was published
+    ///
+    pub fn on_disconnected(&mut self, connection_id: u64, reason: DisconnectReason) -> bool{
+        let connection = match self.connections.remove(&connection_id) {
+            Some(connection) => connection,
+            None => return false,
+        };
+        self.publish(ConnectionEvent::Disconnected{
+            connection_id,
+            peer_id: connection.peer_id,
+            endpoint: connection.endpoint,
+            reason,
+        });
+        true
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::transport::{ModuleTransportService, TransportService};
+    use crate::testing::transport::LoopbackTransportService;
+
+    #[test]
+    fn test_connection_events() {
+        let received = Arc::new(Mutex::new(Vec::<ConnectionEvent>::new()));
+        let received_clone = received.clone();
+        let mut events = ConnectionEvents::new();
+        let all = events.subscribe(Box::new(move |event: &ConnectionEvent| {
+            received_clone.lock().unwrap().push(event.clone());
+        }));
+        events.subscribe_owned(7, Box::new(|_: &ConnectionEvent| {}));
+        events.subscribe_owned(7, Box::new(|_: &ConnectionEvent| {}));
+
+        events.on_authorized(1, 5, vec![0, 2]);
+        events.on_connected(1, "10.0.0.2:2804");
+        events.on_connected(2, "10.0.0.3:2804");
+        events.on_version_negotiated(1, 1);
+        events.on_authorized(1, 5, vec![0, 2]);
+        assert_eq!(events.get_connected_peers(), vec![5]);
+        assert!(events.on_disconnected(1, DisconnectReason::KeepAliveTimeout));
+        assert!(!events.on_disconnected(1, DisconnectReason::Closed));
+        events.on_disconnected(2, DisconnectReason::Closed);
+        assert!(events.get_connected_peers().is_empty());
+
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 5);
+        assert_eq!(received[2], ConnectionEvent::Authorized{ connection_id: 1, peer_id: 5, certificates: vec![0, 2],
+            endpoint: "10.0.0.2:2804".to_string(), protocol_version: Some(1) });
+        assert_eq!(received[3], ConnectionEvent::Disconnected{ connection_id: 1, peer_id: Some(5),
+            endpoint: "10.0.0.2:2804".to_string(), reason: DisconnectReason::KeepAliveTimeout });
+        assert_eq!(received[4].get_peer_id(), None);
+        assert_eq!(received[4].get_endpoint(), "10.0.0.3:2804");
+
+        assert_eq!(events.unsubscribe_all(7), 2);
+        assert!(events.unsubscribe(all));
+        assert!(!events.unsubscribe(all));
+        assert_eq!(events.get_subscription_count(), 0);
+    }
+
+    #[test]
+    fn test_module_connection_events() {
+        let mut transport = LoopbackTransportService::new(1);
+        assert!(transport.subscribe_connection_events(Box::new(|_: &ConnectionEvent| {})).is_none());
+        let events = ConnectionEvents::new_shared();
+        transport.set_connection_events(events.clone());
+        let peers = Arc::new(Mutex::new(Vec::<u128>::new()));
+        let peers_clone = peers.clone();
+        let mut module_transport = ModuleTransportService::new(Box::new(transport), 3);
+        module_transport.subscribe_connection_events(Box::new(move |event: &ConnectionEvent| {
+            if let ConnectionEvent::Authorized{ peer_id, .. } = event{
+                peers_clone.lock().unwrap().push(*peer_id);
+            }
+        })).unwrap();
+        events.lock().unwrap().on_connected(1, "peer");
+        events.lock().unwrap().on_authorized(1, 9, vec![0, 4]);
+        assert_eq!(*peers.lock().unwrap(), vec![9]);
+        assert_eq!(module_transport.unsubscribe_all(3), 1);
+        assert_eq!(events.lock().unwrap().get_subscription_count(), 0);
+    }
+}
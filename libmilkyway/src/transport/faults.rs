@@ -0,0 +1,387 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::message::common::Message;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::transport::ratelimit::TokenBucket;
+use crate::transport::{TransportSender, TransportTransformer};
+
+///
+/// Network conditions simulated by FaultInjector. Rates are probabilities from 0 to 1,
+/// zero values disable a fault.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FaultPolicy{
+    /** Probability of frame being lost **/
+    pub drop_rate: f64,
+    /** Probability of frame being delivered twice **/
+    pub duplicate_rate: f64,
+    /** Probability of frame being delivered after the next one **/
+    pub reorder_rate: f64,
+    /** Delay of every frame in milliseconds **/
+    pub latency: u64,
+    /** Maximal random delay added to latency in milliseconds **/
+    pub jitter: u64,
+    /** Simulated link capacity, frames wait until it is available **/
+    pub bytes_per_second: u64,
+    /** Seed of random decisions, so failures in tests can be reproduced **/
+    pub seed: Option<u64>,
+}
+
+///
+/// Malformed fault specification
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum FaultPolicyError{
+    UnknownKey(String),
+    InvalidValue(String),
+    /** Rate is not within 0 and 1 **/
+    InvalidRate(String),
+}
+
+impl Display for FaultPolicyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FaultPolicyError::UnknownKey(key) => write!(f, "unknown fault '{}'", key),
+            FaultPolicyError::InvalidValue(pair) => write!(f, "invalid value in '{}'", pair),
+            FaultPolicyError::InvalidRate(pair) => write!(f, "rate in '{}' must be between 0 and 1", pair),
+        }
+    }
+}
+
+///
+/// Parses specification passed to debug options, e.g.
+/// `drop=0.05,duplicate=0.01,reorder=0.1,latency=50,jitter=20,bandwidth=65536,seed=7`
+///
+impl FromStr for FaultPolicy {
+    type Err = FaultPolicyError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut policy = FaultPolicy::default();
+        for pair in value.split(',').filter(|pair| !pair.is_empty()){
+            let (key, value) = pair.split_once('=')
+                .ok_or_else(|| FaultPolicyError::InvalidValue(pair.to_string()))?;
+            match key {
+                "drop" | "duplicate" | "reorder" => {
+                    let rate = value.parse::<f64>().map_err(|_| FaultPolicyError::InvalidValue(pair.to_string()))?;
+                    if !(0.0..=1.0).contains(&rate){
+                        return Err(FaultPolicyError::InvalidRate(pair.to_string()));
+                    }
+                    match key {
+                        "drop" => policy.drop_rate = rate,
+                        "duplicate" => policy.duplicate_rate = rate,
+                        _ => policy.reorder_rate = rate,
+                    }
+                }
+                "latency" | "jitter" | "bandwidth" | "seed" => {
+                    let number = value.parse::<u64>().map_err(|_| FaultPolicyError::InvalidValue(pair.to_string()))?;
+                    match key {
+                        "latency" => policy.latency = number,
+                        "jitter" => policy.jitter = number,
+                        "bandwidth" => policy.bytes_per_second = number,
+                        _ => policy.seed = Some(number),
+                    }
+                }
+                _ => return Err(FaultPolicyError::UnknownKey(key.to_string())),
+            }
+        }
+        Ok(policy)
+    }
+}
+
+///
+/// What happens to one frame
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FaultDecision{
+    pub drop: bool,
+    pub duplicate: bool,
+    pub reorder: bool,
+    /** Latency, jitter and waiting for simulated link together **/
+    pub delay: Duration,
+}
+
+///
+/// Counters of injected faults
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FaultStats{
+    pub frames: u64,
+    pub dropped: u64,
+    pub duplicated: u64,
+    pub reordered: u64,
+    /** Frames delivered with non-zero delay **/
+    pub delayed: u64,
+}
+
+///
+/// Decides which faults happen to frames according to policy
+///
+pub struct FaultInjector{
+    policy: FaultPolicy,
+    rng: StdRng,
+    link: Option<TokenBucket>,
+    stats: FaultStats,
+}
+
+///
+/// Injector shared between transformer or sender and test checking its statistics
+///
+pub type SharedFaultInjector = Arc<Mutex<FaultInjector>>;
+
+impl FaultInjector {
+    pub fn new(policy: FaultPolicy) -> FaultInjector{
+        let rng = match policy.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        // Link has no burst, so every frame waits for its own bytes
+        let link = (policy.bytes_per_second != 0).then(|| TokenBucket::new(policy.bytes_per_second, 1,
+                                                                            Instant::now()));
+        FaultInjector{
+            policy,
+            rng,
+            link,
+            stats: FaultStats::default(),
+        }
+    }
+
+    #[inline]
+    pub fn new_shared(policy: FaultPolicy) -> SharedFaultInjector{
+        Arc::new(Mutex::new(FaultInjector::new(policy)))
+    }
+
+    #[inline]
+    pub fn get_policy(&self) -> &FaultPolicy{
+        &self.policy
+    }
+
+    #[inline]
+    pub fn get_stats(&self) -> FaultStats{
+        self.stats.clone()
+    }
+
+    ///
+    /// Decides faults of next frame and records them in statistics
+    ///
+    /// # Arguments
+    /// * size: usize: size of frame in bytes
+    ///
+    pub fn decide(&mut self, size: usize) -> FaultDecision{
+        self.decide_at(size, Instant::now())
+    }
+
+    fn decide_at(&mut self, size: usize, now: Instant) -> FaultDecision{
+        self.stats.frames += 1;
+        if self.rng.gen_bool(self.policy.drop_rate){
+            self.stats.dropped += 1;
+            return FaultDecision{
+                drop: true,
+                ..Default::default()
+            };
+        }
+        let duplicate = self.rng.gen_bool(self.policy.duplicate_rate);
+        let reorder = self.rng.gen_bool(self.policy.reorder_rate);
+        let jitter = if self.policy.jitter == 0 { 0 } else { self.rng.gen_range(0..=self.policy.jitter) };
+        let mut delay = Duration::from_millis(self.policy.latency.saturating_add(jitter));
+        if let Some(link) = self.link.as_mut(){
+            delay += link.reserve(size as u64, now);
+        }
+        self.stats.duplicated += duplicate as u64;
+        self.stats.reordered += reorder as u64;
+        self.stats.delayed += !delay.is_zero() as u64;
+        FaultDecision{
+            drop: false,
+            duplicate,
+            reorder,
+            delay,
+        }
+    }
+}
+
+///
+/// Injects faults into frames received over live connection, e.g. in a lab.
+///
+/// Transformer sees frames one by one and must return exactly one for each, so it may only
+/// lose frames(they are reported as undecodable to the connection) and delay them. Delays
+/// block the receiving thread, which is acceptable for debugging only. Transformer does not
+/// change data, so it may be added on one side after transformers are negotiated.
+/// Duplication and reordering are done by FaultySender.
+///
+pub struct FaultInjectionTransformer{
+    injector: SharedFaultInjector,
+}
+
+impl FaultInjectionTransformer {
+    pub fn new(injector: SharedFaultInjector) -> FaultInjectionTransformer{
+        FaultInjectionTransformer{
+            injector,
+        }
+    }
+}
+
+impl TransportTransformer for FaultInjectionTransformer{
+    fn detransform(&self, data: &Serialized) -> Result<Serialized, SerializationError> {
+        let decision = self.injector.lock().unwrap().decide(data.len());
+        if decision.drop{
+            return Err(SerializationError::InvalidDataError("Frame is dropped by fault injection"));
+        }
+        if !decision.delay.is_zero(){
+            std::thread::sleep(decision.delay);
+        }
+        Ok(data.clone())
+    }
+
+    #[inline]
+    fn transform(&self, data: &Serialized) -> Serialized {
+        data.clone()
+    }
+}
+
+///
+/// Sender simulating unreliable network in front of another sender, e.g. of loopback
+/// transport in integration tests.
+///
+/// Reordered message is held back and sent right after the next one, flush sends it if no
+/// other message follows. Delayed messages are sent from a separate thread, so they may
+/// also overtake each other.
+///
+pub struct FaultySender{
+    sender: Arc<Mutex<Box<dyn TransportSender>>>,
+    injector: SharedFaultInjector,
+    held: Option<Message>,
+}
+
+impl FaultySender {
+    pub fn new(sender: Box<dyn TransportSender>, injector: SharedFaultInjector) -> FaultySender{
+        FaultySender{
+            sender: Arc::new(Mutex::new(sender)),
+            injector,
+            held: None,
+        }
+    }
+
+    ///
+    /// Sends message held back for reordering, if any
+    ///
+    pub fn flush(&mut self){
+        if let Some(message) = self.held.take(){
+            self.sender.lock().unwrap().send_message(message);
+        }
+    }
+
+    fn deliver(&self, message: Message, copies: usize, delay: Duration){
+        if delay.is_zero(){
+            let mut sender = self.sender.lock().unwrap();
+            for _ in 0..copies{
+                sender.send_message(message.clone());
+            }
+            return;
+        }
+        let sender = self.sender.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(delay);
+            let mut sender = sender.lock().unwrap();
+            for _ in 0..copies{
+                sender.send_message(message.clone());
+            }
+        });
+    }
+}
+
+impl TransportSender for FaultySender{
+    fn send_message(&mut self, message: Message) {
+        let decision = self.injector.lock().unwrap().decide(message.serialize().len());
+        if decision.drop{
+            return;
+        }
+        if decision.reorder && self.held.is_none(){
+            self.held = Some(message);
+            return;
+        }
+        self.deliver(message, if decision.duplicate { 2 } else { 1 }, decision.delay);
+        self.flush();
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::transport::{MessageFilter, TransportService};
+    use crate::testing::transport::LoopbackTransportService;
+    use crate::transport::TransportListener;
+
+    struct CollectingListener{
+        received: Arc<Mutex<Vec<u128>>>,
+    }
+
+    impl TransportListener for CollectingListener{
+        fn on_message(&mut self, message: Message) {
+            self.received.lock().unwrap().push(message.id);
+        }
+    }
+
+    #[test]
+    fn test_fault_policy_parsing() {
+        let policy: FaultPolicy = "drop=0.5,latency=20,jitter=5,bandwidth=1000,seed=7".parse().unwrap();
+        assert_eq!(policy.drop_rate, 0.5);
+        assert_eq!(policy.latency, 20);
+        assert_eq!(policy.bytes_per_second, 1000);
+        assert_eq!(policy.seed, Some(7));
+        assert_eq!("drop=2".parse::<FaultPolicy>(), Err(FaultPolicyError::InvalidRate("drop=2".to_string())));
+        assert_eq!("loss=0.1".parse::<FaultPolicy>(), Err(FaultPolicyError::UnknownKey("loss".to_string())));
+        assert!("latency".parse::<FaultPolicy>().is_err());
+
+        let mut injector = FaultInjector::new(policy);
+        let now = Instant::now();
+        let decisions: Vec<FaultDecision> = (0..100).map(|_| injector.decide_at(100, now)).collect();
+        let stats = injector.get_stats();
+        assert_eq!(stats.frames, 100);
+        assert!(stats.dropped > 20 && stats.dropped < 80);
+        let delivered: Vec<&FaultDecision> = decisions.iter().filter(|decision| !decision.drop).collect();
+        assert!(delivered.iter().all(|decision| decision.delay >= Duration::from_millis(20)));
+        // Link of 1000 bytes per second falls behind by 100 ms on every delivered frame
+        assert!(delivered.last().unwrap().delay > Duration::from_millis(100 * (delivered.len() as u64 - 1)));
+    }
+
+    #[test]
+    fn test_faulty_sender() {
+        let (first, mut second) = LoopbackTransportService::pair(1, 2);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        second.subscribe_to_messages(&MessageFilter::new(), Box::new(CollectingListener{ received: received.clone() }));
+        let mut message = Message::new();
+        message.set_destination(2);
+
+        let injector = FaultInjector::new_shared(FaultPolicy{ reorder_rate: 1.0, ..Default::default() });
+        let mut sender = FaultySender::new(first.clone().get_sender(), injector);
+        for id in 1..=3{
+            message.set_id(id);
+            sender.send_message(message.clone());
+        }
+        assert_eq!(*received.lock().unwrap(), vec![2, 1]);
+        sender.flush();
+        assert_eq!(*received.lock().unwrap(), vec![2, 1, 3]);
+
+        received.lock().unwrap().clear();
+        let injector = FaultInjector::new_shared(FaultPolicy{ duplicate_rate: 1.0, ..Default::default() });
+        let mut sender = FaultySender::new(first.clone().get_sender(), injector.clone());
+        sender.send_message(message.clone());
+        assert_eq!(*received.lock().unwrap(), vec![3, 3]);
+        assert_eq!(injector.lock().unwrap().get_stats().duplicated, 1);
+
+        received.lock().unwrap().clear();
+        let injector = FaultInjector::new_shared(FaultPolicy{ drop_rate: 1.0, ..Default::default() });
+        let transformer = FaultInjectionTransformer::new(injector.clone());
+        let mut sender = FaultySender::new(first.clone().get_sender(), injector.clone());
+        sender.send_message(message);
+        assert!(received.lock().unwrap().is_empty());
+        assert_eq!(transformer.transform(&vec![1, 2]), vec![1, 2]);
+        assert!(transformer.detransform(&vec![1, 2]).is_err());
+        assert_eq!(injector.lock().unwrap().get_stats().dropped, 2);
+    }
+}
@@ -17,7 +17,10 @@ pub enum TransportHandlerRequest{
     AddListener((MessageFilter, Box<dyn TransportListener>)),
 
     /** Sends a message **/
-    SendMessage(Message)
+    SendMessage(Message),
+
+    /** Sends several messages in one request **/
+    SendBatch(Vec<Message>),
 }
 
 ///
@@ -88,6 +91,15 @@ pub trait TransportHandler: Send + Sync{
     /// * message: Message: a message to send
     ///
     fn send(&mut self, message: Message);
+
+    ///
+    /// This function sends several messages in one request. Messages are routed as by send,
+    /// messages to the same worker are passed to it together.
+    ///
+    /// # Arguments
+    /// * messages: Vec<Message>: messages to send in order
+    ///
+    fn send_batch(&mut self, messages: Vec<Message>);
 }
 
 impl TransportHandler for TransportHandlerServiceBinder{
@@ -112,6 +124,14 @@ impl TransportHandler for TransportHandlerServiceBinder{
             log::error!("send: result {:?} is not Ok", result);
         }
     }
+
+    fn send_batch(&mut self, messages: Vec<Message>) {
+        self.send_message(BinderMessage::Query(TransportHandlerRequest::SendBatch(messages)));
+        let result = unwrap_variant!(self.receive_message(), BinderMessage::Response);
+        if result != TransportHandlerResponse::Ok{
+            log::error!("send_batch: result {:?} is not Ok", result);
+        }
+    }
 }
 
 
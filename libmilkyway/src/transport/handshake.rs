@@ -0,0 +1,228 @@
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+
+///
+/// Default time each stage of handshake may take, milliseconds
+///
+pub const DEFAULT_HANDSHAKE_STAGE_TIMEOUT: u64 = 10_000;
+
+///
+/// Stages of handshake, each one is limited by its own timeout
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HandshakeStage{
+    /** Exchange of protocol versions **/
+    Version,
+    /** Exchange of transformer stacks and capabilities of sides **/
+    Capabilities,
+    /** Reading authorization message of peer **/
+    ReadAuthorization,
+    /** Sending response to authorization message **/
+    SendResponse,
+}
+
+impl HandshakeStage {
+    ///
+    /// All stages in order they happen
+    ///
+    pub const ALL: [HandshakeStage; 4] = [HandshakeStage::Version, HandshakeStage::Capabilities,
+        HandshakeStage::ReadAuthorization, HandshakeStage::SendResponse];
+
+    ///
+    /// Gets name of stage as used in configuration and metrics
+    ///
+    pub fn get_name(&self) -> &'static str{
+        match self {
+            HandshakeStage::Version => "version",
+            HandshakeStage::Capabilities => "capabilities",
+            HandshakeStage::ReadAuthorization => "read_authorization",
+            HandshakeStage::SendResponse => "send_response",
+        }
+    }
+}
+
+impl Display for HandshakeStage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.get_name())
+    }
+}
+
+///
+/// Errors of exchanging handshake frames
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum HandshakeError{
+    /** Peer did not complete stage in time **/
+    TimedOut(HandshakeStage),
+    /** Connection failed or was closed **/
+    ConnectionError,
+}
+
+impl Display for HandshakeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HandshakeError::TimedOut(stage) => write!(f, "handshake stage {} timed out", stage),
+            HandshakeError::ConnectionError => write!(f, "connection failed during handshake"),
+        }
+    }
+}
+
+///
+/// Timeouts of handshake stages, all in milliseconds
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct HandshakeTimeouts{
+    pub version: u64,
+    pub capabilities: u64,
+    pub read_authorization: u64,
+    pub send_response: u64,
+}
+
+impl Default for HandshakeTimeouts {
+    fn default() -> Self {
+        HandshakeTimeouts{
+            version: DEFAULT_HANDSHAKE_STAGE_TIMEOUT,
+            capabilities: DEFAULT_HANDSHAKE_STAGE_TIMEOUT,
+            read_authorization: DEFAULT_HANDSHAKE_STAGE_TIMEOUT,
+            send_response: DEFAULT_HANDSHAKE_STAGE_TIMEOUT,
+        }
+    }
+}
+
+impl HandshakeTimeouts {
+    ///
+    /// Gets timeout of stage
+    ///
+    pub fn get(&self, stage: HandshakeStage) -> u64{
+        match stage {
+            HandshakeStage::Version => self.version,
+            HandshakeStage::Capabilities => self.capabilities,
+            HandshakeStage::ReadAuthorization => self.read_authorization,
+            HandshakeStage::SendResponse => self.send_response,
+        }
+    }
+
+    ///
+    /// Sets timeout of stage
+    ///
+    pub fn set(&mut self, stage: HandshakeStage, timeout: u64) -> &mut Self{
+        match stage {
+            HandshakeStage::Version => self.version = timeout,
+            HandshakeStage::Capabilities => self.capabilities = timeout,
+            HandshakeStage::ReadAuthorization => self.read_authorization = timeout,
+            HandshakeStage::SendResponse => self.send_response = timeout,
+        }
+        self
+    }
+}
+
+///
+/// Counters of expired handshake stages
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HandshakeMetrics{
+    pub version: u64,
+    pub capabilities: u64,
+    pub read_authorization: u64,
+    pub send_response: u64,
+}
+
+///
+/// Handshake metrics shared between connections
+///
+pub type SharedHandshakeMetrics = Arc<Mutex<HandshakeMetrics>>;
+
+impl HandshakeMetrics {
+    #[inline]
+    pub fn new_shared() -> SharedHandshakeMetrics{
+        Arc::new(Mutex::new(HandshakeMetrics::default()))
+    }
+
+    ///
+    /// Counts expiration of stage
+    ///
+    pub fn record_timeout(&mut self, stage: HandshakeStage){
+        match stage {
+            HandshakeStage::Version => self.version += 1,
+            HandshakeStage::Capabilities => self.capabilities += 1,
+            HandshakeStage::ReadAuthorization => self.read_authorization += 1,
+            HandshakeStage::SendResponse => self.send_response += 1,
+        }
+    }
+
+    ///
+    /// Gets count of expirations of stage
+    ///
+    pub fn get_timeouts(&self, stage: HandshakeStage) -> u64{
+        match stage {
+            HandshakeStage::Version => self.version,
+            HandshakeStage::Capabilities => self.capabilities,
+            HandshakeStage::ReadAuthorization => self.read_authorization,
+            HandshakeStage::SendResponse => self.send_response,
+        }
+    }
+
+    ///
+    /// Gets count of expirations of all stages
+    ///
+    pub fn get_total(&self) -> u64{
+        HandshakeStage::ALL.iter().map(|stage| self.get_timeouts(*stage)).sum()
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+    use crate::transport::async_stream::TokioStreamTransport;
+    use crate::transport::events::{ConnectionEvent, ConnectionEvents, DisconnectReason};
+    use crate::transport::version::{VersionNegotiationError, VersionPolicy};
+
+    #[test]
+    fn test_handshake_timeouts() {
+        let mut timeouts = HandshakeTimeouts::default();
+        timeouts.set(HandshakeStage::ReadAuthorization, 500);
+        assert_eq!(timeouts.get(HandshakeStage::ReadAuthorization), 500);
+        assert_eq!(timeouts.get(HandshakeStage::SendResponse), DEFAULT_HANDSHAKE_STAGE_TIMEOUT);
+
+        let mut metrics = HandshakeMetrics::default();
+        metrics.record_timeout(HandshakeStage::Version);
+        metrics.record_timeout(HandshakeStage::Version);
+        metrics.record_timeout(HandshakeStage::SendResponse);
+        assert_eq!(metrics.get_timeouts(HandshakeStage::Version), 2);
+        assert_eq!(metrics.get_total(), 3);
+        assert_eq!(HandshakeError::TimedOut(HandshakeStage::Capabilities).to_string(),
+                   "handshake stage capabilities timed out");
+    }
+
+    #[tokio::test]
+    async fn test_stalled_handshake() {
+        let (client, server) = duplex(1 << 16);
+        let mut client = TokioStreamTransport::from_stream(client);
+        let mut server = TokioStreamTransport::from_stream(server);
+        let metrics = HandshakeMetrics::new_shared();
+        let events = ConnectionEvents::new_shared();
+        let reasons = Arc::new(Mutex::new(Vec::new()));
+        let captured = reasons.clone();
+        events.lock().unwrap().subscribe(Box::new(move |event: &ConnectionEvent| {
+            if let ConnectionEvent::Disconnected{ reason, .. } = event{
+                captured.lock().unwrap().push(reason.clone());
+            }
+        }));
+        server.set_connection_events(events, "client");
+        server.set_handshake_timeouts(HandshakeTimeouts{ version: 50, capabilities: 50, read_authorization: 50, send_response: 50 });
+        server.set_handshake_metrics(metrics.clone());
+
+        client.send_raw(vec![1, 2, 3]).await.unwrap();
+        assert_eq!(server.receive_handshake_frame(HandshakeStage::ReadAuthorization).await, Ok(vec![1, 2, 3]));
+        assert_eq!(server.receive_handshake_frame(HandshakeStage::ReadAuthorization).await,
+                   Err(HandshakeError::TimedOut(HandshakeStage::ReadAuthorization)));
+        assert_eq!(server.negotiate_version(&VersionPolicy::default(), None).await,
+                   Err(VersionNegotiationError::ConnectionError));
+        assert_eq!(metrics.lock().unwrap().get_timeouts(HandshakeStage::ReadAuthorization), 1);
+        assert_eq!(metrics.lock().unwrap().get_total(), 2);
+        drop(server);
+        assert_eq!(*reasons.lock().unwrap(), vec![DisconnectReason::HandshakeTimeout(HandshakeStage::Version)]);
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use crate::pki::certificate::Certificate;
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+use crate::transport::pinning::normalize_fingerprint;
+
+///
+/// Identity which peer is expected to prove after handshake. Only set parts are checked.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExpectedIdentity{
+    /** Name of signing certificate **/
+    pub name: Option<String>,
+    /** Serial of signing certificate **/
+    pub serial: Option<u128>,
+    /** Fingerprint of signing certificate(see Certificate::get_fingerprint) **/
+    pub fingerprint: Option<String>,
+}
+
+///
+/// Part of expected identity which certificate of peer does not match
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum IdentityMismatch{
+    Name{ expected: String, presented: String },
+    Serial{ expected: u128, presented: u128 },
+    Fingerprint{ expected: String, presented: String },
+}
+
+impl Display for IdentityMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IdentityMismatch::Name{ expected, presented } => {
+                write!(f, "expected certificate name '{}', peer presented '{}'", expected, presented)
+            }
+            IdentityMismatch::Serial{ expected, presented } => {
+                write!(f, "expected certificate serial {}, peer presented {}", expected, presented)
+            }
+            IdentityMismatch::Fingerprint{ expected, presented } => {
+                write!(f, "expected certificate fingerprint {}, peer presented {}", expected, presented)
+            }
+        }
+    }
+}
+
+impl ExpectedIdentity {
+    #[inline]
+    pub fn new() -> ExpectedIdentity{
+        ExpectedIdentity::default()
+    }
+
+    pub fn set_name(&mut self, name: &str) -> &mut Self{
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn set_serial(&mut self, serial: u128) -> &mut Self{
+        self.serial = Some(serial);
+        self
+    }
+
+    ///
+    /// Sets expected fingerprint
+    ///
+    /// returns: Result<&mut Self, &'static str>: self or error if fingerprint is not a hex string
+    ///
+    pub fn set_fingerprint(&mut self, fingerprint: &str) -> Result<&mut Self, &'static str>{
+        self.fingerprint = Some(normalize_fingerprint(fingerprint)?);
+        Ok(self)
+    }
+
+    ///
+    /// Checks whether nothing is expected
+    ///
+    #[inline]
+    pub fn is_empty(&self) -> bool{
+        self.name.is_none() && self.serial.is_none() && self.fingerprint.is_none()
+    }
+
+    ///
+    /// Checks signing certificate presented by peer
+    ///
+    /// returns: Result<(), IdentityMismatch>: first part of identity which does not match
+    ///
+    pub fn check(&self, certificate: &Falcon1024Certificate) -> Result<(), IdentityMismatch>{
+        if let Some(serial) = self.serial{
+            if serial != certificate.get_serial(){
+                return Err(IdentityMismatch::Serial{ expected: serial, presented: certificate.get_serial() });
+            }
+        }
+        if let Some(name) = &self.name{
+            if *name != certificate.get_name(){
+                return Err(IdentityMismatch::Name{ expected: name.clone(), presented: certificate.get_name() });
+            }
+        }
+        if let Some(fingerprint) = &self.fingerprint{
+            let presented = certificate.get_fingerprint();
+            if *fingerprint != presented{
+                return Err(IdentityMismatch::Fingerprint{ expected: fingerprint.clone(), presented });
+            }
+        }
+        Ok(())
+    }
+}
+
+///
+/// What happens to connection whose peer does not match expected identity
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum IdentityMode{
+    /** Connection is rejected **/
+    #[default]
+    Strict,
+    /** Mismatch is logged, connection is kept **/
+    Lenient,
+}
+
+///
+/// Identities expected from configured peers, checked after their certificates are verified.
+/// Unlike pins, expected identities are set by operator in configuration and are never
+/// learned from connections.
+///
+#[derive(Clone, Debug, Default)]
+pub struct ExpectedIdentities{
+    identities: HashMap<u128, ExpectedIdentity>,
+    mode: IdentityMode,
+}
+
+///
+/// Expected identities shared between connections
+///
+pub type SharedExpectedIdentities = Arc<Mutex<ExpectedIdentities>>;
+
+impl ExpectedIdentities {
+    #[inline]
+    pub fn new() -> ExpectedIdentities{
+        ExpectedIdentities::default()
+    }
+
+    ///
+    /// Sets identity expected from peer, empty identity removes expectation
+    ///
+    pub fn set_identity(&mut self, peer_id: u128, identity: ExpectedIdentity) -> &mut Self{
+        if identity.is_empty(){
+            self.identities.remove(&peer_id);
+        } else {
+            self.identities.insert(peer_id, identity);
+        }
+        self
+    }
+
+    #[inline]
+    pub fn get_identity(&self, peer_id: u128) -> Option<&ExpectedIdentity>{
+        self.identities.get(&peer_id)
+    }
+
+    pub fn set_mode(&mut self, mode: IdentityMode) -> &mut Self{
+        self.mode = mode;
+        self
+    }
+
+    #[inline]
+    pub fn get_mode(&self) -> IdentityMode{
+        self.mode
+    }
+
+    ///
+    /// Verifies signing certificate presented by peer after handshake. Mismatches are logged
+    /// with what was expected and what was presented.
+    ///
+    /// # Arguments
+    /// * peer_id: u128: ID of peer connection was made to
+    /// * certificate: &Falcon1024Certificate: verified signing certificate of peer
+    ///
+    /// returns: bool: whether connection may be kept, peers without expectation are always accepted
+    ///
+    pub fn verify(&self, peer_id: u128, certificate: &Falcon1024Certificate) -> bool{
+        let error = match self.identities.get(&peer_id).map(|identity| identity.check(certificate)) {
+            None | Some(Ok(())) => return true,
+            Some(Err(error)) => error,
+        };
+        match self.mode {
+            IdentityMode::Strict => {
+                log::error!("Connection to peer {} is rejected: {}", peer_id, error);
+                false
+            }
+            IdentityMode::Lenient => {
+                log::warn!("Peer {} does not match expected identity: {}", peer_id, error);
+                true
+            }
+        }
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::certificate::{test_certificates, TEST_SIGNING_CERTIFICATE_SERIAL};
+
+    #[test]
+    fn test_expected_identity() {
+        let certificate = test_certificates().signing;
+        let mut identity = ExpectedIdentity::new();
+        identity.set_name(&certificate.get_name()).set_serial(TEST_SIGNING_CERTIFICATE_SERIAL);
+        identity.set_fingerprint(&certificate.get_fingerprint().to_ascii_uppercase()).unwrap();
+        assert_eq!(identity.check(&certificate), Ok(()));
+        assert!(identity.set_fingerprint("peer").is_err());
+
+        let mut identities = ExpectedIdentities::new();
+        identities.set_identity(3, ExpectedIdentity::new().set_name("gateway").clone());
+        assert!(identities.verify(2, &certificate));
+        assert!(!identities.verify(3, &certificate));
+        assert_eq!(identities.get_identity(3).unwrap().check(&certificate).unwrap_err().to_string(),
+                   format!("expected certificate name 'gateway', peer presented '{}'", certificate.get_name()));
+        identities.set_mode(IdentityMode::Lenient);
+        assert!(identities.verify(3, &certificate));
+        identities.set_identity(3, ExpectedIdentity::new());
+        assert!(identities.get_identity(3).is_none());
+    }
+}
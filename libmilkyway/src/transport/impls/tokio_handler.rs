@@ -56,6 +56,9 @@ impl TokioTransportHandlerImpl {
             TransportHandlerRequest::SendMessage(_) => {
                 log::error!("Somebody is trying to send a message, but now workers listen us");
             }
+            TransportHandlerRequest::SendBatch(_) => {
+                log::error!("Somebody is trying to send messages, but now workers listen us");
+            }
         }
     }
 }
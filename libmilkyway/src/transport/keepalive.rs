@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::serialization::serializable::Serialized;
+use crate::transport::events::{DisconnectReason, SharedConnectionEvents};
+
+///
+/// Payload of frame asking the other side whether it is alive. Messages are never
+/// serialized to a single byte, so probes can not be confused with them.
+///
+pub const KEEPALIVE_PROBE: [u8; 1] = [0x01];
+
+///
+/// Payload of frame answering a probe
+///
+pub const KEEPALIVE_REPLY: [u8; 1] = [0x02];
+
+///
+/// Timeouts of idle connections, all in milliseconds
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeepAlivePolicy{
+    /** Connection without traffic for this long is probed **/
+    pub idle_timeout: u64,
+    /** Probed connection is closed if nothing arrives within this time **/
+    pub probe_timeout: u64,
+    /** How often ConnectionReaper looks for dead connections **/
+    pub reap_interval: u64,
+}
+
+impl Default for KeepAlivePolicy {
+    fn default() -> Self {
+        KeepAlivePolicy{
+            idle_timeout: 60_000,
+            probe_timeout: 15_000,
+            reap_interval: 5_000,
+        }
+    }
+}
+
+impl KeepAlivePolicy {
+    ///
+    /// Gets time without traffic after which connection is considered dead
+    ///
+    #[inline]
+    pub fn get_dead_timeout(&self) -> Duration{
+        Duration::from_millis(self.idle_timeout.saturating_add(self.probe_timeout))
+    }
+}
+
+///
+/// Checks whether received payload is a probe or a reply rather than a message
+///
+#[inline]
+pub fn is_keepalive_frame(data: &Serialized) -> bool{
+    data.as_slice() == KEEPALIVE_PROBE || data.as_slice() == KEEPALIVE_REPLY
+}
+
+///
+/// Called with ID of connection once it is closed, e.g. to remove routing and presence entries
+///
+pub type ConnectionCleanup = Box<dyn FnMut(u128) + Send>;
+
+///
+/// Tracks activity of connections and closes those which stayed silent after being probed.
+///
+/// Connection tasks report every received frame with on_activity and probe peers themselves
+/// (see TokioStreamTransport::receive_alive). Reaper is a backstop for connections which
+/// are stuck without noticing it: they are forgotten and cleanup hooks are run, so stale
+/// routes and presence entries do not linger forever.
+///
+pub struct ConnectionReaper{
+    policy: KeepAlivePolicy,
+    last_activity: HashMap<u128, Instant>,
+    cleanups: Vec<ConnectionCleanup>,
+    events: Option<SharedConnectionEvents>,
+}
+
+///
+/// Reaper shared between connection tasks and reaping loop
+///
+pub type SharedConnectionReaper = Arc<Mutex<ConnectionReaper>>;
+
+impl ConnectionReaper {
+    pub fn new(policy: KeepAlivePolicy) -> ConnectionReaper{
+        ConnectionReaper{
+            policy,
+            last_activity: HashMap::new(),
+            cleanups: Vec::new(),
+            events: None,
+        }
+    }
+
+    #[inline]
+    pub fn get_policy(&self) -> &KeepAlivePolicy{
+        &self.policy
+    }
+
+    ///
+    /// Adds hook run for every closed connection, hooks run in order they were added
+    ///
+    pub fn add_cleanup(&mut self, cleanup: ConnectionCleanup) -> &mut Self{
+        self.cleanups.push(cleanup);
+        self
+    }
+
+    ///
+    /// Sets connection events which reaped connections are reported to
+    ///
+    pub fn set_connection_events(&mut self, events: SharedConnectionEvents) -> &mut Self{
+        self.events = Some(events);
+        self
+    }
+
+    ///
+    /// Starts tracking connection, it counts as active right now
+    ///
+    pub fn register(&mut self, connection_id: u128){
+        self.last_activity.insert(connection_id, Instant::now());
+    }
+
+    ///
+    /// Records traffic on connection, unknown connections are ignored
+    ///
+    pub fn on_activity(&mut self, connection_id: u128){
+        if let Some(last_activity) = self.last_activity.get_mut(&connection_id){
+            *last_activity = Instant::now();
+        }
+    }
+
+    #[inline]
+    pub fn is_tracked(&self, connection_id: u128) -> bool{
+        self.last_activity.contains_key(&connection_id)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize{
+        self.last_activity.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool{
+        self.last_activity.is_empty()
+    }
+
+    ///
+    /// Forgets connection and runs cleanup hooks, should be called whenever connection is closed
+    ///
+    /// returns: bool: whether connection was tracked
+    ///
+    pub fn close(&mut self, connection_id: u128) -> bool{
+        if self.last_activity.remove(&connection_id).is_none(){
+            return false;
+        }
+        for cleanup in self.cleanups.iter_mut(){
+            cleanup(connection_id);
+        }
+        true
+    }
+
+    ///
+    /// Closes connections without traffic for longer than idle and probe timeouts together
+    ///
+    /// returns: Vec<u128>: IDs of closed connections
+    ///
+    pub fn reap(&mut self) -> Vec<u128>{
+        self.reap_at(Instant::now())
+    }
+
+    fn reap_at(&mut self, now: Instant) -> Vec<u128>{
+        let dead_timeout = self.policy.get_dead_timeout();
+        let dead: Vec<u128> = self.last_activity.iter()
+            .filter(|(_, last_activity)| now.saturating_duration_since(**last_activity) > dead_timeout)
+            .map(|(connection_id, _)| *connection_id)
+            .collect();
+        for connection_id in dead.iter(){
+            log::warn!("Connection {} is silent for more than {} ms, closing it", connection_id,
+                dead_timeout.as_millis());
+            if let Some(events) = &self.events{
+                events.lock().unwrap().on_disconnected(*connection_id as u64, DisconnectReason::KeepAliveTimeout);
+            }
+            self.close(*connection_id);
+        }
+        dead
+    }
+}
+
+///
+/// Reaps dead connections every reap interval of policy. Runs forever, so it should be
+/// spawned as a separate task.
+///
+pub async fn run_reaper(reaper: SharedConnectionReaper){
+    loop {
+        let interval = reaper.lock().unwrap().get_policy().reap_interval;
+        tokio::time::sleep(Duration::from_millis(interval.max(1))).await;
+        reaper.lock().unwrap().reap();
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reaper() {
+        let closed = Arc::new(Mutex::new(Vec::<u128>::new()));
+        let closed_clone = closed.clone();
+        let mut reaper = ConnectionReaper::new(KeepAlivePolicy{ idle_timeout: 100, probe_timeout: 50,
+                                                                reap_interval: 10 });
+        reaper.add_cleanup(Box::new(move |connection_id| closed_clone.lock().unwrap().push(connection_id)));
+        reaper.register(1);
+        reaper.register(2);
+        let now = Instant::now();
+        assert!(reaper.reap_at(now + Duration::from_millis(100)).is_empty());
+        reaper.last_activity.insert(2, now + Duration::from_millis(100));
+        assert_eq!(reaper.reap_at(now + Duration::from_millis(200)), vec![1]);
+        assert!(!reaper.is_tracked(1));
+        assert!(reaper.is_tracked(2));
+        assert!(reaper.close(2));
+        assert!(!reaper.close(2));
+        assert!(reaper.is_empty());
+        assert_eq!(*closed.lock().unwrap(), vec![1, 2]);
+        assert!(is_keepalive_frame(&KEEPALIVE_PROBE.to_vec()));
+        assert!(!is_keepalive_frame(&vec![1, 2]));
+    }
+}
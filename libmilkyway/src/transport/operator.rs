@@ -0,0 +1,219 @@
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use crate::message::common::Message;
+use crate::pki::certificate::{Certificate, FLAG_SIGN_MESSAGES, FLAG_USER_CERT};
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+use crate::pki::signature::MESSAGE_SIGNATURE_LABEL;
+use crate::services::certificate::CertificateService;
+use crate::transport::signature::SignatureRejection;
+use crate::transport::{SendError, TransportSender};
+
+///
+/// Checks whether certificate identifies an operator: it is a user certificate allowed
+/// to sign messages
+///
+#[inline]
+pub fn is_operator_certificate(certificate: &Falcon1024Certificate) -> bool{
+    certificate.check_flag(FLAG_USER_CERT) && certificate.check_flag(FLAG_SIGN_MESSAGES)
+}
+
+///
+/// Errors of creating operator identity
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum OperatorIdentityError{
+    /** Certificate has no FLAG_USER_CERT or FLAG_SIGN_MESSAGES **/
+    NotOperator(u128),
+    /** Secret key of certificate is not available, so messages can not be signed **/
+    NoSecretKey(u128),
+}
+
+impl Display for OperatorIdentityError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OperatorIdentityError::NotOperator(serial) => write!(f, "certificate {} is not an operator \
+                certificate(user certificate signing messages)", serial),
+            OperatorIdentityError::NoSecretKey(serial) => write!(f, "secret key of certificate {} is not available",
+                                                                 serial),
+        }
+    }
+}
+
+///
+/// Operator issuing commands from CLI. Every message sent on behalf of operator is stamped
+/// with serial of operator certificate and signed with it, so peers can authorize commands
+/// and attribute them in audit.
+///
+#[derive(Clone)]
+pub struct OperatorIdentity{
+    certificate: Falcon1024Certificate,
+}
+
+impl OperatorIdentity {
+    ///
+    /// Creates operator identity
+    ///
+    /// # Arguments
+    /// * certificate: Falcon1024Certificate: operator certificate with secret key
+    ///
+    pub fn new(certificate: Falcon1024Certificate) -> Result<OperatorIdentity, OperatorIdentityError>{
+        if !is_operator_certificate(&certificate){
+            return Err(OperatorIdentityError::NotOperator(certificate.get_serial()));
+        }
+        if certificate.get_secret_key().is_none(){
+            return Err(OperatorIdentityError::NoSecretKey(certificate.get_serial()));
+        }
+        Ok(OperatorIdentity{
+            certificate,
+        })
+    }
+
+    ///
+    /// Gets serial of operator certificate
+    ///
+    #[inline]
+    pub fn get_serial(&self) -> u128{
+        self.certificate.get_serial()
+    }
+
+    ///
+    /// Gets operator certificate without secret key
+    ///
+    #[inline]
+    pub fn get_certificate(&self) -> Falcon1024Certificate{
+        self.certificate.clone_without_sk()
+    }
+
+    ///
+    /// Stamps message with serial of operator certificate and signs it. Previous signature
+    /// is replaced, ID and timestamp are assigned before signing if not set.
+    ///
+    /// # Arguments
+    /// * message: &mut Message: message to sign
+    ///
+    pub fn sign(&self, message: &mut Message){
+        message.signature = None;
+        message.ensure_id();
+        if message.timestamp == 0{
+            message.set_current_timestamp();
+        }
+        message.sign_by(&self.certificate).unwrap();
+    }
+}
+
+///
+/// Sender which signs every message with operator certificate before passing it further
+///
+pub struct OperatorSigningSender{
+    inner: Box<dyn TransportSender>,
+    identity: Arc<OperatorIdentity>,
+}
+
+impl OperatorSigningSender {
+    pub fn new(inner: Box<dyn TransportSender>, identity: Arc<OperatorIdentity>) -> OperatorSigningSender{
+        OperatorSigningSender{
+            inner,
+            identity,
+        }
+    }
+}
+
+impl TransportSender for OperatorSigningSender{
+    fn send_message(&mut self, mut message: Message) {
+        self.identity.sign(&mut message);
+        self.inner.send_message(message);
+    }
+
+    fn try_send_message(&mut self, mut message: Message) -> Result<(), SendError> {
+        self.identity.sign(&mut message);
+        self.inner.try_send_message(message)
+    }
+
+    fn send_batch(&mut self, mut messages: Vec<Message>) {
+        for message in messages.iter_mut(){
+            self.identity.sign(message);
+        }
+        self.inner.send_batch(messages);
+    }
+}
+
+///
+/// Verifies that message was issued by an operator. Servers use it to authorize commands
+/// coming from CLI and to attribute them to operator in audit.
+///
+/// # Arguments
+/// * service: &mut S: service with known certificates
+/// * message: &Message: received message
+///
+/// returns: Result<Falcon1024Certificate, SignatureRejection>: certificate of operator or
+/// the reason why message is not accepted as issued by operator
+///
+pub fn verify_operator<S: CertificateService + ?Sized>(service: &mut S,
+                                                       message: &Message) -> Result<Falcon1024Certificate, SignatureRejection>{
+    let signature = message.signature.as_ref().ok_or(SignatureRejection::Unsigned)?;
+    if !signature.is_made_for(MESSAGE_SIGNATURE_LABEL){
+        return Err(SignatureRejection::InvalidSignature);
+    }
+    let certificate = service.get_signing_certificate(message.get_signer_serial())
+        .ok_or(SignatureRejection::UnknownCertificate)?;
+    if !is_operator_certificate(&certificate){
+        return Err(SignatureRejection::NotOperator);
+    }
+    if !service.verify_signing_certificate(&certificate){
+        return Err(SignatureRejection::UntrustedCertificate);
+    }
+    if !certificate.verify_signature(&message.as_signable(), signature){
+        return Err(SignatureRejection::InvalidSignature);
+    }
+    Ok(certificate)
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use super::*;
+    use crate::message::types::MessageType;
+    use crate::testing::certificate::{test_certificates, MockCertificateService, TEST_SIGNING_CERTIFICATE_SERIAL};
+
+    struct CollectingSender{
+        sent: Arc<Mutex<Vec<Message>>>,
+    }
+
+    impl TransportSender for CollectingSender{
+        fn send_message(&mut self, message: Message) {
+            self.sent.lock().unwrap().push(message);
+        }
+    }
+
+    #[test]
+    fn test_operator_signing() {
+        let mut certificate = test_certificates().signing;
+        assert_eq!(OperatorIdentity::new(certificate.clone()).err(),
+                   Some(OperatorIdentityError::NotOperator(TEST_SIGNING_CERTIFICATE_SERIAL)));
+        certificate.flags |= FLAG_USER_CERT;
+        let mut service = MockCertificateService::with_test_certificates();
+        service.add_signing_certificate(certificate.clone());
+        let mut public_only = certificate.clone_without_sk();
+        public_only.serial_number = 9;
+        assert_eq!(OperatorIdentity::new(public_only).err(), Some(OperatorIdentityError::NoSecretKey(9)));
+
+        let identity = Arc::new(OperatorIdentity::new(certificate).unwrap());
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let mut sender = OperatorSigningSender::new(Box::new(CollectingSender{ sent: sent.clone() }), identity);
+        let mut message = Message::new();
+        message.set_type(MessageType::Exec);
+        sender.send_message(message);
+        let message = sent.lock().unwrap().remove(0);
+        assert_eq!(message.certificate_id, TEST_SIGNING_CERTIFICATE_SERIAL);
+        assert_ne!(message.id, 0);
+        assert_eq!(verify_operator(&mut service, &message).unwrap().get_serial(), TEST_SIGNING_CERTIFICATE_SERIAL);
+
+        let mut tampered = message.clone();
+        tampered.destination = 7;
+        assert_eq!(verify_operator(&mut service, &tampered).err(), Some(SignatureRejection::InvalidSignature));
+        let mut unsigned = message;
+        unsigned.signature = None;
+        assert_eq!(verify_operator(&mut service, &unsigned).err(), Some(SignatureRejection::Unsigned));
+    }
+}
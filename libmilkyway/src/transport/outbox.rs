@@ -0,0 +1,258 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use libmilkyway_derive::{Deserializable, Serializable};
+use crate::message::common::Message;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::migration::{dump_versioned, load_versioned, VersionedStorage};
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::transport::TransportSender;
+
+///
+/// Default count of messages outbox keeps before dropping the oldest ones
+///
+pub const DEFAULT_OUTBOX_CAPACITY: u64 = 4096;
+
+///
+/// Counters of durable messages, kept across restarts
+///
+#[derive(Clone, Debug, Default, PartialEq, Serializable, Deserializable)]
+pub struct OutboxStats{
+    /** Messages found untransmitted when outbox was loaded after restart **/
+    pub recovered: u64,
+    /** Messages sent again after reconnection **/
+    pub replayed: u64,
+    /** Messages dropped because outbox was full **/
+    pub lost: u64,
+}
+
+///
+/// Durable messages which were queued but not transmitted yet.
+///
+/// Message is persisted when it is queued and removed only once worker acknowledges it was
+/// written to connection, so messages queued in channels of a dying worker survive and are
+/// replayed after worker reconnects.
+///
+#[derive(Serializable, Deserializable)]
+pub struct Outbox{
+    storage_file_name: String,
+    capacity: u64,
+    messages: Vec<Message>,
+    stats: OutboxStats,
+}
+
+///
+/// Outbox shared between senders and workers
+///
+pub type SharedOutbox = Arc<Mutex<Outbox>>;
+
+impl Outbox {
+    ///
+    /// Creates empty outbox storing data in provided file
+    ///
+    /// # Arguments
+    /// * filename: &str: file to store messages in
+    /// * capacity: u64: count of messages kept before the oldest ones are dropped
+    ///
+    pub fn new(filename: &str, capacity: u64) -> Outbox{
+        Outbox{
+            storage_file_name: filename.to_string(),
+            capacity,
+            messages: Vec::new(),
+            stats: OutboxStats::default(),
+        }
+    }
+
+    ///
+    /// Loads outbox, all messages in it are counted as recovered
+    ///
+    pub fn load_from_file(file: &str) -> Outbox{
+        let mut outbox = load_versioned::<Outbox>(Path::new(file)).expect("Failed to load outbox");
+        outbox.storage_file_name = file.to_string();
+        outbox.stats.recovered += outbox.messages.len() as u64;
+        if !outbox.messages.is_empty(){
+            log::warn!("Recovered {} untransmitted messages from {}", outbox.messages.len(), file);
+        }
+        outbox
+    }
+
+    ///
+    /// Loads outbox from file or creates empty one if file does not exist
+    ///
+    pub fn open_shared(file: &str) -> SharedOutbox{
+        let outbox = if Path::new(file).exists(){
+            Outbox::load_from_file(file)
+        } else {
+            Outbox::new(file, DEFAULT_OUTBOX_CAPACITY)
+        };
+        Arc::new(Mutex::new(outbox))
+    }
+
+    pub fn set_capacity(&mut self, capacity: u64) -> &mut Self{
+        self.capacity = capacity;
+        self
+    }
+
+    ///
+    /// Queues message until it is acknowledged. If outbox is full the oldest message is dropped.
+    ///
+    pub fn push(&mut self, message: Message){
+        while self.messages.len() as u64 >= self.capacity.max(1){
+            let dropped = self.messages.remove(0);
+            self.stats.lost += 1;
+            log::warn!("Outbox is full, message {} to {} is lost", dropped.id, dropped.destination);
+        }
+        self.messages.push(message);
+        self.commit();
+    }
+
+    ///
+    /// Removes message which was written to connection
+    ///
+    /// returns: bool: whether message was in outbox
+    ///
+    pub fn acknowledge(&mut self, message_id: u128) -> bool{
+        let count = self.messages.len();
+        self.messages.retain(|message| message.id != message_id);
+        if count == self.messages.len(){
+            return false;
+        }
+        self.commit();
+        true
+    }
+
+    ///
+    /// Gets untransmitted messages in order they were queued
+    ///
+    /// # Arguments
+    /// * destination: Option<u128>: only messages to this peer, all messages if None
+    ///
+    pub fn get_pending(&self, destination: Option<u128>) -> Vec<Message>{
+        self.messages.iter()
+            .filter(|message| destination.is_none_or(|destination| message.destination == destination))
+            .cloned()
+            .collect()
+    }
+
+    ///
+    /// Counts message sent again after reconnection
+    ///
+    #[inline]
+    pub fn record_replayed(&mut self){
+        self.stats.replayed += 1;
+    }
+
+    #[inline]
+    pub fn get_stats(&self) -> &OutboxStats{
+        &self.stats
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize{
+        self.messages.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool{
+        self.messages.is_empty()
+    }
+
+    ///
+    /// Saves outbox to storage
+    ///
+    #[inline]
+    pub fn commit(&mut self){
+        if dump_versioned(self, &self.storage_file_name).is_err(){
+            log::error!("Failed to save outbox to {}", self.storage_file_name);
+        }
+    }
+}
+
+impl VersionedStorage for Outbox {
+    const STORE_NAME: &'static str = "outbox";
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+///
+/// A sender which persists every message to outbox before passing it on
+///
+pub struct DurableSender{
+    sender: Box<dyn TransportSender>,
+    outbox: SharedOutbox,
+}
+
+impl DurableSender {
+    pub fn new(sender: Box<dyn TransportSender>, outbox: SharedOutbox) -> DurableSender{
+        DurableSender{
+            sender,
+            outbox,
+        }
+    }
+}
+
+impl TransportSender for DurableSender{
+    fn send_message(&mut self, mut message: Message) {
+        // Outbox acknowledges messages by ID, so it must be unique
+        message.ensure_id();
+        self.outbox.lock().unwrap().push(message.clone());
+        self.sender.send_message(message);
+    }
+
+    fn send_batch(&mut self, mut messages: Vec<Message>) {
+        let mut outbox = self.outbox.lock().unwrap();
+        for message in messages.iter_mut(){
+            message.ensure_id();
+            outbox.push(message.clone());
+        }
+        drop(outbox);
+        self.sender.send_batch(messages);
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+    use crate::transport::async_stream::TokioStreamTransport;
+
+    fn message(id: u128, destination: u128) -> Message{
+        let mut message = Message::new();
+        message.set_id(id);
+        message.destination = destination;
+        message
+    }
+
+    #[tokio::test]
+    async fn test_outbox_replay() {
+        let file = std::env::temp_dir().join(format!("milkyway-outbox-{}.dat", rand::random::<u64>()));
+        let file = file.to_str().unwrap();
+        {
+            let shared = Outbox::open_shared(file);
+            let mut outbox = shared.lock().unwrap();
+            outbox.set_capacity(3);
+            for id in 1..=4{
+                outbox.push(message(id, if id % 2 == 0 { 20 } else { 30 }));
+            }
+            assert_eq!(outbox.get_stats().lost, 1);
+            assert!(outbox.acknowledge(3));
+            assert!(!outbox.acknowledge(3));
+        }
+        // Outbox is dropped without transmitting messages as if worker crashed
+        let outbox = Arc::new(Mutex::new(Outbox::load_from_file(file)));
+        assert_eq!(outbox.lock().unwrap().get_stats().recovered, 2);
+        let (client, server) = duplex(4096);
+        let mut client = TokioStreamTransport::from_stream(client);
+        let mut server = TokioStreamTransport::from_stream(server);
+        client.set_outbox(outbox.clone());
+        assert_eq!(client.replay_outbox(Some(20)).await, 2);
+        for id in [2, 4]{
+            let data = server.receive_raw(Some(1000)).await.unwrap();
+            assert_eq!(Message::from_serialized(&data).unwrap().0.id, id);
+        }
+        let outbox = outbox.lock().unwrap();
+        assert!(outbox.is_empty());
+        assert_eq!(outbox.get_stats(), &OutboxStats{ recovered: 2, replayed: 2, lost: 1 });
+        std::fs::remove_file(file).unwrap();
+    }
+}
@@ -0,0 +1,247 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use libmilkyway_derive::{Deserializable, Serializable};
+use crate::get_timestamp_with_milliseconds;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::migration::{dump_versioned, load_versioned, VersionedStorage};
+use crate::serialization::serializable::{Serializable, Serialized};
+
+///
+/// Fingerprint of signing certificate bound to peer
+///
+#[derive(Clone, Debug, PartialEq, Serializable, Deserializable)]
+pub struct PeerPin{
+    pub peer_id: u128,
+    /** Fingerprint of signing certificate(see Certificate::get_fingerprint) **/
+    pub fingerprint: String,
+    /** When pin was added or fingerprint was first seen **/
+    pub timestamp: u128,
+}
+
+///
+/// Result of checking certificate of peer against pins
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PinCheck{
+    /** Peer is not pinned, certificate must be trusted through chain **/
+    Unpinned,
+    /** Peer is pinned to this certificate **/
+    Matched,
+    /** Peer is pinned to another certificate **/
+    Mismatched,
+}
+
+///
+/// Trust on first use: peers pinned to fingerprints of their signing certificates.
+///
+/// Pinned peer must present the same certificate on every connection, which is checked before
+/// its chain is verified, so peers may be trusted before root certificate is distributed.
+/// Fingerprints of unpinned peers are recorded on first connection and become pins only once
+/// operator confirms them.
+///
+#[derive(Serializable, Deserializable)]
+pub struct PeerPins{
+    storage_file_name: String,
+    pins: Vec<PeerPin>,
+    /** First seen fingerprints waiting for confirmation **/
+    pending: Vec<PeerPin>,
+}
+
+///
+/// Pins shared between authorization and CLI
+///
+pub type SharedPeerPins = Arc<Mutex<PeerPins>>;
+
+///
+/// Checks that fingerprint is a hex string and lowercases it
+///
+pub fn normalize_fingerprint(fingerprint: &str) -> Result<String, &'static str>{
+    if fingerprint.is_empty() || !fingerprint.chars().all(|c| c.is_ascii_hexdigit()){
+        return Err("Fingerprint must be a hex string");
+    }
+    Ok(fingerprint.to_ascii_lowercase())
+}
+
+impl PeerPins {
+    ///
+    /// Creates empty pins storing data in provided file
+    ///
+    pub fn new(filename: &str) -> PeerPins{
+        PeerPins{
+            storage_file_name: filename.to_string(),
+            pins: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn load_from_file(file: &str) -> PeerPins{
+        let mut pins = load_versioned::<PeerPins>(Path::new(file)).expect("Failed to load peer pins");
+        pins.storage_file_name = file.to_string();
+        pins
+    }
+
+    ///
+    /// Loads pins from file or creates empty ones if file does not exist
+    ///
+    pub fn open_shared(file: &str) -> SharedPeerPins{
+        let pins = if Path::new(file).exists(){
+            PeerPins::load_from_file(file)
+        } else {
+            PeerPins::new(file)
+        };
+        Arc::new(Mutex::new(pins))
+    }
+
+    ///
+    /// Pins peer to fingerprint, replacing previous pin and discarding pending fingerprint
+    ///
+    /// # Arguments
+    /// * peer_id: u128: ID of peer
+    /// * fingerprint: &str: hex-encoded fingerprint of signing certificate
+    ///
+    /// returns: Result<bool, &'static str>: whether pin changed or error if fingerprint is malformed
+    ///
+    pub fn pin(&mut self, peer_id: u128, fingerprint: &str) -> Result<bool, &'static str>{
+        let fingerprint = normalize_fingerprint(fingerprint)?;
+        self.pending.retain(|pin| pin.peer_id != peer_id);
+        if self.get_pin(peer_id).is_some_and(|pin| pin.fingerprint == fingerprint){
+            return Ok(false);
+        }
+        self.pins.retain(|pin| pin.peer_id != peer_id);
+        self.pins.push(PeerPin{
+            peer_id,
+            fingerprint,
+            timestamp: get_timestamp_with_milliseconds(),
+        });
+        Ok(true)
+    }
+
+    ///
+    /// Pins peer to fingerprint seen on its first connection
+    ///
+    /// returns: Option<String>: pinned fingerprint or None if nothing is pending for peer
+    ///
+    pub fn confirm(&mut self, peer_id: u128) -> Option<String>{
+        let index = self.pending.iter().position(|pin| pin.peer_id == peer_id)?;
+        let pending = self.pending.remove(index);
+        self.pins.retain(|pin| pin.peer_id != peer_id);
+        self.pins.push(pending.clone());
+        Some(pending.fingerprint)
+    }
+
+    ///
+    /// Removes pin and pending fingerprint of peer
+    ///
+    /// returns: bool: whether peer had any of them
+    ///
+    pub fn unpin(&mut self, peer_id: u128) -> bool{
+        let count = self.pins.len() + self.pending.len();
+        self.pins.retain(|pin| pin.peer_id != peer_id);
+        self.pending.retain(|pin| pin.peer_id != peer_id);
+        count != self.pins.len() + self.pending.len()
+    }
+
+    #[inline]
+    pub fn get_pin(&self, peer_id: u128) -> Option<&PeerPin>{
+        self.pins.iter().find(|pin| pin.peer_id == peer_id)
+    }
+
+    #[inline]
+    pub fn get_pins(&self) -> &[PeerPin]{
+        &self.pins
+    }
+
+    #[inline]
+    pub fn get_pending(&self) -> &[PeerPin]{
+        &self.pending
+    }
+
+    ///
+    /// Checks fingerprint of signing certificate presented by peer
+    ///
+    pub fn check(&self, peer_id: u128, fingerprint: &str) -> PinCheck{
+        match self.get_pin(peer_id) {
+            None => PinCheck::Unpinned,
+            Some(pin) if pin.fingerprint == fingerprint => PinCheck::Matched,
+            Some(_) => PinCheck::Mismatched,
+        }
+    }
+
+    ///
+    /// Records fingerprint of unpinned peer for confirmation by operator. Only the latest
+    /// fingerprint is kept for each peer.
+    ///
+    /// returns: bool: whether fingerprint was not pending before
+    ///
+    pub fn observe(&mut self, peer_id: u128, fingerprint: &str) -> bool{
+        if self.get_pin(peer_id).is_some(){
+            return false;
+        }
+        if self.pending.iter().any(|pin| pin.peer_id == peer_id && pin.fingerprint == fingerprint){
+            return false;
+        }
+        self.pending.retain(|pin| pin.peer_id != peer_id);
+        self.pending.push(PeerPin{
+            peer_id,
+            fingerprint: fingerprint.to_string(),
+            timestamp: get_timestamp_with_milliseconds(),
+        });
+        true
+    }
+
+    ///
+    /// Saves pins to storage
+    ///
+    #[inline]
+    pub fn commit(&mut self){
+        if dump_versioned(self, &self.storage_file_name).is_err(){
+            log::error!("Failed to save peer pins to {}", self.storage_file_name);
+        }
+    }
+}
+
+impl VersionedStorage for PeerPins {
+    const STORE_NAME: &'static str = "pins";
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pins() {
+        let file = std::env::temp_dir().join(format!("milkyway-pins-{}.dat", rand::random::<u64>()));
+        let file = file.to_str().unwrap();
+        let shared = PeerPins::open_shared(file);
+        {
+            let mut pins = shared.lock().unwrap();
+            assert_eq!(pins.check(1, "ab"), PinCheck::Unpinned);
+            assert!(pins.observe(1, "ab"));
+            assert!(!pins.observe(1, "ab"));
+            // Observed fingerprint is not trusted until confirmed
+            assert_eq!(pins.check(1, "ab"), PinCheck::Unpinned);
+            assert_eq!(pins.confirm(1), Some("ab".to_string()));
+            assert_eq!(pins.confirm(1), None);
+            assert_eq!(pins.check(1, "ab"), PinCheck::Matched);
+            assert_eq!(pins.check(1, "cd"), PinCheck::Mismatched);
+            assert!(!pins.observe(1, "cd"));
+            assert_eq!(pins.pin(2, "XY"), Err("Fingerprint must be a hex string"));
+            assert_eq!(pins.pin(2, "EF"), Ok(true));
+            assert_eq!(pins.pin(2, "ef"), Ok(false));
+            pins.observe(3, "01");
+            pins.commit();
+        }
+        let mut loaded = PeerPins::load_from_file(file);
+        assert_eq!(loaded.check(2, "ef"), PinCheck::Matched);
+        assert_eq!(loaded.get_pins().len(), 2);
+        assert_eq!(loaded.get_pending().len(), 1);
+        assert!(loaded.unpin(1));
+        assert!(!loaded.unpin(1));
+        assert_eq!(loaded.check(1, "ab"), PinCheck::Unpinned);
+        std::fs::remove_file(file).unwrap();
+    }
+}
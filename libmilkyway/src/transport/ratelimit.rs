@@ -0,0 +1,405 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::message::common::Message;
+use crate::serialization::serializable::Serializable;
+
+///
+/// A token bucket: holds up to `capacity` tokens refilled at `rate` tokens per second
+///
+pub(crate) struct TokenBucket{
+    capacity: f64,
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(rate: u64, burst: u64, now: Instant) -> TokenBucket{
+        let capacity = if burst == 0 { rate } else { burst } as f64;
+        TokenBucket{
+            capacity,
+            rate: rate as f64,
+            tokens: capacity,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant){
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    ///
+    /// Amount of tokens needed for a request. Requests bigger than bucket need a full bucket,
+    /// otherwise they could never pass.
+    ///
+    #[inline]
+    fn get_cost(&self, amount: u64) -> f64{
+        (amount as f64).min(self.capacity)
+    }
+
+    fn can_consume(&mut self, amount: u64, now: Instant) -> bool{
+        self.refill(now);
+        self.tokens >= self.get_cost(amount)
+    }
+
+    fn consume(&mut self, amount: u64){
+        self.tokens -= self.get_cost(amount);
+    }
+
+    ///
+    /// Takes tokens for sending data even if bucket does not have enough of them, bucket goes
+    /// into debt which is paid by waiting
+    ///
+    /// returns: Duration: how long to wait before sending, so average rate is kept
+    ///
+    pub(crate) fn reserve(&mut self, amount: u64, now: Instant) -> Duration{
+        self.refill(now);
+        self.tokens -= amount as f64;
+        if self.tokens >= 0.0{
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(-self.tokens / self.rate)
+    }
+
+    ///
+    /// Changes rate and burst keeping current tokens(or debt) within new capacity
+    ///
+    pub(crate) fn set_limits(&mut self, rate: u64, burst: u64, now: Instant){
+        self.refill(now);
+        self.capacity = if burst == 0 { rate } else { burst } as f64;
+        self.rate = rate as f64;
+        self.tokens = self.tokens.min(self.capacity);
+    }
+}
+
+///
+/// Rate and bandwidth quota. Zero rate means no limit for that dimension.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QuotaLimits{
+    pub messages_per_second: u64,
+    /** Maximum burst of messages, equals to rate if zero **/
+    pub message_burst: u64,
+    pub bytes_per_second: u64,
+    /** Maximum burst of bytes, equals to rate if zero **/
+    pub byte_burst: u64,
+}
+
+///
+/// What to do with a message exceeding quota
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QuotaAction{
+    /** Message is silently dropped **/
+    Drop,
+    /** Message is delivered after all messages within quota **/
+    Deprioritize,
+    /** Message is dropped and peer is disconnected **/
+    Disconnect,
+}
+
+///
+/// Verdict of rate limiter on a message
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RateLimitVerdict{
+    Allow,
+    Drop,
+    Deprioritize,
+    Disconnect,
+}
+
+///
+/// Quotas enforced by transport. Overrides take precedence over default limits.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimitPolicy{
+    pub peer_limits: Option<QuotaLimits>,
+    pub module_limits: Option<QuotaLimits>,
+    pub peer_overrides: HashMap<u128, QuotaLimits>,
+    pub module_overrides: HashMap<u64, QuotaLimits>,
+    pub action: QuotaAction,
+}
+
+impl RateLimitPolicy {
+    ///
+    /// Creates a policy without any limits
+    ///
+    /// # Arguments
+    /// * action: QuotaAction: action on messages exceeding quota
+    ///
+    pub fn new(action: QuotaAction) -> RateLimitPolicy{
+        RateLimitPolicy{
+            peer_limits: None,
+            module_limits: None,
+            peer_overrides: HashMap::new(),
+            module_overrides: HashMap::new(),
+            action,
+        }
+    }
+
+    fn get_peer_limits(&self, peer_id: u128) -> Option<QuotaLimits>{
+        self.peer_overrides.get(&peer_id).cloned().or(self.peer_limits)
+    }
+
+    fn get_module_limits(&self, module_id: u64) -> Option<QuotaLimits>{
+        self.module_overrides.get(&module_id).cloned().or(self.module_limits)
+    }
+}
+
+///
+/// Counters of rate limiter decisions
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RateLimitMetrics{
+    pub allowed: u64,
+    pub dropped: u64,
+    pub deprioritized: u64,
+    pub disconnected: u64,
+    /** Messages exceeding quota by peer ID **/
+    pub peer_violations: HashMap<u128, u64>,
+    /** Messages exceeding quota by module ID **/
+    pub module_violations: HashMap<u64, u64>,
+}
+
+struct QuotaBuckets{
+    messages: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+impl QuotaBuckets {
+    fn new(limits: &QuotaLimits, now: Instant) -> QuotaBuckets{
+        QuotaBuckets{
+            messages: (limits.messages_per_second != 0)
+                .then(|| TokenBucket::new(limits.messages_per_second, limits.message_burst, now)),
+            bytes: (limits.bytes_per_second != 0)
+                .then(|| TokenBucket::new(limits.bytes_per_second, limits.byte_burst, now)),
+        }
+    }
+
+    fn can_consume(&mut self, size: u64, now: Instant) -> bool{
+        let messages_ok = self.messages.as_mut().is_none_or(|bucket| bucket.can_consume(1, now));
+        let bytes_ok = self.bytes.as_mut().is_none_or(|bucket| bucket.can_consume(size, now));
+        messages_ok && bytes_ok
+    }
+
+    fn consume(&mut self, size: u64){
+        if let Some(bucket) = self.messages.as_mut(){
+            bucket.consume(1);
+        }
+        if let Some(bucket) = self.bytes.as_mut(){
+            bucket.consume(size);
+        }
+    }
+}
+
+///
+/// Enforces per-peer and per-module message rate and bandwidth quotas
+///
+pub struct RateLimiter{
+    policy: RateLimitPolicy,
+    peers: HashMap<u128, QuotaBuckets>,
+    modules: HashMap<u64, QuotaBuckets>,
+    disconnected: HashSet<u128>,
+    metrics: RateLimitMetrics,
+}
+
+///
+/// A rate limiter shared between transport service and its consumers
+///
+pub type SharedRateLimiter = Arc<Mutex<RateLimiter>>;
+
+impl RateLimiter {
+    ///
+    /// Creates rate limiter enforcing a policy
+    ///
+    pub fn new(policy: RateLimitPolicy) -> RateLimiter{
+        RateLimiter{
+            policy,
+            peers: HashMap::new(),
+            modules: HashMap::new(),
+            disconnected: HashSet::new(),
+            metrics: RateLimitMetrics::default(),
+        }
+    }
+
+    ///
+    /// Creates a shared rate limiter enforcing a policy
+    ///
+    #[inline]
+    pub fn new_shared(policy: RateLimitPolicy) -> SharedRateLimiter{
+        Arc::new(Mutex::new(Self::new(policy)))
+    }
+
+    ///
+    /// Checks received message against quotas of its source peer and module
+    ///
+    /// # Arguments
+    /// * message: &Message: received message
+    ///
+    /// returns: RateLimitVerdict: what to do with message
+    ///
+    #[inline]
+    pub fn check(&mut self, message: &Message) -> RateLimitVerdict{
+        self.check_at(message, Instant::now())
+    }
+
+    ///
+    /// Same as check, but at specified moment of time
+    ///
+    pub fn check_at(&mut self, message: &Message, now: Instant) -> RateLimitVerdict{
+        if self.disconnected.contains(&message.source){
+            self.metrics.disconnected += 1;
+            return RateLimitVerdict::Disconnect;
+        }
+        let size = message.serialize().len() as u64;
+        let peer_limits = self.policy.get_peer_limits(message.source);
+        let module_limits = self.policy.get_module_limits(message.module_id);
+        let peer_buckets = peer_limits.map(|limits| self.peers.entry(message.source)
+            .or_insert_with(|| QuotaBuckets::new(&limits, now)));
+        let peer_ok = peer_buckets.is_none_or(|buckets| buckets.can_consume(size, now));
+        let module_buckets = module_limits.map(|limits| self.modules.entry(message.module_id)
+            .or_insert_with(|| QuotaBuckets::new(&limits, now)));
+        let module_ok = module_buckets.is_none_or(|buckets| buckets.can_consume(size, now));
+        if peer_ok && module_ok{
+            if let Some(buckets) = self.peers.get_mut(&message.source){
+                buckets.consume(size);
+            }
+            if let Some(buckets) = self.modules.get_mut(&message.module_id){
+                buckets.consume(size);
+            }
+            self.metrics.allowed += 1;
+            return RateLimitVerdict::Allow;
+        }
+        if !peer_ok{
+            *self.metrics.peer_violations.entry(message.source).or_insert(0) += 1;
+        }
+        if !module_ok{
+            *self.metrics.module_violations.entry(message.module_id).or_insert(0) += 1;
+        }
+        match self.policy.action {
+            QuotaAction::Drop => {
+                self.metrics.dropped += 1;
+                RateLimitVerdict::Drop
+            }
+            QuotaAction::Deprioritize => {
+                self.metrics.deprioritized += 1;
+                RateLimitVerdict::Deprioritize
+            }
+            QuotaAction::Disconnect => {
+                log::warn!("Peer {} exceeded quota and is disconnected", message.source);
+                self.disconnected.insert(message.source);
+                self.metrics.disconnected += 1;
+                RateLimitVerdict::Disconnect
+            }
+        }
+    }
+
+    ///
+    /// Checks whether peer was disconnected for exceeding quota
+    ///
+    #[inline]
+    pub fn is_disconnected(&self, peer_id: u128) -> bool{
+        self.disconnected.contains(&peer_id)
+    }
+
+    ///
+    /// Allows disconnected peer to connect again
+    ///
+    #[inline]
+    pub fn reconnect(&mut self, peer_id: u128){
+        self.disconnected.remove(&peer_id);
+        self.peers.remove(&peer_id);
+    }
+
+    ///
+    /// Gets counters of enforcement decisions
+    ///
+    #[inline]
+    pub fn get_metrics(&self) -> RateLimitMetrics{
+        self.metrics.clone()
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn message_from(source: u128, module_id: u64) -> Message{
+        let mut message = Message::new();
+        message.source = source;
+        message.module_id = module_id;
+        message
+    }
+
+    fn limit_messages(rate: u64, burst: u64) -> QuotaLimits{
+        QuotaLimits{
+            messages_per_second: rate,
+            message_burst: burst,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_peer_message_rate() {
+        let mut policy = RateLimitPolicy::new(QuotaAction::Drop);
+        policy.peer_limits = Some(limit_messages(2, 3));
+        let mut limiter = RateLimiter::new(policy);
+        let start = Instant::now();
+        for _ in 0..3{
+            assert_eq!(limiter.check_at(&message_from(5, 1), start), RateLimitVerdict::Allow);
+        }
+        assert_eq!(limiter.check_at(&message_from(5, 1), start), RateLimitVerdict::Drop);
+        // Other peers have own buckets
+        assert_eq!(limiter.check_at(&message_from(6, 1), start), RateLimitVerdict::Allow);
+        // Two tokens per second
+        let later = start + Duration::from_millis(500);
+        assert_eq!(limiter.check_at(&message_from(5, 1), later), RateLimitVerdict::Allow);
+        assert_eq!(limiter.check_at(&message_from(5, 1), later), RateLimitVerdict::Drop);
+        let metrics = limiter.get_metrics();
+        assert_eq!(metrics.allowed, 5);
+        assert_eq!(metrics.dropped, 2);
+        assert_eq!(metrics.peer_violations.get(&5), Some(&2));
+    }
+
+    #[test]
+    fn test_module_bandwidth_and_overrides() {
+        let size = message_from(1, 7).serialize().len() as u64;
+        let mut policy = RateLimitPolicy::new(QuotaAction::Deprioritize);
+        policy.module_limits = Some(QuotaLimits{
+            bytes_per_second: size,
+            ..Default::default()
+        });
+        policy.module_overrides.insert(8, QuotaLimits::default());
+        let mut limiter = RateLimiter::new(policy);
+        let now = Instant::now();
+        assert_eq!(limiter.check_at(&message_from(1, 7), now), RateLimitVerdict::Allow);
+        assert_eq!(limiter.check_at(&message_from(2, 7), now), RateLimitVerdict::Deprioritize);
+        // Module 8 is unlimited
+        for _ in 0..10{
+            assert_eq!(limiter.check_at(&message_from(1, 8), now), RateLimitVerdict::Allow);
+        }
+        assert_eq!(limiter.get_metrics().module_violations.get(&7), Some(&1));
+    }
+
+    #[test]
+    fn test_disconnect_action() {
+        let mut policy = RateLimitPolicy::new(QuotaAction::Disconnect);
+        policy.peer_limits = Some(limit_messages(1, 1));
+        let mut limiter = RateLimiter::new(policy);
+        let now = Instant::now();
+        assert_eq!(limiter.check_at(&message_from(3, 1), now), RateLimitVerdict::Allow);
+        assert_eq!(limiter.check_at(&message_from(3, 1), now), RateLimitVerdict::Disconnect);
+        assert!(limiter.is_disconnected(3));
+        // Peer stays disconnected even when quota is refilled
+        let later = now + Duration::from_secs(10);
+        assert_eq!(limiter.check_at(&message_from(3, 1), later), RateLimitVerdict::Disconnect);
+        limiter.reconnect(3);
+        assert_eq!(limiter.check_at(&message_from(3, 1), later), RateLimitVerdict::Allow);
+    }
+}
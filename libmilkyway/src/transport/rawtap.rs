@@ -0,0 +1,380 @@
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::time::Duration;
+use crate::get_timestamp_with_milliseconds;
+use crate::pki::certificate::{Certificate, FLAG_TRANSPORT_TAP};
+use crate::serialization::serializable::Serialized;
+use crate::services::certificate::CertificateService;
+use crate::transport::operator::OperatorIdentity;
+use crate::transport::stats::FrameDirection;
+use crate::errors::ErrorCode;
+
+///
+/// Default amount of frames waiting for subscriber before newer ones are dropped
+///
+pub const DEFAULT_RAW_FRAME_CAPACITY: usize = 256;
+
+///
+/// Frame as it is on wire: sent frames after transformation, received ones before
+/// detransformation. Size prefix of frame is not included.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawFrame{
+    /** When frame was tapped, milliseconds since epoch **/
+    pub timestamp: u128,
+    pub direction: FrameDirection,
+    pub connection_id: u64,
+    /** ID of peer on the other side, 0 until it is known **/
+    pub peer_id: u128,
+    pub data: Serialized,
+}
+
+///
+/// Directions of frames subscriber gets
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RawFrameFilter{
+    pub send: bool,
+    pub receive: bool,
+}
+
+impl RawFrameFilter {
+    ///
+    /// Filter matching frames of both directions
+    ///
+    #[inline]
+    pub fn all() -> RawFrameFilter{
+        RawFrameFilter{
+            send: true,
+            receive: true,
+        }
+    }
+
+    ///
+    /// Filter matching frames of one direction
+    ///
+    pub fn only(direction: FrameDirection) -> RawFrameFilter{
+        RawFrameFilter{
+            send: direction == FrameDirection::Send,
+            receive: direction == FrameDirection::Receive,
+        }
+    }
+
+    #[inline]
+    pub fn matches(&self, direction: FrameDirection) -> bool{
+        match direction {
+            FrameDirection::Send => self.send,
+            FrameDirection::Receive => self.receive,
+        }
+    }
+}
+
+///
+/// Errors of subscribing to raw frames
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum RawTapError{
+    /** Tapping raw frames is not enabled on host(`raw_frame_tap` in daemon configuration) **/
+    Disabled,
+    /** Operator certificate has no transport-tap flag **/
+    NotPermitted(u128),
+    /** Operator certificate is not trusted by certificate service **/
+    Untrusted(u128),
+    /** Filter matches neither direction **/
+    EmptyFilter,
+}
+
+impl Display for RawTapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RawTapError::Disabled => write!(f, "tapping raw frames is not enabled on this host"),
+            RawTapError::NotPermitted(serial) => write!(f, "certificate {} has no transport-tap flag", serial),
+            RawTapError::Untrusted(serial) => write!(f, "certificate {} is not trusted", serial),
+            RawTapError::EmptyFilter => write!(f, "filter matches no direction of frames"),
+        }
+    }
+}
+
+impl ErrorCode for RawTapError {
+    fn get_error_code(&self) -> &'static str {
+        match self {
+            RawTapError::Disabled => "MW-TRAN-0001",
+            RawTapError::NotPermitted(_) => "MW-TRAN-0002",
+            RawTapError::Untrusted(_) => "MW-TRAN-0003",
+            RawTapError::EmptyFilter => "MW-TRAN-0004",
+        }
+    }
+}
+
+struct RawSubscriber{
+    id: u128,
+    filter: RawFrameFilter,
+    sender: SyncSender<RawFrame>,
+    /** Frames not delivered as subscriber did not keep up **/
+    dropped: Arc<AtomicU64>,
+}
+
+///
+/// Raw frame tap shared between transports and subscribers
+///
+pub type SharedRawFrameTap = Arc<RawFrameTap>;
+
+///
+/// Delivers copies of raw frames passing through transports to subscribers, e.g. protocol
+/// analyzers debugging interop. Every subscriber has a bounded queue: frames which do not
+/// fit are dropped and counted, so a slow subscriber never stalls transports. Transports
+/// copy nothing while there are no subscribers.
+///
+pub struct RawFrameTap{
+    enabled: bool,
+    active: AtomicBool,
+    subscribers: Mutex<Vec<RawSubscriber>>,
+    last_subscription_id: Mutex<u128>,
+}
+
+impl RawFrameTap {
+    ///
+    /// Creates shared tap
+    ///
+    /// # Arguments
+    /// * enabled: bool: whether host allows tapping raw frames, subscriptions fail otherwise
+    ///
+    pub fn new_shared(enabled: bool) -> SharedRawFrameTap{
+        Arc::new(RawFrameTap{
+            enabled,
+            active: AtomicBool::new(false),
+            subscribers: Mutex::new(vec![]),
+            last_subscription_id: Mutex::new(0),
+        })
+    }
+
+    #[inline]
+    pub fn is_enabled(&self) -> bool{
+        self.enabled
+    }
+
+    ///
+    /// Checks whether anybody is subscribed, transports skip tapping otherwise
+    ///
+    #[inline]
+    pub fn is_active(&self) -> bool{
+        self.active.load(Ordering::Relaxed)
+    }
+
+    ///
+    /// Subscribes to raw frames on behalf of operator. Operator certificate must have
+    /// transport-tap flag and be trusted.
+    ///
+    /// # Arguments
+    /// * certificates: &mut S: service to verify operator certificate with
+    /// * operator: &OperatorIdentity: operator requesting frames
+    /// * filter: RawFrameFilter: directions of frames to deliver
+    /// * capacity: usize: how many frames may wait for subscriber
+    ///
+    /// returns: Result<RawFrameSubscription, RawTapError>: subscription or reason it is refused
+    ///
+    pub fn subscribe<S: CertificateService + ?Sized>(&self, certificates: &mut S, operator: &OperatorIdentity,
+                                                     filter: RawFrameFilter, capacity: usize)
+                                                     -> Result<RawFrameSubscription, RawTapError>{
+        if !self.enabled{
+            return Err(RawTapError::Disabled);
+        }
+        let certificate = operator.get_certificate();
+        if !certificate.check_flag(FLAG_TRANSPORT_TAP){
+            return Err(RawTapError::NotPermitted(certificate.get_serial()));
+        }
+        if !certificates.verify_signing_certificate(&certificate){
+            return Err(RawTapError::Untrusted(certificate.get_serial()));
+        }
+        if !filter.send && !filter.receive{
+            return Err(RawTapError::EmptyFilter);
+        }
+        if capacity == 0{
+            panic!("Capacity of raw frame subscription must be positive");
+        }
+        let id = {
+            let mut last_id = self.last_subscription_id.lock().unwrap();
+            *last_id += 1;
+            *last_id
+        };
+        let (sender, receiver) = sync_channel(capacity);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.push(RawSubscriber{
+            id,
+            filter,
+            sender,
+            dropped: dropped.clone(),
+        });
+        self.active.store(true, Ordering::Relaxed);
+        log::warn!("Raw frames are tapped by operator {}(subscription {})", certificate.get_serial(), id);
+        Ok(RawFrameSubscription{
+            id,
+            receiver,
+            dropped,
+        })
+    }
+
+    ///
+    /// Delivers copy of frame to matching subscribers. Subscribers which are gone are removed.
+    ///
+    /// # Arguments
+    /// * direction: FrameDirection: whether frame is sent or received
+    /// * connection_id: u64: ID of connection frame passes through
+    /// * peer_id: u128: ID of peer on the other side, 0 if it is unknown
+    /// * data: &Serialized: frame as it is on wire
+    ///
+    pub fn publish(&self, direction: FrameDirection, connection_id: u64, peer_id: u128, data: &Serialized){
+        if !self.is_active(){
+            return;
+        }
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let timestamp = get_timestamp_with_milliseconds();
+        subscribers.retain(|subscriber| {
+            if !subscriber.filter.matches(direction){
+                return true;
+            }
+            let frame = RawFrame{
+                timestamp,
+                direction,
+                connection_id,
+                peer_id,
+                data: data.clone(),
+            };
+            match subscriber.sender.try_send(frame) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    subscriber.dropped.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    log::info!("Raw frame subscription {} is closed", subscriber.id);
+                    false
+                }
+            }
+        });
+        self.active.store(!subscribers.is_empty(), Ordering::Relaxed);
+    }
+}
+
+///
+/// Subscription to raw frames, it is closed once dropped
+///
+pub struct RawFrameSubscription{
+    id: u128,
+    receiver: Receiver<RawFrame>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl RawFrameSubscription {
+    #[inline]
+    pub fn get_id(&self) -> u128{
+        self.id
+    }
+
+    ///
+    /// Waits for next frame
+    ///
+    /// # Arguments
+    /// * timeout: Duration: how long to wait
+    ///
+    /// returns: Option<RawFrame>: frame or None if nothing arrived in time
+    ///
+    #[inline]
+    pub fn receive(&self, timeout: Duration) -> Option<RawFrame>{
+        self.receiver.recv_timeout(timeout).ok()
+    }
+
+    ///
+    /// Gets frames which already arrived without waiting
+    ///
+    pub fn take_pending(&self) -> Vec<RawFrame>{
+        self.receiver.try_iter().collect()
+    }
+
+    ///
+    /// Gets count of frames dropped as subscriber did not keep up with them
+    ///
+    #[inline]
+    pub fn get_dropped(&self) -> u64{
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+    use crate::pki::certificate::{FLAG_SIGN_MESSAGES, FLAG_USER_CERT};
+    use crate::testing::certificate::{test_certificates, MockCertificateService};
+    use crate::transport::async_stream::TokioStreamTransport;
+    use crate::transport::checksum::{ChecksumAlgorithm, ChecksumTransformer};
+
+    fn operator(flags: u128) -> OperatorIdentity{
+        let mut certificate = test_certificates().signing;
+        certificate.flags = FLAG_USER_CERT | FLAG_SIGN_MESSAGES | flags;
+        OperatorIdentity::new(certificate).unwrap()
+    }
+
+    #[test]
+    fn test_raw_tap_requires_permission() {
+        let mut certificates = MockCertificateService::with_test_certificates();
+        let tapper = operator(FLAG_TRANSPORT_TAP);
+        let disabled = RawFrameTap::new_shared(false);
+        assert_eq!(disabled.subscribe(&mut certificates, &tapper, RawFrameFilter::all(), 4).err(),
+                   Some(RawTapError::Disabled));
+        let tap = RawFrameTap::new_shared(true);
+        let serial = tapper.get_serial();
+        assert_eq!(tap.subscribe(&mut certificates, &operator(0), RawFrameFilter::all(), 4).err(),
+                   Some(RawTapError::NotPermitted(serial)));
+        let empty = RawFrameFilter{ send: false, receive: false };
+        assert_eq!(tap.subscribe(&mut certificates, &tapper, empty, 4).err(), Some(RawTapError::EmptyFilter));
+        certificates.set_verification_result(false);
+        assert_eq!(tap.subscribe(&mut certificates, &tapper, RawFrameFilter::all(), 4).err(),
+                   Some(RawTapError::Untrusted(serial)));
+        certificates.set_verification_result(true);
+        assert!(!tap.is_active());
+        let subscription = tap.subscribe(&mut certificates, &tapper, RawFrameFilter::all(), 4).unwrap();
+        assert!(tap.is_active());
+        drop(subscription);
+        tap.publish(FrameDirection::Send, 1, 2, &vec![1]);
+        assert!(!tap.is_active());
+    }
+
+    #[tokio::test]
+    async fn test_raw_tap_delivers_wire_frames() {
+        let (client, server) = duplex(1 << 16);
+        let mut client = TokioStreamTransport::from_stream(client);
+        let mut server = TokioStreamTransport::from_stream(server);
+        client.add_transformer(Box::new(ChecksumTransformer::new(ChecksumAlgorithm::Crc32)));
+        server.add_transformer(Box::new(ChecksumTransformer::new(ChecksumAlgorithm::Crc32)));
+        let tap = RawFrameTap::new_shared(true);
+        client.set_raw_frame_tap(tap.clone());
+        server.set_raw_frame_tap(tap.clone());
+        let mut certificates = MockCertificateService::with_test_certificates();
+        let tapper = operator(FLAG_TRANSPORT_TAP);
+        let received = tap.subscribe(&mut certificates, &tapper, RawFrameFilter::only(FrameDirection::Receive), 1)
+            .unwrap();
+        let all = tap.subscribe(&mut certificates, &tapper, RawFrameFilter::all(), 8).unwrap();
+
+        client.send_raw(vec![1, 2, 3]).await.unwrap();
+        assert_eq!(server.receive_raw(None).await, Some(vec![1, 2, 3]));
+        let frames = all.take_pending();
+        assert_eq!(frames.len(), 2);
+        assert_eq!((frames[0].direction, frames[1].direction), (FrameDirection::Send, FrameDirection::Receive));
+        // Frames are tapped with checksum, as they are on wire
+        assert_eq!(frames[0].data.len(), 8);
+        assert_eq!(frames[0].data, frames[1].data);
+        assert_eq!(frames[0].connection_id, client.get_connection_id());
+
+        // Subscriber which does not keep up loses frames, pipeline is not affected
+        client.send_raw(vec![4]).await.unwrap();
+        assert_eq!(server.receive_raw(None).await, Some(vec![4]));
+        assert_eq!(received.take_pending().len(), 1);
+        assert_eq!(received.get_dropped(), 1);
+        assert_eq!(all.get_dropped(), 0);
+    }
+}
@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use crate::message::common::Message;
+use crate::services::impls::transport::LocalTransportService;
+use crate::tokio::init_tokio;
+use crate::transport::{SendError, TransportSender};
+use crate::transport::async_stream::{TokioStreamTransport, TransportWriteHalf};
+use crate::transport::events::DisconnectReason;
+use crate::transport::keepalive::KeepAlivePolicy;
+use crate::transport::ratelimit::{RateLimitVerdict, SharedRateLimiter};
+
+struct Route{
+    connection_id: u64,
+    messages: UnboundedSender<Message>,
+}
+
+///
+/// Routes of host to its peers: each authorized connection registers a route to its peer
+/// and sends messages router queues for it. Messages to hosts without route are sent by
+/// default route, e.g. clients send everything through server.
+///
+pub struct Router{
+    host_id: u128,
+    routes: HashMap<u128, Route>,
+    default_route: Option<u128>,
+    /** Count of messages which had no route **/
+    unroutable: u64,
+}
+
+///
+/// Router shared by connections of host and its transport service
+///
+pub type SharedRouter = Arc<Mutex<Router>>;
+
+impl Router {
+    pub fn new(host_id: u128) -> Router{
+        Router{
+            host_id,
+            routes: HashMap::new(),
+            default_route: None,
+            unroutable: 0,
+        }
+    }
+
+    pub fn new_shared(host_id: u128) -> SharedRouter{
+        Arc::new(Mutex::new(Self::new(host_id)))
+    }
+
+    #[inline]
+    pub fn get_host_id(&self) -> u128{
+        self.host_id
+    }
+
+    ///
+    /// Adds route to peer, replacing route of its previous connection
+    ///
+    /// # Arguments
+    /// * peer_id: u128: ID of authorized peer
+    /// * connection_id: u64: connection to peer
+    ///
+    /// returns: UnboundedReceiver<Message>: messages connection must send to peer, closed
+    /// once route is removed or replaced
+    ///
+    pub fn add_route(&mut self, peer_id: u128, connection_id: u64) -> UnboundedReceiver<Message>{
+        let (messages, receiver) = unbounded_channel();
+        if self.routes.insert(peer_id, Route{ connection_id, messages }).is_some(){
+            log::info!("Route to peer {} is replaced by connection {}", peer_id, connection_id);
+        }
+        receiver
+    }
+
+    ///
+    /// Removes route to peer if it still goes through given connection
+    ///
+    /// returns: bool: whether route was removed
+    ///
+    pub fn remove_route(&mut self, peer_id: u128, connection_id: u64) -> bool{
+        if self.routes.get(&peer_id).is_some_and(|route| route.connection_id == connection_id){
+            self.routes.remove(&peer_id);
+            return true;
+        }
+        false
+    }
+
+    ///
+    /// Removes route going through given connection, e.g. once reaper closes it
+    ///
+    /// returns: bool: whether route was removed
+    ///
+    pub fn remove_connection(&mut self, connection_id: u64) -> bool{
+        let count = self.routes.len();
+        self.routes.retain(|_, route| route.connection_id != connection_id);
+        self.routes.len() != count
+    }
+
+    ///
+    /// Sets peer messages to hosts without route are sent to, None to drop them
+    ///
+    pub fn set_default_route(&mut self, peer_id: Option<u128>) -> &mut Self{
+        self.default_route = peer_id;
+        self
+    }
+
+    ///
+    /// Gets IDs of peers with route in ascending order
+    ///
+    pub fn get_peers(&self) -> Vec<u128>{
+        let mut peers: Vec<u128> = self.routes.keys().copied().collect();
+        peers.sort();
+        peers
+    }
+
+    #[inline]
+    pub fn has_route(&self, peer_id: u128) -> bool{
+        self.routes.contains_key(&peer_id)
+    }
+
+    #[inline]
+    pub fn get_unroutable_count(&self) -> u64{
+        self.unroutable
+    }
+
+    ///
+    /// Queues message for connection to its destination or for default route
+    ///
+    /// returns: Result<(), SendError>: error if there is no route to destination
+    ///
+    pub fn route(&mut self, message: Message) -> Result<(), SendError>{
+        let destination = message.destination;
+        let route = self.routes.get(&destination)
+            .or_else(|| self.default_route.and_then(|peer_id| self.routes.get(&peer_id)));
+        if route.is_some_and(|route| route.messages.send(message).is_ok()){
+            return Ok(());
+        }
+        self.unroutable += 1;
+        Err(SendError::Unreachable(destination))
+    }
+}
+
+///
+/// Sends messages by router, e.g. as remote sender of LocalTransportService
+///
+pub struct RouterSender{
+    router: SharedRouter,
+}
+
+impl RouterSender {
+    pub fn new(router: SharedRouter) -> RouterSender{
+        RouterSender{
+            router,
+        }
+    }
+}
+
+impl TransportSender for RouterSender{
+    #[inline]
+    fn send_message(&mut self, message: Message) {
+        let _ = self.try_send_message(message);
+    }
+
+    fn try_send_message(&mut self, mut message: Message) -> Result<(), SendError> {
+        message.ensure_id();
+        self.router.lock().unwrap().route(message)
+    }
+}
+
+///
+/// Delivers received messages to transport service of host on its own thread, so listeners
+/// of modules may use certificate service binders, which can not be used from coroutines
+/// of connections.
+///
+#[derive(Clone)]
+pub struct LocalDelivery{
+    messages: mpsc::Sender<Message>,
+}
+
+impl LocalDelivery {
+    ///
+    /// Starts delivery thread, it is stopped once all clones are dropped
+    ///
+    /// # Arguments
+    /// * service: LocalTransportService: service checking and delivering received messages
+    ///
+    pub fn spawn(service: LocalTransportService) -> LocalDelivery{
+        let (messages, receiver) = mpsc::channel::<Message>();
+        std::thread::spawn(move || {
+            init_tokio();
+            for message in receiver{
+                // Rate limiter of service remembers peers to disconnect, connections check it
+                if service.receive_message(message) == RateLimitVerdict::Disconnect{
+                    log::warn!("Peer exceeded its quota and is disconnected");
+                }
+            }
+        });
+        LocalDelivery{
+            messages,
+        }
+    }
+
+    pub fn deliver(&self, message: Message){
+        if self.messages.send(message).is_err(){
+            log::error!("Delivery of received messages is stopped, message dropped");
+        }
+    }
+}
+
+///
+/// Serves authorized connections: messages received from peer are delivered to host or
+/// routed to their destination, messages router queues for peer are sent to it.
+///
+#[derive(Clone)]
+pub struct PeerLink{
+    router: SharedRouter,
+    delivery: LocalDelivery,
+    keepalive: KeepAlivePolicy,
+    rate_limiter: Option<SharedRateLimiter>,
+    /** Whether peer forwards messages of other hosts, e.g. server of client **/
+    is_relay: bool,
+}
+
+impl PeerLink {
+    ///
+    /// Creates link of host
+    ///
+    /// # Arguments
+    /// * router: SharedRouter: routes of host
+    /// * delivery: LocalDelivery: delivers messages addressed to host
+    /// * keepalive: KeepAlivePolicy: timeouts of idle connections
+    ///
+    pub fn new(router: SharedRouter, delivery: LocalDelivery, keepalive: KeepAlivePolicy) -> PeerLink{
+        PeerLink{
+            router,
+            delivery,
+            keepalive,
+            rate_limiter: None,
+            is_relay: false,
+        }
+    }
+
+    ///
+    /// Sets limiter enforcing quotas on messages routed through host. Messages to host itself
+    /// are checked by its transport service.
+    ///
+    pub fn set_rate_limiter(&mut self, limiter: SharedRateLimiter) -> &mut Self{
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    ///
+    /// Sets whether peers may send messages on behalf of other hosts. Otherwise messages
+    /// whose source is not peer itself are dropped.
+    ///
+    pub fn set_relay(&mut self, is_relay: bool) -> &mut Self{
+        self.is_relay = is_relay;
+        self
+    }
+
+    fn is_disconnected(&self, peer_id: u128) -> bool{
+        self.rate_limiter.as_ref().is_some_and(|limiter| limiter.lock().unwrap().is_disconnected(peer_id))
+    }
+
+    ///
+    /// Serves connection until it is closed or peer is disconnected by rate limiter. Must be
+    /// called within tokio runtime once handshake is done.
+    ///
+    /// # Arguments
+    /// * transport: TokioStreamTransport<T>: connection to peer
+    /// * peer_id: u128: ID peer was authorized with
+    ///
+    pub async fn serve<T>(&self, mut transport: TokioStreamTransport<T>, peer_id: u128)
+        where T: AsyncReadExt + AsyncWriteExt + Sync + Send + Unpin + 'static{
+        if self.is_disconnected(peer_id){
+            log::warn!("Peer {} is disconnected by rate limiter, connection is refused", peer_id);
+            transport.set_disconnect_reason(DisconnectReason::Error("rate limit exceeded".to_string()));
+            return;
+        }
+        let connection_id = transport.get_connection_id();
+        let (mut reader, writer) = transport.into_split();
+        let messages = self.router.lock().unwrap().add_route(peer_id, connection_id);
+        let writer = tokio::spawn(Self::write(writer, messages));
+        while let Some(message) = reader.receive_message(&self.keepalive).await{
+            self.forward(peer_id, message);
+            if self.is_disconnected(peer_id){
+                log::warn!("Peer {} exceeded its quota, closing connection", peer_id);
+                reader.set_disconnect_reason(DisconnectReason::Error("rate limit exceeded".to_string()));
+                break;
+            }
+        }
+        self.router.lock().unwrap().remove_route(peer_id, connection_id);
+        writer.abort();
+    }
+
+    fn forward(&self, peer_id: u128, message: Message){
+        if !self.is_relay && message.source != peer_id{
+            log::warn!("Peer {} sent message id={} on behalf of {}, message dropped", peer_id, message.id,
+                message.source);
+            return;
+        }
+        let mut router = self.router.lock().unwrap();
+        if message.destination == router.get_host_id(){
+            drop(router);
+            self.delivery.deliver(message);
+            return;
+        }
+        let verdict = match &self.rate_limiter {
+            Some(limiter) => limiter.lock().unwrap().check(&message),
+            None => RateLimitVerdict::Allow,
+        };
+        if matches!(verdict, RateLimitVerdict::Drop | RateLimitVerdict::Disconnect){
+            log::warn!("Message id={} from {} dropped by rate limiter", message.id, message.source);
+            return;
+        }
+        let id = message.id;
+        if let Err(error) = router.route(message){
+            log::warn!("Can not route message id={} from {}: {}", id, peer_id, error);
+        }
+    }
+
+    // Sends messages queued by router and keep-alive frames of receiving half
+    async fn write<T>(mut writer: TransportWriteHalf<T>, mut messages: UnboundedReceiver<Message>)
+        where T: AsyncReadExt + AsyncWriteExt + Sync + Send + Unpin{
+        loop {
+            tokio::select! {
+                frame = writer.receive_control_frame() => {
+                    let sent = match frame {
+                        Some(frame) => writer.send_raw(frame).await.is_ok(),
+                        None => false,
+                    };
+                    if !sent{
+                        break;
+                    }
+                }
+                message = messages.recv() => {
+                    let mut batch = match message {
+                        Some(message) => vec![message],
+                        None => break,
+                    };
+                    while let Ok(message) = messages.try_recv(){
+                        batch.push(message);
+                    }
+                    if writer.send_messages(&batch).await < batch.len(){
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::message::types::MessageType;
+    use crate::services::transport::{MessageFilter, TransportService};
+    use crate::testing::transport::loopback_stream_pair;
+    use crate::transport::TransportListener;
+
+    struct CollectingListener{
+        received: mpsc::Sender<Message>,
+    }
+
+    impl TransportListener for CollectingListener{
+        fn on_message(&mut self, message: Message) {
+            let _ = self.received.send(message);
+        }
+    }
+
+    fn message_from(source: u128, destination: u128) -> Message{
+        let mut message = Message::new();
+        message.set_type(MessageType::Pong);
+        message.source = source;
+        message.destination = destination;
+        message
+    }
+
+    #[test]
+    fn test_router() {
+        let mut router = Router::new(1);
+        let mut first = router.add_route(5, 10);
+        assert_eq!(router.route(message_from(1, 5)), Ok(()));
+        assert_eq!(router.route(message_from(1, 6)), Err(SendError::Unreachable(6)));
+        router.set_default_route(Some(5));
+        assert_eq!(router.route(message_from(1, 6)), Ok(()));
+        assert_eq!(first.try_recv().unwrap().destination, 5);
+        assert_eq!(first.try_recv().unwrap().destination, 6);
+
+        // Newer connection of peer takes route over, older one can not remove it
+        let _second = router.add_route(5, 11);
+        assert!(first.try_recv().is_err());
+        assert!(!router.remove_route(5, 10));
+        assert_eq!(router.get_peers(), vec![5]);
+        assert!(router.remove_connection(11));
+        assert!(!router.has_route(5));
+        assert_eq!(router.get_unroutable_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_link_delivers_and_routes() {
+        let mut service = LocalTransportService::new(1);
+        let (received, receiver) = mpsc::channel();
+        service.subscribe_to_messages(&MessageFilter::new(), Box::new(CollectingListener{ received }));
+        let router = Router::new_shared(1);
+        let link = PeerLink::new(router.clone(), LocalDelivery::spawn(service),
+                                 KeepAlivePolicy{ idle_timeout: 1000, probe_timeout: 1000, reap_interval: 10 });
+        let mut other = router.lock().unwrap().add_route(7, 100);
+        let (mut client, server) = loopback_stream_pair();
+        let served = tokio::spawn(async move { link.serve(server, 5).await });
+
+        client.send_message(&message_from(5, 1)).await;
+        client.send_message(&message_from(5, 7)).await;
+        client.send_message(&message_from(6, 7)).await;
+        client.send_message(&message_from(5, 7)).await;
+        // Message on behalf of another host is dropped
+        assert_eq!(other.recv().await.unwrap().source, 5);
+        assert_eq!(other.recv().await.unwrap().source, 5);
+        let delivered = tokio::task::spawn_blocking(move || receiver.recv_timeout(Duration::from_secs(5))).await;
+        assert_eq!(delivered.unwrap().unwrap().source, 5);
+
+        // Messages queued for peer are sent by its connection
+        RouterSender::new(router.clone()).send_message(message_from(1, 5));
+        let policy = KeepAlivePolicy{ idle_timeout: 1000, probe_timeout: 1000, reap_interval: 10 };
+        assert_eq!(client.receive_message(&policy).await.unwrap().destination, 5);
+
+        drop(client);
+        served.await.unwrap();
+        assert!(!router.lock().unwrap().has_route(5));
+    }
+}
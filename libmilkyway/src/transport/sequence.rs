@@ -0,0 +1,540 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use libmilkyway_derive::{Describe, Deserializable, Serializable};
+use crate::message::common::Message;
+use crate::message::types::MessageType;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::migration::{dump_versioned, load_versioned, VersionedStorage};
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+use crate::transport::{TransportListener, TransportSender};
+
+///
+/// Default count of sequences received ahead of a gap which are remembered. Once exceeded,
+/// missing sequences are given up as lost.
+///
+pub const DEFAULT_MAX_OUT_OF_ORDER: u64 = 1024;
+
+///
+/// Data of Sequenced message: original type and data of message with its sequence number
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq, Describe)]
+pub struct SequencedEnvelope{
+    /** Sequences of each sender and receiver pair start from 1 **/
+    pub sequence: u64,
+    pub message_type: MessageType,
+    pub data: Option<Serialized>,
+}
+
+///
+/// Data of SequenceAck message: receiver has recorded sequence and will not deliver it again
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq, Describe)]
+pub struct SequenceAck{
+    pub sequence: u64,
+}
+
+///
+/// Wraps message into Sequenced one, the rest of fields(including signature) are kept
+///
+/// # Arguments
+/// * message: Message: message to wrap
+/// * sequence: u64: sequence of message
+///
+pub fn wrap_sequenced(mut message: Message, sequence: u64) -> Message{
+    let envelope = SequencedEnvelope{
+        sequence,
+        message_type: message.message_type.clone(),
+        data: message.data.take(),
+    };
+    message.set_type(MessageType::Sequenced).set_data(Some(envelope.serialize()));
+    message
+}
+
+///
+/// Restores message wrapped by wrap_sequenced
+///
+/// returns: Option<(u64, Message)>: sequence and original message or None if message is
+/// not a valid Sequenced one
+///
+pub fn unwrap_sequenced(mut message: Message) -> Option<(u64, Message)>{
+    if message.message_type != MessageType::Sequenced{
+        return None;
+    }
+    let (envelope, _) = SequencedEnvelope::from_serialized(message.data.as_ref()?).ok()?;
+    message.set_type(envelope.message_type).set_data(envelope.data);
+    Some((envelope.sequence, message))
+}
+
+///
+/// Counters of sequenced messages of a peer, kept across restarts
+///
+#[derive(Clone, Debug, Default, PartialEq, Serializable, Deserializable)]
+pub struct SequenceStats{
+    /** Messages sequenced for peer **/
+    pub sent: u64,
+    /** Messages peer acknowledged **/
+    pub acknowledged: u64,
+    /** Unacknowledged messages sent again **/
+    pub resent: u64,
+    /** Messages of peer delivered to listener **/
+    pub delivered: u64,
+    /** Messages of peer which were already delivered **/
+    pub duplicates: u64,
+    /** Sequences found missing when a later sequence arrived **/
+    pub gaps: u64,
+    /** Missing sequences given up because too many later ones arrived **/
+    pub lost: u64,
+}
+
+impl SequenceStats {
+    fn add(&mut self, other: &SequenceStats){
+        self.sent += other.sent;
+        self.acknowledged += other.acknowledged;
+        self.resent += other.resent;
+        self.delivered += other.delivered;
+        self.duplicates += other.duplicates;
+        self.gaps += other.gaps;
+        self.lost += other.lost;
+    }
+}
+
+///
+/// Whether received sequenced message should be delivered
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SequenceVerdict{
+    Deliver,
+    /** Message was delivered before, e.g. it was resent as acknowledgement got lost **/
+    Duplicate,
+}
+
+#[derive(Clone, Default, Serializable, Deserializable)]
+struct PeerSequences{
+    last_sent: u64,
+    /** Sent messages(already wrapped) waiting for acknowledgement **/
+    unacknowledged: Vec<Message>,
+    /** Every sequence up to this one was received **/
+    received: u64,
+    /** Received sequences after a gap, sorted **/
+    ahead: Vec<u64>,
+    stats: SequenceStats,
+}
+
+///
+/// Persistent sequence numbers of messages sent to and received from each peer, giving
+/// exactly-once delivery of messages which must not be executed twice:
+/// * sender numbers messages per peer and keeps them until peer acknowledges them, so they
+///   survive restarts and may be sent again
+/// * receiver records sequence before delivering message and acknowledges it, repeated
+///   sequences are dropped as duplicates
+///
+/// Sequence is recorded before message is delivered, so crash in between loses message
+/// instead of executing it twice.
+///
+#[derive(Serializable, Deserializable)]
+pub struct SequenceStore{
+    storage_file_name: String,
+    max_out_of_order: u64,
+    peers: HashMap<u128, PeerSequences>,
+}
+
+///
+/// Sequence store shared between senders and listeners
+///
+pub type SharedSequenceStore = Arc<Mutex<SequenceStore>>;
+
+impl SequenceStore {
+    ///
+    /// Creates empty store keeping data in provided file
+    ///
+    /// # Arguments
+    /// * filename: &str: file to store sequences in
+    ///
+    pub fn new(filename: &str) -> SequenceStore{
+        SequenceStore{
+            storage_file_name: filename.to_string(),
+            max_out_of_order: DEFAULT_MAX_OUT_OF_ORDER,
+            peers: HashMap::new(),
+        }
+    }
+
+    ///
+    /// Loads store from file
+    ///
+    pub fn load_from_file(file: &str) -> SequenceStore{
+        let mut store = load_versioned::<SequenceStore>(Path::new(file)).expect("Failed to load sequence store");
+        store.storage_file_name = file.to_string();
+        store
+    }
+
+    ///
+    /// Loads store from file or creates empty one if file does not exist
+    ///
+    pub fn open_shared(file: &str) -> SharedSequenceStore{
+        let store = if Path::new(file).exists(){
+            SequenceStore::load_from_file(file)
+        } else {
+            SequenceStore::new(file)
+        };
+        Arc::new(Mutex::new(store))
+    }
+
+    pub fn set_max_out_of_order(&mut self, max_out_of_order: u64) -> &mut Self{
+        self.max_out_of_order = max_out_of_order;
+        self
+    }
+
+    ///
+    /// Numbers message for its destination and keeps it until acknowledged
+    ///
+    /// # Arguments
+    /// * message: Message: message with ID set
+    ///
+    /// returns: Message: Sequenced message to send
+    ///
+    pub fn prepare(&mut self, message: Message) -> Message{
+        let peer = self.peers.entry(message.destination).or_default();
+        peer.last_sent += 1;
+        peer.stats.sent += 1;
+        let message = wrap_sequenced(message, peer.last_sent);
+        peer.unacknowledged.push(message.clone());
+        self.commit();
+        message
+    }
+
+    ///
+    /// Forgets message peer acknowledged
+    ///
+    /// returns: bool: whether message was waiting for acknowledgement
+    ///
+    pub fn acknowledge(&mut self, peer_id: u128, sequence: u64) -> bool{
+        let peer = match self.peers.get_mut(&peer_id) {
+            Some(peer) => peer,
+            None => return false,
+        };
+        let count = peer.unacknowledged.len();
+        peer.unacknowledged.retain(|message| unwrap_sequenced(message.clone())
+            .is_none_or(|(pending, _)| pending != sequence));
+        if count == peer.unacknowledged.len(){
+            return false;
+        }
+        peer.stats.acknowledged += 1;
+        self.commit();
+        true
+    }
+
+    ///
+    /// Gets messages peer did not acknowledge yet in order they were sent, e.g. to send
+    /// them again after reconnection
+    ///
+    pub fn get_unacknowledged(&self, peer_id: u128) -> Vec<Message>{
+        self.peers.get(&peer_id).map_or(Vec::new(), |peer| peer.unacknowledged.clone())
+    }
+
+    ///
+    /// Counts unacknowledged message sent again
+    ///
+    pub fn record_resent(&mut self, peer_id: u128){
+        self.peers.entry(peer_id).or_default().stats.resent += 1;
+    }
+
+    ///
+    /// Records sequence received from peer
+    ///
+    /// # Arguments
+    /// * peer_id: u128: ID of sender
+    /// * sequence: u64: sequence of message
+    ///
+    /// returns: SequenceVerdict: whether message should be delivered
+    ///
+    pub fn accept(&mut self, peer_id: u128, sequence: u64) -> SequenceVerdict{
+        let max_out_of_order = self.max_out_of_order;
+        let peer = self.peers.entry(peer_id).or_default();
+        if sequence <= peer.received || peer.ahead.binary_search(&sequence).is_ok(){
+            peer.stats.duplicates += 1;
+            self.commit();
+            return SequenceVerdict::Duplicate;
+        }
+        let last_known = peer.ahead.last().copied().unwrap_or(peer.received);
+        if sequence > last_known + 1{
+            peer.stats.gaps += sequence - last_known - 1;
+            log::warn!("Sequences {}..{} of peer {} are missing", last_known + 1, sequence - 1, peer_id);
+        }
+        let position = peer.ahead.binary_search(&sequence).unwrap_err();
+        peer.ahead.insert(position, sequence);
+        if peer.ahead.len() as u64 > max_out_of_order{
+            // Oldest gap will not be filled anymore
+            let first = peer.ahead[0];
+            peer.stats.lost += first - peer.received - 1;
+            peer.received = first - 1;
+        }
+        while peer.ahead.first() == Some(&(peer.received + 1)){
+            peer.received += 1;
+            peer.ahead.remove(0);
+        }
+        peer.stats.delivered += 1;
+        self.commit();
+        SequenceVerdict::Deliver
+    }
+
+    ///
+    /// Gets IDs of peers which sent or received sequenced messages
+    ///
+    pub fn get_peers(&self) -> Vec<u128>{
+        let mut peers: Vec<u128> = self.peers.keys().copied().collect();
+        peers.sort();
+        peers
+    }
+
+    ///
+    /// Gets counters of peer
+    ///
+    pub fn get_peer_stats(&self, peer_id: u128) -> Option<SequenceStats>{
+        self.peers.get(&peer_id).map(|peer| peer.stats.clone())
+    }
+
+    ///
+    /// Gets counters of all peers together
+    ///
+    pub fn get_stats(&self) -> SequenceStats{
+        let mut stats = SequenceStats::default();
+        for peer in self.peers.values(){
+            stats.add(&peer.stats);
+        }
+        stats
+    }
+
+    ///
+    /// Saves store to storage
+    ///
+    #[inline]
+    pub fn commit(&mut self){
+        if dump_versioned(self, &self.storage_file_name).is_err(){
+            log::error!("Failed to save sequences to {}", self.storage_file_name);
+        }
+    }
+}
+
+impl VersionedStorage for SequenceStore {
+    const STORE_NAME: &'static str = "sequences";
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+///
+/// A sender numbering every message in sequence store, use it for messages which must not be
+/// executed twice. Receiver must handle messages with ExactlyOnceListener.
+///
+pub struct SequencedSender{
+    sender: Box<dyn TransportSender>,
+    store: SharedSequenceStore,
+}
+
+impl SequencedSender {
+    pub fn new(sender: Box<dyn TransportSender>, store: SharedSequenceStore) -> SequencedSender{
+        SequencedSender{
+            sender,
+            store,
+        }
+    }
+
+    ///
+    /// Sends again messages peer did not acknowledge, e.g. once it reconnected
+    ///
+    /// returns: usize: count of messages sent
+    ///
+    pub fn resend_unacknowledged(&mut self, peer_id: u128) -> usize{
+        let pending = self.store.lock().unwrap().get_unacknowledged(peer_id);
+        for message in pending.iter(){
+            self.store.lock().unwrap().record_resent(peer_id);
+            self.sender.send_message(message.clone());
+        }
+        pending.len()
+    }
+}
+
+impl TransportSender for SequencedSender{
+    fn send_message(&mut self, mut message: Message) {
+        message.ensure_id();
+        let message = self.store.lock().unwrap().prepare(message);
+        self.sender.send_message(message);
+    }
+
+    fn send_batch(&mut self, messages: Vec<Message>) {
+        let mut store = self.store.lock().unwrap();
+        let messages = messages.into_iter().map(|mut message| {
+            message.ensure_id();
+            store.prepare(message)
+        }).collect();
+        drop(store);
+        self.sender.send_batch(messages);
+    }
+}
+
+///
+/// Listener delivering each Sequenced message once and acknowledging it to sender.
+/// Acknowledgements of peers are recorded in store, other messages are passed as is.
+/// Subscription must not filter by message type, as messages arrive as Sequenced.
+///
+pub struct ExactlyOnceListener{
+    listener: Box<dyn TransportListener>,
+    store: SharedSequenceStore,
+    sender: Box<dyn TransportSender>,
+}
+
+impl ExactlyOnceListener {
+    ///
+    /// Creates a listener
+    ///
+    /// # Arguments
+    /// * listener: Box<dyn TransportListener>: listener messages are delivered to
+    /// * store: SharedSequenceStore: store of received sequences
+    /// * sender: Box<dyn TransportSender>: sender of acknowledgements
+    ///
+    pub fn new(listener: Box<dyn TransportListener>, store: SharedSequenceStore,
+               sender: Box<dyn TransportSender>) -> ExactlyOnceListener{
+        ExactlyOnceListener{
+            listener,
+            store,
+            sender,
+        }
+    }
+}
+
+impl TransportListener for ExactlyOnceListener{
+    fn on_message(&mut self, message: Message) {
+        match message.message_type {
+            MessageType::Sequenced => {
+                let (source, module_id) = (message.source, message.module_id);
+                let (sequence, message) = match unwrap_sequenced(message) {
+                    Some(unwrapped) => unwrapped,
+                    None => {
+                        log::warn!("Malformed sequenced message from {} dropped", source);
+                        return;
+                    }
+                };
+                let verdict = self.store.lock().unwrap().accept(source, sequence);
+                // Duplicates are acknowledged too, as previous acknowledgement could be lost
+                let mut ack = Message::new();
+                ack.set_type(MessageType::SequenceAck)
+                    .set_destination(source)
+                    .set_module_id(module_id)
+                    .set_data(Some(SequenceAck{ sequence }.serialize()));
+                self.sender.send_message(ack);
+                if verdict == SequenceVerdict::Deliver{
+                    self.listener.on_message(message);
+                }
+            }
+            MessageType::SequenceAck => {
+                let ack = message.data.as_ref().and_then(|data| SequenceAck::from_serialized(data).ok());
+                match ack {
+                    Some((ack, _)) => {
+                        self.store.lock().unwrap().acknowledge(message.source, ack.sequence);
+                    }
+                    None => log::warn!("Malformed sequence acknowledgement from {} dropped", message.source),
+                }
+            }
+            _ => self.listener.on_message(message),
+        }
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct CapturingSender{
+        sent: Arc<Mutex<Vec<Message>>>,
+    }
+
+    impl TransportSender for CapturingSender{
+        fn send_message(&mut self, message: Message) {
+            self.sent.lock().unwrap().push(message);
+        }
+    }
+
+    struct CollectingListener{
+        received: Arc<Mutex<Vec<Message>>>,
+    }
+
+    impl TransportListener for CollectingListener{
+        fn on_message(&mut self, message: Message) {
+            self.received.lock().unwrap().push(message);
+        }
+    }
+
+    fn temporary_file(name: &str) -> String{
+        std::env::temp_dir().join(format!("milkyway-{}-{}.dat", name, rand::random::<u64>()))
+            .to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_exactly_once_delivery() {
+        let (sender_file, receiver_file) = (temporary_file("sequences-a"), temporary_file("sequences-b"));
+        let sender_store = SequenceStore::open_shared(&sender_file);
+        let receiver_store = SequenceStore::open_shared(&receiver_file);
+        let wire = Arc::new(Mutex::new(Vec::new()));
+        let acks = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let mut sender = SequencedSender::new(Box::new(CapturingSender{ sent: wire.clone() }), sender_store.clone());
+        let mut receiver = ExactlyOnceListener::new(Box::new(CollectingListener{ received: received.clone() }),
+                                                    receiver_store.clone(),
+                                                    Box::new(CapturingSender{ sent: acks.clone() }));
+        for command in [b"rm".to_vec(), b"mv".to_vec()]{
+            let mut message = Message::new();
+            message.set_type(MessageType::Exec).set_destination(20).set_data(Some(command));
+            sender.send_message(message);
+        }
+        let sent: Vec<Message> = wire.lock().unwrap().drain(..).collect();
+        assert_eq!(sent[0].message_type, MessageType::Sequenced);
+        for mut message in sent.into_iter(){
+            message.source = 10;
+            receiver.on_message(message);
+        }
+        // Acknowledgement of the second message is lost, so it is sent again
+        let mut ack = acks.lock().unwrap().remove(0);
+        ack.source = 20;
+        let mut listener = ExactlyOnceListener::new(Box::new(CollectingListener{ received: Arc::new(Mutex::new(Vec::new())) }),
+                                                    sender_store.clone(), Box::new(CapturingSender{ sent: acks.clone() }));
+        listener.on_message(ack);
+        acks.lock().unwrap().clear();
+        // Sender restarts
+        let mut sender = SequencedSender::new(Box::new(CapturingSender{ sent: wire.clone() }),
+                                              Arc::new(Mutex::new(SequenceStore::load_from_file(&sender_file))));
+        assert_eq!(sender.resend_unacknowledged(20), 1);
+        let mut resent = wire.lock().unwrap().remove(0);
+        resent.source = 10;
+        receiver.on_message(resent);
+        assert_eq!(acks.lock().unwrap().len(), 1);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!((received[1].message_type.clone(), received[1].data.clone()), (MessageType::Exec, Some(b"mv".to_vec())));
+        let stats = SequenceStore::load_from_file(&receiver_file).get_peer_stats(10).unwrap();
+        assert_eq!((stats.delivered, stats.duplicates), (2, 1));
+        assert_eq!(sender_store.lock().unwrap().get_stats().acknowledged, 1);
+        std::fs::remove_file(sender_file).unwrap();
+        std::fs::remove_file(receiver_file).unwrap();
+    }
+
+    #[test]
+    fn test_sequence_gaps() {
+        let mut store = SequenceStore::new(&temporary_file("sequences-gaps"));
+        store.set_max_out_of_order(1);
+        assert_eq!(store.accept(5, 1), SequenceVerdict::Deliver);
+        assert_eq!(store.accept(5, 4), SequenceVerdict::Deliver);
+        assert_eq!(store.accept(5, 4), SequenceVerdict::Duplicate);
+        assert_eq!(store.accept(5, 2), SequenceVerdict::Deliver);
+        assert_eq!(store.accept(5, 1), SequenceVerdict::Duplicate);
+        // Sequence 3 is given up once window overflows
+        assert_eq!(store.accept(5, 6), SequenceVerdict::Deliver);
+        assert_eq!(store.accept(5, 3), SequenceVerdict::Duplicate);
+        assert_eq!(store.accept(5, 5), SequenceVerdict::Deliver);
+        let stats = store.get_peer_stats(5).unwrap();
+        assert_eq!((stats.delivered, stats.duplicates, stats.gaps, stats.lost), (5, 3, 3, 1));
+        std::fs::remove_file(&store.storage_file_name).unwrap();
+    }
+}
@@ -0,0 +1,443 @@
+use std::fmt::{Display, Formatter};
+use std::sync::mpsc;
+use libmilkyway_derive::{Deserializable, EnumDeserializable, EnumSerializable, Serializable};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::oneshot;
+use crate::controllers::authorization::{AuthorizationController, AuthorizationMessage, AuthorizationStatus};
+use crate::controllers::authorization::chain::{ChainRequest, ChainResponse};
+use crate::controllers::authorization::factor::{AuthChallenge, AuthChallengeResponse};
+use crate::pki::certificate::Certificate;
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::tokio::init_tokio;
+use crate::transport::async_stream::TokioStreamTransport;
+use crate::transport::handshake::{HandshakeError, HandshakeStage};
+use crate::transport::stack::{TransformerNegotiationError, TransformerStack, TransformerStackDescriptor,
+                              CRYPTO_TRANSFORMER_NAME};
+use crate::transport::version::{VersionNegotiationError, VersionPolicy};
+
+///
+/// Errors of establishing session
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum SessionError{
+    Version(VersionNegotiationError),
+    Handshake(HandshakeError),
+    /** Frame of peer is not the one expected at this stage **/
+    Malformed,
+    /** Authorization message of local side can not be generated **/
+    Identity(&'static str),
+    /** Certificates of peer or of local side are rejected **/
+    Rejected,
+    /** Challenge of server requires an answer, but no responder is set or it gave none **/
+    ChallengeUnanswered,
+    Transformers(TransformerNegotiationError),
+    /** Thread of authorization controller is gone **/
+    AuthorityUnavailable,
+}
+
+impl Display for SessionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionError::Version(error) => write!(f, "{}", error),
+            SessionError::Handshake(error) => write!(f, "{}", error),
+            SessionError::Malformed => write!(f, "peer sent malformed handshake frame"),
+            SessionError::Identity(error) => write!(f, "can not present local certificates: {}", error),
+            SessionError::Rejected => write!(f, "authorization is rejected"),
+            SessionError::ChallengeUnanswered => write!(f, "authentication challenge is not answered"),
+            SessionError::Transformers(error) => write!(f, "can not agree on transformers: {:?}", error),
+            SessionError::AuthorityUnavailable => write!(f, "authorization controller is not running"),
+        }
+    }
+}
+
+///
+/// First frame of authorization sent by connecting side
+///
+#[derive(Clone, Serializable, Deserializable)]
+pub struct SessionHello{
+    /** ID connecting side claims, checked against its pins **/
+    pub peer_id: u128,
+    pub authorization: AuthorizationMessage,
+}
+
+///
+/// Outcome of authorization step reported by accepting side
+///
+#[derive(Clone, Copy, Debug, PartialEq, EnumSerializable, EnumDeserializable)]
+pub enum SessionStatus{
+    /** Payload is authorization message of accepting side **/
+    Authorized,
+    /** Payload is ChainRequest, connecting side answers with ChainResponse **/
+    ChainRequired,
+    /** Payload is AuthChallenge, connecting side answers with AuthChallengeResponse **/
+    ChallengeRequired,
+    Rejected,
+}
+
+///
+/// Reply of accepting side to SessionHello and to answers of connecting side
+///
+#[derive(Clone, Serializable, Deserializable)]
+pub struct SessionReply{
+    pub status: SessionStatus,
+    /** ID of accepting side **/
+    pub host_id: u128,
+    pub payload: Serialized,
+}
+
+///
+/// Answers authentication challenges of server, e.g. asks user for TOTP code
+///
+pub type ChallengeResponder = Box<dyn Fn(&AuthChallenge) -> Option<String> + Send + Sync>;
+
+type AuthorityCall = Box<dyn FnOnce(&mut AuthorizationController) + Send>;
+
+///
+/// Runs AuthorizationController on its own thread, as certificate service binders block on
+/// thread-local runtime and can not be used from async tasks. Clones share the controller,
+/// so challenges and chain requests issued for one connection are answered on another.
+///
+#[derive(Clone)]
+pub struct AuthorizationAuthority{
+    calls: mpsc::Sender<AuthorityCall>,
+}
+
+impl AuthorizationAuthority {
+    ///
+    /// Starts thread of controller, it is stopped once all clones are dropped
+    ///
+    /// # Arguments
+    /// * create: F: creates controller on its thread, e.g. binds it to certificate service
+    ///
+    pub fn spawn<F>(create: F) -> AuthorizationAuthority
+        where F: FnOnce() -> AuthorizationController + Send + 'static{
+        let (calls, receiver) = mpsc::channel::<AuthorityCall>();
+        std::thread::spawn(move || {
+            init_tokio();
+            let mut controller = create();
+            for call in receiver{
+                call(&mut controller);
+            }
+            controller.finalize();
+        });
+        AuthorizationAuthority{
+            calls,
+        }
+    }
+
+    ///
+    /// Runs function with controller on its thread and waits for result
+    ///
+    pub async fn call<R, F>(&self, call: F) -> Result<R, SessionError>
+        where R: Send + 'static,
+              F: FnOnce(&mut AuthorizationController) -> R + Send + 'static{
+        let (reply, result) = oneshot::channel();
+        self.calls.send(Box::new(move |controller| {
+            let _ = reply.send(call(controller));
+        })).map_err(|_| SessionError::AuthorityUnavailable)?;
+        result.await.map_err(|_| SessionError::AuthorityUnavailable)
+    }
+}
+
+// Parses frame expected at current stage of handshake
+fn parse_frame<T: Deserializable>(frame: &Serialized) -> Result<T, SessionError>{
+    T::from_serialized(frame).map(|(value, _)| value).map_err(|_: SerializationError| SessionError::Malformed)
+}
+
+// Serials of certificates presented in authorization message, leaf last
+fn get_presented(message: &AuthorizationMessage) -> Vec<u128>{
+    let mut presented: Vec<u128> = message.signing_chain.iter().map(|certificate| certificate.get_serial()).collect();
+    presented.push(message.signing_certificate.get_serial());
+    presented
+}
+
+// Crypto layer of peer must use certificates peer was authorized with, so session keys belong to it
+fn check_crypto_layer(remote: &TransformerStackDescriptor,
+                      certificates: &(Falcon1024Certificate, Kyber1024Certificate)) -> Result<(), SessionError>{
+    let serials = (certificates.0.get_serial(), certificates.1.get_serial());
+    let is_bound = remote.layers.iter().filter(|layer| layer.name == CRYPTO_TRANSFORMER_NAME).all(|layer| {
+        matches!(<(u128, u128, bool, u128)>::from_serialized(&layer.parameters),
+                 Ok(((signing, encryption, _, _), _)) if (signing, encryption) == serials)
+    });
+    match is_bound {
+        true => Ok(()),
+        false => Err(SessionError::Transformers(TransformerNegotiationError::InvalidParameters(
+            "Crypto layer of peer uses certificates it was not authorized with".to_string()))),
+    }
+}
+
+///
+/// Establishes sessions on connections: both sides negotiate protocol version, connecting
+/// side is authorized by accepting side and checks authorization message of accepting side
+/// in return, then both sides negotiate transformers. Transformers are negotiated last, so
+/// certificates exchanged during authorization are known to crypto layer.
+///
+/// Each stage is limited by handshake timeouts of transport. Connection must be closed
+/// once handshake fails.
+///
+pub struct SessionHandshake{
+    host_id: u128,
+    signing_serial: u128,
+    encryption_serial: u128,
+    authority: AuthorizationAuthority,
+    version_policy: VersionPolicy,
+    stack: TransformerStack,
+    challenge_responder: Option<ChallengeResponder>,
+}
+
+impl SessionHandshake {
+    ///
+    /// Creates handshake of local side with default version policy and empty transformer stack
+    ///
+    /// # Arguments
+    /// * host_id: u128: ID of local side
+    /// * signing_serial: u128: certificate authorization messages are signed with
+    /// * encryption_serial: u128: certificate presented in authorization messages
+    /// * authority: AuthorizationAuthority: controller verifying peers
+    ///
+    pub fn new(host_id: u128, signing_serial: u128, encryption_serial: u128,
+               authority: AuthorizationAuthority) -> SessionHandshake{
+        SessionHandshake{
+            host_id,
+            signing_serial,
+            encryption_serial,
+            authority,
+            version_policy: VersionPolicy::default(),
+            stack: TransformerStack::new(),
+            challenge_responder: None,
+        }
+    }
+
+    pub fn set_version_policy(&mut self, policy: VersionPolicy) -> &mut Self{
+        self.version_policy = policy;
+        self
+    }
+
+    ///
+    /// Sets transformer stack negotiated once peer is authorized
+    ///
+    pub fn set_stack(&mut self, stack: TransformerStack) -> &mut Self{
+        self.stack = stack;
+        self
+    }
+
+    ///
+    /// Sets responder to authentication challenges, connecting side fails challenged
+    /// authorization without it
+    ///
+    pub fn set_challenge_responder(&mut self, responder: ChallengeResponder) -> &mut Self{
+        self.challenge_responder = Some(responder);
+        self
+    }
+
+    #[inline]
+    pub fn get_host_id(&self) -> u128{
+        self.host_id
+    }
+
+    #[inline]
+    pub fn get_authority(&self) -> &AuthorizationAuthority{
+        &self.authority
+    }
+
+    ///
+    /// Establishes session with accepting side
+    ///
+    /// # Arguments
+    /// * transport: &mut TokioStreamTransport<T>: freshly opened connection
+    ///
+    /// returns: Result<u128, SessionError>: ID of accepting side or error
+    ///
+    pub async fn connect<T>(&self, transport: &mut TokioStreamTransport<T>) -> Result<u128, SessionError>
+        where T: AsyncReadExt + AsyncWriteExt + Sync + Send + Unpin{
+        transport.negotiate_version(&self.version_policy, None).await.map_err(SessionError::Version)?;
+        let (encryption_serial, signing_serial) = (self.encryption_serial, self.signing_serial);
+        let authorization = self.authority.call(move |controller| {
+            controller.generate_authorization_message(encryption_serial, signing_serial, true)
+        }).await?.map_err(SessionError::Identity)?;
+        let hello = SessionHello{
+            peer_id: self.host_id,
+            authorization,
+        };
+        transport.send_handshake_frame(HandshakeStage::ReadAuthorization, hello.serialize()).await
+            .map_err(SessionError::Handshake)?;
+        let (host_id, message) = loop {
+            let frame = transport.receive_handshake_frame(HandshakeStage::SendResponse).await
+                .map_err(SessionError::Handshake)?;
+            let reply: SessionReply = parse_frame(&frame)?;
+            let answer = match reply.status {
+                SessionStatus::Authorized => break (reply.host_id, parse_frame::<AuthorizationMessage>(&reply.payload)?),
+                SessionStatus::Rejected => return Err(SessionError::Rejected),
+                SessionStatus::ChainRequired => {
+                    let request: ChainRequest = parse_frame(&reply.payload)?;
+                    self.authority.call(move |controller| controller.generate_chain_response(&request)).await?
+                        .serialize()
+                }
+                SessionStatus::ChallengeRequired => {
+                    let challenge: AuthChallenge = parse_frame(&reply.payload)?;
+                    let answer = self.challenge_responder.as_ref().and_then(|responder| responder(&challenge))
+                        .ok_or(SessionError::ChallengeUnanswered)?;
+                    AuthChallengeResponse::new(&challenge, &answer).serialize()
+                }
+            };
+            transport.send_handshake_frame(HandshakeStage::ReadAuthorization, answer).await
+                .map_err(SessionError::Handshake)?;
+        };
+        let presented = get_presented(&message);
+        let certificates = self.authority.call(move |controller| {
+            controller.check_peer_authorization_message(host_id, message)
+        }).await?.ok_or(SessionError::Rejected)?;
+        self.add_peer_certificates(&certificates).await?;
+        let remote = transport.negotiate_transformers(&self.stack, None).await.map_err(SessionError::Transformers)?;
+        check_crypto_layer(&remote, &certificates)?;
+        transport.set_authorized(host_id, presented);
+        Ok(host_id)
+    }
+
+    ///
+    /// Establishes session with connecting side
+    ///
+    /// # Arguments
+    /// * transport: &mut TokioStreamTransport<T>: freshly accepted connection
+    ///
+    /// returns: Result<u128, SessionError>: ID of connecting side or error
+    ///
+    pub async fn accept<T>(&self, transport: &mut TokioStreamTransport<T>) -> Result<u128, SessionError>
+        where T: AsyncReadExt + AsyncWriteExt + Sync + Send + Unpin{
+        transport.negotiate_version(&self.version_policy, None).await.map_err(SessionError::Version)?;
+        let frame = transport.receive_handshake_frame(HandshakeStage::ReadAuthorization).await
+            .map_err(SessionError::Handshake)?;
+        let hello: SessionHello = parse_frame(&frame)?;
+        let (peer_id, presented) = (hello.peer_id, get_presented(&hello.authorization));
+        let mut status = self.authority.call(move |controller| {
+            controller.authorize_peer(peer_id, hello.authorization)
+        }).await?;
+        let certificates = loop {
+            let (reply_status, payload) = match status {
+                AuthorizationStatus::Authorized(certificates) => break *certificates,
+                AuthorizationStatus::Rejected => (SessionStatus::Rejected, Serialized::new()),
+                AuthorizationStatus::ChainRequired(request) => (SessionStatus::ChainRequired, request.serialize()),
+                AuthorizationStatus::ChallengeRequired(challenge) => (SessionStatus::ChallengeRequired, challenge.serialize()),
+            };
+            self.send_reply(transport, reply_status, payload).await?;
+            if reply_status == SessionStatus::Rejected{
+                return Err(SessionError::Rejected);
+            }
+            let frame = transport.receive_handshake_frame(HandshakeStage::ReadAuthorization).await
+                .map_err(SessionError::Handshake)?;
+            status = match reply_status {
+                SessionStatus::ChainRequired => {
+                    let response: ChainResponse = parse_frame(&frame)?;
+                    self.authority.call(move |controller| controller.check_chain_response(&response)).await?
+                }
+                _ => {
+                    let response: AuthChallengeResponse = parse_frame(&frame)?;
+                    self.authority.call(move |controller| controller.check_challenge_response(&response)).await?
+                        .map_or(AuthorizationStatus::Rejected, |certificates| AuthorizationStatus::Authorized(Box::new(certificates)))
+                }
+            };
+        };
+        let (encryption_serial, signing_serial) = (self.encryption_serial, self.signing_serial);
+        let message = self.authority.call(move |controller| {
+            controller.generate_peer_authorization_message(peer_id, encryption_serial, signing_serial)
+        }).await?;
+        let (message, _) = match message {
+            Ok(message) => message,
+            Err(error) => {
+                self.send_reply(transport, SessionStatus::Rejected, Serialized::new()).await?;
+                return Err(SessionError::Identity(error));
+            }
+        };
+        self.send_reply(transport, SessionStatus::Authorized, message.serialize()).await?;
+        self.add_peer_certificates(&certificates).await?;
+        let remote = transport.negotiate_transformers(&self.stack, None).await.map_err(SessionError::Transformers)?;
+        check_crypto_layer(&remote, &certificates)?;
+        transport.set_authorized(peer_id, presented);
+        Ok(peer_id)
+    }
+
+    // Crypto layer looks certificates of peer up in certificate service
+    async fn add_peer_certificates(&self, certificates: &(Falcon1024Certificate, Kyber1024Certificate))
+        -> Result<(), SessionError>{
+        let certificates = certificates.clone();
+        self.authority.call(move |controller| controller.add_peer_certificates(&certificates)).await
+    }
+
+    async fn send_reply<T>(&self, transport: &mut TokioStreamTransport<T>, status: SessionStatus,
+                           payload: Serialized) -> Result<(), SessionError>
+        where T: AsyncReadExt + AsyncWriteExt + Sync + Send + Unpin{
+        let reply = SessionReply{
+            status,
+            host_id: self.host_id,
+            payload,
+        };
+        transport.send_handshake_frame(HandshakeStage::SendResponse, reply.serialize()).await
+            .map_err(SessionError::Handshake)
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actor::binder::BinderChannelProvider;
+    use crate::message::common::Message;
+    use crate::services::certificate::CertificateAsyncService;
+    use crate::services::certificate::CertificateService;
+    use crate::testing::certificate::{generate_test_certificates, test_certificates, MockCertificateService, TestCertificates,
+                                      TEST_ENCRYPTION_CERTIFICATE_SERIAL, TEST_SIGNING_CERTIFICATE_SERIAL};
+    use crate::testing::transport::loopback_stream_pair;
+    use crate::transport::keepalive::KeepAlivePolicy;
+    use crate::transport::stack::CryptoTransformerFactory;
+
+    fn create_handshake(host_id: u128, certificates: TestCertificates) -> SessionHandshake{
+        let mut service = MockCertificateService::new();
+        service.set_root_certificate(certificates.root);
+        service.add_signing_certificate(certificates.signing.clone());
+        service.add_encryption_certificate(certificates.encryption.clone());
+        let authority_service = service.clone();
+        let authority = AuthorizationAuthority::spawn(move || {
+            let mut service = CertificateAsyncService::run(Box::new(authority_service));
+            AuthorizationController::new(service.bind())
+        });
+        let mut stack = TransformerStack::new();
+        stack.add_factory(Box::new(CryptoTransformerFactory::new(certificates.signing, certificates.encryption,
+                                                                 Box::new(service))));
+        let mut handshake = SessionHandshake::new(host_id, TEST_SIGNING_CERTIFICATE_SERIAL,
+                                                  TEST_ENCRYPTION_CERTIFICATE_SERIAL, authority);
+        handshake.set_stack(stack);
+        handshake
+    }
+
+    #[tokio::test]
+    async fn test_session_established() {
+        let (mut client, mut server) = loopback_stream_pair();
+        let client_handshake = create_handshake(100, test_certificates());
+        let server_handshake = create_handshake(1, test_certificates());
+        let (connected, accepted) = tokio::join!(client_handshake.connect(&mut client),
+                                                 server_handshake.accept(&mut server));
+        assert_eq!((connected, accepted), (Ok(1), Ok(100)));
+        assert!(client.get_protocol_version().is_some());
+        // Frames pass through negotiated crypto layer
+        let mut message = Message::new();
+        message.set_id(7).set_destination(1).set_data(Some(vec![1, 2, 3]));
+        message.set_source(100);
+        assert!(client.send_message(&message).await);
+        let received = server.receive_message(&KeepAlivePolicy::default()).await.unwrap();
+        assert_eq!((received.id, received.data), (7, Some(vec![1, 2, 3])));
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_client_rejected() {
+        let (mut client, mut server) = loopback_stream_pair();
+        let client_handshake = create_handshake(100, generate_test_certificates("untrusted"));
+        let server_handshake = create_handshake(1, test_certificates());
+        let (connected, accepted) = tokio::join!(client_handshake.connect(&mut client),
+                                                 server_handshake.accept(&mut server));
+        assert_eq!((connected, accepted), (Err(SessionError::Rejected), Err(SessionError::Rejected)));
+    }
+}
@@ -0,0 +1,497 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use libmilkyway_derive::{Describe, Deserializable, Serializable};
+use crate::message::builder::MessageBuilder;
+use crate::message::common::{AsMessage, Message};
+use crate::message::types::MessageType;
+use crate::pki::certificate::{Certificate, FLAG_SIGN_MESSAGES, FLAG_TRANSPORT_SHAPING};
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::serialization::schema::{Describe, SchemaRegistry, TypeSchema};
+use crate::services::certificate::CertificateService;
+use crate::transport::ratelimit::TokenBucket;
+use crate::transport::{TransportListener, TransportSender};
+
+///
+/// Bandwidth cap of sending. Zero rate means no cap.
+///
+#[derive(Serializable, Deserializable, Clone, Copy, Debug, Default, PartialEq, Describe)]
+pub struct BandwidthLimits{
+    pub bytes_per_second: u64,
+    /** Maximum burst of bytes sent without pacing, equals to rate if zero **/
+    pub burst: u64,
+}
+
+///
+/// Caps enforced on sending. Overrides take precedence over default connection cap.
+///
+#[derive(Serializable, Deserializable, Clone, Debug, Default, PartialEq, Describe)]
+pub struct ShapingLimits{
+    /** Cap of all connections together **/
+    pub global: Option<BandwidthLimits>,
+    /** Cap of every connection **/
+    pub connection: Option<BandwidthLimits>,
+    /** Caps of connections with particular peers **/
+    pub peer_overrides: HashMap<u128, BandwidthLimits>,
+}
+
+impl ShapingLimits {
+    fn get_connection_limits(&self, peer_id: u128) -> Option<BandwidthLimits>{
+        self.peer_overrides.get(&peer_id).cloned().or(self.connection)
+            .filter(|limits| limits.bytes_per_second != 0)
+    }
+
+    fn get_global_limits(&self) -> Option<BandwidthLimits>{
+        self.global.filter(|limits| limits.bytes_per_second != 0)
+    }
+}
+
+///
+/// Counters of sent and throttled frames
+///
+#[derive(Serializable, Deserializable, Clone, Debug, Default, PartialEq, Describe)]
+pub struct ShapingStats{
+    pub sent_frames: u64,
+    pub sent_bytes: u64,
+    /** Frames which had to wait before sending **/
+    pub throttled_frames: u64,
+    /** Total time frames waited in milliseconds **/
+    pub throttled_millis: u64,
+}
+
+impl ShapingStats {
+    fn record(&mut self, size: u64, delay: Duration){
+        self.sent_frames += 1;
+        self.sent_bytes += size;
+        if !delay.is_zero(){
+            self.throttled_frames += 1;
+            self.throttled_millis += delay.as_millis() as u64;
+        }
+    }
+}
+
+///
+/// Counters of all connections and of each of them
+///
+#[derive(Serializable, Deserializable, Clone, Debug, Default, PartialEq, Describe)]
+pub struct BandwidthStats{
+    pub total: ShapingStats,
+    pub peers: HashMap<u128, ShapingStats>,
+}
+
+///
+/// Paces sending with token buckets, so bulk transfers do not saturate narrow links.
+///
+/// Sending is never refused: a frame exceeding cap takes tokens on credit and sender waits
+/// until the debt is paid, so average rate stays within cap. Caps may be changed at any time,
+/// new caps apply to frames sent after the change.
+///
+pub struct BandwidthShaper{
+    limits: ShapingLimits,
+    global: Option<TokenBucket>,
+    connections: HashMap<u128, TokenBucket>,
+    stats: BandwidthStats,
+}
+
+///
+/// A bandwidth shaper shared between connection workers
+///
+pub type SharedBandwidthShaper = Arc<Mutex<BandwidthShaper>>;
+
+impl BandwidthShaper {
+    ///
+    /// Creates shaper enforcing caps
+    ///
+    pub fn new(limits: ShapingLimits) -> BandwidthShaper{
+        let mut shaper = BandwidthShaper{
+            limits: ShapingLimits::default(),
+            global: None,
+            connections: HashMap::new(),
+            stats: BandwidthStats::default(),
+        };
+        shaper.set_limits(limits);
+        shaper
+    }
+
+    ///
+    /// Creates a shared shaper enforcing caps
+    ///
+    #[inline]
+    pub fn new_shared(limits: ShapingLimits) -> SharedBandwidthShaper{
+        Arc::new(Mutex::new(Self::new(limits)))
+    }
+
+    ///
+    /// Replaces caps. Connections keep their current debt, but pay it at new rate.
+    ///
+    pub fn set_limits(&mut self, limits: ShapingLimits){
+        let now = Instant::now();
+        self.global = match (limits.get_global_limits(), self.global.take()) {
+            (Some(global), Some(mut bucket)) => {
+                bucket.set_limits(global.bytes_per_second, global.burst, now);
+                Some(bucket)
+            }
+            (Some(global), None) => Some(TokenBucket::new(global.bytes_per_second, global.burst, now)),
+            (None, _) => None,
+        };
+        self.connections.retain(|peer_id, bucket| match limits.get_connection_limits(*peer_id) {
+            Some(connection) => {
+                bucket.set_limits(connection.bytes_per_second, connection.burst, now);
+                true
+            }
+            None => false,
+        });
+        self.limits = limits;
+    }
+
+    #[inline]
+    pub fn get_limits(&self) -> ShapingLimits{
+        self.limits.clone()
+    }
+
+    ///
+    /// Takes tokens for a frame to be sent to peer
+    ///
+    /// # Arguments
+    /// * peer_id: u128: peer connection sends to
+    /// * size: u64: size of frame in bytes
+    ///
+    /// returns: Duration: how long to wait before sending the frame
+    ///
+    #[inline]
+    pub fn reserve(&mut self, peer_id: u128, size: u64) -> Duration{
+        self.reserve_at(peer_id, size, Instant::now())
+    }
+
+    ///
+    /// Same as reserve, but at specified moment of time
+    ///
+    pub fn reserve_at(&mut self, peer_id: u128, size: u64, now: Instant) -> Duration{
+        let global_delay = self.global.as_mut().map_or(Duration::ZERO, |bucket| bucket.reserve(size, now));
+        let connection_delay = match self.limits.get_connection_limits(peer_id) {
+            Some(limits) => self.connections.entry(peer_id)
+                .or_insert_with(|| TokenBucket::new(limits.bytes_per_second, limits.burst, now))
+                .reserve(size, now),
+            None => Duration::ZERO,
+        };
+        let delay = global_delay.max(connection_delay);
+        self.stats.total.record(size, delay);
+        self.stats.peers.entry(peer_id).or_default().record(size, delay);
+        delay
+    }
+
+    ///
+    /// Forgets bucket and counters of closed connection
+    ///
+    pub fn remove_connection(&mut self, peer_id: u128){
+        self.connections.remove(&peer_id);
+        self.stats.peers.remove(&peer_id);
+    }
+
+    #[inline]
+    pub fn get_stats(&self) -> BandwidthStats{
+        self.stats.clone()
+    }
+}
+
+///
+/// Handle of one connection worker to shared shaper
+///
+#[derive(Clone)]
+pub struct ConnectionShaper{
+    shaper: SharedBandwidthShaper,
+    peer_id: u128,
+}
+
+impl ConnectionShaper {
+    pub fn new(shaper: SharedBandwidthShaper, peer_id: u128) -> ConnectionShaper{
+        ConnectionShaper{
+            shaper,
+            peer_id,
+        }
+    }
+
+    ///
+    /// Waits until frame of given size may be sent
+    ///
+    pub async fn pace(&self, size: u64){
+        let delay = self.shaper.lock().unwrap().reserve(self.peer_id, size);
+        if !delay.is_zero(){
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+///
+/// Runtime command to shaper of a host
+///
+#[derive(Clone, Debug, PartialEq, Describe)]
+pub enum BandwidthControlCommand{
+    /** Replaces caps, requires FLAG_TRANSPORT_SHAPING **/
+    SetLimits(ShapingLimits),
+    /** Gets current caps and counters **/
+    GetStats,
+}
+
+impl Serializable for BandwidthControlCommand {
+    fn serialize(&self) -> Serialized {
+        let mut result = Serialized::new();
+        match self {
+            BandwidthControlCommand::SetLimits(limits) => {
+                result.extend(0u8.serialize());
+                result.extend(limits.serialize());
+            }
+            BandwidthControlCommand::GetStats => result.extend(1u8.serialize()),
+        }
+        result
+    }
+}
+
+impl Deserializable for BandwidthControlCommand {
+    fn from_serialized(serialized: &Serialized) -> Result<(Self, usize), SerializationError> {
+        if serialized.is_empty(){
+            return Err(SerializationError::LengthError);
+        }
+        match serialized[0] {
+            0 => {
+                let (limits, offset) = ShapingLimits::from_serialized(&serialized[1..].to_vec())?;
+                Ok((BandwidthControlCommand::SetLimits(limits), offset + 1))
+            }
+            1 => Ok((BandwidthControlCommand::GetStats, 1)),
+            _ => Err(SerializationError::InvalidDataError("Unknown bandwidth control command")),
+        }
+    }
+}
+
+///
+/// Signed request carrying bandwidth control command
+///
+#[derive(Serializable, Deserializable, Clone, Debug, Describe)]
+pub struct BandwidthControlRequest{
+    pub request_id: u128,
+    pub command: BandwidthControlCommand,
+}
+
+///
+/// Result of bandwidth control request
+///
+#[derive(Serializable, Deserializable, Clone, Debug, Describe)]
+pub struct BandwidthControlResponse{
+    pub request_id: u128,
+    /** Whether command was executed, false if signer is not allowed to run it **/
+    pub accepted: bool,
+    /** Caps after command **/
+    pub limits: ShapingLimits,
+    pub stats: BandwidthStats,
+}
+
+impl AsMessage for BandwidthControlRequest{
+    fn as_message(&self) -> Message {
+        Message{
+            id: 0,
+            timestamp: 0,
+            message_type: MessageType::BandwidthControlRequest,
+            data: Some(self.serialize()),
+            signature: None,
+            source: 0,
+            destination: 0,
+            module_id: 0,
+            certificate_id: 0,
+        }
+    }
+}
+
+impl AsMessage for BandwidthControlResponse{
+    fn as_message(&self) -> Message {
+        Message{
+            id: 0,
+            timestamp: 0,
+            message_type: MessageType::BandwidthControlResponse,
+            data: Some(self.serialize()),
+            signature: None,
+            source: 0,
+            destination: 0,
+            module_id: 0,
+            certificate_id: 0,
+        }
+    }
+}
+
+///
+/// Answers BandwidthControlRequest messages, so caps of a host are changed without restart
+///
+pub struct BandwidthControlServer{
+    shaper: SharedBandwidthShaper,
+    certificates: Box<dyn CertificateService>,
+    sender: Box<dyn TransportSender>,
+    host_id: u128,
+}
+
+impl BandwidthControlServer {
+    ///
+    /// Creates a server
+    ///
+    /// # Arguments
+    /// * shaper: SharedBandwidthShaper: shaper to control
+    /// * certificates: Box<dyn CertificateService>: service to authenticate requests with
+    /// * sender: Box<dyn TransportSender>: sender of responses
+    /// * host_id: u128: ID of host
+    ///
+    pub fn new(shaper: SharedBandwidthShaper, certificates: Box<dyn CertificateService>,
+               sender: Box<dyn TransportSender>, host_id: u128) -> BandwidthControlServer{
+        BandwidthControlServer{
+            shaper,
+            certificates,
+            sender,
+            host_id,
+        }
+    }
+
+    ///
+    /// Gets certificate which signed message, if it is trusted and signature is valid
+    ///
+    fn authenticate(&mut self, message: &Message) -> Option<Falcon1024Certificate>{
+        let signature = message.signature.as_ref()?;
+        let certificate = self.certificates.get_signing_certificate(message.get_signer_serial())?;
+        if !certificate.check_flag(FLAG_SIGN_MESSAGES) || !self.certificates.verify_signing_certificate(&certificate){
+            return None;
+        }
+        if !certificate.verify_signature(&message.as_signable(), signature){
+            return None;
+        }
+        Some(certificate)
+    }
+}
+
+impl TransportListener for BandwidthControlServer{
+    fn on_message(&mut self, message: Message) {
+        if message.message_type != MessageType::BandwidthControlRequest{
+            return;
+        }
+        let request = match message.data.as_ref().map(BandwidthControlRequest::from_serialized) {
+            Some(Ok((request, _))) => request,
+            _ => {
+                log::warn!("Malformed bandwidth control request from {}", message.source);
+                return;
+            }
+        };
+        let certificate = self.authenticate(&message);
+        let mut shaper = self.shaper.lock().unwrap();
+        let accepted = match (&request.command, certificate) {
+            (_, None) => false,
+            (BandwidthControlCommand::SetLimits(limits), Some(certificate)) => {
+                let allowed = certificate.check_flag(FLAG_TRANSPORT_SHAPING);
+                if allowed{
+                    log::info!("Bandwidth caps changed by certificate {}: {:?}", certificate.get_serial(), limits);
+                    shaper.set_limits(limits.clone());
+                }
+                allowed
+            }
+            (BandwidthControlCommand::GetStats, Some(_)) => true,
+        };
+        if !accepted{
+            log::warn!("Denied bandwidth control request from {} signed by {}",
+                message.source, message.certificate_id);
+        }
+        let response = BandwidthControlResponse{
+            request_id: request.request_id,
+            accepted,
+            limits: if accepted { shaper.get_limits() } else { ShapingLimits::default() },
+            stats: if accepted { shaper.get_stats() } else { BandwidthStats::default() },
+        };
+        drop(shaper);
+        let reply = MessageBuilder::from_payload(&response)
+            .set_source(self.host_id)
+            .set_destination(message.source)
+            .set_module_id(message.module_id)
+            .build()
+            .expect("All required fields are set");
+        self.sender.send_message(reply);
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::common::CORE_MODULE_ID;
+    use crate::pki::hash::HashType;
+    use crate::services::transport::{MessageFilter, TransportService};
+    use crate::testing::certificate::{test_certificates, MockCertificateService};
+    use crate::testing::transport::LoopbackTransportService;
+
+    fn limits(bytes_per_second: u64, burst: u64) -> BandwidthLimits{
+        BandwidthLimits{
+            bytes_per_second,
+            burst,
+        }
+    }
+
+    #[test]
+    fn test_pacing() {
+        let mut shaper = BandwidthShaper::new(ShapingLimits{
+            global: Some(limits(3000, 0)),
+            connection: Some(limits(1000, 1000)),
+            peer_overrides: HashMap::from([(2, limits(0, 0))]),
+        });
+        let now = Instant::now();
+        assert_eq!(shaper.reserve_at(1, 1000, now), Duration::ZERO);
+        // Debt of 500 bytes is paid in half of second
+        assert_eq!(shaper.reserve_at(1, 500, now), Duration::from_millis(500));
+        // Peer 2 has no connection cap, but global cap still applies
+        assert_eq!(shaper.reserve_at(2, 1500, now), Duration::ZERO);
+        assert_eq!(shaper.reserve_at(2, 300, now), Duration::from_millis(100));
+        let stats = shaper.get_stats();
+        assert_eq!((stats.total.sent_frames, stats.total.throttled_frames), (4, 2));
+        assert_eq!(stats.peers.get(&1).unwrap().throttled_millis, 500);
+
+        // Caps changed at runtime apply to next frames
+        let later = now + Duration::from_secs(1);
+        shaper.set_limits(ShapingLimits::default());
+        assert_eq!(shaper.reserve_at(1, 1_000_000, later), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_control_server() {
+        let (mut host, mut operator) = LoopbackTransportService::pair(1, 2);
+        let shaper = BandwidthShaper::new_shared(ShapingLimits::default());
+        let certificates = MockCertificateService::with_test_certificates();
+        let server = BandwidthControlServer::new(shaper.clone(), Box::new(certificates.clone()),
+                                                 host.get_sender(), 1);
+        host.subscribe_to_messages(&MessageFilter::new(), Box::new(server));
+        let signing = test_certificates().signing;
+        let new_limits = ShapingLimits{
+            connection: Some(limits(4096, 0)),
+            ..Default::default()
+        };
+        let send = |operator: &mut LoopbackTransportService, command: BandwidthControlCommand| {
+            let message = MessageBuilder::from_payload(&BandwidthControlRequest{
+                request_id: 7,
+                command,
+            }).set_source(2)
+                .set_destination(1)
+                .set_module_id(CORE_MODULE_ID)
+                .set_certificate_id(signing.get_serial())
+                .build_signed(signing.secret_key.as_ref().unwrap(), HashType::None)
+                .unwrap();
+            operator.send_message(message);
+            let reply = host.sent_messages().pop().unwrap();
+            BandwidthControlResponse::from_serialized(reply.data.as_ref().unwrap()).unwrap().0
+        };
+        // Test signing certificate has no FLAG_TRANSPORT_SHAPING
+        assert!(!send(&mut operator, BandwidthControlCommand::SetLimits(new_limits.clone())).accepted);
+        assert!(send(&mut operator, BandwidthControlCommand::GetStats).accepted);
+
+        let mut shaping_certificate = signing.clone();
+        shaping_certificate.flags |= FLAG_TRANSPORT_SHAPING;
+        let mut service = certificates.clone();
+        service.add_signing_certificate(shaping_certificate);
+        let response = send(&mut operator, BandwidthControlCommand::SetLimits(new_limits.clone()));
+        assert!(response.accepted);
+        assert_eq!(response.limits, new_limits);
+        assert_eq!(shaper.lock().unwrap().get_limits(), new_limits);
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use crate::get_timestamp_with_milliseconds;
+use crate::message::common::Message;
+use crate::message::types::MessageType;
+use crate::pki::certificate::{Certificate, FLAG_SIGN_MESSAGES};
+use crate::pki::signature::MESSAGE_SIGNATURE_LABEL;
+use crate::services::certificate::CertificateService;
+
+///
+/// Default amount of audit entries kept by signature policy
+///
+pub const DEFAULT_SIGNATURE_AUDIT_CAPACITY: usize = 256;
+
+///
+/// Reason why message was rejected by signature policy
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SignatureRejection{
+    /** Message has no signature **/
+    Unsigned,
+    /** Certificate referenced by message is not known **/
+    UnknownCertificate,
+    /** Certificate has no FLAG_SIGN_MESSAGES **/
+    NotAllowedToSign,
+    /** Certificate does not chain to root **/
+    UntrustedCertificate,
+    /** Certificate is not bound to source peer **/
+    NotPeerCertificate,
+    /** Signature does not match message **/
+    InvalidSignature,
+    /** Certificate is not an operator certificate(see transport::operator) **/
+    NotOperator,
+}
+
+///
+/// Record about rejected message
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignatureAuditEntry{
+    /** When message was rejected **/
+    pub timestamp: u128,
+    pub message_id: u128,
+    pub message_type: MessageType,
+    pub source: u128,
+    pub module_id: u64,
+    pub certificate_id: u128,
+    pub reason: SignatureRejection,
+}
+
+///
+/// Declares which messages must be signed and verifies them before delivery to listeners.
+///
+/// Message is verified with signing certificate which serial is embedded in its signature(see
+/// Message::sign_by) or, for signatures without context, referenced by its certificate_id.
+/// Peer may sign only with certificates bound to it(see bind_peer_certificate), so it can not
+/// pass messages signed by others as its own. Messages of peers without bound certificates are
+/// rejected unless set_allow_unbound_peers is set. Signatures without context(see
+/// Signature::is_made_for) are rejected unless set_accept_legacy_signatures is set.
+///
+pub struct SignaturePolicy{
+    required_modules: HashSet<u64>,
+    required_types: Vec<MessageType>,
+    peer_certificates: HashMap<u128, Vec<u128>>,
+    allow_unbound_peers: bool,
+    accept_legacy_signatures: bool,
+    audit_capacity: usize,
+    audit: VecDeque<SignatureAuditEntry>,
+}
+
+///
+/// A signature policy shared between transport service and its consumers
+///
+pub type SharedSignaturePolicy = Arc<Mutex<SignaturePolicy>>;
+
+impl SignaturePolicy {
+    ///
+    /// Creates a policy which does not require any signatures
+    ///
+    /// # Arguments
+    /// * audit_capacity: usize: how many audit entries to keep
+    ///
+    pub fn new(audit_capacity: usize) -> SignaturePolicy{
+        if audit_capacity == 0{
+            panic!("Capacity of signature audit must be positive");
+        }
+        SignaturePolicy{
+            required_modules: HashSet::new(),
+            required_types: Vec::new(),
+            peer_certificates: HashMap::new(),
+            allow_unbound_peers: false,
+            accept_legacy_signatures: false,
+            audit_capacity,
+            audit: VecDeque::with_capacity(audit_capacity),
+        }
+    }
+
+    ///
+    /// Creates a shared policy which does not require any signatures
+    ///
+    #[inline]
+    pub fn new_shared(audit_capacity: usize) -> SharedSignaturePolicy{
+        Arc::new(Mutex::new(Self::new(audit_capacity)))
+    }
+
+    ///
+    /// Requires all messages of module to be signed
+    ///
+    pub fn require_module(&mut self, module_id: u64) -> &mut SignaturePolicy{
+        self.required_modules.insert(module_id);
+        self
+    }
+
+    ///
+    /// Requires all messages of type to be signed
+    ///
+    pub fn require_type(&mut self, message_type: MessageType) -> &mut SignaturePolicy{
+        if !self.required_types.contains(&message_type){
+            self.required_types.push(message_type);
+        }
+        self
+    }
+
+    ///
+    /// Binds signing certificate to peer, e.g. once peer is authorized with it. Messages from
+    /// peer signed by any other certificate are rejected.
+    ///
+    /// # Arguments
+    /// * peer_id: u128: ID of peer
+    /// * serial: u128: serial of signing certificate of peer
+    ///
+    pub fn bind_peer_certificate(&mut self, peer_id: u128, serial: u128) -> &mut SignaturePolicy{
+        let serials = self.peer_certificates.entry(peer_id).or_default();
+        if !serials.contains(&serial){
+            serials.push(serial);
+        }
+        self
+    }
+
+    ///
+    /// Removes certificates bound to peer, e.g. once it disconnected
+    ///
+    pub fn unbind_peer(&mut self, peer_id: u128) -> &mut SignaturePolicy{
+        self.peer_certificates.remove(&peer_id);
+        self
+    }
+
+    ///
+    /// Accepts messages of peers without bound certificates if they are signed by any trusted
+    /// certificate. Any holder of such certificate may then sign messages on behalf of them.
+    ///
+    pub fn set_allow_unbound_peers(&mut self, allow: bool) -> &mut SignaturePolicy{
+        self.allow_unbound_peers = allow;
+        self
+    }
+
+    ///
+    /// Accepts signatures without context, which are made by keys directly and reference signer
+    /// by certificate_id only. Such signature is not bound to messages and may be taken from
+    /// data signed by the same key for another purpose.
+    ///
+    pub fn set_accept_legacy_signatures(&mut self, accept: bool) -> &mut SignaturePolicy{
+        self.accept_legacy_signatures = accept;
+        self
+    }
+
+    ///
+    /// Checks whether peer may sign messages with certificate: certificate is bound to peer or
+    /// peer has no bound certificates and set_allow_unbound_peers is set
+    ///
+    /// # Arguments
+    /// * peer_id: u128: ID of peer
+    /// * serial: u128: serial of signing certificate
+    ///
+    pub fn is_peer_certificate(&self, peer_id: u128, serial: u128) -> bool{
+        match self.peer_certificates.get(&peer_id) {
+            Some(serials) => serials.contains(&serial),
+            None => self.allow_unbound_peers,
+        }
+    }
+
+    ///
+    /// Checks whether message must be signed
+    ///
+    pub fn is_required(&self, message: &Message) -> bool{
+        self.required_modules.contains(&message.module_id) || self.required_types.contains(&message.message_type)
+    }
+
+    fn find_rejection<S: CertificateService + ?Sized>(&self, service: &mut S,
+                                                      message: &Message) -> Option<SignatureRejection>{
+        let signature = match &message.signature {
+            Some(signature) => signature,
+            None => return Some(SignatureRejection::Unsigned),
+        };
+        let serial = message.get_signer_serial();
+        let made_for_messages = match self.accept_legacy_signatures {
+            true => signature.is_made_for_or_legacy(MESSAGE_SIGNATURE_LABEL),
+            false => signature.is_made_for(MESSAGE_SIGNATURE_LABEL),
+        };
+        if !made_for_messages{
+            return Some(SignatureRejection::InvalidSignature);
+        }
+        let certificate = match service.get_signing_certificate(serial) {
+            Some(certificate) => certificate,
+            None => return Some(SignatureRejection::UnknownCertificate),
+        };
+        if !certificate.check_flag(FLAG_SIGN_MESSAGES){
+            return Some(SignatureRejection::NotAllowedToSign);
+        }
+        if !self.is_peer_certificate(message.source, serial){
+            return Some(SignatureRejection::NotPeerCertificate);
+        }
+        if !service.verify_signing_certificate(&certificate){
+            return Some(SignatureRejection::UntrustedCertificate);
+        }
+        if !certificate.verify_signature(&message.as_signable(), signature){
+            return Some(SignatureRejection::InvalidSignature);
+        }
+        None
+    }
+
+    ///
+    /// Verifies message if policy requires it to be signed. Rejected messages are audited.
+    ///
+    /// # Arguments
+    /// * service: &mut S: service with known certificates
+    /// * message: &Message: received message
+    ///
+    /// returns: bool: true if message may be delivered, false otherwise
+    ///
+    pub fn check<S: CertificateService + ?Sized>(&mut self, service: &mut S, message: &Message) -> bool{
+        if !self.is_required(message){
+            return true;
+        }
+        let reason = match self.find_rejection(service, message) {
+            Some(reason) => reason,
+            None => return true,
+        };
+        log::warn!("Rejected message {} from {}: {:?}", message.id, message.source, reason);
+        if self.audit.len() == self.audit_capacity{
+            self.audit.pop_front();
+        }
+        self.audit.push_back(SignatureAuditEntry{
+            timestamp: get_timestamp_with_milliseconds(),
+            message_id: message.id,
+            message_type: message.message_type.clone(),
+            source: message.source,
+            module_id: message.module_id,
+            certificate_id: message.certificate_id,
+            reason,
+        });
+        false
+    }
+
+    ///
+    /// Gets audit entries from oldest to newest
+    ///
+    pub fn get_audit_entries(&self) -> Vec<SignatureAuditEntry>{
+        self.audit.iter().cloned().collect()
+    }
+
+    ///
+    /// Removes all audit entries
+    ///
+    #[inline]
+    pub fn clear_audit(&mut self){
+        self.audit.clear();
+    }
+}
+
+///
+/// Signature policy with certificate service verifying signatures, as transport services
+/// enforce it on received messages
+///
+pub struct SignatureEnforcement{
+    pub policy: SharedSignaturePolicy,
+    pub certificates: Box<dyn CertificateService>,
+}
+
+impl SignatureEnforcement {
+    pub fn new(policy: SharedSignaturePolicy, certificates: Box<dyn CertificateService>) -> SignatureEnforcement{
+        SignatureEnforcement{
+            policy,
+            certificates,
+        }
+    }
+
+    ///
+    /// Verifies message if policy requires it to be signed, see SignaturePolicy::check
+    ///
+    #[inline]
+    pub fn check(&mut self, message: &Message) -> bool{
+        self.policy.lock().unwrap().check(self.certificates.as_mut(), message)
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pki::hash::HashType;
+    use crate::testing::certificate::{test_certificates, MockCertificateService, TEST_SIGNING_CERTIFICATE_SERIAL};
+
+    const SIGNED_MODULE_ID: u64 = 5;
+
+    fn signed_message(source: u128) -> Message{
+        let mut message = Message::new();
+        message.source = source;
+        message.module_id = SIGNED_MODULE_ID;
+        message.certificate_id = TEST_SIGNING_CERTIFICATE_SERIAL;
+        message.set_current_timestamp();
+        message.sign(&test_certificates().signing.secret_key.unwrap(), HashType::None);
+        message
+    }
+
+    fn create_policy() -> SignaturePolicy{
+        let mut policy = SignaturePolicy::new(DEFAULT_SIGNATURE_AUDIT_CAPACITY);
+        policy.require_module(SIGNED_MODULE_ID)
+            .bind_peer_certificate(3, TEST_SIGNING_CERTIFICATE_SERIAL)
+            .set_accept_legacy_signatures(true);
+        policy
+    }
+
+    #[test]
+    fn test_legacy_signatures_rejected() {
+        let mut service = MockCertificateService::with_test_certificates();
+        let mut policy = create_policy();
+        policy.set_accept_legacy_signatures(false);
+        assert!(!policy.check(&mut service, &signed_message(3)));
+        assert_eq!(policy.get_audit_entries()[0].reason, SignatureRejection::InvalidSignature);
+        let mut message = Message::new();
+        message.source = 3;
+        message.module_id = SIGNED_MODULE_ID;
+        message.sign_by(&test_certificates().signing).unwrap();
+        assert!(policy.check(&mut service, &message));
+    }
+
+    #[test]
+    fn test_signed_message_is_accepted() {
+        let mut service = MockCertificateService::with_test_certificates();
+        let mut policy = create_policy();
+        assert!(policy.check(&mut service, &signed_message(3)));
+        // Messages of other modules are not checked
+        assert!(policy.check(&mut service, &Message::new()));
+        assert!(policy.get_audit_entries().is_empty());
+    }
+
+    #[test]
+    fn test_rejections_are_audited() {
+        let mut service = MockCertificateService::with_test_certificates();
+        let mut policy = create_policy();
+        policy.require_type(MessageType::Exec);
+
+        let mut unsigned = Message::new();
+        unsigned.set_type(MessageType::Exec);
+        assert!(!policy.check(&mut service, &unsigned));
+
+        let mut tampered = signed_message(3);
+        tampered.destination = 7;
+        assert!(!policy.check(&mut service, &tampered));
+
+        let mut unknown = signed_message(3);
+        unknown.certificate_id = 100;
+        assert!(!policy.check(&mut service, &unknown));
+
+        service.set_verification_result(false);
+        assert!(!policy.check(&mut service, &signed_message(3)));
+
+        let reasons: Vec<SignatureRejection> = policy.get_audit_entries().iter()
+            .map(|entry| entry.reason)
+            .collect();
+        assert_eq!(reasons, vec![SignatureRejection::Unsigned, SignatureRejection::InvalidSignature,
+                                 SignatureRejection::UnknownCertificate, SignatureRejection::UntrustedCertificate]);
+        assert_eq!(policy.get_audit_entries()[1].source, 3);
+    }
+
+    #[test]
+    fn test_signer_serial_embedded_in_signature() {
+        let mut service = MockCertificateService::with_test_certificates();
+        let mut policy = create_policy();
+        let mut message = Message::new();
+        message.source = 3;
+        message.module_id = SIGNED_MODULE_ID;
+        message.sign_by(&test_certificates().signing).unwrap();
+        assert_eq!(message.signature.as_ref().unwrap().get_signer_serial(), Some(TEST_SIGNING_CERTIFICATE_SERIAL));
+        assert!(policy.check(&mut service, &message));
+        // Signed serial is used for lookup whatever certificate_id says
+        let mut misleading = message.clone();
+        misleading.certificate_id = 100;
+        assert!(!policy.check(&mut service, &misleading));
+        assert_eq!(policy.get_audit_entries()[0].reason, SignatureRejection::InvalidSignature);
+    }
+
+    #[test]
+    fn test_peer_bound_certificates() {
+        let mut service = MockCertificateService::with_test_certificates();
+        let mut policy = create_policy();
+        policy.bind_peer_certificate(4, 100);
+        assert!(policy.check(&mut service, &signed_message(3)));
+        assert!(!policy.check(&mut service, &signed_message(4)));
+        // Peers without bound certificates can not sign by default
+        assert!(!policy.check(&mut service, &signed_message(5)));
+        assert_eq!(policy.get_audit_entries().iter().map(|entry| entry.reason).collect::<Vec<_>>(),
+                   vec![SignatureRejection::NotPeerCertificate, SignatureRejection::NotPeerCertificate]);
+        policy.set_allow_unbound_peers(true);
+        assert!(policy.check(&mut service, &signed_message(5)));
+        assert!(!policy.check(&mut service, &signed_message(4)));
+        policy.unbind_peer(3).set_allow_unbound_peers(false);
+        assert!(!policy.check(&mut service, &signed_message(3)));
+        policy.clear_audit();
+        assert!(policy.get_audit_entries().is_empty());
+    }
+}
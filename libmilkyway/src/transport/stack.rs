@@ -0,0 +1,547 @@
+use std::sync::{Arc, Mutex};
+use libmilkyway_derive::{Deserializable, Serializable};
+use crate::pki::certificate::Certificate;
+use crate::pki::impls::certificates::falcon1024::Falcon1024Certificate;
+use crate::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+use crate::services::certificate::{CertificateService, VerifiableCertificate};
+use crate::services::certificate::usage::{KeyUsage, KeyUsageRecorder};
+use crate::transport::checksum::{ChecksumMode, ChecksumTransformerFactory};
+use crate::transport::crypto::{CryptoTransformer, SharedCryptoAlerts, DEFAULT_CRYPTO_FAILURE_THRESHOLD};
+use crate::transport::TransportTransformer;
+
+///
+/// Name of CryptoTransformer in stack descriptors
+///
+pub const CRYPTO_TRANSFORMER_NAME: &str = "crypto";
+
+///
+/// Version of CryptoTransformer wire format. Version 2 derives session keys of compact
+/// mode with HKDF, version 3 binds frames to nonces of session.
+///
+pub const CRYPTO_TRANSFORMER_VERSION: u32 = 3;
+
+///
+/// Description of one transformer advertised to remote side
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq)]
+pub struct TransformerDescriptor{
+    /** Unique name of transformer, e.g. "crypto" **/
+    pub name: String,
+    /** Version of wire format, both sides must use the same one **/
+    pub version: u32,
+    /** Transformer specific data, e.g. serials of certificates **/
+    pub parameters: Serialized,
+}
+
+///
+/// Description of transformer stack exchanged during capability negotiation.
+/// Layers are listed in order of transformation: first layer transforms data first.
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq)]
+pub struct TransformerStackDescriptor{
+    pub layers: Vec<TransformerDescriptor>,
+}
+
+impl TransformerStackDescriptor {
+    ///
+    /// Gets names of layers
+    ///
+    pub fn get_names(&self) -> Vec<String>{
+        self.layers.iter().map(|layer| layer.name.clone()).collect()
+    }
+}
+
+///
+/// Reasons why transformer stacks of both sides can not work together
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum TransformerNegotiationError{
+    /** Sides use different transformers or different order of them **/
+    StackMismatch{ local: Vec<String>, remote: Vec<String> },
+    /** Sides use incompatible versions of transformer **/
+    VersionMismatch(String),
+    /** Parameters of transformer sent by remote side are rejected **/
+    InvalidParameters(String),
+    /** Descriptor can not be exchanged **/
+    ConnectionError,
+    /** Transformers are already installed on connection **/
+    AlreadyNegotiated,
+}
+
+///
+/// Creates transformers of one kind once both sides agreed on stack
+///
+pub trait TransformerFactory: Send + Sync{
+    ///
+    /// Describes transformer created by this factory as sent to remote side
+    ///
+    fn get_descriptor(&self) -> TransformerDescriptor;
+
+    ///
+    /// Checks whether remote transformer can work with local one. By default
+    /// versions must match exactly.
+    ///
+    fn is_compatible(&self, remote: &TransformerDescriptor) -> bool{
+        remote.version == self.get_descriptor().version
+    }
+
+    ///
+    /// Checks whether created transformer detects tampering and corruption of frames on its
+    /// own, e.g. by authenticated encryption. If no layer of stack does, stack checksums frames.
+    ///
+    fn is_authenticated(&self) -> bool{
+        false
+    }
+
+    ///
+    /// Creates transformer for connection
+    ///
+    /// # Arguments
+    /// * local: &TransformerDescriptor: descriptor local side sent, it may carry data of this
+    ///   connection only, e.g. nonce
+    /// * remote: &TransformerDescriptor: descriptor of same transformer on remote side
+    ///
+    fn create(&self, local: &TransformerDescriptor,
+              remote: &TransformerDescriptor) -> Result<Box<dyn TransportTransformer>, TransformerNegotiationError>;
+}
+
+///
+/// Transformer stack of local side. Both ends of connection exchange descriptors of
+/// their stacks and construct transformers only if stacks are identical.
+///
+/// Unless disabled with set_checksum_mode, ChecksumTransformer is added on top of stacks
+/// without authenticated layer, so corruption is detected on plain connections too.
+///
+#[derive(Default)]
+pub struct TransformerStack{
+    factories: Vec<Box<dyn TransformerFactory>>,
+    checksum_mode: ChecksumMode,
+    checksum_factory: ChecksumTransformerFactory,
+}
+
+impl TransformerStack {
+    pub fn new() -> TransformerStack{
+        TransformerStack{
+            factories: Vec::new(),
+            checksum_mode: ChecksumMode::Auto,
+            checksum_factory: ChecksumTransformerFactory::default(),
+        }
+    }
+
+    ///
+    /// Sets whether frames are checksummed, both sides must use the same mode
+    ///
+    pub fn set_checksum_mode(&mut self, mode: ChecksumMode) -> &mut TransformerStack{
+        self.checksum_mode = mode;
+        self
+    }
+
+    ///
+    /// Checks whether ChecksumTransformer is added on top of stack
+    ///
+    pub fn is_checksummed(&self) -> bool{
+        match self.checksum_mode {
+            ChecksumMode::Auto => !self.factories.iter().any(|factory| factory.is_authenticated()),
+            ChecksumMode::Always => true,
+            ChecksumMode::Never => false,
+        }
+    }
+
+    // Added factories followed by checksum one if it is used
+    fn get_factories(&self) -> Vec<&dyn TransformerFactory>{
+        let mut factories: Vec<&dyn TransformerFactory> = self.factories.iter().map(|factory| factory.as_ref()).collect();
+        if self.is_checksummed(){
+            factories.push(&self.checksum_factory);
+        }
+        factories
+    }
+
+    ///
+    /// Adds transformer on top of stack
+    ///
+    pub fn add_factory(&mut self, factory: Box<dyn TransformerFactory>) -> &mut TransformerStack{
+        self.factories.push(factory);
+        self
+    }
+
+    ///
+    /// Describes stack for sending to remote side. Descriptor may carry data of one
+    /// connection, so the one which was sent must be passed to build.
+    ///
+    pub fn get_descriptor(&self) -> TransformerStackDescriptor{
+        TransformerStackDescriptor{
+            layers: self.get_factories().iter().map(|factory| factory.get_descriptor()).collect(),
+        }
+    }
+
+    ///
+    /// Checks compatibility of stacks of both sides
+    ///
+    /// # Arguments
+    /// * remote: &TransformerStackDescriptor: stack of remote side
+    ///
+    pub fn validate(&self, remote: &TransformerStackDescriptor) -> Result<(), TransformerNegotiationError>{
+        let local = self.get_descriptor();
+        if local.get_names() != remote.get_names(){
+            return Err(TransformerNegotiationError::StackMismatch{
+                local: local.get_names(),
+                remote: remote.get_names(),
+            });
+        }
+        for (factory, layer) in self.get_factories().iter().zip(remote.layers.iter()){
+            if !factory.is_compatible(layer){
+                return Err(TransformerNegotiationError::VersionMismatch(layer.name.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Validates stacks and constructs agreed pipeline
+    ///
+    /// # Arguments
+    /// * local: &TransformerStackDescriptor: stack descriptor sent to remote side
+    /// * remote: &TransformerStackDescriptor: stack of remote side
+    ///
+    /// returns: Result<Vec<Box<dyn TransportTransformer>>, TransformerNegotiationError>: transformers
+    /// in order they must be added to transport
+    ///
+    pub fn build(&self, local: &TransformerStackDescriptor,
+                 remote: &TransformerStackDescriptor) -> Result<Vec<Box<dyn TransportTransformer>>, TransformerNegotiationError>{
+        self.validate(remote)?;
+        if local.get_names() != remote.get_names(){
+            return Err(TransformerNegotiationError::StackMismatch{
+                local: local.get_names(),
+                remote: remote.get_names(),
+            });
+        }
+        self.get_factories().iter().zip(local.layers.iter().zip(remote.layers.iter()))
+            .map(|(factory, (local, remote))| factory.create(local, remote))
+            .collect()
+    }
+}
+
+///
+/// Creates CryptoTransformer with remote certificates advertised in descriptor.
+/// Remote certificates must be known to certificate service and chain to its root.
+///
+/// Compact mode is advertised after certificate serials and is used only if both sides
+/// advertise it. Every descriptor carries a fresh nonce, transformers of a connection sign
+/// frames for nonce of receiving side, so frames of other sessions are rejected.
+///
+/// Every created session is counted as usage of local signing certificate. Usage counted by
+/// transformers is recorded to certificate service when a session is created and on flush_usage.
+///
+pub struct CryptoTransformerFactory<S: CertificateService + ?Sized + Send>{
+    local_signing_cert: Falcon1024Certificate,
+    local_encryption_cert: Kyber1024Certificate,
+    certificates: Mutex<Box<S>>,
+    compact: bool,
+    usage_recorder: Arc<KeyUsageRecorder>,
+    failure_threshold: u64,
+    alerts: Option<SharedCryptoAlerts>,
+}
+
+impl<S: CertificateService + ?Sized + Send> CryptoTransformerFactory<S> {
+    ///
+    /// Creates a factory
+    ///
+    /// # Arguments
+    /// * local_signing_cert: Falcon1024Certificate: local signing certificate with secret key
+    /// * local_encryption_cert: Kyber1024Certificate: local encryption certificate with secret key
+    /// * certificates: Box<S>: service to find and verify remote certificates with
+    ///
+    pub fn new(local_signing_cert: Falcon1024Certificate, local_encryption_cert: Kyber1024Certificate,
+               certificates: Box<S>) -> CryptoTransformerFactory<S>{
+        CryptoTransformerFactory{
+            local_signing_cert,
+            local_encryption_cert,
+            certificates: Mutex::new(certificates),
+            compact: false,
+            usage_recorder: Arc::new(KeyUsageRecorder::new()),
+            failure_threshold: DEFAULT_CRYPTO_FAILURE_THRESHOLD,
+            alerts: None,
+        }
+    }
+
+    ///
+    /// Sets whether to offer compact mode of CryptoTransformer(see CryptoTransformer::set_compact)
+    ///
+    pub fn set_compact(&mut self, compact: bool) -> &mut Self{
+        self.compact = compact;
+        self
+    }
+
+    ///
+    /// Sets how many failures transformers tolerate before terminating session
+    /// (see CryptoTransformer::set_failure_threshold)
+    ///
+    pub fn set_failure_threshold(&mut self, threshold: u64) -> &mut Self{
+        self.failure_threshold = threshold;
+        self
+    }
+
+    ///
+    /// Sets alerts of host transformers publish their alerts to
+    ///
+    pub fn set_alerts(&mut self, alerts: SharedCryptoAlerts) -> &mut Self{
+        self.alerts = Some(alerts);
+        self
+    }
+
+    ///
+    /// Records usage counted by transformers to certificate service and commits it
+    ///
+    /// returns: bool: whether a rotation threshold was reached
+    ///
+    pub fn flush_usage(&self) -> bool{
+        Self::record_usage(&mut self.certificates.lock().unwrap(), self.usage_recorder.take())
+    }
+
+    fn record_usage(certificates: &mut Box<S>, usages: Vec<KeyUsage>) -> bool{
+        if usages.is_empty(){
+            return false;
+        }
+        let mut is_exceeded = false;
+        for usage in usages{
+            is_exceeded |= certificates.record_key_usage(usage);
+        }
+        certificates.commit();
+        is_exceeded
+    }
+}
+
+impl<S: CertificateService + ?Sized + Send> TransformerFactory for CryptoTransformerFactory<S>{
+    fn get_descriptor(&self) -> TransformerDescriptor {
+        TransformerDescriptor{
+            name: CRYPTO_TRANSFORMER_NAME.to_string(),
+            version: CRYPTO_TRANSFORMER_VERSION,
+            parameters: (self.local_signing_cert.get_serial(), self.local_encryption_cert.get_serial(),
+                         self.compact, rand::random::<u128>()).serialize(),
+        }
+    }
+
+    fn is_authenticated(&self) -> bool {
+        true
+    }
+
+    fn create(&self, local: &TransformerDescriptor,
+              remote: &TransformerDescriptor) -> Result<Box<dyn TransportTransformer>, TransformerNegotiationError> {
+        let invalid = |reason: &str| TransformerNegotiationError::InvalidParameters(reason.to_string());
+        let ((_, _, _, local_nonce), _) = <(u128, u128, bool, u128)>::from_serialized(&local.parameters)
+            .map_err(|_| invalid("Malformed local parameters of crypto transformer"))?;
+        let ((signing_serial, encryption_serial, remote_compact, remote_nonce), _) =
+            <(u128, u128, bool, u128)>::from_serialized(&remote.parameters)
+            .map_err(|_| invalid("Malformed parameters of crypto transformer"))?;
+        if local_nonce == remote_nonce{
+            return Err(invalid("Remote side reflected session nonce"));
+        }
+        let mut certificates = self.certificates.lock().unwrap();
+        let remote_signing_cert = certificates.get_signing_certificate(signing_serial)
+            .ok_or_else(|| invalid("Unknown remote signing certificate"))?;
+        let remote_encryption_cert = certificates.get_encryption_certificate(encryption_serial)
+            .ok_or_else(|| invalid("Unknown remote encryption certificate"))?;
+        // Both certificates usually share a chain, so they are verified in one pass
+        let verified = certificates.verify_many(&[VerifiableCertificate::Signing(Box::new(remote_signing_cert.clone())),
+                                                  VerifiableCertificate::Encryption(Box::new(remote_encryption_cert.clone()))]);
+        if verified != [true, true]{
+            return Err(invalid("Remote certificates are not trusted"));
+        }
+        let mut transformer = CryptoTransformer::new(self.local_signing_cert.clone(), self.local_encryption_cert.clone(),
+                                                     remote_signing_cert, remote_encryption_cert,
+                                                     (local_nonce, remote_nonce));
+        transformer.set_compact(self.compact && remote_compact);
+        transformer.set_failure_threshold(self.failure_threshold);
+        if let Some(alerts) = &self.alerts{
+            transformer.publish_alerts_to(alerts.clone());
+        }
+        transformer.set_usage_recorder(self.usage_recorder.clone());
+        self.usage_recorder.record(KeyUsage::new(self.local_signing_cert.get_serial()).set_sessions(1));
+        Self::record_usage(&mut certificates, self.usage_recorder.take());
+        Ok(Box::new(transformer))
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+    use crate::testing::certificate::{test_certificates, MockCertificateService};
+    use crate::transport::async_stream::TokioStreamTransport;
+    use crate::transport::crypto::{CryptoAlertKind, CryptoAlerts};
+
+    struct XorTransformer;
+
+    impl TransportTransformer for XorTransformer{
+        fn detransform(&self, data: &Serialized) -> Result<Serialized, SerializationError> {
+            Ok(self.transform(data))
+        }
+
+        fn transform(&self, data: &Serialized) -> Serialized {
+            data.iter().map(|byte| byte ^ 0x5a).collect()
+        }
+    }
+
+    struct XorTransformerFactory{
+        version: u32,
+    }
+
+    impl TransformerFactory for XorTransformerFactory{
+        fn get_descriptor(&self) -> TransformerDescriptor {
+            TransformerDescriptor{
+                name: "xor".to_string(),
+                version: self.version,
+                parameters: vec![],
+            }
+        }
+
+        fn create(&self, _local: &TransformerDescriptor,
+                  _remote: &TransformerDescriptor) -> Result<Box<dyn TransportTransformer>, TransformerNegotiationError> {
+            Ok(Box::new(XorTransformer))
+        }
+    }
+
+    fn create_stack(xor_version: Option<u32>) -> TransformerStack{
+        let certificates = test_certificates();
+        let mut stack = TransformerStack::new();
+        if let Some(version) = xor_version{
+            stack.add_factory(Box::new(XorTransformerFactory{ version }));
+        }
+        stack.add_factory(Box::new(CryptoTransformerFactory::new(certificates.signing, certificates.encryption,
+                                                                 Box::new(MockCertificateService::with_test_certificates()))));
+        stack
+    }
+
+    #[test]
+    fn test_validate_stacks() {
+        let stack = create_stack(Some(1));
+        assert!(stack.validate(&create_stack(Some(1)).get_descriptor()).is_ok());
+        assert_eq!(stack.validate(&create_stack(Some(2)).get_descriptor()),
+                   Err(TransformerNegotiationError::VersionMismatch("xor".to_string())));
+        assert_eq!(stack.validate(&create_stack(None).get_descriptor()),
+                   Err(TransformerNegotiationError::StackMismatch{
+                       local: vec!["xor".to_string(), "crypto".to_string()],
+                       remote: vec!["crypto".to_string()],
+                   }));
+    }
+
+    #[test]
+    fn test_checksum_without_authenticated_layer() {
+        let mut stack = TransformerStack::new();
+        stack.add_factory(Box::new(XorTransformerFactory{ version: 1 }));
+        assert_eq!(stack.get_descriptor().get_names(), vec!["xor".to_string(), "checksum".to_string()]);
+        assert_eq!(create_stack(Some(1)).get_descriptor().get_names(), vec!["xor".to_string(), "crypto".to_string()]);
+        let transformers = stack.build(&stack.get_descriptor(), &stack.get_descriptor()).unwrap();
+        assert_eq!(transformers.len(), 2);
+        let mut frame = transformers[1].transform(&transformers[0].transform(&vec![1, 2, 3]));
+        frame[6] ^= 1;
+        assert!(transformers[1].detransform(&frame).is_err());
+
+        stack.set_checksum_mode(ChecksumMode::Never);
+        assert_eq!(stack.get_descriptor().get_names(), vec!["xor".to_string()]);
+        assert!(create_stack(Some(1)).set_checksum_mode(ChecksumMode::Always).is_checksummed());
+    }
+
+    #[test]
+    fn test_untrusted_remote_certificates() {
+        let certificates = test_certificates();
+        let mut service = MockCertificateService::with_test_certificates();
+        service.set_verification_result(false);
+        let mut stack = TransformerStack::new();
+        stack.add_factory(Box::new(CryptoTransformerFactory::new(certificates.signing, certificates.encryption,
+                                                                 Box::new(service))));
+        let result = stack.build(&stack.get_descriptor(), &create_stack(None).get_descriptor());
+        assert!(matches!(result, Err(TransformerNegotiationError::InvalidParameters(_))));
+    }
+
+    #[test]
+    fn test_factory_publishes_alerts_of_transformers() {
+        let certificates = test_certificates();
+        let alerts = CryptoAlerts::new_shared();
+        let mut factory = CryptoTransformerFactory::new(certificates.signing, certificates.encryption,
+                                                        Box::new(MockCertificateService::with_test_certificates()));
+        factory.set_failure_threshold(1).set_alerts(alerts.clone());
+        let mut stack = TransformerStack::new();
+        stack.add_factory(Box::new(factory));
+        let (first, second) = (stack.get_descriptor(), stack.get_descriptor());
+        let sender = stack.build(&first, &second).unwrap();
+        let receiver = stack.build(&second, &first).unwrap();
+
+        let mut frame = sender[0].transform(&vec![1, 2, 3]);
+        frame[10] ^= 0xFF;
+        assert!(receiver[0].detransform(&frame).is_err());
+        let kinds: Vec<CryptoAlertKind> = alerts.lock().unwrap().get_audit().iter()
+            .map(|entry| entry.alert.kind).collect();
+        assert_eq!(kinds, vec![CryptoAlertKind::SignatureFailure, CryptoAlertKind::ThresholdExceeded]);
+        assert!(receiver[0].detransform(&sender[0].transform(&vec![4])).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_negotiate_transformers() {
+        let (client, server) = duplex(1 << 16);
+        let mut client = TokioStreamTransport::from_stream(client);
+        let mut server = TokioStreamTransport::from_stream(server);
+        let client_stack = create_stack(Some(1));
+        let server_stack = create_stack(Some(1));
+        let (client_result, server_result) = tokio::join!(
+            client.negotiate_transformers(&client_stack, Some(1000)),
+            server.negotiate_transformers(&server_stack, Some(1000)));
+        assert_eq!(client_result.unwrap().get_names(), vec!["xor".to_string(), "crypto".to_string()]);
+        assert!(server_result.is_ok());
+
+        let data: Serialized = vec![1, 2, 3, 4, 5];
+        client.send_raw(data.clone()).await.unwrap();
+        assert_eq!(server.receive_raw(Some(1000)).await, Some(data));
+        assert_eq!(client.negotiate_transformers(&client_stack, Some(1000)).await,
+                   Err(TransformerNegotiationError::AlreadyNegotiated));
+    }
+
+    #[tokio::test]
+    async fn test_negotiation_rejects_mismatched_stacks() {
+        let (client, server) = duplex(1 << 16);
+        let mut client = TokioStreamTransport::from_stream(client);
+        let mut server = TokioStreamTransport::from_stream(server);
+        let client_stack = create_stack(Some(1));
+        let server_stack = create_stack(None);
+        let (client_result, server_result) = tokio::join!(
+            client.negotiate_transformers(&client_stack, Some(1000)),
+            server.negotiate_transformers(&server_stack, Some(1000)));
+        assert!(matches!(client_result, Err(TransformerNegotiationError::StackMismatch{ .. })));
+        assert!(matches!(server_result, Err(TransformerNegotiationError::StackMismatch{ .. })));
+        // No transformers are installed, so connection is not silently half-transformed
+        let data: Serialized = vec![1, 2, 3];
+        client.send_raw(data.clone()).await.unwrap();
+        assert_eq!(server.receive_raw(Some(1000)).await, Some(data));
+    }
+
+    #[tokio::test]
+    async fn test_compact_mode_requires_both_sides() {
+        let certificates = test_certificates();
+        let create_compact_stack = |compact: bool| {
+            let mut factory = CryptoTransformerFactory::new(certificates.signing.clone(), certificates.encryption.clone(),
+                                                            Box::new(MockCertificateService::with_test_certificates()));
+            factory.set_compact(compact);
+            let mut stack = TransformerStack::new();
+            stack.add_factory(Box::new(factory));
+            stack
+        };
+        for (client_compact, server_compact) in [(true, true), (true, false), (false, true)]{
+            let (client, server) = duplex(1 << 16);
+            let mut client = TokioStreamTransport::from_stream(client);
+            let mut server = TokioStreamTransport::from_stream(server);
+            let (client_stack, server_stack) = (create_compact_stack(client_compact), create_compact_stack(server_compact));
+            let (client_result, server_result) = tokio::join!(
+                client.negotiate_transformers(&client_stack, Some(1000)),
+                server.negotiate_transformers(&server_stack, Some(1000)));
+            assert!(client_result.is_ok() && server_result.is_ok());
+            for data in [vec![1u8, 2, 3], vec![4u8]]{
+                client.send_raw(data.clone()).await.unwrap();
+                assert_eq!(server.receive_raw(Some(1000)).await, Some(data));
+            }
+        }
+    }
+}
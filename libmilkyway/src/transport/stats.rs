@@ -0,0 +1,319 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+///
+/// Upper bounds of buckets of DurationHistogram in microseconds, longer durations fall
+/// into the last bucket
+///
+pub const DURATION_BUCKETS: [u64; 10] = [10, 50, 100, 500, 1_000, 5_000, 10_000, 50_000, 100_000, 1_000_000];
+
+///
+/// Direction of frame passing through transport
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameDirection{
+    /** Frame is transformed and written to stream **/
+    Send,
+    /** Frame is read from stream and detransformed **/
+    Receive,
+}
+
+///
+/// Histogram of durations with fixed buckets(see DURATION_BUCKETS)
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DurationHistogram{
+    /** Counts of durations per bucket, the last one counts durations above all bounds **/
+    pub buckets: [u64; DURATION_BUCKETS.len() + 1],
+    pub count: u64,
+    /** Sum of all durations in microseconds **/
+    pub total: u64,
+    pub max: u64,
+}
+
+impl DurationHistogram {
+    ///
+    /// Counts duration
+    ///
+    /// # Arguments
+    /// * microseconds: u64: duration in microseconds
+    ///
+    pub fn record(&mut self, microseconds: u64){
+        let bucket = DURATION_BUCKETS.iter().position(|bound| microseconds <= *bound)
+            .unwrap_or(DURATION_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.total = self.total.saturating_add(microseconds);
+        self.max = self.max.max(microseconds);
+    }
+
+    ///
+    /// Gets mean duration in microseconds, 0 if nothing is counted
+    ///
+    pub fn get_mean(&self) -> u64{
+        self.total.checked_div(self.count).unwrap_or(0)
+    }
+
+    ///
+    /// Estimates percentile of durations as upper bound of bucket it falls into
+    ///
+    /// # Arguments
+    /// * percentile: u8: percentile from 0 to 100
+    ///
+    /// returns: u64: duration in microseconds, maximal duration if it is above all bounds
+    ///
+    pub fn get_percentile(&self, percentile: u8) -> u64{
+        let rank = (self.count * percentile.min(100) as u64).div_ceil(100).max(1);
+        let mut counted = 0;
+        for (bucket, count) in self.buckets.iter().enumerate(){
+            counted += count;
+            if counted >= rank{
+                return DURATION_BUCKETS.get(bucket).map_or(self.max, |bound| (*bound).min(self.max));
+            }
+        }
+        self.max
+    }
+}
+
+///
+/// Counters of one direction of transformer or frame path
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DirectionStats{
+    pub invocations: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /** Invocations which failed, e.g. frames which could not be detransformed **/
+    pub failures: u64,
+    pub duration: DurationHistogram,
+}
+
+impl DirectionStats {
+    // Counts invocation which took data of size bytes_in and produced bytes_out, None if it failed
+    fn record(&mut self, bytes_in: usize, bytes_out: Option<usize>, microseconds: u64){
+        self.invocations += 1;
+        self.bytes_in += bytes_in as u64;
+        match bytes_out {
+            Some(bytes_out) => self.bytes_out += bytes_out as u64,
+            None => self.failures += 1,
+        }
+        self.duration.record(microseconds);
+    }
+}
+
+///
+/// Counters of transformer: transform is called for sent frames, detransform for received ones
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TransformerStats{
+    pub transform: DirectionStats,
+    pub detransform: DirectionStats,
+}
+
+///
+/// Snapshot of frame statistics of all connections
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameCounters{
+    /** Whole send path: transformation, pacing and write to stream **/
+    pub sent: DirectionStats,
+    /** Whole receive path after size of frame is read: reading body and detransformation **/
+    pub received: DirectionStats,
+    /** Counters of transformers by name, e.g. `crypto` **/
+    pub transformers: BTreeMap<String, TransformerStats>,
+}
+
+impl FrameCounters {
+    // Counters of direction of frame path or of transformer
+    fn get_direction(&mut self, direction: FrameDirection, transformer: Option<&str>) -> &mut DirectionStats{
+        match (transformer, direction) {
+            (None, FrameDirection::Send) => &mut self.sent,
+            (None, FrameDirection::Receive) => &mut self.received,
+            (Some(name), direction) => {
+                let stats = self.transformers.entry(name.to_string()).or_default();
+                match direction {
+                    FrameDirection::Send => &mut stats.transform,
+                    FrameDirection::Receive => &mut stats.detransform,
+                }
+            }
+        }
+    }
+}
+
+// One row of report: invocations, bytes, failures and latencies
+fn format_direction(f: &mut Formatter<'_>, name: &str, stats: &DirectionStats) -> std::fmt::Result{
+    writeln!(f, "{:<24} {:>10} {:>14} {:>14} {:>8} {:>10} {:>10} {:>10}", name, stats.invocations,
+             stats.bytes_in, stats.bytes_out, stats.failures, stats.duration.get_mean(),
+             stats.duration.get_percentile(99), stats.duration.max)
+}
+
+impl Display for FrameCounters {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:<24} {:>10} {:>14} {:>14} {:>8} {:>10} {:>10} {:>10}", "PATH", "COUNT", "BYTES IN",
+                 "BYTES OUT", "FAILED", "MEAN(us)", "P99(us)", "MAX(us)")?;
+        format_direction(f, "send", &self.sent)?;
+        format_direction(f, "receive", &self.received)?;
+        for (name, stats) in self.transformers.iter(){
+            format_direction(f, &format!("{}.transform", name), &stats.transform)?;
+            format_direction(f, &format!("{}.detransform", name), &stats.detransform)?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Frame statistics shared between connections
+///
+pub type SharedFrameStats = Arc<FrameStats>;
+
+///
+/// Counts frames sent and received by transports and invocations of each transformer with
+/// bytes in and out and durations. Collection may be switched on and off at runtime, while
+/// it is off transports do not even read the clock.
+///
+#[derive(Debug, Default)]
+pub struct FrameStats{
+    enabled: AtomicBool,
+    counters: Mutex<FrameCounters>,
+}
+
+impl FrameStats {
+    ///
+    /// Creates shared statistics
+    ///
+    /// # Arguments
+    /// * enabled: bool: whether statistics are collected from the start
+    ///
+    pub fn new_shared(enabled: bool) -> SharedFrameStats{
+        Arc::new(FrameStats{
+            enabled: AtomicBool::new(enabled),
+            counters: Mutex::new(FrameCounters::default()),
+        })
+    }
+
+    ///
+    /// Switches collection on or off, counted values are kept
+    ///
+    #[inline]
+    pub fn set_enabled(&self, enabled: bool){
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn is_enabled(&self) -> bool{
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    ///
+    /// Starts measuring invocation
+    ///
+    /// returns: Option<Instant>: start time to pass to record_* or None if collection is off
+    ///
+    #[inline]
+    pub fn start(&self) -> Option<Instant>{
+        match self.is_enabled() {
+            true => Some(Instant::now()),
+            false => None,
+        }
+    }
+
+    ///
+    /// Counts invocation of transformer
+    ///
+    /// # Arguments
+    /// * name: &str: name of transformer
+    /// * direction: FrameDirection: Send for transform and Receive for detransform
+    /// * bytes_in: usize: size of data passed to transformer
+    /// * bytes_out: Option<usize>: size of result, None if transformer failed
+    /// * started: Instant: result of start
+    ///
+    pub fn record_transformer(&self, name: &str, direction: FrameDirection, bytes_in: usize,
+                              bytes_out: Option<usize>, started: Instant){
+        let microseconds = started.elapsed().as_micros() as u64;
+        self.counters.lock().unwrap().get_direction(direction, Some(name)).record(bytes_in, bytes_out, microseconds);
+    }
+
+    ///
+    /// Counts frame which passed whole send or receive path
+    ///
+    /// # Arguments
+    /// * direction: FrameDirection: whether frame was sent or received
+    /// * bytes_in: usize: size of frame before transformation(sent) or on wire(received)
+    /// * bytes_out: Option<usize>: size on wire(sent) or after detransformation(received), None if frame failed
+    /// * started: Instant: result of start
+    ///
+    pub fn record_frame(&self, direction: FrameDirection, bytes_in: usize, bytes_out: Option<usize>, started: Instant){
+        let microseconds = started.elapsed().as_micros() as u64;
+        self.counters.lock().unwrap().get_direction(direction, None).record(bytes_in, bytes_out, microseconds);
+    }
+
+    ///
+    /// Gets snapshot of counters
+    ///
+    pub fn get_counters(&self) -> FrameCounters{
+        self.counters.lock().unwrap().clone()
+    }
+
+    ///
+    /// Drops counted values, e.g. before measuring a new build
+    ///
+    pub fn reset(&self){
+        *self.counters.lock().unwrap() = FrameCounters::default();
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+    use crate::transport::async_stream::TokioStreamTransport;
+    use crate::transport::checksum::{ChecksumAlgorithm, ChecksumTransformer};
+
+    #[test]
+    fn test_duration_histogram() {
+        let mut histogram = DurationHistogram::default();
+        assert_eq!((histogram.get_mean(), histogram.get_percentile(99)), (0, 0));
+        for microseconds in [5, 8, 40, 700, 2_000_000]{
+            histogram.record(microseconds);
+        }
+        assert_eq!(histogram.buckets[0], 2);
+        assert_eq!(histogram.buckets[DURATION_BUCKETS.len()], 1);
+        assert_eq!(histogram.get_mean(), 400_150);
+        assert_eq!(histogram.get_percentile(50), 50);
+        assert_eq!(histogram.get_percentile(80), 1_000);
+        assert_eq!(histogram.get_percentile(100), 2_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_frame_stats() {
+        let (client, server) = duplex(1 << 16);
+        let mut client = TokioStreamTransport::from_stream(client);
+        let mut server = TokioStreamTransport::from_stream(server);
+        client.add_transformer(Box::new(ChecksumTransformer::new(ChecksumAlgorithm::Crc32)));
+        server.add_transformer(Box::new(ChecksumTransformer::new(ChecksumAlgorithm::Crc32)));
+        let stats = FrameStats::new_shared(false);
+        client.set_frame_stats(stats.clone());
+        server.set_frame_stats(stats.clone());
+
+        client.send_raw(vec![1, 2, 3]).await.unwrap();
+        assert_eq!(server.receive_raw(None).await, Some(vec![1, 2, 3]));
+        assert_eq!(stats.get_counters(), FrameCounters::default());
+
+        stats.set_enabled(true);
+        client.send_raw(vec![1, 2, 3, 4]).await.unwrap();
+        assert_eq!(server.receive_raw(None).await, Some(vec![1, 2, 3, 4]));
+        let counters = stats.get_counters();
+        assert_eq!((counters.sent.invocations, counters.sent.bytes_in, counters.sent.bytes_out), (1, 4, 9));
+        assert_eq!((counters.received.invocations, counters.received.bytes_in, counters.received.bytes_out), (1, 9, 4));
+        let layer = &counters.transformers["layer0"];
+        assert_eq!((layer.transform.invocations, layer.detransform.invocations, layer.detransform.failures), (1, 1, 0));
+        assert!(counters.to_string().contains("layer0.detransform"));
+
+        stats.reset();
+        assert_eq!(stats.get_counters(), FrameCounters::default());
+    }
+}
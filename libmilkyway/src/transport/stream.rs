@@ -0,0 +1,538 @@
+use std::collections::{BTreeMap, HashMap};
+use std::collections::hash_map::Entry;
+use std::fmt::{Display, Formatter};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::Notify;
+use crate::message::builder::MessageBuilder;
+use crate::message::common::{AsMessage, Message};
+use crate::message::stream::{StreamChunk, StreamCredit};
+use crate::message::types::MessageType;
+use crate::serialization::deserializable::Deserializable;
+use crate::services::transport::{MessageFilter, TransportService};
+use crate::transport::{TransportListener, TransportSender};
+
+///
+/// Default maximal size of data in one chunk
+///
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+///
+/// Default count of chunks sender may send ahead of receiver
+///
+pub const DEFAULT_WINDOW: u64 = 16;
+
+///
+/// Priority of stream subscription, streams are consumed before other subscriptions of module
+///
+pub const STREAM_SUBSCRIPTION_PRIORITY: i32 = 1000;
+
+///
+/// Errors of sending a stream
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamError{
+    /** Receiver cancelled stream **/
+    Cancelled,
+    /** Stream is already finished **/
+    Finished,
+}
+
+impl Display for StreamError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StreamError::Cancelled => write!(f, "stream is cancelled by receiver"),
+            StreamError::Finished => write!(f, "stream is already finished"),
+        }
+    }
+}
+
+/** Credit granted to a writer **/
+#[derive(Default)]
+struct WriterState{
+    credit: Mutex<(u64, bool)>,
+    notify: Notify,
+}
+
+struct ReaderEntry{
+    source: u128,
+    next_sequence: u64,
+    /** Chunks received ahead of next_sequence **/
+    pending: BTreeMap<u64, StreamChunk>,
+    chunks: UnboundedSender<StreamChunk>,
+}
+
+struct WriterEntry{
+    destination: u128,
+    state: Arc<WriterState>,
+}
+
+///
+/// State shared by manager, its listener, readers and writers
+///
+struct StreamShared{
+    host_id: u128,
+    module_id: u64,
+    window: u64,
+    sender: Mutex<Box<dyn TransportSender>>,
+    readers: Mutex<HashMap<u128, ReaderEntry>>,
+    writers: Mutex<HashMap<u128, WriterEntry>>,
+    incoming: UnboundedSender<StreamReader>,
+}
+
+impl StreamShared {
+    fn send<T: AsMessage>(&self, destination: u128, payload: &T){
+        let message = MessageBuilder::from_payload(payload)
+            .set_source(self.host_id)
+            .set_destination(destination)
+            .set_module_id(self.module_id)
+            .build()
+            .expect("Type, destination and module are set");
+        self.sender.lock().unwrap().send_message(message);
+    }
+
+    fn on_chunk(self: &Arc<Self>, source: u128, chunk: StreamChunk){
+        let mut readers = self.readers.lock().unwrap();
+        let entry = match readers.entry(chunk.stream_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                if chunk.sequence != 0{
+                    log::warn!("Chunk {} of unknown stream {} from {} is dropped", chunk.sequence, chunk.stream_id,
+                        source);
+                    return;
+                }
+                let (sender, receiver) = unbounded_channel();
+                let _ = self.incoming.send(StreamReader::new(self.clone(), chunk.stream_id, source, receiver));
+                entry.insert(ReaderEntry{
+                    source,
+                    next_sequence: 0,
+                    pending: BTreeMap::new(),
+                    chunks: sender,
+                })
+            }
+        };
+        if entry.source != source || chunk.sequence < entry.next_sequence{
+            return;
+        }
+        let stream_id = chunk.stream_id;
+        entry.pending.insert(chunk.sequence, chunk);
+        while let Some(chunk) = entry.pending.remove(&entry.next_sequence){
+            entry.next_sequence += 1;
+            let is_end = chunk.is_last || chunk.is_aborted;
+            let _ = entry.chunks.send(chunk);
+            if is_end{
+                // Reader keeps receiving what is already in channel
+                readers.remove(&stream_id);
+                return;
+            }
+        }
+    }
+
+    fn on_credit(&self, source: u128, credit: StreamCredit){
+        let writers = self.writers.lock().unwrap();
+        let entry = match writers.get(&credit.stream_id) {
+            Some(entry) if entry.destination == source => entry,
+            _ => return,
+        };
+        let mut state = entry.state.credit.lock().unwrap();
+        state.0 = state.0.max(credit.allowed);
+        state.1 |= credit.is_cancelled;
+        entry.state.notify.notify_one();
+    }
+}
+
+///
+/// Consumes stream messages of module before its other subscriptions
+///
+struct StreamListener{
+    shared: Arc<StreamShared>,
+}
+
+impl TransportListener for StreamListener{
+    fn on_message(&mut self, message: Message) {
+        self.on_exclusive_message(message);
+    }
+
+    fn on_exclusive_message(&mut self, message: Message) -> bool {
+        let data = match (&message.message_type, &message.data) {
+            (MessageType::StreamChunk | MessageType::StreamCredit, Some(data)) => data,
+            _ => return false,
+        };
+        if message.message_type == MessageType::StreamChunk{
+            match StreamChunk::from_serialized(data) {
+                Ok((chunk, _)) => self.shared.on_chunk(message.source, chunk),
+                Err(_) => log::warn!("Malformed stream chunk from {}", message.source),
+            }
+        } else {
+            match StreamCredit::from_serialized(data) {
+                Ok((credit, _)) => self.shared.on_credit(message.source, credit),
+                Err(_) => log::warn!("Malformed stream credit from {}", message.source),
+            }
+        }
+        true
+    }
+}
+
+///
+/// Sends and receives payloads of a module as streams of chunk messages, so payloads
+/// are never held in memory as a whole. Receiver grants sender credit for a window of
+/// chunks as it reads them, so a slow reader slows down the writer.
+///
+pub struct StreamManager{
+    shared: Arc<StreamShared>,
+    incoming: UnboundedReceiver<StreamReader>,
+    filter_id: u128,
+    chunk_size: usize,
+}
+
+impl StreamManager {
+    ///
+    /// Creates manager and subscribes to stream messages of module
+    ///
+    /// # Arguments
+    /// * transport: &mut dyn TransportService: transport of module
+    /// * host_id: u128: ID of this host, used as source of messages
+    /// * module_id: u64: ID of module streams belong to
+    ///
+    pub fn new(transport: &mut dyn TransportService, host_id: u128, module_id: u64) -> StreamManager{
+        StreamManager::with_window(transport, host_id, module_id, DEFAULT_WINDOW)
+    }
+
+    ///
+    /// Creates manager with custom window of incoming streams
+    ///
+    /// # Arguments
+    /// * window: u64: count of chunks sender may send ahead of reader, at least 1
+    ///
+    pub fn with_window(transport: &mut dyn TransportService, host_id: u128, module_id: u64,
+                       window: u64) -> StreamManager{
+        let (incoming_sender, incoming) = unbounded_channel();
+        let shared = Arc::new(StreamShared{
+            host_id,
+            module_id,
+            window: window.max(1),
+            sender: Mutex::new(transport.get_sender()),
+            readers: Mutex::new(HashMap::new()),
+            writers: Mutex::new(HashMap::new()),
+            incoming: incoming_sender,
+        });
+        let mut filter = MessageFilter::new();
+        filter.filter_module(module_id);
+        filter.set_priority(STREAM_SUBSCRIPTION_PRIORITY).set_exclusive();
+        let filter_id = transport.subscribe_to_messages(&filter, Box::new(StreamListener{ shared: shared.clone() }));
+        StreamManager{
+            shared,
+            incoming,
+            filter_id,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    ///
+    /// Sets maximal size of data in chunks of streams opened afterwards
+    ///
+    pub fn set_chunk_size(&mut self, chunk_size: usize) -> &mut Self{
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    ///
+    /// Gets ID of subscription, module unsubscribes it when streams are no longer needed
+    ///
+    #[inline]
+    pub fn get_filter_id(&self) -> u128{
+        self.filter_id
+    }
+
+    ///
+    /// Opens a stream to the same module of another host
+    ///
+    /// # Arguments
+    /// * destination: u128: ID of receiving host
+    ///
+    pub fn open(&self, destination: u128) -> StreamWriter{
+        let stream_id: u128 = rand::random();
+        let state = Arc::new(WriterState::default());
+        // Receiver grants first window implicitly
+        state.credit.lock().unwrap().0 = self.shared.window;
+        self.shared.writers.lock().unwrap().insert(stream_id, WriterEntry{
+            destination,
+            state: state.clone(),
+        });
+        StreamWriter{
+            shared: self.shared.clone(),
+            state,
+            stream_id,
+            destination,
+            sequence: 0,
+            chunk_size: self.chunk_size,
+            is_finished: false,
+        }
+    }
+
+    ///
+    /// Waits for a stream opened by another host
+    ///
+    /// returns: Option<StreamReader>: reader of stream, None if manager can not receive
+    /// streams anymore
+    ///
+    pub async fn accept(&mut self) -> Option<StreamReader>{
+        self.incoming.recv().await
+    }
+}
+
+///
+/// Sending side of a stream. Stream is aborted if writer is dropped before it is finished.
+///
+pub struct StreamWriter{
+    shared: Arc<StreamShared>,
+    state: Arc<WriterState>,
+    stream_id: u128,
+    destination: u128,
+    sequence: u64,
+    chunk_size: usize,
+    is_finished: bool,
+}
+
+impl StreamWriter {
+    #[inline]
+    pub fn get_stream_id(&self) -> u128{
+        self.stream_id
+    }
+
+    ///
+    /// Waits until receiver allows next chunk
+    ///
+    async fn wait_credit(&self) -> Result<(), StreamError>{
+        loop {
+            let (allowed, is_cancelled) = *self.state.credit.lock().unwrap();
+            if is_cancelled{
+                return Err(StreamError::Cancelled);
+            }
+            if self.sequence < allowed{
+                return Ok(());
+            }
+            self.state.notify.notified().await;
+        }
+    }
+
+    async fn send_chunk(&mut self, data: &[u8], is_last: bool) -> Result<(), StreamError>{
+        if self.is_finished{
+            return Err(StreamError::Finished);
+        }
+        self.wait_credit().await?;
+        self.shared.send(self.destination, &StreamChunk{
+            stream_id: self.stream_id,
+            sequence: self.sequence,
+            data: data.to_vec(),
+            is_last,
+            is_aborted: false,
+        });
+        self.sequence += 1;
+        self.is_finished = is_last;
+        Ok(())
+    }
+
+    ///
+    /// Sends data, waiting for credit of receiver when window is exhausted
+    ///
+    pub async fn write(&mut self, data: &[u8]) -> Result<(), StreamError>{
+        for chunk in data.chunks(self.chunk_size){
+            self.send_chunk(chunk, false).await?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Finishes stream, reader gets end of stream after all sent data
+    ///
+    pub async fn finish(&mut self) -> Result<(), StreamError>{
+        self.send_chunk(&[], true).await
+    }
+}
+
+impl Drop for StreamWriter {
+    fn drop(&mut self) {
+        self.shared.writers.lock().unwrap().remove(&self.stream_id);
+        if self.is_finished || self.state.credit.lock().unwrap().1{
+            return;
+        }
+        self.shared.send(self.destination, &StreamChunk{
+            stream_id: self.stream_id,
+            sequence: self.sequence,
+            data: Vec::new(),
+            is_last: false,
+            is_aborted: true,
+        });
+    }
+}
+
+///
+/// Receiving side of a stream. Reading an aborted stream fails with
+/// `ErrorKind::ConnectionAborted`, dropping reader before end of stream cancels it.
+///
+pub struct StreamReader{
+    shared: Arc<StreamShared>,
+    stream_id: u128,
+    source: u128,
+    chunks: UnboundedReceiver<StreamChunk>,
+    current: Vec<u8>,
+    position: usize,
+    /** Count of chunks taken from channel **/
+    consumed: u64,
+    /** Credit last granted to writer **/
+    granted: u64,
+    is_finished: bool,
+}
+
+impl StreamReader {
+    fn new(shared: Arc<StreamShared>, stream_id: u128, source: u128,
+           chunks: UnboundedReceiver<StreamChunk>) -> StreamReader{
+        let granted = shared.window;
+        StreamReader{
+            shared,
+            stream_id,
+            source,
+            chunks,
+            current: Vec::new(),
+            position: 0,
+            consumed: 0,
+            granted,
+            is_finished: false,
+        }
+    }
+
+    #[inline]
+    pub fn get_stream_id(&self) -> u128{
+        self.stream_id
+    }
+
+    ///
+    /// Gets ID of host which sends stream
+    ///
+    #[inline]
+    pub fn get_source(&self) -> u128{
+        self.source
+    }
+
+    ///
+    /// Grants writer more credit once half of window is consumed
+    ///
+    fn on_chunk_taken(&mut self){
+        self.consumed += 1;
+        let allowed = self.consumed + self.shared.window;
+        if self.is_finished || allowed - self.granted < self.shared.window.div_ceil(2){
+            return;
+        }
+        self.granted = allowed;
+        self.shared.send(self.source, &StreamCredit{
+            stream_id: self.stream_id,
+            allowed,
+            is_cancelled: false,
+        });
+    }
+}
+
+impl AsyncRead for StreamReader {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        loop {
+            if self.position < self.current.len(){
+                let count = buf.remaining().min(self.current.len() - self.position);
+                let position = self.position;
+                buf.put_slice(&self.current[position..position + count]);
+                self.position += count;
+                return Poll::Ready(Ok(()));
+            }
+            if self.is_finished{
+                return Poll::Ready(Ok(()));
+            }
+            let chunk = match self.chunks.poll_recv(cx) {
+                Poll::Ready(Some(chunk)) => chunk,
+                Poll::Ready(None) => return Poll::Ready(Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof, "stream is closed"))),
+                Poll::Pending => return Poll::Pending,
+            };
+            if chunk.is_aborted{
+                self.is_finished = true;
+                return Poll::Ready(Err(std::io::Error::new(std::io::ErrorKind::ConnectionAborted,
+                                                           "stream is aborted by sender")));
+            }
+            self.current = chunk.data;
+            self.position = 0;
+            self.is_finished = chunk.is_last;
+            self.on_chunk_taken();
+        }
+    }
+}
+
+impl Drop for StreamReader {
+    fn drop(&mut self) {
+        if self.is_finished{
+            return;
+        }
+        self.shared.readers.lock().unwrap().remove(&self.stream_id);
+        self.shared.send(self.source, &StreamCredit{
+            stream_id: self.stream_id,
+            allowed: 0,
+            is_cancelled: true,
+        });
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use crate::testing::transport::LoopbackTransportService;
+
+    #[tokio::test]
+    async fn test_stream_with_flow_control() {
+        let (mut first, mut second) = LoopbackTransportService::pair(1, 2);
+        let mut sender = StreamManager::with_window(&mut first, 1, 7, 2);
+        sender.set_chunk_size(3);
+        let mut receiver = StreamManager::with_window(&mut second, 2, 7, 2);
+        let mut writer = sender.open(2);
+        writer.write(b"abcdef").await.unwrap();
+        // Window of two chunks is exhausted until reader consumes them
+        let blocked = tokio::time::timeout(std::time::Duration::from_millis(50), writer.write(b"g")).await;
+        assert!(blocked.is_err());
+        let mut reader = receiver.accept().await.unwrap();
+        assert_eq!((reader.get_source(), reader.get_stream_id()), (1, writer.get_stream_id()));
+        let mut buffer = [0u8; 6];
+        reader.read_exact(&mut buffer).await.unwrap();
+        assert_eq!(&buffer, b"abcdef");
+        writer.write(b"ghi").await.unwrap();
+        writer.finish().await.unwrap();
+        assert_eq!(writer.write(b"late").await, Err(StreamError::Finished));
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, b"ghi");
+        let chunks = first.sent_messages().iter()
+            .filter(|message| message.message_type == MessageType::StreamChunk).count();
+        assert_eq!(chunks, 4);
+    }
+
+    #[tokio::test]
+    async fn test_stream_abort_and_cancel() {
+        let (mut first, mut second) = LoopbackTransportService::pair(1, 2);
+        let sender = StreamManager::new(&mut first, 1, 7);
+        let mut receiver = StreamManager::new(&mut second, 2, 7);
+        let mut writer = sender.open(2);
+        writer.write(b"partial").await.unwrap();
+        drop(writer);
+        let mut reader = receiver.accept().await.unwrap();
+        let mut data = Vec::new();
+        let error = reader.read_to_end(&mut data).await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::ConnectionAborted);
+        assert_eq!(data, b"partial");
+
+        let mut writer = sender.open(2);
+        writer.write(b"unwanted").await.unwrap();
+        drop(receiver.accept().await.unwrap());
+        assert_eq!(writer.write(b"more").await, Err(StreamError::Cancelled));
+    }
+}
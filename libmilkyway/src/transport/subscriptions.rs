@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use crate::message::common::Message;
+use crate::module::supervisor::panic_message;
+use crate::services::transport::MessageFilter;
+use crate::transport::deadletter::SharedDeadLetterQueue;
+use crate::transport::TransportListener;
+
+///
+/// Delivery counters of one subscription, for debugging which listener gets what
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SubscriptionStats{
+    /** Messages passed to listener **/
+    pub delivered: u64,
+    /** Messages consumed by exclusive listener **/
+    pub consumed: u64,
+    /** Matching messages not delivered as subscription of higher priority consumed them **/
+    pub skipped: u64,
+}
+
+struct Subscription{
+    id: u128,
+    /** ID of module which made subscription, if known **/
+    owner: Option<u64>,
+    filter: MessageFilter,
+    listener: Box<dyn TransportListener>,
+    stats: SubscriptionStats,
+}
+
+///
+/// Subscriptions of one host ordered by priority. Subscriptions with equal priority
+/// receive messages in order they were made.
+///
+/// Once dead-letter queue is set, panics of listeners are caught: message is delivered to
+/// panicking listener again until queue dead-letters it.
+///
+#[derive(Default)]
+pub struct Subscriptions{
+    subscriptions: Vec<Subscription>,
+    dead_letters: Option<SharedDeadLetterQueue>,
+}
+
+impl Subscriptions {
+    pub fn new() -> Subscriptions{
+        Subscriptions::default()
+    }
+
+    ///
+    /// Sets queue which messages listeners repeatedly panic on are moved to
+    ///
+    pub fn set_dead_letter_queue(&mut self, queue: SharedDeadLetterQueue){
+        self.dead_letters = Some(queue);
+    }
+
+    ///
+    /// Adds subscription
+    ///
+    /// # Arguments
+    /// * id: u128: ID of subscription, must be unique and grow with every subscription
+    /// * filter: MessageFilter: filter with priority and exclusivity of subscription
+    /// * listener: Box<dyn TransportListener>: listener to deliver messages to
+    ///
+    pub fn add(&mut self, id: u128, filter: MessageFilter, listener: Box<dyn TransportListener>){
+        self.add_owned(id, None, filter, listener);
+    }
+
+    ///
+    /// Adds subscription made by module, so it can be removed with remove_owned
+    ///
+    pub fn add_owned(&mut self, id: u128, owner: Option<u64>, filter: MessageFilter,
+                     listener: Box<dyn TransportListener>){
+        let position = self.subscriptions.iter()
+            .position(|subscription| subscription.filter.priority < filter.priority)
+            .unwrap_or(self.subscriptions.len());
+        self.subscriptions.insert(position, Subscription{
+            id,
+            owner,
+            filter,
+            listener,
+            stats: SubscriptionStats::default(),
+        });
+    }
+
+    ///
+    /// Removes subscription
+    ///
+    /// returns: bool: whether subscription existed
+    ///
+    pub fn remove(&mut self, id: u128) -> bool{
+        let count = self.subscriptions.len();
+        self.subscriptions.retain(|subscription| subscription.id != id);
+        count != self.subscriptions.len()
+    }
+
+    ///
+    /// Removes all subscriptions of module
+    ///
+    /// returns: usize: count of removed subscriptions
+    ///
+    pub fn remove_owned(&mut self, owner: u64) -> usize{
+        let count = self.subscriptions.len();
+        self.subscriptions.retain(|subscription| subscription.owner != Some(owner));
+        count - self.subscriptions.len()
+    }
+
+    ///
+    /// Counts subscriptions by module which made them, subscriptions without owner are not counted
+    ///
+    pub fn get_owner_counts(&self) -> HashMap<u64, usize>{
+        let mut counts = HashMap::new();
+        for owner in self.subscriptions.iter().filter_map(|subscription| subscription.owner){
+            *counts.entry(owner).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    #[inline]
+    pub fn get_stats(&self, id: u128) -> Option<SubscriptionStats>{
+        self.subscriptions.iter()
+            .find(|subscription| subscription.id == id)
+            .map(|subscription| subscription.stats.clone())
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize{
+        self.subscriptions.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool{
+        self.subscriptions.is_empty()
+    }
+
+    ///
+    /// Passes message to listener of subscription
+    ///
+    /// returns: bool: whether message is consumed
+    ///
+    fn deliver(subscription: &mut Subscription, message: &Message) -> bool{
+        if !subscription.filter.exclusive{
+            subscription.listener.on_message(message.clone());
+            return false;
+        }
+        subscription.listener.on_exclusive_message(message.clone())
+    }
+
+    ///
+    /// Passes message to listener until it is handled without panic or dead-lettered
+    ///
+    /// returns: bool: whether message is consumed, dead-lettered message is not
+    ///
+    fn deliver_guarded(queue: &SharedDeadLetterQueue, subscription: &mut Subscription, message: &Message) -> bool{
+        loop {
+            match catch_unwind(AssertUnwindSafe(|| Self::deliver(subscription, message))) {
+                Ok(is_consumed) => {
+                    queue.lock().unwrap().record_success(message.id);
+                    return is_consumed;
+                }
+                Err(payload) => {
+                    let error = panic_message(payload.as_ref());
+                    log::error!("Listener of subscription {} panicked on message {}: {}", subscription.id,
+                        message.id, error);
+                    if queue.lock().unwrap().record_failure(message, &error){
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+
+    ///
+    /// Delivers message to matching subscriptions in order of priority until an
+    /// exclusive listener consumes it
+    ///
+    /// returns: usize: count of listeners message was delivered to
+    ///
+    pub fn dispatch(&mut self, message: &Message) -> usize{
+        let mut delivered = 0;
+        let mut is_consumed = false;
+        for subscription in self.subscriptions.iter_mut(){
+            if !subscription.filter.matches(message){
+                continue;
+            }
+            if is_consumed{
+                subscription.stats.skipped += 1;
+                continue;
+            }
+            subscription.stats.delivered += 1;
+            delivered += 1;
+            is_consumed = match &self.dead_letters {
+                Some(queue) => Self::deliver_guarded(queue, subscription, message),
+                None => Self::deliver(subscription, message),
+            };
+            if is_consumed{
+                subscription.stats.consumed += 1;
+            }
+        }
+        delivered
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::transport::deadletter::DeadLetterQueue;
+
+    struct RecordingListener{
+        name: &'static str,
+        log: Arc<Mutex<Vec<&'static str>>>,
+        /** Consumes messages with odd IDs **/
+        consume_odd: bool,
+    }
+
+    impl TransportListener for RecordingListener{
+        fn on_message(&mut self, _message: Message) {
+            self.log.lock().unwrap().push(self.name);
+        }
+
+        fn on_exclusive_message(&mut self, message: Message) -> bool {
+            let is_odd = message.id % 2 == 1;
+            self.on_message(message);
+            self.consume_odd && is_odd
+        }
+    }
+
+    #[test]
+    fn test_priorities_and_exclusive() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let listener = |name, consume_odd| Box::new(RecordingListener{
+            name,
+            log: log.clone(),
+            consume_odd,
+        });
+        let mut subscriptions = Subscriptions::new();
+        subscriptions.add(1, MessageFilter::new(), listener("default", false));
+        subscriptions.add(2, MessageFilter::new().set_priority(-5).clone(), listener("low", false));
+        subscriptions.add(3, MessageFilter::new().set_priority(10).set_exclusive().clone(), listener("consumer", true));
+        subscriptions.add(4, MessageFilter::new().filter_module(9).clone(), listener("other", false));
+        subscriptions.add(5, MessageFilter::new(), listener("late", false));
+        let mut message = Message::new();
+        message.set_id(2);
+        assert_eq!(subscriptions.dispatch(&message), 4);
+        assert_eq!(*log.lock().unwrap(), vec!["consumer", "default", "late", "low"]);
+        log.lock().unwrap().clear();
+        message.set_id(3);
+        assert_eq!(subscriptions.dispatch(&message), 1);
+        assert_eq!(*log.lock().unwrap(), vec!["consumer"]);
+        assert_eq!(subscriptions.get_stats(3), Some(SubscriptionStats{ delivered: 2, consumed: 1, skipped: 0 }));
+        assert_eq!(subscriptions.get_stats(2), Some(SubscriptionStats{ delivered: 1, consumed: 0, skipped: 1 }));
+        assert_eq!(subscriptions.get_stats(4), Some(SubscriptionStats::default()));
+        assert!(subscriptions.remove(3));
+        assert_eq!(subscriptions.get_stats(3), None);
+        assert_eq!(subscriptions.dispatch(&message), 3);
+    }
+
+    #[test]
+    fn test_remove_owned() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let listener = || Box::new(RecordingListener{
+            name: "listener",
+            log: log.clone(),
+            consume_odd: false,
+        });
+        let mut subscriptions = Subscriptions::new();
+        subscriptions.add_owned(1, Some(7), MessageFilter::new(), listener());
+        subscriptions.add_owned(2, Some(7), MessageFilter::new(), listener());
+        subscriptions.add_owned(3, Some(8), MessageFilter::new(), listener());
+        subscriptions.add(4, MessageFilter::new(), listener());
+        assert_eq!(subscriptions.get_owner_counts(), HashMap::from([(7, 2), (8, 1)]));
+        assert_eq!(subscriptions.remove_owned(7), 2);
+        assert_eq!(subscriptions.remove_owned(7), 0);
+        assert_eq!(subscriptions.len(), 2);
+        assert_eq!(subscriptions.dispatch(&Message::new()), 2);
+    }
+
+    struct PanickingListener{
+        attempts: Arc<Mutex<u32>>,
+    }
+
+    impl TransportListener for PanickingListener{
+        fn on_message(&mut self, _message: Message) {
+            *self.attempts.lock().unwrap() += 1;
+            panic!("poison");
+        }
+    }
+
+    #[test]
+    fn test_poison_message_dead_lettered() {
+        let file = std::env::temp_dir().join(format!("milkyway-poison-{}.dat", rand::random::<u64>()));
+        let queue = Arc::new(Mutex::new(DeadLetterQueue::new(file.to_str().unwrap(), 3)));
+        let attempts = Arc::new(Mutex::new(0));
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut subscriptions = Subscriptions::new();
+        subscriptions.set_dead_letter_queue(queue.clone());
+        subscriptions.add(1, MessageFilter::new().set_priority(1).clone(), Box::new(PanickingListener{
+            attempts: attempts.clone(),
+        }));
+        subscriptions.add(2, MessageFilter::new(), Box::new(RecordingListener{
+            name: "healthy",
+            log: log.clone(),
+            consume_odd: false,
+        }));
+        let mut message = Message::new();
+        message.set_id(5);
+        assert_eq!(subscriptions.dispatch(&message), 2);
+        assert_eq!(*attempts.lock().unwrap(), 3);
+        assert_eq!(*log.lock().unwrap(), vec!["healthy"]);
+        let queue = queue.lock().unwrap();
+        assert_eq!(queue.get_depth(), 1);
+        assert_eq!(queue.get_letters()[0].message_id, 5);
+        assert_eq!(queue.get_letters()[0].error, "poison");
+        std::fs::remove_file(file).unwrap();
+    }
+}
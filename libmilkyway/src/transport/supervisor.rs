@@ -0,0 +1,148 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use crate::module::supervisor::RestartPolicy;
+use crate::trace::Span;
+
+///
+/// How transport worker task ended
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WorkerExit{
+    /** Worker is done, e.g. host shuts down, and must not be restarted **/
+    Finished,
+    /** Connection was lost, worker is restarted to reconnect **/
+    Disconnected,
+}
+
+///
+/// Counters of worker restarts
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WorkerStats{
+    pub restarts: u32,
+    pub panics: u32,
+    pub disconnects: u32,
+    /** Whether worker was stopped after running out of restarts **/
+    pub stopped: bool,
+}
+
+///
+/// Stats shared between supervisor and whoever reports them
+///
+pub type SharedWorkerStats = Arc<Mutex<WorkerStats>>;
+
+///
+/// Runs transport worker as tokio task and restarts it with backoff when it panics or
+/// loses connection. Restarted worker is expected to reconnect and replay its outbox
+/// (see TokioStreamTransport::replay_outbox), so durable messages survive the crash.
+/// Every run of worker is recorded as `worker` span with its outcome.
+///
+pub struct WorkerSupervisor{
+    name: String,
+    policy: RestartPolicy,
+    stats: SharedWorkerStats,
+}
+
+impl WorkerSupervisor {
+    ///
+    /// Creates supervisor of worker
+    ///
+    /// # Arguments
+    /// * name: &str: name of worker used in logs
+    /// * policy: RestartPolicy: restart limit and backoff. Failure count is reset once worker
+    ///   runs longer than max backoff.
+    ///
+    pub fn new(name: &str, policy: RestartPolicy) -> WorkerSupervisor{
+        WorkerSupervisor{
+            name: name.to_string(),
+            policy,
+            stats: Arc::new(Mutex::new(WorkerStats::default())),
+        }
+    }
+
+    #[inline]
+    pub fn get_stats(&self) -> SharedWorkerStats{
+        self.stats.clone()
+    }
+
+    ///
+    /// Runs worker until it finishes or restarts are exhausted. Must be called within tokio runtime.
+    ///
+    /// # Arguments
+    /// * spawn: FnMut() -> F: creates new run of worker
+    ///
+    pub async fn run<S, F>(&self, mut spawn: S)
+        where S: FnMut() -> F,
+              F: Future<Output = WorkerExit> + Send + 'static {
+        let mut failures = 0u32;
+        for run in 1u64..{
+            let started = Instant::now();
+            let mut span = Span::root("worker").with_field("worker", &self.name).with_field("run", run);
+            match tokio::spawn(spawn()).await {
+                Ok(WorkerExit::Finished) => return,
+                Ok(WorkerExit::Disconnected) => {
+                    log::warn!("{}: Worker {} lost connection", span, self.name);
+                    span.record_error("disconnected");
+                    self.stats.lock().unwrap().disconnects += 1;
+                }
+                Err(error) => {
+                    log::error!("{}: Worker {} died: {}", span, self.name, error);
+                    span.record_error(&error);
+                    self.stats.lock().unwrap().panics += 1;
+                }
+            }
+            drop(span);
+            if started.elapsed() > self.policy.max_backoff{
+                failures = 0;
+            }
+            failures += 1;
+            if failures > self.policy.max_restarts{
+                log::error!("Worker {} is stopped after {} restarts", self.name, failures - 1);
+                self.stats.lock().unwrap().stopped = true;
+                return;
+            }
+            tokio::time::sleep(self.policy.get_backoff(failures)).await;
+            self.stats.lock().unwrap().restarts += 1;
+        }
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_worker_restarted() {
+        let supervisor = WorkerSupervisor::new("test", RestartPolicy{
+            max_restarts: 2,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_secs(10),
+        });
+        let runs = Arc::new(AtomicU32::new(0));
+        supervisor.run(|| {
+            let runs = runs.clone();
+            async move {
+                match runs.fetch_add(1, Ordering::SeqCst) {
+                    0 => panic!("worker crashed"),
+                    1 => WorkerExit::Disconnected,
+                    _ => WorkerExit::Finished,
+                }
+            }
+        }).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        assert_eq!(*supervisor.get_stats().lock().unwrap(), WorkerStats{
+            restarts: 2,
+            panics: 1,
+            disconnects: 1,
+            stopped: false,
+        });
+        // Worker which keeps failing is stopped
+        let supervisor = WorkerSupervisor::new("failing", RestartPolicy::never());
+        supervisor.run(|| async { WorkerExit::Disconnected }).await;
+        assert!(supervisor.get_stats().lock().unwrap().stopped);
+    }
+}
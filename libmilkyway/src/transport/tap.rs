@@ -0,0 +1,199 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use libmilkyway_derive::{Deserializable, EnumDeserializable, EnumSerializable, Serializable};
+use crate::get_timestamp_with_milliseconds;
+use crate::message::common::Message;
+use crate::message::types::MessageType;
+use crate::pki::certificate::FLAG_TRANSPORT_TAP;
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+
+///
+/// Default amount of records kept by tap
+///
+pub const DEFAULT_TAP_CAPACITY: usize = 256;
+
+///
+/// Direction of a tapped message
+///
+#[derive(EnumSerializable, EnumDeserializable, Clone, Copy, Debug, PartialEq)]
+pub enum TapDirection{
+    Incoming,
+    Outgoing,
+}
+
+///
+/// Metadata of a message passed through transport
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq)]
+pub struct TapRecord{
+    /** When message was recorded **/
+    pub timestamp: u128,
+    pub direction: TapDirection,
+    pub message_id: u128,
+    pub message_type: MessageType,
+    pub source: u128,
+    pub destination: u128,
+    pub module_id: u64,
+    /** Size of serialized message **/
+    pub size: usize,
+    /** Message payload, only if payload recording is enabled **/
+    pub payload: Option<Serialized>,
+}
+
+///
+/// A ring buffer of recent messages metadata for troubleshooting message flow
+///
+pub struct TransportTap{
+    capacity: usize,
+    record_payloads: bool,
+    records: VecDeque<TapRecord>,
+}
+
+///
+/// A tap shared between transport service and its consumers
+///
+pub type SharedTransportTap = Arc<Mutex<TransportTap>>;
+
+impl TransportTap {
+    ///
+    /// Creates a tap if host is allowed to have it
+    ///
+    /// # Arguments
+    /// * capacity: usize: how many records to keep
+    /// * certificate_flags: u128: flags of host certificate, FLAG_TRANSPORT_TAP must be set
+    ///
+    /// returns: Option<TransportTap>: a tap or None if host has no capability to tap messages
+    /// or capacity is zero
+    ///
+    pub fn new(capacity: usize, certificate_flags: u128) -> Option<TransportTap>{
+        if certificate_flags & FLAG_TRANSPORT_TAP == 0{
+            log::warn!("Transport tap requested, but certificate has no transport tap flag");
+            return None;
+        }
+        if capacity == 0{
+            log::warn!("Transport tap requested with zero capacity, tapping is disabled");
+            return None;
+        }
+        Some(TransportTap{
+            capacity,
+            record_payloads: false,
+            records: VecDeque::with_capacity(capacity),
+        })
+    }
+
+    ///
+    /// Creates a shared tap if host is allowed to have it
+    ///
+    /// # Arguments
+    /// * capacity: usize: how many records to keep
+    /// * certificate_flags: u128: flags of host certificate, FLAG_TRANSPORT_TAP must be set
+    ///
+    #[inline]
+    pub fn new_shared(capacity: usize, certificate_flags: u128) -> Option<SharedTransportTap>{
+        Self::new(capacity, certificate_flags).map(|tap| Arc::new(Mutex::new(tap)))
+    }
+
+    ///
+    /// Enables or disables recording of payloads. Disabled by default as payloads
+    /// may contain sensitive data.
+    ///
+    /// # Arguments
+    /// * enabled: bool: whether payloads should be recorded
+    ///
+    pub fn set_record_payloads(&mut self, enabled: bool){
+        if enabled{
+            log::warn!("Transport tap records payloads, sensitive data may be exposed");
+        }
+        self.record_payloads = enabled;
+    }
+
+    ///
+    /// Records a message, evicting the oldest record if tap is full
+    ///
+    /// # Arguments
+    /// * direction: TapDirection: whether message was sent or received
+    /// * message: &Message: message to record
+    ///
+    pub fn record(&mut self, direction: TapDirection, message: &Message){
+        if self.records.len() == self.capacity{
+            self.records.pop_front();
+        }
+        let payload = if self.record_payloads { message.data.clone() } else { None };
+        self.records.push_back(TapRecord{
+            timestamp: get_timestamp_with_milliseconds(),
+            direction,
+            message_id: message.id,
+            message_type: message.message_type.clone(),
+            source: message.source,
+            destination: message.destination,
+            module_id: message.module_id,
+            size: message.serialize().len(),
+            payload,
+        });
+    }
+
+    ///
+    /// Gets recorded messages from oldest to newest
+    ///
+    pub fn get_records(&self) -> Vec<TapRecord>{
+        self.records.iter().cloned().collect()
+    }
+
+    ///
+    /// Removes all records
+    ///
+    #[inline]
+    pub fn clear(&mut self){
+        self.records.clear();
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message_with_data(id: u128) -> Message{
+        let mut message = Message::new();
+        message.set_id(id);
+        message.set_data(Some(vec![1, 2, 3]));
+        message
+    }
+
+    #[test]
+    fn test_tap_requires_capability() {
+        assert!(TransportTap::new(4, 0).is_none());
+        assert!(TransportTap::new(4, FLAG_TRANSPORT_TAP).is_some());
+    }
+
+    #[test]
+    fn test_tap_rejects_zero_capacity() {
+        assert!(TransportTap::new(0, FLAG_TRANSPORT_TAP).is_none());
+    }
+
+    #[test]
+    fn test_tap_evicts_oldest_records() {
+        let mut tap = TransportTap::new(2, FLAG_TRANSPORT_TAP).unwrap();
+        for id in 0..3{
+            tap.record(TapDirection::Outgoing, &message_with_data(id));
+        }
+        let records = tap.get_records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].message_id, 1);
+        assert_eq!(records[1].message_id, 2);
+        tap.clear();
+        assert!(tap.get_records().is_empty());
+    }
+
+    #[test]
+    fn test_tap_payloads_are_opt_in() {
+        let mut tap = TransportTap::new(4, FLAG_TRANSPORT_TAP).unwrap();
+        tap.record(TapDirection::Incoming, &message_with_data(1));
+        assert_eq!(tap.get_records()[0].payload, None);
+        tap.set_record_payloads(true);
+        tap.record(TapDirection::Incoming, &message_with_data(2));
+        assert_eq!(tap.get_records()[1].payload, Some(vec![1, 2, 3]));
+    }
+}
@@ -0,0 +1,220 @@
+use std::fmt::{Display, Formatter};
+use libmilkyway_derive::{Deserializable, Serializable};
+use crate::serialization::deserializable::Deserializable;
+use crate::serialization::error::SerializationError;
+use crate::serialization::serializable::{Serializable, Serialized};
+
+///
+/// Version of wire protocol spoken by this build. MUST be bumped whenever format of frames,
+/// handshake or messages changes incompatibly.
+///
+/// Version 2 adds batch frames(see transport::batch).
+///
+pub const PROTOCOL_VERSION: u32 = 2;
+
+///
+/// Oldest protocol version this build can still talk to
+///
+pub const MIN_SUPPORTED_PROTOCOL_VERSION: u32 = 1;
+
+///
+/// Marker at the beginning of version announcement, so peers predating version handshake
+/// are recognized instead of misreading their first frame
+///
+pub const VERSION_HELLO_MAGIC: [u8; 8] = *b"MWAYPVER";
+
+///
+/// Versions announced by each side as the very first frame of connection
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq)]
+pub struct VersionHello{
+    /** Newest version side speaks **/
+    pub version: u32,
+    /** Oldest version side accepts **/
+    pub minimum: u32,
+}
+
+impl VersionHello {
+    ///
+    /// Makes frame: VERSION_HELLO_MAGIC followed by serialized announcement
+    ///
+    pub fn to_frame(&self) -> Serialized{
+        let mut frame = VERSION_HELLO_MAGIC.to_vec();
+        frame.extend(self.serialize());
+        frame
+    }
+
+    ///
+    /// Parses frame of remote side
+    ///
+    /// returns: Option<VersionHello>: announcement or None if frame is not an announcement
+    ///
+    pub fn from_frame(frame: &[u8]) -> Option<VersionHello>{
+        let data = frame.strip_prefix(&VERSION_HELLO_MAGIC)?.to_vec();
+        VersionHello::from_serialized(&data).ok().map(|(hello, _)| hello)
+    }
+}
+
+///
+/// Reasons why sides can not agree on protocol version
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum VersionNegotiationError{
+    /** Peer speaks protocol older than local minimum **/
+    PeerTooOld{ peer: u32, minimum: u32 },
+    /** Local protocol is older than minimum required by peer **/
+    RejectedByPeer{ local: u32, minimum: u32 },
+    /** First frame of peer is not a version announcement, peer predates version handshake **/
+    Unversioned,
+    /** Announcement can not be exchanged **/
+    ConnectionError,
+    /** Transformers are already installed, version must be negotiated before them **/
+    AfterTransformers,
+}
+
+impl Display for VersionNegotiationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VersionNegotiationError::PeerTooOld{ peer, minimum } =>
+                write!(f, "peer speaks protocol version {}, but at least {} is required", peer, minimum),
+            VersionNegotiationError::RejectedByPeer{ local, minimum } =>
+                write!(f, "peer requires protocol version {}, but this host speaks only {}", minimum, local),
+            VersionNegotiationError::Unversioned =>
+                write!(f, "peer does not announce protocol version, it is older than version {}",
+                       MIN_SUPPORTED_PROTOCOL_VERSION),
+            VersionNegotiationError::ConnectionError => write!(f, "can not exchange protocol versions"),
+            VersionNegotiationError::AfterTransformers =>
+                write!(f, "protocol version must be negotiated before transformers are installed"),
+        }
+    }
+}
+
+///
+/// Versions local side announces and accepts
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct VersionPolicy{
+    /** Newest version local side speaks, PROTOCOL_VERSION unless downgraded **/
+    pub version: u32,
+    /** Oldest version of peer which is accepted **/
+    pub minimum: u32,
+}
+
+impl Default for VersionPolicy {
+    fn default() -> Self {
+        VersionPolicy{
+            version: PROTOCOL_VERSION,
+            minimum: MIN_SUPPORTED_PROTOCOL_VERSION,
+        }
+    }
+}
+
+impl VersionPolicy {
+    ///
+    /// Creates policy accepting peers starting from version
+    ///
+    /// # Arguments
+    /// * minimum: u32: oldest accepted version, raised to MIN_SUPPORTED_PROTOCOL_VERSION and
+    ///   lowered to PROTOCOL_VERSION if out of range
+    ///
+    pub fn new(minimum: u32) -> VersionPolicy{
+        VersionPolicy{
+            version: PROTOCOL_VERSION,
+            minimum: minimum.clamp(MIN_SUPPORTED_PROTOCOL_VERSION, PROTOCOL_VERSION),
+        }
+    }
+
+    ///
+    /// Gets announcement of local side
+    ///
+    pub fn get_hello(&self) -> VersionHello{
+        VersionHello{
+            version: self.version,
+            minimum: self.minimum,
+        }
+    }
+
+    ///
+    /// Agrees on version with peer. Both sides come to the same result, so no rejection
+    /// needs to be sent.
+    ///
+    /// # Arguments
+    /// * remote: &VersionHello: announcement of peer
+    ///
+    /// returns: Result<u32, VersionNegotiationError>: newest version both sides speak
+    ///
+    pub fn negotiate(&self, remote: &VersionHello) -> Result<u32, VersionNegotiationError>{
+        if remote.version < self.minimum{
+            return Err(VersionNegotiationError::PeerTooOld{ peer: remote.version, minimum: self.minimum });
+        }
+        if self.version < remote.minimum{
+            return Err(VersionNegotiationError::RejectedByPeer{ local: self.version, minimum: remote.minimum });
+        }
+        Ok(self.version.min(remote.version))
+    }
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+    use crate::transport::async_stream::TokioStreamTransport;
+    use crate::transport::checksum::{ChecksumAlgorithm, ChecksumTransformer};
+    use crate::transport::events::ConnectionEvents;
+
+    #[test]
+    fn test_negotiate_version() {
+        let policy = VersionPolicy{ version: 5, minimum: 3 };
+        assert_eq!(policy.negotiate(&VersionHello{ version: 4, minimum: 1 }), Ok(4));
+        assert_eq!(policy.negotiate(&VersionHello{ version: 7, minimum: 5 }), Ok(5));
+        assert_eq!(policy.negotiate(&VersionHello{ version: 2, minimum: 1 }),
+                   Err(VersionNegotiationError::PeerTooOld{ peer: 2, minimum: 3 }));
+        assert_eq!(policy.negotiate(&VersionHello{ version: 9, minimum: 6 }),
+                   Err(VersionNegotiationError::RejectedByPeer{ local: 5, minimum: 6 }));
+        assert_eq!(VersionHello::from_frame(&policy.get_hello().to_frame()), Some(policy.get_hello()));
+        assert_eq!(VersionHello::from_frame(&[1, 2, 3]), None);
+        assert_eq!(VersionPolicy::new(0), VersionPolicy::default());
+    }
+
+    #[tokio::test]
+    async fn test_version_handshake() {
+        let (client, server) = duplex(1 << 16);
+        let mut client = TokioStreamTransport::from_stream(client);
+        let mut server = TokioStreamTransport::from_stream(server);
+        let events = ConnectionEvents::new_shared();
+        server.set_connection_events(events.clone(), "client");
+        let (client_result, server_result) = tokio::join!(
+            client.negotiate_version(&VersionPolicy{ version: 3, minimum: 1 }, Some(1000)),
+            server.negotiate_version(&VersionPolicy{ version: 2, minimum: 2 }, Some(1000)));
+        assert_eq!(client_result, Ok(2));
+        assert_eq!(server_result, Ok(2));
+        assert_eq!(server.get_protocol_version(), Some(2));
+        assert_eq!(events.lock().unwrap().get_protocol_version(server.get_connection_id()), Some(2));
+        assert_eq!(events.lock().unwrap().get_protocol_version_counts().get(&2), Some(&1));
+
+        let (client, server) = duplex(1 << 16);
+        let mut client = TokioStreamTransport::from_stream(client);
+        let mut server = TokioStreamTransport::from_stream(server);
+        let (client_result, server_result) = tokio::join!(
+            client.negotiate_version(&VersionPolicy{ version: 1, minimum: 1 }, Some(1000)),
+            server.negotiate_version(&VersionPolicy{ version: 2, minimum: 2 }, Some(1000)));
+        assert_eq!(client_result, Err(VersionNegotiationError::RejectedByPeer{ local: 1, minimum: 2 }));
+        assert_eq!(server_result, Err(VersionNegotiationError::PeerTooOld{ peer: 1, minimum: 2 }));
+        assert_eq!(server.get_protocol_version(), None);
+
+        // Peer predating handshake starts with transformer negotiation
+        let (client, server) = duplex(1 << 16);
+        let mut client = TokioStreamTransport::from_stream(client);
+        let mut server = TokioStreamTransport::from_stream(server);
+        client.send_raw(vec![0, 1, 2]).await.unwrap();
+        assert_eq!(server.negotiate_version(&VersionPolicy::default(), Some(1000)).await,
+                   Err(VersionNegotiationError::Unversioned));
+
+        let (client, _server) = duplex(1 << 16);
+        let mut client = TokioStreamTransport::from_stream(client);
+        client.add_transformer(Box::new(ChecksumTransformer::new(ChecksumAlgorithm::Crc32)));
+        assert_eq!(client.negotiate_version(&VersionPolicy::default(), Some(1000)).await,
+                   Err(VersionNegotiationError::AfterTransformers));
+    }
+}
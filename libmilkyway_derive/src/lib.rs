@@ -17,10 +17,17 @@ pub fn derive_serializable(input: TokenStream) -> TokenStream {
         _ => panic!("Serializable can only be derived for structs"),
     };
 
-    let serialize_fields = fields.iter().map(|f| {
-        let name = &f.ident;
+    // Tuple structs have no field names, so fields are accessed by index(self.0, self.1, ...)
+    let serialize_fields = fields.iter().enumerate().map(|(i, f)| {
+        let accessor = match &f.ident {
+            Some(name) => quote! { #name },
+            None => {
+                let index = syn::Index::from(i);
+                quote! { #index }
+            }
+        };
         quote! {
-            result.extend(self.#name.serialize());
+            result.extend(self.#accessor.serialize());
         }
     });
 
@@ -53,13 +60,15 @@ pub fn derive_deserializable(input: TokenStream) -> TokenStream {
         _ => panic!("Deserializable can only be derived for structs"),
     };
 
-    let fields_quote = fields.iter().enumerate().map(|(_, f)| {
-        let name = f.ident.clone().unwrap().clone();
-        quote! {#name,}
-    });
+    // Tuple struct fields get synthetic names field_0, field_1, ...
+    let field_names: Vec<syn::Ident> = fields.iter().enumerate().map(|(i, f)| {
+        match &f.ident {
+            Some(name) => name.clone(),
+            None => syn::Ident::new(&format!("field_{}", i), proc_macro2::Span::call_site()),
+        }
+    }).collect();
 
-    let deserialize_fields = fields.iter().enumerate().map(|(_, f)| {
-        let name = &f.ident;
+    let deserialize_fields = fields.iter().zip(field_names.iter()).map(|(f, name)| {
         let ty = &f.ty;
 
         quote! {
@@ -73,7 +82,11 @@ pub fn derive_deserializable(input: TokenStream) -> TokenStream {
         }
     });
 
-
+    let construct = match fields {
+        Fields::Named(_) => quote! { Self { #(#field_names,)* } },
+        Fields::Unnamed(_) => quote! { Self ( #(#field_names,)* ) },
+        Fields::Unit => quote! { Self },
+    };
 
     let expanded = quote! {
         impl Deserializable for #name {
@@ -81,9 +94,7 @@ pub fn derive_deserializable(input: TokenStream) -> TokenStream {
                 let mut offset = 0;
                 #(#deserialize_fields)*
 
-                Ok((Self {
-                    #(#fields_quote)*
-                }, offset))
+                Ok((#construct, offset))
             }
         }
     };
@@ -92,10 +103,55 @@ pub fn derive_deserializable(input: TokenStream) -> TokenStream {
 }
 
 /* Enum serialization/deserialization */
+///
+/// Finds catch-all variant set by `#[enum_serializable(other = Variant)]`
+///
+/// # Panics
+/// * If variant does not exist, is not the last one or does not hold exactly one field
+///   with raw bytes
+/// * If any other variant has fields: size of unknown variant could not be known, so it
+///   would swallow fields serialized after the enum
+///
+fn find_other_variant(input: &DeriveInput) -> Option<syn::Ident> {
+    let mut other: Option<syn::Ident> = None;
+    for attribute in input.attrs.iter().filter(|attribute| attribute.path().is_ident("enum_serializable")) {
+        attribute.parse_nested_meta(|meta| {
+            if meta.path.is_ident("other") {
+                other = Some(meta.value()?.parse()?);
+                return Ok(());
+            }
+            Err(meta.error("unknown enum_serializable option"))
+        }).expect("Invalid enum_serializable attribute");
+    }
+    let other = other?;
+    let variants = match &input.data {
+        Data::Enum(e) => &e.variants,
+        _ => panic!("enum_serializable attribute can only be used on enums"),
+    };
+    match variants.last() {
+        Some(last) if last.ident == other => {
+            match &last.fields {
+                Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {}
+                _ => panic!("Variant {} must hold raw bytes in a single field, e.g. {}(Serialized)", other, other),
+            }
+        }
+        _ => panic!("Variant {} must be declared last, so it does not shift tags of other variants", other),
+    }
+    if let Some(variant) = variants.iter().find(|v| v.ident != other && !matches!(v.fields, Fields::Unit)) {
+        panic!("Catch-all variant {} can only be used when other variants have no fields, but {} has them",
+               other, variant.ident);
+    }
+    Some(other)
+}
+
 ///
 /// Enum automatic serialization
 ///
-#[proc_macro_derive(EnumSerializable)]
+/// Variant is written as its index followed by its fields. Catch-all variant set by
+/// `#[enum_serializable(other = Variant)]` is written as raw bytes it holds, so unknown
+/// variant is passed on exactly as it was received.
+///
+#[proc_macro_derive(EnumSerializable, attributes(enum_serializable))]
 pub fn derive_enum_serializable(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let name = &input.ident;
@@ -103,8 +159,14 @@ pub fn derive_enum_serializable(input: TokenStream) -> TokenStream {
         Data::Enum(e) => &e.variants,
         _ => panic!("EnumSerializable can only be derived for enums"),
     };
+    let other = find_other_variant(&input);
+    let serialize_other = other.iter().map(|other| quote! {
+        #name::#other(ref raw) => {
+            result.extend(raw.iter());
+        }
+    });
 
-    let serialize_variants = variants.iter().enumerate().map(|(i, v)| {
+    let serialize_variants = variants.iter().filter(|v| Some(&v.ident) != other.as_ref()).enumerate().map(|(i, v)| {
         let v_name = &v.ident;
         let idx = i as u8;
         match &v.fields {
@@ -150,6 +212,7 @@ pub fn derive_enum_serializable(input: TokenStream) -> TokenStream {
                 let mut result = Serialized::new();
                 match *self {
                     #(#serialize_variants)*
+                    #(#serialize_other)*
                 }
                 result
             }
@@ -166,7 +229,12 @@ pub fn derive_enum_serializable(input: TokenStream) -> TokenStream {
 /// Compatible only with #[derive(EnumSerializable)] Serializable trait
 /// implementations
 ///
-#[proc_macro_derive(EnumDeserializable)]
+/// Unknown index is an error, unless enum has catch-all variant set by
+/// `#[enum_serializable(other = Variant)]`, which then gets the tag of unknown variant.
+/// Catch-all variant is allowed only when other variants have no fields, so unknown variant is always
+/// a single byte and fields serialized after the enum are read intact.
+///
+#[proc_macro_derive(EnumDeserializable, attributes(enum_serializable))]
 pub fn derive_enum_deserializable(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let name = &input.ident;
@@ -174,8 +242,17 @@ pub fn derive_enum_deserializable(input: TokenStream) -> TokenStream {
         Data::Enum(e) => &e.variants,
         _ => panic!("EnumDeserializable can only be derived for enums"),
     };
+    let other = find_other_variant(&input);
+    let deserialize_unknown = match &other {
+        Some(other) => quote! {
+            _ => Ok((#name::#other(serialized[..1].to_vec()), 1)),
+        },
+        None => quote! {
+            _ => Err(SerializationError::InvalidDataError("Invalid enum variant")),
+        },
+    };
 
-    let deserialize_variants = variants.iter().enumerate().map(|(i, v)| {
+    let deserialize_variants = variants.iter().filter(|v| Some(&v.ident) != other.as_ref()).enumerate().map(|(i, v)| {
         let v_name = &v.ident;
         let idx = i as u8;
         match &v.fields {
@@ -238,11 +315,119 @@ pub fn derive_enum_deserializable(input: TokenStream) -> TokenStream {
                 let variant_idx = serialized[0];
                 match variant_idx {
                     #(#deserialize_variants,)*
-                    _ => Err(SerializationError::InvalidDataError("Invalid enum variant")),
+                    #deserialize_unknown
+                }
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+///
+/// Automatic implementation of CryptoHashable trait: fields are fed to hasher in order
+///
+/// # Note
+/// Serializable types are CryptoHashable already, so derive it only for types which are not
+/// Serializable. For fields which are Serializable the hash is the same as hash of
+/// #[derive(Serializable)] serialization.
+///
+#[proc_macro_derive(CryptoHashable)]
+pub fn derive_crypto_hashable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let fields = match &input.data {
+        syn::Data::Struct(s) => &s.fields,
+        _ => panic!("CryptoHashable can only be derived for structs"),
+    };
+
+    let hash_fields = fields.iter().enumerate().map(|(i, f)| {
+        let accessor = match &f.ident {
+            Some(name) => quote! { #name },
+            None => {
+                let index = syn::Index::from(i);
+                quote! { #index }
+            }
+        };
+        quote! {
+            CryptoHashable::update_hasher(&self.#accessor, hasher);
+        }
+    });
+
+    let expanded = quote! {
+        impl CryptoHashable for #name {
+            fn update_hasher(&self, hasher: &mut Hasher) {
+                #(#hash_fields)*
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
+///
+/// Automatic implementation of Describe trait: exports wire layout of a type to schema registry
+///
+/// # Note
+/// Enum variants are described with their index as tag, same as #[derive(EnumSerializable)]
+/// and hand-written enum serializations do. Catch-all variant of
+/// `#[enum_serializable(other = Variant)]` has no tag and is not described.
+/// Types of all fields must implement Describe.
+///
+#[proc_macro_derive(Describe)]
+pub fn derive_describe(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+
+    // Each field is a pair of name(index for tuple fields) and schema of its type
+    let describe_fields = |fields: &Fields| -> (Vec<proc_macro2::TokenStream>, Vec<syn::Type>) {
+        let described = fields.iter().enumerate().map(|(i, f)| {
+            let ty = &f.ty;
+            let field_name = match &f.ident {
+                Some(ident) => ident.to_string(),
+                None => i.to_string(),
+            };
+            quote! { (#field_name, <#ty as Describe>::describe()) }
+        }).collect();
+        (described, fields.iter().map(|f| f.ty.clone()).collect())
+    };
+
+    let (definition, types) = match &input.data {
+        Data::Struct(s) => {
+            let (fields, types) = describe_fields(&s.fields);
+            (quote! { registry.define_struct(stringify!(#name), vec![#(#fields),*]) }, types)
+        }
+        Data::Enum(e) => {
+            let other = find_other_variant(&input);
+            let mut all_types = Vec::new();
+            let variants: Vec<proc_macro2::TokenStream> = e.variants.iter()
+                .filter(|v| Some(&v.ident) != other.as_ref())
+                .enumerate()
+                .map(|(i, v)| {
+                    let idx = i as u8;
+                    let v_name = v.ident.to_string();
+                    let (fields, types) = describe_fields(&v.fields);
+                    all_types.extend(types);
+                    quote! { (#idx, #v_name, vec![#(#fields),*]) }
+                }).collect();
+            (quote! { registry.define_enum(stringify!(#name), vec![#(#variants),*]) }, all_types)
+        }
+        _ => panic!("Describe can only be derived for structs and enums"),
+    };
+
+    let expanded = quote! {
+        impl Describe for #name {
+            fn describe() -> TypeSchema {
+                TypeSchema::Named(stringify!(#name).to_string())
+            }
+
+            fn register(registry: &mut SchemaRegistry) {
+                // Already defined types are not visited again, so recursive types terminate
+                if #definition {
+                    #(<#types as Describe>::register(registry);)*
                 }
             }
         }
     };
 
     TokenStream::from(expanded)
-}
\ No newline at end of file
+}
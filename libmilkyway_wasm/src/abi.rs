@@ -0,0 +1,118 @@
+/*
+ All data is passed through memory of module serialized with libmilkyway serialization.
+ A buffer is passed as a pair of i32 pointer and length, buffers returned from functions
+ are packed into one i64: pointer in high 32 bits and length in low 32 bits, 0 means no data.
+
+ Module exports:
+ * `memory`
+ * `mway_alloc(len: i32) -> i32`: allocates buffer which host fills with data passed to module
+ * `mway_describe() -> i64`: ModuleDescription
+ * `mway_on_load(ptr: i32, len: i32)`: WasmLoadInfo
+ * `mway_on_cli_command(ptr: i32, len: i32) -> i64`: (Vec<String>, Vec<String>) of command and
+   arguments, returns Option<Vec<String>> with namespace to change to
+ * `mway_on_receive(host_type: i32, ptr: i32, len: i32)`: Message received by host of given type
+ * `mway_on_message(subscription: i64, ptr: i32, len: i32)`: Message matching subscription
+
+ Host imports in `mway` namespace:
+ * `send_message(ptr: i32, len: i32)`: Message
+ * `subscribe(ptr: i32, len: i32) -> i64`: MessageFilter, returns subscription ID
+ * `unsubscribe(subscription: i64)`
+ * `get_signing_certificate(ptr: i32, len: i32) -> i64`: u128 serial, returns
+   Option<Falcon1024Certificate> without secret key
+ * `get_encryption_certificate(ptr: i32, len: i32) -> i64`: u128 serial, returns
+   Option<Kyber1024Certificate> without secret key
+ * `verify_signature(ptr: i32, len: i32) -> i32`: WasmSignatureCheck, returns 1 if signature is
+   made by trusted signing certificate, 0 otherwise
+ * `log(level: i32, ptr: i32, len: i32)`: String, level is 1(error) to 5(trace)
+*/
+use libmilkyway::module::HostType;
+use libmilkyway::pki::signature::Signature;
+use libmilkyway::serialization::deserializable::Deserializable;
+use libmilkyway::serialization::error::SerializationError;
+use libmilkyway::serialization::serializable::{Serializable, Serialized};
+use libmilkyway_derive::{Deserializable, Serializable};
+
+///
+/// Namespace of host functions
+///
+pub const HOST_NAMESPACE: &str = "mway";
+
+pub const EXPORT_MEMORY: &str = "memory";
+pub const EXPORT_ALLOC: &str = "mway_alloc";
+pub const EXPORT_DESCRIBE: &str = "mway_describe";
+pub const EXPORT_ON_LOAD: &str = "mway_on_load";
+pub const EXPORT_ON_CLI_COMMAND: &str = "mway_on_cli_command";
+pub const EXPORT_ON_RECEIVE: &str = "mway_on_receive";
+pub const EXPORT_ON_MESSAGE: &str = "mway_on_message";
+
+///
+/// Information passed to module when it is loaded
+///
+#[derive(Serializable, Deserializable, Clone, Debug, PartialEq)]
+pub struct WasmLoadInfo{
+    pub host_type: HostType,
+    pub host_id: Option<u128>,
+    pub domain: String,
+}
+
+///
+/// Request to verify signature of data made by signing certificate
+///
+#[derive(Serializable, Deserializable, Clone)]
+pub struct WasmSignatureCheck{
+    pub serial: u128,
+    pub data: Serialized,
+    pub signature: Signature,
+}
+
+///
+/// Converts host type to value passed to mway_on_receive
+///
+pub fn host_type_to_i32(host_type: HostType) -> i32{
+    match host_type {
+        HostType::CLI => 0,
+        HostType::Broker => 1,
+        HostType::Peer => 2,
+    }
+}
+
+///
+/// Packs buffer into a value returned from function
+///
+#[inline]
+pub fn pack_buffer(ptr: i32, len: i32) -> i64{
+    ((ptr as u32 as i64) << 32) | (len as u32 as i64)
+}
+
+///
+/// Unpacks buffer returned from function
+///
+/// returns: (i32, i32): pointer and length of buffer
+///
+#[inline]
+pub fn unpack_buffer(packed: i64) -> (i32, i32){
+    ((packed >> 32) as u32 as i32, packed as u32 as i32)
+}
+
+/* Tests begin here */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_buffer() {
+        assert_eq!(unpack_buffer(pack_buffer(1024, 77)), (1024, 77));
+        assert_eq!(unpack_buffer(pack_buffer(i32::MAX, 1)), (i32::MAX, 1));
+        assert_eq!(pack_buffer(0, 0), 0);
+    }
+
+    #[test]
+    fn test_load_info_serialization() {
+        let info = WasmLoadInfo{
+            host_type: HostType::Peer,
+            host_id: Some(12),
+            domain: "test".to_string(),
+        };
+        assert_eq!(WasmLoadInfo::from_serialized(&info.serialize()).unwrap().0, info);
+    }
+}
@@ -0,0 +1,9 @@
+///
+/// Interface between host and WASM modules: exported and imported functions and data passed through them
+///
+pub mod abi;
+
+///
+/// WASM module implementing MilkywayModule
+///
+pub mod module;
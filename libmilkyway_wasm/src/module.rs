@@ -0,0 +1,410 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::{Arc, Mutex, Weak};
+use wasmtime::{AsContext, AsContextMut, Caller, Engine, Instance, Linker, Memory, Module, Store, TypedFunc};
+use libmilkyway::cli::describe::ModuleDescription;
+use libmilkyway::message::common::Message;
+use libmilkyway::module::{CLIStatus, HostType, MilkywayModule, ModuleDataBus};
+use libmilkyway::pki::certificate::Certificate;
+use libmilkyway::serialization::deserializable::Deserializable;
+use libmilkyway::serialization::serializable::{Serializable, Serialized};
+use libmilkyway::services::certificate::{CertificateService, CertificateServiceBinder};
+use libmilkyway::services::transport::{MessageFilter, TransportService};
+use libmilkyway::transport::{TransportListener, TransportSender};
+use crate::abi::*;
+
+type PendingMessages = Arc<Mutex<VecDeque<(u64, Message)>>>;
+
+///
+/// Services available to module through host functions
+///
+struct WasmHostState{
+    transport: Option<Box<dyn TransportService>>,
+    sender: Option<Box<dyn TransportSender>>,
+    certificates: Option<Box<CertificateServiceBinder>>,
+    /** Host subscription IDs by module subscription IDs **/
+    subscriptions: HashMap<u64, u128>,
+    last_subscription_id: u64,
+    pending: PendingMessages,
+    instance: Weak<Mutex<WasmInstance>>,
+}
+
+struct WasmInstance{
+    store: Store<WasmHostState>,
+    instance: Instance,
+}
+
+///
+/// Listener queueing messages for module, they are delivered as soon as module is not busy
+///
+struct QueueingListener{
+    subscription: u64,
+    pending: PendingMessages,
+    instance: Weak<Mutex<WasmInstance>>,
+}
+
+impl TransportListener for QueueingListener{
+    fn on_message(&mut self, message: Message) {
+        self.pending.lock().unwrap().push_back((self.subscription, message));
+        if let Some(instance) = self.instance.upgrade(){
+            deliver_pending(&instance, &self.pending);
+        }
+    }
+}
+
+///
+/// Delivers queued messages unless module is already running, in which case
+/// the running call delivers them once it finishes
+///
+fn deliver_pending(instance: &Mutex<WasmInstance>, pending: &PendingMessages){
+    loop {
+        let mut guard = match instance.try_lock() {
+            Ok(guard) => guard,
+            Err(_) => return,
+        };
+        loop {
+            let next = pending.lock().unwrap().pop_front();
+            match next {
+                Some((subscription, message)) => guard.deliver(subscription, &message),
+                None => break,
+            }
+        }
+        drop(guard);
+        if pending.lock().unwrap().is_empty(){
+            return;
+        }
+    }
+}
+
+fn get_memory<T>(caller: &mut Caller<'_, T>) -> wasmtime::Result<Memory>{
+    caller.get_export(EXPORT_MEMORY)
+        .and_then(|export| export.into_memory())
+        .ok_or_else(|| wasmtime::Error::msg("Module does not export memory"))
+}
+
+fn get_alloc<T>(caller: &mut Caller<'_, T>) -> wasmtime::Result<TypedFunc<i32, i32>>{
+    caller.get_export(EXPORT_ALLOC)
+        .and_then(|export| export.into_func())
+        .ok_or_else(|| wasmtime::Error::msg("Module does not export mway_alloc"))?
+        .typed::<i32, i32>(&*caller)
+}
+
+fn read_from_guest(store: impl AsContext, memory: Memory, ptr: i32, len: i32) -> wasmtime::Result<Serialized>{
+    let mut data = vec![0u8; len as u32 as usize];
+    memory.read(&store, ptr as u32 as usize, &mut data)?;
+    Ok(data)
+}
+
+fn write_to_guest(mut store: impl AsContextMut, memory: Memory, alloc: &TypedFunc<i32, i32>,
+                  data: &[u8]) -> wasmtime::Result<(i32, i32)>{
+    let len = i32::try_from(data.len())?;
+    let ptr = alloc.call(&mut store, len)?;
+    memory.write(&mut store, ptr as u32 as usize, data)?;
+    Ok((ptr, len))
+}
+
+fn read_argument<T: Deserializable>(caller: &mut Caller<'_, WasmHostState>, ptr: i32,
+                                    len: i32) -> wasmtime::Result<T>{
+    let memory = get_memory(caller)?;
+    let data = read_from_guest(&*caller, memory, ptr, len)?;
+    match T::from_serialized(&data) {
+        Ok((value, _)) => Ok(value),
+        Err(_) => Err(wasmtime::Error::msg("Malformed argument passed by module")),
+    }
+}
+
+fn return_value<T: Serializable>(caller: &mut Caller<'_, WasmHostState>, value: &T) -> wasmtime::Result<i64>{
+    let memory = get_memory(caller)?;
+    let alloc = get_alloc(caller)?;
+    let (ptr, len) = write_to_guest(&mut *caller, memory, &alloc, &value.serialize())?;
+    Ok(pack_buffer(ptr, len))
+}
+
+fn get_certificates<'a>(caller: &'a mut Caller<'_, WasmHostState>) -> wasmtime::Result<&'a mut Box<CertificateServiceBinder>>{
+    caller.data_mut().certificates.as_mut()
+        .ok_or_else(|| wasmtime::Error::msg("Module uses certificates before being loaded"))
+}
+
+fn create_linker(engine: &Engine) -> wasmtime::Result<Linker<WasmHostState>>{
+    let mut linker = Linker::new(engine);
+    linker.func_wrap(HOST_NAMESPACE, "send_message",
+                     |mut caller: Caller<'_, WasmHostState>, ptr: i32, len: i32| -> wasmtime::Result<()> {
+        let message: Message = read_argument(&mut caller, ptr, len)?;
+        match caller.data_mut().sender.as_mut() {
+            Some(sender) => sender.send_message(message),
+            None => log::warn!("WASM module sends message before being loaded"),
+        }
+        Ok(())
+    })?;
+    linker.func_wrap(HOST_NAMESPACE, "subscribe",
+                     |mut caller: Caller<'_, WasmHostState>, ptr: i32, len: i32| -> wasmtime::Result<i64> {
+        let filter: MessageFilter = read_argument(&mut caller, ptr, len)?;
+        let state = caller.data_mut();
+        state.last_subscription_id += 1;
+        let subscription = state.last_subscription_id;
+        let listener = Box::new(QueueingListener{
+            subscription,
+            pending: state.pending.clone(),
+            instance: state.instance.clone(),
+        });
+        let host_subscription = match state.transport.as_mut() {
+            Some(transport) => transport.subscribe_to_messages(&filter, listener),
+            None => return Err(wasmtime::Error::msg("Module subscribes before being loaded")),
+        };
+        state.subscriptions.insert(subscription, host_subscription);
+        Ok(subscription as i64)
+    })?;
+    linker.func_wrap(HOST_NAMESPACE, "unsubscribe",
+                     |mut caller: Caller<'_, WasmHostState>, subscription: i64| {
+        let state = caller.data_mut();
+        if let Some(host_subscription) = state.subscriptions.remove(&(subscription as u64)){
+            if let Some(transport) = state.transport.as_mut(){
+                transport.unsubscribe(host_subscription);
+            }
+        }
+    })?;
+    linker.func_wrap(HOST_NAMESPACE, "get_signing_certificate",
+                     |mut caller: Caller<'_, WasmHostState>, ptr: i32, len: i32| -> wasmtime::Result<i64> {
+        let serial: u128 = read_argument(&mut caller, ptr, len)?;
+        let certificate = get_certificates(&mut caller)?.get_signing_certificate(serial)
+            .map(|certificate| certificate.clone_without_sk());
+        return_value(&mut caller, &certificate)
+    })?;
+    linker.func_wrap(HOST_NAMESPACE, "get_encryption_certificate",
+                     |mut caller: Caller<'_, WasmHostState>, ptr: i32, len: i32| -> wasmtime::Result<i64> {
+        let serial: u128 = read_argument(&mut caller, ptr, len)?;
+        let certificate = get_certificates(&mut caller)?.get_encryption_certificate(serial)
+            .map(|certificate| certificate.clone_without_sk());
+        return_value(&mut caller, &certificate)
+    })?;
+    linker.func_wrap(HOST_NAMESPACE, "verify_signature",
+                     |mut caller: Caller<'_, WasmHostState>, ptr: i32, len: i32| -> wasmtime::Result<i32> {
+        let check: WasmSignatureCheck = read_argument(&mut caller, ptr, len)?;
+        let certificates = get_certificates(&mut caller)?;
+        let verified = match certificates.get_signing_certificate(check.serial) {
+            Some(certificate) => certificates.verify_signing_certificate(&certificate)
+                && certificate.verify_signature(&check.data, &check.signature),
+            None => false,
+        };
+        Ok(i32::from(verified))
+    })?;
+    linker.func_wrap(HOST_NAMESPACE, "log",
+                     |mut caller: Caller<'_, WasmHostState>, level: i32, ptr: i32, len: i32| -> wasmtime::Result<()> {
+        let memory = get_memory(&mut caller)?;
+        let text = read_from_guest(&caller, memory, ptr, len)?;
+        let text = String::from_utf8_lossy(&text);
+        match level {
+            1 => log::error!("WASM module: {}", text),
+            2 => log::warn!("WASM module: {}", text),
+            3 => log::info!("WASM module: {}", text),
+            4 => log::debug!("WASM module: {}", text),
+            _ => log::trace!("WASM module: {}", text),
+        }
+        Ok(())
+    })?;
+    Ok(linker)
+}
+
+impl WasmInstance {
+    fn get_memory(&mut self) -> wasmtime::Result<Memory>{
+        self.instance.get_memory(&mut self.store, EXPORT_MEMORY)
+            .ok_or_else(|| wasmtime::Error::msg("Module does not export memory"))
+    }
+
+    fn write(&mut self, data: &Serialized) -> wasmtime::Result<(i32, i32)>{
+        let memory = self.get_memory()?;
+        let alloc = self.instance.get_typed_func::<i32, i32>(&mut self.store, EXPORT_ALLOC)?;
+        write_to_guest(&mut self.store, memory, &alloc, data)
+    }
+
+    fn read_packed(&mut self, packed: i64) -> wasmtime::Result<Serialized>{
+        let (ptr, len) = unpack_buffer(packed);
+        let memory = self.get_memory()?;
+        read_from_guest(&self.store, memory, ptr, len)
+    }
+
+    fn describe(&mut self) -> wasmtime::Result<ModuleDescription>{
+        let describe = self.instance.get_typed_func::<(), i64>(&mut self.store, EXPORT_DESCRIBE)?;
+        let packed = describe.call(&mut self.store, ())?;
+        let data = self.read_packed(packed)?;
+        match ModuleDescription::from_serialized(&data) {
+            Ok((description, _)) => Ok(description),
+            Err(_) => Err(wasmtime::Error::msg("Malformed module description")),
+        }
+    }
+
+    fn call_with_buffer(&mut self, name: &str, data: &Serialized) -> wasmtime::Result<()>{
+        let (ptr, len) = self.write(data)?;
+        let function = self.instance.get_typed_func::<(i32, i32), ()>(&mut self.store, name)?;
+        function.call(&mut self.store, (ptr, len))
+    }
+
+    fn on_cli_command(&mut self, command: Vec<String>, arguments: Vec<String>) -> wasmtime::Result<Option<Vec<String>>>{
+        let (ptr, len) = self.write(&(command, arguments).serialize())?;
+        let function = self.instance.get_typed_func::<(i32, i32), i64>(&mut self.store, EXPORT_ON_CLI_COMMAND)?;
+        let packed = function.call(&mut self.store, (ptr, len))?;
+        if packed == 0{
+            return Ok(None);
+        }
+        let data = self.read_packed(packed)?;
+        match Option::<Vec<String>>::from_serialized(&data) {
+            Ok((namespace, _)) => Ok(namespace),
+            Err(_) => Err(wasmtime::Error::msg("Malformed CLI status")),
+        }
+    }
+
+    fn receive(&mut self, host_type: HostType, message: &Message) -> wasmtime::Result<()>{
+        let (ptr, len) = self.write(&message.serialize())?;
+        let function = self.instance.get_typed_func::<(i32, i32, i32), ()>(&mut self.store, EXPORT_ON_RECEIVE)?;
+        function.call(&mut self.store, (host_type_to_i32(host_type), ptr, len))
+    }
+
+    fn deliver(&mut self, subscription: u64, message: &Message){
+        let result = self.write(&message.serialize()).and_then(|(ptr, len)| {
+            let function = self.instance.get_typed_func::<(i64, i32, i32), ()>(&mut self.store, EXPORT_ON_MESSAGE)?;
+            function.call(&mut self.store, (subscription as i64, ptr, len))
+        });
+        if let Err(error) = result{
+            log::error!("WASM module failed to handle message: {}", error);
+        }
+    }
+}
+
+///
+/// A portable module compiled to WASM. Module talks to host only through host functions
+/// described in abi, so it never sees secret keys or memory of host.
+///
+pub struct WasmModule{
+    description: ModuleDescription,
+    instance: Arc<Mutex<WasmInstance>>,
+    pending: PendingMessages,
+}
+
+impl WasmModule {
+    ///
+    /// Loads module from .wasm file
+    ///
+    /// # Arguments
+    /// * path: &Path: path to module
+    ///
+    pub fn load(path: &Path) -> Result<WasmModule, Box<dyn std::error::Error>>{
+        let engine = Engine::default();
+        let module = Module::from_file(&engine, path)?;
+        Self::instantiate(&engine, &module)
+    }
+
+    ///
+    /// Loads module from binary or text WASM
+    ///
+    /// # Arguments
+    /// * bytes: &[u8]: contents of module
+    ///
+    pub fn from_binary(bytes: &[u8]) -> Result<WasmModule, Box<dyn std::error::Error>>{
+        let engine = Engine::default();
+        let module = Module::new(&engine, bytes)?;
+        Self::instantiate(&engine, &module)
+    }
+
+    fn instantiate(engine: &Engine, module: &Module) -> Result<WasmModule, Box<dyn std::error::Error>>{
+        let pending: PendingMessages = Arc::new(Mutex::new(VecDeque::new()));
+        let mut store = Store::new(engine, WasmHostState{
+            transport: None,
+            sender: None,
+            certificates: None,
+            subscriptions: HashMap::new(),
+            last_subscription_id: 0,
+            pending: pending.clone(),
+            instance: Weak::new(),
+        });
+        let instance = create_linker(engine)?.instantiate(&mut store, module)?;
+        let mut instance = WasmInstance{ store, instance };
+        let description = instance.describe()?;
+        let instance = Arc::new(Mutex::new(instance));
+        instance.lock().unwrap().store.data_mut().instance = Arc::downgrade(&instance);
+        Ok(WasmModule{
+            description,
+            instance,
+            pending,
+        })
+    }
+
+    ///
+    /// Runs call on module and delivers messages queued meanwhile
+    ///
+    fn call<R>(&self, call: impl FnOnce(&mut WasmInstance) -> wasmtime::Result<R>) -> Option<R>{
+        let result = call(&mut self.instance.lock().unwrap());
+        deliver_pending(&self.instance, &self.pending);
+        match result {
+            Ok(result) => Some(result),
+            Err(error) => {
+                log::error!("WASM module {} failed: {}", self.description.module_id, error);
+                None
+            }
+        }
+    }
+}
+
+impl MilkywayModule for WasmModule{
+    #[inline]
+    fn get_id(&self) -> u64 {
+        self.description.module_id
+    }
+
+    #[inline]
+    fn get_commands(&self) -> Vec<String> {
+        self.description.commands.clone()
+    }
+
+    #[inline]
+    fn describe(&self) -> ModuleDescription {
+        self.description.clone()
+    }
+
+    fn on_load(&mut self, data_bus: Box<dyn ModuleDataBus>) {
+        let info = WasmLoadInfo{
+            host_type: data_bus.get_host_type(),
+            host_id: data_bus.get_host_id(),
+            domain: data_bus.get_name_service().get_domain(),
+        };
+        {
+            let mut instance = self.instance.lock().unwrap();
+            let state = instance.store.data_mut();
+            let mut transport = data_bus.get_transport_service();
+            state.sender = Some(transport.get_sender());
+            state.transport = Some(transport);
+            state.certificates = Some(data_bus.get_certificate_service());
+        }
+        self.call(|instance| instance.call_with_buffer(EXPORT_ON_LOAD, &info.serialize()));
+    }
+
+    fn on_cli_command(&mut self, command: Vec<String>, arguments: Vec<String>) -> CLIStatus {
+        match self.call(|instance| instance.on_cli_command(command, arguments)) {
+            Some(Some(namespace)) => CLIStatus::NamespaceChange(namespace),
+            _ => CLIStatus::Done,
+        }
+    }
+
+    fn on_server_receive(&self, packet: &Message) {
+        self.call(|instance| instance.receive(HostType::Broker, packet));
+    }
+
+    fn on_client_receive(&self, packet: &Message) {
+        self.call(|instance| instance.receive(HostType::Peer, packet));
+    }
+
+    fn on_cli_receive(&self, packet: &Message) {
+        self.call(|instance| instance.receive(HostType::CLI, packet));
+    }
+}
+
+impl Drop for WasmModule {
+    fn drop(&mut self) {
+        let mut instance = self.instance.lock().unwrap();
+        let state = instance.store.data_mut();
+        if let Some(transport) = state.transport.as_mut(){
+            for (_, host_subscription) in state.subscriptions.drain(){
+                transport.unsubscribe(host_subscription);
+            }
+        }
+    }
+}
@@ -1,12 +1,53 @@
-use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
 use libmilkyway::actor::binder::BinderChannelProvider;
-use libmilkyway::actor::binder::coroutine::BinderAsyncService;
+use libmilkyway::actor::binder::pool::DEFAULT_BINDER_POOL_SIZE;
 use libmilkyway::module::{HostType, ModuleDataBus};
-use libmilkyway::services::certificate::{CertificateAsyncService, CertificateServiceBinder};
+use libmilkyway::module::state::{ModuleState, ModuleStateStore, SharedModuleStateStore};
+use libmilkyway::pki::certificate::profile::CertificateProfile;
+use libmilkyway::services::certificate::{CertificateAsyncService, CertificateServiceBinder, CertificateServicePool};
+use libmilkyway::services::certificate::detached::DetachedCertificateService;
+use libmilkyway::services::group::SharedGroupService;
 use libmilkyway::services::name::NameService;
+use libmilkyway::services::name::resolver::{NameResolver, ResolverNameService};
 use libmilkyway::services::transport::TransportService;
+use libmilkyway::services::certificate::keystore::get_key_store_passphrase;
+use libmilkyway::services::certificate::readonly::ReadOnlyCertificateService;
 use libmilkyway::services::impls::certificate::AsyncCertificateServiceImpl;
+use libmilkyway::services::impls::group::GroupServiceImpl;
+use libmilkyway::services::impls::transport::LocalTransportService;
+use libmilkyway::tokio::{init_tokio, tokio_block_on};
+use libmilkyway::transport::access::{AccessControl, SharedAccessControl};
+use libmilkyway::transport::operator::OperatorIdentity;
+use libmilkyway::transport::pinning::{PeerPins, SharedPeerPins};
+use libmilkyway::transport::tap::SharedTransportTap;
+
+///
+/// Runs certificate service on its own thread, so it keeps answering binders of connection
+/// to server while CLI waits for input
+///
+fn start_certificate_service(certificate_storage: PathBuf, key_storage: PathBuf) -> CertificateAsyncService{
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        init_tokio();
+        let storage = certificate_storage.to_str().unwrap();
+        let mut service_impl = if certificate_storage.exists(){
+            AsyncCertificateServiceImpl::load_from_file(storage)
+        } else {
+            AsyncCertificateServiceImpl::new(storage)
+        };
+        // Secret keys are kept apart only once store is split
+        if key_storage.exists(){
+            service_impl.set_key_store(&key_storage, get_key_store_passphrase().as_deref())
+                .expect("Failed to load secret key storage");
+        }
+        // Read-only mode is switched by configuration once bus is created
+        let service = Box::new(ReadOnlyCertificateService::new(service_impl, false));
+        sender.send(CertificateAsyncService::run(service)).expect("CLI is gone");
+        tokio_block_on(std::future::pending::<()>());
+    });
+    receiver.recv().expect("Certificate service failed to start")
+}
 
 ///
 /// A DataBus for CLI program
@@ -14,43 +55,158 @@ use libmilkyway::services::impls::certificate::AsyncCertificateServiceImpl;
 #[derive(Clone)]
 pub struct CLIDataBus{
     certificate_service: Arc<Mutex<CertificateAsyncService>>,
+    /** Binders in pool of each module **/
+    certificate_pool_size: usize,
+    group_service: SharedGroupService,
+    access_control: SharedAccessControl,
+    peer_pins: SharedPeerPins,
+    certificate_profiles: Vec<CertificateProfile>,
+    module_state: SharedModuleStateStore,
+    operator: Option<Arc<OperatorIdentity>>,
+    /** Messages to other hosts are passed to server once CLI is connected to it **/
+    transport_service: LocalTransportService,
+    name_service: ResolverNameService,
 }
 
 impl CLIDataBus{
-    pub fn new(certificate_storage: &str) -> CLIDataBus{
-        let fpath = Path::new(certificate_storage);
-        let service_impl = if fpath.exists(){
-            AsyncCertificateServiceImpl::load_from_file(certificate_storage)
+    pub fn new(host_id: u128, certificate_storage: &str, key_storage: &Path, group_storage: &str,
+               access_storage: &str, pins_storage: &str, state_storage: &str) -> CLIDataBus{
+        let service = start_certificate_service(PathBuf::from(certificate_storage), key_storage.to_path_buf());
+        let group_service = if Path::new(group_storage).exists(){
+            GroupServiceImpl::load_from_file(group_storage)
         } else {
-            AsyncCertificateServiceImpl::new(certificate_storage)
+            GroupServiceImpl::new(group_storage)
         };
-        let service = Box::new(service_impl);
-        let service = BinderAsyncService::run(service);
+        let group_service: SharedGroupService = Arc::new(Mutex::new(group_service));
+        let mut transport_service = LocalTransportService::new(host_id);
+        transport_service.set_group_service(group_service.clone());
         CLIDataBus{
             certificate_service: Arc::new(Mutex::new(service)),
+            certificate_pool_size: DEFAULT_BINDER_POOL_SIZE,
+            group_service,
+            access_control: AccessControl::open_shared(access_storage),
+            peer_pins: PeerPins::open_shared(pins_storage),
+            certificate_profiles: Vec::new(),
+            module_state: ModuleStateStore::open_shared(state_storage),
+            operator: None,
+            transport_service,
+            name_service: ResolverNameService::new(NameResolver::new_shared("")),
         }
     }
+
+    ///
+    /// Sets certificate profiles from configuration
+    ///
+    pub fn set_certificate_profiles(&mut self, profiles: Vec<CertificateProfile>) -> &mut Self{
+        self.certificate_profiles = profiles;
+        self
+    }
+
+    ///
+    /// Sets number of binders to certificate service in pool of each module
+    ///
+    pub fn set_certificate_pool_size(&mut self, size: usize) -> &mut Self{
+        self.certificate_pool_size = size;
+        self
+    }
+
+    ///
+    /// Sets resolver of peer names from configuration
+    ///
+    pub fn set_name_resolver(&mut self, resolver: NameResolver) -> &mut Self{
+        self.name_service = ResolverNameService::new(Arc::new(Mutex::new(resolver)));
+        self
+    }
+
+    ///
+    /// Sets operator issuing commands: every message sent from CLI namespaces is stamped
+    /// with serial of operator certificate and signed with it
+    ///
+    pub fn set_operator(&mut self, operator: OperatorIdentity) -> &mut Self{
+        self.operator = Some(Arc::new(operator));
+        self
+    }
+
+    ///
+    /// Sets tap recording messages passing through transport of CLI
+    ///
+    pub fn set_transport_tap(&mut self, tap: Option<SharedTransportTap>) -> &mut Self{
+        self.transport_service.set_tap(tap);
+        self
+    }
+
+    ///
+    /// Gets transport service of CLI to connect it to server. Clones share subscriptions.
+    ///
+    #[inline]
+    pub fn get_local_transport(&mut self) -> &mut LocalTransportService{
+        &mut self.transport_service
+    }
+
+    ///
+    /// Creates certificate service usable from coroutines and threads without runtime
+    ///
+    pub fn get_detached_certificate_service(&self) -> DetachedCertificateService{
+        let service = self.certificate_service.clone();
+        DetachedCertificateService::spawn(move || service.lock().unwrap().bind())
+    }
+
+    ///
+    /// Gets state of all modules, e.g. to inspect it from CLI
+    ///
+    #[inline]
+    pub fn get_module_state_store(&self) -> SharedModuleStateStore{
+        self.module_state.clone()
+    }
 }
 
 impl ModuleDataBus for CLIDataBus{
     fn get_transport_service(&self) -> Box<dyn TransportService> {
-        todo!()
+        Box::new(self.transport_service.clone())
     }
 
     fn get_name_service(&self) -> Box<dyn NameService> {
-        todo!()
+        Box::new(self.name_service.clone())
     }
 
     fn get_certificate_service(&self) -> Box<CertificateServiceBinder> {
         self.certificate_service.lock().unwrap().bind()
     }
 
+    fn get_certificate_pool(&self) -> CertificateServicePool {
+        CertificateServicePool::new(&mut *self.certificate_service.lock().unwrap(), self.certificate_pool_size)
+    }
+
     fn get_host_type(&self) -> HostType {
         HostType::CLI
     }
 
     fn get_host_id(&self) -> Option<u128> {
-        todo!()
+        Some(self.transport_service.get_host_id())
+    }
+
+    fn get_group_service(&self) -> Option<SharedGroupService> {
+        Some(self.group_service.clone())
+    }
+
+    fn get_access_control(&self) -> Option<SharedAccessControl> {
+        Some(self.access_control.clone())
+    }
+
+    fn get_peer_pins(&self) -> Option<SharedPeerPins> {
+        Some(self.peer_pins.clone())
+    }
+
+    fn get_certificate_profiles(&self) -> Vec<CertificateProfile> {
+        self.certificate_profiles.clone()
+    }
+
+    fn get_module_state(&self, module_id: u64) -> Option<ModuleState> {
+        Some(ModuleState::new(module_id, self.module_state.clone()))
+    }
+
+    fn get_operator(&self) -> Option<Arc<OperatorIdentity>> {
+        self.operator.clone()
     }
 }
 
@@ -1,36 +1,299 @@
+use std::collections::HashMap;
 use std::io::{BufRead, stdin, stdout, Write};
+use std::time::Instant;
 use colored::Colorize;
+use libmilkyway::cli::arguments::parse_arguments;
+use libmilkyway::cli::output;
+use libmilkyway::cli::completion::complete_path;
+use libmilkyway::cli::describe::{ArgumentKind, ModuleDescription};
+use libmilkyway::cli::router::{complete_word, expand_alias, CommandRouter};
+use libmilkyway::cli::tap::TransportTapNamespace;
+use libmilkyway::cli::table::Table;
+use libmilkyway::cli::io::TerminalSessionHost;
 use libmilkyway::module::CLIStatus;
-use libmilkyway::module::loader::DynamicModule;
+use libmilkyway::module::session::drive_cli_session;
+use libmilkyway::module::state::SharedModuleStateStore;
+use libmilkyway::module::supervisor::SupervisedModule;
+use libmilkyway::transport::tap::SharedTransportTap;
 
 ///
 /// Stores state of CLI and handles commands
 ///
 pub(crate) struct CLIController{
     known_commands: Vec<String>,
-    modules: Vec<DynamicModule>,
+    modules: Vec<SupervisedModule>,
+    descriptions: Vec<ModuleDescription>,
     current_namespace: Vec<String>,
+    module_state: Option<SharedModuleStateStore>,
+    /** User-defined aliases: target paths by alias paths **/
+    aliases: HashMap<Vec<String>, Vec<String>>,
+    /** Namespaces of CLI itself, e.g. `transport/tap` **/
+    router: CommandRouter,
 }
 
 impl CLIController {
     ///
-    /// Creates a CLIController with given modules
+    /// Creates a CLIController with given modules. Modules which commands or namespaces
+    /// collide with already accepted modules are not used.
     ///
     /// # Arguments
-    /// * modules: Vec<DynamicModule>: a vector of modules
+    /// * modules: Vec<SupervisedModule>: a vector of loaded modules
     ///
     /// returns: CLIController: new CLI controller
     ///
-    pub fn new(mut modules: Vec<DynamicModule>) -> Self{
+    pub fn new(modules: Vec<SupervisedModule>) -> Self{
         let mut known_commands = Vec::<String>::new();
-        for module in &mut modules{
-            known_commands.extend(module.instance.get_commands());
+        let mut accepted_modules = Vec::<SupervisedModule>::new();
+        let mut descriptions = Vec::<ModuleDescription>::new();
+        for mut module in modules{
+            let description = match module.invoke("describe", |instance| instance.describe()) {
+                Some(description) => description,
+                None => {
+                    output::error(format!("module {} is not loaded: it failed to start", module.get_status().name));
+                    continue;
+                }
+            };
+            let mut collisions = Vec::<String>::new();
+            for accepted in descriptions.iter(){
+                collisions.extend(description.find_collisions(accepted));
+            }
+            if !collisions.is_empty(){
+                output::error(format!("module {} is not loaded: commands collide with other modules: {}",
+                                      description.module_id, collisions.join(", ")));
+                continue;
+            }
+            known_commands.extend(description.commands.clone());
+            descriptions.push(description);
+            accepted_modules.push(module);
         }
         CLIController{
             known_commands,
-            modules,
+            modules: accepted_modules,
+            descriptions,
             current_namespace: Vec::<String>::new(),
+            module_state: None,
+            aliases: HashMap::new(),
+            router: CommandRouter::new(),
+        }
+    }
+
+    ///
+    /// Sets state of modules inspected by `modules state`
+    ///
+    pub fn set_module_state(&mut self, store: SharedModuleStateStore) -> &mut Self{
+        self.module_state = Some(store);
+        self
+    }
+
+    ///
+    /// Sets user-defined aliases which are expanded before commands are routed to modules
+    ///
+    pub fn set_aliases(&mut self, aliases: HashMap<Vec<String>, Vec<String>>) -> &mut Self{
+        self.aliases = aliases;
+        self
+    }
+
+    ///
+    /// Sets tap of CLI transport inspected by `transport/tap` commands
+    ///
+    /// # Arguments
+    /// * tap: Option<SharedTransportTap>: tap of transport service, None if tapping is disabled
+    ///
+    pub fn set_transport_tap(&mut self, tap: Option<SharedTransportTap>) -> &mut Self{
+        self.router.register_namespace(vec!["transport".to_string(), "tap".to_string()],
+                                       Box::new(TransportTapNamespace::new(tap)));
+        self
+    }
+
+    ///
+    /// Shows commands which path starts with given prefix
+    ///
+    /// # Arguments
+    /// * prefix: Vec<String>: path to namespace to show, empty to show everything
+    ///
+    fn show_help(&self, prefix: Vec<String>){
+        let mut table = Table::new(vec!["COMMAND", "ARGUMENTS", "DESCRIPTION"]);
+        let mut found = false;
+        for description in self.descriptions.iter(){
+            for command in description.commands.iter(){
+                if prefix.is_empty() && description.namespaces.is_empty(){
+                    table.add_row(vec![command.as_str(), "", ""]);
+                    found = true;
+                }
+            }
+            for namespace in description.namespaces.iter(){
+                if !namespace.path.starts_with(&prefix){
+                    continue;
+                }
+                for command in namespace.commands.iter(){
+                    table.add_row(vec![&namespace.get_command_path(command), &command.get_usage(),
+                                       &command.description]);
+                    found = true;
+                }
+            }
+        }
+        for namespace in self.router.describe().iter(){
+            if !namespace.path.starts_with(&prefix){
+                continue;
+            }
+            for command in namespace.commands.iter(){
+                table.add_row(vec![&namespace.get_command_path(command), &command.get_usage(),
+                                   &command.description]);
+                found = true;
+            }
+        }
+        if !found{
+            output::error("No commands found");
+            return;
+        }
+        table.display();
+    }
+
+    ///
+    /// Prints completion data: a command path followed by its arguments on each line
+    ///
+    fn show_completions(&self){
+        for description in self.descriptions.iter(){
+            for (path, arguments) in description.get_completions(){
+                if arguments.is_empty(){
+                    println!("{}", path);
+                } else {
+                    println!("{} {}", path, arguments.join(" "));
+                }
+            }
+        }
+    }
+
+    ///
+    /// Prints values of argument of command one per line: local paths for file arguments,
+    /// values module completes from its services otherwise
+    ///
+    /// # Arguments
+    /// * arguments: Vec<String>: path to command and `argument=prefix`
+    ///
+    fn show_value_completions(&mut self, arguments: Vec<String>) -> bool{
+        let (command, argument) = match arguments.as_slice() {
+            [command, argument] => (command, argument),
+            _ => {
+                output::error("Usage: completions <command> <argument>=<prefix>");
+                return false;
+            }
+        };
+        let (argument, prefix) = argument.split_once('=').unwrap_or((argument, ""));
+        let mut path = self.current_namespace.clone();
+        path.extend(command.split("/").filter(|part| !part.is_empty()).map(|part| part.to_string()));
+        let mut path = expand_alias(&path, &self.aliases);
+        match path.first().map(|word| complete_word(word, &self.known_commands)) {
+            Some(Ok(Some(command))) => path[0] = command,
+            _ => return false,
+        }
+        let owner = self.modules.iter_mut().zip(self.descriptions.iter())
+            .find(|(_, description)| description.commands.contains(&path[0]));
+        let values = match owner {
+            None => vec![],
+            Some((_, description)) if description.get_argument(&path, argument)
+                .is_some_and(|argument| argument.kind == ArgumentKind::File) => complete_path(prefix),
+            Some((module, _)) => module.invoke("on_cli_complete", |instance| {
+                instance.on_cli_complete(path.clone(), argument.to_string(), prefix.to_string())
+            }).unwrap_or_default(),
+        };
+        for value in values{
+            println!("{}", value);
+        }
+        true
+    }
+
+    ///
+    /// Shows health of loaded modules
+    ///
+    fn show_modules_status(&self){
+        let mut table = Table::new(vec!["MODULE", "ID", "STATUS", "FAILURES", "RESTARTS", "LAST ERROR"]);
+        for module in self.modules.iter(){
+            let status = module.get_status();
+            let health = match status.restart_at {
+                Some(restart_at) => format!("{} (restart in {}s)", status.health,
+                                            restart_at.saturating_duration_since(Instant::now()).as_secs()),
+                None => status.health.to_string(),
+            };
+            table.add_row(vec![&status.name, &status.module_id.map(|id| id.to_string()).unwrap_or_default(),
+                               &health, &status.failures.to_string(), &status.restarts.to_string(),
+                               status.last_error.as_deref().unwrap_or("")]);
         }
+        table.display();
+    }
+
+    ///
+    /// Shows or clears persistent state of modules
+    ///
+    /// # Arguments
+    /// * arguments: Vec<String>: optional `clear`, `module=<id>` and `key=<key>`
+    ///
+    fn handle_module_state(&self, mut arguments: Vec<String>) -> bool{
+        let store = match &self.module_state {
+            Some(store) => store,
+            None => {
+                output::error("State of modules is not available");
+                return false;
+            }
+        };
+        let is_clear = arguments.first().is_some_and(|argument| argument == "clear");
+        if is_clear{
+            arguments.remove(0);
+        }
+        let argmap = parse_arguments(arguments);
+        let module_id = match argmap.get("module") {
+            Some(id) => match id.as_ref().and_then(|id| id.parse::<u64>().ok()) {
+                Some(id) => Some(id),
+                None => {
+                    output::error("Argument 'module' must be an ID of module");
+                    return false;
+                }
+            },
+            None => None,
+        };
+        let mut store = store.lock().unwrap();
+        if is_clear{
+            let module_id = match module_id {
+                Some(module_id) => module_id,
+                None => {
+                    output::error("Argument 'module' is required");
+                    return false;
+                }
+            };
+            match argmap.get("key").cloned().flatten() {
+                Some(key) => {
+                    if !store.remove(module_id, &key){
+                        output::error(format!("Module {} has no key '{}'", module_id, key));
+                        return false;
+                    }
+                    output::info(format!("Removed key '{}' of module {}", key, module_id));
+                }
+                None => output::info(format!("Removed {} keys of module {}", store.clear(module_id), module_id)),
+            }
+            store.commit();
+            return true;
+        }
+        if let Some(module_id) = module_id{
+            let mut table = Table::new(vec!["KEY", "SIZE"]);
+            for key in store.get_keys(module_id){
+                let size = store.get(module_id, &key).map_or(0, |value| value.len());
+                table.add_row(vec![&key, &size.to_string()]);
+            }
+            table.display();
+            return true;
+        }
+        let mut table = Table::new(vec!["MODULE", "ID", "KEYS", "USED", "QUOTA"]);
+        for module_id in store.get_modules(){
+            let name = self.modules.iter()
+                .map(|module| module.get_status())
+                .find(|status| status.module_id == Some(module_id))
+                .map(|status| status.name.clone())
+                .unwrap_or_default();
+            table.add_row(vec![&name, &module_id.to_string(), &store.get_keys(module_id).len().to_string(),
+                               &store.get_usage(module_id).to_string(), &store.get_quota(module_id).to_string()]);
+        }
+        table.display();
+        true
     }
 
     ///
@@ -45,26 +308,92 @@ impl CLIController {
         if namespaces.len() == 0{
             return false;
         }
+        if namespaces[0] == "help"{
+            let mut prefix = self.current_namespace.clone();
+            if let Some(path) = arguments.first(){
+                prefix.extend(path.split("/").filter(|part| !part.is_empty()).map(|part| part.to_string()));
+            }
+            self.show_help(prefix);
+            return true;
+        }
+        if namespaces[0] == "completions" && !arguments.is_empty(){
+            return self.show_value_completions(arguments);
+        }
+        if namespaces[0] == "completions"{
+            self.show_completions();
+            return true;
+        }
+        if namespaces[0] == "modules" && (namespaces.get(1) == Some(&"status")
+            || (namespaces.len() == 1 && arguments.first().is_some_and(|argument| argument == "status"))){
+            self.show_modules_status();
+            return true;
+        }
+        if namespaces[0] == "modules"{
+            let path: Vec<String> = namespaces[1..].iter().map(|namespace| namespace.to_string())
+                .chain(arguments.iter().cloned())
+                .collect();
+            if path.first().is_some_and(|word| word == "state"){
+                return self.handle_module_state(path[1..].to_vec());
+            }
+        }
+        if namespaces[0] == "transport"{
+            let path: Vec<String> = namespaces.iter().map(|namespace| namespace.to_string()).collect();
+            if path.len() < 2 || !self.router.on_command(path, arguments){
+                output::error("No such command");
+                return false;
+            }
+            return true;
+        }
         let mut string_namespaces = self.current_namespace.clone();
         for s in &namespaces{
             string_namespaces.push(s.to_string());
         }
         //println!("{:?}", string_namespaces);
-        let toplevel_command = string_namespaces[0].clone();
-        if !self.known_commands.contains(&toplevel_command.to_string()){
-             println!("{}: {}{}", "error".red().bold().underline(), "unknown command: ".clear(),
-                      toplevel_command);
-            return false;
-        }
-        for module in &mut self.modules{
-            match module.instance.on_cli_command(string_namespaces.clone(), arguments.clone()){
-                CLIStatus::NamespaceChange(path) => {
+        let mut string_namespaces = expand_alias(&string_namespaces, &self.aliases);
+        let toplevel_command = match complete_word(&string_namespaces[0], &self.known_commands) {
+            Ok(Some(command)) => command,
+            Ok(None) => {
+                output::error(format!("unknown command: {}", string_namespaces[0]));
+                return false;
+            }
+            Err(candidates) => {
+                output::error(format!("{} is ambiguous, it may be one of: {}", string_namespaces[0],
+                                      candidates.join(", ")));
+                return false;
+            }
+        };
+        string_namespaces[0] = toplevel_command.clone();
+        let mut handled = true;
+        for (module, description) in self.modules.iter_mut().zip(self.descriptions.iter()){
+            let status = module.invoke("on_cli_command", |instance| {
+                instance.on_cli_command(string_namespaces.clone(), arguments.clone())
+            }).and_then(|status| drive_cli_session(status, &mut TerminalSessionHost, |input| {
+                module.invoke("on_cli_continue", |instance| instance.on_cli_continue(input))
+            }));
+            match status {
+                Some(CLIStatus::NamespaceChange(path)) => {
                     self.current_namespace = path;
                 }
-                CLIStatus::Done => {}
+                // Sessions are driven until they finish, so only Done is left
+                Some(CLIStatus::Done | CLIStatus::InProgress(_) | CLIStatus::NeedsInput(_)) => {}
+                Some(CLIStatus::Failed(failure)) => {
+                    output::error(failure.message);
+                    for detail in failure.details{
+                        output::info(detail);
+                    }
+                    handled = false;
+                }
+                // Module panicked or is waiting for restart
+                None => {
+                    let status = module.get_status();
+                    if description.commands.contains(&toplevel_command){
+                        output::error(format!("module {} is {}, see `modules status`", status.name, status.health));
+                        handled = false;
+                    }
+                }
             }
         }
-        true
+        handled
     }
 
     ///
@@ -1,7 +1,64 @@
+use std::collections::HashMap;
 use std::path::Path;
-use colored::Colorize;
+use std::time::Duration;
+use libmilkyway::cli::output;
+use libmilkyway::actor::binder::pool::DEFAULT_BINDER_POOL_SIZE;
+use libmilkyway::controllers::admin::DEFAULT_ADMIN_SOCKET_PATH;
+use libmilkyway::module::state::DEFAULT_MODULE_STATE_QUOTA;
+use libmilkyway::module::supervisor::RestartPolicy;
+use libmilkyway::peer::{PeerId, PeerIdError};
+use libmilkyway::pki::certificate::flags::parse_flags;
+use libmilkyway::pki::certificate::profile::CertificateProfile;
+use libmilkyway::secrets::SecretResolver;
+use libmilkyway::services::certificate::usage::UsageThresholds;
+use libmilkyway::services::name::dns::{DnsNameBackend, UdpDnsLookup};
+use libmilkyway::services::name::resolver::{NameResolver, StaticNameBackend};
+use libmilkyway::transport::TRANSPORT_TARGET_SERVER;
+use libmilkyway::transport::checksum::ChecksumMode;
+use libmilkyway::transport::compression::{CompressionAlgorithm, CompressionPolicy};
+use libmilkyway::transport::tap::DEFAULT_TAP_CAPACITY;
 use yaml_rust2::{Yaml, YamlLoader};
 
+///
+/// Replaces encrypted string values(`enc:...`) with decrypted ones
+///
+/// # Arguments
+/// * yaml: &mut Yaml: node to resolve recursively
+/// * resolver: &SecretResolver: keys of this node
+/// * path: &str: path to node, used in errors
+///
+fn resolve_yaml_secrets(yaml: &mut Yaml, resolver: &SecretResolver, path: &str) -> Result<(), String>{
+    match yaml {
+        Yaml::String(value) => {
+            *value = resolver.resolve(value).map_err(|error| format!("{}: {}", path, error))?;
+        }
+        Yaml::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate(){
+                resolve_yaml_secrets(item, resolver, &format!("{}[{}]", path, index))?;
+            }
+        }
+        Yaml::Hash(items) => {
+            for (key, item) in items.iter_mut(){
+                let key = key.as_str().map_or_else(|| format!("{:?}", key), |key| key.to_string());
+                resolve_yaml_secrets(item, resolver, &if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) })?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+///
+/// Parses ID or serial, which may be written as string since it may not fit into YAML integer
+///
+fn parse_id(yaml: &Yaml) -> Option<u128>{
+    match yaml {
+        Yaml::Integer(value) => u128::try_from(*value).ok(),
+        Yaml::String(value) => value.parse().ok(),
+        _ => None,
+    }
+}
+
 ///
 /// A configuration data for CLI
 /// 
@@ -18,12 +75,12 @@ impl CLIConfiguration {
     pub fn load(path: &Path) -> Option<Self>{
         let data = std::fs::read_to_string(path);
         if data.is_err(){
-            println!("{}:{}", "error".red().bold().underline(), " Can not read rc file".clear());
+            output::error("Can not read rc file");
             return None;
         }
         let configuration_result = YamlLoader::load_from_str(&data.unwrap());
         if configuration_result.is_err(){
-            println!("{}:{}", "error".red().bold().underline(), " Can not parse rc file".clear());
+            output::error("Can not parse rc file");
             return None;
         }
         Some(CLIConfiguration{
@@ -56,4 +113,337 @@ impl CLIConfiguration {
         }
         Some(Path::new(str_path.unwrap()))
     }
+
+    ///
+    /// Gets path of admin socket of local daemon driven by `mway daemon ...`
+    ///
+    /// returns: &Path: configured `admin_socket` or DEFAULT_ADMIN_SOCKET_PATH
+    ///
+    pub fn get_admin_socket_path(&self) -> &Path{
+        Path::new(self.config_yaml[0]["admin_socket"].as_str().unwrap_or(DEFAULT_ADMIN_SOCKET_PATH))
+    }
+
+    ///
+    /// Gets how panicked modules are restarted. Defaults are used for missing options,
+    /// `module_max_restarts: 0` disables restarts.
+    ///
+    pub fn get_restart_policy(&self) -> RestartPolicy{
+        let mut policy = RestartPolicy::default();
+        let yaml = &self.config_yaml[0];
+        if let Some(max_restarts) = yaml["module_max_restarts"].as_i64(){
+            policy.max_restarts = max_restarts.max(0) as u32;
+        }
+        if let Some(backoff) = yaml["module_restart_backoff_ms"].as_i64(){
+            policy.initial_backoff = Duration::from_millis(backoff.max(0) as u64);
+        }
+        if let Some(backoff) = yaml["module_max_restart_backoff_ms"].as_i64(){
+            policy.max_backoff = Duration::from_millis(backoff.max(0) as u64);
+        }
+        policy
+    }
+
+    ///
+    /// Gets whether certificate store is opened read-only, e.g. during maintenance windows
+    ///
+    /// returns: bool: `read_only` or false if it is not set
+    ///
+    pub fn is_read_only(&self) -> bool{
+        self.config_yaml[0]["read_only"].as_bool().unwrap_or(false)
+    }
+
+    ///
+    /// Gets path of secret key store kept apart from certificates, see `certman store split-keys`
+    ///
+    /// returns: Option<&Path>: `key_store_path` or None if key store is kept in storage directory
+    ///
+    pub fn get_key_store_path(&self) -> Option<&Path>{
+        self.config_yaml[0]["key_store_path"].as_str().map(Path::new)
+    }
+
+    ///
+    /// Gets number of binders to certificate service in pool of each module
+    ///
+    /// returns: usize: `certificate_binder_pool` or DEFAULT_BINDER_POOL_SIZE if it is not set or not positive
+    ///
+    pub fn get_certificate_pool_size(&self) -> usize{
+        self.config_yaml[0]["certificate_binder_pool"].as_i64().filter(|size| *size > 0)
+            .map(|size| size as usize).unwrap_or(DEFAULT_BINDER_POOL_SIZE)
+    }
+
+    ///
+    /// Gets serial of operator certificate messages sent from CLI are signed with
+    ///
+    /// returns: Option<u128>: `operator_certificate` or None if it is not set or invalid
+    ///
+    pub fn get_operator_serial(&self) -> Option<u128>{
+        let value = &self.config_yaml[0]["operator_certificate"];
+        let serial = match value {
+            Yaml::BadValue | Yaml::Null => return None,
+            Yaml::Integer(serial) if *serial >= 0 => Some(*serial as u128),
+            Yaml::String(serial) => serial.parse::<u128>().ok(),
+            _ => None,
+        };
+        if serial.is_none(){
+            output::warning(format!("Invalid serial of operator certificate: {:?}", value));
+        }
+        serial
+    }
+
+    ///
+    /// Gets settings of tap recording messages passing through CLI transport from
+    /// `transport_tap` section(`capacity`, `payloads`)
+    ///
+    /// returns: Option<(usize, bool)>: capacity of tap and whether payloads are recorded,
+    /// None if section is not set
+    ///
+    pub fn get_transport_tap(&self) -> Option<(usize, bool)>{
+        let section = &self.config_yaml[0]["transport_tap"];
+        if section.is_badvalue() || section.is_null(){
+            return None;
+        }
+        let capacity = match &section["capacity"] {
+            Yaml::BadValue => DEFAULT_TAP_CAPACITY,
+            Yaml::Integer(capacity) if *capacity > 0 => *capacity as usize,
+            value => {
+                output::warning(format!("Invalid capacity of transport tap: {:?}", value));
+                return None;
+            }
+        };
+        Some((capacity, section["payloads"].as_bool().unwrap_or(false)))
+    }
+
+    ///
+    /// Gets quotas of module state: `module_state_quota` bytes for every module and
+    /// `module_state_quotas` overrides by module ID
+    ///
+    /// returns: (u64, HashMap<u64, u64>): default quota and overrides
+    ///
+    pub fn get_module_state_quotas(&self) -> (u64, HashMap<u64, u64>){
+        let yaml = &self.config_yaml[0];
+        let default_quota = yaml["module_state_quota"].as_i64()
+            .map_or(DEFAULT_MODULE_STATE_QUOTA, |quota| quota.max(0) as u64);
+        let mut quotas = HashMap::new();
+        if let Some(overrides) = yaml["module_state_quotas"].as_hash(){
+            for (module_id, quota) in overrides{
+                match (module_id.as_i64(), quota.as_i64()) {
+                    (Some(module_id), Some(quota)) if module_id >= 0 => {
+                        quotas.insert(module_id as u64, quota.max(0) as u64);
+                    }
+                    _ => output::warning(format!("Invalid quota of module state: {:?}: {:?}", module_id, quota)),
+                }
+            }
+        }
+        (default_quota, quotas)
+    }
+
+    ///
+    /// Gets user-defined command aliases of `aliases` section, e.g. `gen: certman/signing/generate`.
+    /// Invalid aliases are reported and skipped.
+    ///
+    /// returns: HashMap<Vec<String>, Vec<String>>: target paths by alias paths
+    ///
+    pub fn get_aliases(&self) -> HashMap<Vec<String>, Vec<String>>{
+        let mut aliases = HashMap::new();
+        let section = match self.config_yaml[0]["aliases"].as_hash() {
+            Some(section) => section,
+            None => return aliases,
+        };
+        for (alias, target) in section{
+            let split = |path: &str| -> Vec<String> {
+                path.split("/").filter(|part| !part.is_empty()).map(|part| part.to_string()).collect()
+            };
+            match (alias.as_str().map(split), target.as_str().map(split)) {
+                (Some(alias), Some(target)) if !alias.is_empty() && !target.is_empty() => {
+                    aliases.insert(alias, target);
+                }
+                _ => output::warning(format!("Invalid command alias: {:?}: {:?}", alias, target)),
+            }
+        }
+        aliases
+    }
+
+    ///
+    /// Gets key usage after which certificates should be rotated from `key_usage_thresholds`
+    /// section(`signatures`, `encrypted_bytes`, `sessions`), missing values are defaults
+    ///
+    /// returns: Option<UsageThresholds>: thresholds or None if section is not set
+    ///
+    pub fn get_usage_thresholds(&self) -> Option<UsageThresholds>{
+        let section = &self.config_yaml[0]["key_usage_thresholds"];
+        if section.is_badvalue() || section.is_null(){
+            return None;
+        }
+        let mut thresholds = UsageThresholds::default();
+        for (name, threshold) in [("signatures", &mut thresholds.signatures),
+                                  ("encrypted_bytes", &mut thresholds.encrypted_bytes),
+                                  ("sessions", &mut thresholds.sessions)]{
+            match &section[name] {
+                Yaml::BadValue => {}
+                Yaml::Integer(value) if *value >= 0 => *threshold = *value as u64,
+                value => output::warning(format!("Invalid key usage threshold {}: {:?}", name, value)),
+            }
+        }
+        Some(thresholds)
+    }
+
+    ///
+    /// Gets certificate profiles of `certificate_profiles` section. Invalid profiles are
+    /// reported and skipped.
+    ///
+    pub fn get_certificate_profiles(&self) -> Vec<CertificateProfile>{
+        let mut profiles = Vec::<CertificateProfile>::new();
+        let section = match self.config_yaml[0]["certificate_profiles"].as_vec() {
+            Some(section) => section,
+            None => return profiles,
+        };
+        for item in section{
+            let name = match item["name"].as_str() {
+                Some(name) => name,
+                None => {
+                    output::error("certificate profile must have a name");
+                    continue;
+                }
+            };
+            let flags = match parse_flags(item["flags"].as_str().unwrap_or(""), true) {
+                Ok(flags) => flags,
+                Err(error) => {
+                    output::error(format!("certificate profile {}: {}", name, error));
+                    continue;
+                }
+            };
+            let profile = CertificateProfile::new(name, item["description"].as_str().unwrap_or(""), flags,
+                                                  item["name_template"].as_str().unwrap_or("{name}"));
+            if let Err(error) = profile.validate(){
+                output::error(format!("certificate profile {}: {}", name, error));
+                continue;
+            }
+            profiles.push(profile);
+        }
+        profiles
+    }
+
+    ///
+    /// Gets name resolver of `names` section: static entries and DNS, each switched by its
+    /// `enabled` flag. CLI does not exchange records with peers.
+    ///
+    pub fn get_name_resolver(&self) -> NameResolver{
+        let section = &self.config_yaml[0]["names"];
+        let domain = section["domain"].as_str().unwrap_or("");
+        let mut resolver = NameResolver::new(domain);
+        if let Some(ttl) = section["max_ttl"].as_i64(){
+            resolver.set_max_ttl(ttl.max(0) as u64);
+        }
+        let mut backend = StaticNameBackend::new();
+        let entries = section["static"]["entries"].as_vec().cloned().unwrap_or_default();
+        for entry in entries.iter(){
+            let id = match &entry["id"] {
+                Yaml::Integer(id) => PeerId::try_from(*id),
+                Yaml::String(id) => id.parse::<PeerId>(),
+                id => Err(PeerIdError::Invalid(format!("{:?}", id))),
+            };
+            match (entry["name"].as_str(), id) {
+                (Some(name), Ok(id)) => {
+                    let endpoints = entry["endpoints"].as_vec()
+                        .map(|endpoints| endpoints.iter().filter_map(|endpoint| endpoint.as_str().map(String::from)).collect())
+                        .unwrap_or_default();
+                    backend.add_record(name, id.get(), endpoints);
+                }
+                (_, Err(error)) => output::error(format!("name entry has invalid id: {}: {:?}", error, entry)),
+                _ => output::error(format!("name entry must have a name: {:?}", entry)),
+            }
+        }
+        resolver.add_backend(Box::new(backend), section["static"]["enabled"].as_bool().unwrap_or(true));
+        let dns = &section["dns"];
+        if let Some(server) = dns["server"].as_str(){
+            match server.parse() {
+                Ok(server) => {
+                    let mut lookup = UdpDnsLookup::new(server);
+                    if let Some(timeout) = dns["timeout"].as_i64(){
+                        lookup.set_timeout(timeout.max(0) as u64);
+                    }
+                    resolver.add_backend(Box::new(DnsNameBackend::new(Box::new(lookup), domain)),
+                                         dns["enabled"].as_bool().unwrap_or(false));
+                }
+                Err(_) => output::error(format!("invalid DNS server address '{}'", server)),
+            }
+        }
+        resolver
+    }
+
+    ///
+    /// Gets identity CLI presents to server from `identity` section: `id` of this node and
+    /// serials of its certificates(`signing_serial` and `encryption_serial`) kept with secret keys
+    ///
+    /// returns: Option<(u128, u128, u128)>: ID, signing and encryption serials or None if not set
+    ///
+    pub fn get_identity(&self) -> Option<(u128, u128, u128)>{
+        let section = &self.config_yaml[0]["identity"];
+        Some((parse_id(&section["id"])?, parse_id(&section["signing_serial"])?,
+              parse_id(&section["encryption_serial"])?))
+    }
+
+    ///
+    /// Gets server CLI connects to from `server` section(`address` and `id`)
+    ///
+    /// returns: Option<(String, u128)>: address and ID of server(TRANSPORT_TARGET_SERVER
+    /// by default) or None if CLI only delivers messages between its modules
+    ///
+    pub fn get_server(&self) -> Option<(String, u128)>{
+        let section = &self.config_yaml[0]["server"];
+        let address = section["address"].as_str()?.to_string();
+        Some((address, parse_id(&section["id"]).unwrap_or(TRANSPORT_TARGET_SERVER)))
+    }
+
+    ///
+    /// Gets compression of frames from `compression` section, it must match one of server
+    ///
+    /// returns: Option<CompressionPolicy>: policy or None if frames are not compressed
+    ///
+    pub fn get_compression_policy(&self) -> Option<CompressionPolicy>{
+        let section = &self.config_yaml[0]["compression"];
+        section.as_hash()?;
+        let mut policy = CompressionPolicy::default();
+        if let Some(algorithms) = section["algorithms"].as_vec(){
+            policy.algorithms.clear();
+            for name in algorithms.iter().filter_map(|name| name.as_str()){
+                match CompressionAlgorithm::from_name(name) {
+                    Some(algorithm) => policy.algorithms.push(algorithm),
+                    None => output::error(format!("unknown compression algorithm '{}'", name)),
+                }
+            }
+        }
+        if let Some(threshold) = section["threshold"].as_i64(){
+            policy.threshold = threshold.max(0) as usize;
+        }
+        Some(policy)
+    }
+
+    ///
+    /// Gets whether frames are checksummed from `checksum` value(`auto`, `always` or `never`),
+    /// it must match one of server
+    ///
+    pub fn get_checksum_mode(&self) -> ChecksumMode{
+        match self.config_yaml[0]["checksum"].as_str().unwrap_or("auto") {
+            "always" => ChecksumMode::Always,
+            "never" => ChecksumMode::Never,
+            "auto" => ChecksumMode::Auto,
+            mode => {
+                output::error(format!("unknown checksum mode '{}', using auto", mode));
+                ChecksumMode::Auto
+            }
+        }
+    }
+
+    ///
+    /// Decrypts encrypted values. Paths to storage and modules are used to find keys,
+    /// so they must not be encrypted.
+    ///
+    /// returns: Result<(), String>: error with path of value which can not be decrypted
+    ///
+    pub fn resolve_secrets(&mut self, resolver: &SecretResolver) -> Result<(), String>{
+        for document in self.config_yaml.iter_mut(){
+            resolve_yaml_secrets(document, resolver, "")?;
+        }
+        Ok(())
+    }
 }
\ No newline at end of file
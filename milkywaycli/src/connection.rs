@@ -0,0 +1,124 @@
+use std::sync::mpsc;
+use libmilkyway::cli::output;
+use libmilkyway::controllers::authorization::AuthorizationController;
+use libmilkyway::module::ModuleDataBus;
+use libmilkyway::services::certificate::CertificateService;
+use libmilkyway::tokio::{init_tokio, tokio_block_on};
+use libmilkyway::transport::async_stream::TokioStreamTransport;
+use libmilkyway::transport::compression::CompressionTransformerFactory;
+use libmilkyway::transport::connector::ConnectionManager;
+use libmilkyway::transport::events::{ConnectionEvent, ConnectionEvents, DisconnectReason};
+use libmilkyway::transport::keepalive::KeepAlivePolicy;
+use libmilkyway::transport::router::{LocalDelivery, PeerLink, Router, RouterSender};
+use libmilkyway::transport::session::{AuthorizationAuthority, SessionHandshake};
+use libmilkyway::transport::signature::{SignaturePolicy, DEFAULT_SIGNATURE_AUDIT_CAPACITY};
+use libmilkyway::transport::stack::{CryptoTransformerFactory, TransformerStack};
+use crate::bus::CLIDataBus;
+use crate::configuration::CLIConfiguration;
+
+///
+/// Connects CLI to server and routes messages of modules to other hosts through it. Session
+/// is established and served on a thread of its own, so it keeps running while CLI waits
+/// for input.
+///
+/// # Arguments
+/// * data_bus: &mut CLIDataBus: bus of CLI, its transport sends messages to server
+/// * configuration: &CLIConfiguration: `identity` and `server` of CLI
+///
+/// returns: Result<u128, String>: ID of server once session is established or error
+///
+pub fn connect_to_server(data_bus: &mut CLIDataBus, configuration: &CLIConfiguration) -> Result<u128, String>{
+    let (address, server_id) = configuration.get_server().ok_or("server.address is not set")?;
+    let (host_id, signing_serial, encryption_serial) = configuration.get_identity()
+        .ok_or("identity.id, identity.signing_serial and identity.encryption_serial must be set")?;
+    let mut certificates = data_bus.get_certificate_service();
+    let signing_certificate = certificates.get_signing_certificate(signing_serial)
+        .filter(|certificate| certificate.secret_key.is_some())
+        .ok_or(format!("No signing certificate {} with secret key", signing_serial))?;
+    let encryption_certificate = certificates.get_encryption_certificate(encryption_serial)
+        .filter(|certificate| certificate.secret_key.is_some())
+        .ok_or(format!("No encryption certificate {} with secret key", encryption_serial))?;
+
+    // Layers must be the same as ones of server
+    let mut stack = TransformerStack::new();
+    stack.set_checksum_mode(configuration.get_checksum_mode());
+    if let Some(policy) = configuration.get_compression_policy(){
+        stack.add_factory(Box::new(CompressionTransformerFactory::new(signing_serial, policy)));
+    }
+    stack.add_factory(Box::new(CryptoTransformerFactory::new(signing_certificate, encryption_certificate,
+                                                             Box::new(data_bus.get_detached_certificate_service()))));
+    let authority_bus = data_bus.clone();
+    let authority = AuthorizationAuthority::spawn(move || {
+        let mut controller = AuthorizationController::new(authority_bus.get_certificate_service());
+        controller.set_access_control(authority_bus.get_access_control().unwrap());
+        controller.set_peer_pins(authority_bus.get_peer_pins().unwrap());
+        controller
+    });
+    let mut handshake = SessionHandshake::new(host_id, signing_serial, encryption_serial, authority);
+    handshake.set_stack(stack);
+
+    // Server relays messages of other hosts, so everything not for CLI goes to it
+    let router = Router::new_shared(host_id);
+    router.lock().unwrap().set_default_route(Some(server_id));
+    let mut link = PeerLink::new(router.clone(), LocalDelivery::spawn(data_bus.get_local_transport().clone()),
+                                 KeepAlivePolicy::default());
+    link.set_relay(true);
+
+    // Modules verify that signed messages of server come from it, e.g. its inventory, so
+    // signing certificate server is authorized with is bound to it while it is connected
+    let signature_policy = SignaturePolicy::new_shared(DEFAULT_SIGNATURE_AUDIT_CAPACITY);
+    let events = ConnectionEvents::new_shared();
+    let bound_policy = signature_policy.clone();
+    events.lock().unwrap().subscribe(Box::new(move |event: &ConnectionEvent| {
+        match event {
+            ConnectionEvent::Authorized{peer_id, certificates, ..} => {
+                if let Some(serial) = certificates.last(){
+                    bound_policy.lock().unwrap().bind_peer_certificate(*peer_id, *serial);
+                }
+            }
+            ConnectionEvent::Disconnected{peer_id: Some(peer_id), ..} => {
+                bound_policy.lock().unwrap().unbind_peer(*peer_id);
+            }
+            _ => {}
+        }
+    }));
+    let signature_certificates = data_bus.get_detached_certificate_service();
+    let local_transport = data_bus.get_local_transport();
+    local_transport.set_signature_policy(signature_policy, Box::new(signature_certificates));
+    local_transport.set_connection_events(events.clone());
+
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        init_tokio();
+        tokio_block_on(async move {
+            let connection = match ConnectionManager::new().connect(server_id, &address).await {
+                Ok(connection) => connection,
+                Err(error) => {
+                    let _ = sender.send(Err(format!("Can not connect to {}: {}", address, error)));
+                    return;
+                }
+            };
+            let mut transport = TokioStreamTransport::from_stream(connection.stream);
+            transport.set_connection_events(events, &address);
+            match handshake.connect(&mut transport).await {
+                Ok(id) if id == server_id => {
+                    let _ = sender.send(Ok(id));
+                    link.serve(transport, id).await;
+                    output::warning(format!("Connection to server {} is closed", id));
+                }
+                Ok(id) => {
+                    transport.set_disconnect_reason(DisconnectReason::Error("unexpected peer".to_string()));
+                    let _ = sender.send(Err(format!("Server at {} is authorized as {}, not as {}", address, id,
+                                                    server_id)));
+                }
+                Err(error) => {
+                    transport.set_disconnect_reason(DisconnectReason::Error(error.to_string()));
+                    let _ = sender.send(Err(format!("Can not establish session with server: {}", error)));
+                }
+            }
+        });
+    });
+    let server_id = receiver.recv().map_err(|_| "Connection thread failed".to_string())??;
+    data_bus.get_local_transport().set_remote_sender(Box::new(RouterSender::new(router)));
+    Ok(server_id)
+}
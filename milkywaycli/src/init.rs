@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use libmilkyway::cli::output;
+use libmilkyway::cli::arguments::parse_arguments;
+use libmilkyway::cli::io::{ask, confirm};
+use libmilkyway::paths::PathResolver;
+use libmilkyway::pki::certificate::{Certificate, FLAG_CLIENT_CERT, FLAG_SERVER_CERT, FLAG_SIGN_MESSAGES};
+use libmilkyway::pki::hash::HashType;
+use libmilkyway::pki::impls::certificates::falcon1024::{Falcon1024Certificate, Falcon1024RootCertificate, generate_falcon1024_root_certificate};
+use libmilkyway::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use libmilkyway::pki::impls::keys::falcon1024::generate_falcon1024_keypair;
+use libmilkyway::pki::impls::keys::kyber1024::generate_kyber1024_keypair;
+use libmilkyway::serialization::deserializable::Deserializable;
+use libmilkyway::services::certificate::{CertificateService, ROOT_CERTIFICATE_SERIAL};
+use libmilkyway::services::certificate::serial::generate_serial;
+use libmilkyway::services::group::GROUP_ADDRESS_FLAG;
+use libmilkyway::services::impls::certificate::AsyncCertificateServiceImpl;
+use libmilkyway::transport::TRANSPORT_TARGET_SERVER;
+use yaml_rust2::{Yaml, YamlEmitter};
+use yaml_rust2::yaml::Hash;
+
+/// Address daemon of server node listens on unless `listen` is given
+const DEFAULT_LISTENER_ADDRESS: &str = "127.0.0.1:2804";
+
+///
+/// Role of a node being initialized, defines flags of leaf certificates
+///
+#[derive(Clone, Copy, PartialEq)]
+enum NodeRole{
+    Server,
+    Client,
+}
+
+impl NodeRole {
+    fn parse(value: &str) -> Option<NodeRole>{
+        match value {
+            "server" => Some(NodeRole::Server),
+            "client" => Some(NodeRole::Client),
+            &_ => None,
+        }
+    }
+
+    fn get_flags(&self) -> u128{
+        match self {
+            NodeRole::Server => FLAG_SERVER_CERT,
+            NodeRole::Client => FLAG_CLIENT_CERT,
+        }
+    }
+}
+
+///
+/// Options of init wizard. Missing options are asked interactively
+/// unless non-interactive mode is requested.
+///
+struct InitOptions{
+    storage_path: PathBuf,
+    modules_path: PathBuf,
+    root_name: Option<String>,
+    root_file: Option<PathBuf>,
+    node_name: String,
+    role: NodeRole,
+    /** Address daemon listens on, set for server role only **/
+    listener_address: Option<String>,
+    /** Address of server CLI connects to, set for client role only **/
+    server_address: Option<String>,
+    interactive: bool,
+    force: bool,
+}
+
+fn print_error(message: &str){
+    output::error(message);
+}
+
+///
+/// Gets value of argument or asks user for it
+///
+fn get_value(argmap: &HashMap<String, Option<String>>, name: &str, prompt: &str,
+             default: Option<&str>, interactive: bool) -> Result<Option<String>, String>{
+    if argmap.contains_key(name){
+        let value = argmap.get(name).unwrap();
+        if value.is_none(){
+            return Err(format!("Argument '{}' requires a value", name));
+        }
+        return Ok(value.clone());
+    }
+    if !interactive{
+        return Ok(default.map(|value| value.to_string()));
+    }
+    Ok(Some(ask(prompt, default)))
+}
+
+fn parse_options(arguments: Vec<String>, resolver: &PathResolver) -> Result<InitOptions, String>{
+    let argmap = parse_arguments(arguments);
+    let interactive = !argmap.contains_key("non-interactive");
+    let default_storage_path = resolver.resolve_storage(None).path.display().to_string();
+    let default_modules_path = resolver.resolve_modules(None).path.display().to_string();
+    let storage_path = get_value(&argmap, "storage", "Storage directory",
+                                 Some(&default_storage_path), interactive)?.unwrap();
+    let modules_path = get_value(&argmap, "modules", "Modules directory",
+                                 Some(&default_modules_path), interactive)?.unwrap();
+    let mut root_file = get_value(&argmap, "root-file", "", None, false)?;
+    let mut root_name = get_value(&argmap, "root-name", "", None, false)?;
+    if root_file.is_some() && root_name.is_some(){
+        return Err("Arguments 'root-file' and 'root-name' can not be used together".to_string());
+    }
+    if root_file.is_none() && root_name.is_none(){
+        if !interactive{
+            return Err("Either 'root-file' or 'root-name' is required".to_string());
+        }
+        let answer = ask("Root certificate file to import(empty to generate a new root)", Some(""));
+        if answer.is_empty(){
+            root_name = Some(ask("Root certificate name", None));
+        } else {
+            root_file = Some(answer);
+        }
+    }
+    let node_name = get_value(&argmap, "name", "Node name", None, interactive)?;
+    if node_name.is_none(){
+        return Err("Argument 'name' is required".to_string());
+    }
+    let role = get_value(&argmap, "role", "Node role(server/client)", Some("client"), interactive)?.unwrap();
+    let role = NodeRole::parse(&role);
+    if role.is_none(){
+        return Err("Argument 'role' must be either 'server' or 'client'".to_string());
+    }
+    let listener_address = match role {
+        Some(NodeRole::Server) => get_value(&argmap, "listen", "Listener address", Some(DEFAULT_LISTENER_ADDRESS),
+                                            interactive)?,
+        _ => None,
+    };
+    let server_address = match role {
+        Some(NodeRole::Client) => get_value(&argmap, "server", "Server address", Some(DEFAULT_LISTENER_ADDRESS),
+                                            interactive)?,
+        _ => None,
+    };
+    Ok(InitOptions{
+        storage_path: PathBuf::from(storage_path),
+        modules_path: PathBuf::from(modules_path),
+        root_name,
+        root_file: root_file.map(PathBuf::from),
+        node_name: node_name.unwrap(),
+        role: role.unwrap(),
+        listener_address,
+        server_address,
+        interactive,
+        force: argmap.contains_key("force"),
+    })
+}
+
+fn create_directory(path: &Path) -> Result<(), String>{
+    let result = fs::create_dir_all(path);
+    if result.is_err(){
+        return Err(format!("Can not create directory {}: {}", path.display(), result.err().unwrap()));
+    }
+    Ok(())
+}
+
+fn obtain_root_certificate(options: &InitOptions) -> Result<Falcon1024RootCertificate, String>{
+    if let Some(path) = &options.root_file{
+        let certificate = Falcon1024RootCertificate::from_file(path);
+        if certificate.is_err(){
+            return Err("Can not read root certificate. Does format is correct?".to_string());
+        }
+        output::info(format!("Imported root certificate from {}", path.display()));
+        return Ok(certificate.unwrap());
+    }
+    let certificate = generate_falcon1024_root_certificate(options.root_name.clone().unwrap());
+    output::info("Generated root certificate");
+    Ok(certificate)
+}
+
+// Serials of nodes must differ, since peers keep certificates of each other in one storage
+fn generate_leaf_certificates(root_certificate: &Falcon1024RootCertificate, name: &str, role: NodeRole,
+                              serials: (u128, u128)) -> Result<(Falcon1024Certificate, Kyber1024Certificate), String>{
+    let (public_key, secret_key) = generate_falcon1024_keypair();
+    let mut signing_certificate = Falcon1024Certificate{
+        serial_number: serials.0,
+        parent_serial_number: ROOT_CERTIFICATE_SERIAL,
+        secret_key: Some(secret_key),
+        public_key,
+        signature: None,
+        name: name.to_string(),
+        flags: FLAG_SIGN_MESSAGES | role.get_flags(),
+        metadata: Default::default(),
+    };
+    let signature = root_certificate.sign_data(&signing_certificate.clone_without_signature_and_sk(),
+                                               HashType::None);
+    if signature.is_err(){
+        return Err("Can not sign node signing certificate".to_string());
+    }
+    signing_certificate.signature = Some(signature.unwrap());
+    let (public_key, secret_key) = generate_kyber1024_keypair();
+    let mut encryption_certificate = Kyber1024Certificate{
+        serial_number: serials.1,
+        parent_serial_number: ROOT_CERTIFICATE_SERIAL,
+        secret_key: Some(secret_key),
+        public_key,
+        signature: None,
+        name: name.to_string(),
+        flags: role.get_flags(),
+        metadata: Default::default(),
+    };
+    // Leaf signing certificate has no FLAG_SIGN_CERTS, so encryption certificate is signed by root
+    let signature = root_certificate.sign_data(&encryption_certificate.clone_without_signature_and_sk(),
+                                               HashType::None);
+    if signature.is_err(){
+        return Err("Can not sign node encryption certificate".to_string());
+    }
+    encryption_certificate.signature = Some(signature.unwrap());
+    Ok((signing_certificate, encryption_certificate))
+}
+
+// Asks whether existing file may be overwritten, non-interactive init fails unless forced
+fn confirm_overwrite(options: &InitOptions, question: &str) -> Result<(), String>{
+    if options.force{
+        return Ok(());
+    }
+    if !options.interactive{
+        return Err(format!("{}, pass 'force' to overwrite it", question));
+    }
+    if !confirm(question){
+        return Err("Aborted".to_string());
+    }
+    Ok(())
+}
+
+// Emits YAML document, so values are quoted and escaped by emitter
+fn emit_yaml(document: Hash) -> Result<String, String>{
+    let mut result = String::new();
+    if let Err(error) = YamlEmitter::new(&mut result).dump(&Yaml::Hash(document)){
+        return Err(format!("Can not emit configuration: {}", error));
+    }
+    result.push('\n');
+    Ok(result)
+}
+
+fn get_paths_section(options: &InitOptions) -> Hash{
+    let mut document = Hash::new();
+    document.insert(Yaml::String("storage_path".to_string()),
+                    Yaml::String(options.storage_path.display().to_string()));
+    document.insert(Yaml::String("modules_path".to_string()),
+                    Yaml::String(options.modules_path.display().to_string()));
+    document
+}
+
+// Serials do not fit into YAML integers, so IDs are written as strings
+fn get_identity_section(host_id: u128, serials: (u128, u128)) -> Hash{
+    let mut identity = Hash::new();
+    identity.insert(Yaml::String("id".to_string()), Yaml::String(host_id.to_string()));
+    identity.insert(Yaml::String("signing_serial".to_string()), Yaml::String(serials.0.to_string()));
+    identity.insert(Yaml::String("encryption_serial".to_string()), Yaml::String(serials.1.to_string()));
+    identity
+}
+
+// Writes configuration of CLI, clients also get identity and server they connect to
+fn write_configuration(path: &Path, options: &InitOptions, host_id: u128,
+                       serials: (u128, u128)) -> Result<(), String>{
+    let mut document = get_paths_section(options);
+    if let Some(server_address) = &options.server_address{
+        document.insert(Yaml::String("identity".to_string()), Yaml::Hash(get_identity_section(host_id, serials)));
+        let mut server = Hash::new();
+        server.insert(Yaml::String("address".to_string()), Yaml::String(server_address.to_string()));
+        document.insert(Yaml::String("server".to_string()), Yaml::Hash(server));
+    }
+    let result = fs::write(path, emit_yaml(document)?);
+    if result.is_err(){
+        return Err(format!("Can not write configuration to {}: {}", path.display(), result.err().unwrap()));
+    }
+    Ok(())
+}
+
+// Writes configuration of daemon: storage and modules shared with CLI, identity and listener address
+fn write_server_configuration(path: &Path, options: &InitOptions, listener_address: &str,
+                              serials: (u128, u128)) -> Result<(), String>{
+    let mut document = get_paths_section(options);
+    document.insert(Yaml::String("identity".to_string()),
+                    Yaml::Hash(get_identity_section(TRANSPORT_TARGET_SERVER, serials)));
+    let mut listener = Hash::new();
+    listener.insert(Yaml::String("address".to_string()), Yaml::String(listener_address.to_string()));
+    document.insert(Yaml::String("listener".to_string()), Yaml::Hash(listener));
+    let result = fs::write(path, emit_yaml(document)?);
+    if result.is_err(){
+        return Err(format!("Can not write server configuration to {}: {}", path.display(), result.err().unwrap()));
+    }
+    Ok(())
+}
+
+fn init(options: InitOptions, configuration_path: &Path, server_configuration_path: &Path) -> Result<(), String>{
+    create_directory(&options.storage_path)?;
+    create_directory(&options.modules_path)?;
+    for path in [configuration_path, server_configuration_path]{
+        if let Some(configuration_directory) = path.parent(){
+            create_directory(configuration_directory)?;
+        }
+    }
+    let store_path = options.storage_path.join("certs.dat");
+    let store_path_str = store_path.to_str().unwrap();
+    if store_path.exists(){
+        confirm_overwrite(&options, "Certificate storage already exists and will be overwritten")?;
+    }
+    if configuration_path.exists(){
+        confirm_overwrite(&options, "Configuration file already exists and will be overwritten")?;
+    }
+    if options.listener_address.is_some() && server_configuration_path.exists(){
+        confirm_overwrite(&options, "Server configuration file already exists and will be overwritten")?;
+    }
+    let root_certificate = obtain_root_certificate(&options)?;
+    let mut service = AsyncCertificateServiceImpl::new(store_path_str);
+    let serials = match (generate_serial(&mut service), generate_serial(&mut service)) {
+        (Some(signing_serial), Some(encryption_serial)) if signing_serial != encryption_serial => {
+            (signing_serial, encryption_serial)
+        }
+        _ => return Err("Can not generate serials of node certificates".to_string()),
+    };
+    // IDs of clients are random like serials, server is known to them by its well-known ID.
+    // Highest bit marks addresses of groups, so it is never set in ID of node.
+    let host_id = match options.role {
+        NodeRole::Server => TRANSPORT_TARGET_SERVER,
+        NodeRole::Client => generate_serial(&mut service).ok_or("Can not generate ID of node")? & !GROUP_ADDRESS_FLAG,
+    };
+    let (signing_certificate, encryption_certificate) = generate_leaf_certificates(&root_certificate,
+                                                                                   &options.node_name,
+                                                                                   options.role, serials)?;
+    output::info("Generated node certificates");
+    service.set_root_certificate(root_certificate);
+    if !service.add_signing_certificate(signing_certificate){
+        return Err("Can not add node signing certificate".to_string());
+    }
+    if !service.add_encryption_certificate(encryption_certificate){
+        return Err("Can not add node encryption certificate".to_string());
+    }
+    service.commit();
+    output::info(format!("Stored certificates in {}", store_path.display()));
+    write_configuration(configuration_path, &options, host_id, serials)?;
+    output::info(format!("Written configuration to {}", configuration_path.display()));
+    if let Some(listener_address) = &options.listener_address{
+        write_server_configuration(server_configuration_path, &options, listener_address, serials)?;
+        output::info(format!("Written server configuration to {}", server_configuration_path.display()));
+    }
+    Ok(())
+}
+
+///
+/// Runs init wizard bootstrapping a new node: creates storage layout, generates or
+/// imports root certificate, generates leaf certificates and writes configuration.
+///
+/// # Arguments
+/// * arguments: Vec<String>: arguments of init command
+/// * configuration_path: &Path: where to write configuration file
+/// * resolver: &PathResolver: resolver of default storage and modules directories
+///
+/// # Supported arguments
+/// * storage=<dir>: storage directory
+/// * modules=<dir>: modules directory
+/// * root-name=<name>: generate a new root certificate with given name
+/// * root-file=<file>: import root certificate exported by certman/root/export
+/// * name=<name>: name of node certificates
+/// * role=server|client: role of node, server also gets configuration of daemon
+/// * listen=<address>: address daemon of server listens on, DEFAULT_LISTENER_ADDRESS by default
+/// * server=<address>: address of server client connects to, DEFAULT_LISTENER_ADDRESS by default
+/// * non-interactive: fail instead of asking for missing values or confirmation
+/// * force: overwrite existing storage and configuration without asking
+///
+/// returns: bool: whether initialization was successful
+///
+pub fn run_init(arguments: Vec<String>, configuration_path: &Path, resolver: &PathResolver) -> bool{
+    let options = parse_options(arguments, resolver);
+    if options.is_err(){
+        print_error(&options.err().unwrap());
+        return false;
+    }
+    let server_configuration_path = resolver.resolve_server_configuration(None).path;
+    let result = init(options.unwrap(), configuration_path, &server_configuration_path);
+    if result.is_err(){
+        print_error(&result.err().unwrap());
+        return false;
+    }
+    output::info("Node initialized successfully");
+    true
+}
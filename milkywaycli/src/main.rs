@@ -1,25 +1,58 @@
 mod bus;
 mod configuration;
 mod cli;
+mod connection;
+mod init;
 
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::exit;
-use colored::Colorize;
+use std::sync::Arc;
+use libmilkyway::cli::arguments::parse_arguments;
+use libmilkyway::cli::output;
+use libmilkyway::cli::output::{set_output_mode, OutputMode};
+use libmilkyway::cli::table::Table;
+use libmilkyway::controllers::admin::{send_admin_request, AdminCommand, AdminRequest};
+use libmilkyway::controllers::shutdown::{CertificateFlushHook, ShutdownController, ShutdownStage};
+use libmilkyway::errors::{explain, CATALOG};
+use libmilkyway::message::protocol::describe_protocol;
 use libmilkyway::module::loader::DynamicModule;
+use libmilkyway::module::ModuleDataBus;
+use libmilkyway::module::registry::{BuiltinModule, ModuleRegistry};
+use libmilkyway::module::state::ModuleStateStore;
+use libmilkyway::module::supervisor::{DataBusProvider, SupervisedModule};
+use libmilkyway::paths::{PathResolver, ResolvedPath};
+use libmilkyway::peer::PeerId;
+use libmilkyway::pki::certificate::Certificate;
+use libmilkyway::secrets::{encrypt_with_certificate, encrypt_with_passphrase, SecretResolver, DEFAULT_KDF_ITERATIONS,
+                           PASSPHRASE_VARIABLE};
+use libmilkyway::serialization::migration::{MigrationError, MigrationReport, Migrator};
+use libmilkyway::services::certificate::CertificateService;
+use libmilkyway::services::certificate::inspect::{inspect_certificate_store, upgrade_certificate_store};
+use libmilkyway::services::certificate::keystore::{get_key_store_passphrase, KEY_STORE_PASSPHRASE_VARIABLE};
+use libmilkyway::services::impls::certificate::AsyncCertificateServiceImpl;
+use libmilkyway::services::impls::group::GroupServiceImpl;
+use libmilkyway::services::impls::transport::LOCAL_HOST_ID;
 use libmilkyway::tokio::init_tokio;
+use libmilkyway::transport::access::AccessControl;
+use libmilkyway::transport::deadletter::{DeadLetterQueue, DEFAULT_MAX_DELIVERY_FAILURES};
+use libmilkyway::transport::sequence::{SequenceStats, SequenceStore};
+use libmilkyway::transport::operator::OperatorIdentity;
+use libmilkyway::transport::pinning::PeerPins;
+use libmilkyway::transport::tap::TransportTap;
 use crate::bus::CLIDataBus;
 use crate::cli::CLIController;
 use crate::configuration::CLIConfiguration;
+use crate::connection::connect_to_server;
+use crate::init::run_init;
 
 
 #[allow(unsafe_code)]
-unsafe fn load_modules_from(dir_path: &Path) -> Vec<DynamicModule> {
-    let mut result = Vec::<DynamicModule>::new();
+unsafe fn load_modules_from(dir_path: &Path) -> Vec<(String, DynamicModule)> {
+    let mut result = Vec::<(String, DynamicModule)>::new();
     let paths = fs::read_dir(dir_path.iter());
     if paths.is_err(){
-        println!("{}{}{}", "warning:".yellow().bold().underline(), " ".clear(),
-                 "No modules directory found");
+        output::warning("No modules directory found");
         return vec![];
     }
     for entry in paths.unwrap() {
@@ -42,68 +75,808 @@ unsafe fn load_modules_from(dir_path: &Path) -> Vec<DynamicModule> {
             DynamicModule::load(fname)
         };
         if module.is_err() {
-            println!("{}{}{} {}{}", "warning:".yellow().bold().underline(), " ".clear(),
-                     "Failed to load module:".bold(), "".clear(),
-                     fname);
+            output::warning(format!("Failed to load module: {}", fname));
             //println!("{:?}", module.err().unwrap());
             continue;
         }
-        result.push(module.unwrap());
+        let name = entry.file_name().to_string_lossy().to_string();
+        result.push((name, module.unwrap()));
     }
     result
 }
 
+///
+/// Gets modules compiled into CLI, each is enabled by cargo feature of the same name
+///
+fn get_builtin_modules() -> Vec<BuiltinModule>{
+    vec![
+        #[cfg(feature = "certman")]
+        certman::BUILTIN_MODULE,
+        #[cfg(feature = "ping")]
+        ping::BUILTIN_MODULE,
+        #[cfg(feature = "inventory")]
+        inventory::BUILTIN_MODULE,
+    ]
+}
+
+
+///
+/// Takes `--output=<mode>` options out of arguments and applies them
+///
+fn apply_output_options(arguments: Vec<String>) -> Vec<String>{
+    let mut result = Vec::<String>::new();
+    for argument in arguments{
+        match argument.strip_prefix("--output=") {
+            Some(mode) => match mode.parse::<OutputMode>() {
+                Ok(mode) => set_output_mode(mode),
+                Err(error) => {
+                    output::error(format!("{}: {}", error, mode));
+                    exit(-1);
+                }
+            }
+            None => result.push(argument),
+        }
+    }
+    result
+}
+
+///
+/// Takes `--config=<path>` option out of arguments
+///
+/// returns: (Vec<String>, Option<PathBuf>): remaining arguments and path to configuration if provided
+///
+fn take_config_option(arguments: Vec<String>) -> (Vec<String>, Option<PathBuf>){
+    let mut result = Vec::<String>::new();
+    let mut path = None;
+    for argument in arguments{
+        match argument.strip_prefix("--config=") {
+            Some(value) => path = Some(PathBuf::from(value)),
+            None => result.push(argument),
+        }
+    }
+    (result, path)
+}
+
+///
+/// Shows resolved paths and where each of them was taken from
+///
+fn show_configuration(configuration_path: &ResolvedPath, configuration: Option<&CLIConfiguration>,
+                      resolver: &PathResolver){
+    if configuration.is_none(){
+        output::warning(format!("Configuration file {} does not exist", configuration_path.path.display()));
+    }
+    let storage_path = resolver.resolve_storage(configuration.and_then(|configuration| configuration.get_storage_path()));
+    let modules_path = resolver.resolve_modules(configuration.and_then(|configuration| configuration.get_modules_path()));
+    let mut table = Table::new(vec!["NAME", "PATH", "SOURCE"]);
+    for (name, resolved) in [("configuration", configuration_path), ("storage", &storage_path),
+                             ("modules", &modules_path)]{
+        table.add_row(vec![name, &resolved.path.display().to_string(), &resolved.source.to_string()]);
+    }
+    table.display();
+}
+
+///
+/// Encrypts value for configuration with encryption certificate of this node or with passphrase
+///
+/// # Arguments
+/// * arguments: Vec<String>: `value=<value>` and optionally `certificate=<serial>` or `iterations=<count>`
+/// * certificate_store_path: &Path: store with encryption certificates
+///
+fn encrypt_value(arguments: Vec<String>, certificate_store_path: &Path) -> bool{
+    let argmap = parse_arguments(arguments);
+    let value = match argmap.get("value") {
+        Some(Some(value)) => value,
+        _ => {
+            output::error("Argument 'value' is required");
+            return false;
+        }
+    };
+    if let Some(serial) = argmap.get("certificate"){
+        let serial = match serial.as_ref().and_then(|serial| serial.parse::<u128>().ok()) {
+            Some(serial) => serial,
+            None => {
+                output::error("Argument 'certificate' must be a serial");
+                return false;
+            }
+        };
+        let mut certificates = AsyncCertificateServiceImpl::load_from_file(certificate_store_path.to_str().unwrap());
+        match certificates.get_encryption_certificate(serial) {
+            Some(certificate) => println!("{}", encrypt_with_certificate(value, &certificate)),
+            None => {
+                output::error(format!("No encryption certificate with serial {}", serial));
+                return false;
+            }
+        }
+        return true;
+    }
+    let passphrase = match std::env::var(PASSPHRASE_VARIABLE) {
+        Ok(passphrase) if !passphrase.is_empty() => passphrase,
+        _ => {
+            output::error(format!("Either certificate=<serial> or {} must be set", PASSPHRASE_VARIABLE));
+            return false;
+        }
+    };
+    let iterations = match argmap.get("iterations") {
+        Some(iterations) => match iterations.as_ref().and_then(|iterations| iterations.parse::<u32>().ok()) {
+            Some(iterations) if iterations > 0 => iterations,
+            _ => {
+                output::error("Argument 'iterations' must be a positive number");
+                return false;
+            }
+        },
+        None => DEFAULT_KDF_ITERATIONS,
+    };
+    println!("{}", encrypt_with_passphrase(value, &passphrase, iterations));
+    true
+}
+
+///
+/// Shows what migration of stores did or would do
+///
+fn show_migrations(reports: &[MigrationReport]){
+    let mut table = Table::new(vec!["STORE", "PATH", "FROM", "TO", "STEPS"]);
+    for report in reports{
+        let steps = if report.is_up_to_date() { "up to date".to_string() } else { report.steps.join("; ") };
+        table.add_row(vec![report.store, &report.path.display().to_string(), &format!("v{}", report.from_version),
+                           &format!("v{}", report.to_version), &steps]);
+    }
+    table.display();
+}
+
+///
+/// Explains code of error from catalog, lists all codes if none is given
+///
+/// returns: bool: false if code is unknown
+///
+fn explain_error(arguments: Vec<String>) -> bool{
+    let code = match arguments.first() {
+        Some(code) => code,
+        None => {
+            let mut table = Table::new(vec!["CODE", "SUMMARY"]);
+            for explanation in CATALOG{
+                table.add_row(vec![explanation.code, explanation.summary]);
+            }
+            table.display();
+            return true;
+        }
+    };
+    match explain(code) {
+        Some(explanation) => {
+            println!("{}: {}\n\n{}", explanation.code, explanation.summary, explanation.explanation);
+            true
+        }
+        None => {
+            output::error(format!("Unknown error code {}, `mway explain` lists all codes", code));
+            false
+        }
+    }
+}
+
+///
+/// Runs steps of graceful shutdown, e.g. flush of pending changes of certificates
+///
+/// returns: bool: whether every step completed, failed ones are reported
+///
+fn shut_down(shutdown: &mut ShutdownController) -> bool{
+    let report = shutdown.shutdown();
+    for failure in report.failures.iter(){
+        output::error(format!("Shutdown step {} failed: {}", failure.hook, failure.error));
+    }
+    report.is_clean()
+}
+
+///
+/// Loads certificates of this node joined with secret keys of separate key store if it exists
+///
+fn load_certificates(certificate_store_path: &Path, key_store_path: &Path) -> AsyncCertificateServiceImpl{
+    let mut certificates = AsyncCertificateServiceImpl::load_from_file(certificate_store_path.to_str().unwrap());
+    if key_store_path.exists(){
+        if let Err(error) = certificates.set_key_store(key_store_path, get_key_store_passphrase().as_deref()){
+            output::error(format!("can not read secret keys from {}: {}", key_store_path.display(), error));
+            exit(-1);
+        }
+    }
+    certificates
+}
+
+///
+/// Sends signed command to admin channel of local daemon and shows the answer
+///
+/// # Arguments
+/// * arguments: Vec<String>: command(`status`, `reload`, `reload-certificates`, `drain`,
+///   `log-level level=<level>` or `frame-stats [mode=on|off|show|reset]`), `signer=<serial>` of
+///   operator certificate and optionally `socket=<path>`
+/// * certificate_store_path: &Path: store with operator certificate
+/// * key_store_path: &Path: store with secret key of operator certificate if keys are kept apart
+/// * socket_path: &Path: admin socket from configuration
+///
+fn run_daemon_command(arguments: Vec<String>, certificate_store_path: &Path, key_store_path: &Path,
+                      socket_path: &Path) -> bool{
+    let command = match arguments.first().and_then(|name| AdminCommand::from_name(name)) {
+        Some(command) => command,
+        None => {
+            output::error("Command must be one of status, reload, reload-certificates, drain, log-level, frame-stats");
+            return false;
+        }
+    };
+    let argmap = parse_arguments(arguments[1..].to_vec());
+    let signer = match argmap.get("signer").cloned().flatten().and_then(|serial| serial.parse::<u128>().ok()) {
+        Some(serial) => serial,
+        None => {
+            output::error("Argument 'signer' must be a serial of operator certificate");
+            return false;
+        }
+    };
+    let argument = match command {
+        AdminCommand::SetLogLevel => argmap.get("level").cloned().flatten(),
+        AdminCommand::FrameStats => argmap.get("mode").cloned().flatten(),
+        _ => None,
+    };
+    if command == AdminCommand::SetLogLevel && argument.is_none(){
+        output::error("Argument 'level' is required");
+        return false;
+    }
+    let mut certificates = load_certificates(certificate_store_path, key_store_path);
+    let certificate = match certificates.get_signing_certificate(signer) {
+        Some(certificate) if certificate.get_secret_key().is_some() => certificate,
+        _ => {
+            output::error(format!("No signing certificate with secret key and serial {}", signer));
+            return false;
+        }
+    };
+    let mut request = AdminRequest::new(command, argument);
+    if let Err(error) = request.sign(&certificate){
+        output::error(format!("Can not sign command: {:?}", error));
+        return false;
+    }
+    let socket_path = match argmap.get("socket") {
+        Some(Some(path)) => PathBuf::from(path),
+        _ => socket_path.to_path_buf(),
+    };
+    let response = match send_admin_request(&socket_path, &request) {
+        Ok(response) => response,
+        Err(error) => {
+            output::error(format!("Can not reach daemon at {}: {}", socket_path.display(), error));
+            return false;
+        }
+    };
+    if let Some(error) = response.error{
+        output::error(error);
+        return false;
+    }
+    if let Some(status) = response.status{
+        let peers: Vec<String> = status.peers.iter().map(|peer| peer.to_string()).collect();
+        let mut table = Table::new(vec!["UPTIME", "PEERS", "DRAINING", "LOG LEVEL"]);
+        table.add_row(vec![&format!("{}s", status.uptime_seconds), &peers.join(", "),
+                           &status.is_draining.to_string(), &status.log_level]);
+        table.display();
+        let mut modules = Table::new(vec!["MODULE", "STATUS", "FAILURES", "RESTARTS", "LAST ERROR"]);
+        for module in status.modules.iter(){
+            modules.add_row(vec![&module.name, &module.health, &module.failures.to_string(),
+                                 &module.restarts.to_string(), module.last_error.as_deref().unwrap_or("")]);
+        }
+        modules.display();
+    }
+    if !response.message.is_empty(){
+        output::info(response.message);
+    }
+    true
+}
+
+///
+/// Inspects, replays and purges messages which repeatedly failed processing
+///
+/// # Arguments
+/// * arguments: Vec<String>: command(`list`, `stats`, `replay` or `purge`), `replay` and `purge`
+///   take optional `id=<id>` of letter and handle all letters without it
+/// * dead_letter_store_path: &Path: dead-letter queue of this node
+///
+fn run_dead_letter_command(arguments: Vec<String>, dead_letter_store_path: &Path) -> bool{
+    let argmap = parse_arguments(arguments.iter().skip(1).cloned().collect());
+    let id = match argmap.get("id") {
+        Some(id) => match id.as_ref().and_then(|id| id.parse::<u64>().ok()) {
+            Some(id) => Some(id),
+            None => {
+                output::error("Argument 'id' must be an ID of letter");
+                return false;
+            }
+        },
+        None => None,
+    };
+    let path = dead_letter_store_path.to_str().unwrap();
+    let mut queue = if dead_letter_store_path.exists(){
+        DeadLetterQueue::load_from_file(path)
+    } else {
+        DeadLetterQueue::new(path, DEFAULT_MAX_DELIVERY_FAILURES)
+    };
+    match arguments.first().map(|command| command.as_str()) {
+        Some("list") => {
+            let mut table = Table::new(vec!["ID", "MESSAGE", "SOURCE", "MODULE", "KIND", "FAILURES", "ERROR", "REPLAY"]);
+            for letter in queue.get_letters(){
+                table.add_row(vec![&letter.id.to_string(), &letter.message_id.to_string(), &letter.source.to_string(),
+                                   &letter.module_id.to_string(), letter.kind.get_name(),
+                                   &letter.failures.to_string(), &letter.error, &letter.replay_requested.to_string()]);
+            }
+            table.display();
+        }
+        Some("stats") => {
+            let stats = queue.get_stats();
+            let mut table = Table::new(vec!["DEPTH", "DEAD-LETTERED", "REPLAYED", "PURGED", "DROPPED"]);
+            table.add_row(vec![&queue.get_depth().to_string(), &stats.dead_lettered.to_string(),
+                               &stats.replayed.to_string(), &stats.purged.to_string(), &stats.dropped.to_string()]);
+            table.display();
+        }
+        Some("replay") => {
+            let count = queue.request_replay(id);
+            output::info(format!("{} letters will be delivered again once transport loads the queue", count));
+        }
+        Some("purge") => {
+            output::info(format!("Purged {} letters", queue.purge(id)));
+        }
+        _ => {
+            output::error("Command must be one of list, stats, replay, purge");
+            return false;
+        }
+    }
+    true
+}
+
+///
+/// Shows sequence counters of exactly-once delivery for monitoring
+///
+/// # Arguments
+/// * arguments: Vec<String>: optional `peer=<id>` to show only one peer
+/// * sequence_store_path: &Path: sequence store of this node
+///
+fn run_sequences_command(arguments: Vec<String>, sequence_store_path: &Path) -> bool{
+    let argmap = parse_arguments(arguments);
+    let peer = match argmap.get("peer") {
+        Some(Some(peer)) => match peer.parse::<PeerId>() {
+            Ok(peer) => Some(peer.get()),
+            Err(error) => {
+                output::coded_error(format!("Argument 'peer' is invalid: {}", error), &error);
+                return false;
+            }
+        },
+        Some(None) => {
+            output::error("Argument 'peer' requires a value");
+            return false;
+        }
+        None => None,
+    };
+    let path = sequence_store_path.to_str().unwrap();
+    let store = if sequence_store_path.exists(){
+        SequenceStore::load_from_file(path)
+    } else {
+        SequenceStore::new(path)
+    };
+    let mut table = Table::new(vec!["PEER", "SENT", "ACKNOWLEDGED", "RESENT", "DELIVERED", "DUPLICATES",
+                                    "GAPS", "LOST"]);
+    let mut add_row = |name: &str, stats: &SequenceStats| {
+        table.add_row(vec![name, &stats.sent.to_string(), &stats.acknowledged.to_string(),
+                           &stats.resent.to_string(), &stats.delivered.to_string(),
+                           &stats.duplicates.to_string(), &stats.gaps.to_string(), &stats.lost.to_string()]);
+    };
+    match peer {
+        Some(peer) => match store.get_peer_stats(peer) {
+            Some(stats) => add_row(&peer.to_string(), &stats),
+            None => {
+                output::error(format!("No sequenced messages were exchanged with peer {}", peer));
+                return false;
+            }
+        },
+        None => {
+            for peer in store.get_peers(){
+                add_row(&peer.to_string(), &store.get_peer_stats(peer).unwrap());
+            }
+            add_row("total", &store.get_stats());
+        }
+    }
+    table.display();
+    true
+}
+
+///
+/// Inspects and repairs certificate store offline, without loading certificate service
+///
+/// # Arguments
+/// * arguments: Vec<String>: command(`inspect` or `repair`), optional `file=<path>` of store and
+///   `output=<path>` of repaired store which `repair` requires
+/// * certificate_store_path: &Path: store of this node used if file is not given
+///
+///
+/// Converts certificate store of older schema, e.g. legacy one without version header, and shows
+/// converted certificates
+///
+/// # Arguments
+/// * path: &Path: store to convert
+/// * output: Option<String>: file to write converted store to, store is replaced in place if None
+/// * dry_run: bool: only show what would be converted
+///
+fn run_store_upgrade(path: &Path, output: Option<String>, dry_run: bool) -> bool{
+    let upgrade = match upgrade_certificate_store(path, output.as_deref().map(Path::new), dry_run) {
+        Ok(upgrade) => upgrade,
+        Err(MigrationError::ValidationFailed{ .. }) => {
+            output::error(format!("{} is damaged, salvage it with `certman store repair` first", path.display()));
+            return false;
+        }
+        Err(error) => {
+            output::error(format!("Can not upgrade store: {}", error));
+            return false;
+        }
+    };
+    if upgrade.is_up_to_date() && output.is_none(){
+        output::info(format!("{} already has schema v{}", path.display(), upgrade.to_version));
+        return true;
+    }
+    let mut table = Table::new(vec!["KIND", "SERIAL", "NAME"]);
+    for entry in upgrade.entries.iter(){
+        table.add_row(vec![entry.kind.get_name(), &entry.serial.to_string(), &entry.name]);
+    }
+    table.display();
+    let summary = format!("{} certificates and {} key usage records from schema v{} to v{}", upgrade.entries.len(),
+                          upgrade.usage_records, upgrade.from_version, upgrade.to_version);
+    if dry_run{
+        output::info(format!("Would convert {}", summary));
+        return true;
+    }
+    output::info(format!("Converted {}", summary));
+    match (&upgrade.backup, &output) {
+        (Some(backup), _) => output::info(format!("Backup of {} is kept at {}", path.display(), backup.display())),
+        (None, Some(output)) => output::info(format!("Converted store is written to {}, {} is left as is", output,
+                                                     path.display())),
+        (None, None) => {}
+    }
+    true
+}
+
+///
+/// Moves secret keys of certificate store to separate key store, encrypted with passphrase
+/// from KEY_STORE_PASSPHRASE_VARIABLE. Unencrypted key store is written only if asked explicitly.
+///
+/// # Arguments
+/// * path: &Path: certificate store with secret keys
+/// * key_store_path: &Path: key store to create
+/// * unencrypted: bool: whether key store may be written unencrypted if passphrase is not set
+///
+fn run_store_split_keys(path: &Path, key_store_path: &Path, unencrypted: bool) -> bool{
+    let passphrase = get_key_store_passphrase();
+    if passphrase.is_none() && !unencrypted{
+        output::error(format!("{} is not set, set it or pass 'unencrypted' to keep secret keys unencrypted",
+                              KEY_STORE_PASSPHRASE_VARIABLE));
+        return false;
+    }
+    match AsyncCertificateServiceImpl::split_key_store(path, key_store_path, passphrase.as_deref()) {
+        Ok((moved, backup)) => {
+            output::info(format!("Moved {} secret keys to {}, backup of {} is kept at {}", moved,
+                                 key_store_path.display(), path.display(), backup.display()));
+            if passphrase.is_none(){
+                output::warning(format!("Key store is not encrypted, set {} to encrypt it",
+                                        KEY_STORE_PASSPHRASE_VARIABLE));
+            }
+            true
+        }
+        Err(error) => {
+            output::error(format!("Can not split store: {}", error));
+            false
+        }
+    }
+}
+
+fn run_store_command(arguments: Vec<String>, certificate_store_path: &Path, key_store_path: &Path) -> bool{
+    let argmap = parse_arguments(arguments.iter().skip(1).cloned().collect());
+    let path = match argmap.get("file") {
+        Some(Some(file)) => PathBuf::from(file),
+        Some(None) => {
+            output::error("Argument 'file' requires a value");
+            return false;
+        }
+        None => certificate_store_path.to_path_buf(),
+    };
+    if arguments.first().is_some_and(|command| command == "upgrade"){
+        return run_store_upgrade(&path, argmap.get("output").cloned().flatten(), argmap.contains_key("dry-run"));
+    }
+    if arguments.first().is_some_and(|command| command == "split-keys"){
+        let key_store_path = match argmap.get("keys") {
+            Some(Some(keys)) => PathBuf::from(keys),
+            Some(None) => {
+                output::error("Argument 'keys' requires a value");
+                return false;
+            }
+            None => key_store_path.to_path_buf(),
+        };
+        return run_store_split_keys(&path, &key_store_path, argmap.contains_key("unencrypted"));
+    }
+    let inspection = match inspect_certificate_store(&path) {
+        Ok(inspection) => inspection,
+        Err(error) => {
+            output::error(format!("Can not inspect store: {}", error));
+            return false;
+        }
+    };
+    match arguments.first().map(|command| command.as_str()) {
+        Some("inspect") => {
+            let mut table = Table::new(vec!["KIND", "SERIAL", "NAME", "SIZE", "SECRET KEY", "CHAIN"]);
+            for entry in inspection.entries.iter(){
+                let chain = match &entry.chain_error {
+                    Some(error) => error.get_code().to_string(),
+                    None => "valid".to_string(),
+                };
+                table.add_row(vec![entry.kind.get_name(), &entry.serial.to_string(), &entry.name,
+                                   &entry.size.to_string(), &entry.has_secret_key.to_string(), &chain]);
+            }
+            table.display();
+            output::info(format!("{}: {} bytes, schema v{}, SHA-256 {}", path.display(), inspection.file_size,
+                                 inspection.schema_version, inspection.checksum));
+            output::info(format!("{} certificates, {} key usage records, {} policy rules, {} revocations",
+                                 inspection.entries.len(), inspection.usage_records, inspection.policy_rules,
+                                 inspection.revocations));
+            for error in inspection.errors.iter(){
+                output::error(error);
+            }
+            if inspection.trailing_bytes > 0{
+                output::error(format!("{} unexpected bytes follow the last section", inspection.trailing_bytes));
+            }
+            if inspection.is_intact(){
+                output::info("Store is intact");
+            }
+            inspection.is_intact()
+        }
+        Some("repair") => {
+            let output_path = match argmap.get("output") {
+                Some(Some(output_path)) => output_path,
+                _ => {
+                    output::error("Argument 'output' is required");
+                    return false;
+                }
+            };
+            if Path::new(output_path) == path{
+                output::error("Repaired store must be written to another file");
+                return false;
+            }
+            match inspection.write_repaired(output_path) {
+                Ok(_) => {
+                    output::info(format!("Salvaged {} certificates to {}, replace {} with it once checked",
+                                         inspection.entries.len(), output_path, path.display()));
+                    true
+                }
+                Err(error) => {
+                    output::error(format!("Can not write {}: {}", output_path, error));
+                    false
+                }
+            }
+        }
+        _ => {
+            output::error("Command must be one of inspect, repair, upgrade, split-keys");
+            false
+        }
+    }
+}
 
 fn main() {
     // Initialize tokio
     init_tokio();
 
+    // Output mode must be known before modules are loaded
+    let arguments = apply_output_options(std::env::args().collect());
+
+    // Resolve configuration path: --config, then MWAY_CONFIG, then per-user default
+    let resolver = PathResolver::from_environment();
+    let (arguments, configuration_argument) = take_config_option(arguments);
+    let configuration_path = resolver.resolve_configuration(configuration_argument.as_deref());
+
+    // Bootstrap a new node if requested, it does not require configuration
+    if arguments.len() > 1 && arguments[1] == "init"{
+        if !run_init(arguments[2..].to_vec(), &configuration_path.path, &resolver){
+            exit(-1);
+        }
+        exit(0);
+    }
+
+    // Protocol description is built into binary and does not depend on configuration
+    if arguments.len() > 2 && arguments[1] == "protocol" && arguments[2] == "dump"{
+        println!("{}", describe_protocol().to_json());
+        exit(0);
+    }
+
+    // Catalog of error codes is built into binary as well
+    if arguments.len() > 1 && arguments[1] == "explain"{
+        exit(if explain_error(arguments[2..].to_vec()) { 0 } else { -1 });
+    }
+
+    // Showing configuration must work even if configuration file is missing
+    if arguments.len() > 2 && arguments[1] == "config" && arguments[2] == "show"{
+        let configuration = if configuration_path.path.exists(){
+            CLIConfiguration::load(&configuration_path.path)
+        } else {
+            None
+        };
+        show_configuration(&configuration_path, configuration.as_ref(), &resolver);
+        exit(0);
+    }
+
     // Read configuration
-    let configuration = CLIConfiguration::load(Path::new("/tmp/mwayrc.yml"));
+    let configuration = CLIConfiguration::load(&configuration_path.path);
     if configuration.is_none(){
-        println!("{}:{}", "error".red().bold().underline(), " can not read configuration".clear());
+        output::error(format!("can not read configuration from {}", configuration_path.path.display()));
+        exit(-1);
+    }
+    let mut configuration = configuration.unwrap();
+    let storage_path = resolver.resolve_storage(configuration.get_storage_path()).path;
+    let certificate_store_path = storage_path.join(Path::new("certs.dat"));
+    let key_store_path = configuration.get_key_store_path().map(Path::to_path_buf)
+        .unwrap_or_else(|| storage_path.join(Path::new("keys.dat")));
+    let group_store_path = storage_path.join(Path::new("groups.dat"));
+    let access_store_path = storage_path.join(Path::new("access.dat"));
+    let pins_store_path = storage_path.join(Path::new("pins.dat"));
+    let state_store_path = storage_path.join(Path::new("state.dat"));
+    let dead_letter_store_path = storage_path.join(Path::new("deadletter.dat"));
+    let sequence_store_path = storage_path.join(Path::new("sequences.dat"));
+    let modules_path = resolver.resolve_modules(configuration.get_modules_path()).path;
+
+    // Damaged certificate store can not be migrated or loaded, so it is inspected first
+    if arguments.len() > 2 && arguments[1] == "certman" && arguments[2] == "store"{
+        exit(if run_store_command(arguments[3..].to_vec(), &certificate_store_path, &key_store_path) { 0 } else { -1 });
+    }
+
+    // Stores are migrated before they are loaded
+    let mut migrator = Migrator::new();
+    migrator.register::<AsyncCertificateServiceImpl>(&certificate_store_path)
+        .register::<GroupServiceImpl>(&group_store_path)
+        .register::<AccessControl>(&access_store_path)
+        .register::<PeerPins>(&pins_store_path)
+        .register::<ModuleStateStore>(&state_store_path)
+        .register::<DeadLetterQueue>(&dead_letter_store_path)
+        .register::<SequenceStore>(&sequence_store_path);
+    if arguments.len() > 2 && arguments[1] == "storage" && arguments[2] == "migrate"{
+        let dry_run = arguments[3..].iter().any(|argument| argument == "--dry-run");
+        match migrator.run(dry_run) {
+            Ok(reports) => show_migrations(&reports),
+            Err(error) => {
+                output::error(error);
+                exit(-1);
+            }
+        }
+        exit(0);
+    }
+    match migrator.run(false) {
+        Ok(reports) => {
+            for report in reports.iter().filter(|report| !report.is_up_to_date()){
+                output::info(format!("Migrated {} storage from v{} to v{}, backup is kept at {}", report.store,
+                                     report.from_version, report.to_version,
+                                     report.backup.as_ref().unwrap().display()));
+            }
+        }
+        Err(error) => {
+            output::error(format!("can not migrate storage: {}", error));
+            exit(-1);
+        }
+    }
+
+    // Values for configuration are encrypted with keys of this node, so storage must be known
+    if arguments.len() > 2 && arguments[1] == "config" && arguments[2] == "encrypt-value"{
+        exit(if encrypt_value(arguments[3..].to_vec(), &certificate_store_path) { 0 } else { -1 });
+    }
+
+    // Daemon is managed with operator certificate from storage, modules are not needed
+    if arguments.len() > 1 && arguments[1] == "daemon"{
+        exit(if run_daemon_command(arguments[2..].to_vec(), &certificate_store_path, &key_store_path,
+                                   configuration.get_admin_socket_path()) { 0 } else { -1 });
+    }
+
+    // Dead letters are kept in storage, modules are not needed
+    if arguments.len() > 1 && arguments[1] == "deadletter"{
+        exit(if run_dead_letter_command(arguments[2..].to_vec(), &dead_letter_store_path) { 0 } else { -1 });
+    }
+
+    // Sequence counters are kept in storage, modules are not needed
+    if arguments.len() > 1 && arguments[1] == "sequences"{
+        exit(if run_sequences_command(arguments[2..].to_vec(), &sequence_store_path) { 0 } else { -1 });
+    }
+
+    // Decrypt secrets of configuration before anything uses them
+    let mut secrets = SecretResolver::from_environment();
+    if certificate_store_path.exists(){
+        secrets.add_certificates_from(&mut load_certificates(&certificate_store_path, &key_store_path));
+    }
+    if let Err(error) = configuration.resolve_secrets(&secrets){
+        output::error(format!("can not decrypt configuration value {}", error));
         exit(-1);
     }
-    let configuration = configuration.unwrap();
-    let storage_path_option = configuration.get_storage_path();
-    if storage_path_option.is_none(){
-        println!("{}:{}", "error".red().bold().underline(), " no storage_path in configuration".clear());
-    }
-    let storage_path = storage_path_option.unwrap();
-    let binding = storage_path.join(Path::new("certs.dat"));
-    let certificate_store_path = binding.as_path();
-    let modules_path_option = configuration.get_modules_path();
-    let modules_path = if modules_path_option.is_none(){
-        Path::new("/opt/mway/lib/modules")
-    } else {
-        modules_path_option.unwrap()
-    };
 
-    // Load modules
-    let mut modules: Vec<DynamicModule>;
+    // Load modules, built-in ones go first, so libraries of the same modules are refused
+    let mut registry = ModuleRegistry::new();
+    for module in get_builtin_modules(){
+        if let Err(error) = registry.register_builtin(module){
+            output::warning(format!("Built-in module is not loaded: {}", error));
+        }
+    }
+    let modules: Vec<(String, DynamicModule)>;
     unsafe {
-        modules = load_modules_from(modules_path);
+        modules = load_modules_from(&modules_path);
+    }
+    for (name, module) in modules{
+        if let Err(error) = registry.register_dynamic(&name, module){
+            output::warning(format!("Module is not loaded: {}", error));
+        }
     }
 
     // Create data bus
     // It will also start services
-    let data_bus = CLIDataBus::new(certificate_store_path.to_str().unwrap());
+    let host_id = configuration.get_identity().map_or(LOCAL_HOST_ID, |(id, _, _)| id);
+    let mut data_bus = CLIDataBus::new(host_id, certificate_store_path.to_str().unwrap(), &key_store_path,
+                                       group_store_path.to_str().unwrap(),
+                                       access_store_path.to_str().unwrap(),
+                                       pins_store_path.to_str().unwrap(),
+                                       state_store_path.to_str().unwrap());
+    data_bus.set_certificate_profiles(configuration.get_certificate_profiles());
+    data_bus.set_certificate_pool_size(configuration.get_certificate_pool_size());
+    data_bus.set_name_resolver(configuration.get_name_resolver());
+    if configuration.is_read_only(){
+        data_bus.get_certificate_service().set_read_only(true);
+    }
+    if let Some(serial) = configuration.get_operator_serial(){
+        let certificate = data_bus.get_certificate_service().get_signing_certificate(serial);
+        match certificate.map(OperatorIdentity::new) {
+            Some(Ok(operator)) => {
+                data_bus.set_operator(operator);
+            }
+            Some(Err(error)) => output::warning(format!("Messages will not be signed: {}", error)),
+            None => output::warning(format!("Messages will not be signed: no operator certificate {}", serial)),
+        }
+    }
+    // Tapping messages is a capability of operator certificate
+    let transport_tap = configuration.get_transport_tap().and_then(|(capacity, payloads)| {
+        let flags = data_bus.get_operator().map(|operator| operator.get_certificate().get_flags()).unwrap_or(0);
+        let tap = TransportTap::new_shared(capacity, flags)?;
+        tap.lock().unwrap().set_record_payloads(payloads);
+        Some(tap)
+    });
+    data_bus.set_transport_tap(transport_tap.clone());
+    if let Some(thresholds) = configuration.get_usage_thresholds(){
+        let mut certificates = data_bus.get_certificate_service();
+        certificates.set_usage_thresholds(thresholds);
+        certificates.commit();
+    }
+    // Changes of certificates queued by modules are flushed once CLI exits
+    let mut shutdown = ShutdownController::new();
+    shutdown.register(ShutdownStage::Flush, Box::new(CertificateFlushHook::new(data_bus.get_certificate_service())));
+    let (default_quota, quotas) = configuration.get_module_state_quotas();
+    let module_state = data_bus.get_module_state_store();
+    module_state.lock().unwrap().set_quotas(default_quota, quotas);
+    // Without server messages are only delivered between modules of CLI
+    if configuration.get_server().is_some(){
+        if let Err(error) = connect_to_server(&mut data_bus, &configuration){
+            output::warning(format!("Working offline: {}", error));
+        }
+    }
 
     //Now tell all modules they are loaded
-    for module in &mut modules{
-        module.instance.on_load(Box::new(data_bus.clone()));
+    // Modules are supervised, so panic inside of module does not take CLI down
+    let data_bus_provider: DataBusProvider = Arc::new(move || Box::new(data_bus.clone()) as Box<dyn ModuleDataBus>);
+    let restart_policy = configuration.get_restart_policy();
+    let mut supervised = Vec::<SupervisedModule>::new();
+    for mut module in registry.into_modules(){
+        module.set_restart_policy(restart_policy.clone());
+        if !module.load(data_bus_provider.clone()){
+            output::error(format!("module {} panicked while loading: {}", module.get_status().name,
+                                  module.get_status().last_error.clone().unwrap_or_default()));
+        }
+        supervised.push(module);
     }
 
     // Create a CLI controller
-    let mut controller = CLIController::new(modules);
+    let mut controller = CLIController::new(supervised);
+    controller.set_module_state(module_state);
+    controller.set_aliases(configuration.get_aliases());
+    controller.set_transport_tap(transport_tap);
 
     // Check arguments
-    let arguments: Vec<String> = std::env::args().collect();
     let arguments = arguments[1..].to_vec();
     if arguments.len() > 0{
         // Execute command provided
         let result = controller.handle_command(arguments[0].clone(), arguments[1..].to_vec().clone());
-        if !result{
+        if !shut_down(&mut shutdown) || !result{
             exit(-1);
         }
         exit(0);
@@ -111,4 +884,7 @@ fn main() {
 
     // No arguments were provided => start interactive shell
     controller.run();
+    if !shut_down(&mut shutdown){
+        exit(-1);
+    }
 }
@@ -0,0 +1,75 @@
+use std::os::unix::net::UnixStream;
+#[cfg(feature = "wasm")]
+use std::path::Path;
+use std::process::exit;
+use libmilkyway::module::isolated::run_isolated_module;
+use libmilkyway::module::MilkywayModule;
+use libmilkyway::module::loader::DynamicModule;
+use libmilkyway::tokio::init_tokio;
+#[cfg(feature = "wasm")]
+use libmilkyway_wasm::module::WasmModule;
+
+///
+/// Runs one module out of daemon process.
+///
+/// Usage: milkywaymodrunner --socket <path> --module <path>
+///
+fn main() {
+    init_tokio();
+    env_logger::init();
+    let arguments: Vec<String> = std::env::args().collect();
+    let get_argument = |name: &str| {
+        arguments.iter().position(|argument| argument == name)
+            .and_then(|index| arguments.get(index + 1))
+            .cloned()
+    };
+    let (socket, module_path) = match (get_argument("--socket"), get_argument("--module")) {
+        (Some(socket), Some(module_path)) => (socket, module_path),
+        _ => {
+            eprintln!("Usage: {} --socket <path> --module <path>", arguments[0]);
+            exit(2);
+        }
+    };
+    let stream = match UnixStream::connect(&socket) {
+        Ok(stream) => stream,
+        Err(error) => {
+            log::error!("Can not connect to {}: {}", socket, error);
+            exit(1);
+        }
+    };
+    // Portable modules are run by WASM runtime, native ones are loaded as libraries
+    let mut portable = None;
+    let mut native = None;
+    let module: &mut dyn MilkywayModule = if module_path.ends_with(".wasm") {
+        load_portable(&module_path, &mut portable)
+    } else {
+        match unsafe { DynamicModule::load(&module_path) } {
+            Ok(module) => native.insert(module).instance.as_mut(),
+            Err(error) => {
+                log::error!("Can not load module {}: {}", module_path, error);
+                exit(1);
+            }
+        }
+    };
+    if let Err(error) = run_isolated_module(module, stream) {
+        log::error!("Module runner failed: {}", error);
+        exit(1);
+    }
+}
+
+#[cfg(feature = "wasm")]
+fn load_portable<'a>(module_path: &str, portable: &'a mut Option<WasmModule>) -> &'a mut dyn MilkywayModule{
+    match WasmModule::load(Path::new(module_path)) {
+        Ok(module) => portable.insert(module),
+        Err(error) => {
+            log::error!("Can not load WASM module {}: {}", module_path, error);
+            exit(1);
+        }
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+fn load_portable<'a>(module_path: &str, _portable: &'a mut Option<()>) -> &'a mut dyn MilkywayModule{
+    log::error!("Can not load WASM module {}: module runner is built without `wasm` feature", module_path);
+    exit(1);
+}
@@ -0,0 +1,199 @@
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use libmilkyway::actor::binder::BinderChannelProvider;
+use libmilkyway::actor::binder::pool::DEFAULT_BINDER_POOL_SIZE;
+use libmilkyway::module::{HostType, ModuleDataBus};
+use libmilkyway::module::state::{ModuleState, ModuleStateStore, SharedModuleStateStore};
+use libmilkyway::services::certificate::{CertificateAsyncService, CertificateServiceBinder, CertificateServicePool};
+use libmilkyway::services::certificate::detached::DetachedCertificateService;
+use libmilkyway::services::certificate::keystore::get_key_store_passphrase;
+use libmilkyway::services::certificate::readonly::ReadOnlyCertificateService;
+use libmilkyway::services::group::SharedGroupService;
+use libmilkyway::services::impls::certificate::AsyncCertificateServiceImpl;
+use libmilkyway::services::impls::group::GroupServiceImpl;
+use libmilkyway::services::impls::transport::LocalTransportService;
+use libmilkyway::services::name::NameService;
+use libmilkyway::services::name::resolver::{NameResolver, ResolverNameService, SharedNameResolver};
+use libmilkyway::services::transport::TransportService;
+use libmilkyway::tokio::{init_tokio, tokio_block_on};
+use libmilkyway::transport::access::{AccessControl, SharedAccessControl};
+use libmilkyway::transport::pinning::{PeerPins, SharedPeerPins};
+
+///
+/// Runs certificate service on its own thread, so it keeps answering binders while main
+/// thread is blocked on listener
+///
+fn start_certificate_service(certificate_storage: PathBuf, key_storage: PathBuf,
+                             read_only: bool) -> CertificateAsyncService{
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        init_tokio();
+        let storage = certificate_storage.to_str().unwrap();
+        let mut service_impl = if certificate_storage.exists(){
+            AsyncCertificateServiceImpl::load_from_file(storage)
+        } else {
+            AsyncCertificateServiceImpl::new(storage)
+        };
+        // Secret keys are kept apart only once store is split
+        if key_storage.exists(){
+            service_impl.set_key_store(&key_storage, get_key_store_passphrase().as_deref())
+                .expect("Failed to load secret key storage");
+        }
+        let service = Box::new(ReadOnlyCertificateService::new(service_impl, read_only));
+        sender.send(CertificateAsyncService::run(service)).expect("Daemon is gone");
+        tokio_block_on(std::future::pending::<()>());
+    });
+    receiver.recv().expect("Certificate service failed to start")
+}
+
+///
+/// A DataBus for server daemon
+///
+#[derive(Clone)]
+pub struct ServerDataBus{
+    certificate_service: Arc<Mutex<CertificateAsyncService>>,
+    /** Binders in pool of each module **/
+    certificate_pool_size: usize,
+    group_service: SharedGroupService,
+    access_control: SharedAccessControl,
+    peer_pins: SharedPeerPins,
+    module_state: SharedModuleStateStore,
+    /** Messages to other hosts are passed to router once it is set as remote sender **/
+    transport_service: LocalTransportService,
+    name_service: ResolverNameService,
+    loaded_modules: Arc<Mutex<Vec<String>>>,
+    listener_endpoints: Vec<String>,
+}
+
+impl ServerDataBus{
+    pub fn new(host_id: u128, storage_path: &Path, key_storage: &Path, read_only: bool) -> ServerDataBus{
+        let service = start_certificate_service(storage_path.join("certs.dat"), key_storage.to_path_buf(),
+                                                read_only);
+        let group_storage = storage_path.join("groups.dat");
+        let group_service = if group_storage.exists(){
+            GroupServiceImpl::load_from_file(group_storage.to_str().unwrap())
+        } else {
+            GroupServiceImpl::new(group_storage.to_str().unwrap())
+        };
+        let group_service: SharedGroupService = Arc::new(Mutex::new(group_service));
+        let access_control = AccessControl::open_shared(storage_path.join("access.dat").to_str().unwrap());
+        let mut transport_service = LocalTransportService::new(host_id);
+        transport_service.set_group_service(group_service.clone());
+        transport_service.set_access_control(access_control.clone());
+        ServerDataBus{
+            certificate_service: Arc::new(Mutex::new(service)),
+            certificate_pool_size: DEFAULT_BINDER_POOL_SIZE,
+            group_service,
+            access_control,
+            peer_pins: PeerPins::open_shared(storage_path.join("pins.dat").to_str().unwrap()),
+            module_state: ModuleStateStore::open_shared(storage_path.join("state.dat").to_str().unwrap()),
+            transport_service,
+            name_service: ResolverNameService::new(NameResolver::new_shared("")),
+            loaded_modules: Arc::new(Mutex::new(Vec::new())),
+            listener_endpoints: Vec::new(),
+        }
+    }
+
+    ///
+    /// Sets number of binders to certificate service in pool of each module
+    ///
+    pub fn set_certificate_pool_size(&mut self, size: usize) -> &mut Self{
+        self.certificate_pool_size = size;
+        self
+    }
+
+    ///
+    /// Sets resolver of peer names from configuration
+    ///
+    pub fn set_name_resolver(&mut self, resolver: NameResolver) -> &mut Self{
+        self.name_service = ResolverNameService::new(Arc::new(Mutex::new(resolver)));
+        self
+    }
+
+    ///
+    /// Sets addresses daemon accepts connections on
+    ///
+    pub fn set_listener_endpoints(&mut self, endpoints: Vec<String>) -> &mut Self{
+        self.listener_endpoints = endpoints;
+        self
+    }
+
+    ///
+    /// Sets names of modules reported to modules and admin clients
+    ///
+    pub fn set_loaded_modules(&self, modules: Vec<String>){
+        *self.loaded_modules.lock().unwrap() = modules;
+    }
+
+    ///
+    /// Gets transport service of daemon to configure it before modules are loaded. Clones
+    /// share subscriptions and policies.
+    ///
+    #[inline]
+    pub fn get_local_transport(&mut self) -> &mut LocalTransportService{
+        &mut self.transport_service
+    }
+
+    #[inline]
+    pub fn get_name_resolver(&self) -> SharedNameResolver{
+        self.name_service.get_resolver()
+    }
+
+    ///
+    /// Creates certificate service usable from coroutines and threads without runtime
+    ///
+    pub fn get_detached_certificate_service(&self) -> DetachedCertificateService{
+        let service = self.certificate_service.clone();
+        DetachedCertificateService::spawn(move || service.lock().unwrap().bind())
+    }
+}
+
+impl ModuleDataBus for ServerDataBus{
+    fn get_transport_service(&self) -> Box<dyn TransportService> {
+        Box::new(self.transport_service.clone())
+    }
+
+    fn get_name_service(&self) -> Box<dyn NameService> {
+        Box::new(self.name_service.clone())
+    }
+
+    fn get_certificate_service(&self) -> Box<CertificateServiceBinder> {
+        self.certificate_service.lock().unwrap().bind()
+    }
+
+    fn get_certificate_pool(&self) -> CertificateServicePool {
+        CertificateServicePool::new(&mut *self.certificate_service.lock().unwrap(), self.certificate_pool_size)
+    }
+
+    fn get_host_type(&self) -> HostType {
+        HostType::Broker
+    }
+
+    fn get_host_id(&self) -> Option<u128> {
+        Some(self.transport_service.get_host_id())
+    }
+
+    fn get_group_service(&self) -> Option<SharedGroupService> {
+        Some(self.group_service.clone())
+    }
+
+    fn get_access_control(&self) -> Option<SharedAccessControl> {
+        Some(self.access_control.clone())
+    }
+
+    fn get_peer_pins(&self) -> Option<SharedPeerPins> {
+        Some(self.peer_pins.clone())
+    }
+
+    fn get_module_state(&self, module_id: u64) -> Option<ModuleState> {
+        Some(ModuleState::new(module_id, self.module_state.clone()))
+    }
+
+    fn get_loaded_modules(&self) -> Vec<String> {
+        self.loaded_modules.lock().unwrap().clone()
+    }
+
+    fn get_listener_endpoints(&self) -> Vec<String> {
+        self.listener_endpoints.clone()
+    }
+}
@@ -1,6 +1,180 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use colored::Colorize;
 use yaml_rust2::{Yaml, YamlLoader};
+use libmilkyway::actor::binder::pool::DEFAULT_BINDER_POOL_SIZE;
+use libmilkyway::controllers::admin::DEFAULT_ADMIN_SOCKET_PATH;
+use libmilkyway::controllers::gateway::{GatewayToken, DEFAULT_GATEWAY_ADDRESS};
+use libmilkyway::controllers::authorization::factor::{decode_base32, AuthenticationFactor, ExternalCommandFactor,
+                                                     OsUserFactor, TotpFactor, DEFAULT_TOTP_DIGITS, DEFAULT_TOTP_STEP};
+use libmilkyway::message::types::MessageType;
+use libmilkyway::module::isolation::{IsolationPolicy, ModuleIsolation};
+use libmilkyway::module::supervisor::RestartPolicy;
+use libmilkyway::peer::{PeerId, PeerIdError};
+use libmilkyway::secrets::SecretResolver;
+use libmilkyway::serialization::deserializable::{Deserializable, ParsingMode};
+use libmilkyway::serialization::schema::{DefinitionSchema, SchemaRegistry};
+use libmilkyway::services::certificate::gc::CertificateGcPolicy;
+use libmilkyway::services::certificate::remote::RemoteCertificatePolicy;
+use libmilkyway::services::name::dns::{DnsNameBackend, UdpDnsLookup};
+use libmilkyway::services::name::exchange::PeerExchangeNameBackend;
+use libmilkyway::services::name::resolver::{NameResolver, StaticNameBackend};
+use libmilkyway::trace::{FileSpanExporter, OtlpSpanExporter, SpanExporter};
+use libmilkyway::transport::checksum::ChecksumMode;
+use libmilkyway::transport::compression::{CompressionAlgorithm, CompressionPolicy};
+use libmilkyway::transport::connector::{ConnectionManager, ProxyConfig};
+use libmilkyway::transport::crypto::DEFAULT_CRYPTO_FAILURE_THRESHOLD;
+use libmilkyway::transport::handshake::{HandshakeStage, HandshakeTimeouts};
+use libmilkyway::transport::identity::{ExpectedIdentities, ExpectedIdentity, IdentityMode};
+use libmilkyway::transport::keepalive::KeepAlivePolicy;
+use libmilkyway::transport::ratelimit::{QuotaAction, QuotaLimits, RateLimitPolicy};
+use libmilkyway::transport::shaping::{BandwidthLimits, ShapingLimits};
+use libmilkyway::transport::signature::{SignaturePolicy, DEFAULT_SIGNATURE_AUDIT_CAPACITY};
+use libmilkyway::transport::version::VersionPolicy;
+use libmilkyway::transport::TRANSPORT_TARGET_SERVER;
+
+///
+/// Parses quota limits from yaml, missing values mean no limit
+///
+fn parse_quota_limits(yaml: &Yaml) -> Option<QuotaLimits>{
+    yaml.as_hash()?;
+    let get_value = |key: &str| yaml[key].as_i64().map_or(0, |value| value.max(0) as u64);
+    Some(QuotaLimits{
+        messages_per_second: get_value("messages_per_second"),
+        message_burst: get_value("message_burst"),
+        bytes_per_second: get_value("bytes_per_second"),
+        byte_burst: get_value("byte_burst"),
+    })
+}
+
+///
+/// Parses ID of peer given as integer or string(for IDs not fitting into i64)
+///
+fn parse_peer_id(yaml: &Yaml) -> Result<PeerId, PeerIdError>{
+    match yaml {
+        Yaml::Integer(id) => PeerId::try_from(*id),
+        Yaml::String(id) => id.parse(),
+        yaml => Err(PeerIdError::Invalid(format!("{:?}", yaml))),
+    }
+}
+
+///
+/// Parses bandwidth cap from yaml, missing burst means burst equal to rate
+///
+fn parse_bandwidth_limits(yaml: &Yaml) -> Option<BandwidthLimits>{
+    let bytes_per_second = yaml["bytes_per_second"].as_i64()?.max(0) as u64;
+    Some(BandwidthLimits{
+        bytes_per_second,
+        burst: yaml["burst"].as_i64().map_or(0, |value| value.max(0) as u64),
+    })
+}
+
+///
+/// Parses an ID which may be written both as integer and as string(for IDs not fitting into i64)
+///
+fn parse_id(yaml: &Yaml) -> Option<u128>{
+    match yaml {
+        Yaml::Integer(value) => u128::try_from(*value).ok(),
+        Yaml::String(value) => value.parse().ok(),
+        _ => None,
+    }
+}
+
+///
+/// Parses message type by name of its variant, e.g. `Exec`
+///
+fn parse_message_type(yaml: &Yaml) -> Option<MessageType>{
+    let name = yaml.as_str()?;
+    let mut registry = SchemaRegistry::new();
+    registry.add::<MessageType>();
+    let tag = match registry.get_definition("MessageType") {
+        Some(DefinitionSchema::Enum(variants)) => variants.iter().find(|variant| variant.name == name)?.tag,
+        _ => return None,
+    };
+    MessageType::from_serialized(&vec![tag]).ok().map(|(message_type, _)| message_type)
+}
+
+///
+/// Parses module isolation mode
+///
+fn parse_isolation(yaml: &Yaml) -> Option<ModuleIsolation>{
+    match yaml.as_str()? {
+        "in-process" => Some(ModuleIsolation::InProcess),
+        "isolated" => Some(ModuleIsolation::Isolated),
+        other => {
+            println!("{}: Unknown module isolation '{}'", "error".red().bold().underline(), other);
+            None
+        }
+    }
+}
+
+///
+/// Parses one entry of `authentication.factors`
+///
+fn parse_authentication_factor(yaml: &Yaml) -> Option<Box<dyn AuthenticationFactor>>{
+    match yaml["type"].as_str()? {
+        "totp" => {
+            let step = yaml["step"].as_i64().map(|step| step as u64).unwrap_or(DEFAULT_TOTP_STEP);
+            let digits = yaml["digits"].as_i64().map(|digits| digits as u32).unwrap_or(DEFAULT_TOTP_DIGITS);
+            let skew = yaml["skew"].as_i64().map(|skew| skew as u64).unwrap_or(1);
+            let mut factor = match TotpFactor::new(step, digits, skew) {
+                Ok(factor) => factor,
+                Err(error) => {
+                    println!("{}: {}", "error".red().bold().underline(), error);
+                    return None;
+                }
+            };
+            for (serial, secret) in yaml["secrets"].as_hash()?.iter(){
+                match (parse_id(serial), secret.as_str().and_then(decode_base32)) {
+                    (Some(serial), Some(secret)) => {
+                        factor.add_secret(serial, secret);
+                    }
+                    _ => println!("{}: Invalid TOTP secret", "error".red().bold().underline()),
+                }
+            }
+            Some(Box::new(factor))
+        }
+        "command" => {
+            let arguments = yaml["arguments"].as_vec()
+                .map(|arguments| arguments.iter().filter_map(|argument| argument.as_str())
+                    .map(|argument| argument.to_string()).collect())
+                .unwrap_or_default();
+            let mut factor = ExternalCommandFactor::new(yaml["command"].as_str()?, arguments);
+            if let Some(timeout) = yaml["timeout"].as_i64(){
+                factor.set_timeout(Duration::from_secs(timeout as u64));
+            }
+            Some(Box::new(factor))
+        }
+        other => {
+            println!("{}: Unknown authentication factor '{}'", "error".red().bold().underline(), other);
+            None
+        }
+    }
+}
+
+///
+/// Replaces encrypted string values(`enc:...`) with decrypted ones, path is used in errors
+///
+fn resolve_yaml_secrets(yaml: &mut Yaml, resolver: &SecretResolver, path: &str) -> Result<(), String>{
+    match yaml {
+        Yaml::String(value) => {
+            *value = resolver.resolve(value).map_err(|error| format!("{}: {}", path, error))?;
+        }
+        Yaml::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate(){
+                resolve_yaml_secrets(item, resolver, &format!("{}[{}]", path, index))?;
+            }
+        }
+        Yaml::Hash(items) => {
+            for (key, item) in items.iter_mut(){
+                let key = key.as_str().map_or_else(|| format!("{:?}", key), |key| key.to_string());
+                resolve_yaml_secrets(item, resolver, &if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) })?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
 
 ///
 /// A configuration data for server
@@ -31,17 +205,27 @@ impl ServerConfiguration {
         })
     }
 
+    ///
+    /// Decrypts encrypted values, e.g. TOTP secrets. Paths to storage and modules are used
+    /// to find keys, so they must not be encrypted.
+    ///
+    /// returns: Result<(), String>: error with path of value which can not be decrypted
+    ///
+    pub fn resolve_secrets(&mut self, resolver: &SecretResolver) -> Result<(), String>{
+        for document in self.config_yaml.iter_mut(){
+            resolve_yaml_secrets(document, resolver, "")?;
+        }
+        Ok(())
+    }
+
     ///
     /// Gets a path to the storage
     ///
     /// returns: Option<&Path>: path to a storage directory
     ///
     pub fn get_storage_path(&self) -> Option<&Path>{
-        let str_path = self.config_yaml[0]["storage_path"].as_str();
-        if str_path.is_none(){
-            return None;
-        }
-        Some(Path::new(str_path.unwrap()))
+        let str_path = self.config_yaml[0]["storage_path"].as_str()?;
+        Some(Path::new(str_path))
     }
 
     ///
@@ -50,11 +234,8 @@ impl ServerConfiguration {
     /// returns: Option<&Path>: path to a storage directory
     ///
     pub fn get_modules_path(&self) -> Option<&Path>{
-        let str_path = self.config_yaml[0]["modules_path"].as_str();
-        if str_path.is_none(){
-            return None;
-        }
-        Some(Path::new(str_path.unwrap()))
+        let str_path = self.config_yaml[0]["modules_path"].as_str()?;
+        Some(Path::new(str_path))
     }
     
     ///
@@ -63,6 +244,693 @@ impl ServerConfiguration {
     /// returns: Option<String>: a listener bind address
     ///
     pub fn get_listener_address(&self) -> Option<String>{
-        todo!()
+        self.config_yaml[0]["listener"]["address"].as_str().map(|address| address.to_string())
+    }
+
+    ///
+    /// Gets ID of this host in network from `identity` section
+    ///
+    /// returns: u128: `id` or TRANSPORT_TARGET_SERVER if it is not set
+    ///
+    pub fn get_host_id(&self) -> u128{
+        parse_id(&self.config_yaml[0]["identity"]["id"]).unwrap_or(TRANSPORT_TARGET_SERVER)
+    }
+
+    ///
+    /// Gets serials of certificates daemon presents to peers(`signing_serial` and
+    /// `encryption_serial` of `identity` section), both must be kept with secret keys
+    ///
+    /// returns: Option<(u128, u128)>: signing and encryption serials or None if not set
+    ///
+    pub fn get_identity_serials(&self) -> Option<(u128, u128)>{
+        let section = &self.config_yaml[0]["identity"];
+        Some((parse_id(&section["signing_serial"])?, parse_id(&section["encryption_serial"])?))
+    }
+
+    ///
+    /// Gets rate limiting policy from `rate_limits` section
+    ///
+    /// returns: Option<RateLimitPolicy>: policy or None if rate limiting is not configured
+    ///
+    pub fn get_rate_limit_policy(&self) -> Option<RateLimitPolicy>{
+        let section = &self.config_yaml[0]["rate_limits"];
+        section.as_hash()?;
+        let action = match section["action"].as_str().unwrap_or("drop") {
+            "drop" => QuotaAction::Drop,
+            "deprioritize" => QuotaAction::Deprioritize,
+            "disconnect" => QuotaAction::Disconnect,
+            other => {
+                println!("{}: Unknown rate limit action '{}'", "error".red().bold().underline(), other);
+                return None;
+            }
+        };
+        let mut policy = RateLimitPolicy::new(action);
+        policy.peer_limits = parse_quota_limits(&section["peer"]);
+        policy.module_limits = parse_quota_limits(&section["module"]);
+        if let Some(peers) = section["peers"].as_hash(){
+            for (id, limits) in peers.iter(){
+                match (parse_peer_id(id), parse_quota_limits(limits)) {
+                    (Ok(id), Some(limits)) => {
+                        policy.peer_overrides.insert(id.get(), limits);
+                    }
+                    (Err(error), _) => println!("{}: Rate limits of peer: {}", "error".red().bold().underline(), error),
+                    _ => {}
+                }
+            }
+        }
+        if let Some(modules) = section["modules"].as_hash(){
+            for (id, limits) in modules.iter(){
+                let id = parse_id(id).and_then(|id| u64::try_from(id).ok());
+                if let (Some(id), Some(limits)) = (id, parse_quota_limits(limits)){
+                    policy.module_overrides.insert(id, limits);
+                }
+            }
+        }
+        Some(policy)
+    }
+
+    ///
+    /// Gets policy of signatures on received messages from `signatures` section: `modules`(IDs)
+    /// and `types`(names of message types) which messages must be signed, signing certificates
+    /// bound to peers besides ones they authorized with(`peers`, lists of serials by peer ID)
+    /// and `allow_unbound_peers`
+    ///
+    /// returns: Option<SignaturePolicy>: policy or None if signatures are not required
+    ///
+    pub fn get_signature_policy(&self) -> Option<SignaturePolicy>{
+        let section = &self.config_yaml[0]["signatures"];
+        section.as_hash()?;
+        let capacity = section["audit_capacity"].as_i64().map_or(DEFAULT_SIGNATURE_AUDIT_CAPACITY,
+                                                                   |capacity| capacity.max(1) as usize);
+        let mut policy = SignaturePolicy::new(capacity);
+        for module_id in section["modules"].as_vec().into_iter().flatten(){
+            match parse_id(module_id).and_then(|id| u64::try_from(id).ok()) {
+                Some(module_id) => {
+                    policy.require_module(module_id);
+                }
+                None => println!("{}: Invalid module ID {:?}", "error".red().bold().underline(), module_id),
+            }
+        }
+        for message_type in section["types"].as_vec().into_iter().flatten(){
+            match parse_message_type(message_type) {
+                Some(message_type) => {
+                    policy.require_type(message_type);
+                }
+                None => println!("{}: Unknown message type {:?}", "error".red().bold().underline(), message_type),
+            }
+        }
+        if let Some(peers) = section["peers"].as_hash(){
+            for (id, serials) in peers.iter(){
+                let id = match parse_peer_id(id) {
+                    Ok(id) => id,
+                    Err(error) => {
+                        println!("{}: Certificates of peer: {}", "error".red().bold().underline(), error);
+                        continue;
+                    }
+                };
+                for serial in serials.as_vec().into_iter().flatten().filter_map(parse_id){
+                    policy.bind_peer_certificate(id.get(), serial);
+                }
+            }
+        }
+        policy.set_allow_unbound_peers(section["allow_unbound_peers"].as_bool().unwrap_or(false));
+        Some(policy)
+    }
+
+    ///
+    /// Gets bandwidth caps of sending from `bandwidth` section
+    ///
+    /// returns: Option<ShapingLimits>: caps or None if bandwidth is not capped
+    ///
+    pub fn get_shaping_limits(&self) -> Option<ShapingLimits>{
+        let section = &self.config_yaml[0]["bandwidth"];
+        section.as_hash()?;
+        let mut limits = ShapingLimits{
+            global: parse_bandwidth_limits(&section["global"]),
+            connection: parse_bandwidth_limits(&section["connection"]),
+            ..Default::default()
+        };
+        if let Some(peers) = section["peers"].as_hash(){
+            for (id, peer_limits) in peers.iter(){
+                match (parse_peer_id(id), parse_bandwidth_limits(peer_limits)) {
+                    (Ok(id), Some(peer_limits)) => {
+                        limits.peer_overrides.insert(id.get(), peer_limits);
+                    }
+                    (Err(error), _) => println!("{}: Bandwidth caps of peer: {}", "error".red().bold().underline(), error),
+                    _ => {}
+                }
+            }
+        }
+        Some(limits)
+    }
+
+    ///
+    /// Gets module isolation policy from `module_isolation` section
+    ///
+    /// returns: IsolationPolicy: configured policy, all modules run in-process if section is missing
+    ///
+    pub fn get_isolation_policy(&self) -> IsolationPolicy{
+        let section = &self.config_yaml[0]["module_isolation"];
+        let mut policy = IsolationPolicy::new(parse_isolation(&section["default"])
+            .unwrap_or(ModuleIsolation::InProcess));
+        if let Some(modules) = section["modules"].as_hash(){
+            for (name, isolation) in modules.iter(){
+                if let (Some(name), Some(isolation)) = (name.as_str(), parse_isolation(isolation)){
+                    policy.set_override(name, isolation);
+                }
+            }
+        }
+        if let Some(signers) = section["trusted_signers"].as_vec(){
+            for serial in signers.iter().filter_map(parse_id){
+                policy.trust_signer(serial);
+            }
+        }
+        policy
+    }
+
+    ///
+    /// Gets a path to module runner binary used for isolated modules
+    ///
+    /// returns: Option<&Path>: path to module runner
+    ///
+    pub fn get_module_runner_path(&self) -> Option<&Path>{
+        self.config_yaml[0]["module_isolation"]["runner"].as_str().map(Path::new)
+    }
+
+    ///
+    /// Gets how panicked modules are restarted from `module_max_restarts`, `module_restart_backoff_ms`
+    /// and `module_max_restart_backoff_ms`. Defaults are used for missing options,
+    /// `module_max_restarts: 0` disables restarts.
+    ///
+    pub fn get_restart_policy(&self) -> RestartPolicy{
+        let mut policy = RestartPolicy::default();
+        let yaml = &self.config_yaml[0];
+        if let Some(max_restarts) = yaml["module_max_restarts"].as_i64(){
+            policy.max_restarts = max_restarts.max(0) as u32;
+        }
+        if let Some(backoff) = yaml["module_restart_backoff_ms"].as_i64(){
+            policy.initial_backoff = Duration::from_millis(backoff.max(0) as u64);
+        }
+        if let Some(backoff) = yaml["module_max_restart_backoff_ms"].as_i64(){
+            policy.max_backoff = Duration::from_millis(backoff.max(0) as u64);
+        }
+        policy
+    }
+
+    ///
+    /// Gets additional authentication factors for certificates with FLAG_REQUIRE_2FA
+    ///
+    /// returns: Vec<Box<dyn AuthenticationFactor>>: factors in order of preference
+    ///
+    pub fn get_authentication_factors(&self) -> Vec<Box<dyn AuthenticationFactor>>{
+        match self.config_yaml[0]["authentication"]["factors"].as_vec() {
+            Some(factors) => factors.iter().filter_map(parse_authentication_factor).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    ///
+    /// Gets whether certificate service starts read-only: certificates can not be added or removed
+    /// locally or by peers until mode is switched off, e.g. after maintenance window
+    ///
+    /// returns: bool: `read_only` or false if it is not set
+    ///
+    pub fn is_read_only(&self) -> bool{
+        self.config_yaml[0]["read_only"].as_bool().unwrap_or(false)
+    }
+
+    ///
+    /// Gets path of secret key store kept apart from certificates, its passphrase is read from
+    /// KEY_STORE_PASSPHRASE_VARIABLE
+    ///
+    /// returns: Option<&Path>: `key_store_path` or None if key store is kept in storage directory
+    ///
+    pub fn get_key_store_path(&self) -> Option<&Path>{
+        self.config_yaml[0]["key_store_path"].as_str().map(Path::new)
+    }
+
+    ///
+    /// Gets number of binders to certificate service in pool of each module
+    ///
+    /// returns: usize: `certificate_binder_pool` or DEFAULT_BINDER_POOL_SIZE if it is not set or not positive
+    ///
+    pub fn get_certificate_pool_size(&self) -> usize{
+        self.config_yaml[0]["certificate_binder_pool"].as_i64().filter(|size| *size > 0)
+            .map(|size| size as usize).unwrap_or(DEFAULT_BINDER_POOL_SIZE)
+    }
+
+    ///
+    /// Gets directory which certificates dropped into are imported automatically, e.g. by
+    /// configuration management(see CertificateDirectoryWatcher)
+    ///
+    /// returns: Option<&Path>: `certificate_import_dir` or None if directory is not watched
+    ///
+    pub fn get_certificate_import_directory(&self) -> Option<&Path>{
+        self.config_yaml[0]["certificate_import_dir"].as_str().map(Path::new)
+    }
+
+    ///
+    /// Gets whether certificate store is reloaded once it is changed on disk, e.g. by restore or
+    /// CLI(see CertificateStoreWatcher)
+    ///
+    /// returns: bool: `watch_certificate_store` or false if it is not set
+    ///
+    pub fn is_certificate_store_watched(&self) -> bool{
+        self.config_yaml[0]["watch_certificate_store"].as_bool().unwrap_or(false)
+    }
+
+    ///
+    /// Gets policy of certificate garbage collection from `certificate_gc` section(`interval`
+    /// and `retention` in seconds), missing values are defaults
+    ///
+    /// returns: Option<CertificateGcPolicy>: policy or None if certificates are not collected
+    ///
+    pub fn get_certificate_gc_policy(&self) -> Option<CertificateGcPolicy>{
+        let section = &self.config_yaml[0]["certificate_gc"];
+        if section.is_badvalue(){
+            return None;
+        }
+        let mut policy = CertificateGcPolicy::default();
+        if let Some(interval) = section["interval"].as_i64(){
+            policy.interval = interval.max(1) as u64;
+        }
+        if let Some(retention) = section["retention"].as_i64(){
+            policy.retention = retention.max(0) as u64;
+        }
+        Some(policy)
+    }
+
+    ///
+    /// Gets policy of certificate service exposed to peers from `remote_certificates` section
+    ///
+    /// returns: Option<RemoteCertificatePolicy>: policy or None if service is not exposed
+    ///
+    pub fn get_remote_certificate_policy(&self) -> Option<RemoteCertificatePolicy>{
+        let section = &self.config_yaml[0]["remote_certificates"];
+        if !section["enabled"].as_bool().unwrap_or(false){
+            return None;
+        }
+        Some(RemoteCertificatePolicy{
+            allow_write: section["allow_write"].as_bool().unwrap_or(false),
+        })
+    }
+
+    ///
+    /// Gets compression of frames from `compression` section
+    ///
+    /// returns: Option<CompressionPolicy>: policy or None if frames are not compressed
+    ///
+    pub fn get_compression_policy(&self) -> Option<CompressionPolicy>{
+        let section = &self.config_yaml[0]["compression"];
+        section.as_hash()?;
+        let mut policy = CompressionPolicy::default();
+        if let Some(algorithms) = section["algorithms"].as_vec(){
+            policy.algorithms.clear();
+            for name in algorithms.iter().filter_map(|name| name.as_str()){
+                match CompressionAlgorithm::from_name(name) {
+                    Some(algorithm) => policy.algorithms.push(algorithm),
+                    None => println!("{}: Unknown compression algorithm '{}'", "error".red().bold().underline(), name),
+                }
+            }
+        }
+        if let Some(threshold) = section["threshold"].as_i64(){
+            policy.threshold = threshold.max(0) as usize;
+        }
+        Some(policy)
     }
-}
\ No newline at end of file
+
+    ///
+    /// Gets whether frames are checksummed from `checksum` value(`auto`, `always` or `never`)
+    ///
+    pub fn get_checksum_mode(&self) -> ChecksumMode{
+        match self.config_yaml[0]["checksum"].as_str().unwrap_or("auto") {
+            "always" => ChecksumMode::Always,
+            "never" => ChecksumMode::Never,
+            "auto" => ChecksumMode::Auto,
+            mode => {
+                println!("{}: Unknown checksum mode '{}', using auto", "error".red().bold().underline(), mode);
+                ChecksumMode::Auto
+            }
+        }
+    }
+
+    ///
+    /// Gets how received messages are parsed from `strict_parsing`: frames with bytes after
+    /// message are rejected if it is set
+    ///
+    pub fn get_parsing_mode(&self) -> ParsingMode{
+        match self.config_yaml[0]["strict_parsing"].as_bool().unwrap_or(false) {
+            true => ParsingMode::Strict,
+            false => ParsingMode::Lenient,
+        }
+    }
+
+    ///
+    /// Gets how many signature and decryption failures are tolerated on a connection before
+    /// it is terminated(`crypto_failure_threshold`), 0 disables termination
+    ///
+    pub fn get_crypto_failure_threshold(&self) -> u64{
+        match &self.config_yaml[0]["crypto_failure_threshold"] {
+            Yaml::BadValue => DEFAULT_CRYPTO_FAILURE_THRESHOLD,
+            Yaml::Integer(threshold) if *threshold >= 0 => *threshold as u64,
+            value => {
+                println!("{}: Invalid crypto failure threshold: {:?}", "error".red().bold().underline(), value);
+                DEFAULT_CRYPTO_FAILURE_THRESHOLD
+            }
+        }
+    }
+
+    ///
+    /// Gets timeouts of idle connections from `keepalive` section(`idle_timeout`, `probe_timeout`
+    /// and `reap_interval` in seconds), missing values are defaults
+    ///
+    pub fn get_keepalive_policy(&self) -> KeepAlivePolicy{
+        let section = &self.config_yaml[0]["keepalive"];
+        let mut policy = KeepAlivePolicy::default();
+        for (name, timeout) in [("idle_timeout", &mut policy.idle_timeout),
+                                ("probe_timeout", &mut policy.probe_timeout),
+                                ("reap_interval", &mut policy.reap_interval)]{
+            match &section[name] {
+                Yaml::BadValue => {}
+                Yaml::Integer(seconds) if *seconds > 0 => *timeout = (*seconds as u64).saturating_mul(1000),
+                value => println!("{}: Invalid keepalive {}: {:?}", "error".red().bold().underline(), name, value),
+            }
+        }
+        policy
+    }
+
+    ///
+    /// Gets timeouts of handshake stages from `handshake` section(`version`, `capabilities`,
+    /// `read_authorization` and `send_response` in milliseconds), missing values are defaults
+    ///
+    pub fn get_handshake_timeouts(&self) -> HandshakeTimeouts{
+        let section = &self.config_yaml[0]["handshake"];
+        let mut timeouts = HandshakeTimeouts::default();
+        for stage in HandshakeStage::ALL{
+            match &section[stage.get_name()] {
+                Yaml::BadValue => {}
+                Yaml::Integer(milliseconds) if *milliseconds > 0 => {
+                    timeouts.set(stage, *milliseconds as u64);
+                }
+                value => println!("{}: Invalid handshake {} timeout: {:?}", "error".red().bold().underline(), stage, value),
+            }
+        }
+        timeouts
+    }
+
+    ///
+    /// Checks whether frame statistics(`frame_stats`) are collected from start, they may be
+    /// switched on and off later with FrameStats admin command
+    ///
+    pub fn is_frame_stats_enabled(&self) -> bool{
+        self.config_yaml[0]["frame_stats"].as_bool().unwrap_or(false)
+    }
+
+    ///
+    /// Checks whether modules may tap raw frames(`raw_frame_tap`). Even then only operators
+    /// with transport-tap flag may subscribe to them.
+    ///
+    pub fn is_raw_frame_tap_enabled(&self) -> bool{
+        self.config_yaml[0]["raw_frame_tap"].as_bool().unwrap_or(false)
+    }
+
+    ///
+    /// Gets protocol versions accepted from peers, `min_protocol_version` is raised to oldest
+    /// supported and lowered to current version if out of range
+    ///
+    /// returns: VersionPolicy: configured policy or default one if minimum is not set
+    ///
+    pub fn get_version_policy(&self) -> VersionPolicy{
+        match &self.config_yaml[0]["min_protocol_version"] {
+            Yaml::BadValue => VersionPolicy::default(),
+            Yaml::Integer(minimum) if *minimum >= 0 => VersionPolicy::new(u32::try_from(*minimum).unwrap_or(u32::MAX)),
+            value => {
+                println!("{}: Invalid min_protocol_version: {:?}", "error".red().bold().underline(), value);
+                VersionPolicy::default()
+            }
+        }
+    }
+
+    ///
+    /// Gets name resolver from `names` section: static entries, DNS and records exchanged
+    /// with peers are asked in this order, each backend is switched by its `enabled` flag
+    ///
+    /// # Arguments
+    /// * exchange: &PeerExchangeNameBackend: records received from peers, shared with handler
+    ///   of name exchange messages
+    ///
+    pub fn get_name_resolver(&self, exchange: &PeerExchangeNameBackend) -> NameResolver{
+        let section = &self.config_yaml[0]["names"];
+        let domain = section["domain"].as_str().unwrap_or("");
+        let mut resolver = NameResolver::new(domain);
+        if let Some(ttl) = section["max_ttl"].as_i64(){
+            resolver.set_max_ttl(ttl.max(0) as u64);
+        }
+        let mut backend = StaticNameBackend::new();
+        let entries = section["static"]["entries"].as_vec().cloned().unwrap_or_default();
+        for entry in entries.iter(){
+            match (entry["name"].as_str(), parse_peer_id(&entry["id"])) {
+                (Some(name), Ok(id)) => {
+                    let endpoints = entry["endpoints"].as_vec()
+                        .map(|endpoints| endpoints.iter().filter_map(|endpoint| endpoint.as_str().map(String::from)).collect())
+                        .unwrap_or_default();
+                    backend.add_record(name, id.get(), endpoints);
+                }
+                (_, Err(error)) => println!("{}: Name entry has invalid id: {}: {:?}", "error".red().bold().underline(),
+                                            error, entry),
+                _ => println!("{}: Name entry must have a name: {:?}", "error".red().bold().underline(), entry),
+            }
+        }
+        resolver.add_backend(Box::new(backend), section["static"]["enabled"].as_bool().unwrap_or(true));
+        let dns = &section["dns"];
+        if let Some(server) = dns["server"].as_str(){
+            match server.parse() {
+                Ok(server) => {
+                    let mut lookup = UdpDnsLookup::new(server);
+                    if let Some(timeout) = dns["timeout"].as_i64(){
+                        lookup.set_timeout(timeout.max(0) as u64);
+                    }
+                    resolver.add_backend(Box::new(DnsNameBackend::new(Box::new(lookup), domain)),
+                                         dns["enabled"].as_bool().unwrap_or(false));
+                }
+                Err(_) => println!("{}: Invalid DNS server address '{}'", "error".red().bold().underline(), server),
+            }
+        }
+        resolver.add_backend(Box::new(exchange.clone()), section["exchange"]["enabled"].as_bool().unwrap_or(false));
+        resolver
+    }
+
+    ///
+    /// Gets manager of outbound connections from `connections` section, invalid proxies are
+    /// reported and ignored
+    ///
+    pub fn get_connection_manager(&self) -> ConnectionManager{
+        let section = &self.config_yaml[0]["connections"];
+        let mut manager = ConnectionManager::new();
+        if let Some(delay) = section["attempt_delay"].as_i64(){
+            manager.set_attempt_delay(delay.max(0) as u64);
+        }
+        if let Some(timeout) = section["timeout"].as_i64(){
+            manager.set_connect_timeout(timeout.max(0) as u64);
+        }
+        if let Some(proxy) = section["proxy"].as_str(){
+            match ProxyConfig::parse(proxy) {
+                Ok(proxy) => {
+                    manager.set_proxy(Some(proxy));
+                }
+                Err(error) => println!("{}: {}", "error".red().bold().underline(), error),
+            }
+        }
+        let peers = section["peers"].as_vec().cloned().unwrap_or_default();
+        // Peers may be listed only for their expected identity
+        for peer in peers.iter().filter(|peer| !peer["proxy"].is_badvalue()){
+            let id = parse_peer_id(&peer["id"]);
+            let proxy = match peer["proxy"].as_str() {
+                Some("direct") => Some(None),
+                Some(proxy) => ProxyConfig::parse(proxy).ok().map(Some),
+                None => None,
+            };
+            match (id, proxy) {
+                (Ok(id), Some(proxy)) => {
+                    manager.set_peer_proxy(id.get(), proxy);
+                }
+                (Err(error), _) => println!("{}: Peer connection has invalid id: {}: {:?}",
+                                            "error".red().bold().underline(), error, peer),
+                _ => println!("{}: Peer connection must have a valid proxy(URL or direct): {:?}",
+                              "error".red().bold().underline(), peer),
+            }
+        }
+        manager
+    }
+
+    ///
+    /// Gets IDs of peers in `connections` section, daemon connects to each of them at
+    /// endpoints its name resolver finds for the ID
+    ///
+    pub fn get_outbound_peers(&self) -> Vec<u128>{
+        let peers = self.config_yaml[0]["connections"]["peers"].as_vec().cloned().unwrap_or_default();
+        peers.iter().filter_map(|peer| parse_peer_id(&peer["id"]).ok()).map(|id| id.get()).collect()
+    }
+
+    ///
+    /// Gets identities expected from peers in `connections` section: `expect` of each peer
+    /// and `identity_mode`(strict or lenient). Invalid entries are reported and ignored.
+    ///
+    pub fn get_expected_identities(&self) -> ExpectedIdentities{
+        let section = &self.config_yaml[0]["connections"];
+        let mut identities = ExpectedIdentities::new();
+        match section["identity_mode"].as_str() {
+            Some("strict") | None => {}
+            Some("lenient") => {
+                identities.set_mode(IdentityMode::Lenient);
+            }
+            Some(mode) => println!("{}: Unknown identity mode {}, expected strict or lenient",
+                                   "error".red().bold().underline(), mode),
+        }
+        let peers = section["peers"].as_vec().cloned().unwrap_or_default();
+        for peer in peers.iter().filter(|peer| !peer["expect"].is_badvalue()){
+            let expect = &peer["expect"];
+            let mut identity = ExpectedIdentity::new();
+            if let Some(name) = expect["name"].as_str(){
+                identity.set_name(name);
+            }
+            if let Some(serial) = parse_id(&expect["serial"]){
+                identity.set_serial(serial);
+            }
+            let fingerprint = match expect["fingerprint"].as_str() {
+                Some(fingerprint) => identity.set_fingerprint(fingerprint).map(|_| ()),
+                None => Ok(()),
+            };
+            match (parse_peer_id(&peer["id"]), fingerprint) {
+                (Ok(id), Ok(())) if !identity.is_empty() => {
+                    identities.set_identity(id.get(), identity);
+                }
+                (Err(error), _) => println!("{}: Expected identity has invalid peer id: {}: {:?}",
+                                            "error".red().bold().underline(), error, peer),
+                (_, Err(error)) => println!("{}: {}: {:?}", "error".red().bold().underline(), error, peer),
+                _ => println!("{}: Expected identity must have a valid peer id and name, serial or fingerprint: {:?}",
+                              "error".red().bold().underline(), peer),
+            }
+        }
+        identities
+    }
+
+    ///
+    /// Gets address HTTP gateway listens on from `gateway` section
+    ///
+    /// returns: Option<String>: `address` or None if gateway is not enabled
+    ///
+    pub fn get_gateway_address(&self) -> Option<String>{
+        let section = &self.config_yaml[0]["gateway"];
+        if !section["enabled"].as_bool().unwrap_or(false){
+            return None;
+        }
+        Some(section["address"].as_str().unwrap_or(DEFAULT_GATEWAY_ADDRESS).to_string())
+    }
+
+    ///
+    /// Gets tokens of `gateway` section, invalid tokens are reported and skipped
+    ///
+    pub fn get_gateway_tokens(&self) -> Vec<GatewayToken>{
+        let mut tokens = Vec::new();
+        let items = self.config_yaml[0]["gateway"]["tokens"].as_vec().cloned().unwrap_or_default();
+        for item in items.iter(){
+            let (name, token) = match (item["name"].as_str(), item["token"].as_str()) {
+                (Some(name), Some(token)) if !token.is_empty() => (name, token),
+                _ => {
+                    println!("{}: Gateway token must have a name and a token", "error".red().bold().underline());
+                    continue;
+                }
+            };
+            let certificate = match &item["certificate"] {
+                Yaml::BadValue | Yaml::Null => Ok(None),
+                Yaml::Integer(serial) if *serial >= 0 => Ok(Some(*serial as u128)),
+                Yaml::String(serial) => serial.parse::<u128>().map(Some).map_err(|_| ()),
+                _ => Err(()),
+            };
+            let certificate = match certificate {
+                Ok(certificate) => certificate,
+                Err(_) => {
+                    println!("{}: Invalid certificate of gateway token {}: {:?}", "error".red().bold().underline(),
+                             name, item["certificate"]);
+                    continue;
+                }
+            };
+            tokens.push(GatewayToken{
+                name: name.to_string(),
+                token: token.to_string(),
+                read_only: item["read_only"].as_bool().unwrap_or(false),
+                certificate,
+            });
+        }
+        tokens
+    }
+
+    ///
+    /// Gets path of admin control socket from `admin` section
+    ///
+    /// returns: PathBuf: configured path or DEFAULT_ADMIN_SOCKET_PATH
+    ///
+    pub fn get_admin_socket_path(&self) -> PathBuf{
+        PathBuf::from(self.config_yaml[0]["admin"]["socket"].as_str().unwrap_or(DEFAULT_ADMIN_SOCKET_PATH))
+    }
+
+    ///
+    /// Gets OS users allowed to use certificates with FLAG_REQUIRE_2FA on admin socket
+    /// (`admin.os-users`, user IDs by certificate serial)
+    ///
+    /// returns: Option<OsUserFactor>: factor or None if users are not restricted
+    ///
+    pub fn get_admin_os_user_factor(&self) -> Option<OsUserFactor>{
+        let users = self.config_yaml[0]["admin"]["os-users"].as_hash().filter(|users| !users.is_empty())?;
+        let mut factor = OsUserFactor::default();
+        for (serial, uids) in users.iter(){
+            let serial = match parse_id(serial) {
+                Some(serial) => serial,
+                None => {
+                    println!("{}: Invalid certificate serial in admin.os-users", "error".red().bold().underline());
+                    continue;
+                }
+            };
+            for uid in uids.as_vec().map(|uids| uids.as_slice()).unwrap_or_default(){
+                match uid.as_i64().and_then(|uid| u32::try_from(uid).ok()) {
+                    Some(uid) => { factor.allow_user(serial, uid); }
+                    None => println!("{}: Invalid user ID for certificate {}", "error".red().bold().underline(), serial),
+                }
+            }
+        }
+        Some(factor)
+    }
+
+    ///
+    /// Gets exporter of spans from `tracing` section
+    ///
+    /// returns: Option<Box<dyn SpanExporter>>: exporter or None if spans are not exported
+    ///
+    pub fn get_span_exporter(&self) -> Option<Box<dyn SpanExporter>>{
+        let section = &self.config_yaml[0]["tracing"];
+        match section["exporter"].as_str()? {
+            "file" => match FileSpanExporter::open(Path::new(section["path"].as_str()?)) {
+                Ok(exporter) => Some(Box::new(exporter)),
+                Err(error) => {
+                    println!("{}: Can not open spans file: {}", "error".red().bold().underline(), error);
+                    None
+                }
+            },
+            "otlp" => {
+                let service_name = section["service_name"].as_str().unwrap_or("milkywaysrvd");
+                match OtlpSpanExporter::new(section["endpoint"].as_str()?, service_name) {
+                    Ok(exporter) => Some(Box::new(exporter)),
+                    Err(error) => {
+                        println!("{}: {}", "error".red().bold().underline(), error);
+                        None
+                    }
+                }
+            }
+            other => {
+                println!("{}: Unknown span exporter '{}'", "error".red().bold().underline(), other);
+                None
+            }
+        }
+    }
+}
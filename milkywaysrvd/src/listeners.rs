@@ -0,0 +1,257 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::net::{TcpListener, TcpStream};
+use libmilkyway::serialization::deserializable::ParsingMode;
+use libmilkyway::services::name::resolver::SharedNameResolver;
+use libmilkyway::transport::async_stream::TokioStreamTransport;
+use libmilkyway::transport::connector::{Connection, ConnectionManager};
+use libmilkyway::transport::deadletter::SharedDeadLetterQueue;
+use libmilkyway::transport::events::{DisconnectReason, SharedConnectionEvents};
+use libmilkyway::transport::handshake::{HandshakeTimeouts, SharedHandshakeMetrics};
+use libmilkyway::transport::keepalive::SharedConnectionReaper;
+use libmilkyway::transport::rawtap::SharedRawFrameTap;
+use libmilkyway::transport::router::PeerLink;
+use libmilkyway::transport::session::SessionHandshake;
+use libmilkyway::transport::shaping::{ConnectionShaper, SharedBandwidthShaper};
+use libmilkyway::transport::stats::SharedFrameStats;
+
+///
+/// Establishes sessions on connections of daemon and serves them until they are closed:
+/// connections accepted by listener and outbound connections to peers
+///
+pub struct ConnectionHandler{
+    handshake: SessionHandshake,
+    link: PeerLink,
+    handshake_timeouts: HandshakeTimeouts,
+    handshake_metrics: Option<SharedHandshakeMetrics>,
+    frame_stats: Option<SharedFrameStats>,
+    raw_frame_tap: Option<SharedRawFrameTap>,
+    parsing_mode: ParsingMode,
+    shaper: Option<SharedBandwidthShaper>,
+    reaper: Option<SharedConnectionReaper>,
+    events: Option<SharedConnectionEvents>,
+    dead_letters: Option<SharedDeadLetterQueue>,
+    /** New connections are refused while daemon is draining **/
+    is_draining: Arc<AtomicBool>,
+}
+
+impl ConnectionHandler {
+    ///
+    /// Creates handler of connections
+    ///
+    /// # Arguments
+    /// * handshake: SessionHandshake: establishes sessions of daemon
+    /// * link: PeerLink: serves connections once their peers are authorized
+    ///
+    pub fn new(handshake: SessionHandshake, link: PeerLink) -> ConnectionHandler{
+        ConnectionHandler{
+            handshake,
+            link,
+            handshake_timeouts: HandshakeTimeouts::default(),
+            handshake_metrics: None,
+            frame_stats: None,
+            raw_frame_tap: None,
+            parsing_mode: ParsingMode::default(),
+            shaper: None,
+            reaper: None,
+            events: None,
+            dead_letters: None,
+            is_draining: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn set_handshake_timeouts(&mut self, timeouts: HandshakeTimeouts, metrics: SharedHandshakeMetrics) -> &mut Self{
+        self.handshake_timeouts = timeouts;
+        self.handshake_metrics = Some(metrics);
+        self
+    }
+
+    pub fn set_frame_stats(&mut self, stats: SharedFrameStats) -> &mut Self{
+        self.frame_stats = Some(stats);
+        self
+    }
+
+    pub fn set_raw_frame_tap(&mut self, tap: SharedRawFrameTap) -> &mut Self{
+        self.raw_frame_tap = Some(tap);
+        self
+    }
+
+    pub fn set_parsing_mode(&mut self, mode: ParsingMode) -> &mut Self{
+        self.parsing_mode = mode;
+        self
+    }
+
+    ///
+    /// Sets shaper pacing sending of every connection
+    ///
+    pub fn set_shaper(&mut self, shaper: SharedBandwidthShaper) -> &mut Self{
+        self.shaper = Some(shaper);
+        self
+    }
+
+    ///
+    /// Sets reaper every connection is registered in
+    ///
+    pub fn set_reaper(&mut self, reaper: SharedConnectionReaper) -> &mut Self{
+        self.reaper = Some(reaper);
+        self
+    }
+
+    ///
+    /// Sets events connections are published to
+    ///
+    pub fn set_connection_events(&mut self, events: SharedConnectionEvents) -> &mut Self{
+        self.events = Some(events);
+        self
+    }
+
+    ///
+    /// Sets queue frames which can not be deserialized are recorded to
+    ///
+    pub fn set_dead_letter_queue(&mut self, queue: SharedDeadLetterQueue) -> &mut Self{
+        self.dead_letters = Some(queue);
+        self
+    }
+
+    ///
+    /// Gets flag which makes handler refuse new connections once it is set
+    ///
+    #[inline]
+    pub fn get_draining_flag(&self) -> Arc<AtomicBool>{
+        self.is_draining.clone()
+    }
+
+    // Applies settings of daemon to a new connection
+    fn create_transport(&self, stream: TcpStream, endpoint: &str) -> TokioStreamTransport<TcpStream>{
+        let mut transport = TokioStreamTransport::from_stream(stream);
+        transport.set_handshake_timeouts(self.handshake_timeouts.clone());
+        if let Some(metrics) = &self.handshake_metrics{
+            transport.set_handshake_metrics(metrics.clone());
+        }
+        if let Some(stats) = &self.frame_stats{
+            transport.set_frame_stats(stats.clone());
+        }
+        if let Some(tap) = &self.raw_frame_tap{
+            transport.set_raw_frame_tap(tap.clone());
+        }
+        transport.set_parsing_mode(self.parsing_mode);
+        if let Some(reaper) = &self.reaper{
+            transport.set_reaper(reaper.clone());
+        }
+        if let Some(events) = &self.events{
+            transport.set_connection_events(events.clone(), endpoint);
+        }
+        if let Some(queue) = &self.dead_letters{
+            transport.set_dead_letter_queue(queue.clone());
+        }
+        transport
+    }
+
+    // Serves connection once its peer is authorized
+    async fn serve(&self, mut transport: TokioStreamTransport<TcpStream>, peer_id: u128, link: &PeerLink){
+        if let Some(shaper) = &self.shaper{
+            transport.set_shaper(ConnectionShaper::new(shaper.clone(), peer_id));
+        }
+        link.serve(transport, peer_id).await;
+    }
+
+    ///
+    /// Establishes session with connecting peer and serves it
+    ///
+    /// # Arguments
+    /// * stream: TcpStream: accepted connection
+    /// * endpoint: String: address of peer
+    ///
+    pub async fn accept(&self, stream: TcpStream, endpoint: String){
+        let mut transport = self.create_transport(stream, &endpoint);
+        match self.handshake.accept(&mut transport).await {
+            Ok(peer_id) => {
+                log::info!("Peer {} connected from {}", peer_id, endpoint);
+                self.serve(transport, peer_id, &self.link).await;
+                log::info!("Peer {} disconnected", peer_id);
+            }
+            Err(error) => {
+                log::warn!("Can not establish session with {}: {}", endpoint, error);
+                transport.set_disconnect_reason(DisconnectReason::Error(error.to_string()));
+            }
+        }
+    }
+
+    ///
+    /// Establishes session with peer daemon connected to and serves it. Peer is a relay, so
+    /// it may forward messages of hosts connected to it.
+    ///
+    /// # Arguments
+    /// * connection: Connection: outbound connection
+    /// * peer_id: u128: ID peer must be authorized with
+    ///
+    pub async fn connect(&self, connection: Connection, peer_id: u128){
+        let mut transport = self.create_transport(connection.stream, &connection.endpoint);
+        match self.handshake.connect(&mut transport).await {
+            Ok(id) if id == peer_id => {
+                log::info!("Connected to peer {} at {}", peer_id, connection.endpoint);
+                let mut link = self.link.clone();
+                link.set_relay(true);
+                self.serve(transport, peer_id, &link).await;
+                log::info!("Connection to peer {} is closed", peer_id);
+            }
+            Ok(id) => {
+                log::warn!("Peer at {} is authorized as {}, not as {}", connection.endpoint, id, peer_id);
+                transport.set_disconnect_reason(DisconnectReason::Error("unexpected peer".to_string()));
+            }
+            Err(error) => {
+                log::warn!("Can not establish session with peer {}: {}", peer_id, error);
+                transport.set_disconnect_reason(DisconnectReason::Error(error.to_string()));
+            }
+        }
+    }
+}
+
+///
+/// Accepts connections until listener fails, every connection is served by its own task.
+/// Must be called within tokio runtime.
+///
+/// # Arguments
+/// * handler: Arc<ConnectionHandler>: handler of accepted connections
+/// * listener: TcpListener: bound listener
+///
+pub async fn listen(handler: Arc<ConnectionHandler>, listener: TcpListener){
+    loop {
+        let (stream, address) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(error) => {
+                log::error!("Listener failed: {}", error);
+                return;
+            }
+        };
+        if handler.is_draining.load(Ordering::Relaxed){
+            log::info!("Daemon is draining, connection from {} is refused", address);
+            continue;
+        }
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            handler.accept(stream, address.to_string()).await;
+        });
+    }
+}
+
+///
+/// Connects to peer at endpoints name resolver knows for its ID and serves connection
+/// until it is closed. Must be called within tokio runtime.
+///
+/// # Arguments
+/// * handler: Arc<ConnectionHandler>: handler of connection
+/// * manager: Arc<ConnectionManager>: manager making connection
+/// * resolver: SharedNameResolver: resolver of endpoints of peer
+/// * peer_id: u128: ID of peer
+///
+pub async fn connect_peer(handler: Arc<ConnectionHandler>, manager: Arc<ConnectionManager>,
+                          resolver: SharedNameResolver, peer_id: u128){
+    // Backends may do blocking lookups, e.g. DNS
+    let record = tokio::task::spawn_blocking(move || resolver.lock().unwrap().resolve_id(peer_id)).await;
+    let endpoints = record.ok().flatten().map(|record| record.endpoints).unwrap_or_default();
+    match manager.connect_endpoints(peer_id, &endpoints).await {
+        Ok(connection) => handler.connect(connection, peer_id).await,
+        Err(error) => log::warn!("Can not connect to peer {}: {}", peer_id, error),
+    }
+}
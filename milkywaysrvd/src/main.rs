@@ -1,12 +1,387 @@
-use libmilkyway::tokio::init_tokio;
-use libmilkyway::transport::async_stream::TokioStreamTransport;
-
+mod bus;
 mod configuration;
-mod services;
 mod listeners;
+mod modules;
+mod services;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use colored::Colorize;
+use libmilkyway::controllers::admin::AdminServer;
+use libmilkyway::controllers::authorization::AuthorizationController;
+use libmilkyway::controllers::gateway::GatewayServer;
+use libmilkyway::controllers::shutdown::{CertificateFlushHook, ShutdownController, ShutdownStage};
+use libmilkyway::module::ModuleDataBus;
+use libmilkyway::module::loader::{load_module, LoadedModule};
+use libmilkyway::module::registry::ModuleRegistry;
+use libmilkyway::module::state::ModuleStateStore;
+use libmilkyway::module::supervisor::{DataBusProvider, SupervisedModule};
+use libmilkyway::paths::PathResolver;
+use libmilkyway::secrets::SecretResolver;
+use libmilkyway::serialization::migration::Migrator;
+use libmilkyway::services::certificate::CertificateService;
+use libmilkyway::services::certificate::directory::CertificateDirectoryWatcher;
+use libmilkyway::services::certificate::gc::run_certificate_gc;
+use libmilkyway::services::certificate::reload::CertificateStoreWatcher;
+use libmilkyway::services::certificate::remote::RemoteCertificateServer;
+use libmilkyway::services::impls::certificate::AsyncCertificateServiceImpl;
+use libmilkyway::services::impls::group::GroupServiceImpl;
+use libmilkyway::services::name::exchange::PeerExchangeNameBackend;
+use libmilkyway::services::transport::MessageFilter;
+use libmilkyway::tokio::{init_tokio, tokio_block_on};
+use libmilkyway::trace;
+use libmilkyway::transport::access::AccessControl;
+use libmilkyway::transport::compression::CompressionTransformerFactory;
+use libmilkyway::transport::crypto::CryptoAlerts;
+use libmilkyway::transport::deadletter::DeadLetterQueue;
+use libmilkyway::transport::events::ConnectionEvents;
+use libmilkyway::transport::handshake::HandshakeMetrics;
+use libmilkyway::transport::keepalive::{run_reaper, ConnectionReaper};
+use libmilkyway::transport::pinning::PeerPins;
+use libmilkyway::transport::ratelimit::RateLimiter;
+use libmilkyway::transport::rawtap::RawFrameTap;
+use libmilkyway::transport::router::{LocalDelivery, PeerLink, Router, RouterSender};
+use libmilkyway::transport::sequence::SequenceStore;
+use libmilkyway::transport::session::{AuthorizationAuthority, SessionHandshake};
+use libmilkyway::transport::shaping::{BandwidthControlServer, BandwidthShaper};
+use libmilkyway::transport::stack::{CryptoTransformerFactory, TransformerStack};
+use libmilkyway::transport::stats::FrameStats;
+use crate::bus::ServerDataBus;
+use crate::configuration::ServerConfiguration;
+use crate::listeners::{connect_peer, listen, ConnectionHandler};
+use crate::modules::{get_builtin_modules, get_module_reports, run_module_supervisor};
+use crate::services::{DaemonControl, NameExchangeListener};
+
+/// Module runner used for isolated modules unless `module_isolation.runner` is set
+const DEFAULT_MODULE_RUNNER_PATH: &str = "/usr/bin/milkywaymodrunner";
+
+fn print_error<T: std::fmt::Display>(message: T){
+    println!("{}: {}", "error".red().bold().underline(), message);
+}
+
+// Takes `--config=<path>` from arguments of daemon
+fn take_config_option(arguments: Vec<String>) -> Option<PathBuf>{
+    arguments.iter().find_map(|argument| argument.strip_prefix("--config=").map(PathBuf::from))
+}
+
+///
+/// Loads modules of modules directory according to isolation policy, so modules which are
+/// not trusted run in module runner
+///
+#[allow(unsafe_code)]
+fn load_modules_from<S: CertificateService + ?Sized>(dir_path: &Path, configuration: &ServerConfiguration,
+                                                    certificates: &mut S, socket_dir: &Path) -> Vec<SupervisedModule>{
+    let entries = match fs::read_dir(dir_path) {
+        Ok(entries) => entries,
+        Err(_) => {
+            log::warn!("No modules directory found");
+            return vec![];
+        }
+    };
+    let policy = configuration.get_isolation_policy();
+    let runner = configuration.get_module_runner_path().unwrap_or(Path::new(DEFAULT_MODULE_RUNNER_PATH));
+    let mut modules = Vec::new();
+    for entry in entries.flatten(){
+        let path = entry.path();
+        // Signatures of modules lie next to them
+        if path.is_dir() || path.extension().is_some_and(|extension| extension == "sig"){
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        let module = unsafe {
+            load_module(&path, &policy, certificates, runner, socket_dir)
+        };
+        match module {
+            Ok(LoadedModule::InProcess(module)) => modules.push(SupervisedModule::from_dynamic(&name, module)),
+            Ok(LoadedModule::Isolated(module)) => {
+                // Runner restarts are not supported, module stays stopped once it fails
+                modules.push(SupervisedModule::new(&name, Box::new(module), Box::new(|| None)));
+            }
+            Err(error) => print_error(format!("Failed to load module {}: {}", path.display(), error)),
+        }
+    }
+    modules
+}
 
 fn main() {
     init_tokio();
     env_logger::init();
-    let listener = 
+
+    // Resolve configuration: --config, then MWAY_SERVER_CONFIG, then per-user default
+    let resolver = PathResolver::from_environment();
+    let configuration_path = resolver.resolve_server_configuration(take_config_option(std::env::args().collect()).as_deref());
+    let configuration = ServerConfiguration::load(&configuration_path.path);
+    if configuration.is_none(){
+        print_error(format!("Can not read configuration from {}", configuration_path.path.display()));
+        exit(-1);
+    }
+    let mut configuration = configuration.unwrap();
+    let listener_address = match configuration.get_listener_address() {
+        Some(address) => address,
+        None => {
+            print_error("listener.address is not set");
+            exit(-1);
+        }
+    };
+    let (signing_serial, encryption_serial) = match configuration.get_identity_serials() {
+        Some(serials) => serials,
+        None => {
+            print_error("identity.signing_serial and identity.encryption_serial must be set");
+            exit(-1);
+        }
+    };
+    let host_id = configuration.get_host_id();
+    let storage_path = resolver.resolve_storage(configuration.get_storage_path()).path;
+    let certificate_store_path = storage_path.join(Path::new("certs.dat"));
+    let key_store_path = configuration.get_key_store_path().map(Path::to_path_buf)
+        .unwrap_or_else(|| storage_path.join(Path::new("keys.dat")));
+    let dead_letter_store_path = storage_path.join(Path::new("deadletter.dat"));
+    let modules_path = resolver.resolve_modules(configuration.get_modules_path()).path;
+
+    // Stores are migrated before they are loaded
+    let mut migrator = Migrator::new();
+    migrator.register::<AsyncCertificateServiceImpl>(&certificate_store_path)
+        .register::<GroupServiceImpl>(&storage_path.join(Path::new("groups.dat")))
+        .register::<AccessControl>(&storage_path.join(Path::new("access.dat")))
+        .register::<PeerPins>(&storage_path.join(Path::new("pins.dat")))
+        .register::<ModuleStateStore>(&storage_path.join(Path::new("state.dat")))
+        .register::<DeadLetterQueue>(&dead_letter_store_path)
+        .register::<SequenceStore>(&storage_path.join(Path::new("sequences.dat")));
+    match migrator.run(false) {
+        Ok(reports) => {
+            for report in reports.iter().filter(|report| !report.is_up_to_date()){
+                log::info!("Migrated {} storage from v{} to v{}", report.store, report.from_version, report.to_version);
+            }
+        }
+        Err(error) => {
+            print_error(format!("Can not migrate storage: {}", error));
+            exit(-1);
+        }
+    }
+
+    // Create data bus, it starts certificate service
+    let mut data_bus = ServerDataBus::new(host_id, &storage_path, &key_store_path, configuration.is_read_only());
+    let mut certificates = data_bus.get_certificate_service();
+
+    // Decrypt secrets of configuration before anything uses them
+    let mut secrets = SecretResolver::from_environment();
+    secrets.add_certificates_from(certificates.as_mut());
+    if let Err(error) = configuration.resolve_secrets(&secrets){
+        print_error(format!("Can not decrypt configuration value {}", error));
+        exit(-1);
+    }
+    if let Some(exporter) = configuration.get_span_exporter(){
+        trace::set_exporter(exporter);
+    }
+    let (signing_certificate, encryption_certificate) = match (certificates.get_signing_certificate(signing_serial),
+                                                               certificates.get_encryption_certificate(encryption_serial)) {
+        (Some(signing), Some(encryption)) if signing.secret_key.is_some() && encryption.secret_key.is_some() => {
+            (signing, encryption)
+        }
+        _ => {
+            print_error("Certificates of identity are not found or have no secret keys");
+            exit(-1);
+        }
+    };
+    data_bus.set_certificate_pool_size(configuration.get_certificate_pool_size());
+    data_bus.set_listener_endpoints(vec![listener_address.clone()]);
+    let name_exchange = PeerExchangeNameBackend::new();
+    data_bus.set_name_resolver(configuration.get_name_resolver(&name_exchange));
+    let detached_certificates = data_bus.get_detached_certificate_service();
+
+    // Policies of transport service apply to messages received from peers
+    let router = Router::new_shared(host_id);
+    let events = ConnectionEvents::new_shared();
+    let alerts = CryptoAlerts::new_shared();
+    let raw_frame_tap = RawFrameTap::new_shared(configuration.is_raw_frame_tap_enabled());
+    let dead_letters = DeadLetterQueue::open_shared(dead_letter_store_path.to_str().unwrap());
+    let rate_limiter = configuration.get_rate_limit_policy().map(RateLimiter::new_shared);
+    let transport = data_bus.get_local_transport();
+    transport.set_remote_sender(Box::new(RouterSender::new(router.clone())));
+    transport.set_connection_events(events.clone());
+    transport.set_crypto_alerts(alerts.clone());
+    transport.set_raw_frame_tap(raw_frame_tap.clone());
+    transport.set_dead_letter_queue(dead_letters.clone());
+    if let Some(limiter) = &rate_limiter{
+        transport.set_rate_limiter(limiter.clone());
+    }
+    if let Some(policy) = configuration.get_signature_policy(){
+        transport.set_signature_policy(Arc::new(Mutex::new(policy)), Box::new(detached_certificates.clone()));
+    }
+    let mut transport = data_bus.get_transport_service();
+    transport.subscribe_to_messages(&MessageFilter::new(),
+                                    Box::new(NameExchangeListener::new(name_exchange, data_bus.get_certificate_service())));
+    if let Some(policy) = configuration.get_remote_certificate_policy(){
+        let server = RemoteCertificateServer::new(data_bus.get_certificate_service(), transport.get_sender(), policy,
+                                                  host_id);
+        transport.subscribe_to_messages(&MessageFilter::new(), Box::new(server));
+    }
+    let shaper = configuration.get_shaping_limits().map(BandwidthShaper::new_shared);
+    if let Some(shaper) = &shaper{
+        let server = BandwidthControlServer::new(shaper.clone(), Box::new(detached_certificates.clone()),
+                                                 transport.get_sender(), host_id);
+        transport.subscribe_to_messages(&MessageFilter::new(), Box::new(server));
+    }
+
+    // Load modules, built-in ones go first, so libraries of the same modules are refused
+    let mut registry = ModuleRegistry::new();
+    for module in get_builtin_modules(){
+        if let Err(error) = registry.register_builtin(module){
+            print_error(format!("Built-in module is not loaded: {}", error));
+        }
+    }
+    let mut supervised = registry.into_modules();
+    supervised.extend(load_modules_from(&modules_path, &configuration, certificates.as_mut(), &storage_path));
+    data_bus.set_loaded_modules(supervised.iter().map(|module| module.get_status().name.clone()).collect());
+    let mut shutdown = ShutdownController::new();
+    shutdown.register(ShutdownStage::Flush, Box::new(CertificateFlushHook::new(data_bus.get_certificate_service())));
+    let bus = data_bus.clone();
+    let data_bus_provider: DataBusProvider = Arc::new(move || Box::new(bus.clone()) as Box<dyn ModuleDataBus>);
+    let restart_policy = configuration.get_restart_policy();
+    for module in supervised.iter_mut(){
+        module.set_restart_policy(restart_policy.clone());
+        if !module.load(data_bus_provider.clone()){
+            print_error(format!("Module {} panicked while loading: {}", module.get_status().name,
+                                module.get_status().last_error.clone().unwrap_or_default()));
+        }
+    }
+    let module_reports = Arc::new(Mutex::new(get_module_reports(&supervised)));
+    let supervised = Arc::new(Mutex::new(supervised));
+    let is_stopped = Arc::new(AtomicBool::new(false));
+    let supervisor = {
+        let (modules, reports, is_stopped) = (supervised.clone(), module_reports.clone(), is_stopped.clone());
+        std::thread::spawn(move || run_module_supervisor(modules, reports, is_stopped))
+    };
+
+    // Sessions: peers are authorized by controller of its own thread, then transformers are negotiated
+    let authority_bus = data_bus.clone();
+    let expected_identities = Arc::new(Mutex::new(configuration.get_expected_identities()));
+    // Peers whose certificates require second factor are challenged with the first one
+    let factors = configuration.get_authentication_factors();
+    let authority = AuthorizationAuthority::spawn(move || {
+        let mut controller = AuthorizationController::new(authority_bus.get_certificate_service());
+        for factor in factors{
+            controller.add_factor(factor);
+        }
+        controller.set_expected_identities(expected_identities);
+        controller.set_access_control(authority_bus.get_access_control().unwrap());
+        controller.set_peer_pins(authority_bus.get_peer_pins().unwrap());
+        controller
+    });
+    let mut stack = TransformerStack::new();
+    stack.set_checksum_mode(configuration.get_checksum_mode());
+    // Encrypted data does not compress, so compression goes first
+    if let Some(policy) = configuration.get_compression_policy(){
+        stack.add_factory(Box::new(CompressionTransformerFactory::new(signing_serial, policy)));
+    }
+    let mut crypto = CryptoTransformerFactory::new(signing_certificate, encryption_certificate,
+                                                   Box::new(detached_certificates.clone()));
+    crypto.set_failure_threshold(configuration.get_crypto_failure_threshold());
+    crypto.set_alerts(alerts);
+    stack.add_factory(Box::new(crypto));
+    let mut handshake = SessionHandshake::new(host_id, signing_serial, encryption_serial, authority);
+    handshake.set_version_policy(configuration.get_version_policy());
+    handshake.set_stack(stack);
+
+    // Connections deliver messages for daemon on a thread of its own and route the rest
+    let keepalive = configuration.get_keepalive_policy();
+    let delivery = LocalDelivery::spawn(data_bus.get_local_transport().clone());
+    let mut link = PeerLink::new(router.clone(), delivery.clone(), keepalive.clone());
+    if let Some(limiter) = rate_limiter{
+        link.set_rate_limiter(limiter);
+    }
+    let mut reaper = ConnectionReaper::new(keepalive);
+    let reaped_router = router.clone();
+    reaper.set_connection_events(events.clone());
+    reaper.add_cleanup(Box::new(move |connection_id| {
+        reaped_router.lock().unwrap().remove_connection(connection_id as u64);
+    }));
+    let reaper = Arc::new(Mutex::new(reaper));
+    let frame_stats = FrameStats::new_shared(configuration.is_frame_stats_enabled());
+    let mut handler = ConnectionHandler::new(handshake, link);
+    handler.set_handshake_timeouts(configuration.get_handshake_timeouts(), HandshakeMetrics::new_shared())
+        .set_frame_stats(frame_stats.clone())
+        .set_raw_frame_tap(raw_frame_tap)
+        .set_parsing_mode(configuration.get_parsing_mode())
+        .set_reaper(reaper.clone())
+        .set_connection_events(events)
+        .set_dead_letter_queue(dead_letters);
+    if let Some(shaper) = shaper{
+        handler.set_shaper(shaper);
+    }
+    let control = DaemonControl::new(data_bus.clone(), router, delivery, detached_certificates.clone(),
+                                     handler.get_draining_flag(), module_reports);
+    let handler = Arc::new(handler);
+
+    // Control channels for operators and external integrations
+    let mut admin = AdminServer::new(detached_certificates.clone(), Box::new(control.clone()));
+    admin.set_frame_stats(frame_stats);
+    if let Some(factor) = configuration.get_admin_os_user_factor(){
+        admin.set_os_user_factor(factor);
+    }
+    let admin_socket_path = configuration.get_admin_socket_path();
+    if let Err(error) = admin.listen(&admin_socket_path){
+        print_error(format!("Can not listen on admin socket {}: {}", admin_socket_path.display(), error));
+    }
+    if let Some(address) = configuration.get_gateway_address(){
+        let mut gateway = GatewayServer::new(detached_certificates.clone(), Box::new(control));
+        for token in configuration.get_gateway_tokens(){
+            gateway.add_token(token);
+        }
+        gateway.set_name_service(data_bus.get_name_service());
+        if let Err(error) = gateway.listen(&address){
+            print_error(format!("Can not start gateway on {}: {}", address, error));
+        }
+    }
+
+    // Watchers of certificates run on threads of their own and stop once dropped
+    let shared_certificates = Arc::new(Mutex::new(Box::new(detached_certificates)));
+    let _directory_watcher = configuration.get_certificate_import_directory().and_then(|directory| {
+        CertificateDirectoryWatcher::start(directory, shared_certificates.clone())
+            .map_err(|error| print_error(format!("Can not watch {}: {}", directory.display(), error))).ok()
+    });
+    let _store_watcher = match configuration.is_certificate_store_watched() {
+        true => CertificateStoreWatcher::start(&certificate_store_path, shared_certificates.clone())
+            .map_err(|error| print_error(format!("Can not watch certificate store: {}", error))).ok(),
+        false => None,
+    };
+
+    let manager = Arc::new(configuration.get_connection_manager());
+    let name_resolver = data_bus.get_name_resolver();
+    tokio_block_on(async move {
+        let listener = match tokio::net::TcpListener::bind(&listener_address).await {
+            Ok(listener) => listener,
+            Err(error) => {
+                print_error(format!("Can not listen on {}: {}", listener_address, error));
+                return;
+            }
+        };
+        log::info!("Listening on {}", listener_address);
+        tokio::spawn(run_reaper(reaper));
+        if let Some(policy) = configuration.get_certificate_gc_policy(){
+            tokio::spawn(run_certificate_gc(shared_certificates, policy));
+        }
+        for peer_id in configuration.get_outbound_peers(){
+            tokio::spawn(connect_peer(handler.clone(), manager.clone(), name_resolver.clone(), peer_id));
+        }
+        listen(handler, listener).await;
+    });
+
+    is_stopped.store(true, Ordering::Relaxed);
+    if supervisor.join().is_err(){
+        print_error("Supervisor of modules panicked");
+    }
+    for module in supervised.lock().unwrap().iter_mut(){
+        module.unload();
+    }
+    let report = shutdown.shutdown();
+    for failure in report.failures.iter(){
+        print_error(format!("Shutdown step {} failed: {}", failure.hook, failure.error));
+    }
+    if !report.is_clean(){
+        exit(-1);
+    }
 }
@@ -0,0 +1,61 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use libmilkyway::controllers::admin::ModuleReport;
+use libmilkyway::module::registry::BuiltinModule;
+use libmilkyway::module::supervisor::SupervisedModule;
+use libmilkyway::tokio::init_tokio;
+
+/// How often faults of modules are checked and failed modules are restarted
+pub const MODULE_SUPERVISION_INTERVAL: Duration = Duration::from_millis(500);
+
+///
+/// Health of modules reported to operators
+///
+pub type SharedModuleReports = Arc<Mutex<Vec<ModuleReport>>>;
+
+///
+/// Gets modules compiled into daemon, each is enabled by cargo feature of the same name
+///
+pub fn get_builtin_modules() -> Vec<BuiltinModule>{
+    vec![
+        #[cfg(feature = "certman")]
+        certman::BUILTIN_MODULE,
+        #[cfg(feature = "ping")]
+        ping::BUILTIN_MODULE,
+        #[cfg(feature = "inventory")]
+        inventory::BUILTIN_MODULE,
+    ]
+}
+
+///
+/// Gets reports of modules
+///
+pub fn get_module_reports(modules: &[SupervisedModule]) -> Vec<ModuleReport>{
+    modules.iter().map(|module| ModuleReport::from(module.get_status())).collect()
+}
+
+///
+/// Fails modules whose listeners panicked and restarts them once their backoff elapses, until
+/// daemon stops. Daemon calls modules only through their listeners, so this is the only place
+/// modules are supervised. Runs on a thread of its own, so modules are restarted outside of
+/// runtime like they are loaded on start.
+///
+/// # Arguments
+/// * modules: Arc<Mutex<Vec<SupervisedModule>>>: modules of daemon
+/// * reports: SharedModuleReports: reports refreshed after every check
+/// * is_stopped: Arc<AtomicBool>: set once daemon shuts down
+///
+pub fn run_module_supervisor(modules: Arc<Mutex<Vec<SupervisedModule>>>, reports: SharedModuleReports,
+                             is_stopped: Arc<AtomicBool>){
+    init_tokio();
+    while !is_stopped.load(Ordering::Relaxed){
+        let mut modules = modules.lock().unwrap();
+        for module in modules.iter_mut(){
+            module.supervise();
+        }
+        *reports.lock().unwrap() = get_module_reports(&modules);
+        drop(modules);
+        std::thread::sleep(MODULE_SUPERVISION_INTERVAL);
+    }
+}
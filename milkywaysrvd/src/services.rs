@@ -0,0 +1,118 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use libmilkyway::controllers::admin::{AdminHandler, DaemonStatus};
+use libmilkyway::controllers::gateway::GatewayHandler;
+use libmilkyway::message::common::Message;
+use libmilkyway::message::types::MessageType;
+use libmilkyway::module::ModuleDataBus;
+use libmilkyway::serialization::deserializable::Deserializable;
+use libmilkyway::services::certificate::{CertificateService, CertificateServiceBinder};
+use libmilkyway::services::certificate::detached::DetachedCertificateService;
+use libmilkyway::services::name::exchange::{NameExchangeMessage, PeerExchangeNameBackend};
+use libmilkyway::transport::TransportListener;
+use libmilkyway::transport::router::{LocalDelivery, SharedRouter};
+use crate::bus::ServerDataBus;
+use crate::modules::SharedModuleReports;
+
+///
+/// Answers admin and gateway requests about daemon and sends messages of gateway
+///
+#[derive(Clone)]
+pub struct DaemonControl{
+    started: Instant,
+    bus: ServerDataBus,
+    router: SharedRouter,
+    /** Messages gateway sends to daemon itself are delivered as received ones **/
+    delivery: LocalDelivery,
+    certificates: DetachedCertificateService,
+    is_draining: Arc<AtomicBool>,
+    modules: SharedModuleReports,
+}
+
+impl DaemonControl {
+    pub fn new(bus: ServerDataBus, router: SharedRouter, delivery: LocalDelivery,
+               certificates: DetachedCertificateService, is_draining: Arc<AtomicBool>,
+               modules: SharedModuleReports) -> DaemonControl{
+        DaemonControl{
+            started: Instant::now(),
+            bus,
+            router,
+            delivery,
+            certificates,
+            is_draining,
+            modules,
+        }
+    }
+}
+
+impl AdminHandler for DaemonControl{
+    fn get_status(&mut self) -> DaemonStatus {
+        DaemonStatus{
+            uptime_seconds: self.started.elapsed().as_secs(),
+            peers: self.router.lock().unwrap().get_peers(),
+            modules: self.modules.lock().unwrap().clone(),
+            is_draining: self.is_draining.load(Ordering::Relaxed),
+            log_level: log::max_level().to_string(),
+        }
+    }
+
+    fn reload(&mut self) -> Result<String, String> {
+        let report = self.certificates.reload().map_err(|error| error.to_string())?;
+        Ok(format!("Reloaded certificate store: {} changes, {} conflicts", report.changes.len(),
+                   report.conflicts.len()))
+    }
+
+    fn drain(&mut self) -> Result<String, String> {
+        self.is_draining.store(true, Ordering::Relaxed);
+        Ok(format!("Draining {} connections", self.router.lock().unwrap().get_peers().len()))
+    }
+}
+
+impl GatewayHandler for DaemonControl{
+    fn get_status(&mut self) -> DaemonStatus {
+        AdminHandler::get_status(self)
+    }
+
+    fn send_message(&mut self, message: Message) -> Result<(), String> {
+        // Threads of gateway have no runtime, so listeners of modules must not run on them
+        if message.destination == self.bus.get_host_id().unwrap(){
+            self.delivery.deliver(message);
+            return Ok(());
+        }
+        self.bus.get_transport_service().try_send_message(message).map_err(|error| error.to_string())
+    }
+}
+
+///
+/// Accepts name records gossiped by peers into exchange backend of name resolver
+///
+pub struct NameExchangeListener{
+    backend: PeerExchangeNameBackend,
+    certificates: Box<CertificateServiceBinder>,
+}
+
+impl NameExchangeListener {
+    pub fn new(backend: PeerExchangeNameBackend, certificates: Box<CertificateServiceBinder>) -> NameExchangeListener{
+        NameExchangeListener{
+            backend,
+            certificates,
+        }
+    }
+}
+
+impl TransportListener for NameExchangeListener{
+    fn on_message(&mut self, message: Message) {
+        if message.message_type != MessageType::NameExchange{
+            return;
+        }
+        match message.data.as_ref().map(NameExchangeMessage::from_serialized) {
+            Some(Ok((exchange, _))) => {
+                let accepted = self.backend.accept_message(self.certificates.as_mut(), exchange);
+                log::debug!("Accepted {} name records from {}", accepted.len(), message.source);
+            }
+            _ => log::warn!("Malformed name exchange message from {}", message.source),
+        }
+    }
+}
+
@@ -0,0 +1,366 @@
+/* Topologies of milkywaysrvd and CLI processes for integration tests */
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+use yaml_rust2::YamlLoader;
+
+///
+/// Serial of operator certificate clients sign their requests with
+///
+pub const OPERATOR_CERTIFICATE_SERIAL: u128 = 500;
+
+///
+/// ID server is known to clients by
+///
+pub const SERVER_ID: u128 = 1;
+
+///
+/// How long server is waited for to start listening and to log connections
+///
+pub const STARTUP_TIMEOUT: Duration = Duration::from_secs(20);
+
+///
+/// Environment variable with path to CLI binary, CLI is built with cargo if it is not set
+///
+pub const CLI_BINARY_VARIABLE: &str = "MWAY_TEST_CLI";
+
+/// Count of topologies built by test process
+static TOPOLOGIES: AtomicU32 = AtomicU32::new(0);
+
+/// Variables of paths of nodes, they must not leak into processes of topology
+const PATH_VARIABLES: [&str; 4] = ["MWAY_CONFIG", "MWAY_STORAGE_PATH", "MWAY_MODULES_PATH", "MWAY_SERVER_CONFIG"];
+
+///
+/// Gets CLI binary, building it with the same modules as daemon under test
+///
+fn get_cli_binary() -> &'static Path{
+    static CLI_BINARY: OnceLock<PathBuf> = OnceLock::new();
+    CLI_BINARY.get_or_init(|| {
+        if let Some(path) = std::env::var_os(CLI_BINARY_VARIABLE){
+            return PathBuf::from(path);
+        }
+        // Daemon lies in <target>/<profile>, CLI is built next to it
+        let profile_directory = Path::new(env!("CARGO_BIN_EXE_milkywaysrvd")).parent().unwrap();
+        let target_directory = profile_directory.parent().unwrap();
+        let mut features = Vec::new();
+        for (feature, is_enabled) in [("certman", cfg!(feature = "certman")), ("ping", cfg!(feature = "ping")),
+                                      ("inventory", cfg!(feature = "inventory"))]{
+            if is_enabled{
+                features.push(feature);
+            }
+        }
+        let mut command = Command::new(env!("CARGO"));
+        command.arg("build")
+            .arg("--manifest-path").arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("../milkywaycli/Cargo.toml"))
+            .arg("--target-dir").arg(target_directory)
+            .arg("--features").arg(features.join(","));
+        if !cfg!(debug_assertions){
+            command.arg("--release");
+        }
+        let status = command.status().expect("Can not run cargo");
+        assert!(status.success(), "Can not build CLI");
+        profile_directory.join("milkywaycli")
+    })
+}
+
+///
+/// Gets free port of localhost. Port is released before it is returned, so it is free
+/// unless someone else takes it before daemon listens on it.
+///
+fn get_free_port() -> u16{
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+///
+/// Appends lines to YAML configuration written by `mway init`
+///
+fn append_configuration(path: &Path, lines: &str){
+    let mut file = OpenOptions::new().append(true).open(path).unwrap();
+    file.write_all(lines.as_bytes()).unwrap();
+}
+
+///
+/// Output of CLI command
+///
+pub struct CommandResult{
+    pub is_success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl From<Output> for CommandResult {
+    fn from(output: Output) -> Self {
+        CommandResult{
+            is_success: output.status.success(),
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        }
+    }
+}
+
+impl CommandResult {
+    ///
+    /// Asserts that command succeeded and printed every given line part
+    ///
+    pub fn expect_output(&self, parts: &[&str]){
+        assert!(self.is_success, "Command failed:\n{}\n{}", self.stdout, self.stderr);
+        for part in parts{
+            assert!(self.stdout.contains(part), "No '{}' in output:\n{}\n{}", part, self.stdout, self.stderr);
+        }
+    }
+}
+
+///
+/// Node of topology: its directory with storage, modules and configuration of CLI
+///
+pub struct TestNode{
+    directory: PathBuf,
+    peer_id: u128,
+}
+
+impl TestNode {
+    fn get_configuration_path(&self) -> PathBuf{
+        self.directory.join("mway.yml")
+    }
+
+    #[inline]
+    pub fn get_peer_id(&self) -> u128{
+        self.peer_id
+    }
+
+    fn command(&self) -> Command{
+        let mut command = Command::new(get_cli_binary());
+        command.arg(format!("--config={}", self.get_configuration_path().display()))
+            .env("HOME", &self.directory)
+            .stdin(Stdio::null());
+        for variable in PATH_VARIABLES{
+            command.env_remove(variable);
+        }
+        command
+    }
+
+    ///
+    /// Runs CLI command of node, e.g. `inventory/show peer=1`
+    ///
+    /// # Arguments
+    /// * arguments: &[&str]: command and its arguments
+    ///
+    pub fn run(&self, arguments: &[&str]) -> CommandResult{
+        self.command().args(arguments).output().expect("Can not run CLI").into()
+    }
+
+    ///
+    /// Initializes node with `mway init`
+    ///
+    fn init(directory: PathBuf, arguments: &[&str], server_configuration: Option<&Path>) -> TestNode{
+        fs::create_dir_all(&directory).unwrap();
+        let mut node = TestNode{
+            directory,
+            peer_id: 0,
+        };
+        let storage = format!("storage={}", node.directory.join("store").display());
+        let modules = format!("modules={}", node.directory.join("modules").display());
+        let mut command = node.command();
+        command.args(["init", &storage, &modules, "non-interactive", "force"]).args(arguments);
+        if let Some(path) = server_configuration{
+            command.env("MWAY_SERVER_CONFIG", path);
+        }
+        let result: CommandResult = command.output().expect("Can not run CLI").into();
+        assert!(result.is_success, "Can not initialize node:\n{}\n{}", result.stdout, result.stderr);
+        // Identity of server is written into configuration of daemon
+        let configuration_path = server_configuration.map_or(node.get_configuration_path(), Path::to_path_buf);
+        let configuration = fs::read_to_string(configuration_path).unwrap();
+        let configuration = YamlLoader::load_from_str(&configuration).unwrap();
+        node.peer_id = configuration[0]["identity"]["id"].as_str().and_then(|id| id.parse().ok()).unwrap();
+        node
+    }
+}
+
+///
+/// Running milkywaysrvd listening on localhost and clients authorized by it. Daemon is
+/// killed and files of topology are removed once it is dropped.
+///
+pub struct TestTopology{
+    directory: PathBuf,
+    address: String,
+    process: Child,
+    clients: Vec<TestNode>,
+}
+
+impl TestTopology {
+    pub fn builder() -> TopologyBuilder{
+        TopologyBuilder::new()
+    }
+
+    #[inline]
+    pub fn get_address(&self) -> &str{
+        &self.address
+    }
+
+    #[inline]
+    pub fn client(&self, index: usize) -> &TestNode{
+        &self.clients[index]
+    }
+
+    #[inline]
+    pub fn get_clients(&self) -> &Vec<TestNode>{
+        &self.clients
+    }
+
+    ///
+    /// Gets log of daemon
+    ///
+    pub fn get_server_log(&self) -> String{
+        fs::read_to_string(self.directory.join("milkywaysrvd.log")).unwrap_or_default()
+    }
+
+    ///
+    /// Waits until daemon logs line containing given text
+    ///
+    pub fn expect_server_log(&self, text: &str){
+        let started = Instant::now();
+        while !self.get_server_log().contains(text){
+            assert!(started.elapsed() < STARTUP_TIMEOUT, "No '{}' in log of daemon:\n{}", text, self.get_server_log());
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    fn wait_listening(&mut self){
+        let started = Instant::now();
+        while TcpStream::connect(&self.address).is_err(){
+            if let Ok(Some(status)) = self.process.try_wait(){
+                panic!("Daemon exited with {}:\n{}", status, self.get_server_log());
+            }
+            assert!(started.elapsed() < STARTUP_TIMEOUT, "Daemon does not listen:\n{}", self.get_server_log());
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+impl Drop for TestTopology {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        if !std::thread::panicking(){
+            let _ = fs::remove_dir_all(&self.directory);
+        }
+    }
+}
+
+///
+/// Builds topology of one server and clients on ephemeral port of localhost. Fixture
+/// certificates are generated for every topology: server creates root, certificates of
+/// its own and operator certificate, clients get certificates under the same root and
+/// import operator certificate, so their requests are signed.
+///
+pub struct TopologyBuilder{
+    clients: usize,
+    untrusted_clients: usize,
+}
+
+impl TopologyBuilder {
+    pub fn new() -> TopologyBuilder{
+        TopologyBuilder{
+            clients: 0,
+            untrusted_clients: 0,
+        }
+    }
+
+    ///
+    /// Sets count of clients, they are initialized with root of server
+    ///
+    pub fn with_clients(mut self, count: usize) -> TopologyBuilder{
+        self.clients = count;
+        self
+    }
+
+    ///
+    /// Sets count of clients initialized with roots of their own, server does not trust them.
+    /// They follow trusted clients.
+    ///
+    pub fn with_untrusted_clients(mut self, count: usize) -> TopologyBuilder{
+        self.untrusted_clients = count;
+        self
+    }
+
+    ///
+    /// Initializes nodes and starts daemon
+    ///
+    /// returns: TestTopology: topology with daemon listening
+    ///
+    pub fn build(self) -> TestTopology{
+        // Tests of one binary run in parallel, so every topology gets directory of its own
+        let directory = std::env::temp_dir().join(format!("milkyway-topology-{}-{}", std::process::id(),
+                                                          TOPOLOGIES.fetch_add(1, Ordering::Relaxed)));
+        let address = format!("127.0.0.1:{}", get_free_port());
+        let server_configuration = directory.join("server").join("mway-server.yml");
+        let server = TestNode::init(directory.join("server"),
+                                    &["root-name=root", "name=server", "role=server",
+                                      &format!("listen={}", address)], Some(&server_configuration));
+        append_configuration(&server_configuration, &format!("admin:\n  socket: {}\n",
+                                                             directory.join("admin.sock").display()));
+        let root_file = directory.join("root.bin");
+        let operator_file = directory.join("operator.bin");
+        let serial = OPERATOR_CERTIFICATE_SERIAL.to_string();
+        server.run(&["certman/root/export", &format!("file={}", root_file.display()), "unsigned"])
+            .expect_output(&[]);
+        server.run(&["certman/signing/generate", "parent=0", "name=operator", "flags=user-cert,sign-messages",
+                     &format!("serial={}", serial)]).expect_output(&[]);
+        server.run(&["certman/signing/export", &format!("serial={}", serial),
+                     &format!("file={}", operator_file.display()), "unsigned"]).expect_output(&[]);
+
+        let mut clients = Vec::new();
+        for index in 0..self.clients + self.untrusted_clients{
+            let name = format!("client{}", index);
+            let root = match index < self.clients {
+                true => format!("root-file={}", root_file.display()),
+                false => format!("root-name={}-root", name),
+            };
+            let client = TestNode::init(directory.join(&name),
+                                        &[&root, &format!("name={}", name), "role=client",
+                                          &format!("server={}", address)], None);
+            if index < self.clients{
+                client.run(&["certman/signing/import", &format!("file={}", operator_file.display())])
+                    .expect_output(&[]);
+                append_configuration(&client.get_configuration_path(),
+                                     &format!("operator_certificate: \"{}\"\nadmin_socket: {}\n", serial,
+                                              directory.join("admin.sock").display()));
+            }
+            clients.push(client);
+        }
+
+        let log = fs::File::create(directory.join("milkywaysrvd.log")).unwrap();
+        let mut command = Command::new(env!("CARGO_BIN_EXE_milkywaysrvd"));
+        command.arg(format!("--config={}", server_configuration.display()))
+            .env("HOME", &server.directory)
+            .env("RUST_LOG", "info")
+            .stdin(Stdio::null())
+            .stdout(log.try_clone().unwrap())
+            .stderr(log);
+        for variable in PATH_VARIABLES{
+            command.env_remove(variable);
+        }
+        let process = command.spawn().expect("Can not start milkywaysrvd");
+        let mut topology = TestTopology{
+            directory,
+            address,
+            process,
+            clients,
+        };
+        topology.wait_listening();
+        topology
+    }
+}
+
+impl Default for TopologyBuilder {
+    fn default() -> Self {
+        TopologyBuilder::new()
+    }
+}
+
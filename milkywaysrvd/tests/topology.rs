@@ -0,0 +1,64 @@
+// Helpers of module messages are only used with inventory module
+#[allow(dead_code)]
+mod common;
+
+use std::thread;
+use common::{TestTopology, OPERATOR_CERTIFICATE_SERIAL};
+
+#[test]
+fn test_clients_authorized() {
+    let topology = TestTopology::builder().with_clients(3).build();
+    // Clients connect at once, each one through session of its own
+    thread::scope(|scope| {
+        for client in topology.get_clients(){
+            scope.spawn(|| client.run(&["modules", "status"]).expect_output(&[]));
+        }
+    });
+    for client in topology.get_clients(){
+        topology.expect_server_log(&format!("Peer {} connected from", client.get_peer_id()));
+    }
+    // Operator of clients is trusted by daemon
+    let signer = format!("signer={}", OPERATOR_CERTIFICATE_SERIAL);
+    topology.client(0).run(&["daemon", "status", &signer]).expect_output(&["UPTIME", "MODULE"]);
+}
+
+#[test]
+fn test_untrusted_client_rejected() {
+    let topology = TestTopology::builder().with_clients(1).with_untrusted_clients(1).build();
+    let untrusted = topology.client(1);
+    let result = untrusted.run(&["modules", "status"]);
+    assert!(result.stdout.contains("Working offline") || result.stderr.contains("Working offline"),
+            "Client is not rejected:\n{}\n{}", result.stdout, result.stderr);
+    topology.expect_server_log("Can not establish session with");
+    assert!(!topology.get_server_log().contains(&format!("Peer {} connected", untrusted.get_peer_id())));
+    // Rejected client does not affect others
+    topology.client(0).run(&["modules", "status"]).expect_output(&[]);
+    topology.expect_server_log(&format!("Peer {} connected from", topology.client(0).get_peer_id()));
+}
+
+#[cfg(feature = "inventory")]
+#[test]
+fn test_module_messages_delivered() {
+    use common::SERVER_ID;
+
+    let topology = TestTopology::builder().with_clients(2).build();
+    // Request of inventory module goes to daemon and its signed answer comes back
+    let peer = format!("peer={}", SERVER_ID);
+    for client in topology.get_clients(){
+        client.run(&["inventory/show", &peer]).expect_output(&[
+            &format!("{} ({})", SERVER_ID, SERVER_ID),
+            "inventory",
+            topology.get_address(),
+        ]);
+    }
+}
+
+#[cfg(feature = "ping")]
+#[test]
+fn test_daemon_answers_ping() {
+    use common::SERVER_ID;
+
+    let topology = TestTopology::builder().with_clients(1).build();
+    let target = format!("target={}", SERVER_ID);
+    topology.client(0).run(&["ping", &target, "timeout=10000"]).expect_output(&[&format!("Pong from {}", SERVER_ID)]);
+}
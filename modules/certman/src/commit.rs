@@ -0,0 +1,23 @@
+use libmilkyway::cli::output;
+use libmilkyway::cli::arguments::parse_arguments;
+use libmilkyway::services::certificate::{CertificateService, CertificateServiceBinder};
+
+// Arguments of command(those ones in argmap)
+// * wait -- also accepted as `--wait`, flush commit queue and block until changes are durable
+pub fn commit_certificates(binder: &mut Box<CertificateServiceBinder>, arguments: Vec<String>){
+    let argmap = parse_arguments(arguments);
+    let wait = argmap.contains_key("wait") || argmap.contains_key("--wait");
+    if !wait{
+        if !binder.is_dirty(){
+            output::info("Nothing to commit");
+            return;
+        }
+        binder.commit();
+        output::info("Changes of certificates are committed");
+        return;
+    }
+    match binder.flush() {
+        Ok(()) => output::info("Changes of certificates are durable"),
+        Err(error) => output::coded_error(format!("Changes of certificates are not committed: {}", error), &error),
+    }
+}
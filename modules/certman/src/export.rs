@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::path::Path;
+use libmilkyway::cli::output;
+use libmilkyway::pki::export::{ExportFile, SignedExport};
+use libmilkyway::pki::impls::certificates::falcon1024::Falcon1024RootCertificate;
+use libmilkyway::pki::impls::keys::falcon1024::{Falcon1024PublicKey, Falcon1024SecretKey};
+use libmilkyway::pki::keyexport::{EncryptedSecretKey, KEY_PASSPHRASE_VARIABLE};
+use libmilkyway::pki::certificate::Certificate;
+use libmilkyway::secrets::DEFAULT_KDF_ITERATIONS;
+use libmilkyway::serialization::serializable::Serializable;
+use libmilkyway::services::certificate::{CertificateService, CertificateServiceBinder, ROOT_CERTIFICATE_SERIAL};
+
+// Arguments of export commands(those ones in argmap)
+// * signer -- serial of certificate which signs export, root by default
+// * unsigned -- write bare object without envelope
+pub fn write_export<T: Serializable>(binder: &mut Box<CertificateServiceBinder>,
+                                     argmap: &HashMap<String, Option<String>>, content: &T, file: &str) -> bool{
+    if argmap.contains_key("unsigned"){
+        output::warning("Export is not signed, its origin can not be verified on import");
+        return match content.dump(file) {
+            Ok(_) => true,
+            Err(_) => {
+                output::error("Can not save file");
+                false
+            }
+        };
+    }
+    let signer = match argmap.get("signer") {
+        None => ROOT_CERTIFICATE_SERIAL,
+        Some(signer) => match signer.as_ref().map(|signer| signer.parse::<u128>()) {
+            Some(Ok(signer)) => signer,
+            _ => {
+                output::error("Argument 'signer' must be a serial number");
+                return false;
+            }
+        }
+    };
+    let export = if signer == ROOT_CERTIFICATE_SERIAL {
+        binder.get_root_certificate().map(|root| SignedExport::sign(content, &root))
+    } else {
+        binder.get_signing_certificate(signer).map(|certificate| SignedExport::sign(content, &certificate))
+    };
+    let export = match export {
+        Some(Ok(export)) => export,
+        Some(Err(_)) => {
+            output::error(format!("Certificate {} has no secret key, pass signer=<serial> or unsigned", signer));
+            return false;
+        }
+        None => {
+            output::error(format!("No certificate {} to sign export with", signer));
+            return false;
+        }
+    };
+    if export.dump_to_file(file).is_err(){
+        output::error("Can not save file");
+        return false;
+    }
+    true
+}
+
+pub fn read_export(file: &str) -> Option<ExportFile>{
+    match ExportFile::read(Path::new(file)) {
+        Ok(export) => Some(export),
+        Err(_) => {
+            output::error("Can not read file");
+            None
+        }
+    }
+}
+
+// Checks envelope of exported file, files without it are accepted with a warning
+// Arguments of import commands(those ones in argmap)
+// * max_age -- reject exports older than given count of seconds
+// `root` is used to verify exports signed by root which is not in store yet
+pub fn check_export(binder: &mut Box<CertificateServiceBinder>, argmap: &HashMap<String, Option<String>>,
+                    file: &ExportFile, root: Option<&Falcon1024RootCertificate>) -> bool{
+    let export = match file.get_signed() {
+        Some(export) => export,
+        None => {
+            output::warning("File is not signed, its origin and age can not be verified");
+            return true;
+        }
+    };
+    let signer_serial = export.provenance.signer_serial;
+    let result = if signer_serial == ROOT_CERTIFICATE_SERIAL {
+        binder.get_root_certificate().or(root.cloned()).map(|root| export.verify(&root))
+    } else {
+        binder.get_signing_certificate(signer_serial).map(|certificate| export.verify(&certificate))
+    };
+    match result {
+        Some(Ok(())) => {}
+        Some(Err(error)) => {
+            output::coded_error(&error, &error);
+            return false;
+        }
+        None => {
+            output::error(format!("Export is signed by unknown certificate {}", signer_serial));
+            return false;
+        }
+    }
+    let age = export.get_age() / 1000;
+    if let Some(max_age) = argmap.get("max_age"){
+        match max_age.as_ref().map(|max_age| max_age.parse::<u128>()) {
+            Some(Ok(max_age)) if age > max_age => {
+                output::error(format!("Export is {} seconds old, which is more than {} allowed", age, max_age));
+                return false;
+            }
+            Some(Ok(_)) => {}
+            _ => {
+                output::error("Argument 'max_age' must be a count of seconds");
+                return false;
+            }
+        }
+    }
+    output::info(format!("Export is signed by certificate {} {} seconds ago", signer_serial, age));
+    true
+}
+
+// Passphrase of exported secret key
+// Arguments of export-key and import-key commands(those ones in argmap)
+// * passphrase -- passphrase, MWAY_KEY_PASSPHRASE is used if it is not given
+pub fn get_key_passphrase(argmap: &HashMap<String, Option<String>>) -> Option<String>{
+    let passphrase = match argmap.get("passphrase") {
+        Some(Some(passphrase)) => Some(passphrase.clone()),
+        Some(None) => {
+            output::error("Argument 'passphrase' requires a value");
+            return None;
+        }
+        None => std::env::var(KEY_PASSPHRASE_VARIABLE).ok(),
+    };
+    match passphrase.filter(|passphrase| !passphrase.is_empty()) {
+        Some(passphrase) => Some(passphrase),
+        None => {
+            output::error(format!("Pass passphrase=<passphrase> or set {}", KEY_PASSPHRASE_VARIABLE));
+            None
+        }
+    }
+}
+
+// Encrypts secret key of certificate with passphrase and writes it to file
+pub fn write_key_export<C: Certificate<Falcon1024PublicKey, Falcon1024SecretKey>>(
+    argmap: &HashMap<String, Option<String>>, certificate: &C, file: &str) -> bool{
+    let passphrase = match get_key_passphrase(argmap) {
+        Some(passphrase) => passphrase,
+        None => return false,
+    };
+    let result = EncryptedSecretKey::encrypt(certificate, &passphrase, DEFAULT_KDF_ITERATIONS)
+        .and_then(|exported| exported.dump_to_file(file));
+    match result {
+        Ok(_) => {
+            output::warning("File contains secret key, keep it and its passphrase apart");
+            true
+        }
+        Err(error) => {
+            output::coded_error(format!("Can not export key: {}", error), &error);
+            false
+        }
+    }
+}
+
+// Reads secret key exported for certificate, checking that it matches public key of certificate
+pub fn read_key_export<C: Certificate<Falcon1024PublicKey, Falcon1024SecretKey>>(
+    argmap: &HashMap<String, Option<String>>, certificate: &C, file: &str) -> Option<Falcon1024SecretKey>{
+    let exported = match EncryptedSecretKey::read_from_file(Path::new(file)) {
+        Ok(exported) => exported,
+        Err(error) => {
+            output::coded_error(format!("Can not read key: {}", error), &error);
+            return None;
+        }
+    };
+    let passphrase = get_key_passphrase(argmap)?;
+    match exported.decrypt(certificate, &passphrase) {
+        Ok(secret_key) => Some(secret_key),
+        Err(error) => {
+            output::coded_error(format!("Can not import key: {}", error), &error);
+            None
+        }
+    }
+}
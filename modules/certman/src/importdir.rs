@@ -0,0 +1,88 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use libmilkyway::cli::output;
+use libmilkyway::cli::arguments::parse_arguments;
+use libmilkyway::services::certificate::CertificateServiceBinder;
+use libmilkyway::services::certificate::directory::{import_certificate_directory, CertificateDirectoryWatcher,
+                                                    DirectoryImportAuditEntry, DirectoryImportOutcome};
+use crate::utils::check_writable;
+
+// How often watch mode prints files imported by watcher
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+fn print_outcome(path: &Path, outcome: &DirectoryImportOutcome){
+    match outcome {
+        DirectoryImportOutcome::ImportedSigning(serial) =>
+            output::info(format!("{}: imported signing certificate {}", path.display(), serial)),
+        DirectoryImportOutcome::ImportedEncryption(serial) =>
+            output::info(format!("{}: imported encryption certificate {}", path.display(), serial)),
+        DirectoryImportOutcome::Known(serial) =>
+            output::info(format!("{}: certificate {} is already known, skipped", path.display(), serial)),
+    }
+}
+
+fn print_audit_entry(entry: &DirectoryImportAuditEntry){
+    match &entry.result {
+        Ok(outcome) => print_outcome(&entry.path, outcome),
+        Err(error) => output::error(format!("{}: {}", entry.path.display(), error)),
+    }
+}
+
+// Arguments of command(those ones in argmap)
+// * path -- directory with exported certificates
+// * watch -- keep importing files dropped into directory until interrupted
+pub fn import_directory(binder: Arc<Mutex<Box<CertificateServiceBinder>>>, arguments: Vec<String>){
+    let argmap = parse_arguments(arguments);
+    let directory = match argmap.get("path") {
+        Some(Some(path)) => Path::new(path).to_path_buf(),
+        _ => {
+            output::error("Argument 'path' with a value is required");
+            return;
+        }
+    };
+    if !check_writable(&mut binder.lock().unwrap()){
+        return;
+    }
+    if !argmap.contains_key("watch"){
+        let report = match import_certificate_directory(binder.lock().unwrap().as_mut(), &directory) {
+            Ok(report) => report,
+            Err(error) => {
+                output::error(format!("Can not read {}: {}", directory.display(), error));
+                return;
+            }
+        };
+        for (path, outcome) in report.imported.iter(){
+            print_outcome(path, outcome);
+        }
+        for (path, error) in report.failed.iter(){
+            output::error(format!("{}: {}", path.display(), error));
+        }
+        output::info(format!("Imported {}, skipped {} known, failed {}", report.imported.len(),
+                             report.skipped.len(), report.failed.len()));
+        return;
+    }
+    let watcher = match CertificateDirectoryWatcher::start(&directory, binder) {
+        Ok(watcher) => watcher,
+        Err(error) => {
+            output::error(format!("Can not watch {}: {}", directory.display(), error));
+            return;
+        }
+    };
+    output::info(format!("Watching {}, press Ctrl+C to stop", directory.display()));
+    let mut last_printed: Option<DirectoryImportAuditEntry> = None;
+    loop {
+        let audit = watcher.get_audit();
+        // Audit is bounded, so entries are matched by value instead of index
+        let start = last_printed.as_ref()
+            .and_then(|last| audit.iter().rposition(|entry| entry == last))
+            .map_or(0, |position| position + 1);
+        for entry in audit[start..].iter(){
+            print_audit_entry(entry);
+        }
+        if let Some(entry) = audit.last(){
+            last_printed = Some(entry.clone());
+        }
+        std::thread::sleep(WATCH_POLL_INTERVAL);
+    }
+}
@@ -1,30 +1,59 @@
 mod namespaces;
+mod export;
 mod utils;
+mod receiver;
+mod verify;
+mod search;
+mod importdir;
+mod commit;
+mod sync;
+mod pushes;
 
-use std::sync::{Arc, Mutex};
-use colored::Colorize;
+use std::sync::Arc;
+use std::time::Duration;
+use libmilkyway::cli::completion::CompletionCache;
+use libmilkyway::cli::output;
+use libmilkyway::cli::describe::ModuleDescription;
 use libmilkyway::cli::router::CommandRouter;
 use libmilkyway::message::common::Message;
-use libmilkyway::module::{CLIStatus, MilkywayModule, ModuleDataBus};
+use libmilkyway::module::{CLIStatus, HostType, MilkywayModule, ModuleDataBus};
+use libmilkyway::module::registry::BuiltinModule;
 use libmilkyway::module::CLIStatus::{Done, NamespaceChange};
-use libmilkyway::services::certificate::CertificateServiceBinder;
+use libmilkyway::services::certificate::CertificateServicePool;
+use libmilkyway::services::certificate::sync::DEFAULT_CERTIFICATE_SYNC_INTERVAL_SECONDS;
+use libmilkyway::services::transport::MessageFilter;
+use crate::namespaces::access::AccessNamespace;
+use crate::namespaces::peers::PeersNamespace;
 use crate::namespaces::encryption::EncryptionNamespace;
+use crate::namespaces::group::GroupNamespace;
+use crate::namespaces::maintenance::MaintenanceNamespace;
+use crate::namespaces::policy::PolicyNamespace;
+use crate::namespaces::push::PushNamespace;
 use crate::namespaces::root::RootNamespace;
 use crate::namespaces::signing::SigningNamespace;
+use crate::pushes::PushState;
+use crate::receiver::CertificatePushReceiver;
+use crate::sync::CertificateSyncSender;
 
 ///
 /// The module for managing certificates
 /// 
 pub struct CertmanModule{
-    certificate_service: Option<Arc<Mutex<Box<CertificateServiceBinder>>>>,
+    certificate_pool: Option<CertificateServicePool>,
     router: CommandRouter,
+    /** Values completed from services, shared by namespaces **/
+    completions: CompletionCache,
+    /** Sends snapshots of certificates to peers on servers, None in CLI **/
+    sync_sender: Option<CertificateSyncSender>,
 }
 
 impl CertmanModule {
     pub fn new() -> CertmanModule{
         CertmanModule{
-            certificate_service: None,
+            certificate_pool: None,
             router: CommandRouter::new(),
+            completions: CompletionCache::default(),
+            sync_sender: None,
         }
     }
 }
@@ -35,30 +64,87 @@ impl MilkywayModule for CertmanModule {
     }
 
     fn get_commands(&self) -> Vec<String> {
-        vec!["certman".to_string()]
+        vec!["certman".to_string(), "cm".to_string()]
+    }
+
+    fn describe(&self) -> ModuleDescription {
+        ModuleDescription::new(self.get_id(), self.get_commands(), self.router.describe())
     }
 
     fn on_load(&mut self, data_bus: Box<dyn ModuleDataBus>) {
-        let binder = Arc::new(Mutex::new(data_bus.get_certificate_service()));
-        self.certificate_service = Some(binder.clone());
+        // Pushes received from network get their own binder, namespaces share the rest of pool
+        let pool = data_bus.get_certificate_pool();
+        // Daemon receives pushes and CLI confirms them, so both keep pushes in module state
+        let pushes = PushState::new(data_bus.get_module_state(self.get_id()));
+        if data_bus.get_host_type() != HostType::CLI{
+            let receiver = CertificatePushReceiver::new(pool.get_shared(), pushes.clone(),
+                                                        data_bus.get_group_service());
+            data_bus.get_transport_service().subscribe_to_messages(MessageFilter::new()
+                                                                       .filter_module(self.get_id()),
+                                                                   Box::new(receiver));
+        }
+        let data_bus = Arc::new(data_bus);
+        if data_bus.get_host_type() != HostType::CLI{
+            self.sync_sender = Some(CertificateSyncSender::start(pool.get_shared(), data_bus.clone(), self.get_id(),
+                                    Duration::from_secs(DEFAULT_CERTIFICATE_SYNC_INTERVAL_SECONDS)));
+        }
         self.router.register_namespace(vec!["certman".to_string(), "root".to_string()], 
-                                       Box::new(RootNamespace::new(binder.clone())));
+                                       Box::new(RootNamespace::new(pool.get_shared())));
         self.router.register_namespace(vec!["certman".to_string(), "signing".to_string()], 
-                                       Box::new(SigningNamespace::new(binder.clone())));
+                                       Box::new(SigningNamespace::new(pool.get_shared(),
+                                                                      data_bus.get_certificate_profiles(),
+                                                                      self.completions.clone())));
         self.router.register_namespace(vec!["certman".to_string(), "encryption".to_string()],
-                                       Box::new(EncryptionNamespace::new(binder.clone())));
+                                       Box::new(EncryptionNamespace::new(pool.get_shared(), self.completions.clone())));
+        self.router.register_namespace(vec!["certman".to_string(), "group".to_string()],
+                                       Box::new(GroupNamespace::new(pool.get_shared(), data_bus.clone(),
+                                                                    self.get_id())));
+        self.router.register_namespace(vec!["certman".to_string(), "access".to_string()],
+                                       Box::new(AccessNamespace::new(data_bus.clone())));
+        self.router.register_namespace(vec!["certman".to_string(), "maintenance".to_string()],
+                                       Box::new(MaintenanceNamespace::new(pool.get_shared())));
+        self.router.register_namespace(vec!["certman".to_string(), "policy".to_string()],
+                                       Box::new(PolicyNamespace::new(pool.get_shared(), self.completions.clone())));
+        self.router.register_namespace(vec!["certman".to_string(), "peers".to_string()],
+                                       Box::new(PeersNamespace::new(data_bus.clone(), self.completions.clone())));
+        self.router.register_namespace(vec!["certman".to_string()],
+                                       Box::new(PushNamespace::new(pool.get_shared(), data_bus, self.get_id(),
+                                                                   pushes)));
+        self.certificate_pool = Some(pool);
+        self.router.add_alias(vec![], "cm", "certman");
+        for (alias, target) in [("sg", "signing"), ("enc", "encryption"), ("rt", "root"), ("grp", "group")]{
+            self.router.add_alias(vec!["certman".to_string()], alias, target);
+        }
+        for namespace in ["signing", "encryption", "root"]{
+            let path = vec!["certman".to_string(), namespace.to_string()];
+            self.router.add_alias(path.clone(), "gen", "generate");
+            self.router.add_alias(path, "ls", "show");
+        }
     }
 
     fn on_cli_command(&mut self, command: Vec<String>, arguments: Vec<String>) -> CLIStatus {
+        let command = match self.router.resolve(&command) {
+            Ok(command) => command,
+            Err(error) => {
+                output::error(error.to_string());
+                return Done;
+            }
+        };
         if self.router.is_namespace(&command){
             return NamespaceChange(command);
         }
         if !self.router.on_command(command, arguments){
-            println!("{} {}", "error:".red().bold().underline(), "No such command");
+            output::error("No such command");
         }
+        // Command may have added or removed certificates
+        self.completions.clear();
         Done
     }
 
+    fn on_cli_complete(&mut self, command: Vec<String>, argument: String, prefix: String) -> Vec<String> {
+        self.router.complete(&command, &argument, &prefix)
+    }
+
     fn on_server_receive(&self, _packet: &Message) { /* stub */ }
 
     fn on_client_receive(&self, _packet: &Message) { /* stub */ }
@@ -66,6 +152,15 @@ impl MilkywayModule for CertmanModule {
     fn on_cli_receive(&self, _packet: &Message) { /* stub */ }
 }
 
+///
+/// Certman compiled into host(`certman` feature of milkywaycli and milkywaysrvd)
+///
+pub const BUILTIN_MODULE: BuiltinModule = BuiltinModule{
+    name: "certman",
+    constructor: || Box::new(CertmanModule::new()),
+};
+
+#[cfg(not(feature = "builtin"))]
 #[no_mangle]
 #[allow(improper_ctypes_definitions)]
 pub extern "C" fn create() -> *mut dyn MilkywayModule{
@@ -1,3 +1,9 @@
 pub mod root;
 pub mod signing;
-pub mod encryption;
\ No newline at end of file
+pub mod encryption;
+pub mod push;
+pub mod group;
+pub mod access;
+pub mod peers;
+pub mod maintenance;
+pub mod policy;
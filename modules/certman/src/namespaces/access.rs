@@ -0,0 +1,170 @@
+use std::sync::Arc;
+use libmilkyway::cli::output;
+use libmilkyway::cli::arguments::parse_arguments;
+use libmilkyway::cli::describe::{ArgumentDescription, CommandDescription};
+use libmilkyway::cli::router::CommandNamespace;
+use libmilkyway::cli::table::Table;
+use libmilkyway::module::ModuleDataBus;
+use libmilkyway::peer::resolve_peer;
+use libmilkyway::transport::access::{AccessRule, PeerSelector, SharedAccessControl};
+
+pub struct AccessNamespace{
+    data_bus: Arc<Box<dyn ModuleDataBus>>,
+}
+
+impl AccessNamespace {
+    pub fn new(data_bus: Arc<Box<dyn ModuleDataBus>>) -> Self{
+        AccessNamespace{
+            data_bus,
+        }
+    }
+
+    ///
+    /// Builds selector from exactly one of `fingerprint`, `serial` or `peer` arguments
+    ///
+    fn parse_selector(&self, arguments: Vec<String>) -> Option<PeerSelector>{
+        let argmap = parse_arguments(arguments);
+        let given: Vec<&str> = ["fingerprint", "serial", "peer"].into_iter()
+            .filter(|name| argmap.contains_key(*name))
+            .collect();
+        if given.len() != 1{
+            output::error("Exactly one of 'fingerprint', 'serial' or 'peer' arguments is required");
+            return None;
+        }
+        let value = match argmap.get(given[0]) {
+            Some(Some(value)) => value,
+            _ => {
+                output::error(format!("Argument '{}' requires a value", given[0]));
+                return None;
+            }
+        };
+        if given[0] == "peer"{
+            return match resolve_peer(self.data_bus.get_name_service().as_ref(), value) {
+                Ok(peer_id) => Some(PeerSelector::PeerId(peer_id.get())),
+                Err(error) => {
+                    output::coded_error(format!("Argument 'peer' is invalid: {}", error), &error);
+                    None
+                }
+            };
+        }
+        match format!("{}:{}", given[0], value).parse::<PeerSelector>() {
+            Ok(selector) => Some(selector),
+            Err(error) => {
+                output::error(error);
+                None
+            }
+        }
+    }
+
+    pub fn add(&mut self, access: &SharedAccessControl, rule: AccessRule, arguments: Vec<String>){
+        let selector = match self.parse_selector(arguments) {
+            Some(selector) => selector,
+            None => return,
+        };
+        let mut access = access.lock().unwrap();
+        if !access.add(rule, selector.clone()){
+            output::warning(format!("{} is already in list", selector));
+            return;
+        }
+        access.commit();
+        match rule {
+            AccessRule::Allow => output::info(format!("Allowed {}", selector)),
+            AccessRule::Block => output::info(format!("Blocked {}", selector)),
+        }
+    }
+
+    pub fn remove(&mut self, access: &SharedAccessControl, arguments: Vec<String>){
+        let selector = match self.parse_selector(arguments) {
+            Some(selector) => selector,
+            None => return,
+        };
+        let mut access = access.lock().unwrap();
+        if !access.remove(&selector){
+            output::error(format!("{} is not in any list", selector));
+            return;
+        }
+        access.commit();
+        output::info(format!("Removed {}", selector));
+    }
+
+    pub fn show(&mut self, access: &SharedAccessControl){
+        let access = access.lock().unwrap();
+        let mut table = Table::new(vec!["RULE", "SELECTOR"]);
+        for selector in access.get_blocked(){
+            table.add_row(vec!["block", &selector.to_string()]);
+        }
+        for selector in access.get_allowed(){
+            table.add_row(vec!["allow", &selector.to_string()]);
+        }
+        table.display();
+    }
+
+    pub fn audit(&mut self, access: &SharedAccessControl, arguments: Vec<String>){
+        let mut access = access.lock().unwrap();
+        if parse_arguments(arguments).contains_key("clear"){
+            access.clear_audit();
+            access.commit();
+            output::info("Audit is cleared");
+            return;
+        }
+        let mut table = Table::new(vec!["TIMESTAMP", "STAGE", "PEER", "SERIALS", "REASON", "SELECTOR"]);
+        for entry in access.get_audit(){
+            let serials: Vec<String> = entry.serials.iter().map(|serial| serial.to_string()).collect();
+            table.add_row(vec![&entry.timestamp.to_string(), &entry.stage.to_string(),
+                               &entry.peer_id.map(|peer_id| peer_id.to_string()).unwrap_or_default(),
+                               &serials.join(","), &entry.reason.to_string(),
+                               &entry.selector.as_ref().map(|selector| selector.to_string()).unwrap_or_default()]);
+        }
+        table.display();
+    }
+}
+
+impl CommandNamespace for AccessNamespace{
+    fn on_command(&mut self, command: String, args: Vec<String>) {
+        let access = match self.data_bus.get_access_control() {
+            Some(access) => access,
+            None => {
+                output::error("Access lists are not supported on this host");
+                return;
+            }
+        };
+        match command.as_str() {
+            "block" => {
+                self.add(&access, AccessRule::Block, args);
+            }
+            "allow" => {
+                self.add(&access, AccessRule::Allow, args);
+            }
+            "remove" => {
+                self.remove(&access, args);
+            }
+            "show" => {
+                self.show(&access);
+            }
+            "audit" => {
+                self.audit(&access, args);
+            }
+            &_ => {
+                output::error("No such command");
+            }
+        }
+    }
+
+    fn describe(&self) -> Vec<CommandDescription> {
+        let selector = vec![
+            ArgumentDescription::optional("fingerprint", "Fingerprint of signing or encryption certificate"),
+            ArgumentDescription::optional("serial", "Serial number of signing or encryption certificate"),
+            ArgumentDescription::optional("peer", "ID or name of peer"),
+        ];
+        vec![
+            CommandDescription::new("block", "Blocks peer, takes precedence over allow list", selector.clone()),
+            CommandDescription::new("allow", "Allows peer, once allow list is not empty other peers are denied",
+                                    selector.clone()),
+            CommandDescription::new("remove", "Removes peer from allow and block lists", selector),
+            CommandDescription::new("show", "Shows allow and block lists", vec![]),
+            CommandDescription::new("audit", "Shows denied connection attempts", vec![
+                ArgumentDescription::flag("clear", "Clears audit instead of showing it"),
+            ]),
+        ]
+    }
+}
@@ -1,33 +1,38 @@
-use std::path::Path;
 use std::sync::{Arc, Mutex};
 use libmilkyway::cli::router::CommandNamespace;
-use libmilkyway::cli::table::Table;
-use libmilkyway::pki::certificate::{Certificate, FLAG_CLIENT_CERT, FLAG_NO_READ, FLAG_NO_WRITE, FLAG_SERVER_CERT, FLAG_SIGN_CERTS, FLAG_SIGN_MESSAGES, FLAG_USER_CERT};
+use libmilkyway::pki::certificate::{Certificate, FLAG_ROOT_CERT, FLAG_SIGN_CERTS};
+use libmilkyway::pki::certificate::flags::{parse_flags, FlagError};
+use libmilkyway::pki::certificate::metadata::CertificateMetadata;
 use libmilkyway::services::certificate::{CertificateService, CertificateServiceBinder, ROOT_CERTIFICATE_SERIAL};
-use crate::utils::{certificates_flags_to_string, optional_serial_to_string};
-use colored::Colorize;
+use crate::export::{check_export, read_export, write_export};
+use crate::utils::{check_sign_policy, check_writable, complete_serials, get_new_serial, parse_metadata,
+                   print_generated, show_certificates};
+use libmilkyway::cli::output;
 use libmilkyway::cli::arguments::parse_arguments;
+use libmilkyway::cli::completion::CompletionCache;
+use libmilkyway::cli::describe::{ArgumentDescription, ArgumentKind, CommandDescription};
 use libmilkyway::pki::hash::HashType;
 use libmilkyway::pki::impls::certificates::falcon1024::Falcon1024Certificate;
 use libmilkyway::pki::impls::certificates::kyber1024::Kyber1024Certificate;
 use libmilkyway::pki::impls::keys::falcon1024::generate_falcon1024_keypair;
 use libmilkyway::pki::impls::keys::kyber1024::generate_kyber1024_keypair;
-use libmilkyway::serialization::deserializable::Deserializable;
-use libmilkyway::serialization::serializable::Serializable;
 
 pub struct EncryptionNamespace{
     cert_binder: Arc<Mutex<Box<CertificateServiceBinder>>>,
+    completions: CompletionCache,
 }
 
 impl EncryptionNamespace {
-    pub fn new(binder: Arc<Mutex<Box<CertificateServiceBinder>>>) -> EncryptionNamespace{
+    pub fn new(binder: Arc<Mutex<Box<CertificateServiceBinder>>>, completions: CompletionCache) -> EncryptionNamespace{
         EncryptionNamespace{
             cert_binder: binder,
+            completions,
         }
     }
     fn generate_signed_certificate(&self, binder: &mut Box<CertificateServiceBinder>, serial_number: u128,
                                    parent_serial_number: u128, /* Serial number of certificate to sign with */
-                                   name: String, flags: u128) -> Result<Kyber1024Certificate, &'static str>{
+                                   name: String, flags: u128,
+                                   metadata: CertificateMetadata) -> Result<Kyber1024Certificate, &'static str>{
         if parent_serial_number==ROOT_CERTIFICATE_SERIAL{
             let root_certificate = binder.get_root_certificate();
             if root_certificate.is_none(){
@@ -43,6 +48,7 @@ impl EncryptionNamespace {
                 signature: None,
                 name: name,
                 flags: flags,
+                metadata: metadata.clone(),
             };
             let result = root_certificate.sign_data(&certificate.clone_without_signature_and_sk(),
                                                     HashType::None);
@@ -70,6 +76,7 @@ impl EncryptionNamespace {
                 signature: None,
                 name: name,
                 flags: flags,
+                metadata,
             };
             let result = parent_certificate.sign_data(&certificate.clone_without_signature_and_sk(), HashType::None);
             if result.is_err(){
@@ -79,86 +86,39 @@ impl EncryptionNamespace {
             return Ok(certificate);
         }
     }
-    fn parse_flags(value: String) -> Option<u128> {
-        let flags = value.split(",");
-        let mut result = 0;
-        for flag in flags{
-            if flag == "no-read"{
-                result = result | FLAG_NO_READ;
-                continue;
-            }
-            if flag == "no-write" {
-                result = result | FLAG_NO_WRITE;
-                continue;
-            }
-            if flag == "sign-messages" {
-                result = result | FLAG_SIGN_MESSAGES;
-                continue;
-            }
-            if flag == "sign-certs" {
-                result = result | FLAG_SIGN_CERTS;
-                continue;
-            }
-            if flag == "client-cert" {
-                result = result | FLAG_CLIENT_CERT;
-                continue;
-            }
-            if flag == "server-cert" {
-                result = result | FLAG_SERVER_CERT;
-                continue;
-            }
-            if flag == "user-cert" {
-                result = result | FLAG_USER_CERT;
-                continue;
-            }
-            return None;
+    fn parse_flags(value: String) -> Result<u128, FlagError> {
+        let flags = parse_flags(&value, true)?;
+        if flags & FLAG_ROOT_CERT != 0 {
+            // Root certificates are only created with `root generate`
+            return Err(FlagError::UnknownFlag("root".to_string()));
         }
-        return Some(result);
+        Ok(flags)
     }
     pub fn generate(&mut self, args:Vec<String>){
         let argmap = parse_arguments(args);
-        /* Check serial */
-        if !argmap.contains_key("serial"){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'serial' is required");
-            return;
-        }
-        let serial = argmap.get("serial").unwrap();
-        if serial.is_none(){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'serial' must have a value");
-            return;
-        }
-        let serial = serial.clone().unwrap();
-        let serial = serial.parse::<u128>();
-        if serial.is_err(){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument serial must be a positive number");
-            return;
-        }
-        let serial = serial.unwrap();
         if !argmap.contains_key("parent"){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'parent' is required");
+            output::error("Argument 'parent' is required");
             return;
         }
         let parent = argmap.get("parent").unwrap();
         if parent.is_none(){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'parent' must have a value");
+            output::error("Argument 'parent' must have a value");
             return;
         }
         let parent = parent.clone().unwrap();
         let parent = parent.parse::<u128>();
         if parent.is_err(){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument 'parent' must be a positive number");
+            output::error("Argument 'parent' must be a positive number");
             return;
         }
         let parent = parent.unwrap();
         if !argmap.contains_key("name"){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'name' is required");
+            output::error("Argument 'name' is required");
             return;
         }
         let name = argmap.get("name").unwrap();
         if name.is_none(){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'name' requires a value");
+            output::error("Argument 'name' requires a value");
             return;
         }
         let name = name.clone().unwrap();
@@ -166,54 +126,65 @@ impl EncryptionNamespace {
         if argmap.contains_key("flags"){
             let flags_argument =  argmap.get("flags").unwrap();
             if flags_argument.is_none(){
-                println!("{} {}", "error:".red().bold().underline(), "Argument 'flags' requires a value");
+                output::error("Argument 'flags' requires a value");
                 return;
             }
             let flags_result = Self::parse_flags(flags_argument.clone().unwrap());
-            if flags_result.is_none(){
-                println!("{} {}", "error:".red().bold().underline(), "Argument 'flags' is invalid");
+            if let Err(error) = flags_result{
+                output::coded_error(format!("Argument 'flags' is invalid: {}", error), &error);
                 return;
             }
             flags = flags_result.unwrap();
         }
+        let metadata = match parse_metadata(&argmap) {
+            Some(metadata) => metadata,
+            None => return,
+        };
         let mut binder = self.cert_binder.lock().unwrap();
+        if !check_sign_policy(&mut binder, parent, &name, flags){
+            return;
+        }
+        let serial = match get_new_serial(&mut binder, &argmap) {
+            Some(serial) => serial,
+            None => return,
+        };
         let signed_certificate = self.generate_signed_certificate(&mut binder,
-                                                                  serial, parent, name, flags);
+                                                                  serial, parent, name, flags, metadata);
         if signed_certificate.is_err(){
-            println!("{} {}", "error:".red().bold().underline(),signed_certificate.err().unwrap());
+            output::error(signed_certificate.err().unwrap());
             return;
         }
         let encryption_certificate = signed_certificate.unwrap();
         let result = binder.add_encryption_certificate(encryption_certificate);
         if !result{
-            println!("{} {}", "error:".red().bold().underline(), "Can not add certificate to servise");
+            output::error("Can not add certificate to servise");
             return;
         }
         binder.commit();
+        print_generated(&argmap, "encryption", serial);
     }
     pub fn remove(&mut self, args:Vec<String>){
         let argmap = parse_arguments(args);
         if !argmap.contains_key("serial"){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'serial' is required");
+            output::error("Argument 'serial' is required");
             return;
         }
         let serial = argmap.get("serial").unwrap();
         if serial.is_none(){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'serial' must have a value");
+            output::error("Argument 'serial' must have a value");
             return;
         }
         let serial = serial.clone().unwrap();
         let serial = serial.parse::<u128>();
         if serial.is_err(){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument serial must be a positive number");
+            output::error("Argument serial must be a positive number");
             return;
         }
         let serial = serial.unwrap();
         let mut binder = self.cert_binder.lock().unwrap();
         let result = binder.remove_encryption_certificate(serial);
         if !result {
-            println!("{} {}", "error:".red().bold().underline(), "Can not remove certificate");
+            output::error("Can not remove certificate");
             return;
         }
         binder.commit();
@@ -221,92 +192,84 @@ impl EncryptionNamespace {
     pub fn export(&mut self, args:Vec<String>){
         let argmap = parse_arguments(args);
         if !argmap.contains_key("file"){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'file' is required");
+            output::error("Argument 'file' is required");
             return;
         }
         let file = argmap.get("file").unwrap();
         if file.is_none(){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'file' requires a value");
+            output::error("Argument 'file' requires a value");
             return;
         }
         if !argmap.contains_key("serial") {
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'serial' is required");
+            output::error("Argument 'serial' is required");
             return;
         }
         let serial = argmap.get("serial").unwrap();
         if serial.is_none(){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'serial' requires a value");
+            output::error("Argument 'serial' requires a value");
             return;
         }
         let mut binder = self.cert_binder.lock().unwrap();
         let serial = serial.clone().unwrap().parse::<u128>();
         if serial.is_err(){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument 'serial' must be a positive integer");
+            output::error("Argument 'serial' must be a positive integer");
             return;
         }
         let serial = serial.unwrap();
         if serial==0{
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Can not export root certificate");
+            output::error("Can not export root certificate");
             return;
         }
         let certificate = binder.get_encryption_certificate(serial);
         if certificate.is_none(){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "No certificate with such serial number");
+            output::error("No certificate with such serial number");
             return;
         }
         let certificate = certificate.unwrap();
-        let result = certificate.dump(&file.clone().unwrap());
-        if result.is_err(){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Can not save certificate");
-            return;
-        }
+        write_export(&mut binder, &argmap, &certificate, &file.clone().unwrap());
     }
     pub fn import(&mut self, args:Vec<String>){
         let argmap = parse_arguments(args);
         if !argmap.contains_key("file"){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument 'file' is required");
+            output::error("Argument 'file' is required");
             return;
         }
         let argument = argmap.get("file").unwrap();
         if argument.is_none(){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument 'file' requires a value");
+            output::error("Argument 'file' requires a value");
             return;
         }
         let file_name = argument.clone().unwrap();
-        let certificate = Kyber1024Certificate::from_file(Path::new(&file_name));
+        let export = match read_export(&file_name) {
+            Some(export) => export,
+            None => return,
+        };
+        let certificate = export.get_content::<Kyber1024Certificate>();
         if certificate.is_err(){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Can not read a certificate");
+            output::error("Can not read a certificate");
             return;
         }
         let certificate = certificate.unwrap();
         let mut binder = self.cert_binder.lock().unwrap();
+        if !check_export(&mut binder, &argmap, &export, None){
+            return;
+        }
         let result = binder.add_encryption_certificate(certificate);
         if !result{
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Can not add certificate to service");
+            output::error("Can not add certificate to service");
             return;
         }
     }
     pub fn show(&mut self){
-        let result =self.cert_binder.lock().unwrap().get_encryption_certificates();
-        let mut table = Table::new(vec!["SERIAL", "NAME", "FLAGS", "PARENT SERIAL"]);
-        for certificate in result{
-            table.add_row(vec![&certificate.get_serial().to_string(),
-                               &certificate.get_name(), &certificates_flags_to_string(certificate.get_flags()),
-                               &*optional_serial_to_string(certificate.get_parent_serial())]);
-        }
-        table.display();
+        show_certificates(&mut self.cert_binder.lock().unwrap(), true);
     }
 }
 impl CommandNamespace for EncryptionNamespace{
     fn on_command(&mut self, command: String, args: Vec<String>) {
+        if matches!(command.as_str(), "generate" | "remove" | "import")
+            && !check_writable(&mut self.cert_binder.lock().unwrap()){
+            return;
+        }
         match command.as_str() {
             "generate" => {
                 self.generate(args);
@@ -324,8 +287,48 @@ impl CommandNamespace for EncryptionNamespace{
                 self.show();
             }
             &_ => {
-                println!("{} {}", "error:".red().bold().underline(), "No such command");
+                output::error("No such command");
             }
         }
     }
-}
\ No newline at end of file
+
+    fn describe(&self) -> Vec<CommandDescription> {
+        vec![
+            CommandDescription::new("generate", "Generates encryption certificate", vec![
+                ArgumentDescription::optional("serial", "Serial number of certificate, random if omitted"),
+                ArgumentDescription::required("parent", "Serial number of signing certificate")
+                    .with_kind(ArgumentKind::Serial),
+                ArgumentDescription::required("name", "Name of certificate"),
+                ArgumentDescription::optional("flags", "Comma-separated flags, e.g. sign-messages,client-cert"),
+                ArgumentDescription::optional("description", "Description of certificate"),
+                ArgumentDescription::optional("owner", "Person or team responsible for certificate"),
+                ArgumentDescription::optional("tags", "Comma-separated tags, e.g. env:prod,team:web"),
+                ArgumentDescription::optional("lifetime", "Days certificate is valid for, forever if omitted"),
+                ArgumentDescription::flag("json", "Print serial of generated certificate as JSON object"),
+            ]),
+            CommandDescription::new("remove", "Removes encryption certificate", vec![
+                ArgumentDescription::required("serial", "Serial number of certificate").with_kind(ArgumentKind::Serial),
+            ]),
+            CommandDescription::new("export", "Exports encryption certificate to file", vec![
+                ArgumentDescription::required("serial", "Serial number of certificate").with_kind(ArgumentKind::Serial),
+                ArgumentDescription::required("file", "File to write certificate to").with_kind(ArgumentKind::File),
+                ArgumentDescription::optional("signer", "Serial of certificate signing export, root by default")
+                    .with_kind(ArgumentKind::Serial),
+                ArgumentDescription::flag("unsigned", "Do not sign export"),
+            ]),
+            CommandDescription::new("import", "Imports encryption certificate from file", vec![
+                ArgumentDescription::required("file", "File with certificate").with_kind(ArgumentKind::File),
+                ArgumentDescription::optional("max_age", "Reject exports older than given count of seconds"),
+            ]),
+            CommandDescription::new("show", "Shows encryption certificates", vec![]),
+        ]
+    }
+    fn complete(&self, command: &str, argument: &str, prefix: &str) -> Vec<String> {
+        match argument {
+            "serial" if command == "generate" => vec![],
+            "serial" => complete_serials(&self.completions, &self.cert_binder, true, prefix),
+            "parent" | "signer" => complete_serials(&self.completions, &self.cert_binder, false, prefix),
+            _ => vec![],
+        }
+    }
+}
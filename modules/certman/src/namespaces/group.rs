@@ -0,0 +1,287 @@
+use std::sync::{Arc, Mutex};
+use libmilkyway::cli::output;
+use libmilkyway::cli::arguments::parse_arguments;
+use libmilkyway::cli::describe::{ArgumentDescription, CommandDescription};
+use libmilkyway::cli::router::CommandNamespace;
+use libmilkyway::cli::table::Table;
+use libmilkyway::message::builder::MessageBuilder;
+use libmilkyway::message::group::{GroupOperation, GroupRecord};
+use libmilkyway::module::ModuleDataBus;
+use libmilkyway::peer::resolve_peer;
+use libmilkyway::services::certificate::{CertificateService, CertificateServiceBinder};
+use libmilkyway::services::group::{apply_group_record, Group, SharedGroupService,
+                                   GROUP_ADDRESS_FLAG};
+
+pub struct GroupNamespace{
+    cert_binder: Arc<Mutex<Box<CertificateServiceBinder>>>,
+    data_bus: Arc<Box<dyn ModuleDataBus>>,
+    module_id: u64,
+}
+
+impl GroupNamespace {
+    pub fn new(binder: Arc<Mutex<Box<CertificateServiceBinder>>>, data_bus: Arc<Box<dyn ModuleDataBus>>,
+               module_id: u64) -> Self{
+        GroupNamespace{
+            cert_binder: binder,
+            data_bus,
+            module_id,
+        }
+    }
+
+    fn parse_id(arguments: &[String], name: &str) -> Option<u128>{
+        let argmap = parse_arguments(arguments.to_vec());
+        let value = match argmap.get(name) {
+            Some(Some(value)) => value,
+            _ => {
+                output::error(format!("Argument '{}' with a value is required", name));
+                return None;
+            }
+        };
+        match value.parse::<u128>() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                output::error(format!("Argument '{}' must be a positive integer", name));
+                None
+            }
+        }
+    }
+
+    ///
+    /// Finds group given by `group` argument either by ID or by name
+    ///
+    fn find_group(service: &SharedGroupService, arguments: &[String]) -> Option<Group>{
+        let argmap = parse_arguments(arguments.to_vec());
+        let group = match argmap.get("group") {
+            Some(Some(group)) => group,
+            _ => {
+                output::error("Argument 'group' with a value is required");
+                return None;
+            }
+        };
+        let service = service.lock().unwrap();
+        let result = match group.parse::<u128>() {
+            Ok(id) => service.get_group(id),
+            Err(_) => service.get_group_by_name(group),
+        };
+        if result.is_none(){
+            output::error("No such group");
+        }
+        result
+    }
+
+    ///
+    /// Signs record, applies it locally and sends it to peer if requested
+    ///
+    fn publish(&mut self, service: &SharedGroupService, group_id: u128, name: &str,
+               operation: GroupOperation, arguments: &[String]) -> bool{
+        let serial = match Self::parse_id(arguments, "serial") {
+            Some(serial) => serial,
+            None => return false,
+        };
+        let mut binder = self.cert_binder.lock().unwrap();
+        let signer = match binder.get_signing_certificate(serial) {
+            Some(signer) => signer,
+            None => {
+                output::error("No signing certificate with such serial number");
+                return false;
+            }
+        };
+        let record = match GroupRecord::new(group_id, name, operation, &signer) {
+            Ok(record) => record,
+            Err(_) => {
+                output::error("Certificate can not sign group records");
+                return false;
+            }
+        };
+        let mut groups = service.lock().unwrap();
+        if let Err(error) = apply_group_record(&mut *groups, &mut **binder, &record){
+            output::error(error);
+            return false;
+        }
+        groups.commit();
+        drop(groups);
+        let argmap = parse_arguments(arguments.to_vec());
+        if let Some(peer) = argmap.get("peer"){
+            let peer = peer.as_deref().unwrap_or_default();
+            let destination = match resolve_peer(self.data_bus.get_name_service().as_ref(), peer) {
+                Ok(destination) => destination.get(),
+                Err(error) => {
+                    output::coded_error(format!("Argument 'peer' is invalid: {}, record is applied only locally", error),
+                                        &error);
+                    return false;
+                }
+            };
+            let source = match self.data_bus.get_host_id() {
+                Some(source) => source,
+                None => {
+                    output::error("Not connected to network, record is applied only locally");
+                    return false;
+                }
+            };
+            let message = MessageBuilder::from_payload(&record)
+                .set_source(source)
+                .set_destination(destination)
+                .set_module_id(self.module_id)
+                .build()
+                .expect("All required fields are set");
+            if let Err(error) = self.data_bus.get_transport_service().try_send_message(message){
+                output::error(format!("Record is applied only locally: {}", error));
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn create(&mut self, service: &SharedGroupService, arguments: Vec<String>){
+        let group_id = match Self::parse_id(&arguments, "id") {
+            Some(group_id) => group_id,
+            None => return,
+        };
+        if group_id & GROUP_ADDRESS_FLAG != 0{
+            output::error("Argument 'id' must be less than 2^127");
+            return;
+        }
+        let argmap = parse_arguments(arguments.clone());
+        let name = match argmap.get("name") {
+            Some(Some(name)) => name.clone(),
+            _ => {
+                output::error("Argument 'name' with a value is required");
+                return;
+            }
+        };
+        if self.publish(service, group_id, &name, GroupOperation::Create, &arguments){
+            output::info(format!("Created group {}", name));
+        }
+    }
+
+    pub fn delete(&mut self, service: &SharedGroupService, arguments: Vec<String>){
+        let group = match Self::find_group(service, &arguments) {
+            Some(group) => group,
+            None => return,
+        };
+        if self.publish(service, group.id, &group.name, GroupOperation::Delete, &arguments){
+            output::info(format!("Deleted group {}", group.name));
+        }
+    }
+
+    pub fn change_member(&mut self, service: &SharedGroupService, arguments: Vec<String>, add: bool){
+        let group = match Self::find_group(service, &arguments) {
+            Some(group) => group,
+            None => return,
+        };
+        let argmap = parse_arguments(arguments.clone());
+        let member = match argmap.get("member") {
+            Some(Some(member)) => member.clone(),
+            _ => {
+                output::error("Argument 'member' with a value is required");
+                return;
+            }
+        };
+        let member_id = match resolve_peer(self.data_bus.get_name_service().as_ref(), &member) {
+            Ok(member_id) => member_id.get(),
+            Err(error) => {
+                output::coded_error(format!("Argument 'member' is invalid: {}", error), &error);
+                return;
+            }
+        };
+        let operation = if add{
+            GroupOperation::AddMember(member_id)
+        } else {
+            GroupOperation::RemoveMember(member_id)
+        };
+        if self.publish(service, group.id, &group.name, operation, &arguments){
+            if add{
+                output::info(format!("Added {} to group {}", member, group.name));
+            } else {
+                output::info(format!("Removed {} from group {}", member, group.name));
+            }
+        }
+    }
+
+    pub fn show(&mut self, service: &SharedGroupService, arguments: Vec<String>){
+        let argmap = parse_arguments(arguments.clone());
+        if argmap.contains_key("group"){
+            let group = match Self::find_group(service, &arguments) {
+                Some(group) => group,
+                None => return,
+            };
+            let name_service = self.data_bus.get_name_service();
+            let mut table = Table::new(vec!["ID", "NAME"]);
+            for member in group.members{
+                table.add_row(vec![&member.to_string(), &name_service.get_name_by_id(member)]);
+            }
+            table.display();
+            return;
+        }
+        let mut table = Table::new(vec!["ID", "NAME", "MEMBERS"]);
+        for group in service.lock().unwrap().get_groups(){
+            table.add_row(vec![&group.id.to_string(), &group.name, &group.members.len().to_string()]);
+        }
+        table.display();
+    }
+}
+
+impl CommandNamespace for GroupNamespace{
+    fn on_command(&mut self, command: String, args: Vec<String>) {
+        let service = match self.data_bus.get_group_service() {
+            Some(service) => service,
+            None => {
+                output::error("Groups are not supported on this host");
+                return;
+            }
+        };
+        match command.as_str() {
+            "create" => {
+                self.create(&service, args);
+            }
+            "delete" => {
+                self.delete(&service, args);
+            }
+            "add" => {
+                self.change_member(&service, args, true);
+            }
+            "remove" => {
+                self.change_member(&service, args, false);
+            }
+            "show" => {
+                self.show(&service, args);
+            }
+            &_ => {
+                output::error("No such command");
+            }
+        }
+    }
+
+    fn describe(&self) -> Vec<CommandDescription> {
+        let serial = ArgumentDescription::required("serial", "Serial number of certificate which can sign certificates");
+        let peer = ArgumentDescription::optional("peer", "ID or name of peer to send change to");
+        vec![
+            CommandDescription::new("create", "Creates a group of peers", vec![
+                ArgumentDescription::required("id", "ID of group"),
+                ArgumentDescription::required("name", "Name of group, e.g. branch-office-A"),
+                serial.clone(),
+                peer.clone(),
+            ]),
+            CommandDescription::new("delete", "Deletes a group", vec![
+                ArgumentDescription::required("group", "ID or name of group"),
+                serial.clone(),
+                peer.clone(),
+            ]),
+            CommandDescription::new("add", "Adds peer to a group", vec![
+                ArgumentDescription::required("group", "ID or name of group"),
+                ArgumentDescription::required("member", "ID or name of peer"),
+                serial.clone(),
+                peer.clone(),
+            ]),
+            CommandDescription::new("remove", "Removes peer from a group", vec![
+                ArgumentDescription::required("group", "ID or name of group"),
+                ArgumentDescription::required("member", "ID or name of peer"),
+                serial,
+                peer,
+            ]),
+            CommandDescription::new("show", "Shows groups or members of a group", vec![
+                ArgumentDescription::optional("group", "ID or name of group to show members of"),
+            ]),
+        ]
+    }
+}
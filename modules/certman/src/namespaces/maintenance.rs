@@ -0,0 +1,100 @@
+use std::sync::{Arc, Mutex};
+use libmilkyway::cli::output;
+use libmilkyway::cli::arguments::parse_arguments;
+use libmilkyway::cli::describe::{ArgumentDescription, CommandDescription};
+use libmilkyway::cli::router::CommandNamespace;
+use libmilkyway::cli::table::Table;
+use libmilkyway::get_timestamp_with_milliseconds;
+use libmilkyway::services::certificate::CertificateServiceBinder;
+use libmilkyway::services::certificate::gc::{collect_garbage, find_garbage, CertificateGcPolicy, GarbageCertificate};
+use crate::utils::check_writable;
+
+pub struct MaintenanceNamespace{
+    cert_binder: Arc<Mutex<Box<CertificateServiceBinder>>>,
+}
+
+fn show_garbage(garbage: &[&GarbageCertificate]){
+    let mut table = Table::new(vec!["KIND", "SERIAL", "NAME", "REASON"]);
+    for certificate in garbage{
+        table.add_row(vec![&certificate.kind.to_string(), &certificate.serial.to_string(), &certificate.name,
+                           &certificate.reason.to_string()]);
+    }
+    table.display();
+}
+
+impl MaintenanceNamespace {
+    pub fn new(binder: Arc<Mutex<Box<CertificateServiceBinder>>>) -> MaintenanceNamespace{
+        MaintenanceNamespace{
+            cert_binder: binder,
+        }
+    }
+
+    // Arguments of command(those ones in argmap)
+    // * dry-run -- only show what would be removed
+    // * retention -- seconds expired and revoked certificates are kept, 0 if omitted
+    pub fn gc(&mut self, arguments: Vec<String>){
+        let argmap = parse_arguments(arguments);
+        let mut policy = CertificateGcPolicy::default();
+        match argmap.get("retention") {
+            None => {}
+            Some(Some(retention)) => match retention.parse::<u64>() {
+                Ok(retention) => policy.retention = retention,
+                Err(_) => {
+                    output::error("Argument 'retention' must be a number of seconds");
+                    return;
+                }
+            },
+            Some(None) => {
+                output::error("Argument 'retention' requires a value");
+                return;
+            }
+        }
+        let mut binder = self.cert_binder.lock().unwrap();
+        let now = (get_timestamp_with_milliseconds() / 1000) as u64;
+        if argmap.contains_key("dry-run"){
+            let garbage = find_garbage(binder.as_mut(), &policy, now);
+            if garbage.is_empty(){
+                output::info("No certificates to remove");
+                return;
+            }
+            show_garbage(&garbage.iter().collect::<Vec<_>>());
+            output::info(format!("{} certificates would be removed", garbage.len()));
+            return;
+        }
+        if !check_writable(&mut binder){
+            return;
+        }
+        let audit = collect_garbage(binder.as_mut(), &policy, now);
+        if audit.is_empty(){
+            output::info("No certificates to remove");
+            return;
+        }
+        show_garbage(&audit.iter().map(|entry| &entry.certificate).collect::<Vec<_>>());
+        for entry in audit.iter().filter(|entry| !entry.removed){
+            output::error(format!("Can not remove {} certificate {}", entry.certificate.kind, entry.certificate.serial));
+        }
+        output::info(format!("Removed {} certificates", audit.iter().filter(|entry| entry.removed).count()));
+    }
+}
+
+impl CommandNamespace for MaintenanceNamespace{
+    fn on_command(&mut self, command: String, args: Vec<String>) {
+        match command.as_str() {
+            "gc" => {
+                self.gc(args);
+            }
+            &_ => {
+                output::error("No such command");
+            }
+        }
+    }
+
+    fn describe(&self) -> Vec<CommandDescription> {
+        vec![
+            CommandDescription::new("gc", "Removes expired and revoked certificates and certificates whose chain is broken", vec![
+                ArgumentDescription::flag("dry-run", "Only show certificates which would be removed"),
+                ArgumentDescription::optional("retention", "Seconds expired and revoked certificates are kept, 0 if omitted"),
+            ]),
+        ]
+    }
+}
@@ -0,0 +1,152 @@
+use std::sync::Arc;
+use libmilkyway::cli::output;
+use libmilkyway::cli::arguments::parse_arguments;
+use libmilkyway::cli::completion::CompletionCache;
+use libmilkyway::cli::describe::{ArgumentDescription, ArgumentKind, CommandDescription};
+use libmilkyway::cli::router::CommandNamespace;
+use libmilkyway::cli::table::Table;
+use libmilkyway::module::ModuleDataBus;
+use libmilkyway::peer::resolve_peer;
+use libmilkyway::transport::pinning::SharedPeerPins;
+
+pub struct PeersNamespace{
+    data_bus: Arc<Box<dyn ModuleDataBus>>,
+    completions: CompletionCache,
+}
+
+impl PeersNamespace {
+    pub fn new(data_bus: Arc<Box<dyn ModuleDataBus>>, completions: CompletionCache) -> Self{
+        PeersNamespace{
+            data_bus,
+            completions,
+        }
+    }
+
+    ///
+    /// Gets ID of peer from `peer` argument which is either an ID or a name
+    ///
+    fn parse_peer(&self, peer: Option<&Option<String>>) -> Option<u128>{
+        let peer = match peer {
+            Some(Some(peer)) => peer,
+            Some(None) => {
+                output::error("Argument 'peer' requires a value");
+                return None;
+            }
+            None => {
+                output::error("Argument 'peer' is required");
+                return None;
+            }
+        };
+        match resolve_peer(self.data_bus.get_name_service().as_ref(), peer) {
+            Ok(peer_id) => Some(peer_id.get()),
+            Err(error) => {
+                output::coded_error(format!("Argument 'peer' is invalid: {}", error), &error);
+                None
+            }
+        }
+    }
+
+    pub fn pin(&mut self, pins: &SharedPeerPins, arguments: Vec<String>){
+        let argmap = parse_arguments(arguments);
+        let peer_id = match self.parse_peer(argmap.get("peer")) {
+            Some(peer_id) => peer_id,
+            None => return,
+        };
+        let mut pins = pins.lock().unwrap();
+        let fingerprint = match argmap.get("fingerprint") {
+            Some(Some(fingerprint)) => match pins.pin(peer_id, fingerprint) {
+                Ok(_) => fingerprint.to_ascii_lowercase(),
+                Err(error) => {
+                    output::error(error);
+                    return;
+                }
+            },
+            Some(None) => {
+                output::error("Argument 'fingerprint' requires a value");
+                return;
+            }
+            // Without fingerprint operator confirms the one seen on first connection
+            None => match pins.confirm(peer_id) {
+                Some(fingerprint) => fingerprint,
+                None => {
+                    output::error(format!("No fingerprint of peer {} awaits confirmation", peer_id));
+                    return;
+                }
+            },
+        };
+        pins.commit();
+        output::info(format!("Pinned peer {} to {}", peer_id, fingerprint));
+    }
+
+    pub fn unpin(&mut self, pins: &SharedPeerPins, arguments: Vec<String>){
+        let peer_id = match self.parse_peer(parse_arguments(arguments).get("peer")) {
+            Some(peer_id) => peer_id,
+            None => return,
+        };
+        let mut pins = pins.lock().unwrap();
+        if !pins.unpin(peer_id){
+            output::error(format!("Peer {} is not pinned", peer_id));
+            return;
+        }
+        pins.commit();
+        output::info(format!("Unpinned peer {}", peer_id));
+    }
+
+    pub fn show(&mut self, pins: &SharedPeerPins){
+        let pins = pins.lock().unwrap();
+        let mut table = Table::new(vec!["PEER", "STATUS", "FINGERPRINT", "TIMESTAMP"]);
+        for (status, list) in [("pinned", pins.get_pins()), ("pending", pins.get_pending())]{
+            for pin in list{
+                table.add_row(vec![&pin.peer_id.to_string(), status, &pin.fingerprint, &pin.timestamp.to_string()]);
+            }
+        }
+        table.display();
+    }
+}
+
+impl CommandNamespace for PeersNamespace{
+    fn on_command(&mut self, command: String, args: Vec<String>) {
+        let pins = match self.data_bus.get_peer_pins() {
+            Some(pins) => pins,
+            None => {
+                output::error("Peer pinning is not supported on this host");
+                return;
+            }
+        };
+        match command.as_str() {
+            "pin" => {
+                self.pin(&pins, args);
+            }
+            "unpin" => {
+                self.unpin(&pins, args);
+            }
+            "show" => {
+                self.show(&pins);
+            }
+            &_ => {
+                output::error("No such command");
+            }
+        }
+    }
+
+    fn describe(&self) -> Vec<CommandDescription> {
+        vec![
+            CommandDescription::new("pin", "Pins peer to fingerprint of its signing certificate", vec![
+                ArgumentDescription::required("peer", "ID or name of peer").with_kind(ArgumentKind::Peer),
+                ArgumentDescription::optional("fingerprint",
+                                              "Fingerprint to pin, fingerprint seen on first connection is confirmed otherwise"),
+            ]),
+            CommandDescription::new("unpin", "Removes pin and pending fingerprint of peer", vec![
+                ArgumentDescription::required("peer", "ID or name of peer").with_kind(ArgumentKind::Peer),
+            ]),
+            CommandDescription::new("show", "Shows pinned peers and fingerprints awaiting confirmation", vec![]),
+        ]
+    }
+    fn complete(&self, _command: &str, argument: &str, prefix: &str) -> Vec<String> {
+        if argument != "peer"{
+            return vec![];
+        }
+        let data_bus = self.data_bus.clone();
+        self.completions.complete("peers", prefix, move || data_bus.get_name_service().get_known_names())
+    }
+}
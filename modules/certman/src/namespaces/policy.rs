@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use libmilkyway::cli::output;
+use libmilkyway::cli::arguments::parse_arguments;
+use libmilkyway::cli::completion::CompletionCache;
+use libmilkyway::cli::describe::{ArgumentDescription, CommandDescription};
+use libmilkyway::cli::router::CommandNamespace;
+use libmilkyway::cli::table::Table;
+use libmilkyway::pki::certificate::flags::{format_flags, parse_flags};
+use libmilkyway::services::certificate::{CertificateService, CertificateServiceBinder, ROOT_CERTIFICATE_SERIAL};
+use libmilkyway::services::certificate::policy::{PolicyOperation, PolicyRule, PolicySubject};
+use crate::utils::{check_writable, complete_serials};
+
+pub struct PolicyNamespace{
+    cert_binder: Arc<Mutex<Box<CertificateServiceBinder>>>,
+    completions: CompletionCache,
+}
+
+// Gets value of argument, prints error if it is missing
+fn get_required_argument(argmap: &HashMap<String, Option<String>>, name: &str) -> Option<String>{
+    match argmap.get(name) {
+        Some(Some(value)) => Some(value.clone()),
+        Some(None) => {
+            output::error(format!("Argument '{}' requires a value", name));
+            None
+        }
+        None => {
+            output::error(format!("Argument '{}' is required", name));
+            None
+        }
+    }
+}
+
+// Serial of issuer, `root` stands for root certificate
+fn parse_issuer(argmap: &HashMap<String, Option<String>>) -> Option<u128>{
+    let issuer = get_required_argument(argmap, "issuer")?;
+    if issuer == "root"{
+        return Some(ROOT_CERTIFICATE_SERIAL);
+    }
+    match issuer.parse::<u128>() {
+        Ok(issuer) => Some(issuer),
+        Err(_) => {
+            output::error("Argument 'issuer' must be a serial or 'root'");
+            None
+        }
+    }
+}
+
+// Flags of argument 'flags', 0 if it is omitted
+fn parse_flags_argument(argmap: &HashMap<String, Option<String>>) -> Option<u128>{
+    if !argmap.contains_key("flags"){
+        return Some(0);
+    }
+    let flags = get_required_argument(argmap, "flags")?;
+    match parse_flags(&flags, true) {
+        Ok(flags) => Some(flags),
+        Err(error) => {
+            output::coded_error(format!("Argument 'flags' is invalid: {}", error), &error);
+            None
+        }
+    }
+}
+
+impl PolicyNamespace {
+    pub fn new(binder: Arc<Mutex<Box<CertificateServiceBinder>>>, completions: CompletionCache) -> PolicyNamespace{
+        PolicyNamespace{
+            cert_binder: binder,
+            completions,
+        }
+    }
+
+    pub fn show(&mut self){
+        let mut binder = self.cert_binder.lock().unwrap();
+        let policy = binder.get_policy();
+        let mut table = Table::new(vec!["INDEX", "ISSUER", "NAMES", "ALLOWED FLAGS"]);
+        for (index, rule) in policy.rules.iter().enumerate(){
+            let issuer = match rule.issuer {
+                ROOT_CERTIFICATE_SERIAL => "root".to_string(),
+                issuer => issuer.to_string(),
+            };
+            table.add_row(vec![&index.to_string(), &issuer, &rule.name_pattern, &format_flags(rule.allowed_flags)]);
+        }
+        table.display();
+        let signed = match policy.is_signed_by(binder.get_root_certificate().as_ref()) {
+            true => "signed by root certificate",
+            false => "NOT signed by root certificate, nothing is allowed",
+        };
+        output::info(format!("Policy version {}, {}", policy.version, signed));
+    }
+
+    // Arguments of command(those ones in argmap)
+    // * issuer -- serial of signing certificate or 'root'
+    // * names -- pattern of names of issued certificates, `*` matches any characters and `?` a single one
+    // * flags -- flags issued certificates may have(use parse_flags), none if omitted
+    pub fn add(&mut self, arguments: Vec<String>){
+        let argmap = parse_arguments(arguments);
+        let issuer = match parse_issuer(&argmap) {
+            Some(issuer) => issuer,
+            None => return,
+        };
+        let names = match get_required_argument(&argmap, "names") {
+            Some(names) => names,
+            None => return,
+        };
+        let flags = match parse_flags_argument(&argmap) {
+            Some(flags) => flags,
+            None => return,
+        };
+        self.update(|rules| {
+            rules.push(PolicyRule::new(issuer, &names, flags));
+            true
+        });
+    }
+
+    // Arguments of command(those ones in argmap)
+    // * index -- index of rule as shown by show
+    pub fn remove(&mut self, arguments: Vec<String>){
+        let argmap = parse_arguments(arguments);
+        let index = match get_required_argument(&argmap, "index").map(|index| index.parse::<usize>()) {
+            Some(Ok(index)) => index,
+            Some(Err(_)) => {
+                output::error("Argument 'index' must be a number");
+                return;
+            }
+            None => return,
+        };
+        self.update(|rules| {
+            if index >= rules.len(){
+                output::error(format!("No rule with index {}", index));
+                return false;
+            }
+            rules.remove(index);
+            true
+        });
+    }
+
+    // Changes rules, signs new version of policy with root certificate and commits it
+    fn update<F: FnOnce(&mut Vec<PolicyRule>) -> bool>(&mut self, change: F){
+        let mut binder = self.cert_binder.lock().unwrap();
+        if !check_writable(&mut binder){
+            return;
+        }
+        let root = match binder.get_root_certificate() {
+            Some(root) => root,
+            None => {
+                output::error("No root certificate");
+                return;
+            }
+        };
+        let mut policy = binder.get_policy();
+        if !change(&mut policy.rules){
+            return;
+        }
+        policy.version += 1;
+        if let Err(error) = policy.sign(&root){
+            output::coded_error(format!("Can not sign policy: {}", error), &error);
+            return;
+        }
+        if let Err(error) = binder.set_policy(policy.clone()){
+            output::coded_error(format!("Can not set policy: {}", error), &error);
+            return;
+        }
+        binder.commit();
+        output::info(format!("Policy version {} with {} rules is set", policy.version, policy.rules.len()));
+    }
+
+    // Arguments of command(those ones in argmap)
+    // * operation -- add, sign or verify
+    // * issuer -- serial of signing certificate or 'root'
+    // * name -- name of certificate
+    // * flags -- flags of certificate(use parse_flags), none if omitted
+    pub fn test(&mut self, arguments: Vec<String>){
+        let argmap = parse_arguments(arguments);
+        let operation = match get_required_argument(&argmap, "operation") {
+            Some(operation) => operation,
+            None => return,
+        };
+        let operation = match PolicyOperation::from_name(&operation) {
+            Some(operation) => operation,
+            None => {
+                output::error("Argument 'operation' must be one of add, sign, verify");
+                return;
+            }
+        };
+        let issuer = match parse_issuer(&argmap) {
+            Some(issuer) => issuer,
+            None => return,
+        };
+        let name = match get_required_argument(&argmap, "name") {
+            Some(name) => name,
+            None => return,
+        };
+        let flags = match parse_flags_argument(&argmap) {
+            Some(flags) => flags,
+            None => return,
+        };
+        let subject = PolicySubject{ operation, issuer, name, flags };
+        match self.cert_binder.lock().unwrap().evaluate_policy(&subject) {
+            Ok(()) => output::info(format!("Allowed to {} certificate {}", operation, subject.name)),
+            Err(error) => output::coded_error(format!("Denied: {}", error), &error),
+        }
+    }
+}
+
+impl CommandNamespace for PolicyNamespace{
+    fn on_command(&mut self, command: String, args: Vec<String>) {
+        match command.as_str() {
+            "show" => {
+                self.show();
+            }
+            "add" => {
+                self.add(args);
+            }
+            "remove" => {
+                self.remove(args);
+            }
+            "test" => {
+                self.test(args);
+            }
+            &_ => {
+                output::error("No such command");
+            }
+        }
+    }
+
+    fn describe(&self) -> Vec<CommandDescription> {
+        vec![
+            CommandDescription::new("show", "Shows rules of certificate policy", vec![]),
+            CommandDescription::new("add", "Allows issuer to sign only certificates matching rules of it", vec![
+                ArgumentDescription::required("issuer", "Serial of signing certificate or 'root'"),
+                ArgumentDescription::required("names", "Pattern of names, '*' matches any characters and '?' a single one"),
+                ArgumentDescription::optional("flags", "Comma-separated flags issued certificates may have"),
+            ]),
+            CommandDescription::new("remove", "Removes rule of certificate policy", vec![
+                ArgumentDescription::required("index", "Index of rule as shown by show"),
+            ]),
+            CommandDescription::new("test", "Evaluates policy for hypothetical operation", vec![
+                ArgumentDescription::required("operation", "One of add, sign, verify"),
+                ArgumentDescription::required("issuer", "Serial of signing certificate or 'root'"),
+                ArgumentDescription::required("name", "Name of certificate"),
+                ArgumentDescription::optional("flags", "Comma-separated flags of certificate"),
+            ]),
+        ]
+    }
+
+    fn complete(&self, _command: &str, argument: &str, prefix: &str) -> Vec<String> {
+        match argument {
+            "issuer" => complete_serials(&self.completions, &self.cert_binder, false, prefix),
+            "operation" => ["add", "sign", "verify"].iter().filter(|operation| operation.starts_with(prefix))
+                .map(|operation| operation.to_string()).collect(),
+            _ => vec![],
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use std::sync::{Arc, Mutex};
+use libmilkyway::cli::output;
+use libmilkyway::cli::arguments::parse_arguments;
+use libmilkyway::cli::describe::{ArgumentDescription, CommandDescription};
+use libmilkyway::cli::io::confirm;
+use libmilkyway::cli::router::CommandNamespace;
+use libmilkyway::cli::table::Table;
+use libmilkyway::message::certpush::CertificatePushMessage;
+use libmilkyway::message::builder::MessageBuilder;
+use libmilkyway::module::ModuleDataBus;
+use libmilkyway::peer::resolve_peer;
+use libmilkyway::services::certificate::{CertificateService, CertificateServiceBinder};
+use libmilkyway::services::certificate::push::{install_certificate_push, CertificatePushPolicy,
+                                               PendingCertificatePush};
+use crate::pushes::PushState;
+use crate::utils::optional_serial_to_string;
+use crate::search::search_certificates;
+use crate::importdir::import_directory;
+use crate::commit::commit_certificates;
+use crate::verify::verify_certificate;
+
+pub struct PushNamespace{
+    cert_binder: Arc<Mutex<Box<CertificateServiceBinder>>>,
+    data_bus: Arc<Box<dyn ModuleDataBus>>,
+    module_id: u64,
+    pushes: PushState,
+}
+
+impl PushNamespace {
+    pub fn new(binder: Arc<Mutex<Box<CertificateServiceBinder>>>, data_bus: Arc<Box<dyn ModuleDataBus>>,
+               module_id: u64, pushes: PushState) -> Self{
+        PushNamespace{
+            cert_binder: binder,
+            data_bus,
+            module_id,
+            pushes,
+        }
+    }
+
+    fn parse_serial(arguments: &[String]) -> Option<u128>{
+        let argmap = parse_arguments(arguments.to_vec());
+        let serial = match argmap.get("serial") {
+            Some(Some(serial)) => serial,
+            Some(None) => {
+                output::error("Argument 'serial' requires a value");
+                return None;
+            }
+            None => {
+                output::error("Argument 'serial' is required");
+                return None;
+            }
+        };
+        match serial.parse::<u128>() {
+            Ok(serial) => Some(serial),
+            Err(_) => {
+                output::error("Argument 'serial' must be a positive integer");
+                None
+            }
+        }
+    }
+
+    pub fn push(&mut self, arguments: Vec<String>){
+        let serial = match Self::parse_serial(&arguments) {
+            Some(serial) => serial,
+            None => return,
+        };
+        let argmap = parse_arguments(arguments);
+        let peer = match argmap.get("peer") {
+            Some(Some(peer)) => peer.clone(),
+            _ => {
+                output::error("Argument 'peer' with a value is required");
+                return;
+            }
+        };
+        let destination = match resolve_peer(self.data_bus.get_name_service().as_ref(), &peer) {
+            Ok(destination) => destination.get(),
+            Err(error) => {
+                output::coded_error(format!("Argument 'peer' is invalid: {}", error), &error);
+                return;
+            }
+        };
+        let source = match self.data_bus.get_host_id() {
+            Some(source) => source,
+            None => {
+                output::error("Not connected to network");
+                return;
+            }
+        };
+        let mut binder = self.cert_binder.lock().unwrap();
+        let mut push = if let Some(certificate) = binder.get_signing_certificate(serial){
+            CertificatePushMessage::new_signing(&certificate)
+        } else if let Some(certificate) = binder.get_encryption_certificate(serial){
+            CertificatePushMessage::new_encryption(&certificate)
+        } else {
+            output::error("No certificate with such serial number");
+            return;
+        };
+        if argmap.contains_key("chain") && !push.add_chain(&mut **binder){
+            output::error("Can not find chain of certificate");
+            return;
+        }
+        let message = MessageBuilder::from_payload(&push)
+            .set_source(source)
+            .set_destination(destination)
+            .set_module_id(self.module_id)
+            .build()
+            .expect("All required fields are set");
+        if let Err(error) = self.data_bus.get_transport_service().try_send_message(message){
+            output::error(format!("Can not push certificate {} to {}: {}", serial, peer, error));
+            return;
+        }
+        output::info(format!("Pushed certificate {} to {}", serial, peer));
+    }
+
+    pub fn pending(&mut self){
+        let pending = self.pushes.get_pending();
+        let name_service = self.data_bus.get_name_service();
+        let mut table = Table::new(vec!["SERIAL", "NAME", "PARENT SERIAL", "CHAIN", "PUSHED BY"]);
+        for entry in pending.iter(){
+            table.add_row(vec![&entry.push.get_serial().to_string(), &entry.push.get_name(),
+                               &optional_serial_to_string(entry.push.get_parent_serial()),
+                               &entry.push.chain.len().to_string(),
+                               &name_service.get_name_by_id(entry.source)]);
+        }
+        table.display();
+    }
+
+    fn take_pending(&mut self, arguments: &[String]) -> Option<PendingCertificatePush>{
+        let serial = Self::parse_serial(arguments)?;
+        let entry = self.pushes.take_pending(serial);
+        if entry.is_none(){
+            output::error("No pending certificate with such serial number");
+        }
+        entry
+    }
+
+    pub fn accept(&mut self, arguments: Vec<String>){
+        let entry = match self.take_pending(&arguments) {
+            Some(entry) => entry,
+            None => return,
+        };
+        let peer = self.data_bus.get_name_service().get_name_by_id(entry.source);
+        if !confirm(&format!("Install certificate '{}' pushed by {}", entry.push.get_name(), peer)){
+            self.pushes.add_pending(entry);
+            return;
+        }
+        let mut binder = self.cert_binder.lock().unwrap();
+        match install_certificate_push(&mut **binder, &entry.push) {
+            Ok(report) => output::info(format!("Installed certificate, {} certificates added", report.applied)),
+            Err(error) => output::error(error),
+        }
+    }
+
+    pub fn reject(&mut self, arguments: Vec<String>){
+        if self.take_pending(&arguments).is_some(){
+            output::info("Rejected certificate");
+        }
+    }
+
+    pub fn set_policy(&mut self, arguments: Vec<String>){
+        let argmap = parse_arguments(arguments);
+        let policy = match argmap.get("policy") {
+            Some(Some(policy)) => policy.parse::<CertificatePushPolicy>(),
+            _ => {
+                output::info(format!("Current push policy: {}", self.pushes.get_policy()));
+                return;
+            }
+        };
+        match policy.map(|policy| self.pushes.set_policy(policy)) {
+            Ok(Ok(_)) => {}
+            Ok(Err(error)) => output::error(format!("Can not save push policy: {}", error)),
+            Err(error) => output::error(error),
+        }
+    }
+}
+
+impl CommandNamespace for PushNamespace{
+    fn on_command(&mut self, command: String, args: Vec<String>) {
+        match command.as_str() {
+            "push" => {
+                self.push(args);
+            }
+            "pending" => {
+                self.pending();
+            }
+            "accept" => {
+                self.accept(args);
+            }
+            "reject" => {
+                self.reject(args);
+            }
+            "push-policy" => {
+                self.set_policy(args);
+            }
+            "verify" => {
+                verify_certificate(&mut self.cert_binder.lock().unwrap(), args);
+            }
+            "search" => {
+                search_certificates(&mut self.cert_binder.lock().unwrap(), args);
+            }
+            "import-dir" => {
+                import_directory(self.cert_binder.clone(), args);
+            }
+            "commit" => {
+                commit_certificates(&mut self.cert_binder.lock().unwrap(), args);
+            }
+            &_ => {
+                output::error("No such command");
+            }
+        }
+    }
+
+    fn describe(&self) -> Vec<CommandDescription> {
+        vec![
+            CommandDescription::new("push", "Sends certificate to peer for installation", vec![
+                ArgumentDescription::required("serial", "Serial number of certificate"),
+                ArgumentDescription::required("peer", "ID or name of peer"),
+                ArgumentDescription::flag("chain", "Send signing certificates up to root as well"),
+            ]),
+            CommandDescription::new("pending", "Shows certificates pushed by peers", vec![]),
+            CommandDescription::new("accept", "Installs certificate pushed by peer", vec![
+                ArgumentDescription::required("serial", "Serial number of pushed certificate"),
+            ]),
+            CommandDescription::new("reject", "Discards certificate pushed by peer", vec![
+                ArgumentDescription::required("serial", "Serial number of pushed certificate"),
+            ]),
+            CommandDescription::new("push-policy", "Shows or sets handling of pushed certificates", vec![
+                ArgumentDescription::optional("policy", "One of reject, confirm, accept-verified"),
+            ]),
+            CommandDescription::new("verify", "Verifies certificate file without importing it", vec![
+                ArgumentDescription::required("file", "File with certificate"),
+                ArgumentDescription::optional("type", "Type of certificate: signing(default) or encryption"),
+                ArgumentDescription::optional("chain", "File with list of signing certificates up to root"),
+                ArgumentDescription::optional("root", "File with root certificate, root from store otherwise"),
+                ArgumentDescription::flag("machine", "Print result as key=value pairs"),
+            ]),
+            CommandDescription::new("search", "Finds certificates by name and metadata", vec![
+                ArgumentDescription::optional("name", "Substring of certificate name"),
+                ArgumentDescription::optional("owner", "Owner of certificate"),
+                ArgumentDescription::optional("tag", "Tag which must be set, as key or key:value"),
+                ArgumentDescription::optional("text", "Substring of name, owner or description"),
+                ArgumentDescription::optional("type", "signing or encryption, both by default"),
+                ArgumentDescription::flag("json", "Print every certificate as JSON object"),
+            ]),
+            CommandDescription::new("import-dir", "Imports all certificate files of directory", vec![
+                ArgumentDescription::required("path", "Directory with exported certificates"),
+                ArgumentDescription::flag("watch", "Keep importing files dropped into directory until interrupted"),
+            ]),
+            CommandDescription::new("commit", "Writes changes of certificates to store", vec![
+                ArgumentDescription::flag("wait", "Flush commit queue and wait until changes are durable, `--wait` works too"),
+            ]),
+        ]
+    }
+}
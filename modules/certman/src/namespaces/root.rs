@@ -1,18 +1,27 @@
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
-use colored::Colorize;
+use libmilkyway::cli::output;
 
 use libmilkyway::cli::arguments::parse_arguments;
+use libmilkyway::cli::describe::{ArgumentDescription, CommandDescription};
 use libmilkyway::cli::io::confirm;
-use libmilkyway::serialization::serializable::Serializable;
-use libmilkyway::serialization::deserializable::Deserializable;
 use libmilkyway::cli::router::CommandNamespace;
 use libmilkyway::cli::table::Table;
-use libmilkyway::pki::certificate::Certificate;
-use libmilkyway::pki::impls::certificates::falcon1024::{Falcon1024RootCertificate, generate_falcon1024_root_certificate};
-use libmilkyway::services::certificate::{CertificateService, CertificateServiceBinder};
-use crate::utils::certificates_flags_to_string;
+use libmilkyway::pki::ceremony::{reconstruct_root_key, split_root_key, DecryptedKeyShare, EncryptedKeyShare};
+use libmilkyway::pki::certificate::{Certificate, FLAG_ROOT_CERT};
+use libmilkyway::pki::certificate::flags::parse_flags;
+use libmilkyway::pki::hash::HashType;
+use libmilkyway::pki::impls::certificates::falcon1024::{Falcon1024Certificate, Falcon1024RootCertificate,
+                                                         generate_falcon1024_root_certificate};
+use libmilkyway::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use libmilkyway::pki::impls::keys::falcon1024::generate_falcon1024_keypair;
+use libmilkyway::pki::signature::Signature;
+use libmilkyway::services::certificate::{CertificateService, CertificateServiceBinder, ROOT_CERTIFICATE_SERIAL};
+use libmilkyway::pki::certificate::flags::format_flags_short;
+use crate::export::{check_export, read_export, read_key_export, write_export, write_key_export};
+use crate::utils::{check_writable, get_new_serial, parse_metadata, print_generated};
 
 pub struct RootNamespace{
     cert_binder: Arc<Mutex<Box<CertificateServiceBinder>>>,
@@ -28,13 +37,13 @@ impl RootNamespace {
     pub fn show(&mut self){
         let result = self.cert_binder.lock().unwrap().get_root_certificate();
         if result.is_none(){
-            println!("No root certificate found");
+            output::info("No root certificate found");
         } else {
             let certificate = result.unwrap();
             let flags = certificate.get_flags();
             let mut table = Table::new(vec!["SERIAL", "NAME", "FLAGS"]);
             table.add_row(vec![&certificate.get_serial().to_string(),
-                               &certificate.get_name(), &certificates_flags_to_string(flags)]);
+                               &certificate.get_name(), &format_flags_short(flags)]);
             table.display();
         }
     }
@@ -42,17 +51,17 @@ impl RootNamespace {
     pub fn generate(&mut self, arguments: Vec<String>){
         let argmap = parse_arguments(arguments);
         if !argmap.contains_key("name"){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'name' is required");
+            output::error("Argument 'name' is required");
             return;
         }
         let name = argmap.get("name").unwrap();
         if name.is_none(){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'name' requires a value");
+            output::error("Argument 'name' requires a value");
             return;
         }
         let name = name.clone().unwrap().to_string();
         let certificate = generate_falcon1024_root_certificate(name);
-        println!("Certificate generation successful");
+        output::info("Certificate generation successful");
         let mut binder = self.cert_binder.lock().unwrap();
         let old_certificate = binder.get_root_certificate();
         if old_certificate.is_some(){
@@ -62,24 +71,24 @@ impl RootNamespace {
         }
         binder.set_root_certificate(certificate);
         binder.commit();
-        println!("Registered certificate in service");
+        output::info("Registered certificate in service");
     }
     
     pub fn export(&mut self, arguments: Vec<String>){
         let argmap = parse_arguments(arguments);
         if !argmap.contains_key("file"){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'file' is required");
+            output::error("Argument 'file' is required");
             return;
         }
         let file = argmap.get("file").unwrap();
         if file.is_none(){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'file' requires a value");
+            output::error("Argument 'file' requires a value");
             return;
         }
         let mut binder = self.cert_binder.lock().unwrap();
         let certificate = binder.get_root_certificate();
         if certificate.is_none(){
-            println!("{} {}", "error:".red().bold().underline(), "No root certificate is available");
+            output::error("No root certificate is available");
             return;
         }
         let certificate = certificate.unwrap();
@@ -88,30 +97,38 @@ impl RootNamespace {
                 return;
             }
         }
-        certificate.dump(&file.clone().unwrap());
-        println!("Export successful");
+        if write_export(&mut binder, &argmap, &certificate, &file.clone().unwrap()){
+            output::info("Export successful");
+        }
     }
     
     pub fn import(&mut self, arguments: Vec<String>){
         let argmap = parse_arguments(arguments);
         if !argmap.contains_key("file"){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'file' is required");
+            output::error("Argument 'file' is required");
             return;
         }
         let file = argmap.get("file").unwrap();
         if file.is_none(){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'file' requires a value");
+            output::error("Argument 'file' requires a value");
             return;
         }
         let file = file.clone().unwrap();
-        let certificate_result = Falcon1024RootCertificate::from_file(Path::new(&file));
+        let export = match read_export(&file) {
+            Some(export) => export,
+            None => return,
+        };
+        let certificate_result = export.get_content::<Falcon1024RootCertificate>();
         if certificate_result.is_err(){
-            println!("{} {}", "error:".red().bold().underline(), "Can not read file. Does format is correct?");
+            output::error("Can not read file. Does format is correct?");
             return;
         }
-        println!("Loaded certificate successfully");
+        output::info("Loaded certificate successfully");
         let certificate = certificate_result.unwrap();
         let mut binder = self.cert_binder.lock().unwrap();
+        if !check_export(&mut binder, &argmap, &export, Some(&certificate)){
+            return;
+        }
         let old_certificate = binder.get_root_certificate();
         if old_certificate.is_some(){
             if !confirm("Root certificate is already generated"){
@@ -120,12 +137,335 @@ impl RootNamespace {
         }
         binder.set_root_certificate(certificate);
         binder.commit();
-        println!("Registered certificate in service");
+        output::info("Registered certificate in service");
+    }
+
+    fn get_file_argument(argmap: &HashMap<String, Option<String>>) -> Option<String>{
+        match argmap.get("file") {
+            Some(Some(file)) => Some(file.clone()),
+            Some(None) => {
+                output::error("Argument 'file' requires a value");
+                None
+            }
+            None => {
+                output::error("Argument 'file' is required");
+                None
+            }
+        }
+    }
+
+    pub fn export_key(&mut self, arguments: Vec<String>){
+        let argmap = parse_arguments(arguments);
+        let file = match Self::get_file_argument(&argmap) {
+            Some(file) => file,
+            None => return,
+        };
+        let certificate = match self.cert_binder.lock().unwrap().get_root_certificate() {
+            Some(certificate) => certificate,
+            None => {
+                output::error("No root certificate is available");
+                return;
+            }
+        };
+        if Path::new(&file).exists() && !confirm("File already exists"){
+            return;
+        }
+        if write_key_export(&argmap, &certificate, &file){
+            output::info("Export successful");
+        }
+    }
+
+    pub fn import_key(&mut self, arguments: Vec<String>){
+        let argmap = parse_arguments(arguments);
+        let file = match Self::get_file_argument(&argmap) {
+            Some(file) => file,
+            None => return,
+        };
+        let mut binder = self.cert_binder.lock().unwrap();
+        let mut certificate = match binder.get_root_certificate() {
+            Some(certificate) => certificate,
+            None => {
+                output::error("Import root certificate before its key");
+                return;
+            }
+        };
+        if certificate.secret_key.is_some() && !confirm("Root certificate already has secret key"){
+            return;
+        }
+        certificate.secret_key = match read_key_export(&argmap, &certificate, &file) {
+            Some(secret_key) => Some(secret_key),
+            None => return,
+        };
+        binder.set_root_certificate(certificate);
+        binder.commit();
+        output::info("Secret key of root certificate is imported");
+    }
+
+    // Arguments of command(those ones in argmap)
+    // * threshold -- count of shares required to restore key
+    // * custodians -- comma-separated files with exported encryption certificates of custodians,
+    //   one share is made for each. Only public keys are used, secret keys in files are ignored
+    // * directory -- directory to write shares to
+    // * name -- name of a new root certificate, secret key of current root is split if omitted
+    pub fn ceremony(&mut self, arguments: Vec<String>){
+        let argmap = parse_arguments(arguments);
+        let threshold = match Self::get_required_argument(&argmap, "threshold").map(|value| value.parse::<u8>()) {
+            Some(Ok(threshold)) => threshold,
+            Some(Err(_)) => {
+                output::error("Argument 'threshold' must be a number from 1 to 255");
+                return;
+            }
+            None => return,
+        };
+        let directory = match Self::get_required_argument(&argmap, "directory") {
+            Some(directory) => directory,
+            None => return,
+        };
+        let files = match Self::get_required_argument(&argmap, "custodians") {
+            Some(files) => files,
+            None => return,
+        };
+        let mut binder = self.cert_binder.lock().unwrap();
+        let mut custodians = Vec::new();
+        for file in files.split(','){
+            let export = match read_export(file.trim()) {
+                Some(export) => export,
+                None => return,
+            };
+            let mut custodian = match export.get_content::<Kyber1024Certificate>() {
+                Ok(custodian) => custodian,
+                Err(_) => {
+                    output::error(format!("File {} does not contain an encryption certificate", file));
+                    return;
+                }
+            };
+            if !check_export(&mut binder, &argmap, &export, None){
+                return;
+            }
+            if custodian.secret_key.take().is_some(){
+                output::warning(format!("File {} contains secret key of custodian {}, it is ignored",
+                                        file, custodian.get_serial()));
+            }
+            custodians.push(custodian);
+        }
+        let root = match argmap.get("name") {
+            Some(Some(name)) => {
+                if binder.get_root_certificate().is_some() && !confirm("Root certificate is already generated"){
+                    return;
+                }
+                generate_falcon1024_root_certificate(name.clone())
+            }
+            Some(None) => {
+                output::error("Argument 'name' requires a value");
+                return;
+            }
+            None => match binder.get_root_certificate() {
+                Some(root) => root,
+                None => {
+                    output::error("No root certificate is available");
+                    return;
+                }
+            },
+        };
+        let shares = match split_root_key(&root, threshold, &custodians) {
+            Ok(shares) => shares,
+            Err(error) => {
+                output::error(format!("Can not split key: {}", error));
+                return;
+            }
+        };
+        for share in shares.iter(){
+            let file = Path::new(&directory).join(format!("root-share-{}-{}.share", share.index, share.custodian_serial));
+            if let Err(error) = share.dump_to_file(file.to_str().unwrap()){
+                output::error(format!("Can not write {}: {}", file.display(), error));
+                return;
+            }
+            output::info(format!("Share {} for custodian {} is written to {}", share.index,
+                                 share.custodian_serial, file.display()));
+        }
+        // Key is kept only as shares from now on
+        binder.set_root_certificate(root.clone_without_sk());
+        binder.commit();
+        output::info(format!("Secret key of root certificate is split, {} of {} shares restore it",
+                             threshold, shares.len()));
+    }
+
+    // Runs on machine of custodian: decrypts share with encryption certificate of custodian from store
+    // Arguments of command(those ones in argmap)
+    // * share -- file with share made by ceremony command
+    // * output -- file to write decrypted share to, it is handed over to ceremony-sign
+    pub fn ceremony_decrypt(&mut self, arguments: Vec<String>){
+        let argmap = parse_arguments(arguments);
+        let file = match Self::get_required_argument(&argmap, "share") {
+            Some(file) => file,
+            None => return,
+        };
+        let output_file = match Self::get_required_argument(&argmap, "output") {
+            Some(output_file) => output_file,
+            None => return,
+        };
+        let share = match EncryptedKeyShare::read_from_file(Path::new(&file)) {
+            Ok(share) => share,
+            Err(error) => {
+                output::error(format!("Can not read share {}: {}", file, error));
+                return;
+            }
+        };
+        let custodian = match self.cert_binder.lock().unwrap().get_encryption_certificate(share.custodian_serial) {
+            Some(custodian) if custodian.get_secret_key().is_some() => custodian,
+            _ => {
+                output::error(format!("No encryption certificate of custodian {} with secret key", share.custodian_serial));
+                return;
+            }
+        };
+        let mut decrypted = match share.decrypt(&custodian) {
+            Ok(decrypted) => decrypted,
+            Err(error) => {
+                output::error(format!("Can not decrypt share {}: {}", file, error));
+                return;
+            }
+        };
+        let result = decrypted.dump_to_file(&output_file);
+        decrypted.wipe();
+        match result {
+            Ok(_) => {
+                output::info(format!("Share {} is decrypted to {}", share.index, output_file));
+                output::warning("File contains share of root key, delete it after ceremony");
+            }
+            Err(error) => output::error(format!("Can not write {}: {}", output_file, error)),
+        }
+    }
+
+    // Arguments of command(those ones in argmap)
+    // * shares -- comma-separated files with shares decrypted by their custodians(see ceremony_decrypt)
+    // * name -- name of signing certificate to issue
+    // * flags -- flags of signing certificate, optional
+    // * serial, description, owner, tags, lifetime -- see get_new_serial and parse_metadata
+    pub fn ceremony_sign(&mut self, arguments: Vec<String>){
+        let argmap = parse_arguments(arguments);
+        let files = match Self::get_required_argument(&argmap, "shares") {
+            Some(files) => files,
+            None => return,
+        };
+        let name = match Self::get_required_argument(&argmap, "name") {
+            Some(name) => name,
+            None => return,
+        };
+        let flags = match argmap.get("flags") {
+            Some(Some(flags)) => match parse_flags(flags, true) {
+                Ok(flags) if flags & FLAG_ROOT_CERT == 0 => flags,
+                Ok(_) => {
+                    output::error("Argument 'flags' is invalid: root certificate can not be issued");
+                    return;
+                }
+                Err(error) => {
+                    output::coded_error(format!("Argument 'flags' is invalid: {}", error), &error);
+                    return;
+                }
+            },
+            Some(None) => {
+                output::error("Argument 'flags' requires a value");
+                return;
+            }
+            None => 0,
+        };
+        let metadata = match parse_metadata(&argmap) {
+            Some(metadata) => metadata,
+            None => return,
+        };
+        let mut binder = self.cert_binder.lock().unwrap();
+        let root = match binder.get_root_certificate() {
+            Some(root) => root,
+            None => {
+                output::error("No root certificate is available");
+                return;
+            }
+        };
+        let serial = match get_new_serial(&mut binder, &argmap) {
+            Some(serial) => serial,
+            None => return,
+        };
+        let mut shares = Vec::new();
+        for file in files.split(','){
+            match DecryptedKeyShare::read_from_file(Path::new(file.trim())) {
+                Ok(share) => shares.push(share),
+                Err(error) => {
+                    output::error(format!("Can not read share {}: {}", file, error));
+                    shares.iter_mut().for_each(|share| share.wipe());
+                    return;
+                }
+            }
+        }
+        let (public_key, secret_key) = generate_falcon1024_keypair();
+        let mut certificate = Falcon1024Certificate{
+            serial_number: serial,
+            parent_serial_number: ROOT_CERTIFICATE_SERIAL,
+            secret_key: Some(secret_key),
+            public_key,
+            signature: None,
+            name,
+            flags,
+            metadata,
+        };
+        let signature = Self::sign_with_shares(&root, &shares, &certificate);
+        shares.iter_mut().for_each(|share| share.wipe());
+        certificate.signature = match signature {
+            Some(signature) => Some(signature),
+            None => return,
+        };
+        if !binder.add_signing_certificate(certificate){
+            output::error("Can not add certificate to service");
+            return;
+        }
+        binder.commit();
+        print_generated(&argmap, "signing", serial);
+    }
+
+    // Restores root key from shares, signs certificate and wipes key. Restored key is never
+    // given to certificate service
+    fn sign_with_shares(root: &Falcon1024RootCertificate, shares: &[DecryptedKeyShare],
+                        certificate: &Falcon1024Certificate) -> Option<Signature>{
+        let mut restored = match reconstruct_root_key(root, shares) {
+            Ok(restored) => restored,
+            Err(error) => {
+                output::error(format!("Can not restore key: {}", error));
+                return None;
+            }
+        };
+        let signature = restored.sign_data(&certificate.clone_without_signature_and_sk(), HashType::None);
+        if let Some(secret_key) = restored.secret_key.as_mut(){
+            secret_key.wipe();
+        }
+        match signature {
+            Ok(signature) => Some(signature),
+            Err(_) => {
+                output::error("Can not sign certificate");
+                None
+            }
+        }
+    }
+
+    fn get_required_argument(argmap: &HashMap<String, Option<String>>, name: &str) -> Option<String>{
+        match argmap.get(name) {
+            Some(Some(value)) => Some(value.clone()),
+            Some(None) => {
+                output::error(format!("Argument '{}' requires a value", name));
+                None
+            }
+            None => {
+                output::error(format!("Argument '{}' is required", name));
+                None
+            }
+        }
     }
 }
 
 impl CommandNamespace for RootNamespace{
     fn on_command(&mut self, command: String, args: Vec<String>) {
+        if matches!(command.as_str(), "generate" | "import" | "import-key" | "ceremony" | "ceremony-sign")
+            && !check_writable(&mut self.cert_binder.lock().unwrap()){
+            return;
+        }
         match command.as_str() {
             "show" => {
                 self.show();
@@ -139,9 +479,67 @@ impl CommandNamespace for RootNamespace{
             "import" => {
                 self.import(args)
             }
+            "export-key" => {
+                self.export_key(args);
+            }
+            "import-key" => {
+                self.import_key(args);
+            }
+            "ceremony" => {
+                self.ceremony(args);
+            }
+            "ceremony-decrypt" => {
+                self.ceremony_decrypt(args);
+            }
+            "ceremony-sign" => {
+                self.ceremony_sign(args);
+            }
             &_ => {
-                println!("{} {}", "error:".red().bold().underline(), "No such command");
+                output::error("No such command");
             }
         }
     }
+
+    fn describe(&self) -> Vec<CommandDescription> {
+        vec![
+            CommandDescription::new("show", "Shows root certificate", vec![]),
+            CommandDescription::new("generate", "Generates root certificate", vec![
+                ArgumentDescription::required("name", "Name of certificate"),
+            ]),
+            CommandDescription::new("export", "Exports root certificate to file", vec![
+                ArgumentDescription::required("file", "File to write certificate to"),
+                ArgumentDescription::optional("signer", "Serial of certificate signing export, root by default"),
+                ArgumentDescription::flag("unsigned", "Do not sign export"),
+            ]),
+            CommandDescription::new("import", "Imports root certificate from file", vec![
+                ArgumentDescription::required("file", "File with certificate"),
+                ArgumentDescription::optional("max_age", "Reject exports older than given count of seconds"),
+            ]),
+            CommandDescription::new("export-key", "Exports secret key of root certificate encrypted with passphrase", vec![
+                ArgumentDescription::required("file", "File to write key to"),
+                ArgumentDescription::optional("passphrase", "Passphrase, MWAY_KEY_PASSPHRASE by default"),
+            ]),
+            CommandDescription::new("import-key", "Imports secret key of root certificate already in store", vec![
+                ArgumentDescription::required("file", "File with key"),
+                ArgumentDescription::optional("passphrase", "Passphrase, MWAY_KEY_PASSPHRASE by default"),
+            ]),
+            CommandDescription::new("ceremony", "Splits secret key of root certificate between custodians", vec![
+                ArgumentDescription::required("threshold", "Count of shares required to restore key"),
+                ArgumentDescription::required("custodians", "Comma-separated files with exported encryption certificates of custodians"),
+                ArgumentDescription::required("directory", "Directory to write shares to"),
+                ArgumentDescription::optional("name", "Generate a new root certificate with given name"),
+            ]),
+            CommandDescription::new("ceremony-decrypt", "Decrypts share of root key with encryption certificate of custodian", vec![
+                ArgumentDescription::required("share", "File with share"),
+                ArgumentDescription::required("output", "File to write decrypted share to"),
+            ]),
+            CommandDescription::new("ceremony-sign", "Issues signing certificate with root key restored from shares", vec![
+                ArgumentDescription::required("shares", "Comma-separated files with shares decrypted by custodians"),
+                ArgumentDescription::required("name", "Name of certificate"),
+                ArgumentDescription::optional("flags", "Flags of certificate"),
+                ArgumentDescription::optional("serial", "Serial of certificate, a free one by default"),
+                ArgumentDescription::optional("lifetime", "Days certificate is valid for, forever if omitted"),
+            ]),
+        ]
+    }
 }
\ No newline at end of file
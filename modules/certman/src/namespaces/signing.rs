@@ -1,37 +1,63 @@
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufReader, Read, Write};
+use std::io::{BufReader, Write};
 use std::path::Path;
 use std::sync::{Arc, Mutex};
-use colored::Colorize;
+use std::time::Instant;
+use libmilkyway::cli::output;
 use libmilkyway::cli::arguments::parse_arguments;
+use libmilkyway::cli::completion::CompletionCache;
+use libmilkyway::cli::describe::{ArgumentDescription, ArgumentKind, CommandDescription};
+use libmilkyway::cli::io::confirm;
 use libmilkyway::cli::router::CommandNamespace;
 use libmilkyway::cli::table::Table;
-use libmilkyway::pki::certificate::{Certificate, FLAG_CLIENT_CERT, FLAG_NO_READ, FLAG_NO_WRITE, FLAG_SERVER_CERT, FLAG_SIGN_CERTS, FLAG_SIGN_MESSAGES, FLAG_USER_CERT};
-use libmilkyway::pki::hash::HashType;
+use libmilkyway::pki::certificate::{Certificate, FLAG_ROOT_CERT, FLAG_SIGN_CERTS, FLAG_SIGN_MESSAGES};
+use libmilkyway::pki::certificate::flags::{format_flags, parse_flags, FlagError};
+use libmilkyway::pki::certificate::metadata::CertificateMetadata;
+use libmilkyway::pki::certificate::profile::{find_profile, get_profiles, CertificateProfile};
+use libmilkyway::pki::hash::{Hash, HashType, Hasher};
 use libmilkyway::pki::impls::certificates::falcon1024::Falcon1024Certificate;
 use libmilkyway::pki::impls::keys::falcon1024::generate_falcon1024_keypair;
+use libmilkyway::pki::signature::{hash_file_chunks, is_chunked_signature, ChunkedFileSignature, FileSignature,
+                                  Signature, DEFAULT_SIGNATURE_CHUNK_SIZE};
 use libmilkyway::serialization::deserializable::Deserializable;
-use libmilkyway::serialization::serializable::Serializable;
-use libmilkyway::services::certificate::{CertificateService, CertificateServiceBinder, ROOT_CERTIFICATE_SERIAL};
-use crate::utils::{certificates_flags_to_string, optional_serial_to_string};
+use libmilkyway::services::certificate::{CertificateService, CertificateServiceBinder, VerifiableCertificate,
+                                         ROOT_CERTIFICATE_SERIAL};
+use libmilkyway::services::certificate::usage::KeyUsage;
+use crate::export::{check_export, read_export, read_key_export, write_export, write_key_export};
+use crate::utils::{check_sign_policy, check_writable, complete_serials, get_new_serial, parse_metadata,
+                   print_generated, show_certificates};
 
 
-const SIGNING_CHUNK_SIZE: usize = 65536;
+// Who signed file and when, if signature embeds time of signing
+fn describe_signer(serial: u128, signature: &Signature) -> String{
+    match signature.get_timestamp() {
+        Some(timestamp) => format!("signed by {} at {}", serial, timestamp),
+        None => format!("signed by {}", serial),
+    }
+}
 
 pub struct SigningNamespace{
     cert_binder: Arc<Mutex<Box<CertificateServiceBinder>>>,
+    /** Profiles configured on host, built-in ones are not included **/
+    profiles: Vec<CertificateProfile>,
+    completions: CompletionCache,
 }
 
 impl SigningNamespace {
-    pub fn new(binder: Arc<Mutex<Box<CertificateServiceBinder>>>) -> Self{
+    pub fn new(binder: Arc<Mutex<Box<CertificateServiceBinder>>>, profiles: Vec<CertificateProfile>,
+               completions: CompletionCache) -> Self{
         SigningNamespace{
-            cert_binder: binder
+            cert_binder: binder,
+            profiles,
+            completions,
         }
     }
 
     fn generate_signed_certificate(&self, binder: &mut Box<CertificateServiceBinder>, serial_number: u128,
                                    parent_serial_number: u128, /* Serial number of certificate to sign with */
-                                   name: String, flags: u128) -> Result<Falcon1024Certificate, &'static str>{
+                                   name: String, flags: u128,
+                                   metadata: CertificateMetadata) -> Result<Falcon1024Certificate, &'static str>{
         if parent_serial_number==ROOT_CERTIFICATE_SERIAL{
             let root_certificate = binder.get_root_certificate();
             if root_certificate.is_none(){
@@ -47,6 +73,7 @@ impl SigningNamespace {
                 signature: None,
                 name: name,
                 flags: flags,
+                metadata: metadata.clone(),
             };
             let result = root_certificate.sign_data(&certificate.clone_without_signature_and_sk(),
                                                     HashType::None);
@@ -74,6 +101,7 @@ impl SigningNamespace {
                 signature: None,
                 name: name,
                 flags: flags,
+                metadata,
             };
             let result = parent_certificate.sign_data(&certificate.clone_without_signature_and_sk(), HashType::None);
             if result.is_err(){
@@ -83,41 +111,13 @@ impl SigningNamespace {
             return Ok(certificate);
         }
     }
-    fn parse_flags(value: String) -> Option<u128> {
-        let flags = value.split(",");
-        let mut result = 0;
-        for flag in flags{
-            if flag == "no-read"{
-                result = result | FLAG_NO_READ;
-                continue;
-            }
-            if flag == "no-write" {
-                result = result | FLAG_NO_WRITE;
-                continue;
-            }
-            if flag == "sign-messages" {
-                result = result | FLAG_SIGN_MESSAGES;
-                continue;
-            }
-            if flag == "sign-certs" {
-                result = result | FLAG_SIGN_CERTS;
-                continue;
-            }
-            if flag == "client-cert" {
-                result = result | FLAG_CLIENT_CERT;
-                continue;
-            }
-            if flag == "server-cert" {
-                result = result | FLAG_SERVER_CERT;
-                continue;
-            }
-            if flag == "user-cert" {
-                result = result | FLAG_USER_CERT;
-                continue;
-            }
-            return None;
+    fn parse_flags(value: String) -> Result<u128, FlagError> {
+        let flags = parse_flags(&value, true)?;
+        if flags & FLAG_ROOT_CERT != 0 {
+            // Root certificates are only created with `root generate`
+            return Err(FlagError::UnknownFlag("root".to_string()));
         }
-        return Some(result);
+        Ok(flags)
     }
 
 
@@ -126,50 +126,32 @@ impl SigningNamespace {
     // * parent -- a serial number of parent certificate
     // * name -- a name of certificate
     // * flags -- flags list, optional(use parse_flags), if not provided default 0
+    // * profile -- a profile, optional. Its flags are added to flags and name follows its naming convention
     pub fn generate(&mut self, arguments: Vec<String>){
         let argmap = parse_arguments(arguments);
-        /* Check serial */
-        if !argmap.contains_key("serial"){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'serial' is required");
-            return;
-        }
-        let serial = argmap.get("serial").unwrap();
-        if serial.is_none(){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'serial' must have a value");
-            return;
-        }
-        let serial = serial.clone().unwrap();
-        let serial = serial.parse::<u128>();
-        if serial.is_err(){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument serial must be a positive number");
-            return;
-        }
-        let serial = serial.unwrap();
         if !argmap.contains_key("parent"){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'parent' is required");
+            output::error("Argument 'parent' is required");
             return;
         }
         let parent = argmap.get("parent").unwrap();
         if parent.is_none(){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'parent' must have a value");
+            output::error("Argument 'parent' must have a value");
             return;
         }
         let parent = parent.clone().unwrap();
         let parent = parent.parse::<u128>();
         if parent.is_err(){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument 'parent' must be a positive number");
+            output::error("Argument 'parent' must be a positive number");
             return;
         }
         let parent = parent.unwrap();
         if !argmap.contains_key("name"){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'name' is required");
+            output::error("Argument 'name' is required");
             return;
         }
         let name = argmap.get("name").unwrap();
         if name.is_none(){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'name' requires a value");
+            output::error("Argument 'name' requires a value");
             return;
         }
         let name = name.clone().unwrap();
@@ -177,55 +159,88 @@ impl SigningNamespace {
         if argmap.contains_key("flags"){
             let flags_argument =  argmap.get("flags").unwrap();
             if flags_argument.is_none(){
-                println!("{} {}", "error:".red().bold().underline(), "Argument 'flags' requires a value");
+                output::error("Argument 'flags' requires a value");
                 return;
             }
             let flags_result = Self::parse_flags(flags_argument.clone().unwrap());
-            if flags_result.is_none(){
-                println!("{} {}", "error:".red().bold().underline(), "Argument 'flags' is invalid");
+            if let Err(error) = flags_result{
+                output::coded_error(format!("Argument 'flags' is invalid: {}", error), &error);
                 return;
             }
             flags = flags_result.unwrap();
         }
+        let mut name = name;
+        if argmap.contains_key("profile"){
+            let profile = match Self::get_required_argument(&argmap, "profile") {
+                Some(profile) => profile,
+                None => return,
+            };
+            let profile = match find_profile(&profile, &self.profiles) {
+                Ok(profile) => profile,
+                Err(error) => {
+                    output::coded_error(format!("Argument 'profile' is invalid: {}", error), &error);
+                    return;
+                }
+            };
+            name = match profile.make_name(&name) {
+                Ok(name) => name,
+                Err(error) => {
+                    output::coded_error(format!("Argument 'name' is invalid: {}", error), &error);
+                    return;
+                }
+            };
+            flags |= profile.flags;
+        }
+        let metadata = match parse_metadata(&argmap) {
+            Some(metadata) => metadata,
+            None => return,
+        };
         let mut binder = self.cert_binder.lock().unwrap();
+        if !check_sign_policy(&mut binder, parent, &name, flags){
+            return;
+        }
+        let serial = match get_new_serial(&mut binder, &argmap) {
+            Some(serial) => serial,
+            None => return,
+        };
         let signed_certificate = self.generate_signed_certificate(&mut binder,
-                                                                  serial, parent, name, flags);
+                                                                  serial, parent, name, flags, metadata);
         if signed_certificate.is_err(){
-            println!("{} {}", "error:".red().bold().underline(),signed_certificate.err().unwrap());
+            output::error(signed_certificate.err().unwrap());
             return;
         }
         let signed_certificate = signed_certificate.unwrap();
         let result = binder.add_signing_certificate(signed_certificate);
         if !result{
-            println!("{} {}", "error:".red().bold().underline(), "Can not add certificate to servise");
+            output::error("Can not add certificate to servise");
             return;
         }
         binder.commit();
+        print_generated(&argmap, "signing", serial);
     }
 
     pub fn remove(&mut self, arguments: Vec<String>){
         let argmap = parse_arguments(arguments);
         if !argmap.contains_key("serial"){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'serial' is required");
+            output::error("Argument 'serial' is required");
             return;
         }
         let serial = argmap.get("serial").unwrap();
         if serial.is_none(){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'serial' must have a value");
+            output::error("Argument 'serial' must have a value");
             return;
         }
         let serial = serial.clone().unwrap();
         let serial = serial.parse::<u128>();
         if serial.is_err(){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument serial must be a positive number");
+            output::error("Argument serial must be a positive number");
             return;
         }
         let serial = serial.unwrap();
         let mut binder = self.cert_binder.lock().unwrap();
         let result = binder.remove_signing_certificate(serial);
         if !result {
-            println!("{} {}", "error:".red().bold().underline(), "Can not remove certificate");
+            output::error("Can not remove certificate");
             return;
         }
     }
@@ -235,299 +250,465 @@ impl SigningNamespace {
         println!("{:?}", parse_arguments(arguments.clone()));
         let argmap = parse_arguments(arguments);
         if !argmap.contains_key("file"){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'file' is required");
+            output::error("Argument 'file' is required");
             return;
         }
         let file = argmap.get("file").unwrap();
         if file.is_none(){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'file' requires a value");
+            output::error("Argument 'file' requires a value");
             return;
         }
         if !argmap.contains_key("serial") {
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'serial' is required");
+            output::error("Argument 'serial' is required");
             return;
         }
         let serial = argmap.get("serial").unwrap();
         if serial.is_none(){
-            println!("{} {}", "error:".red().bold().underline(), "Argument 'serial' requires a value");
+            output::error("Argument 'serial' requires a value");
             return;
         }
         let mut binder = self.cert_binder.lock().unwrap();
         let serial = serial.clone().unwrap().parse::<u128>();
         if serial.is_err(){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument 'serial' must be a positive integer");
+            output::error("Argument 'serial' must be a positive integer");
             return;
         }
         let serial = serial.unwrap();
         if serial==0{
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Can not export root certificate");
+            output::error("Can not export root certificate");
             return;
         }
         let certificate = binder.get_signing_certificate(serial);
         if certificate.is_none(){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "No certificate with such serial number");
+            output::error("No certificate with such serial number");
             return;
         }
         let certificate = certificate.unwrap();
-        certificate.dump(&file.clone().unwrap());
+        write_export(&mut binder, &argmap, &certificate, &file.clone().unwrap());
     }
 
     pub fn import(&mut self, arguments: Vec<String>){
         let argmap = parse_arguments(arguments);
+        if argmap.contains_key("bundle"){
+            if let Some(bundle) = Self::get_required_argument(&argmap, "bundle"){
+                self.import_bundle(&argmap, &bundle);
+            }
+            return;
+        }
         if !argmap.contains_key("file"){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument 'file' is required");
+            output::error("Argument 'file' is required");
             return;
         }
         //None
         //Some(_)
         let argument = argmap.get("file").unwrap();
         if argument.is_none(){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument 'file' requires a value");
+            output::error("Argument 'file' requires a value");
             return;
         }
         let file_name = argument.clone().unwrap();
-        let certificate = Falcon1024Certificate::from_file(Path::new(&file_name));
+        let export = match read_export(&file_name) {
+            Some(export) => export,
+            None => return,
+        };
+        let certificate = export.get_content::<Falcon1024Certificate>();
         if certificate.is_err(){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Can not read a certificate");
+            output::error("Can not read a certificate");
             return;
         }
         let certificate = certificate.unwrap();
         let mut binder = self.cert_binder.lock().unwrap();
+        if !check_export(&mut binder, &argmap, &export, None){
+            return;
+        }
         let result = binder.add_signing_certificate(certificate);
         if !result{
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Can not add certificate to service");
+            output::error("Can not add certificate to service");
             return;
         }
     }
 
-    // sign-file file=/tmp/satanic_kitten_orgy
-    pub fn sign_file(&mut self, arguments: Vec<String>) {
-        let argmap = parse_arguments(arguments);
-        if !argmap.contains_key("signature-file") {
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument 'signature-file' is required");
-            return;
-        }
-        let signature_file = argmap.get("signature-file").unwrap();
-        if signature_file.is_none() {
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument 'signature-file' requires a value");
-            return;
+    ///
+    /// Gets certificate by value of required argument 'serial'
+    ///
+    fn get_certificate_argument(binder: &mut Box<CertificateServiceBinder>,
+                                argmap: &HashMap<String, Option<String>>) -> Option<Falcon1024Certificate>{
+        let serial = match Self::get_required_argument(argmap, "serial").map(|serial| serial.parse::<u128>()) {
+            Some(Ok(serial)) => serial,
+            Some(Err(_)) => {
+                output::error("Argument 'serial' must be a positive integer");
+                return None;
+            }
+            None => return None,
+        };
+        let certificate = binder.get_signing_certificate(serial);
+        if certificate.is_none(){
+            output::error("No certificate with such serial number");
         }
-        // use std::fs::File;
-        // use std::io::Write;
-        //
-        // fn main() -> std::io::Result<()> {
-        //     // Create a file named "example.txt"
-        //     let mut file = File::create("example.txt")?;
-        //
-        //     // Write some data to the file
-        //     file.write_all(b"Hello, world!")?;
-        //
-        //     Ok(())
-        // }
-        let mut signature_file = File::create(signature_file);
-        if signature_file.is_err() {
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Can not create signature file");
-            return;
+        certificate
+    }
+
+    // export-key serial=1 file=/tmp/1.sk passphrase=...
+    pub fn export_key(&mut self, arguments: Vec<String>){
+        let argmap = parse_arguments(arguments);
+        let file = match Self::get_required_argument(&argmap, "file") {
+            Some(file) => file,
+            None => return,
+        };
+        let mut binder = self.cert_binder.lock().unwrap();
+        let certificate = match Self::get_certificate_argument(&mut binder, &argmap) {
+            Some(certificate) => certificate,
+            None => return,
+        };
+        if write_key_export(&argmap, &certificate, &file){
+            output::info("Export successful");
         }
-        let mut signature_file = signature_file.unwrap();
-        if !argmap.contains_key("file") {
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument 'file' is required");
+    }
+
+    // import-key serial=1 file=/tmp/1.sk passphrase=...
+    pub fn import_key(&mut self, arguments: Vec<String>){
+        let argmap = parse_arguments(arguments);
+        let file = match Self::get_required_argument(&argmap, "file") {
+            Some(file) => file,
+            None => return,
+        };
+        let mut binder = self.cert_binder.lock().unwrap();
+        let mut certificate = match Self::get_certificate_argument(&mut binder, &argmap) {
+            Some(certificate) => certificate,
+            None => return,
+        };
+        if certificate.secret_key.is_some() && !confirm("Certificate already has secret key"){
             return;
         }
-        let file = argmap.get("file").unwrap();
-        if file.is_none() {
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument 'file' requires a value");
+        certificate.secret_key = match read_key_export(&argmap, &certificate, &file) {
+            Some(secret_key) => Some(secret_key),
+            None => return,
+        };
+        // Store has no way to update certificate, so it is re-added with key
+        let serial = certificate.get_serial();
+        if !binder.remove_signing_certificate(serial) || !binder.add_signing_certificate(certificate){
+            output::error("Can not add certificate to service");
             return;
         }
-        let file = File::open(file.clone().unwrap());
-        if file.is_err() {
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Can not open file");
-            return;
+        binder.commit();
+        output::info(format!("Secret key of certificate {} is imported", serial));
+    }
+
+    ///
+    /// Imports list of signing certificates. Certificates are verified in bulk, parents
+    /// must be either already known or present in bundle.
+    ///
+    fn import_bundle(&mut self, argmap: &HashMap<String, Option<String>>, file_name: &str){
+        let export = match read_export(file_name) {
+            Some(export) => export,
+            None => return,
+        };
+        let mut pending = match export.get_content::<Vec<Falcon1024Certificate>>() {
+            Ok(bundle) => bundle,
+            Err(_) => {
+                output::error("Can not read a bundle of certificates");
+                return;
+            }
+        };
+        let mut binder = self.cert_binder.lock().unwrap();
+        if !check_export(&mut binder, argmap, &export, None){
+            return;
+        }
+        let mut table = Table::new(vec!["SERIAL", "NAME", "RESULT"]);
+        // Each round imports certificates which parents are already known, so bundle may be in any order
+        while !pending.is_empty(){
+            let certificates: Vec<VerifiableCertificate> = pending.iter().cloned()
+                .map(|certificate| VerifiableCertificate::Signing(Box::new(certificate)))
+                .collect();
+            let verified = binder.verify_many(&certificates);
+            let mut rejected = Vec::<Falcon1024Certificate>::new();
+            for (certificate, is_valid) in pending.into_iter().zip(verified){
+                if is_valid && binder.add_signing_certificate(certificate.clone()){
+                    table.add_row(vec![&certificate.get_serial().to_string(), &certificate.get_name(), "imported"]);
+                } else {
+                    rejected.push(certificate);
+                }
+            }
+            let is_stuck = rejected.len() == certificates.len();
+            pending = rejected;
+            if is_stuck{
+                break;
+            }
         }
-        let argument = argmap.get("serial");
-        if argument.is_none() {
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument 'serial' is required");
-            return;
+        for certificate in pending{
+            table.add_row(vec![&certificate.get_serial().to_string(), &certificate.get_name(), "rejected"]);
         }
-        let argument = argument.unwrap();
-        if argument.is_none() {
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument requires a value");
-            return;
+        table.display();
+    }
+
+    ///
+    /// Gets value of a required argument, printing an error if it is missing
+    ///
+    fn get_required_argument(argmap: &HashMap<String, Option<String>>, name: &str) -> Option<String>{
+        match argmap.get(name) {
+            Some(Some(value)) => Some(value.clone()),
+            Some(None) => {
+                output::error(format!("Argument '{}' requires a value", name));
+                None
+            }
+            None => {
+                output::error(format!("Argument '{}' is required", name));
+                None
+            }
         }
-        let argument = argument.parse::<u128>();
-        if argument.is_err() {
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument 'serial' must be a positive integer");
-            return;
+    }
+
+    ///
+    /// Hashes file in a streaming fashion, so files of any size are never read into memory
+    ///
+    fn hash_file(file_name: &str, hash_type: HashType) -> Option<Hash>{
+        let file = match File::open(file_name) {
+            Ok(file) => file,
+            Err(_) => {
+                output::error("Can not open file");
+                return None;
+            }
+        };
+        match Hasher::hash_reader(hash_type, &mut BufReader::new(file)) {
+            Ok(hash) => Some(hash),
+            Err(_) => {
+                output::error("Can not read file");
+                None
+            }
         }
-        let argument = argument.unwrap();
-        let mut binder = self.cert_binder.lock().unwrap();
-        let certificate = binder.get_signing_certificate(argument);
-        if certificate.is_none() {
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Can not find certificate");
-            return;
+    }
+
+    ///
+    /// Gets count of hashing threads from optional argument 'jobs', all CPUs are used by default
+    ///
+    fn get_jobs(argmap: &HashMap<String, Option<String>>) -> Option<usize>{
+        match argmap.get("jobs") {
+            None => Some(std::thread::available_parallelism().map_or(1, |jobs| jobs.get())),
+            Some(value) => match value.as_ref().map(|value| value.parse::<usize>()) {
+                Some(Ok(jobs)) if jobs > 0 => Some(jobs),
+                _ => {
+                    output::error("Argument 'jobs' must be a positive integer");
+                    None
+                }
+            },
         }
-        let certificate = certificate.unwrap();
-        //Result<Type, ErrorType>
-        // * Some(value): Result<Type, ErrorType>
-        // * Err(error_value): Result<Type, ErrorType>
-        // .unwrap() ->
-        // * value: Type
-        // * PANIC
-        //Option<Type>
-        // * Some(value): Option<Type>
-        // * None: Option<Type>
-        //.unwrap() ->
-        // * value: Type
-        // * PANIC
-        let file = file.unwrap();
-        let mut reader = BufReader::new(file);
-        let mut buffer = vec![0u8; SIGNING_CHUNK_SIZE];
-        loop {
-            let bytes_read = reader.read(&mut buffer).unwrap();
-            if bytes_read == 0 {
-                break;
+    }
+
+    ///
+    /// Hashes file by chunks on several threads and reports throughput
+    ///
+    fn hash_file_chunks(file_name: &str, hash_type: HashType, chunk_size: u64, jobs: usize) -> Option<(Vec<Hash>, u64)>{
+        let started = Instant::now();
+        let (hashes, length) = match hash_file_chunks(Path::new(file_name), hash_type, chunk_size, jobs) {
+            Ok(result) => result,
+            Err(error) => {
+                output::error(format!("Can not read file: {}", error));
+                return None;
+            }
+        };
+        let elapsed = started.elapsed().as_secs_f64();
+        output::info(format!("Hashed {} bytes in {} chunks with {} jobs in {:.2} s({:.1} MiB/s)", length,
+                             hashes.len(), jobs, elapsed, length as f64 / 1048576.0 / elapsed.max(1e-6)));
+        Some((hashes, length))
+    }
+
+    // sign-file file=/tmp/file signature-file=/tmp/file.sig serial=1 [jobs=4] [chunk-size=16777216]
+    pub fn sign_file(&mut self, arguments: Vec<String>) {
+        let argmap = parse_arguments(arguments);
+        let file_name = match Self::get_required_argument(&argmap, "file") {
+            Some(file_name) => file_name,
+            None => return,
+        };
+        let signature_file = match Self::get_required_argument(&argmap, "signature-file") {
+            Some(signature_file) => signature_file,
+            None => return,
+        };
+        let serial = match Self::get_required_argument(&argmap, "serial").map(|serial| serial.parse::<u128>()) {
+            Some(Ok(serial)) => serial,
+            Some(Err(_)) => {
+                output::error("Argument 'serial' must be a positive integer");
+                return;
             }
-            let data = &buffer[..bytes_read];
-            let signature = certificate.sign_data(data, HashType::None);
-            if signature.is_err() {
-                println!("{} {}", "error:".red().bold().underline(),
-                         "Can not sign chunk");
+            None => return,
+        };
+        let certificate = self.cert_binder.lock().unwrap().get_signing_certificate(serial);
+        let certificate = match certificate {
+            Some(certificate) => certificate,
+            None => {
+                output::error("Can not find certificate");
                 return;
             }
-            let signature = signature.unwrap();
-            let serialized_signature = signature.serialize();
-            let serialized_signature_size = serialized_signature.len();
-            if signature_file.write_all(serialized_signature_size.serialize()).is_err() {
-                if signature_file.write_all(signature.serialize()).is_err() {
-                    println!("{} {}", "error:".red().bold().underline(),
-                             "Can not write signature file");
-                    return;
-                }
-                if signature_file.write_all(signature.serialize()).is_err() {
-                    println!("{} {}", "error:".red().bold().underline(),
-                             "Can not write signature file");
+        };
+        if certificate.get_secret_key().is_none(){
+            output::error("Can not sign file, certificate has no secret key");
+            return;
+        }
+        let jobs = match Self::get_jobs(&argmap) {
+            Some(jobs) => jobs,
+            None => return,
+        };
+        let chunk_size = match argmap.get("chunk-size") {
+            None => DEFAULT_SIGNATURE_CHUNK_SIZE,
+            Some(value) => match value.as_ref().map(|value| value.parse::<u64>()) {
+                Some(Ok(chunk_size)) if chunk_size > 0 => chunk_size,
+                _ => {
+                    output::error("Argument 'chunk-size' must be a positive integer");
                     return;
                 }
+            },
+        };
+        let (hashes, length) = match Self::hash_file_chunks(&file_name, HashType::SHA512, chunk_size, jobs) {
+            Some(result) => result,
+            None => return,
+        };
+        let signature = match ChunkedFileSignature::sign(hashes, chunk_size, length, &certificate) {
+            Ok(signature) => signature,
+            Err(error) => {
+                output::error(format!("Can not sign file: {:?}", error));
+                return;
             }
-
-            //chunks
-            //reading chunk by chunk
-            //use std::fs::File;
-            // use std::io::{self, Read, BufReader};
-            //
-            // fn main() -> io::Result<()> {
-            //     // Open the file in read-only mode
-            //     let file = File::open("path/to/your/file.txt").unwrap();
-            //     let mut reader = BufReader::new(file);
-            //
-            //     // Define the size of each chunk
-            //     let chunk_size = 1024;
-            //     let mut buffer = vec![0; chunk_size];
-            //
-            //     loop {
-            //         // Read a chunk of the file
-            //         let bytes_read = reader.read(&mut buffer).unwrap();
-            //
-            //         // If no more bytes are read, we've reached the end of the file
-            //         if bytes_read == 0 {
-            //             break;
-            //         }
-            //
-            //         // Process the chunk (here we simply print it as a string)
-            //         let data = &buffer[..bytes_read];
-            //     }
-            //
-            //     Ok(())
-            // }
-
-            /*
-        File: Kuzya, Watson, Murczyk, Pusheen, Fintus
-        Buffer: [_, _]
-        read(File) -> 2
-        Buffer: [Kuzya, Watson] -> szpital
-        read(File) -> 2
-        Buffer [Murczyk, Pusheen] -> szpital
-        read(File) -> 1
-        [Fintus, _] -> szpital
-        read(File) -> 0
-         */
-        }
-    }
-
-    pub fn verify_file_signature(&mut self, argument: Vec<String>){
-        let argmap = parse_arguments(argument);
-        if !argmap.contains_key("file"){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument 'file' is required");
-            return;
-        }
-        let file_name = argmap.get("file").unwrap();
-        if file_name.is_none(){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument 'file' requires a value");
+        };
+        let written = File::create(&signature_file)
+            .and_then(|mut file| file.write_all(&signature.to_bytes()));
+        if written.is_err(){
+            output::error("Can not write signature file");
             return;
         }
-        let file_name = file_name.clone().unwrap();
-        if !argmap.contains_key("signature-file"){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument 'signature-file' is required");
-            return;
+        let mut binder = self.cert_binder.lock().unwrap();
+        if binder.record_key_usage(KeyUsage::new(serial).set_signatures(1).clone()){
+            output::warning(format!("Certificate {} reached usage threshold and should be rotated", serial));
         }
-        let signature_file = argmap.get("signature-file").unwrap();
-        if signature_file.is_none(){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Argument 'signature-file' requires a value");
+        binder.commit();
+        output::info(format!("Signature written to {}", signature_file));
+    }
+
+    pub fn verify_file_signature(&mut self, arguments: Vec<String>){
+        let argmap = parse_arguments(arguments);
+        let file_name = match Self::get_required_argument(&argmap, "file") {
+            Some(file_name) => file_name,
+            None => return,
+        };
+        let signature_file = match Self::get_required_argument(&argmap, "signature-file") {
+            Some(signature_file) => signature_file,
+            None => return,
+        };
+        let signature = match std::fs::read(&signature_file) {
+            Ok(signature) => signature,
+            Err(_) => {
+                output::error("Can not open signature-file");
+                return;
+            }
+        };
+        if is_chunked_signature(&signature){
+            self.verify_chunked_signature(&argmap, &file_name, &signature);
             return;
         }
-        let mut signature_file = File::open(signature_file.clone().unwrap());
-        let mut file = File::open(file_name);
-        if signature_file.is_err(){
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Can not open signature-file");
-            return;
+        let signature = match FileSignature::from_serialized(&signature) {
+            Ok((signature, _)) => signature,
+            Err(_) => {
+                output::error("Malformed signature-file");
+                return;
+            }
+        };
+        let certificate = match self.get_signer_certificate(signature.get_signer_serial()) {
+            Some(certificate) => certificate,
+            None => return,
+        };
+        let hash = match Self::hash_file(&file_name, signature.hash_type.clone()) {
+            Some(hash) => hash,
+            None => return,
+        };
+        if signature.verify(&hash, &certificate){
+            output::info(format!("Signature is valid, {}", describe_signer(signature.get_signer_serial(),
+                                                                           &signature.signature)));
+        } else {
+            output::error("Signature is not valid");
         }
-        let signature_file = signature_file.unwrap();
-        if file.is_err() {
-            println!("{} {}", "error:".red().bold().underline(),
-                     "Can not open file");
-            return;
+    }
+
+    ///
+    /// Gets certificate which signed file if it is trusted to sign
+    ///
+    fn get_signer_certificate(&mut self, serial: u128) -> Option<Falcon1024Certificate>{
+        let mut binder = self.cert_binder.lock().unwrap();
+        let certificate = match binder.get_signing_certificate(serial) {
+            Some(certificate) => certificate,
+            None => {
+                output::error(format!("Unknown signer certificate {}", serial));
+                return None;
+            }
+        };
+        if !certificate.check_flag(FLAG_SIGN_MESSAGES) || !binder.verify_signing_certificate(&certificate){
+            output::error("Signer certificate is not trusted");
+            return None;
         }
-        let file = file.unwrap();
-        
+        Some(certificate)
+    }
+
+    ///
+    /// Verifies signature made by chunks, chunks are hashed on 'jobs' threads
+    ///
+    fn verify_chunked_signature(&mut self, argmap: &HashMap<String, Option<String>>, file_name: &str, data: &[u8]){
+        let signature = match ChunkedFileSignature::from_bytes(data) {
+            Ok(signature) => signature,
+            Err(_) => {
+                output::error("Malformed signature-file");
+                return;
+            }
+        };
+        let certificate = match self.get_signer_certificate(signature.get_signer_serial()) {
+            Some(certificate) => certificate,
+            None => return,
+        };
+        if !signature.verify_manifest(&certificate){
+            output::error("Signature is not valid");
+            return;
+        }
+        let jobs = match Self::get_jobs(argmap) {
+            Some(jobs) => jobs,
+            None => return,
+        };
+        let (hashes, length) = match Self::hash_file_chunks(file_name, signature.hash_type.clone(),
+                                                            signature.chunk_size, jobs) {
+            Some(result) => result,
+            None => return,
+        };
+        let mismatches = signature.find_mismatches(&hashes, length);
+        if mismatches.is_empty(){
+            output::info(format!("Signature is valid, {}", describe_signer(signature.get_signer_serial(),
+                                                                           &signature.signature)));
+            return;
+        }
+        let chunks: Vec<String> = mismatches.iter().map(|index| index.to_string()).collect();
+        output::error(format!("Signature is not valid, chunks {} of {} bytes differ", chunks.join(", "),
+                              signature.chunk_size));
     }
 
 
-    pub fn show(&mut self){
-        let result =self.cert_binder.lock().unwrap().get_signing_certificates();
-        let mut table = Table::new(vec!["SERIAL", "NAME", "FLAGS", "PARENT SERIAL"]);
-        for certificate in result{
-            table.add_row(vec![&certificate.get_serial().to_string(),
-                               &certificate.get_name(), &certificates_flags_to_string(certificate.get_flags()),
-                               &*optional_serial_to_string(certificate.get_parent_serial())]);
+    pub fn show_profiles(&self){
+        let mut table = Table::new(vec!["PROFILE", "FLAGS", "NAME", "STATUS", "DESCRIPTION"]);
+        for profile in get_profiles(&self.profiles){
+            let status = match profile.validate() {
+                Ok(_) => "valid".to_string(),
+                Err(error) => error.to_string(),
+            };
+            table.add_row(vec![&profile.name, &format_flags(profile.flags), &profile.name_template,
+                               &status, &profile.description]);
         }
         table.display();
     }
+
+    pub fn show(&mut self){
+        show_certificates(&mut self.cert_binder.lock().unwrap(), false);
+    }
 }
 
 impl CommandNamespace for SigningNamespace {
     fn on_command(&mut self, command: String, args: Vec<String>) {
+        if matches!(command.as_str(), "generate" | "remove" | "import" | "import-key")
+            && !check_writable(&mut self.cert_binder.lock().unwrap()){
+            return;
+        }
         match command.as_str() {
             "generate" => {
                 self.generate(args);
@@ -541,6 +722,12 @@ impl CommandNamespace for SigningNamespace {
             "import" => {
                 self.import(args);
             }
+            "export-key" => {
+                self.export_key(args);
+            }
+            "import-key" => {
+                self.import_key(args);
+            }
             "sign-file" => {
                 self.sign_file(args);
             }
@@ -550,9 +737,80 @@ impl CommandNamespace for SigningNamespace {
             "show" => {
                 self.show();
             }
+            "profiles" => {
+                self.show_profiles();
+            }
             &_ => {
-                println!("{} {}", "error:".red().bold().underline(), "No such command");
+                output::error("No such command");
             }
         }
     }
-}
\ No newline at end of file
+
+    fn describe(&self) -> Vec<CommandDescription> {
+        vec![
+            CommandDescription::new("generate", "Generates signing certificate", vec![
+                ArgumentDescription::optional("serial", "Serial number of certificate, random if omitted"),
+                ArgumentDescription::required("parent", "Serial number of signing certificate")
+                    .with_kind(ArgumentKind::Serial),
+                ArgumentDescription::required("name", "Name of certificate"),
+                ArgumentDescription::optional("flags", "Comma-separated flags, e.g. sign-messages,client-cert"),
+                ArgumentDescription::optional("profile", "Profile adding flags and naming convention, e.g. server"),
+                ArgumentDescription::optional("description", "Description of certificate"),
+                ArgumentDescription::optional("owner", "Person or team responsible for certificate"),
+                ArgumentDescription::optional("tags", "Comma-separated tags, e.g. env:prod,team:web"),
+                ArgumentDescription::optional("lifetime", "Days certificate is valid for, forever if omitted"),
+                ArgumentDescription::flag("json", "Print serial of generated certificate as JSON object"),
+            ]),
+            CommandDescription::new("remove", "Removes signing certificate", vec![
+                ArgumentDescription::required("serial", "Serial number of certificate").with_kind(ArgumentKind::Serial),
+            ]),
+            CommandDescription::new("export", "Exports signing certificate to file", vec![
+                ArgumentDescription::required("serial", "Serial number of certificate").with_kind(ArgumentKind::Serial),
+                ArgumentDescription::required("file", "File to write certificate to").with_kind(ArgumentKind::File),
+                ArgumentDescription::optional("signer", "Serial of certificate signing export, root by default")
+                    .with_kind(ArgumentKind::Serial),
+                ArgumentDescription::flag("unsigned", "Do not sign export"),
+            ]),
+            CommandDescription::new("import", "Imports signing certificate from file", vec![
+                ArgumentDescription::optional("file", "File with certificate").with_kind(ArgumentKind::File),
+                ArgumentDescription::optional("bundle", "File with list of certificates to import instead of file")
+                    .with_kind(ArgumentKind::File),
+                ArgumentDescription::optional("max_age", "Reject exports older than given count of seconds"),
+            ]),
+            CommandDescription::new("export-key", "Exports secret key of signing certificate encrypted with passphrase", vec![
+                ArgumentDescription::required("serial", "Serial number of certificate").with_kind(ArgumentKind::Serial),
+                ArgumentDescription::required("file", "File to write key to").with_kind(ArgumentKind::File),
+                ArgumentDescription::optional("passphrase", "Passphrase, MWAY_KEY_PASSPHRASE by default"),
+            ]),
+            CommandDescription::new("import-key", "Imports secret key of signing certificate already in store", vec![
+                ArgumentDescription::required("serial", "Serial number of certificate").with_kind(ArgumentKind::Serial),
+                ArgumentDescription::required("file", "File with key").with_kind(ArgumentKind::File),
+                ArgumentDescription::optional("passphrase", "Passphrase, MWAY_KEY_PASSPHRASE by default"),
+            ]),
+            CommandDescription::new("sign-file", "Signs a file", vec![
+                ArgumentDescription::required("file", "File to sign").with_kind(ArgumentKind::File),
+                ArgumentDescription::required("signature-file", "File to write signature to")
+                    .with_kind(ArgumentKind::File),
+                ArgumentDescription::required("serial", "Serial number of signing certificate")
+                    .with_kind(ArgumentKind::Serial),
+                ArgumentDescription::optional("jobs", "Count of threads hashing file, all CPUs by default"),
+                ArgumentDescription::optional("chunk-size", "Size of chunks hashed in parallel, 16 MiB by default"),
+            ]),
+            CommandDescription::new("verify-file-signature", "Verifies signature of a file", vec![
+                ArgumentDescription::required("file", "Signed file").with_kind(ArgumentKind::File),
+                ArgumentDescription::required("signature-file", "File with signature").with_kind(ArgumentKind::File),
+                ArgumentDescription::optional("jobs", "Count of threads hashing file, all CPUs by default"),
+            ]),
+            CommandDescription::new("show", "Shows signing certificates", vec![]),
+            CommandDescription::new("profiles", "Shows certificate profiles", vec![]),
+        ]
+    }
+    fn complete(&self, command: &str, argument: &str, prefix: &str) -> Vec<String> {
+        match argument {
+            // Serial of generated certificate is new
+            "serial" if command == "generate" => vec![],
+            "serial" | "parent" | "signer" => complete_serials(&self.completions, &self.cert_binder, false, prefix),
+            _ => vec![],
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::{Arc, Mutex};
+use libmilkyway::module::state::{ModuleState, StateError};
+use libmilkyway::services::certificate::push::{CertificatePushPolicy, PendingCertificatePush,
+                                               MAX_PENDING_CERTIFICATE_PUSHES};
+
+const POLICY_KEY: &str = "push-policy";
+const PENDING_KEY: &str = "pending-pushes";
+
+///
+/// Push policy and certificates waiting for confirmation. Both are kept in state of module, so
+/// CLI sees pushes received by daemon and policy set from CLI is saved and reaches daemon.
+/// Hosts which do not keep state of modules hold them in memory.
+///
+#[derive(Clone)]
+pub struct PushState{
+    state: Option<ModuleState>,
+    /** Policy and pending pushes of hosts without module state **/
+    memory: Arc<Mutex<(CertificatePushPolicy, Vec<PendingCertificatePush>)>>,
+}
+
+impl PushState {
+    pub fn new(state: Option<ModuleState>) -> PushState{
+        PushState{
+            state,
+            memory: Arc::new(Mutex::new((CertificatePushPolicy::Confirm, Vec::new()))),
+        }
+    }
+
+    ///
+    /// Gets push policy, Confirm unless another one was set
+    ///
+    pub fn get_policy(&self) -> CertificatePushPolicy{
+        match &self.state {
+            Some(state) => state.get_value::<String>(POLICY_KEY)
+                .and_then(|policy| policy.parse().ok())
+                .unwrap_or(CertificatePushPolicy::Confirm),
+            None => self.memory.lock().unwrap().0,
+        }
+    }
+
+    pub fn set_policy(&self, policy: CertificatePushPolicy) -> Result<(), StateError>{
+        match &self.state {
+            Some(state) => state.set_value(POLICY_KEY, &policy.to_string()),
+            None => {
+                self.memory.lock().unwrap().0 = policy;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn get_pending(&self) -> Vec<PendingCertificatePush>{
+        match &self.state {
+            Some(state) => state.get_value(PENDING_KEY).unwrap_or_default(),
+            None => self.memory.lock().unwrap().1.clone(),
+        }
+    }
+
+    ///
+    /// Queues push for confirmation. Push of certificate already queued by the same peer
+    /// replaces queued one.
+    ///
+    /// returns: bool: false if MAX_PENDING_CERTIFICATE_PUSHES pushes are queued already or
+    /// queue can not be saved
+    ///
+    pub fn add_pending(&self, entry: PendingCertificatePush) -> bool{
+        let add = |pending: &mut Vec<PendingCertificatePush>| {
+            pending.retain(|queued| queued.source != entry.source || queued.push.get_serial() != entry.push.get_serial());
+            if pending.len() >= MAX_PENDING_CERTIFICATE_PUSHES{
+                return false;
+            }
+            pending.push(entry);
+            true
+        };
+        match &self.state {
+            Some(state) => state.update(PENDING_KEY, |pending: &mut Option<Vec<PendingCertificatePush>>| {
+                add(pending.get_or_insert_with(Vec::new))
+            }).unwrap_or_else(|error| {
+                log::error!("Can not queue certificate push: {}", error);
+                false
+            }),
+            None => add(&mut self.memory.lock().unwrap().1),
+        }
+    }
+
+    ///
+    /// Removes push of certificate from queue
+    ///
+    /// returns: Option<PendingCertificatePush>: removed push or None if certificate is not queued
+    ///
+    pub fn take_pending(&self, serial: u128) -> Option<PendingCertificatePush>{
+        let take = |pending: &mut Vec<PendingCertificatePush>| {
+            let index = pending.iter().position(|entry| entry.push.get_serial() == serial)?;
+            Some(pending.remove(index))
+        };
+        match &self.state {
+            Some(state) => state.update(PENDING_KEY, |pending: &mut Option<Vec<PendingCertificatePush>>| {
+                let taken = pending.as_mut().and_then(take);
+                if pending.as_ref().is_some_and(|pending| pending.is_empty()){
+                    *pending = None;
+                }
+                taken
+            }).ok().flatten(),
+            None => take(&mut self.memory.lock().unwrap().1),
+        }
+    }
+}
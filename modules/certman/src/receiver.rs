@@ -0,0 +1,122 @@
+use std::sync::{Arc, Mutex};
+use libmilkyway::message::certpush::CertificatePushMessage;
+use libmilkyway::message::certsync::CertificateSyncMessage;
+use libmilkyway::message::common::Message;
+use libmilkyway::message::group::GroupRecord;
+use libmilkyway::message::types::MessageType;
+use libmilkyway::serialization::deserializable::Deserializable;
+use libmilkyway::services::certificate::CertificateServiceBinder;
+use libmilkyway::services::certificate::push::{install_certificate_push, CertificatePushPolicy,
+                                               PendingCertificatePush};
+use crate::pushes::PushState;
+use libmilkyway::services::certificate::sync::apply_certificate_sync;
+use libmilkyway::services::group::{apply_group_record, SharedGroupService};
+use libmilkyway::transport::TransportListener;
+
+///
+/// Receives certificates pushed by peers and installs them according to policy.
+/// Also applies certificate syncs and group records signed by trusted certificates.
+///
+pub struct CertificatePushReceiver{
+    cert_binder: Arc<Mutex<Box<CertificateServiceBinder>>>,
+    pushes: PushState,
+    groups: Option<SharedGroupService>,
+}
+
+impl CertificatePushReceiver {
+    pub fn new(binder: Arc<Mutex<Box<CertificateServiceBinder>>>,
+               pushes: PushState,
+               groups: Option<SharedGroupService>) -> Self{
+        CertificatePushReceiver{
+            cert_binder: binder,
+            pushes,
+            groups,
+        }
+    }
+
+    fn on_group_record(&mut self, message: Message){
+        let groups = match &self.groups {
+            Some(groups) => groups,
+            None => return,
+        };
+        let record = match message.data.as_ref().map(GroupRecord::from_serialized) {
+            Some(Ok((record, _))) => record,
+            _ => {
+                log::warn!("Malformed group record from {}", message.source);
+                return;
+            }
+        };
+        let mut binder = self.cert_binder.lock().unwrap();
+        let mut groups = groups.lock().unwrap();
+        match apply_group_record(&mut *groups, &mut **binder, &record) {
+            Ok(_) => {
+                groups.commit();
+                log::info!("Applied change of group {} from {}", record.name, message.source);
+            }
+            Err(error) => log::warn!("Change of group {} from {} is not applied: {}",
+                record.name, message.source, error),
+        }
+    }
+
+    fn on_certificate_sync(&mut self, message: Message){
+        let sync = match message.data.as_ref().map(CertificateSyncMessage::from_serialized) {
+            Some(Ok((sync, _))) => sync,
+            _ => {
+                log::warn!("Malformed certificate sync from {}", message.source);
+                return;
+            }
+        };
+        // Every entry is verified against local chains, so sync does not depend on push policy
+        let mut binder = self.cert_binder.lock().unwrap();
+        let report = apply_certificate_sync(&mut **binder, &sync);
+        if report.applied > 0{
+            log::info!("Applied {} certificate changes synced by {}", report.applied, message.source);
+        }
+    }
+}
+
+impl TransportListener for CertificatePushReceiver{
+    fn on_message(&mut self, message: Message) {
+        if message.message_type == MessageType::GroupRecord{
+            self.on_group_record(message);
+            return;
+        }
+        if message.message_type == MessageType::CertificateSync{
+            self.on_certificate_sync(message);
+            return;
+        }
+        if message.message_type != MessageType::CertificatePush{
+            return;
+        }
+        let push = match message.data.as_ref().map(CertificatePushMessage::from_serialized) {
+            Some(Ok((push, _))) => push,
+            _ => {
+                log::warn!("Malformed certificate push from {}", message.source);
+                return;
+            }
+        };
+        match self.pushes.get_policy() {
+            CertificatePushPolicy::Reject => {
+                log::warn!("Rejected certificate {} pushed by {}", push.get_serial(), message.source);
+            }
+            CertificatePushPolicy::Confirm => {
+                let serial = push.get_serial();
+                if self.pushes.add_pending(PendingCertificatePush{ source: message.source, push }){
+                    log::info!("Certificate {} pushed by {} waits for confirmation", serial, message.source);
+                } else {
+                    log::warn!("Rejected certificate {} pushed by {}: too many pushes wait for confirmation",
+                        serial, message.source);
+                }
+            }
+            CertificatePushPolicy::AcceptVerified => {
+                let mut binder = self.cert_binder.lock().unwrap();
+                match install_certificate_push(&mut **binder, &push) {
+                    Ok(_) => log::info!("Installed certificate {} pushed by {}",
+                        push.get_serial(), message.source),
+                    Err(error) => log::warn!("Certificate {} pushed by {} is not installed: {}",
+                        push.get_serial(), message.source, error),
+                }
+            }
+        }
+    }
+}
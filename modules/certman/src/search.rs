@@ -0,0 +1,85 @@
+use libmilkyway::cli::output;
+use libmilkyway::cli::arguments::parse_arguments;
+use libmilkyway::cli::table::Table;
+use libmilkyway::pki::certificate::Certificate;
+use libmilkyway::pki::certificate::metadata::{CertificateMetadata, CertificateQuery};
+use libmilkyway::serialization::schema::json_string;
+use libmilkyway::services::certificate::{CertificateService, CertificateServiceBinder};
+
+///
+/// Prints certificate as a single JSON object
+///
+fn print_json(kind: &str, serial: u128, name: &str, metadata: &CertificateMetadata){
+    println!("{{\"serial\":{},\"kind\":{},\"name\":{},\"metadata\":{}}}", json_string(&serial.to_string()),
+             json_string(kind), json_string(name), metadata.to_json());
+}
+
+// Arguments of search command(those ones in argmap)
+// * name -- substring of certificate name, optional
+// * owner -- owner of certificate, optional
+// * tag -- required tag as key or key:value, optional
+// * text -- substring of name, owner or description, optional
+// * type -- signing or encryption, both by default
+// * json -- print every found certificate as JSON object on its own line
+pub fn search_certificates(binder: &mut Box<CertificateServiceBinder>, arguments: Vec<String>){
+    let argmap = parse_arguments(arguments);
+    let mut query = CertificateQuery::new();
+    for key in ["name", "owner", "tag", "text", "type"]{
+        let value = match argmap.get(key) {
+            None => continue,
+            Some(Some(value)) => value,
+            Some(None) => {
+                output::error(format!("Argument '{}' requires a value", key));
+                return;
+            }
+        };
+        match key {
+            "name" => {
+                query.set_name(value);
+            }
+            "owner" => {
+                query.set_owner(value);
+            }
+            "tag" => {
+                match value.split_once(':') {
+                    Some((tag, expected)) => query.add_tag(tag, Some(expected)),
+                    None => query.add_tag(value, None),
+                };
+            }
+            "text" => {
+                query.set_text(value);
+            }
+            _ => {
+                if value != "signing" && value != "encryption"{
+                    output::error("Argument 'type' must be signing or encryption");
+                    return;
+                }
+            }
+        }
+    }
+    let kind = argmap.get("type").cloned().flatten();
+    let json = argmap.contains_key("json");
+    let mut found: Vec<(&str, u128, String, CertificateMetadata)> = Vec::new();
+    if kind.as_deref() != Some("encryption"){
+        for certificate in binder.query_signing_certificates(&query){
+            found.push(("signing", certificate.get_serial(), certificate.get_name(), certificate.get_metadata()));
+        }
+    }
+    if kind.as_deref() != Some("signing"){
+        for certificate in binder.query_encryption_certificates(&query){
+            found.push(("encryption", certificate.get_serial(), certificate.get_name(), certificate.get_metadata()));
+        }
+    }
+    if json{
+        for (kind, serial, name, metadata) in found.iter(){
+            print_json(kind, *serial, name, metadata);
+        }
+        return;
+    }
+    let mut table = Table::new(vec!["SERIAL", "TYPE", "NAME", "OWNER", "DESCRIPTION", "TAGS"]);
+    for (kind, serial, name, metadata) in found.iter(){
+        table.add_row(vec![&serial.to_string(), kind, name, &metadata.owner, &metadata.description,
+                           &metadata.format_tags()]);
+    }
+    table.display();
+}
@@ -0,0 +1,53 @@
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::time::Duration;
+use libmilkyway::module::ModuleDataBus;
+use libmilkyway::services::certificate::CertificateServiceBinder;
+use libmilkyway::services::certificate::sync::send_certificate_sync;
+
+///
+/// Periodically sends snapshot of local certificates and revocations to known peers.
+/// Sending stops once the sender is dropped.
+///
+pub struct CertificateSyncSender{
+    /** Dropping it wakes and stops sending thread **/
+    _stop: Sender<()>,
+}
+
+impl CertificateSyncSender {
+    ///
+    /// Starts sending snapshots
+    ///
+    /// # Arguments
+    /// * binder: binder to take certificates from
+    /// * data_bus: data bus of host to find peers and send snapshots with
+    /// * module_id: u64: ID of module receiving snapshots on peers
+    /// * interval: Duration: time between snapshots
+    ///
+    pub fn start(binder: Arc<Mutex<Box<CertificateServiceBinder>>>, data_bus: Arc<Box<dyn ModuleDataBus>>,
+                 module_id: u64, interval: Duration) -> CertificateSyncSender{
+        let (stop, stopped) = channel::<()>();
+        std::thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval){
+                // Host which is not connected yet has nobody to sync with
+                let source = match data_bus.get_host_id() {
+                    Some(source) => source,
+                    None => continue,
+                };
+                let name_service = data_bus.get_name_service();
+                let mut peers: Vec<u128> = name_service.get_known_names().iter()
+                    .filter_map(|name| name_service.get_id_by_name(name))
+                    .collect();
+                peers.sort();
+                peers.dedup();
+                let mut transport = data_bus.get_transport_service();
+                let mut binder = binder.lock().unwrap();
+                let sent = send_certificate_sync(&mut **binder, transport.as_mut(), source, module_id, &peers);
+                log::debug!("Certificate snapshot is sent to {} peers", sent);
+            }
+        });
+        CertificateSyncSender{
+            _stop: stop,
+        }
+    }
+}
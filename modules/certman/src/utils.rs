@@ -1,37 +1,220 @@
-use libmilkyway::pki::certificate::{FLAG_CLIENT_CERT, FLAG_NO_READ, FLAG_NO_WRITE, FLAG_ROOT_CERT, FLAG_SERVER_CERT, FLAG_SIGN_CERTS, FLAG_SIGN_MESSAGES, FLAG_USER_CERT};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use libmilkyway::cli::completion::CompletionCache;
+use libmilkyway::cli::output;
+use libmilkyway::cli::table::Table;
+use libmilkyway::pki::certificate::flags::format_flags_short;
+use libmilkyway::pki::certificate::metadata::{CertificateMetadata, CertificateValidity};
+use libmilkyway::serialization::schema::json_string;
+use libmilkyway::services::certificate::{CertificateService, CertificateServiceBinder, ROOT_CERTIFICATE_SERIAL};
+use libmilkyway::services::certificate::policy::{PolicyOperation, PolicySubject};
+use libmilkyway::services::certificate::listing::{DEFAULT_CERTIFICATE_PAGE_SIZE, MAX_CERTIFICATE_PAGE_SIZE};
+use libmilkyway::services::certificate::serial::{generate_serial, is_serial_taken};
+use libmilkyway::services::certificate::usage::KeyUsage;
 
-pub fn certificates_flags_to_string(flags: u128) -> String{
-    let mut result = "".to_string();
-    if flags & FLAG_SIGN_CERTS != 0{
-        result += "G";
-    }
-    if flags & FLAG_SIGN_MESSAGES != 0{
-        result += "M";
-    }
-    if flags & FLAG_NO_WRITE == 0{
-        result += "W";
+#[inline]
+pub fn optional_serial_to_string(serial: Option<u128>) ->String{
+    if serial.is_none(){
+        return "X".to_string();
     }
-    if flags & FLAG_NO_READ == 0{
-        result += "R";
+    serial.unwrap().to_string()
+}
+
+// Completes serials of encryption or signing certificates, root is offered among signing ones
+pub fn complete_serials(completions: &CompletionCache, binder: &Arc<Mutex<Box<CertificateServiceBinder>>>,
+                        encryption: bool, prefix: &str) -> Vec<String>{
+    let binder = binder.clone();
+    let key = if encryption { "encryption-serials" } else { "signing-serials" };
+    completions.complete(key, prefix, move || {
+        let mut binder = binder.lock().unwrap();
+        let mut serials = Vec::<String>::new();
+        let mut cursor = None;
+        loop {
+            let page = match encryption {
+                true => binder.list_encryption_certificates(cursor, MAX_CERTIFICATE_PAGE_SIZE),
+                false => binder.list_signing_certificates(cursor, MAX_CERTIFICATE_PAGE_SIZE),
+            };
+            serials.extend(page.certificates.iter().map(|certificate| certificate.serial.to_string()));
+            cursor = page.next_cursor;
+            if cursor.is_none(){
+                break;
+            }
+        }
+        if !encryption && binder.get_root_certificate().is_some(){
+            serials.push(ROOT_CERTIFICATE_SERIAL.to_string());
+        }
+        serials
+    })
+}
+
+// Usage counters of certificates by serial
+pub fn get_key_usage(binder: &mut Box<CertificateServiceBinder>) -> HashMap<u128, KeyUsage>{
+    binder.get_key_usage().into_iter().map(|usage| (usage.serial, usage)).collect()
+}
+
+// Columns SIGNATURES, ENCRYPTED, SESSIONS of show tables
+pub fn usage_columns(usage: Option<&KeyUsage>) -> Vec<String>{
+    let usage = usage.cloned().unwrap_or_default();
+    vec![usage.signatures.to_string(), usage.encrypted_bytes.to_string(), usage.sessions.to_string()]
+}
+
+// Prints warning for every certificate which reached usage thresholds
+pub fn warn_rotation(binder: &mut Box<CertificateServiceBinder>, usages: &HashMap<u128, KeyUsage>, serials: &[u128]){
+    let thresholds = binder.get_usage_thresholds();
+    for serial in serials{
+        let exceeded = match usages.get(serial) {
+            Some(usage) => thresholds.get_exceeded(usage),
+            None => continue,
+        };
+        if !exceeded.is_empty(){
+            output::warning(format!("Certificate {} should be rotated, it reached {}", serial, exceeded.join(", ")));
+        }
     }
-    if flags & FLAG_CLIENT_CERT != 0{
-        result += "C";
+}
+
+// Shows encryption or signing certificates page by page, without fetching their keys
+pub fn show_certificates(binder: &mut Box<CertificateServiceBinder>, encryption: bool){
+    let usages = get_key_usage(binder);
+    let mut table = Table::new(vec!["SERIAL", "NAME", "FLAGS", "PARENT SERIAL", "OWNER", "TAGS",
+                                    "SIGNATURES", "ENCRYPTED", "SESSIONS"]);
+    let mut serials = Vec::<u128>::new();
+    let mut cursor = None;
+    table.display_pages(|table| {
+        let page = match encryption {
+            true => binder.list_encryption_certificates(cursor, DEFAULT_CERTIFICATE_PAGE_SIZE),
+            false => binder.list_signing_certificates(cursor, DEFAULT_CERTIFICATE_PAGE_SIZE),
+        };
+        for certificate in &page.certificates{
+            let usage = usage_columns(usages.get(&certificate.serial));
+            table.add_row(vec![&certificate.serial.to_string(), &certificate.name,
+                               &format_flags_short(certificate.flags),
+                               &optional_serial_to_string(certificate.parent_serial),
+                               &certificate.metadata.owner, &certificate.metadata.format_tags(),
+                               &usage[0], &usage[1], &usage[2]]);
+            serials.push(certificate.serial);
+        }
+        cursor = page.next_cursor;
+        cursor.is_some()
+    });
+    warn_rotation(binder, &usages, &serials);
+}
+
+// Checks that certificate store may be changed, prints why it can not otherwise
+pub fn check_writable(binder: &mut Box<CertificateServiceBinder>) -> bool{
+    match binder.check_writable() {
+        Ok(()) => true,
+        Err(error) => {
+            output::coded_error(format!("Can not change certificates: {}", error), &error);
+            false
+        }
     }
-    if flags & FLAG_USER_CERT != 0{
-        result += "U";
+}
+
+// Checks that certificate policy allows issuer to sign certificate, prints why not otherwise
+pub fn check_sign_policy(binder: &mut Box<CertificateServiceBinder>, issuer: u128, name: &str, flags: u128) -> bool{
+    let subject = PolicySubject{
+        operation: PolicyOperation::Sign,
+        issuer,
+        name: name.to_string(),
+        flags,
+    };
+    match binder.evaluate_policy(&subject) {
+        Ok(()) => true,
+        Err(error) => {
+            output::coded_error(format!("Can not sign certificate: {}", error), &error);
+            false
+        }
     }
-    if flags & FLAG_SERVER_CERT != 0{
-        result += "S";
+}
+
+// Serial of generated certificate
+// Arguments of generate commands(those ones in argmap)
+// * serial -- serial of certificate, a random free one is generated if omitted
+pub fn get_new_serial(binder: &mut Box<CertificateServiceBinder>, argmap: &HashMap<String, Option<String>>) -> Option<u128>{
+    let serial = match argmap.get("serial") {
+        None => {
+            let serial = generate_serial(binder.as_mut());
+            if serial.is_none(){
+                output::error("Can not generate a free serial");
+            }
+            return serial;
+        }
+        Some(None) => {
+            output::error("Argument 'serial' must have a value");
+            return None;
+        }
+        Some(Some(serial)) => serial,
+    };
+    let serial = match serial.parse::<u128>() {
+        Ok(serial) => serial,
+        Err(_) => {
+            output::error("Argument serial must be a positive number");
+            return None;
+        }
+    };
+    if is_serial_taken(binder.as_mut(), serial){
+        output::error(format!("Serial {} is already used", serial));
+        return None;
     }
-    if flags & FLAG_ROOT_CERT != 0{
-        result += "O";
+    Some(serial)
+}
+
+// Prints serial of generated certificate, as JSON object if argument 'json' is given
+pub fn print_generated(argmap: &HashMap<String, Option<String>>, kind: &str, serial: u128){
+    if argmap.contains_key("json"){
+        println!("{{\"serial\":{},\"kind\":{}}}", json_string(&serial.to_string()), json_string(kind));
+    } else {
+        output::info(format!("Generated {} certificate with serial {}", kind, serial));
     }
-    result
 }
-#[inline]
-pub fn optional_serial_to_string(serial: Option<u128>) ->String{
-    if serial.is_none(){
-        return "X".to_string();
+
+// Metadata of generated certificate
+// Arguments of generate commands(those ones in argmap)
+// * description -- description of certificate, optional
+// * owner -- person or team responsible for certificate, optional
+// * tags -- comma-separated key:value pairs, optional
+// * lifetime -- days certificate is valid for, it does not expire if omitted
+// Issue time is always set, so peers order rotations of certificate by it
+pub fn parse_metadata(argmap: &HashMap<String, Option<String>>) -> Option<CertificateMetadata>{
+    let mut metadata = CertificateMetadata::new();
+    let lifetime = match argmap.get("lifetime") {
+        None => None,
+        Some(Some(days)) => match days.parse::<u64>() {
+            Ok(days) if days > 0 => Some(days.saturating_mul(24 * 60 * 60)),
+            _ => {
+                output::error("Argument 'lifetime' must be a positive number of days");
+                return None;
+            }
+        },
+        Some(None) => {
+            output::error("Argument 'lifetime' requires a value");
+            return None;
+        }
+    };
+    metadata.set_validity(CertificateValidity::issued_now(lifetime));
+    for key in ["description", "owner", "tags"]{
+        let value = match argmap.get(key) {
+            None => continue,
+            Some(Some(value)) => value,
+            Some(None) => {
+                output::error(format!("Argument '{}' requires a value", key));
+                return None;
+            }
+        };
+        match key {
+            "description" => {
+                metadata.set_description(value);
+            }
+            "owner" => {
+                metadata.set_owner(value);
+            }
+            _ => {
+                if let Err(pair) = metadata.parse_tags(value){
+                    output::error(format!("Tag '{}' must be formatted as key:value", pair));
+                    return None;
+                }
+            }
+        }
     }
-    serial.unwrap().to_string()
-}
\ No newline at end of file
+    Some(metadata)
+}
@@ -0,0 +1,113 @@
+use std::path::Path;
+use libmilkyway::cli::output;
+use libmilkyway::cli::output::{OutputLevel, CODE_FIELD};
+use libmilkyway::errors::ErrorCode;
+use libmilkyway::cli::arguments::parse_arguments;
+use libmilkyway::pki::export::ExportFile;
+use libmilkyway::pki::impls::certificates::falcon1024::{Falcon1024Certificate, Falcon1024RootCertificate};
+use libmilkyway::pki::impls::certificates::kyber1024::Kyber1024Certificate;
+use libmilkyway::serialization::deserializable::Deserializable;
+use libmilkyway::services::certificate::CertificateServiceBinder;
+use libmilkyway::services::certificate::chain::{CertificateChain, ChainVerificationError};
+
+///
+/// Prints an error, as `result=error` line if machine-readable output is requested
+///
+fn report_error(machine: bool, code: &str, message: &str){
+    if machine{
+        println!("result=error code={}", code);
+    } else {
+        output::error(message);
+    }
+}
+
+fn report_result(machine: bool, result: Result<Vec<u128>, ChainVerificationError>){
+    match result {
+        Ok(path) => {
+            let path: Vec<String> = path.iter().map(|serial| serial.to_string()).collect();
+            if machine{
+                println!("result=ok chain={}", path.join(","));
+            } else {
+                output::info(format!("Certificate is valid, chain: {}", path.join(" -> ")));
+            }
+        }
+        Err(error) => {
+            if machine{
+                println!("result=error code={} serial={}", error.get_code(), error.get_serial());
+            } else {
+                output::write(OutputLevel::Error, &error.to_string(),
+                              &[(CODE_FIELD, error.get_error_code().to_string()),
+                                ("serial", error.get_serial().to_string())]);
+            }
+        }
+    }
+}
+
+// Arguments of command(those ones in argmap)
+// * file -- a certificate to verify
+// * type -- signing(default) or encryption
+// * chain -- a file with list of signing certificates, optional
+// * root -- a file with root certificate, optional, root from store is used otherwise
+// * machine -- print one line of key=value pairs instead of human-readable message
+pub fn verify_certificate(binder: &mut Box<CertificateServiceBinder>, arguments: Vec<String>){
+    let argmap = parse_arguments(arguments);
+    let machine = argmap.contains_key("machine");
+    let file = match argmap.get("file") {
+        Some(Some(file)) => file.clone(),
+        _ => {
+            report_error(machine, "invalid-arguments", "Argument 'file' with a value is required");
+            return;
+        }
+    };
+    let mut chain = CertificateChain::new();
+    if let Some(root) = argmap.get("root"){
+        let root = root.as_ref().map(|root| Falcon1024RootCertificate::from_file(Path::new(root)));
+        match root {
+            Some(Ok(root)) => {
+                chain.set_root_certificate(root);
+            }
+            _ => {
+                report_error(machine, "invalid-root", "Can not read root certificate");
+                return;
+            }
+        }
+    }
+    if let Some(bundle) = argmap.get("chain"){
+        let bundle = bundle.as_ref().map(|bundle| Vec::<Falcon1024Certificate>::from_file(Path::new(bundle)));
+        match bundle {
+            Some(Ok(bundle)) => {
+                for certificate in bundle{
+                    chain.add_signing_certificate(certificate);
+                }
+            }
+            _ => {
+                report_error(machine, "invalid-chain", "Can not read chain of certificates");
+                return;
+            }
+        }
+    }
+    // Store only fills what is missing in given files and is never modified
+    chain.add_from_service(&mut **binder);
+    let kind = argmap.get("type").cloned().flatten().unwrap_or_else(|| "signing".to_string());
+    let result = match kind.as_str() {
+        "signing" => match ExportFile::read(Path::new(&file)).and_then(|file| file.get_content::<Falcon1024Certificate>()) {
+            Ok(certificate) => chain.verify_signing_certificate(&certificate),
+            Err(_) => {
+                report_error(machine, "invalid-certificate", "Can not read a certificate");
+                return;
+            }
+        }
+        "encryption" => match ExportFile::read(Path::new(&file)).and_then(|file| file.get_content::<Kyber1024Certificate>()) {
+            Ok(certificate) => chain.verify_encryption_certificate(&certificate),
+            Err(_) => {
+                report_error(machine, "invalid-certificate", "Can not read a certificate");
+                return;
+            }
+        }
+        _ => {
+            report_error(machine, "invalid-arguments", "Argument 'type' must be signing or encryption");
+            return;
+        }
+    };
+    report_result(machine, result);
+}
@@ -0,0 +1,128 @@
+mod namespace;
+
+use std::sync::Arc;
+use libmilkyway::cli::completion::CompletionCache;
+use libmilkyway::cli::output;
+use libmilkyway::cli::describe::ModuleDescription;
+use libmilkyway::cli::router::CommandRouter;
+use libmilkyway::message::common::Message;
+use libmilkyway::message::inventory::NodeInventory;
+use libmilkyway::module::{CLIStatus, HostType, MilkywayModule, ModuleDataBus};
+use libmilkyway::module::registry::BuiltinModule;
+use libmilkyway::module::CLIStatus::{Done, NamespaceChange};
+use libmilkyway::services::inventory::{InventoryCache, InventoryCollector, InventoryReceiver,
+                                       InventoryResponder, SharedInventoryCache, INVENTORY_MODULE_ID};
+use libmilkyway::services::transport::MessageFilter;
+use crate::namespace::InventoryNamespace;
+
+///
+/// The module for asking peers for facts about them: hostname, OS, version, modules,
+/// endpoints and certificates
+///
+pub struct InventoryModule{
+    router: CommandRouter,
+    /** Inventories received from peers **/
+    cache: SharedInventoryCache,
+}
+
+impl InventoryModule {
+    pub fn new() -> InventoryModule{
+        InventoryModule{
+            router: CommandRouter::new(),
+            cache: InventoryCache::new_shared(),
+        }
+    }
+}
+
+impl Default for InventoryModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MilkywayModule for InventoryModule {
+    fn get_id(&self) -> u64 {
+        INVENTORY_MODULE_ID
+    }
+
+    fn get_commands(&self) -> Vec<String> {
+        vec!["inventory".to_string()]
+    }
+
+    fn describe(&self) -> ModuleDescription {
+        ModuleDescription::new(self.get_id(), self.get_commands(), self.router.describe())
+    }
+
+    fn on_load(&mut self, data_bus: Box<dyn ModuleDataBus>) {
+        let data_bus = Arc::new(data_bus);
+        let mut transport = data_bus.get_transport_service();
+        let mut filter = MessageFilter::new();
+        filter.filter_module(self.get_id());
+        if data_bus.get_host_type() != HostType::CLI{
+            match data_bus.get_host_id() {
+                Some(host_id) => {
+                    let collector_bus = data_bus.clone();
+                    let collector: InventoryCollector = Box::new(move || {
+                        NodeInventory::collect(collector_bus.get_certificate_service().as_mut(),
+                                               collector_bus.get_loaded_modules(),
+                                               collector_bus.get_listener_endpoints())
+                    });
+                    let responder = InventoryResponder::new(collector, data_bus.get_certificate_service(),
+                                                            transport.get_sender(), host_id);
+                    transport.subscribe_to_messages(&filter, Box::new(responder));
+                }
+                None => log::error!("Can not answer inventory requests: not in a network"),
+            }
+        }
+        let receiver = InventoryReceiver::new(data_bus.get_certificate_service(), self.cache.clone(),
+                                              transport.get_signature_policy());
+        transport.subscribe_to_messages(&filter, Box::new(receiver));
+        self.router.register_namespace(vec!["inventory".to_string()],
+                                       Box::new(InventoryNamespace::new(data_bus, self.get_id(), self.cache.clone(),
+                                                                        CompletionCache::default())));
+    }
+
+    fn on_cli_command(&mut self, command: Vec<String>, arguments: Vec<String>) -> CLIStatus {
+        let command = match self.router.resolve(&command) {
+            Ok(command) => command,
+            Err(error) => {
+                output::error(error.to_string());
+                return Done;
+            }
+        };
+        if self.router.is_namespace(&command){
+            return NamespaceChange(command);
+        }
+        if !self.router.on_command(command, arguments){
+            output::error("No such command");
+        }
+        Done
+    }
+
+    fn on_cli_complete(&mut self, command: Vec<String>, argument: String, prefix: String) -> Vec<String> {
+        self.router.complete(&command, &argument, &prefix)
+    }
+
+    fn on_server_receive(&self, _packet: &Message) { /* stub */ }
+
+    fn on_client_receive(&self, _packet: &Message) { /* stub */ }
+
+    fn on_cli_receive(&self, _packet: &Message) { /* stub */ }
+}
+
+///
+/// Inventory compiled into host(`inventory` feature of milkywaycli and milkywaysrvd)
+///
+pub const BUILTIN_MODULE: BuiltinModule = BuiltinModule{
+    name: "inventory",
+    constructor: || Box::new(InventoryModule::new()),
+};
+
+#[cfg(not(feature = "builtin"))]
+#[no_mangle]
+#[allow(improper_ctypes_definitions)]
+pub extern "C" fn create() -> *mut dyn MilkywayModule{
+    let object = InventoryModule::new();
+    let boxed: Box<dyn MilkywayModule> = Box::new(object);
+    Box::into_raw(boxed)
+}
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use libmilkyway::cli::output;
+use libmilkyway::cli::arguments::parse_arguments;
+use libmilkyway::cli::completion::CompletionCache;
+use libmilkyway::cli::describe::{ArgumentDescription, ArgumentKind, CommandDescription};
+use libmilkyway::cli::router::CommandNamespace;
+use libmilkyway::cli::table::Table;
+use libmilkyway::get_timestamp_with_milliseconds;
+use libmilkyway::message::builder::MessageBuilder;
+use libmilkyway::message::inventory::InventoryRequest;
+use libmilkyway::module::ModuleDataBus;
+use libmilkyway::peer::resolve_peer;
+use libmilkyway::services::inventory::{CachedInventory, SharedInventoryCache};
+
+///
+/// Age of cached inventory `show` accepts unless `max-age` is given, in seconds
+///
+pub const DEFAULT_MAX_AGE: u64 = 60;
+
+///
+/// How long `show` waits for peer to answer
+///
+pub const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct InventoryNamespace{
+    data_bus: Arc<Box<dyn ModuleDataBus>>,
+    module_id: u64,
+    cache: SharedInventoryCache,
+    completions: CompletionCache,
+}
+
+// Formats age in milliseconds as seconds
+fn format_age(age: u128) -> String{
+    format!("{}.{:03}s", age / 1000, age % 1000)
+}
+
+// Formats list of serials, `-` if it is empty
+fn format_serials(serials: &[u128]) -> String{
+    match serials.is_empty() {
+        true => "-".to_string(),
+        false => serials.iter().map(|serial| serial.to_string()).collect::<Vec<String>>().join(", "),
+    }
+}
+
+// Gets max age given in seconds, DEFAULT_MAX_AGE if it is omitted
+fn parse_max_age(argmap: &HashMap<String, Option<String>>) -> Option<u64>{
+    match argmap.get("max-age") {
+        None => Some(DEFAULT_MAX_AGE),
+        Some(Some(max_age)) => match max_age.parse::<u64>() {
+            Ok(max_age) => Some(max_age),
+            Err(_) => {
+                output::error("Argument 'max-age' must be a number of seconds");
+                None
+            }
+        },
+        Some(None) => {
+            output::error("Argument 'max-age' requires a value");
+            None
+        }
+    }
+}
+
+impl InventoryNamespace {
+    pub fn new(data_bus: Arc<Box<dyn ModuleDataBus>>, module_id: u64, cache: SharedInventoryCache,
+               completions: CompletionCache) -> InventoryNamespace{
+        InventoryNamespace{
+            data_bus,
+            module_id,
+            cache,
+            completions,
+        }
+    }
+
+    // Asks peer for inventory and waits for it to answer
+    fn request(&self, peer_id: u128, max_age: u64) -> Option<CachedInventory>{
+        let source = match self.data_bus.get_host_id() {
+            Some(source) => source,
+            None => {
+                output::error("Not connected to network");
+                return None;
+            }
+        };
+        let request = InventoryRequest{
+            request_id: rand::random(),
+            max_age: max_age.saturating_mul(1000),
+        };
+        let message = MessageBuilder::from_payload(&request)
+            .set_source(source)
+            .set_destination(peer_id)
+            .set_module_id(self.module_id)
+            .build()
+            .expect("All required fields are set");
+        let sent_at = get_timestamp_with_milliseconds();
+        if let Err(error) = self.data_bus.get_transport_service().try_send_message(message){
+            output::error(format!("Can not ask peer: {}", error));
+            return None;
+        }
+        let cached = self.cache.wait_for(peer_id, sent_at, RESPONSE_TIMEOUT);
+        if cached.is_none(){
+            output::error(format!("Peer did not answer in {} seconds", RESPONSE_TIMEOUT.as_secs()));
+        }
+        cached
+    }
+
+    // Arguments of command(those ones in argmap)
+    // * peer -- ID or name of peer
+    // * max-age -- age in seconds of inventory which is shown without asking peer again
+    pub fn show(&mut self, arguments: Vec<String>){
+        let argmap = parse_arguments(arguments);
+        let peer = match argmap.get("peer") {
+            Some(Some(peer)) => peer.clone(),
+            _ => {
+                output::error("Argument 'peer' with a value is required");
+                return;
+            }
+        };
+        let peer_id = match resolve_peer(self.data_bus.get_name_service().as_ref(), &peer) {
+            Ok(peer_id) => peer_id.get(),
+            Err(error) => {
+                output::coded_error(format!("Argument 'peer' is invalid: {}", error), &error);
+                return;
+            }
+        };
+        let max_age = match parse_max_age(&argmap) {
+            Some(max_age) => max_age,
+            None => return,
+        };
+        let now = get_timestamp_with_milliseconds();
+        let cached = match self.cache.get_fresh(peer_id, max_age.saturating_mul(1000), now) {
+            Some(cached) => cached,
+            None => match self.request(peer_id, max_age) {
+                Some(cached) => cached,
+                None => return,
+            }
+        };
+        let inventory = &cached.inventory;
+        let mut table = Table::new(vec!["FIELD", "VALUE"]);
+        table.add_row(vec!["Peer", &format!("{} ({})", self.data_bus.get_name_service().get_name_by_id(peer_id), peer_id)]);
+        table.add_row(vec!["Hostname", &inventory.hostname]);
+        table.add_row(vec!["OS", &inventory.os]);
+        table.add_row(vec!["Version", &inventory.version]);
+        table.add_row(vec!["Protocol version", &inventory.protocol_version.to_string()]);
+        table.add_row(vec!["Modules", &inventory.modules.join(", ")]);
+        table.add_row(vec!["Endpoints", &inventory.endpoints.join(", ")]);
+        table.add_row(vec!["Signing certificates", &format_serials(&inventory.signing_certificates)]);
+        table.add_row(vec!["Encryption certificates", &format_serials(&inventory.encryption_certificates)]);
+        table.add_row(vec!["Signed by", &cached.signer.to_string()]);
+        table.add_row(vec!["Age", &format_age(inventory.get_age(get_timestamp_with_milliseconds()))]);
+        table.display();
+    }
+
+    pub fn list(&mut self){
+        let now = get_timestamp_with_milliseconds();
+        let name_service = self.data_bus.get_name_service();
+        let mut table = Table::new(vec!["PEER", "NAME", "HOSTNAME", "VERSION", "AGE", "FRESH"]);
+        for (peer_id, cached) in self.cache.get_all(){
+            let age = cached.inventory.get_age(now);
+            let fresh = match age <= DEFAULT_MAX_AGE as u128 * 1000 {
+                true => "yes",
+                false => "no",
+            };
+            table.add_row(vec![&peer_id.to_string(), &name_service.get_name_by_id(peer_id),
+                               &cached.inventory.hostname, &cached.inventory.version, &format_age(age), fresh]);
+        }
+        table.display();
+    }
+}
+
+impl CommandNamespace for InventoryNamespace{
+    fn on_command(&mut self, command: String, args: Vec<String>) {
+        match command.as_str() {
+            "show" => {
+                self.show(args);
+            }
+            "list" => {
+                self.list();
+            }
+            &_ => {
+                output::error("No such command");
+            }
+        }
+    }
+
+    fn describe(&self) -> Vec<CommandDescription> {
+        vec![
+            CommandDescription::new("show", "Shows inventory of peer, asking it if cached one is too old", vec![
+                ArgumentDescription::required("peer", "ID or name of peer").with_kind(ArgumentKind::Peer),
+                ArgumentDescription::optional("max-age", "Age in seconds of inventory shown without asking peer, 60 by default"),
+            ]),
+            CommandDescription::new("list", "Lists cached inventories of peers", vec![]),
+        ]
+    }
+
+    fn complete(&self, _command: &str, argument: &str, prefix: &str) -> Vec<String> {
+        if argument != "peer"{
+            return vec![];
+        }
+        let data_bus = self.data_bus.clone();
+        self.completions.complete("peers", prefix, move || data_bus.get_name_service().get_known_names())
+    }
+}
@@ -1,27 +1,54 @@
 mod responder;
 mod ping;
 
-use colored::Colorize;
+use std::sync::Mutex;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+use libmilkyway::cli::arguments::parse_arguments;
+use libmilkyway::cli::output;
 use libmilkyway::message::common::Message;
-use libmilkyway::module::{CLIStatus, HostType, MilkywayModule, ModuleDataBus};
+use libmilkyway::module::{CLIStatus, MilkywayModule, ModuleDataBus};
+use libmilkyway::module::CLIStatus::Done;
+use libmilkyway::module::registry::BuiltinModule;
 use libmilkyway::services::transport::MessageFilter;
-use crate::responder::PingResponder;
+use crate::ping::ping;
+use crate::responder::{PingResponder, PongReceiver};
+
+/// How long ping waits for pong unless `timeout` is given, in milliseconds
+const DEFAULT_PING_TIMEOUT: u64 = 5000;
 
 ///
 /// The module for pinging peers
 ///
 pub struct PingModule {
-    filter_id: Option<u128>,
+    data_bus: Option<Box<dyn ModuleDataBus>>,
+    /** IDs of ping messages answered by peers **/
+    pongs: Option<Mutex<Receiver<u128>>>,
 }
 
 impl PingModule {
     pub fn new() -> PingModule {
         PingModule {
-            filter_id: None,
+            data_bus: None,
+            pongs: None,
+        }
+    }
+
+    // Target is either ID or name known to name service
+    fn resolve_target(data_bus: &dyn ModuleDataBus, target: &str) -> Option<u128>{
+        match target.parse::<u128>() {
+            Ok(id) => Some(id),
+            Err(_) => data_bus.get_name_service().get_id_by_name(target),
         }
     }
 }
 
+impl Default for PingModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MilkywayModule for PingModule {
     fn get_id(&self) -> u64 {
         2
@@ -39,16 +66,62 @@ impl MilkywayModule for PingModule {
             return;
         }
         let my_id = my_id.unwrap();
-        let transport = service.get_sender();
-        let responder = Box::new(PingResponder::new(my_id, self.get_id(), 
-                                                    transport));
-        self.filter_id = Some(service.subscribe_to_messages(MessageFilter::new()
-                                                                .filter_module(self.get_id()), 
-                                                            responder));
+        let mut filter = MessageFilter::new();
+        filter.filter_module(self.get_id());
+        let responder = Box::new(PingResponder::new(my_id, self.get_id(), service.get_sender()));
+        service.subscribe_to_messages(&filter, responder);
+        let (pongs, receiver) = channel();
+        service.subscribe_to_messages(&filter, Box::new(PongReceiver::new(pongs)));
+        self.pongs = Some(Mutex::new(receiver));
+        self.data_bus = Some(data_bus);
     }
 
     fn on_cli_command(&mut self, command: Vec<String>, arguments: Vec<String>) -> CLIStatus {
-        todo!()
+        if command != self.get_commands(){
+            output::error("No such command");
+            return Done;
+        }
+        let (data_bus, pongs) = match (&self.data_bus, &self.pongs) {
+            (Some(data_bus), Some(pongs)) => (data_bus, pongs),
+            _ => {
+                output::error("Ping module is not in a network");
+                return Done;
+            }
+        };
+        let argmap = parse_arguments(arguments);
+        let target = match argmap.get("target") {
+            Some(Some(target)) => target,
+            _ => {
+                output::error("Argument 'target' is required");
+                return Done;
+            }
+        };
+        let target_id = match Self::resolve_target(data_bus.as_ref(), target) {
+            Some(target_id) => target_id,
+            None => {
+                output::error(format!("Unknown host: {}", target));
+                return Done;
+            }
+        };
+        let timeout = match argmap.get("timeout") {
+            Some(Some(timeout)) => match timeout.parse::<u64>() {
+                Ok(timeout) => timeout,
+                Err(_) => {
+                    output::error("Timeout must be a number of milliseconds");
+                    return Done;
+                }
+            },
+            _ => DEFAULT_PING_TIMEOUT,
+        };
+        let mut sender = data_bus.get_transport_service().get_sender();
+        // Host ID is known once module is loaded into network
+        let source = data_bus.get_host_id().unwrap();
+        match ping(sender.as_mut(), &pongs.lock().unwrap(), source, self.get_id(), target_id,
+                   Duration::from_millis(timeout)) {
+            Some(elapsed) => output::info(format!("Pong from {} in {} ms", target, elapsed.as_millis())),
+            None => output::error(format!("No pong from {} within {} ms", target, timeout)),
+        }
+        Done
     }
 
     fn on_server_receive(&self, _packet: &Message) { /* stub */ }
@@ -58,6 +131,15 @@ impl MilkywayModule for PingModule {
     fn on_cli_receive(&self, _packet: &Message) { /* stub */ }
 }
 
+///
+/// Ping compiled into host(`ping` feature of milkywaycli and milkywaysrvd)
+///
+pub const BUILTIN_MODULE: BuiltinModule = BuiltinModule{
+    name: "ping",
+    constructor: || Box::new(PingModule::new()),
+};
+
+#[cfg(not(feature = "builtin"))]
 #[no_mangle]
 #[allow(improper_ctypes_definitions)]
 pub extern "C" fn create() -> *mut dyn MilkywayModule{
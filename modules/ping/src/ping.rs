@@ -1,21 +1,41 @@
-use libmilkyway::message::common::Message;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+use libmilkyway::message::builder::MessageBuilder;
 use libmilkyway::message::types::MessageType;
-use libmilkyway::services::transport::TransportService;
 use libmilkyway::transport::TransportSender;
 
-pub(crate) fn ping(service: &mut Box<dyn TransportService>, 
-                   sender: &mut Box<dyn TransportSender>, 
-                   target: u128, timeout: u64){
-    let mut message = Message::new();
-    let ping_message = message
-        .set_current_timestamp()
+///
+/// Sends ping to target and waits for its pong
+///
+/// # Arguments
+/// * sender: &mut dyn TransportSender: sender of host
+/// * pongs: &Receiver<u128>: IDs of ping messages answered by peers
+/// * source: u128: ID of host
+/// * module_id: u64: ID of ping module
+/// * target: u128: ID of host to ping
+/// * timeout: Duration: how long to wait for pong
+///
+/// returns: Option<Duration>: round trip time or None if no pong came in time
+///
+pub(crate) fn ping(sender: &mut dyn TransportSender, pongs: &Receiver<u128>, source: u128, module_id: u64,
+                   target: u128, timeout: Duration) -> Option<Duration>{
+    let ping_message = MessageBuilder::new()
+        .set_type(MessageType::Ping)
+        .set_source(source)
         .set_destination(target)
-        .set_type(MessageType::Ping);
-    sender.send_message(ping_message.clone());
-    let msg = service.blocking_recv(target, Some(timeout));
-    if msg.is_some(){
-        println!("Got message");
-    } else {
-        println!("Timeout");
+        .set_module_id(module_id)
+        .build()
+        .expect("All required fields are set");
+    let ping_message_id = ping_message.id;
+    let started = Instant::now();
+    sender.send_message(ping_message);
+    loop {
+        let remaining = timeout.checked_sub(started.elapsed())?;
+        match pongs.recv_timeout(remaining) {
+            Ok(id) if id == ping_message_id => return Some(started.elapsed()),
+            // Late pong of ping which already timed out
+            Ok(_) => continue,
+            Err(_) => return None,
+        }
     }
-}
\ No newline at end of file
+}
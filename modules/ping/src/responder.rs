@@ -1,13 +1,14 @@
-use libmilkyway::get_timestamp_with_milliseconds;
-use libmilkyway::message::common::{AsMessage, Message};
+use std::sync::mpsc::Sender;
+use libmilkyway::message::builder::MessageBuilder;
+use libmilkyway::message::common::Message;
 use libmilkyway::message::ping::PongMessage;
 use libmilkyway::message::types::MessageType;
-use libmilkyway::services::transport::TransportServiceListener;
-use libmilkyway::transport::{Transport, TransportListener, TransportSender};
+use libmilkyway::serialization::deserializable::Deserializable;
+use libmilkyway::transport::{TransportListener, TransportSender};
 
 ///
 /// A struct which responds to ping requests
-/// 
+///
 pub struct PingResponder{
     source_id: u128,
     module_id: u64,
@@ -26,14 +27,47 @@ impl PingResponder {
 
 impl TransportListener for PingResponder{
     fn on_message(&mut self, message: Message) {
+        // Pongs are handled by PongReceiver
         if message.message_type != MessageType::Ping{
-            log::warn!("Received message of not ping type(id={}, module_id={})",
-                message.id, message.module_id);
             return;
         }
-        let pong = PongMessage::from_ping_message(&message);
+        let pong = MessageBuilder::from_payload(&PongMessage::from_ping_message(&message))
+            .set_source(self.source_id)
+            .set_destination(message.source)
+            .set_module_id(self.module_id)
+            .build()
+            .expect("All required fields are set");
         // We don't actually care if this message ever reaches recepient, so no reason for blocking
         // current thread/coroutine
-        self.sender.send_message(pong.as_message());
+        self.sender.send_message(pong);
+    }
+}
+
+///
+/// Passes IDs of ping messages answered by peers to ping command
+///
+pub struct PongReceiver{
+    pongs: Sender<u128>,
+}
+
+impl PongReceiver {
+    pub fn new(pongs: Sender<u128>) -> PongReceiver{
+        PongReceiver{
+            pongs,
+        }
     }
-}
\ No newline at end of file
+}
+
+impl TransportListener for PongReceiver{
+    fn on_message(&mut self, message: Message) {
+        if message.message_type != MessageType::Pong{
+            return;
+        }
+        let ping_message_id = message.data.as_ref().map(u128::from_serialized);
+        match ping_message_id {
+            // Command may be already gone, then pong is not needed anymore
+            Some(Ok((ping_message_id, _))) => { let _ = self.pongs.send(ping_message_id); }
+            _ => log::warn!("Received malformed pong(id={}) from {}", message.id, message.source),
+        }
+    }
+}